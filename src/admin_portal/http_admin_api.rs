@@ -1,25 +1,77 @@
+use crate::admin_portal::site_scope::{ScopedConfiguration, SiteScope};
+use crate::admin_portal::status_page::{build_status_page, render_status_page_html};
+use crate::authentication::authenticator::authenticate_admin_user;
+use crate::configuration::cached_configuration::get_cached_configuration;
 use crate::configuration::configuration::Configuration;
+use crate::configuration::configuration_impact::analyze_configuration_impact;
+use crate::configuration::deprecated_fields::find_deprecated_fields;
+use crate::configuration::load_configuration::fetch_configuration_in_db;
 use crate::configuration::save_configuration::save_configuration;
 use crate::configuration::site::Site;
-use crate::core::admin_user::{LoginRequest, authenticate_user, create_session, invalidate_session, verify_session_token};
+use crate::configuration::site_templates::{SiteTemplateRequest, add_site_from_template, create_web_root};
+use crate::core::admin_alerts::get_admin_alerts;
+use crate::core::admin_user::{
+    LoginRequest, Session, add_sites_to_user_scope, create_session, delete_user, get_client_binding, get_user_site_scope, invalidate_session, list_users, save_user, verify_session_token,
+};
+use crate::core::cluster_sync::{CLUSTER_CONFIGURATION_PATH, CLUSTER_TOKEN_HEADER, get_configuration_for_replicas, is_valid_cluster_token};
+use crate::core::email_alerts::send_email;
 use crate::core::monitoring::get_monitoring_state;
 use crate::core::operation_mode::{get_operation_mode_as_string, is_valid_operation_mode, set_new_operation_mode};
+use crate::core::running_state_manager::get_running_state_manager;
+use crate::core::scheduled_changes::{cancel_scheduled_change, list_scheduled_changes, schedule_configuration_change};
+use crate::core::scheduled_tasks::{SCHEDULED_TASK_JOB_TYPES, delete_scheduled_task, list_scheduled_tasks, run_scheduled_task, save_scheduled_task};
+use crate::core::synthetic_probes::{get_probe_runs, get_probe_summary, run_synthetic_probe};
 use crate::core::triggers::get_trigger_handler;
+use crate::core::usage_reports::{UsagePeriod, build_usage_report, get_usage_sites};
+use crate::database::database_backup::{create_backup, list_backups, restore_backup_and_reload};
 use crate::error::gruxi_error::GruxiError;
 use crate::error::gruxi_error_enums::{AdminApiError, GruxiErrorKind};
-use crate::file::normalized_path::{NormalizedPath};
+use crate::file::disk_usage::{get_disk_usage, is_over_disk_quota};
+use crate::file::normalized_path::NormalizedPath;
+use crate::file::site_deployments::{activate_release, deploy_bundle, get_deployment_status, get_site_deployment_web_root};
+use crate::file::upload_scanner::{delete_quarantine_entry, list_quarantine};
+use crate::http::cache_warmer::{get_cache_warm_reports, spawn_cache_warming};
+use crate::http::long_running_connections::close_site_connections;
 use crate::http::request_response::gruxi_request::GruxiRequest;
 use crate::http::request_response::gruxi_response::GruxiResponse;
+use crate::logging::access_log_analytics::get_access_log_summaries;
+use crate::logging::debug_dump::{DEBUG_DUMP_DEFAULT_DURATION_SECONDS, DEBUG_DUMP_DEFAULT_MAX_REQUESTS, get_debug_dumps, start_debug_dump, stop_debug_dump};
 use crate::logging::syslog::{debug, error, info, trace};
+use crate::tls::acme_account::{export_acme_account_key, get_acme_directory_url, import_acme_account_key};
+use crate::tls::acme_cache::AcmeCache;
+use crate::tls::acme_certificate_log::list_acme_certificates;
+use crate::tls::acme_smoke_test::run_acme_smoke_test;
+use crate::tls::certificate_export::{CERTIFICATE_EXPORT_FORMATS, CertificateWithKey};
+use crate::tls::certificate_store::{delete_installed_certificate, get_installed_certificate, install_certificate, list_installed_certificates};
+use crate::tls::shared_acme_manager::{get_cache_dir, get_shared_acme_orders, load_acme_certificate_pem};
 use http::HeaderValue;
 use serde::{Deserialize, Serialize};
 use serde_json;
+use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
 use tokio_util::bytes;
 
 const JSON_HEADER_VALUE: HeaderValue = HeaderValue::from_static("application/json");
 const TEXT_PLAIN_HEADER_VALUE: HeaderValue = HeaderValue::from_static("text/plain");
+const CSV_HEADER_VALUE: HeaderValue = HeaderValue::from_static("text/csv; charset=utf-8");
+
+// Routes that delegated admins can use, which are scoped to their sites. All other routes are for full admins only
+const DELEGATED_ADMIN_ROUTES: [&str; 13] = [
+    "/login",
+    "/logout",
+    "/healthcheck",
+    "/config",
+    "/configuration/reload",
+    "/logs",
+    "/usage",
+    "/connections",
+    "/cache-warm",
+    "/deployments",
+    "/disk-usage",
+    "/analytics",
+    "/synthetic-probes",
+];
 
 pub async fn handle_api_routes(gruxi_request: &mut GruxiRequest, site: &Site) -> Result<GruxiResponse, GruxiError> {
     let path = gruxi_request.get_path();
@@ -38,6 +90,22 @@ pub async fn handle_api_routes(gruxi_request: &mut GruxiRequest, site: &Site) ->
 
     trace(format!("Handling request for admin portal with path: {}", path_cleaned));
 
+    // Replicas of a cluster authenticate with the shared cluster token instead of a session
+    if path_cleaned == CLUSTER_CONFIGURATION_PATH && method == "GET" {
+        return admin_get_cluster_configuration_endpoint(gruxi_request, site).await;
+    }
+
+    // The status page is public, when enabled
+    if (path_cleaned == "/status" || path_cleaned == "/status.json") && method == "GET" {
+        return admin_get_status_page_endpoint(gruxi_request, site, path_cleaned == "/status.json").await;
+    }
+
+    if !DELEGATED_ADMIN_ROUTES.iter().any(|route| path_cleaned == *route || path_cleaned.starts_with(&format!("{}/", route)))
+        && let Err(response) = require_full_admin(gruxi_request).await
+    {
+        return Ok(response);
+    }
+
     // We only want to handle a few paths in the admin portal
     let response_result = if path_cleaned == "/login" && method == "POST" {
         handle_login_request(gruxi_request, site).await
@@ -49,6 +117,14 @@ pub async fn handle_api_routes(gruxi_request: &mut GruxiRequest, site: &Site) ->
         admin_get_configuration_endpoint(gruxi_request, site).await
     } else if path_cleaned == "/config" && method == "POST" {
         admin_post_configuration_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/config/preview" && method == "POST" {
+        admin_post_configuration_preview_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/config/export" && method == "GET" {
+        admin_get_configuration_export_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/config/schema" && method == "GET" {
+        admin_get_configuration_schema_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/sites/provision" && method == "POST" {
+        admin_post_site_provision_endpoint(gruxi_request, site).await
     } else if path_cleaned == "/monitoring" && method == "GET" {
         admin_monitoring_endpoint(gruxi_request, site).await
     } else if path_cleaned == "/healthcheck" && method == "GET" {
@@ -61,6 +137,90 @@ pub async fn handle_api_routes(gruxi_request: &mut GruxiRequest, site: &Site) ->
         admin_get_operation_mode_endpoint(gruxi_request, site).await
     } else if path_cleaned == "/operation-mode" && method == "POST" {
         admin_post_operation_mode_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/external-handlers" && method == "GET" {
+        admin_get_external_handlers_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/upload-quarantine" && method == "GET" {
+        admin_get_upload_quarantine_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/upload-quarantine/") && method == "DELETE" {
+        admin_delete_upload_quarantine_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/users" && method == "GET" {
+        admin_get_users_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/users" && method == "POST" {
+        admin_post_user_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/users/") && method == "DELETE" {
+        admin_delete_user_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/usage" && method == "GET" {
+        admin_get_usage_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/connections/") && method == "DELETE" {
+        admin_delete_site_connections_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/cache-warm" && method == "GET" {
+        admin_get_cache_warm_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/cache-warm/") && method == "POST" {
+        admin_post_cache_warm_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/synthetic-probes" && method == "GET" {
+        admin_get_synthetic_probes_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/synthetic-probes/") && path_cleaned.ends_with("/run") && method == "POST" {
+        admin_post_synthetic_probe_run_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/synthetic-probes/") && method == "GET" {
+        admin_get_synthetic_probe_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/deployments/") && path_cleaned.ends_with("/activate") && method == "POST" {
+        admin_post_deployment_activate_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/deployments/") && method == "GET" {
+        admin_get_deployments_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/deployments/") && method == "POST" {
+        admin_post_deployment_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/disk-usage" && method == "GET" {
+        admin_get_disk_usage_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/analytics" && method == "GET" {
+        admin_get_analytics_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/debug-dumps" && method == "GET" {
+        admin_get_debug_dumps_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/debug-dumps/") && method == "POST" {
+        admin_post_debug_dump_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/debug-dumps/") && method == "DELETE" {
+        admin_delete_debug_dump_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/database-backups" && method == "GET" {
+        admin_get_database_backups_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/database-backups" && method == "POST" {
+        admin_post_database_backup_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/database-backups/") && path_cleaned.ends_with("/restore") && method == "POST" {
+        admin_post_database_backup_restore_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/email-alerts/test" && method == "POST" {
+        admin_post_email_alert_test_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/scheduled-changes" && method == "GET" {
+        admin_get_scheduled_changes_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/scheduled-changes" && method == "POST" {
+        admin_post_scheduled_change_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/scheduled-changes/") && method == "DELETE" {
+        admin_delete_scheduled_change_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/scheduled-tasks" && method == "GET" {
+        admin_get_scheduled_tasks_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/scheduled-tasks" && method == "POST" {
+        admin_post_scheduled_task_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/scheduled-tasks/") && path_cleaned.ends_with("/run") && method == "POST" {
+        admin_post_scheduled_task_run_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/scheduled-tasks/") && method == "DELETE" {
+        admin_delete_scheduled_task_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/certificates/acme" && method == "GET" {
+        admin_get_acme_certificates_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/certificates/acme/log" && method == "GET" {
+        admin_get_acme_certificate_log_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/certificates/acme/export" && method == "POST" {
+        admin_post_acme_certificate_export_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/certificates/acme/smoke-test" && method == "POST" {
+        admin_post_acme_smoke_test_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/certificates/acme/account-key" && method == "GET" {
+        admin_get_acme_account_key_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/certificates/acme/account-key" && method == "POST" {
+        admin_post_acme_account_key_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/certificates" && method == "GET" {
+        admin_get_installed_certificates_endpoint(gruxi_request, site).await
+    } else if path_cleaned == "/certificates" && method == "POST" {
+        admin_post_installed_certificate_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/certificates/") && path_cleaned.ends_with("/assign") && method == "POST" {
+        admin_post_installed_certificate_assign_endpoint(gruxi_request, site).await
+    } else if path_cleaned.starts_with("/certificates/") && method == "DELETE" {
+        admin_delete_installed_certificate_endpoint(gruxi_request, site).await
     } else {
         // If we reach here, no matching admin API route was found
         trace(format!("No matching admin API route found for path: {}", path_cleaned));
@@ -94,8 +254,8 @@ pub async fn handle_login_request(gruxi_request: &mut GruxiRequest, _admin_site:
 
     debug(format!("Login attempt for username: {}", login_request.username));
 
-    // Authenticate user
-    let user = match authenticate_user(&login_request.username, &login_request.password) {
+    // Authenticate user, with the auth provider selected for the admin portal
+    let user = match authenticate_admin_user(&login_request.username, &login_request.password).await {
         Ok(Some(user)) => user,
         Ok(None) => {
             info(format!("Failed login attempt for username: {}", login_request.username));
@@ -104,15 +264,16 @@ pub async fn handle_login_request(gruxi_request: &mut GruxiRequest, _admin_site:
             return Ok(response);
         }
         Err(e) => {
-            error(format!("Database error during authentication: {}", e));
+            error(format!("Error during authentication: {}", e));
             let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(r#"{"error": "Internal server error"}"#));
             response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
             return Ok(response);
         }
     };
 
-    // Create session
-    let session = match create_session(&user) {
+    // Create session, bound to the client if configured
+    let (shared_session_database, client_binding) = get_session_settings(gruxi_request).await;
+    let session = match create_session(&user, &shared_session_database, &client_binding) {
         Ok(session) => session,
         Err(e) => {
             error(format!("Failed to create session: {}", e));
@@ -125,13 +286,20 @@ pub async fn handle_login_request(gruxi_request: &mut GruxiRequest, _admin_site:
 
     info(format!("Successful login for user: {}", user.username));
 
+    // The site scope tells the client whether the user is a delegated admin, and of which sites
+    let site_scope = get_user_site_scope(&user.username).unwrap_or_else(|e| {
+        error(format!("Failed to get site scope for user {}: {}", user.username, e));
+        None
+    });
+
     // Return success response with session token
     let response_json = serde_json::json!({
         "success": true,
         "message": "Login successful",
         "session_token": session.token,
         "username": session.username,
-        "expires_at": session.expires_at.to_rfc3339()
+        "expires_at": session.expires_at.to_rfc3339(),
+        "site_scope": site_scope
     });
 
     let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
@@ -151,7 +319,8 @@ pub async fn handle_logout_request(gruxi_request: &mut GruxiRequest, _admin_site
     let token = get_session_token_from_request(&gruxi_request).await;
 
     if let Some(token) = token {
-        match invalidate_session(&token) {
+        let (shared_session_database, _) = get_session_settings(gruxi_request).await;
+        match invalidate_session(&token, &shared_session_database) {
             Ok(true) => {
                 info("Successfully logged out session".to_string());
                 let response_json = serde_json::json!({
@@ -182,23 +351,17 @@ pub async fn handle_logout_request(gruxi_request: &mut GruxiRequest, _admin_site
 }
 
 pub async fn admin_get_configuration_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
-    // Check authentication first
-    match require_authentication(&gruxi_request).await {
-        Ok(Some(_session)) => {
-            // User is authenticated, proceed with getting configuration
+    // Check authentication first, delegated admins only get their own sites
+    let site_scope = match require_site_scope(gruxi_request).await {
+        Ok((_session, site_scope)) => {
             debug("User authenticated, retrieving configuration".to_string());
-        }
-        Ok(None) => {
-            // This shouldn't happen as require_authentication returns error for None
-            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::UNAUTHORIZED.as_u16(), bytes::Bytes::from(r#"{"error": "Authentication required"}"#));
-            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
-            return Ok(response);
+            site_scope
         }
         Err(auth_response) => {
             // Authentication failed, return the auth error response
             return Ok(auth_response);
         }
-    }
+    };
 
     // Get configuration
     let config_result = crate::configuration::load_configuration::fetch_configuration_in_db();
@@ -206,13 +369,20 @@ pub async fn admin_get_configuration_endpoint(gruxi_request: &mut GruxiRequest,
         Ok(cfg) => cfg,
         Err(e) => {
             error(format!("Failed to retrieve configuration from database: {}", e));
-            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(r#"{"error": "Failed to retrieve configuration"}"#));
+            let mut response = GruxiResponse::new_with_bytes(
+                hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                bytes::Bytes::from(r#"{"error": "Failed to retrieve configuration"}"#),
+            );
             response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
             return Ok(response);
         }
     };
 
-    let json_config = match serde_json::to_string_pretty(&config) {
+    let json_config_result = match &site_scope {
+        Some(site_scope) => serde_json::to_string_pretty(&site_scope.filter_configuration(&config)),
+        None => serde_json::to_string_pretty(&config),
+    };
+    let json_config = match json_config_result {
         Ok(json) => json,
         Err(e) => {
             error(format!("Failed to serialize configuration: {}", e));
@@ -230,6 +400,17 @@ pub async fn admin_get_configuration_endpoint(gruxi_request: &mut GruxiRequest,
     return Ok(response);
 }
 
+// JSON Schema of the configuration, which is the same for delegated admins, as it holds no sites
+pub async fn admin_get_configuration_schema_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    if let Err(auth_response) = require_site_scope(gruxi_request).await {
+        return Ok(auth_response);
+    }
+
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(Configuration::get_json_schema().to_string()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
 pub async fn admin_post_configuration_reload(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
     // Check authentication first
     match require_authentication(&gruxi_request).await {
@@ -274,20 +455,16 @@ pub async fn admin_post_configuration_endpoint(gruxi_request: &mut GruxiRequest,
         return Ok(response);
     }
 
-    // Check authentication first
-    match require_authentication(&gruxi_request).await {
-        Ok(Some(_session)) => {
+    // Check authentication first, delegated admins only submit their own sites
+    let (session, mut site_scope) = match require_site_scope(gruxi_request).await {
+        Ok(result) => {
             debug("User authenticated for configuration update".to_string());
-        }
-        Ok(None) => {
-            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::UNAUTHORIZED.as_u16(), bytes::Bytes::from(r#"{"error": "Authentication required"}"#));
-            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
-            return Ok(response);
+            result
         }
         Err(auth_response) => {
             return Ok(auth_response);
         }
-    }
+    };
 
     // Read the request body
     if gruxi_request.get_body_size() == 0 {
@@ -298,28 +475,34 @@ pub async fn admin_post_configuration_endpoint(gruxi_request: &mut GruxiRequest,
     let body_bytes = gruxi_request.get_body_bytes().await;
 
     // Parse JSON body into Configuration struct
-    let mut configuration: Configuration = match serde_json::from_slice(&body_bytes) {
-        Ok(config) => config,
-        Err(e) => {
-            error(format!("Failed to parse configuration JSON: {}", e));
-            let error_response = serde_json::json!({
-                "error": "Invalid JSON format",
-                "details": e.to_string()
-            });
-
-            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
-            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
-            return Ok(response);
-        }
+    let (mut configuration, new_site_ids) = match parse_submitted_configuration(&body_bytes, site_scope.as_ref()).await {
+        Ok(result) => result,
+        Err(response) => return Ok(response),
     };
 
+    // Keep the configuration before saving, to report the impact of the change
+    let current_configuration_result = fetch_configuration_in_db();
+
     // Save the configuration
     match save_configuration(&mut configuration, false) {
         Ok(true) => {
             info("Configuration updated successfully".to_string());
+            let impact = current_configuration_result
+                .ok()
+                .map(|current_configuration| analyze_configuration_impact(&current_configuration, &configuration));
+
+            // Sites created by a delegated admin are theirs to manage from now on
+            if let Some(site_scope) = &mut site_scope
+                && !new_site_ids.is_empty()
+            {
+                if let Err(e) = add_sites_to_user_scope(&session.username, &new_site_ids) {
+                    error(format!("Failed to add new sites to the scope of user {}: {}", session.username, e));
+                }
+                site_scope.add_sites(&new_site_ids);
+            }
 
             // Serialize the sanitized configuration to return to the client
-            let config_json = match serde_json::to_value(&configuration) {
+            let config_json = match get_configuration_json(&configuration, site_scope.as_ref()) {
                 Ok(json) => json,
                 Err(e) => {
                     error(format!("Failed to serialize updated configuration: {}", e));
@@ -336,7 +519,8 @@ pub async fn admin_post_configuration_endpoint(gruxi_request: &mut GruxiRequest,
             let success_response = serde_json::json!({
                 "success": true,
                 "message": "Configuration updated successfully. Please restart the server for changes to take effect.",
-                "configuration": config_json
+                "configuration": config_json,
+                "impact": impact
             });
 
             let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(success_response.to_string()));
@@ -347,7 +531,7 @@ pub async fn admin_post_configuration_endpoint(gruxi_request: &mut GruxiRequest,
             info("Configuration save requested, but no changes detected".to_string());
 
             // Even if no changes were made, return the current configuration
-            let config_json = match serde_json::to_value(&configuration) {
+            let config_json = match get_configuration_json(&configuration, site_scope.as_ref()) {
                 Ok(json) => json,
                 Err(e) => {
                     error(format!("Failed to serialize configuration: {}", e));
@@ -370,16 +554,302 @@ pub async fn admin_post_configuration_endpoint(gruxi_request: &mut GruxiRequest,
             response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
             return Ok(response);
         }
-        Err(validation_errors) => {
-            info(format!("Configuration validation failed: {}", validation_errors.join("; ")));
+        Err(save_error) => Ok(get_save_configuration_error_response(save_error)),
+    }
+}
+
+// A configuration that failed validation is answered with 400 and its problems, a failure to store it with 500 or 503
+fn get_save_configuration_error_response(save_error: GruxiError) -> GruxiResponse {
+    let status_code = save_error.get_http_status_code();
+    if status_code == hyper::StatusCode::BAD_REQUEST.as_u16() {
+        info(format!("Configuration validation failed: {}", save_error));
+    } else {
+        error(format!("Failed to save configuration: {}", save_error));
+    }
+    let error_response = serde_json::json!({
+        "errors": save_error.get_messages()
+    });
+
+    let mut response = GruxiResponse::new_with_bytes(status_code, bytes::Bytes::from(error_response.to_string()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    response
+}
+
+// Configuration of a cluster primary, polled by its replicas with the shared token in the X-Gruxi-Cluster-Token header
+pub async fn admin_get_cluster_configuration_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let cluster_sync = {
+        let cached_configuration = crate::configuration::cached_configuration::get_cached_configuration();
+        cached_configuration.get_configuration().await.core.cluster_sync.clone()
+    };
+    let token = gruxi_request.get_headers().get(CLUSTER_TOKEN_HEADER).and_then(|v| v.to_str().ok()).unwrap_or("");
+    if !cluster_sync.is_primary() || !is_valid_cluster_token(&cluster_sync, token) {
+        info(format!(
+            "Cluster configuration was refused to {}, with an invalid token or as this instance is not a primary",
+            gruxi_request.get_remote_ip()
+        ));
+        let mut response = GruxiResponse::new_with_bytes(
+            hyper::StatusCode::FORBIDDEN.as_u16(),
+            bytes::Bytes::from(r#"{"error": "Not a cluster primary, or invalid cluster token"}"#),
+        );
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+
+    let config = match crate::configuration::load_configuration::fetch_configuration_in_db() {
+        Ok(cfg) => get_configuration_for_replicas(cfg),
+        Err(e) => {
+            error(format!("Failed to retrieve configuration from database: {}", e));
+            let mut response = GruxiResponse::new_with_bytes(
+                hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                bytes::Bytes::from(r#"{"error": "Failed to retrieve configuration"}"#),
+            );
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(serde_json::to_string(&config).unwrap_or_default()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Export the configuration as a download: /config/export?format=json|nginx|caddy|docker-compose
+// Only for full admins, as the export covers all sites and handlers
+pub async fn admin_get_configuration_export_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    if let Err(auth_response) = require_full_admin(gruxi_request).await {
+        return Ok(auth_response);
+    }
+
+    let query = gruxi_request.get_query();
+    let format = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "format")
+        .map(|(_, value)| urlencoding::decode(value).map(|v| v.to_string()).unwrap_or_default())
+        .unwrap_or("json".to_string());
+    let (file_name, content_type) = match format.as_str() {
+        "nginx" => ("gruxi-nginx.conf", TEXT_PLAIN_HEADER_VALUE),
+        "caddy" => ("Caddyfile", TEXT_PLAIN_HEADER_VALUE),
+        "docker-compose" => ("docker-compose.yml", TEXT_PLAIN_HEADER_VALUE),
+        _ => ("gruxi-configuration.json", JSON_HEADER_VALUE),
+    };
+
+    let config = match crate::configuration::load_configuration::fetch_configuration_in_db() {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            error(format!("Failed to retrieve configuration from database: {}", e));
+            let mut response = GruxiResponse::new_with_bytes(
+                hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                bytes::Bytes::from(r#"{"error": "Failed to retrieve configuration"}"#),
+            );
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    match crate::configuration::export_formats::render_configuration(&config, &format) {
+        Ok(exported) => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(exported));
+            response.headers_mut().insert("Content-Type", content_type);
+            if let Ok(value) = HeaderValue::from_str(&format!("attachment; filename=\"{}\"", file_name)) {
+                response.headers_mut().insert("Content-Disposition", value);
+            }
+            Ok(response)
+        }
+        Err(e) => {
+            let error_response = serde_json::json!({ "error": e });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Validate a configuration and report what applying it would change, without saving it
+pub async fn admin_post_configuration_preview_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let site_scope = match require_site_scope(gruxi_request).await {
+        Ok((_session, site_scope)) => site_scope,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    if gruxi_request.get_body_size() == 0 {
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(r#"{"error": "Empty request body"}"#));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+    let body_bytes = gruxi_request.get_body_bytes().await;
+
+    let (mut configuration, _new_site_ids) = match parse_submitted_configuration(&body_bytes, site_scope.as_ref()).await {
+        Ok(result) => result,
+        Err(response) => return Ok(response),
+    };
+
+    // Same checks as when saving, so the preview is of the configuration that would actually be applied
+    configuration.sanitize();
+    if let Err(validation_errors) = configuration.validate() {
+        let error_response = serde_json::json!({
+            "errors": validation_errors
+        });
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+
+    let current_configuration = match fetch_configuration_in_db() {
+        Ok(current_configuration) => current_configuration,
+        Err(e) => {
+            error(format!("Failed to fetch current configuration for preview: {}", e));
+            let mut response = GruxiResponse::new_with_bytes(
+                hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                bytes::Bytes::from(r#"{"error": "Failed to fetch current configuration"}"#),
+            );
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    // Deprecated fields are accepted, but reported so the client can switch to the current names
+    let deprecations = serde_json::from_slice::<serde_json::Value>(&body_bytes)
+        .map(|submitted| find_deprecated_fields(&submitted))
+        .unwrap_or_default();
+
+    let preview_response = serde_json::json!({
+        "success": true,
+        "impact": analyze_configuration_impact(&current_configuration, &configuration),
+        "deprecations": deprecations
+    });
+
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(preview_response.to_string()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Admin site provision POST endpoint - adds a site from a template in one call, with its request handlers, processors
+// and bindings, and creates its web root with a default index file: /sites/provision
+pub async fn admin_post_site_provision_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let errors_response = |status: hyper::StatusCode, errors: Vec<String>| {
+        let error_response = serde_json::json!({
+            "errors": errors
+        });
+        let mut response = GruxiResponse::new_with_bytes(status.as_u16(), bytes::Bytes::from(error_response.to_string()));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        response
+    };
+
+    let body_bytes = gruxi_request.get_body_bytes().await;
+    let mut template_request: SiteTemplateRequest = match serde_json::from_slice(&body_bytes) {
+        Ok(template_request) => template_request,
+        Err(e) => {
             let error_response = serde_json::json!({
-                "errors": validation_errors
+                "error": "Invalid JSON format",
+                "details": e.to_string()
             });
-
             let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
             response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
             return Ok(response);
         }
+    };
+    template_request.sanitize();
+
+    let mut configuration = match fetch_configuration_in_db() {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            error(format!("Failed to fetch configuration to provision a site in: {}", e));
+            return Ok(errors_response(hyper::StatusCode::INTERNAL_SERVER_ERROR, vec!["Failed to fetch current configuration".to_string()]));
+        }
+    };
+    if let Err(errors) = template_request.validate(&configuration) {
+        return Ok(errors_response(hyper::StatusCode::BAD_REQUEST, errors));
+    }
+
+    // The configuration is validated before the web root is created, so a rejected site leaves nothing behind
+    let site_id = add_site_from_template(&mut configuration, &template_request);
+    configuration.sanitize();
+    if let Err(errors) = configuration.validate() {
+        return Ok(errors_response(hyper::StatusCode::BAD_REQUEST, errors));
+    }
+    let created_files = match create_web_root(&template_request) {
+        Ok(created_files) => created_files,
+        Err(e) => {
+            error(format!("Failed to create the web root of a provisioned site: {}", e));
+            return Ok(errors_response(hyper::StatusCode::INTERNAL_SERVER_ERROR, vec![e]));
+        }
+    };
+
+    if let Err(save_error) = save_configuration(&mut configuration, false) {
+        return Ok(get_save_configuration_error_response(save_error));
+    }
+    info(format!(
+        "Site {} was provisioned from the '{}' template by {}",
+        template_request.hostnames.join(", "),
+        template_request.template,
+        session.username
+    ));
+
+    let site = configuration.sites.iter().find(|site| site.id == site_id);
+    let success_response = serde_json::json!({
+        "success": true,
+        "message": "Site provisioned successfully. Please restart the server for changes to take effect.",
+        "site": site,
+        "web_root": template_request.web_root,
+        "created_files": created_files
+    });
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::CREATED.as_u16(), bytes::Bytes::from(success_response.to_string()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Parse a submitted configuration. Delegated admins submit only their own sites, which are merged into the current
+// configuration. Returns the full configuration and the IDs of sites the delegated admin added
+async fn parse_submitted_configuration(body_bytes: &[u8], site_scope: Option<&SiteScope>) -> Result<(Configuration, Vec<String>), GruxiResponse> {
+    let invalid_json_response = |e: serde_json::Error| {
+        error(format!("Failed to parse configuration JSON: {}", e));
+        let error_response = serde_json::json!({
+            "error": "Invalid JSON format",
+            "details": e.to_string()
+        });
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        response
+    };
+
+    let Some(site_scope) = site_scope else {
+        let configuration: Configuration = serde_json::from_slice(body_bytes).map_err(invalid_json_response)?;
+        return Ok((configuration, Vec::new()));
+    };
+
+    let submitted: ScopedConfiguration = serde_json::from_slice(body_bytes).map_err(invalid_json_response)?;
+    let current_configuration = fetch_configuration_in_db().map_err(|e| {
+        error(format!("Failed to fetch current configuration to merge the sites into: {}", e));
+        let mut response = GruxiResponse::new_with_bytes(
+            hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            bytes::Bytes::from(r#"{"error": "Failed to fetch current configuration"}"#),
+        );
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        response
+    })?;
+
+    site_scope.merge_configuration(current_configuration, submitted).map_err(|errors| {
+        info(format!("Scoped configuration rejected: {}", errors.join("; ")));
+        let error_response = serde_json::json!({
+            "errors": errors
+        });
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        response
+    })
+}
+
+// The configuration to return to the client, limited to the sites in scope for delegated admins
+fn get_configuration_json(configuration: &Configuration, site_scope: Option<&SiteScope>) -> Result<serde_json::Value, serde_json::Error> {
+    match site_scope {
+        Some(site_scope) => Ok(site_scope.filter_configuration(configuration)),
+        None => serde_json::to_value(configuration),
     }
 }
 
@@ -398,8 +868,38 @@ async fn get_session_token_from_request(gruxi_request: &GruxiRequest) -> Option<
 }
 
 // Helper function to verify session token and return session info
-pub fn verify_session(token: &str) -> Result<Option<crate::core::admin_user::Session>, String> {
-    verify_session_token(token)
+pub fn verify_session(token: &str, shared_session_database: &str, client_binding: &str) -> Result<Option<crate::core::admin_user::Session>, String> {
+    verify_session_token(token, shared_session_database, client_binding)
+}
+
+// The shared session database, if any, and the client binding for sessions of this request, based on the admin portal settings
+async fn get_session_settings(gruxi_request: &GruxiRequest) -> (String, String) {
+    let admin_portal = get_cached_configuration().get_configuration().await.core.admin_portal.clone();
+
+    let client_ip = if admin_portal.session_bind_ip {
+        Some(get_session_client_ip(gruxi_request, &admin_portal.session_client_ip_header))
+    } else {
+        None
+    };
+    let user_agent = if admin_portal.session_bind_user_agent {
+        Some(gruxi_request.get_headers().get(http::header::USER_AGENT).and_then(|v| v.to_str().ok()).unwrap_or("").to_string())
+    } else {
+        None
+    };
+
+    (admin_portal.shared_session_database, get_client_binding(client_ip.as_deref(), user_agent.as_deref()))
+}
+
+// Behind a load balancer, the client IP is taken from the last entry of the configured header, which is the one added by the load balancer itself
+fn get_session_client_ip(gruxi_request: &GruxiRequest, client_ip_header: &str) -> String {
+    if !client_ip_header.is_empty()
+        && let Some(header_value) = gruxi_request.get_headers().get(client_ip_header).and_then(|v| v.to_str().ok())
+        && let Some(client_ip) = header_value.rsplit(',').next().map(|ip| ip.trim())
+        && !client_ip.is_empty()
+    {
+        return client_ip.to_string();
+    }
+    gruxi_request.get_calculated_data("remote_ip").unwrap_or_default()
 }
 
 // Middleware-like function to check if request is authenticated
@@ -407,7 +907,8 @@ pub async fn require_authentication(gruxi_request: &GruxiRequest) -> Result<Opti
     let token = get_session_token_from_request(gruxi_request).await;
 
     if let Some(token) = token {
-        match verify_session(&token) {
+        let (shared_session_database, client_binding) = get_session_settings(gruxi_request).await;
+        match verify_session(&token, &shared_session_database, &client_binding) {
             Ok(Some(session)) => Ok(Some(session)),
             Ok(None) => {
                 let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::UNAUTHORIZED.as_u16(), bytes::Bytes::from(r#"{"error": "Invalid or expired session"}"#));
@@ -428,6 +929,41 @@ pub async fn require_authentication(gruxi_request: &GruxiRequest) -> Result<Opti
     }
 }
 
+// Like require_authentication, and also returns the sites the user is delegated to manage. The scope is None for full admins
+pub async fn require_site_scope(gruxi_request: &GruxiRequest) -> Result<(Session, Option<SiteScope>), GruxiResponse> {
+    let session = match require_authentication(gruxi_request).await? {
+        Some(session) => session,
+        None => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::UNAUTHORIZED.as_u16(), bytes::Bytes::from(r#"{"error": "Authentication required"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Err(response);
+        }
+    };
+
+    match SiteScope::for_user(&session.username) {
+        Ok(site_scope) => Ok((session, site_scope)),
+        Err(e) => {
+            error(format!("Failed to get site scope for user {}: {}", session.username, e));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(r#"{"error": "Internal server error"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Err(response)
+        }
+    }
+}
+
+// Like require_authentication, but delegated admins are refused
+pub async fn require_full_admin(gruxi_request: &GruxiRequest) -> Result<Session, GruxiResponse> {
+    match require_site_scope(gruxi_request).await? {
+        (session, None) => Ok(session),
+        (session, Some(_)) => {
+            info(format!("Delegated admin {} was refused access to {}", session.username, gruxi_request.get_uri_struct().path()));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::FORBIDDEN.as_u16(), bytes::Bytes::from(r#"{"error": "Not available to delegated admins"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Err(response)
+        }
+    }
+}
+
 // Admin monitoring endpoint - returns monitoring data as JSON
 pub async fn admin_monitoring_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
     // Check authentication first
@@ -486,22 +1022,43 @@ pub async fn admin_healthcheck_endpoint(_gruxi_request: &mut GruxiRequest, _admi
     return Ok(response);
 }
 
+// Public status page, as HTML or as JSON, without authentication. Not found when the status page is disabled
+pub async fn admin_get_status_page_endpoint(_gruxi_request: &mut GruxiRequest, _admin_site: &Site, as_json: bool) -> Result<GruxiResponse, GruxiError> {
+    let configuration = get_cached_configuration().get_configuration().await;
+    if !configuration.core.status_page.is_enabled {
+        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::NOT_FOUND.as_u16()));
+    }
+
+    let uptime_seconds = get_monitoring_state().await.get_uptime_seconds();
+    let status_page = build_status_page(&configuration, uptime_seconds, &get_admin_alerts());
+    let mut response = if as_json {
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(serde_json::to_string(&status_page).unwrap_or_default()));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        response
+    } else {
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(render_status_page_html(&status_page)));
+        response.headers_mut().insert("Content-Type", HeaderValue::from_static("text/html; charset=utf-8"));
+        response
+    };
+    response.headers_mut().insert("Cache-Control", HeaderValue::from_static("no-cache"));
+    Ok(response)
+}
+
 // Admin logs endpoint - lists available log files or returns specific log content
 pub async fn admin_logs_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
-    // Check authentication first
-    match require_authentication(&gruxi_request).await {
-        Ok(Some(_session)) => {
+    // Check authentication first, delegated admins only get the access logs of their own sites
+    let visible_log_files = match require_site_scope(gruxi_request).await {
+        Ok((_session, site_scope)) => {
             debug("User authenticated, retrieving logs".to_string());
-        }
-        Ok(None) => {
-            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::UNAUTHORIZED.as_u16(), bytes::Bytes::from(r#"{"error": "Authentication required"}"#));
-            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
-            return Ok(response);
+            match site_scope {
+                Some(site_scope) => Some(site_scope.get_log_file_names(&*get_cached_configuration().get_configuration().await)),
+                None => None,
+            }
         }
         Err(auth_response) => {
             return Ok(auth_response);
         }
-    }
+    };
 
     let path = gruxi_request.get_path();
     let path_parts: Vec<&str> = path.split('/').collect();
@@ -509,11 +1066,11 @@ pub async fn admin_logs_endpoint(gruxi_request: &mut GruxiRequest, _admin_site:
     // Parse the request path: /logs or /logs/{filename}
     if path_parts.len() == 2 && path_parts[1] == "logs" {
         // List all available log files
-        list_log_files().await
+        list_log_files(visible_log_files.as_ref()).await
     } else if path_parts.len() == 3 && path_parts[1] == "logs" {
         // Return specific log file content
         let filename = path_parts[2];
-        get_log_file_content(filename).await
+        get_log_file_content(filename, visible_log_files.as_ref()).await
     } else {
         let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(r#"{"error": "Invalid logs endpoint path"}"#));
         response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
@@ -521,8 +1078,8 @@ pub async fn admin_logs_endpoint(gruxi_request: &mut GruxiRequest, _admin_site:
     }
 }
 
-// Helper function to list all .log files in the logs directory
-async fn list_log_files() -> Result<GruxiResponse, GruxiError> {
+// Helper function to list all .log files in the logs directory, or only the visible ones if given
+async fn list_log_files(visible_log_files: Option<&HashSet<String>>) -> Result<GruxiResponse, GruxiError> {
     let logs_dir = Path::new("logs");
 
     match fs::read_dir(logs_dir) {
@@ -536,6 +1093,9 @@ async fn list_log_files() -> Result<GruxiResponse, GruxiError> {
                         if extension == "log" {
                             if let Some(filename) = path.file_name() {
                                 if let Some(filename_str) = filename.to_str() {
+                                    if visible_log_files.is_some_and(|visible_log_files| !visible_log_files.contains(filename_str)) {
+                                        continue;
+                                    }
                                     let metadata = fs::metadata(&path);
                                     let file_size = metadata.map(|m| m.len()).unwrap_or(0);
 
@@ -575,7 +1135,7 @@ async fn list_log_files() -> Result<GruxiResponse, GruxiError> {
 }
 
 // Helper function to get log file content with 1MB limit
-async fn get_log_file_content(filename: &str) -> Result<GruxiResponse, GruxiError> {
+async fn get_log_file_content(filename: &str, visible_log_files: Option<&HashSet<String>>) -> Result<GruxiResponse, GruxiError> {
     // Validate filename to prevent directory traversal
     if filename.contains("..") || filename.contains("/") || filename.contains("\\") {
         let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(r#"{"error": "Invalid filename"}"#));
@@ -592,7 +1152,8 @@ async fn get_log_file_content(filename: &str) -> Result<GruxiResponse, GruxiErro
 
     let log_path = Path::new("logs").join(filename);
 
-    if !log_path.exists() {
+    // Log files that are not visible are reported as not found, so their existence is not revealed
+    if !log_path.exists() || visible_log_files.is_some_and(|visible_log_files| !visible_log_files.contains(filename)) {
         let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::NOT_FOUND.as_u16(), bytes::Bytes::from(r#"{"error": "Log file not found"}"#));
         response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
         return Ok(response);
@@ -797,3 +1358,1757 @@ pub async fn admin_post_operation_mode_endpoint(gruxi_request: &mut GruxiRequest
     response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
     return Ok(response);
 }
+
+// Admin upload quarantine GET endpoint - lists uploads rejected by upload scanning
+pub async fn admin_get_upload_quarantine_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    // Check authentication first
+    match require_authentication(gruxi_request).await {
+        Ok(Some(_session)) => {
+            debug("User authenticated, retrieving upload quarantine".to_string());
+        }
+        Ok(None) => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::UNAUTHORIZED.as_u16(), bytes::Bytes::from(r#"{"error": "Authentication required"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+        Err(auth_response) => {
+            return Ok(auth_response);
+        }
+    }
+
+    let upload_scanning = get_cached_configuration().get_configuration().await.core.upload_scanning.clone();
+
+    match list_quarantine(&upload_scanning) {
+        Ok(entries) => {
+            let response_json = serde_json::json!({
+                "success": true,
+                "is_enabled": upload_scanning.is_enabled,
+                "entries": entries
+            });
+
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            error(format!("Failed to read upload quarantine directory: {}", e));
+            let error_response = serde_json::json!({
+                "error": "Failed to read upload quarantine directory",
+                "details": e.to_string()
+            });
+
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin external handlers endpoint - returns the runtime status of the PHP-CGI, Python and Node.js handlers managed by Gruxi
+pub async fn admin_get_external_handlers_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    // Check authentication first
+    match require_authentication(gruxi_request).await {
+        Ok(Some(_session)) => {
+            debug("User authenticated, retrieving external handler status".to_string());
+        }
+        Ok(None) => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::UNAUTHORIZED.as_u16(), bytes::Bytes::from(r#"{"error": "Authentication required"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+        Err(auth_response) => {
+            return Ok(auth_response);
+        }
+    }
+
+    let handlers = {
+        let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+        running_state.get_external_system_handler().get_handlers_status_json()
+    };
+
+    let response_json = serde_json::json!({
+        "success": true,
+        "handlers": handlers
+    });
+
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Admin upload quarantine DELETE endpoint - permanently deletes a quarantined upload: /upload-quarantine/{id}
+pub async fn admin_delete_upload_quarantine_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    // Check authentication first
+    match require_authentication(gruxi_request).await {
+        Ok(Some(_session)) => {
+            debug("User authenticated for upload quarantine deletion".to_string());
+        }
+        Ok(None) => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::UNAUTHORIZED.as_u16(), bytes::Bytes::from(r#"{"error": "Authentication required"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+        Err(auth_response) => {
+            return Ok(auth_response);
+        }
+    }
+
+    let path = gruxi_request.get_path();
+    let id = path.trim_start_matches("/upload-quarantine/").to_string();
+    let upload_scanning = get_cached_configuration().get_configuration().await.core.upload_scanning.clone();
+
+    match delete_quarantine_entry(&upload_scanning, &id) {
+        Ok(true) => {
+            info(format!("Quarantined upload '{}' was deleted through the admin portal", id));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(r#"{"success": true}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Ok(false) => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::NOT_FOUND.as_u16(), bytes::Bytes::from(r#"{"error": "Quarantined upload not found"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            error(format!("Failed to delete quarantined upload '{}': {}", id, e));
+            let mut response = GruxiResponse::new_with_bytes(
+                hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                bytes::Bytes::from(r#"{"error": "Failed to delete quarantined upload"}"#),
+            );
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// A user to create or update. A list of site IDs makes the user a delegated admin of those sites, no list a full admin
+#[derive(Deserialize)]
+struct UserRequest {
+    username: String,
+    #[serde(default)]
+    password: Option<String>, // Only needed for new users, or to change the password
+    #[serde(default = "default_user_is_active")]
+    is_active: bool,
+    #[serde(default)]
+    site_ids: Option<Vec<String>>,
+}
+
+fn default_user_is_active() -> bool {
+    true
+}
+
+// Admin users GET endpoint - lists the users of the admin portal and the sites delegated admins manage
+pub async fn admin_get_users_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    if let Err(auth_response) = require_full_admin(gruxi_request).await {
+        return Ok(auth_response);
+    }
+
+    match list_users() {
+        Ok(users) => {
+            let response_json = serde_json::json!({
+                "success": true,
+                "users": users
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            error(format!("Failed to list users: {}", e));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(r#"{"error": "Failed to list users"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin users POST endpoint - creates or updates a user
+pub async fn admin_post_user_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let body_bytes = gruxi_request.get_body_bytes().await;
+    let user_request: UserRequest = match serde_json::from_slice(&body_bytes) {
+        Ok(user_request) => user_request,
+        Err(e) => {
+            let error_response = serde_json::json!({
+                "error": "Invalid JSON format",
+                "details": e.to_string()
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    let username = user_request.username.trim().to_string();
+    let mut errors = Vec::new();
+    if username.is_empty() || username.len() > 100 || username.chars().any(|c| c.is_control()) {
+        errors.push("Username must be 1 to 100 characters, without control characters".to_string());
+    }
+    if let Some(password) = &user_request.password
+        && password.len() < 8
+    {
+        errors.push("Password must be at least 8 characters".to_string());
+    }
+    // The built-in admin and the current user are kept as active full admins, so nobody can lock themselves out
+    if (username == "admin" || username == session.username) && (user_request.site_ids.is_some() || !user_request.is_active) {
+        errors.push(format!("User '{}' has to stay an active full admin", username));
+    }
+    if let Some(site_ids) = &user_request.site_ids {
+        match fetch_configuration_in_db() {
+            Ok(configuration) => {
+                for site_id in site_ids {
+                    if !configuration.sites.iter().any(|site| &site.id == site_id) {
+                        errors.push(format!("Site '{}' does not exist", site_id));
+                    }
+                }
+            }
+            Err(e) => errors.push(format!("Failed to fetch configuration to check the sites: {}", e)),
+        }
+    }
+    if errors.is_empty()
+        && let Err(e) = save_user(&username, user_request.password.as_deref(), user_request.is_active, &user_request.site_ids)
+    {
+        errors.push(e);
+    }
+
+    if !errors.is_empty() {
+        let error_response = serde_json::json!({
+            "errors": errors
+        });
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+
+    info(format!("User '{}' was saved by {}", username, session.username));
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(r#"{"success": true}"#));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Admin users DELETE endpoint - deletes a user: /users/{username}
+pub async fn admin_delete_user_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let path = gruxi_request.get_path();
+    let username = urlencoding::decode(path.trim_start_matches("/users/")).map(|u| u.to_string()).unwrap_or_default();
+
+    if username == "admin" || username == session.username {
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(r#"{"error": "This user cannot be deleted"}"#));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+
+    match delete_user(&username) {
+        Ok(true) => {
+            info(format!("User '{}' was deleted by {}", username, session.username));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(r#"{"success": true}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Ok(false) => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::NOT_FOUND.as_u16(), bytes::Bytes::from(r#"{"error": "User not found"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            error(format!("Failed to delete user '{}': {}", username, e));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(r#"{"error": "Failed to delete user"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin usage endpoint - returns the usage per site for a period, as JSON or as CSV with format=csv.
+// The period is given with from and to (YYYY-MM-DD, both inclusive), and defaults to the last finished billing period.
+// Delegated admins only get the usage of their own sites
+pub async fn admin_get_usage_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let site_scope = match require_site_scope(gruxi_request).await {
+        Ok((_session, site_scope)) => site_scope,
+        Err(auth_response) => {
+            return Ok(auth_response);
+        }
+    };
+
+    let query = gruxi_request.get_query();
+    let get_query_value = |name: &str| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| urlencoding::decode(value).map(|v| v.to_string()).unwrap_or_default())
+    };
+
+    let (start_day, usage_sites) = {
+        let configuration = get_cached_configuration().get_configuration().await;
+        let usage_sites = get_usage_sites(&configuration, |site| site_scope.as_ref().is_none_or(|site_scope| site_scope.contains_site(&site.id)));
+        (configuration.core.usage_reports.billing_period_start_day, usage_sites)
+    };
+    let period = match (get_query_value("from"), get_query_value("to")) {
+        (None, None) => UsagePeriod::containing(chrono::Utc::now().date_naive(), start_day).previous(start_day),
+        (Some(from), Some(to)) => match (chrono::NaiveDate::parse_from_str(&from, "%Y-%m-%d"), chrono::NaiveDate::parse_from_str(&to, "%Y-%m-%d")) {
+            (Ok(start), Ok(end)) if start <= end => UsagePeriod { start, end },
+            _ => {
+                let mut response = GruxiResponse::new_with_bytes(
+                    hyper::StatusCode::BAD_REQUEST.as_u16(),
+                    bytes::Bytes::from(r#"{"error": "Invalid period, 'from' and 'to' must be dates as YYYY-MM-DD, with 'from' not after 'to'"}"#),
+                );
+                response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+                return Ok(response);
+            }
+        },
+        _ => {
+            let mut response = GruxiResponse::new_with_bytes(
+                hyper::StatusCode::BAD_REQUEST.as_u16(),
+                bytes::Bytes::from(r#"{"error": "Both 'from' and 'to' are required for a custom period"}"#),
+            );
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    let report = match build_usage_report(usage_sites, period).await {
+        Ok(report) => report,
+        Err(e) => {
+            error(format!("Failed to build usage report: {}", e));
+            let error_response = serde_json::json!({
+                "error": "Failed to build usage report",
+                "details": e
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    if get_query_value("format").as_deref() == Some("csv") {
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(report.to_csv()));
+        response.headers_mut().insert("Content-Type", CSV_HEADER_VALUE);
+        if let Ok(value) = HeaderValue::from_str(&format!("attachment; filename=\"usage-{}-{}.csv\"", report.period_start, report.period_end)) {
+            response.headers_mut().insert("Content-Disposition", value);
+        }
+        return Ok(response);
+    }
+
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(serde_json::to_string(&report).unwrap_or_default()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Admin long-running connections DELETE endpoint - closes the WebSocket connections, tunnels and streamed responses of a site,
+// such as before maintenance: /connections/{site_id}. Delegated admins can only close those of their own sites
+pub async fn admin_delete_site_connections_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let (session, site_scope) = match require_site_scope(gruxi_request).await {
+        Ok(result) => result,
+        Err(auth_response) => {
+            return Ok(auth_response);
+        }
+    };
+
+    let path = gruxi_request.get_path();
+    let site_id = urlencoding::decode(path.trim_start_matches("/connections/")).map(|id| id.to_string()).unwrap_or_default();
+    let is_known_site = get_cached_configuration().get_configuration().await.sites.iter().any(|site| site.id == site_id);
+    if !is_known_site || site_scope.as_ref().is_some_and(|site_scope| !site_scope.contains_site(&site_id)) {
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::NOT_FOUND.as_u16(), bytes::Bytes::from(r#"{"error": "Site not found"}"#));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+
+    let closed = close_site_connections(&site_id);
+    info(format!(
+        "{} long-running connections of site '{}' were closed through the admin portal by '{}'",
+        closed, site_id, session.username
+    ));
+
+    let response_json = serde_json::json!({ "success": true, "closed": closed });
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Admin cache warming GET endpoint - returns the report of the last crawl of each site, with the warmed URLs.
+// Delegated admins only get the reports of their own sites
+pub async fn admin_get_cache_warm_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let site_scope = match require_site_scope(gruxi_request).await {
+        Ok((_session, site_scope)) => site_scope,
+        Err(auth_response) => {
+            return Ok(auth_response);
+        }
+    };
+
+    let reports: Vec<_> = get_cache_warm_reports()
+        .into_iter()
+        .filter(|report| site_scope.as_ref().is_none_or(|site_scope| site_scope.contains_site(&report.site_id)))
+        .collect();
+
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(serde_json::to_string(&reports).unwrap_or_default()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Admin cache warming POST endpoint - starts a crawl of a site in the background, such as from a deploy script: /cache-warm/{site_id}.
+// Delegated admins can only start crawls of their own sites
+pub async fn admin_post_cache_warm_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let (session, site_scope) = match require_site_scope(gruxi_request).await {
+        Ok(result) => result,
+        Err(auth_response) => {
+            return Ok(auth_response);
+        }
+    };
+
+    let path = gruxi_request.get_path();
+    let site_id = urlencoding::decode(path.trim_start_matches("/cache-warm/")).map(|id| id.to_string()).unwrap_or_default();
+    let site = get_cached_configuration().get_configuration().await.sites.iter().find(|site| site.id == site_id).cloned();
+    let site = match site {
+        Some(site) if site_scope.as_ref().is_none_or(|site_scope| site_scope.contains_site(&site_id)) => site,
+        _ => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::NOT_FOUND.as_u16(), bytes::Bytes::from(r#"{"error": "Site not found"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+    if !site.is_enabled || !site.cache_warm.is_enabled {
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(r#"{"error": "Cache warming is not enabled for the site"}"#));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+
+    spawn_cache_warming(&site_id);
+    info(format!("Cache warming of site '{}' was started through the admin portal by '{}'", site_id, session.username));
+
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::ACCEPTED.as_u16(), bytes::Bytes::from(r#"{"success": true}"#));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Admin synthetic probes GET endpoint - returns a summary of the probes of each site, with their latest run.
+// Delegated admins only get the probes of their own sites
+pub async fn admin_get_synthetic_probes_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let site_scope = match require_site_scope(gruxi_request).await {
+        Ok((_session, site_scope)) => site_scope,
+        Err(auth_response) => {
+            return Ok(auth_response);
+        }
+    };
+
+    let summaries: Vec<_> = get_cached_configuration()
+        .get_configuration()
+        .await
+        .sites
+        .iter()
+        .filter(|site| site_scope.as_ref().is_none_or(|site_scope| site_scope.contains_site(&site.id)))
+        .map(get_probe_summary)
+        .collect();
+
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(serde_json::to_string(&summaries).unwrap_or_default()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// The site of a /synthetic-probes/{site_id} path, or the response when it is not found or not in the scope of a delegated admin
+async fn get_synthetic_probe_site(path: &str, site_scope: Option<&SiteScope>) -> Result<Site, GruxiResponse> {
+    let site_id = urlencoding::decode(path.trim_start_matches("/synthetic-probes/").trim_end_matches("/run"))
+        .map(|id| id.to_string())
+        .unwrap_or_default();
+    let site = get_cached_configuration().get_configuration().await.sites.iter().find(|site| site.id == site_id).cloned();
+    match site {
+        Some(site) if site_scope.is_none_or(|site_scope| site_scope.contains_site(&site_id)) => Ok(site),
+        _ => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::NOT_FOUND.as_u16(), bytes::Bytes::from(r#"{"error": "Site not found"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Err(response)
+        }
+    }
+}
+
+// Admin synthetic probe GET endpoint - returns the summary and the kept runs of the probe of a site, newest first, at most
+// limit of them (100 by default): /synthetic-probes/{site_id}?limit=100
+pub async fn admin_get_synthetic_probe_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let site_scope = match require_site_scope(gruxi_request).await {
+        Ok((_session, site_scope)) => site_scope,
+        Err(auth_response) => {
+            return Ok(auth_response);
+        }
+    };
+    let site = match get_synthetic_probe_site(&gruxi_request.get_path(), site_scope.as_ref()).await {
+        Ok(site) => site,
+        Err(response) => return Ok(response),
+    };
+
+    let limit = gruxi_request
+        .get_query()
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "limit")
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(100);
+    let probe = serde_json::json!({
+        "summary": get_probe_summary(&site),
+        "runs": get_probe_runs(&site.id, limit),
+    });
+
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(probe.to_string()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Admin synthetic probe run POST endpoint - probes a site now, outside of its interval, and waits for the result:
+// /synthetic-probes/{site_id}/run. Delegated admins can only probe their own sites
+pub async fn admin_post_synthetic_probe_run_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let (session, site_scope) = match require_site_scope(gruxi_request).await {
+        Ok(result) => result,
+        Err(auth_response) => {
+            return Ok(auth_response);
+        }
+    };
+    let site = match get_synthetic_probe_site(&gruxi_request.get_path(), site_scope.as_ref()).await {
+        Ok(site) => site,
+        Err(response) => return Ok(response),
+    };
+    if !site.is_enabled || !site.synthetic_probe.is_enabled {
+        let mut response = GruxiResponse::new_with_bytes(
+            hyper::StatusCode::BAD_REQUEST.as_u16(),
+            bytes::Bytes::from(r#"{"error": "The synthetic probe is not enabled for the site"}"#),
+        );
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+
+    info(format!("Synthetic probe of site '{}' was run through the admin portal by '{}'", site.id, session.username));
+    let run = run_synthetic_probe(&site).await;
+
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(serde_json::to_string(&run).unwrap_or_default()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// The web root deployments of the site go to, or the response when the site is not found, not in the scope of a delegated
+// admin or has no single static web root
+async fn get_deployment_web_root_for_site(site_id: &str, site_scope: Option<&SiteScope>) -> Result<String, GruxiResponse> {
+    let configuration = get_cached_configuration().get_configuration().await;
+    let is_known_site = configuration.sites.iter().any(|site| site.id == site_id);
+    if !is_known_site || site_scope.is_some_and(|site_scope| !site_scope.contains_site(site_id)) {
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::NOT_FOUND.as_u16(), bytes::Bytes::from(r#"{"error": "Site not found"}"#));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Err(response);
+    }
+
+    get_site_deployment_web_root(&configuration, site_id).map_err(|e| {
+        let error_response = serde_json::json!({ "error": e });
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        response
+    })
+}
+
+// Admin deployments GET endpoint - lists the releases of a site and the active one: /deployments/{site_id}
+pub async fn admin_get_deployments_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let site_scope = match require_site_scope(gruxi_request).await {
+        Ok((_session, site_scope)) => site_scope,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let path = gruxi_request.get_path();
+    let site_id = urlencoding::decode(path.trim_start_matches("/deployments/")).map(|id| id.to_string()).unwrap_or_default();
+    let web_root = match get_deployment_web_root_for_site(&site_id, site_scope.as_ref()).await {
+        Ok(web_root) => web_root,
+        Err(response) => return Ok(response),
+    };
+
+    let mut response = GruxiResponse::new_with_bytes(
+        hyper::StatusCode::OK.as_u16(),
+        bytes::Bytes::from(serde_json::to_string(&get_deployment_status(&web_root)).unwrap_or_default()),
+    );
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Admin deployment POST endpoint - deploys a zip, tar or tar.gz bundle in the body as a new release of the site:
+// /deployments/{site_id}. The bundle is verified against the X-Gruxi-Deployment-Sha256 header, when sent
+pub async fn admin_post_deployment_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let (session, site_scope) = match require_site_scope(gruxi_request).await {
+        Ok(result) => result,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let path = gruxi_request.get_path();
+    let site_id = urlencoding::decode(path.trim_start_matches("/deployments/")).map(|id| id.to_string()).unwrap_or_default();
+    let web_root = match get_deployment_web_root_for_site(&site_id, site_scope.as_ref()).await {
+        Ok(web_root) => web_root,
+        Err(response) => return Ok(response),
+    };
+
+    if is_over_disk_quota(&site_id) {
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INSUFFICIENT_STORAGE.as_u16(), bytes::Bytes::from(r#"{"error": "The site is over its disk quota"}"#));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+
+    let expected_sha256 = gruxi_request
+        .get_headers()
+        .get("X-Gruxi-Deployment-Sha256")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+    let bundle = gruxi_request.get_body_bytes().await.to_vec();
+    if bundle.is_empty() {
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(r#"{"error": "Empty request body"}"#));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+
+    match deploy_bundle(&web_root, bundle, expected_sha256).await {
+        Ok(release_id) => {
+            info(format!(
+                "Release '{}' of site '{}' was deployed through the admin portal by '{}'",
+                release_id, site_id, session.username
+            ));
+            let response_json = serde_json::json!({ "success": true, "release": release_id, "deployments": get_deployment_status(&web_root) });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::CREATED.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            info(format!("Deployment to site '{}' by '{}' was rejected: {}", site_id, session.username, e));
+            let error_response = serde_json::json!({
+                "error": "Deployment failed",
+                "details": e
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin deployment activate POST endpoint - switches a site to one of its releases, such as to roll back:
+// /deployments/{site_id}/{release_id}/activate
+pub async fn admin_post_deployment_activate_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let (session, site_scope) = match require_site_scope(gruxi_request).await {
+        Ok(result) => result,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let path = gruxi_request.get_path();
+    let (site_id, release_id) = path
+        .trim_start_matches("/deployments/")
+        .trim_end_matches("/activate")
+        .split_once('/')
+        .map(|(site_id, release_id)| {
+            (
+                urlencoding::decode(site_id).map(|id| id.to_string()).unwrap_or_default(),
+                urlencoding::decode(release_id).map(|id| id.to_string()).unwrap_or_default(),
+            )
+        })
+        .unwrap_or_default();
+    let web_root = match get_deployment_web_root_for_site(&site_id, site_scope.as_ref()).await {
+        Ok(web_root) => web_root,
+        Err(response) => return Ok(response),
+    };
+
+    match activate_release(&web_root, &release_id).await {
+        Ok(()) => {
+            info(format!(
+                "Site '{}' was switched to release '{}' through the admin portal by '{}'",
+                site_id, release_id, session.username
+            ));
+            let response_json = serde_json::json!({ "success": true, "deployments": get_deployment_status(&web_root) });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            let error_response = serde_json::json!({ "error": e });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin disk usage GET endpoint - the disk usage of the web roots of the sites, as last measured, and their quotas.
+// Delegated admins only see their own sites
+pub async fn admin_get_disk_usage_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let site_scope = match require_site_scope(gruxi_request).await {
+        Ok((_session, site_scope)) => site_scope,
+        Err(auth_response) => {
+            return Ok(auth_response);
+        }
+    };
+
+    let usage: Vec<_> = get_disk_usage()
+        .into_iter()
+        .filter(|usage| site_scope.as_ref().is_none_or(|site_scope| site_scope.contains_site(&usage.site_id)))
+        .collect();
+
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(serde_json::to_string(&usage).unwrap_or_default()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Admin analytics GET endpoint - summaries of the access logs of the sites over the last 24 hours, with the most requested
+// paths, status codes, unique IPs and bytes sent: /analytics?top={n}. Delegated admins only see their own sites
+pub async fn admin_get_analytics_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let site_scope = match require_site_scope(gruxi_request).await {
+        Ok((_session, site_scope)) => site_scope,
+        Err(auth_response) => {
+            return Ok(auth_response);
+        }
+    };
+
+    let top_paths = gruxi_request
+        .get_query()
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == "top")
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(10);
+    let summaries = get_access_log_summaries(|site_id| site_scope.as_ref().is_none_or(|site_scope| site_scope.contains_site(site_id)), top_paths);
+
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(serde_json::to_string(&summaries).unwrap_or_default()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+#[derive(Deserialize)]
+struct DebugDumpRequest {
+    #[serde(default = "default_debug_dump_duration_seconds")]
+    duration_seconds: u64,
+    #[serde(default = "default_debug_dump_max_requests")]
+    max_requests: u64,
+}
+
+fn default_debug_dump_duration_seconds() -> u64 {
+    DEBUG_DUMP_DEFAULT_DURATION_SECONDS
+}
+
+fn default_debug_dump_max_requests() -> u64 {
+    DEBUG_DUMP_DEFAULT_MAX_REQUESTS
+}
+
+// Admin debug dumps GET endpoint - lists the running debug dumps, with the log files they write to
+pub async fn admin_get_debug_dumps_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    if let Err(auth_response) = require_full_admin(gruxi_request).await {
+        return Ok(auth_response);
+    }
+
+    let response_json = serde_json::json!({ "debug_dumps": get_debug_dumps() });
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Admin debug dumps POST endpoint - dumps the request and response headers of a site to a log file, until the duration
+// or the number of requests is reached: /debug-dumps/{site_id}, with an optional body like {"duration_seconds": 300, "max_requests": 1000}
+pub async fn admin_post_debug_dump_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let path = gruxi_request.get_path();
+    let site_id = urlencoding::decode(path.trim_start_matches("/debug-dumps/")).map(|id| id.to_string()).unwrap_or_default();
+    let is_known_site = get_cached_configuration().get_configuration().await.sites.iter().any(|site| site.id == site_id);
+    if !is_known_site {
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::NOT_FOUND.as_u16(), bytes::Bytes::from(r#"{"error": "Site not found"}"#));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+
+    let body_bytes = gruxi_request.get_body_bytes().await;
+    let body_bytes = if body_bytes.is_empty() { bytes::Bytes::from_static(b"{}") } else { body_bytes };
+    let dump_request: DebugDumpRequest = match serde_json::from_slice(&body_bytes) {
+        Ok(dump_request) => dump_request,
+        Err(e) => {
+            let error_response = serde_json::json!({
+                "error": "Invalid JSON format",
+                "details": e.to_string()
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    match start_debug_dump(&site_id, dump_request.duration_seconds, dump_request.max_requests, &session.username) {
+        Ok(status) => {
+            let response_json = serde_json::json!({ "success": true, "debug_dump": status });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            let response_json = serde_json::json!({ "error": e });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin debug dumps DELETE endpoint - stops the debug dump of a site before it ends by itself: /debug-dumps/{site_id}
+pub async fn admin_delete_debug_dump_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let path = gruxi_request.get_path();
+    let site_id = urlencoding::decode(path.trim_start_matches("/debug-dumps/")).map(|id| id.to_string()).unwrap_or_default();
+
+    match stop_debug_dump(&site_id) {
+        Some(status) => {
+            info(format!("Debug dump of site '{}' was stopped through the admin portal by '{}'", site_id, session.username));
+            let response_json = serde_json::json!({ "success": true, "debug_dump": status });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        None => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::NOT_FOUND.as_u16(), bytes::Bytes::from(r#"{"error": "No debug dump is running for the site"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin database backups GET endpoint - lists the backups of the configuration database, newest first
+pub async fn admin_get_database_backups_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    if let Err(auth_response) = require_full_admin(gruxi_request).await {
+        return Ok(auth_response);
+    }
+
+    let settings = get_cached_configuration().get_configuration().await.core.database_backup.clone();
+    match list_backups(&settings.directory) {
+        Ok(backups) => {
+            let response_json = serde_json::json!({
+                "success": true,
+                "directory": settings.directory,
+                "backups": backups
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            error(format!("Failed to list database backups: {}", e));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(r#"{"error": "Failed to list database backups"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin database backups POST endpoint - takes a backup of the configuration database now
+pub async fn admin_post_database_backup_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let settings = get_cached_configuration().get_configuration().await.core.database_backup.clone();
+    match tokio::task::spawn_blocking(move || create_backup(&settings)).await.map_err(|e| e.to_string()).flatten() {
+        Ok(backup) => {
+            info(format!("Database backup {} was taken through the admin portal by '{}'", backup.name, session.username));
+            let response_json = serde_json::json!({ "success": true, "backup": backup });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            error(format!("Failed to take database backup: {}", e));
+            let response_json = serde_json::json!({ "error": "Failed to take database backup", "details": e });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin database backup restore POST endpoint - replaces the configuration database with a backup and reloads the
+// configuration: /database-backups/{name}/restore. The database is backed up first, and that backup is returned
+pub async fn admin_post_database_backup_restore_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let path = gruxi_request.get_path();
+    let name = path.trim_start_matches("/database-backups/").trim_end_matches("/restore");
+    let name = urlencoding::decode(name).map(|name| name.to_string()).unwrap_or_default();
+
+    let settings = get_cached_configuration().get_configuration().await.core.database_backup.clone();
+    match restore_backup_and_reload(settings, name.clone()).await {
+        Ok(pre_restore_backup) => {
+            info(format!("Database backup {} was restored through the admin portal by '{}'", name, session.username));
+            let response_json = serde_json::json!({
+                "success": true,
+                "message": "Database backup restored. Server is restarting...",
+                "pre_restore_backup": pre_restore_backup
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            error(format!("Failed to restore database backup {}: {}", name, e));
+            let response_json = serde_json::json!({ "error": e });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin email alert test POST endpoint - emails a test alert to all recipients of the email alert routes, to check the
+// SMTP settings. The settings are used as saved, whether email alerts are enabled or not
+pub async fn admin_post_email_alert_test_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let settings = get_cached_configuration().get_configuration().await.core.email_alerts.clone();
+    let mut recipients: Vec<String> = settings.routes.iter().flat_map(|route| route.recipients.iter().cloned()).collect();
+    recipients.sort();
+    recipients.dedup();
+    if recipients.is_empty() {
+        let mut response = GruxiResponse::new_with_bytes(
+            hyper::StatusCode::BAD_REQUEST.as_u16(),
+            bytes::Bytes::from(r#"{"error": "No email alert routes with recipients are configured"}"#),
+        );
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+
+    let body = format!(
+        "This is a test of the email alerts of Gruxi, sent through the admin portal by '{}'.\n\nAdmin alerts routed to you will be emailed like this.\n",
+        session.username
+    );
+    match send_email(&settings, &recipients, "[Gruxi] Test alert", &body).await {
+        Ok(()) => {
+            info(format!("Test email alert was sent to {} through the admin portal by '{}'", recipients.join(", "), session.username));
+            let response_json = serde_json::json!({ "success": true, "recipients": recipients });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            let response_json = serde_json::json!({ "error": "Failed to send the test email", "details": e });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_GATEWAY.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ScheduledChangeRequest {
+    configuration: Configuration,
+    scheduled_at: String, // RFC 3339, such as "2026-01-31T22:00:00Z"
+    #[serde(default)]
+    description: String,
+}
+
+// Admin scheduled changes GET endpoint - lists the scheduled configuration changes with their audit trail
+pub async fn admin_get_scheduled_changes_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    if let Err(auth_response) = require_full_admin(gruxi_request).await {
+        return Ok(auth_response);
+    }
+
+    match list_scheduled_changes() {
+        Ok(scheduled_changes) => {
+            let response_json = serde_json::json!({
+                "success": true,
+                "scheduled_changes": scheduled_changes
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            error(format!("Failed to list scheduled changes: {}", e));
+            let mut response = GruxiResponse::new_with_bytes(
+                hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                bytes::Bytes::from(r#"{"error": "Failed to list scheduled changes"}"#),
+            );
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin scheduled changes POST endpoint - schedules the changes from the current configuration to the submitted one
+pub async fn admin_post_scheduled_change_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let body_bytes = gruxi_request.get_body_bytes().await;
+    let change_request: ScheduledChangeRequest = match serde_json::from_slice(&body_bytes) {
+        Ok(change_request) => change_request,
+        Err(e) => {
+            let error_response = serde_json::json!({
+                "error": "Invalid JSON format",
+                "details": e.to_string()
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    let result = match chrono::DateTime::parse_from_rfc3339(&change_request.scheduled_at) {
+        Ok(scheduled_at) => schedule_configuration_change(
+            change_request.configuration,
+            scheduled_at.with_timezone(&chrono::Utc),
+            change_request.description.trim(),
+            &session.username,
+        ),
+        Err(_) => Err(vec![format!("Invalid time '{}', it must be in RFC 3339 format", change_request.scheduled_at)]),
+    };
+
+    match result {
+        Ok(id) => {
+            let response_json = serde_json::json!({ "success": true, "id": id });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(errors) => {
+            let error_response = serde_json::json!({
+                "errors": errors
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin scheduled changes DELETE endpoint - cancels a pending change: /scheduled-changes/{id}
+pub async fn admin_delete_scheduled_change_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let path = gruxi_request.get_path();
+    let change_id = urlencoding::decode(path.trim_start_matches("/scheduled-changes/")).map(|id| id.to_string()).unwrap_or_default();
+
+    match cancel_scheduled_change(&change_id, &session.username) {
+        Ok(true) => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(r#"{"success": true}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Ok(false) => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::NOT_FOUND.as_u16(), bytes::Bytes::from(r#"{"error": "No pending scheduled change with this id"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            error(format!("Failed to cancel scheduled change '{}': {}", change_id, e));
+            let mut response = GruxiResponse::new_with_bytes(
+                hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                bytes::Bytes::from(r#"{"error": "Failed to cancel scheduled change"}"#),
+            );
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ScheduledTaskRequest {
+    #[serde(default)]
+    id: Option<String>, // Set to update a task, else a task is added
+    #[serde(default)]
+    name: String,
+    job_type: String,
+    schedule: String, // Cron schedule in UTC, such as "0 3 * * *"
+    #[serde(default)]
+    parameters: serde_json::Map<String, serde_json::Value>,
+    #[serde(default = "default_scheduled_task_enabled")]
+    is_enabled: bool,
+}
+
+fn default_scheduled_task_enabled() -> bool {
+    true
+}
+
+// Admin scheduled tasks GET endpoint - lists the recurring maintenance tasks with their last and next runs
+pub async fn admin_get_scheduled_tasks_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    if let Err(auth_response) = require_full_admin(gruxi_request).await {
+        return Ok(auth_response);
+    }
+
+    match list_scheduled_tasks() {
+        Ok(scheduled_tasks) => {
+            let response_json = serde_json::json!({
+                "success": true,
+                "scheduled_tasks": scheduled_tasks,
+                "job_types": SCHEDULED_TASK_JOB_TYPES
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            error(format!("Failed to list scheduled tasks: {}", e));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(r#"{"error": "Failed to list scheduled tasks"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin scheduled tasks POST endpoint - adds a task, or updates the task with the id in the body
+pub async fn admin_post_scheduled_task_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let body_bytes = gruxi_request.get_body_bytes().await;
+    let task_request: ScheduledTaskRequest = match serde_json::from_slice(&body_bytes) {
+        Ok(task_request) => task_request,
+        Err(e) => {
+            let error_response = serde_json::json!({
+                "error": "Invalid JSON format",
+                "details": e.to_string()
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    let result = save_scheduled_task(
+        task_request.id.as_deref(),
+        task_request.name.trim(),
+        task_request.job_type.trim(),
+        &task_request.schedule,
+        &task_request.parameters,
+        task_request.is_enabled,
+        &session.username,
+    );
+
+    match result {
+        Ok(id) => {
+            let response_json = serde_json::json!({ "success": true, "id": id });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(errors) => {
+            let error_response = serde_json::json!({
+                "errors": errors
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin scheduled task run POST endpoint - runs a task now, outside of its schedule, and waits for it: /scheduled-tasks/{id}/run
+pub async fn admin_post_scheduled_task_run_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let path = gruxi_request.get_path();
+    let task_id = urlencoding::decode(path.trim_start_matches("/scheduled-tasks/").trim_end_matches("/run"))
+        .map(|id| id.to_string())
+        .unwrap_or_default();
+
+    let task = match list_scheduled_tasks() {
+        Ok(tasks) => tasks.into_iter().find(|task| task.id == task_id),
+        Err(e) => {
+            error(format!("Failed to list scheduled tasks: {}", e));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(r#"{"error": "Failed to list scheduled tasks"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+    let Some(task) = task else {
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::NOT_FOUND.as_u16(), bytes::Bytes::from(r#"{"error": "No scheduled task with this id"}"#));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    };
+
+    info(format!("Scheduled task '{}' ({}) was run through the admin portal by '{}'", task.id, task.job_type, session.username));
+    let (status_code, response_json) = match run_scheduled_task(&task).await {
+        Some(Ok(message)) => (hyper::StatusCode::OK, serde_json::json!({ "success": true, "message": message })),
+        Some(Err(message)) => (hyper::StatusCode::OK, serde_json::json!({ "success": false, "message": message })),
+        None => (hyper::StatusCode::CONFLICT, serde_json::json!({ "error": "The scheduled task is already running" })),
+    };
+    let mut response = GruxiResponse::new_with_bytes(status_code.as_u16(), bytes::Bytes::from(response_json.to_string()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Admin scheduled tasks DELETE endpoint - deletes a task: /scheduled-tasks/{id}
+pub async fn admin_delete_scheduled_task_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let path = gruxi_request.get_path();
+    let task_id = urlencoding::decode(path.trim_start_matches("/scheduled-tasks/")).map(|id| id.to_string()).unwrap_or_default();
+
+    match delete_scheduled_task(&task_id, &session.username) {
+        Ok(true) => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(r#"{"success": true}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Ok(false) => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::NOT_FOUND.as_u16(), bytes::Bytes::from(r#"{"error": "No scheduled task with this id"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            error(format!("Failed to delete scheduled task '{}': {}", task_id, e));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(r#"{"error": "Failed to delete scheduled task"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin ACME certificates GET endpoint - lists the certificate orders of the ACME manager, with their domains
+pub async fn admin_get_acme_certificates_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    if let Err(auth_response) = require_full_admin(gruxi_request).await {
+        return Ok(auth_response);
+    }
+
+    let orders: Vec<serde_json::Value> = get_shared_acme_orders()
+        .await
+        .into_iter()
+        .map(|order| {
+            serde_json::json!({
+                "domains": order.domains,
+                "directory_url": order.settings.directory_url,
+                "key_type": order.settings.key_type,
+                "profile": order.settings.profile
+            })
+        })
+        .collect();
+    let response_json = serde_json::json!({ "orders": orders, "formats": CERTIFICATE_EXPORT_FORMATS });
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Admin ACME certificate log GET endpoint - lists the certificates issued and renewed through ACME, the most recent first.
+// Filtered to one domain with domain=, and limited to 100 records unless limit= is given
+pub async fn admin_get_acme_certificate_log_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    if let Err(auth_response) = require_full_admin(gruxi_request).await {
+        return Ok(auth_response);
+    }
+
+    let query = gruxi_request.get_query();
+    let get_query_value = |name: &str| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| urlencoding::decode(value).map(|v| v.to_string()).unwrap_or_default())
+    };
+    let domain = get_query_value("domain").filter(|domain| !domain.is_empty());
+    let limit = get_query_value("limit").and_then(|limit| limit.parse::<usize>().ok()).unwrap_or(100);
+
+    match list_acme_certificates(domain.as_deref(), limit) {
+        Ok(certificates) => {
+            let response_json = serde_json::json!({
+                "success": true,
+                "certificates": certificates
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            error(format!("Failed to list the ACME certificate log: {}", e));
+            let mut response = GruxiResponse::new_with_bytes(
+                hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                bytes::Bytes::from(r#"{"error": "Failed to list the ACME certificate log"}"#),
+            );
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AcmeCertificateExportRequest {
+    domain: String,
+    #[serde(default = "default_certificate_export_format")]
+    format: String, // "pem" for the key and chain, "cert" for the chain, "key" for the key, or "pkcs12"
+    #[serde(default)]
+    passphrase: String, // Required for "pkcs12"
+}
+
+fn default_certificate_export_format() -> String {
+    "pem".to_string()
+}
+
+// Admin ACME certificate export POST endpoint - downloads the ACME certificate of a domain with its private key, so other
+// services on the host can use it. The passphrase is in the body, so it does not end up in access logs
+pub async fn admin_post_acme_certificate_export_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let body_bytes = gruxi_request.get_body_bytes().await;
+    let export_request: AcmeCertificateExportRequest = match serde_json::from_slice(&body_bytes) {
+        Ok(export_request) => export_request,
+        Err(e) => {
+            let error_response = serde_json::json!({
+                "error": "Invalid JSON format",
+                "details": e.to_string()
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+    let domain = export_request.domain.trim().to_lowercase();
+
+    if !CERTIFICATE_EXPORT_FORMATS.contains(&export_request.format.as_str()) {
+        let error_response = serde_json::json!({ "error": format!("Format must be one of {}", CERTIFICATE_EXPORT_FORMATS.join(", ")) });
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+
+    let certificate = match load_acme_certificate_pem(&domain)
+        .await
+        .and_then(|pem| pem.map(|pem| CertificateWithKey::from_acme_pem(&pem)).transpose())
+    {
+        Ok(Some(certificate)) => certificate,
+        Ok(None) => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::NOT_FOUND.as_u16(), bytes::Bytes::from(r#"{"error": "No ACME certificate for this domain"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+        Err(e) => {
+            error(format!("Failed to load ACME certificate for '{}': {}", domain, e));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(r#"{"error": "Failed to load certificate"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    let (body, file_name, content_type) = match export_request.format.as_str() {
+        "cert" => (certificate.get_certificate_chain_pem().into_bytes(), format!("{}.crt", domain), "application/x-pem-file"),
+        "key" => (certificate.get_private_key_pem().into_bytes(), format!("{}.key", domain), "application/x-pem-file"),
+        "pkcs12" => match certificate.to_pkcs12(&export_request.passphrase, &domain) {
+            Ok(pkcs12) => (pkcs12, format!("{}.p12", domain), "application/x-pkcs12"),
+            Err(e) => {
+                let error_response = serde_json::json!({ "error": e });
+                let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+                response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+                return Ok(response);
+            }
+        },
+        _ => (
+            format!("{}{}", certificate.get_private_key_pem(), certificate.get_certificate_chain_pem()).into_bytes(),
+            format!("{}.pem", domain),
+            "application/x-pem-file",
+        ),
+    };
+
+    // Private keys leaving the server are worth a trace in the log
+    info(format!("ACME certificate for '{}' exported as {} by admin user '{}'", domain, export_request.format, session.username));
+
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(body));
+    response.headers_mut().insert("Content-Type", HeaderValue::from_static(content_type));
+    response.headers_mut().insert("Cache-Control", HeaderValue::from_static("no-store"));
+    if let Ok(value) = HeaderValue::from_str(&format!("attachment; filename=\"{}\"", file_name)) {
+        response.headers_mut().insert("Content-Disposition", value);
+    }
+    Ok(response)
+}
+
+#[derive(Deserialize)]
+struct AcmeSmokeTestRequest {
+    domain: String,
+    #[serde(default)]
+    challenge_type: Option<String>, // The configured challenge type when not given
+}
+
+// Admin ACME smoke test POST endpoint - issues a certificate for the domain from the staging CA, step by step, and reports
+// how each step went. The certificates in use are left alone, so this can be run before turning off use_staging_server
+pub async fn admin_post_acme_smoke_test_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let body_bytes = gruxi_request.get_body_bytes().await;
+    let smoke_test_request: AcmeSmokeTestRequest = match serde_json::from_slice(&body_bytes) {
+        Ok(smoke_test_request) => smoke_test_request,
+        Err(e) => {
+            let error_response = serde_json::json!({
+                "error": "Invalid JSON format",
+                "details": e.to_string()
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    info(format!("ACME smoke test for '{}' started by admin user '{}'", smoke_test_request.domain, session.username));
+
+    match run_acme_smoke_test(&smoke_test_request.domain, smoke_test_request.challenge_type.as_deref()).await {
+        Ok(report) => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(serde_json::json!(report).to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            let error_response = serde_json::json!({ "error": e });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+// Admin ACME account key GET endpoint - downloads the key of the ACME account at the configured CA, as PKCS#8 PEM, to move
+// the account to another installation
+pub async fn admin_get_acme_account_key_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let tls_settings = get_cached_configuration().get_configuration().await.core.tls_settings.clone();
+    let cache = AcmeCache::from_settings(&tls_settings, &get_cache_dir(&tls_settings));
+    match export_acme_account_key(&tls_settings, &cache).await {
+        Ok(Some(account_key)) => {
+            // Like certificate keys, the account key leaving the server is worth a trace in the log
+            info(format!("ACME account key exported by admin user '{}'", session.username));
+            let response_json = serde_json::json!({
+                "directory_url": get_acme_directory_url(&tls_settings),
+                "account_email": tls_settings.account_email,
+                "account_key": account_key
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            response.headers_mut().insert("Cache-Control", HeaderValue::from_static("no-store"));
+            Ok(response)
+        }
+        Ok(None) => {
+            let mut response = GruxiResponse::new_with_bytes(
+                hyper::StatusCode::NOT_FOUND.as_u16(),
+                bytes::Bytes::from(r#"{"error": "No ACME account is registered at the configured CA yet"}"#),
+            );
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            error(format!("Failed to load ACME account key: {}", e));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(r#"{"error": "Failed to load account key"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AcmeAccountKeyImportRequest {
+    account_key: String, // PKCS#8 PEM of an ECDSA P-256 key
+}
+
+// Admin ACME account key POST endpoint - stores an account key for the configured CA, replacing the cached account, and
+// reloads the server so the next orders are made with it
+pub async fn admin_post_acme_account_key_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let body_bytes = gruxi_request.get_body_bytes().await;
+    let import_request: AcmeAccountKeyImportRequest = match serde_json::from_slice(&body_bytes) {
+        Ok(import_request) => import_request,
+        Err(e) => {
+            let error_response = serde_json::json!({
+                "error": "Invalid JSON format",
+                "details": e.to_string()
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    let tls_settings = get_cached_configuration().get_configuration().await.core.tls_settings.clone();
+    if tls_settings.account_email.is_empty() {
+        let mut response = GruxiResponse::new_with_bytes(
+            hyper::StatusCode::BAD_REQUEST.as_u16(),
+            bytes::Bytes::from(r#"{"error": "Configure the ACME account email before importing an account key"}"#),
+        );
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+
+    let cache = AcmeCache::from_settings(&tls_settings, &get_cache_dir(&tls_settings));
+    if let Err(e) = import_acme_account_key(&tls_settings, &cache, &import_request.account_key).await {
+        let error_response = serde_json::json!({ "error": e });
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+
+    info(format!("ACME account key for {} imported by admin user '{}'", get_acme_directory_url(&tls_settings), session.username));
+
+    // The orders in progress hold the previous account, and the bindings the resolver of the orders, so the server is
+    // reloaded for the ACME manager to start over with the new account
+    get_trigger_handler().run_trigger("reload_configuration").await;
+
+    let mut response = GruxiResponse::new_with_bytes(
+        hyper::StatusCode::OK.as_u16(),
+        bytes::Bytes::from(r#"{"success": true, "message": "Account key imported. Server is restarting..."}"#),
+    );
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Admin installed certificates GET endpoint - lists the uploaded certificates, with their SANs, expiry and the sites using them
+pub async fn admin_get_installed_certificates_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    if let Err(auth_response) = require_full_admin(gruxi_request).await {
+        return Ok(auth_response);
+    }
+
+    match fetch_configuration_in_db()
+        .map_err(String::from)
+        .and_then(|configuration| list_installed_certificates(&configuration.sites))
+    {
+        Ok(certificates) => {
+            let response_json = serde_json::json!({
+                "success": true,
+                "certificates": certificates
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            error(format!("Failed to list installed certificates: {}", e));
+            let mut response = GruxiResponse::new_with_bytes(
+                hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+                bytes::Bytes::from(r#"{"error": "Failed to list installed certificates"}"#),
+            );
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct InstalledCertificateRequest {
+    id: String,
+    certificate: String, // PEM chain, starting with the site certificate
+    private_key: String, // PEM
+}
+
+// Admin installed certificates POST endpoint - uploads a certificate chain and key pair, replacing the certificate with the
+// same id. Sites already using it get the new certificate on the next reload
+pub async fn admin_post_installed_certificate_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let body_bytes = gruxi_request.get_body_bytes().await;
+    let certificate_request: InstalledCertificateRequest = match serde_json::from_slice(&body_bytes) {
+        Ok(certificate_request) => certificate_request,
+        Err(e) => {
+            let error_response = serde_json::json!({
+                "error": "Invalid JSON format",
+                "details": e.to_string()
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    match install_certificate(&certificate_request.id, &certificate_request.certificate, &certificate_request.private_key) {
+        Ok(certificate) => {
+            info(format!("Certificate '{}' for {} was installed by {}", certificate.id, certificate.sans.join(", "), session.username));
+            let response_json = serde_json::json!({ "success": true, "certificate": certificate });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(errors) => {
+            let error_response = serde_json::json!({
+                "errors": errors
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct InstalledCertificateAssignRequest {
+    site_ids: Vec<String>,
+}
+
+// Admin installed certificate assign POST endpoint - makes sites use an installed certificate: /certificates/{id}/assign.
+// Automatic TLS is turned off for these sites, as it would otherwise take precedence
+pub async fn admin_post_installed_certificate_assign_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let path = gruxi_request.get_path();
+    let certificate_id = urlencoding::decode(path.trim_start_matches("/certificates/").trim_end_matches("/assign"))
+        .map(|id| id.to_string())
+        .unwrap_or_default();
+
+    let body_bytes = gruxi_request.get_body_bytes().await;
+    let assign_request: InstalledCertificateAssignRequest = match serde_json::from_slice(&body_bytes) {
+        Ok(assign_request) => assign_request,
+        Err(e) => {
+            let error_response = serde_json::json!({
+                "error": "Invalid JSON format",
+                "details": e.to_string()
+            });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    let mut configuration = match fetch_configuration_in_db() {
+        Ok(configuration) => configuration,
+        Err(e) => {
+            error(format!("Failed to fetch configuration to assign certificate '{}': {}", certificate_id, e));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(r#"{"error": "Failed to fetch configuration"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    let certificate = match get_installed_certificate(&certificate_id, &configuration.sites) {
+        Ok(Some(certificate)) => certificate,
+        Ok(None) => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::NOT_FOUND.as_u16(), bytes::Bytes::from(r#"{"error": "Certificate not found"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+        Err(e) => {
+            error(format!("Failed to load installed certificate '{}': {}", certificate_id, e));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(r#"{"error": "Failed to load certificate"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    let errors: Vec<String> = assign_request
+        .site_ids
+        .iter()
+        .filter(|site_id| !configuration.sites.iter().any(|site| &site.id == *site_id))
+        .map(|site_id| format!("Site '{}' does not exist", site_id))
+        .collect();
+    if errors.is_empty() {
+        for site in configuration.sites.iter_mut().filter(|site| assign_request.site_ids.contains(&site.id)) {
+            site.tls_automatic_enabled = false;
+            site.tls_cert_path = certificate.cert_path.clone();
+            site.tls_key_path = certificate.key_path.clone();
+            site.tls_cert_content = String::new();
+            site.tls_key_content = String::new();
+        }
+        if let Err(save_error) = save_configuration(&mut configuration, false) {
+            return Ok(get_save_configuration_error_response(save_error));
+        }
+    }
+
+    if !errors.is_empty() {
+        let error_response = serde_json::json!({
+            "errors": errors
+        });
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+        response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+        return Ok(response);
+    }
+
+    info(format!(
+        "Certificate '{}' was assigned to site(s) {} by {}",
+        certificate.id,
+        assign_request.site_ids.join(", "),
+        session.username
+    ));
+    let response_json = serde_json::json!({
+        "success": true,
+        "message": "Certificate assigned. Reload the configuration for the sites to use it."
+    });
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(response_json.to_string()));
+    response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+    Ok(response)
+}
+
+// Admin installed certificate DELETE endpoint - removes a certificate no site uses anymore: /certificates/{id}
+pub async fn admin_delete_installed_certificate_endpoint(gruxi_request: &mut GruxiRequest, _admin_site: &Site) -> Result<GruxiResponse, GruxiError> {
+    let session = match require_full_admin(gruxi_request).await {
+        Ok(session) => session,
+        Err(auth_response) => return Ok(auth_response),
+    };
+
+    let path = gruxi_request.get_path();
+    let certificate_id = urlencoding::decode(path.trim_start_matches("/certificates/")).map(|id| id.to_string()).unwrap_or_default();
+
+    let certificate = match fetch_configuration_in_db()
+        .map_err(String::from)
+        .and_then(|configuration| get_installed_certificate(&certificate_id, &configuration.sites))
+    {
+        Ok(Some(certificate)) => certificate,
+        Ok(None) => {
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::NOT_FOUND.as_u16(), bytes::Bytes::from(r#"{"error": "Certificate not found"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+        Err(e) => {
+            error(format!("Failed to load installed certificate '{}': {}", certificate_id, e));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16(), bytes::Bytes::from(r#"{"error": "Failed to load certificate"}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            return Ok(response);
+        }
+    };
+
+    match delete_installed_certificate(&certificate) {
+        Ok(()) => {
+            info(format!("Certificate '{}' was deleted by {}", certificate.id, session.username));
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), bytes::Bytes::from(r#"{"success": true}"#));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+        Err(e) => {
+            let error_response = serde_json::json!({ "error": e });
+            let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::BAD_REQUEST.as_u16(), bytes::Bytes::from(error_response.to_string()));
+            response.headers_mut().insert("Content-Type", JSON_HEADER_VALUE);
+            Ok(response)
+        }
+    }
+}
@@ -2,7 +2,7 @@ use crate::{core::admin_user::create_default_admin_user, logging::syslog::error}
 
 pub fn initialize_admin_site() -> Result<(), ()>{
     // Check if there is at least one admin user
-    let connection_result = crate::core::database_connection::get_database_connection();
+    let connection_result = crate::core::database_connection::get_database_writer();
     let connection = match connection_result {
         Ok(conn) => conn,
         Err(e) => {
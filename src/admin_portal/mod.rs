@@ -1,2 +1,4 @@
 pub mod http_admin_api;
-pub mod init;
\ No newline at end of file
+pub mod init;
+pub mod site_scope;
+pub mod status_page;
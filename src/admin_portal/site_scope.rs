@@ -0,0 +1,424 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::{
+    configuration::{binding_site_relation::BindingSiteRelationship, configuration::Configuration, location::Location, site::Site},
+    core::admin_user::get_user_site_scope,
+    http::site_match::hostname_pattern::is_regex_hostname,
+};
+
+// The fields of a site a delegated admin may change, everything else stays as a full admin set it, or at its default for new sites
+const TENANT_EDITABLE_SITE_FIELDS: [&str; 15] = [
+    "hostnames",
+    "is_enabled",
+    "tls_automatic_enabled",
+    "tls_cert_content",
+    "tls_key_content",
+    "request_handlers",
+    "rewrite_functions",
+    "extra_headers",
+    "locations",
+    "websocket",
+    "waf",
+    "bots",
+    "error_response_format",
+    "cache_header_rules",
+    "access_log_enabled",
+];
+
+// The sites a delegated admin manages. A delegated admin sees and changes only these sites, with the bindings they can be
+// served on and the request handlers they already use. Handlers, processors and everything else stay with the full admins,
+// as they point to files and services on the server that other sites may not have access to
+#[derive(Debug)]
+pub struct SiteScope {
+    site_ids: HashSet<String>,
+}
+
+// The part of the configuration that a delegated admin submits, as returned by filter_configuration()
+#[derive(Debug, Default, Deserialize)]
+pub struct ScopedConfiguration {
+    #[serde(default)]
+    pub sites: Vec<Site>,
+    #[serde(default)]
+    pub binding_sites: Vec<BindingSiteRelationship>,
+}
+
+impl SiteScope {
+    pub fn new(site_ids: Vec<String>) -> Self {
+        SiteScope {
+            site_ids: site_ids.into_iter().collect(),
+        }
+    }
+
+    // The scope of a user, None if the user is a full admin
+    pub fn for_user(username: &str) -> Result<Option<SiteScope>, String> {
+        Ok(get_user_site_scope(username)?.map(SiteScope::new))
+    }
+
+    pub fn contains_site(&self, site_id: &str) -> bool {
+        self.site_ids.contains(site_id)
+    }
+
+    pub fn add_sites(&mut self, site_ids: &[String]) {
+        self.site_ids.extend(site_ids.iter().cloned());
+    }
+
+    // The configuration as a delegated admin sees it. Request handlers and processors are included for reference only
+    pub fn filter_configuration(&self, configuration: &Configuration) -> Value {
+        let sites: Vec<&Site> = configuration.sites.iter().filter(|site| self.contains_site(&site.id)).collect();
+        let request_handler_ids = get_request_handler_ids(&sites);
+        let request_handlers: Vec<_> = configuration.request_handlers.iter().filter(|handler| request_handler_ids.contains(&handler.id)).collect();
+        let processor_ids: HashSet<String> = request_handlers.iter().map(|handler| handler.processor_id.clone()).collect();
+
+        let mut site_ids: Vec<&String> = self.site_ids.iter().collect();
+        site_ids.sort();
+
+        json!({
+            "version": configuration.version,
+            "site_scope": site_ids,
+            "bindings": configuration.bindings.iter().filter(|binding| !binding.is_admin).collect::<Vec<_>>(),
+            "sites": sites,
+            "binding_sites": configuration.binding_sites.iter().filter(|relation| self.contains_site(&relation.site_id)).collect::<Vec<_>>(),
+            "request_handlers": request_handlers,
+            "static_file_processors": filter_by_id(&configuration.static_file_processors, &processor_ids),
+            "php_processors": filter_by_id(&configuration.php_processors, &processor_ids),
+            "proxy_processors": filter_by_id(&configuration.proxy_processors, &processor_ids),
+            "webdav_processors": filter_by_id(&configuration.webdav_processors, &processor_ids),
+            "cgi_processors": filter_by_id(&configuration.cgi_processors, &processor_ids),
+            "python_processors": filter_by_id(&configuration.python_processors, &processor_ids),
+            "node_processors": filter_by_id(&configuration.node_processors, &processor_ids),
+            "image_processors": filter_by_id(&configuration.image_processors, &processor_ids),
+        })
+    }
+
+    // Replace the sites in scope, and their bindings, in the full configuration with those submitted by the delegated admin.
+    // Returns the merged configuration and the IDs of the sites that were added, which are not yet in the scope
+    pub fn merge_configuration(&self, mut configuration: Configuration, submitted: ScopedConfiguration) -> Result<(Configuration, Vec<String>), Vec<String>> {
+        let mut errors = Vec::new();
+
+        let current_sites: Vec<&Site> = configuration.sites.iter().filter(|site| self.contains_site(&site.id)).collect();
+        let usable_request_handler_ids = get_request_handler_ids(&current_sites);
+
+        let taken_hostnames: HashSet<String> = configuration
+            .sites
+            .iter()
+            .filter(|site| !self.contains_site(&site.id))
+            .flat_map(|site| site.hostnames.iter().map(|hostname| hostname.to_lowercase()))
+            .collect();
+
+        let mut new_site_ids = Vec::new();
+        for site in &submitted.sites {
+            let default_site;
+            let current_site = match configuration.sites.iter().find(|current_site| current_site.id == site.id) {
+                Some(current_site) if !self.contains_site(&current_site.id) => {
+                    errors.push(format!("Site '{}' is not managed by you", site.id));
+                    continue;
+                }
+                Some(current_site) => current_site,
+                None => {
+                    if Uuid::parse_str(&site.id).is_err() {
+                        errors.push(format!("Site '{}': New sites need a UUID as ID", site.id));
+                    }
+                    new_site_ids.push(site.id.clone());
+                    default_site = Site::new();
+                    &default_site
+                }
+            };
+
+            let changed_fields = get_changed_site_fields(site, current_site);
+            if !changed_fields.is_empty() {
+                errors.push(format!("Site '{}': {} can only be changed by a full admin", site.id, changed_fields.join(", ")));
+            }
+            if site.is_default && !current_site.is_default {
+                errors.push(format!("Site '{}': Only a full admin can make a site the default site", site.id));
+            }
+            for hostname in site.hostnames.iter().filter(|hostname| !current_site.hostnames.contains(hostname)) {
+                // Wildcard, regex and catch-all hostnames take requests for hostnames of other sites, that have no exact match
+                if hostname.contains('*') || is_regex_hostname(hostname) {
+                    errors.push(format!("Site '{}': Hostname '{}' is a pattern, which only a full admin can add", site.id, hostname));
+                } else if taken_hostnames.contains(&hostname.to_lowercase()) {
+                    errors.push(format!("Site '{}': Hostname '{}' is used by a site that is not managed by you", site.id, hostname));
+                }
+            }
+
+            // Auth providers check credentials of the admins and of other services, which the tenant would get to try out
+            let default_location = Location::new();
+            for location in &site.locations {
+                let current_location = current_site.locations.iter().find(|current_location| current_location.id == location.id).unwrap_or(&default_location);
+                if location.auth_provider_id != current_location.auth_provider_id
+                    || location.auth_allowed_groups != current_location.auth_allowed_groups
+                    || location.auth_forward_user_header != current_location.auth_forward_user_header
+                {
+                    errors.push(format!("Site '{}': The auth provider of location '{}' can only be changed by a full admin", site.id, location.name));
+                }
+            }
+
+            for request_handler_id in site.request_handlers.iter().chain(site.locations.iter().flat_map(|location| location.request_handlers.iter())) {
+                if !usable_request_handler_ids.contains(request_handler_id) {
+                    errors.push(format!("Site '{}': Request handler '{}' is not available to your sites", site.id, request_handler_id));
+                }
+            }
+        }
+
+        let submitted_site_ids: HashSet<&String> = submitted.sites.iter().map(|site| &site.id).collect();
+        for relation in &submitted.binding_sites {
+            if !submitted_site_ids.contains(&relation.site_id) {
+                errors.push(format!("Binding relation for site '{}' refers to a site that is not submitted", relation.site_id));
+            }
+            if !configuration.bindings.iter().any(|binding| binding.id == relation.binding_id && !binding.is_admin) {
+                errors.push(format!("Site '{}': Binding '{}' is not available to your sites", relation.site_id, relation.binding_id));
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        configuration.sites.retain(|site| !self.contains_site(&site.id));
+        configuration.binding_sites.retain(|relation| !self.contains_site(&relation.site_id));
+
+        // New sites get their access log in the log directory, named after the site, so the admin can read it
+        for mut site in submitted.sites {
+            if new_site_ids.contains(&site.id) {
+                site.access_log_file = format!("./logs/{}.log", site.id);
+            }
+            configuration.sites.push(site);
+        }
+        configuration.binding_sites.extend(submitted.binding_sites);
+
+        Ok((configuration, new_site_ids))
+    }
+
+    // The file names in the log directory of the access logs of the sites in scope
+    pub fn get_log_file_names(&self, configuration: &Configuration) -> HashSet<String> {
+        configuration
+            .sites
+            .iter()
+            .filter(|site| self.contains_site(&site.id))
+            .filter_map(|site| {
+                let path = std::path::Path::new(&site.access_log_file);
+                let parent = path.parent()?.to_string_lossy().to_string();
+                if parent == "logs" || parent == "./logs" {
+                    path.file_name().map(|name| name.to_string_lossy().to_string())
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+fn get_request_handler_ids(sites: &[&Site]) -> HashSet<String> {
+    sites
+        .iter()
+        .flat_map(|site| site.request_handlers.iter().chain(site.locations.iter().flat_map(|location| location.request_handlers.iter())))
+        .cloned()
+        .collect()
+}
+
+// The names of the fields of a submitted site that differ from the current site, other than those a delegated admin may edit.
+// The ID and the default flag are checked on their own
+fn get_changed_site_fields(site: &Site, current_site: &Site) -> Vec<String> {
+    let (Ok(Value::Object(fields)), Ok(Value::Object(current_fields))) = (serde_json::to_value(site), serde_json::to_value(current_site)) else {
+        return vec!["The site".to_string()];
+    };
+    let mut changed_fields: Vec<String> = fields
+        .iter()
+        .filter(|(name, _)| !TENANT_EDITABLE_SITE_FIELDS.contains(&name.as_str()) && name.as_str() != "id" && name.as_str() != "is_default")
+        .filter(|(name, value)| current_fields.get(name.as_str()) != Some(value))
+        .map(|(name, _)| name.clone())
+        .collect();
+    changed_fields.sort();
+    changed_fields
+}
+
+fn filter_by_id<T: Serialize>(items: &[T], ids: &HashSet<String>) -> Vec<Value> {
+    items
+        .iter()
+        .filter_map(|item| serde_json::to_value(item).ok())
+        .filter(|value| value.get("id").and_then(|id| id.as_str()).is_some_and(|id| ids.contains(id)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::{binding::Binding, request_handler::RequestHandler, site::PhpIniSetting};
+
+    fn get_test_configuration() -> Configuration {
+        let mut configuration = Configuration::new();
+        configuration.bindings.clear();
+        configuration.sites.clear();
+        configuration.binding_sites.clear();
+
+        for (id, is_admin) in [("public", false), ("admin", true)] {
+            let mut binding = Binding::new();
+            binding.id = id.to_string();
+            binding.is_admin = is_admin;
+            configuration.bindings.push(binding);
+        }
+        for (site_id, handler_id) in [("own", "own-handler"), ("other", "other-handler")] {
+            let mut site = Site::new();
+            site.id = site_id.to_string();
+            site.hostnames = vec![format!("{}.example.com", site_id)];
+            site.request_handlers = vec![handler_id.to_string()];
+            site.access_log_file = format!("./logs/{}.log", site_id);
+            configuration.sites.push(site);
+            configuration.binding_sites.push(BindingSiteRelationship {
+                binding_id: "public".to_string(),
+                site_id: site_id.to_string(),
+            });
+            configuration.request_handlers.push(RequestHandler {
+                id: handler_id.to_string(),
+                is_enabled: true,
+                name: handler_id.to_string(),
+                processor_type: "static".to_string(),
+                processor_id: format!("{}-processor", site_id),
+                url_match: vec!["*".to_string()],
+            });
+        }
+        configuration
+    }
+
+    #[tokio::test]
+    async fn test_site_scope_filters_configuration() {
+        let configuration = get_test_configuration();
+        let site_scope = SiteScope::new(vec!["own".to_string()]);
+
+        let filtered = site_scope.filter_configuration(&configuration);
+        assert_eq!(filtered["sites"].as_array().unwrap().len(), 1);
+        assert_eq!(filtered["sites"][0]["id"], "own");
+        assert_eq!(filtered["binding_sites"].as_array().unwrap().len(), 1);
+        assert_eq!(filtered["bindings"].as_array().unwrap().len(), 1);
+        assert_eq!(filtered["request_handlers"][0]["id"], "own-handler");
+        assert!(filtered.get("core").is_none());
+
+        assert_eq!(site_scope.get_log_file_names(&configuration), HashSet::from(["own.log".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_site_scope_merges_configuration() {
+        let site_scope = SiteScope::new(vec!["own".to_string()]);
+        let configuration = get_test_configuration();
+        let mut own_site = configuration.sites[0].clone();
+        own_site.hostnames = vec!["own.example.com".to_string(), "www.own.example.com".to_string()];
+        let mut new_site = Site::new();
+        new_site.request_handlers = vec!["own-handler".to_string()];
+        let new_site_id = new_site.id.clone();
+
+        let submitted = ScopedConfiguration {
+            sites: vec![own_site.clone(), new_site.clone()],
+            binding_sites: vec![BindingSiteRelationship {
+                binding_id: "public".to_string(),
+                site_id: new_site_id.clone(),
+            }],
+        };
+        let (merged, new_site_ids) = site_scope.merge_configuration(configuration, submitted).unwrap();
+        assert_eq!(new_site_ids, vec![new_site_id.clone()]);
+        assert_eq!(merged.sites.len(), 3);
+        assert!(merged.sites.iter().any(|site| site.id == "other"));
+        assert!(merged.sites.iter().any(|site| site.id == "own" && site.hostnames.len() == 2));
+        assert_eq!(merged.sites.iter().find(|site| site.id == new_site_id).unwrap().access_log_file, format!("./logs/{}.log", new_site_id));
+        assert_eq!(merged.binding_sites.len(), 2);
+        assert!(!merged.binding_sites.iter().any(|relation| relation.site_id == "own"));
+    }
+
+    fn get_merge_errors(sites: Vec<Site>, binding_sites: Vec<BindingSiteRelationship>) -> Vec<String> {
+        let site_scope = SiteScope::new(vec!["own".to_string()]);
+        site_scope.merge_configuration(get_test_configuration(), ScopedConfiguration { sites, binding_sites }).unwrap_err()
+    }
+
+    fn get_own_site() -> Site {
+        get_test_configuration().sites[0].clone()
+    }
+
+    #[tokio::test]
+    async fn test_site_scope_rejects_other_sites() {
+        let mut other_site = get_test_configuration().sites[1].clone();
+        other_site.hostnames = vec!["taken.example.com".to_string()];
+        let errors = get_merge_errors(vec![other_site], Vec::new());
+        assert_eq!(errors, vec!["Site 'other' is not managed by you".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_site_scope_rejects_request_handlers_of_other_sites() {
+        let mut own_site = get_own_site();
+        own_site.request_handlers = vec!["other-handler".to_string()];
+        let errors = get_merge_errors(vec![own_site], Vec::new());
+        assert_eq!(errors, vec!["Site 'own': Request handler 'other-handler' is not available to your sites".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_site_scope_rejects_admin_bindings() {
+        let relation = BindingSiteRelationship {
+            binding_id: "admin".to_string(),
+            site_id: "own".to_string(),
+        };
+        let errors = get_merge_errors(vec![get_own_site()], vec![relation]);
+        assert_eq!(errors, vec!["Site 'own': Binding 'admin' is not available to your sites".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_site_scope_rejects_fields_of_full_admins() {
+        let mut own_site = get_own_site();
+        own_site.sendfile_root = "/".to_string();
+        own_site.php_ini_settings = vec![PhpIniSetting {
+            name: "open_basedir".to_string(),
+            value: "/".to_string(),
+            is_admin: true,
+        }];
+        let errors = get_merge_errors(vec![own_site], Vec::new());
+        assert_eq!(errors, vec!["Site 'own': php_ini_settings, sendfile_root can only be changed by a full admin".to_string()]);
+
+        let mut own_site = get_own_site();
+        own_site.webroot_sync.protocol = "sftp".to_string();
+        own_site.webroot_sync.local_path = "/".to_string();
+        let errors = get_merge_errors(vec![own_site], Vec::new());
+        assert_eq!(errors, vec!["Site 'own': webroot_sync can only be changed by a full admin".to_string()]);
+
+        let mut new_site = Site::new();
+        new_site.disk_quota_mb = 1;
+        let errors = get_merge_errors(vec![get_own_site(), new_site.clone()], Vec::new());
+        assert_eq!(errors, vec![format!("Site '{}': disk_quota_mb can only be changed by a full admin", new_site.id)]);
+    }
+
+    #[tokio::test]
+    async fn test_site_scope_rejects_hostnames_of_other_sites() {
+        let mut own_site = get_own_site();
+        own_site.hostnames.push("OTHER.example.com".to_string());
+        let errors = get_merge_errors(vec![own_site], Vec::new());
+        assert_eq!(errors, vec!["Site 'own': Hostname 'OTHER.example.com' is used by a site that is not managed by you".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_site_scope_rejects_hostname_patterns() {
+        for hostname in ["*.example.com", "~.*", "*"] {
+            let mut own_site = get_own_site();
+            own_site.hostnames.push(hostname.to_string());
+            let errors = get_merge_errors(vec![own_site], Vec::new());
+            assert_eq!(errors, vec![format!("Site 'own': Hostname '{}' is a pattern, which only a full admin can add", hostname)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_site_scope_rejects_auth_providers() {
+        let mut own_site = get_own_site();
+        let mut location = Location::new();
+        location.name = "Private".to_string();
+        location.auth_provider_id = "local".to_string();
+        own_site.locations.push(location);
+        let errors = get_merge_errors(vec![own_site], Vec::new());
+        assert_eq!(errors, vec!["Site 'own': The auth provider of location 'Private' can only be changed by a full admin".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_site_scope_rejects_default_site() {
+        let mut own_site = get_own_site();
+        own_site.is_default = true;
+        let errors = get_merge_errors(vec![own_site], Vec::new());
+        assert_eq!(errors, vec!["Site 'own': Only a full admin can make a site the default site".to_string()]);
+    }
+}
@@ -0,0 +1,235 @@
+// ============================================================================
+// STATUS PAGE
+// ============================================================================
+//
+// Public status page of the admin portal, served without authentication at
+// /status as HTML and at /status.json, when enabled in the core settings. It
+// shows the uptime of the server, the health of the sites from the health
+// checks of their upstream servers, with their availability over the last 24
+// hours, and the recent admin alerts as incidents.
+//
+// Alert messages can name internal systems, such as upstream servers or file
+// paths, so incidents only show when and where they happened, unless the
+// messages are shown too.
+// ============================================================================
+
+use serde::Serialize;
+
+use crate::configuration::configuration::Configuration;
+use crate::core::admin_alerts::AdminAlert;
+use crate::core::site_health::{SiteHealth, get_site_health, is_admin_portal_site};
+use crate::http::request_handlers::processors::server_side_includes::escape_html;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusPageIncident {
+    pub created_at: String,
+    pub source: String,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusPage {
+    pub title: String,
+    pub status: String, // "operational", "degraded" or "down", over the enabled sites shown
+    pub uptime_seconds: u64,
+    pub generated_at: String,
+    pub sites: Vec<SiteHealth>,
+    pub incidents: Vec<StatusPageIncident>,
+}
+
+// The status page from the configuration, with the admin alerts newest first
+pub fn build_status_page(configuration: &Configuration, uptime_seconds: u64, alerts: &[AdminAlert]) -> StatusPage {
+    let settings = &configuration.core.status_page;
+
+    let sites: Vec<SiteHealth> = if settings.site_ids.is_empty() {
+        configuration
+            .sites
+            .iter()
+            .filter(|site| site.is_enabled && !is_admin_portal_site(configuration, site))
+            .map(|site| get_site_health(configuration, site))
+            .collect()
+    } else {
+        settings
+            .site_ids
+            .iter()
+            .filter_map(|site_id| configuration.sites.iter().find(|site| &site.id == site_id))
+            .map(|site| get_site_health(configuration, site))
+            .collect()
+    };
+
+    let enabled_sites: Vec<&SiteHealth> = sites.iter().filter(|site| site.status != "disabled").collect();
+    let status = if !enabled_sites.is_empty() && enabled_sites.iter().all(|site| site.status == "down") {
+        "down"
+    } else if enabled_sites.iter().any(|site| site.status != "operational") {
+        "degraded"
+    } else {
+        "operational"
+    };
+
+    let incidents = alerts
+        .iter()
+        .take(settings.incident_count as usize)
+        .map(|alert| StatusPageIncident {
+            created_at: alert.created_at.clone(),
+            source: alert.source.clone(),
+            message: settings.show_incident_messages.then(|| alert.message.clone()),
+        })
+        .collect();
+
+    StatusPage {
+        title: settings.title.clone(),
+        status: status.to_string(),
+        uptime_seconds,
+        generated_at: chrono::Utc::now().to_rfc3339(),
+        sites,
+        incidents,
+    }
+}
+
+// Such as "3 days, 4 hours" or "12 minutes"
+fn format_uptime(uptime_seconds: u64) -> String {
+    let units = [("day", uptime_seconds / 86400), ("hour", uptime_seconds % 86400 / 3600), ("minute", uptime_seconds % 3600 / 60)];
+    let parts: Vec<String> = units
+        .iter()
+        .skip_while(|(_, value)| *value == 0)
+        .take(2)
+        .filter(|(_, value)| *value > 0)
+        .map(|(name, value)| format!("{} {}{}", value, name, if *value == 1 { "" } else { "s" }))
+        .collect();
+    if parts.is_empty() { "less than a minute".to_string() } else { parts.join(", ") }
+}
+
+fn status_label(status: &str) -> &'static str {
+    match status {
+        "operational" => "Operational",
+        "degraded" => "Degraded",
+        "down" => "Down",
+        _ => "Disabled",
+    }
+}
+
+fn status_color(status: &str) -> &'static str {
+    match status {
+        "operational" => "#1a7f37",
+        "degraded" => "#bf8700",
+        "down" => "#cf222e",
+        _ => "#6e7781",
+    }
+}
+
+pub fn render_status_page_html(status_page: &StatusPage) -> String {
+    let title = escape_html(&status_page.title);
+    let overall = match status_page.status.as_str() {
+        "operational" => "All systems operational",
+        "degraded" => "Some systems are degraded",
+        _ => "Systems are down",
+    };
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<meta http-equiv=\"refresh\" content=\"60\">\n");
+    html.push_str(&format!("<title>{}</title>\n", title));
+    html.push_str(
+        "<style>body{font-family:system-ui,sans-serif;max-width:760px;margin:2em auto;padding:0 1em;color:#1f2328}\
+         .banner{color:#fff;padding:1em;border-radius:6px;font-weight:600}\
+         table{width:100%;border-collapse:collapse;margin:1em 0}td,th{text-align:left;padding:.5em;border-bottom:1px solid #d0d7de}\
+         .muted{color:#6e7781;font-size:.9em}</style>\n</head>\n<body>\n",
+    );
+    html.push_str(&format!("<h1>{}</h1>\n", title));
+    html.push_str(&format!("<div class=\"banner\" style=\"background:{}\">{}</div>\n", status_color(&status_page.status), overall));
+    html.push_str(&format!("<p class=\"muted\">Server uptime: {}</p>\n", format_uptime(status_page.uptime_seconds)));
+
+    html.push_str("<h2>Sites</h2>\n");
+    if status_page.sites.is_empty() {
+        html.push_str("<p class=\"muted\">No sites to show.</p>\n");
+    } else {
+        html.push_str("<table>\n<tr><th>Site</th><th>Status</th><th>Availability (24h)</th></tr>\n");
+        for site in &status_page.sites {
+            let availability = match site.availability_percent {
+                Some(percent) => format!("{:.2}%", percent),
+                None => "-".to_string(),
+            };
+            html.push_str(&format!(
+                "<tr><td>{}</td><td style=\"color:{}\">{}</td><td>{}</td></tr>\n",
+                escape_html(&site.name),
+                status_color(&site.status),
+                status_label(&site.status),
+                availability
+            ));
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("<h2>Recent incidents</h2>\n");
+    if status_page.incidents.is_empty() {
+        html.push_str("<p class=\"muted\">No recent incidents.</p>\n");
+    } else {
+        html.push_str("<table>\n<tr><th>Time</th><th>Source</th>");
+        let show_messages = status_page.incidents.iter().any(|incident| incident.message.is_some());
+        if show_messages {
+            html.push_str("<th>Message</th>");
+        }
+        html.push_str("</tr>\n");
+        for incident in &status_page.incidents {
+            html.push_str(&format!("<tr><td>{}</td><td>{}</td>", escape_html(&incident.created_at), escape_html(&incident.source)));
+            if show_messages {
+                html.push_str(&format!("<td>{}</td>", escape_html(incident.message.as_deref().unwrap_or(""))));
+            }
+            html.push_str("</tr>\n");
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str(&format!("<p class=\"muted\">Updated {}</p>\n</body>\n</html>\n", escape_html(&status_page.generated_at)));
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::site::Site;
+
+    #[test]
+    fn test_build_status_page() {
+        let mut configuration = Configuration::new();
+        configuration.core.status_page.incident_count = 1;
+        let mut site = Site::new();
+        site.is_enabled = true;
+        site.hostnames = vec!["shop.example.com".to_string()];
+        let shown_site_id = site.id.clone();
+        configuration.sites.push(site);
+        let mut disabled_site = Site::new();
+        disabled_site.is_enabled = false;
+        configuration.sites.push(disabled_site);
+
+        let alerts = vec![
+            AdminAlert {
+                created_at: "2026-03-10T10:00:00+00:00".to_string(),
+                source: "external_handler".to_string(),
+                message: "<php-fpm at 10.0.0.5 crashed>".to_string(),
+            },
+            AdminAlert {
+                created_at: "2026-03-09T10:00:00+00:00".to_string(),
+                source: "disk_usage".to_string(),
+                message: "Disk quota exceeded".to_string(),
+            },
+        ];
+
+        let status_page = build_status_page(&configuration, 90061, &alerts);
+        assert_eq!(status_page.status, "operational");
+        assert!(status_page.sites.iter().any(|site| site.site_id == shown_site_id && site.name == "shop.example.com"));
+        assert!(status_page.sites.iter().all(|site| site.status != "disabled"));
+        assert_eq!(status_page.incidents.len(), 1);
+        assert_eq!(status_page.incidents[0].message, None);
+
+        let html = render_status_page_html(&status_page);
+        assert!(html.contains("shop.example.com"));
+        assert!(html.contains("Server uptime: 1 day, 1 hour"));
+        assert!(!html.contains("10.0.0.5"));
+
+        configuration.core.status_page.show_incident_messages = true;
+        let html = render_status_page_html(&build_status_page(&configuration, 30, &alerts));
+        assert!(html.contains("&lt;php-fpm at 10.0.0.5 crashed&gt;"));
+        assert!(html.contains("Server uptime: less than a minute"));
+    }
+}
@@ -0,0 +1,10 @@
+// Trait that authentication providers must implement
+#[allow(async_fn_in_trait)]
+pub trait AuthProviderTrait {
+    // Returns the type of the provider as a string, e.g. "local", "ldap", "oidc"
+    fn get_type(&self) -> String;
+
+    // Verify the username and password. Returns the authenticated username, or None if the credentials are not valid.
+    // Errors are for when the provider could not give an answer, such as an unreachable LDAP server
+    async fn authenticate(&self, username: &str, password: &str) -> Result<Option<String>, String>;
+}
@@ -0,0 +1,285 @@
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use dashmap::DashMap;
+
+use crate::{
+    authentication::{
+        auth_provider_trait::AuthProviderTrait,
+        providers::{
+            htpasswd_provider::HtpasswdAuthProvider,
+            kerberos_provider::KerberosAuthProvider,
+            ldap_provider::{LdapAuthProvider, get_group_common_name},
+            local_provider::LocalAuthProvider,
+            oidc_provider::OidcAuthProvider,
+        },
+    },
+    configuration::{auth_provider::AuthProvider, cached_configuration::get_cached_configuration, location::Location},
+    core::admin_user::{User, authenticate_user, get_or_create_external_user},
+    http::basic_auth::parse_basic_auth_header,
+    logging::syslog::{error, trace},
+};
+
+// Cached entries are cleaned up when the cache grows beyond this
+const CREDENTIAL_CACHE_CLEANUP_SIZE: usize = 10_000;
+
+// A successful login and the groups of the user, until it expires
+struct CachedLogin {
+    expires_at: Instant,
+    groups: Vec<String>,
+}
+
+// Result of checking the credentials of a request for a protected location
+#[derive(Debug, PartialEq)]
+pub enum LocationAccess {
+    Granted(String),         // The authenticated username
+    Unauthenticated(String), // The "WWW-Authenticate" challenge to respond with
+    Forbidden,               // Valid credentials, but the user is not in one of the allowed groups
+}
+
+// Successful logins, keyed by a hash of the provider and credentials, so no passwords are kept in memory
+fn get_credential_cache() -> &'static DashMap<String, CachedLogin> {
+    static CREDENTIAL_CACHE: OnceLock<DashMap<String, CachedLogin>> = OnceLock::new();
+    CREDENTIAL_CACHE.get_or_init(DashMap::new)
+}
+
+fn get_credential_cache_key(provider: &AuthProvider, username: &str, password: &str) -> String {
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    for part in [provider.id.as_str(), username, password] {
+        context.update(part.as_bytes());
+        context.update(&[0]);
+    }
+    context.finish().as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Verify a username and password with the given provider. Returns the authenticated username if valid
+pub async fn authenticate_with_provider(provider: &AuthProvider, username: &str, password: &str) -> Result<Option<String>, String> {
+    Ok(authenticate_and_get_groups(provider, username, password).await?.map(|_| username.to_string()))
+}
+
+// Verify a username and password with the given provider. Returns the groups of the user if valid, which are only
+// looked up for providers that support groups. Groups are cached along with the login, so changes apply after the cache time
+pub async fn authenticate_and_get_groups(provider: &AuthProvider, username: &str, password: &str) -> Result<Option<Vec<String>>, String> {
+    let cache = get_credential_cache();
+    let cache_key = get_credential_cache_key(provider, username, password);
+    if provider.cache_seconds > 0
+        && let Some(groups) = cache.get(&cache_key).filter(|entry| entry.expires_at > Instant::now()).map(|entry| entry.groups.clone())
+    {
+        return Ok(Some(groups));
+    }
+    cache.remove(&cache_key);
+
+    let result = match provider.provider_type.as_str() {
+        "local" => LocalAuthProvider.authenticate(username, password).await.map(|user| user.map(|_| Vec::new())),
+        "htpasswd" => HtpasswdAuthProvider {
+            htpasswd_file: provider.htpasswd_file.clone(),
+        }
+        .authenticate(username, password)
+        .await
+        .map(|user| user.map(|_| Vec::new())),
+        "ldap" => {
+            LdapAuthProvider {
+                ldap_url: provider.ldap_url.clone(),
+                bind_dn_template: provider.ldap_bind_dn_template.clone(),
+                search_base_dn: provider.ldap_search_base_dn.clone(),
+                user_attribute: provider.ldap_user_attribute.clone(),
+                group_attribute: provider.ldap_group_attribute.clone(),
+                timeout_seconds: provider.timeout_seconds,
+            }
+            .authenticate_and_get_groups(username, password)
+            .await
+        }
+        "oidc" => OidcAuthProvider {
+            token_url: provider.oidc_token_url.clone(),
+            client_id: provider.oidc_client_id.clone(),
+            client_secret: provider.oidc_client_secret.clone(),
+            scope: provider.oidc_scope.clone(),
+            timeout_seconds: provider.timeout_seconds,
+        }
+        .authenticate(username, password)
+        .await
+        .map(|user| user.map(|_| Vec::new())),
+        "kerberos" => KerberosAuthProvider {
+            keytab_file: provider.kerberos_keytab_file.clone(),
+        }
+        .authenticate(username, password)
+        .await
+        .map(|user| user.map(|_| Vec::new())),
+        unknown => Err(format!("Auth provider type '{}' is not supported", unknown)),
+    };
+
+    if provider.cache_seconds > 0
+        && let Ok(Some(groups)) = &result
+    {
+        if cache.len() >= CREDENTIAL_CACHE_CLEANUP_SIZE {
+            let now = Instant::now();
+            cache.retain(|_, entry| entry.expires_at > now);
+        }
+        cache.insert(
+            cache_key,
+            CachedLogin {
+                expires_at: Instant::now() + Duration::from_secs(provider.cache_seconds as u64),
+                groups: groups.clone(),
+            },
+        );
+    }
+
+    result
+}
+
+// Check if any of the group DNs of a user matches the allowed groups, by full DN or by CN (case insensitive)
+fn is_member_of_allowed_group(groups: &[String], allowed_groups: &[String]) -> bool {
+    groups.iter().any(|group_dn| {
+        allowed_groups
+            .iter()
+            .any(|allowed| group_dn.eq_ignore_ascii_case(allowed) || get_group_common_name(group_dn).is_some_and(|common_name| common_name.eq_ignore_ascii_case(allowed)))
+    })
+}
+
+// Authenticate an admin portal login. Without a selected provider, only the Gruxi user database is used.
+// With a provider, local users are tried first while local login is allowed, so existing accounts keep working
+pub async fn authenticate_admin_user(username: &str, password: &str) -> Result<Option<User>, String> {
+    let (admin_portal, provider) = {
+        let config = get_cached_configuration().get_configuration().await;
+        let admin_portal = config.core.admin_portal.clone();
+        let provider = config.auth_providers.iter().find(|p| p.id == admin_portal.auth_provider_id).cloned();
+        (admin_portal, provider)
+    };
+
+    if admin_portal.auth_provider_id.is_empty() {
+        return authenticate_user(username, password);
+    }
+
+    if admin_portal.allow_local_login
+        && let Some(user) = authenticate_user(username, password)?
+    {
+        return Ok(Some(user));
+    }
+
+    let provider = match provider {
+        Some(provider) => provider,
+        None => return Err(format!("Admin portal auth provider '{}' does not exist", admin_portal.auth_provider_id)),
+    };
+
+    match authenticate_with_provider(&provider, username, password).await? {
+        Some(authenticated_username) => {
+            trace(format!("Admin user '{}' authenticated by auth provider '{}'", authenticated_username, provider.name));
+            get_or_create_external_user(&authenticated_username)
+        }
+        None => Ok(None),
+    }
+}
+
+// Verify the "Authorization" header of a request for a protected location. Locations with an auth provider use it,
+// otherwise the users configured on the location are used. Locations with allowed groups also require group membership
+pub async fn authenticate_location_request(location: &Location, authorization_header: &str) -> LocationAccess {
+    let basic_challenge = format!("Basic realm=\"{}\"", location.get_auth_realm());
+
+    if location.auth_provider_id.is_empty() {
+        return match parse_basic_auth_header(authorization_header) {
+            Some((username, _)) if location.verify_basic_auth(authorization_header).await => LocationAccess::Granted(username),
+            _ => LocationAccess::Unauthenticated(basic_challenge),
+        };
+    }
+
+    let provider = {
+        let config = get_cached_configuration().get_configuration().await;
+        config.auth_providers.iter().find(|p| p.id == location.auth_provider_id).cloned()
+    };
+    let provider = match provider {
+        Some(provider) => provider,
+        None => {
+            error(format!("Location '{}' uses auth provider '{}', which does not exist", location.name, location.auth_provider_id));
+            return LocationAccess::Unauthenticated(basic_challenge);
+        }
+    };
+
+    if provider.provider_type == "kerberos" {
+        return authenticate_negotiate_request(location, &provider, authorization_header).await;
+    }
+
+    let (username, password) = match parse_basic_auth_header(authorization_header) {
+        Some(credentials) => credentials,
+        None => return LocationAccess::Unauthenticated(basic_challenge),
+    };
+
+    let groups = match authenticate_and_get_groups(&provider, &username, &password).await {
+        Ok(Some(groups)) => groups,
+        Ok(None) => return LocationAccess::Unauthenticated(basic_challenge),
+        Err(e) => {
+            error(format!("Auth provider '{}' failed to authenticate user '{}': {}", provider.name, username, e));
+            return LocationAccess::Unauthenticated(basic_challenge);
+        }
+    };
+
+    if location.auth_allowed_groups.is_empty() || is_member_of_allowed_group(&groups, &location.auth_allowed_groups) {
+        LocationAccess::Granted(username)
+    } else {
+        trace(format!("User '{}' is not a member of any allowed group for location '{}'", username, location.name));
+        LocationAccess::Forbidden
+    }
+}
+
+// Verify a "Negotiate" (SPNEGO) Authorization header, which browsers send with a Kerberos ticket after the challenge.
+// Tickets are single use, so they are not cached like passwords
+async fn authenticate_negotiate_request(location: &Location, provider: &AuthProvider, authorization_header: &str) -> LocationAccess {
+    let negotiate_challenge = "Negotiate".to_string();
+
+    let token = match authorization_header.trim().split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("negotiate") => match STANDARD.decode(token.trim()) {
+            Ok(token) if !token.is_empty() => token,
+            _ => return LocationAccess::Unauthenticated(negotiate_challenge),
+        },
+        _ => return LocationAccess::Unauthenticated(negotiate_challenge),
+    };
+
+    let kerberos_provider = KerberosAuthProvider {
+        keytab_file: provider.kerberos_keytab_file.clone(),
+    };
+    match kerberos_provider.accept_negotiate_token(token).await {
+        Ok(principal) => {
+            trace(format!("Kerberos principal '{}' authenticated for location '{}'", principal, location.name));
+            LocationAccess::Granted(principal)
+        }
+        Err(e) => {
+            error(format!("Auth provider '{}' failed to authenticate a Kerberos ticket: {}", provider.name, e));
+            LocationAccess::Unauthenticated(negotiate_challenge)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_member_of_allowed_group() {
+        let groups = vec!["CN=Web Admins,OU=Groups,DC=corp,DC=example,DC=com".to_string(), "cn=staff,dc=example,dc=com".to_string()];
+
+        assert!(is_member_of_allowed_group(&groups, &["web admins".to_string()]));
+        assert!(is_member_of_allowed_group(&groups, &["Other".to_string(), "CN=Staff,DC=example,DC=com".to_string()]));
+        assert!(!is_member_of_allowed_group(&groups, &["Admins".to_string(), "OU=Groups".to_string()]));
+        assert!(!is_member_of_allowed_group(&[], &["staff".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_with_provider_caches_successful_logins() {
+        let htpasswd_path = std::env::temp_dir().join(format!("gruxi-htpasswd-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&htpasswd_path, format!("alice:{}\n", bcrypt::hash("secret", 4).unwrap())).unwrap();
+
+        let mut provider = AuthProvider::new();
+        provider.provider_type = "htpasswd".to_string();
+        provider.htpasswd_file = htpasswd_path.to_string_lossy().to_string();
+
+        assert_eq!(authenticate_with_provider(&provider, "alice", "secret").await, Ok(Some("alice".to_string())));
+        assert_eq!(authenticate_with_provider(&provider, "alice", "wrong").await, Ok(None));
+
+        // Removing the file does not affect cached logins, but new credentials fail
+        std::fs::remove_file(&htpasswd_path).unwrap();
+        assert_eq!(authenticate_with_provider(&provider, "alice", "secret").await, Ok(Some("alice".to_string())));
+        assert!(authenticate_with_provider(&provider, "alice", "other").await.is_err());
+    }
+}
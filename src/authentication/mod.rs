@@ -0,0 +1,3 @@
+pub mod auth_provider_trait;
+pub mod authenticator;
+pub mod providers;
@@ -0,0 +1,80 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+use crate::{authentication::auth_provider_trait::AuthProviderTrait, logging::syslog::warn};
+
+// Authenticates against an Apache style htpasswd file, as created by "htpasswd -B"
+pub struct HtpasswdAuthProvider {
+    pub htpasswd_file: String,
+}
+
+impl AuthProviderTrait for HtpasswdAuthProvider {
+    fn get_type(&self) -> String {
+        "htpasswd".to_string()
+    }
+
+    async fn authenticate(&self, username: &str, password: &str) -> Result<Option<String>, String> {
+        // The file is read on each call, so changes apply without a reload. Successful logins are cached by the caller
+        let content = tokio::fs::read_to_string(&self.htpasswd_file)
+            .await
+            .map_err(|e| format!("Failed to read htpasswd file '{}': {}", self.htpasswd_file, e))?;
+
+        let hash = match find_htpasswd_hash(&content, username) {
+            Some(hash) => hash,
+            None => return Ok(None),
+        };
+
+        match verify_htpasswd_hash(hash, password) {
+            Some(true) => Ok(Some(username.to_string())),
+            Some(false) => Ok(None),
+            None => {
+                warn(format!(
+                    "htpasswd entry for '{}' uses an unsupported hash format, only bcrypt and {{SHA}} are supported - Recreate it with 'htpasswd -B'",
+                    username
+                ));
+                Ok(None)
+            }
+        }
+    }
+}
+
+// Find the hash for a user in the "username:hash" lines of a htpasswd file
+fn find_htpasswd_hash<'a>(content: &'a str, username: &str) -> Option<&'a str> {
+    content
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| *name == username)
+        .map(|(_, hash)| hash)
+}
+
+// Returns None for hash formats we do not support, such as Apache MD5 ("$apr1$") and crypt
+fn verify_htpasswd_hash(hash: &str, password: &str) -> Option<bool> {
+    if hash.starts_with("$2") {
+        // bcrypt, "$2y$" from htpasswd is handled by the bcrypt crate
+        Some(bcrypt::verify(password, hash).unwrap_or(false))
+    } else if let Some(expected) = hash.strip_prefix("{SHA}") {
+        let digest = ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, password.as_bytes());
+        Some(STANDARD.encode(digest.as_ref()) == expected)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_htpasswd_verify_sha_and_bcrypt() {
+        let bcrypt_hash = bcrypt::hash("secret", 4).unwrap();
+        // {SHA} hash of "secret"
+        let content = format!("# users\nalice:{{SHA}}5en6G6MezRroT3XKqkdPOmY/BfQ=\nbob:{}\ncarol:$apr1$abc$def\n", bcrypt_hash);
+
+        assert_eq!(verify_htpasswd_hash(find_htpasswd_hash(&content, "alice").unwrap(), "secret"), Some(true));
+        assert_eq!(verify_htpasswd_hash(find_htpasswd_hash(&content, "alice").unwrap(), "wrong"), Some(false));
+        assert_eq!(verify_htpasswd_hash(find_htpasswd_hash(&content, "bob").unwrap(), "secret"), Some(true));
+        assert_eq!(verify_htpasswd_hash(find_htpasswd_hash(&content, "carol").unwrap(), "secret"), None);
+        assert!(find_htpasswd_hash(&content, "dave").is_none());
+    }
+}
@@ -0,0 +1,283 @@
+use crate::authentication::auth_provider_trait::AuthProviderTrait;
+
+// Authenticates browsers with Kerberos single sign-on, through the "Negotiate" (SPNEGO) scheme. The ticket is validated
+// by GSSAPI with the configured keytab, or by SSPI with the account Gruxi runs as on Windows
+pub struct KerberosAuthProvider {
+    pub keytab_file: String, // Empty means the default keytab of the system (KRB5_KTNAME or /etc/krb5.keytab). Not used on Windows
+}
+
+impl AuthProviderTrait for KerberosAuthProvider {
+    fn get_type(&self) -> String {
+        "kerberos".to_string()
+    }
+
+    async fn authenticate(&self, _username: &str, _password: &str) -> Result<Option<String>, String> {
+        Err("Kerberos auth providers only support Negotiate authentication, not usernames and passwords".to_string())
+    }
+}
+
+impl KerberosAuthProvider {
+    // Validate the SPNEGO token of a "Negotiate" Authorization header. Returns the client principal,
+    // such as "alice@CORP.EXAMPLE.COM" with GSSAPI or "CORP\alice" with SSPI
+    pub async fn accept_negotiate_token(&self, token: Vec<u8>) -> Result<String, String> {
+        let keytab_file = self.keytab_file.clone();
+        tokio::task::spawn_blocking(move || accept_security_context(&keytab_file, &token))
+            .await
+            .map_err(|e| format!("Kerberos authentication task failed: {}", e))?
+    }
+}
+
+#[cfg(unix)]
+use gssapi::accept_security_context;
+#[cfg(windows)]
+use sspi::accept_security_context;
+
+#[cfg(unix)]
+mod gssapi {
+    use std::{
+        ffi::{CString, c_char, c_void},
+        ptr,
+        sync::{Mutex, OnceLock},
+    };
+
+    use libloading::{Library, Symbol};
+
+    const GSS_S_COMPLETE: u32 = 0;
+
+    // gss_buffer_desc
+    #[repr(C)]
+    struct GssBuffer {
+        length: usize,
+        value: *mut c_void,
+    }
+
+    type AcceptSecContextFn = unsafe extern "C" fn(
+        *mut u32,         // minor_status
+        *mut *mut c_void, // context_handle
+        *mut c_void,      // acceptor_cred_handle
+        *mut GssBuffer,   // input_token_buffer
+        *mut c_void,      // input_chan_bindings
+        *mut *mut c_void, // src_name
+        *mut *mut c_void, // mech_type
+        *mut GssBuffer,   // output_token
+        *mut u32,         // ret_flags
+        *mut u32,         // time_rec
+        *mut *mut c_void, // delegated_cred_handle
+    ) -> u32;
+    type DisplayNameFn = unsafe extern "C" fn(*mut u32, *mut c_void, *mut GssBuffer, *mut *mut c_void) -> u32;
+    type ReleaseBufferFn = unsafe extern "C" fn(*mut u32, *mut GssBuffer) -> u32;
+    type ReleaseNameFn = unsafe extern "C" fn(*mut u32, *mut *mut c_void) -> u32;
+    type DeleteSecContextFn = unsafe extern "C" fn(*mut u32, *mut *mut c_void, *mut GssBuffer) -> u32;
+    type RegisterAcceptorIdentityFn = unsafe extern "C" fn(*const c_char) -> u32;
+
+    // The library is loaded on first use, so Gruxi runs without Kerberos libraries installed, when it is not used
+    static GSSAPI_LIBRARY: OnceLock<Result<Library, String>> = OnceLock::new();
+    // The acceptor keytab is process wide in GSSAPI, so tokens are accepted one at a time
+    static ACCEPT_LOCK: Mutex<()> = Mutex::new(());
+
+    fn get_library() -> Result<&'static Library, String> {
+        GSSAPI_LIBRARY
+            .get_or_init(|| {
+                ["libgssapi_krb5.so.2", "libgssapi_krb5.so", "libgssapi_krb5.dylib"]
+                    .iter()
+                    .find_map(|name| unsafe { Library::new(name) }.ok())
+                    .ok_or("GSSAPI library (libgssapi_krb5) not found - Install the MIT Kerberos libraries to use Kerberos".to_string())
+            })
+            .as_ref()
+            .map_err(|e| e.clone())
+    }
+
+    fn get_symbol<'a, T>(library: &'a Library, name: &[u8]) -> Result<Symbol<'a, T>, String> {
+        unsafe { library.get(name) }.map_err(|e| format!("GSSAPI function {} not found: {}", String::from_utf8_lossy(&name[..name.len() - 1]), e))
+    }
+
+    pub fn accept_security_context(keytab_file: &str, token: &[u8]) -> Result<String, String> {
+        let library = get_library()?;
+        let accept_sec_context: Symbol<AcceptSecContextFn> = get_symbol(library, b"gss_accept_sec_context\0")?;
+        let display_name: Symbol<DisplayNameFn> = get_symbol(library, b"gss_display_name\0")?;
+        let release_buffer: Symbol<ReleaseBufferFn> = get_symbol(library, b"gss_release_buffer\0")?;
+        let release_name: Symbol<ReleaseNameFn> = get_symbol(library, b"gss_release_name\0")?;
+        let delete_sec_context: Symbol<DeleteSecContextFn> = get_symbol(library, b"gss_delete_sec_context\0")?;
+
+        let _accept_guard = ACCEPT_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if !keytab_file.is_empty() {
+            let register_acceptor_identity: Symbol<RegisterAcceptorIdentityFn> = get_symbol(library, b"gsskrb5_register_acceptor_identity\0")?;
+            let keytab_file = CString::new(keytab_file).map_err(|_| "Invalid keytab file path".to_string())?;
+            if unsafe { register_acceptor_identity(keytab_file.as_ptr()) } != GSS_S_COMPLETE {
+                return Err("Failed to use the configured keytab file".to_string());
+            }
+        }
+
+        let mut minor_status = 0u32;
+        let mut context: *mut c_void = ptr::null_mut();
+        let mut source_name: *mut c_void = ptr::null_mut();
+        let mut input_token = GssBuffer {
+            length: token.len(),
+            value: token.as_ptr() as *mut c_void,
+        };
+        let mut output_token = GssBuffer { length: 0, value: ptr::null_mut() };
+
+        // No acceptor credential (any service principal in the keytab) and no channel bindings. Kerberos completes in one step
+        let major_status = unsafe {
+            accept_sec_context(
+                &mut minor_status,
+                &mut context,
+                ptr::null_mut(),
+                &mut input_token,
+                ptr::null_mut(),
+                &mut source_name,
+                ptr::null_mut(),
+                &mut output_token,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+
+        let mut release_minor_status = 0u32;
+        unsafe {
+            release_buffer(&mut release_minor_status, &mut output_token);
+            if !context.is_null() {
+                delete_sec_context(&mut release_minor_status, &mut context, ptr::null_mut());
+            }
+        }
+
+        let result = if major_status != GSS_S_COMPLETE {
+            Err(format!("GSSAPI rejected the Kerberos ticket (major status {:#x}, minor status {})", major_status, minor_status))
+        } else {
+            let mut name_buffer = GssBuffer { length: 0, value: ptr::null_mut() };
+            let major_status = unsafe { display_name(&mut minor_status, source_name, &mut name_buffer, ptr::null_mut()) };
+            let principal = if major_status == GSS_S_COMPLETE && !name_buffer.value.is_null() {
+                let name = unsafe { std::slice::from_raw_parts(name_buffer.value as *const u8, name_buffer.length) };
+                Ok(String::from_utf8_lossy(name).to_string())
+            } else {
+                Err(format!("Failed to get the Kerberos client principal (major status {:#x})", major_status))
+            };
+            unsafe { release_buffer(&mut release_minor_status, &mut name_buffer) };
+            principal
+        };
+
+        if !source_name.is_null() {
+            unsafe { release_name(&mut release_minor_status, &mut source_name) };
+        }
+
+        result
+    }
+}
+
+#[cfg(windows)]
+mod sspi {
+    use std::{ffi::c_void, ptr};
+
+    use windows_sys::Win32::Security::{
+        Authentication::Identity::{
+            ASC_REQ_ALLOCATE_MEMORY, AcceptSecurityContext, AcquireCredentialsHandleW, DeleteSecurityContext, FreeContextBuffer, FreeCredentialsHandle, QueryContextAttributesW, SECBUFFER_TOKEN,
+            SECBUFFER_VERSION, SECPKG_ATTR_NAMES, SECPKG_CRED_INBOUND, SECURITY_NATIVE_DREP, SecBuffer, SecBufferDesc, SecPkgContext_NamesW,
+        },
+        Credentials::SecHandle,
+    };
+
+    const SEC_E_OK: i32 = 0;
+
+    // The keytab is not used, as SSPI validates tickets with the account Gruxi runs as, which needs an SPN for the site hostname
+    pub fn accept_security_context(_keytab_file: &str, token: &[u8]) -> Result<String, String> {
+        let package: Vec<u16> = "Negotiate\0".encode_utf16().collect();
+        let mut credentials = SecHandle::default();
+        let mut expiry = 0i64;
+        let status = unsafe {
+            AcquireCredentialsHandleW(
+                ptr::null(),
+                package.as_ptr(),
+                SECPKG_CRED_INBOUND,
+                ptr::null(),
+                ptr::null(),
+                None,
+                ptr::null(),
+                &mut credentials,
+                &mut expiry,
+            )
+        };
+        if status != SEC_E_OK {
+            return Err(format!("Failed to acquire SSPI credentials (status {:#x})", status));
+        }
+
+        let mut input_buffer = SecBuffer {
+            cbBuffer: token.len() as u32,
+            BufferType: SECBUFFER_TOKEN,
+            pvBuffer: token.as_ptr() as *mut c_void,
+        };
+        let input = SecBufferDesc {
+            ulVersion: SECBUFFER_VERSION,
+            cBuffers: 1,
+            pBuffers: &mut input_buffer,
+        };
+        let mut output_buffer = SecBuffer {
+            cbBuffer: 0,
+            BufferType: SECBUFFER_TOKEN,
+            pvBuffer: ptr::null_mut(),
+        };
+        let mut output = SecBufferDesc {
+            ulVersion: SECBUFFER_VERSION,
+            cBuffers: 1,
+            pBuffers: &mut output_buffer,
+        };
+        let mut context = SecHandle::default();
+        let mut context_attributes = 0u32;
+
+        let status = unsafe {
+            AcceptSecurityContext(
+                &credentials,
+                ptr::null(),
+                &input,
+                ASC_REQ_ALLOCATE_MEMORY,
+                SECURITY_NATIVE_DREP,
+                &mut context,
+                &mut output,
+                &mut context_attributes,
+                &mut expiry,
+            )
+        };
+        if !output_buffer.pvBuffer.is_null() {
+            unsafe { FreeContextBuffer(output_buffer.pvBuffer) };
+        }
+
+        // Only single step Kerberos logons are accepted, NTLM would need another round trip on the same connection
+        let result = if status != SEC_E_OK {
+            Err(format!("SSPI rejected the Kerberos ticket (status {:#x})", status))
+        } else {
+            let mut names = SecPkgContext_NamesW::default();
+            let status = unsafe { QueryContextAttributesW(&context, SECPKG_ATTR_NAMES, &mut names as *mut SecPkgContext_NamesW as *mut c_void) };
+            if status == SEC_E_OK && !names.sUserName.is_null() {
+                let name = unsafe {
+                    let length = (0..).take_while(|&i| *names.sUserName.add(i) != 0).count();
+                    String::from_utf16_lossy(std::slice::from_raw_parts(names.sUserName, length))
+                };
+                unsafe { FreeContextBuffer(names.sUserName as *mut c_void) };
+                Ok(name)
+            } else {
+                Err(format!("Failed to get the client name from SSPI (status {:#x})", status))
+            }
+        };
+
+        unsafe {
+            if context.dwLower != 0 || context.dwUpper != 0 {
+                DeleteSecurityContext(&context);
+            }
+            FreeCredentialsHandle(&credentials);
+        }
+
+        result
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kerberos_rejects_invalid_token() {
+        // Fails in GSSAPI, or when the library is not installed, but never accepts the token
+        assert!(accept_security_context("", b"not a kerberos ticket").is_err());
+    }
+}
@@ -0,0 +1,371 @@
+use std::{sync::Arc, time::Duration};
+
+use tls_listener::rustls as tokio_rustls;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::{authentication::auth_provider_trait::AuthProviderTrait, tls::tls_config::tls_config};
+
+const LDAP_RESULT_SUCCESS: u32 = 0;
+const LDAP_RESULT_INVALID_CREDENTIALS: u32 = 49;
+// Responses are bind results and a single user entry, anything larger than this is not a response we expect
+const LDAP_MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+// Authenticates against LDAP or Active Directory, by doing a simple bind as the user
+pub struct LdapAuthProvider {
+    pub ldap_url: String,
+    pub bind_dn_template: String,
+    pub search_base_dn: String, // Empty means the groups of the user are not looked up
+    pub user_attribute: String,
+    pub group_attribute: String,
+    pub timeout_seconds: u32,
+}
+
+enum LdapSearchResponse {
+    Entry(Vec<String>), // Values of the requested attribute
+    Done(u32),
+    Other,
+}
+
+impl AuthProviderTrait for LdapAuthProvider {
+    fn get_type(&self) -> String {
+        "ldap".to_string()
+    }
+
+    async fn authenticate(&self, username: &str, password: &str) -> Result<Option<String>, String> {
+        Ok(self.authenticate_and_get_groups(username, password).await?.map(|_| username.to_string()))
+    }
+}
+
+impl LdapAuthProvider {
+    // Bind as the user, and when a search base is set, look up the group DNs of the user on the same connection.
+    // Returns None for invalid credentials. Nested groups are not resolved, as "memberOf" only lists direct memberships
+    pub async fn authenticate_and_get_groups(&self, username: &str, password: &str) -> Result<Option<Vec<String>>, String> {
+        // A bind with an empty password is an unauthenticated bind, which LDAP servers report as successful
+        if username.is_empty() || password.is_empty() {
+            return Ok(None);
+        }
+
+        let uri: http::Uri = self.ldap_url.parse().map_err(|e| format!("Invalid LDAP URL '{}': {}", self.ldap_url, e))?;
+        let is_secure = uri.scheme_str() == Some("ldaps");
+        let host = uri
+            .host()
+            .ok_or(format!("LDAP URL '{}' has no host", self.ldap_url))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let port = uri.port_u16().unwrap_or(if is_secure { 636 } else { 389 });
+
+        let bind_dn = self.bind_dn_template.replace("{username}", &escape_dn_value(username));
+        let bind_request = encode_bind_request(1, &bind_dn, password);
+        let search_request = if self.search_base_dn.is_empty() {
+            None
+        } else {
+            Some(encode_search_request(
+                2,
+                &self.search_base_dn,
+                &self.user_attribute,
+                username,
+                &self.group_attribute,
+                self.timeout_seconds,
+            ))
+        };
+
+        tokio::time::timeout(Duration::from_secs(self.timeout_seconds as u64), async {
+            let mut tcp_stream = TcpStream::connect((host.as_str(), port))
+                .await
+                .map_err(|e| format!("Failed to connect to LDAP server {}:{}: {}", host, port, e))?;
+            if is_secure {
+                let connector = tokio_rustls::TlsConnector::from(Arc::new(tls_config()));
+                let server_name = rustls_pki_types::ServerName::try_from(host.clone()).map_err(|e| format!("Invalid LDAP server name '{}': {}", host, e))?;
+                let mut tls_stream = connector.connect(server_name, tcp_stream).await.map_err(|e| format!("TLS handshake with LDAP server failed: {}", e))?;
+                run_ldap_session(&mut tls_stream, &bind_request, search_request.as_deref(), &self.group_attribute).await
+            } else {
+                run_ldap_session(&mut tcp_stream, &bind_request, search_request.as_deref(), &self.group_attribute).await
+            }
+        })
+        .await
+        .map_err(|_| format!("LDAP request timed out after {} seconds", self.timeout_seconds))?
+    }
+}
+
+// Bind, and if a search request is given, run it as the bound user and collect the values of the group attribute
+async fn run_ldap_session<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, bind_request: &[u8], search_request: Option<&[u8]>, group_attribute: &str) -> Result<Option<Vec<String>>, String> {
+    match send_bind_request(stream, bind_request).await? {
+        LDAP_RESULT_SUCCESS => {}
+        LDAP_RESULT_INVALID_CREDENTIALS => return Ok(None),
+        code => return Err(format!("LDAP bind failed with result code {}", code)),
+    }
+
+    let search_request = match search_request {
+        Some(request) => request,
+        None => return Ok(Some(Vec::new())),
+    };
+
+    stream.write_all(search_request).await.map_err(|e| format!("Failed to send LDAP search request: {}", e))?;
+    stream.flush().await.map_err(|e| format!("Failed to send LDAP search request: {}", e))?;
+
+    let mut groups = Vec::new();
+    loop {
+        let message = read_ber_message(stream).await?;
+        match parse_search_response(&message, group_attribute).ok_or("Invalid LDAP search response".to_string())? {
+            LdapSearchResponse::Entry(values) => groups.extend(values),
+            LdapSearchResponse::Done(LDAP_RESULT_SUCCESS) => break,
+            LdapSearchResponse::Done(code) => return Err(format!("LDAP group search failed with result code {}", code)),
+            // Referrals to other servers are not followed
+            LdapSearchResponse::Other => {}
+        }
+    }
+    Ok(Some(groups))
+}
+
+// Send the bind request and return the result code of the response
+async fn send_bind_request<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut S, request: &[u8]) -> Result<u32, String> {
+    stream.write_all(request).await.map_err(|e| format!("Failed to send LDAP bind request: {}", e))?;
+    stream.flush().await.map_err(|e| format!("Failed to send LDAP bind request: {}", e))?;
+    let message = read_ber_message(stream).await?;
+    parse_bind_response(&message).ok_or("Invalid LDAP bind response".to_string())
+}
+
+// Escape a value for use in a distinguished name (RFC 4514), so a username cannot change the DN structure
+fn escape_dn_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let last_index = value.chars().count().saturating_sub(1);
+    for (index, c) in value.chars().enumerate() {
+        match c {
+            '\\' | ',' | '+' | '"' | '<' | '>' | ';' | '=' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '#' | ' ' if index == 0 => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' ' if index == last_index => escaped.push_str("\\ "),
+            '\0' => escaped.push_str("\\00"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn encode_ber_tlv(tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    let length = value.len();
+    if length < 0x80 {
+        encoded.push(length as u8);
+    } else {
+        let length_bytes: Vec<u8> = length.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+        encoded.push(0x80 | length_bytes.len() as u8);
+        encoded.extend(length_bytes);
+    }
+    encoded.extend_from_slice(value);
+    encoded
+}
+
+// LDAPMessage { messageID, BindRequest [APPLICATION 0] { version 3, name, simple [0] password } }
+fn encode_bind_request(message_id: u8, bind_dn: &str, password: &str) -> Vec<u8> {
+    let bind_request = [encode_ber_tlv(0x02, &[3]), encode_ber_tlv(0x04, bind_dn.as_bytes()), encode_ber_tlv(0x80, password.as_bytes())].concat();
+    let message = [encode_ber_tlv(0x02, &[message_id]), encode_ber_tlv(0x60, &bind_request)].concat();
+    encode_ber_tlv(0x30, &message)
+}
+
+// LDAPMessage { messageID, SearchRequest [APPLICATION 3] { baseObject, scope wholeSubtree, derefAliases never, sizeLimit 0,
+// timeLimit, typesOnly false, filter equalityMatch [3] (attribute=value), attributes } }. The value is sent as is, so no filter escaping is needed
+fn encode_search_request(message_id: u8, base_dn: &str, attribute: &str, value: &str, requested_attribute: &str, time_limit_seconds: u32) -> Vec<u8> {
+    let filter = encode_ber_tlv(0xa3, &[encode_ber_tlv(0x04, attribute.as_bytes()), encode_ber_tlv(0x04, value.as_bytes())].concat());
+    let search_request = [
+        encode_ber_tlv(0x04, base_dn.as_bytes()),
+        encode_ber_tlv(0x0a, &[2]),
+        encode_ber_tlv(0x0a, &[0]),
+        encode_ber_tlv(0x02, &[0]),
+        // Kept to a single byte positive integer, the client side timeout applies anyway
+        encode_ber_tlv(0x02, &[time_limit_seconds.min(127) as u8]),
+        encode_ber_tlv(0x01, &[0]),
+        filter,
+        encode_ber_tlv(0x30, &encode_ber_tlv(0x04, requested_attribute.as_bytes())),
+    ]
+    .concat();
+    let message = [encode_ber_tlv(0x02, &[message_id]), encode_ber_tlv(0x63, &search_request)].concat();
+    encode_ber_tlv(0x30, &message)
+}
+
+// Read one LDAPMessage from the stream and return the content of its outer sequence
+async fn read_ber_message<S: AsyncRead + Unpin>(stream: &mut S) -> Result<Vec<u8>, String> {
+    let tag = stream.read_u8().await.map_err(|e| format!("Failed to read LDAP response: {}", e))?;
+    if tag != 0x30 {
+        return Err(format!("Unexpected LDAP response tag: {:#x}", tag));
+    }
+
+    let first_length_byte = stream.read_u8().await.map_err(|e| format!("Failed to read LDAP response: {}", e))?;
+    let length = if first_length_byte < 0x80 {
+        first_length_byte as usize
+    } else {
+        let length_bytes = (first_length_byte & 0x7f) as usize;
+        if length_bytes == 0 || length_bytes > 4 {
+            return Err("Unsupported LDAP response length".to_string());
+        }
+        let mut length = 0usize;
+        for _ in 0..length_bytes {
+            let byte = stream.read_u8().await.map_err(|e| format!("Failed to read LDAP response: {}", e))?;
+            length = (length << 8) | byte as usize;
+        }
+        length
+    };
+    if length > LDAP_MAX_MESSAGE_SIZE {
+        return Err("LDAP response is too large".to_string());
+    }
+
+    let mut message = vec![0u8; length];
+    stream.read_exact(&mut message).await.map_err(|e| format!("Failed to read LDAP response: {}", e))?;
+    Ok(message)
+}
+
+// Split the first TLV from the data, returning the tag, the value and the remaining data
+fn read_ber_tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.first()?;
+    let first_length_byte = *data.get(1)?;
+    let (length, header_length) = if first_length_byte < 0x80 {
+        (first_length_byte as usize, 2)
+    } else {
+        let length_bytes = (first_length_byte & 0x7f) as usize;
+        if length_bytes == 0 || length_bytes > 4 {
+            return None;
+        }
+        let length = data.get(2..2 + length_bytes)?.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        (length, 2 + length_bytes)
+    };
+    let value = data.get(header_length..header_length + length)?;
+    Some((tag, value, &data[header_length + length..]))
+}
+
+// Split the message ID from the message content, returning the protocol operation tag and its value
+fn read_protocol_operation(message: &[u8]) -> Option<(u8, &[u8])> {
+    let (tag, _message_id, rest) = read_ber_tlv(message)?;
+    if tag != 0x02 {
+        return None;
+    }
+    let (tag, operation, _) = read_ber_tlv(rest)?;
+    Some((tag, operation))
+}
+
+// LDAPResult { resultCode ENUMERATED, matchedDN, diagnosticMessage }
+fn parse_result_code(result: &[u8]) -> Option<u32> {
+    let (tag, result_code, _) = read_ber_tlv(result)?;
+    if tag != 0x0a || result_code.is_empty() || result_code.len() > 4 {
+        return None;
+    }
+    Some(result_code.iter().fold(0u32, |acc, b| (acc << 8) | *b as u32))
+}
+
+// BindResponse [APPLICATION 1] { LDAPResult }
+fn parse_bind_response(message: &[u8]) -> Option<u32> {
+    match read_protocol_operation(message)? {
+        (0x61, bind_response) => parse_result_code(bind_response),
+        _ => None,
+    }
+}
+
+// SearchResultEntry [APPLICATION 4] { objectName, attributes SEQUENCE OF { type, vals SET OF value } }
+// or SearchResultDone [APPLICATION 5] { LDAPResult }
+fn parse_search_response(message: &[u8], attribute: &str) -> Option<LdapSearchResponse> {
+    match read_protocol_operation(message)? {
+        (0x64, entry) => {
+            let (_, _object_name, rest) = read_ber_tlv(entry)?;
+            let (_, mut attributes, _) = read_ber_tlv(rest)?;
+            let mut values = Vec::new();
+            while !attributes.is_empty() {
+                let (_, partial_attribute, remaining) = read_ber_tlv(attributes)?;
+                attributes = remaining;
+                let (_, attribute_type, rest) = read_ber_tlv(partial_attribute)?;
+                if !String::from_utf8_lossy(attribute_type).eq_ignore_ascii_case(attribute) {
+                    continue;
+                }
+                let (_, mut attribute_values, _) = read_ber_tlv(rest)?;
+                while !attribute_values.is_empty() {
+                    let (_, value, remaining) = read_ber_tlv(attribute_values)?;
+                    values.push(String::from_utf8_lossy(value).to_string());
+                    attribute_values = remaining;
+                }
+            }
+            Some(LdapSearchResponse::Entry(values))
+        }
+        (0x65, search_done) => parse_result_code(search_done).map(LdapSearchResponse::Done),
+        _ => Some(LdapSearchResponse::Other),
+    }
+}
+
+// Get the value of the first RDN, when it is a CN, such as "Web Admins" for "CN=Web Admins,OU=Groups,DC=corp,DC=example,DC=com"
+pub fn get_group_common_name(group_dn: &str) -> Option<&str> {
+    let first_rdn = group_dn.split(',').next()?.trim();
+    let (attribute, value) = first_rdn.split_once('=')?;
+    if attribute.trim().eq_ignore_ascii_case("cn") { Some(value.trim()) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ldap_bind_request_encoding() {
+        let request = encode_bind_request(1, "cn=a", "b");
+        assert_eq!(
+            request,
+            vec![0x30, 0x11, 0x02, 0x01, 0x01, 0x60, 0x0c, 0x02, 0x01, 0x03, 0x04, 0x04, b'c', b'n', b'=', b'a', 0x80, 0x01, b'b']
+        );
+
+        // Long form length for values of 128 bytes or more
+        let long_value = vec![b'x'; 200];
+        assert_eq!(&encode_ber_tlv(0x04, &long_value)[..3], &[0x04, 0x81, 200]);
+    }
+
+    #[test]
+    fn test_ldap_bind_response_parsing() {
+        // Content of the outer sequence, for a success and an invalid credentials response
+        let success = [0x02, 0x01, 0x01, 0x61, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00];
+        let invalid_credentials = [0x02, 0x01, 0x01, 0x61, 0x07, 0x0a, 0x01, 0x31, 0x04, 0x00, 0x04, 0x00];
+        assert_eq!(parse_bind_response(&success), Some(LDAP_RESULT_SUCCESS));
+        assert_eq!(parse_bind_response(&invalid_credentials), Some(LDAP_RESULT_INVALID_CREDENTIALS));
+        assert_eq!(parse_bind_response(&[0x02, 0x01]), None);
+    }
+
+    #[test]
+    fn test_ldap_search_request_and_response() {
+        let request = encode_search_request(2, "dc=a", "uid", "bob", "memberOf", 10);
+        // Outer sequence, message ID 2, then the search request starting with the base DN
+        assert_eq!(&request[2..5], &[0x02, 0x01, 0x02]);
+        assert_eq!(request[5], 0x63);
+        assert_eq!(&request[7..13], &[0x04, 0x04, b'd', b'c', b'=', b'a']);
+
+        let values = [encode_ber_tlv(0x04, b"cn=Web Admins,dc=a"), encode_ber_tlv(0x04, b"cn=Staff,dc=a")].concat();
+        let attributes = [
+            encode_ber_tlv(0x30, &[encode_ber_tlv(0x04, b"cn"), encode_ber_tlv(0x31, &encode_ber_tlv(0x04, b"bob"))].concat()),
+            encode_ber_tlv(0x30, &[encode_ber_tlv(0x04, b"memberof"), encode_ber_tlv(0x31, &values)].concat()),
+        ]
+        .concat();
+        let entry = [
+            encode_ber_tlv(0x02, &[2]),
+            encode_ber_tlv(0x64, &[encode_ber_tlv(0x04, b"uid=bob,dc=a"), encode_ber_tlv(0x30, &attributes)].concat()),
+        ]
+        .concat();
+        match parse_search_response(&entry, "memberOf") {
+            Some(LdapSearchResponse::Entry(groups)) => assert_eq!(groups, vec!["cn=Web Admins,dc=a".to_string(), "cn=Staff,dc=a".to_string()]),
+            _ => panic!("Expected a search result entry"),
+        }
+
+        let done = [0x02, 0x01, 0x02, 0x65, 0x07, 0x0a, 0x01, 0x00, 0x04, 0x00, 0x04, 0x00];
+        assert!(matches!(parse_search_response(&done, "memberOf"), Some(LdapSearchResponse::Done(LDAP_RESULT_SUCCESS))));
+
+        assert_eq!(get_group_common_name("CN=Web Admins,OU=Groups,DC=corp"), Some("Web Admins"));
+        assert_eq!(get_group_common_name("OU=Groups,DC=corp"), None);
+    }
+
+    #[test]
+    fn test_ldap_dn_value_escaping() {
+        assert_eq!(escape_dn_value("alice"), "alice");
+        assert_eq!(escape_dn_value("a,ou=admins"), "a\\,ou\\=admins");
+        assert_eq!(escape_dn_value("#alice "), "\\#alice\\ ");
+    }
+}
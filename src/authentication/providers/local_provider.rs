@@ -0,0 +1,14 @@
+use crate::{authentication::auth_provider_trait::AuthProviderTrait, core::admin_user::authenticate_user};
+
+// Authenticates against the Gruxi user database, which is also used for the admin portal by default
+pub struct LocalAuthProvider;
+
+impl AuthProviderTrait for LocalAuthProvider {
+    fn get_type(&self) -> String {
+        "local".to_string()
+    }
+
+    async fn authenticate(&self, username: &str, password: &str) -> Result<Option<String>, String> {
+        Ok(authenticate_user(username, password)?.map(|user| user.username))
+    }
+}
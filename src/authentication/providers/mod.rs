@@ -0,0 +1,5 @@
+pub mod htpasswd_provider;
+pub mod kerberos_provider;
+pub mod ldap_provider;
+pub mod local_provider;
+pub mod oidc_provider;
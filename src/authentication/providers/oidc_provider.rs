@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+
+use crate::{authentication::auth_provider_trait::AuthProviderTrait, core::running_state_manager::get_running_state_manager};
+
+// Authenticates against an OpenID Connect provider (such as Keycloak or Entra ID), using the resource owner password
+// credentials grant on the token endpoint. This fits username and password logins, without a browser redirect flow
+pub struct OidcAuthProvider {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    pub scope: String,
+    pub timeout_seconds: u32,
+}
+
+impl AuthProviderTrait for OidcAuthProvider {
+    fn get_type(&self) -> String {
+        "oidc".to_string()
+    }
+
+    async fn authenticate(&self, username: &str, password: &str) -> Result<Option<String>, String> {
+        if username.is_empty() || password.is_empty() {
+            return Ok(None);
+        }
+
+        let request = hyper::Request::builder()
+            .method(hyper::Method::POST)
+            .uri(&self.token_url)
+            .header(hyper::header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(hyper::header::ACCEPT, "application/json")
+            .body(Full::new(Bytes::from(self.get_token_request_body(username, password))).map_err(|never| match never {}).boxed())
+            .map_err(|e| format!("Failed to build OIDC token request: {}", e))?;
+
+        let client = {
+            let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+            running_state.get_http_client().get_client(true)
+        };
+
+        let timeout = Duration::from_secs(self.timeout_seconds as u64);
+        let response = tokio::time::timeout(timeout, client.request(request))
+            .await
+            .map_err(|_| "OIDC token request timed out".to_string())?
+            .map_err(|e| format!("OIDC token request failed: {}", e))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(Some(username.to_string()));
+        }
+
+        // Wrong credentials are reported as "invalid_grant", anything else means the provider is not set up correctly
+        let body = tokio::time::timeout(timeout, response.into_body().collect())
+            .await
+            .map_err(|_| "Reading OIDC token response timed out".to_string())?
+            .map_err(|e| format!("Failed to read OIDC token response: {}", e))?
+            .to_bytes();
+        let error_code = serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|json| json.get("error").and_then(|e| e.as_str()).map(|e| e.to_string()))
+            .unwrap_or_default();
+
+        if error_code == "invalid_grant" {
+            Ok(None)
+        } else {
+            Err(format!("OIDC token endpoint returned status {} ({})", status, error_code))
+        }
+    }
+}
+
+impl OidcAuthProvider {
+    fn get_token_request_body(&self, username: &str, password: &str) -> String {
+        let mut parameters = vec![("grant_type", "password"), ("username", username), ("password", password), ("client_id", self.client_id.as_str())];
+        if !self.client_secret.is_empty() {
+            parameters.push(("client_secret", self.client_secret.as_str()));
+        }
+        if !self.scope.is_empty() {
+            parameters.push(("scope", self.scope.as_str()));
+        }
+        parameters
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, urlencoding::encode(value)))
+            .collect::<Vec<String>>()
+            .join("&")
+    }
+}
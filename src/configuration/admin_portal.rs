@@ -1,14 +1,37 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::configuration::site::Site;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct AdminPortal {
     pub is_enabled: bool,
     pub domain_name: String,
     pub tls_automatic_enabled: bool,
     pub tls_certificate_path: Option<String>,
     pub tls_key_path: Option<String>,
+    // Auth provider used for logins, if empty only the Gruxi user database is used
+    #[serde(default)]
+    pub auth_provider_id: String,
+    // Keep accepting Gruxi database users when an auth provider is selected, so existing accounts keep working
+    #[serde(default = "default_allow_local_login")]
+    pub allow_local_login: bool,
+    // Database file for sessions shared by all replicas of the admin portal, so a login on one node is valid on all of them.
+    // It must be on storage every node can reach, with working file locks. If empty, sessions are kept in the local database
+    #[serde(default)]
+    pub shared_session_database: String,
+    // Sessions only accepted from the client IP and/or user agent they were created with, so a stolen token cannot be replayed
+    #[serde(default)]
+    pub session_bind_ip: bool,
+    #[serde(default)]
+    pub session_bind_user_agent: bool,
+    // Header with the client IP set by the load balancer in front of the replicas, such as "X-Forwarded-For". If empty, the connecting IP is used
+    #[serde(default)]
+    pub session_client_ip_header: String,
+}
+
+fn default_allow_local_login() -> bool {
+    true
 }
 
 impl AdminPortal {
@@ -21,6 +44,12 @@ impl AdminPortal {
             tls_automatic_enabled: false,
             tls_certificate_path: None,
             tls_key_path: None,
+            auth_provider_id: String::new(),
+            allow_local_login: default_allow_local_login(),
+            shared_session_database: String::new(),
+            session_bind_ip: false,
+            session_bind_user_agent: false,
+            session_client_ip_header: String::new(),
         }
     }
 
@@ -31,6 +60,10 @@ impl AdminPortal {
             self.domain_name = "".to_string();
         }
 
+        self.auth_provider_id = self.auth_provider_id.trim().to_string();
+        self.shared_session_database = self.shared_session_database.trim().to_string();
+        self.session_client_ip_header = self.session_client_ip_header.trim().to_string();
+
         if let Some(cert_path) = &mut self.tls_certificate_path {
             *cert_path = cert_path.trim().to_string();
         }
@@ -68,6 +101,18 @@ impl AdminPortal {
             }
         }
 
+        // The shared session database is created on first use, but its directory has to exist
+        if !self.shared_session_database.is_empty() {
+            let shared_session_directory = std::path::Path::new(&self.shared_session_database).parent();
+            if shared_session_directory.is_some_and(|directory| !directory.as_os_str().is_empty() && !directory.is_dir()) {
+                errors.push(format!("Directory of the shared session database does not exist: {}", self.shared_session_database));
+            }
+        }
+
+        if !self.session_client_ip_header.is_empty() && http::HeaderName::from_bytes(self.session_client_ip_header.as_bytes()).is_err() {
+            errors.push(format!("Session client IP header '{}' is not a valid header name", self.session_client_ip_header));
+        }
+
         if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
 
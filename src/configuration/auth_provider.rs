@@ -0,0 +1,160 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub static AUTH_PROVIDER_TYPES: &[&str] = &["local", "htpasswd", "ldap", "oidc", "kerberos"];
+
+// An authentication provider, which can be selected for the admin portal login and for protected locations
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct AuthProvider {
+    pub id: String,
+    pub name: String,
+    pub provider_type: String, // "local" (Gruxi user database), "htpasswd", "ldap" (LDAP/Active Directory), "oidc" or "kerberos"
+    // htpasswd
+    #[serde(default)]
+    pub htpasswd_file: String, // Path to an Apache style htpasswd file, with bcrypt or {SHA} hashes
+    // LDAP / Active Directory, authenticated with a simple bind as the user
+    #[serde(default)]
+    pub ldap_url: String, // Such as "ldaps://ldap.example.com" or "ldap://dc01.corp.example.com:389"
+    #[serde(default)]
+    pub ldap_bind_dn_template: String, // "{username}" is replaced, such as "uid={username},ou=people,dc=example,dc=com" or "{username}@corp.example.com"
+    // Groups of the user are looked up below the search base, as the bound user. Needed for locations that only allow some groups
+    #[serde(default)]
+    pub ldap_search_base_dn: String, // Such as "dc=corp,dc=example,dc=com", empty means no group lookup
+    #[serde(default = "default_ldap_user_attribute")]
+    pub ldap_user_attribute: String, // Attribute matched against the username, "sAMAccountName" for Active Directory or "uid" for OpenLDAP
+    #[serde(default = "default_ldap_group_attribute")]
+    pub ldap_group_attribute: String, // Attribute with the group DNs of the user, "memberOf" on both Active Directory and OpenLDAP
+    // OIDC, authenticated with the resource owner password credentials grant against the token endpoint
+    #[serde(default)]
+    pub oidc_token_url: String,
+    #[serde(default)]
+    pub oidc_client_id: String,
+    #[serde(default)]
+    pub oidc_client_secret: String,
+    #[serde(default)]
+    pub oidc_scope: String,
+    // Kerberos single sign-on with the "Negotiate" scheme, only for locations as browsers send tickets instead of passwords
+    #[serde(default)]
+    pub kerberos_keytab_file: String, // Keytab with the HTTP/<hostname> service key. Empty uses the system default. On Windows, SSPI is used instead
+    // Common
+    pub timeout_seconds: u32,
+    pub cache_seconds: u32, // How long successful logins are remembered, so not every request reaches the provider. 0 disables it
+}
+
+fn default_ldap_user_attribute() -> String {
+    "sAMAccountName".to_string()
+}
+
+fn default_ldap_group_attribute() -> String {
+    "memberOf".to_string()
+}
+
+impl Default for AuthProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AuthProvider {
+    pub fn new() -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name: String::new(),
+            provider_type: "local".to_string(),
+            htpasswd_file: String::new(),
+            ldap_url: String::new(),
+            ldap_bind_dn_template: String::new(),
+            ldap_search_base_dn: String::new(),
+            ldap_user_attribute: default_ldap_user_attribute(),
+            ldap_group_attribute: default_ldap_group_attribute(),
+            oidc_token_url: String::new(),
+            oidc_client_id: String::new(),
+            oidc_client_secret: String::new(),
+            oidc_scope: "openid".to_string(),
+            kerberos_keytab_file: String::new(),
+            timeout_seconds: 10,
+            cache_seconds: 60,
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.id = self.id.trim().to_string();
+        self.name = self.name.trim().to_string();
+        self.provider_type = self.provider_type.trim().to_lowercase();
+        self.htpasswd_file = self.htpasswd_file.trim().to_string();
+        self.ldap_url = self.ldap_url.trim().to_string();
+        self.ldap_bind_dn_template = self.ldap_bind_dn_template.trim().to_string();
+        self.ldap_search_base_dn = self.ldap_search_base_dn.trim().to_string();
+        self.ldap_user_attribute = self.ldap_user_attribute.trim().to_string();
+        self.ldap_group_attribute = self.ldap_group_attribute.trim().to_string();
+        self.oidc_token_url = self.oidc_token_url.trim().to_string();
+        self.oidc_client_id = self.oidc_client_id.trim().to_string();
+        self.oidc_client_secret = self.oidc_client_secret.trim().to_string();
+        self.oidc_scope = self.oidc_scope.trim().to_string();
+        self.kerberos_keytab_file = self.kerberos_keytab_file.trim().to_string();
+    }
+
+    // Whether the groups of users can be looked up, which is needed for locations that only allow some groups
+    pub fn supports_groups(&self) -> bool {
+        self.provider_type == "ldap" && !self.ldap_search_base_dn.is_empty()
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.id.is_empty() {
+            errors.push("Auth provider ID cannot be empty".to_string());
+        }
+
+        if self.name.is_empty() {
+            errors.push("Auth provider name cannot be empty".to_string());
+        }
+
+        match self.provider_type.as_str() {
+            "local" => {}
+            "htpasswd" => {
+                if self.htpasswd_file.is_empty() {
+                    errors.push("htpasswd file cannot be empty".to_string());
+                } else if !std::path::Path::new(&self.htpasswd_file).is_file() {
+                    errors.push(format!("htpasswd file does not exist: {}", self.htpasswd_file));
+                }
+            }
+            "ldap" => {
+                match self.ldap_url.parse::<http::Uri>() {
+                    Ok(uri) if matches!(uri.scheme_str(), Some("ldap") | Some("ldaps")) && uri.host().is_some() => {}
+                    _ => errors.push(format!("Invalid LDAP URL, expected format 'ldaps://host' or 'ldap://host:389': {}", self.ldap_url)),
+                }
+                if !self.ldap_bind_dn_template.contains("{username}") {
+                    errors.push("LDAP bind DN template must contain '{username}'".to_string());
+                }
+                if !self.ldap_search_base_dn.is_empty() && (self.ldap_user_attribute.is_empty() || self.ldap_group_attribute.is_empty()) {
+                    errors.push("LDAP user and group attributes cannot be empty, when a search base DN is set".to_string());
+                }
+            }
+            "oidc" => {
+                match self.oidc_token_url.parse::<http::Uri>() {
+                    Ok(uri) if matches!(uri.scheme_str(), Some("https") | Some("http")) && uri.host().is_some() => {}
+                    _ => errors.push(format!("Invalid OIDC token endpoint URL: {}", self.oidc_token_url)),
+                }
+                if self.oidc_client_id.is_empty() {
+                    errors.push("OIDC client ID cannot be empty".to_string());
+                }
+            }
+            "kerberos" => {
+                if !self.kerberos_keytab_file.is_empty() && !std::path::Path::new(&self.kerberos_keytab_file).is_file() {
+                    errors.push(format!("Kerberos keytab file does not exist: {}", self.kerberos_keytab_file));
+                }
+            }
+            _ => {
+                errors.push(format!("Auth provider type '{}' is not supported, use one of: {}", self.provider_type, AUTH_PROVIDER_TYPES.join(", ")));
+            }
+        }
+
+        if self.timeout_seconds < 1 {
+            errors.push("Auth provider timeout must be at least 1 second".to_string());
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
@@ -0,0 +1,70 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// Download limits of a site, for fair sharing of a constrained uplink and disk. 0 means no limit for all of them
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BandwidthSettings {
+    pub connection_bytes_per_second: u64, // Rate of each response body of the site
+    pub site_bytes_per_second: u64,       // Rate of all response bodies of the site together
+    // Responses of at least this many bytes need one of the download slots of the site while they are sent
+    #[serde(default)]
+    pub large_file_size_threshold: u64,
+    #[serde(default)]
+    pub large_file_slots: u32,
+    // How long a large download waits for a free slot, before it is answered with 503. 0 answers with 503 right away
+    #[serde(default)]
+    pub large_file_queue_timeout_seconds: u32,
+}
+
+impl Default for BandwidthSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BandwidthSettings {
+    pub fn new() -> Self {
+        Self {
+            connection_bytes_per_second: 0,
+            site_bytes_per_second: 0,
+            large_file_size_threshold: 0,
+            large_file_slots: 0,
+            large_file_queue_timeout_seconds: 0,
+        }
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.connection_bytes_per_second > 0 || self.site_bytes_per_second > 0
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        // Lower rates would send a few bytes at a time, which clients and proxies take for a stalled connection
+        if self.connection_bytes_per_second > 0 && self.connection_bytes_per_second < 1024 {
+            errors.push(format!(
+                "Bandwidth limit per connection must be 0 (no limit) or at least 1024 bytes per second, got {}",
+                self.connection_bytes_per_second
+            ));
+        }
+        if self.site_bytes_per_second > 0 && self.site_bytes_per_second < 1024 {
+            errors.push(format!(
+                "Bandwidth limit per site must be 0 (no limit) or at least 1024 bytes per second, got {}",
+                self.site_bytes_per_second
+            ));
+        }
+
+        if self.connection_bytes_per_second > 0 && self.site_bytes_per_second > 0 && self.connection_bytes_per_second > self.site_bytes_per_second {
+            errors.push("Bandwidth limit per connection cannot be higher than the limit per site".to_string());
+        }
+
+        if self.large_file_size_threshold > 0 && self.large_file_slots == 0 {
+            errors.push("Large file download slots must be at least 1 when a large file size threshold is set".to_string());
+        }
+        if self.large_file_queue_timeout_seconds > 300 {
+            errors.push(format!("Large file queue timeout must be at most 300 seconds, got {}", self.large_file_queue_timeout_seconds));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
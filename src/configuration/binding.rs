@@ -1,14 +1,62 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+// HTTP versions a binding serves: "auto" for HTTP/1.x and HTTP/2, or pinned to one of them
+pub const BINDING_HTTP_VERSIONS: [&str; 3] = ["auto", "http1_only", "http2_only"];
+
+// What a TLS binding does with requests whose Host differs from the SNI name of the connection: serve them, serve and log
+// them, or answer them with 421 Misdirected Request, so the client retries on a connection for the right hostname
+pub const BINDING_SNI_MISMATCH_POLICIES: [&str; 3] = ["allow", "log", "reject"];
+
+// What a binding does with requests whose Host matches none of its sites: serve the default site, answer them with
+// 404 Not Found, or answer them with 421 Misdirected Request, as the binding does not serve that hostname
+pub const BINDING_UNKNOWN_HOST_POLICIES: [&str; 3] = ["default", "not_found", "reject"];
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 #[allow(unused)]
 pub struct Binding {
     pub id: String,
     pub ip: String,
+    // 0 lets the OS pick a free port when the binding starts, such as for tests
     pub port: u16,
     pub is_admin: bool,
     pub is_tls: bool,
+    // Always close HTTP/1.0 connections after the response, also when the client asks for keep-alive
+    #[serde(default)]
+    pub http10_strict_close: bool,
+    // Idle time before kept-alive connections are closed, announced to clients in a Keep-Alive header. 0 for no idle timeout
+    #[serde(default, alias = "keep_alive_timeout")]
+    pub keep_alive_timeout_seconds: u32,
+    // Requests served on a kept-alive connection before it is closed, with "Connection: close" on the last response. 0 for no limit
+    #[serde(default)]
+    pub max_keep_alive_requests: u32,
+    // Pin the binding to one HTTP version, such as HTTP/1.1 only for clients or upstreams with HTTP/2 bugs. On TLS bindings,
+    // only the pinned version is offered in ALPN. HTTP/2 only on plain bindings requires clients with prior knowledge (h2c)
+    #[serde(default = "default_http_versions")]
+    pub http_versions: String,
+    // Handling of requests whose Host differs from the SNI name of the TLS connection, one of BINDING_SNI_MISMATCH_POLICIES
+    #[serde(default = "default_sni_mismatch_policy")]
+    pub sni_mismatch_policy: String,
+    // The site serving requests for unknown hostnames. Empty for the sites marked as default, which are also used when this
+    // site is disabled
+    #[serde(default)]
+    pub default_site_id: String,
+    // Handling of requests whose Host matches no site, one of BINDING_UNKNOWN_HOST_POLICIES
+    #[serde(default = "default_unknown_host_policy")]
+    pub unknown_host_policy: String,
+}
+
+fn default_http_versions() -> String {
+    "auto".to_string()
+}
+
+fn default_sni_mismatch_policy() -> String {
+    "allow".to_string()
+}
+
+fn default_unknown_host_policy() -> String {
+    "default".to_string()
 }
 
 impl Binding {
@@ -19,11 +67,39 @@ impl Binding {
             port: 80,
             is_admin: false,
             is_tls: false,
+            http10_strict_close: false,
+            keep_alive_timeout_seconds: 0,
+            max_keep_alive_requests: 0,
+            http_versions: default_http_versions(),
+            sni_mismatch_policy: default_sni_mismatch_policy(),
+            default_site_id: String::new(),
+            unknown_host_policy: default_unknown_host_policy(),
         }
     }
 
     pub fn sanitize(&mut self) {
         self.ip = self.ip.trim().to_string();
+        self.http_versions = self.http_versions.trim().to_lowercase();
+        self.sni_mismatch_policy = self.sni_mismatch_policy.trim().to_lowercase();
+        self.default_site_id = self.default_site_id.trim().to_string();
+        self.unknown_host_policy = self.unknown_host_policy.trim().to_lowercase();
+    }
+
+    pub fn is_http1_only(&self) -> bool {
+        self.http_versions == "http1_only"
+    }
+
+    pub fn is_http2_only(&self) -> bool {
+        self.http_versions == "http2_only"
+    }
+
+    // Protocols offered in the TLS handshake, most preferred first
+    pub fn get_alpn_protocols(&self) -> Vec<Vec<u8>> {
+        match self.http_versions.as_str() {
+            "http1_only" => vec![b"http/1.1".to_vec()],
+            "http2_only" => vec![b"h2".to_vec()],
+            _ => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+        }
     }
 
     pub fn validate(&self) -> Result<(), Vec<String>> {
@@ -36,11 +112,6 @@ impl Binding {
             errors.push(format!("Invalid IP address: {}", self.ip));
         }
 
-        // Validate port
-        if self.port == 0 {
-            errors.push("Port cannot be 0".to_string());
-        }
-
         // Validate common TLS port usage
         if self.is_tls && self.port == 80 {
             errors.push("Port 80 is typically used for HTTP, not HTTPS. Consider using port 443 for TLS".to_string());
@@ -49,6 +120,35 @@ impl Binding {
             errors.push("Port 443 is typically used for HTTPS, not HTTP. Consider using port 80 for non-TLS or enable TLS".to_string());
         }
 
+        // Clients reconnect before the Keep-Alive timeout, so an hour is plenty
+        if self.keep_alive_timeout_seconds > 3600 {
+            errors.push(format!("Keep-alive timeout must be 0 (no timeout) or at most 3600 seconds, got {}", self.keep_alive_timeout_seconds));
+        }
+
+        if self.max_keep_alive_requests > 1_000_000 {
+            errors.push(format!("Max keep-alive requests must be 0 (no limit) or at most 1000000, got {}", self.max_keep_alive_requests));
+        }
+
+        if !BINDING_HTTP_VERSIONS.contains(&self.http_versions.as_str()) {
+            errors.push(format!("HTTP versions must be one of {}, got '{}'", BINDING_HTTP_VERSIONS.join(", "), self.http_versions));
+        }
+
+        if !BINDING_SNI_MISMATCH_POLICIES.contains(&self.sni_mismatch_policy.as_str()) {
+            errors.push(format!(
+                "SNI mismatch policy must be one of {}, got '{}'",
+                BINDING_SNI_MISMATCH_POLICIES.join(", "),
+                self.sni_mismatch_policy
+            ));
+        }
+
+        if !BINDING_UNKNOWN_HOST_POLICIES.contains(&self.unknown_host_policy.as_str()) {
+            errors.push(format!(
+                "Unknown host policy must be one of {}, got '{}'",
+                BINDING_UNKNOWN_HOST_POLICIES.join(", "),
+                self.unknown_host_policy
+            ));
+        }
+
         // Admin binding specific validations
         if self.is_admin {
             // Admin bindings should typically use TLS for security
@@ -60,3 +160,20 @@ impl Binding {
         if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alpn_protocols() {
+        let mut binding = Binding::new();
+        assert_eq!(binding.get_alpn_protocols(), vec![b"h2".to_vec(), b"http/1.1".to_vec()]);
+
+        binding.http_versions = "http1_only".to_string();
+        assert_eq!(binding.get_alpn_protocols(), vec![b"http/1.1".to_vec()]);
+
+        binding.http_versions = "http2_only".to_string();
+        assert_eq!(binding.get_alpn_protocols(), vec![b"h2".to_vec()]);
+    }
+}
@@ -0,0 +1,172 @@
+use std::sync::OnceLock;
+
+use regex::{Regex, RegexBuilder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::network::ip_range::IpRange;
+
+// "allow" lets the request through without checking further rules, "deny" answers with 403, "throttle" answers with 429
+// when the client goes over the requests per minute of the rule and "robots_only" only serves robots.txt
+pub const BOT_ACTIONS: [&str; 4] = ["allow", "deny", "throttle", "robots_only"];
+
+// A bot rule, matching requests by their User-Agent and, for verified crawlers, by the IP ranges the crawler is known to
+// crawl from, as published by search engines. Requests claiming to be such a crawler from elsewhere do not match, so a
+// later rule for the same User-Agent catches impostors
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BotRule {
+    pub name: String,
+    pub user_agent_pattern: String, // Regular expression, matched without case. Empty matches any User-Agent
+    #[serde(default)]
+    pub ip_ranges: Vec<String>, // CIDR ranges the request has to come from. Empty matches any address
+    pub action: String,
+    #[serde(default)]
+    pub requests_per_minute: u32, // For "throttle", per client IP address
+    #[serde(skip)]
+    compiled_user_agent_pattern: OnceLock<Option<Regex>>,
+    #[serde(skip)]
+    parsed_ip_ranges: OnceLock<Vec<IpRange>>,
+}
+
+impl BotRule {
+    pub fn new(name: &str, user_agent_pattern: &str, action: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            user_agent_pattern: user_agent_pattern.to_string(),
+            ip_ranges: Vec::new(),
+            action: action.to_string(),
+            requests_per_minute: 0,
+            compiled_user_agent_pattern: OnceLock::new(),
+            parsed_ip_ranges: OnceLock::new(),
+        }
+    }
+
+    fn compile_user_agent_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+        RegexBuilder::new(pattern).case_insensitive(true).build()
+    }
+
+    // The compiled User-Agent pattern, None when it is empty or invalid, which the validation rejects
+    pub fn get_user_agent_pattern(&self) -> Option<&Regex> {
+        self.compiled_user_agent_pattern
+            .get_or_init(|| {
+                if self.user_agent_pattern.is_empty() {
+                    None
+                } else {
+                    Self::compile_user_agent_pattern(&self.user_agent_pattern).ok()
+                }
+            })
+            .as_ref()
+    }
+
+    pub fn get_ip_ranges(&self) -> &Vec<IpRange> {
+        self.parsed_ip_ranges.get_or_init(|| self.ip_ranges.iter().filter_map(|range| IpRange::parse(range).ok()).collect())
+    }
+
+    pub fn sanitize(&mut self) {
+        self.name = self.name.trim().to_string();
+        self.user_agent_pattern = self.user_agent_pattern.trim().to_string();
+        self.ip_ranges = self.ip_ranges.iter().map(|range| range.trim().to_string()).filter(|range| !range.is_empty()).collect();
+        self.action = self.action.trim().to_lowercase();
+        // Pattern and ranges may have changed, so parse them again on next use
+        self.compiled_user_agent_pattern = OnceLock::new();
+        self.parsed_ip_ranges = OnceLock::new();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.name.is_empty() {
+            errors.push("Bot rule name cannot be empty".to_string());
+        }
+        if self.user_agent_pattern.is_empty() && self.ip_ranges.is_empty() {
+            errors.push(format!("Bot rule '{}': Needs a User-Agent pattern, IP ranges or both", self.name));
+        }
+        if !self.user_agent_pattern.is_empty()
+            && let Err(e) = Self::compile_user_agent_pattern(&self.user_agent_pattern)
+        {
+            errors.push(format!("Bot rule '{}': User-Agent pattern is not a valid regular expression: {}", self.name, e));
+        }
+        for range in &self.ip_ranges {
+            if let Err(e) = IpRange::parse(range) {
+                errors.push(format!("Bot rule '{}': {}", self.name, e));
+            }
+        }
+        if !BOT_ACTIONS.contains(&self.action.as_str()) {
+            errors.push(format!("Bot rule '{}': Action must be one of {}, got '{}'", self.name, BOT_ACTIONS.join(", "), self.action));
+        }
+        if self.action == "throttle" && self.requests_per_minute == 0 {
+            errors.push(format!("Bot rule '{}': Throttled bots need at least 1 request per minute", self.name));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+// How a site treats bots and crawlers, with the first matching rule deciding, and the robots.txt it serves
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BotSettings {
+    pub is_enabled: bool,
+    pub rules: Vec<BotRule>,
+    // Serve a robots.txt generated from these settings, instead of a robots.txt of the site
+    pub robots_txt_enabled: bool,
+    pub robots_disallow_paths: Vec<String>, // Disallowed for all crawlers
+    pub robots_crawl_delay_seconds: u32,    // 0 to leave it out
+    pub robots_sitemap_url: String,
+}
+
+impl Default for BotSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BotSettings {
+    pub fn new() -> Self {
+        Self {
+            is_enabled: false,
+            rules: Vec::new(),
+            robots_txt_enabled: false,
+            robots_disallow_paths: Vec::new(),
+            robots_crawl_delay_seconds: 0,
+            robots_sitemap_url: String::new(),
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        for rule in &mut self.rules {
+            rule.sanitize();
+        }
+        self.robots_disallow_paths = self.robots_disallow_paths.iter().map(|path| path.trim().to_string()).filter(|path| !path.is_empty()).collect();
+        self.robots_sitemap_url = self.robots_sitemap_url.trim().to_string();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        if !self.is_enabled {
+            return Ok(());
+        }
+
+        for rule in &self.rules {
+            if let Err(rule_errors) = rule.validate() {
+                errors.extend(rule_errors);
+            }
+        }
+        for path in &self.robots_disallow_paths {
+            if !path.starts_with('/') || path.contains(['\r', '\n']) {
+                errors.push(format!("Robots.txt disallowed path '{}' must start with '/'", path));
+            }
+        }
+        let is_absolute_url = self.robots_sitemap_url.starts_with("http://") || self.robots_sitemap_url.starts_with("https://");
+        if !self.robots_sitemap_url.is_empty() && !is_absolute_url {
+            errors.push(format!("Robots.txt sitemap URL must be an absolute http:// or https:// URL, got '{}'", self.robots_sitemap_url));
+        }
+        if self.robots_sitemap_url.contains(['\r', '\n']) {
+            errors.push("Robots.txt sitemap URL cannot contain line breaks".to_string());
+        }
+        if self.robots_crawl_delay_seconds > 3600 {
+            errors.push(format!("Robots.txt crawl delay must be at most 3600 seconds, got {}", self.robots_crawl_delay_seconds));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
@@ -0,0 +1,159 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// Cache-Control and Expires headers attached to the responses of a site by path pattern or content type, so the apps
+// behind it do not have to set them. The first enabled rule matching a response applies
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CacheHeaderRule {
+    pub name: String,
+    pub is_enabled: bool,
+    // Path pattern, where * matches any characters, such as "/assets/*.js". Empty to match any path
+    #[serde(default)]
+    pub path_pattern: String,
+    // Content types, where "image/*" matches all images. Empty to match any content type
+    #[serde(default)]
+    pub content_types: Vec<String>,
+    #[serde(default)]
+    pub cache_control: String, // Such as "public, max-age=31536000, immutable". Empty to not set it
+    #[serde(default)]
+    pub expires_seconds: u64, // Expires is set this many seconds after the response. 0 to not set it
+    // Replace the headers when the handler already set them, instead of keeping those
+    #[serde(default)]
+    pub replace_existing: bool,
+
+    // Calculated fields (not serialized)
+    #[serde(skip)]
+    compiled_path_pattern: OnceLock<Option<Regex>>,
+}
+
+impl CacheHeaderRule {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            is_enabled: true,
+            path_pattern: String::new(),
+            content_types: Vec::new(),
+            cache_control: String::new(),
+            expires_seconds: 0,
+            replace_existing: false,
+            compiled_path_pattern: OnceLock::new(),
+        }
+    }
+
+    // Whether the rule applies to a response with this content type, for a request with this path. The query string and
+    // the parameters of the content type, such as the charset, are ignored
+    pub fn matches(&self, url_path: &str, content_type: &str) -> bool {
+        if !self.path_pattern.is_empty() {
+            let url_path = url_path.split('?').next().unwrap_or_default();
+            let is_match = self
+                .compiled_path_pattern
+                .get_or_init(|| compile_path_pattern(&self.path_pattern))
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(url_path));
+            if !is_match {
+                return false;
+            }
+        }
+
+        if !self.content_types.is_empty() {
+            let media_type = content_type.split(';').next().unwrap_or_default().trim().to_lowercase();
+            let is_match = self.content_types.iter().any(|pattern| match pattern.strip_suffix("/*") {
+                Some(main_type) => media_type.split('/').next() == Some(main_type) && media_type.contains('/'),
+                None => media_type == *pattern,
+            });
+            if !is_match {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    pub fn sanitize(&mut self) {
+        self.name = self.name.trim().to_string();
+        self.path_pattern = self.path_pattern.trim().to_string();
+        self.content_types = self
+            .content_types
+            .iter()
+            .map(|content_type| content_type.trim().to_lowercase())
+            .filter(|content_type| !content_type.is_empty())
+            .collect();
+        self.cache_control = self.cache_control.trim().to_string();
+
+        // Pattern may have changed, so recompile on next use
+        self.compiled_path_pattern = OnceLock::new();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.name.is_empty() {
+            errors.push("Cache header rule name cannot be empty".to_string());
+        }
+        if !self.path_pattern.is_empty() && !self.path_pattern.starts_with('/') {
+            errors.push(format!("Cache header rule '{}': Path pattern must start with '/', got '{}'", self.name, self.path_pattern));
+        }
+        for content_type in &self.content_types {
+            if content_type.split('/').count() != 2 || content_type.contains(char::is_whitespace) {
+                errors.push(format!("Cache header rule '{}': Content type must be like 'text/css' or 'image/*', got '{}'", self.name, content_type));
+            }
+        }
+        if self.cache_control.is_empty() && self.expires_seconds == 0 {
+            errors.push(format!("Cache header rule '{}': Needs a Cache-Control value or an Expires time", self.name));
+        }
+        if hyper::header::HeaderValue::from_str(&self.cache_control).is_err() {
+            errors.push(format!("Cache header rule '{}': Cache-Control value is not a valid header value", self.name));
+        }
+        // Expires should not be more than a year ahead, as RFC 9111 advises caches to treat such dates as a year
+        if self.expires_seconds > 365 * 24 * 60 * 60 {
+            errors.push(format!("Cache header rule '{}': Expires must be at most 31536000 seconds, got {}", self.name, self.expires_seconds));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+// The pattern as a regex matching the whole path, with * matching any characters and everything else literally
+fn compile_path_pattern(pattern: &str) -> Option<Regex> {
+    let escaped: Vec<String> = pattern.split('*').map(regex::escape).collect();
+    Regex::new(&format!("^{}$", escaped.join(".*"))).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_header_rule_matches() {
+        let mut rule = CacheHeaderRule::new("Hashed assets");
+        rule.path_pattern = "/assets/*.js".to_string();
+        assert!(rule.matches("/assets/app.3f2a1c.js", "application/javascript"));
+        assert!(rule.matches("/assets/vendor/chunk.js?v=2", ""));
+        assert!(!rule.matches("/assets/app.css", "text/css"));
+        assert!(!rule.matches("/static/assets/app.js", "application/javascript"));
+
+        rule.content_types = vec!["image/*".to_string(), "text/css".to_string()];
+        rule.path_pattern = String::new();
+        assert!(rule.matches("/logo.png", "image/png"));
+        assert!(rule.matches("/site.css", "text/css; charset=utf-8"));
+        assert!(!rule.matches("/index.html", "text/html"));
+        assert!(!rule.matches("/image", "imagex/png"));
+    }
+
+    #[test]
+    fn test_cache_header_rule_validation() {
+        let mut rule = CacheHeaderRule::new("Images");
+        rule.content_types = vec!["image/*".to_string()];
+        assert_eq!(rule.validate().unwrap_err().len(), 1);
+
+        rule.cache_control = "public, max-age=86400".to_string();
+        assert!(rule.validate().is_ok());
+
+        rule.path_pattern = "assets/*".to_string();
+        rule.content_types = vec!["images".to_string()];
+        assert_eq!(rule.validate().unwrap_err().len(), 2);
+    }
+}
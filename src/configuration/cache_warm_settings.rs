@@ -0,0 +1,63 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// Crawl of the site's own pages, to warm the file cache, the upstreams and their caches after starts, reloads and deploys
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CacheWarmSettings {
+    pub is_enabled: bool,
+    pub start_paths: Vec<String>, // Pages the crawl starts from, such as "/"
+    pub sitemap_path: String,     // Sitemap whose URLs are warmed as well, such as "/sitemap.xml", or empty for none
+    pub max_depth: u32,           // How many links deep the crawl follows from the start pages and sitemap URLs, 0 to not follow links
+    pub max_urls: u32,            // Most URLs requested per crawl
+    pub requests_per_second: u32, // Rate of the crawl, so it does not load the site or its upstreams
+    pub interval_minutes: u32,    // Crawl again at an interval, 0 for only after starts, reloads and webroot syncs
+}
+
+impl Default for CacheWarmSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CacheWarmSettings {
+    pub fn new() -> Self {
+        Self {
+            is_enabled: false,
+            start_paths: vec!["/".to_string()],
+            sitemap_path: String::new(),
+            max_depth: 2,
+            max_urls: 200,
+            requests_per_second: 5,
+            interval_minutes: 0,
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.start_paths = self.start_paths.iter().map(|path| path.trim().to_string()).filter(|path| !path.is_empty()).collect();
+        self.sitemap_path = self.sitemap_path.trim().to_string();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        if !self.is_enabled {
+            return Ok(());
+        }
+
+        if self.start_paths.is_empty() && self.sitemap_path.is_empty() {
+            errors.push("Cache warming needs at least one start path or a sitemap path".to_string());
+        }
+        for path in self.start_paths.iter().chain(std::iter::once(&self.sitemap_path).filter(|path| !path.is_empty())) {
+            if !path.starts_with('/') || path.contains(char::is_whitespace) {
+                errors.push(format!("Cache warming paths must start with '/' and cannot contain whitespace, got '{}'", path));
+            }
+        }
+        if self.max_urls < 1 {
+            errors.push("Cache warming max URLs must be at least 1".to_string());
+        }
+        if self.requests_per_second < 1 || self.requests_per_second > 100 {
+            errors.push(format!("Cache warming requests per second must be between 1 and 100, got {}", self.requests_per_second));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
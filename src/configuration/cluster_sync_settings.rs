@@ -0,0 +1,79 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+pub const CLUSTER_ROLES: [&str; 3] = ["standalone", "primary", "replica"];
+
+// Keeps the configuration of a fleet of Gruxi instances in sync. A primary serves its configuration to replicas,
+// which poll it from the admin portal of the primary and apply it locally
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ClusterSyncSettings {
+    pub role: String,                  // "standalone", "primary" or "replica"
+    pub primary_url: String,           // Admin portal of the primary, such as "https://primary.example.com:8000", for replicas
+    pub shared_token: String,          // Secret the replicas authenticate to the primary with, the same on all instances
+    pub poll_interval_seconds: u32,    // How often replicas check the primary for changes
+    pub verify_tls_certificates: bool, // Whether replicas verify the certificate of the primary, set to false for self-signed certs
+}
+
+impl Default for ClusterSyncSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClusterSyncSettings {
+    pub fn new() -> Self {
+        Self {
+            role: "standalone".to_string(),
+            primary_url: String::new(),
+            shared_token: String::new(),
+            poll_interval_seconds: 30,
+            verify_tls_certificates: true,
+        }
+    }
+
+    pub fn is_primary(&self) -> bool {
+        self.role == "primary"
+    }
+
+    pub fn is_replica(&self) -> bool {
+        self.role == "replica"
+    }
+
+    pub fn sanitize(&mut self) {
+        self.role = self.role.trim().to_lowercase();
+        self.primary_url = self.primary_url.trim().trim_end_matches('/').to_string();
+        self.shared_token = self.shared_token.trim().to_string();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if !CLUSTER_ROLES.contains(&self.role.as_str()) {
+            errors.push(format!("Role must be one of {}, got '{}'", CLUSTER_ROLES.join(", "), self.role));
+        }
+
+        if self.is_primary() || self.is_replica() {
+            // The token gives access to the whole configuration, including secrets, so it has to be hard to guess
+            if self.shared_token.len() < 16 {
+                errors.push("Shared token must be at least 16 characters".to_string());
+            }
+            if self.shared_token.chars().any(|c| c.is_control()) {
+                errors.push("Shared token cannot contain control characters".to_string());
+            }
+        }
+
+        if self.is_replica() {
+            if !(self.primary_url.starts_with("https://") || self.primary_url.starts_with("http://")) || self.primary_url.parse::<hyper::Uri>().is_err() {
+                errors.push(format!(
+                    "Primary URL must be the http:// or https:// address of the admin portal of the primary, got '{}'",
+                    self.primary_url
+                ));
+            }
+            if self.poll_interval_seconds < 5 {
+                errors.push("Poll interval must be at least 5 seconds".to_string());
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
@@ -0,0 +1,85 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// "pre_reload" runs before a new configuration is applied, and delays it until the hook is done.
+// "acme_dns_challenge" runs while a certificate for a wildcard hostname is ordered, to publish ("present") and later remove
+// ("cleanup") the TXT record of a DNS-01 challenge, and the CA is asked to check the record once the hook is done.
+// The other events run in the background, so a slow hook never holds up serving requests
+pub static COMMAND_HOOK_EVENTS: &[&str] = &["pre_reload", "post_reload", "certificate_renewed", "site_created", "acme_dns_challenge"];
+
+// An external command, run when an event happens in Gruxi, such as reloading a certificate into another service after a renewal.
+// The event is described to the command in environment variables starting with "GRUXI_"
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CommandHook {
+    pub id: String,
+    pub name: String,
+    pub event: String,             // One of COMMAND_HOOK_EVENTS
+    pub command: String,           // Executable to run, which is started directly and not through a shell
+    pub arguments: Vec<String>,    // Arguments passed to the command as they are
+    pub working_directory: String, // Empty uses the working directory of Gruxi
+    pub timeout_seconds: u32,      // The command is killed when it runs longer
+    pub is_enabled: bool,
+}
+
+impl Default for CommandHook {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandHook {
+    pub fn new() -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name: String::new(),
+            event: "post_reload".to_string(),
+            command: String::new(),
+            arguments: Vec::new(),
+            working_directory: String::new(),
+            timeout_seconds: 30,
+            is_enabled: true,
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.id = self.id.trim().to_string();
+        self.name = self.name.trim().to_string();
+        self.event = self.event.trim().to_lowercase();
+        self.command = self.command.trim().to_string();
+        self.working_directory = self.working_directory.trim().to_string();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.id.is_empty() {
+            errors.push("Command hook ID cannot be empty".to_string());
+        }
+
+        if self.name.is_empty() {
+            errors.push("Command hook name cannot be empty".to_string());
+        }
+
+        if !COMMAND_HOOK_EVENTS.contains(&self.event.as_str()) {
+            errors.push(format!("Command hook event '{}' is not supported, use one of: {}", self.event, COMMAND_HOOK_EVENTS.join(", ")));
+        }
+
+        // Commands without a path are looked up in PATH when they run, so only paths can be checked here
+        if self.command.is_empty() {
+            errors.push("Command hook command cannot be empty".to_string());
+        } else if (self.command.contains('/') || self.command.contains('\\')) && !std::path::Path::new(&self.command).is_file() {
+            errors.push(format!("Command hook command does not exist: {}", self.command));
+        }
+
+        if !self.working_directory.is_empty() && !std::path::Path::new(&self.working_directory).is_dir() {
+            errors.push(format!("Command hook working directory does not exist: {}", self.working_directory));
+        }
+
+        if self.timeout_seconds < 1 || self.timeout_seconds > 3600 {
+            errors.push("Command hook timeout must be between 1 and 3600 seconds".to_string());
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
@@ -1,21 +1,44 @@
 use crate::configuration::admin_portal::AdminPortal;
+use crate::configuration::auth_provider::AuthProvider;
+use crate::configuration::cluster_sync_settings::ClusterSyncSettings;
+use crate::configuration::command_hook::CommandHook;
 use crate::configuration::core::Core;
+use crate::configuration::database_backup::DatabaseBackupSettings;
+use crate::configuration::dns_resolution::DnsResolution;
+use crate::configuration::email_alerts::EmailAlertSettings;
 use crate::configuration::file_cache::FileCache;
 use crate::configuration::gzip::Gzip;
+use crate::configuration::health_probe_settings::HealthProbeSettings;
+use crate::configuration::landing_page::LandingPageSettings;
 use crate::configuration::request_handler::RequestHandler;
-use crate::configuration::server_settings::ServerSettings;
+use crate::configuration::request_priority::RequestPrioritySettings;
+use crate::configuration::server_settings::{ServerSettings, default_max_chunk_size, default_max_header_count, default_max_header_size, default_max_uri_length};
 use crate::configuration::site::Site;
+use crate::configuration::status_page::StatusPageSettings;
 use crate::configuration::tls_settings::TlsSettings;
+use crate::configuration::upload_scanning::UploadScanning;
+use crate::configuration::usage_reports::UsageReports;
 use crate::configuration::{binding::Binding, binding_site_relation::BindingSiteRelationship};
+use crate::core::usage_reports::get_site_web_roots;
+use crate::external_connections::managed_system::node_app::NodeApp;
 use crate::external_connections::managed_system::php_cgi::PhpCgi;
+use crate::external_connections::managed_system::python_app::PythonApp;
+use crate::file::normalized_path::NormalizedPath;
 use crate::http::request_handlers::processor_trait::ProcessorTrait;
+use crate::http::request_handlers::processors::cgi_processor::CgiProcessor;
+use crate::http::request_handlers::processors::image_processor::ImageProcessor;
+use crate::http::request_handlers::processors::node_processor::NodeProcessor;
 use crate::http::request_handlers::processors::php_processor::PHPProcessor;
 use crate::http::request_handlers::processors::proxy_processor::ProxyProcessor;
+use crate::http::request_handlers::processors::python_processor::PythonProcessor;
 use crate::http::request_handlers::processors::static_files_processor::StaticFileProcessor;
+use crate::http::request_handlers::processors::webdav_processor::WebDavProcessor;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Configuration {
     pub version: i32,
     pub bindings: Vec<Binding>,
@@ -27,11 +50,31 @@ pub struct Configuration {
     pub static_file_processors: Vec<StaticFileProcessor>,
     pub php_processors: Vec<PHPProcessor>,
     pub proxy_processors: Vec<ProxyProcessor>,
+    #[serde(default)]
+    pub webdav_processors: Vec<WebDavProcessor>,
+    #[serde(default)]
+    pub cgi_processors: Vec<CgiProcessor>,
+    #[serde(default)]
+    pub python_processors: Vec<PythonProcessor>,
+    #[serde(default)]
+    pub node_processors: Vec<NodeProcessor>,
+    #[serde(default)]
+    pub image_processors: Vec<ImageProcessor>,
     // External systems, such as PHP-CGI instances, FastCGI handlers, etc.
     pub php_cgi_handlers: Vec<PhpCgi>,
+    #[serde(default)]
+    pub python_handlers: Vec<PythonApp>,
+    #[serde(default)]
+    pub node_handlers: Vec<NodeApp>,
+    // Authentication providers, used by the admin portal and protected locations
+    #[serde(default)]
+    pub auth_providers: Vec<AuthProvider>,
+    // External commands run on events, such as after a reload or a certificate renewal
+    #[serde(default)]
+    pub command_hooks: Vec<CommandHook>,
 }
 
-pub static CURRENT_CONFIGURATION_VERSION: i32 = 4;
+pub static CURRENT_CONFIGURATION_VERSION: i32 = 53;
 
 impl Configuration {
     pub fn new() -> Self {
@@ -49,6 +92,12 @@ impl Configuration {
                     cleanup_thread_interval: 10,        // seconds
                     max_item_lifetime: 60,              // seconds
                     forced_eviction_threshold: 70,      // 1-99 %
+                    open_file_cache_max_entries: 256,
+                    open_file_cache_revalidate_seconds: 2,
+                    mmap_is_enabled: false,
+                    mmap_min_file_size: 1024 * 1024,
+                    mmap_max_file_size: 1024 * 1024 * 1024,
+                    compressed_variants_max_size: 64 * 1024 * 1024,
                 },
                 gzip: Gzip {
                     is_enabled: false,
@@ -83,16 +132,42 @@ impl Configuration {
                         ".log".to_string(),
                         ".key".to_string(),
                         ".pem".to_string(),
-                    ]
+                    ],
+                    http_version_policy: "standard".to_string(),
+                    trace_method_enabled: false,
+                    encoded_slash_policy: "keep".to_string(),
+                    max_header_count: default_max_header_count(),
+                    max_header_size: default_max_header_size(),
+                    max_uri_length: default_max_uri_length(),
+                    max_chunk_size: default_max_chunk_size(),
                 },
                 admin_portal: AdminPortal::new(),
                 tls_settings: TlsSettings::new(),
+                upload_scanning: UploadScanning::new(),
+                usage_reports: UsageReports::new(),
+                dns_resolution: DnsResolution::new(),
+                cluster_sync: ClusterSyncSettings::new(),
+                database_backup: DatabaseBackupSettings::new(),
+                request_priority: RequestPrioritySettings::new(),
+                email_alerts: EmailAlertSettings::new(),
+                status_page: StatusPageSettings::new(),
+                health_probes: HealthProbeSettings::new(),
+                landing_page: LandingPageSettings::new(),
             },
             request_handlers: vec![],
             static_file_processors: vec![],
             php_processors: vec![],
             proxy_processors: vec![],
+            webdav_processors: vec![],
+            cgi_processors: vec![],
+            python_processors: vec![],
+            node_processors: vec![],
+            image_processors: vec![],
             php_cgi_handlers: vec![],
+            python_handlers: vec![],
+            node_handlers: vec![],
+            auth_providers: vec![],
+            command_hooks: vec![],
         }
     }
 
@@ -131,10 +206,51 @@ impl Configuration {
             processor.sanitize();
         }
 
+        // Sanitize WebDAV processors
+        for processor in &mut self.webdav_processors {
+            processor.sanitize();
+        }
+
+        // Sanitize CGI processors
+        for processor in &mut self.cgi_processors {
+            processor.sanitize();
+        }
+
+        // Sanitize Python processors
+        for processor in &mut self.python_processors {
+            processor.sanitize();
+        }
+
+        // Sanitize Node.js processors
+        for processor in &mut self.node_processors {
+            processor.sanitize();
+        }
+
+        // Sanitize image processors
+        for processor in &mut self.image_processors {
+            processor.sanitize();
+        }
+
         // Sanitize external systems
         for php_cgi in &mut self.php_cgi_handlers {
             php_cgi.sanitize();
         }
+        for python_app in &mut self.python_handlers {
+            python_app.sanitize();
+        }
+        for node_app in &mut self.node_handlers {
+            node_app.sanitize();
+        }
+
+        // Sanitize auth providers
+        for provider in &mut self.auth_providers {
+            provider.sanitize();
+        }
+
+        // Sanitize command hooks
+        for hook in &mut self.command_hooks {
+            hook.sanitize();
+        }
     }
 
     // Validates the entire configuration
@@ -155,11 +271,24 @@ impl Configuration {
             }
         }
 
+        // A webroot sync replaces the whole local directory, so it can only write into a web root of the site itself
+        for (site_idx, site) in self.sites.iter().enumerate().filter(|(_, site)| site.webroot_sync.is_enabled()) {
+            let normalize = |path: &str| NormalizedPath::new(path, "").map(|path| path.get_full_path()).ok();
+            let local_path = normalize(&site.webroot_sync.local_path);
+            if local_path.is_some() && !get_site_web_roots(self, site).iter().any(|web_root| normalize(web_root) == local_path) {
+                errors.push(format!(
+                    "Site {}: Webroot sync local path '{}' must be one of the web roots of the site",
+                    site_idx + 1,
+                    site.webroot_sync.local_path
+                ));
+            }
+        }
+
         // Validate bindings
 
-        // First check that none of the bindings have duplicate IP/port combinations
+        // First check that none of the bindings have duplicate IP/port combinations. Bindings on port 0 each get their own free port
         let mut binding_combinations = std::collections::HashSet::new();
-        for binding in &self.bindings {
+        for binding in self.bindings.iter().filter(|binding| binding.port != 0) {
             let combo = format!("{}:{}", binding.ip, binding.port);
             if !binding_combinations.insert(combo) {
                 errors.push(format!("Duplicate binding for IP/Port combination: {}:{}", binding.ip, binding.port));
@@ -172,6 +301,9 @@ impl Configuration {
                     errors.push(format!("Binding {}: {}", binding_idx + 1, error));
                 }
             }
+            if !binding.default_site_id.is_empty() && !self.binding_sites.iter().any(|bs| bs.binding_id == binding.id && bs.site_id == binding.default_site_id) {
+                errors.push(format!("Binding {}: Default site '{}' is not a site of the binding", binding_idx + 1, binding.default_site_id));
+            }
         }
 
         // Validate core settings
@@ -220,6 +352,47 @@ impl Configuration {
                 }
             }
         }
+        for processor in &self.webdav_processors {
+            if let Err(processor_errors) = processor.validate() {
+                for error in processor_errors {
+                    errors.push(format!("WebDAV Processor {}: {}", processor.id, error));
+                }
+            }
+        }
+        for processor in &self.cgi_processors {
+            if let Err(processor_errors) = processor.validate() {
+                for error in processor_errors {
+                    errors.push(format!("CGI Processor {}: {}", processor.id, error));
+                }
+            }
+        }
+        for processor in &self.python_processors {
+            if let Err(processor_errors) = processor.validate() {
+                for error in processor_errors {
+                    errors.push(format!("Python Processor {}: {}", processor.id, error));
+                }
+            }
+            if !processor.python_handler_id.is_empty() && !self.python_handlers.iter().any(|h| h.id == processor.python_handler_id) {
+                errors.push(format!("Python Processor {}: Python handler '{}' does not exist", processor.id, processor.python_handler_id));
+            }
+        }
+        for processor in &self.node_processors {
+            if let Err(processor_errors) = processor.validate() {
+                for error in processor_errors {
+                    errors.push(format!("Node.js Processor {}: {}", processor.id, error));
+                }
+            }
+            if !processor.node_handler_id.is_empty() && !self.node_handlers.iter().any(|h| h.id == processor.node_handler_id) {
+                errors.push(format!("Node.js Processor {}: Node.js handler '{}' does not exist", processor.id, processor.node_handler_id));
+            }
+        }
+        for processor in &self.image_processors {
+            if let Err(processor_errors) = processor.validate() {
+                for error in processor_errors {
+                    errors.push(format!("Image Processor {}: {}", processor.id, error));
+                }
+            }
+        }
 
         // Validate external systems
         for (_, php_cgi) in self.php_cgi_handlers.iter().enumerate() {
@@ -229,6 +402,58 @@ impl Configuration {
                 }
             }
         }
+        for python_app in &self.python_handlers {
+            if let Err(python_app_errors) = python_app.validate() {
+                for error in python_app_errors {
+                    errors.push(format!("Python Handler '{}': {}", python_app.id, error));
+                }
+            }
+        }
+        for node_app in &self.node_handlers {
+            if let Err(node_app_errors) = node_app.validate() {
+                for error in node_app_errors {
+                    errors.push(format!("Node.js Handler '{}': {}", node_app.id, error));
+                }
+            }
+        }
+
+        // Validate auth providers, and that the admin portal and locations only reference existing ones
+        for provider in &self.auth_providers {
+            if let Err(provider_errors) = provider.validate() {
+                for error in provider_errors {
+                    errors.push(format!("Auth Provider '{}': {}", provider.name, error));
+                }
+            }
+        }
+        for hook in &self.command_hooks {
+            if let Err(hook_errors) = hook.validate() {
+                for error in hook_errors {
+                    errors.push(format!("Command Hook '{}': {}", hook.name, error));
+                }
+            }
+        }
+        let auth_provider_exists = |id: &str| self.auth_providers.iter().any(|p| p.id == id);
+        if !self.core.admin_portal.auth_provider_id.is_empty() && !auth_provider_exists(&self.core.admin_portal.auth_provider_id) {
+            errors.push(format!("Admin Portal: Auth provider '{}' does not exist", self.core.admin_portal.auth_provider_id));
+        }
+        if self.auth_providers.iter().any(|p| p.id == self.core.admin_portal.auth_provider_id && p.provider_type == "kerberos") {
+            errors.push("Admin Portal: Kerberos auth providers can only be used for locations, as the login form uses passwords".to_string());
+        }
+        for site_id in &self.core.status_page.site_ids {
+            if !self.sites.iter().any(|site| &site.id == site_id) {
+                errors.push(format!("Status Page: Site '{}' does not exist", site_id));
+            }
+        }
+        for site in &self.sites {
+            for location in &site.locations {
+                if !location.auth_provider_id.is_empty() && !auth_provider_exists(&location.auth_provider_id) {
+                    errors.push(format!("Location '{}': Auth provider '{}' does not exist", location.name, location.auth_provider_id));
+                }
+                if !location.auth_allowed_groups.is_empty() && !self.auth_providers.iter().any(|p| p.id == location.auth_provider_id && p.supports_groups()) {
+                    errors.push(format!("Location '{}': Allowed groups require an LDAP auth provider with a search base DN", location.name));
+                }
+            }
+        }
 
         // Validate that account email in TLS settings, if any of the sites have TLS automatic enabled
         let tls_automatic_sites: Vec<&Site> = self.sites.iter().filter(|s| s.tls_automatic_enabled).collect();
@@ -236,6 +461,17 @@ impl Configuration {
             errors.push("At least one site has TLS automatic enabled, but no account email is set in TLS settings".to_string());
         }
 
+        // The external account binding is configured in TLS settings, so sites can only use providers requiring one when it is the provider there
+        for site in &tls_automatic_sites {
+            if crate::tls::acme_account::provider_requires_eab(&site.acme.provider) && site.acme.provider != self.core.tls_settings.acme_provider {
+                errors.push(format!(
+                    "Site '{}': ACME provider '{}' requires an external account binding, which is only supported for the provider in TLS settings",
+                    site.hostnames.first().cloned().unwrap_or_default(),
+                    site.acme.provider
+                ));
+            }
+        }
+
         if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
 
@@ -248,7 +484,14 @@ impl Configuration {
             ip: "0.0.0.0".to_string(),
             port: 80,
             is_admin: false,
-            is_tls: false
+            is_tls: false,
+            http10_strict_close: false,
+            keep_alive_timeout_seconds: 0,
+            max_keep_alive_requests: 0,
+            http_versions: "auto".to_string(),
+            sni_mismatch_policy: "allow".to_string(),
+            default_site_id: String::new(),
+            unknown_host_policy: "default".to_string(),
         };
 
         let default_binding_tls = Binding {
@@ -256,7 +499,14 @@ impl Configuration {
             ip: "0.0.0.0".to_string(),
             port: 443,
             is_admin: false,
-            is_tls: true
+            is_tls: true,
+            http10_strict_close: false,
+            keep_alive_timeout_seconds: 0,
+            max_keep_alive_requests: 0,
+            http_versions: "auto".to_string(),
+            sni_mismatch_policy: "allow".to_string(),
+            default_site_id: String::new(),
+            unknown_host_policy: "default".to_string(),
         };
 
         // Static file processor for first site
@@ -295,4 +545,58 @@ impl Configuration {
 
         configuration
     }
+
+    /// JSON Schema of the configuration of this version of Gruxi, for tools and the web UI to build forms and validate edits
+    /// against. Generated once, as it only changes with the code
+    pub fn get_json_schema() -> &'static serde_json::Value {
+        static JSON_SCHEMA: OnceLock<serde_json::Value> = OnceLock::new();
+        JSON_SCHEMA.get_or_init(|| {
+            let mut schema = schemars::schema_for!(Configuration);
+            schema.insert("title".to_string(), "Gruxi configuration".into());
+            schema.insert("x-gruxi-version".to_string(), env!("CARGO_PKG_VERSION").into());
+            schema.insert("x-configuration-version".to_string(), CURRENT_CONFIGURATION_VERSION.into());
+            schema.to_value()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_schema_describes_every_serialized_field() {
+        let schema = Configuration::get_json_schema();
+        assert_eq!(schema["x-configuration-version"], CURRENT_CONFIGURATION_VERSION);
+
+        let configuration = serde_json::to_value(Configuration::get_default()).unwrap();
+        for key in configuration.as_object().unwrap().keys() {
+            assert!(schema["properties"].get(key).is_some(), "{} is missing in the schema", key);
+        }
+
+        let site = &configuration["sites"][0];
+        let site_schema = &schema["$defs"]["Site"]["properties"];
+        for key in site.as_object().unwrap().keys() {
+            assert!(site_schema.get(key).is_some(), "Site {} is missing in the schema", key);
+        }
+    }
+
+    #[test]
+    fn test_webroot_sync_local_path_is_a_web_root_of_the_site() {
+        let mut configuration = Configuration::get_default();
+        let web_root = configuration.static_file_processors[0].web_root.clone();
+        let site = &mut configuration.sites[0];
+        site.webroot_sync.protocol = "ftps".to_string();
+        site.webroot_sync.host = "ftp.example.com".to_string();
+        site.webroot_sync.username = "user".to_string();
+        site.webroot_sync.password = "password".to_string();
+        site.webroot_sync.remote_path = "/public_html".to_string();
+        let is_rejected = |configuration: &Configuration| configuration.validate().err().unwrap_or_default().iter().any(|error| error.contains("must be one of the web roots"));
+
+        configuration.sites[0].webroot_sync.local_path = "./www-other".to_string();
+        assert!(is_rejected(&configuration));
+
+        configuration.sites[0].webroot_sync.local_path = format!("{}/", web_root);
+        assert!(!is_rejected(&configuration));
+    }
 }
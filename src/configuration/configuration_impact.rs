@@ -0,0 +1,347 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::configuration::{configuration::Configuration, site::Site};
+
+// What applying a new configuration would do to the running server, so the blast radius of a save is known up front.
+// Everything is compared by ID, so renaming a site is a change to it, while recreating it is a removal and an addition
+#[derive(Debug, Default, Serialize)]
+pub struct ConfigurationImpact {
+    pub has_changes: bool,
+    pub core: Vec<String>,                    // Changed core settings sections, such as "gzip" or "admin_portal"
+    pub bindings: Vec<ImpactChange>,          // Listeners that are started, stopped or restarted
+    pub sites: Vec<ImpactChange>,             // Sites that are added, removed or route requests differently
+    pub request_handlers: Vec<ImpactChange>,  // Request handlers, including changes to the processor they use
+    pub external_handlers: Vec<ImpactChange>, // Managed PHP-CGI, Python and Node.js handlers that are started, stopped or rolled
+    pub certificates: Vec<CertificateImpact>, // Automatic TLS certificates that will be requested again
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ImpactChange {
+    pub id: String,
+    pub kind: String,
+    pub name: String,
+    pub change: String, // "added", "removed" or "changed"
+    pub changed_fields: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CertificateImpact {
+    pub site_id: String,
+    pub hostnames: Vec<String>,
+    pub reason: String,
+}
+
+pub fn analyze_configuration_impact(current: &Configuration, new: &Configuration) -> ConfigurationImpact {
+    let mut impact = ConfigurationImpact {
+        core: get_changed_fields(&to_value(&current.core), &to_value(&new.core)),
+        ..Default::default()
+    };
+
+    // Bindings restart when they change themselves or serve another set of sites
+    let current_sites_per_binding = get_relations(current, true);
+    let new_sites_per_binding = get_relations(new, true);
+    impact.bindings = compare_items(
+        "binding",
+        &current.bindings,
+        &new.bindings,
+        |binding| binding.id.clone(),
+        |binding| format!("{}:{}{}", binding.ip, binding.port, if binding.is_admin { " (admin)" } else { "" }),
+        |id| changed_if(current_sites_per_binding.get(id) != new_sites_per_binding.get(id), "sites"),
+    );
+
+    // Request handlers route differently when they change, or when the processor behind them changes
+    let changed_processor_ids = get_changed_ids(&get_processors_by_id(current), &get_processors_by_id(new));
+    let new_processor_by_handler: BTreeMap<&str, &str> = new.request_handlers.iter().map(|h| (h.id.as_str(), h.processor_id.as_str())).collect();
+    impact.request_handlers = compare_items(
+        "request_handler",
+        &current.request_handlers,
+        &new.request_handlers,
+        |handler| handler.id.clone(),
+        |handler| handler.name.clone(),
+        |id| changed_if(new_processor_by_handler.get(id).is_some_and(|processor_id| changed_processor_ids.contains(*processor_id)), "processor"),
+    );
+    let changed_request_handler_ids: HashSet<&str> = impact.request_handlers.iter().map(|change| change.id.as_str()).collect();
+
+    let current_bindings_per_site = get_relations(current, false);
+    let new_bindings_per_site = get_relations(new, false);
+    let new_sites_by_id: BTreeMap<&str, &Site> = new.sites.iter().map(|site| (site.id.as_str(), site)).collect();
+    impact.sites = compare_items(
+        "site",
+        &current.sites,
+        &new.sites,
+        |site| site.id.clone(),
+        |site| site.hostnames.join(", "),
+        |id| {
+            let mut fields = changed_if(current_bindings_per_site.get(id) != new_bindings_per_site.get(id), "bindings");
+            if let Some(site) = new_sites_by_id.get(id)
+                && get_site_request_handler_ids(site).iter().any(|handler_id| changed_request_handler_ids.contains(handler_id))
+            {
+                fields.push("request_handler_routing".to_string());
+            }
+            fields
+        },
+    );
+
+    let no_extra_fields = |_: &str| Vec::new();
+    impact.external_handlers.extend(compare_items(
+        "php_cgi",
+        &current.php_cgi_handlers,
+        &new.php_cgi_handlers,
+        |h| h.id.clone(),
+        |h| h.name.clone(),
+        no_extra_fields,
+    ));
+    impact.external_handlers.extend(compare_items(
+        "python",
+        &current.python_handlers,
+        &new.python_handlers,
+        |h| h.id.clone(),
+        |h| h.name.clone(),
+        no_extra_fields,
+    ));
+    impact
+        .external_handlers
+        .extend(compare_items("node", &current.node_handlers, &new.node_handlers, |h| h.id.clone(), |h| h.name.clone(), no_extra_fields));
+
+    impact.certificates = get_certificate_impact(current, new);
+
+    impact.has_changes = !impact.core.is_empty()
+        || !impact.bindings.is_empty()
+        || !impact.sites.is_empty()
+        || !impact.request_handlers.is_empty()
+        || !impact.external_handlers.is_empty()
+        || !impact.certificates.is_empty()
+        || to_value(&current.auth_providers) != to_value(&new.auth_providers)
+        || to_value(&current.command_hooks) != to_value(&new.command_hooks);
+
+    impact
+}
+
+// Sites with automatic TLS get a new certificate order, when they start using it or the hostnames in the order change
+fn get_certificate_impact(current: &Configuration, new: &Configuration) -> Vec<CertificateImpact> {
+    let current_tls = &current.core.tls_settings;
+    let new_tls = &new.core.tls_settings;
+    let acme_settings_changed = current_tls.use_staging_server != new_tls.use_staging_server
+        || current_tls.certificate_cache_path != new_tls.certificate_cache_path
+        || current_tls.consolidate_acme_orders != new_tls.consolidate_acme_orders
+        || current_tls.acme_challenge_type != new_tls.acme_challenge_type
+        || current_tls.acme_provider != new_tls.acme_provider
+        || current_tls.acme_directory_url != new_tls.acme_directory_url
+        || current_tls.acme_eab_key_id != new_tls.acme_eab_key_id;
+
+    let mut certificates = Vec::new();
+    for site in new.sites.iter().filter(|site| site.is_enabled && site.tls_automatic_enabled) {
+        let reason = match current.sites.iter().find(|current_site| current_site.id == site.id) {
+            None => "New site with automatic TLS",
+            Some(current_site) if !current_site.is_enabled || !current_site.tls_automatic_enabled => "Automatic TLS enabled",
+            Some(current_site) if get_sorted_hostnames(current_site) != get_sorted_hostnames(site) => "Hostnames changed",
+            Some(current_site) if current_site.acme != site.acme => "ACME settings of the site changed",
+            Some(_) if acme_settings_changed => "ACME settings changed",
+            Some(_) => continue,
+        };
+        certificates.push(CertificateImpact {
+            site_id: site.id.clone(),
+            hostnames: site.hostnames.clone(),
+            reason: reason.to_string(),
+        });
+    }
+    certificates
+}
+
+fn compare_items<T: Serialize>(
+    kind: &str,
+    current: &[T],
+    new: &[T],
+    get_id: impl Fn(&T) -> String,
+    get_name: impl Fn(&T) -> String,
+    get_extra_changed_fields: impl Fn(&str) -> Vec<String>,
+) -> Vec<ImpactChange> {
+    let current_by_id: BTreeMap<String, &T> = current.iter().map(|item| (get_id(item), item)).collect();
+    let new_ids: HashSet<String> = new.iter().map(&get_id).collect();
+    let mut changes = Vec::new();
+
+    for item in new {
+        let id = get_id(item);
+        let (change, changed_fields) = match current_by_id.get(&id) {
+            None => ("added", Vec::new()),
+            Some(current_item) => {
+                let mut changed_fields = get_changed_fields(&to_value(*current_item), &to_value(item));
+                for field in get_extra_changed_fields(&id) {
+                    if !changed_fields.contains(&field) {
+                        changed_fields.push(field);
+                    }
+                }
+                if changed_fields.is_empty() {
+                    continue;
+                }
+                ("changed", changed_fields)
+            }
+        };
+        changes.push(ImpactChange {
+            id,
+            kind: kind.to_string(),
+            name: get_name(item),
+            change: change.to_string(),
+            changed_fields,
+        });
+    }
+
+    for item in current.iter().filter(|item| !new_ids.contains(&get_id(item))) {
+        changes.push(ImpactChange {
+            id: get_id(item),
+            kind: kind.to_string(),
+            name: get_name(item),
+            change: "removed".to_string(),
+            changed_fields: Vec::new(),
+        });
+    }
+
+    changes
+}
+
+fn get_changed_fields(current: &Value, new: &Value) -> Vec<String> {
+    match (current, new) {
+        (Value::Object(current_fields), Value::Object(new_fields)) => {
+            let names: BTreeSet<&String> = current_fields.keys().chain(new_fields.keys()).collect();
+            names.into_iter().filter(|name| current_fields.get(*name) != new_fields.get(*name)).cloned().collect()
+        }
+        _ if current != new => vec!["value".to_string()],
+        _ => Vec::new(),
+    }
+}
+
+fn changed_if(changed: bool, field: &str) -> Vec<String> {
+    if changed { vec![field.to_string()] } else { Vec::new() }
+}
+
+fn to_value<T: Serialize>(item: &T) -> Value {
+    serde_json::to_value(item).unwrap_or(Value::Null)
+}
+
+// Site IDs per binding ID, or binding IDs per site ID
+fn get_relations(configuration: &Configuration, by_binding: bool) -> BTreeMap<String, BTreeSet<String>> {
+    let mut relations: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for relation in &configuration.binding_sites {
+        let (key, value) = if by_binding {
+            (&relation.binding_id, &relation.site_id)
+        } else {
+            (&relation.site_id, &relation.binding_id)
+        };
+        relations.entry(key.clone()).or_default().insert(value.clone());
+    }
+    relations
+}
+
+// All processors, of any type, by their ID
+fn get_processors_by_id(configuration: &Configuration) -> BTreeMap<String, Value> {
+    let processor_lists = [
+        to_value(&configuration.static_file_processors),
+        to_value(&configuration.php_processors),
+        to_value(&configuration.proxy_processors),
+        to_value(&configuration.webdav_processors),
+        to_value(&configuration.cgi_processors),
+        to_value(&configuration.python_processors),
+        to_value(&configuration.node_processors),
+        to_value(&configuration.image_processors),
+    ];
+
+    let mut processors = BTreeMap::new();
+    for processor in processor_lists.into_iter().filter_map(|list| if let Value::Array(items) = list { Some(items) } else { None }).flatten() {
+        if let Some(id) = processor.get("id").and_then(|id| id.as_str()) {
+            processors.insert(id.to_string(), processor);
+        }
+    }
+    processors
+}
+
+fn get_changed_ids(current: &BTreeMap<String, Value>, new: &BTreeMap<String, Value>) -> HashSet<String> {
+    new.iter().filter(|(id, value)| current.get(*id) != Some(*value)).map(|(id, _)| id.clone()).collect()
+}
+
+fn get_site_request_handler_ids(site: &Site) -> Vec<&str> {
+    site.request_handlers
+        .iter()
+        .chain(site.locations.iter().flat_map(|location| location.request_handlers.iter()))
+        .map(|id| id.as_str())
+        .collect()
+}
+
+fn get_sorted_hostnames(site: &Site) -> BTreeSet<&str> {
+    site.hostnames.iter().map(|hostname| hostname.as_str()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::{binding::Binding, binding_site_relation::BindingSiteRelationship, request_handler::RequestHandler};
+
+    fn get_test_configuration() -> Configuration {
+        let mut configuration = Configuration::new();
+        let binding = Binding::new();
+        let mut site = Site::new();
+        site.hostnames = vec!["example.com".to_string()];
+        let mut request_handler = RequestHandler::new();
+        request_handler.processor_id = "processor-1".to_string();
+        site.request_handlers = vec![request_handler.id.clone()];
+        configuration.binding_sites.push(BindingSiteRelationship {
+            binding_id: binding.id.clone(),
+            site_id: site.id.clone(),
+        });
+        configuration.bindings.push(binding);
+        configuration.sites.push(site);
+        configuration.request_handlers.push(request_handler);
+        configuration
+    }
+
+    #[test]
+    fn test_configuration_impact_no_changes() {
+        let configuration = get_test_configuration();
+        let impact = analyze_configuration_impact(&configuration, &configuration);
+        assert!(!impact.has_changes);
+        assert!(impact.sites.is_empty() && impact.bindings.is_empty() && impact.certificates.is_empty());
+    }
+
+    #[test]
+    fn test_configuration_impact_site_changes() {
+        let current = get_test_configuration();
+        let mut new: Configuration = serde_json::from_value(to_value(&current)).unwrap();
+
+        // New hostnames with automatic TLS, and a request handler matching other URLs
+        new.sites[0].hostnames.push("www.example.com".to_string());
+        new.sites[0].tls_automatic_enabled = true;
+        new.request_handlers[0].url_match = vec!["/api/*".to_string()];
+        new.binding_sites.clear();
+
+        let impact = analyze_configuration_impact(&current, &new);
+        assert!(impact.has_changes);
+        assert_eq!(impact.bindings.len(), 1);
+        assert_eq!(impact.bindings[0].changed_fields, vec!["sites".to_string()]);
+        assert_eq!(impact.sites[0].change, "changed");
+        assert_eq!(
+            impact.sites[0].changed_fields,
+            vec![
+                "hostnames".to_string(),
+                "tls_automatic_enabled".to_string(),
+                "bindings".to_string(),
+                "request_handler_routing".to_string()
+            ]
+        );
+        assert_eq!(impact.request_handlers[0].changed_fields, vec!["url_match".to_string()]);
+        assert_eq!(impact.certificates.len(), 1);
+        assert_eq!(impact.certificates[0].reason, "Automatic TLS enabled");
+    }
+
+    #[test]
+    fn test_configuration_impact_added_and_removed() {
+        let current = get_test_configuration();
+        let new = get_test_configuration();
+
+        // All IDs are new, so everything is replaced
+        let impact = analyze_configuration_impact(&current, &new);
+        assert_eq!(impact.sites.iter().map(|change| change.change.as_str()).collect::<Vec<&str>>(), vec!["added", "removed"]);
+        assert_eq!(impact.bindings.len(), 2);
+    }
+}
@@ -1,16 +1,47 @@
-use crate::configuration::tls_settings::TlsSettings;
-use crate::configuration::{admin_portal::AdminPortal, file_cache::FileCache};
+use crate::configuration::cluster_sync_settings::ClusterSyncSettings;
+use crate::configuration::database_backup::DatabaseBackupSettings;
+use crate::configuration::dns_resolution::DnsResolution;
+use crate::configuration::email_alerts::EmailAlertSettings;
 use crate::configuration::gzip::Gzip;
+use crate::configuration::health_probe_settings::HealthProbeSettings;
+use crate::configuration::landing_page::LandingPageSettings;
+use crate::configuration::request_priority::RequestPrioritySettings;
 use crate::configuration::server_settings::ServerSettings;
+use crate::configuration::status_page::StatusPageSettings;
+use crate::configuration::tls_settings::TlsSettings;
+use crate::configuration::upload_scanning::UploadScanning;
+use crate::configuration::usage_reports::UsageReports;
+use crate::configuration::{admin_portal::AdminPortal, file_cache::FileCache};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Core {
     pub file_cache: FileCache,
     pub gzip: Gzip,
     pub server_settings: ServerSettings,
     pub admin_portal: AdminPortal,
     pub tls_settings: TlsSettings,
+    #[serde(default)]
+    pub upload_scanning: UploadScanning,
+    #[serde(default)]
+    pub usage_reports: UsageReports,
+    #[serde(default)]
+    pub dns_resolution: DnsResolution,
+    #[serde(default)]
+    pub cluster_sync: ClusterSyncSettings,
+    #[serde(default)]
+    pub database_backup: DatabaseBackupSettings,
+    #[serde(default)]
+    pub request_priority: RequestPrioritySettings,
+    #[serde(default)]
+    pub email_alerts: EmailAlertSettings,
+    #[serde(default)]
+    pub status_page: StatusPageSettings,
+    #[serde(default)]
+    pub health_probes: HealthProbeSettings,
+    #[serde(default)]
+    pub landing_page: LandingPageSettings,
 }
 
 impl Core {
@@ -20,6 +51,16 @@ impl Core {
         self.server_settings.sanitize();
         self.admin_portal.sanitize();
         self.tls_settings.sanitize();
+        self.upload_scanning.sanitize();
+        self.usage_reports.sanitize();
+        self.dns_resolution.sanitize();
+        self.cluster_sync.sanitize();
+        self.database_backup.sanitize();
+        self.request_priority.sanitize();
+        self.email_alerts.sanitize();
+        self.status_page.sanitize();
+        self.health_probes.sanitize();
+        self.landing_page.sanitize();
     }
 
     pub fn validate(&self) -> Result<(), Vec<String>> {
@@ -60,6 +101,76 @@ impl Core {
             }
         }
 
+        // Validate upload scanning settings
+        if let Err(upload_scanning_errors) = self.upload_scanning.validate() {
+            for error in upload_scanning_errors {
+                errors.push(format!("Upload Scanning: {}", error));
+            }
+        }
+
+        // Validate usage report settings
+        if let Err(usage_reports_errors) = self.usage_reports.validate() {
+            for error in usage_reports_errors {
+                errors.push(format!("Usage Reports: {}", error));
+            }
+        }
+
+        // Validate DNS resolution settings
+        if let Err(dns_resolution_errors) = self.dns_resolution.validate() {
+            for error in dns_resolution_errors {
+                errors.push(format!("DNS Resolution: {}", error));
+            }
+        }
+
+        // Validate cluster sync settings
+        if let Err(cluster_sync_errors) = self.cluster_sync.validate() {
+            for error in cluster_sync_errors {
+                errors.push(format!("Cluster Sync: {}", error));
+            }
+        }
+
+        // Validate database backup settings
+        if let Err(database_backup_errors) = self.database_backup.validate() {
+            for error in database_backup_errors {
+                errors.push(format!("Database Backup: {}", error));
+            }
+        }
+
+        // Validate request priority settings
+        if let Err(request_priority_errors) = self.request_priority.validate() {
+            for error in request_priority_errors {
+                errors.push(format!("Request Priority: {}", error));
+            }
+        }
+
+        // Validate email alert settings
+        if let Err(email_alert_errors) = self.email_alerts.validate() {
+            for error in email_alert_errors {
+                errors.push(format!("Email Alerts: {}", error));
+            }
+        }
+
+        // Validate status page settings
+        if let Err(status_page_errors) = self.status_page.validate() {
+            for error in status_page_errors {
+                errors.push(format!("Status Page: {}", error));
+            }
+        }
+
+        // Validate health probe settings
+        if let Err(health_probe_errors) = self.health_probes.validate() {
+            for error in health_probe_errors {
+                errors.push(format!("Health Probes: {}", error));
+            }
+        }
+
+        // Validate landing page settings
+        if let Err(landing_page_errors) = self.landing_page.validate() {
+            for error in landing_page_errors {
+                errors.push(format!("Landing Page: {}", error));
+            }
+        }
+
         if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
 }
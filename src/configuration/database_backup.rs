@@ -0,0 +1,48 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// Online backups of the configuration database, taken while Gruxi is running. The newest backups are kept and older ones removed
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DatabaseBackupSettings {
+    pub is_enabled: bool,     // Whether backups are taken on schedule. Backups from the admin portal work either way
+    pub directory: String,    // Where the backups are written, such as "./backups"
+    pub interval_hours: u32,  // Time between scheduled backups
+    pub retention_count: u32, // Number of backups to keep, the oldest are removed first
+}
+
+impl Default for DatabaseBackupSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DatabaseBackupSettings {
+    pub fn new() -> Self {
+        Self {
+            is_enabled: false,
+            directory: "./backups".to_string(),
+            interval_hours: 24,
+            retention_count: 7,
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.directory = self.directory.trim().to_string();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.directory.is_empty() {
+            errors.push("Directory cannot be empty".to_string());
+        }
+        if self.interval_hours < 1 || self.interval_hours > 8760 {
+            errors.push("Interval must be between 1 and 8760 hours".to_string());
+        }
+        if self.retention_count < 1 || self.retention_count > 1000 {
+            errors.push("Retention count must be between 1 and 1000".to_string());
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
@@ -0,0 +1,207 @@
+use std::sync::{Mutex, OnceLock};
+
+use serde::Serialize;
+use serde_json::Value;
+
+// A configuration field that was renamed. The old name is still read, through a serde alias on the field in
+// configuration files and in configurations stored as JSON, and through the setting key for the server settings of
+// the SQLite database, but it is reported until the configuration is migrated with "gruxi config migrate"
+pub struct DeprecatedField {
+    pub path: &'static str, // Object the field is in, with "*" for each item of a list, such as "bindings.*"
+    pub old_name: &'static str,
+    pub new_name: &'static str,
+    pub since_version: &'static str,
+}
+
+// Renamed fields in configuration files and JSON stored configurations
+pub const DEPRECATED_FIELDS: &[DeprecatedField] = &[
+    DeprecatedField {
+        path: "bindings.*",
+        old_name: "keep_alive_timeout",
+        new_name: "keep_alive_timeout_seconds",
+        since_version: "0.1.7",
+    },
+    DeprecatedField {
+        path: "sites.*",
+        old_name: "tls_automatic",
+        new_name: "tls_automatic_enabled",
+        since_version: "0.1.7",
+    },
+    DeprecatedField {
+        path: "core.server_settings",
+        old_name: "max_request_body_size",
+        new_name: "max_body_size",
+        since_version: "0.1.7",
+    },
+    DeprecatedField {
+        path: "core.tls_settings",
+        old_name: "use_staging",
+        new_name: "use_staging_server",
+        since_version: "0.1.7",
+    },
+];
+
+// Renamed keys of the server settings table in the SQLite database
+pub const DEPRECATED_SETTING_KEYS: &[DeprecatedField] = &[
+    DeprecatedField {
+        path: "server_settings",
+        old_name: "max_request_body_size",
+        new_name: "max_body_size",
+        since_version: "0.1.7",
+    },
+    DeprecatedField {
+        path: "server_settings",
+        old_name: "tls_use_staging",
+        new_name: "tls_use_staging_server",
+        since_version: "0.1.7",
+    },
+    DeprecatedField {
+        path: "server_settings",
+        old_name: "gzip_enabled",
+        new_name: "gzip_is_enabled",
+        since_version: "0.1.7",
+    },
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeprecationWarning {
+    pub field: String, // Where the deprecated field was found, such as "bindings[0].keep_alive_timeout"
+    pub replacement: String,
+    pub since_version: String,
+    pub message: String,
+}
+
+impl DeprecationWarning {
+    fn new(field: String, deprecated_field: &DeprecatedField) -> Self {
+        let message = format!(
+            "'{}' is deprecated since {}, use '{}' instead. Run \"gruxi config migrate\" to rename it in the stored configuration",
+            field, deprecated_field.since_version, deprecated_field.new_name
+        );
+        DeprecationWarning {
+            field,
+            replacement: deprecated_field.new_name.to_string(),
+            since_version: deprecated_field.since_version.to_string(),
+            message,
+        }
+    }
+}
+
+// The deprecated fields used in a configuration in JSON, such as an imported file
+pub fn find_deprecated_fields(configuration: &Value) -> Vec<DeprecationWarning> {
+    let mut configuration = configuration.clone();
+    rename_deprecated_fields(&mut configuration)
+}
+
+// Rename the deprecated fields in a configuration in JSON to their current names. When both names are set, the
+// current one wins, as it is the one serde would otherwise fail on as a duplicate
+pub fn rename_deprecated_fields(configuration: &mut Value) -> Vec<DeprecationWarning> {
+    let mut warnings = Vec::new();
+    for deprecated_field in DEPRECATED_FIELDS {
+        let path: Vec<&str> = deprecated_field.path.split('.').collect();
+        rename_in_objects(configuration, &path, String::new(), deprecated_field, &mut warnings);
+    }
+    warnings
+}
+
+fn rename_in_objects(value: &mut Value, path: &[&str], location: String, deprecated_field: &DeprecatedField, warnings: &mut Vec<DeprecationWarning>) {
+    let Some((segment, rest)) = path.split_first() else {
+        if let Value::Object(object) = value
+            && let Some(old_value) = object.remove(deprecated_field.old_name)
+        {
+            if !object.contains_key(deprecated_field.new_name) {
+                object.insert(deprecated_field.new_name.to_string(), old_value);
+            }
+            warnings.push(DeprecationWarning::new(format!("{}.{}", location, deprecated_field.old_name), deprecated_field));
+        }
+        return;
+    };
+
+    if *segment == "*" {
+        if let Value::Array(items) = value {
+            for (index, item) in items.iter_mut().enumerate() {
+                rename_in_objects(item, rest, format!("{}[{}]", location, index), deprecated_field, warnings);
+            }
+        }
+    } else if let Some(child) = value.get_mut(*segment) {
+        let location = if location.is_empty() { segment.to_string() } else { format!("{}.{}", location, segment) };
+        rename_in_objects(child, rest, location, deprecated_field, warnings);
+    }
+}
+
+// The current key of a server setting, for settings saved under a deprecated key
+pub fn get_current_setting_key(key: &str) -> &str {
+    DEPRECATED_SETTING_KEYS.iter().find(|d| d.old_name == key).map(|d| d.new_name).unwrap_or(key)
+}
+
+// The deprecated keys among the keys of the server settings table
+pub fn find_deprecated_setting_keys(keys: &[String]) -> Vec<DeprecationWarning> {
+    DEPRECATED_SETTING_KEYS
+        .iter()
+        .filter(|deprecated_field| keys.iter().any(|key| key == deprecated_field.old_name))
+        .map(|deprecated_field| DeprecationWarning::new(format!("{}.{}", deprecated_field.path, deprecated_field.old_name), deprecated_field))
+        .collect()
+}
+
+fn get_configuration_deprecations_store() -> &'static Mutex<Vec<DeprecationWarning>> {
+    static CONFIGURATION_DEPRECATIONS: OnceLock<Mutex<Vec<DeprecationWarning>>> = OnceLock::new();
+    CONFIGURATION_DEPRECATIONS.get_or_init(|| Mutex::new(Vec::new()))
+}
+
+// Keep the deprecations of the stored configuration, for the admin portal
+pub fn set_configuration_deprecations(warnings: Vec<DeprecationWarning>) {
+    *get_configuration_deprecations_store().lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = warnings;
+}
+
+pub fn get_configuration_deprecations() -> Vec<DeprecationWarning> {
+    get_configuration_deprecations_store().lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::binding::Binding;
+
+    #[test]
+    fn test_rename_deprecated_fields() {
+        let mut configuration = serde_json::json!({
+            "bindings": [{ "port": 80 }, { "port": 443, "keep_alive_timeout": 30 }],
+            "core": { "tls_settings": { "use_staging": true, "use_staging_server": false } }
+        });
+
+        let warnings = rename_deprecated_fields(&mut configuration);
+        let fields: Vec<&str> = warnings.iter().map(|w| w.field.as_str()).collect();
+        assert_eq!(fields, vec!["bindings[1].keep_alive_timeout", "core.tls_settings.use_staging"]);
+        assert_eq!(warnings[0].replacement, "keep_alive_timeout_seconds");
+
+        // The old value is moved to the new name, unless the new name is set too
+        assert_eq!(configuration["bindings"][1]["keep_alive_timeout_seconds"], 30);
+        assert!(configuration["bindings"][1].get("keep_alive_timeout").is_none());
+        assert_eq!(configuration["core"]["tls_settings"]["use_staging_server"], false);
+        assert!(configuration["core"]["tls_settings"].get("use_staging").is_none());
+
+        assert!(find_deprecated_fields(&configuration).is_empty());
+    }
+
+    #[test]
+    fn test_deprecated_fields_still_deserialize() {
+        let mut binding = serde_json::to_value(Binding::new()).unwrap();
+        let object = binding.as_object_mut().unwrap();
+        object.remove("keep_alive_timeout_seconds");
+        object.insert("keep_alive_timeout".to_string(), serde_json::json!(15));
+
+        let binding: Binding = serde_json::from_value(binding).unwrap();
+        assert_eq!(binding.keep_alive_timeout_seconds, 15);
+    }
+
+    #[test]
+    fn test_deprecated_setting_keys() {
+        assert_eq!(get_current_setting_key("gzip_enabled"), "gzip_is_enabled");
+        assert_eq!(get_current_setting_key("gzip_is_enabled"), "gzip_is_enabled");
+
+        let keys = vec!["gzip_is_enabled".to_string(), "tls_use_staging".to_string()];
+        let warnings = find_deprecated_setting_keys(&keys);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].field, "server_settings.tls_use_staging");
+        assert_eq!(warnings[0].replacement, "tls_use_staging_server");
+    }
+}
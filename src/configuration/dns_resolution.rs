@@ -0,0 +1,109 @@
+use std::net::{IpAddr, SocketAddr};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+pub const DNS_RESOLUTION_MODES: [&str; 3] = ["system", "doh", "dot"];
+// "auto" keeps the order of the resolver, which for the OS resolver follows the address selection rules of RFC 6724.
+// The "_only" preferences drop the addresses of the other family, for single-stack networks where they can never connect
+pub const ADDRESS_PREFERENCES: [&str; 5] = ["auto", "ipv6", "ipv4", "ipv6_only", "ipv4_only"];
+
+// How Gruxi resolves host names of upstreams, health checks and webhooks
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct DnsResolution {
+    pub mode: String,           // "system" for the resolver of the OS, "doh" for DNS-over-HTTPS or "dot" for DNS-over-TLS
+    pub server_address: String, // IP address of the DoH/DoT provider, optionally with a port (default 443 for DoH, 853 for DoT)
+    pub server_name: String,    // Host name the certificate of the provider is verified against, such as "cloudflare-dns.com"
+    pub doh_path: String,       // Path of the DoH endpoint of the provider
+    #[serde(default = "default_address_preference")]
+    pub address_preference: String, // Which address family of dual-stack hosts is connected to first, one of ADDRESS_PREFERENCES
+    #[serde(default = "default_happy_eyeballs_delay_ms")]
+    pub happy_eyeballs_delay_ms: u32, // How long a connection to the preferred family may take before the other family is raced (RFC 8305), 0 to only try the other family after it failed
+}
+
+fn default_address_preference() -> String {
+    "auto".to_string()
+}
+
+// The connection attempt delay recommended by RFC 8305
+fn default_happy_eyeballs_delay_ms() -> u32 {
+    250
+}
+
+impl Default for DnsResolution {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DnsResolution {
+    pub fn new() -> Self {
+        Self {
+            mode: "system".to_string(),
+            server_address: String::new(),
+            server_name: String::new(),
+            doh_path: "/dns-query".to_string(),
+            address_preference: default_address_preference(),
+            happy_eyeballs_delay_ms: default_happy_eyeballs_delay_ms(),
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.mode = self.mode.trim().to_lowercase();
+        self.server_address = self.server_address.trim().to_string();
+        self.server_name = self.server_name.trim().to_string();
+        self.doh_path = self.doh_path.trim().to_string();
+        self.address_preference = self.address_preference.trim().to_lowercase();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if !DNS_RESOLUTION_MODES.contains(&self.mode.as_str()) {
+            errors.push(format!("Mode must be one of {}, got '{}'", DNS_RESOLUTION_MODES.join(", "), self.mode));
+        }
+
+        if self.mode == "doh" || self.mode == "dot" {
+            // The provider is given by IP address, as there is no resolver yet to look up its name
+            if self.get_server_address().is_none() {
+                errors.push(format!(
+                    "Server address must be an IP address, optionally with a port, such as '1.1.1.1' or '[2606:4700:4700::1111]:853', got '{}'",
+                    self.server_address
+                ));
+            }
+            if self.server_name.is_empty() || rustls_pki_types::ServerName::try_from(self.server_name.as_str()).is_err() {
+                errors.push(format!(
+                    "Server name must be the host name in the certificate of the provider, such as 'cloudflare-dns.com', got '{}'",
+                    self.server_name
+                ));
+            }
+        }
+
+        if self.mode == "doh" && !self.doh_path.starts_with('/') {
+            errors.push(format!("DoH path must start with '/', such as '/dns-query', got '{}'", self.doh_path));
+        }
+
+        if !ADDRESS_PREFERENCES.contains(&self.address_preference.as_str()) {
+            errors.push(format!("Address preference must be one of {}, got '{}'", ADDRESS_PREFERENCES.join(", "), self.address_preference));
+        }
+
+        if self.happy_eyeballs_delay_ms > 10000 {
+            errors.push(format!("Happy eyeballs delay must be at most 10000 ms, got {}", self.happy_eyeballs_delay_ms));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    pub fn is_system(&self) -> bool {
+        self.mode == "system"
+    }
+
+    // The address of the DoH/DoT provider, with the default port of the mode if none is given
+    pub fn get_server_address(&self) -> Option<SocketAddr> {
+        if let Ok(address) = self.server_address.parse::<SocketAddr>() {
+            return Some(address);
+        }
+        let default_port = if self.mode == "doh" { 443 } else { 853 };
+        self.server_address.parse::<IpAddr>().ok().map(|ip| SocketAddr::new(ip, default_port))
+    }
+}
@@ -0,0 +1,144 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// How the connection to the SMTP server is secured: "starttls" upgrades a plain connection, usually on port 587, "tls" is
+// TLS from the start, usually on port 465, and "none" is for local relays only, as credentials are sent in the clear
+pub const SMTP_TLS_MODES: [&str; 3] = ["starttls", "tls", "none"];
+
+// The admin alerts of the event types are emailed to the recipients. Event types are the sources of admin alerts, such as
+// "external_handler", or "*" for all of them
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct EmailAlertRoute {
+    pub event_types: Vec<String>,
+    pub recipients: Vec<String>,
+}
+
+impl EmailAlertRoute {
+    pub fn matches(&self, event_type: &str) -> bool {
+        self.event_types
+            .iter()
+            .any(|route_event_type| route_event_type == "*" || route_event_type.eq_ignore_ascii_case(event_type))
+    }
+}
+
+// Admin alerts sent by email, as a notification channel next to the admin portal
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct EmailAlertSettings {
+    pub is_enabled: bool,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_tls_mode: String,
+    pub smtp_verify_certificates: bool,
+    pub smtp_username: String, // Empty to send without authentication
+    pub smtp_password: String,
+    pub from_address: String,
+    pub routes: Vec<EmailAlertRoute>,
+    pub repeat_interval_minutes: u32, // The same alert is emailed at most once per interval, repeats are counted in the next email
+}
+
+impl Default for EmailAlertSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EmailAlertSettings {
+    pub fn new() -> Self {
+        Self {
+            is_enabled: false,
+            smtp_host: String::new(),
+            smtp_port: 587,
+            smtp_tls_mode: "starttls".to_string(),
+            smtp_verify_certificates: true,
+            smtp_username: String::new(),
+            smtp_password: String::new(),
+            from_address: String::new(),
+            routes: Vec::new(),
+            repeat_interval_minutes: 60,
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.smtp_host = self.smtp_host.trim().to_string();
+        self.smtp_tls_mode = self.smtp_tls_mode.trim().to_lowercase();
+        self.smtp_username = self.smtp_username.trim().to_string();
+        self.from_address = self.from_address.trim().to_string();
+        for route in &mut self.routes {
+            route.event_types = route
+                .event_types
+                .iter()
+                .map(|event_type| event_type.trim().to_string())
+                .filter(|event_type| !event_type.is_empty())
+                .collect();
+            route.recipients = route
+                .recipients
+                .iter()
+                .map(|recipient| recipient.trim().to_string())
+                .filter(|recipient| !recipient.is_empty())
+                .collect();
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if !SMTP_TLS_MODES.contains(&self.smtp_tls_mode.as_str()) {
+            errors.push(format!("SMTP TLS mode must be one of {}, got '{}'", SMTP_TLS_MODES.join(", "), self.smtp_tls_mode));
+        }
+        if self.repeat_interval_minutes > 10080 {
+            errors.push("Repeat interval cannot be more than 10080 minutes (a week)".to_string());
+        }
+
+        if self.is_enabled {
+            if self.smtp_host.is_empty() {
+                errors.push("SMTP host is required when email alerts are enabled".to_string());
+            }
+            if self.smtp_port == 0 {
+                errors.push("SMTP port cannot be 0".to_string());
+            }
+            if !is_valid_email_address(&self.from_address) {
+                errors.push(format!("From address is not a valid email address: '{}'", self.from_address));
+            }
+            if self.routes.is_empty() {
+                errors.push("At least one route is required when email alerts are enabled".to_string());
+            }
+        }
+
+        for (route_idx, route) in self.routes.iter().enumerate() {
+            if route.event_types.is_empty() {
+                errors.push(format!("Route #{}: Needs at least one event type, or '*' for all", route_idx + 1));
+            }
+            if route.recipients.is_empty() {
+                errors.push(format!("Route #{}: Needs at least one recipient", route_idx + 1));
+            }
+            for recipient in &route.recipients {
+                if !is_valid_email_address(recipient) {
+                    errors.push(format!("Route #{}: Recipient is not a valid email address: '{}'", route_idx + 1, recipient));
+                }
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    // The recipients of the alerts of the event type, from all routes matching it
+    pub fn get_recipients(&self, event_type: &str) -> Vec<String> {
+        let mut recipients: Vec<String> = self
+            .routes
+            .iter()
+            .filter(|route| route.matches(event_type))
+            .flat_map(|route| route.recipients.iter().cloned())
+            .collect();
+        recipients.sort();
+        recipients.dedup();
+        recipients
+    }
+}
+
+// Simple check of the form local@domain, which also keeps addresses from breaking SMTP commands and mail headers
+pub fn is_valid_email_address(address: &str) -> bool {
+    match address.split_once('@') {
+        Some((local, domain)) => !local.is_empty() && !domain.is_empty() && !domain.contains('@') && !address.chars().any(|c| c.is_whitespace() || c.is_control() || c == '<' || c == '>' || c == ','),
+        None => false,
+    }
+}
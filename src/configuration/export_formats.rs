@@ -0,0 +1,1108 @@
+// ============================================================================
+// CONFIGURATION EXPORT FORMATS
+// ============================================================================
+//
+// Renders the configuration as an approximate nginx.conf or Caddyfile, and a
+// docker-compose snippet for the PHP-CGI, Python and Node.js handlers, for
+// documentation, review and comparing or migrating to and from other servers.
+//
+// The output is a starting point, not a drop-in replacement: settings without
+// an equivalent are written as comments, and the managed handlers are expected
+// to run as the services of the docker-compose snippet, which the nginx and
+// Caddy output refer to by service name.
+// ============================================================================
+
+use std::collections::{HashMap, HashSet};
+
+use crate::configuration::binding::Binding;
+use crate::configuration::configuration::Configuration;
+use crate::configuration::location::Location;
+use crate::configuration::request_handler::RequestHandler;
+use crate::configuration::site::Site;
+use crate::http::request_handlers::processors::cgi_processor::CgiProcessor;
+use crate::http::request_handlers::processors::image_processor::ImageProcessor;
+use crate::http::request_handlers::processors::php_processor::PHPProcessor;
+use crate::http::request_handlers::processors::proxy_processor::ProxyProcessor;
+use crate::http::request_handlers::processors::static_files_processor::StaticFileProcessor;
+use crate::http::request_handlers::processors::webdav_processor::WebDavProcessor;
+
+pub const EXPORT_FORMATS: [&str; 4] = ["json", "nginx", "caddy", "docker-compose"];
+
+// Ports the handler services listen on in the docker-compose snippet
+const PYTHON_SERVICE_PORT: u16 = 8000;
+const NODE_SERVICE_PORT: u16 = 3000;
+const PHP_FPM_SERVICE_PORT: u16 = 9000;
+
+// Web root of PHP in the PHP-FPM services that replace PHP-CGI handlers
+const PHP_FPM_SERVICE_WEB_ROOT: &str = "/var/www/html";
+
+/// Render the configuration in one of the export formats
+pub fn render_configuration(configuration: &Configuration, format: &str) -> Result<String, String> {
+    match format {
+        "json" => serde_json::to_string_pretty(configuration).map_err(|e| format!("Failed to serialize configuration: {}", e)),
+        "nginx" => Ok(render_nginx(configuration)),
+        "caddy" => Ok(render_caddyfile(configuration)),
+        "docker-compose" => Ok(render_docker_compose(configuration)),
+        _ => Err(format!("Unknown export format '{}', must be one of {}", format, EXPORT_FORMATS.join(", "))),
+    }
+}
+
+// Text with nested blocks, indented four spaces per level
+struct ConfigWriter {
+    text: String,
+    depth: usize,
+}
+
+impl ConfigWriter {
+    fn new(header: &str) -> Self {
+        let mut writer = ConfigWriter { text: String::new(), depth: 0 };
+        for line in header.lines() {
+            writer.line(format!("# {}", line).trim_end());
+        }
+        writer.blank();
+        writer
+    }
+
+    fn line(&mut self, text: impl AsRef<str>) {
+        self.text.push_str(&"    ".repeat(self.depth));
+        self.text.push_str(text.as_ref());
+        self.text.push('\n');
+    }
+
+    fn comment(&mut self, text: impl AsRef<str>) {
+        self.line(format!("# {}", text.as_ref()));
+    }
+
+    fn blank(&mut self) {
+        self.text.push('\n');
+    }
+
+    fn open(&mut self, header: impl AsRef<str>) {
+        self.line(format!("{} {{", header.as_ref()));
+        self.depth += 1;
+    }
+
+    fn close(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+        self.line("}");
+    }
+}
+
+// Quote a value for nginx and Caddy, when it has characters that would end or split it
+fn quote(value: &str) -> String {
+    let needs_quotes = value.is_empty() || value.chars().any(|c| c.is_whitespace() || matches!(c, ';' | '{' | '}' | '"' | '\'' | '#'));
+    if needs_quotes { format!("\"{}\"", value.replace('"', "\\\"")) } else { value.to_string() }
+}
+
+// A string in YAML, as JSON strings are valid YAML
+fn yaml_string(value: &str) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| "\"\"".to_string())
+}
+
+// URL patterns of request handlers, as matched by RequestHandler::matches_url
+#[derive(Debug, PartialEq)]
+enum UrlPattern {
+    All,
+    Suffix(String),
+    Prefix(String),
+    Exact(String),
+}
+
+impl UrlPattern {
+    fn parse(pattern: &str) -> Self {
+        let pattern = pattern.to_lowercase();
+        if pattern == "*" {
+            UrlPattern::All
+        } else if let Some(suffix) = pattern.strip_prefix('*') {
+            UrlPattern::Suffix(suffix.to_string())
+        } else if let Some(prefix) = pattern.strip_suffix('*') {
+            UrlPattern::Prefix(prefix.to_string())
+        } else {
+            UrlPattern::Exact(pattern)
+        }
+    }
+
+    fn nginx_location(&self) -> String {
+        match self {
+            UrlPattern::All => "location /".to_string(),
+            UrlPattern::Suffix(suffix) => format!("location ~* {}", quote(&format!("{}$", regex::escape(suffix)))),
+            UrlPattern::Prefix(prefix) => format!("location ^~ {}", quote(prefix)),
+            UrlPattern::Exact(path) => format!("location = {}", quote(path)),
+        }
+    }
+
+    fn caddy_matcher(&self) -> Option<String> {
+        match self {
+            UrlPattern::All => None,
+            UrlPattern::Suffix(suffix) => Some(format!("path {}", quote(&format!("*{}", suffix)))),
+            UrlPattern::Prefix(prefix) => Some(format!("path {}", quote(&format!("{}*", prefix)))),
+            UrlPattern::Exact(path) => Some(format!("path {}", quote(path))),
+        }
+    }
+}
+
+// What a request handler passes its requests to
+enum Backend<'a> {
+    Static(&'a StaticFileProcessor),
+    // With the FastCGI address, which is the PHP-FPM service of a PHP-CGI handler
+    Php(&'a PHPProcessor, String),
+    Proxy(&'a ProxyProcessor),
+    WebDav(&'a WebDavProcessor),
+    Cgi(&'a CgiProcessor),
+    Image(&'a ImageProcessor),
+    // Service name and port of a Python or Node.js handler, and whether the Host header is preserved
+    Service(String, u16, bool),
+    Missing,
+}
+
+struct ExportContext<'a> {
+    configuration: &'a Configuration,
+    service_names: HashMap<String, String>, // Handler id to the name of its service in the docker-compose snippet
+}
+
+impl<'a> ExportContext<'a> {
+    fn new(configuration: &'a Configuration) -> Self {
+        let mut service_names = HashMap::new();
+        let mut used_names = HashSet::new();
+        let handlers = configuration
+            .php_cgi_handlers
+            .iter()
+            .map(|handler| ("php", &handler.id, &handler.name))
+            .chain(configuration.python_handlers.iter().map(|handler| ("python", &handler.id, &handler.name)))
+            .chain(configuration.node_handlers.iter().map(|handler| ("node", &handler.id, &handler.name)));
+        for (kind, id, name) in handlers {
+            let slug: String = name.to_lowercase().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '-' }).collect();
+            let slug = slug.split('-').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("-");
+            let short_id: String = id.chars().filter(|c| c.is_ascii_alphanumeric()).take(8).collect();
+            let mut service_name = if slug.is_empty() { format!("{}-{}", kind, short_id) } else { format!("{}-{}", kind, slug) };
+            if used_names.contains(&service_name) {
+                service_name = format!("{}-{}", service_name, short_id);
+            }
+            used_names.insert(service_name.clone());
+            service_names.insert(id.clone(), service_name);
+        }
+        ExportContext { configuration, service_names }
+    }
+
+    fn get_service_name(&self, handler_id: &str) -> String {
+        self.service_names.get(handler_id).cloned().unwrap_or_else(|| format!("missing-handler-{}", handler_id))
+    }
+
+    fn get_backend(&self, handler: &RequestHandler) -> Backend<'a> {
+        let configuration = self.configuration;
+        let id = handler.processor_id.as_str();
+        let backend = match handler.processor_type.as_str() {
+            "static" => configuration.static_file_processors.iter().find(|p| p.id == id).map(Backend::Static),
+            "php" => configuration.php_processors.iter().find(|p| p.id == id).map(|p| {
+                let address = match p.served_by_type.as_str() {
+                    "php-fpm" => p.fastcgi_ip_and_port.clone(),
+                    _ => format!("{}:{}", self.get_service_name(&p.php_cgi_handler_id), PHP_FPM_SERVICE_PORT),
+                };
+                Backend::Php(p, address)
+            }),
+            "proxy" => configuration.proxy_processors.iter().find(|p| p.id == id).map(Backend::Proxy),
+            "webdav" => configuration.webdav_processors.iter().find(|p| p.id == id).map(Backend::WebDav),
+            "cgi" => configuration.cgi_processors.iter().find(|p| p.id == id).map(Backend::Cgi),
+            "image" => configuration.image_processors.iter().find(|p| p.id == id).map(Backend::Image),
+            "python" => configuration
+                .python_processors
+                .iter()
+                .find(|p| p.id == id)
+                .map(|p| Backend::Service(self.get_service_name(&p.python_handler_id), PYTHON_SERVICE_PORT, p.preserve_host_header)),
+            "node" => configuration
+                .node_processors
+                .iter()
+                .find(|p| p.id == id)
+                .map(|p| Backend::Service(self.get_service_name(&p.node_handler_id), NODE_SERVICE_PORT, p.preserve_host_header)),
+            _ => None,
+        };
+        backend.unwrap_or(Backend::Missing)
+    }
+
+    // Enabled request handlers, in the order they are tried
+    fn get_handlers(&self, handler_ids: &[String]) -> Vec<&'a RequestHandler> {
+        handler_ids
+            .iter()
+            .filter_map(|id| self.configuration.request_handlers.iter().find(|handler| &handler.id == id))
+            .filter(|handler| handler.is_enabled)
+            .collect()
+    }
+
+    // Bindings the site is served on, except admin bindings, which serve the admin portal
+    fn get_site_bindings(&self, site: &Site) -> Vec<&'a Binding> {
+        self.configuration
+            .binding_sites
+            .iter()
+            .filter(|relation| relation.site_id == site.id)
+            .filter_map(|relation| self.configuration.bindings.iter().find(|binding| binding.id == relation.binding_id))
+            .filter(|binding| !binding.is_admin)
+            .collect()
+    }
+
+    fn get_exported_sites(&self) -> Vec<&'a Site> {
+        self.configuration.sites.iter().filter(|site| site.is_enabled).collect()
+    }
+}
+
+fn get_hostnames(site: &Site) -> Vec<&str> {
+    site.hostnames.iter().map(String::as_str).filter(|hostname| !hostname.is_empty() && *hostname != "*").collect()
+}
+
+fn get_header(version: i32, format: &str) -> String {
+    format!(
+        "Generated by Gruxi {} from configuration version {}, as an approximate {}.\n\
+         Review it before use: settings without an equivalent are written as comments, and request handlers are\n\
+         tried in order by Gruxi, where the server may pick the most specific match instead.",
+        env!("CARGO_PKG_VERSION"),
+        version,
+        format
+    )
+}
+
+//
+// nginx
+//
+
+pub fn render_nginx(configuration: &Configuration) -> String {
+    let context = ExportContext::new(configuration);
+    let mut writer = ConfigWriter::new(&format!("{}\nInclude this file in the http block of nginx.conf.", get_header(configuration.version, "nginx.conf")));
+
+    writer.line(format!("client_max_body_size {};", configuration.core.server_settings.max_body_size));
+    if configuration.core.gzip.is_enabled {
+        writer.line("gzip on;");
+        let content_types: Vec<String> = configuration
+            .core
+            .gzip
+            .compressible_content_types
+            .iter()
+            .filter(|t| t.contains('/') && !t.ends_with('/'))
+            .map(|t| quote(t))
+            .collect();
+        if !content_types.is_empty() {
+            writer.line(format!("gzip_types {};", content_types.join(" ")));
+        }
+    }
+    writer.blank();
+
+    // Keep WebSocket upgrades working through proxied locations
+    writer.open("map $http_upgrade $connection_upgrade");
+    writer.line("default upgrade;");
+    writer.line("'' close;");
+    writer.close();
+    writer.blank();
+
+    for processor in &configuration.proxy_processors {
+        if processor.upstream_servers.len() > 1 {
+            writer.open(format!("upstream {}", get_upstream_name(processor)));
+            if processor.load_balancing_strategy != "round_robin" && !processor.load_balancing_strategy.is_empty() {
+                writer.comment(format!("Load balancing strategy in Gruxi: {}", processor.load_balancing_strategy));
+            }
+            for server in &processor.upstream_servers {
+                writer.line(format!("server {};", quote(get_upstream_address(server))));
+            }
+            writer.close();
+            writer.blank();
+        }
+    }
+
+    for site in context.get_exported_sites() {
+        let bindings = context.get_site_bindings(site);
+        if bindings.is_empty() {
+            writer.comment(format!("Site {} is not on any binding", site.id));
+            writer.blank();
+            continue;
+        }
+
+        writer.open("server");
+        writer.comment(format!("Site {}", site.id));
+        for binding in &bindings {
+            let mut listen = format!("listen {}", get_listen_address(binding));
+            if binding.is_tls {
+                listen.push_str(" ssl");
+            }
+            // The default site of the binding, when it serves unknown hostnames
+            let is_binding_default = if binding.default_site_id.is_empty() {
+                site.is_default
+            } else {
+                binding.default_site_id == site.id
+            };
+            if binding.unknown_host_policy == "default" && is_binding_default {
+                listen.push_str(" default_server");
+            }
+            writer.line(format!("{};", listen));
+        }
+        let hostnames = get_hostnames(site);
+        let server_names: Vec<String> = if hostnames.is_empty() {
+            vec!["_".to_string()]
+        } else {
+            hostnames.iter().map(|h| quote(h)).collect()
+        };
+        writer.line(format!("server_name {};", server_names.join(" ")));
+
+        if bindings.iter().any(|binding| binding.is_tls) {
+            // nginx enables HTTP/2 per server, not per listen address
+            if bindings.iter().any(|binding| binding.is_tls && !binding.is_http1_only()) {
+                writer.line("http2 on;");
+            }
+            if !site.tls_cert_path.is_empty() && !site.tls_key_path.is_empty() {
+                writer.line(format!("ssl_certificate {};", quote(&site.tls_cert_path)));
+                writer.line(format!("ssl_certificate_key {};", quote(&site.tls_key_path)));
+            } else if site.tls_automatic_enabled {
+                writer.comment("Certificate from ACME in Gruxi, use certbot or acme.sh for ssl_certificate and ssl_certificate_key");
+            } else if !site.tls_cert_content.is_empty() {
+                writer.comment("Certificate stored in the Gruxi configuration, save it to files for ssl_certificate and ssl_certificate_key");
+            } else {
+                writer.comment("No certificate, Gruxi serves a self-signed one");
+            }
+        }
+        if site.access_log_enabled && !site.access_log_file.is_empty() {
+            writer.line(format!("access_log {};", quote(&site.access_log_file)));
+        }
+        for header in &site.extra_headers {
+            writer.line(format!("add_header {} {} always;", quote(&header.key), quote(&header.value)));
+        }
+        if !site.rewrite_functions.is_empty() {
+            writer.comment(format!("Gruxi rewrite functions: {}", site.rewrite_functions.join(", ")));
+        }
+
+        let mut rendered_locations = HashSet::new();
+        for location in site.locations.iter().filter(|location| location.is_enabled) {
+            let header = match location.match_type.as_str() {
+                "regex" => format!("location ~ {}", quote(&location.pattern)),
+                _ => format!("location ^~ {}", quote(&location.pattern)),
+            };
+            if !rendered_locations.insert(header.clone()) {
+                writer.comment(format!("Location '{}' is already matched above", location.name));
+                continue;
+            }
+            writer.blank();
+            writer.open(header);
+            writer.comment(format!("Location '{}'", location.name));
+            render_nginx_location_settings(&mut writer, location);
+            match context.get_handlers(&location.request_handlers).first() {
+                Some(handler) => render_nginx_backend(&mut writer, &context.get_backend(handler), handler),
+                None => writer.comment("Served by the request handlers of the site"),
+            }
+            writer.close();
+        }
+
+        for handler in context.get_handlers(&site.request_handlers) {
+            for pattern in &handler.url_match {
+                let header = UrlPattern::parse(pattern).nginx_location();
+                if !rendered_locations.insert(header.clone()) {
+                    writer.comment(format!("'{}' of request handler '{}' is already matched by an earlier handler", pattern, handler.name));
+                    continue;
+                }
+                writer.blank();
+                writer.open(header);
+                writer.comment(format!("Request handler '{}'", handler.name));
+                render_nginx_backend(&mut writer, &context.get_backend(handler), handler);
+                writer.close();
+            }
+        }
+        writer.close();
+        writer.blank();
+    }
+
+    writer.text
+}
+
+fn get_listen_address(binding: &Binding) -> String {
+    match binding.ip.as_str() {
+        "0.0.0.0" | "" => binding.port.to_string(),
+        ip if ip.contains(':') => format!("[{}]:{}", ip, binding.port),
+        ip => format!("{}:{}", ip, binding.port),
+    }
+}
+
+fn get_upstream_name(processor: &ProxyProcessor) -> String {
+    let id: String = processor.id.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    format!("gruxi_proxy_{}", id)
+}
+
+// "host:port" of an upstream URL such as "http://server1:8080/"
+fn get_upstream_address(server: &str) -> &str {
+    let without_scheme = server.split_once("://").map(|(_, rest)| rest).unwrap_or(server);
+    without_scheme.split('/').next().unwrap_or(without_scheme)
+}
+
+fn render_nginx_location_settings(writer: &mut ConfigWriter, location: &Location) {
+    for header in &location.extra_headers {
+        writer.line(format!("add_header {} {} always;", quote(&header.key), quote(&header.value)));
+    }
+    if !location.cache_control.is_empty() {
+        writer.line(format!("add_header Cache-Control {} always;", quote(&location.cache_control)));
+    }
+    if !location.auth_users.is_empty() || !location.auth_provider_id.is_empty() {
+        writer.line(format!("auth_basic {};", quote(if location.auth_realm.is_empty() { "Restricted" } else { &location.auth_realm })));
+        if location.auth_provider_id.is_empty() {
+            let usernames: Vec<&str> = location.auth_users.iter().map(|user| user.username.as_str()).collect();
+            writer.comment(format!("Users in Gruxi, with bcrypt password hashes: {}", usernames.join(", ")));
+        } else {
+            writer.comment(format!("Users are verified by auth provider {} in Gruxi, such as with auth_request", location.auth_provider_id));
+        }
+        writer.line("auth_basic_user_file /etc/nginx/htpasswd;");
+    }
+}
+
+fn render_nginx_backend(writer: &mut ConfigWriter, backend: &Backend, handler: &RequestHandler) {
+    match backend {
+        Backend::Static(processor) => {
+            writer.line(format!("root {};", quote(&processor.web_root)));
+            if !processor.web_root_index_file_list.is_empty() {
+                let index_files: Vec<String> = processor.web_root_index_file_list.iter().map(|f| quote(f)).collect();
+                writer.line(format!("index {};", index_files.join(" ")));
+            }
+            writer.line("try_files $uri $uri/ =404;");
+        }
+        Backend::Php(processor, address) => {
+            writer.line(format!("root {};", quote(&processor.local_web_root)));
+            writer.line("include fastcgi_params;");
+            let script_root = match processor.served_by_type.as_str() {
+                "php-fpm" if processor.fastcgi_web_root.is_empty() => "$document_root",
+                "php-fpm" => processor.fastcgi_web_root.as_str(),
+                _ => {
+                    writer.comment("PHP-CGI handler of Gruxi, as a PHP-FPM service of the docker-compose export");
+                    PHP_FPM_SERVICE_WEB_ROOT
+                }
+            };
+            writer.line(format!("fastcgi_param SCRIPT_FILENAME {};", quote(&format!("{}$fastcgi_script_name", script_root))));
+            writer.line(format!("fastcgi_pass {};", quote(address)));
+            writer.line(format!("fastcgi_read_timeout {}s;", processor.request_timeout));
+        }
+        Backend::Proxy(processor) => render_nginx_proxy(writer, processor),
+        Backend::WebDav(processor) => {
+            writer.line(format!("root {};", quote(&processor.web_root)));
+            if processor.read_only {
+                writer.open("limit_except GET HEAD OPTIONS PROPFIND");
+                writer.line("deny all;");
+                writer.close();
+            } else {
+                writer.line("dav_methods PUT DELETE MKCOL COPY MOVE;");
+                writer.line("create_full_put_path on;");
+            }
+            writer.comment("PROPFIND and OPTIONS need the nginx-dav-ext-module");
+            writer.line("dav_ext_methods PROPFIND OPTIONS;");
+            if processor.require_authentication {
+                writer.line(format!("auth_basic {};", quote(if processor.auth_realm.is_empty() { "WebDAV" } else { &processor.auth_realm })));
+                let usernames: Vec<&str> = processor.auth_users.iter().map(|user| user.username.as_str()).collect();
+                writer.comment(format!("Users in Gruxi, with bcrypt password hashes: {}", usernames.join(", ")));
+                writer.line("auth_basic_user_file /etc/nginx/htpasswd;");
+            }
+        }
+        Backend::Cgi(processor) => {
+            writer.comment("nginx does not run CGI scripts itself, they are run through fcgiwrap");
+            for interpreter in &processor.interpreters {
+                writer.comment(format!("Scripts ending in {} are run with {}", interpreter.extension, interpreter.executable));
+            }
+            writer.line("include fastcgi_params;");
+            let script_path = match processor.url_prefix.as_str() {
+                "" => "$fastcgi_script_name".to_string(),
+                _ => "$cgi_script".to_string(),
+            };
+            if !processor.url_prefix.is_empty() {
+                writer.line("set $cgi_script $fastcgi_script_name;");
+                writer.open(format!("if ($fastcgi_script_name ~ {})", quote(&format!("^{}(/.*)$", regex::escape(&processor.url_prefix)))));
+                writer.line("set $cgi_script $1;");
+                writer.close();
+            }
+            writer.line(format!("fastcgi_param SCRIPT_FILENAME {};", quote(&format!("{}{}", processor.cgi_bin_dir, script_path))));
+            writer.line("fastcgi_pass unix:/run/fcgiwrap.socket;");
+            writer.line(format!("fastcgi_read_timeout {}s;", processor.request_timeout));
+        }
+        Backend::Image(processor) => {
+            writer.comment("Images are served as they are, resizing needs the ngx_http_image_filter_module");
+            writer.line(format!("root {};", quote(&processor.web_root)));
+            writer.line("try_files $uri =404;");
+        }
+        Backend::Service(service_name, port, preserve_host_header) => {
+            writer.line(format!("proxy_pass http://{}:{};", service_name, port));
+            if *preserve_host_header {
+                writer.line("proxy_set_header Host $host;");
+            }
+            writer.line("proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;");
+            writer.line("proxy_set_header X-Forwarded-Proto $scheme;");
+        }
+        Backend::Missing => writer.comment(format!("Processor {} of type '{}' was not found", handler.processor_id, handler.processor_type)),
+    }
+}
+
+fn render_nginx_proxy(writer: &mut ConfigWriter, processor: &ProxyProcessor) {
+    let Some(first_server) = processor.upstream_servers.first() else {
+        writer.comment("Proxy without upstream servers");
+        writer.line("return 502;");
+        return;
+    };
+    let scheme = if first_server.starts_with("https://") { "https" } else { "http" };
+    if processor.upstream_servers.len() > 1 {
+        writer.line(format!("proxy_pass {}://{};", scheme, get_upstream_name(processor)));
+    } else {
+        writer.line(format!("proxy_pass {};", quote(first_server)));
+    }
+
+    writer.line("proxy_http_version 1.1;");
+    writer.line("proxy_set_header Upgrade $http_upgrade;");
+    writer.line("proxy_set_header Connection $connection_upgrade;");
+    if !processor.forced_host_header.is_empty() {
+        writer.line(format!("proxy_set_header Host {};", quote(&processor.forced_host_header)));
+    } else if processor.preserve_host_header {
+        writer.line("proxy_set_header Host $host;");
+    }
+    writer.line("proxy_set_header X-Forwarded-For $proxy_add_x_forwarded_for;");
+    writer.line("proxy_set_header X-Forwarded-Proto $scheme;");
+    for header in processor.request_headers_denied.iter().filter(|header| !header.ends_with('*')) {
+        writer.line(format!("proxy_set_header {} \"\";", header));
+    }
+    for header in processor.response_headers_denied.iter().filter(|header| !header.ends_with('*')) {
+        writer.line(format!("proxy_hide_header {};", header));
+    }
+    if !processor.request_headers_allowed.is_empty()
+        || !processor.response_headers_allowed.is_empty()
+        || processor.request_headers_denied.iter().chain(&processor.response_headers_denied).any(|header| header.ends_with('*'))
+    {
+        writer.comment("Gruxi also filters headers by allow lists and prefixes, which nginx has no equivalent for");
+    }
+
+    if processor.connect_timeout_seconds > 0 {
+        writer.line(format!("proxy_connect_timeout {}s;", processor.connect_timeout_seconds));
+    }
+    writer.line(format!("proxy_read_timeout {}s;", processor.timeout_seconds));
+    if processor.total_timeout_seconds > 0 {
+        writer.comment(format!("Gruxi limits the whole request to {} seconds", processor.total_timeout_seconds));
+    }
+    if processor.max_request_body_bytes > 0 {
+        writer.line(format!("client_max_body_size {};", processor.max_request_body_bytes));
+    }
+    if processor.pool_max_idle_per_host > 0 && processor.upstream_servers.len() > 1 {
+        writer.comment(format!(
+            "Add 'keepalive {};' to the upstream block to keep idle connections like Gruxi",
+            processor.pool_max_idle_per_host
+        ));
+    }
+
+    if scheme == "https" {
+        if processor.verify_tls_certificates {
+            writer.line("proxy_ssl_verify on;");
+            let ca_bundle = if processor.tls_ca_bundle_path.is_empty() {
+                "/etc/ssl/certs/ca-certificates.crt"
+            } else {
+                processor.tls_ca_bundle_path.as_str()
+            };
+            writer.line(format!("proxy_ssl_trusted_certificate {};", quote(ca_bundle)));
+        }
+        writer.line("proxy_ssl_server_name on;");
+        if !processor.tls_server_name.is_empty() {
+            writer.line(format!("proxy_ssl_name {};", quote(&processor.tls_server_name)));
+        }
+        if !processor.tls_client_cert_path.is_empty() {
+            writer.line(format!("proxy_ssl_certificate {};", quote(&processor.tls_client_cert_path)));
+            writer.line(format!("proxy_ssl_certificate_key {};", quote(&processor.tls_client_key_path)));
+        }
+    }
+
+    if processor.upstream_http2_only {
+        writer.comment("Gruxi speaks only HTTP/2 to these upstreams, nginx proxies HTTP/1.1, use grpc_pass for gRPC");
+    }
+    if !processor.streaming_paths.is_empty() {
+        writer.comment(format!("Streamed without buffering in Gruxi, add 'proxy_buffering off;' for: {}", processor.streaming_paths.join(", ")));
+    }
+    if !processor.health_check_path.is_empty() {
+        writer.comment(format!(
+            "Gruxi checks the health of the upstreams on {} every {} seconds, nginx only marks failed servers",
+            processor.health_check_path, processor.health_check_interval_seconds
+        ));
+    }
+    for rewrite in &processor.url_rewrites {
+        writer.comment(format!("Gruxi rewrites '{}' to '{}' in the URL", rewrite.from, rewrite.to));
+    }
+    if !processor.upstream_groups.is_empty() {
+        writer.comment("Gruxi splits the traffic over weighted upstream groups, such as with split_clients in nginx");
+    }
+}
+
+//
+// Caddy
+//
+
+pub fn render_caddyfile(configuration: &Configuration) -> String {
+    let context = ExportContext::new(configuration);
+    let mut writer = ConfigWriter::new(&get_header(configuration.version, "Caddyfile"));
+
+    for site in context.get_exported_sites() {
+        let bindings = context.get_site_bindings(site);
+        if bindings.is_empty() {
+            writer.comment(format!("Site {} is not on any binding", site.id));
+            writer.blank();
+            continue;
+        }
+
+        let hostnames = get_hostnames(site);
+        let mut addresses = Vec::new();
+        for binding in &bindings {
+            let scheme = if binding.is_tls { "https" } else { "http" };
+            let default_port = if binding.is_tls { 443 } else { 80 };
+            let port = if binding.port == default_port { String::new() } else { format!(":{}", binding.port) };
+            if hostnames.is_empty() {
+                addresses.push(format!("{}://{}", scheme, if port.is_empty() { format!(":{}", binding.port) } else { port.clone() }));
+            } else {
+                for hostname in &hostnames {
+                    addresses.push(format!("{}://{}{}", scheme, hostname, port));
+                }
+            }
+        }
+        addresses.dedup();
+
+        writer.open(addresses.join(", "));
+        writer.comment(format!("Site {}", site.id));
+        let bind_addresses: Vec<&str> = bindings.iter().map(|binding| binding.ip.as_str()).filter(|ip| !ip.is_empty() && *ip != "0.0.0.0").collect();
+        if bind_addresses.len() == bindings.len() {
+            writer.line(format!("bind {}", bind_addresses.join(" ")));
+        }
+
+        if bindings.iter().any(|binding| binding.is_tls) {
+            if !site.tls_cert_path.is_empty() && !site.tls_key_path.is_empty() {
+                writer.line(format!("tls {} {}", quote(&site.tls_cert_path), quote(&site.tls_key_path)));
+            } else if site.tls_automatic_enabled {
+                writer.comment("Certificate from ACME, which Caddy does automatically");
+            } else if !site.tls_cert_content.is_empty() {
+                writer.comment("Certificate stored in the Gruxi configuration, save it to files for the tls directive");
+            } else {
+                writer.line("tls internal");
+            }
+        }
+        writer.open("request_body");
+        writer.line(format!("max_size {}", configuration.core.server_settings.max_body_size));
+        writer.close();
+        if configuration.core.gzip.is_enabled {
+            writer.line("encode gzip");
+        }
+        if site.access_log_enabled && !site.access_log_file.is_empty() {
+            writer.open("log");
+            writer.line(format!("output file {}", quote(&site.access_log_file)));
+            writer.close();
+        }
+        for header in &site.extra_headers {
+            writer.line(format!("header {} {}", quote(&header.key), quote(&header.value)));
+        }
+        if !site.rewrite_functions.is_empty() {
+            writer.comment(format!("Gruxi rewrite functions: {}", site.rewrite_functions.join(", ")));
+        }
+
+        // Handle blocks in a route are tried in the order they are written, like request handlers in Gruxi
+        writer.open("route");
+        let mut matcher_index = 0;
+        for location in site.locations.iter().filter(|location| location.is_enabled) {
+            matcher_index += 1;
+            let matcher = match location.match_type.as_str() {
+                "regex" => format!("path_regexp {}", quote(&location.pattern)),
+                _ => format!("path {}", quote(&format!("{}*", location.pattern))),
+            };
+            writer.line(format!("@match{} {}", matcher_index, matcher));
+            writer.open(format!("handle @match{}", matcher_index));
+            writer.comment(format!("Location '{}'", location.name));
+            render_caddy_location_settings(&mut writer, location);
+            let handlers = context.get_handlers(if location.request_handlers.is_empty() {
+                &site.request_handlers
+            } else {
+                &location.request_handlers
+            });
+            match handlers.first() {
+                Some(handler) => render_caddy_backend(&mut writer, &context.get_backend(handler), handler),
+                None => writer.comment("No request handler"),
+            }
+            writer.close();
+        }
+        for handler in context.get_handlers(&site.request_handlers) {
+            let patterns: Vec<UrlPattern> = handler.url_match.iter().map(|pattern| UrlPattern::parse(pattern)).collect();
+            let matchers: Vec<String> = patterns.iter().filter_map(UrlPattern::caddy_matcher).collect();
+            if patterns.contains(&UrlPattern::All) || matchers.is_empty() {
+                writer.open("handle");
+            } else {
+                matcher_index += 1;
+                writer.open(format!("@match{}", matcher_index));
+                for matcher in &matchers {
+                    writer.line(matcher);
+                }
+                writer.close();
+                writer.open(format!("handle @match{}", matcher_index));
+            }
+            writer.comment(format!("Request handler '{}'", handler.name));
+            render_caddy_backend(&mut writer, &context.get_backend(handler), handler);
+            writer.close();
+        }
+        writer.close();
+        writer.close();
+        writer.blank();
+    }
+
+    writer.text
+}
+
+fn render_caddy_location_settings(writer: &mut ConfigWriter, location: &Location) {
+    for header in &location.extra_headers {
+        writer.line(format!("header {} {}", quote(&header.key), quote(&header.value)));
+    }
+    if !location.cache_control.is_empty() {
+        writer.line(format!("header Cache-Control {}", quote(&location.cache_control)));
+    }
+    if !location.auth_provider_id.is_empty() {
+        writer.comment(format!("Users are verified by auth provider {} in Gruxi, such as with forward_auth", location.auth_provider_id));
+    } else if !location.auth_users.is_empty() {
+        render_caddy_basic_auth(writer, &location.auth_realm, location.auth_users.iter().map(|user| (user.username.as_str(), user.password.as_str())));
+    }
+}
+
+// Caddy verifies the bcrypt hashes Gruxi keeps for basic authentication as they are
+fn render_caddy_basic_auth<'a>(writer: &mut ConfigWriter, realm: &str, users: impl Iterator<Item = (&'a str, &'a str)>) {
+    writer.open(if realm.is_empty() {
+        "basic_auth".to_string()
+    } else {
+        format!("basic_auth bcrypt {}", quote(realm))
+    });
+    for (username, password_hash) in users {
+        writer.line(format!("{} {}", quote(username), quote(password_hash)));
+    }
+    writer.close();
+}
+
+fn render_caddy_backend(writer: &mut ConfigWriter, backend: &Backend, handler: &RequestHandler) {
+    match backend {
+        Backend::Static(processor) => {
+            writer.line(format!("root * {}", quote(&processor.web_root)));
+            if processor.web_root_index_file_list.is_empty() {
+                writer.line("file_server");
+            } else {
+                writer.open("file_server");
+                let index_files: Vec<String> = processor.web_root_index_file_list.iter().map(|f| quote(f)).collect();
+                writer.line(format!("index {}", index_files.join(" ")));
+                writer.close();
+            }
+        }
+        Backend::Php(processor, address) => {
+            writer.line(format!("root * {}", quote(&processor.local_web_root)));
+            let address = match address.strip_prefix("unix:") {
+                Some(socket_path) => format!("unix/{}", socket_path),
+                None => address.clone(),
+            };
+            writer.open(format!("php_fastcgi {}", quote(&address)));
+            match processor.served_by_type.as_str() {
+                "php-fpm" if !processor.fastcgi_web_root.is_empty() => writer.line(format!("root {}", quote(&processor.fastcgi_web_root))),
+                "php-fpm" => {}
+                _ => {
+                    writer.comment("PHP-CGI handler of Gruxi, as a PHP-FPM service of the docker-compose export");
+                    writer.line(format!("root {}", PHP_FPM_SERVICE_WEB_ROOT));
+                }
+            }
+            writer.line(format!("read_timeout {}s", processor.request_timeout));
+            writer.close();
+        }
+        Backend::Proxy(processor) => render_caddy_proxy(writer, processor),
+        Backend::WebDav(processor) => {
+            writer.comment("Needs Caddy built with the github.com/mholt/caddy-webdav module");
+            if processor.require_authentication {
+                render_caddy_basic_auth(writer, &processor.auth_realm, processor.auth_users.iter().map(|user| (user.username.as_str(), user.password.as_str())));
+            }
+            if processor.read_only {
+                writer.line("@writes not method GET HEAD OPTIONS PROPFIND");
+                writer.line("respond @writes 405");
+            }
+            writer.open("webdav");
+            writer.line(format!("root {}", quote(&processor.web_root)));
+            writer.close();
+        }
+        Backend::Cgi(processor) => {
+            writer.comment("Needs Caddy built with the github.com/aksdb/caddy-cgi module");
+            for interpreter in &processor.interpreters {
+                writer.comment(format!("Scripts ending in {} are run with {}", interpreter.extension, interpreter.executable));
+            }
+            let prefix = if processor.url_prefix.is_empty() { "" } else { processor.url_prefix.as_str() };
+            writer.open(format!("cgi {}/* {}/{{path}}", prefix, quote(&processor.cgi_bin_dir)));
+            writer.line(format!("script_name {}", if prefix.is_empty() { "/" } else { prefix }));
+            writer.close();
+        }
+        Backend::Image(processor) => {
+            writer.comment("Images are served as they are, resizing needs a Caddy image processing module");
+            writer.line(format!("root * {}", quote(&processor.web_root)));
+            writer.line("file_server");
+        }
+        Backend::Service(service_name, port, preserve_host_header) => {
+            if *preserve_host_header {
+                writer.line(format!("reverse_proxy {}:{}", service_name, port));
+            } else {
+                writer.open(format!("reverse_proxy {}:{}", service_name, port));
+                writer.line("header_up Host {upstream_hostport}");
+                writer.close();
+            }
+        }
+        Backend::Missing => writer.comment(format!("Processor {} of type '{}' was not found", handler.processor_id, handler.processor_type)),
+    }
+}
+
+fn render_caddy_proxy(writer: &mut ConfigWriter, processor: &ProxyProcessor) {
+    if processor.upstream_servers.is_empty() {
+        writer.comment("Proxy without upstream servers");
+        writer.line("respond 502");
+        return;
+    }
+    for rewrite in &processor.url_rewrites {
+        writer.comment(format!("Gruxi rewrites '{}' to '{}' in the URL", rewrite.from, rewrite.to));
+    }
+    if !processor.upstream_groups.is_empty() {
+        writer.comment("Gruxi splits the traffic over weighted upstream groups, such as with lb_policy weighted_round_robin in Caddy");
+    }
+
+    // Upstream URLs with a path are not supported by Caddy, only scheme, host and port
+    let upstreams: Vec<String> = processor
+        .upstream_servers
+        .iter()
+        .map(|server| {
+            let scheme = if server.starts_with("https://") { "https://" } else { "" };
+            quote(&format!("{}{}", scheme, get_upstream_address(server)))
+        })
+        .collect();
+    writer.open(format!("reverse_proxy {}", upstreams.join(" ")));
+    if processor.upstream_servers.len() > 1 {
+        let policy = match processor.load_balancing_strategy.as_str() {
+            "" => "round_robin",
+            strategy => strategy,
+        };
+        writer.line(format!("lb_policy {}", policy));
+    }
+    if !processor.health_check_path.is_empty() {
+        writer.line(format!("health_uri {}", quote(&processor.health_check_path)));
+        writer.line(format!("health_interval {}s", processor.health_check_interval_seconds));
+        writer.line(format!("health_timeout {}s", processor.health_check_timeout_seconds));
+    }
+    if !processor.forced_host_header.is_empty() {
+        writer.line(format!("header_up Host {}", quote(&processor.forced_host_header)));
+    } else if !processor.preserve_host_header {
+        writer.line("header_up Host {upstream_hostport}");
+    }
+    if !processor.streaming_paths.is_empty() {
+        writer.comment(format!("Streamed without buffering in Gruxi: {}", processor.streaming_paths.join(", ")));
+        writer.line("flush_interval -1");
+    }
+    if processor.total_timeout_seconds > 0 {
+        writer.comment(format!("Gruxi limits the whole request to {} seconds", processor.total_timeout_seconds));
+    }
+
+    writer.open("transport http");
+    if processor.connect_timeout_seconds > 0 {
+        writer.line(format!("dial_timeout {}s", processor.connect_timeout_seconds));
+    }
+    writer.line(format!("response_header_timeout {}s", processor.timeout_seconds));
+    if processor.pool_idle_timeout_seconds > 0 {
+        writer.line(format!("keepalive {}s", processor.pool_idle_timeout_seconds));
+    }
+    if processor.pool_max_idle_per_host > 0 {
+        writer.line(format!("keepalive_idle_conns_per_host {}", processor.pool_max_idle_per_host));
+    }
+    if processor.upstream_http2_only {
+        writer.line("versions h2c 2");
+    }
+    if processor.upstream_servers.iter().any(|server| server.starts_with("https://")) {
+        if !processor.verify_tls_certificates {
+            writer.line("tls_insecure_skip_verify");
+        }
+        if !processor.tls_ca_bundle_path.is_empty() {
+            writer.line(format!("tls_trust_pool file {}", quote(&processor.tls_ca_bundle_path)));
+        }
+        if !processor.tls_server_name.is_empty() {
+            writer.line(format!("tls_server_name {}", quote(&processor.tls_server_name)));
+        }
+        if !processor.tls_client_cert_path.is_empty() {
+            writer.line(format!("tls_client_auth {} {}", quote(&processor.tls_client_cert_path), quote(&processor.tls_client_key_path)));
+        }
+    }
+    writer.close();
+    writer.close();
+    if processor.max_request_body_bytes > 0 {
+        writer.comment(format!("Gruxi forwards request bodies up to {} bytes to these upstreams", processor.max_request_body_bytes));
+    }
+}
+
+//
+// docker-compose
+//
+
+pub fn render_docker_compose(configuration: &Configuration) -> String {
+    let context = ExportContext::new(configuration);
+    let mut writer = ConfigWriter::new(&format!(
+        "{}\nServices for the PHP-CGI, Python and Node.js handlers, which the nginx and Caddy exports refer to by service name.\n\
+         Build images with the dependencies of the applications, the images below are only a starting point.",
+        get_header(configuration.version, "docker-compose.yml")
+    ));
+
+    if configuration.php_cgi_handlers.is_empty() && configuration.python_handlers.is_empty() && configuration.node_handlers.is_empty() {
+        writer.line("services: {}");
+        return writer.text;
+    }
+
+    writer.line("services:");
+    writer.depth += 1;
+    for handler in &configuration.php_cgi_handlers {
+        writer.line(format!("{}:", context.get_service_name(&handler.id)));
+        writer.depth += 1;
+        writer.comment(format!("PHP-CGI handler '{}' ({}), as PHP-FPM", handler.name, handler.executable));
+        writer.line("image: php:fpm");
+        let web_roots: HashSet<&str> = configuration
+            .php_processors
+            .iter()
+            .filter(|processor| processor.php_cgi_handler_id == handler.id && !processor.local_web_root.is_empty())
+            .map(|processor| processor.local_web_root.as_str())
+            .collect();
+        let mut web_roots: Vec<&str> = web_roots.into_iter().collect();
+        web_roots.sort();
+        if let Some(web_root) = web_roots.first() {
+            writer.line("volumes:");
+            writer.line(format!("  - {}", yaml_string(&format!("{}:{}", web_root, PHP_FPM_SERVICE_WEB_ROOT))));
+        }
+        if web_roots.len() > 1 {
+            writer.comment(format!("Also used for the web roots {}, which need services of their own", web_roots[1..].join(", ")));
+        }
+        writer.line("expose:");
+        writer.line(format!("  - \"{}\"", PHP_FPM_SERVICE_PORT));
+        writer.line("restart: unless-stopped");
+        writer.depth -= 1;
+    }
+
+    for handler in &configuration.python_handlers {
+        writer.line(format!("{}:", context.get_service_name(&handler.id)));
+        writer.depth += 1;
+        writer.comment(format!("Python handler '{}'", handler.name));
+        writer.line("image: python:3-slim");
+        render_compose_app_directory(&mut writer, &handler.working_directory);
+        let executable = get_executable_name(&handler.executable, &handler.server_type);
+        let command: Vec<String> = std::iter::once(executable)
+            .chain(handler.get_arguments("0.0.0.0", PYTHON_SERVICE_PORT))
+            .map(|argument| yaml_string(&argument))
+            .collect();
+        writer.line(format!("command: [{}]", command.join(", ")));
+        render_compose_environment(&mut writer, handler.extra_environment.iter().map(|variable| (variable.key.as_str(), variable.value.as_str())));
+        writer.line("expose:");
+        writer.line(format!("  - \"{}\"", PYTHON_SERVICE_PORT));
+        writer.line("restart: unless-stopped");
+        writer.depth -= 1;
+    }
+
+    for handler in &configuration.node_handlers {
+        writer.line(format!("{}:", context.get_service_name(&handler.id)));
+        writer.depth += 1;
+        writer.comment(format!("Node.js handler '{}'", handler.name));
+        writer.line("image: node:lts-slim");
+        render_compose_app_directory(&mut writer, &handler.working_directory);
+        let executable = get_executable_name(&handler.executable, "node");
+        let command: Vec<String> = std::iter::once(executable).chain(handler.get_arguments()).map(|argument| yaml_string(&argument)).collect();
+        writer.line(format!("command: [{}]", command.join(", ")));
+        let port = NODE_SERVICE_PORT.to_string();
+        let environment = std::iter::once(("PORT", port.as_str())).chain(handler.extra_environment.iter().map(|variable| (variable.key.as_str(), variable.value.as_str())));
+        render_compose_environment(&mut writer, environment);
+        writer.line("expose:");
+        writer.line(format!("  - \"{}\"", NODE_SERVICE_PORT));
+        writer.line("restart: unless-stopped");
+        writer.depth -= 1;
+    }
+    writer.depth -= 1;
+
+    writer.text
+}
+
+// The application is mounted at /app, where it is started
+fn render_compose_app_directory(writer: &mut ConfigWriter, working_directory: &str) {
+    writer.line("working_dir: /app");
+    if !working_directory.is_empty() {
+        writer.line("volumes:");
+        writer.line(format!("  - {}", yaml_string(&format!("{}:/app", working_directory))));
+    }
+}
+
+fn render_compose_environment<'a>(writer: &mut ConfigWriter, variables: impl Iterator<Item = (&'a str, &'a str)>) {
+    let variables: Vec<(&str, &str)> = variables.collect();
+    if variables.is_empty() {
+        return;
+    }
+    writer.line("environment:");
+    for (key, value) in variables {
+        writer.line(format!("  {}: {}", yaml_string(key), yaml_string(value)));
+    }
+}
+
+// Executables are found in the PATH of the image, as paths on the host, such as in a virtualenv, do not exist there
+fn get_executable_name(executable: &str, default: &str) -> String {
+    let name = executable.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name = name.strip_suffix(".exe").unwrap_or(name);
+    if name.is_empty() { default.to_string() } else { name.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::site::HeaderKV;
+    use crate::external_connections::managed_system::python_app::PythonApp;
+    use crate::http::request_handlers::processors::python_processor::PythonProcessor;
+
+    fn create_configuration() -> Configuration {
+        let mut configuration = Configuration::get_default();
+        configuration.sites[0].hostnames = vec!["example.com".to_string(), "www.example.com".to_string()];
+        configuration.sites[0].extra_headers = vec![HeaderKV {
+            key: "X-Frame-Options".to_string(),
+            value: "SAMEORIGIN".to_string(),
+        }];
+
+        let mut python_app = PythonApp::new();
+        python_app.name = "Shop API".to_string();
+        python_app.executable = "/srv/shop/venv/bin/uvicorn".to_string();
+        python_app.app_module = "main:app".to_string();
+        python_app.working_directory = "/srv/shop".to_string();
+        let mut python_processor = PythonProcessor::new();
+        python_processor.python_handler_id = python_app.id.clone();
+        let mut handler = RequestHandler::new();
+        handler.name = "API".to_string();
+        handler.processor_type = "python".to_string();
+        handler.processor_id = python_processor.id.clone();
+        handler.url_match = vec!["/api*".to_string()];
+
+        // The API handler is tried before the static files
+        configuration.sites[0].request_handlers.insert(0, handler.id.clone());
+        configuration.request_handlers.push(handler);
+        configuration.python_processors.push(python_processor);
+        configuration.python_handlers.push(python_app);
+        configuration
+    }
+
+    #[test]
+    fn test_render_nginx() {
+        let nginx = render_nginx(&create_configuration());
+        assert!(nginx.contains("    listen 80 default_server;\n    listen 443 ssl default_server;\n    server_name example.com www.example.com;\n"));
+        assert!(nginx.contains("    add_header X-Frame-Options SAMEORIGIN always;\n"));
+        assert!(nginx.contains("    location ^~ /api {\n        # Request handler 'API'\n        proxy_pass http://python-shop-api:8000;\n"));
+        assert!(nginx.contains("    location / {\n        # Request handler 'Static File Handler'\n        root ./www-default;\n        index index.html;\n"));
+    }
+
+    #[test]
+    fn test_render_caddyfile() {
+        let caddyfile = render_caddyfile(&create_configuration());
+        assert!(caddyfile.contains("http://example.com, http://www.example.com, https://example.com, https://www.example.com {\n"));
+        assert!(caddyfile.contains("        @match1 {\n            path /api*\n        }\n        handle @match1 {\n"));
+        assert!(caddyfile.contains("            # Request handler 'API'\n            reverse_proxy python-shop-api:8000\n"));
+        assert!(caddyfile.contains("        handle {\n            # Request handler 'Static File Handler'\n            root * ./www-default\n"));
+    }
+
+    #[test]
+    fn test_render_docker_compose() {
+        let docker_compose = render_docker_compose(&create_configuration());
+        assert!(docker_compose.contains("services:\n    python-shop-api:\n"));
+        assert!(docker_compose.contains("        volumes:\n          - \"/srv/shop:/app\"\n"));
+        assert!(docker_compose.contains("    command: [\"uvicorn\", \"main:app\", \"--host\", \"0.0.0.0\", \"--port\", \"8000\", \"--workers\", \"1\"]\n"));
+    }
+
+    #[test]
+    fn test_url_patterns_and_quoting() {
+        assert_eq!(UrlPattern::parse("*").nginx_location(), "location /");
+        assert_eq!(UrlPattern::parse("*.PHP").nginx_location(), "location ~* \\.php$");
+        assert_eq!(UrlPattern::parse("/admin/*").caddy_matcher(), Some("path /admin/*".to_string()));
+        assert_eq!(UrlPattern::parse("/robots.txt").nginx_location(), "location = /robots.txt");
+        assert_eq!(quote("C:\\Program Files\\site"), "\"C:\\Program Files\\site\"");
+        assert_eq!(quote("say \"hi\""), "\"say \\\"hi\\\"\"");
+    }
+}
@@ -1,6 +1,7 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct FileCache {
     pub is_enabled: bool,
     pub cache_item_size: usize,
@@ -9,6 +10,43 @@ pub struct FileCache {
     pub cleanup_thread_interval: usize,
     pub max_item_lifetime: usize,         // in seconds
     pub forced_eviction_threshold: usize, // 1-99 %
+    // Files kept open, with their metadata, when their content is not cached, such as large files. 0 disables it
+    #[serde(default = "default_open_file_cache_max_entries")]
+    pub open_file_cache_max_entries: usize,
+    // How long an open file is used before it is checked for changes on disk, in seconds
+    #[serde(default = "default_open_file_cache_revalidate_seconds")]
+    pub open_file_cache_revalidate_seconds: usize,
+    // Serve files within the size thresholds from a memory mapping, instead of reading them into buffers (unix only)
+    #[serde(default)]
+    pub mmap_is_enabled: bool,
+    #[serde(default = "default_mmap_min_file_size")]
+    pub mmap_min_file_size: usize, // in bytes
+    #[serde(default = "default_mmap_max_file_size")]
+    pub mmap_max_file_size: usize, // in bytes
+    // Memory for the compressed variants of the cached files, such as gzip, so they are not compressed for every request.
+    // Variants past it are compressed per request. 0 to not keep variants
+    #[serde(default = "default_compressed_variants_max_size")]
+    pub compressed_variants_max_size: usize, // in bytes
+}
+
+fn default_open_file_cache_max_entries() -> usize {
+    256
+}
+
+fn default_open_file_cache_revalidate_seconds() -> usize {
+    2
+}
+
+fn default_mmap_min_file_size() -> usize {
+    1024 * 1024
+}
+
+fn default_mmap_max_file_size() -> usize {
+    1024 * 1024 * 1024
+}
+
+fn default_compressed_variants_max_size() -> usize {
+    64 * 1024 * 1024
 }
 
 impl FileCache {
@@ -47,6 +85,16 @@ impl FileCache {
             errors.push("Forced eviction threshold must be between 1-99%".to_string());
         }
 
+        // Validate the memory mapping thresholds
+        if self.mmap_is_enabled {
+            if self.mmap_max_file_size == 0 {
+                errors.push("Max memory-mapped file size cannot be 0 bytes".to_string());
+            }
+            if self.mmap_min_file_size > self.mmap_max_file_size {
+                errors.push("Min memory-mapped file size cannot be larger than the max memory-mapped file size".to_string());
+            }
+        }
+
         // Note: cache_item_size is a count of items, cache_max_size_per_file is bytes per file
         // These are different units and cannot be compared directly
 
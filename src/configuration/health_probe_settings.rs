@@ -0,0 +1,79 @@
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::network::ip_range::IpRange;
+
+// Liveness and readiness endpoints of Gruxi itself, for Kubernetes probes and load balancers. They are answered on every
+// binding, before the sites, for the clients allowed
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct HealthProbeSettings {
+    pub is_enabled: bool,
+    pub liveness_path: String,    // Answered while the server runs
+    pub readiness_path: String,   // Answered with 503 while the server cannot serve, such as during a reload
+    pub allowed_ips: Vec<String>, // IP addresses or CIDR ranges the probes are answered for, empty for any client
+    #[serde(skip)]
+    parsed_allowed_ips: OnceLock<Vec<IpRange>>,
+}
+
+impl Default for HealthProbeSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HealthProbeSettings {
+    pub fn new() -> Self {
+        Self {
+            is_enabled: false,
+            liveness_path: "/healthz".to_string(),
+            readiness_path: "/readyz".to_string(),
+            allowed_ips: Vec::new(),
+            parsed_allowed_ips: OnceLock::new(),
+        }
+    }
+
+    // Whether the probes are answered for the client. Other clients get the site, which may have such paths of its own
+    pub fn is_allowed(&self, remote_ip: &str) -> bool {
+        if self.allowed_ips.is_empty() {
+            return true;
+        }
+        let Ok(remote_ip) = remote_ip.parse::<IpAddr>() else {
+            return false;
+        };
+        self.parsed_allowed_ips
+            .get_or_init(|| self.allowed_ips.iter().filter_map(|range| IpRange::parse(range).ok()).collect())
+            .iter()
+            .any(|range| range.contains(&remote_ip))
+    }
+
+    pub fn sanitize(&mut self) {
+        self.liveness_path = self.liveness_path.trim().to_string();
+        self.readiness_path = self.readiness_path.trim().to_string();
+        self.allowed_ips = self.allowed_ips.iter().map(|range| range.trim().to_string()).filter(|range| !range.is_empty()).collect();
+        // The ranges may have changed, so parse them again on next use
+        self.parsed_allowed_ips = OnceLock::new();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        for (name, path) in [("Liveness", &self.liveness_path), ("Readiness", &self.readiness_path)] {
+            if !path.starts_with('/') {
+                errors.push(format!("{} path '{}' must start with '/'", name, path));
+            }
+        }
+        if self.liveness_path == self.readiness_path {
+            errors.push("Liveness and readiness paths must differ".to_string());
+        }
+        for range in &self.allowed_ips {
+            if let Err(e) = IpRange::parse(range) {
+                errors.push(format!("Allowed IPs: {}", e));
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
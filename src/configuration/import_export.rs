@@ -1,7 +1,9 @@
+use crate::configuration::deprecated_fields::{DeprecationWarning, rename_deprecated_fields};
+use crate::configuration::export_formats::render_configuration;
 use crate::configuration::load_configuration::fetch_configuration_in_db;
 use std::path::PathBuf;
 
-pub fn export_configuration_to_file(path: &PathBuf) -> Result<(), String> {
+pub fn export_configuration_to_file(path: &PathBuf, format: &str) -> Result<(), String> {
     let cached_configuration_result = fetch_configuration_in_db();
     let cached_configuration = match cached_configuration_result {
         Ok(cfg) => cfg,
@@ -10,8 +12,8 @@ pub fn export_configuration_to_file(path: &PathBuf) -> Result<(), String> {
         }
     };
 
-    // Serialize configuration to JSON
-    let serialized = serde_json::to_string_pretty(&cached_configuration).map_err(|e| format!("Failed to serialize configuration: {}", e))?;
+    // Serialize configuration to JSON, or render it as the equivalent configuration of another server
+    let serialized = render_configuration(&cached_configuration, format)?;
 
     std::fs::write(path, serialized).map_err(|e| format!("Failed to write configuration to file: {}", e))?;
     println!("Configuration successfully exported to {}", path.display());
@@ -24,7 +26,7 @@ pub fn import_configuration_from_file(path: &PathBuf) -> Result<(), String> {
     let file_contents = std::fs::read_to_string(path).map_err(|e| format!("Failed to read configuration file {}: {}", path.display(), e))?;
 
     // Load json into loose typed to validate version and possibly do version migrations later
-    let loose_typed: serde_json::Value = serde_json::from_str(&file_contents).map_err(|e| format!("Failed to parse configuration file {}: {}", path.display(), e))?;
+    let mut loose_typed: serde_json::Value = serde_json::from_str(&file_contents).map_err(|e| format!("Failed to parse configuration file {}: {}", path.display(), e))?;
 
     // Check that versions match
     if loose_typed["version"] != crate::configuration::configuration::CURRENT_CONFIGURATION_VERSION {
@@ -38,9 +40,14 @@ pub fn import_configuration_from_file(path: &PathBuf) -> Result<(), String> {
         ));
     }
 
+    // Deprecated fields are imported under their current names
+    for warning in rename_deprecated_fields(&mut loose_typed) {
+        println!("Warning: {}", warning.message);
+    }
+
     // Deserialize JSON to Configuration struct
     let mut configuration: crate::configuration::configuration::Configuration =
-        serde_json::from_str(&file_contents).map_err(|e| format!("Failed to deserialize configuration from file {}: {}", path.display(), e))?;
+        serde_json::from_value(loose_typed).map_err(|e| format!("Failed to deserialize configuration from file {}: {}", path.display(), e))?;
 
     // Save configuration to database
     crate::configuration::save_configuration::save_configuration(&mut configuration, false).map_err(|e| format!("Failed to save imported configuration to database: {:?}", e))?;
@@ -50,12 +57,13 @@ pub fn import_configuration_from_file(path: &PathBuf) -> Result<(), String> {
     Ok(())
 }
 
-pub fn validate_configuration_file(path: &PathBuf) -> Result<(), String> {
+// Returns the deprecated fields used in the file, which are valid but should be renamed
+pub fn validate_configuration_file(path: &PathBuf) -> Result<Vec<DeprecationWarning>, String> {
     // Read file contents
     let file_contents = std::fs::read_to_string(path).map_err(|e| format!("Failed to read configuration file {}: {}", path.display(), e))?;
 
     // Load json into loose typed to validate version
-    let loose_typed: serde_json::Value = serde_json::from_str(&file_contents).map_err(|e| format!("Failed to parse configuration file {}: {}", path.display(), e))?;
+    let mut loose_typed: serde_json::Value = serde_json::from_str(&file_contents).map_err(|e| format!("Failed to parse configuration file {}: {}", path.display(), e))?;
 
     // Check that versions match
     if loose_typed["version"] != crate::configuration::configuration::CURRENT_CONFIGURATION_VERSION {
@@ -67,10 +75,25 @@ pub fn validate_configuration_file(path: &PathBuf) -> Result<(), String> {
     }
 
     // Deserialize JSON to Configuration struct to ensure it's valid
+    let deprecation_warnings = rename_deprecated_fields(&mut loose_typed);
     let configuration: crate::configuration::configuration::Configuration =
-        serde_json::from_str(&file_contents).map_err(|e| format!("Failed to deserialize configuration from file {}: {}", path.display(), e))?;
+        serde_json::from_value(loose_typed).map_err(|e| format!("Failed to deserialize configuration from file {}: {}", path.display(), e))?;
 
     configuration.validate().map_err(|e| format!("Configuration validation failed: {:?}", e))?;
 
-    Ok(())
+    Ok(deprecation_warnings)
+}
+
+// Rename the deprecated fields of a configuration file in place, keeping the rest of the file as it is
+pub fn migrate_configuration_file(path: &PathBuf, dry_run: bool) -> Result<Vec<DeprecationWarning>, String> {
+    let file_contents = std::fs::read_to_string(path).map_err(|e| format!("Failed to read configuration file {}: {}", path.display(), e))?;
+    let mut loose_typed: serde_json::Value = serde_json::from_str(&file_contents).map_err(|e| format!("Failed to parse configuration file {}: {}", path.display(), e))?;
+
+    let deprecation_warnings = rename_deprecated_fields(&mut loose_typed);
+    if !dry_run && !deprecation_warnings.is_empty() {
+        let serialized = serde_json::to_string_pretty(&loose_typed).map_err(|e| format!("Failed to serialize configuration: {}", e))?;
+        std::fs::write(path, serialized).map_err(|e| format!("Failed to write configuration to file: {}", e))?;
+    }
+
+    Ok(deprecation_warnings)
 }
@@ -0,0 +1,58 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::http::error_response::ERROR_RESPONSE_FORMATS;
+
+// Page answered, still as 404, for requests on a binding whose hostname matches none of its sites and no default site
+// serves, instead of an empty response. It can list the sites of the binding, as a directory to pick from
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct LandingPageSettings {
+    pub is_enabled: bool,
+    pub title: String,
+    pub message: String,  // Text shown under the title, can be empty
+    pub show_sites: bool, // Whether the page links to the hostnames of the enabled sites on the binding
+    pub format: String,   // One of ERROR_RESPONSE_FORMATS, where "negotiate" answers JSON to clients preferring it
+}
+
+impl Default for LandingPageSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LandingPageSettings {
+    pub fn new() -> Self {
+        Self {
+            is_enabled: true,
+            title: "Welcome to Gruxi".to_string(),
+            message: "There is no site configured for this hostname.".to_string(),
+            show_sites: true,
+            format: "negotiate".to_string(),
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.title = self.title.trim().to_string();
+        self.message = self.message.trim().to_string();
+        self.format = self.format.trim().to_lowercase();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.title.is_empty() {
+            errors.push("Title cannot be empty".to_string());
+        }
+        if self.title.chars().count() > 100 {
+            errors.push("Title cannot be longer than 100 characters".to_string());
+        }
+        if self.message.chars().count() > 1000 {
+            errors.push("Message cannot be longer than 1000 characters".to_string());
+        }
+        if !ERROR_RESPONSE_FORMATS.contains(&self.format.as_str()) {
+            errors.push(format!("Format must be one of {}, got '{}'", ERROR_RESPONSE_FORMATS.join(", "), self.format));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
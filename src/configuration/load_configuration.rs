@@ -1,17 +1,54 @@
+use crate::configuration::auth_provider::AuthProvider;
 use crate::configuration::binding_site_relation::BindingSiteRelationship;
+use crate::configuration::command_hook::CommandHook;
+use crate::configuration::deprecated_fields::{get_current_setting_key, set_configuration_deprecations};
+use crate::database::configuration_storage::get_configuration_storage;
+use crate::database::data_access::query;
 use crate::database::database_migration::migrate_database;
 use crate::database::database_schema::{CURRENT_DB_SCHEMA_VERSION, get_schema_version, set_schema_version};
+use crate::error::gruxi_error::GruxiError;
+use crate::error::gruxi_error_enums::ConfigurationError;
+use crate::external_connections::managed_system::environment_variable::EnvironmentVariable;
+use crate::external_connections::managed_system::node_app::NodeApp;
 use crate::external_connections::managed_system::php_cgi;
+use crate::external_connections::managed_system::python_app::PythonApp;
+use crate::http::basic_auth::BasicAuthUser;
 use crate::http::request_handlers::processor_trait::ProcessorTrait;
+use crate::http::request_handlers::processors::cgi_processor::{CgiInterpreter, CgiProcessor};
+use crate::http::request_handlers::processors::image_processor::ImageProcessor;
+use crate::http::request_handlers::processors::node_processor::NodeProcessor;
 use crate::http::request_handlers::processors::php_processor::{self, PHPProcessor};
-use crate::http::request_handlers::processors::proxy_processor::{ProxyProcessor, ProxyProcessorRewrite};
+use crate::http::request_handlers::processors::proxy_processor::{ProxyProcessor, ProxyProcessorRewrite, ProxyUpstreamGroup};
+use crate::http::request_handlers::processors::python_processor::PythonProcessor;
 use crate::http::request_handlers::processors::static_files_processor::StaticFileProcessor;
-use crate::logging::syslog::{info, trace};
+use crate::http::request_handlers::processors::webdav_processor::WebDavProcessor;
+use crate::http::site_match::hostname_pattern::is_regex_hostname;
+use crate::logging::syslog::{info, trace, warn};
 use crate::{
-    configuration::{binding::Binding, configuration::Configuration, core::Core, request_handler::RequestHandler, save_configuration::save_configuration, site::HeaderKV, site::Site},
+    configuration::{
+        bandwidth_settings::BandwidthSettings,
+        binding::Binding,
+        bot_settings::BotSettings,
+        cache_header_rule::CacheHeaderRule,
+        cache_warm_settings::CacheWarmSettings,
+        configuration::Configuration,
+        core::Core,
+        location::Location,
+        lua_hook::LuaHook,
+        plugin_settings::WasmPlugin,
+        request_handler::RequestHandler,
+        save_configuration::save_configuration,
+        site::{HeaderKV, PhpIniSetting, Site},
+        site_acme_settings::SiteAcmeSettings,
+        synthetic_probe_settings::SyntheticProbeSettings,
+        waf_settings::WafSettings,
+        webroot_sync_settings::WebrootSyncSettings,
+        websocket_settings::WebSocketSettings,
+    },
     core::database_connection::get_database_connection,
 };
 use sqlite::Connection;
+use std::sync::OnceLock;
 use uuid::Uuid;
 
 // Load the configuration from the database or create a default one if it doesn't exist
@@ -25,13 +62,20 @@ pub fn init() -> Configuration {
             "Database schema version {} is older than current version {}, migrating...",
             schema_version, CURRENT_DB_SCHEMA_VERSION
         ));
-        migrate_database();
+        if let Err(e) = migrate_database() {
+            panic!("Failed to migrate the database: {}", e);
+        }
     }
 
     // Load configuration
+    let storage = get_configuration_storage();
+    let has_configuration = match storage.has_configuration() {
+        Ok(has_configuration) => has_configuration,
+        Err(e) => panic!("Failed to check for a configuration in {}: {}", storage.get_name(), e),
+    };
     let mut configuration = {
-        if schema_version == 0 {
-            // No schema version found, likely first run - create default configuration
+        if !has_configuration {
+            // No configuration found, likely first run - create default configuration
             info("No configuration found, creating default configuration");
             let mut configuration = Configuration::get_default();
             let save_result = save_configuration(&mut configuration, true);
@@ -39,12 +83,6 @@ pub fn init() -> Configuration {
                 panic!("Failed to save default configuration to database: {:?}", e);
             }
 
-            // Update schema version to value of constant CURRENT_CONFIGURATION_VERSION
-            let set_schema_version_result = set_schema_version(CURRENT_DB_SCHEMA_VERSION).map_err(|e| vec![format!("Failed to set schema version: {}", e)]);
-            if let Err(e) = set_schema_version_result {
-                panic!("Failed to set schema version: {:?}", e);
-            }
-
             configuration
         } else {
             // Load existing configuration
@@ -59,6 +97,26 @@ pub fn init() -> Configuration {
         }
     };
 
+    // A new database is at the current schema version, also when the configuration is kept in another storage
+    if schema_version == 0 {
+        // Update schema version to value of constant CURRENT_CONFIGURATION_VERSION
+        let set_schema_version_result = set_schema_version(CURRENT_DB_SCHEMA_VERSION).map_err(|e| vec![format!("Failed to set schema version: {}", e)]);
+        if let Err(e) = set_schema_version_result {
+            panic!("Failed to set schema version: {:?}", e);
+        }
+    }
+
+    // Deprecated fields are still read, but the admins are told to migrate them
+    match storage.get_deprecated_fields() {
+        Ok(deprecation_warnings) => {
+            for warning in &deprecation_warnings {
+                warn(format!("Configuration: {}", warning.message));
+            }
+            set_configuration_deprecations(deprecation_warnings);
+        }
+        Err(e) => warn(format!("Failed to check the configuration for deprecated fields: {}", e)),
+    }
+
     // Add admin portal to configuration if we have it enabled (which it is by default)
     if configuration.core.admin_portal.is_enabled {
         trace("Admin portal is enabled, adding it to configuration");
@@ -75,6 +133,13 @@ fn add_admin_portal_to_configuration(configuration: &mut Configuration) {
         port: 8000,
         is_admin: true,
         is_tls: true,
+        http10_strict_close: false,
+        keep_alive_timeout_seconds: 0,
+        max_keep_alive_requests: 0,
+        http_versions: "auto".to_string(),
+        sni_mismatch_policy: "allow".to_string(),
+        default_site_id: String::new(),
+        unknown_host_policy: "default".to_string(),
     };
 
     // Static file processor for admin site
@@ -114,8 +179,28 @@ fn add_admin_portal_to_configuration(configuration: &mut Configuration) {
         request_handlers: vec![request_handler.id.clone()],
         rewrite_functions: vec![],
         extra_headers: vec![],
+        locations: vec![],
+        php_ini_settings: vec![],
+        php_environment: vec![],
+        sendfile_root: "".to_string(),
+        websocket: WebSocketSettings::new(),
+        webroot_sync: WebrootSyncSettings::new(),
+        cache_warm: CacheWarmSettings::new(),
+        bandwidth: BandwidthSettings::new(),
+        waf: WafSettings::new(),
+        bots: BotSettings::new(),
+        middleware: Vec::new(),
+        plugins: Vec::new(),
+        lua_hooks: Vec::new(),
+        error_response_format: "html".to_string(),
+        disk_quota_mb: 0,
+        synthetic_probe: SyntheticProbeSettings::new(),
+        acme: SiteAcmeSettings::new(),
+        cache_header_rules: Vec::new(),
         access_log_enabled: true,
         access_log_file: "./logs/admin-portal-access.log".to_string(),
+        hostname_patterns: OnceLock::new(),
+        location_sites: OnceLock::new(),
     };
 
     // Admin site
@@ -130,8 +215,13 @@ fn add_admin_portal_to_configuration(configuration: &mut Configuration) {
     configuration.bindings.push(admin_binding);
 }
 
-// Load the configuration from the normalized database tables - Returns the data from db as fresh
-pub fn fetch_configuration_in_db() -> Result<Configuration, String> {
+// Load the configuration from the configuration storage - Returns the data from db as fresh
+pub fn fetch_configuration_in_db() -> Result<Configuration, GruxiError> {
+    get_configuration_storage().load_configuration()
+}
+
+// Load the configuration from the normalized database tables
+pub fn load_configuration_from_sqlite() -> Result<Configuration, GruxiError> {
     let schema_version = get_schema_version();
 
     let connection = get_database_connection()?;
@@ -149,9 +239,22 @@ pub fn fetch_configuration_in_db() -> Result<Configuration, String> {
     let static_file_processors = load_static_file_processors(&connection)?;
     let php_processors = load_php_processors(&connection)?;
     let proxy_processors = load_proxy_processors(&connection)?;
+    let webdav_processors = load_webdav_processors(&connection)?;
+    let cgi_processors = load_cgi_processors(&connection)?;
+    let python_processors = load_python_processors(&connection)?;
+    let node_processors = load_node_processors(&connection)?;
+    let image_processors = load_image_processors(&connection)?;
 
     // External systems
     let php_cgi_handlers = load_php_cgi_handlers(&connection)?;
+    let python_handlers = load_python_handlers(&connection)?;
+    let node_handlers = load_node_handlers(&connection)?;
+
+    // Authentication
+    let auth_providers = load_auth_providers(&connection)?;
+
+    // Command hooks
+    let command_hooks = load_command_hooks(&connection)?;
 
     // Do a sanitize, in case there are any invalid entries in the database
     let mut configuration = Configuration {
@@ -164,38 +267,69 @@ pub fn fetch_configuration_in_db() -> Result<Configuration, String> {
         static_file_processors,
         php_processors,
         proxy_processors,
+        webdav_processors,
+        cgi_processors,
+        python_processors,
+        node_processors,
+        image_processors,
         php_cgi_handlers: php_cgi_handlers,
+        python_handlers,
+        node_handlers,
+        auth_providers,
+        command_hooks,
     };
     configuration.sanitize();
 
     Ok(configuration)
 }
 
-fn load_proxy_processors(connection: &Connection) -> Result<Vec<ProxyProcessor>, String> {
-    let mut statement = connection
-        .prepare("SELECT * FROM proxy_processors")
-        .map_err(|e| format!("Failed to prepare Proxy processors query: {}", e))?;
-
-    let mut processors = Vec::new();
-    while let sqlite::State::Row = statement.next().map_err(|e| format!("Failed to execute Proxy processors query: {}", e))? {
-        let processor_id: String = statement.read(0).map_err(|e| format!("Failed to read processor id: {}", e))?;
-        let proxy_type: String = statement.read(1).map_err(|e| format!("Failed to read proxy_type: {}", e))?;
-        let upstream_servers_str: String = statement.read(2).map_err(|e| format!("Failed to read upstream_servers: {}", e))?;
-        let load_balancing_strategy: String = statement.read(3).map_err(|e| format!("Failed to read load_balancing_strategy: {}", e))?;
-        let timeout_seconds: i64 = statement.read(4).map_err(|e| format!("Failed to read timeout_seconds: {}", e))?;
-        let health_check_path: String = statement.read(5).map_err(|e| format!("Failed to read health_check_path: {}", e))?;
-        let health_check_interval_seconds: i64 = statement.read(6).map_err(|e| format!("Failed to read health_check_interval_seconds: {}", e))?;
-        let health_check_timeout_seconds: i64 = statement.read(7).map_err(|e| format!("Failed to read health_check_timeout_seconds: {}", e))?;
-        let url_rewrites_str: String = statement.read(8).map_err(|e| format!("Failed to read url_rewrites: {}", e))?;
-        let preserve_host_header_int: i64 = statement.read(9).map_err(|e| format!("Failed to read preserve_host_header: {}", e))?;
-        let forced_host_header: String = statement.read(10).map_err(|e| format!("Failed to read forced_host_header: {}", e))?;
-        let verify_tls_certificates_int: i64 = statement.read(11).map_err(|e| format!("Failed to read verify_tls_certificates: {}", e))?;
+fn load_proxy_processors(connection: &Connection) -> Result<Vec<ProxyProcessor>, GruxiError> {
+    query(connection, "SELECT * FROM proxy_processors", &[], |row| {
+        let processor_id = row.get_string("id")?;
+        let proxy_type = row.get_string("proxy_type")?;
+        let upstream_servers_str = row.get_string("upstream_servers")?;
+        let load_balancing_strategy = row.get_string("load_balancing_strategy")?;
+        let timeout_seconds = row.get_i64("timeout_seconds")?;
+        let health_check_path = row.get_string("health_check_path")?;
+        let health_check_interval_seconds = row.get_i64("health_check_interval_seconds")?;
+        let health_check_timeout_seconds = row.get_i64("health_check_timeout_seconds")?;
+        let url_rewrites_str = row.get_string("url_rewrites")?;
+        let preserve_host_header_int = row.get_i64("preserve_host_header")?;
+        let forced_host_header = row.get_string("forced_host_header")?;
+        let verify_tls_certificates_int = row.get_i64("verify_tls_certificates")?;
+        let streaming_paths_str = row.get_string("streaming_paths")?;
+        let streaming_timeout_seconds = row.get_i64("streaming_timeout_seconds")?;
+        let tls_ca_bundle_path = row.get_string("tls_ca_bundle_path")?;
+        let tls_server_name = row.get_string("tls_server_name")?;
+        let tls_client_cert_path = row.get_string("tls_client_cert_path")?;
+        let tls_client_key_path = row.get_string("tls_client_key_path")?;
+        let pool_max_idle_per_host = row.get_i64("pool_max_idle_per_host")?;
+        let pool_idle_timeout_seconds = row.get_i64("pool_idle_timeout_seconds")?;
+        let upstream_http2_only_int = row.get_i64("upstream_http2_only")?;
+        let connect_timeout_seconds = row.get_i64("connect_timeout_seconds")?;
+        let total_timeout_seconds = row.get_i64("total_timeout_seconds")?;
+        let max_request_body_bytes = row.get_i64("max_request_body_bytes")?;
+        let max_response_body_bytes = row.get_i64("max_response_body_bytes")?;
+        let upstream_groups_str = row.get_string("upstream_groups")?;
+        let upstream_group_header = row.get_string("upstream_group_header")?;
+        let upstream_group_cookie = row.get_string("upstream_group_cookie")?;
+        let request_headers_allowed_str = row.get_string("request_headers_allowed")?;
+        let request_headers_denied_str = row.get_string("request_headers_denied")?;
+        let response_headers_allowed_str = row.get_string("response_headers_allowed")?;
+        let response_headers_denied_str = row.get_string("response_headers_denied")?;
 
         // Upstream servers is stored as comma separated
         let upstream_servers = parse_comma_separated_list(&upstream_servers_str, true);
 
         // Url rewrites is stored as JSON array
-        let url_rewrites: Vec<ProxyProcessorRewrite> = serde_json::from_str(&url_rewrites_str).map_err(|e| format!("Failed to parse url_rewrites JSON: {}", e))?;
+        let url_rewrites: Vec<ProxyProcessorRewrite> = serde_json::from_str(&url_rewrites_str).map_err(|e| load_error(format!("Failed to parse url_rewrites JSON: {}", e)))?;
+
+        // Upstream groups are stored as JSON array (added in schema version 27)
+        let upstream_groups: Vec<ProxyUpstreamGroup> = if upstream_groups_str.is_empty() {
+            Vec::new()
+        } else {
+            serde_json::from_str(&upstream_groups_str).map_err(|e| load_error(format!("Failed to parse upstream_groups JSON: {}", e)))?
+        };
 
         let mut new_processor = ProxyProcessor::new();
         new_processor.id = processor_id;
@@ -210,28 +344,176 @@ fn load_proxy_processors(connection: &Connection) -> Result<Vec<ProxyProcessor>,
         new_processor.preserve_host_header = preserve_host_header_int != 0;
         new_processor.forced_host_header = forced_host_header;
         new_processor.verify_tls_certificates = verify_tls_certificates_int != 0;
+        new_processor.streaming_paths = parse_comma_separated_list(&streaming_paths_str, true);
+        new_processor.streaming_timeout_seconds = streaming_timeout_seconds as u32;
+        new_processor.tls_ca_bundle_path = tls_ca_bundle_path;
+        new_processor.tls_server_name = tls_server_name;
+        new_processor.tls_client_cert_path = tls_client_cert_path;
+        new_processor.tls_client_key_path = tls_client_key_path;
+        new_processor.pool_max_idle_per_host = pool_max_idle_per_host as u32;
+        new_processor.pool_idle_timeout_seconds = pool_idle_timeout_seconds as u32;
+        new_processor.upstream_http2_only = upstream_http2_only_int != 0;
+        new_processor.connect_timeout_seconds = connect_timeout_seconds as u32;
+        new_processor.total_timeout_seconds = total_timeout_seconds as u32;
+        new_processor.max_request_body_bytes = max_request_body_bytes as u64;
+        new_processor.max_response_body_bytes = max_response_body_bytes as u64;
+        new_processor.upstream_groups = upstream_groups;
+        new_processor.upstream_group_header = upstream_group_header;
+        new_processor.upstream_group_cookie = upstream_group_cookie;
+        new_processor.request_headers_allowed = parse_comma_separated_list(&request_headers_allowed_str, true);
+        new_processor.request_headers_denied = parse_comma_separated_list(&request_headers_denied_str, true);
+        new_processor.response_headers_allowed = parse_comma_separated_list(&response_headers_allowed_str, true);
+        new_processor.response_headers_denied = parse_comma_separated_list(&response_headers_denied_str, true);
 
         new_processor.initialize();
-        processors.push(new_processor);
-    }
-    Ok(processors)
+        Ok(new_processor)
+    })
+}
+
+fn load_webdav_processors(connection: &Connection) -> Result<Vec<WebDavProcessor>, GruxiError> {
+    query(connection, "SELECT * FROM webdav_processors", &[], |row| {
+        let processor_id = row.get_string("id")?;
+        let web_root = row.get_string("web_root")?;
+        let read_only_int = row.get_i64("read_only")?;
+        let require_authentication_int = row.get_i64("require_authentication")?;
+        let auth_realm = row.get_string("auth_realm")?;
+        let auth_users_str = row.get_string("auth_users")?;
+
+        // Auth users are stored as JSON array
+        let auth_users: Vec<BasicAuthUser> = if auth_users_str.is_empty() {
+            Vec::new()
+        } else {
+            serde_json::from_str(&auth_users_str).map_err(|e| load_error(format!("Failed to parse auth_users JSON: {}", e)))?
+        };
+
+        let mut new_processor = WebDavProcessor::new(web_root);
+        new_processor.id = processor_id;
+        new_processor.read_only = read_only_int != 0;
+        new_processor.require_authentication = require_authentication_int != 0;
+        new_processor.auth_realm = auth_realm;
+        new_processor.auth_users = auth_users;
+
+        new_processor.initialize();
+        Ok(new_processor)
+    })
 }
 
-fn load_php_processors(connection: &Connection) -> Result<Vec<php_processor::PHPProcessor>, String> {
-    let mut statement = connection
-        .prepare("SELECT * FROM php_processors")
-        .map_err(|e| format!("Failed to prepare PHP processors query: {}", e))?;
-
-    let mut processors = Vec::new();
-    while let sqlite::State::Row = statement.next().map_err(|e| format!("Failed to execute PHP processors query: {}", e))? {
-        let processor_id: String = statement.read(0).map_err(|e| format!("Failed to read processor id: {}", e))?;
-        let served_by_type: String = statement.read(1).map_err(|e| format!("Failed to read served_by_type: {}", e))?;
-        let php_cgi_handler_id: String = statement.read(2).map_err(|e| format!("Failed to read php_cgi_handler_id: {}", e))?;
-        let fastcgi_ip_and_port: String = statement.read(3).map_err(|e| format!("Failed to read fastcgi_ip_and_port: {}", e))?;
-        let request_timeout: i64 = statement.read(4).map_err(|e| format!("Failed to read request_timeout: {}", e))?;
-        let local_web_root: String = statement.read(5).map_err(|e| format!("Failed to read local_web_root: {}", e))?;
-        let fastcgi_web_root: String = statement.read(6).map_err(|e| format!("Failed to read fastcgi_web_root: {}", e))?;
-        let server_software_spoof: String = statement.read(7).map_err(|e| format!("Failed to read server_software_spoof: {}", e))?;
+fn load_cgi_processors(connection: &Connection) -> Result<Vec<CgiProcessor>, GruxiError> {
+    query(connection, "SELECT * FROM cgi_processors", &[], |row| {
+        let processor_id = row.get_string("id")?;
+        let cgi_bin_dir = row.get_string("cgi_bin_dir")?;
+        let url_prefix = row.get_string("url_prefix")?;
+        let interpreters_str = row.get_string("interpreters")?;
+        let request_timeout = row.get_i64("request_timeout")?;
+
+        // Interpreters are stored as JSON array
+        let interpreters: Vec<CgiInterpreter> = if interpreters_str.is_empty() {
+            Vec::new()
+        } else {
+            serde_json::from_str(&interpreters_str).map_err(|e| load_error(format!("Failed to parse interpreters JSON: {}", e)))?
+        };
+
+        let mut new_processor = CgiProcessor::new();
+        new_processor.id = processor_id;
+        new_processor.cgi_bin_dir = cgi_bin_dir;
+        new_processor.url_prefix = url_prefix;
+        new_processor.interpreters = interpreters;
+        new_processor.request_timeout = request_timeout as u32;
+
+        new_processor.initialize();
+        Ok(new_processor)
+    })
+}
+
+fn load_python_processors(connection: &Connection) -> Result<Vec<PythonProcessor>, GruxiError> {
+    query(connection, "SELECT * FROM python_processors", &[], |row| {
+        let processor_id = row.get_string("id")?;
+        let python_handler_id = row.get_string("python_handler_id")?;
+        let request_timeout = row.get_i64("request_timeout")?;
+        let preserve_host_header_int = row.get_i64("preserve_host_header")?;
+
+        let mut new_processor = PythonProcessor::new();
+        new_processor.id = processor_id;
+        new_processor.python_handler_id = python_handler_id;
+        new_processor.request_timeout = request_timeout as u32;
+        new_processor.preserve_host_header = preserve_host_header_int != 0;
+
+        new_processor.initialize();
+        Ok(new_processor)
+    })
+}
+
+fn load_node_processors(connection: &Connection) -> Result<Vec<NodeProcessor>, GruxiError> {
+    query(connection, "SELECT * FROM node_processors", &[], |row| {
+        let processor_id = row.get_string("id")?;
+        let node_handler_id = row.get_string("node_handler_id")?;
+        let request_timeout = row.get_i64("request_timeout")?;
+        let preserve_host_header_int = row.get_i64("preserve_host_header")?;
+
+        let mut new_processor = NodeProcessor::new();
+        new_processor.id = processor_id;
+        new_processor.node_handler_id = node_handler_id;
+        new_processor.request_timeout = request_timeout as u32;
+        new_processor.preserve_host_header = preserve_host_header_int != 0;
+
+        new_processor.initialize();
+        Ok(new_processor)
+    })
+}
+
+fn load_image_processors(connection: &Connection) -> Result<Vec<ImageProcessor>, GruxiError> {
+    query(connection, "SELECT * FROM image_processors", &[], |row| {
+        let processor_id = row.get_string("id")?;
+        let web_root = row.get_string("web_root")?;
+        let allowed_paths_str = row.get_string("allowed_paths")?;
+        let cache_dir = row.get_string("cache_dir")?;
+        let converter_path = row.get_string("converter_path")?;
+        let max_width = row.get_i64("max_width")?;
+        let allowed_widths_str = row.get_string("allowed_widths")?;
+        let default_quality = row.get_i64("default_quality")?;
+        let max_concurrent_conversions = row.get_i64("max_concurrent_conversions")?;
+        let request_timeout = row.get_i64("request_timeout")?;
+
+        // Allowed paths and widths are stored as JSON arrays
+        let allowed_paths: Vec<String> = if allowed_paths_str.is_empty() {
+            Vec::new()
+        } else {
+            serde_json::from_str(&allowed_paths_str).map_err(|e| load_error(format!("Failed to parse allowed paths JSON: {}", e)))?
+        };
+        let allowed_widths: Vec<u32> = if allowed_widths_str.is_empty() {
+            Vec::new()
+        } else {
+            serde_json::from_str(&allowed_widths_str).map_err(|e| load_error(format!("Failed to parse allowed widths JSON: {}", e)))?
+        };
+
+        let mut new_processor = ImageProcessor::new();
+        new_processor.id = processor_id;
+        new_processor.web_root = web_root;
+        new_processor.allowed_paths = allowed_paths;
+        new_processor.cache_dir = cache_dir;
+        new_processor.converter_path = converter_path;
+        new_processor.max_width = max_width as u32;
+        new_processor.allowed_widths = allowed_widths;
+        new_processor.default_quality = default_quality as u32;
+        new_processor.max_concurrent_conversions = max_concurrent_conversions as u32;
+        new_processor.request_timeout = request_timeout as u32;
+
+        new_processor.initialize();
+        Ok(new_processor)
+    })
+}
+
+fn load_php_processors(connection: &Connection) -> Result<Vec<php_processor::PHPProcessor>, GruxiError> {
+    query(connection, "SELECT * FROM php_processors", &[], |row| {
+        let processor_id = row.get_string("id")?;
+        let served_by_type = row.get_string("served_by_type")?;
+        let php_cgi_handler_id = row.get_string("php_cgi_handler_id")?;
+        let fastcgi_ip_and_port = row.get_string("fastcgi_ip_and_port")?;
+        let request_timeout = row.get_i64("request_timeout")?;
+        let local_web_root = row.get_string("local_web_root")?;
+        let fastcgi_web_root = row.get_string("fastcgi_web_root")?;
+        let server_software_spoof = row.get_string("server_software_spoof")?;
+        let fastcgi_pool_size = row.get_i64("fastcgi_pool_size")?;
 
         let mut new_processor = PHPProcessor::new();
         new_processor.id = processor_id;
@@ -242,74 +524,235 @@ fn load_php_processors(connection: &Connection) -> Result<Vec<php_processor::PHP
         new_processor.local_web_root = local_web_root;
         new_processor.fastcgi_web_root = fastcgi_web_root;
         new_processor.server_software_spoof = server_software_spoof;
+        new_processor.fastcgi_pool_size = fastcgi_pool_size as u32;
 
         new_processor.initialize();
-        processors.push(new_processor);
-    }
+        Ok(new_processor)
+    })
+}
 
-    Ok(processors)
+fn load_php_cgi_handlers(connection: &Connection) -> Result<Vec<php_cgi::PhpCgi>, GruxiError> {
+    query(connection, "SELECT * FROM php_cgi_handlers", &[], |row| {
+        let handler_id = row.get_string("id")?;
+        let name = row.get_string("name")?;
+        let request_timeout = row.get_i64("request_timeout")?;
+        let concurrent_threads = row.get_i64("concurrent_threads")?;
+        let executable = row.get_string("executable")?;
+        let max_queue_length = row.get_i64("max_queue_length")?;
+        let queue_timeout = row.get_i64("queue_timeout")?;
+        let max_requests_per_worker = row.get_i64("max_requests_per_worker")?;
+        let max_worker_lifetime = row.get_i64("max_worker_lifetime")?;
+
+        let mut new_handler = php_cgi::PhpCgi::new(handler_id, name, request_timeout as u32, concurrent_threads as u32, executable);
+        new_handler.max_queue_length = max_queue_length as u32;
+        new_handler.queue_timeout = queue_timeout as u32;
+        new_handler.max_requests_per_worker = max_requests_per_worker as u32;
+        new_handler.max_worker_lifetime = max_worker_lifetime as u32;
+        Ok(new_handler)
+    })
 }
 
-fn load_php_cgi_handlers(connection: &Connection) -> Result<Vec<php_cgi::PhpCgi>, String> {
-    let mut statement = connection
-        .prepare("SELECT * FROM php_cgi_handlers")
-        .map_err(|e| format!("Failed to prepare PHP-CGI handlers query: {}", e))?;
+fn load_python_handlers(connection: &Connection) -> Result<Vec<PythonApp>, GruxiError> {
+    query(connection, "SELECT * FROM python_handlers", &[], |row| {
+        let handler_id = row.get_string("id")?;
+        let name = row.get_string("name")?;
+        let server_type = row.get_string("server_type")?;
+        let executable = row.get_string("executable")?;
+        let app_module = row.get_string("app_module")?;
+        let working_directory = row.get_string("working_directory")?;
+        let workers = row.get_i64("workers")?;
+        let extra_arguments_str = row.get_string("extra_arguments")?;
+        let extra_environment_str = row.get_string("extra_environment")?;
+        let health_check_path = row.get_string("health_check_path")?;
+        let max_concurrent_requests = row.get_i64("max_concurrent_requests")?;
+        let max_queue_length = row.get_i64("max_queue_length")?;
+        let queue_timeout = row.get_i64("queue_timeout")?;
+        let max_requests_per_worker = row.get_i64("max_requests_per_worker")?;
+        let max_worker_lifetime = row.get_i64("max_worker_lifetime")?;
+
+        let mut new_handler = PythonApp::new();
+        new_handler.id = handler_id;
+        new_handler.name = name;
+        new_handler.server_type = server_type;
+        new_handler.executable = executable;
+        new_handler.app_module = app_module;
+        new_handler.working_directory = working_directory;
+        new_handler.workers = workers as u32;
+        new_handler.health_check_path = health_check_path;
+        new_handler.max_concurrent_requests = max_concurrent_requests as u32;
+        new_handler.max_queue_length = max_queue_length as u32;
+        new_handler.queue_timeout = queue_timeout as u32;
+        new_handler.max_requests_per_worker = max_requests_per_worker as u32;
+        new_handler.max_worker_lifetime = max_worker_lifetime as u32;
+
+        // Arguments and environment are stored as JSON arrays
+        if !extra_arguments_str.is_empty() {
+            new_handler.extra_arguments = serde_json::from_str(&extra_arguments_str).map_err(|e| load_error(format!("Failed to parse extra_arguments JSON: {}", e)))?;
+        }
+        if !extra_environment_str.is_empty() {
+            new_handler.extra_environment = serde_json::from_str(&extra_environment_str).map_err(|e| load_error(format!("Failed to parse extra_environment JSON: {}", e)))?;
+        }
+
+        Ok(new_handler)
+    })
+}
 
-    let mut handlers = Vec::new();
-    while let sqlite::State::Row = statement.next().map_err(|e| format!("Failed to execute PHP-CGI handlers query: {}", e))? {
-        let handler_id: String = statement.read(0).map_err(|e| format!("Failed to read handler id: {}", e))?;
-        let name: String = statement.read(1).map_err(|e| format!("Failed to read name: {}", e))?;
-        let request_timeout: i64 = statement.read(2).map_err(|e| format!("Failed to read request_timeout: {}", e))?;
-        let concurrent_threads: i64 = statement.read(3).map_err(|e| format!("Failed to read concurrent_threads: {}", e))?;
-        let executable: String = statement.read(4).map_err(|e| format!("Failed to read executable: {}", e))?;
+fn load_node_handlers(connection: &Connection) -> Result<Vec<NodeApp>, GruxiError> {
+    query(connection, "SELECT * FROM node_handlers", &[], |row| {
+        let handler_id = row.get_string("id")?;
+        let name = row.get_string("name")?;
+        let executable = row.get_string("executable")?;
+        let entry_script = row.get_string("entry_script")?;
+        let working_directory = row.get_string("working_directory")?;
+        let extra_arguments_str = row.get_string("extra_arguments")?;
+        let extra_environment_str = row.get_string("extra_environment")?;
+        let health_check_path = row.get_string("health_check_path")?;
+        let max_concurrent_requests = row.get_i64("max_concurrent_requests")?;
+        let max_queue_length = row.get_i64("max_queue_length")?;
+        let queue_timeout = row.get_i64("queue_timeout")?;
+
+        let mut new_handler = NodeApp::new();
+        new_handler.id = handler_id;
+        new_handler.name = name;
+        new_handler.executable = executable;
+        new_handler.entry_script = entry_script;
+        new_handler.working_directory = working_directory;
+        new_handler.health_check_path = health_check_path;
+        new_handler.max_concurrent_requests = max_concurrent_requests as u32;
+        new_handler.max_queue_length = max_queue_length as u32;
+        new_handler.queue_timeout = queue_timeout as u32;
+
+        // Arguments and environment are stored as JSON arrays
+        if !extra_arguments_str.is_empty() {
+            new_handler.extra_arguments = serde_json::from_str(&extra_arguments_str).map_err(|e| load_error(format!("Failed to parse extra_arguments JSON: {}", e)))?;
+        }
+        if !extra_environment_str.is_empty() {
+            new_handler.extra_environment = serde_json::from_str(&extra_environment_str).map_err(|e| load_error(format!("Failed to parse extra_environment JSON: {}", e)))?;
+        }
 
-        handlers.push(php_cgi::PhpCgi::new(handler_id, name, request_timeout as u32, concurrent_threads as u32, executable));
-    }
+        Ok(new_handler)
+    })
+}
 
-    Ok(handlers)
+fn load_auth_providers(connection: &Connection) -> Result<Vec<AuthProvider>, GruxiError> {
+    query(connection, "SELECT * FROM auth_providers", &[], |row| {
+        let mut new_provider = AuthProvider::new();
+        new_provider.id = row.get_string("id")?;
+        new_provider.name = row.get_string("name")?;
+        new_provider.provider_type = row.get_string("provider_type")?;
+        new_provider.htpasswd_file = row.get_string("htpasswd_file")?;
+        new_provider.ldap_url = row.get_string("ldap_url")?;
+        new_provider.ldap_bind_dn_template = row.get_string("ldap_bind_dn_template")?;
+        new_provider.oidc_token_url = row.get_string("oidc_token_url")?;
+        new_provider.oidc_client_id = row.get_string("oidc_client_id")?;
+        new_provider.oidc_client_secret = row.get_string("oidc_client_secret")?;
+        new_provider.oidc_scope = row.get_string("oidc_scope")?;
+        let timeout_seconds = row.get_i64("timeout_seconds")?;
+        let cache_seconds = row.get_i64("cache_seconds")?;
+        new_provider.timeout_seconds = timeout_seconds as u32;
+        new_provider.cache_seconds = cache_seconds as u32;
+        new_provider.ldap_search_base_dn = row.get_string("ldap_search_base_dn")?;
+        new_provider.ldap_user_attribute = row.get_string("ldap_user_attribute")?;
+        new_provider.ldap_group_attribute = row.get_string("ldap_group_attribute")?;
+        new_provider.kerberos_keytab_file = row.get_string("kerberos_keytab_file")?;
+
+        Ok(new_provider)
+    })
 }
 
-fn load_core_config(connection: &Connection) -> Result<Core, String> {
-    // Load server settings (single record with id=1)
-    let mut statement = connection
-        .prepare("SELECT DISTINCT setting_key, setting_value FROM server_settings")
-        .map_err(|e| format!("Failed to prepare server settings query: {}", e))?;
+fn load_command_hooks(connection: &Connection) -> Result<Vec<CommandHook>, GruxiError> {
+    query(connection, "SELECT * FROM command_hooks", &[], |row| {
+        let mut new_hook = CommandHook::new();
+        new_hook.id = row.get_string("id")?;
+        new_hook.name = row.get_string("name")?;
+        new_hook.event = row.get_string("event")?;
+        new_hook.command = row.get_string("command")?;
+        let arguments_str = row.get_string("arguments")?;
+        new_hook.working_directory = row.get_string("working_directory")?;
+        let timeout_seconds = row.get_i64("timeout_seconds")?;
+        let is_enabled_int = row.get_i64("is_enabled")?;
+        new_hook.timeout_seconds = timeout_seconds as u32;
+        new_hook.is_enabled = is_enabled_int != 0;
+
+        // Arguments are stored as a JSON array
+        if !arguments_str.is_empty() {
+            new_hook.arguments = serde_json::from_str(&arguments_str).map_err(|e| load_error(format!("Failed to parse arguments JSON: {}", e)))?;
+        }
+
+        Ok(new_hook)
+    })
+}
+
+fn load_core_config(connection: &Connection) -> Result<Core, GruxiError> {
+    // Load server settings, as key/value pairs
+    let settings = query(connection, "SELECT DISTINCT setting_key, setting_value FROM server_settings", &[], |row| {
+        Ok((row.get_string("setting_key")?, row.get_string("setting_value")?))
+    })?;
 
     // Get the default configuration for core
     let configuration = Configuration::get_default();
     let mut core = configuration.core;
 
     // Each row is a key/value pair, where key should be checked against known settings in the server settings struct
-    while let sqlite::State::Row = statement.next().map_err(|e| format!("Failed to execute core settings query: {}", e))? {
-        let key: String = statement.read(0).map_err(|e| format!("Failed to read key: {}", e))?;
-        let value: String = statement.read(1).map_err(|e| format!("Failed to read value: {}", e))?;
-
-        match key.as_str() {
+    // Settings saved under a deprecated key are read as the current key, unless it is saved under the current key too
+    let saved_keys: Vec<String> = settings.iter().map(|(key, _)| key.clone()).collect();
+    for (key, value) in settings {
+        let current_key = get_current_setting_key(&key);
+        if current_key != key && saved_keys.iter().any(|saved_key| saved_key == current_key) {
+            continue;
+        }
+        match current_key {
             // File cache
             "file_cache_is_enabled" => {
-                core.file_cache.is_enabled = value.parse::<bool>().map_err(|e| format!("Failed to parse file_cache_is_enabled: {}", e))?;
+                core.file_cache.is_enabled = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse file_cache_is_enabled: {}", e)))?;
             }
             "file_cache_cache_item_size" => {
-                core.file_cache.cache_item_size = value.parse::<usize>().map_err(|e| format!("Failed to parse file_cache_cache_item_size: {}", e))?;
+                core.file_cache.cache_item_size = value.parse::<usize>().map_err(|e| load_error(format!("Failed to parse file_cache_cache_item_size: {}", e)))?;
             }
             "file_cache_cache_max_size_per_file" => {
-                core.file_cache.cache_max_size_per_file = value.parse::<usize>().map_err(|e| format!("Failed to parse file_cache_cache_max_size_per_file: {}", e))?;
+                core.file_cache.cache_max_size_per_file = value.parse::<usize>().map_err(|e| load_error(format!("Failed to parse file_cache_cache_max_size_per_file: {}", e)))?;
             }
             "file_cache_cache_item_time_between_checks" => {
-                core.file_cache.cache_item_time_between_checks = value.parse::<usize>().map_err(|e| format!("Failed to parse file_cache_cache_item_time_between_checks: {}", e))?;
+                core.file_cache.cache_item_time_between_checks = value
+                    .parse::<usize>()
+                    .map_err(|e| load_error(format!("Failed to parse file_cache_cache_item_time_between_checks: {}", e)))?;
             }
             "file_cache_cleanup_thread_interval" => {
-                core.file_cache.cleanup_thread_interval = value.parse::<usize>().map_err(|e| format!("Failed to parse file_cache_cleanup_thread_interval: {}", e))?;
+                core.file_cache.cleanup_thread_interval = value.parse::<usize>().map_err(|e| load_error(format!("Failed to parse file_cache_cleanup_thread_interval: {}", e)))?;
             }
             "file_cache_max_item_lifetime" => {
-                core.file_cache.max_item_lifetime = value.parse::<usize>().map_err(|e| format!("Failed to parse file_cache_max_item_lifetime: {}", e))?;
+                core.file_cache.max_item_lifetime = value.parse::<usize>().map_err(|e| load_error(format!("Failed to parse file_cache_max_item_lifetime: {}", e)))?;
             }
             "file_cache_forced_eviction_threshold" => {
-                core.file_cache.forced_eviction_threshold = value.parse::<usize>().map_err(|e| format!("Failed to parse file_cache_forced_eviction_threshold: {}", e))?;
+                core.file_cache.forced_eviction_threshold = value.parse::<usize>().map_err(|e| load_error(format!("Failed to parse file_cache_forced_eviction_threshold: {}", e)))?;
+            }
+            "file_cache_open_file_cache_max_entries" => {
+                core.file_cache.open_file_cache_max_entries = value
+                    .parse::<usize>()
+                    .map_err(|e| load_error(format!("Failed to parse file_cache_open_file_cache_max_entries: {}", e)))?;
+            }
+            "file_cache_open_file_cache_revalidate_seconds" => {
+                core.file_cache.open_file_cache_revalidate_seconds = value
+                    .parse::<usize>()
+                    .map_err(|e| load_error(format!("Failed to parse file_cache_open_file_cache_revalidate_seconds: {}", e)))?;
+            }
+            "file_cache_mmap_is_enabled" => {
+                core.file_cache.mmap_is_enabled = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse file_cache_mmap_is_enabled: {}", e)))?;
+            }
+            "file_cache_mmap_min_file_size" => {
+                core.file_cache.mmap_min_file_size = value.parse::<usize>().map_err(|e| load_error(format!("Failed to parse file_cache_mmap_min_file_size: {}", e)))?;
+            }
+            "file_cache_mmap_max_file_size" => {
+                core.file_cache.mmap_max_file_size = value.parse::<usize>().map_err(|e| load_error(format!("Failed to parse file_cache_mmap_max_file_size: {}", e)))?;
+            }
+            "file_cache_compressed_variants_max_size" => {
+                core.file_cache.compressed_variants_max_size = value
+                    .parse::<usize>()
+                    .map_err(|e| load_error(format!("Failed to parse file_cache_compressed_variants_max_size: {}", e)))?;
             }
             // Gzip
             "gzip_is_enabled" => {
-                core.gzip.is_enabled = value.parse::<bool>().map_err(|e| format!("Failed to parse gzip_is_enabled: {}", e))?;
+                core.gzip.is_enabled = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse gzip_is_enabled: {}", e)))?;
             }
             "gzip_compressible_content_types" => {
                 core.gzip.compressible_content_types = parse_comma_separated_list(&value, true);
@@ -317,18 +760,39 @@ fn load_core_config(connection: &Connection) -> Result<Core, String> {
 
             // Server settings
             "max_body_size" => {
-                core.server_settings.max_body_size = value.parse::<u64>().map_err(|e| format!("Failed to parse max_body_size: {}", e))?;
+                core.server_settings.max_body_size = value.parse::<u64>().map_err(|e| load_error(format!("Failed to parse max_body_size: {}", e)))?;
             }
             "blocked_file_patterns" => {
                 core.server_settings.blocked_file_patterns = parse_comma_separated_list(&value, true);
             }
+            "http_version_policy" => {
+                core.server_settings.http_version_policy = value;
+            }
+            "trace_method_enabled" => {
+                core.server_settings.trace_method_enabled = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse trace_method_enabled: {}", e)))?;
+            }
+            "encoded_slash_policy" => {
+                core.server_settings.encoded_slash_policy = value;
+            }
+            "max_header_count" => {
+                core.server_settings.max_header_count = value.parse::<usize>().map_err(|e| load_error(format!("Failed to parse max_header_count: {}", e)))?;
+            }
+            "max_header_size" => {
+                core.server_settings.max_header_size = value.parse::<usize>().map_err(|e| load_error(format!("Failed to parse max_header_size: {}", e)))?;
+            }
+            "max_uri_length" => {
+                core.server_settings.max_uri_length = value.parse::<usize>().map_err(|e| load_error(format!("Failed to parse max_uri_length: {}", e)))?;
+            }
+            "max_chunk_size" => {
+                core.server_settings.max_chunk_size = value.parse::<u64>().map_err(|e| load_error(format!("Failed to parse max_chunk_size: {}", e)))?;
+            }
 
             // Admin portal settings
             "admin_portal_domain_name" => {
                 core.admin_portal.domain_name = value;
             }
             "admin_portal_tls_automatic_enabled" => {
-                core.admin_portal.tls_automatic_enabled = value.parse::<bool>().map_err(|e| format!("Failed to parse admin_portal_tls_automatic_enabled: {}", e))?;
+                core.admin_portal.tls_automatic_enabled = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse admin_portal_tls_automatic_enabled: {}", e)))?;
             }
             "admin_portal_tls_certificate_path" => {
                 core.admin_portal.tls_certificate_path = Some(value);
@@ -336,17 +800,267 @@ fn load_core_config(connection: &Connection) -> Result<Core, String> {
             "admin_portal_tls_key_path" => {
                 core.admin_portal.tls_key_path = Some(value);
             }
+            "admin_portal_auth_provider_id" => {
+                core.admin_portal.auth_provider_id = value;
+            }
+            "admin_portal_allow_local_login" => {
+                core.admin_portal.allow_local_login = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse admin_portal_allow_local_login: {}", e)))?;
+            }
+            "admin_portal_shared_session_database" => {
+                core.admin_portal.shared_session_database = value;
+            }
+            "admin_portal_session_bind_ip" => {
+                core.admin_portal.session_bind_ip = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse admin_portal_session_bind_ip: {}", e)))?;
+            }
+            "admin_portal_session_bind_user_agent" => {
+                core.admin_portal.session_bind_user_agent = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse admin_portal_session_bind_user_agent: {}", e)))?;
+            }
+            "admin_portal_session_client_ip_header" => {
+                core.admin_portal.session_client_ip_header = value;
+            }
 
             // TLS settings
             "tls_account_email" => {
                 core.tls_settings.account_email = value;
             }
             "tls_use_staging_server" => {
-                core.tls_settings.use_staging_server = value.parse::<bool>().map_err(|e| format!("Failed to parse tls_use_staging_server: {}", e))?;
+                core.tls_settings.use_staging_server = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse tls_use_staging_server: {}", e)))?;
             }
             "tls_certificate_cache_path" => {
                 core.tls_settings.certificate_cache_path = value;
             }
+            "tls_certificate_cache_backend" => {
+                core.tls_settings.certificate_cache_backend = value;
+            }
+            "tls_certificate_cache_database" => {
+                core.tls_settings.certificate_cache_database = value;
+            }
+            "tls_certificate_cache_s3_endpoint" => {
+                core.tls_settings.certificate_cache_s3_endpoint = value;
+            }
+            "tls_certificate_cache_s3_bucket" => {
+                core.tls_settings.certificate_cache_s3_bucket = value;
+            }
+            "tls_certificate_cache_s3_region" => {
+                core.tls_settings.certificate_cache_s3_region = value;
+            }
+            "tls_certificate_cache_s3_access_key_id" => {
+                core.tls_settings.certificate_cache_s3_access_key_id = value;
+            }
+            "tls_certificate_cache_s3_secret_access_key" => {
+                core.tls_settings.certificate_cache_s3_secret_access_key = value;
+            }
+            "tls_ct_monitoring_enabled" => {
+                core.tls_settings.ct_monitoring_enabled = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse tls_ct_monitoring_enabled: {}", e)))?;
+            }
+            "tls_ct_monitoring_interval_minutes" => {
+                core.tls_settings.ct_monitoring_interval_minutes = value.parse::<u32>().map_err(|e| load_error(format!("Failed to parse tls_ct_monitoring_interval_minutes: {}", e)))?;
+            }
+            "tls_ct_monitoring_webhook_url" => {
+                core.tls_settings.ct_monitoring_webhook_url = value;
+            }
+            "tls_session_resumption_enabled" => {
+                core.tls_settings.session_resumption_enabled = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse tls_session_resumption_enabled: {}", e)))?;
+            }
+            "tls_session_ticket_lifetime_seconds" => {
+                core.tls_settings.session_ticket_lifetime_seconds = value.parse::<u32>().map_err(|e| load_error(format!("Failed to parse tls_session_ticket_lifetime_seconds: {}", e)))?;
+            }
+            "tls_acme_challenge_type" => {
+                core.tls_settings.acme_challenge_type = value;
+            }
+            "tls_acme_provider" => {
+                core.tls_settings.acme_provider = value;
+            }
+            "tls_acme_directory_url" => {
+                core.tls_settings.acme_directory_url = value;
+            }
+            "tls_acme_eab_key_id" => {
+                core.tls_settings.acme_eab_key_id = value;
+            }
+            "tls_acme_eab_hmac_key" => {
+                core.tls_settings.acme_eab_hmac_key = value;
+            }
+            "tls_consolidate_acme_orders" => {
+                core.tls_settings.consolidate_acme_orders = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse tls_consolidate_acme_orders: {}", e)))?;
+            }
+            "upload_scanning_is_enabled" => {
+                core.upload_scanning.is_enabled = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse upload_scanning_is_enabled: {}", e)))?;
+            }
+            "upload_scanning_scanner_type" => {
+                core.upload_scanning.scanner_type = value;
+            }
+            "upload_scanning_command" => {
+                core.upload_scanning.command = value;
+            }
+            "upload_scanning_icap_url" => {
+                core.upload_scanning.icap_url = value;
+            }
+            "upload_scanning_timeout_seconds" => {
+                core.upload_scanning.timeout_seconds = value.parse::<u32>().map_err(|e| load_error(format!("Failed to parse upload_scanning_timeout_seconds: {}", e)))?;
+            }
+            "upload_scanning_quarantine_path" => {
+                core.upload_scanning.quarantine_path = value;
+            }
+
+            // Usage report settings
+            "usage_reports_billing_period_start_day" => {
+                core.usage_reports.billing_period_start_day = value.parse::<u32>().map_err(|e| load_error(format!("Failed to parse usage_reports_billing_period_start_day: {}", e)))?;
+            }
+            "usage_reports_webhook_url" => {
+                core.usage_reports.webhook_url = value;
+            }
+            "usage_reports_webhook_secret" => {
+                core.usage_reports.webhook_secret = value;
+            }
+
+            // DNS resolution settings
+            "dns_resolution_mode" => {
+                core.dns_resolution.mode = value;
+            }
+            "dns_resolution_server_address" => {
+                core.dns_resolution.server_address = value;
+            }
+            "dns_resolution_server_name" => {
+                core.dns_resolution.server_name = value;
+            }
+            "dns_resolution_doh_path" => {
+                core.dns_resolution.doh_path = value;
+            }
+            "dns_resolution_address_preference" => {
+                core.dns_resolution.address_preference = value;
+            }
+            "dns_resolution_happy_eyeballs_delay_ms" => {
+                core.dns_resolution.happy_eyeballs_delay_ms = value.parse::<u32>().map_err(|e| load_error(format!("Failed to parse dns_resolution_happy_eyeballs_delay_ms: {}", e)))?;
+            }
+
+            // Cluster sync settings
+            "cluster_sync_role" => {
+                core.cluster_sync.role = value;
+            }
+            "cluster_sync_primary_url" => {
+                core.cluster_sync.primary_url = value;
+            }
+            "cluster_sync_shared_token" => {
+                core.cluster_sync.shared_token = value;
+            }
+            "cluster_sync_poll_interval_seconds" => {
+                core.cluster_sync.poll_interval_seconds = value.parse::<u32>().map_err(|e| load_error(format!("Failed to parse cluster_sync_poll_interval_seconds: {}", e)))?;
+            }
+            "cluster_sync_verify_tls_certificates" => {
+                core.cluster_sync.verify_tls_certificates = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse cluster_sync_verify_tls_certificates: {}", e)))?;
+            }
+
+            // Database backup settings
+            "database_backup_is_enabled" => {
+                core.database_backup.is_enabled = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse database_backup_is_enabled: {}", e)))?;
+            }
+            "database_backup_directory" => {
+                core.database_backup.directory = value;
+            }
+            "database_backup_interval_hours" => {
+                core.database_backup.interval_hours = value.parse::<u32>().map_err(|e| load_error(format!("Failed to parse database_backup_interval_hours: {}", e)))?;
+            }
+            "database_backup_retention_count" => {
+                core.database_backup.retention_count = value.parse::<u32>().map_err(|e| load_error(format!("Failed to parse database_backup_retention_count: {}", e)))?;
+            }
+
+            // Request priority settings
+            "request_priority_is_enabled" => {
+                core.request_priority.is_enabled = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse request_priority_is_enabled: {}", e)))?;
+            }
+            "request_priority_max_concurrent_requests" => {
+                core.request_priority.max_concurrent_requests = value
+                    .parse::<u32>()
+                    .map_err(|e| load_error(format!("Failed to parse request_priority_max_concurrent_requests: {}", e)))?;
+            }
+            "request_priority_max_queue_length" => {
+                core.request_priority.max_queue_length = value.parse::<u32>().map_err(|e| load_error(format!("Failed to parse request_priority_max_queue_length: {}", e)))?;
+            }
+            "request_priority_queue_timeout_seconds" => {
+                core.request_priority.queue_timeout_seconds = value.parse::<u32>().map_err(|e| load_error(format!("Failed to parse request_priority_queue_timeout_seconds: {}", e)))?;
+            }
+            "request_priority_high_priority_paths" => {
+                core.request_priority.high_priority_paths = parse_comma_separated_list(&value, false);
+            }
+            "request_priority_low_priority_paths" => {
+                core.request_priority.low_priority_paths = parse_comma_separated_list(&value, false);
+            }
+            // Email alert settings
+            "email_alerts_is_enabled" => {
+                core.email_alerts.is_enabled = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse email_alerts_is_enabled: {}", e)))?;
+            }
+            "email_alerts_smtp_host" => {
+                core.email_alerts.smtp_host = value;
+            }
+            "email_alerts_smtp_port" => {
+                core.email_alerts.smtp_port = value.parse::<u16>().map_err(|e| load_error(format!("Failed to parse email_alerts_smtp_port: {}", e)))?;
+            }
+            "email_alerts_smtp_tls_mode" => {
+                core.email_alerts.smtp_tls_mode = value;
+            }
+            "email_alerts_smtp_verify_certificates" => {
+                core.email_alerts.smtp_verify_certificates = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse email_alerts_smtp_verify_certificates: {}", e)))?;
+            }
+            "email_alerts_smtp_username" => {
+                core.email_alerts.smtp_username = value;
+            }
+            "email_alerts_smtp_password" => {
+                core.email_alerts.smtp_password = value;
+            }
+            "email_alerts_from_address" => {
+                core.email_alerts.from_address = value;
+            }
+            "email_alerts_routes" => {
+                // Routes are stored as JSON
+                if !value.is_empty() {
+                    core.email_alerts.routes = serde_json::from_str(&value).map_err(|e| load_error(format!("Failed to parse email_alerts_routes: {}", e)))?;
+                }
+            }
+            "email_alerts_repeat_interval_minutes" => {
+                core.email_alerts.repeat_interval_minutes = value.parse::<u32>().map_err(|e| load_error(format!("Failed to parse email_alerts_repeat_interval_minutes: {}", e)))?;
+            }
+            "status_page_is_enabled" => {
+                core.status_page.is_enabled = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse status_page_is_enabled: {}", e)))?;
+            }
+            "status_page_title" => {
+                core.status_page.title = value;
+            }
+            "status_page_site_ids" => {
+                core.status_page.site_ids = parse_comma_separated_list(&value, false);
+            }
+            "status_page_incident_count" => {
+                core.status_page.incident_count = value.parse::<u32>().map_err(|e| load_error(format!("Failed to parse status_page_incident_count: {}", e)))?;
+            }
+            "status_page_show_incident_messages" => {
+                core.status_page.show_incident_messages = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse status_page_show_incident_messages: {}", e)))?;
+            }
+            "health_probes_is_enabled" => {
+                core.health_probes.is_enabled = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse health_probes_is_enabled: {}", e)))?;
+            }
+            "health_probes_liveness_path" => {
+                core.health_probes.liveness_path = value;
+            }
+            "health_probes_readiness_path" => {
+                core.health_probes.readiness_path = value;
+            }
+            "health_probes_allowed_ips" => {
+                core.health_probes.allowed_ips = parse_comma_separated_list(&value, false);
+            }
+            "landing_page_is_enabled" => {
+                core.landing_page.is_enabled = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse landing_page_is_enabled: {}", e)))?;
+            }
+            "landing_page_title" => {
+                core.landing_page.title = value;
+            }
+            "landing_page_message" => {
+                core.landing_page.message = value;
+            }
+            "landing_page_show_sites" => {
+                core.landing_page.show_sites = value.parse::<bool>().map_err(|e| load_error(format!("Failed to parse landing_page_show_sites: {}", e)))?;
+            }
+            "landing_page_format" => {
+                core.landing_page.format = value;
+            }
             _ => continue,
         }
     }
@@ -354,73 +1068,212 @@ fn load_core_config(connection: &Connection) -> Result<Core, String> {
     Ok(core)
 }
 
-fn load_bindings(connection: &Connection) -> Result<Vec<Binding>, String> {
-    let mut statement = connection.prepare("SELECT * FROM bindings").map_err(|e| format!("Failed to prepare bindings query: {}", e))?;
-
-    let mut bindings = Vec::new();
-    while let sqlite::State::Row = statement.next().map_err(|e| format!("Failed to execute bindings query: {}", e))? {
-        let binding_id: String = statement.read(0).map_err(|e| format!("Failed to read binding id: {}", e))?;
-        let ip: String = statement.read(1).map_err(|e| format!("Failed to read ip: {}", e))?;
-        let port: i64 = statement.read(2).map_err(|e| format!("Failed to read port: {}", e))?;
-        let is_admin: i64 = statement.read(3).map_err(|e| format!("Failed to read is_admin: {}", e))?;
-        let is_tls: i64 = statement.read(4).map_err(|e| format!("Failed to read is_tls: {}", e))?;
-
-        bindings.push(Binding {
+fn load_bindings(connection: &Connection) -> Result<Vec<Binding>, GruxiError> {
+    query(connection, "SELECT * FROM bindings", &[], |row| {
+        let binding_id = row.get_string("id")?;
+        let ip = row.get_string("ip")?;
+        let port = row.get_i64("port")?;
+        let is_admin = row.get_i64("is_admin")?;
+        let is_tls = row.get_i64("is_tls")?;
+        // Connection settings (added in schema version 29)
+        let http10_strict_close = row.get_i64("http10_strict_close").ok().unwrap_or(0);
+        let keep_alive_timeout_seconds = row.get_i64("keep_alive_timeout_seconds").ok().unwrap_or(0);
+        // Keep-alive request limit (added in schema version 37)
+        let max_keep_alive_requests = row.get_i64("max_keep_alive_requests").ok().unwrap_or(0);
+        // HTTP version pinning (added in schema version 33)
+        let http_versions = row.get_string("http_versions").ok().unwrap_or_else(|| "auto".to_string());
+        // Host and SNI mismatch handling (added in schema version 39)
+        let sni_mismatch_policy = row.get_string("sni_mismatch_policy").ok().unwrap_or_else(|| "allow".to_string());
+        // Default site and unknown host handling (added in schema version 40)
+        let default_site_id = row.get_string("default_site_id").ok().unwrap_or_default();
+        let unknown_host_policy = row.get_string("unknown_host_policy").ok().unwrap_or_else(|| "default".to_string());
+
+        Ok(Binding {
             id: binding_id,
             ip,
             port: port as u16,
             is_admin: is_admin != 0,
             is_tls: is_tls != 0,
-        });
-    }
-
-    Ok(bindings)
+            http10_strict_close: http10_strict_close != 0,
+            keep_alive_timeout_seconds: keep_alive_timeout_seconds as u32,
+            max_keep_alive_requests: max_keep_alive_requests as u32,
+            http_versions,
+            sni_mismatch_policy,
+            default_site_id,
+            unknown_host_policy,
+        })
+    })
 }
 
-fn load_sites(connection: &Connection) -> Result<Vec<Site>, String> {
-    let mut statement = connection.prepare("SELECT * FROM sites").map_err(|e| format!("Failed to prepare sites query: {}", e))?;
-
-    let mut sites = Vec::new();
-    while let sqlite::State::Row = statement.next().map_err(|e| format!("Failed to execute sites query: {}", e))? {
-        let site_id: String = statement.read(0).map_err(|e| format!("Failed to read site id: {}", e))?;
-        let is_default: i64 = statement.read(1).map_err(|e| format!("Failed to read is_default: {}", e))?;
-        let is_enabled: i64 = statement.read(2).map_err(|e| format!("Failed to read is_enabled: {}", e))?;
+fn load_sites(connection: &Connection) -> Result<Vec<Site>, GruxiError> {
+    query(connection, "SELECT * FROM sites", &[], |row| {
+        let site_id = row.get_string("id")?;
+        let is_default = row.get_i64("is_default")?;
+        let is_enabled = row.get_i64("is_enabled")?;
 
         // Hostnames is comma separated
-        let hostnames_str: String = statement.read(3).map_err(|e| format!("Failed to read hostnames: {}", e))?;
-        let hostnames = parse_comma_separated_list(&hostnames_str, true);
-
-        let tls_cert_path: String = statement.read(4).ok().unwrap_or_default();
-        let tls_cert_content: String = statement.read(5).ok().unwrap_or_default();
-        let tls_key_path: String = statement.read(6).ok().unwrap_or_default();
-        let tls_key_content: String = statement.read(7).ok().unwrap_or_default();
+        let hostnames_str = row.get_string("hostnames")?;
+        // Regular expression hostnames keep their case, as lowercasing changes escapes such as \D
+        let hostnames = parse_comma_separated_list(&hostnames_str, false)
+            .into_iter()
+            .map(|hostname| if is_regex_hostname(&hostname) { hostname } else { hostname.to_lowercase() })
+            .collect();
+
+        let tls_cert_path = row.get_string("tls_cert_path").ok().unwrap_or_default();
+        let tls_cert_content = row.get_string("tls_cert_content").ok().unwrap_or_default();
+        let tls_key_path = row.get_string("tls_key_path").ok().unwrap_or_default();
+        let tls_key_content = row.get_string("tls_key_content").ok().unwrap_or_default();
 
         // Request handlers is comma separated
-        let request_handlers_str: String = statement.read(8).map_err(|e| format!("Failed to read request_handlers: {}", e))?;
+        let request_handlers_str = row.get_string("request_handlers")?;
         let request_handlers: Vec<String> = parse_comma_separated_list(&request_handlers_str, false);
 
         // Rewrite functions is comma separated
-        let rewrite_functions_str: String = statement.read(9).map_err(|e| format!("Failed to read rewrite_functions: {}", e))?;
+        let rewrite_functions_str = row.get_string("rewrite_functions")?;
         let rewrite_functions: Vec<String> = parse_comma_separated_list(&rewrite_functions_str, false);
 
         // Access log
-        let access_log_enabled: i64 = statement.read(10).map_err(|e| format!("Failed to read access_log_enabled: {}", e))?;
-        let access_log_file: String = statement.read(11).map_err(|e| format!("Failed to read access_log_file: {}", e))?;
+        let access_log_enabled = row.get_i64("access_log_enabled")?;
+        let access_log_file = row.get_string("access_log_file")?;
 
         // Optional extra_headers column (comma separated key=value)
-        let extra_headers_str: String = statement.read(12).ok().unwrap_or_default();
+        let extra_headers_str = row.get_string("extra_headers").ok().unwrap_or_default();
         let extra_headers_pairs = parse_key_value_pairs(&extra_headers_str);
         let extra_headers: Vec<HeaderKV> = extra_headers_pairs.into_iter().map(|(k, v)| HeaderKV { key: k, value: v }).collect();
 
         // TLS Automatic Enabled (added in schema version 4)
-        let tls_automatic_enabled: i64 = statement.read(13).map_err(|e| format!("Failed to read tls_automatic_enabled: {}", e))?;
+        let tls_automatic_enabled = row.get_i64("tls_automatic_enabled")?;
+
+        // Locations is stored as JSON (added in schema version 5)
+        let locations_str = row.get_string("locations").ok().unwrap_or_default();
+        let locations: Vec<Location> = if locations_str.is_empty() {
+            Vec::new()
+        } else {
+            serde_json::from_str(&locations_str).map_err(|e| load_error(format!("Failed to parse locations JSON: {}", e)))?
+        };
+
+        // PHP ini settings and environment variables are stored as JSON (added in schema version 17)
+        let php_ini_settings_str = row.get_string("php_ini_settings").ok().unwrap_or_default();
+        let php_ini_settings: Vec<PhpIniSetting> = if php_ini_settings_str.is_empty() {
+            Vec::new()
+        } else {
+            serde_json::from_str(&php_ini_settings_str).map_err(|e| load_error(format!("Failed to parse PHP ini settings JSON: {}", e)))?
+        };
+        let php_environment_str = row.get_string("php_environment").ok().unwrap_or_default();
+        let php_environment: Vec<EnvironmentVariable> = if php_environment_str.is_empty() {
+            Vec::new()
+        } else {
+            serde_json::from_str(&php_environment_str).map_err(|e| load_error(format!("Failed to parse PHP environment JSON: {}", e)))?
+        };
+
+        // Sendfile root (added in schema version 18)
+        let sendfile_root = row.get_string("sendfile_root").ok().unwrap_or_default();
+
+        // WebSocket limits are stored as JSON (added in schema version 23)
+        let websocket_str = row.get_string("websocket").ok().unwrap_or_default();
+        let websocket: WebSocketSettings = if websocket_str.is_empty() {
+            WebSocketSettings::new()
+        } else {
+            serde_json::from_str(&websocket_str).map_err(|e| load_error(format!("Failed to parse WebSocket settings JSON: {}", e)))?
+        };
+
+        // Webroot sync is stored as JSON (added in schema version 26)
+        let webroot_sync_str = row.get_string("webroot_sync").ok().unwrap_or_default();
+        let webroot_sync: WebrootSyncSettings = if webroot_sync_str.is_empty() {
+            WebrootSyncSettings::new()
+        } else {
+            serde_json::from_str(&webroot_sync_str).map_err(|e| load_error(format!("Failed to parse webroot sync JSON: {}", e)))?
+        };
+
+        // Cache warming is stored as JSON (added in schema version 28)
+        let cache_warm_str = row.get_string("cache_warm").ok().unwrap_or_default();
+        let cache_warm: CacheWarmSettings = if cache_warm_str.is_empty() {
+            CacheWarmSettings::new()
+        } else {
+            serde_json::from_str(&cache_warm_str).map_err(|e| load_error(format!("Failed to parse cache warming JSON: {}", e)))?
+        };
+
+        // Bandwidth limits are stored as JSON (added in schema version 38)
+        let bandwidth_str = row.get_string("bandwidth").ok().unwrap_or_default();
+        let bandwidth: BandwidthSettings = if bandwidth_str.is_empty() {
+            BandwidthSettings::new()
+        } else {
+            serde_json::from_str(&bandwidth_str).map_err(|e| load_error(format!("Failed to parse bandwidth limits JSON: {}", e)))?
+        };
+
+        // Web application firewall is stored as JSON (added in schema version 41)
+        let waf_str = row.get_string("waf").ok().unwrap_or_default();
+        let waf: WafSettings = if waf_str.is_empty() {
+            WafSettings::new()
+        } else {
+            serde_json::from_str(&waf_str).map_err(|e| load_error(format!("Failed to parse web application firewall JSON: {}", e)))?
+        };
+
+        // Bot rules are stored as JSON (added in schema version 42)
+        let bots_str = row.get_string("bots").ok().unwrap_or_default();
+        let bots: BotSettings = if bots_str.is_empty() {
+            BotSettings::new()
+        } else {
+            serde_json::from_str(&bots_str).map_err(|e| load_error(format!("Failed to parse bot rules JSON: {}", e)))?
+        };
+
+        // Middleware chain is comma separated, empty for the default chain (added in schema version 43)
+        let middleware_str = row.get_string("middleware").ok().unwrap_or_default();
+        let middleware: Vec<String> = parse_comma_separated_list(&middleware_str, true);
+
+        // Plugins are stored as JSON (added in schema version 44)
+        let plugins_str = row.get_string("plugins").ok().unwrap_or_default();
+        let plugins: Vec<WasmPlugin> = if plugins_str.is_empty() {
+            Vec::new()
+        } else {
+            serde_json::from_str(&plugins_str).map_err(|e| load_error(format!("Failed to parse plugins JSON: {}", e)))?
+        };
+
+        // Lua hooks are stored as JSON (added in schema version 45)
+        let lua_hooks_str = row.get_string("lua_hooks").ok().unwrap_or_default();
+        let lua_hooks: Vec<LuaHook> = if lua_hooks_str.is_empty() {
+            Vec::new()
+        } else {
+            serde_json::from_str(&lua_hooks_str).map_err(|e| load_error(format!("Failed to parse Lua hooks JSON: {}", e)))?
+        };
+
+        // Error response format (added in schema version 36)
+        let error_response_format = row.get_string("error_response_format").ok().unwrap_or_else(|| "html".to_string());
+
+        // Disk quota (added in schema version 47)
+        let disk_quota_mb = row.get_i64("disk_quota_mb").ok().unwrap_or(0).max(0) as u64;
+
+        // Synthetic probe is stored as JSON (added in schema version 49)
+        let synthetic_probe_str = row.get_string("synthetic_probe").ok().unwrap_or_default();
+        let synthetic_probe: SyntheticProbeSettings = if synthetic_probe_str.is_empty() {
+            SyntheticProbeSettings::new()
+        } else {
+            serde_json::from_str(&synthetic_probe_str).map_err(|e| load_error(format!("Failed to parse synthetic probe JSON: {}", e)))?
+        };
 
-        sites.push(Site {
+        // Cache header rules are stored as JSON (added in schema version 50)
+        let cache_header_rules_str = row.get_string("cache_header_rules").ok().unwrap_or_default();
+        let cache_header_rules: Vec<CacheHeaderRule> = if cache_header_rules_str.is_empty() {
+            Vec::new()
+        } else {
+            serde_json::from_str(&cache_header_rules_str).map_err(|e| load_error(format!("Failed to parse cache header rules JSON: {}", e)))?
+        };
+
+        // ACME settings are stored as JSON (added in schema version 52)
+        let acme_str = row.get_string("acme").ok().unwrap_or_default();
+        let acme: SiteAcmeSettings = if acme_str.is_empty() {
+            SiteAcmeSettings::new()
+        } else {
+            serde_json::from_str(&acme_str).map_err(|e| load_error(format!("Failed to parse ACME settings JSON: {}", e)))?
+        };
+
+        Ok(Site {
             id: site_id,
             hostnames,
             is_default: is_default != 0,
             is_enabled: is_enabled != 0,
             tls_automatic_enabled: tls_automatic_enabled != 0,
+            acme,
             tls_cert_path,
             tls_cert_content,
             tls_key_path,
@@ -430,82 +1283,87 @@ fn load_sites(connection: &Connection) -> Result<Vec<Site>, String> {
             access_log_enabled: access_log_enabled != 0,
             access_log_file,
             extra_headers,
-        });
-    }
-
-    Ok(sites)
+            locations,
+            php_ini_settings,
+            php_environment,
+            sendfile_root,
+            websocket,
+            webroot_sync,
+            cache_warm,
+            bandwidth,
+            waf,
+            bots,
+            middleware,
+            plugins,
+            lua_hooks,
+            error_response_format,
+            disk_quota_mb,
+            synthetic_probe,
+            cache_header_rules,
+            hostname_patterns: OnceLock::new(),
+            location_sites: OnceLock::new(),
+        })
+    })
 }
-fn load_binding_sites_relationships(connection: &Connection) -> Result<Vec<BindingSiteRelationship>, String> {
-    let mut statement = connection
-        .prepare("SELECT DISTINCT binding_id, site_id FROM binding_sites")
-        .map_err(|e| format!("Failed to prepare binding_sites query: {}", e))?;
-
-    let mut binding_sites = Vec::new();
-    while let sqlite::State::Row = statement.next().map_err(|e| format!("Failed to execute binding_sites query: {}", e))? {
-        let binding_id: String = statement.read(0).map_err(|e| format!("Failed to read binding_id: {}", e))?;
-        let site_id: String = statement.read(1).map_err(|e| format!("Failed to read site_id: {}", e))?;
+fn load_binding_sites_relationships(connection: &Connection) -> Result<Vec<BindingSiteRelationship>, GruxiError> {
+    query(connection, "SELECT DISTINCT binding_id, site_id FROM binding_sites", &[], |row| {
+        let binding_id = row.get_string("binding_id")?;
+        let site_id = row.get_string("site_id")?;
 
-        binding_sites.push(BindingSiteRelationship {
+        Ok(BindingSiteRelationship {
             binding_id: binding_id,
             site_id: site_id,
-        });
-    }
-
-    Ok(binding_sites)
+        })
+    })
 }
 
-fn load_request_handlers(connection: &Connection) -> Result<Vec<RequestHandler>, String> {
-    let mut statement = connection
-        // Select explicit columns to remain compatible with older schemas that may still have a legacy 'priority' column.
-        .prepare("SELECT id, is_enabled, name, processor_type, processor_id, url_match FROM request_handler")
-        .map_err(|e| format!("Failed to prepare request handlers query: {}", e))?;
-
-    let mut request_handlers = Vec::new();
-    while let sqlite::State::Row = statement.next().map_err(|e| format!("Failed to execute request handlers query: {}", e))? {
-        let handler_id: String = statement.read(0).map_err(|e| format!("Failed to read handler id: {}", e))?;
-        let is_enabled: i64 = statement.read(1).map_err(|e| format!("Failed to read is_enabled: {}", e))?;
-        let name: String = statement.read(2).map_err(|e| format!("Failed to read name: {}", e))?;
-        let processor_type: String = statement.read(3).map_err(|e| format!("Failed to read processor_type: {}", e))?;
-        let processor_id: String = statement.read(4).map_err(|e| format!("Failed to read processor_id: {}", e))?;
-        let url_match_str: Option<String> = statement.read(5).ok();
+fn load_request_handlers(connection: &Connection) -> Result<Vec<RequestHandler>, GruxiError> {
+    query(connection, "SELECT id, is_enabled, name, processor_type, processor_id, url_match FROM request_handler", &[], |row| {
+        let handler_id = row.get_string("id")?;
+        let is_enabled = row.get_i64("is_enabled")?;
+        let name = row.get_string("name")?;
+        let processor_type = row.get_string("processor_type")?;
+        let processor_id = row.get_string("processor_id")?;
+        let url_match_str = row.get_optional_string("url_match").ok().flatten();
 
         // Parse comma-separated strings
         let url_match = parse_comma_separated_list(&url_match_str.unwrap_or_default(), false);
 
-        request_handlers.push(RequestHandler {
+        Ok(RequestHandler {
             id: handler_id,
             is_enabled: is_enabled != 0,
             name,
             processor_type,
             processor_id,
             url_match,
-        });
-    }
-
-    Ok(request_handlers)
+        })
+    })
 }
 
-fn load_static_file_processors(connection: &Connection) -> Result<Vec<StaticFileProcessor>, String> {
-    let mut statement = connection
-        .prepare("SELECT * FROM static_file_processors")
-        .map_err(|e| format!("Failed to prepare static file processors query: {}", e))?;
+fn load_static_file_processors(connection: &Connection) -> Result<Vec<StaticFileProcessor>, GruxiError> {
+    query(connection, "SELECT * FROM static_file_processors", &[], |row| {
+        let processor_id = row.get_string("id")?;
+        let web_root = row.get_string("web_root")?;
+        let web_root_index_file_list_str = row.get_string("web_root_index_file_list")?;
 
-    let mut processors = Vec::new();
-    while let sqlite::State::Row = statement.next().map_err(|e| format!("Failed to execute static file processors query: {}", e))? {
-        let processor_id: String = statement.read(0).map_err(|e| format!("Failed to read processor id: {}", e))?;
-        let web_root: String = statement.read(1).map_err(|e| format!("Failed to read web_root: {}", e))?;
-        let web_root_index_file_list_str: String = statement.read(2).map_err(|e| format!("Failed to read web_root_index_file_list: {}", e))?;
+        let integrity_headers_enabled_int = row.get_i64("integrity_headers_enabled")?;
+        let ssi_enabled_int = row.get_i64("ssi_enabled")?;
 
         let web_root_index_file_list = parse_comma_separated_list(&web_root_index_file_list_str, false);
 
         let mut new_processor = StaticFileProcessor::new(web_root, web_root_index_file_list);
         new_processor.id = processor_id;
+        new_processor.integrity_headers_enabled = integrity_headers_enabled_int != 0;
+        new_processor.ssi_enabled = ssi_enabled_int != 0;
         new_processor.initialize();
 
-        processors.push(new_processor);
-    }
+        Ok(new_processor)
+    })
+}
 
-    Ok(processors)
+// A value in the database that cannot be read as what the configuration expects
+fn load_error(message: String) -> GruxiError {
+    GruxiError::configuration(ConfigurationError::Load, message)
 }
 
 fn parse_comma_separated_list(input: &str, to_lowercase: bool) -> Vec<String> {
@@ -0,0 +1,230 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    configuration::site::{HeaderKV, REWRITE_FUNCTIONS},
+    http::basic_auth::{BasicAuthUser, sanitize_basic_auth_users, validate_basic_auth_users, verify_basic_auth},
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Location {
+    pub id: String,
+    pub is_enabled: bool,
+    pub name: String,       // A name to identify the location for the user, self chosen
+    pub match_type: String, // "prefix" or "regex"
+    pub pattern: String,    // e.g. "/api/" for prefix or "^/files/.*\.pdf$" for regex
+    // Request handlers to use for this location, in order of priority. If empty, the site's request handlers are used
+    pub request_handlers: Vec<String>,
+    // Rewrite functions to apply for this location. If empty, the site's rewrite functions are used
+    pub rewrite_functions: Vec<String>,
+    // Headers added on top of the site's extra headers
+    pub extra_headers: Vec<HeaderKV>,
+    // Cache-Control header value to set on responses, if empty, the processor decides
+    pub cache_control: String,
+    // Basic authentication, enabled when there is at least one user or an auth provider is selected
+    pub auth_realm: String,
+    pub auth_users: Vec<BasicAuthUser>,
+    #[serde(default)]
+    pub auth_provider_id: String, // If set, credentials are verified by this auth provider instead of the users above
+    #[serde(default)]
+    pub auth_allowed_groups: Vec<String>, // If set, only members of one of these groups are allowed, matched by group DN or CN. Needs an LDAP auth provider
+    #[serde(default)]
+    pub auth_forward_user_header: String, // If set, the authenticated username is sent to the request handlers in this header, such as "X-Remote-User"
+
+    // Calculated fields (not serialized)
+    #[serde(skip)]
+    compiled_regex: OnceLock<Option<Regex>>,
+}
+
+// Supported match types
+pub static LOCATION_MATCH_TYPES: &[&str] = &["prefix", "regex"];
+
+impl Default for Location {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Location {
+    pub fn new() -> Self {
+        Location {
+            id: Uuid::new_v4().to_string(),
+            is_enabled: true,
+            name: "New Location".to_string(),
+            match_type: "prefix".to_string(),
+            pattern: "/".to_string(),
+            request_handlers: Vec::new(),
+            rewrite_functions: Vec::new(),
+            extra_headers: Vec::new(),
+            cache_control: String::new(),
+            auth_realm: String::new(),
+            auth_users: Vec::new(),
+            auth_provider_id: String::new(),
+            auth_allowed_groups: Vec::new(),
+            auth_forward_user_header: String::new(),
+            compiled_regex: OnceLock::new(),
+        }
+    }
+
+    // Check if the location matches the path. Query string is ignored, if present
+    pub fn matches_path(&self, url_path: &str) -> bool {
+        let url_path = match url_path.find('?') {
+            Some(pos) => &url_path[..pos],
+            None => url_path,
+        };
+
+        match self.match_type.as_str() {
+            "prefix" => url_path.to_lowercase().starts_with(&self.pattern.to_lowercase()),
+            "regex" => match self.compiled_regex.get_or_init(|| Regex::new(&self.pattern).ok()) {
+                Some(regex) => regex.is_match(url_path),
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    pub fn requires_authentication(&self) -> bool {
+        !self.auth_users.is_empty() || !self.auth_provider_id.is_empty()
+    }
+
+    // Verify a basic auth "Authorization" header value against the configured users
+    pub async fn verify_basic_auth(&self, authorization_header: &str) -> bool {
+        verify_basic_auth(&self.auth_users, authorization_header).await
+    }
+
+    pub fn get_auth_realm(&self) -> String {
+        if self.auth_realm.is_empty() { "Restricted".to_string() } else { self.auth_realm.clone() }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.id = self.id.trim().to_string();
+        self.name = self.name.trim().to_string();
+        self.match_type = self.match_type.trim().to_lowercase();
+        self.pattern = self.pattern.trim().to_string();
+        self.request_handlers = self.request_handlers.iter().map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+        self.rewrite_functions = self.rewrite_functions.iter().map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+        self.cache_control = self.cache_control.trim().to_string();
+        self.auth_realm = self.auth_realm.trim().to_string();
+        self.auth_provider_id = self.auth_provider_id.trim().to_string();
+        self.auth_forward_user_header = self.auth_forward_user_header.trim().to_string();
+        self.auth_allowed_groups = self.auth_allowed_groups.iter().map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+
+        for kv in &mut self.extra_headers {
+            kv.key = kv.key.trim().to_string();
+            kv.value = kv.value.trim().to_string();
+        }
+
+        // Make sure we never store plain text passwords
+        sanitize_basic_auth_users(&mut self.auth_users);
+
+        // Pattern may have changed, so recompile on next use
+        self.compiled_regex = OnceLock::new();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.id.is_empty() {
+            errors.push("Location ID cannot be empty".to_string());
+        }
+
+        if !LOCATION_MATCH_TYPES.contains(&self.match_type.as_str()) {
+            errors.push(format!("Location '{}' has unknown match type '{}', must be 'prefix' or 'regex'", self.name, self.match_type));
+        }
+
+        if self.pattern.is_empty() {
+            errors.push(format!("Location '{}' pattern cannot be empty", self.name));
+        } else if self.match_type == "prefix" && !self.pattern.starts_with('/') {
+            errors.push(format!("Location '{}' prefix pattern '{}' must start with '/'", self.name, self.pattern));
+        } else if self.match_type == "regex"
+            && let Err(e) = Regex::new(&self.pattern)
+        {
+            errors.push(format!("Location '{}' regex pattern '{}' is invalid: {}", self.name, self.pattern, e));
+        }
+
+        for func in &self.rewrite_functions {
+            if !REWRITE_FUNCTIONS.contains(&func.as_str()) {
+                errors.push(format!("Location '{}' has unknown rewrite function: '{}'", self.name, func));
+            }
+        }
+
+        for (idx, kv) in self.extra_headers.iter().enumerate() {
+            if kv.key.is_empty() {
+                errors.push(format!("Location '{}' extra header {} key cannot be empty", self.name, idx + 1));
+            }
+            if kv.value.is_empty() {
+                errors.push(format!("Location '{}' extra header {} value cannot be empty", self.name, idx + 1));
+            }
+        }
+
+        if !self.cache_control.is_empty() && http::HeaderValue::from_str(&self.cache_control).is_err() {
+            errors.push(format!("Location '{}' cache control value '{}' is not a valid header value", self.name, self.cache_control));
+        }
+
+        for error in validate_basic_auth_users(&self.auth_users) {
+            errors.push(format!("Location '{}': {}", self.name, error));
+        }
+
+        if !self.auth_forward_user_header.is_empty() && http::HeaderName::from_bytes(self.auth_forward_user_header.as_bytes()).is_err() {
+            errors.push(format!("Location '{}' forward user header '{}' is not a valid header name", self.name, self.auth_forward_user_header));
+        }
+        if !self.auth_forward_user_header.is_empty() && !self.requires_authentication() {
+            errors.push(format!(
+                "Location '{}' forward user header requires authentication, otherwise clients could set it themselves",
+                self.name
+            ));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_location_prefix_match() {
+        let mut location = Location::new();
+        location.pattern = "/api/".to_string();
+
+        assert!(location.matches_path("/api/users"));
+        assert!(location.matches_path("/API/users?id=1"));
+        assert!(!location.matches_path("/api"));
+        assert!(!location.matches_path("/static/api/"));
+    }
+
+    #[test]
+    fn test_location_regex_match() {
+        let mut location = Location::new();
+        location.match_type = "regex".to_string();
+        location.pattern = r"^/files/.*\.pdf$".to_string();
+
+        assert!(location.matches_path("/files/report.pdf"));
+        assert!(location.matches_path("/files/sub/report.pdf?download=1"));
+        assert!(!location.matches_path("/files/report.txt"));
+    }
+
+    #[test]
+    fn test_location_validation_invalid_regex() {
+        let mut location = Location::new();
+        location.match_type = "regex".to_string();
+        location.pattern = "([a-z".to_string();
+
+        let errors = location.validate().unwrap_err();
+        assert!(errors.iter().any(|e| e.contains("regex pattern")));
+    }
+
+    #[test]
+    fn test_location_validation_prefix_must_start_with_slash() {
+        let mut location = Location::new();
+        location.pattern = "api".to_string();
+
+        let errors = location.validate().unwrap_err();
+        assert!(errors.iter().any(|e| e.contains("must start with '/'")));
+    }
+}
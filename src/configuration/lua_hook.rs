@@ -0,0 +1,64 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// The phases Lua hooks run in, in this order: "rewrite" and "access" before the request handlers, where scripts can change
+// the request or answer it, "header_filter" and "body_filter" on the response, and "log" last, to only read the outcome
+pub const LUA_HOOK_PHASES: [&str; 5] = ["rewrite", "access", "header_filter", "body_filter", "log"];
+
+// A Lua script run on the requests of a site at one phase, as the "lua" middleware. The API scripts get is described in
+// http/lua_hooks.rs
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct LuaHook {
+    pub name: String,
+    pub is_enabled: bool,
+    pub phase: String,
+    // The Lua source inline, or the path of a .lua file with it
+    #[serde(default)]
+    pub script: String,
+    #[serde(default)]
+    pub script_path: String,
+    pub instruction_limit: u64, // Per run, so a script cannot hold up a request
+    pub memory_limit_mb: u32,
+}
+
+impl LuaHook {
+    pub fn new(name: &str, phase: &str, script: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            is_enabled: true,
+            phase: phase.to_string(),
+            script: script.to_string(),
+            script_path: String::new(),
+            instruction_limit: 1_000_000,
+            memory_limit_mb: 8,
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.name = self.name.trim().to_string();
+        self.phase = self.phase.trim().to_lowercase();
+        self.script_path = self.script_path.trim().to_string();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.name.is_empty() {
+            errors.push("Lua hook name cannot be empty".to_string());
+        }
+        if !LUA_HOOK_PHASES.contains(&self.phase.as_str()) {
+            errors.push(format!("Lua hook '{}': Phase must be one of {}, got '{}'", self.name, LUA_HOOK_PHASES.join(", "), self.phase));
+        }
+        if self.script.trim().is_empty() == self.script_path.is_empty() {
+            errors.push(format!("Lua hook '{}': Needs either an inline script or a script path", self.name));
+        }
+        if self.instruction_limit == 0 || self.instruction_limit > 1_000_000_000 {
+            errors.push(format!("Lua hook '{}': Instruction limit must be between 1 and 1000000000, got {}", self.name, self.instruction_limit));
+        }
+        if self.memory_limit_mb == 0 || self.memory_limit_mb > 1024 {
+            errors.push(format!("Lua hook '{}': Memory limit must be between 1 and 1024 MB, got {}", self.name, self.memory_limit_mb));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
@@ -1,15 +1,43 @@
-pub mod site;
+pub mod admin_portal;
+pub mod auth_provider;
+pub mod bandwidth_settings;
 pub mod binding;
-pub mod configuration;
 pub mod binding_site_relation;
-pub mod request_handler;
+pub mod bot_settings;
+pub mod cache_header_rule;
+pub mod cache_warm_settings;
+pub mod cached_configuration;
+pub mod cluster_sync_settings;
+pub mod command_hook;
+pub mod configuration;
+pub mod configuration_impact;
+pub mod core;
+pub mod database_backup;
+pub mod deprecated_fields;
+pub mod dns_resolution;
+pub mod email_alerts;
+pub mod export_formats;
 pub mod file_cache;
 pub mod gzip;
-pub mod server_settings;
-pub mod core;
+pub mod health_probe_settings;
+pub mod import_export;
+pub mod landing_page;
 pub mod load_configuration;
+pub mod location;
+pub mod lua_hook;
+pub mod plugin_settings;
+pub mod request_handler;
+pub mod request_priority;
 pub mod save_configuration;
-pub mod cached_configuration;
-pub mod import_export;
-pub mod admin_portal;
+pub mod server_settings;
+pub mod site;
+pub mod site_acme_settings;
+pub mod site_templates;
+pub mod status_page;
+pub mod synthetic_probe_settings;
 pub mod tls_settings;
+pub mod upload_scanning;
+pub mod usage_reports;
+pub mod waf_settings;
+pub mod webroot_sync_settings;
+pub mod websocket_settings;
@@ -0,0 +1,57 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// A WebAssembly plugin filtering the requests and responses of a site, in a sandbox without access to the system. The
+// ABI plugins implement is described in http/wasm_plugins.rs
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct WasmPlugin {
+    pub name: String,
+    pub is_enabled: bool,
+    pub path: String, // The .wasm file, or a .wat file while developing a plugin
+    #[serde(default)]
+    pub config: String, // Passed to the plugin with each call, such as JSON settings of its own
+    pub fuel_limit: u64, // Fuel per call, about one unit per WebAssembly instruction, so a plugin cannot hold up a request
+    pub memory_limit_mb: u32,
+    // Let requests through when the plugin fails, such as by running out of fuel, instead of answering them with 500
+    #[serde(default)]
+    pub is_fail_open: bool,
+}
+
+impl WasmPlugin {
+    pub fn new(name: &str, path: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            is_enabled: true,
+            path: path.to_string(),
+            config: String::new(),
+            fuel_limit: 10_000_000,
+            memory_limit_mb: 16,
+            is_fail_open: false,
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.name = self.name.trim().to_string();
+        self.path = self.path.trim().to_string();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.name.is_empty() {
+            errors.push("Plugin name cannot be empty".to_string());
+        }
+        let path = self.path.to_lowercase();
+        if !path.ends_with(".wasm") && !path.ends_with(".wat") {
+            errors.push(format!("Plugin '{}': Path must be a .wasm or .wat file, got '{}'", self.name, self.path));
+        }
+        if self.fuel_limit == 0 || self.fuel_limit > 10_000_000_000 {
+            errors.push(format!("Plugin '{}': Fuel limit must be between 1 and 10000000000, got {}", self.name, self.fuel_limit));
+        }
+        if self.memory_limit_mb == 0 || self.memory_limit_mb > 4096 {
+            errors.push(format!("Plugin '{}': Memory limit must be between 1 and 4096 MB, got {}", self.name, self.memory_limit_mb));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
@@ -1,3 +1,4 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -5,6 +6,7 @@ use crate::{
     configuration::site::Site,
     core::running_state_manager::get_running_state_manager,
     error::{gruxi_error::GruxiError, gruxi_error_enums::*},
+    external_connections::handler_request_queue::HANDLER_QUEUE_RETRY_AFTER_SECONDS,
     http::{
         request_handlers::processor_trait::ProcessorTrait,
         request_response::{gruxi_request::GruxiRequest, gruxi_response::GruxiResponse},
@@ -12,7 +14,7 @@ use crate::{
     logging::syslog::trace,
 };
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct RequestHandler {
     pub id: String,             // Generated uuid, unique, so it can be referenced from sites as a handler
     pub is_enabled: bool,       // Whether it is enabled or not
@@ -117,6 +119,14 @@ impl RequestHandler {
         if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
 
+    // Hand the request to a processor, with the body inspector of the processor, if it has one, on the request body
+    async fn handle_request_with_processor<P: ProcessorTrait>(processor: &P, gruxi_request: &mut GruxiRequest, site: &Site) -> Result<GruxiResponse, GruxiError> {
+        if let Some(inspector) = processor.get_request_body_inspector(gruxi_request, site) {
+            gruxi_request.add_body_inspector(inspector);
+        }
+        processor.handle_request(gruxi_request, site).await
+    }
+
     pub async fn handle_request(&self, gruxi_request: &mut GruxiRequest, site: &Site) -> Result<GruxiResponse, GruxiError> {
         let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
         let processor_manager = running_state.get_processor_manager();
@@ -127,7 +137,7 @@ impl RequestHandler {
                 trace(format!("Handling request with static file processor id '{}'", &self.processor_id));
                 let pm_option = processor_manager.get_static_file_processor_by_id(&self.processor_id);
                 match pm_option {
-                    Some(p) => p.handle_request(gruxi_request, &site).await,
+                    Some(p) => Self::handle_request_with_processor(p, gruxi_request, site).await,
                     None => {
                         return Err(GruxiError::new(
                             GruxiErrorKind::StaticFileProcessor(StaticFileProcessorError::Internal),
@@ -140,7 +150,7 @@ impl RequestHandler {
                 trace(format!("Handling request with PHP processor id '{}'", &self.processor_id));
                 let pm_option = processor_manager.get_php_processor_by_id(&self.processor_id);
                 match pm_option {
-                    Some(p) => p.handle_request(gruxi_request, &site).await,
+                    Some(p) => Self::handle_request_with_processor(p, gruxi_request, site).await,
                     None => {
                         return Err(GruxiError::new(
                             GruxiErrorKind::PHPProcessor(PHPProcessorError::Internal),
@@ -153,7 +163,7 @@ impl RequestHandler {
                 trace(format!("Handling request with proxy processor id '{}'", &self.processor_id));
                 let pm_option = processor_manager.get_proxy_processor_by_id(&self.processor_id);
                 match pm_option {
-                    Some(p) => p.handle_request(gruxi_request, &site).await,
+                    Some(p) => Self::handle_request_with_processor(p, gruxi_request, site).await,
                     None => {
                         return Err(GruxiError::new(
                             GruxiErrorKind::ProxyProcessor(ProxyProcessorError::Internal),
@@ -162,6 +172,71 @@ impl RequestHandler {
                     }
                 }
             }
+            "webdav" => {
+                trace(format!("Handling request with WebDAV processor id '{}'", &self.processor_id));
+                let pm_option = processor_manager.get_webdav_processor_by_id(&self.processor_id);
+                match pm_option {
+                    Some(p) => Self::handle_request_with_processor(p, gruxi_request, site).await,
+                    None => {
+                        return Err(GruxiError::new(
+                            GruxiErrorKind::WebDavProcessor(WebDavProcessorError::Internal),
+                            format!("WebDAV processor with id '{}' not found for request handler '{}'", &self.processor_id, &self.name),
+                        ));
+                    }
+                }
+            }
+            "cgi" => {
+                trace(format!("Handling request with CGI processor id '{}'", &self.processor_id));
+                let pm_option = processor_manager.get_cgi_processor_by_id(&self.processor_id);
+                match pm_option {
+                    Some(p) => Self::handle_request_with_processor(p, gruxi_request, site).await,
+                    None => {
+                        return Err(GruxiError::new(
+                            GruxiErrorKind::CgiProcessor(CgiProcessorError::Internal),
+                            format!("CGI processor with id '{}' not found for request handler '{}'", &self.processor_id, &self.name),
+                        ));
+                    }
+                }
+            }
+            "python" => {
+                trace(format!("Handling request with Python processor id '{}'", &self.processor_id));
+                let pm_option = processor_manager.get_python_processor_by_id(&self.processor_id);
+                match pm_option {
+                    Some(p) => Self::handle_request_with_processor(p, gruxi_request, site).await,
+                    None => {
+                        return Err(GruxiError::new(
+                            GruxiErrorKind::PythonProcessor(PythonProcessorError::Internal),
+                            format!("Python processor with id '{}' not found for request handler '{}'", &self.processor_id, &self.name),
+                        ));
+                    }
+                }
+            }
+            "node" => {
+                trace(format!("Handling request with Node.js processor id '{}'", &self.processor_id));
+                let pm_option = processor_manager.get_node_processor_by_id(&self.processor_id);
+                match pm_option {
+                    Some(p) => Self::handle_request_with_processor(p, gruxi_request, site).await,
+                    None => {
+                        return Err(GruxiError::new(
+                            GruxiErrorKind::NodeProcessor(NodeProcessorError::Internal),
+                            format!("Node.js processor with id '{}' not found for request handler '{}'", &self.processor_id, &self.name),
+                        ));
+                    }
+                }
+            }
+            "image" => {
+                trace(format!("Handling request with image processor id '{}'", &self.processor_id));
+                let pm_option = processor_manager.get_image_processor_by_id(&self.processor_id);
+                match pm_option {
+                    Some(p) => Self::handle_request_with_processor(p, gruxi_request, site).await,
+                    None => {
+                        return Err(GruxiError::new(
+                            GruxiErrorKind::ImageProcessor(ImageProcessorError::Internal),
+                            format!("Image processor with id '{}' not found for request handler '{}'", &self.processor_id, &self.name),
+                        ));
+                    }
+                }
+            }
             _ => {
                 return Err(GruxiError::new(
                     GruxiErrorKind::Internal("Unknown processor type"),
@@ -170,6 +245,14 @@ impl RequestHandler {
             }
         };
 
+        // A body inspector stopping the request decides the response, whatever the processor made of the cut off body
+        if let Some(rejection) = gruxi_request.get_body_rejection() {
+            trace(format!("Request body rejected for request handler '{}': {}", &self.name, rejection.reason));
+            let mut response = GruxiResponse::new_empty_with_status(rejection.status_code);
+            response.headers_mut().insert("Connection", hyper::header::HeaderValue::from_static("close"));
+            return Ok(response);
+        }
+
         return match &response_result {
             Ok(_) => response_result,
             Err(err) => {
@@ -192,6 +275,18 @@ impl RequestHandler {
                     GruxiErrorKind::ProxyProcessor(ProxyProcessorError::ConnectionFailed) => {
                         return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::BAD_GATEWAY.as_u16()));
                     }
+                    GruxiErrorKind::ProxyProcessor(ProxyProcessorError::TooManyWebSocketConnections) => {
+                        return Ok(Self::get_overloaded_response());
+                    }
+                    GruxiErrorKind::ProxyProcessor(ProxyProcessorError::RequestBodyTooLarge) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::PAYLOAD_TOO_LARGE.as_u16()));
+                    }
+                    GruxiErrorKind::ProxyProcessor(ProxyProcessorError::ResponseBodyTooLarge) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::BAD_GATEWAY.as_u16()));
+                    }
+                    GruxiErrorKind::ProxyProcessor(ProxyProcessorError::ClientTimeout) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::REQUEST_TIMEOUT.as_u16()));
+                    }
 
                     // PHP errors that we want to convey directly
                     GruxiErrorKind::PHPProcessor(PHPProcessorError::PathError(_)) => {
@@ -203,6 +298,70 @@ impl RequestHandler {
                     GruxiErrorKind::PHPProcessor(PHPProcessorError::Connection) => {
                         return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::BAD_GATEWAY.as_u16()));
                     }
+                    GruxiErrorKind::PHPProcessor(PHPProcessorError::Unavailable) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::SERVICE_UNAVAILABLE.as_u16()));
+                    }
+                    GruxiErrorKind::PHPProcessor(PHPProcessorError::Overloaded) => {
+                        return Ok(Self::get_overloaded_response());
+                    }
+
+                    // WebDAV errors that we want to convey directly
+                    GruxiErrorKind::WebDavProcessor(WebDavProcessorError::Io(_)) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16()));
+                    }
+                    GruxiErrorKind::WebDavProcessor(WebDavProcessorError::UploadScanFailed) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::SERVICE_UNAVAILABLE.as_u16()));
+                    }
+
+                    // CGI errors that we want to convey directly
+                    GruxiErrorKind::CgiProcessor(CgiProcessorError::Execution(_)) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16()));
+                    }
+                    GruxiErrorKind::CgiProcessor(CgiProcessorError::Timeout) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::GATEWAY_TIMEOUT.as_u16()));
+                    }
+                    GruxiErrorKind::CgiProcessor(CgiProcessorError::InvalidResponse) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::BAD_GATEWAY.as_u16()));
+                    }
+
+                    // Python errors that we want to convey directly
+                    GruxiErrorKind::PythonProcessor(PythonProcessorError::HandlerUnavailable) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::SERVICE_UNAVAILABLE.as_u16()));
+                    }
+                    GruxiErrorKind::PythonProcessor(PythonProcessorError::Overloaded) => {
+                        return Ok(Self::get_overloaded_response());
+                    }
+                    GruxiErrorKind::PythonProcessor(PythonProcessorError::ConnectionFailed) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::BAD_GATEWAY.as_u16()));
+                    }
+                    GruxiErrorKind::PythonProcessor(PythonProcessorError::Timeout) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::GATEWAY_TIMEOUT.as_u16()));
+                    }
+
+                    // Node.js errors that we want to convey directly
+                    GruxiErrorKind::NodeProcessor(NodeProcessorError::HandlerUnavailable) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::SERVICE_UNAVAILABLE.as_u16()));
+                    }
+                    GruxiErrorKind::NodeProcessor(NodeProcessorError::Overloaded) => {
+                        return Ok(Self::get_overloaded_response());
+                    }
+                    GruxiErrorKind::NodeProcessor(NodeProcessorError::ConnectionFailed) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::BAD_GATEWAY.as_u16()));
+                    }
+                    GruxiErrorKind::NodeProcessor(NodeProcessorError::Timeout) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::GATEWAY_TIMEOUT.as_u16()));
+                    }
+
+                    // Image errors that we want to convey directly
+                    GruxiErrorKind::ImageProcessor(ImageProcessorError::InvalidParameters) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::BAD_REQUEST.as_u16()));
+                    }
+                    GruxiErrorKind::ImageProcessor(ImageProcessorError::Execution(_) | ImageProcessorError::ConversionFailed | ImageProcessorError::Io(_)) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16()));
+                    }
+                    GruxiErrorKind::ImageProcessor(ImageProcessorError::Timeout) => {
+                        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::GATEWAY_TIMEOUT.as_u16()));
+                    }
 
                     // Other errors we have logged, but will continue to the next handler
                     _ => response_result
@@ -210,6 +369,15 @@ impl RequestHandler {
             }
         };
     }
+
+    // 503 for requests rejected by a saturated external handler, telling the client when to try again
+    fn get_overloaded_response() -> GruxiResponse {
+        let mut response = GruxiResponse::new_empty_with_status(hyper::StatusCode::SERVICE_UNAVAILABLE.as_u16());
+        response
+            .headers_mut()
+            .insert(hyper::header::RETRY_AFTER, hyper::header::HeaderValue::from(HANDLER_QUEUE_RETRY_AFTER_SECONDS));
+        response
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,62 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// Priority classes of requests, deciding which requests wait and which are turned away first when the server is overloaded.
+// Requests on admin portal bindings and high priority paths are always let through, so management access survives traffic storms
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct RequestPrioritySettings {
+    pub is_enabled: bool,                 // When disabled, requests are not limited or queued by priority
+    pub max_concurrent_requests: u32,     // Normal and low priority requests handled at the same time, before they have to wait
+    pub max_queue_length: u32,            // Requests waiting for a slot, before new ones are rejected. Low priority waiters are shed first
+    pub queue_timeout_seconds: u32,       // How long a request may wait for a slot
+    pub high_priority_paths: Vec<String>, // Path prefixes, such as "/health", that are never queued or shed
+    pub low_priority_paths: Vec<String>,  // Path prefixes of expensive handlers, such as "/search", that wait behind all others
+}
+
+impl Default for RequestPrioritySettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestPrioritySettings {
+    pub fn new() -> Self {
+        Self {
+            is_enabled: false,
+            max_concurrent_requests: 1000,
+            max_queue_length: 1000,
+            queue_timeout_seconds: 10,
+            high_priority_paths: vec!["/health".to_string()],
+            low_priority_paths: Vec::new(),
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        for paths in [&mut self.high_priority_paths, &mut self.low_priority_paths] {
+            *paths = paths.iter().map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect();
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.max_concurrent_requests < 1 {
+            errors.push("Max concurrent requests must be at least 1".to_string());
+        }
+        if self.queue_timeout_seconds > 3600 {
+            errors.push("Queue timeout cannot be more than 3600 seconds".to_string());
+        }
+        for path in self.high_priority_paths.iter().chain(&self.low_priority_paths) {
+            if !path.starts_with('/') {
+                errors.push(format!("Priority path must start with a slash: {}", path));
+            }
+        }
+        for path in &self.high_priority_paths {
+            if self.low_priority_paths.contains(path) {
+                errors.push(format!("Path cannot be both high and low priority: {}", path));
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
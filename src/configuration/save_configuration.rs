@@ -1,35 +1,52 @@
+use crate::configuration::auth_provider::AuthProvider;
 use crate::configuration::binding::Binding;
+use crate::configuration::command_hook::CommandHook;
 use crate::configuration::configuration::Configuration;
 use crate::configuration::core::Core;
 use crate::configuration::load_configuration::fetch_configuration_in_db;
 use crate::configuration::request_handler::RequestHandler;
 use crate::configuration::site::HeaderKV;
 use crate::configuration::site::Site;
-use crate::core::database_connection::get_database_connection;
+use crate::core::command_hooks::{get_command_hooks_for_event, spawn_command_hooks};
+use crate::core::database_connection::get_database_writer;
+use crate::database::configuration_storage::get_configuration_storage;
+use crate::database::data_access::{execute, query_one};
+use crate::error::gruxi_error::GruxiError;
+use crate::error::gruxi_error_enums::{ConfigurationError, DatabaseError, GruxiErrorKind};
+use crate::external_connections::managed_system::node_app::NodeApp;
 use crate::external_connections::managed_system::php_cgi::PhpCgi;
+use crate::external_connections::managed_system::python_app::PythonApp;
+use crate::http::request_handlers::processors::cgi_processor::CgiProcessor;
+use crate::http::request_handlers::processors::image_processor::ImageProcessor;
+use crate::http::request_handlers::processors::node_processor::NodeProcessor;
 use crate::http::request_handlers::processors::php_processor::PHPProcessor;
 use crate::http::request_handlers::processors::proxy_processor::ProxyProcessor;
+use crate::http::request_handlers::processors::python_processor::PythonProcessor;
 use crate::http::request_handlers::processors::static_files_processor::StaticFileProcessor;
+use crate::http::request_handlers::processors::webdav_processor::WebDavProcessor;
 use crate::logging::syslog::{info, trace};
 use serde_json;
 use sqlite::Connection;
-use sqlite::State;
 
 /// Save a new configuration to the database
-/// Returns Ok(true) if changes were saved, Ok(false) if no changes were needed
-pub fn save_configuration(config: &mut Configuration, force: bool) -> Result<bool, Vec<String>> {
+/// Returns Ok(true) if changes were saved, Ok(false) if no changes were needed. A configuration that fails validation
+/// is a ConfigurationError::Validation error with the problems found, anything else is a failure to save it
+pub fn save_configuration(config: &mut Configuration, force: bool) -> Result<bool, GruxiError> {
     // First, we sanitize the configuration
     config.sanitize();
 
     // Then we validate the configuration
-    config.validate()?;
+    config
+        .validate()
+        .map_err(|errors| GruxiError::new_with_kind_only(GruxiErrorKind::Configuration(ConfigurationError::Validation(errors))))?;
 
     // Check if the configuration is different from what's currently in the database
-    let current_config = fetch_configuration_in_db().map_err(|e| vec![format!("Failed to fetch current configuration from database: {}", e)])?;
+    let current_config = fetch_configuration_in_db().map_err(|e| e.with_context("Failed to fetch current configuration from database"))?;
 
     // Serialize both configurations to JSON for comparison
-    let new_config_json = serde_json::to_string(config).map_err(|e| vec![format!("Failed to serialize new configuration: {}", e)])?;
-    let current_config_json = serde_json::to_string(&current_config).map_err(|e| vec![format!("Failed to serialize current configuration: {}", e)])?;
+    let new_config_json = serde_json::to_string(config).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize new configuration: {}", e)))?;
+    let current_config_json =
+        serde_json::to_string(&current_config).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize current configuration: {}", e)))?;
 
     // If configurations are identical, no need to save
     if !force && new_config_json == current_config_json {
@@ -37,172 +54,498 @@ pub fn save_configuration(config: &mut Configuration, force: bool) -> Result<boo
     }
 
     // Do the actual saving
-    let connection = get_database_connection().map_err(|e| vec![format!("Failed to get database connection: {}", e)])?;
+    get_configuration_storage().save_configuration(config)?;
+
+    info("Configuration saved successfully");
+
+    // Let the command hooks know about the sites that did not exist before
+    let site_created_hooks = get_command_hooks_for_event(config, "site_created");
+    if !site_created_hooks.is_empty() {
+        for site in config.sites.iter().filter(|site| !current_config.sites.iter().any(|current| current.id == site.id)) {
+            spawn_command_hooks(
+                site_created_hooks.clone(),
+                "site_created",
+                vec![("GRUXI_SITE_ID", site.id.clone()), ("GRUXI_SITE_HOSTNAMES", site.hostnames.join(","))],
+            );
+        }
+    }
+
+    Ok(true) // Changes were saved
+}
+
+// Replace the configuration in the normalized database tables, in one transaction
+pub fn save_configuration_in_sqlite(configuration: &Configuration) -> Result<(), GruxiError> {
+    let connection = get_database_writer().map_err(|e| e.with_context("Failed to get database connection"))?;
 
     // Begin transaction for atomicity
-    connection.execute("BEGIN TRANSACTION").map_err(|e| vec![format!("Failed to begin transaction: {}", e)])?;
+    connection
+        .execute("BEGIN IMMEDIATE TRANSACTION")
+        .map_err(|e| GruxiError::database(DatabaseError::Transaction, format!("Failed to begin transaction: {}", e)))?;
 
     // Save the schema version, clear it first
-    connection.execute("DELETE FROM gruxi WHERE gruxi_key = 'schema_version'").map_err(|e| vec![format!("Failed to clear existing schema version: {}", e)])?;
     connection
-        .execute(format!(
-            "INSERT INTO gruxi (gruxi_key, gruxi_value) VALUES ('schema_version', '{}')",
-            config.version
-        ))
-        .map_err(|e| vec![format!("Failed to save schema version: {}", e)])?;
+        .execute("DELETE FROM gruxi WHERE gruxi_key = 'schema_version'")
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing schema version: {}", e)))?;
+    execute(&connection, "INSERT INTO gruxi (gruxi_key, gruxi_value) VALUES ('schema_version', ?)", &[&configuration.version]).map_err(|e| e.with_context("Failed to save schema version"))?;
 
     // Save core configuration (file cache, gzip, server settings)
-    save_core_config(&connection, &config.core).map_err(|e| vec![format!("Failed to save core configuration: {}", e)])?;
+    save_core_config(&connection, &configuration.core).map_err(|e| e.with_context("Failed to save core configuration"))?;
 
     // Clear and re-insert all bindings (simpler than update/delete logic)
-    connection.execute("DELETE FROM bindings").map_err(|e| vec![format!("Failed to clear existing bindings: {}", e)])?;
+    connection
+        .execute("DELETE FROM bindings")
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing bindings: {}", e)))?;
 
-    for binding in &config.bindings {
-        save_binding(&connection, binding).map_err(|e| vec![format!("Failed to save binding: {}", e)])?;
+    for binding in &configuration.bindings {
+        save_binding(&connection, binding).map_err(|e| e.with_context("Failed to save binding"))?;
     }
 
     // Clear and re-insert all sites (simpler than update/delete logic)
-    connection.execute("DELETE FROM sites").map_err(|e| vec![format!("Failed to clear existing sites: {}", e)])?;
+    connection
+        .execute("DELETE FROM sites")
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing sites: {}", e)))?;
 
-    for site in &config.sites {
-        save_site(&connection, site).map_err(|e| vec![format!("Failed to save site: {}", e)])?;
+    for site in &configuration.sites {
+        save_site(&connection, site).map_err(|e| e.with_context("Failed to save site"))?;
     }
 
     // Save the binding-site relationships
     // First, clear existing relationships
     connection
         .execute("DELETE FROM binding_sites")
-        .map_err(|e| vec![format!("Failed to clear existing binding-site relationships: {}", e)])?;
-
-    for relationship in &config.binding_sites {
-        connection
-            .execute(format!(
-                "INSERT INTO binding_sites (binding_id, site_id) VALUES ('{}', '{}')",
-                relationship.binding_id, relationship.site_id
-            ))
-            .map_err(|e| vec![format!("Failed to insert binding-site relationship: {}", e)])?;
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing binding-site relationships: {}", e)))?;
+
+    for relationship in &configuration.binding_sites {
+        execute(
+            &connection,
+            "INSERT INTO binding_sites (binding_id, site_id) VALUES (?, ?)",
+            &[&relationship.binding_id, &relationship.site_id],
+        )
+        .map_err(|e| e.with_context("Failed to insert binding-site relationship"))?;
     }
 
     // Save request handlers, but clear existing one first
     connection
         .execute("DELETE FROM request_handler")
-        .map_err(|e| vec![format!("Failed to clear existing request handlers: {}", e)])?;
-    for handler in &config.request_handlers {
-        save_request_handler(&connection, handler).map_err(|e| vec![format!("Failed to save request handler: {}", e)])?;
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing request handlers: {}", e)))?;
+    for handler in &configuration.request_handlers {
+        save_request_handler(&connection, handler).map_err(|e| e.with_context("Failed to save request handler"))?;
     }
 
     // Save static file processors, clear existing first
     connection
         .execute("DELETE FROM static_file_processors")
-        .map_err(|e| vec![format!("Failed to clear existing processors: {}", e)])?;
-    for processor in &config.static_file_processors {
-        save_static_file_processor(&connection, processor).map_err(|e| vec![format!("Failed to save static file processor: {}", e)])?;
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing processors: {}", e)))?;
+    for processor in &configuration.static_file_processors {
+        save_static_file_processor(&connection, processor).map_err(|e| e.with_context("Failed to save static file processor"))?;
     }
 
     // Save PHP processors, clear existing first
     connection
         .execute("DELETE FROM php_processors")
-        .map_err(|e| vec![format!("Failed to clear existing PHP processors: {}", e)])?;
-    for processor in &config.php_processors {
-        save_php_processor(&connection, processor).map_err(|e| vec![format!("Failed to save PHP processor: {}", e)])?;
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing PHP processors: {}", e)))?;
+    for processor in &configuration.php_processors {
+        save_php_processor(&connection, processor).map_err(|e| e.with_context("Failed to save PHP processor"))?;
     }
 
     // Save proxy processors, clear existing first
     connection
         .execute("DELETE FROM proxy_processors")
-        .map_err(|e| vec![format!("Failed to clear existing Proxy processors: {}", e)])?;
-    for processor in &config.proxy_processors {
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing Proxy processors: {}", e)))?;
+    for processor in &configuration.proxy_processors {
         // Implement save_proxy_processor similarly to other save functions
-        save_proxy_processor(&connection, processor).map_err(|e| vec![format!("Failed to save Proxy processor: {}", e)])?;
+        save_proxy_processor(&connection, processor).map_err(|e| e.with_context("Failed to save Proxy processor"))?;
+    }
+
+    // Save WebDAV processors, clear existing first
+    connection
+        .execute("DELETE FROM webdav_processors")
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing WebDAV processors: {}", e)))?;
+    for processor in &configuration.webdav_processors {
+        save_webdav_processor(&connection, processor).map_err(|e| e.with_context("Failed to save WebDAV processor"))?;
+    }
+
+    // Save CGI processors, clear existing first
+    connection
+        .execute("DELETE FROM cgi_processors")
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing CGI processors: {}", e)))?;
+    for processor in &configuration.cgi_processors {
+        save_cgi_processor(&connection, processor).map_err(|e| e.with_context("Failed to save CGI processor"))?;
+    }
+
+    // Save Python processors, clear existing first
+    connection
+        .execute("DELETE FROM python_processors")
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing Python processors: {}", e)))?;
+    for processor in &configuration.python_processors {
+        save_python_processor(&connection, processor).map_err(|e| e.with_context("Failed to save Python processor"))?;
+    }
+
+    // Save Node.js processors, clear existing first
+    connection
+        .execute("DELETE FROM node_processors")
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing Node.js processors: {}", e)))?;
+    for processor in &configuration.node_processors {
+        save_node_processor(&connection, processor).map_err(|e| e.with_context("Failed to save Node.js processor"))?;
+    }
+
+    // Save image processors, clear existing first
+    connection
+        .execute("DELETE FROM image_processors")
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing image processors: {}", e)))?;
+    for processor in &configuration.image_processors {
+        save_image_processor(&connection, processor).map_err(|e| e.with_context("Failed to save image processor"))?;
     }
 
     // Save PHP-CGI handlers, clear existing first
     connection
         .execute("DELETE FROM php_cgi_handlers")
-        .map_err(|e| vec![format!("Failed to clear existing PHP-CGI handlers: {}", e)])?;
-    for handler in &config.php_cgi_handlers {
-        save_php_cgi_handler(&connection, handler).map_err(|e| vec![format!("Failed to save PHP-CGI handler: {}", e)])?;
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing PHP-CGI handlers: {}", e)))?;
+    for handler in &configuration.php_cgi_handlers {
+        save_php_cgi_handler(&connection, handler).map_err(|e| e.with_context("Failed to save PHP-CGI handler"))?;
+    }
+
+    // Save Python handlers, clear existing first
+    connection
+        .execute("DELETE FROM python_handlers")
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing Python handlers: {}", e)))?;
+    for handler in &configuration.python_handlers {
+        save_python_handler(&connection, handler).map_err(|e| e.with_context("Failed to save Python handler"))?;
+    }
+
+    // Save Node.js handlers, clear existing first
+    connection
+        .execute("DELETE FROM node_handlers")
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing Node.js handlers: {}", e)))?;
+    for handler in &configuration.node_handlers {
+        save_node_handler(&connection, handler).map_err(|e| e.with_context("Failed to save Node.js handler"))?;
+    }
+
+    // Save auth providers, clear existing first
+    connection
+        .execute("DELETE FROM auth_providers")
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing auth providers: {}", e)))?;
+    for provider in &configuration.auth_providers {
+        save_auth_provider(&connection, provider).map_err(|e| e.with_context("Failed to save auth provider"))?;
+    }
+
+    // Save command hooks, clear existing first
+    connection
+        .execute("DELETE FROM command_hooks")
+        .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to clear existing command hooks: {}", e)))?;
+    for hook in &configuration.command_hooks {
+        save_command_hook(&connection, hook).map_err(|e| e.with_context("Failed to save command hook"))?;
     }
 
     // Commit transaction
-    connection.execute("COMMIT").map_err(|e| vec![format!("Failed to commit transaction: {}", e)])?;
+    connection
+        .execute("COMMIT")
+        .map_err(|e| GruxiError::database(DatabaseError::Transaction, format!("Failed to commit transaction: {}", e)))?;
 
-    info("Configuration saved successfully");
+    Ok(())
+}
 
-    Ok(true) // Changes were saved
+fn save_proxy_processor(connection: &Connection, processor: &ProxyProcessor) -> Result<(), GruxiError> {
+    let url_rewrites_json =
+        serde_json::to_string(&processor.url_rewrites).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize URL rewrites: {}", e)))?;
+    let upstream_groups_json =
+        serde_json::to_string(&processor.upstream_groups).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize upstream groups: {}", e)))?;
+
+    execute(
+        connection,
+        "INSERT INTO proxy_processors (id, proxy_type, upstream_servers, load_balancing_strategy, timeout_seconds, health_check_path, health_check_interval_seconds, health_check_timeout_seconds, url_rewrites, preserve_host_header, forced_host_header, verify_tls_certificates, streaming_paths, streaming_timeout_seconds, tls_ca_bundle_path, tls_server_name, tls_client_cert_path, tls_client_key_path, pool_max_idle_per_host, pool_idle_timeout_seconds, upstream_http2_only, connect_timeout_seconds, total_timeout_seconds, max_request_body_bytes, max_response_body_bytes, upstream_groups, upstream_group_header, upstream_group_cookie, request_headers_allowed, request_headers_denied, response_headers_allowed, response_headers_denied) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        &[
+            &processor.id,
+            &processor.proxy_type,
+            &processor.upstream_servers.join(","),
+            &processor.load_balancing_strategy,
+            &processor.timeout_seconds,
+            &processor.health_check_path,
+            &processor.health_check_interval_seconds,
+            &processor.health_check_timeout_seconds,
+            &url_rewrites_json,
+            &processor.preserve_host_header,
+            &processor.forced_host_header,
+            &processor.verify_tls_certificates,
+            &processor.streaming_paths.join(","),
+            &processor.streaming_timeout_seconds,
+            &processor.tls_ca_bundle_path,
+            &processor.tls_server_name,
+            &processor.tls_client_cert_path,
+            &processor.tls_client_key_path,
+            &processor.pool_max_idle_per_host,
+            &processor.pool_idle_timeout_seconds,
+            &processor.upstream_http2_only,
+            &processor.connect_timeout_seconds,
+            &processor.total_timeout_seconds,
+            &processor.max_request_body_bytes,
+            &processor.max_response_body_bytes,
+            &upstream_groups_json,
+            &processor.upstream_group_header,
+            &processor.upstream_group_cookie,
+            &processor.request_headers_allowed.join(","),
+            &processor.request_headers_denied.join(","),
+            &processor.response_headers_allowed.join(","),
+            &processor.response_headers_denied.join(","),
+        ],
+    )
+    .map_err(|e| e.with_context("Failed to insert Proxy processor"))?;
+
+    Ok(())
 }
 
-fn save_proxy_processor(connection: &Connection, processor: &ProxyProcessor) -> Result<(), String> {
-    let url_rewrites_json = serde_json::to_string(&processor.url_rewrites).map_err(|e| format!("Failed to serialize URL rewrites: {}", e))?;
+fn save_webdav_processor(connection: &Connection, processor: &WebDavProcessor) -> Result<(), GruxiError> {
+    let auth_users_json = serde_json::to_string(&processor.auth_users).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize auth users: {}", e)))?;
+
+    execute(
+        connection,
+        "INSERT INTO webdav_processors (id, web_root, read_only, require_authentication, auth_realm, auth_users) VALUES (?, ?, ?, ?, ?, ?)",
+        &[
+            &processor.id,
+            &processor.web_root,
+            &processor.read_only,
+            &processor.require_authentication,
+            &processor.auth_realm,
+            &auth_users_json,
+        ],
+    )
+    .map_err(|e| e.with_context("Failed to insert WebDAV processor"))?;
 
-    connection
-        .execute(format!(
-            "INSERT INTO proxy_processors (id, proxy_type, upstream_servers, load_balancing_strategy, timeout_seconds, health_check_path, health_check_interval_seconds, health_check_timeout_seconds, url_rewrites, preserve_host_header, forced_host_header, verify_tls_certificates) VALUES ('{}', '{}', '{}', '{}', {}, '{}', {}, {}, '{}', {}, '{}', {})",
-            processor.id,
-            processor.proxy_type.replace("'", "''"),
-            processor.upstream_servers.join(",").replace("'", "''"),
-            processor.load_balancing_strategy.replace("'", "''"),
-            processor.timeout_seconds,
-            processor.health_check_path.replace("'", "''"),
-            processor.health_check_interval_seconds,
-            processor.health_check_timeout_seconds,
-            url_rewrites_json.replace("'", "''"),
-            if processor.preserve_host_header { 1 } else { 0 },
-            processor.forced_host_header.replace("'", "''"),
-            if processor.verify_tls_certificates { 1 } else { 0 }
-        ))
-        .map_err(|e| format!("Failed to insert Proxy processor: {}", e))?;
+    Ok(())
+}
+
+fn save_cgi_processor(connection: &Connection, processor: &CgiProcessor) -> Result<(), GruxiError> {
+    let interpreters_json =
+        serde_json::to_string(&processor.interpreters).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize interpreters: {}", e)))?;
+
+    execute(
+        connection,
+        "INSERT INTO cgi_processors (id, cgi_bin_dir, url_prefix, interpreters, request_timeout) VALUES (?, ?, ?, ?, ?)",
+        &[&processor.id, &processor.cgi_bin_dir, &processor.url_prefix, &interpreters_json, &processor.request_timeout],
+    )
+    .map_err(|e| e.with_context("Failed to insert CGI processor"))?;
 
     Ok(())
 }
 
-fn save_php_processor(connection: &Connection, processor: &PHPProcessor) -> Result<(), String> {
-    connection
-        .execute(format!(
-            "INSERT INTO php_processors (id, served_by_type, php_cgi_handler_id, fastcgi_ip_and_port, request_timeout, local_web_root, fastcgi_web_root, server_software_spoof) VALUES ('{}', '{}', '{}', '{}', {}, '{}', '{}', '{}')",
-            processor.id,
-            processor.served_by_type.replace("'", "''"),
-            processor.php_cgi_handler_id.replace("'", "''"),
-            processor.fastcgi_ip_and_port.replace("'", "''"),
-            processor.request_timeout,
-            processor.local_web_root.replace("'", "''"),
-            processor.fastcgi_web_root.replace("'", "''"),
-            processor.server_software_spoof.replace("'", "''")
-        ))
-        .map_err(|e| format!("Failed to insert PHP processor: {}", e))?;
+fn save_python_processor(connection: &Connection, processor: &PythonProcessor) -> Result<(), GruxiError> {
+    execute(
+        connection,
+        "INSERT INTO python_processors (id, python_handler_id, request_timeout, preserve_host_header) VALUES (?, ?, ?, ?)",
+        &[&processor.id, &processor.python_handler_id, &processor.request_timeout, &processor.preserve_host_header],
+    )
+    .map_err(|e| e.with_context("Failed to insert Python processor"))?;
 
     Ok(())
 }
 
-fn save_php_cgi_handler(connection: &Connection, handler: &PhpCgi) -> Result<(), String> {
-    connection
-        .execute(format!(
-            "INSERT INTO php_cgi_handlers (id, name, request_timeout, concurrent_threads, executable) VALUES ('{}', '{}', {}, {}, '{}')",
-            handler.id,
-            handler.name.replace("'", "''"),
-            handler.request_timeout,
-            handler.concurrent_threads,
-            handler.executable.replace("'", "''")
-        ))
-        .map_err(|e| format!("Failed to insert PHP-CGI handler: {}", e))?;
+fn save_node_processor(connection: &Connection, processor: &NodeProcessor) -> Result<(), GruxiError> {
+    execute(
+        connection,
+        "INSERT INTO node_processors (id, node_handler_id, request_timeout, preserve_host_header) VALUES (?, ?, ?, ?)",
+        &[&processor.id, &processor.node_handler_id, &processor.request_timeout, &processor.preserve_host_header],
+    )
+    .map_err(|e| e.with_context("Failed to insert Node.js processor"))?;
 
     Ok(())
 }
 
-fn save_static_file_processor(connection: &Connection, processor: &StaticFileProcessor) -> Result<(), String> {
-    connection
-        .execute(format!(
-            "INSERT INTO static_file_processors (id, web_root, web_root_index_file_list) VALUES ('{}', '{}', '{}')",
-            processor.id,
-            processor.web_root.replace("'", "''"),
-            processor.web_root_index_file_list.join(",").replace("'", "''")
-        ))
-        .map_err(|e| format!("Failed to insert static file processor: {}", e))?;
+fn save_image_processor(connection: &Connection, processor: &ImageProcessor) -> Result<(), GruxiError> {
+    let allowed_paths_json =
+        serde_json::to_string(&processor.allowed_paths).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize allowed paths: {}", e)))?;
+    let allowed_widths_json =
+        serde_json::to_string(&processor.allowed_widths).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize allowed widths: {}", e)))?;
+
+    execute(
+        connection,
+        "INSERT INTO image_processors (id, web_root, allowed_paths, cache_dir, converter_path, max_width, allowed_widths, default_quality, max_concurrent_conversions, request_timeout) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        &[
+            &processor.id,
+            &processor.web_root,
+            &allowed_paths_json,
+            &processor.cache_dir,
+            &processor.converter_path,
+            &processor.max_width,
+            &allowed_widths_json,
+            &processor.default_quality,
+            &processor.max_concurrent_conversions,
+            &processor.request_timeout,
+        ],
+    )
+    .map_err(|e| e.with_context("Failed to insert image processor"))?;
+
+    Ok(())
+}
+
+fn save_php_processor(connection: &Connection, processor: &PHPProcessor) -> Result<(), GruxiError> {
+    execute(
+        connection,
+        "INSERT INTO php_processors (id, served_by_type, php_cgi_handler_id, fastcgi_ip_and_port, request_timeout, local_web_root, fastcgi_web_root, server_software_spoof, fastcgi_pool_size) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        &[
+            &processor.id,
+            &processor.served_by_type,
+            &processor.php_cgi_handler_id,
+            &processor.fastcgi_ip_and_port,
+            &processor.request_timeout,
+            &processor.local_web_root,
+            &processor.fastcgi_web_root,
+            &processor.server_software_spoof,
+            &processor.fastcgi_pool_size,
+        ],
+    )
+    .map_err(|e| e.with_context("Failed to insert PHP processor"))?;
+
+    Ok(())
+}
+
+fn save_php_cgi_handler(connection: &Connection, handler: &PhpCgi) -> Result<(), GruxiError> {
+    execute(
+        connection,
+        "INSERT INTO php_cgi_handlers (id, name, request_timeout, concurrent_threads, executable, max_queue_length, queue_timeout, max_requests_per_worker, max_worker_lifetime) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        &[
+            &handler.id,
+            &handler.name,
+            &handler.request_timeout,
+            &handler.concurrent_threads,
+            &handler.executable,
+            &handler.max_queue_length,
+            &handler.queue_timeout,
+            &handler.max_requests_per_worker,
+            &handler.max_worker_lifetime,
+        ],
+    )
+    .map_err(|e| e.with_context("Failed to insert PHP-CGI handler"))?;
+
+    Ok(())
+}
+
+fn save_python_handler(connection: &Connection, handler: &PythonApp) -> Result<(), GruxiError> {
+    let extra_arguments_json =
+        serde_json::to_string(&handler.extra_arguments).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize extra_arguments: {}", e)))?;
+    let extra_environment_json =
+        serde_json::to_string(&handler.extra_environment).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize extra_environment: {}", e)))?;
+
+    execute(
+        connection,
+        "INSERT INTO python_handlers (id, name, server_type, executable, app_module, working_directory, workers, extra_arguments, extra_environment, health_check_path, max_concurrent_requests, max_queue_length, queue_timeout, max_requests_per_worker, max_worker_lifetime) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        &[
+            &handler.id,
+            &handler.name,
+            &handler.server_type,
+            &handler.executable,
+            &handler.app_module,
+            &handler.working_directory,
+            &handler.workers,
+            &extra_arguments_json,
+            &extra_environment_json,
+            &handler.health_check_path,
+            &handler.max_concurrent_requests,
+            &handler.max_queue_length,
+            &handler.queue_timeout,
+            &handler.max_requests_per_worker,
+            &handler.max_worker_lifetime,
+        ],
+    )
+    .map_err(|e| e.with_context("Failed to insert Python handler"))?;
+
+    Ok(())
+}
+
+fn save_node_handler(connection: &Connection, handler: &NodeApp) -> Result<(), GruxiError> {
+    let extra_arguments_json =
+        serde_json::to_string(&handler.extra_arguments).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize extra_arguments: {}", e)))?;
+    let extra_environment_json =
+        serde_json::to_string(&handler.extra_environment).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize extra_environment: {}", e)))?;
+
+    execute(
+        connection,
+        "INSERT INTO node_handlers (id, name, executable, entry_script, working_directory, extra_arguments, extra_environment, health_check_path, max_concurrent_requests, max_queue_length, queue_timeout) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        &[
+            &handler.id,
+            &handler.name,
+            &handler.executable,
+            &handler.entry_script,
+            &handler.working_directory,
+            &extra_arguments_json,
+            &extra_environment_json,
+            &handler.health_check_path,
+            &handler.max_concurrent_requests,
+            &handler.max_queue_length,
+            &handler.queue_timeout,
+        ],
+    )
+    .map_err(|e| e.with_context("Failed to insert Node.js handler"))?;
+
+    Ok(())
+}
+
+fn save_auth_provider(connection: &Connection, provider: &AuthProvider) -> Result<(), GruxiError> {
+    execute(
+        connection,
+        "INSERT INTO auth_providers (id, name, provider_type, htpasswd_file, ldap_url, ldap_bind_dn_template, oidc_token_url, oidc_client_id, oidc_client_secret, oidc_scope, timeout_seconds, cache_seconds, ldap_search_base_dn, ldap_user_attribute, ldap_group_attribute, kerberos_keytab_file) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        &[
+            &provider.id,
+            &provider.name,
+            &provider.provider_type,
+            &provider.htpasswd_file,
+            &provider.ldap_url,
+            &provider.ldap_bind_dn_template,
+            &provider.oidc_token_url,
+            &provider.oidc_client_id,
+            &provider.oidc_client_secret,
+            &provider.oidc_scope,
+            &provider.timeout_seconds,
+            &provider.cache_seconds,
+            &provider.ldap_search_base_dn,
+            &provider.ldap_user_attribute,
+            &provider.ldap_group_attribute,
+            &provider.kerberos_keytab_file,
+        ],
+    )
+    .map_err(|e| e.with_context("Failed to insert auth provider"))?;
+
+    Ok(())
+}
+
+fn save_command_hook(connection: &Connection, hook: &CommandHook) -> Result<(), GruxiError> {
+    let arguments_json = serde_json::to_string(&hook.arguments).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize arguments: {}", e)))?;
+    execute(
+        connection,
+        "INSERT INTO command_hooks (id, name, event, command, arguments, working_directory, timeout_seconds, is_enabled) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        &[
+            &hook.id,
+            &hook.name,
+            &hook.event,
+            &hook.command,
+            &arguments_json,
+            &hook.working_directory,
+            &hook.timeout_seconds,
+            &hook.is_enabled,
+        ],
+    )
+    .map_err(|e| e.with_context("Failed to insert command hook"))?;
+
+    Ok(())
+}
+
+fn save_static_file_processor(connection: &Connection, processor: &StaticFileProcessor) -> Result<(), GruxiError> {
+    execute(
+        connection,
+        "INSERT INTO static_file_processors (id, web_root, web_root_index_file_list, integrity_headers_enabled, ssi_enabled) VALUES (?, ?, ?, ?, ?)",
+        &[
+            &processor.id,
+            &processor.web_root,
+            &processor.web_root_index_file_list.join(","),
+            &processor.integrity_headers_enabled,
+            &processor.ssi_enabled,
+        ],
+    )
+    .map_err(|e| e.with_context("Failed to insert static file processor"))?;
 
     Ok(())
 }
 
-fn save_core_config(connection: &Connection, core: &Core) -> Result<(), String> {
+fn save_core_config(connection: &Connection, core: &Core) -> Result<(), GruxiError> {
     // Save file cache settings
     save_server_settings(connection, "file_cache_is_enabled", &core.file_cache.is_enabled.to_string())?;
     save_server_settings(connection, "file_cache_cache_item_size", &core.file_cache.cache_item_size.to_string())?;
@@ -211,6 +554,16 @@ fn save_core_config(connection: &Connection, core: &Core) -> Result<(), String>
     save_server_settings(connection, "file_cache_cleanup_thread_interval", &core.file_cache.cleanup_thread_interval.to_string())?;
     save_server_settings(connection, "file_cache_max_item_lifetime", &core.file_cache.max_item_lifetime.to_string())?;
     save_server_settings(connection, "file_cache_forced_eviction_threshold", &core.file_cache.forced_eviction_threshold.to_string())?;
+    save_server_settings(connection, "file_cache_open_file_cache_max_entries", &core.file_cache.open_file_cache_max_entries.to_string())?;
+    save_server_settings(
+        connection,
+        "file_cache_open_file_cache_revalidate_seconds",
+        &core.file_cache.open_file_cache_revalidate_seconds.to_string(),
+    )?;
+    save_server_settings(connection, "file_cache_mmap_is_enabled", &core.file_cache.mmap_is_enabled.to_string())?;
+    save_server_settings(connection, "file_cache_mmap_min_file_size", &core.file_cache.mmap_min_file_size.to_string())?;
+    save_server_settings(connection, "file_cache_mmap_max_file_size", &core.file_cache.mmap_max_file_size.to_string())?;
+    save_server_settings(connection, "file_cache_compressed_variants_max_size", &core.file_cache.compressed_variants_max_size.to_string())?;
 
     // Save gzip settings
     save_server_settings(connection, "gzip_is_enabled", &core.gzip.is_enabled.to_string())?;
@@ -219,6 +572,13 @@ fn save_core_config(connection: &Connection, core: &Core) -> Result<(), String>
     // Save server settings
     save_server_settings(connection, "max_body_size", &core.server_settings.max_body_size.to_string())?;
     save_server_settings(connection, "blocked_file_patterns", &core.server_settings.blocked_file_patterns.join(","))?;
+    save_server_settings(connection, "http_version_policy", &core.server_settings.http_version_policy)?;
+    save_server_settings(connection, "trace_method_enabled", &core.server_settings.trace_method_enabled.to_string())?;
+    save_server_settings(connection, "encoded_slash_policy", &core.server_settings.encoded_slash_policy)?;
+    save_server_settings(connection, "max_header_count", &core.server_settings.max_header_count.to_string())?;
+    save_server_settings(connection, "max_header_size", &core.server_settings.max_header_size.to_string())?;
+    save_server_settings(connection, "max_uri_length", &core.server_settings.max_uri_length.to_string())?;
+    save_server_settings(connection, "max_chunk_size", &core.server_settings.max_chunk_size.to_string())?;
 
     // Save admin portal settings
     save_server_settings(connection, "admin_portal_domain_name", &core.admin_portal.domain_name.to_string())?;
@@ -234,125 +594,256 @@ fn save_core_config(connection: &Connection, core: &Core) -> Result<(), String>
     } else {
         save_server_settings(connection, "admin_portal_tls_key_path", "")?;
     }
+    save_server_settings(connection, "admin_portal_auth_provider_id", &core.admin_portal.auth_provider_id)?;
+    save_server_settings(connection, "admin_portal_allow_local_login", &core.admin_portal.allow_local_login.to_string())?;
+    save_server_settings(connection, "admin_portal_shared_session_database", &core.admin_portal.shared_session_database)?;
+    save_server_settings(connection, "admin_portal_session_bind_ip", &core.admin_portal.session_bind_ip.to_string())?;
+    save_server_settings(connection, "admin_portal_session_bind_user_agent", &core.admin_portal.session_bind_user_agent.to_string())?;
+    save_server_settings(connection, "admin_portal_session_client_ip_header", &core.admin_portal.session_client_ip_header)?;
 
     // Save TLS settings
     save_server_settings(connection, "tls_account_email", &core.tls_settings.account_email)?;
     save_server_settings(connection, "tls_use_staging_server", &core.tls_settings.use_staging_server.to_string())?;
     save_server_settings(connection, "tls_certificate_cache_path", &core.tls_settings.certificate_cache_path)?;
+    save_server_settings(connection, "tls_certificate_cache_backend", &core.tls_settings.certificate_cache_backend)?;
+    save_server_settings(connection, "tls_certificate_cache_database", &core.tls_settings.certificate_cache_database)?;
+    save_server_settings(connection, "tls_certificate_cache_s3_endpoint", &core.tls_settings.certificate_cache_s3_endpoint)?;
+    save_server_settings(connection, "tls_certificate_cache_s3_bucket", &core.tls_settings.certificate_cache_s3_bucket)?;
+    save_server_settings(connection, "tls_certificate_cache_s3_region", &core.tls_settings.certificate_cache_s3_region)?;
+    save_server_settings(connection, "tls_certificate_cache_s3_access_key_id", &core.tls_settings.certificate_cache_s3_access_key_id)?;
+    save_server_settings(connection, "tls_certificate_cache_s3_secret_access_key", &core.tls_settings.certificate_cache_s3_secret_access_key)?;
+    save_server_settings(connection, "tls_consolidate_acme_orders", &core.tls_settings.consolidate_acme_orders.to_string())?;
+    save_server_settings(connection, "tls_acme_challenge_type", &core.tls_settings.acme_challenge_type)?;
+    save_server_settings(connection, "tls_acme_provider", &core.tls_settings.acme_provider)?;
+    save_server_settings(connection, "tls_acme_directory_url", &core.tls_settings.acme_directory_url)?;
+    save_server_settings(connection, "tls_acme_eab_key_id", &core.tls_settings.acme_eab_key_id)?;
+    save_server_settings(connection, "tls_acme_eab_hmac_key", &core.tls_settings.acme_eab_hmac_key)?;
+    save_server_settings(connection, "tls_ct_monitoring_enabled", &core.tls_settings.ct_monitoring_enabled.to_string())?;
+    save_server_settings(connection, "tls_ct_monitoring_interval_minutes", &core.tls_settings.ct_monitoring_interval_minutes.to_string())?;
+    save_server_settings(connection, "tls_ct_monitoring_webhook_url", &core.tls_settings.ct_monitoring_webhook_url)?;
+    save_server_settings(connection, "tls_session_resumption_enabled", &core.tls_settings.session_resumption_enabled.to_string())?;
+    save_server_settings(connection, "tls_session_ticket_lifetime_seconds", &core.tls_settings.session_ticket_lifetime_seconds.to_string())?;
+
+    // Save upload scanning settings
+    save_server_settings(connection, "upload_scanning_is_enabled", &core.upload_scanning.is_enabled.to_string())?;
+    save_server_settings(connection, "upload_scanning_scanner_type", &core.upload_scanning.scanner_type)?;
+    save_server_settings(connection, "upload_scanning_command", &core.upload_scanning.command)?;
+    save_server_settings(connection, "upload_scanning_icap_url", &core.upload_scanning.icap_url)?;
+    save_server_settings(connection, "upload_scanning_timeout_seconds", &core.upload_scanning.timeout_seconds.to_string())?;
+    save_server_settings(connection, "upload_scanning_quarantine_path", &core.upload_scanning.quarantine_path)?;
+
+    // Save usage report settings
+    save_server_settings(connection, "usage_reports_billing_period_start_day", &core.usage_reports.billing_period_start_day.to_string())?;
+    save_server_settings(connection, "usage_reports_webhook_url", &core.usage_reports.webhook_url)?;
+    save_server_settings(connection, "usage_reports_webhook_secret", &core.usage_reports.webhook_secret)?;
+
+    // Save DNS resolution settings
+    save_server_settings(connection, "dns_resolution_mode", &core.dns_resolution.mode)?;
+    save_server_settings(connection, "dns_resolution_server_address", &core.dns_resolution.server_address)?;
+    save_server_settings(connection, "dns_resolution_server_name", &core.dns_resolution.server_name)?;
+    save_server_settings(connection, "dns_resolution_doh_path", &core.dns_resolution.doh_path)?;
+    save_server_settings(connection, "dns_resolution_address_preference", &core.dns_resolution.address_preference)?;
+    save_server_settings(connection, "dns_resolution_happy_eyeballs_delay_ms", &core.dns_resolution.happy_eyeballs_delay_ms.to_string())?;
+
+    // Save cluster sync settings
+    save_server_settings(connection, "cluster_sync_role", &core.cluster_sync.role)?;
+    save_server_settings(connection, "cluster_sync_primary_url", &core.cluster_sync.primary_url)?;
+    save_server_settings(connection, "cluster_sync_shared_token", &core.cluster_sync.shared_token)?;
+    save_server_settings(connection, "cluster_sync_poll_interval_seconds", &core.cluster_sync.poll_interval_seconds.to_string())?;
+    save_server_settings(connection, "cluster_sync_verify_tls_certificates", &core.cluster_sync.verify_tls_certificates.to_string())?;
+
+    // Save database backup settings
+    save_server_settings(connection, "database_backup_is_enabled", &core.database_backup.is_enabled.to_string())?;
+    save_server_settings(connection, "database_backup_directory", &core.database_backup.directory)?;
+    save_server_settings(connection, "database_backup_interval_hours", &core.database_backup.interval_hours.to_string())?;
+    save_server_settings(connection, "database_backup_retention_count", &core.database_backup.retention_count.to_string())?;
+
+    // Save request priority settings
+    save_server_settings(connection, "request_priority_is_enabled", &core.request_priority.is_enabled.to_string())?;
+    save_server_settings(connection, "request_priority_max_concurrent_requests", &core.request_priority.max_concurrent_requests.to_string())?;
+    save_server_settings(connection, "request_priority_max_queue_length", &core.request_priority.max_queue_length.to_string())?;
+    save_server_settings(connection, "request_priority_queue_timeout_seconds", &core.request_priority.queue_timeout_seconds.to_string())?;
+    save_server_settings(connection, "request_priority_high_priority_paths", &core.request_priority.high_priority_paths.join(","))?;
+    save_server_settings(connection, "request_priority_low_priority_paths", &core.request_priority.low_priority_paths.join(","))?;
+
+    // Save email alert settings
+    let email_alert_routes_json =
+        serde_json::to_string(&core.email_alerts.routes).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize email alert routes: {}", e)))?;
+    save_server_settings(connection, "email_alerts_is_enabled", &core.email_alerts.is_enabled.to_string())?;
+    save_server_settings(connection, "email_alerts_smtp_host", &core.email_alerts.smtp_host)?;
+    save_server_settings(connection, "email_alerts_smtp_port", &core.email_alerts.smtp_port.to_string())?;
+    save_server_settings(connection, "email_alerts_smtp_tls_mode", &core.email_alerts.smtp_tls_mode)?;
+    save_server_settings(connection, "email_alerts_smtp_verify_certificates", &core.email_alerts.smtp_verify_certificates.to_string())?;
+    save_server_settings(connection, "email_alerts_smtp_username", &core.email_alerts.smtp_username)?;
+    save_server_settings(connection, "email_alerts_smtp_password", &core.email_alerts.smtp_password)?;
+    save_server_settings(connection, "email_alerts_from_address", &core.email_alerts.from_address)?;
+    save_server_settings(connection, "email_alerts_routes", &email_alert_routes_json)?;
+    save_server_settings(connection, "email_alerts_repeat_interval_minutes", &core.email_alerts.repeat_interval_minutes.to_string())?;
+
+    // Save status page settings
+    save_server_settings(connection, "status_page_is_enabled", &core.status_page.is_enabled.to_string())?;
+    save_server_settings(connection, "status_page_title", &core.status_page.title)?;
+    save_server_settings(connection, "status_page_site_ids", &core.status_page.site_ids.join(","))?;
+    save_server_settings(connection, "status_page_incident_count", &core.status_page.incident_count.to_string())?;
+    save_server_settings(connection, "status_page_show_incident_messages", &core.status_page.show_incident_messages.to_string())?;
+
+    // Save health probe settings
+    save_server_settings(connection, "health_probes_is_enabled", &core.health_probes.is_enabled.to_string())?;
+    save_server_settings(connection, "health_probes_liveness_path", &core.health_probes.liveness_path)?;
+    save_server_settings(connection, "health_probes_readiness_path", &core.health_probes.readiness_path)?;
+    save_server_settings(connection, "health_probes_allowed_ips", &core.health_probes.allowed_ips.join(","))?;
+
+    // Save landing page settings
+    save_server_settings(connection, "landing_page_is_enabled", &core.landing_page.is_enabled.to_string())?;
+    save_server_settings(connection, "landing_page_title", &core.landing_page.title)?;
+    save_server_settings(connection, "landing_page_message", &core.landing_page.message)?;
+    save_server_settings(connection, "landing_page_show_sites", &core.landing_page.show_sites.to_string())?;
+    save_server_settings(connection, "landing_page_format", &core.landing_page.format)?;
 
     Ok(())
 }
 
-fn save_server_settings(connection: &Connection, key: &str, value: &str) -> Result<(), String> {
+fn save_server_settings(connection: &Connection, key: &str, value: &str) -> Result<(), GruxiError> {
     // check if it is insert or update
-    let mut statement = connection
-        .prepare(format!("SELECT COUNT(*) FROM server_settings WHERE setting_key = '{}'", key.replace("'", "''")))
-        .map_err(|e| format!("Failed to prepare server settings query: {}", e))?;
-    let exists = match statement.next().map_err(|e| format!("Failed to execute server settings query: {}", e))? {
-        State::Row => {
-            let count: i64 = statement.read(0).map_err(|e| format!("Failed to read count: {}", e))?;
-            count > 0
-        }
-        State::Done => false,
-    };
-    drop(statement);
+    let count = query_one(connection, "SELECT COUNT(*) AS count FROM server_settings WHERE setting_key = ?", &[&key], |row| row.get_i64("count"))
+        .map_err(|e| e.with_context("Failed to query server settings"))?;
+    let exists = count.unwrap_or(0) > 0;
 
     if exists {
-        connection
-            .execute(format!(
-                "UPDATE server_settings SET setting_value = '{}' WHERE setting_key = '{}'",
-                value.replace("'", "''"),
-                key.replace("'", "''")
-            ))
-            .map_err(|e| format!("Failed to update server setting {}: {}", key, e))?;
+        execute(connection, "UPDATE server_settings SET setting_value = ? WHERE setting_key = ?", &[&value, &key]).map_err(|e| e.with_context(format!("Failed to update server setting {}", key)))?;
     } else {
-        connection
-            .execute(format!(
-                "INSERT INTO server_settings (setting_key, setting_value) VALUES ('{}', '{}')",
-                key.replace("'", "''"),
-                value.replace("'", "''")
-            ))
-            .map_err(|e| format!("Failed to insert/update server setting {}: {}", key, e))?;
+        execute(connection, "INSERT INTO server_settings (setting_key, setting_value) VALUES (?, ?)", &[&key, &value])
+            .map_err(|e| e.with_context(format!("Failed to insert/update server setting {}", key)))?;
     }
 
     Ok(())
 }
 
-fn save_binding(connection: &Connection, binding: &Binding) -> Result<(), String> {
+fn save_binding(connection: &Connection, binding: &Binding) -> Result<(), GruxiError> {
     // Insert binding with explicit ID (all bindings are re-inserted after DELETE FROM bindings)
-    connection
-        .execute(format!(
-            "INSERT INTO bindings (id, ip, port, is_admin, is_tls) VALUES ('{}', '{}', {}, {}, {})",
-            binding.id,
-            binding.ip.replace("'", "''"),
-            binding.port,
-            if binding.is_admin { 1 } else { 0 },
-            if binding.is_tls { 1 } else { 0 }
-        ))
-        .map_err(|e| format!("Failed to insert binding: {}", e))?;
+    execute(
+        connection,
+        "INSERT INTO bindings (id, ip, port, is_admin, is_tls, http10_strict_close, keep_alive_timeout_seconds, max_keep_alive_requests, http_versions, sni_mismatch_policy, default_site_id, unknown_host_policy) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        &[
+            &binding.id,
+            &binding.ip,
+            &binding.port,
+            &binding.is_admin,
+            &binding.is_tls,
+            &binding.http10_strict_close,
+            &binding.keep_alive_timeout_seconds,
+            &binding.max_keep_alive_requests,
+            &binding.http_versions,
+            &binding.sni_mismatch_policy,
+            &binding.default_site_id,
+            &binding.unknown_host_policy,
+        ],
+    )
+    .map_err(|e| e.with_context("Failed to insert binding"))?;
 
     trace(format!("Inserted binding with id: {}", binding.id));
 
     Ok(())
 }
 
-pub fn save_site(connection: &Connection, site: &Site) -> Result<(), String> {
+pub fn save_site(connection: &Connection, site: &Site) -> Result<(), GruxiError> {
     // Remove any site with the same ID first (to avoid conflicts)
-    connection
-        .execute(format!("DELETE FROM sites WHERE id = '{}'", site.id))
-        .map_err(|e| format!("Failed to delete existing site with id {}: {}", site.id, e))?;
+    execute(connection, "DELETE FROM sites WHERE id = ?", &[&site.id]).map_err(|e| e.with_context(format!("Failed to delete existing site with id {}", site.id)))?;
 
     let extra_headers_str = if site.extra_headers.is_empty() {
         "".to_string()
     } else {
-        site.extra_headers
-            .iter()
-            .map(|HeaderKV { key, value }| format!("{}={}", key.replace("'", "''"), value.replace("'", "''")))
-            .collect::<Vec<String>>()
-            .join(",")
+        site.extra_headers.iter().map(|HeaderKV { key, value }| format!("{}={}", key, value)).collect::<Vec<String>>().join(",")
     };
 
-    connection
-        .execute(format!(
-            "INSERT INTO sites (id, is_default, is_enabled, hostnames, tls_cert_path, tls_cert_content, tls_key_path, tls_key_content, request_handlers, rewrite_functions, access_log_enabled, access_log_file, extra_headers, tls_automatic_enabled) VALUES ('{}', {}, {}, '{}', '{}', '{}', '{}', '{}', '{}', '{}', {}, '{}', '{}', {})",
-            site.id,
-            if site.is_default { 1 } else { 0 },
-            if site.is_enabled { 1 } else { 0 },
-            site.hostnames.join(",").replace("'", "''"),
-            site.tls_cert_path.replace("'", "''"),
-            site.tls_cert_content.replace("'", "''"),
-            site.tls_key_path.replace("'", "''"),
-            site.tls_key_content.replace("'", "''"),
-            site.request_handlers.join(","),
-            site.rewrite_functions.join(","),
-            if site.access_log_enabled { 1 } else { 0 },
-            site.access_log_file.replace("'", "''"),
-            extra_headers_str,
-            if site.tls_automatic_enabled { 1 } else { 0 }
-        ))
-        .map_err(|e| format!("Failed to insert site: {}", e))?;
+    let locations_json = if site.locations.is_empty() {
+        "".to_string()
+    } else {
+        serde_json::to_string(&site.locations).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize locations: {}", e)))?
+    };
+
+    let php_ini_settings_json = if site.php_ini_settings.is_empty() {
+        "".to_string()
+    } else {
+        serde_json::to_string(&site.php_ini_settings).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize PHP ini settings: {}", e)))?
+    };
+
+    let php_environment_json = if site.php_environment.is_empty() {
+        "".to_string()
+    } else {
+        serde_json::to_string(&site.php_environment).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize PHP environment: {}", e)))?
+    };
+
+    let websocket_json = serde_json::to_string(&site.websocket).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize WebSocket settings: {}", e)))?;
+    let webroot_sync_json = serde_json::to_string(&site.webroot_sync).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize webroot sync: {}", e)))?;
+    let cache_warm_json = serde_json::to_string(&site.cache_warm).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize cache warming: {}", e)))?;
+    let bandwidth_json = serde_json::to_string(&site.bandwidth).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize bandwidth limits: {}", e)))?;
+    let waf_json = serde_json::to_string(&site.waf).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize web application firewall: {}", e)))?;
+    let bots_json = serde_json::to_string(&site.bots).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize bot rules: {}", e)))?;
+    let plugins_json = serde_json::to_string(&site.plugins).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize plugins: {}", e)))?;
+    let lua_hooks_json = serde_json::to_string(&site.lua_hooks).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize Lua hooks: {}", e)))?;
+    let synthetic_probe_json =
+        serde_json::to_string(&site.synthetic_probe).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize synthetic probe: {}", e)))?;
+    let cache_header_rules_json =
+        serde_json::to_string(&site.cache_header_rules).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize cache header rules: {}", e)))?;
+    let acme_json = serde_json::to_string(&site.acme).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize ACME settings: {}", e)))?;
+
+    execute(
+        connection,
+        "INSERT INTO sites (id, is_default, is_enabled, hostnames, tls_cert_path, tls_cert_content, tls_key_path, tls_key_content, request_handlers, rewrite_functions, access_log_enabled, access_log_file, extra_headers, tls_automatic_enabled, locations, php_ini_settings, php_environment, sendfile_root, websocket, webroot_sync, cache_warm, error_response_format, bandwidth, waf, bots, middleware, plugins, lua_hooks, disk_quota_mb, synthetic_probe, cache_header_rules, acme) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        &[
+            &site.id,
+            &site.is_default,
+            &site.is_enabled,
+            &site.hostnames.join(","),
+            &site.tls_cert_path,
+            &site.tls_cert_content,
+            &site.tls_key_path,
+            &site.tls_key_content,
+            &site.request_handlers.join(","),
+            &site.rewrite_functions.join(","),
+            &site.access_log_enabled,
+            &site.access_log_file,
+            &extra_headers_str,
+            &site.tls_automatic_enabled,
+            &locations_json,
+            &php_ini_settings_json,
+            &php_environment_json,
+            &site.sendfile_root,
+            &websocket_json,
+            &webroot_sync_json,
+            &cache_warm_json,
+            &site.error_response_format,
+            &bandwidth_json,
+            &waf_json,
+            &bots_json,
+            &site.middleware.join(","),
+            &plugins_json,
+            &lua_hooks_json,
+            &site.disk_quota_mb,
+            &synthetic_probe_json,
+            &cache_header_rules_json,
+            &acme_json,
+        ],
+    )
+    .map_err(|e| e.with_context("Failed to insert site"))?;
 
     trace(format!("Inserted site with id: {}", site.id));
 
     Ok(())
 }
 
-fn save_request_handler(connection: &Connection, handler: &RequestHandler) -> Result<(), String> {
+fn save_request_handler(connection: &Connection, handler: &RequestHandler) -> Result<(), GruxiError> {
     // Prepare comma-separated strings
     let url_match_str = handler.url_match.join(",");
 
     // Insert request handler with comma-separated fields
-    connection
-        .execute(format!(
-            "INSERT INTO request_handler (id, is_enabled, name, processor_type, processor_id, url_match) VALUES ('{}', {}, '{}', '{}', '{}', '{}')",
-            handler.id,
-            if handler.is_enabled { 1 } else { 0 },
-            handler.name.replace("'", "''"),
-            handler.processor_type,
-            handler.processor_id,
-            url_match_str
-        ))
-        .map_err(|e| format!("Failed to insert request handler: {}", e))?;
+    execute(
+        connection,
+        "INSERT INTO request_handler (id, is_enabled, name, processor_type, processor_id, url_match) VALUES (?, ?, ?, ?, ?, ?)",
+        &[&handler.id, &handler.is_enabled, &handler.name, &handler.processor_type, &handler.processor_id, &url_match_str],
+    )
+    .map_err(|e| e.with_context("Failed to insert request handler"))?;
 
     Ok(())
 }
@@ -1,15 +1,64 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+use crate::http::request_line::HTTP_VERSION_POLICIES;
+use crate::http::uri_normalization::ENCODED_SLASH_POLICIES;
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct ServerSettings {
+    #[serde(alias = "max_request_body_size")]
     pub max_body_size: u64, // in bytes
     pub blocked_file_patterns: Vec<String>,
+    // How unsupported HTTP versions and malformed request lines are answered: "lenient", "standard" or "strict"
+    #[serde(default = "default_http_version_policy")]
+    pub http_version_policy: String,
+    // Whether TRACE requests are served, they are answered with 405 otherwise, as echoing requests back can leak credentials
+    #[serde(default)]
+    pub trace_method_enabled: bool,
+    // How encoded slashes (%2F) in request paths are handled, when the path is made canonical: "keep", "decode" or "reject"
+    #[serde(default = "default_encoded_slash_policy")]
+    pub encoded_slash_policy: String,
+    // Hard limits on the heads and chunks of requests, enforced before hyper buffers them
+    #[serde(default = "default_max_header_count")]
+    pub max_header_count: usize,
+    #[serde(default = "default_max_header_size")]
+    pub max_header_size: usize, // in bytes, all header lines together
+    #[serde(default = "default_max_uri_length")]
+    pub max_uri_length: usize, // in bytes
+    #[serde(default = "default_max_chunk_size")]
+    pub max_chunk_size: u64, // in bytes, for each chunk of a chunked request body
+}
+
+fn default_http_version_policy() -> String {
+    "standard".to_string()
+}
+
+fn default_encoded_slash_policy() -> String {
+    "keep".to_string()
+}
+
+pub fn default_max_header_count() -> usize {
+    100
+}
+
+pub fn default_max_header_size() -> usize {
+    64 * 1024
+}
+
+pub fn default_max_uri_length() -> usize {
+    8192
+}
+
+pub fn default_max_chunk_size() -> u64 {
+    16 * 1024 * 1024
 }
 
 impl ServerSettings {
     pub fn sanitize(&mut self) {
         // Ensure blocked file patterns are lowercase for consistent matching and remove any asterisk before extension
         self.blocked_file_patterns = self.blocked_file_patterns.iter().map(|p| p.to_lowercase().replace("*", "")).collect();
+        self.http_version_policy = self.http_version_policy.trim().to_lowercase();
+        self.encoded_slash_policy = self.encoded_slash_policy.trim().to_lowercase();
     }
 
     pub fn validate(&self) -> Result<(), Vec<String>> {
@@ -27,6 +76,32 @@ impl ServerSettings {
             errors.push("Max body size cannot be 0".to_string());
         }
 
+        if !HTTP_VERSION_POLICIES.contains(&self.http_version_policy.as_str()) {
+            errors.push(format!("HTTP version policy must be one of {}, got '{}'", HTTP_VERSION_POLICIES.join(", "), self.http_version_policy));
+        }
+
+        if !ENCODED_SLASH_POLICIES.contains(&self.encoded_slash_policy.as_str()) {
+            errors.push(format!(
+                "Encoded slash policy must be one of {}, got '{}'",
+                ENCODED_SLASH_POLICIES.join(", "),
+                self.encoded_slash_policy
+            ));
+        }
+
+        // Too low limits would turn away ordinary browser requests, and hyper cannot parse URIs beyond 65534 bytes
+        if !(10..=10000).contains(&self.max_header_count) {
+            errors.push(format!("Max header count must be between 10 and 10000, got {}", self.max_header_count));
+        }
+        if !(4096..=1024 * 1024).contains(&self.max_header_size) {
+            errors.push(format!("Max header size must be between 4096 and 1048576 bytes, got {}", self.max_header_size));
+        }
+        if !(256..=65534).contains(&self.max_uri_length) {
+            errors.push(format!("Max URI length must be between 256 and 65534 bytes, got {}", self.max_uri_length));
+        }
+        if self.max_chunk_size < 1024 {
+            errors.push(format!("Max chunk size must be at least 1024 bytes, got {}", self.max_chunk_size));
+        }
+
         if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
 }
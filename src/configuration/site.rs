@@ -1,13 +1,61 @@
+use std::sync::{Arc, OnceLock};
+
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+use crate::http::error_response::ERROR_RESPONSE_FORMATS;
+use crate::http::middleware::middleware_chain::validate_middleware_chain;
+use crate::http::site_match::hostname_pattern::{HostnamePattern, is_regex_hostname};
+use crate::{
+    configuration::bandwidth_settings::BandwidthSettings, configuration::bot_settings::BotSettings, configuration::cache_header_rule::CacheHeaderRule,
+    configuration::cache_warm_settings::CacheWarmSettings, configuration::location::Location, configuration::lua_hook::LuaHook, configuration::plugin_settings::WasmPlugin,
+    configuration::site_acme_settings::SiteAcmeSettings, configuration::synthetic_probe_settings::SyntheticProbeSettings, configuration::waf_settings::WafSettings,
+    configuration::webroot_sync_settings::WebrootSyncSettings, configuration::websocket_settings::WebSocketSettings, external_connections::managed_system::environment_variable::EnvironmentVariable,
+    file::normalized_path::NormalizedPath,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct HeaderKV {
     pub key: String,
     pub value: String,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+// PHP ini directive for a site, such as memory_limit=256M. Passed to the PHP handler in the PHP_VALUE FastCGI param,
+// or PHP_ADMIN_VALUE when is_admin is set, so the script cannot change it with ini_set()
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PhpIniSetting {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub is_admin: bool,
+}
+
+impl PhpIniSetting {
+    pub fn sanitize(&mut self) {
+        self.name = self.name.trim().to_string();
+        self.value = self.value.trim().to_string();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.name.is_empty() {
+            errors.push("PHP setting name cannot be empty.".to_string());
+        } else if !self.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_') {
+            errors.push(format!("PHP setting name '{}' can only contain letters, digits, '.' and '_'.", self.name));
+        }
+
+        // Settings are sent as newline separated lines, so a newline in the value would inject another setting
+        if self.value.contains(['\n', '\r', '\0']) {
+            errors.push(format!("PHP setting '{}' cannot contain newlines or null characters in its value.", self.name));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 #[allow(unused)]
 pub struct Site {
     pub id: String,
@@ -15,7 +63,11 @@ pub struct Site {
     pub is_default: bool,
     pub is_enabled: bool,
     // Automatic TLS
+    #[serde(alias = "tls_automatic")]
     pub tls_automatic_enabled: bool,
+    // CA, certificate keys and ACME profile of the automatic TLS certificates, on top of the global ACME settings
+    #[serde(default)]
+    pub acme: SiteAcmeSettings,
     // TLS certificate path or actual content
     pub tls_cert_path: String,
     pub tls_cert_content: String,
@@ -28,9 +80,71 @@ pub struct Site {
     pub request_handlers: Vec<String>, // List of request handler IDs for this site
     #[serde(default)]
     pub extra_headers: Vec<HeaderKV>,
+    // Per-path locations, first enabled match wins and overrides the site settings above
+    #[serde(default)]
+    pub locations: Vec<Location>,
+    // PHP ini directives and environment variables for this site, on top of those of the PHP handler
+    #[serde(default)]
+    pub php_ini_settings: Vec<PhpIniSetting>,
+    #[serde(default)]
+    pub php_environment: Vec<EnvironmentVariable>,
+    // Internal root for files that handlers ask to be sent with X-Sendfile or X-Accel-Redirect. Empty disables it
+    #[serde(default)]
+    pub sendfile_root: String,
+    // Limits for WebSocket connections upgraded through the handlers of this site
+    #[serde(default)]
+    pub websocket: WebSocketSettings,
+    // Remote SFTP or FTPS server the web root of the site is pulled from, for sites still uploaded to the old way
+    #[serde(default)]
+    pub webroot_sync: WebrootSyncSettings,
+    // Crawl of the site's own pages, to warm its caches after starts, reloads and deploys
+    #[serde(default)]
+    pub cache_warm: CacheWarmSettings,
+    // Download rate limits for the response bodies of the site
+    #[serde(default)]
+    pub bandwidth: BandwidthSettings,
+    // Web application firewall, checking requests before they reach the request handlers
+    #[serde(default)]
+    pub waf: WafSettings,
+    // Rules for bots and crawlers, and the robots.txt served to them
+    #[serde(default)]
+    pub bots: BotSettings,
+    // The middleware requests go through around the request handlers, in order. Empty for the default chain
+    #[serde(default)]
+    pub middleware: Vec<String>,
+    // WebAssembly plugins filtering requests and responses, in order, run by the "plugins" middleware
+    #[serde(default)]
+    pub plugins: Vec<WasmPlugin>,
+    // Lua scripts run at the phases of requests, run by the "lua" middleware
+    #[serde(default)]
+    pub lua_hooks: Vec<LuaHook>,
+    // How error responses without a body are answered: "html" as before, "json" with the status, message and request ID for
+    // API sites, or "negotiate" for JSON when the client prefers it in its Accept header
+    #[serde(default = "default_error_response_format")]
+    pub error_response_format: String,
+    // Soft quota for the disk usage of the web roots of the site, in MB. WebDAV writes and deployments are rejected while
+    // the site is over it. 0 for no quota
+    #[serde(default)]
+    pub disk_quota_mb: u64,
+    // Request of a URL of the site at an interval, recording its latency and status, alerting when it keeps failing
+    #[serde(default)]
+    pub synthetic_probe: SyntheticProbeSettings,
+    // Cache-Control and Expires headers attached to responses by path pattern or content type, first match wins
+    #[serde(default)]
+    pub cache_header_rules: Vec<CacheHeaderRule>,
     // Logs
     pub access_log_enabled: bool,
     pub access_log_file: String,
+
+    // Calculated fields (not serialized)
+    #[serde(skip)]
+    pub(crate) hostname_patterns: OnceLock<Vec<HostnamePattern>>,
+    #[serde(skip)]
+    pub(crate) location_sites: OnceLock<Vec<Arc<Site>>>,
+}
+
+fn default_error_response_format() -> String {
+    "html".to_string()
 }
 
 // Supported rewrite functions
@@ -44,6 +158,7 @@ impl Site {
             is_default: false,
             is_enabled: true,
             tls_automatic_enabled: false,
+            acme: SiteAcmeSettings::new(),
             tls_cert_path: String::new(),
             tls_cert_content: String::new(),
             tls_key_path: String::new(),
@@ -51,8 +166,27 @@ impl Site {
             request_handlers: Vec::new(),
             rewrite_functions: Vec::new(),
             extra_headers: Vec::new(),
+            locations: Vec::new(),
+            php_ini_settings: Vec::new(),
+            php_environment: Vec::new(),
+            sendfile_root: String::new(),
+            websocket: WebSocketSettings::new(),
+            webroot_sync: WebrootSyncSettings::new(),
+            cache_warm: CacheWarmSettings::new(),
+            bandwidth: BandwidthSettings::new(),
+            waf: WafSettings::new(),
+            bots: BotSettings::new(),
+            middleware: Vec::new(),
+            plugins: Vec::new(),
+            lua_hooks: Vec::new(),
+            error_response_format: default_error_response_format(),
+            disk_quota_mb: 0,
+            synthetic_probe: SyntheticProbeSettings::new(),
+            cache_header_rules: Vec::new(),
             access_log_enabled: false,
             access_log_file: String::new(),
+            hostname_patterns: OnceLock::new(),
+            location_sites: OnceLock::new(),
         }
     }
 
@@ -61,12 +195,55 @@ impl Site {
         for hostname in &mut self.hostnames {
             *hostname = hostname.trim().to_string();
         }
+        // Hostnames may have changed, so parse them again on next use
+        self.hostname_patterns = OnceLock::new();
 
         // Trim whitespace from rewrite functions
         for func in &mut self.rewrite_functions {
             *func = func.trim().to_string();
         }
 
+        // Trim whitespace from sendfile root
+        self.sendfile_root = self.sendfile_root.trim().to_string();
+
+        // Sanitize the ACME settings
+        self.acme.sanitize();
+
+        // Sanitize the webroot sync
+        self.webroot_sync.sanitize();
+
+        // Sanitize the cache warming
+        self.cache_warm.sanitize();
+
+        // Sanitize the synthetic probe
+        self.synthetic_probe.sanitize();
+
+        // Sanitize the web application firewall
+        self.waf.sanitize();
+
+        // Sanitize the bot rules
+        self.bots.sanitize();
+
+        // Trim whitespace from the middleware names
+        self.middleware = self.middleware.iter().map(|name| name.trim().to_lowercase()).filter(|name| !name.is_empty()).collect();
+
+        // Sanitize the plugins
+        for plugin in &mut self.plugins {
+            plugin.sanitize();
+        }
+
+        // Sanitize the Lua hooks
+        for hook in &mut self.lua_hooks {
+            hook.sanitize();
+        }
+
+        // Sanitize the cache header rules
+        for rule in &mut self.cache_header_rules {
+            rule.sanitize();
+        }
+
+        self.error_response_format = self.error_response_format.trim().to_lowercase();
+
         // Trim whitespace from access log file
         self.access_log_file = self.access_log_file.trim().to_string();
 
@@ -75,6 +252,19 @@ impl Site {
             kv.key = kv.key.trim().to_string();
             kv.value = kv.value.trim().to_string();
         }
+
+        // Sanitize locations
+        for location in &mut self.locations {
+            location.sanitize();
+        }
+
+        // Sanitize PHP settings
+        for setting in &mut self.php_ini_settings {
+            setting.sanitize();
+        }
+        for variable in &mut self.php_environment {
+            variable.sanitize();
+        }
     }
 
     pub fn validate(&self) -> Result<(), Vec<String>> {
@@ -90,6 +280,11 @@ impl Site {
                 errors.push(format!("Hostname {} cannot be empty", hostname_idx + 1));
             } else if hostname.trim() != "*" && hostname.trim().len() < 3 {
                 errors.push(format!("Hostname '{}' is too short (minimum 3 characters unless wildcard '*')", hostname.trim()));
+            } else if is_regex_hostname(hostname.trim()) && hostname.contains(',') {
+                // Hostnames are stored separated by commas
+                errors.push(format!("Hostname regex '{}' cannot contain commas, such as in '{{1,3}}'", hostname.trim()));
+            } else if let Err(e) = HostnamePattern::parse(hostname) {
+                errors.push(e);
             }
         }
 
@@ -154,6 +349,10 @@ impl Site {
             }
         }
 
+        if let Err(acme_errors) = self.acme.validate() {
+            errors.extend(acme_errors);
+        }
+
         // Validate extra headers (optional but keys/values must be non-empty when present)
         for (idx, kv) in self.extra_headers.iter().enumerate() {
             if kv.key.trim().is_empty() {
@@ -164,9 +363,160 @@ impl Site {
             }
         }
 
+        // Validate locations
+        let mut location_ids = std::collections::HashSet::new();
+        for (idx, location) in self.locations.iter().enumerate() {
+            if !location_ids.insert(&location.id) {
+                errors.push(format!("Duplicate location ID found: '{}'", location.id));
+            }
+            if let Err(location_errors) = location.validate() {
+                for error in location_errors {
+                    errors.push(format!("Location {}: {}", idx + 1, error));
+                }
+            }
+        }
+
+        // Validate the sendfile root, which must be a usable path when set
+        if !self.sendfile_root.is_empty() && NormalizedPath::new(&self.sendfile_root, "").is_err() {
+            errors.push(format!("Sendfile root path is invalid: '{}' - Check strange characters and path format", self.sendfile_root));
+        }
+
+        // Validate PHP settings
+        for (idx, setting) in self.php_ini_settings.iter().enumerate() {
+            if let Err(setting_errors) = setting.validate() {
+                for error in setting_errors {
+                    errors.push(format!("PHP setting {}: {}", idx + 1, error));
+                }
+            }
+        }
+        for (idx, variable) in self.php_environment.iter().enumerate() {
+            if let Err(variable_errors) = variable.validate() {
+                for error in variable_errors {
+                    errors.push(format!("PHP environment variable {}: {}", idx + 1, error));
+                }
+            }
+        }
+
+        // Validate WebSocket limits
+        if let Err(websocket_errors) = self.websocket.validate() {
+            errors.extend(websocket_errors);
+        }
+
+        // Validate the webroot sync
+        if let Err(webroot_sync_errors) = self.webroot_sync.validate() {
+            errors.extend(webroot_sync_errors);
+        }
+
+        // Validate the cache warming
+        if let Err(cache_warm_errors) = self.cache_warm.validate() {
+            errors.extend(cache_warm_errors);
+        }
+
+        // Validate the synthetic probe
+        if let Err(synthetic_probe_errors) = self.synthetic_probe.validate() {
+            errors.extend(synthetic_probe_errors);
+        }
+
+        if let Err(bandwidth_errors) = self.bandwidth.validate() {
+            errors.extend(bandwidth_errors);
+        }
+
+        if let Err(waf_errors) = self.waf.validate() {
+            errors.extend(waf_errors);
+        }
+
+        if let Err(bot_errors) = self.bots.validate() {
+            errors.extend(bot_errors);
+        }
+
+        if let Err(middleware_errors) = validate_middleware_chain(&self.middleware) {
+            errors.extend(middleware_errors);
+        }
+
+        for plugin in &self.plugins {
+            if let Err(plugin_errors) = plugin.validate() {
+                errors.extend(plugin_errors);
+            }
+        }
+        // Plugins only run in the "plugins" middleware, which the default chain has
+        let has_enabled_plugins = self.plugins.iter().any(|plugin| plugin.is_enabled);
+        if has_enabled_plugins && !self.middleware.is_empty() && !self.middleware.iter().any(|name| name == "plugins") {
+            errors.push("Site has plugins, but its middleware chain does not contain 'plugins'".to_string());
+        }
+
+        for hook in &self.lua_hooks {
+            if let Err(hook_errors) = hook.validate() {
+                errors.extend(hook_errors);
+            }
+        }
+        let has_enabled_lua_hooks = self.lua_hooks.iter().any(|hook| hook.is_enabled);
+        if has_enabled_lua_hooks && !self.middleware.is_empty() && !self.middleware.iter().any(|name| name == "lua") {
+            errors.push("Site has Lua hooks, but its middleware chain does not contain 'lua'".to_string());
+        }
+
+        for rule in &self.cache_header_rules {
+            if let Err(rule_errors) = rule.validate() {
+                errors.extend(rule_errors);
+            }
+        }
+
+        if !ERROR_RESPONSE_FORMATS.contains(&self.error_response_format.as_str()) {
+            errors.push(format!(
+                "Error response format must be one of {}, got '{}'",
+                ERROR_RESPONSE_FORMATS.join(", "),
+                self.error_response_format
+            ));
+        }
+
         if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
 
+    // Find the first enabled location matching the path, if any
+    pub fn get_matching_location(&self, url_path: &str) -> Option<&Location> {
+        self.locations.iter().find(|location| location.is_enabled && location.matches_path(url_path))
+    }
+
+    // The site with the overrides from the location applied, so processors can use it as a normal site. Made once for
+    // each location of the site, as requests share the site for as long as the configuration is in use
+    pub fn with_location_overrides(&self, location: &Location) -> Arc<Site> {
+        let location_sites = self
+            .location_sites
+            .get_or_init(|| self.locations.iter().map(|location| Arc::new(self.apply_location_overrides(location))).collect());
+        match self.locations.iter().position(|site_location| std::ptr::eq(site_location, location)) {
+            Some(index) => location_sites[index].clone(),
+            None => Arc::new(self.apply_location_overrides(location)),
+        }
+    }
+
+    fn apply_location_overrides(&self, location: &Location) -> Site {
+        let mut site = self.clone();
+        if !location.request_handlers.is_empty() {
+            site.request_handlers = location.request_handlers.clone();
+        }
+        if !location.rewrite_functions.is_empty() {
+            site.rewrite_functions = location.rewrite_functions.clone();
+        }
+        site.extra_headers.extend(location.extra_headers.iter().cloned());
+        site
+    }
+
+    // The PHP ini settings in the PHP_VALUE (or PHP_ADMIN_VALUE) format, one "name=value" per line
+    pub fn get_php_ini_values(&self, is_admin: bool) -> String {
+        self.php_ini_settings
+            .iter()
+            .filter(|setting| setting.is_admin == is_admin)
+            .map(|setting| format!("{}={}", setting.name, setting.value))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // The hostnames of the site, as patterns to match requested hostnames against. Hostnames that are not valid patterns
+    // are left out, as the validation rejects them
+    pub fn get_hostname_patterns(&self) -> &[HostnamePattern] {
+        self.hostname_patterns
+            .get_or_init(|| self.hostnames.iter().filter_map(|hostname| HostnamePattern::parse(hostname).ok()).collect())
+    }
+
     pub fn get_rewrite_functions_hashmap(&self) -> std::collections::HashMap<String, ()> {
         let mut hashmap = std::collections::HashMap::new();
         for func in &self.rewrite_functions {
@@ -179,7 +529,17 @@ impl Site {
         let hostname_trimmed = hostname.trim();
 
         // Check the basics
-        if hostname_trimmed == "*" {
+        if is_regex_hostname(hostname_trimmed) {
+            return Err(format!(
+                "Hostname '{}' cannot be a regular expression when automatic TLS is enabled - It needs to be public domains, such as example.com",
+                hostname_trimmed
+            ));
+        } else if hostname_trimmed.starts_with("*.") {
+            return Err(format!(
+                "Hostname '{}' cannot be a wildcard when automatic TLS is enabled, as wildcard certificates need DNS validation",
+                hostname_trimmed
+            ));
+        } else if hostname_trimmed == "*" {
             return Err(format!(
                 "Hostname '{}' cannot be wildcard '*' when automatic TLS is enabled - It needs to be public domains, such as example.com",
                 hostname_trimmed
@@ -215,6 +575,64 @@ impl Site {
     }
 }
 
+#[test]
+fn test_site_location_overrides() {
+    let mut site = Site::new();
+    site.request_handlers = vec!["site-handler".to_string()];
+    site.extra_headers = vec![HeaderKV {
+        key: "X-Site".to_string(),
+        value: "1".to_string(),
+    }];
+
+    let mut location = Location::new();
+    location.pattern = "/api/".to_string();
+    location.request_handlers = vec!["api-handler".to_string()];
+    location.extra_headers = vec![HeaderKV {
+        key: "X-Api".to_string(),
+        value: "1".to_string(),
+    }];
+    site.locations = vec![location];
+
+    assert!(site.get_matching_location("/static/file.css").is_none());
+    let location = site.get_matching_location("/api/users").expect("Location should match");
+    let effective_site = site.with_location_overrides(location);
+    assert_eq!(effective_site.request_handlers, vec!["api-handler".to_string()]);
+    assert_eq!(effective_site.extra_headers.len(), 2);
+    assert!(Arc::ptr_eq(&effective_site, &site.with_location_overrides(location)));
+}
+
+#[test]
+fn test_site_disabled_location_is_skipped() {
+    let mut site = Site::new();
+    let mut location = Location::new();
+    location.is_enabled = false;
+    site.locations = vec![location];
+
+    assert!(site.get_matching_location("/anything").is_none());
+}
+
+#[test]
+fn test_site_php_ini_settings() {
+    let mut site = Site::new();
+    let php_ini_setting = |name: &str, value: &str, is_admin: bool| PhpIniSetting {
+        name: name.to_string(),
+        value: value.to_string(),
+        is_admin,
+    };
+    site.php_ini_settings = vec![
+        php_ini_setting("memory_limit", "256M", false),
+        php_ini_setting("opcache.enable", "1", false),
+        php_ini_setting("open_basedir", "/var/www", true),
+    ];
+    assert!(site.validate().is_ok());
+    assert_eq!(site.get_php_ini_values(false), "memory_limit=256M\nopcache.enable=1");
+    assert_eq!(site.get_php_ini_values(true), "open_basedir=/var/www");
+
+    // A newline in a value would sneak in another setting
+    site.php_ini_settings[0].value = "256M\ndisable_functions=".to_string();
+    assert!(site.validate().is_err());
+}
+
 #[test]
 fn test_site_validation_access_log_enabled_empty_file() {
     let mut site = Site::new();
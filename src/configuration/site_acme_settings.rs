@@ -0,0 +1,61 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::tls::acme_account::ACME_PROVIDERS;
+
+// Keys of the certificates ordered for a site, where "both" orders an RSA certificate next to the ECDSA one, for clients
+// without ECDSA support
+pub const ACME_KEY_TYPES: [&str; 3] = ["ecdsa", "rsa", "both"];
+
+// How the automatic TLS certificates of a site are ordered, on top of the global ACME settings
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct SiteAcmeSettings {
+    pub provider: String,      // One of ACME_PROVIDERS, or empty for the provider of the TLS settings
+    pub directory_url: String, // For the "custom" provider
+    pub key_type: String,      // One of ACME_KEY_TYPES
+    pub profile: String,       // ACME profile, such as "tlsserver" or "shortlived" at Let's Encrypt, or empty for the default of the CA
+}
+
+impl Default for SiteAcmeSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SiteAcmeSettings {
+    pub fn new() -> Self {
+        Self {
+            provider: String::new(),
+            directory_url: String::new(),
+            key_type: "ecdsa".to_string(),
+            profile: String::new(),
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.provider = self.provider.trim().to_lowercase();
+        self.directory_url = self.directory_url.trim().to_string();
+        self.key_type = self.key_type.trim().to_lowercase();
+        self.profile = self.profile.trim().to_string();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if !self.provider.is_empty() && !ACME_PROVIDERS.contains(&self.provider.as_str()) {
+            errors.push(format!("ACME provider must be empty or one of {}, got '{}'", ACME_PROVIDERS.join(", "), self.provider));
+        }
+        if self.provider == "custom" && self.directory_url.parse::<http::Uri>().map(|u| u.scheme_str() != Some("https") || u.host().is_none()).unwrap_or(true) {
+            errors.push(format!("ACME directory URL must be an https:// URL for the custom provider, got '{}'", self.directory_url));
+        }
+        if !ACME_KEY_TYPES.contains(&self.key_type.as_str()) {
+            errors.push(format!("ACME key type must be one of {}, got '{}'", ACME_KEY_TYPES.join(", "), self.key_type));
+        }
+        // Profile names are tokens advertised in the directory of the CA
+        if self.profile.len() > 64 || !self.profile.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+            errors.push(format!("ACME profile can only contain letters, digits, '-', '_' and '.', got '{}'", self.profile));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
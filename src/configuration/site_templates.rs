@@ -0,0 +1,259 @@
+use serde::{Deserialize, Serialize};
+
+use crate::configuration::{binding_site_relation::BindingSiteRelationship, configuration::Configuration, request_handler::RequestHandler, site::Site};
+use crate::http::request_handlers::processors::{php_processor::PHPProcessor, static_files_processor::StaticFileProcessor};
+
+// The templates sites can be provisioned from in one call:
+//   static     a static file processor on the web root
+//   php        PHP files and directories with an index.php by PHP, the other files by the static file processor
+//   wordpress  as php, with paths that are not files, such as pretty permalinks, sent to index.php
+pub const SITE_TEMPLATES: [&str; 3] = ["static", "php", "wordpress"];
+
+const DEFAULT_WEB_ROOT_BASE: &str = "./www-sites";
+const DEFAULT_FASTCGI_ADDRESS: &str = "127.0.0.1:9000";
+
+// A request to provision a site from a template, as sent to the admin API
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SiteTemplateRequest {
+    pub template: String,
+    pub hostnames: Vec<String>,
+    // Defaults to ./www-sites/<first hostname>
+    #[serde(default)]
+    pub web_root: String,
+    // The bindings to serve the site on, defaults to all non-admin bindings
+    #[serde(default)]
+    pub binding_ids: Vec<String>,
+    // PHP is served by the PHP-CGI handler if set, otherwise by PHP-FPM on the FastCGI address, defaulting to 127.0.0.1:9000
+    #[serde(default)]
+    pub php_cgi_handler_id: String,
+    #[serde(default)]
+    pub php_fastcgi_address: String,
+    // The web root as seen by PHP-FPM, when it runs elsewhere, such as in a container. Defaults to the web root
+    #[serde(default)]
+    pub php_fastcgi_web_root: String,
+    // Get a certificate for the hostnames from ACME
+    #[serde(default)]
+    pub acme_enabled: bool,
+}
+
+impl SiteTemplateRequest {
+    pub fn sanitize(&mut self) {
+        self.template = self.template.trim().to_lowercase();
+        self.hostnames = self.hostnames.iter().map(|hostname| hostname.trim().to_lowercase()).filter(|hostname| !hostname.is_empty()).collect();
+        self.web_root = self.web_root.trim().replace('\\', "/");
+        self.binding_ids = self.binding_ids.iter().map(|id| id.trim().to_string()).filter(|id| !id.is_empty()).collect();
+        self.php_cgi_handler_id = self.php_cgi_handler_id.trim().to_string();
+        self.php_fastcgi_address = self.php_fastcgi_address.trim().to_string();
+        self.php_fastcgi_web_root = self.php_fastcgi_web_root.trim().replace('\\', "/");
+
+        // The default web root is named by the first hostname, when it is usable as a directory name
+        if self.web_root.is_empty()
+            && let Some(hostname) = self.hostnames.first()
+            && hostname.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+            && !hostname.starts_with('.')
+        {
+            self.web_root = format!("{}/{}", DEFAULT_WEB_ROOT_BASE, hostname);
+        }
+    }
+
+    pub fn validate(&self, configuration: &Configuration) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if !SITE_TEMPLATES.contains(&self.template.as_str()) {
+            errors.push(format!("Template must be one of {}, got '{}'", SITE_TEMPLATES.join(", "), self.template));
+        }
+        if self.hostnames.is_empty() {
+            errors.push("At least one hostname is required".to_string());
+        }
+        for hostname in &self.hostnames {
+            if configuration.sites.iter().any(|site| site.hostnames.iter().any(|existing| existing.eq_ignore_ascii_case(hostname))) {
+                errors.push(format!("Hostname '{}' is already used by another site", hostname));
+            }
+        }
+        if self.web_root.is_empty() {
+            errors.push("Web root must be set, as the first hostname cannot be used as a directory name".to_string());
+        }
+        for binding_id in &self.binding_ids {
+            if !configuration.bindings.iter().any(|binding| &binding.id == binding_id && !binding.is_admin) {
+                errors.push(format!("Binding '{}' does not exist or is the admin portal binding", binding_id));
+            }
+        }
+        if !self.php_cgi_handler_id.is_empty() && !configuration.php_cgi_handlers.iter().any(|handler| handler.id == self.php_cgi_handler_id) {
+            errors.push(format!("PHP-CGI handler '{}' does not exist", self.php_cgi_handler_id));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    // The index file created in a new web root, and its content
+    pub fn get_default_index_file(&self) -> (&'static str, String) {
+        let hostname = self.hostnames.first().map(|hostname| hostname.as_str()).unwrap_or("");
+        match self.template.as_str() {
+            "static" => (
+                "index.html",
+                format!(
+                    "<!DOCTYPE html>\n<html>\n<head><title>{0}</title></head>\n<body><h1>{0}</h1><p>This site is served by Gruxi.</p></body>\n</html>\n",
+                    hostname
+                ),
+            ),
+            _ => (
+                "index.php",
+                "<?php\necho '<h1>' . htmlspecialchars($_SERVER['HTTP_HOST'] ?? '') . '</h1><p>This site is served by Gruxi with PHP ' . PHP_VERSION . '.</p>';\n".to_string(),
+            ),
+        }
+    }
+}
+
+// Add a site, with its request handlers, processors and bindings, to the configuration, as laid out by the template.
+// The request must be sanitized and validated. Returns the ID of the new site
+pub fn add_site_from_template(configuration: &mut Configuration, request: &SiteTemplateRequest) -> String {
+    let mut site = Site::new();
+    site.hostnames = request.hostnames.clone();
+    site.tls_automatic_enabled = request.acme_enabled;
+
+    let is_php = request.template != "static";
+    let mut request_handlers = Vec::new();
+
+    // PHP files first, as the static file processor does not serve them
+    let php_handler = if is_php {
+        let mut php_processor = PHPProcessor::new();
+        if request.php_cgi_handler_id.is_empty() {
+            php_processor.served_by_type = "php-fpm".to_string();
+            php_processor.fastcgi_ip_and_port = if request.php_fastcgi_address.is_empty() {
+                DEFAULT_FASTCGI_ADDRESS.to_string()
+            } else {
+                request.php_fastcgi_address.clone()
+            };
+        } else {
+            php_processor.served_by_type = "win-php-cgi".to_string();
+            php_processor.php_cgi_handler_id = request.php_cgi_handler_id.clone();
+        }
+        php_processor.local_web_root = request.web_root.clone();
+        php_processor.fastcgi_web_root = if request.php_fastcgi_web_root.is_empty() {
+            request.web_root.clone()
+        } else {
+            request.php_fastcgi_web_root.clone()
+        };
+        if request.template == "wordpress" {
+            php_processor.server_software_spoof = "Apache".to_string();
+        }
+
+        let mut php_handler = RequestHandler::new();
+        php_handler.name = format!("PHP for {}", request.hostnames[0]);
+        php_handler.processor_type = "php".to_string();
+        php_handler.processor_id = php_processor.id.clone();
+        php_handler.url_match = vec!["*.php".to_string(), "*/".to_string()];
+        request_handlers.push(php_handler.clone());
+        configuration.php_processors.push(php_processor);
+        Some(php_handler)
+    } else {
+        None
+    };
+
+    let index_files = if is_php { vec![] } else { vec!["index.html".to_string()] };
+    let static_processor = StaticFileProcessor::new(request.web_root.clone(), index_files);
+    let mut static_handler = RequestHandler::new();
+    static_handler.name = format!("Static files for {}", request.hostnames[0]);
+    static_handler.processor_type = "static".to_string();
+    static_handler.processor_id = static_processor.id.clone();
+    request_handlers.push(static_handler);
+    configuration.static_file_processors.push(static_processor);
+
+    // WordPress routes everything that is not a file through index.php, which the PHP processor does with the rewrite
+    if request.template == "wordpress"
+        && let Some(php_handler) = php_handler
+    {
+        let mut fallback_handler = RequestHandler::new();
+        fallback_handler.name = format!("WordPress permalinks for {}", request.hostnames[0]);
+        fallback_handler.processor_type = "php".to_string();
+        fallback_handler.processor_id = php_handler.processor_id;
+        request_handlers.push(fallback_handler);
+        site.rewrite_functions = vec!["OnlyWebRootIndexForSubdirs".to_string()];
+    }
+
+    site.request_handlers = request_handlers.iter().map(|handler| handler.id.clone()).collect();
+    configuration.request_handlers.extend(request_handlers);
+
+    let binding_ids: Vec<String> = if request.binding_ids.is_empty() {
+        configuration.bindings.iter().filter(|binding| !binding.is_admin).map(|binding| binding.id.clone()).collect()
+    } else {
+        request.binding_ids.clone()
+    };
+    for binding_id in binding_ids {
+        configuration.binding_sites.push(BindingSiteRelationship { binding_id, site_id: site.id.clone() });
+    }
+
+    let site_id = site.id.clone();
+    configuration.sites.push(site);
+    site_id
+}
+
+// Create the web root of a provisioned site, with the default index file of the template unless the web root already
+// has an index file. Returns the files created
+pub fn create_web_root(request: &SiteTemplateRequest) -> Result<Vec<String>, String> {
+    let web_root = std::path::Path::new(&request.web_root);
+    std::fs::create_dir_all(web_root).map_err(|e| format!("Failed to create web root '{}': {}", request.web_root, e))?;
+
+    let (index_file_name, index_content) = request.get_default_index_file();
+    if ["index.html", "index.php"].iter().any(|name| web_root.join(name).exists()) {
+        return Ok(Vec::new());
+    }
+    let index_path = web_root.join(index_file_name);
+    std::fs::write(&index_path, index_content).map_err(|e| format!("Failed to write '{}': {}", index_path.display(), e))?;
+    Ok(vec![index_path.to_string_lossy().replace('\\', "/")])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_request(template: &str) -> SiteTemplateRequest {
+        let mut request: SiteTemplateRequest = serde_json::from_str(&format!(r#"{{"template": " {} ", "hostnames": ["Blog.Example.com", " "]}}"#, template)).unwrap();
+        request.sanitize();
+        request
+    }
+
+    #[test]
+    fn test_wordpress_template_wires_php_static_and_permalinks() {
+        let mut configuration = Configuration::get_default();
+        let request = get_request("WordPress");
+        assert_eq!(request.hostnames, vec!["blog.example.com".to_string()]);
+        assert_eq!(request.web_root, "./www-sites/blog.example.com");
+        assert!(request.validate(&configuration).is_ok());
+
+        let site_id = add_site_from_template(&mut configuration, &request);
+        let site = configuration.sites.iter().find(|site| site.id == site_id).unwrap();
+        let handler_types: Vec<(&str, Vec<String>)> = site
+            .request_handlers
+            .iter()
+            .map(|id| configuration.request_handlers.iter().find(|handler| &handler.id == id).unwrap())
+            .map(|handler| (handler.processor_type.as_str(), handler.url_match.clone()))
+            .collect();
+        assert_eq!(
+            handler_types,
+            vec![("php", vec!["*.php".to_string(), "*/".to_string()]), ("static", vec!["*".to_string()]), ("php", vec!["*".to_string()])]
+        );
+        assert_eq!(site.rewrite_functions, vec!["OnlyWebRootIndexForSubdirs".to_string()]);
+        assert_eq!(configuration.binding_sites.iter().filter(|relation| relation.site_id == site_id).count(), 2);
+
+        configuration.sanitize();
+        assert!(configuration.validate().is_ok());
+
+        // The same hostname cannot be provisioned twice
+        assert!(request.validate(&configuration).is_err());
+    }
+
+    #[test]
+    fn test_template_request_validation() {
+        let configuration = Configuration::get_default();
+        let mut request = get_request("unknown");
+        request.hostnames = vec!["*.example.com".to_string()];
+        request.web_root = String::new();
+        request.sanitize();
+        request.php_cgi_handler_id = "missing".to_string();
+
+        let errors = request.validate(&configuration).unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(get_request("static").get_default_index_file().0, "index.html");
+    }
+}
@@ -0,0 +1,53 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// Public status page of the admin portal, at /status and /status.json, showing the uptime of the server, the health of
+// the sites and the recent incidents
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct StatusPageSettings {
+    pub is_enabled: bool,
+    pub title: String,
+    pub site_ids: Vec<String>,        // Sites shown on the page, empty for all enabled sites
+    pub incident_count: u32,          // How many of the recent admin alerts are shown as incidents, 0 to show none
+    pub show_incident_messages: bool, // Whether incidents show the messages of the alerts, which can name internal systems
+}
+
+impl Default for StatusPageSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StatusPageSettings {
+    pub fn new() -> Self {
+        Self {
+            is_enabled: false,
+            title: "Service Status".to_string(),
+            site_ids: Vec::new(),
+            incident_count: 10,
+            show_incident_messages: false,
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.title = self.title.trim().to_string();
+        self.site_ids = self.site_ids.iter().map(|site_id| site_id.trim().to_string()).filter(|site_id| !site_id.is_empty()).collect();
+        self.site_ids.dedup();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.title.is_empty() {
+            errors.push("Title cannot be empty".to_string());
+        }
+        if self.title.chars().count() > 100 {
+            errors.push("Title cannot be longer than 100 characters".to_string());
+        }
+        if self.incident_count > 100 {
+            errors.push("Incident count cannot be more than 100, as only the latest 100 admin alerts are kept".to_string());
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
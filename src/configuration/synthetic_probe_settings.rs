@@ -0,0 +1,75 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// "full_stack" requests the site through one of its bindings, as visitors would, "upstreams" requests the upstream servers
+// of its proxy processors directly, to tell which of them fails
+pub const SYNTHETIC_PROBE_MODES: [&str; 2] = ["full_stack", "upstreams"];
+
+// Request of a URL of the site at an interval, recording its latency and status, alerting when it keeps failing
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct SyntheticProbeSettings {
+    pub is_enabled: bool,
+    pub path: String, // Path and query requested, such as "/" or "/api/status"
+    pub mode: String,
+    pub interval_seconds: u32,
+    pub timeout_seconds: u32,
+    pub expected_status: u16,   // Status the response must have, 0 for any status below 400
+    pub failure_threshold: u32, // Consecutive failed probes before an admin alert is raised
+}
+
+impl Default for SyntheticProbeSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyntheticProbeSettings {
+    pub fn new() -> Self {
+        Self {
+            is_enabled: false,
+            path: "/".to_string(),
+            mode: "full_stack".to_string(),
+            interval_seconds: 60,
+            timeout_seconds: 10,
+            expected_status: 0,
+            failure_threshold: 3,
+        }
+    }
+
+    pub fn is_expected_status(&self, status: u16) -> bool {
+        if self.expected_status == 0 { status > 0 && status < 400 } else { status == self.expected_status }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.path = self.path.trim().to_string();
+        self.mode = self.mode.trim().to_lowercase();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        if !self.is_enabled {
+            return Ok(());
+        }
+
+        if !self.path.starts_with('/') || self.path.contains(char::is_whitespace) {
+            errors.push(format!("Synthetic probe path must start with '/' and cannot contain whitespace, got '{}'", self.path));
+        }
+        if !SYNTHETIC_PROBE_MODES.contains(&self.mode.as_str()) {
+            errors.push(format!("Synthetic probe mode must be one of {}, got '{}'", SYNTHETIC_PROBE_MODES.join(", "), self.mode));
+        }
+        if self.interval_seconds < 10 || self.interval_seconds > 86400 {
+            errors.push(format!("Synthetic probe interval must be between 10 and 86400 seconds, got {}", self.interval_seconds));
+        }
+        if self.timeout_seconds < 1 || self.timeout_seconds > 300 {
+            errors.push(format!("Synthetic probe timeout must be between 1 and 300 seconds, got {}", self.timeout_seconds));
+        }
+        if self.expected_status != 0 && !(100..=599).contains(&self.expected_status) {
+            errors.push(format!("Synthetic probe expected status must be 0 or between 100 and 599, got {}", self.expected_status));
+        }
+        if self.failure_threshold < 1 || self.failure_threshold > 100 {
+            errors.push(format!("Synthetic probe failure threshold must be between 1 and 100, got {}", self.failure_threshold));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
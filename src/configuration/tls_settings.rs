@@ -1,13 +1,88 @@
+use base64::prelude::*;
 use email_address::{EmailAddress, Options};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::file::normalized_path::NormalizedPath;
+use crate::tls::acme_account::{ACME_PROVIDERS, provider_requires_eab};
+use crate::tls::acme_cache::CERTIFICATE_CACHE_BACKENDS;
+use crate::tls::shared_acme_manager::ACME_CHALLENGE_TYPES;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct TlsSettings {
     pub account_email: String,
+    #[serde(alias = "use_staging")]
     pub use_staging_server: bool,
     pub certificate_cache_path: String,
+    // Where ACME accounts and certificates are cached: "filesystem" in the certificate cache path, "database" or "s3".
+    // A cache shared by all nodes behind one DNS name keeps them from each ordering the same certificates
+    #[serde(default = "default_certificate_cache_backend")]
+    pub certificate_cache_backend: String,
+    #[serde(default)]
+    pub certificate_cache_database: String, // Shared SQLite database, or empty for the database of Gruxi
+    #[serde(default)]
+    pub certificate_cache_s3_endpoint: String, // Such as "https://s3.eu-west-1.amazonaws.com" or "https://minio.example.com:9000"
+    #[serde(default)]
+    pub certificate_cache_s3_bucket: String,
+    #[serde(default)]
+    pub certificate_cache_s3_region: String,
+    #[serde(default)]
+    pub certificate_cache_s3_access_key_id: String,
+    #[serde(default)]
+    pub certificate_cache_s3_secret_access_key: String,
+    // Order one multi-SAN certificate per apex domain, instead of one for all hostnames
+    #[serde(default)]
+    pub consolidate_acme_orders: bool,
+    // How domains are validated: "tls-alpn-01" on the TLS bindings, or "http-01" on port 80, for when TLS is terminated
+    // in front of Gruxi, such as by a load balancer
+    #[serde(default = "default_acme_challenge_type")]
+    pub acme_challenge_type: String,
+    // The CA to order certificates from, one of ACME_PROVIDERS, where "custom" uses acme_directory_url
+    #[serde(default = "default_acme_provider")]
+    pub acme_provider: String,
+    #[serde(default)]
+    pub acme_directory_url: String,
+    // External Account Binding, required by ZeroSSL and Google, from the dashboard of the CA
+    #[serde(default)]
+    pub acme_eab_key_id: String,
+    #[serde(default)]
+    pub acme_eab_hmac_key: String, // Base64url encoded
+    // Certificate transparency log monitoring for the configured domains
+    #[serde(default)]
+    pub ct_monitoring_enabled: bool,
+    #[serde(default = "default_ct_monitoring_interval_minutes")]
+    pub ct_monitoring_interval_minutes: u32,
+    #[serde(default)]
+    pub ct_monitoring_webhook_url: String, // Optional, alerts are always logged
+    // Session resumption for returning clients, with session tickets whose keys rotate within the ticket lifetime
+    #[serde(default = "default_session_resumption_enabled")]
+    pub session_resumption_enabled: bool,
+    #[serde(default = "default_session_ticket_lifetime_seconds")]
+    pub session_ticket_lifetime_seconds: u32,
+}
+
+fn default_session_resumption_enabled() -> bool {
+    true
+}
+
+fn default_session_ticket_lifetime_seconds() -> u32 {
+    12 * 60 * 60
+}
+
+fn default_ct_monitoring_interval_minutes() -> u32 {
+    360
+}
+
+fn default_certificate_cache_backend() -> String {
+    "filesystem".to_string()
+}
+
+fn default_acme_challenge_type() -> String {
+    "tls-alpn-01".to_string()
+}
+
+fn default_acme_provider() -> String {
+    "letsencrypt".to_string()
 }
 
 impl TlsSettings {
@@ -16,12 +91,42 @@ impl TlsSettings {
             account_email: String::new(),
             use_staging_server: false,
             certificate_cache_path: String::new(),
+            certificate_cache_backend: default_certificate_cache_backend(),
+            certificate_cache_database: String::new(),
+            certificate_cache_s3_endpoint: String::new(),
+            certificate_cache_s3_bucket: String::new(),
+            certificate_cache_s3_region: String::new(),
+            certificate_cache_s3_access_key_id: String::new(),
+            certificate_cache_s3_secret_access_key: String::new(),
+            consolidate_acme_orders: false,
+            acme_challenge_type: default_acme_challenge_type(),
+            acme_provider: default_acme_provider(),
+            acme_directory_url: String::new(),
+            acme_eab_key_id: String::new(),
+            acme_eab_hmac_key: String::new(),
+            ct_monitoring_enabled: false,
+            ct_monitoring_interval_minutes: default_ct_monitoring_interval_minutes(),
+            ct_monitoring_webhook_url: String::new(),
+            session_resumption_enabled: default_session_resumption_enabled(),
+            session_ticket_lifetime_seconds: default_session_ticket_lifetime_seconds(),
         }
     }
 
     pub fn sanitize(&mut self) {
         self.account_email = self.account_email.trim().to_string();
         self.certificate_cache_path = self.certificate_cache_path.trim().to_string();
+        self.certificate_cache_backend = self.certificate_cache_backend.trim().to_lowercase();
+        self.certificate_cache_database = self.certificate_cache_database.trim().to_string();
+        self.certificate_cache_s3_endpoint = self.certificate_cache_s3_endpoint.trim().trim_end_matches('/').to_string();
+        self.certificate_cache_s3_bucket = self.certificate_cache_s3_bucket.trim().to_string();
+        self.certificate_cache_s3_region = self.certificate_cache_s3_region.trim().to_string();
+        self.certificate_cache_s3_access_key_id = self.certificate_cache_s3_access_key_id.trim().to_string();
+        self.acme_challenge_type = self.acme_challenge_type.trim().to_lowercase();
+        self.acme_provider = self.acme_provider.trim().to_lowercase();
+        self.acme_directory_url = self.acme_directory_url.trim().to_string();
+        self.acme_eab_key_id = self.acme_eab_key_id.trim().to_string();
+        self.acme_eab_hmac_key = self.acme_eab_hmac_key.trim().to_string();
+        self.ct_monitoring_webhook_url = self.ct_monitoring_webhook_url.trim().to_string();
     }
 
     pub fn validate(&self) -> Result<(), Vec<String>> {
@@ -44,6 +149,90 @@ impl TlsSettings {
             }
         }
 
+        if !CERTIFICATE_CACHE_BACKENDS.contains(&self.certificate_cache_backend.as_str()) {
+            errors.push(format!(
+                "Certificate cache backend must be one of {}, got '{}'",
+                CERTIFICATE_CACHE_BACKENDS.join(", "),
+                self.certificate_cache_backend
+            ));
+        }
+
+        // The shared database is created on first use, but its directory has to exist
+        if self.certificate_cache_backend == "database" && !self.certificate_cache_database.is_empty() {
+            let database_directory = std::path::Path::new(&self.certificate_cache_database).parent();
+            if database_directory.is_some_and(|directory| !directory.as_os_str().is_empty() && !directory.is_dir()) {
+                errors.push(format!("Directory of the certificate cache database does not exist: {}", self.certificate_cache_database));
+            }
+        }
+
+        if self.certificate_cache_backend == "s3" {
+            if self
+                .certificate_cache_s3_endpoint
+                .parse::<http::Uri>()
+                .map(|u| !matches!(u.scheme_str(), Some("http" | "https")) || u.host().is_none())
+                .unwrap_or(true)
+            {
+                errors.push(format!(
+                    "Certificate cache S3 endpoint must be an http:// or https:// URL, got '{}'",
+                    self.certificate_cache_s3_endpoint
+                ));
+            }
+            if self.certificate_cache_s3_bucket.is_empty() {
+                errors.push("Certificate cache S3 bucket cannot be empty".to_string());
+            }
+            if self.certificate_cache_s3_region.is_empty() {
+                errors.push("Certificate cache S3 region cannot be empty, use 'us-east-1' for object stores without regions".to_string());
+            }
+            if self.certificate_cache_s3_access_key_id.is_empty() || self.certificate_cache_s3_secret_access_key.is_empty() {
+                errors.push("Certificate cache S3 access key id and secret access key are required".to_string());
+            }
+        }
+
+        if !ACME_CHALLENGE_TYPES.contains(&self.acme_challenge_type.as_str()) {
+            errors.push(format!("ACME challenge type must be one of {}, got '{}'", ACME_CHALLENGE_TYPES.join(", "), self.acme_challenge_type));
+        }
+
+        if !ACME_PROVIDERS.contains(&self.acme_provider.as_str()) {
+            errors.push(format!("ACME provider must be one of {}, got '{}'", ACME_PROVIDERS.join(", "), self.acme_provider));
+        }
+        if self.acme_provider == "custom"
+            && self
+                .acme_directory_url
+                .parse::<http::Uri>()
+                .map(|u| u.scheme_str() != Some("https") || u.host().is_none())
+                .unwrap_or(true)
+        {
+            errors.push(format!("ACME directory URL must be an https:// URL for the custom provider, got '{}'", self.acme_directory_url));
+        }
+        if self.acme_eab_key_id.is_empty() != self.acme_eab_hmac_key.is_empty() {
+            errors.push("ACME external account binding requires both the key id and the HMAC key".to_string());
+        }
+        if !self.acme_eab_hmac_key.is_empty() && BASE64_URL_SAFE_NO_PAD.decode(self.acme_eab_hmac_key.trim_end_matches('=')).is_err() {
+            errors.push("ACME external account binding HMAC key must be base64url encoded".to_string());
+        }
+        if provider_requires_eab(&self.acme_provider) && !self.account_email.is_empty() && self.acme_eab_key_id.is_empty() {
+            errors.push(format!("ACME provider '{}' requires an external account binding key id and HMAC key", self.acme_provider));
+        }
+
+        // Validate CT monitoring, the logs are public and rate limited, so we do not poll too often
+        if self.ct_monitoring_interval_minutes < 15 {
+            errors.push(format!(
+                "Certificate transparency monitoring interval must be at least 15 minutes, got {}",
+                self.ct_monitoring_interval_minutes
+            ));
+        }
+        if !self.ct_monitoring_webhook_url.is_empty() && self.ct_monitoring_webhook_url.parse::<http::Uri>().map(|u| u.scheme().is_none() || u.host().is_none()).unwrap_or(true) {
+            errors.push(format!("Invalid certificate transparency monitoring webhook URL: {}", &self.ct_monitoring_webhook_url));
+        }
+
+        // TLS 1.3 does not allow tickets to be used for more than 7 days
+        if self.session_ticket_lifetime_seconds < 60 || self.session_ticket_lifetime_seconds > 7 * 24 * 60 * 60 {
+            errors.push(format!(
+                "Session ticket lifetime must be between 60 seconds and 7 days, got {} seconds",
+                self.session_ticket_lifetime_seconds
+            ));
+        }
+
         if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
 }
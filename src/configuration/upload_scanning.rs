@@ -0,0 +1,81 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::file::normalized_path::NormalizedPath;
+
+pub static UPLOAD_SCANNER_TYPES: &[&str] = &["command", "icap"];
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct UploadScanning {
+    pub is_enabled: bool,
+    pub scanner_type: String, // "command" or "icap"
+    // Command to run per file, split on whitespace. "{file}" is replaced with the file path, or the path is appended.
+    // Exit code 0 means clean and 1 means infected (ClamAV convention), anything else is a scanner error
+    pub command: String,
+    pub icap_url: String, // Such as "icap://127.0.0.1:1344/avscan"
+    pub timeout_seconds: u32,
+    pub quarantine_path: String, // Directory rejected uploads are moved to
+}
+
+impl Default for UploadScanning {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UploadScanning {
+    pub fn new() -> Self {
+        Self {
+            is_enabled: false,
+            scanner_type: "command".to_string(),
+            command: "clamdscan --no-summary --fdpass {file}".to_string(),
+            icap_url: String::new(),
+            timeout_seconds: 60,
+            quarantine_path: "./quarantine".to_string(),
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.scanner_type = self.scanner_type.trim().to_lowercase();
+        self.command = self.command.trim().to_string();
+        self.icap_url = self.icap_url.trim().to_string();
+        self.quarantine_path = self.quarantine_path.trim().replace("\\", "/");
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if !UPLOAD_SCANNER_TYPES.contains(&self.scanner_type.as_str()) {
+            errors.push(format!("Scanner type '{}' is not supported, use one of: {}", self.scanner_type, UPLOAD_SCANNER_TYPES.join(", ")));
+        }
+
+        // Only require the settings for the selected scanner when enabled, so they can be prepared up front
+        if self.is_enabled {
+            if self.scanner_type == "command" && self.command.is_empty() {
+                errors.push("Scanner command cannot be empty".to_string());
+            }
+            if self.scanner_type == "icap" && self.icap_url.is_empty() {
+                errors.push("ICAP server URL cannot be empty".to_string());
+            }
+        }
+
+        if !self.icap_url.is_empty() {
+            match self.icap_url.parse::<http::Uri>() {
+                Ok(uri) if uri.scheme_str() == Some("icap") && uri.host().is_some() => {}
+                _ => errors.push(format!("Invalid ICAP server URL, expected format 'icap://host:1344/service': {}", self.icap_url)),
+            }
+        }
+
+        if self.timeout_seconds < 1 {
+            errors.push("Scan timeout must be at least 1 second".to_string());
+        }
+
+        if self.quarantine_path.is_empty() {
+            errors.push("Quarantine path cannot be empty".to_string());
+        } else if NormalizedPath::new(&self.quarantine_path, "").is_err() {
+            errors.push(format!("Invalid quarantine path: {}", self.quarantine_path));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
@@ -0,0 +1,45 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct UsageReports {
+    pub billing_period_start_day: u32, // Day of the month the billing periods start on, 1 for calendar months
+    pub webhook_url: String,           // Finalized reports are posted here as JSON when a period ends. Empty disables delivery
+    pub webhook_secret: String,        // If set, reports are signed with HMAC-SHA256 in the X-Gruxi-Signature header
+}
+
+impl Default for UsageReports {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UsageReports {
+    pub fn new() -> Self {
+        Self {
+            billing_period_start_day: 1,
+            webhook_url: String::new(),
+            webhook_secret: String::new(),
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.webhook_url = self.webhook_url.trim().to_string();
+        self.webhook_secret = self.webhook_secret.trim().to_string();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        // Every month has these days, so periods always start on the same day
+        if !(1..=28).contains(&self.billing_period_start_day) {
+            errors.push(format!("Billing period start day must be between 1 and 28, got {}", self.billing_period_start_day));
+        }
+
+        if !self.webhook_url.is_empty() && !self.webhook_url.starts_with("http://") && !self.webhook_url.starts_with("https://") {
+            errors.push(format!("Webhook URL must start with 'http://' or 'https://': {}", self.webhook_url));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
@@ -0,0 +1,148 @@
+use std::sync::OnceLock;
+
+use regex::{Regex, RegexBuilder};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// "block" answers requests reaching the anomaly threshold with 403, "detect" only logs them, to tune the rules first
+pub const WAF_MODES: [&str; 2] = ["block", "detect"];
+
+// What a rule matches its pattern against. Path, query and body are matched percent-decoded
+pub const WAF_RULE_TARGETS: [&str; 5] = ["method", "path", "query", "header", "body"];
+
+// "score" adds the score of the rule to the anomaly score of the request, "block" blocks the request on its own and "log"
+// only logs the match
+pub const WAF_RULE_ACTIONS: [&str; 3] = ["score", "block", "log"];
+
+// Compiled patterns larger than this are rejected, so a rule cannot make matching slow
+const WAF_RULE_PATTERN_SIZE_LIMIT: usize = 1024 * 1024;
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct WafRule {
+    pub name: String,
+    pub target: String,
+    #[serde(default)]
+    pub header_name: String, // The header matched by "header" rules
+    pub pattern: String, // Regular expression, matched without case
+    pub score: u32,
+    pub action: String,
+    #[serde(skip)]
+    compiled_pattern: OnceLock<Option<Regex>>,
+}
+
+impl WafRule {
+    pub fn new(name: &str, target: &str, pattern: &str, score: u32, action: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            target: target.to_string(),
+            header_name: String::new(),
+            pattern: pattern.to_string(),
+            score,
+            action: action.to_string(),
+            compiled_pattern: OnceLock::new(),
+        }
+    }
+
+    fn compile_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+        RegexBuilder::new(pattern).case_insensitive(true).size_limit(WAF_RULE_PATTERN_SIZE_LIMIT).build()
+    }
+
+    // The compiled pattern, None when it is invalid, which the validation rejects
+    pub fn get_pattern(&self) -> Option<&Regex> {
+        self.compiled_pattern.get_or_init(|| Self::compile_pattern(&self.pattern).ok()).as_ref()
+    }
+
+    pub fn sanitize(&mut self) {
+        self.name = self.name.trim().to_string();
+        self.target = self.target.trim().to_lowercase();
+        self.header_name = self.header_name.trim().to_string();
+        self.action = self.action.trim().to_lowercase();
+        // Pattern may have changed, so recompile on next use
+        self.compiled_pattern = OnceLock::new();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.name.is_empty() {
+            errors.push("WAF rule name cannot be empty".to_string());
+        }
+        if !WAF_RULE_TARGETS.contains(&self.target.as_str()) {
+            errors.push(format!("WAF rule '{}': Target must be one of {}, got '{}'", self.name, WAF_RULE_TARGETS.join(", "), self.target));
+        }
+        if self.target == "header" && hyper::header::HeaderName::from_bytes(self.header_name.as_bytes()).is_err() {
+            errors.push(format!("WAF rule '{}': Header rules need a valid header name, got '{}'", self.name, self.header_name));
+        }
+        if !WAF_RULE_ACTIONS.contains(&self.action.as_str()) {
+            errors.push(format!("WAF rule '{}': Action must be one of {}, got '{}'", self.name, WAF_RULE_ACTIONS.join(", "), self.action));
+        }
+        if self.pattern.is_empty() {
+            errors.push(format!("WAF rule '{}': Pattern cannot be empty", self.name));
+        } else if let Err(e) = Self::compile_pattern(&self.pattern) {
+            errors.push(format!("WAF rule '{}': Pattern is not a valid regular expression: {}", self.name, e));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
+// Web application firewall of a site, checking requests against the built-in core rules and the rules of the site before
+// they reach the request handlers
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct WafSettings {
+    pub is_enabled: bool,
+    pub mode: String,
+    pub core_rules_enabled: bool, // The built-in SQL injection and cross-site scripting rules
+    pub anomaly_threshold: u32,   // Requests with at least this score are blocked
+    pub rules: Vec<WafRule>,
+}
+
+impl Default for WafSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WafSettings {
+    pub fn new() -> Self {
+        Self {
+            is_enabled: false,
+            mode: "block".to_string(),
+            core_rules_enabled: true,
+            anomaly_threshold: 5,
+            rules: Vec::new(),
+        }
+    }
+
+    pub fn is_blocking(&self) -> bool {
+        self.mode == "block"
+    }
+
+    pub fn sanitize(&mut self) {
+        self.mode = self.mode.trim().to_lowercase();
+        for rule in &mut self.rules {
+            rule.sanitize();
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        if !self.is_enabled {
+            return Ok(());
+        }
+
+        if !WAF_MODES.contains(&self.mode.as_str()) {
+            errors.push(format!("WAF mode must be one of {}, got '{}'", WAF_MODES.join(", "), self.mode));
+        }
+        if self.anomaly_threshold < 1 {
+            errors.push("WAF anomaly threshold must be at least 1".to_string());
+        }
+        for rule in &self.rules {
+            if let Err(rule_errors) = rule.validate() {
+                errors.extend(rule_errors);
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
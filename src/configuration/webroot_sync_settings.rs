@@ -0,0 +1,107 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::file::normalized_path::NormalizedPath;
+
+pub const WEBROOT_SYNC_PROTOCOLS: [&str; 2] = ["sftp", "ftps"];
+
+// Remote source the web root of a site is pulled from, for sites that are still uploaded to over SFTP or FTPS.
+// Disabled when the protocol is empty
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct WebrootSyncSettings {
+    pub protocol: String,              // "sftp", "ftps" (explicit TLS), or empty to disable the sync
+    pub host: String,                  // Host name or IP address of the server
+    pub port: u16,                     // 0 for the default of the protocol, 22 for SFTP and 21 for FTPS
+    pub username: String,              // User to log in as
+    pub password: String,              // Password, for FTPS
+    pub private_key_path: String,      // Private key file, for SFTP, which logs in without a password
+    pub remote_path: String,           // Directory on the server whose content is synchronized
+    pub local_path: String,            // Local directory it is synchronized into, one of the web roots of the site
+    pub interval_minutes: u32,         // How often the content is pulled
+    pub verify_tls_certificates: bool, // Whether the certificate of an FTPS server is verified, set to false for self-signed certs
+}
+
+impl Default for WebrootSyncSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WebrootSyncSettings {
+    pub fn new() -> Self {
+        Self {
+            protocol: String::new(),
+            host: String::new(),
+            port: 0,
+            username: String::new(),
+            password: String::new(),
+            private_key_path: String::new(),
+            remote_path: String::new(),
+            local_path: String::new(),
+            interval_minutes: 15,
+            verify_tls_certificates: true,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.protocol.is_empty()
+    }
+
+    pub fn get_port(&self) -> u16 {
+        match (self.port, self.protocol.as_str()) {
+            (0, "sftp") => 22,
+            (0, _) => 21,
+            (port, _) => port,
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.protocol = self.protocol.trim().to_lowercase();
+        self.host = self.host.trim().to_string();
+        self.username = self.username.trim().to_string();
+        self.private_key_path = self.private_key_path.trim().to_string();
+        self.remote_path = self.remote_path.trim().to_string();
+        self.local_path = self.local_path.trim().to_string();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        if !WEBROOT_SYNC_PROTOCOLS.contains(&self.protocol.as_str()) {
+            errors.push(format!("Webroot sync protocol must be one of {}, got '{}'", WEBROOT_SYNC_PROTOCOLS.join(", "), self.protocol));
+        }
+        if self.host.is_empty() || self.host.contains(char::is_whitespace) {
+            errors.push(format!("Webroot sync host must be a host name or IP address, got '{}'", self.host));
+        }
+        if self.username.is_empty() {
+            errors.push("Webroot sync username cannot be empty".to_string());
+        }
+        if self.protocol == "sftp" && !std::path::Path::new(&self.private_key_path).is_file() {
+            errors.push(format!("Webroot sync over SFTP needs an existing private key file, got '{}'", self.private_key_path));
+        }
+        if self.protocol == "ftps" && self.password.is_empty() {
+            errors.push("Webroot sync over FTPS needs a password".to_string());
+        }
+        if !self.remote_path.starts_with('/') {
+            errors.push(format!("Webroot sync remote path must start with '/', got '{}'", self.remote_path));
+        }
+        // Line breaks would be taken as further commands by the server, and the paths are quoted in SFTP commands
+        if self.remote_path.contains(['\r', '\n', '"']) || self.local_path.contains(['\r', '\n', '"']) {
+            errors.push("Webroot sync remote and local paths cannot contain line breaks or quotes".to_string());
+        }
+        if self.username.contains(['\r', '\n']) || self.password.contains(['\r', '\n']) {
+            errors.push("Webroot sync username and password cannot contain line breaks".to_string());
+        }
+        if self.local_path.is_empty() || NormalizedPath::new(&self.local_path, "").is_err() {
+            errors.push(format!("Webroot sync local path is invalid: '{}' - Check strange characters and path format", self.local_path));
+        }
+        if self.interval_minutes < 1 {
+            errors.push("Webroot sync interval must be at least 1 minute".to_string());
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
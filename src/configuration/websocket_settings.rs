@@ -0,0 +1,48 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// Limits for the WebSocket connections of a site. 0 means no limit for all of them
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct WebSocketSettings {
+    pub max_connections: u32,      // Concurrent WebSocket connections for the site, further upgrades get a 503
+    pub idle_timeout_seconds: u32, // After this long without any frames, the client is pinged, and closed if it does not answer
+    pub max_frame_size: u64,       // Largest frame payload in bytes, in either direction
+    pub max_message_size: u64,     // Largest message in bytes, over all its frames, in either direction
+}
+
+impl Default for WebSocketSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WebSocketSettings {
+    pub fn new() -> Self {
+        Self {
+            max_connections: 0,
+            idle_timeout_seconds: 0,
+            max_frame_size: 0,
+            max_message_size: 0,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        // Shorter timeouts would close connections between the pings of most clients
+        if self.idle_timeout_seconds > 0 && self.idle_timeout_seconds < 10 {
+            errors.push(format!("WebSocket idle timeout must be 0 (no timeout) or at least 10 seconds, got {}", self.idle_timeout_seconds));
+        }
+
+        // Control frames, such as ping and close, can have up to 125 bytes and must always fit
+        if self.max_frame_size > 0 && self.max_frame_size < 125 {
+            errors.push(format!("WebSocket max frame size must be 0 (no limit) or at least 125 bytes, got {}", self.max_frame_size));
+        }
+
+        if self.max_frame_size > 0 && self.max_message_size > 0 && self.max_frame_size > self.max_message_size {
+            errors.push("WebSocket max frame size cannot be larger than the max message size".to_string());
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
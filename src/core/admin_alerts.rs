@@ -0,0 +1,48 @@
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, OnceLock},
+};
+
+use serde::Serialize;
+
+use crate::core::email_alerts::notify_by_email;
+use crate::logging::syslog::error;
+
+// Only the latest alerts are kept, they are meant for the admin portal and not as a log
+const MAX_ADMIN_ALERTS: usize = 100;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminAlert {
+    pub created_at: String,
+    pub source: String,
+    pub message: String,
+}
+
+fn get_admin_alerts_store() -> &'static Mutex<VecDeque<AdminAlert>> {
+    static ADMIN_ALERTS: OnceLock<Mutex<VecDeque<AdminAlert>>> = OnceLock::new();
+    ADMIN_ALERTS.get_or_init(|| Mutex::new(VecDeque::new()))
+}
+
+// Raise an alert for the admins, which is logged as an error, shown in the monitoring data and emailed, if routed
+pub fn add_admin_alert(source: &str, message: String) {
+    error(format!("Admin alert from {}: {}", source, message));
+
+    let alert = AdminAlert {
+        created_at: chrono::Utc::now().to_rfc3339(),
+        source: source.to_string(),
+        message,
+    };
+    notify_by_email(&alert);
+
+    let mut alerts = get_admin_alerts_store().lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    alerts.push_back(alert);
+    while alerts.len() > MAX_ADMIN_ALERTS {
+        alerts.pop_front();
+    }
+}
+
+// Newest alerts first
+pub fn get_admin_alerts() -> Vec<AdminAlert> {
+    let alerts = get_admin_alerts_store().lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    alerts.iter().rev().cloned().collect()
+}
@@ -1,11 +1,15 @@
+use crate::logging::syslog::{error, info, warn};
 use chrono::{DateTime, Duration, Utc};
-use crate::logging::syslog::{error, info};
 use random_password_generator::generate_password;
 use serde::{Deserialize, Serialize};
 use sqlite::Connection;
 use uuid::Uuid;
 
-use crate::core::database_connection::get_database_connection;
+use crate::core::database_connection::{get_database_connection, get_database_writer};
+use crate::database::configuration_storage::get_configuration_storage;
+use crate::database::data_access::{Row, execute, query, query_one};
+use crate::error::gruxi_error::GruxiError;
+use crate::error::gruxi_error_enums::DatabaseError;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct User {
@@ -25,6 +29,17 @@ pub struct Session {
     pub token: String,
     pub expires_at: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
+    pub client_binding: String, // Fingerprint of the client the session is bound to, empty if not bound
+}
+
+// A user as listed for full admins. The site scope is the list of sites a delegated admin manages, None for full admins
+#[derive(Debug, Serialize)]
+pub struct UserSummary {
+    pub username: String,
+    pub created_at: String,
+    pub last_login: Option<String>,
+    pub is_active: bool,
+    pub site_scope: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -35,17 +50,7 @@ pub struct LoginRequest {
 
 pub fn create_default_admin_user(connection: &Connection) -> Result<(), String> {
     // Check if admin user already exists
-    let mut statement = connection
-        .prepare("SELECT COUNT(*) FROM users WHERE username = 'admin'")
-        .map_err(|e| format!("Failed to prepare admin check statement: {}", e))?;
-
-    let admin_exists = match statement.next() {
-        Ok(sqlite::State::Row) => {
-            let count: i64 = statement.read(0).unwrap_or(0);
-            count > 0
-        }
-        _ => false,
-    };
+    let admin_exists = user_exists(connection, "admin")?;
 
     let mut need_to_clear_sessions = false;
 
@@ -60,12 +65,12 @@ pub fn create_default_admin_user(connection: &Connection) -> Result<(), String>
 
         let created_at = Utc::now().to_rfc3339();
 
-        connection
-            .execute(format!(
-                "INSERT INTO users (username, password_hash, created_at, is_active) VALUES ('{}', '{}', '{}', 1)",
-                "admin", password_hash, created_at
-            ))
-            .map_err(|e| format!("Failed to create default admin user: {}", e))?;
+        execute(
+            connection,
+            "INSERT INTO users (username, password_hash, created_at, is_active) VALUES (?, ?, ?, 1)",
+            &[&"admin", &password_hash, &created_at],
+        )
+        .map_err(|e| format!("Failed to create default admin user: {}", e))?;
 
         info(format!("Default admin user created with username 'admin' and password '{}'", random_password));
         need_to_clear_sessions = true;
@@ -79,15 +84,56 @@ pub fn create_default_admin_user(connection: &Connection) -> Result<(), String>
     Ok(())
 }
 
+fn user_exists(connection: &Connection, username: &str) -> Result<bool, String> {
+    let count = query_one(connection, "SELECT COUNT(*) AS count FROM users WHERE username = ?", &[&username], |row| row.get_i64("count"))
+        .map_err(|e| format!("Failed to check if user {} exists: {}", username, e))?;
+    Ok(count.unwrap_or(0) > 0)
+}
+
+fn map_user(row: &Row) -> Result<User, GruxiError> {
+    let parse_time = |value: &str, column: &str| {
+        DateTime::parse_from_rfc3339(value)
+            .map(|time| time.with_timezone(&Utc))
+            .map_err(|e| GruxiError::database(DatabaseError::Read, format!("Failed to parse {}: {}", column, e)))
+    };
+    let created_at = parse_time(&row.get_string("created_at")?, "created_at")?;
+
+    let last_login = match row.get_optional_string("last_login")? {
+        Some(login_str) => Some(parse_time(&login_str, "last_login")?),
+        None => None,
+    };
+
+    Ok(User {
+        id: row.get_i64("id")?,
+        username: row.get_string("username")?,
+        password_hash: row.get_string("password_hash")?,
+        created_at,
+        last_login,
+        is_active: row.get_bool("is_active")?,
+    })
+}
+
 fn invalidate_sessions_for_user(connection: &Connection, username: &str) -> Result<(), String> {
-    connection
-        .execute(format!("DELETE FROM sessions WHERE username = '{}'", username))
-        .map_err(|e| format!("Failed to invalidate sessions for user {}: {}", username, e))?;
+    execute(connection, "DELETE FROM sessions WHERE username = ?", &[&username]).map_err(|e| format!("Failed to invalidate sessions for user {}: {}", username, e))?;
+    Ok(())
+}
+
+// Invalidate the sessions of a user, including those shared with other replicas
+fn invalidate_all_sessions_for_user(connection: &Connection, username: &str) -> Result<(), String> {
+    invalidate_sessions_for_user(connection, username)?;
+    let storage = get_configuration_storage();
+    let shared_session_database = storage
+        .load_configuration()
+        .map(|configuration| configuration.core.admin_portal.shared_session_database)
+        .unwrap_or_default();
+    if storage.is_shared() || !shared_session_database.is_empty() {
+        storage.get_session_storage(&shared_session_database)?.delete_sessions_for_user(username)?;
+    }
     Ok(())
 }
 
 pub fn reset_admin_password() -> Result<String, String> {
-    let connection = get_database_connection()?;
+    let connection = get_database_writer()?;
 
     let random_password_result = get_random_hashed_password();
     let (random_password, password_hash) = match random_password_result {
@@ -97,12 +143,10 @@ pub fn reset_admin_password() -> Result<String, String> {
         }
     };
 
-    connection
-        .execute(format!("UPDATE users SET password_hash = '{}' WHERE username = 'admin'", password_hash))
-        .map_err(|e| format!("Failed to reset admin password: {}", e))?;
+    execute(&connection, "UPDATE users SET password_hash = ? WHERE username = 'admin'", &[&password_hash]).map_err(|e| format!("Failed to reset admin password: {}", e))?;
 
-    // Invalidate all existing sessions for admin user
-    invalidate_sessions_for_user(&connection, "admin")?;
+    // Invalidate all existing sessions for admin user, including those shared with other replicas
+    invalidate_all_sessions_for_user(&connection, "admin")?;
 
     Ok(random_password)
 }
@@ -114,7 +158,7 @@ fn get_random_hashed_password() -> Result<(String, String), ()> {
         Ok(hash) => hash,
         Err(_) => {
             error("Failed to hash password");
-            return Err(())
+            return Err(());
         }
     };
     Ok((random_password, password_hash))
@@ -123,60 +167,209 @@ fn get_random_hashed_password() -> Result<(String, String), ()> {
 pub fn authenticate_user(username: &str, password: &str) -> Result<Option<User>, String> {
     let connection = get_database_connection()?;
 
-    let mut statement = connection
-        .prepare("SELECT id, username, password_hash, created_at, last_login, is_active FROM users WHERE username = ? AND is_active = 1")
-        .map_err(|e| format!("Failed to prepare authentication statement: {}", e))?;
-
-    statement.bind((1, username)).map_err(|e| format!("Failed to bind username: {}", e))?;
-
-    match statement.next().map_err(|e| format!("Failed to execute authentication query: {}", e))? {
-        sqlite::State::Row => {
-            let id: i64 = statement.read(0).map_err(|e| format!("Failed to read user id: {}", e))?;
-            let db_username: String = statement.read(1).map_err(|e| format!("Failed to read username: {}", e))?;
-            let password_hash: String = statement.read(2).map_err(|e| format!("Failed to read password hash: {}", e))?;
-            let created_at_str: String = statement.read(3).map_err(|e| format!("Failed to read created_at: {}", e))?;
-            let last_login_str: Option<String> = statement.read(4).map_err(|e| format!("Failed to read last_login: {}", e))?;
-            let is_active: i64 = statement.read(5).map_err(|e| format!("Failed to read is_active: {}", e))?;
-            let is_active = is_active != 0;
-
-            // Verify password
-            let password_valid = bcrypt::verify(password, &password_hash).map_err(|e| format!("Failed to verify password: {}", e))?;
-
-            if password_valid {
-                let created_at = DateTime::parse_from_rfc3339(&created_at_str)
-                    .map_err(|e| format!("Failed to parse created_at: {}", e))?
-                    .with_timezone(&Utc);
-
-                let last_login = match last_login_str {
-                    Some(login_str) => Some(DateTime::parse_from_rfc3339(&login_str).map_err(|e| format!("Failed to parse last_login: {}", e))?.with_timezone(&Utc)),
-                    None => None,
-                };
-
-                // Update last login time
-                let now = Utc::now().to_rfc3339();
-                connection
-                    .execute(format!("UPDATE users SET last_login = '{}' WHERE id = {}", now, id))
-                    .map_err(|e| format!("Failed to update last login: {}", e))?;
-
-                Ok(Some(User {
-                    id,
-                    username: db_username,
-                    password_hash,
-                    created_at,
-                    last_login,
-                    is_active,
-                }))
-            } else {
-                Ok(None) // Invalid password
-            }
-        }
-        sqlite::State::Done => Ok(None), // User not found
+    let user = query_one(
+        &connection,
+        "SELECT id, username, password_hash, created_at, last_login, is_active FROM users WHERE username = ? AND is_active = 1",
+        &[&username],
+        map_user,
+    )
+    .map_err(|e| format!("Failed to execute authentication query: {}", e))?;
+    let Some(user) = user else {
+        return Ok(None); // User not found
+    };
+
+    // Verify password
+    let password_valid = bcrypt::verify(password, &user.password_hash).map_err(|e| format!("Failed to verify password: {}", e))?;
+    if !password_valid {
+        return Ok(None); // Invalid password
+    }
+
+    // Update last login time
+    let connection = get_database_writer()?;
+    execute(&connection, "UPDATE users SET last_login = ? WHERE id = ?", &[&Utc::now().to_rfc3339(), &user.id]).map_err(|e| format!("Failed to update last login: {}", e))?;
+
+    Ok(Some(user))
+}
+
+// Users authenticated by an auth provider get a local account on first login, so sessions can reference them.
+// The local password is random, so the account can only be used through the provider. Deactivated accounts are refused
+pub fn get_or_create_external_user(username: &str) -> Result<Option<User>, String> {
+    let connection = get_database_writer()?;
+
+    if !user_exists(&connection, username)? {
+        let (_, password_hash) = get_random_hashed_password().map_err(|_| "Failed to generate password for external user".to_string())?;
+        execute(
+            &connection,
+            "INSERT INTO users (username, password_hash, created_at, is_active) VALUES (?, ?, ?, 1)",
+            &[&username, &password_hash, &Utc::now().to_rfc3339()],
+        )
+        .map_err(|e| format!("Failed to create user for external login: {}", e))?;
+        info(format!("Created local account for externally authenticated user: {}", username));
     }
+
+    let user = query_one(
+        &connection,
+        "SELECT id, username, password_hash, created_at, last_login, is_active FROM users WHERE username = ?",
+        &[&username],
+        map_user,
+    )
+    .map_err(|e| format!("Failed to execute user lookup query: {}", e))?;
+    let Some(mut user) = user else {
+        return Err(format!("User '{}' was not found after creation", username));
+    };
+    if !user.is_active {
+        return Ok(None);
+    }
+
+    // Update last login time
+    let now = Utc::now();
+    execute(&connection, "UPDATE users SET last_login = ? WHERE id = ?", &[&now.to_rfc3339(), &user.id]).map_err(|e| format!("Failed to update last login: {}", e))?;
+    user.last_login = Some(now);
+
+    Ok(Some(user))
 }
 
-pub fn create_session(user: &User) -> Result<Session, String> {
+// The sites a user is delegated to manage. None means the user is a full admin, which is also the case for unknown users,
+// such as those authenticated by an auth provider before they got a local account
+pub fn get_user_site_scope(username: &str) -> Result<Option<Vec<String>>, String> {
     let connection = get_database_connection()?;
 
+    let site_scope = query_one(&connection, "SELECT site_scope FROM users WHERE username = ?", &[&username], |row| {
+        row.get_optional_string("site_scope")
+    })
+    .map_err(|e| format!("Failed to execute site scope query: {}", e))?;
+    match site_scope {
+        Some(site_scope) => parse_site_scope(site_scope).map_err(String::from),
+        None => Ok(None),
+    }
+}
+
+fn parse_site_scope(site_scope: Option<String>) -> Result<Option<Vec<String>>, GruxiError> {
+    match site_scope {
+        Some(site_scope) if site_scope.is_empty() => Ok(Some(Vec::new())),
+        Some(site_scope) => serde_json::from_str(&site_scope)
+            .map(Some)
+            .map_err(|e| GruxiError::database(DatabaseError::Read, format!("Failed to parse site scope: {}", e))),
+        None => Ok(None),
+    }
+}
+
+// The site scope is stored as a JSON array, and as NULL for full admins
+fn get_site_scope_value(site_scope: &Option<Vec<String>>) -> Result<Option<String>, String> {
+    match site_scope {
+        Some(site_ids) => serde_json::to_string(site_ids).map(Some).map_err(|e| format!("Failed to serialize site scope: {}", e)),
+        None => Ok(None),
+    }
+}
+
+pub fn list_users() -> Result<Vec<UserSummary>, String> {
+    let connection = get_database_connection()?;
+
+    query(&connection, "SELECT username, created_at, last_login, is_active, site_scope FROM users ORDER BY username", &[], |row| {
+        Ok(UserSummary {
+            username: row.get_string("username")?,
+            created_at: row.get_string("created_at")?,
+            last_login: row.get_optional_string("last_login")?,
+            is_active: row.get_bool("is_active")?,
+            site_scope: parse_site_scope(row.get_optional_string("site_scope")?)?,
+        })
+    })
+    .map_err(|e| format!("Failed to execute user list query: {}", e))
+}
+
+// Create a user, or update an existing one. The password is only changed when given. Changing the password or
+// deactivating the user ends the sessions of the user
+pub fn save_user(username: &str, password: Option<&str>, is_active: bool, site_scope: &Option<Vec<String>>) -> Result<(), String> {
+    // Hashing is slow, so it is done before other writers are kept waiting
+    let password_hash = match password {
+        Some(password) => Some(bcrypt::hash(password, bcrypt::DEFAULT_COST).map_err(|e| format!("Failed to hash password: {}", e))?),
+        None => None,
+    };
+    let site_scope_value = get_site_scope_value(site_scope)?;
+
+    let connection = get_database_writer()?;
+
+    let user_exists = user_exists(&connection, username)?;
+
+    if user_exists {
+        if let Some(password_hash) = &password_hash {
+            execute(&connection, "UPDATE users SET password_hash = ? WHERE username = ?", &[password_hash, &username])
+                .map_err(|e| format!("Failed to update password for user {}: {}", username, e))?;
+        }
+        execute(
+            &connection,
+            "UPDATE users SET is_active = ?, site_scope = ? WHERE username = ?",
+            &[&is_active, &site_scope_value, &username],
+        )
+        .map_err(|e| format!("Failed to update user {}: {}", username, e))?;
+
+        if password_hash.is_some() || !is_active {
+            invalidate_all_sessions_for_user(&connection, username)?;
+        }
+        info(format!("Updated user: {}", username));
+    } else {
+        let password_hash = password_hash.ok_or_else(|| "A password is required for new users".to_string())?;
+        execute(
+            &connection,
+            "INSERT INTO users (username, password_hash, created_at, is_active, site_scope) VALUES (?, ?, ?, ?, ?)",
+            &[&username, &password_hash, &Utc::now().to_rfc3339(), &is_active, &site_scope_value],
+        )
+        .map_err(|e| format!("Failed to create user {}: {}", username, e))?;
+        info(format!("Created user: {}", username));
+    }
+
+    Ok(())
+}
+
+pub fn delete_user(username: &str) -> Result<bool, String> {
+    let connection = get_database_writer()?;
+
+    execute(&connection, "DELETE FROM users WHERE username = ?", &[&username]).map_err(|e| format!("Failed to delete user {}: {}", username, e))?;
+
+    let deleted = connection.change_count() > 0;
+    if deleted {
+        invalidate_all_sessions_for_user(&connection, username)?;
+        info(format!("Deleted user: {}", username));
+    }
+    Ok(deleted)
+}
+
+// Sites created by a delegated admin are added to the scope of the admin, so they can keep managing them
+pub fn add_sites_to_user_scope(username: &str, site_ids: &[String]) -> Result<(), String> {
+    let Some(mut site_scope) = get_user_site_scope(username)? else {
+        return Ok(());
+    };
+    for site_id in site_ids {
+        if !site_scope.contains(site_id) {
+            site_scope.push(site_id.clone());
+        }
+    }
+
+    let connection = get_database_writer()?;
+    execute(
+        &connection,
+        "UPDATE users SET site_scope = ? WHERE username = ?",
+        &[&get_site_scope_value(&Some(site_scope))?, &username],
+    )
+    .map_err(|e| format!("Failed to update site scope for user {}: {}", username, e))?;
+    Ok(())
+}
+
+// Fingerprint of the client IP and/or user agent, that a session can be bound to. Empty when the session is not bound
+pub fn get_client_binding(client_ip: Option<&str>, user_agent: Option<&str>) -> String {
+    if client_ip.is_none() && user_agent.is_none() {
+        return String::new();
+    }
+
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    for part in [client_ip, user_agent] {
+        context.update(part.unwrap_or("").as_bytes());
+        context.update(&[0]);
+    }
+    context.finish().as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn create_session(user: &User, shared_session_database: &str, client_binding: &str) -> Result<Session, String> {
     let session_id = Uuid::new_v4().to_string();
     let token = Uuid::new_v4().to_string();
     let created_at = Utc::now();
@@ -189,133 +382,59 @@ pub fn create_session(user: &User) -> Result<Session, String> {
         token: token.clone(),
         expires_at,
         created_at,
+        client_binding: client_binding.to_string(),
     };
 
-    connection
-        .execute(format!(
-            "INSERT INTO sessions (id, user_id, username, token, expires_at, created_at) VALUES ('{}', {}, '{}', '{}', '{}', '{}')",
-            session.id,
-            session.user_id,
-            session.username,
-            session.token,
-            session.expires_at.to_rfc3339(),
-            session.created_at.to_rfc3339()
-        ))
+    get_configuration_storage()
+        .get_session_storage(shared_session_database)?
+        .insert_session(&session)
         .map_err(|e| format!("Failed to create session: {}", e))?;
 
     info(format!("Created session for user: {}", user.username));
     Ok(session)
 }
 
-pub fn verify_session_token(token: &str) -> Result<Option<Session>, String> {
-    let connection = get_database_connection()?;
+// Sessions bound to a client are only accepted with the same client binding. A token used from another client is
+// considered stolen, so the session is invalidated
+pub fn verify_session_token(token: &str, shared_session_database: &str, client_binding: &str) -> Result<Option<Session>, String> {
+    let sessions = get_configuration_storage().get_session_storage(shared_session_database)?;
 
     // Clean up expired sessions first
-    cleanup_expired_sessions(&connection)?;
-
-    let mut statement = connection
-        .prepare("SELECT id, user_id, username, token, expires_at, created_at FROM sessions WHERE token = ?")
-        .map_err(|e| format!("Failed to prepare session verification statement: {}", e))?;
-
-    statement.bind((1, token)).map_err(|e| format!("Failed to bind session token: {}", e))?;
-
-    match statement.next().map_err(|e| format!("Failed to execute session verification query: {}", e))? {
-        sqlite::State::Row => {
-            let id: String = statement.read(0).map_err(|e| format!("Failed to read session id: {}", e))?;
-            let user_id: i64 = statement.read(1).map_err(|e| format!("Failed to read user_id: {}", e))?;
-            let username: String = statement.read(2).map_err(|e| format!("Failed to read username: {}", e))?;
-            let session_token: String = statement.read(3).map_err(|e| format!("Failed to read token: {}", e))?;
-            let expires_at_str: String = statement.read(4).map_err(|e| format!("Failed to read expires_at: {}", e))?;
-            let created_at_str: String = statement.read(5).map_err(|e| format!("Failed to read created_at: {}", e))?;
-
-            let expires_at = DateTime::parse_from_rfc3339(&expires_at_str)
-                .map_err(|e| format!("Failed to parse expires_at: {}", e))?
-                .with_timezone(&Utc);
-
-            let created_at = DateTime::parse_from_rfc3339(&created_at_str)
-                .map_err(|e| format!("Failed to parse created_at: {}", e))?
-                .with_timezone(&Utc);
-
-            // Check if session is still valid (not expired)
-            if expires_at > Utc::now() {
-                Ok(Some(Session {
-                    id,
-                    user_id,
-                    username,
-                    token: session_token,
-                    expires_at,
-                    created_at,
-                }))
-            } else {
-                Ok(None) // Session expired
-            }
-        }
-        sqlite::State::Done => Ok(None), // Session not found
-    }
-}
+    sessions.delete_expired_sessions().map_err(|e| format!("Failed to cleanup expired sessions: {}", e))?;
 
-pub fn invalidate_session(token: &str) -> Result<bool, String> {
-    let connection = get_database_connection()?;
-
-    // First check if session exists
-    let mut statement = connection
-        .prepare("SELECT COUNT(*) FROM sessions WHERE token = ?")
-        .map_err(|e| format!("Failed to prepare session check statement: {}", e))?;
-
-    statement.bind((1, token)).map_err(|e| format!("Failed to bind session token: {}", e))?;
-
-    let session_exists = match statement.next().map_err(|e| format!("Failed to execute session check query: {}", e))? {
-        sqlite::State::Row => {
-            let count: i64 = statement.read(0).map_err(|e| format!("Failed to read session count: {}", e))?;
-            count > 0
-        }
-        sqlite::State::Done => false,
+    let session = sessions.get_session_by_token(token).map_err(|e| format!("Failed to execute session verification query: {}", e))?;
+    let Some(session) = session else {
+        return Ok(None); // Session not found
     };
 
-    if session_exists {
-        connection
-            .execute(format!("DELETE FROM sessions WHERE token = '{}'", token))
-            .map_err(|e| format!("Failed to delete session: {}", e))?;
-        Ok(true)
+    if !session.client_binding.is_empty() && session.client_binding != client_binding {
+        warn(format!(
+            "Session token for user {} was used from another client than it was created for, the session is invalidated",
+            session.username
+        ));
+        sessions.delete_session(&session.token).map_err(|e| format!("Failed to delete session: {}", e))?;
+        return Ok(None);
+    }
+
+    // Check if session is still valid (not expired)
+    if session.expires_at > Utc::now() {
+        Ok(Some(session))
     } else {
-        Ok(false)
+        Ok(None) // Session expired
     }
 }
 
-fn cleanup_expired_sessions(connection: &Connection) -> Result<(), String> {
-    let now = Utc::now().to_rfc3339();
-    connection
-        .execute(format!("DELETE FROM sessions WHERE expires_at < '{}'", now))
-        .map_err(|e| format!("Failed to cleanup expired sessions: {}", e))?;
-
-    Ok(())
+pub fn invalidate_session(token: &str, shared_session_database: &str) -> Result<bool, String> {
+    get_configuration_storage()
+        .get_session_storage(shared_session_database)?
+        .delete_session(token)
+        .map_err(|e| format!("Failed to delete session: {}", e))
 }
 
 pub fn cleanup_all_expired_sessions() -> Result<u64, String> {
-    let connection = get_database_connection()?;
-
-    // First count the expired sessions
-    let now = Utc::now().to_rfc3339();
-    let mut statement = connection
-        .prepare("SELECT COUNT(*) FROM sessions WHERE expires_at < ?")
-        .map_err(|e| format!("Failed to prepare expired sessions count statement: {}", e))?;
-
-    statement.bind((1, now.as_str())).map_err(|e| format!("Failed to bind expiration time: {}", e))?;
-
-    let expired_count = match statement.next().map_err(|e| format!("Failed to execute expired sessions count query: {}", e))? {
-        sqlite::State::Row => {
-            let count: i64 = statement.read(0).map_err(|e| format!("Failed to read expired sessions count: {}", e))?;
-            count as u64
-        }
-        sqlite::State::Done => 0,
-    };
-
-    // Drop statement before using connection again
-    drop(statement);
-
-    // Delete expired sessions
-    connection
-        .execute(format!("DELETE FROM sessions WHERE expires_at < '{}'", now))
+    let expired_count = get_configuration_storage()
+        .get_session_storage("")?
+        .delete_expired_sessions()
         .map_err(|e| format!("Failed to cleanup expired sessions: {}", e))?;
 
     if expired_count > 0 {
@@ -324,3 +443,31 @@ pub fn cleanup_all_expired_sessions() -> Result<u64, String> {
 
     Ok(expired_count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_binding() {
+        assert_eq!(get_client_binding(None, None), "");
+
+        let binding = get_client_binding(Some("192.0.2.10"), Some("Mozilla/5.0"));
+        assert_eq!(binding.len(), 64);
+        assert_eq!(binding, get_client_binding(Some("192.0.2.10"), Some("Mozilla/5.0")));
+        assert_ne!(binding, get_client_binding(Some("192.0.2.11"), Some("Mozilla/5.0")));
+        assert_ne!(binding, get_client_binding(Some("192.0.2.10"), None));
+        assert_ne!(get_client_binding(Some("a"), None), get_client_binding(None, Some("a")));
+    }
+
+    #[test]
+    fn test_site_scope_storage_format() {
+        assert_eq!(parse_site_scope(None).unwrap(), None);
+        assert_eq!(parse_site_scope(Some(String::new())).unwrap(), Some(Vec::new()));
+        assert_eq!(parse_site_scope(Some(r#"["a","b"]"#.to_string())).unwrap(), Some(vec!["a".to_string(), "b".to_string()]));
+        assert!(parse_site_scope(Some("a,b".to_string())).is_err());
+
+        assert_eq!(get_site_scope_value(&None).unwrap(), None);
+        assert_eq!(get_site_scope_value(&Some(vec!["it's".to_string()])).unwrap(), Some(r#"["it's"]"#.to_string()));
+    }
+}
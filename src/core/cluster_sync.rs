@@ -0,0 +1,199 @@
+// ============================================================================
+// CLUSTER CONFIGURATION SYNC
+// ============================================================================
+//
+// Keeps a fleet of Gruxi instances on the same configuration, without copying
+// gruxi.db around. One instance is the primary, where the configuration is
+// edited. Replicas poll the admin portal of the primary for its configuration,
+// authenticated with a shared token, and save and reload it when it differs
+// from their own.
+//
+// The cluster sync settings themselves are kept per instance, so a replica
+// stays a replica of the same primary. Changes made directly on a replica are
+// overwritten on the next poll, as the primary is the source of truth.
+// ============================================================================
+
+use std::time::Duration;
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use tokio_util::sync::CancellationToken;
+
+use crate::configuration::cluster_sync_settings::ClusterSyncSettings;
+use crate::configuration::configuration::{CURRENT_CONFIGURATION_VERSION, Configuration};
+use crate::configuration::save_configuration::save_configuration;
+use crate::core::running_state_manager::get_running_state_manager;
+use crate::core::triggers::get_trigger_handler;
+use crate::logging::syslog::{debug, info, trace, warn};
+
+// Header the replicas send the shared token in
+pub const CLUSTER_TOKEN_HEADER: &str = "X-Gruxi-Cluster-Token";
+
+// Path of the configuration of the primary, in its admin portal
+pub const CLUSTER_CONFIGURATION_PATH: &str = "/cluster/configuration";
+
+// Timeout for each request to the primary
+const CLUSTER_REQUEST_TIMEOUT_SECS: u64 = 30;
+
+/// Check a token sent by a replica against the shared token, in constant time so it cannot be guessed byte by byte
+pub fn is_valid_cluster_token(settings: &ClusterSyncSettings, token: &str) -> bool {
+    if settings.shared_token.is_empty() || settings.shared_token.len() != token.len() {
+        return false;
+    }
+    settings.shared_token.bytes().zip(token.bytes()).fold(0u8, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+/// The configuration a primary serves to its replicas, without its own cluster sync settings
+pub fn get_configuration_for_replicas(mut configuration: Configuration) -> Configuration {
+    configuration.core.cluster_sync = ClusterSyncSettings::new();
+    configuration
+}
+
+// The configuration of the primary as it is saved on a replica, which keeps its own cluster sync settings
+fn get_replica_configuration(mut primary_configuration: Configuration, local_settings: &ClusterSyncSettings) -> Result<Configuration, String> {
+    if primary_configuration.version != CURRENT_CONFIGURATION_VERSION {
+        return Err(format!(
+            "Primary has configuration version {}, where this instance has version {}. Run the same version of Gruxi on all instances",
+            primary_configuration.version, CURRENT_CONFIGURATION_VERSION
+        ));
+    }
+    primary_configuration.core.cluster_sync = local_settings.clone();
+    Ok(primary_configuration)
+}
+
+async fn fetch_primary_configuration(settings: &ClusterSyncSettings) -> Result<Configuration, String> {
+    let request = hyper::Request::builder()
+        .method(hyper::Method::GET)
+        .uri(format!("{}{}", settings.primary_url, CLUSTER_CONFIGURATION_PATH))
+        .header(CLUSTER_TOKEN_HEADER, &settings.shared_token)
+        .body(Full::new(Bytes::new()).map_err(|never| match never {}).boxed())
+        .map_err(|e| format!("Failed to build request: {}", e))?;
+
+    let client = {
+        let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+        running_state.get_http_client().get_client(settings.verify_tls_certificates)
+    };
+
+    let response = tokio::time::timeout(Duration::from_secs(CLUSTER_REQUEST_TIMEOUT_SECS), client.request(request))
+        .await
+        .map_err(|_| "Request timed out".to_string())?
+        .map_err(|e| format!("Request failed: {}", e))?;
+
+    match response.status() {
+        status if status.is_success() => {}
+        hyper::StatusCode::FORBIDDEN => return Err("Primary refused the shared token, or is not configured as primary".to_string()),
+        status => return Err(format!("Unexpected status code {}", status)),
+    }
+
+    let body = tokio::time::timeout(Duration::from_secs(CLUSTER_REQUEST_TIMEOUT_SECS), response.into_body().collect())
+        .await
+        .map_err(|_| "Reading response timed out".to_string())?
+        .map_err(|e| format!("Failed to read response: {}", e))?
+        .to_bytes();
+
+    serde_json::from_slice(&body).map_err(|e| format!("Failed to parse configuration: {}", e))
+}
+
+// Fetch the configuration of the primary and save it. Returns whether the configuration changed, so it has to be reloaded
+async fn sync_from_primary(settings: &ClusterSyncSettings) -> Result<bool, String> {
+    let primary_configuration = fetch_primary_configuration(settings).await?;
+    let mut configuration = get_replica_configuration(primary_configuration, settings)?;
+    tokio::task::spawn_blocking(move || save_configuration(&mut configuration, false))
+        .await
+        .map_err(|e| format!("Failed to save configuration: {}", e))?
+        .map_err(|e| match e.get_http_status_code() {
+            400 => format!("Configuration of the primary is not valid here: {}", e),
+            _ => format!("Failed to save configuration: {}", e),
+        })
+}
+
+/// Start polling the primary for configuration changes, if this instance is a replica. It stops on shutdown or
+/// stop_services triggers, so it is started again on configuration reload.
+pub async fn start_cluster_sync() {
+    let cached_configuration = crate::configuration::cached_configuration::get_cached_configuration();
+    let settings = cached_configuration.get_configuration().await.core.cluster_sync.clone();
+    if !settings.is_replica() {
+        debug("Cluster sync is not enabled, this instance is not a replica");
+        return;
+    }
+
+    let triggers = get_trigger_handler();
+    let shutdown_token = triggers.get_token("shutdown").await.unwrap_or_else(CancellationToken::new);
+    let stop_services_token = triggers.get_token("stop_services").await.unwrap_or_else(CancellationToken::new);
+
+    info(format!(
+        "Cluster sync: replica of {}, checking for changes every {} seconds",
+        settings.primary_url, settings.poll_interval_seconds
+    ));
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(settings.poll_interval_seconds as u64));
+        let mut last_error = String::new();
+        loop {
+            tokio::select! {
+                _ = shutdown_token.cancelled() => {
+                    debug("Cluster sync stopping due to shutdown signal");
+                    break;
+                }
+                _ = stop_services_token.cancelled() => {
+                    debug("Cluster sync stopping due to stop_services signal");
+                    break;
+                }
+                _ = interval.tick() => {
+                    match sync_from_primary(&settings).await {
+                        Ok(true) => {
+                            info(format!("Cluster sync: applied the configuration of primary {}", settings.primary_url));
+                            // Same as a reload from the admin portal, which also restarts this task
+                            let triggers = get_trigger_handler();
+                            triggers.run_trigger("refresh_cached_configuration").await;
+                            triggers.run_trigger("reload_configuration").await;
+                            break;
+                        }
+                        Ok(false) => {
+                            if !last_error.is_empty() {
+                                info(format!("Cluster sync: in sync with primary {} again", settings.primary_url));
+                                last_error.clear();
+                            }
+                            trace("Cluster sync: configuration is the same as on the primary");
+                        }
+                        // Errors are logged once until they change, as the primary may be down for a while
+                        Err(e) if e == last_error => trace(format!("Cluster sync failed again: {}", e)),
+                        Err(e) => {
+                            warn(format!("Cluster sync from primary {} failed: {}", settings.primary_url, e));
+                            last_error = e;
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cluster_token_and_replica_settings() {
+        let mut settings = ClusterSyncSettings::new();
+        assert!(!is_valid_cluster_token(&settings, ""));
+        settings.shared_token = "0123456789abcdef".to_string();
+        assert!(is_valid_cluster_token(&settings, "0123456789abcdef"));
+        assert!(!is_valid_cluster_token(&settings, "0123456789abcdeF"));
+        assert!(!is_valid_cluster_token(&settings, "0123456789abcde"));
+
+        // The replica keeps its own cluster sync settings, and does not get those of the primary
+        settings.role = "replica".to_string();
+        settings.primary_url = "https://primary.example.com:8000".to_string();
+        let mut primary_configuration = Configuration::get_default();
+        primary_configuration.core.cluster_sync.role = "primary".to_string();
+        let served_configuration = get_configuration_for_replicas(primary_configuration);
+        assert_eq!(served_configuration.core.cluster_sync.role, "standalone");
+        let replica_configuration = get_replica_configuration(served_configuration, &settings).unwrap();
+        assert_eq!(replica_configuration.core.cluster_sync.primary_url, "https://primary.example.com:8000");
+
+        let mut old_configuration = Configuration::get_default();
+        old_configuration.version = CURRENT_CONFIGURATION_VERSION - 1;
+        assert!(get_replica_configuration(old_configuration, &settings).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use std::process::Stdio;
+use std::time::Duration;
+
+use crate::configuration::command_hook::CommandHook;
+use crate::configuration::configuration::Configuration;
+use crate::logging::syslog::{debug, info, warn};
+
+// The most of the output of a failed command that is logged, so a chatty command does not flood the log
+const MAX_LOGGED_OUTPUT_LENGTH: usize = 1000;
+
+/// The enabled hooks of the configuration for an event, in the order they are configured
+pub fn get_command_hooks_for_event(configuration: &Configuration, event: &str) -> Vec<CommandHook> {
+    configuration.command_hooks.iter().filter(|hook| hook.is_enabled && hook.event == event).cloned().collect()
+}
+
+/// Run the hooks one after another and wait for them. A hook that fails or times out is logged, and does not stop the others.
+/// The command gets "GRUXI_EVENT" and "GRUXI_EVENT_TIME", besides the variables describing the event
+pub async fn run_command_hooks(hooks: &[CommandHook], event: &str, variables: &[(&str, String)]) {
+    for hook in hooks {
+        match run_command_hook(hook, event, variables).await {
+            Ok(()) => info(format!("Command hook '{}' for event '{}' completed", hook.name, event)),
+            Err(e) => warn(format!("Command hook '{}' for event '{}' failed: {}", hook.name, event, e)),
+        }
+    }
+}
+
+/// Run the hooks in the background, for events that should not wait for them
+pub fn spawn_command_hooks(hooks: Vec<CommandHook>, event: &'static str, variables: Vec<(&'static str, String)>) {
+    if hooks.is_empty() {
+        return;
+    }
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn(async move {
+                run_command_hooks(&hooks, event, &variables).await;
+            });
+        }
+        Err(_) => debug(format!("Command hooks for event '{}' not run, as there is no async runtime", event)),
+    }
+}
+
+async fn run_command_hook(hook: &CommandHook, event: &str, variables: &[(&str, String)]) -> Result<(), String> {
+    let mut command = tokio::process::Command::new(&hook.command);
+    command
+        .args(&hook.arguments)
+        .env("GRUXI_EVENT", event)
+        .env("GRUXI_EVENT_TIME", chrono::Utc::now().to_rfc3339())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    for (name, value) in variables {
+        command.env(name, value);
+    }
+    if !hook.working_directory.is_empty() {
+        command.current_dir(&hook.working_directory);
+    }
+
+    let child = command.spawn().map_err(|e| format!("Failed to start '{}': {}", hook.command, e))?;
+
+    // When the timeout passes, the future owning the child is dropped, which kills it
+    let output = match tokio::time::timeout(Duration::from_secs(hook.timeout_seconds as u64), child.wait_with_output()).await {
+        Ok(result) => result.map_err(|e| format!("Failed to wait for '{}': {}", hook.command, e))?,
+        Err(_) => return Err(format!("Timed out after {} seconds and was stopped", hook.timeout_seconds)),
+    };
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let stderr: String = stderr.trim().chars().take(MAX_LOGGED_OUTPUT_LENGTH).collect();
+        return Err(format!("Exited with {}: {}", output.status, stderr));
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn create_hook(arguments: &[&str], timeout_seconds: u32) -> CommandHook {
+        let mut hook = CommandHook::new();
+        hook.name = "test".to_string();
+        hook.command = "sh".to_string();
+        hook.arguments = arguments.iter().map(|a| a.to_string()).collect();
+        hook.timeout_seconds = timeout_seconds;
+        hook
+    }
+
+    #[tokio::test]
+    async fn test_run_command_hook() {
+        let variables = [("GRUXI_DOMAIN", "example.com".to_string())];
+        let hook = create_hook(&["-c", "test \"$GRUXI_EVENT\" = certificate_renewed && test \"$GRUXI_DOMAIN\" = example.com"], 5);
+        assert!(run_command_hook(&hook, "certificate_renewed", &variables).await.is_ok());
+
+        let hook = create_hook(&["-c", "echo broken >&2; exit 3"], 5);
+        let error = run_command_hook(&hook, "post_reload", &[]).await.unwrap_err();
+        assert!(error.contains("broken"), "{}", error);
+
+        let hook = create_hook(&["-c", "sleep 5"], 1);
+        let error = run_command_hook(&hook, "post_reload", &[]).await.unwrap_err();
+        assert!(error.contains("Timed out"), "{}", error);
+    }
+}
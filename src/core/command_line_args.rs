@@ -2,10 +2,27 @@ use std::{path::PathBuf, sync::OnceLock};
 
 use clap::{Arg, ArgMatches, Command};
 
-use crate::{configuration::import_export::{export_configuration_to_file, import_configuration_from_file}, core::admin_user::reset_admin_password};
+use crate::{
+    configuration::{
+        export_formats::EXPORT_FORMATS,
+        import_export::{export_configuration_to_file, import_configuration_from_file, migrate_configuration_file},
+        load_configuration::fetch_configuration_in_db,
+    },
+    core::admin_user::reset_admin_password,
+    core::speedtest::{SPEEDTEST_SCENARIOS, SpeedtestSettings, get_default_speedtest_scenarios},
+    database::configuration_storage::get_configuration_storage,
+    database::database_backup::{DatabaseBackup, resolve_backup_path, restore_backup_file},
+    database::database_migration::migrate_database_to,
+    database::database_schema::{CURRENT_DB_SCHEMA_VERSION, initialize_database},
+    tls::dev_ca::{DevCa, clear_generated_certificates, get_trust_instructions},
+};
 
 pub fn load_command_line_args() -> ArgMatches {
     // Parse command line args
+    get_command_line_command().get_matches()
+}
+
+fn get_command_line_command() -> Command {
     Command::new("Gruxi")
         .version(env!("CARGO_PKG_VERSION"))
         .allow_external_subcommands(true)
@@ -14,7 +31,28 @@ pub fn load_command_line_args() -> ArgMatches {
                 .short('o')
                 .long("opmode")
                 .help("Mode of operation")
-                .value_parser(["DEV", "DEBUG", "PRODUCTION", "ULTIMATE"]),
+                .value_parser(["DEV", "DEBUG", "PRODUCTION", "ULTIMATE", "SPEEDTEST"]),
+        )
+        .arg(
+            Arg::new("speedtest-scenarios")
+                .long("speedtest-scenarios")
+                .help("Scenarios to run in SPEEDTEST mode, separated by commas")
+                .value_delimiter(',')
+                .value_parser(SPEEDTEST_SCENARIOS),
+        )
+        .arg(
+            Arg::new("speedtest-duration")
+                .long("speedtest-duration")
+                .help("Seconds to run each scenario for in SPEEDTEST mode")
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .default_value("10"),
+        )
+        .arg(
+            Arg::new("speedtest-connections")
+                .long("speedtest-connections")
+                .help("Concurrent keep-alive connections of the load generator in SPEEDTEST mode")
+                .value_parser(clap::value_parser!(u64).range(1..10000))
+                .default_value("32"),
         )
         .arg(
             Arg::new("reset-admin-password")
@@ -29,6 +67,13 @@ pub fn load_command_line_args() -> ArgMatches {
                 .help("Export the configuration to a file and exit")
                 .value_parser(clap::value_parser!(PathBuf)),
         )
+        .arg(
+            Arg::new("export-format")
+                .long("export-format")
+                .help("Format of the exported configuration, where nginx, caddy and docker-compose are approximate equivalents for other servers")
+                .value_parser(EXPORT_FORMATS)
+                .default_value("json"),
+        )
         .arg(
             Arg::new("import-configuration")
                 .short('i')
@@ -44,12 +89,78 @@ pub fn load_command_line_args() -> ArgMatches {
                 .value_parser(clap::value_parser!(PathBuf))
                 .value_parser(validate_existing_file),
         )
+        .arg(
+            Arg::new("restore-backup")
+                .long("restore-backup")
+                .help("Restore the database from a backup, by name in the backup directory or by path, and exit. Run it while Gruxi is stopped"),
+        )
+        .arg(
+            Arg::new("config-storage")
+                .long("config-storage")
+                .help("Keep the configuration and admin sessions in PostgreSQL, shared by all nodes with the same postgres:// URL. Also read from GRUXI_CONFIG_STORAGE"),
+        )
         .arg(
             Arg::new("disable-admin-portal")
                 .long("disable-admin-portal")
                 .help("Disable the admin portal")
                 .action(clap::ArgAction::SetTrue),
         )
+        .subcommand(
+            Command::new("test")
+                .about("Run the site test files in a file or directory against the current configuration, with TAP output, and exit")
+                .arg(
+                    Arg::new("path")
+                        .help("Site test file, or directory with .json site test files")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf)),
+                ),
+        )
+        .subcommand(
+            Command::new("migrate")
+                .about("Migrate the database to a schema version, such as before going back to an older Gruxi, and exit")
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .help("Schema version to migrate to, the current version by default")
+                        .value_parser(clap::value_parser!(i32)),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("Apply the migrations in a transaction that is rolled back, and list them")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("config").about("Manage the configuration and exit").subcommand_required(true).subcommand(
+                Command::new("migrate")
+                    .about("Rename deprecated fields in the stored configuration, or in a configuration file, to their current names")
+                    .arg(
+                        Arg::new("file")
+                            .long("file")
+                            .help("Rewrite this exported configuration file instead of the stored configuration")
+                            .value_parser(validate_existing_file),
+                    )
+                    .arg(
+                        Arg::new("dry-run")
+                            .long("dry-run")
+                            .help("Only list the deprecated fields that would be renamed")
+                            .action(clap::ArgAction::SetTrue),
+                    ),
+            ),
+        )
+        .subcommand(
+            Command::new("cert").about("Manage certificates and exit").subcommand_required(true).subcommand(
+                Command::new("trust-dev")
+                    .about("Create a local development CA, sign the generated site certificates with it from the next start, and print how to trust it")
+                    .arg(
+                        Arg::new("export")
+                            .long("export")
+                            .help("Also copy the CA certificate to this file, such as for importing it on another machine")
+                            .value_parser(clap::value_parser!(PathBuf)),
+                    ),
+            ),
+        )
         .arg(
             Arg::new("benchmark")
                 .long("bench")
@@ -57,7 +168,6 @@ pub fn load_command_line_args() -> ArgMatches {
                 .hide(true)
                 .action(clap::ArgAction::SetTrue),
         )
-        .get_matches()
 }
 
 fn validate_existing_file(s: &str) -> Result<PathBuf, String> {
@@ -86,6 +196,37 @@ pub fn cmd_disable_admin_portal() -> bool {
     cli.get_flag("disable-admin-portal")
 }
 
+// The URL of the configuration storage, from the command line or the environment. Empty for the local database
+pub fn cmd_get_configuration_storage() -> String {
+    let cli = get_command_line_args();
+    match cli.get_one::<String>("config-storage") {
+        Some(url) => url.to_string(),
+        None => std::env::var("GRUXI_CONFIG_STORAGE").unwrap_or_default(),
+    }
+}
+
+pub fn cmd_get_backup_to_restore() -> Option<String> {
+    let cli = get_command_line_args();
+    cli.get_one::<String>("restore-backup").map(|s| s.to_string())
+}
+
+pub fn cmd_get_site_test_path() -> Option<PathBuf> {
+    let cli = get_command_line_args();
+    cli.subcommand_matches("test").and_then(|test_matches| test_matches.get_one::<PathBuf>("path").cloned())
+}
+
+pub fn cmd_get_speedtest_settings() -> SpeedtestSettings {
+    let cli = get_command_line_args();
+    SpeedtestSettings {
+        scenarios: cli
+            .get_many::<String>("speedtest-scenarios")
+            .map(|scenarios| scenarios.cloned().collect())
+            .unwrap_or_else(get_default_speedtest_scenarios),
+        duration_seconds: cli.get_one::<u64>("speedtest-duration").copied().unwrap_or(10),
+        connections: cli.get_one::<u64>("speedtest-connections").copied().unwrap_or(32) as usize,
+    }
+}
+
 pub fn check_for_command_line_actions() {
     let cli = get_command_line_args();
 
@@ -103,9 +244,91 @@ pub fn check_for_command_line_actions() {
             }
         }
     }
+    // Check for development CA bootstrap
+    if let Some(trust_dev_matches) = cli.subcommand_matches("cert").and_then(|cert_matches| cert_matches.subcommand_matches("trust-dev")) {
+        match trust_dev_certificate_authority(trust_dev_matches.get_one::<PathBuf>("export")) {
+            Ok(instructions) => {
+                println!("{}", instructions);
+                std::process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("Failed to set up the development CA: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Check for database migration
+    if let Some(migrate_matches) = cli.subcommand_matches("migrate") {
+        let to_version = migrate_matches.get_one::<i32>("to").copied().unwrap_or(CURRENT_DB_SCHEMA_VERSION);
+        let dry_run = migrate_matches.get_flag("dry-run");
+        match migrate_database_to(to_version, dry_run) {
+            Ok(steps) => {
+                for step in &steps {
+                    println!("{}", step);
+                }
+                if steps.is_empty() {
+                    println!("Database is already at schema version {}", to_version);
+                } else if dry_run {
+                    println!("Dry run: {} migration(s) to schema version {} would succeed, the database was not changed", steps.len(), to_version);
+                } else {
+                    println!("Database migrated to schema version {}", to_version);
+                }
+                std::process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("Failed to migrate the database: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Check for configuration field migration
+    if let Some(config_migrate_matches) = cli.subcommand_matches("config").and_then(|config_matches| config_matches.subcommand_matches("migrate")) {
+        let dry_run = config_migrate_matches.get_flag("dry-run");
+        let result = match config_migrate_matches.get_one::<PathBuf>("file") {
+            Some(path) => migrate_configuration_file(path, dry_run),
+            None => initialize_database().and_then(|_| get_configuration_storage().migrate_deprecated_fields(dry_run)).map_err(String::from),
+        };
+        match result {
+            Ok(warnings) => {
+                for warning in &warnings {
+                    println!("{} -> {}", warning.field, warning.replacement);
+                }
+                if warnings.is_empty() {
+                    println!("The configuration has no deprecated fields");
+                } else if dry_run {
+                    println!("Dry run: {} deprecated field(s) would be renamed, the configuration was not changed", warnings.len());
+                } else {
+                    println!("Renamed {} deprecated field(s)", warnings.len());
+                }
+                std::process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("Failed to migrate the configuration: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // Check for restore of a database backup
+    if let Some(backup) = cmd_get_backup_to_restore() {
+        match restore_database_backup(&backup) {
+            Ok(pre_restore_backup) => {
+                println!("Database restored from {}. The database before the restore was backed up as {}", backup, pre_restore_backup.name);
+                std::process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("Failed to restore database backup: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
     // Check for export configuration
     if let Some(path) = cli.get_one::<PathBuf>("export-configuration") {
-        let export_configuration_result = export_configuration_to_file(path);
+        let format = cli.get_one::<String>("export-format").map(|s| s.as_str()).unwrap_or("json");
+        let export_configuration_result = export_configuration_to_file(path, format);
         match export_configuration_result {
             Ok(_) => {
                 println!("Configuration successfully exported to {}", path.display());
@@ -134,15 +357,61 @@ pub fn check_for_command_line_actions() {
     // Check for validate configuration
     if let Some(path) = cli.get_one::<PathBuf>("validate-configuration") {
         match crate::configuration::import_export::validate_configuration_file(path) {
-            Ok(_) => println!("Configuration file is valid: {}", path.display()),
+            Ok(deprecation_warnings) => {
+                for warning in deprecation_warnings {
+                    println!("Warning: {}", warning.message);
+                }
+                println!("Configuration file is valid: {}", path.display());
+            }
             Err(e) => eprintln!("Error validating configuration file: {}", e),
         }
         std::process::exit(0);
     }
 }
 
+// The backup settings are read from the current configuration, so the backup of the database before the restore ends up
+// with the other backups
+fn restore_database_backup(backup: &str) -> Result<DatabaseBackup, String> {
+    initialize_database()?;
+    let settings = fetch_configuration_in_db().map(|configuration| configuration.core.database_backup).unwrap_or_default();
+    restore_backup_file(&settings, &resolve_backup_path(&settings, backup))
+}
+
+fn trust_dev_certificate_authority(export_path: Option<&PathBuf>) -> Result<String, String> {
+    initialize_database()?;
+    let (dev_ca, created) = DevCa::load_or_create()?;
+    let mut output = vec![if created {
+        "Created the development CA".to_string()
+    } else {
+        "Using the existing development CA".to_string()
+    }];
+
+    let cleared = clear_generated_certificates()?;
+    if cleared > 0 {
+        output.push(format!("{} generated certificate(s) will be signed by the development CA on the next start", cleared));
+    }
+
+    if let Some(path) = export_path {
+        std::fs::copy(dev_ca.get_cert_path(), path).map_err(|e| format!("Failed to export the CA certificate to {}: {}", path.display(), e))?;
+        output.push(format!("Exported the CA certificate to {}", path.display()));
+    }
+
+    output.push(String::new());
+    output.push(get_trust_instructions(dev_ca.get_cert_path()));
+    Ok(output.join("\n"))
+}
+
 static COMMAND_LINE_ARGS_SINGLETON: OnceLock<ArgMatches> = OnceLock::new();
 
 pub fn get_command_line_args() -> &'static ArgMatches {
     COMMAND_LINE_ARGS_SINGLETON.get_or_init(|| load_command_line_args())
 }
+
+/// Use these arguments instead of those of the process, such as when Gruxi is embedded in an application with arguments
+/// of its own. Only possible before the arguments are first read. The first argument is the program name
+pub fn set_command_line_args(args: &[&str]) -> Result<(), String> {
+    let matches = get_command_line_command().try_get_matches_from(args).map_err(|e| format!("Invalid arguments: {}", e))?;
+    COMMAND_LINE_ARGS_SINGLETON
+        .set(matches)
+        .map_err(|_| "The command line arguments were read already, and cannot be changed".to_string())
+}
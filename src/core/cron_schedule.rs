@@ -0,0 +1,173 @@
+// ============================================================================
+// CRON SCHEDULE
+// ============================================================================
+//
+// Schedules in the five field format of cron: minute, hour, day of month,
+// month and day of week, such as "30 3 * * 1" for 03:30 every Monday. Each
+// field is "*", a number, a range "a-b", a step "*/n" or "a-b/n", or a comma
+// separated list of those. Day of week 0 and 7 are both Sunday. As in cron,
+// when both day of month and day of week are restricted, a day matching
+// either runs. "@hourly", "@daily", "@weekly" and "@monthly" are shorthands.
+//
+// Schedules are evaluated in UTC, so they do not skip or repeat runs when
+// daylight saving time changes.
+// ============================================================================
+
+use chrono::{DateTime, Datelike, Duration, DurationRound, TimeZone, Timelike, Utc};
+
+// How far ahead the next run is searched for, as schedules such as "0 0 30 2 *" never run
+const MAX_SEARCH_DAYS: i64 = 366 * 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSchedule {
+    minutes: u64,       // Bit per minute 0-59
+    hours: u32,         // Bit per hour 0-23
+    days_of_month: u32, // Bit per day 1-31
+    months: u16,        // Bit per month 1-12
+    days_of_week: u8,   // Bit per day 0-6, Sunday first
+    is_any_day_of_month: bool,
+    is_any_day_of_week: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let expression = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            expression => expression,
+        };
+
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("Schedule '{}' must have 5 fields: minute, hour, day of month, month and day of week", expression));
+        }
+
+        // Sunday is both 0 and 7
+        let days_of_week = parse_field(fields[4], 0, 7, "day of week")?;
+        let days_of_week = ((days_of_week | (days_of_week >> 7)) & 0x7f) as u8;
+
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], 0, 59, "minute")?,
+            hours: parse_field(fields[1], 0, 23, "hour")? as u32,
+            days_of_month: parse_field(fields[2], 1, 31, "day of month")? as u32,
+            months: parse_field(fields[3], 1, 12, "month")? as u16,
+            days_of_week,
+            is_any_day_of_month: fields[2].starts_with('*'),
+            is_any_day_of_week: fields[4].starts_with('*'),
+        })
+    }
+
+    fn matches_day(&self, time: &DateTime<Utc>) -> bool {
+        let day_of_month = self.days_of_month & (1 << time.day()) != 0;
+        let day_of_week = self.days_of_week & (1 << time.weekday().num_days_from_sunday()) != 0;
+        match (self.is_any_day_of_month, self.is_any_day_of_week) {
+            (true, true) => true,
+            (true, false) => day_of_week,
+            (false, true) => day_of_month,
+            (false, false) => day_of_month || day_of_week,
+        }
+    }
+
+    /// The first time after the given one the schedule runs, or None when it does not run within years
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut time = after.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+        let search_end = after + Duration::days(MAX_SEARCH_DAYS);
+
+        while time <= search_end {
+            if self.months & (1 << time.month()) == 0 {
+                // First day of the next month
+                let (year, month) = if time.month() == 12 { (time.year() + 1, 1) } else { (time.year(), time.month() + 1) };
+                time = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()?;
+            } else if !self.matches_day(&time) {
+                time = time.duration_trunc(Duration::days(1)).ok()? + Duration::days(1);
+            } else if self.hours & (1 << time.hour()) == 0 {
+                time = time.duration_trunc(Duration::hours(1)).ok()? + Duration::hours(1);
+            } else if self.minutes & (1 << time.minute()) == 0 {
+                time += Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+}
+
+// The values of a field as bits, such as bits 0, 15, 30 and 45 for "*/15" as minutes
+fn parse_field(field: &str, min: u32, max: u32, name: &str) -> Result<u64, String> {
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("Invalid step '{}' in {} field '{}'", step, name, field)),
+            },
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_value(start, min, max, name)?, parse_value(end, min, max, name)?)
+        } else {
+            let value = parse_value(range, min, max, name)?;
+            // "5/10" runs from 5 to the end of the range, as in cron
+            (value, if part.contains('/') { max } else { value })
+        };
+        if start > end {
+            return Err(format!("Invalid range '{}' in {} field, the start is after the end", range, name));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+fn parse_value(value: &str, min: u32, max: u32, name: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(value) if (min..=max).contains(&value) => Ok(value),
+        _ => Err(format!("Invalid {} '{}', it must be between {} and {}", name, value, min, max)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_next_after() {
+        let schedule = CronSchedule::parse("*/15 * * * *").unwrap();
+        assert_eq!(schedule.next_after(time("2026-03-10T10:07:30Z")), Some(time("2026-03-10T10:15:00Z")));
+        assert_eq!(schedule.next_after(time("2026-03-10T10:15:00Z")), Some(time("2026-03-10T10:30:00Z")));
+
+        // 03:30 on Mondays, 2026-03-10 is a Tuesday
+        let schedule = CronSchedule::parse("30 3 * * 1").unwrap();
+        assert_eq!(schedule.next_after(time("2026-03-10T10:00:00Z")), Some(time("2026-03-16T03:30:00Z")));
+
+        // Sunday as 7, and the first of the month or Sundays when both days are restricted
+        let schedule = CronSchedule::parse("0 0 1 * 7").unwrap();
+        assert_eq!(schedule.next_after(time("2026-03-10T10:00:00Z")), Some(time("2026-03-15T00:00:00Z")));
+        assert_eq!(schedule.next_after(time("2026-03-29T10:00:00Z")), Some(time("2026-04-01T00:00:00Z")));
+
+        let schedule = CronSchedule::parse("@monthly").unwrap();
+        assert_eq!(schedule.next_after(time("2026-12-10T10:00:00Z")), Some(time("2027-01-01T00:00:00Z")));
+
+        // February 30th never comes
+        assert_eq!(CronSchedule::parse("0 0 30 2 *").unwrap().next_after(time("2026-01-01T00:00:00Z")), None);
+    }
+
+    #[test]
+    fn test_invalid_schedules() {
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("0 5-2 * * *").is_err());
+        assert!(CronSchedule::parse("0 0 0 * *").is_err());
+        assert!(CronSchedule::parse("0,30 8-18/2 * 1-6 1-5").is_ok());
+    }
+}
@@ -1,8 +1,233 @@
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
 
-pub fn get_database_connection() -> Result<sqlite::Connection, String> {
-    let mut connection = sqlite::open("./db/gruxi.db").map_err(|e| format!("Failed to open database connection: {}", e))?;
-    connection.set_busy_timeout(500).map_err(|e| format!("Failed to set busy timeout: {}", e))?;
-    connection.execute("PRAGMA journal_mode=WAL;").map_err(|e| format!("Failed to enable WAL journal mode: {}", e))?;
-    connection.execute("PRAGMA foreign_keys=ON;").map_err(|e| format!("Failed to enable foreign key support: {}", e))?;
+use crate::error::gruxi_error::GruxiError;
+use crate::error::gruxi_error_enums::DatabaseError;
+
+// Connections to the configuration database are kept open and reused, as opening one and setting it up costs more than most queries.
+// WAL lets reads go on while a write is in progress. Writes in this process take turns through the writer lock, so they wait
+// for each other instead of failing with SQLITE_BUSY, and the busy timeout covers anything else holding the database
+const DEFAULT_DATABASE_PATH: &str = "./db/gruxi.db";
+const MAX_IDLE_CONNECTIONS: usize = 8;
+const BUSY_TIMEOUT_MILLISECONDS: usize = 5000;
+
+/// Database path for a database that only lives in memory, for embedding Gruxi in tests and other applications
+pub const IN_MEMORY_DATABASE_PATH: &str = ":memory:";
+
+static DATABASE_PATH: Mutex<String> = Mutex::new(String::new());
+// Changed each time the database path is set, so connections to the previous database do not go back to the pool
+static DATABASE_GENERATION: AtomicUsize = AtomicUsize::new(0);
+static IDLE_CONNECTIONS: Mutex<Vec<sqlite::Connection>> = Mutex::new(Vec::new());
+static WRITER_LOCK: Mutex<()> = Mutex::new(());
+// An in-memory database is gone when its last connection closes, so one is kept open until another database is set
+static IN_MEMORY_DATABASE_KEEPER: Mutex<Option<sqlite::Connection>> = Mutex::new(None);
+
+/// Use another database than ./db/gruxi.db, such as IN_MEMORY_DATABASE_PATH, which is a new and empty database each time it is set.
+/// Connections to the previous database still in use keep working on it, so it should be set while no server is running
+pub fn set_database_path(path: &str) {
+    let mut database_path = DATABASE_PATH.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let generation = DATABASE_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    *database_path = if path == IN_MEMORY_DATABASE_PATH {
+        // The memdb VFS shares a database between the connections of the process when its name starts with "/", with the same
+        // locking as a file, where the ":memory:" database would be a new, empty one for each connection. It has no WAL, so reads
+        // from other connections wait while a write commits
+        format!("file:/gruxi-{}?vfs=memdb", generation)
+    } else {
+        path.to_string()
+    };
+
+    IDLE_CONNECTIONS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clear();
+    *IN_MEMORY_DATABASE_KEEPER.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = None;
+}
+
+pub fn get_database_path() -> String {
+    let database_path = DATABASE_PATH.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if database_path.is_empty() { DEFAULT_DATABASE_PATH.to_string() } else { database_path.clone() }
+}
+
+/// A database connection, which goes back to the pool when dropped
+pub struct DatabaseConnection {
+    connection: Option<sqlite::Connection>,
+    is_pooled: bool,
+    generation: usize,
+    // Released after the connection is back in the pool, as fields are dropped after drop() has run
+    _writer_guard: Option<MutexGuard<'static, ()>>,
+}
+
+impl DatabaseConnection {
+    fn unpooled(connection: sqlite::Connection) -> Self {
+        Self {
+            connection: Some(connection),
+            is_pooled: false,
+            generation: 0,
+            _writer_guard: None,
+        }
+    }
+}
+
+impl Deref for DatabaseConnection {
+    type Target = sqlite::Connection;
+
+    fn deref(&self) -> &sqlite::Connection {
+        self.connection.as_ref().expect("Database connection is only taken when dropped")
+    }
+}
+
+impl Drop for DatabaseConnection {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take()
+            && self.is_pooled
+            && self.generation == DATABASE_GENERATION.load(Ordering::SeqCst)
+        {
+            // A transaction left open by a failed operation must not carry over to the next user of the connection
+            let _ = connection.execute("ROLLBACK;");
+            let mut idle_connections = IDLE_CONNECTIONS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if idle_connections.len() < MAX_IDLE_CONNECTIONS {
+                idle_connections.push(connection);
+            }
+        }
+    }
+}
+
+fn connection_error(message: String) -> GruxiError {
+    GruxiError::database(DatabaseError::Connection, message)
+}
+
+fn open_database_connection() -> Result<sqlite::Connection, GruxiError> {
+    let path = get_database_path();
+    if !path.starts_with("file:") {
+        return open_database_connection_at(&path, sqlite::OpenFlags::new().with_create().with_read_write());
+    }
+
+    let connection = open_database_connection_at(&path, sqlite::OpenFlags::new().with_create().with_read_write().with_uri())?;
+    let mut keeper = IN_MEMORY_DATABASE_KEEPER.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if keeper.is_none() {
+        *keeper = Some(open_database_connection_at(&path, sqlite::OpenFlags::new().with_read_write().with_uri())?);
+    }
     Ok(connection)
 }
+
+fn open_database_connection_at(path: &str, flags: sqlite::OpenFlags) -> Result<sqlite::Connection, GruxiError> {
+    let mut connection = sqlite::Connection::open_with_flags(path, flags).map_err(|e| connection_error(format!("Failed to open database connection: {}", e)))?;
+    connection
+        .set_busy_timeout(BUSY_TIMEOUT_MILLISECONDS)
+        .map_err(|e| connection_error(format!("Failed to set busy timeout: {}", e)))?;
+    connection
+        .execute("PRAGMA journal_mode=WAL;")
+        .map_err(|e| connection_error(format!("Failed to enable WAL journal mode: {}", e)))?;
+    // With WAL, NORMAL only syncs at checkpoints, and a power loss can lose the last commits but not corrupt the database
+    connection
+        .execute("PRAGMA synchronous=NORMAL;")
+        .map_err(|e| connection_error(format!("Failed to set synchronous mode: {}", e)))?;
+    connection
+        .execute("PRAGMA foreign_keys=ON;")
+        .map_err(|e| connection_error(format!("Failed to enable foreign key support: {}", e)))?;
+    Ok(connection)
+}
+
+/// A connection to the configuration database, for reading
+pub fn get_database_connection() -> Result<DatabaseConnection, GruxiError> {
+    let generation = DATABASE_GENERATION.load(Ordering::SeqCst);
+    let idle_connection = IDLE_CONNECTIONS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).pop();
+    let connection = match idle_connection {
+        Some(connection) => connection,
+        None => open_database_connection()?,
+    };
+    Ok(DatabaseConnection {
+        connection: Some(connection),
+        is_pooled: true,
+        generation,
+        _writer_guard: None,
+    })
+}
+
+/// A connection to the configuration database, for changing it. Only one is handed out at a time and the next caller waits
+/// until it is dropped, so it should be dropped as soon as the writes are done, and never be held across an await
+pub fn get_database_writer() -> Result<DatabaseConnection, GruxiError> {
+    let writer_guard = WRITER_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let mut connection = get_database_connection()?;
+    connection._writer_guard = Some(writer_guard);
+    Ok(connection)
+}
+
+// Sessions of the admin portal are kept in the shared session database when configured, so all replicas see the same sessions
+pub fn get_session_database_connection(shared_session_database: &str) -> Result<DatabaseConnection, GruxiError> {
+    if shared_session_database.is_empty() {
+        return get_database_connection();
+    }
+
+    let mut connection = sqlite::open(shared_session_database).map_err(|e| connection_error(format!("Failed to open shared session database {}: {}", shared_session_database, e)))?;
+    // Other nodes may hold the lock for a while on network storage
+    connection.set_busy_timeout(5000).map_err(|e| connection_error(format!("Failed to set busy timeout: {}", e)))?;
+    // WAL needs shared memory between the processes, which does not work across hosts, so the rollback journal is used
+    connection
+        .execute("PRAGMA journal_mode=DELETE;")
+        .map_err(|e| connection_error(format!("Failed to set journal mode of shared session database: {}", e)))?;
+    connection
+        .execute(crate::database::database_schema::get_shared_session_schema())
+        .map_err(|e| connection_error(format!("Failed to create sessions table in shared session database: {}", e)))?;
+    Ok(DatabaseConnection::unpooled(connection))
+}
+
+// ACME accounts and certificates are kept in a shared database when configured, so all nodes behind one DNS name use the same certificates
+pub fn get_acme_cache_database_connection(acme_cache_database: &str) -> Result<DatabaseConnection, GruxiError> {
+    if acme_cache_database.is_empty() {
+        return get_database_connection();
+    }
+
+    let mut connection = sqlite::open(acme_cache_database).map_err(|e| connection_error(format!("Failed to open ACME cache database {}: {}", acme_cache_database, e)))?;
+    // Other nodes may hold the lock for a while on network storage
+    connection.set_busy_timeout(5000).map_err(|e| connection_error(format!("Failed to set busy timeout: {}", e)))?;
+    // WAL needs shared memory between the processes, which does not work across hosts, so the rollback journal is used
+    connection
+        .execute("PRAGMA journal_mode=DELETE;")
+        .map_err(|e| connection_error(format!("Failed to set journal mode of ACME cache database: {}", e)))?;
+    connection
+        .execute(crate::database::database_schema::get_acme_cache_schema())
+        .map_err(|e| connection_error(format!("Failed to create ACME cache table in ACME cache database: {}", e)))?;
+    Ok(DatabaseConnection::unpooled(connection))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test_writers_take_turns_and_transactions_do_not_leak() {
+        let writer = get_database_writer().unwrap();
+        writer.execute("BEGIN IMMEDIATE TRANSACTION;").unwrap();
+
+        let second_writer_done = Arc::new(AtomicBool::new(false));
+        let second_writer = {
+            let second_writer_done = second_writer_done.clone();
+            std::thread::spawn(move || {
+                let writer = get_database_writer().unwrap();
+                // The transaction of the first writer was rolled back when it went back to the pool
+                writer.execute("BEGIN IMMEDIATE TRANSACTION;").unwrap();
+                writer.execute("ROLLBACK;").unwrap();
+                second_writer_done.store(true, Ordering::SeqCst);
+            })
+        };
+
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        assert!(!second_writer_done.load(Ordering::SeqCst));
+        drop(writer);
+        second_writer.join().unwrap();
+        assert!(second_writer_done.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_memdb_database_is_shared_between_connections() {
+        let flags = || sqlite::OpenFlags::new().with_create().with_read_write().with_uri();
+        let first = open_database_connection_at("file:/gruxi-test?vfs=memdb", flags()).unwrap();
+        first.execute("CREATE TABLE shared (value INTEGER); INSERT INTO shared VALUES (42);").unwrap();
+
+        let second = open_database_connection_at("file:/gruxi-test?vfs=memdb", flags()).unwrap();
+        let mut statement = second.prepare("SELECT value FROM shared").unwrap();
+        assert_eq!(statement.next().unwrap(), sqlite::State::Row);
+        assert_eq!(statement.read::<i64, _>(0).unwrap(), 42);
+    }
+}
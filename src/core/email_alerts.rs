@@ -0,0 +1,153 @@
+// ============================================================================
+// EMAIL ALERTS
+// ============================================================================
+//
+// Sends admin alerts by email, as a notification channel next to the admin
+// portal. The source of an alert is its event type, which the routes of the
+// email alert settings send to their recipients.
+//
+// Repeats of an alert, with the same source and message, are emailed at most
+// once per repeat interval. Repeats in between are counted, and the count is
+// in the next email of the alert, so a flapping system does not flood the
+// inbox but is not hidden either.
+// ============================================================================
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::configuration::cached_configuration::get_cached_configuration;
+use crate::configuration::email_alerts::EmailAlertSettings;
+use crate::core::admin_alerts::AdminAlert;
+use crate::logging::syslog::{trace, warn};
+use crate::network::smtp_client::{SmtpMessage, SmtpServer, send_mail};
+
+// When an alert was last emailed, and how often it was raised since
+struct AlertRepeats {
+    last_sent: Instant,
+    suppressed: u64,
+}
+
+// Keyed by source and message
+static ALERT_REPEATS: LazyLock<Mutex<HashMap<(String, String), AlertRepeats>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Whether the alert is emailed now, and how many repeats of it were not. Alerts are counted as sent when they are let
+// through, so repeats raised while one is being sent are held back too
+fn should_send(source: &str, message: &str, repeat_interval: Duration, now: Instant) -> Option<u64> {
+    let mut alert_repeats = ALERT_REPEATS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    // Forget alerts that have not been raised for a while
+    alert_repeats.retain(|_, repeats| now.duration_since(repeats.last_sent) < repeat_interval || repeats.suppressed > 0);
+
+    let key = (source.to_string(), message.to_string());
+    match alert_repeats.get_mut(&key) {
+        Some(repeats) if now.duration_since(repeats.last_sent) < repeat_interval => {
+            repeats.suppressed += 1;
+            None
+        }
+        Some(repeats) => {
+            let suppressed = repeats.suppressed;
+            repeats.last_sent = now;
+            repeats.suppressed = 0;
+            Some(suppressed)
+        }
+        None => {
+            alert_repeats.insert(key, AlertRepeats { last_sent: now, suppressed: 0 });
+            Some(0)
+        }
+    }
+}
+
+// Email the alert to the recipients routed its source, in the background. Called for every admin alert
+pub fn notify_by_email(alert: &AdminAlert) {
+    // Alerts raised outside of the runtime, such as during startup, are not emailed
+    let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+        return;
+    };
+    let alert = alert.clone();
+    runtime.spawn(async move {
+        let settings = get_cached_configuration().get_configuration().await.core.email_alerts.clone();
+        if !settings.is_enabled {
+            return;
+        }
+        let recipients = settings.get_recipients(&alert.source);
+        if recipients.is_empty() {
+            trace(format!("No email alert route for alerts from {}", alert.source));
+            return;
+        }
+        let Some(suppressed) = should_send(&alert.source, &alert.message, Duration::from_secs(settings.repeat_interval_minutes as u64 * 60), Instant::now()) else {
+            trace(format!("Repeated alert from {} is not emailed again yet", alert.source));
+            return;
+        };
+
+        let subject = format!("[Gruxi] Alert from {}", alert.source);
+        let mut body = format!("{}\n\nSource: {}\nRaised at: {}\n", alert.message, alert.source, alert.created_at);
+        if suppressed > 0 {
+            body.push_str(&format!(
+                "\nThis alert was raised {} more times since it was last emailed, which were not emailed to limit repeats.\n",
+                suppressed
+            ));
+        }
+
+        // Not raised as an admin alert, which would be emailed in turn
+        if let Err(e) = send_email(&settings, &recipients, &subject, &body).await {
+            warn(format!("Failed to email the alert from {}: {}", alert.source, e));
+        }
+    });
+}
+
+// Send an email through the SMTP server of the settings, such as to test them
+pub async fn send_email(settings: &EmailAlertSettings, recipients: &[String], subject: &str, body: &str) -> Result<(), String> {
+    let server = SmtpServer {
+        host: &settings.smtp_host,
+        port: settings.smtp_port,
+        tls_mode: &settings.smtp_tls_mode,
+        verify_certificates: settings.smtp_verify_certificates,
+        username: &settings.smtp_username,
+        password: &settings.smtp_password,
+    };
+    let message = SmtpMessage {
+        from: &settings.from_address,
+        to: recipients,
+        subject,
+        body,
+    };
+    send_mail(&server, &message).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::email_alerts::EmailAlertRoute;
+
+    #[test]
+    fn test_alert_routing() {
+        let mut settings = EmailAlertSettings::new();
+        settings.routes = vec![
+            EmailAlertRoute {
+                event_types: vec!["*".to_string()],
+                recipients: vec!["ops@example.com".to_string()],
+            },
+            EmailAlertRoute {
+                event_types: vec!["external_handler".to_string()],
+                recipients: vec!["php-team@example.com".to_string(), "ops@example.com".to_string()],
+            },
+        ];
+        assert_eq!(settings.get_recipients("External_Handler"), vec!["ops@example.com", "php-team@example.com"]);
+        assert_eq!(settings.get_recipients("Webroot sync"), vec!["ops@example.com"]);
+    }
+
+    #[test]
+    fn test_repeated_alerts_are_rate_limited() {
+        let source = format!("test-{}", uuid::Uuid::new_v4());
+        let interval = Duration::from_secs(60);
+        let start = Instant::now();
+
+        assert_eq!(should_send(&source, "disk full", interval, start), Some(0));
+        assert_eq!(should_send(&source, "disk full", interval, start + Duration::from_secs(10)), None);
+        assert_eq!(should_send(&source, "disk full", interval, start + Duration::from_secs(20)), None);
+        // Another message of the same source is another alert
+        assert_eq!(should_send(&source, "disk almost full", interval, start + Duration::from_secs(20)), Some(0));
+        assert_eq!(should_send(&source, "disk full", interval, start + Duration::from_secs(61)), Some(2));
+        assert_eq!(should_send(&source, "disk full", interval, start + Duration::from_secs(62)), None);
+    }
+}
@@ -0,0 +1,366 @@
+// ============================================================================
+// EMBEDDED SERVER
+// ============================================================================
+//
+// Runs Gruxi inside another application, such as an integration test, with
+// GruxiServer::builder(). The configuration is given in code and the
+// database can live in memory, so nothing is read from or written to
+// ./db/gruxi.db. Starting returns once every binding is listening, and
+// stopping once every listener is closed, so there is no need to sleep and
+// hope the server is up.
+//
+// The parts of Gruxi are process wide, such as the database, the running
+// state and the triggers, so one server runs in a process at a time. A
+// stopped server can be started again with another configuration and
+// database. The tasks of the server run on the tokio runtime that started
+// it, which must keep running until it is stopped, or on a runtime of its
+// own with start_in_background(), for a server shared by tests. OS signals
+// are left to the application.
+//
+// Bindings on port 0 get a free port from the OS, so tests do not collide on
+// fixed ports, and get_binding_address() tells which one. For the same
+// reason the admin portal, on its fixed port, is not started, as with
+// --disable-admin-portal.
+// ============================================================================
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use tokio::select;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::admin_portal::init::initialize_admin_site;
+use crate::configuration::cached_configuration::get_cached_configuration;
+use crate::configuration::configuration::Configuration;
+use crate::configuration::load_configuration;
+use crate::configuration::save_configuration::save_configuration;
+use crate::core::command_hooks::{get_command_hooks_for_event, run_command_hooks, spawn_command_hooks};
+use crate::core::command_line_args::set_command_line_args;
+use crate::core::database_connection::{IN_MEMORY_DATABASE_PATH, set_database_path};
+use crate::core::monitoring::get_monitoring_state;
+use crate::core::operation_mode::{OperationMode, is_valid_operation_mode, reload_operation_mode, set_new_operation_mode};
+use crate::core::running_state_manager::get_running_state_manager;
+use crate::core::triggers::get_trigger_handler;
+use crate::database::database_schema::initialize_database;
+use crate::error::gruxi_error::GruxiError;
+use crate::error::gruxi_error_enums::ServerError;
+use crate::http::health_probes::get_readiness;
+use crate::http::http_server::get_binding_port;
+use crate::logging::syslog::{error, info};
+
+const DEFAULT_START_TIMEOUT: Duration = Duration::from_secs(10);
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+const LISTENER_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+static IS_SERVER_RUNNING: AtomicBool = AtomicBool::new(false);
+static HAS_SERVER_STARTED: AtomicBool = AtomicBool::new(false);
+
+pub struct GruxiServerBuilder {
+    configuration: Option<Configuration>,
+    database_path: Option<String>,
+    operation_mode: Option<OperationMode>,
+    start_timeout: Duration,
+}
+
+impl GruxiServerBuilder {
+    /// Serve this configuration, which is validated and saved to the database. Without it, the configuration in the database is served
+    pub fn configuration(mut self, configuration: Configuration) -> Self {
+        self.configuration = Some(configuration);
+        self
+    }
+
+    /// Keep the database in memory, so it starts empty and is gone when another database is set or the process ends
+    pub fn in_memory_database(self) -> Self {
+        self.database_path(IN_MEMORY_DATABASE_PATH)
+    }
+
+    pub fn database_path(mut self, path: &str) -> Self {
+        self.database_path = Some(path.to_string());
+        self
+    }
+
+    pub fn operation_mode(mut self, operation_mode: OperationMode) -> Self {
+        self.operation_mode = Some(operation_mode);
+        self
+    }
+
+    /// How long to wait for the bindings to start listening, 10 seconds by default
+    pub fn start_timeout(mut self, start_timeout: Duration) -> Self {
+        self.start_timeout = start_timeout;
+        self
+    }
+
+    /// Start the server, and return when every binding is listening
+    pub async fn start(self) -> Result<GruxiServer, GruxiError> {
+        if IS_SERVER_RUNNING.swap(true, Ordering::SeqCst) {
+            return Err(GruxiError::server(ServerError::AlreadyRunning, "A Gruxi server is running in this process already"));
+        }
+
+        match self.start_server().await {
+            Ok(server) => Ok(server),
+            Err(e) => {
+                IS_SERVER_RUNNING.store(false, Ordering::SeqCst);
+                Err(e)
+            }
+        }
+    }
+
+    /// Start the server on a thread and tokio runtime of its own, which run until it is stopped, and return when every binding
+    /// is listening. For a server shared by tests, as each test has a runtime of its own, which stops the tasks spawned on it
+    pub fn start_in_background(self) -> Result<GruxiServer, GruxiError> {
+        let (result_sender, result_receiver) = std::sync::mpsc::channel();
+        std::thread::Builder::new()
+            .name("gruxi-server".to_string())
+            .spawn(move || {
+                let runtime = match tokio::runtime::Builder::new_multi_thread().enable_all().build() {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        let _ = result_sender.send(Err(GruxiError::server(ServerError::Startup, format!("Failed to start tokio runtime: {}", e))));
+                        return;
+                    }
+                };
+                runtime.block_on(async move {
+                    match self.start().await {
+                        Ok(server) => {
+                            let stopped_token = server.stopped_token.clone();
+                            let _ = result_sender.send(Ok(server));
+                            stopped_token.cancelled().await;
+                        }
+                        Err(e) => {
+                            let _ = result_sender.send(Err(e));
+                        }
+                    }
+                });
+            })
+            .map_err(|e| GruxiError::server(ServerError::Startup, format!("Failed to start server thread: {}", e)))?;
+
+        result_receiver
+            .recv()
+            .map_err(|_| GruxiError::server(ServerError::Startup, "The server thread stopped before the server started"))?
+    }
+
+    async fn start_server(mut self) -> Result<GruxiServer, GruxiError> {
+        if let Some(database_path) = &self.database_path {
+            set_database_path(database_path);
+        }
+        initialize_database()?;
+
+        // Loaded before anything is saved, as logging reads it, which would otherwise happen in the middle of a save.
+        // Loaded again on each start, as it is kept in the database, which may be another one
+        reload_operation_mode();
+        if let Some(operation_mode) = self.operation_mode {
+            let operation_mode = format!("{:?}", operation_mode);
+            if !is_valid_operation_mode(&operation_mode) || !set_new_operation_mode(operation_mode.clone()) {
+                return Err(GruxiError::server(ServerError::Startup, format!("Failed to set operation mode {}", operation_mode)));
+            }
+        }
+
+        if let Some(configuration) = self.configuration.as_mut() {
+            save_configuration(configuration, true).map_err(|e| e.with_context("Failed to save the configuration"))?;
+        }
+
+        initialize_admin_site().map_err(|_| GruxiError::server(ServerError::Startup, "Failed to initialize admin site"))?;
+
+        // The cached configuration is loaded when first used, and has to be loaded again for a later start
+        let cached_configuration = get_cached_configuration();
+        if HAS_SERVER_STARTED.swap(true, Ordering::SeqCst) {
+            *cached_configuration.configuration.write().await = load_configuration::init();
+            get_running_state_manager().await.set_new_running_state().await;
+        } else {
+            get_monitoring_state().await.initialize_monitoring();
+            get_running_state_manager().await;
+        }
+
+        crate::http::http_server::initialize_server().await;
+
+        let shutdown_token = CancellationToken::new();
+        let stopped_token = CancellationToken::new();
+        let server_task = tokio::spawn(run_server(shutdown_token.clone(), stopped_token.clone()));
+        let server = GruxiServer {
+            shutdown_handle: GruxiShutdownHandle { shutdown_token },
+            stopped_token,
+            server_task,
+        };
+
+        // Readiness counts a binding as listening once its listener is bound
+        let started = Instant::now();
+        loop {
+            let readiness = get_readiness(&*cached_configuration.get_configuration().await).await;
+            if readiness.is_ready {
+                break;
+            }
+            if started.elapsed() >= self.start_timeout {
+                let _ = server.stop().await;
+                return Err(GruxiError::server(
+                    ServerError::Timeout,
+                    format!(
+                        "Gruxi was not ready within {:?}, bindings not listening: [{}], handlers not running: [{}]",
+                        self.start_timeout,
+                        readiness.bindings_not_listening.join(", "),
+                        readiness.handlers_not_running.join(", ")
+                    ),
+                ));
+            }
+            tokio::time::sleep(LISTENER_POLL_INTERVAL).await;
+        }
+
+        info(format!("Gruxi {} started", env!("CARGO_PKG_VERSION")));
+        Ok(server)
+    }
+}
+
+/// Gruxi running inside this process. Stop it with stop() or a shutdown handle, as dropping it leaves it running
+pub struct GruxiServer {
+    shutdown_handle: GruxiShutdownHandle,
+    stopped_token: CancellationToken,
+    server_task: JoinHandle<Result<(), GruxiError>>,
+}
+
+impl GruxiServer {
+    /// Call this before anything else of Gruxi, such as Configuration::get_default(), as those read the arguments of the process otherwise
+    pub fn builder() -> GruxiServerBuilder {
+        // The arguments of the application, such as those of the test harness, are not for Gruxi. Set already on a later start
+        let _ = set_command_line_args(&["gruxi", "--disable-admin-portal"]);
+
+        GruxiServerBuilder {
+            configuration: None,
+            database_path: None,
+            operation_mode: None,
+            start_timeout: DEFAULT_START_TIMEOUT,
+        }
+    }
+
+    /// The configuration being served, which changes when the configuration is saved through the admin API
+    pub async fn get_configuration(&self) -> tokio::sync::RwLockReadGuard<'static, Configuration> {
+        get_cached_configuration().get_configuration().await
+    }
+
+    /// The address a binding listens on, with the port picked by the OS for a binding on port 0
+    pub async fn get_binding_address(&self, binding_id: &str) -> Option<SocketAddr> {
+        let configuration = self.get_configuration().await;
+        let binding = configuration.bindings.iter().find(|binding| binding.id == binding_id)?;
+        let ip = binding.ip.parse().ok()?;
+        match get_binding_port(binding) {
+            0 => None,
+            port => Some(SocketAddr::new(ip, port)),
+        }
+    }
+
+    /// A handle that stops the server from anywhere, such as another task or a test fixture
+    pub fn get_shutdown_handle(&self) -> GruxiShutdownHandle {
+        self.shutdown_handle.clone()
+    }
+
+    /// Wait until the server is stopped through a shutdown handle
+    pub async fn wait_for_shutdown(self) -> Result<(), GruxiError> {
+        self.stopped_token.cancelled().await;
+        self.join().await
+    }
+
+    /// Stop the server, and return when every listener is closed
+    pub async fn stop(self) -> Result<(), GruxiError> {
+        self.shutdown_handle.shutdown();
+        self.join().await
+    }
+
+    async fn join(self) -> Result<(), GruxiError> {
+        self.server_task
+            .await
+            .map_err(|e| GruxiError::server(ServerError::Startup, format!("Server task exited with error: {}", e)))?
+    }
+}
+
+/// Stops the server it was taken from. Can be cloned and sent to other tasks and threads
+#[derive(Clone)]
+pub struct GruxiShutdownHandle {
+    shutdown_token: CancellationToken,
+}
+
+impl GruxiShutdownHandle {
+    /// Start stopping the server. GruxiServer::stop() or wait_for_shutdown() return once it is stopped
+    pub fn shutdown(&self) {
+        self.shutdown_token.cancel();
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown_token.is_cancelled()
+    }
+}
+
+// Serve until the shutdown token is cancelled, then stop the listeners and their connections, but not the tasks of the
+// process, such as logging, so a server can start again
+async fn run_server(shutdown_token: CancellationToken, stopped_token: CancellationToken) -> Result<(), GruxiError> {
+    reload_on_configuration_changes(shutdown_token).await;
+    get_trigger_handler().run_trigger("stop_services").await;
+
+    let started = Instant::now();
+    let mut result = Ok(());
+    loop {
+        let configuration = get_cached_configuration().get_configuration().await;
+        if get_readiness(&configuration).await.bindings_not_listening.len() == configuration.bindings.len() {
+            break;
+        }
+        drop(configuration);
+        if started.elapsed() >= STOP_TIMEOUT {
+            result = Err(GruxiError::server(ServerError::Timeout, "The listeners of Gruxi did not stop in time"));
+            break;
+        }
+        tokio::time::sleep(LISTENER_POLL_INTERVAL).await;
+    }
+
+    IS_SERVER_RUNNING.store(false, Ordering::SeqCst);
+    stopped_token.cancel();
+    info("Gruxi stopped");
+    result
+}
+
+/// Replace the running state and restart the bindings each time the configuration is reloaded, until the stop token is cancelled
+pub async fn reload_on_configuration_changes(stop_token: CancellationToken) {
+    let running_state_manager = get_running_state_manager().await;
+    let triggers = get_trigger_handler();
+
+    loop {
+        let configuration_trigger_option = triggers.get_trigger("reload_configuration");
+        let configuration_trigger = match configuration_trigger_option {
+            Some(trigger) => trigger,
+            None => {
+                error("Failed to get reload_configuration trigger - If this happens, please report a bug");
+                return;
+            }
+        };
+        let configuration_token = configuration_trigger.read().await.clone();
+
+        select! {
+            _ = configuration_token.cancelled() => {
+                info("Reloading running state due to configuration change");
+
+                // The hooks of the configuration being applied, which "pre_reload" hooks are waited for
+                let configuration = load_configuration::fetch_configuration_in_db();
+                let (pre_reload_hooks, post_reload_hooks, configuration_version) = match &configuration {
+                    Ok(configuration) => (
+                        get_command_hooks_for_event(configuration, "pre_reload"),
+                        get_command_hooks_for_event(configuration, "post_reload"),
+                        configuration.version.to_string(),
+                    ),
+                    Err(e) => {
+                        error(format!("Failed to load command hooks for the reload: {}", e));
+                        (vec![], vec![], String::new())
+                    }
+                };
+                let variables = vec![("GRUXI_CONFIGURATION_VERSION", configuration_version)];
+                run_command_hooks(&pre_reload_hooks, "pre_reload", &variables).await;
+
+                running_state_manager.set_new_running_state().await;
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                crate::http::http_server::initialize_server().await;
+
+                spawn_command_hooks(post_reload_hooks, "post_reload", variables);
+            }
+            _ = stop_token.cancelled() => {
+                break;
+            }
+        }
+    }
+}
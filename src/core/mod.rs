@@ -1,10 +1,24 @@
-pub mod operation_mode;
-pub mod command_line_args;
+pub mod admin_alerts;
 pub mod admin_user;
+pub mod background_tasks;
+pub mod cluster_sync;
+pub mod command_hooks;
+pub mod command_line_args;
+pub mod cron_schedule;
 pub mod database_connection;
+pub mod email_alerts;
+pub mod gruxi_server;
 pub mod monitoring;
-pub mod background_tasks;
+pub mod operation_mode;
 pub mod os_signal;
 pub mod running_state;
 pub mod running_state_manager;
+pub mod scheduled_changes;
+pub mod scheduled_tasks;
+pub mod site_health;
+pub mod site_test_runner;
+pub mod speedtest;
+pub mod synthetic_probes;
+pub mod traffic_accounting;
 pub mod triggers;
+pub mod usage_reports;
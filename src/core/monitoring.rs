@@ -1,4 +1,9 @@
-use crate::core::{running_state_manager::get_running_state_manager, triggers::get_trigger_handler};
+use crate::configuration::deprecated_fields::get_configuration_deprecations;
+use crate::core::scheduled_tasks::get_scheduled_tasks_json;
+use crate::core::{admin_alerts::get_admin_alerts, running_state_manager::get_running_state_manager, triggers::get_trigger_handler};
+use crate::file::disk_usage::get_disk_usage_json;
+use crate::http::long_running_connections::get_long_running_connections_summary;
+use crate::http::request_priority::get_request_admission;
 use crate::logging::syslog::{debug, trace};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use tokio::{select, sync::OnceCell};
@@ -12,6 +17,11 @@ pub struct MonitoringState {
     file_cache_enabled: AtomicBool,
     file_cache_current_items: AtomicUsize,
     file_cache_max_items: AtomicUsize,
+    file_cache_content_bytes: AtomicUsize,
+    file_cache_variant_bytes: AtomicUsize,
+    file_cache_variant_max_bytes: AtomicUsize,
+    tls_handshakes: AtomicUsize,
+    tls_resumed_handshakes: AtomicUsize,
 }
 
 impl MonitoringState {
@@ -28,6 +38,11 @@ impl MonitoringState {
             file_cache_enabled: AtomicBool::new(configuration.core.file_cache.is_enabled),
             file_cache_current_items: AtomicUsize::new(0), // Updated from monitoring thread
             file_cache_max_items: AtomicUsize::new(configuration.core.file_cache.cache_item_size),
+            file_cache_content_bytes: AtomicUsize::new(0), // Updated from monitoring thread
+            file_cache_variant_bytes: AtomicUsize::new(0), // Updated from monitoring thread
+            file_cache_variant_max_bytes: AtomicUsize::new(configuration.core.file_cache.compressed_variants_max_size),
+            tls_handshakes: AtomicUsize::new(0),         // Updated from http server
+            tls_resumed_handshakes: AtomicUsize::new(0), // Updated from http server
         }
     }
 
@@ -70,15 +85,23 @@ impl MonitoringState {
                 let file_reader_cache = unlocked_running_state.get_file_reader_cache();
 
                 monitoring_state.file_cache_current_items.store(file_reader_cache.get_current_item_count() as usize, Ordering::Relaxed);
+                let (content_bytes, variant_bytes) = file_reader_cache.get_memory_usage();
+                monitoring_state.file_cache_content_bytes.store(content_bytes as usize, Ordering::Relaxed);
+                monitoring_state.file_cache_variant_bytes.store(variant_bytes as usize, Ordering::Relaxed);
 
                 // Clone the configuration values we need, then drop the guard
-                let (file_cache_enabled, file_cache_max_items) = {
+                let (file_cache_enabled, file_cache_max_items, file_cache_variant_max_bytes) = {
                     let cached_configuration = crate::configuration::cached_configuration::get_cached_configuration();
                     let configuration = cached_configuration.get_configuration().await;
-                    (configuration.core.file_cache.is_enabled, configuration.core.file_cache.cache_item_size)
+                    (
+                        configuration.core.file_cache.is_enabled,
+                        configuration.core.file_cache.cache_item_size,
+                        configuration.core.file_cache.compressed_variants_max_size,
+                    )
                 };
                 monitoring_state.file_cache_enabled.store(file_cache_enabled, Ordering::Relaxed);
                 monitoring_state.file_cache_max_items.store(file_cache_max_items, Ordering::Relaxed);
+                monitoring_state.file_cache_variant_max_bytes.store(file_cache_variant_max_bytes, Ordering::Relaxed);
             }
 
             trace("Monitoring data updated");
@@ -109,6 +132,10 @@ impl MonitoringState {
         self.requests_served.load(Ordering::Relaxed)
     }
 
+    pub fn get_uptime_seconds(&self) -> u64 {
+        self.server_start_time.elapsed().as_secs()
+    }
+
     pub fn increment_requests_in_queue(&self) {
         self.requests_in_progress.fetch_add(1, Ordering::Relaxed);
     }
@@ -117,22 +144,53 @@ impl MonitoringState {
         self.requests_in_progress.fetch_sub(1, Ordering::Relaxed);
     }
 
+    pub fn record_tls_handshake(&self, is_resumed: bool) {
+        self.tls_handshakes.fetch_add(1, Ordering::Relaxed);
+        if is_resumed {
+            self.tls_resumed_handshakes.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
     pub async fn get_json(&self) -> serde_json::Value {
         let monitoring_state = get_monitoring_state().await;
 
+        let external_handlers = {
+            let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+            running_state.get_external_system_handler().get_handlers_status_json()
+        };
+
         // Get the requests in progress minus one to account for the current monitoring request
         let requests_in_progress = monitoring_state.requests_in_progress.load(Ordering::Relaxed).saturating_sub(1);
 
+        let tls_handshakes = monitoring_state.tls_handshakes.load(Ordering::Relaxed);
+        let tls_resumed_handshakes = monitoring_state.tls_resumed_handshakes.load(Ordering::Relaxed);
+        let tls_resumption_rate = if tls_handshakes > 0 { tls_resumed_handshakes as f64 * 100.0 / tls_handshakes as f64 } else { 0.0 };
+
         serde_json::json!({
             "requests_served": monitoring_state.get_requests_served(),
             "requests_per_sec": f64::from_bits(monitoring_state.requests_served_per_sec.load(Ordering::Relaxed) as u64),
             "requests_in_progress": requests_in_progress,
-            "uptime_seconds": monitoring_state.server_start_time.elapsed().as_secs(),
+            "uptime_seconds": monitoring_state.get_uptime_seconds(),
             "file_cache": {
                 "enabled": monitoring_state.file_cache_enabled.load(Ordering::Relaxed),
                 "current_items": monitoring_state.file_cache_current_items.load(Ordering::Relaxed),
                 "max_items": monitoring_state.file_cache_max_items.load(Ordering::Relaxed),
-            }
+                "content_bytes": monitoring_state.file_cache_content_bytes.load(Ordering::Relaxed),
+                "compressed_variant_bytes": monitoring_state.file_cache_variant_bytes.load(Ordering::Relaxed),
+                "compressed_variant_max_bytes": monitoring_state.file_cache_variant_max_bytes.load(Ordering::Relaxed),
+            },
+            "tls_sessions": {
+                "handshakes": tls_handshakes,
+                "resumed_handshakes": tls_resumed_handshakes,
+                "resumption_rate": tls_resumption_rate, // in %
+            },
+            "external_handlers": external_handlers,
+            "long_running_connections": get_long_running_connections_summary(),
+            "request_priority": get_request_admission().get_json(),
+            "disk_usage": get_disk_usage_json(),
+            "scheduled_tasks": get_scheduled_tasks_json(),
+            "alerts": get_admin_alerts(),
+            "configuration_deprecations": get_configuration_deprecations(),
         })
     }
 }
@@ -1,6 +1,9 @@
-use crate::core::{command_line_args::cmd_get_operation_mode, database_connection::get_database_connection};
+use crate::core::{
+    command_line_args::cmd_get_operation_mode,
+    database_connection::{get_database_connection, get_database_writer},
+};
+use crate::database::data_access::{execute, query_one};
 use crate::logging::syslog::error;
-use sqlite::State;
 use std::sync::{
     RwLock,
     atomic::{AtomicBool, Ordering},
@@ -13,6 +16,7 @@ pub enum OperationMode {
     DEBUG,
     PRODUCTION,
     ULTIMATE,
+    SPEEDTEST, // Runs the built-in benchmark and exits, only from the command line
 }
 
 pub fn load_operation_mode() -> OperationMode {
@@ -30,29 +34,16 @@ pub fn load_operation_mode() -> OperationMode {
             }
         };
 
-        let stmt_result = connection.prepare("SELECT gruxi_value FROM gruxi WHERE gruxi_key = 'operation_mode'");
-        let mut stmt = match stmt_result {
-            Ok(s) => s,
+        let mode_str = match query_one(&connection, "SELECT gruxi_value FROM gruxi WHERE gruxi_key = 'operation_mode'", &[], |row| {
+            row.get_string("gruxi_value")
+        }) {
+            Ok(mode_str) => mode_str,
             Err(e) => {
-                error(format!("Failed to prepare operation_mode query: {}", e));
+                error(format!("Failed to query operation_mode: {}", e));
                 return OperationMode::PRODUCTION;
             }
         };
 
-        let mode_str_option = stmt.next();
-        let mode_str = match mode_str_option {
-            Ok(opt) => opt,
-            Err(e) => {
-                error(format!("Failed to execute operation_mode query: {}", e));
-                return OperationMode::PRODUCTION;
-            }
-        };
-
-        let mode_str: Option<String> = match mode_str {
-            State::Row => stmt.read::<String, _>(0).ok(),
-            State::Done => None,
-        };
-
         if let Some(mode) = mode_str {
             opmode = mode;
         } else {
@@ -69,6 +60,7 @@ pub fn match_string_to_operation_mode(mode_str: &str) -> Option<OperationMode> {
         "DEBUG" => Some(OperationMode::DEBUG),
         "PRODUCTION" => Some(OperationMode::PRODUCTION),
         "ULTIMATE" => Some(OperationMode::ULTIMATE),
+        "SPEEDTEST" => Some(OperationMode::SPEEDTEST),
         _ => None,
     }
 }
@@ -101,17 +93,25 @@ pub fn get_operation_mode() -> OperationMode {
     }
 }
 
+// Load the operation mode from the command line or the database again, such as after another database is set
+pub fn reload_operation_mode() -> OperationMode {
+    IS_OPERATION_MODE_LOADED.store(false, Ordering::SeqCst);
+    get_operation_mode()
+}
+
 pub fn get_operation_mode_as_string() -> String {
     match get_operation_mode() {
         OperationMode::DEV => "DEV".to_string(),
         OperationMode::DEBUG => "DEBUG".to_string(),
         OperationMode::PRODUCTION => "PRODUCTION".to_string(),
         OperationMode::ULTIMATE => "ULTIMATE".to_string(),
+        OperationMode::SPEEDTEST => "SPEEDTEST".to_string(),
     }
 }
 
+// Whether the mode can be saved as the operation mode. SPEEDTEST would make every start run the benchmark and exit
 pub fn is_valid_operation_mode(mode_str: &str) -> bool {
-    match_string_to_operation_mode(mode_str).is_some()
+    match_string_to_operation_mode(mode_str).is_some_and(|mode| mode != OperationMode::SPEEDTEST)
 }
 
 pub fn set_new_operation_mode(new_mode: String) -> bool {
@@ -129,7 +129,7 @@ pub fn set_new_operation_mode(new_mode: String) -> bool {
             drop(mode_write);
 
             // Update db, if exist, update it else insert new
-            let connection_result = get_database_connection();
+            let connection_result = get_database_writer();
             let connection = match connection_result {
                 Ok(conn) => conn,
                 Err(e) => {
@@ -139,84 +139,26 @@ pub fn set_new_operation_mode(new_mode: String) -> bool {
             };
 
             // Check if operation_mode exists
-            let stmt_result = connection.prepare("SELECT gruxi_value FROM gruxi WHERE gruxi_key = 'operation_mode'");
-            let mut stmt = match stmt_result {
-                Ok(s) => s,
-                Err(e) => {
-                    error(format!("Failed to prepare select query: {} - Returning false", e));
-                    return false;
-                }
-            };
-
-            let existing_id: Option<i64> = match stmt.next() {
-                Ok(state) => match state {
-                    State::Row => stmt.read::<i64, _>(0).ok(),
-                    State::Done => None,
-                },
+            let existing_mode = match query_one(&connection, "SELECT gruxi_value FROM gruxi WHERE gruxi_key = 'operation_mode'", &[], |row| {
+                row.get_string("gruxi_value")
+            }) {
+                Ok(existing_mode) => existing_mode,
                 Err(e) => {
                     error(format!("Failed to execute select query: {} - Returning false", e));
                     return false;
                 }
             };
 
-            drop(stmt);
-
-            if let Some(_) = existing_id {
+            let result = if existing_mode.is_some() {
                 // Update existing record
-                let update_stmt_result = connection.prepare("UPDATE gruxi SET gruxi_value = ? WHERE gruxi_key = ?");
-                let mut update_stmt = match update_stmt_result {
-                    Ok(s) => s,
-                    Err(e) => {
-                        error(format!("Failed to prepare update query: {} - Returning false", e));
-                        return false;
-                    }
-                };
-
-                match update_stmt.bind((1, new_mode.as_str())) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        error(format!("Failed to bind new_mode: {} - Returning false", e));
-                        return false;
-                    }
-                };
-                match update_stmt.bind((2, "operation_mode")) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        error(format!("Failed to bind operation_mode key: {} - Returning false", e));
-                        return false;
-                    }
-                };
-                match update_stmt.next() {
-                    Ok(_) => (),
-                    Err(e) => {
-                        error(format!("Failed to execute update query: {} - Returning false", e));
-                        return false;
-                    }
-                };
+                execute(&connection, "UPDATE gruxi SET gruxi_value = ? WHERE gruxi_key = ?", &[&new_mode, &"operation_mode"])
             } else {
                 // Insert new record
-                let insert_stmt_result = connection.prepare("INSERT INTO gruxi (gruxi_key, gruxi_value) VALUES ('operation_mode', ?)");
-                let mut insert_stmt = match insert_stmt_result {
-                    Ok(s) => s,
-                    Err(e) => {
-                        error(format!("Failed to prepare insert query: {} - Returning false", e));
-                        return false;
-                    }
-                };
-                match insert_stmt.bind((1, new_mode.as_str())) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        error(format!("Failed to bind new_mode: {} - Returning false", e));
-                        return false;
-                    }
-                };
-                match insert_stmt.next() {
-                    Ok(_) => (),
-                    Err(e) => {
-                        error(format!("Failed to execute insert query: {} - Returning false", e));
-                        return false;
-                    }
-                };
+                execute(&connection, "INSERT INTO gruxi (gruxi_key, gruxi_value) VALUES ('operation_mode', ?)", &[&new_mode])
+            };
+            if let Err(e) = result {
+                error(format!("Failed to save operation mode: {} - Returning false", e));
+                return false;
             }
 
             // Trigger operation_mode_changed event
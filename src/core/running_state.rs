@@ -1,4 +1,5 @@
 use crate::{
+    configuration::cached_configuration::get_cached_configuration,
     external_connections::external_system_handler::ExternalSystemHandler,
     file::file_reader_structs::FileReaderCache,
     http::{
@@ -7,6 +8,7 @@ use crate::{
         site_match::binding_site_cache::BindingSiteCache,
     },
     logging::syslog::{debug},
+    network::dns_resolver::DnsResolver,
 };
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -45,8 +47,9 @@ impl RunningState {
         let processor_manager = ProcessorManager::new().await;
         debug("Processor manager initialized");
 
-        // Initialize http clients
-        let http_client = HttpClient::new();
+        // Initialize http clients, with the DNS resolution of the configuration
+        let dns_resolution = get_cached_configuration().get_configuration().await.core.dns_resolution.clone();
+        let http_client = HttpClient::new(DnsResolver::new(dns_resolution));
         debug("HTTP client initialized");
 
         // Start binding site cache
@@ -0,0 +1,457 @@
+// ============================================================================
+// SCHEDULED CONFIGURATION CHANGES
+// ============================================================================
+//
+// Configuration changes that are applied at a later time, such as enabling a
+// site or switching an upstream group at the start of a maintenance window.
+// A change is stored as the fields that differ between the configuration at
+// the time it is scheduled and the submitted configuration, so changes made
+// to other parts of the configuration in the meantime are kept. Lists of
+// items with an id, such as sites and processors, are compared per item.
+//
+// When a field was changed to something else since the change was scheduled,
+// the change fails instead of overwriting it. Every step of a change, from
+// scheduling to being applied, failing or cancelled, is kept as an event.
+// ============================================================================
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::configuration::configuration::Configuration;
+use crate::configuration::load_configuration::fetch_configuration_in_db;
+use crate::configuration::save_configuration::save_configuration;
+use crate::core::database_connection::{get_database_connection, get_database_writer};
+use crate::core::triggers::get_trigger_handler;
+use crate::database::data_access::{execute, query};
+use crate::logging::syslog::{debug, error, info};
+
+// How often to check for changes that are due
+const SCHEDULED_CHANGES_CHECK_INTERVAL_SECS: u64 = 15;
+
+// Username in the events of changes made by the scheduler
+const SCHEDULER_USERNAME: &str = "scheduler";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathSegment {
+    Key(String), // Field of an object
+    Id(String),  // Item with this id in a list
+}
+
+// A field that is changed, added (no old value) or removed (no new value)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigurationChange {
+    pub path: Vec<PathSegment>,
+    pub old_value: Option<Value>,
+    pub new_value: Option<Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduledChangeEvent {
+    pub event: String, // "scheduled", "applied", "failed" or "cancelled"
+    pub username: String,
+    pub details: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduledChange {
+    pub id: String,
+    pub description: String,
+    pub scheduled_at: String,
+    pub status: String, // "pending", "applied", "failed" or "cancelled"
+    pub created_by: String,
+    pub created_at: String,
+    pub changes: Vec<ConfigurationChange>,
+    pub events: Vec<ScheduledChangeEvent>,
+}
+
+pub fn format_path(path: &[PathSegment]) -> String {
+    let mut formatted = String::new();
+    for segment in path {
+        match segment {
+            PathSegment::Key(key) if formatted.is_empty() => formatted.push_str(key),
+            PathSegment::Key(key) => formatted.push_str(&format!(".{}", key)),
+            PathSegment::Id(id) => formatted.push_str(&format!("[{}]", id)),
+        }
+    }
+    formatted
+}
+
+// The changes that turn the current configuration into the new one, both as JSON
+pub fn diff_configurations(current: &Value, new: &Value) -> Vec<ConfigurationChange> {
+    let mut changes = Vec::new();
+    diff_values(&mut Vec::new(), Some(current), Some(new), &mut changes);
+    changes
+}
+
+fn diff_values(path: &mut Vec<PathSegment>, old: Option<&Value>, new: Option<&Value>, changes: &mut Vec<ConfigurationChange>) {
+    if old == new {
+        return;
+    }
+
+    match (old, new) {
+        (Some(Value::Object(old_fields)), Some(Value::Object(new_fields))) => {
+            let added_keys = new_fields.keys().filter(|key| !old_fields.contains_key(*key));
+            for key in old_fields.keys().chain(added_keys) {
+                path.push(PathSegment::Key(key.clone()));
+                diff_values(path, old_fields.get(key), new_fields.get(key), changes);
+                path.pop();
+            }
+        }
+        (Some(Value::Array(old_items)), Some(Value::Array(new_items))) if is_list_with_ids(old_items) && is_list_with_ids(new_items) => {
+            let added_ids = new_items.iter().filter_map(get_item_id).filter(|id| find_item(old_items, id).is_none());
+            let ids: Vec<&str> = old_items.iter().filter_map(get_item_id).chain(added_ids).collect();
+            for id in ids {
+                path.push(PathSegment::Id(id.to_string()));
+                diff_values(path, find_item(old_items, id), find_item(new_items, id), changes);
+                path.pop();
+            }
+        }
+        _ => changes.push(ConfigurationChange {
+            path: path.clone(),
+            old_value: old.cloned(),
+            new_value: new.cloned(),
+        }),
+    }
+}
+
+// Lists where every item has a unique id are compared per item, other lists as a whole
+fn is_list_with_ids(items: &[Value]) -> bool {
+    let ids: Vec<&str> = items.iter().filter_map(get_item_id).collect();
+    ids.len() == items.len() && ids.iter().enumerate().all(|(index, id)| !ids[..index].contains(id))
+}
+
+fn get_item_id(item: &Value) -> Option<&str> {
+    item.get("id").and_then(Value::as_str)
+}
+
+fn find_item<'a>(items: &'a [Value], id: &str) -> Option<&'a Value> {
+    items.iter().find(|item| get_item_id(item) == Some(id))
+}
+
+// Apply changes to the configuration as JSON. A field that has neither the old nor the new value was changed by
+// someone else in the meantime, which fails the whole change
+pub fn apply_changes(configuration: &mut Value, changes: &[ConfigurationChange]) -> Result<(), Vec<String>> {
+    let errors: Vec<String> = changes.iter().filter_map(|change| apply_change(configuration, change).err()).collect();
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+fn apply_change(configuration: &mut Value, change: &ConfigurationChange) -> Result<(), String> {
+    let Some((last, parents)) = change.path.split_last() else {
+        return Err("A scheduled change cannot replace the whole configuration".to_string());
+    };
+
+    let mut container = configuration;
+    for segment in parents {
+        let next = match segment {
+            PathSegment::Key(key) => container.get_mut(key.as_str()),
+            PathSegment::Id(id) => container.as_array_mut().and_then(|items| items.iter_mut().find(|item| get_item_id(item) == Some(id.as_str()))),
+        };
+        container = next.ok_or_else(|| format!("'{}' no longer exists", format_path(&change.path)))?;
+    }
+
+    let current = match last {
+        PathSegment::Key(key) => container.get(key.as_str()),
+        PathSegment::Id(id) => container.as_array().and_then(|items| find_item(items, id)),
+    };
+    if current == change.new_value.as_ref() {
+        return Ok(());
+    }
+    if current != change.old_value.as_ref() {
+        return Err(format!("'{}' was changed since the change was scheduled", format_path(&change.path)));
+    }
+
+    match (last, &change.new_value) {
+        (PathSegment::Key(key), new_value) => {
+            let fields = container.as_object_mut().ok_or_else(|| format!("'{}' is not an object", format_path(parents)))?;
+            match new_value {
+                Some(value) => fields.insert(key.clone(), value.clone()),
+                None => fields.remove(key),
+            };
+        }
+        (PathSegment::Id(id), new_value) => {
+            let items = container.as_array_mut().ok_or_else(|| format!("'{}' is not a list", format_path(parents)))?;
+            let position = items.iter().position(|item| get_item_id(item) == Some(id.as_str()));
+            match (position, new_value) {
+                (Some(position), Some(value)) => items[position] = value.clone(),
+                (Some(position), None) => {
+                    items.remove(position);
+                }
+                (None, Some(value)) => items.push(value.clone()),
+                (None, None) => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Schedule the changes from the current configuration to the given one. Returns the id of the scheduled change
+pub fn schedule_configuration_change(mut configuration: Configuration, scheduled_at: DateTime<Utc>, description: &str, username: &str) -> Result<String, Vec<String>> {
+    if scheduled_at <= Utc::now() {
+        return Err(vec!["The time of a scheduled change must be in the future".to_string()]);
+    }
+
+    // Same checks as when saving, so a change is not scheduled only to fail validation later
+    configuration.sanitize();
+    configuration.validate()?;
+
+    let current_configuration = fetch_configuration_in_db().map_err(|e| vec![format!("Failed to fetch current configuration: {}", e)])?;
+    let current_json = serde_json::to_value(&current_configuration).map_err(|e| vec![format!("Failed to serialize current configuration: {}", e)])?;
+    let new_json = serde_json::to_value(&configuration).map_err(|e| vec![format!("Failed to serialize configuration: {}", e)])?;
+    let changes = diff_configurations(&current_json, &new_json);
+    if changes.is_empty() {
+        return Err(vec!["The configuration has no changes to schedule".to_string()]);
+    }
+
+    let id = Uuid::new_v4().to_string();
+    let changes_json = serde_json::to_string(&changes).map_err(|e| vec![format!("Failed to serialize changes: {}", e)])?;
+    insert_scheduled_change(&id, description, &scheduled_at.to_rfc3339(), &changes_json, username).map_err(|e| vec![e])?;
+    let _ = add_event(&id, "scheduled", username, &format!("{} fields to change at {}", changes.len(), scheduled_at.to_rfc3339()));
+
+    info(format!(
+        "Configuration change '{}' with {} fields was scheduled for {} by '{}'",
+        id,
+        changes.len(),
+        scheduled_at.to_rfc3339(),
+        username
+    ));
+    Ok(id)
+}
+
+fn insert_scheduled_change(id: &str, description: &str, scheduled_at: &str, changes_json: &str, username: &str) -> Result<(), String> {
+    let connection = get_database_writer()?;
+    execute(
+        &connection,
+        "INSERT INTO scheduled_changes (id, description, scheduled_at, status, changes, created_by, created_at) VALUES (?, ?, ?, 'pending', ?, ?, ?)",
+        &[&id, &description, &scheduled_at, &changes_json, &username, &Utc::now().to_rfc3339()],
+    )
+    .map_err(|e| format!("Failed to save scheduled change: {}", e))
+}
+
+fn add_event(change_id: &str, event: &str, username: &str, details: &str) -> Result<(), String> {
+    let connection = get_database_writer()?;
+    execute(
+        &connection,
+        "INSERT INTO scheduled_change_events (change_id, event, username, details, created_at) VALUES (?, ?, ?, ?, ?)",
+        &[&change_id, &event, &username, &details, &Utc::now().to_rfc3339()],
+    )
+    .map_err(|e| format!("Failed to save scheduled change event: {}", e))
+}
+
+fn set_status(change_id: &str, status: &str) -> Result<(), String> {
+    let connection = get_database_writer()?;
+    execute(&connection, "UPDATE scheduled_changes SET status = ? WHERE id = ?", &[&status, &change_id]).map_err(|e| format!("Failed to update scheduled change status: {}", e))
+}
+
+/// All scheduled changes with their events, the most recently scheduled first
+pub fn list_scheduled_changes() -> Result<Vec<ScheduledChange>, String> {
+    let connection = get_database_connection()?;
+    let mut scheduled_changes = query(
+        &connection,
+        "SELECT id, description, scheduled_at, status, changes, created_by, created_at FROM scheduled_changes ORDER BY scheduled_at DESC",
+        &[],
+        |row| {
+            Ok(ScheduledChange {
+                id: row.get_string("id")?,
+                description: row.get_string("description")?,
+                scheduled_at: row.get_string("scheduled_at")?,
+                status: row.get_string("status")?,
+                changes: serde_json::from_str(&row.get_string("changes")?).unwrap_or_default(),
+                created_by: row.get_string("created_by")?,
+                created_at: row.get_string("created_at")?,
+                events: Vec::new(),
+            })
+        },
+    )
+    .map_err(|e| format!("Failed to query scheduled changes: {}", e))?;
+
+    let events = query(
+        &connection,
+        "SELECT change_id, event, username, details, created_at FROM scheduled_change_events ORDER BY id",
+        &[],
+        |row| {
+            let event = ScheduledChangeEvent {
+                event: row.get_string("event")?,
+                username: row.get_string("username")?,
+                details: row.get_string("details")?,
+                created_at: row.get_string("created_at")?,
+            };
+            Ok((row.get_string("change_id")?, event))
+        },
+    )
+    .map_err(|e| format!("Failed to query scheduled change events: {}", e))?;
+    for (change_id, event) in events {
+        if let Some(scheduled_change) = scheduled_changes.iter_mut().find(|scheduled_change| scheduled_change.id == change_id) {
+            scheduled_change.events.push(event);
+        }
+    }
+
+    Ok(scheduled_changes)
+}
+
+/// Cancel a pending change. Returns false when there is no pending change with the id
+pub fn cancel_scheduled_change(change_id: &str, username: &str) -> Result<bool, String> {
+    let connection = get_database_writer()?;
+    execute(&connection, "UPDATE scheduled_changes SET status = 'cancelled' WHERE id = ? AND status = 'pending'", &[&change_id]).map_err(|e| format!("Failed to cancel scheduled change: {}", e))?;
+    if connection.change_count() == 0 {
+        return Ok(false);
+    }
+    drop(connection);
+
+    add_event(change_id, "cancelled", username, "")?;
+    info(format!("Scheduled configuration change '{}' was cancelled by '{}'", change_id, username));
+    Ok(true)
+}
+
+// Pending changes that are due, as id and changes, the earliest first
+fn get_due_changes() -> Result<Vec<(String, Vec<ConfigurationChange>)>, String> {
+    let connection = get_database_connection()?;
+    let pending_changes = query(
+        &connection,
+        "SELECT id, scheduled_at, changes FROM scheduled_changes WHERE status = 'pending' ORDER BY scheduled_at",
+        &[],
+        |row| Ok((row.get_string("id")?, row.get_string("scheduled_at")?, row.get_string("changes")?)),
+    )
+    .map_err(|e| format!("Failed to query due changes: {}", e))?;
+
+    let now = Utc::now();
+    let mut due_changes = Vec::new();
+    for (id, scheduled_at, changes_json) in pending_changes {
+        let is_due = DateTime::parse_from_rfc3339(&scheduled_at).is_ok_and(|scheduled_at| scheduled_at <= now);
+        if is_due {
+            due_changes.push((id, serde_json::from_str(&changes_json).map_err(|e| format!("Failed to parse changes: {}", e))?));
+        }
+    }
+    Ok(due_changes)
+}
+
+// Apply a change to the configuration in the database
+fn apply_scheduled_change(changes: &[ConfigurationChange]) -> Result<bool, Vec<String>> {
+    let current_configuration = fetch_configuration_in_db().map_err(|e| vec![format!("Failed to fetch current configuration: {}", e)])?;
+    let mut configuration_json = serde_json::to_value(&current_configuration).map_err(|e| vec![format!("Failed to serialize current configuration: {}", e)])?;
+    apply_changes(&mut configuration_json, changes)?;
+    let mut configuration: Configuration = serde_json::from_value(configuration_json).map_err(|e| vec![format!("Failed to parse changed configuration: {}", e)])?;
+    save_configuration(&mut configuration, false).map_err(|e| {
+        if e.get_http_status_code() >= 500 {
+            error(format!("Failed to save the scheduled configuration change: {}", e));
+        }
+        e.get_messages()
+    })
+}
+
+// Apply the changes that are due. Returns whether the configuration changed, so it has to be reloaded
+fn apply_due_changes() -> bool {
+    let due_changes = match get_due_changes() {
+        Ok(due_changes) => due_changes,
+        Err(e) => {
+            error(format!("Failed to get due configuration changes: {}", e));
+            return false;
+        }
+    };
+
+    let mut is_changed = false;
+    for (id, changes) in due_changes {
+        let (status, details) = match apply_scheduled_change(&changes) {
+            Ok(was_saved) => {
+                is_changed |= was_saved;
+                info(format!("Scheduled configuration change '{}' was applied", id));
+                ("applied", if was_saved { String::new() } else { "The configuration already had these changes".to_string() })
+            }
+            Err(errors) => {
+                error(format!("Scheduled configuration change '{}' failed: {}", id, errors.join("; ")));
+                ("failed", errors.join("; "))
+            }
+        };
+        if let Err(e) = set_status(&id, status).and_then(|_| add_event(&id, status, SCHEDULER_USERNAME, &details)) {
+            error(format!("Failed to record the result of scheduled configuration change '{}': {}", id, e));
+        }
+    }
+    is_changed
+}
+
+/// Start applying scheduled changes when they are due. It stops on shutdown or stop_services triggers, so it is
+/// started again on configuration reload.
+pub async fn start_scheduled_changes() {
+    let triggers = get_trigger_handler();
+    let shutdown_token = triggers.get_token("shutdown").await.unwrap_or_else(CancellationToken::new);
+    let stop_services_token = triggers.get_token("stop_services").await.unwrap_or_else(CancellationToken::new);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(SCHEDULED_CHANGES_CHECK_INTERVAL_SECS));
+        loop {
+            tokio::select! {
+                _ = shutdown_token.cancelled() => {
+                    debug("Scheduled configuration changes stopping due to shutdown signal");
+                    break;
+                }
+                _ = stop_services_token.cancelled() => {
+                    debug("Scheduled configuration changes stopping due to stop_services signal");
+                    break;
+                }
+                _ = interval.tick() => {
+                    let is_changed = tokio::task::spawn_blocking(apply_due_changes).await.unwrap_or(false);
+                    if is_changed {
+                        // Same as a reload from the admin portal, which also restarts this task
+                        let triggers = get_trigger_handler();
+                        triggers.run_trigger("refresh_cached_configuration").await;
+                        triggers.run_trigger("reload_configuration").await;
+                        break;
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_and_apply_changes() {
+        let current = json!({
+            "version": 1,
+            "sites": [
+                { "id": "a", "is_enabled": false, "hostnames": ["a.com"] },
+                { "id": "b", "is_enabled": true, "hostnames": ["b.com"] }
+            ]
+        });
+        let new = json!({
+            "version": 1,
+            "sites": [
+                { "id": "a", "is_enabled": true, "hostnames": ["a.com", "www.a.com"] },
+                { "id": "c", "is_enabled": true, "hostnames": [] }
+            ]
+        });
+
+        let changes = diff_configurations(&current, &new);
+        let paths: Vec<String> = changes.iter().map(|change| format_path(&change.path)).collect();
+        assert_eq!(paths, vec!["sites[a].hostnames", "sites[a].is_enabled", "sites[b]", "sites[c]"]);
+
+        // Changes made to other fields in the meantime are kept
+        let mut changed_meanwhile = current.clone();
+        changed_meanwhile["version"] = json!(2);
+        apply_changes(&mut changed_meanwhile, &changes).unwrap();
+        let mut expected = new.clone();
+        expected["version"] = json!(2);
+        assert_eq!(changed_meanwhile, expected);
+
+        // A field changed to something else in the meantime fails the change
+        let mut conflicting = current.clone();
+        conflicting["sites"][0]["hostnames"] = json!(["other.com"]);
+        let errors = apply_changes(&mut conflicting, &changes).unwrap_err();
+        assert_eq!(errors, vec!["'sites[a].hostnames' was changed since the change was scheduled"]);
+
+        // A field that already has the new value is left as it is
+        let mut already_changed = current.clone();
+        already_changed["sites"][0]["is_enabled"] = json!(true);
+        assert!(apply_changes(&mut already_changed, &changes).is_ok());
+    }
+}
@@ -0,0 +1,471 @@
+// ============================================================================
+// SCHEDULED TASKS
+// ============================================================================
+//
+// Recurring maintenance jobs, run on a cron schedule (see cron_schedule.rs):
+//
+// - log_rotation: rotates the system and access logs over a size
+// - cache_cleanup: removes expired admin sessions and files cached longer
+//   than the lifetime of the file cache
+// - certificate_check: raises an admin alert for TLS certificates of sites
+//   that expire soon. ACME certificates are renewed by the ACME manager and
+//   are not checked
+// - database_backup: takes a backup of the configuration database, with the
+//   directory and retention of the database backup settings
+// - analytics_rollup: rolls the access log analytics over their window
+//
+// Tasks are managed through the admin API and stored with the status of their
+// last run, which is shown in monitoring. A failed run raises an admin alert.
+// Runs missed while Gruxi was stopped are not caught up, and a task still
+// running when it is due again is skipped.
+// ============================================================================
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::configuration::cached_configuration::get_cached_configuration;
+use crate::core::admin_alerts::add_admin_alert;
+use crate::core::admin_user::cleanup_all_expired_sessions;
+use crate::core::cron_schedule::CronSchedule;
+use crate::core::database_connection::{get_database_connection, get_database_writer};
+use crate::core::running_state_manager::get_running_state_manager;
+use crate::core::triggers::get_trigger_handler;
+use crate::database::data_access::{execute, query};
+use crate::database::database_backup::create_backup;
+use crate::logging::access_log_analytics::roll_up_access_log_analytics;
+use crate::logging::log_rotation::{get_log_file_paths, rotate_log_file};
+use crate::logging::syslog::{debug, error, info, trace};
+use crate::tls::certificate_store::get_certificate_expiry;
+
+pub const SCHEDULED_TASK_JOB_TYPES: [&str; 5] = ["log_rotation", "cache_cleanup", "certificate_check", "database_backup", "analytics_rollup"];
+
+// How often to check for tasks that are due
+const SCHEDULED_TASKS_CHECK_INTERVAL_SECS: u64 = 30;
+
+// Parameters of the jobs, with their defaults and the highest value allowed
+const LOG_ROTATION_MAX_SIZE_MB: (&str, u64, u64) = ("max_size_mb", 100, 1_000_000);
+const LOG_ROTATION_KEEP_FILES: (&str, u64, u64) = ("keep_files", 7, 1000);
+const CERTIFICATE_CHECK_WARNING_DAYS: (&str, u64, u64) = ("warning_days", 14, 365);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledTask {
+    pub id: String,
+    pub name: String,
+    pub job_type: String,
+    pub schedule: String,
+    pub parameters: Map<String, Value>,
+    pub is_enabled: bool,
+    pub created_by: String,
+    pub created_at: String,
+    pub last_run_at: String, // Empty until the task has run
+    pub last_status: String, // "success" or "failed", empty until the task has run
+    pub last_message: String,
+    pub last_duration_ms: u64,
+    pub is_running: bool,
+    pub next_run_at: Option<String>, // None when disabled, or when the schedule never runs
+}
+
+// The next run of a task, with the schedule it was worked out from
+type NextRun = (String, DateTime<Utc>);
+
+// The next run of each enabled task by id
+static NEXT_RUNS: LazyLock<Mutex<HashMap<String, NextRun>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Ids of the tasks that are running
+static RUNNING_TASKS: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+// The parameters the job type takes
+fn get_job_parameters(job_type: &str) -> &'static [(&'static str, u64, u64)] {
+    match job_type {
+        "log_rotation" => &[LOG_ROTATION_MAX_SIZE_MB, LOG_ROTATION_KEEP_FILES],
+        "certificate_check" => &[CERTIFICATE_CHECK_WARNING_DAYS],
+        _ => &[],
+    }
+}
+
+// A parameter of a task, or its default when not set
+fn get_parameter(parameters: &Map<String, Value>, (name, default, _): (&str, u64, u64)) -> u64 {
+    parameters.get(name).and_then(|value| value.as_u64()).unwrap_or(default)
+}
+
+pub fn validate_scheduled_task(job_type: &str, schedule: &str, parameters: &Map<String, Value>) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+
+    if !SCHEDULED_TASK_JOB_TYPES.contains(&job_type) {
+        errors.push(format!("Job type must be one of {}, got '{}'", SCHEDULED_TASK_JOB_TYPES.join(", "), job_type));
+    }
+    if let Err(e) = CronSchedule::parse(schedule) {
+        errors.push(e);
+    }
+
+    let job_parameters = get_job_parameters(job_type);
+    for (name, value) in parameters {
+        match job_parameters.iter().find(|(parameter_name, _, _)| parameter_name == name) {
+            Some((_, _, max)) => match value.as_u64() {
+                Some(value) if (1..=*max).contains(&value) => {}
+                _ => errors.push(format!("Parameter '{}' must be a number between 1 and {}", name, max)),
+            },
+            None => errors.push(format!("Unknown parameter '{}' for job type '{}'", name, job_type)),
+        }
+    }
+
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+/// Add a task, or update the task with the id, keeping the status of its last run. Returns the id
+pub fn save_scheduled_task(id: Option<&str>, name: &str, job_type: &str, schedule: &str, parameters: &Map<String, Value>, is_enabled: bool, username: &str) -> Result<String, Vec<String>> {
+    let schedule = schedule.trim();
+    validate_scheduled_task(job_type, schedule, parameters)?;
+    let parameters_json = Value::Object(parameters.clone()).to_string();
+    let connection = get_database_writer().map_err(|e| vec![e.to_string()])?;
+
+    if let Some(id) = id {
+        execute(
+            &connection,
+            "UPDATE scheduled_tasks SET name = ?, job_type = ?, schedule = ?, parameters = ?, is_enabled = ? WHERE id = ?",
+            &[&name, &job_type, &schedule, &parameters_json, &is_enabled, &id],
+        )
+        .map_err(|e| vec![format!("Failed to update scheduled task: {}", e)])?;
+        if connection.change_count() == 0 {
+            return Err(vec![format!("No scheduled task with id '{}'", id)]);
+        }
+        info(format!("Scheduled task '{}' ({}, '{}') was updated by '{}'", id, job_type, schedule, username));
+        return Ok(id.to_string());
+    }
+
+    let id = Uuid::new_v4().to_string();
+    execute(
+        &connection,
+        "INSERT INTO scheduled_tasks (id, name, job_type, schedule, parameters, is_enabled, created_by, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        &[&id, &name, &job_type, &schedule, &parameters_json, &is_enabled, &username, &Utc::now().to_rfc3339()],
+    )
+    .map_err(|e| vec![format!("Failed to save scheduled task: {}", e)])?;
+    info(format!("Scheduled task '{}' ({}, '{}') was added by '{}'", id, job_type, schedule, username));
+    Ok(id)
+}
+
+/// Delete a task. Returns false when there is no task with the id
+pub fn delete_scheduled_task(id: &str, username: &str) -> Result<bool, String> {
+    let connection = get_database_writer()?;
+    execute(&connection, "DELETE FROM scheduled_tasks WHERE id = ?", &[&id]).map_err(|e| format!("Failed to delete scheduled task: {}", e))?;
+    if connection.change_count() == 0 {
+        return Ok(false);
+    }
+    info(format!("Scheduled task '{}' was deleted by '{}'", id, username));
+    Ok(true)
+}
+
+/// All tasks with the status of their last run and their next run, by name
+pub fn list_scheduled_tasks() -> Result<Vec<ScheduledTask>, String> {
+    let connection = get_database_connection()?;
+    let mut tasks = query(&connection, "SELECT * FROM scheduled_tasks ORDER BY name, created_at", &[], |row| {
+        Ok(ScheduledTask {
+            id: row.get_string("id")?,
+            name: row.get_string("name")?,
+            job_type: row.get_string("job_type")?,
+            schedule: row.get_string("schedule")?,
+            parameters: row.get_json("parameters")?,
+            is_enabled: row.get_bool("is_enabled")?,
+            created_by: row.get_string("created_by")?,
+            created_at: row.get_string("created_at")?,
+            last_run_at: row.get_string("last_run_at")?,
+            last_status: row.get_string("last_status")?,
+            last_message: row.get_string("last_message")?,
+            last_duration_ms: row.get_u64("last_duration_ms")?,
+            is_running: false,
+            next_run_at: None,
+        })
+    })
+    .map_err(|e| format!("Failed to query scheduled tasks: {}", e))?;
+
+    let now = Utc::now();
+    let next_runs = NEXT_RUNS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let running_tasks = RUNNING_TASKS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    for task in &mut tasks {
+        task.is_running = running_tasks.contains(&task.id);
+        if task.is_enabled {
+            let next_run = match next_runs.get(&task.id) {
+                Some((schedule, next_run)) if *schedule == task.schedule => Some(*next_run),
+                _ => CronSchedule::parse(&task.schedule).ok().and_then(|schedule| schedule.next_after(now)),
+            };
+            task.next_run_at = next_run.map(|next_run| next_run.to_rfc3339());
+        }
+    }
+    Ok(tasks)
+}
+
+/// The tasks and their last runs for the monitoring output
+pub fn get_scheduled_tasks_json() -> Value {
+    match list_scheduled_tasks() {
+        Ok(tasks) => serde_json::to_value(tasks).unwrap_or_default(),
+        Err(e) => {
+            error(format!("Failed to list scheduled tasks for monitoring: {}", e));
+            Value::Array(Vec::new())
+        }
+    }
+}
+
+fn save_run_status(id: &str, started_at: DateTime<Utc>, result: &Result<String, String>, duration: Duration) -> Result<(), String> {
+    let (status, message) = match result {
+        Ok(message) => ("success", message),
+        Err(message) => ("failed", message),
+    };
+    let connection = get_database_writer()?;
+    execute(
+        &connection,
+        "UPDATE scheduled_tasks SET last_run_at = ?, last_status = ?, last_message = ?, last_duration_ms = ? WHERE id = ?",
+        &[&started_at.to_rfc3339(), &status, message, &(duration.as_millis() as u64), &id],
+    )
+    .map_err(|e| format!("Failed to save the status of scheduled task '{}': {}", id, e))
+}
+
+/// Run a task now and record the status of the run. Returns None when the task is already running, else the result
+pub async fn run_scheduled_task(task: &ScheduledTask) -> Option<Result<String, String>> {
+    if !RUNNING_TASKS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(task.id.clone()) {
+        return None;
+    }
+
+    let started_at = Utc::now();
+    let start_time = Instant::now();
+    debug(format!("Scheduled task '{}' ({}) started", task.id, task.job_type));
+    let result = run_job(&task.job_type, &task.parameters).await;
+    let duration = start_time.elapsed();
+
+    match &result {
+        Ok(message) => info(format!("Scheduled task '{}' ({}) finished in {} ms: {}", task.id, task.job_type, duration.as_millis(), message)),
+        Err(message) => add_admin_alert("scheduled_task", format!("Scheduled task '{}' ({}) failed: {}", task.name, task.job_type, message)),
+    }
+    if let Err(e) = save_run_status(&task.id, started_at, &result, duration) {
+        error(e);
+    }
+
+    RUNNING_TASKS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&task.id);
+    Some(result)
+}
+
+// Run the job, returning a summary of what it did
+async fn run_job(job_type: &str, parameters: &Map<String, Value>) -> Result<String, String> {
+    match job_type {
+        "log_rotation" => {
+            let max_bytes = get_parameter(parameters, LOG_ROTATION_MAX_SIZE_MB) * 1024 * 1024;
+            let keep_files = get_parameter(parameters, LOG_ROTATION_KEEP_FILES) as usize;
+            let log_file_paths = get_log_file_paths().await;
+            let log_file_count = log_file_paths.len();
+            tokio::task::spawn_blocking(move || {
+                let mut rotated_count = 0;
+                let mut errors = Vec::new();
+                for log_file_path in &log_file_paths {
+                    match rotate_log_file(log_file_path, max_bytes, keep_files) {
+                        Ok(true) => rotated_count += 1,
+                        Ok(false) => {}
+                        Err(e) => errors.push(e),
+                    }
+                }
+                if errors.is_empty() {
+                    Ok(format!("Rotated {} of {} log files", rotated_count, log_file_count))
+                } else {
+                    Err(errors.join("; "))
+                }
+            })
+            .await
+            .map_err(|e| format!("Log rotation failed: {}", e))?
+        }
+        "cache_cleanup" => {
+            let expired_sessions = tokio::task::spawn_blocking(cleanup_all_expired_sessions)
+                .await
+                .map_err(|e| format!("Session cleanup failed: {}", e))??;
+            let max_item_lifetime = get_cached_configuration().get_configuration().await.core.file_cache.max_item_lifetime as u64;
+            let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+            let removed_files = running_state.get_file_reader_cache().remove_items_older_than(Duration::from_secs(max_item_lifetime));
+            Ok(format!("Removed {} expired admin sessions and {} files from the file cache", expired_sessions, removed_files))
+        }
+        "certificate_check" => {
+            let warning_days = get_parameter(parameters, CERTIFICATE_CHECK_WARNING_DAYS) as i64;
+            let certificates: Vec<(String, String)> = get_cached_configuration()
+                .get_configuration()
+                .await
+                .sites
+                .iter()
+                .filter(|site| site.is_enabled && !site.tls_automatic_enabled && !site.tls_cert_path.is_empty())
+                .map(|site| (site.id.clone(), site.tls_cert_path.clone()))
+                .collect();
+            check_certificates(&certificates, warning_days, Utc::now())
+        }
+        "database_backup" => {
+            let settings = get_cached_configuration().get_configuration().await.core.database_backup.clone();
+            let backup = tokio::task::spawn_blocking(move || create_backup(&settings))
+                .await
+                .map_err(|e| format!("Database backup failed: {}", e))??;
+            Ok(format!("Database backup {} written ({} bytes)", backup.name, backup.size_bytes))
+        }
+        "analytics_rollup" => Ok(format!("Removed {} hourly access log analytics buckets", roll_up_access_log_analytics())),
+        _ => Err(format!("Unknown job type '{}'", job_type)),
+    }
+}
+
+// Raise an admin alert for each certificate, by site id and path, that expires within the warning days. Certificates that
+// cannot be read fail the check
+fn check_certificates(certificates: &[(String, String)], warning_days: i64, now: DateTime<Utc>) -> Result<String, String> {
+    let mut expiring_count = 0;
+    let mut errors = Vec::new();
+    for (site_id, cert_path) in certificates {
+        match get_certificate_expiry(cert_path) {
+            Ok(expires_at) if expires_at <= now => {
+                expiring_count += 1;
+                add_admin_alert(
+                    "certificate_check",
+                    format!("The TLS certificate of site '{}' at '{}' expired on {}", site_id, cert_path, expires_at.to_rfc3339()),
+                );
+            }
+            Ok(expires_at) if expires_at - now <= chrono::Duration::days(warning_days) => {
+                expiring_count += 1;
+                add_admin_alert(
+                    "certificate_check",
+                    format!(
+                        "The TLS certificate of site '{}' at '{}' expires in {} days, on {}",
+                        site_id,
+                        cert_path,
+                        (expires_at - now).num_days(),
+                        expires_at.to_rfc3339()
+                    ),
+                );
+            }
+            Ok(_) => {}
+            Err(e) => errors.push(format!("Site '{}': {}", site_id, e)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(format!("Checked {} certificates, {} expire within {} days", certificates.len(), expiring_count, warning_days))
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+// Start the tasks that are due, and work out the next run of the others
+async fn run_due_tasks() {
+    let tasks = match tokio::task::spawn_blocking(list_scheduled_tasks).await {
+        Ok(Ok(tasks)) => tasks,
+        Ok(Err(e)) => {
+            error(format!("Failed to get scheduled tasks: {}", e));
+            return;
+        }
+        Err(e) => {
+            error(format!("Failed to get scheduled tasks: {}", e));
+            return;
+        }
+    };
+
+    let now = Utc::now();
+    let mut due_tasks = Vec::new();
+    {
+        let mut next_runs = NEXT_RUNS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        // Forget disabled and deleted tasks, so they start from now when enabled again
+        next_runs.retain(|id, _| tasks.iter().any(|task| task.id == *id && task.is_enabled));
+
+        for task in tasks.into_iter().filter(|task| task.is_enabled) {
+            let Ok(schedule) = CronSchedule::parse(&task.schedule) else {
+                continue;
+            };
+            match next_runs.get(&task.id) {
+                Some((task_schedule, next_run)) if *task_schedule == task.schedule && *next_run > now => {}
+                Some((task_schedule, _)) if *task_schedule == task.schedule => {
+                    match schedule.next_after(now) {
+                        Some(next_run) => next_runs.insert(task.id.clone(), (task.schedule.clone(), next_run)),
+                        None => next_runs.remove(&task.id),
+                    };
+                    due_tasks.push(task);
+                }
+                // New task, or its schedule changed
+                _ => {
+                    if let Some(next_run) = schedule.next_after(now) {
+                        next_runs.insert(task.id.clone(), (task.schedule.clone(), next_run));
+                    }
+                }
+            }
+        }
+    }
+
+    for task in due_tasks {
+        tokio::spawn(async move {
+            if run_scheduled_task(&task).await.is_none() {
+                info(format!("Scheduled task '{}' ({}) is still running, so this run is skipped", task.id, task.job_type));
+            }
+        });
+    }
+}
+
+/// Start running the scheduled tasks when they are due. It stops on shutdown or stop_services triggers, so it is started
+/// again on configuration reload.
+pub async fn start_scheduled_tasks() {
+    let triggers = get_trigger_handler();
+    let shutdown_token = triggers.get_token("shutdown").await.unwrap_or_else(CancellationToken::new);
+    let stop_services_token = triggers.get_token("stop_services").await.unwrap_or_else(CancellationToken::new);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(SCHEDULED_TASKS_CHECK_INTERVAL_SECS));
+        loop {
+            tokio::select! {
+                _ = shutdown_token.cancelled() => {
+                    debug("Scheduled tasks stopping due to shutdown signal");
+                    break;
+                }
+                _ = stop_services_token.cancelled() => {
+                    debug("Scheduled tasks stopping due to stop_services signal");
+                    break;
+                }
+                _ = interval.tick() => {
+                    trace("Checking for scheduled tasks that are due");
+                    run_due_tasks().await;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate_scheduled_task() {
+        let parameters = |value: Value| value.as_object().cloned().unwrap();
+
+        assert!(validate_scheduled_task("log_rotation", "0 3 * * *", &parameters(json!({ "max_size_mb": 50, "keep_files": 3 }))).is_ok());
+        assert!(validate_scheduled_task("database_backup", "@daily", &Map::new()).is_ok());
+
+        let errors = validate_scheduled_task("log_rotation", "0 3 * *", &parameters(json!({ "keep_files": 0, "warning_days": 5 }))).unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert!(validate_scheduled_task("defragment", "@daily", &Map::new()).is_err());
+        assert!(validate_scheduled_task("certificate_check", "@daily", &parameters(json!({ "warning_days": "14" }))).is_err());
+    }
+
+    // Alerts are logged, which needs the runtime
+    #[tokio::test]
+    async fn test_check_certificates() {
+        let directory = std::env::temp_dir().join(format!("gruxi-certificate-check-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let cert_path = directory.join("site.crt.pem");
+        let key_pair = rcgen::KeyPair::generate().unwrap();
+        let mut params = rcgen::CertificateParams::new(vec!["example.com".to_string()]).unwrap();
+        params.not_after = rcgen::date_time_ymd(2030, 1, 10);
+        std::fs::write(&cert_path, params.self_signed(&key_pair).unwrap().pem()).unwrap();
+        let certificates = vec![("site-a".to_string(), cert_path.to_string_lossy().to_string())];
+
+        let now = DateTime::parse_from_rfc3339("2029-12-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        assert_eq!(check_certificates(&certificates, 14, now).unwrap(), "Checked 1 certificates, 0 expire within 14 days");
+        assert_eq!(check_certificates(&certificates, 60, now).unwrap(), "Checked 1 certificates, 1 expire within 60 days");
+
+        let missing = vec![("site-b".to_string(), directory.join("missing.pem").to_string_lossy().to_string())];
+        assert!(check_certificates(&missing, 14, now).is_err());
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
@@ -0,0 +1,256 @@
+// ============================================================================
+// SITE HEALTH
+// ============================================================================
+//
+// Health of the sites, for the status page. A site is as healthy as the
+// upstream servers of its proxy processors, from the health checks of their
+// load balancers: "operational" when all of them are healthy, "degraded" when
+// some are down and "down" when all are. Sites without upstream servers are
+// served by Gruxi itself, so they are operational while it runs. A site whose
+// synthetic probe keeps failing is down, whatever its upstreams report.
+//
+// The health of each site is sampled every minute, keeping a day of samples,
+// so the status page can show how available each site was over the last 24
+// hours.
+// ============================================================================
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+
+use crate::configuration::cached_configuration::get_cached_configuration;
+use crate::configuration::configuration::Configuration;
+use crate::configuration::site::Site;
+use crate::core::synthetic_probes::is_synthetic_probe_failing;
+use crate::core::triggers::get_trigger_handler;
+use crate::http::request_handlers::processors::proxy_processor::ProxyProcessor;
+use crate::logging::syslog::{debug, trace};
+
+const SITE_HEALTH_SAMPLE_INTERVAL_SECS: u64 = 60;
+
+// A day of samples, one per minute
+const MAX_SITE_HEALTH_SAMPLES: usize = 24 * 60;
+
+#[derive(Debug, Clone)]
+struct UpstreamHealth {
+    is_healthy: bool,
+    checked_at: DateTime<Utc>,
+}
+
+// Keyed by the URI that was checked, which is the upstream server with the health check path appended
+static UPSTREAM_HEALTH: LazyLock<DashMap<String, UpstreamHealth>> = LazyLock::new(DashMap::new);
+
+// Whether each sample of a site found it available, oldest first
+static SITE_HEALTH_SAMPLES: LazyLock<Mutex<HashMap<String, VecDeque<bool>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SiteHealth {
+    pub site_id: String,
+    pub name: String,
+    pub status: String, // "operational", "degraded", "down" or "disabled"
+    pub upstreams_checked: usize,
+    pub upstreams_healthy: usize,
+    pub last_checked_at: Option<String>,
+    pub availability_percent: Option<f64>, // Share of the samples of the last 24 hours the site was available, None before the first sample
+}
+
+// Record the result of a health check of an upstream server, called by the load balancers
+pub fn record_upstream_health(checked_uri: &str, is_healthy: bool) {
+    UPSTREAM_HEALTH.insert(checked_uri.to_string(), UpstreamHealth { is_healthy, checked_at: Utc::now() });
+}
+
+// Whether the site is only bound to admin portal bindings, so it is the admin portal itself
+pub fn is_admin_portal_site(configuration: &Configuration, site: &Site) -> bool {
+    let binding_ids: Vec<&String> = configuration
+        .binding_sites
+        .iter()
+        .filter(|relation| relation.site_id == site.id)
+        .map(|relation| &relation.binding_id)
+        .collect();
+    !binding_ids.is_empty()
+        && binding_ids
+            .iter()
+            .all(|binding_id| configuration.bindings.iter().any(|binding| &binding.id == *binding_id && binding.is_admin))
+}
+
+// The proxy processors of the site, including the ones used by its locations
+pub fn get_site_proxy_processors<'a>(configuration: &'a Configuration, site: &Site) -> Vec<&'a ProxyProcessor> {
+    let request_handler_ids: HashSet<&String> = site
+        .request_handlers
+        .iter()
+        .chain(site.locations.iter().flat_map(|location| location.request_handlers.iter()))
+        .collect();
+    let processor_ids: HashSet<&String> = configuration
+        .request_handlers
+        .iter()
+        .filter(|handler| request_handler_ids.contains(&handler.id))
+        .map(|handler| &handler.processor_id)
+        .collect();
+    configuration.proxy_processors.iter().filter(|processor| processor_ids.contains(&processor.id)).collect()
+}
+
+// The URIs the load balancers check for the upstream servers of the proxy processors of the site
+fn get_site_health_check_uris(configuration: &Configuration, site: &Site) -> Vec<String> {
+    let checked_uris: HashSet<String> = get_site_proxy_processors(configuration, site)
+        .into_iter()
+        .flat_map(|processor| {
+            processor
+                .upstream_servers
+                .iter()
+                .chain(processor.upstream_groups.iter().flat_map(|group| group.upstream_servers.iter()))
+                .map(move |server| format!("{}{}", server, processor.health_check_path))
+        })
+        .collect();
+    checked_uris.into_iter().collect()
+}
+
+// The current health of the site, from the latest health checks of its upstream servers
+pub fn get_site_health(configuration: &Configuration, site: &Site) -> SiteHealth {
+    let mut upstreams_checked = 0;
+    let mut upstreams_healthy = 0;
+    let mut last_checked_at: Option<DateTime<Utc>> = None;
+    for checked_uri in get_site_health_check_uris(configuration, site) {
+        // Upstreams not checked yet, such as right after a reload, are left out until they are
+        let Some(health) = UPSTREAM_HEALTH.get(&checked_uri) else {
+            continue;
+        };
+        upstreams_checked += 1;
+        if health.is_healthy {
+            upstreams_healthy += 1;
+        }
+        last_checked_at = last_checked_at.max(Some(health.checked_at));
+    }
+
+    let status = if !site.is_enabled {
+        "disabled"
+    } else if is_synthetic_probe_failing(&site.id) {
+        "down"
+    } else if upstreams_healthy < upstreams_checked {
+        if upstreams_healthy == 0 { "down" } else { "degraded" }
+    } else {
+        "operational"
+    };
+
+    SiteHealth {
+        site_id: site.id.clone(),
+        name: site.hostnames.first().cloned().unwrap_or_else(|| site.id.clone()),
+        status: status.to_string(),
+        upstreams_checked,
+        upstreams_healthy,
+        last_checked_at: last_checked_at.map(|time| time.to_rfc3339()),
+        availability_percent: get_site_availability_percent(&site.id),
+    }
+}
+
+fn get_site_availability_percent(site_id: &str) -> Option<f64> {
+    let samples = SITE_HEALTH_SAMPLES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let site_samples = samples.get(site_id).filter(|site_samples| !site_samples.is_empty())?;
+    let available = site_samples.iter().filter(|is_available| **is_available).count();
+    Some(available as f64 * 100.0 / site_samples.len() as f64)
+}
+
+// Degraded sites still answer, so they count as available
+fn record_site_health_sample(site_id: &str, status: &str) {
+    let mut samples = SITE_HEALTH_SAMPLES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let site_samples = samples.entry(site_id.to_string()).or_default();
+    site_samples.push_back(status != "down");
+    while site_samples.len() > MAX_SITE_HEALTH_SAMPLES {
+        site_samples.pop_front();
+    }
+}
+
+async fn sample_site_health() {
+    let configuration = get_cached_configuration().get_configuration().await;
+    let site_ids: HashSet<&String> = configuration.sites.iter().map(|site| &site.id).collect();
+    for site in configuration.sites.iter().filter(|site| site.is_enabled) {
+        let health = get_site_health(&configuration, site);
+        record_site_health_sample(&site.id, &health.status);
+    }
+
+    // Forget the samples of removed sites
+    let mut samples = SITE_HEALTH_SAMPLES.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    samples.retain(|site_id, _| site_ids.contains(site_id));
+}
+
+pub async fn start_site_health_sampling() {
+    let triggers = get_trigger_handler();
+    let shutdown_token = triggers.get_token("shutdown").await.unwrap_or_else(CancellationToken::new);
+    let stop_services_token = triggers.get_token("stop_services").await.unwrap_or_else(CancellationToken::new);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(SITE_HEALTH_SAMPLE_INTERVAL_SECS));
+        loop {
+            tokio::select! {
+                _ = shutdown_token.cancelled() => {
+                    debug("Site health sampling stopping due to shutdown signal");
+                    break;
+                }
+                _ = stop_services_token.cancelled() => {
+                    debug("Site health sampling stopping due to stop_services signal");
+                    break;
+                }
+                _ = interval.tick() => {
+                    trace("Sampling the health of the sites");
+                    sample_site_health().await;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::request_handler::RequestHandler;
+    use crate::http::request_handlers::processors::proxy_processor::ProxyUpstreamGroup;
+
+    #[test]
+    fn test_site_health_from_upstream_health() {
+        let id = uuid::Uuid::new_v4();
+        let primary = format!("http://primary-{}:8080", id);
+        let canary = format!("http://canary-{}:8080", id);
+
+        let mut configuration = Configuration::new();
+        let mut processor = ProxyProcessor::new();
+        processor.upstream_servers = vec![primary.clone()];
+        processor.upstream_groups = vec![ProxyUpstreamGroup {
+            name: "canary".to_string(),
+            upstream_servers: vec![canary.clone()],
+            traffic_percent: 10,
+        }];
+        let mut handler = RequestHandler::new();
+        handler.processor_id = processor.id.clone();
+        let mut site = Site::new();
+        site.id = id.to_string();
+        site.is_enabled = true;
+        site.request_handlers = vec![handler.id.clone()];
+        configuration.proxy_processors.push(processor);
+        configuration.request_handlers.push(handler);
+
+        // Not checked yet
+        assert_eq!(get_site_health(&configuration, &site).status, "operational");
+
+        record_upstream_health(&format!("{}/health", primary), true);
+        record_upstream_health(&format!("{}/health", canary), false);
+        let health = get_site_health(&configuration, &site);
+        assert_eq!(health.status, "degraded");
+        assert_eq!((health.upstreams_checked, health.upstreams_healthy), (2, 1));
+
+        record_upstream_health(&format!("{}/health", primary), false);
+        assert_eq!(get_site_health(&configuration, &site).status, "down");
+
+        site.is_enabled = false;
+        assert_eq!(get_site_health(&configuration, &site).status, "disabled");
+
+        record_site_health_sample(&site.id, "operational");
+        record_site_health_sample(&site.id, "degraded");
+        record_site_health_sample(&site.id, "down");
+        record_site_health_sample(&site.id, "operational");
+        assert_eq!(get_site_availability_percent(&site.id), Some(75.0));
+    }
+}
@@ -0,0 +1,241 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use http_body_util::BodyExt;
+use hyper::{HeaderMap, Request, body::Bytes};
+use serde::Deserialize;
+
+use crate::{
+    configuration::{binding::Binding, cached_configuration::get_cached_configuration},
+    core::{running_state_manager::get_running_state_manager, triggers::get_trigger_handler},
+    http::{handle_request::handle_request, http_util::add_standard_headers_to_response, request_response::gruxi_request::GruxiRequest, site_match::site_matcher::find_best_match_site},
+};
+
+// A file with assertions for one site, such as:
+// {"hostname": "example.com", "tests": [{"name": "Home page", "path": "/", "expect": {"status": 200, "body_contains": ["Welcome"]}}]}
+#[derive(Debug, Deserialize)]
+pub struct SiteTestFile {
+    pub hostname: String, // Sent as the Host header, to select the site
+    #[serde(default)]
+    pub port: Option<u16>, // Only use bindings on this port, when the site is served on several bindings
+    pub tests: Vec<SiteTest>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SiteTest {
+    pub name: String,
+    #[serde(default = "default_method")]
+    pub method: String,
+    pub path: String, // Path and query, such as "/search?q=gruxi"
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: String,
+    pub expect: SiteTestExpectation,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SiteTestExpectation {
+    #[serde(default)]
+    pub status: Option<u16>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>, // An empty value only requires the header to be present
+    #[serde(default)]
+    pub body_contains: Vec<String>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+struct SiteTestResult {
+    name: String,
+    failures: Vec<String>,
+}
+
+// Run the site test files in the path (a file, or all .json files in a directory) against the current configuration.
+// Requests are handled in process, just as the server would, without listening on any ports. Results are written to
+// stdout in TAP format, and the returned exit code is 1 if any test failed
+pub async fn run_site_tests(path: &Path) -> i32 {
+    let test_files = match get_site_test_files(path) {
+        Ok(test_files) => test_files,
+        Err(e) => {
+            eprintln!("Failed to find site test files: {}", e);
+            return 1;
+        }
+    };
+
+    let mut results = Vec::new();
+    for test_file_path in &test_files {
+        let file_name = test_file_path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        match load_site_test_file(test_file_path) {
+            Ok(test_file) => {
+                for test in &test_file.tests {
+                    results.push(SiteTestResult {
+                        name: format!("{}: {}", file_name, test.name),
+                        failures: run_site_test(&test_file, test).await,
+                    });
+                }
+            }
+            Err(e) => results.push(SiteTestResult { name: file_name, failures: vec![e] }),
+        }
+    }
+
+    println!("TAP version 13");
+    println!("1..{}", results.len());
+    for (index, result) in results.iter().enumerate() {
+        if result.failures.is_empty() {
+            println!("ok {} - {}", index + 1, result.name);
+        } else {
+            println!("not ok {} - {}", index + 1, result.name);
+            println!("  ---");
+            for failure in &result.failures {
+                println!("  - {}", failure);
+            }
+            println!("  ...");
+        }
+    }
+
+    let failed = results.iter().filter(|r| !r.failures.is_empty()).count();
+    println!("# {} tests, {} passed, {} failed", results.len(), results.len() - failed, failed);
+
+    // Stop the external handlers started for the running state
+    get_trigger_handler().run_trigger("shutdown").await;
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
+    if failed > 0 { 1 } else { 0 }
+}
+
+fn get_site_test_files(path: &Path) -> Result<Vec<PathBuf>, String> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let entries = std::fs::read_dir(path).map_err(|e| format!("Failed to read directory {}: {}", path.display(), e))?;
+    let mut test_files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")))
+        .collect();
+    test_files.sort();
+    Ok(test_files)
+}
+
+fn load_site_test_file(path: &Path) -> Result<SiteTestFile, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("Failed to read site test file: {}", e))?;
+    serde_json::from_str(&contents).map_err(|e| format!("Failed to parse site test file: {}", e))
+}
+
+async fn run_site_test(test_file: &SiteTestFile, test: &SiteTest) -> Vec<String> {
+    let binding = match find_binding_for_hostname(&test_file.hostname, test_file.port).await {
+        Some(binding) => binding,
+        None => return vec![format!("No binding serves a site for hostname '{}'", test_file.hostname)],
+    };
+
+    let mut request_builder = Request::builder()
+        .method(test.method.as_str())
+        .uri(test.path.as_str())
+        .header(hyper::header::HOST, test_file.hostname.as_str());
+    for (name, value) in &test.headers {
+        request_builder = request_builder.header(name.as_str(), value.as_str());
+    }
+    let request = match request_builder.body(Bytes::from(test.body.clone())) {
+        Ok(request) => request,
+        Err(e) => return vec![format!("Invalid request: {}", e)],
+    };
+
+    let mut gruxi_request = GruxiRequest::new(request);
+    gruxi_request.add_calculated_data("remote_ip", "127.0.0.1");
+
+    let mut response = match handle_request(gruxi_request, binding).await {
+        Ok(response) => response,
+        Err(e) => return vec![format!("Request failed: {:?}", e)],
+    };
+    add_standard_headers_to_response(&mut response);
+
+    let hyper_response = response.into_hyper();
+    let status = hyper_response.status().as_u16();
+    let headers = hyper_response.headers().clone();
+    let body = match hyper_response.into_body().collect().await {
+        Ok(collected) => String::from_utf8_lossy(&collected.to_bytes()).to_string(),
+        Err(e) => return vec![format!("Failed to read response body: {:?}", e)],
+    };
+
+    check_expectation(&test.expect, status, &headers, &body)
+}
+
+// The first non-admin binding (on the given port) with a site for the hostname, just as a request to it would be served
+async fn find_binding_for_hostname(hostname: &str, port: Option<u16>) -> Option<Binding> {
+    let bindings = get_cached_configuration().get_configuration().await.bindings.clone();
+    let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+    let binding_site_cache = running_state.get_binding_site_cache();
+
+    bindings
+        .into_iter()
+        .filter(|binding| !binding.is_admin && port.is_none_or(|port| binding.port == port))
+        .find(|binding| find_best_match_site(&binding_site_cache.get_sites_for_binding(&binding.id), hostname, binding).is_some())
+}
+
+fn check_expectation(expect: &SiteTestExpectation, status: u16, headers: &HeaderMap, body: &str) -> Vec<String> {
+    let mut failures = Vec::new();
+
+    if let Some(expected_status) = expect.status
+        && expected_status != status
+    {
+        failures.push(format!("Expected status {}, got {}", expected_status, status));
+    }
+
+    for (name, expected_value) in &expect.headers {
+        match headers.get(name.as_str()).map(|v| v.to_str().unwrap_or("")) {
+            None => failures.push(format!("Expected header '{}', but it is missing", name)),
+            Some(value) if !expected_value.is_empty() && value != expected_value => {
+                failures.push(format!("Expected header '{}' to be '{}', got '{}'", name, expected_value, value));
+            }
+            Some(_) => {}
+        }
+    }
+
+    for expected_text in &expect.body_contains {
+        if !body.contains(expected_text.as_str()) {
+            failures.push(format!("Expected body to contain '{}'", expected_text));
+        }
+    }
+
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_site_test_file_parsing_uses_defaults() {
+        let test_file: SiteTestFile = serde_json::from_str(r#"{"hostname": "example.com", "tests": [{"name": "Home", "path": "/", "expect": {"status": 200}}]}"#).unwrap();
+        assert_eq!(test_file.port, None);
+        assert_eq!(test_file.tests[0].method, "GET");
+        assert!(test_file.tests[0].headers.is_empty());
+        assert_eq!(test_file.tests[0].expect.status, Some(200));
+    }
+
+    #[test]
+    fn test_site_test_check_expectation() {
+        let mut headers = HeaderMap::new();
+        headers.insert("Content-Type", "text/html".parse().unwrap());
+
+        let mut expect = SiteTestExpectation {
+            status: Some(200),
+            ..Default::default()
+        };
+        expect.headers.insert("Content-Type".to_string(), "text/html".to_string());
+        expect.headers.insert("Server".to_string(), String::new());
+        expect.body_contains.push("Welcome".to_string());
+
+        let failures = check_expectation(&expect, 200, &headers, "<h1>Welcome</h1>");
+        assert_eq!(failures, vec!["Expected header 'Server', but it is missing".to_string()]);
+
+        headers.insert("Server", "Gruxi".parse().unwrap());
+        assert!(check_expectation(&expect, 200, &headers, "<h1>Welcome</h1>").is_empty());
+        assert_eq!(check_expectation(&expect, 404, &headers, "Not found").len(), 2);
+    }
+}
@@ -0,0 +1,412 @@
+// ============================================================================
+// SPEEDTEST
+// ============================================================================
+//
+// Benchmark mode, started with "--opmode SPEEDTEST". Instead of the stored
+// configuration, Gruxi serves a speedtest configuration on a loopback port: a
+// static site with a small file, and a proxy site in front of a loopback echo
+// server, with the core settings of the stored configuration. A load generator
+// in the same process then runs each scenario for a fixed time over keep-alive
+// connections, and prints the throughput and latency of each, so performance
+// regressions show up when comparing builds on the same machine.
+// ============================================================================
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::service::service_fn;
+use hyper::{Request, Response};
+use hyper_util::rt::TokioIo;
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::configuration::binding::Binding;
+use crate::configuration::binding_site_relation::BindingSiteRelationship;
+use crate::configuration::cached_configuration::get_cached_configuration;
+use crate::configuration::configuration::Configuration;
+use crate::configuration::request_handler::RequestHandler;
+use crate::configuration::site::Site;
+use crate::core::running_state_manager::get_running_state_manager;
+use crate::core::triggers::get_trigger_handler;
+use crate::http::http_server::start_server_binding;
+use crate::http::request_handlers::processor_trait::ProcessorTrait;
+use crate::http::request_handlers::processors::proxy_processor::ProxyProcessor;
+use crate::http::request_handlers::processors::static_files_processor::StaticFileProcessor;
+
+// "static" reads the small file from disk on each request, "cached" serves it from the file cache and "proxy" posts
+// a small body through the proxy processor to the echo server
+pub const SPEEDTEST_SCENARIOS: [&str; 3] = ["static", "cached", "proxy"];
+
+const SMALL_FILE_NAME: &str = "small.txt";
+const SMALL_FILE_SIZE: usize = 1024;
+const ECHO_BODY_SIZE: usize = 1024;
+const STATIC_HOSTNAME: &str = "static.speedtest";
+const PROXY_HOSTNAME: &str = "proxy.speedtest";
+
+#[derive(Debug, Clone)]
+pub struct SpeedtestSettings {
+    pub scenarios: Vec<String>,
+    pub duration_seconds: u64,
+    pub connections: usize,
+}
+
+#[derive(Debug)]
+pub struct ScenarioReport {
+    pub name: String,
+    pub requests: u64,
+    pub errors: u64, // Failed requests and responses with a status of 400 or above
+    pub bytes: u64,  // Response body bytes received
+    pub elapsed: Duration,
+    pub latencies_micros: Vec<u64>, // Sorted
+}
+
+impl ScenarioReport {
+    pub fn get_requests_per_second(&self) -> f64 {
+        if self.elapsed.is_zero() { 0.0 } else { self.requests as f64 / self.elapsed.as_secs_f64() }
+    }
+
+    pub fn get_megabytes_per_second(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            0.0
+        } else {
+            self.bytes as f64 / (1024.0 * 1024.0) / self.elapsed.as_secs_f64()
+        }
+    }
+
+    // Latency at a percentile, such as 99.0, in microseconds
+    pub fn get_latency_percentile(&self, percentile: f64) -> u64 {
+        if self.latencies_micros.is_empty() {
+            return 0;
+        }
+        let index = ((percentile / 100.0) * (self.latencies_micros.len() - 1) as f64).round() as usize;
+        self.latencies_micros[index.min(self.latencies_micros.len() - 1)]
+    }
+}
+
+// Run the scenarios and print the report. Returns the exit code, which is 1 if a scenario could not be run or had errors
+pub async fn run_speedtest(settings: &SpeedtestSettings) -> i32 {
+    let fixture_directory = std::env::temp_dir().join(format!("gruxi-speedtest-{}", std::process::id()));
+    let result = run_scenarios(settings, &fixture_directory).await;
+    let _ = std::fs::remove_dir_all(&fixture_directory);
+
+    // Stop the listeners and handlers of the running state
+    get_trigger_handler().run_trigger("shutdown").await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    match result {
+        Ok(reports) => {
+            println!("{}", format_report(settings, &reports));
+            if reports.iter().any(|report| report.errors > 0 || report.requests == 0) { 1 } else { 0 }
+        }
+        Err(e) => {
+            eprintln!("Speedtest failed: {}", e);
+            1
+        }
+    }
+}
+
+async fn run_scenarios(settings: &SpeedtestSettings, fixture_directory: &Path) -> Result<Vec<ScenarioReport>, String> {
+    if let Some(unknown) = settings.scenarios.iter().find(|scenario| !SPEEDTEST_SCENARIOS.contains(&scenario.as_str())) {
+        return Err(format!("Unknown scenario '{}', the scenarios are: {}", unknown, SPEEDTEST_SCENARIOS.join(", ")));
+    }
+
+    std::fs::create_dir_all(fixture_directory).map_err(|e| format!("Failed to create {}: {}", fixture_directory.display(), e))?;
+    std::fs::write(fixture_directory.join(SMALL_FILE_NAME), vec![b'x'; SMALL_FILE_SIZE]).map_err(|e| format!("Failed to write the small file: {}", e))?;
+
+    let echo_addr = start_echo_server().await?;
+    let gruxi_addr = get_free_loopback_addr().await?;
+
+    let mut reports = Vec::new();
+    let mut is_first_scenario = true;
+    for scenario in &settings.scenarios {
+        // The running state is rebuilt for each scenario, as the file cache is only enabled for "cached"
+        let binding = set_speedtest_configuration(fixture_directory, gruxi_addr.port(), echo_addr, scenario == "cached").await;
+        if is_first_scenario {
+            get_running_state_manager().await;
+            is_first_scenario = false;
+        } else {
+            get_running_state_manager().await.set_new_running_state().await;
+        }
+        tokio::spawn(start_server_binding(binding));
+        wait_for_listener(gruxi_addr).await?;
+
+        println!("Running scenario '{}' for {} seconds with {} connections", scenario, settings.duration_seconds, settings.connections);
+        reports.push(run_scenario(scenario, gruxi_addr, settings).await);
+    }
+    Ok(reports)
+}
+
+// Replace the cached configuration with the speedtest configuration. It is never saved, so the stored configuration is
+// untouched. Returns the binding the sites are served on
+async fn set_speedtest_configuration(fixture_directory: &Path, port: u16, echo_addr: SocketAddr, is_file_cache_enabled: bool) -> Binding {
+    let cached_configuration = get_cached_configuration();
+    let mut configuration = cached_configuration.configuration.write().await;
+
+    let mut speedtest_configuration = Configuration::new();
+    speedtest_configuration.core = configuration.core.clone();
+    speedtest_configuration.core.file_cache.is_enabled = is_file_cache_enabled;
+
+    let mut binding = Binding::new();
+    binding.ip = "127.0.0.1".to_string();
+    binding.port = port;
+    binding.is_admin = false;
+    binding.is_tls = false;
+
+    let static_processor = StaticFileProcessor::new(fixture_directory.to_string_lossy().to_string(), vec![SMALL_FILE_NAME.to_string()]);
+    let mut proxy_processor = ProxyProcessor::new();
+    proxy_processor.upstream_servers = vec![format!("http://{}", echo_addr)];
+    proxy_processor.health_check_path = String::new();
+
+    for (hostname, processor_type, processor_id) in [(STATIC_HOSTNAME, "static", static_processor.id.clone()), (PROXY_HOSTNAME, "proxy", proxy_processor.id.clone())] {
+        let mut request_handler = RequestHandler::new();
+        request_handler.name = format!("Speedtest {}", processor_type);
+        request_handler.processor_type = processor_type.to_string();
+        request_handler.processor_id = processor_id;
+
+        let mut site = Site::new();
+        site.hostnames = vec![hostname.to_string()];
+        site.request_handlers = vec![request_handler.id.clone()];
+        speedtest_configuration.binding_sites.push(BindingSiteRelationship {
+            binding_id: binding.id.clone(),
+            site_id: site.id.clone(),
+        });
+        speedtest_configuration.sites.push(site);
+        speedtest_configuration.request_handlers.push(request_handler);
+    }
+    speedtest_configuration.static_file_processors.push(static_processor);
+    speedtest_configuration.proxy_processors.push(proxy_processor);
+    speedtest_configuration.bindings.push(binding.clone());
+    speedtest_configuration.sanitize();
+    speedtest_configuration.static_file_processors.iter_mut().for_each(|processor| processor.initialize());
+    speedtest_configuration.proxy_processors.iter_mut().for_each(|processor| processor.initialize());
+
+    *configuration = speedtest_configuration;
+    binding
+}
+
+async fn get_free_loopback_addr() -> Result<SocketAddr, String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.map_err(|e| format!("Failed to find a free loopback port: {}", e))?;
+    listener.local_addr().map_err(|e| format!("Failed to find a free loopback port: {}", e))
+}
+
+async fn wait_for_listener(addr: SocketAddr) -> Result<(), String> {
+    let started = Instant::now();
+    while started.elapsed() < Duration::from_secs(10) {
+        if TcpStream::connect(addr).await.is_ok() {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    Err(format!("Gruxi did not start listening on {}", addr))
+}
+
+// Upstream for the proxy scenario, answering each request with its body
+async fn start_echo_server() -> Result<SocketAddr, String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.map_err(|e| format!("Failed to start the echo server: {}", e))?;
+    let addr = listener.local_addr().map_err(|e| format!("Failed to start the echo server: {}", e))?;
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let service = service_fn(|request: Request<Incoming>| async move {
+                    let body = request.into_body().collect().await.map(|collected| collected.to_bytes()).unwrap_or_default();
+                    Ok::<_, hyper::Error>(Response::new(Full::new(body)))
+                });
+                let _ = hyper::server::conn::http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+            });
+        }
+    });
+    Ok(addr)
+}
+
+struct WorkerResult {
+    requests: u64,
+    errors: u64,
+    bytes: u64,
+    latencies_micros: Vec<u64>,
+}
+
+async fn run_scenario(scenario: &str, addr: SocketAddr, settings: &SpeedtestSettings) -> ScenarioReport {
+    // One request first, so the connections do not all start with a cold cache
+    let _ = run_worker(scenario.to_string(), addr, Instant::now() + Duration::from_millis(1)).await;
+
+    let started = Instant::now();
+    let deadline = started + Duration::from_secs(settings.duration_seconds);
+    let workers: Vec<_> = (0..settings.connections.max(1)).map(|_| tokio::spawn(run_worker(scenario.to_string(), addr, deadline))).collect();
+
+    let mut report = ScenarioReport {
+        name: scenario.to_string(),
+        requests: 0,
+        errors: 0,
+        bytes: 0,
+        elapsed: Duration::ZERO,
+        latencies_micros: Vec::new(),
+    };
+    for worker in workers {
+        if let Ok(result) = worker.await {
+            report.requests += result.requests;
+            report.errors += result.errors;
+            report.bytes += result.bytes;
+            report.latencies_micros.extend(result.latencies_micros);
+        }
+    }
+    report.elapsed = started.elapsed();
+    report.latencies_micros.sort_unstable();
+    report
+}
+
+fn build_scenario_request(scenario: &str) -> Request<Full<Bytes>> {
+    let builder = Request::builder().header(hyper::header::ACCEPT_ENCODING, "identity");
+    let request = match scenario {
+        "proxy" => builder
+            .method("POST")
+            .uri("/echo")
+            .header(hyper::header::HOST, PROXY_HOSTNAME)
+            .body(Full::new(Bytes::from(vec![b'x'; ECHO_BODY_SIZE]))),
+        _ => builder
+            .method("GET")
+            .uri(format!("/{}", SMALL_FILE_NAME))
+            .header(hyper::header::HOST, STATIC_HOSTNAME)
+            .body(Full::new(Bytes::new())),
+    };
+    request.unwrap_or_default()
+}
+
+// Send requests over one keep-alive connection until the deadline, connecting again when the connection is lost
+async fn run_worker(scenario: String, addr: SocketAddr, deadline: Instant) -> WorkerResult {
+    let mut result = WorkerResult {
+        requests: 0,
+        errors: 0,
+        bytes: 0,
+        latencies_micros: Vec::new(),
+    };
+    let mut sender = None;
+
+    while result.requests == 0 || Instant::now() < deadline {
+        if sender.is_none() {
+            sender = connect(addr).await;
+            if sender.is_none() {
+                result.requests += 1;
+                result.errors += 1;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                continue;
+            }
+        }
+        let Some(request_sender) = sender.as_mut() else {
+            continue;
+        };
+
+        let started = Instant::now();
+        let response = match request_sender.send_request(build_scenario_request(&scenario)).await {
+            Ok(response) => response,
+            Err(_) => {
+                result.requests += 1;
+                result.errors += 1;
+                sender = None;
+                continue;
+            }
+        };
+        let is_error = response.status().as_u16() >= 400;
+        let body_size = response.into_body().collect().await.map(|collected| collected.to_bytes().len() as u64);
+
+        result.requests += 1;
+        result.latencies_micros.push(started.elapsed().as_micros() as u64);
+        match body_size {
+            Ok(body_size) if !is_error => result.bytes += body_size,
+            Ok(_) => result.errors += 1,
+            Err(_) => {
+                result.errors += 1;
+                sender = None;
+            }
+        }
+    }
+    result
+}
+
+async fn connect(addr: SocketAddr) -> Option<hyper::client::conn::http1::SendRequest<Full<Bytes>>> {
+    let stream = TcpStream::connect(addr).await.ok()?;
+    let _ = stream.set_nodelay(true);
+    let (sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await.ok()?;
+    tokio::spawn(connection);
+    Some(sender)
+}
+
+pub fn format_report(settings: &SpeedtestSettings, reports: &[ScenarioReport]) -> String {
+    let mut lines = vec![
+        String::new(),
+        format!(
+            "Gruxi {} speedtest, {} connections, {} seconds per scenario",
+            env!("CARGO_PKG_VERSION"),
+            settings.connections,
+            settings.duration_seconds
+        ),
+        format!(
+            "{:<10} {:>10} {:>8} {:>12} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "scenario", "requests", "errors", "req/s", "MB/s", "p50 ms", "p90 ms", "p99 ms", "max ms"
+        ),
+    ];
+    for report in reports {
+        let max_micros = report.latencies_micros.last().copied().unwrap_or(0);
+        lines.push(format!(
+            "{:<10} {:>10} {:>8} {:>12.1} {:>10.2} {:>10.3} {:>10.3} {:>10.3} {:>10.3}",
+            report.name,
+            report.requests,
+            report.errors,
+            report.get_requests_per_second(),
+            report.get_megabytes_per_second(),
+            report.get_latency_percentile(50.0) as f64 / 1000.0,
+            report.get_latency_percentile(90.0) as f64 / 1000.0,
+            report.get_latency_percentile(99.0) as f64 / 1000.0,
+            max_micros as f64 / 1000.0
+        ));
+    }
+    lines.join("\n")
+}
+
+// The default scenarios, in the order they are run
+pub fn get_default_speedtest_scenarios() -> Vec<String> {
+    SPEEDTEST_SCENARIOS.iter().map(|scenario| scenario.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report_with_latencies(latencies_micros: Vec<u64>) -> ScenarioReport {
+        ScenarioReport {
+            name: "static".to_string(),
+            requests: latencies_micros.len() as u64,
+            errors: 0,
+            bytes: 2 * 1024 * 1024,
+            elapsed: Duration::from_secs(2),
+            latencies_micros,
+        }
+    }
+
+    #[test]
+    fn test_scenario_report() {
+        let report = report_with_latencies((1..=100).collect());
+        assert_eq!(report.get_requests_per_second(), 50.0);
+        assert_eq!(report.get_megabytes_per_second(), 1.0);
+        assert_eq!(report.get_latency_percentile(50.0), 51);
+        assert_eq!(report.get_latency_percentile(99.0), 99);
+        assert_eq!(report.get_latency_percentile(100.0), 100);
+        assert_eq!(report_with_latencies(vec![]).get_latency_percentile(99.0), 0);
+    }
+
+    #[test]
+    fn test_format_report() {
+        let settings = SpeedtestSettings {
+            scenarios: get_default_speedtest_scenarios(),
+            duration_seconds: 2,
+            connections: 8,
+        };
+        let report = format_report(&settings, &[report_with_latencies(vec![1500, 2500])]);
+        let row = report.lines().last().unwrap();
+        assert!(row.starts_with("static"));
+        assert!(row.contains("2.500"), "{}", row);
+        assert!(report.contains("8 connections"));
+    }
+}
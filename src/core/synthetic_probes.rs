@@ -0,0 +1,422 @@
+// ============================================================================
+// SYNTHETIC PROBES
+// ============================================================================
+//
+// Requests a URL of each site with a synthetic probe at its interval, as a
+// visitor would, recording the latency and status of each probe. In
+// "full_stack" mode the site is requested in process through one of its
+// bindings, so the probe goes through the middleware and request handlers. In
+// "upstreams" mode each upstream server of its proxy processors is requested
+// directly, to tell which of them fails.
+//
+// A day of probes is kept per site for the admin API. When a probe has failed
+// as many times in a row as the failure threshold of the site, an admin alert
+// is raised, once until the probe succeeds again.
+// ============================================================================
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use http_body_util::BodyExt;
+use hyper::{Request, body::Bytes};
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+
+use crate::configuration::cached_configuration::get_cached_configuration;
+use crate::configuration::site::Site;
+use crate::configuration::synthetic_probe_settings::SyntheticProbeSettings;
+use crate::core::admin_alerts::add_admin_alert;
+use crate::core::running_state_manager::get_running_state_manager;
+use crate::core::site_health::get_site_proxy_processors;
+use crate::core::triggers::get_trigger_handler;
+use crate::http::cache_warmer::find_binding_and_hostname;
+use crate::http::handle_request::handle_request;
+use crate::http::request_response::gruxi_request::GruxiRequest;
+use crate::logging::syslog::{debug, info, trace};
+
+// How often the probes are checked for being due, so intervals are kept to within this
+const SYNTHETIC_PROBES_CHECK_INTERVAL_SECS: u64 = 5;
+const SYNTHETIC_PROBE_USER_AGENT: &str = "Gruxi-Synthetic-Probe";
+
+// A day of probes at the default interval of a minute
+const MAX_PROBE_RUNS: usize = 24 * 60;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeTargetResult {
+    pub target: String, // The URL requested, or the upstream server
+    pub status: u16,    // 0 when the request failed or timed out
+    pub latency_ms: u64,
+    pub is_success: bool,
+    pub error: Option<String>,
+}
+
+// One probe of a site, with a result for the site or for each of its upstream servers
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeRun {
+    pub checked_at: String,
+    pub is_success: bool,
+    pub results: Vec<ProbeTargetResult>,
+}
+
+#[derive(Debug, Default)]
+struct SiteProbeState {
+    runs: VecDeque<ProbeRun>, // Oldest first
+    consecutive_failures: u32,
+    is_alerting: bool, // The failure threshold was reached, and the probe has not succeeded since
+    last_started: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeSummary {
+    pub site_id: String,
+    pub name: String,
+    pub is_enabled: bool,
+    pub mode: String,
+    pub path: String,
+    pub consecutive_failures: u32,
+    pub is_failing: bool,
+    pub success_percent: Option<f64>, // Over the kept probes, None before the first one
+    pub average_latency_ms: Option<u64>,
+    pub last_run: Option<ProbeRun>,
+}
+
+static PROBE_STATES: LazyLock<DashMap<String, SiteProbeState>> = LazyLock::new(DashMap::new);
+
+// Sites whose probe is running, so a slow probe is not started again before it is done
+static RUNNING_PROBES: LazyLock<Mutex<HashSet<String>>> = LazyLock::new(|| Mutex::new(HashSet::new()));
+
+// Whether the probe of the site has reached its failure threshold and not succeeded since
+pub fn is_synthetic_probe_failing(site_id: &str) -> bool {
+    PROBE_STATES.get(site_id).is_some_and(|state| state.is_alerting)
+}
+
+pub fn get_probe_summary(site: &Site) -> ProbeSummary {
+    let state = PROBE_STATES.get(&site.id);
+    let runs = state.as_ref().map(|state| &state.runs);
+    let run_count = runs.map_or(0, |runs| runs.len());
+    let latencies: Vec<u64> = runs
+        .into_iter()
+        .flatten()
+        .flat_map(|run| run.results.iter())
+        .filter(|result| result.status > 0)
+        .map(|result| result.latency_ms)
+        .collect();
+
+    ProbeSummary {
+        site_id: site.id.clone(),
+        name: site.hostnames.first().cloned().unwrap_or_else(|| site.id.clone()),
+        is_enabled: site.synthetic_probe.is_enabled,
+        mode: site.synthetic_probe.mode.clone(),
+        path: site.synthetic_probe.path.clone(),
+        consecutive_failures: state.as_ref().map_or(0, |state| state.consecutive_failures),
+        is_failing: state.as_ref().is_some_and(|state| state.is_alerting),
+        success_percent: (run_count > 0).then(|| runs.into_iter().flatten().filter(|run| run.is_success).count() as f64 * 100.0 / run_count as f64),
+        average_latency_ms: (!latencies.is_empty()).then(|| latencies.iter().sum::<u64>() / latencies.len() as u64),
+        last_run: runs.and_then(|runs| runs.back().cloned()),
+    }
+}
+
+// The kept probes of the site, newest first
+pub fn get_probe_runs(site_id: &str, limit: usize) -> Vec<ProbeRun> {
+    PROBE_STATES.get(site_id).map(|state| state.runs.iter().rev().take(limit).cloned().collect()).unwrap_or_default()
+}
+
+// Keep the probe, and raise an alert when it has failed as many times in a row as the threshold
+fn record_probe_run(site: &Site, run: ProbeRun) {
+    let site_name = site.hostnames.first().cloned().unwrap_or_else(|| site.id.clone());
+    let mut state = PROBE_STATES.entry(site.id.clone()).or_default();
+
+    if run.is_success {
+        if state.is_alerting {
+            info(format!("Synthetic probe of site '{}' succeeded again, after {} failures", site_name, state.consecutive_failures));
+        }
+        state.consecutive_failures = 0;
+        state.is_alerting = false;
+    } else {
+        state.consecutive_failures += 1;
+        if !state.is_alerting && state.consecutive_failures >= site.synthetic_probe.failure_threshold {
+            state.is_alerting = true;
+            let failures: Vec<String> = run
+                .results
+                .iter()
+                .filter(|result| !result.is_success)
+                .map(|result| format!("{}: {}", result.target, result.error.clone().unwrap_or_else(|| format!("status {}", result.status))))
+                .collect();
+            add_admin_alert(
+                "synthetic_probe",
+                format!("Synthetic probe of site '{}' failed {} times in a row - {}", site_name, state.consecutive_failures, failures.join(", ")),
+            );
+        }
+    }
+
+    state.runs.push_back(run);
+    while state.runs.len() > MAX_PROBE_RUNS {
+        state.runs.pop_front();
+    }
+}
+
+// Probe the site now, keep the result and return it
+pub async fn run_synthetic_probe(site: &Site) -> ProbeRun {
+    let settings = &site.synthetic_probe;
+    if let Some(mut state) = PROBE_STATES.get_mut(&site.id) {
+        state.last_started = Some(Instant::now());
+    } else {
+        PROBE_STATES.insert(
+            site.id.clone(),
+            SiteProbeState {
+                last_started: Some(Instant::now()),
+                ..Default::default()
+            },
+        );
+    }
+
+    let checked_at = chrono::Utc::now().to_rfc3339();
+    let results = if settings.mode == "upstreams" {
+        probe_upstreams(site).await
+    } else {
+        vec![probe_full_stack(site).await]
+    };
+    let is_success = results.iter().all(|result| result.is_success);
+    let run = ProbeRun { checked_at, is_success, results };
+    trace(format!("Synthetic probe of site '{}': {:?}", site.id, run));
+    record_probe_run(site, run.clone());
+    run
+}
+
+async fn probe_full_stack(site: &Site) -> ProbeTargetResult {
+    let settings = &site.synthetic_probe;
+    let (binding, hostname) = match find_binding_and_hostname(site).await {
+        Ok(result) => result,
+        Err(e) => return failed_result(&settings.path, 0, e),
+    };
+    let target = format!("{}://{}{}", if binding.is_tls { "https" } else { "http" }, hostname, settings.path);
+
+    let request = Request::builder()
+        .method("GET")
+        .uri(&settings.path)
+        .header(hyper::header::HOST, &hostname)
+        .header(hyper::header::USER_AGENT, SYNTHETIC_PROBE_USER_AGENT)
+        .body(Bytes::new());
+    let request = match request {
+        Ok(request) => request,
+        Err(e) => return failed_result(&target, 0, format!("Invalid request: {}", e)),
+    };
+    let mut gruxi_request = GruxiRequest::new(request);
+    gruxi_request.add_calculated_data("remote_ip", "127.0.0.1");
+
+    let started = Instant::now();
+    let result = tokio::time::timeout(Duration::from_secs(settings.timeout_seconds as u64), async {
+        // Boxed, as a probe can be run from the admin API, which is itself served by handle_request
+        let response = Box::pin(handle_request(gruxi_request, binding)).await.map_err(|e| format!("{:?}", e))?.into_hyper();
+        let status = response.status().as_u16();
+        // The whole body is read, as a response that breaks off is a failure too
+        response.into_body().collect().await.map_err(|e| format!("Failed to read the response: {}", e))?;
+        Ok::<u16, String>(status)
+    })
+    .await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+
+    match result {
+        Ok(Ok(status)) => status_result(settings, &target, status, latency_ms),
+        Ok(Err(e)) => failed_result(&target, latency_ms, e),
+        Err(_) => failed_result(&target, latency_ms, format!("Timed out after {} seconds", settings.timeout_seconds)),
+    }
+}
+
+async fn probe_upstreams(site: &Site) -> Vec<ProbeTargetResult> {
+    let settings = &site.synthetic_probe;
+    // The servers with the client settings of their processor, so the probes are sent as the proxied requests are
+    let upstreams: Vec<(String, _)> = {
+        let configuration = get_cached_configuration().get_configuration().await;
+        get_site_proxy_processors(&configuration, site)
+            .into_iter()
+            .flat_map(|processor| {
+                let client_settings = processor.get_upstream_client_settings();
+                processor
+                    .upstream_servers
+                    .iter()
+                    .chain(processor.upstream_groups.iter().flat_map(|group| group.upstream_servers.iter()))
+                    .map(move |server| (server.clone(), client_settings.clone()))
+            })
+            .collect()
+    };
+
+    // A site without upstream servers fails, as probing its upstreams is a mistake
+    if upstreams.is_empty() {
+        return vec![failed_result(&settings.path, 0, "The site has no upstream servers to probe".to_string())];
+    }
+
+    let mut results = Vec::new();
+    for (server, client_settings) in upstreams {
+        let target = format!("{}{}", server, settings.path);
+        let uri: hyper::Uri = match target.parse() {
+            Ok(uri) => uri,
+            Err(e) => {
+                results.push(failed_result(&target, 0, format!("Invalid URL: {}", e)));
+                continue;
+            }
+        };
+        // The client is taken from the running state, which is not kept locked while the request runs
+        let client = {
+            let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+            running_state.get_http_client().get_upstream_client(&client_settings)
+        };
+        let client = match client {
+            Ok(client) => client,
+            Err(e) => {
+                results.push(failed_result(&target, 0, format!("Could not set up the client: {}", e)));
+                continue;
+            }
+        };
+
+        let started = Instant::now();
+        let result = tokio::time::timeout(Duration::from_secs(settings.timeout_seconds as u64), async {
+            let response = client.get(uri).await.map_err(|e| format!("Request failed: {}", e))?;
+            let status = response.status().as_u16();
+            response.into_body().collect().await.map_err(|e| format!("Failed to read the response: {}", e))?;
+            Ok::<u16, String>(status)
+        })
+        .await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        results.push(match result {
+            Ok(Ok(status)) => status_result(settings, &target, status, latency_ms),
+            Ok(Err(e)) => failed_result(&target, latency_ms, e),
+            Err(_) => failed_result(&target, latency_ms, format!("Timed out after {} seconds", settings.timeout_seconds)),
+        });
+    }
+    results
+}
+
+fn status_result(settings: &SyntheticProbeSettings, target: &str, status: u16, latency_ms: u64) -> ProbeTargetResult {
+    let is_success = settings.is_expected_status(status);
+    ProbeTargetResult {
+        target: target.to_string(),
+        status,
+        latency_ms,
+        is_success,
+        error: (!is_success).then(|| format!("Unexpected status {}", status)),
+    }
+}
+
+fn failed_result(target: &str, latency_ms: u64, error: String) -> ProbeTargetResult {
+    ProbeTargetResult {
+        target: target.to_string(),
+        status: 0,
+        latency_ms,
+        is_success: false,
+        error: Some(error),
+    }
+}
+
+// Start the probes that are due, each in the background
+async fn run_due_probes() {
+    let sites: Vec<Site> = {
+        let configuration = get_cached_configuration().get_configuration().await;
+        // Forget the probes of removed sites
+        let site_ids: HashSet<&String> = configuration.sites.iter().map(|site| &site.id).collect();
+        PROBE_STATES.retain(|site_id, _| site_ids.contains(site_id));
+        configuration.sites.iter().filter(|site| site.is_enabled && site.synthetic_probe.is_enabled).cloned().collect()
+    };
+
+    for site in sites {
+        let interval = Duration::from_secs(site.synthetic_probe.interval_seconds as u64);
+        let is_due = PROBE_STATES
+            .get(&site.id)
+            .and_then(|state| state.last_started)
+            .is_none_or(|last_started| last_started.elapsed() >= interval);
+        if !is_due || !RUNNING_PROBES.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).insert(site.id.clone()) {
+            continue;
+        }
+        tokio::spawn(async move {
+            run_synthetic_probe(&site).await;
+            RUNNING_PROBES.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(&site.id);
+        });
+    }
+}
+
+pub async fn start_synthetic_probes() {
+    let triggers = get_trigger_handler();
+    let shutdown_token = triggers.get_token("shutdown").await.unwrap_or_else(CancellationToken::new);
+    let stop_services_token = triggers.get_token("stop_services").await.unwrap_or_else(CancellationToken::new);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(SYNTHETIC_PROBES_CHECK_INTERVAL_SECS));
+        loop {
+            tokio::select! {
+                _ = shutdown_token.cancelled() => {
+                    debug("Synthetic probes stopping due to shutdown signal");
+                    break;
+                }
+                _ = stop_services_token.cancelled() => {
+                    debug("Synthetic probes stopping due to stop_services signal");
+                    break;
+                }
+                _ = interval.tick() => {
+                    run_due_probes().await;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe_run(is_success: bool) -> ProbeRun {
+        let status = if is_success { 200 } else { 502 };
+        ProbeRun {
+            checked_at: chrono::Utc::now().to_rfc3339(),
+            is_success,
+            results: vec![ProbeTargetResult {
+                target: "http://shop.example.com/".to_string(),
+                status,
+                latency_ms: 40,
+                is_success,
+                error: (!is_success).then(|| "Unexpected status 502".to_string()),
+            }],
+        }
+    }
+
+    // Alerts are logged, which needs the runtime
+    #[tokio::test]
+    async fn test_consecutive_failures_raise_one_alert() {
+        let mut site = Site::new();
+        site.synthetic_probe.is_enabled = true;
+        site.synthetic_probe.failure_threshold = 2;
+
+        record_probe_run(&site, probe_run(false));
+        assert!(!is_synthetic_probe_failing(&site.id));
+        record_probe_run(&site, probe_run(true));
+        record_probe_run(&site, probe_run(false));
+        assert!(!is_synthetic_probe_failing(&site.id));
+        record_probe_run(&site, probe_run(false));
+        assert!(is_synthetic_probe_failing(&site.id));
+        record_probe_run(&site, probe_run(false));
+
+        let summary = get_probe_summary(&site);
+        assert_eq!(summary.consecutive_failures, 3);
+        assert_eq!(summary.success_percent, Some(20.0));
+        assert_eq!(summary.average_latency_ms, Some(40));
+        assert_eq!(get_probe_runs(&site.id, 2).len(), 2);
+
+        record_probe_run(&site, probe_run(true));
+        assert!(!is_synthetic_probe_failing(&site.id));
+        assert_eq!(get_probe_summary(&site).consecutive_failures, 0);
+    }
+
+    #[test]
+    fn test_expected_status() {
+        let mut settings = SyntheticProbeSettings::new();
+        assert!(settings.is_expected_status(200));
+        assert!(settings.is_expected_status(301));
+        assert!(!settings.is_expected_status(404));
+        assert!(!settings.is_expected_status(0));
+        settings.expected_status = 204;
+        assert!(settings.is_expected_status(204));
+        assert!(!settings.is_expected_status(200));
+    }
+}
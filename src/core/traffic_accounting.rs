@@ -0,0 +1,213 @@
+// ============================================================================
+// TRAFFIC ACCOUNTING
+// ============================================================================
+//
+// Counts the requests and the response bytes sent per site and day (UTC), for
+// usage reports. Counts are kept in memory and added to the site_traffic table
+// in the database every minute, and on shutdown or configuration reload.
+// ============================================================================
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{LazyLock, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use chrono::{NaiveDate, Utc};
+use http_body_util::combinators::BoxBody;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use tokio_util::sync::CancellationToken;
+
+use crate::core::database_connection::{get_database_connection, get_database_writer};
+use crate::core::triggers::get_trigger_handler;
+use crate::database::data_access::{execute, query};
+use crate::http::request_response::body_error::BodyError;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+use crate::logging::syslog::{debug, error, trace};
+
+// How often the counts in memory are added to the database
+const TRAFFIC_FLUSH_INTERVAL_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SiteTraffic {
+    pub requests: u64,
+    pub bytes_sent: u64,
+}
+
+impl SiteTraffic {
+    fn add(&mut self, other: &SiteTraffic) {
+        self.requests += other.requests;
+        self.bytes_sent += other.bytes_sent;
+    }
+}
+
+static PENDING_TRAFFIC: LazyLock<Mutex<HashMap<(String, NaiveDate), SiteTraffic>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn add_traffic(site_id: &str, traffic: SiteTraffic) {
+    let day = Utc::now().date_naive();
+    if let Ok(mut pending) = PENDING_TRAFFIC.lock() {
+        pending.entry((site_id.to_string(), day)).or_default().add(&traffic);
+    }
+}
+
+// Count a request to the site, and the bytes of its response body as they are sent. Streaming bodies are counted when
+// they are done, or when the client goes away, so only the bytes actually sent are counted
+pub fn count_response(response: &mut GruxiResponse, site_id: &str, count_body: bool) {
+    add_traffic(site_id, SiteTraffic { requests: 1, bytes_sent: 0 });
+    if !count_body {
+        return;
+    }
+
+    if let Some(length) = response.get_buffered_body_size() {
+        add_traffic(site_id, SiteTraffic { requests: 0, bytes_sent: length });
+        return;
+    }
+
+    let site_id = site_id.to_string();
+    response.map_streaming_body(|body| BoxBody::new(CountedBody { inner: body, site_id, bytes_sent: 0 }));
+}
+
+// Response body that counts the bytes passing through it
+struct CountedBody {
+    inner: BoxBody<Bytes, BodyError>,
+    site_id: String,
+    bytes_sent: u64,
+}
+
+impl Body for CountedBody {
+    type Data = Bytes;
+    type Error = BodyError;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll
+            && let Some(data) = frame.data_ref()
+        {
+            self.bytes_sent += data.len() as u64;
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for CountedBody {
+    fn drop(&mut self) {
+        if self.bytes_sent > 0 {
+            add_traffic(
+                &self.site_id,
+                SiteTraffic {
+                    requests: 0,
+                    bytes_sent: self.bytes_sent,
+                },
+            );
+        }
+    }
+}
+
+// Add the counts in memory to the database. If that fails, they are kept for the next attempt
+pub fn flush_traffic_accounting() {
+    let pending = match PENDING_TRAFFIC.lock() {
+        Ok(mut pending) => std::mem::take(&mut *pending),
+        Err(_) => return,
+    };
+    if pending.is_empty() {
+        return;
+    }
+
+    if let Err(e) = save_traffic(&pending) {
+        error(format!("Failed to save traffic accounting, will retry: {}", e));
+        if let Ok(mut current) = PENDING_TRAFFIC.lock() {
+            for (key, traffic) in pending {
+                current.entry(key).or_default().add(&traffic);
+            }
+        }
+        return;
+    }
+    trace(format!("Saved traffic accounting for {} sites and days", pending.len()));
+}
+
+fn save_traffic(pending: &HashMap<(String, NaiveDate), SiteTraffic>) -> Result<(), String> {
+    let connection = get_database_writer()?;
+    connection.execute("BEGIN IMMEDIATE TRANSACTION;").map_err(|e| format!("Failed to begin transaction: {}", e))?;
+
+    let result: Result<(), String> = (|| {
+        for ((site_id, day), traffic) in pending {
+            execute(
+                &connection,
+                "INSERT INTO site_traffic (site_id, day, requests, bytes_sent) VALUES (?, ?, ?, ?)
+                 ON CONFLICT (site_id, day) DO UPDATE SET requests = requests + excluded.requests, bytes_sent = bytes_sent + excluded.bytes_sent",
+                &[site_id, &day.to_string(), &traffic.requests, &traffic.bytes_sent],
+            )
+            .map_err(|e| format!("Failed to save traffic: {}", e))?;
+        }
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => connection.execute("COMMIT;").map_err(|e| {
+            let _ = connection.execute("ROLLBACK;");
+            format!("Failed to commit traffic: {}", e)
+        }),
+        Err(e) => {
+            let _ = connection.execute("ROLLBACK;");
+            Err(e)
+        }
+    }
+}
+
+// The traffic per site between the two days, both inclusive
+pub fn get_site_traffic(from: NaiveDate, to: NaiveDate) -> Result<HashMap<String, SiteTraffic>, String> {
+    let connection = get_database_connection()?;
+    let rows = query(
+        &connection,
+        "SELECT site_id, SUM(requests) AS requests, SUM(bytes_sent) AS bytes_sent FROM site_traffic WHERE day >= ? AND day <= ? GROUP BY site_id",
+        &[&from.to_string(), &to.to_string()],
+        |row| {
+            let traffic = SiteTraffic {
+                requests: row.get_i64("requests")?.max(0) as u64,
+                bytes_sent: row.get_i64("bytes_sent")?.max(0) as u64,
+            };
+            Ok((row.get_string("site_id")?, traffic))
+        },
+    )
+    .map_err(|e| format!("Failed to query traffic: {}", e))?;
+    Ok(rows.into_iter().collect())
+}
+
+/// Start saving the traffic counts periodically. It stops on shutdown or stop_services triggers, after a last save,
+/// so it is started again on configuration reload.
+pub async fn start_traffic_accounting() {
+    let triggers = get_trigger_handler();
+    let shutdown_token = triggers.get_token("shutdown").await.unwrap_or_else(CancellationToken::new);
+    let stop_services_token = triggers.get_token("stop_services").await.unwrap_or_else(CancellationToken::new);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(TRAFFIC_FLUSH_INTERVAL_SECS));
+        // The first tick is immediate, and there is nothing to save yet
+        interval.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = shutdown_token.cancelled() => {
+                    debug("Traffic accounting stopping due to shutdown signal");
+                    break;
+                }
+                _ = stop_services_token.cancelled() => {
+                    debug("Traffic accounting stopping due to stop_services signal");
+                    break;
+                }
+                _ = interval.tick() => {
+                    let _ = tokio::task::spawn_blocking(flush_traffic_accounting).await;
+                }
+            }
+        }
+        let _ = tokio::task::spawn_blocking(flush_traffic_accounting).await;
+    });
+}
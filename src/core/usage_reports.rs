@@ -0,0 +1,442 @@
+// ============================================================================
+// USAGE REPORTS
+// ============================================================================
+//
+// Per-site usage for billing: requests and bytes sent (from traffic accounting),
+// storage used by the web roots of the site and the number of certificates,
+// over a billing period. Periods start on a configurable day of the month.
+// When a webhook is configured, the report of each finished period is posted
+// to it once, signed with HMAC-SHA256 when a secret is set.
+// ============================================================================
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{Datelike, Months, NaiveDate, Utc};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+
+use crate::configuration::configuration::Configuration;
+use crate::configuration::site::Site;
+use crate::configuration::usage_reports::UsageReports;
+use crate::core::database_connection::{get_database_connection, get_database_writer};
+use crate::core::running_state_manager::get_running_state_manager;
+use crate::core::traffic_accounting::{flush_traffic_accounting, get_site_traffic};
+use crate::core::triggers::get_trigger_handler;
+use crate::database::data_access::{execute, query_one};
+use crate::logging::syslog::{debug, error, info, trace};
+
+// How often to check for finished periods to deliver
+const USAGE_REPORT_CHECK_INTERVAL_SECS: u64 = 3600;
+
+// Timeout for delivering a report to the webhook
+const USAGE_REPORT_WEBHOOK_TIMEOUT_SECS: u64 = 60;
+
+// Key in the gruxi table, with the end of the last period delivered to the webhook
+const LAST_DELIVERED_PERIOD_KEY: &str = "usage_report_last_period_end";
+
+// A billing period, from the start day to the end day, both inclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UsagePeriod {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl UsagePeriod {
+    // The period the date is in, for periods starting on the given day of the month (1-28)
+    pub fn containing(date: NaiveDate, start_day: u32) -> Self {
+        let start_day = start_day.clamp(1, 28);
+        let month_start = date.with_day(start_day).unwrap_or(date);
+        let start = if date.day() >= start_day { month_start } else { month_start - Months::new(1) };
+        let end = start + Months::new(1) - chrono::Duration::days(1);
+        UsagePeriod { start, end }
+    }
+
+    pub fn previous(&self, start_day: u32) -> Self {
+        UsagePeriod::containing(self.start - chrono::Duration::days(1), start_day)
+    }
+
+    pub fn next(&self, start_day: u32) -> Self {
+        UsagePeriod::containing(self.end + chrono::Duration::days(1), start_day)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SiteUsage {
+    pub site_id: String,
+    pub hostnames: Vec<String>,
+    pub requests: u64,
+    pub bytes_sent: u64,
+    pub storage_bytes: u64, // Current size of the web roots of the site, at the time of the report
+    pub certificates: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub generated_at: String,
+    pub is_final: bool, // The period has ended, so the traffic counts will not change anymore
+    pub sites: Vec<SiteUsage>,
+}
+
+impl UsageReport {
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("period_start,period_end,site_id,hostnames,requests,bytes_sent,storage_bytes,certificates\n");
+        for site in &self.sites {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{}\n",
+                self.period_start,
+                self.period_end,
+                escape_csv(&site.site_id),
+                escape_csv(&site.hostnames.join(" ")),
+                site.requests,
+                site.bytes_sent,
+                site.storage_bytes,
+                site.certificates
+            ));
+        }
+        csv
+    }
+}
+
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// What a usage report needs to know about a site, taken from the configuration so it does not have to be held while the
+// web roots are measured
+#[derive(Debug, Clone)]
+pub struct UsageSite {
+    site_id: String,
+    hostnames: Vec<String>,
+    web_roots: Vec<String>,
+    has_certificate: bool,
+}
+
+// The sites in the configuration that match the filter
+pub fn get_usage_sites<F>(configuration: &Configuration, include_site: F) -> Vec<UsageSite>
+where
+    F: Fn(&Site) -> bool,
+{
+    configuration
+        .sites
+        .iter()
+        .filter(|site| include_site(site))
+        .map(|site| UsageSite {
+            site_id: site.id.clone(),
+            hostnames: site.hostnames.clone(),
+            web_roots: get_site_web_roots(configuration, site),
+            has_certificate: site.tls_automatic_enabled || !site.tls_cert_path.is_empty() || !site.tls_cert_content.is_empty(),
+        })
+        .collect()
+}
+
+// Build the usage report for the sites. Traffic counted so far is saved first
+pub async fn build_usage_report(usage_sites: Vec<UsageSite>, period: UsagePeriod) -> Result<UsageReport, String> {
+    let traffic = tokio::task::spawn_blocking(move || {
+        flush_traffic_accounting();
+        get_site_traffic(period.start, period.end)
+    })
+    .await
+    .map_err(|e| format!("Failed to read traffic: {}", e))??;
+
+    let mut sites = Vec::new();
+    for usage_site in usage_sites {
+        let web_roots = usage_site.web_roots;
+        let storage_bytes = tokio::task::spawn_blocking(move || web_roots.iter().map(|root| get_directory_size(Path::new(root))).sum())
+            .await
+            .unwrap_or(0);
+        let site_traffic = traffic.get(&usage_site.site_id).copied().unwrap_or_default();
+
+        sites.push(SiteUsage {
+            site_id: usage_site.site_id,
+            hostnames: usage_site.hostnames,
+            requests: site_traffic.requests,
+            bytes_sent: site_traffic.bytes_sent,
+            storage_bytes,
+            certificates: if usage_site.has_certificate { 1 } else { 0 },
+        });
+    }
+
+    Ok(UsageReport {
+        period_start: period.start,
+        period_end: period.end,
+        generated_at: Utc::now().to_rfc3339(),
+        is_final: period.end < Utc::now().date_naive(),
+        sites,
+    })
+}
+
+// The web roots of the static file, PHP and WebDAV processors the site uses
+pub fn get_site_web_roots(configuration: &Configuration, site: &Site) -> Vec<String> {
+    let request_handler_ids: HashSet<&String> = site
+        .request_handlers
+        .iter()
+        .chain(site.locations.iter().flat_map(|location| location.request_handlers.iter()))
+        .collect();
+    let processor_ids: HashSet<&String> = configuration
+        .request_handlers
+        .iter()
+        .filter(|handler| request_handler_ids.contains(&handler.id))
+        .map(|handler| &handler.processor_id)
+        .collect();
+
+    let web_roots: HashSet<String> = configuration
+        .static_file_processors
+        .iter()
+        .filter(|processor| processor_ids.contains(&processor.id))
+        .map(|processor| processor.web_root.clone())
+        .chain(
+            configuration
+                .php_processors
+                .iter()
+                .filter(|processor| processor_ids.contains(&processor.id))
+                .map(|processor| processor.local_web_root.clone()),
+        )
+        .chain(
+            configuration
+                .webdav_processors
+                .iter()
+                .filter(|processor| processor_ids.contains(&processor.id))
+                .map(|processor| processor.web_root.clone()),
+        )
+        .chain(
+            configuration
+                .image_processors
+                .iter()
+                .filter(|processor| processor_ids.contains(&processor.id))
+                .map(|processor| processor.web_root.clone()),
+        )
+        .filter(|web_root| !web_root.trim().is_empty())
+        .collect();
+    web_roots.into_iter().collect()
+}
+
+// Total size of the files in the directory. Symbolic links are not followed, so nothing is counted twice
+fn get_directory_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let metadata = std::fs::symlink_metadata(entry.path()).ok()?;
+            if metadata.is_dir() {
+                Some(get_directory_size(&entry.path()))
+            } else if metadata.is_file() {
+                Some(metadata.len())
+            } else {
+                None
+            }
+        })
+        .sum()
+}
+
+/// Start delivering the reports of finished periods to the webhook, if one is configured. It stops on shutdown or
+/// stop_services triggers, so it is started again on configuration reload.
+pub async fn start_usage_report_delivery() {
+    let cached_configuration = crate::configuration::cached_configuration::get_cached_configuration();
+    let usage_reports = cached_configuration.get_configuration().await.core.usage_reports.clone();
+    if usage_reports.webhook_url.is_empty() {
+        debug("Usage report delivery is not enabled");
+        return;
+    }
+
+    let triggers = get_trigger_handler();
+    let shutdown_token = triggers.get_token("shutdown").await.unwrap_or_else(CancellationToken::new);
+    let stop_services_token = triggers.get_token("stop_services").await.unwrap_or_else(CancellationToken::new);
+
+    info("Usage report delivery started");
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(USAGE_REPORT_CHECK_INTERVAL_SECS));
+
+        loop {
+            tokio::select! {
+                _ = shutdown_token.cancelled() => {
+                    debug("Usage report delivery stopping due to shutdown signal");
+                    break;
+                }
+                _ = stop_services_token.cancelled() => {
+                    debug("Usage report delivery stopping due to stop_services signal");
+                    break;
+                }
+                _ = interval.tick() => {
+                    deliver_finished_periods(&usage_reports).await;
+                }
+            }
+        }
+    });
+}
+
+// Deliver each finished period not delivered yet, oldest first. Delivery stops at the first failure and is retried later
+async fn deliver_finished_periods(usage_reports: &UsageReports) {
+    let start_day = usage_reports.billing_period_start_day;
+    let last_finished_period = UsagePeriod::containing(Utc::now().date_naive(), start_day).previous(start_day);
+
+    let last_delivered_end = match tokio::task::spawn_blocking(get_last_delivered_period_end).await.map_err(|e| e.to_string()).flatten() {
+        Ok(last_delivered_end) => last_delivered_end,
+        Err(e) => {
+            error(format!("Failed to read the last delivered usage report period: {}", e));
+            return;
+        }
+    };
+
+    // The first time, only periods from now on are delivered, as there is no traffic from before
+    let Some(last_delivered_end) = last_delivered_end else {
+        let _ = tokio::task::spawn_blocking(move || set_last_delivered_period_end(last_finished_period.end)).await;
+        return;
+    };
+
+    let mut period = UsagePeriod::containing(last_delivered_end, start_day).next(start_day);
+    while period.end <= last_finished_period.end {
+        let usage_sites = {
+            let cached_configuration = crate::configuration::cached_configuration::get_cached_configuration();
+            get_usage_sites(&*cached_configuration.get_configuration().await, |_| true)
+        };
+        let report = match build_usage_report(usage_sites, period).await {
+            Ok(report) => report,
+            Err(e) => {
+                error(format!("Failed to build usage report for {} to {}: {}", period.start, period.end, e));
+                return;
+            }
+        };
+
+        if let Err(e) = send_usage_report(usage_reports, &report).await {
+            error(format!("Failed to deliver usage report for {} to {}, will retry: {}", period.start, period.end, e));
+            return;
+        }
+        info(format!("Usage report for {} to {} delivered", period.start, period.end));
+
+        let end = period.end;
+        if let Err(e) = tokio::task::spawn_blocking(move || set_last_delivered_period_end(end)).await.map_err(|e| e.to_string()).flatten() {
+            error(format!("Failed to store the last delivered usage report period: {}", e));
+            return;
+        }
+        period = period.next(start_day);
+    }
+}
+
+async fn send_usage_report(usage_reports: &UsageReports, report: &UsageReport) -> Result<(), String> {
+    let payload = serde_json::to_string(report).map_err(|e| format!("Failed to serialize report: {}", e))?;
+
+    let mut request = hyper::Request::builder()
+        .method(hyper::Method::POST)
+        .uri(&usage_reports.webhook_url)
+        .header(hyper::header::CONTENT_TYPE, "application/json");
+    if !usage_reports.webhook_secret.is_empty() {
+        request = request.header("X-Gruxi-Signature", format!("sha256={}", get_signature(&usage_reports.webhook_secret, payload.as_bytes())));
+    }
+    let request = request
+        .body(Full::new(Bytes::from(payload)).map_err(|never| match never {}).boxed())
+        .map_err(|e| format!("Failed to build request: {}", e))?;
+
+    let client = {
+        let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+        running_state.get_http_client().get_client(true)
+    };
+
+    match tokio::time::timeout(Duration::from_secs(USAGE_REPORT_WEBHOOK_TIMEOUT_SECS), client.request(request)).await {
+        Ok(Ok(response)) if response.status().is_success() => {
+            trace(format!("Usage report webhook returned status {}", response.status()));
+            Ok(())
+        }
+        Ok(Ok(response)) => Err(format!("Webhook returned status {}", response.status())),
+        Ok(Err(e)) => Err(format!("Request failed: {}", e)),
+        Err(_) => Err("Request timed out".to_string()),
+    }
+}
+
+// Hex encoded HMAC-SHA256 of the payload, so the receiver can verify the report is from us
+fn get_signature(secret: &str, payload: &[u8]) -> String {
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+    ring::hmac::sign(&key, payload).as_ref().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn get_last_delivered_period_end() -> Result<Option<NaiveDate>, String> {
+    let connection = get_database_connection()?;
+    let value = query_one(&connection, "SELECT gruxi_value FROM gruxi WHERE gruxi_key = ?", &[&LAST_DELIVERED_PERIOD_KEY], |row| {
+        row.get_string("gruxi_value")
+    })?;
+    Ok(value.and_then(|value| NaiveDate::parse_from_str(&value, "%Y-%m-%d").ok()))
+}
+
+fn set_last_delivered_period_end(end: NaiveDate) -> Result<(), String> {
+    let connection = get_database_writer()?;
+    execute(&connection, "DELETE FROM gruxi WHERE gruxi_key = ?", &[&LAST_DELIVERED_PERIOD_KEY]).map_err(|e| format!("Failed to store value: {}", e))?;
+    execute(&connection, "INSERT INTO gruxi (gruxi_key, gruxi_value) VALUES (?, ?)", &[&LAST_DELIVERED_PERIOD_KEY, &end.to_string()]).map_err(|e| format!("Failed to store value: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_usage_period_boundaries() {
+        let period = UsagePeriod::containing(date(2026, 3, 10), 1);
+        assert_eq!(
+            period,
+            UsagePeriod {
+                start: date(2026, 3, 1),
+                end: date(2026, 3, 31)
+            }
+        );
+        assert_eq!(
+            period.previous(1),
+            UsagePeriod {
+                start: date(2026, 2, 1),
+                end: date(2026, 2, 28)
+            }
+        );
+
+        let period = UsagePeriod::containing(date(2026, 1, 10), 15);
+        assert_eq!(
+            period,
+            UsagePeriod {
+                start: date(2025, 12, 15),
+                end: date(2026, 1, 14)
+            }
+        );
+        assert_eq!(
+            period.next(15),
+            UsagePeriod {
+                start: date(2026, 1, 15),
+                end: date(2026, 2, 14)
+            }
+        );
+        assert_eq!(UsagePeriod::containing(date(2026, 1, 15), 15).start, date(2026, 1, 15));
+    }
+
+    #[test]
+    fn test_usage_report_csv() {
+        let report = UsageReport {
+            period_start: date(2026, 3, 1),
+            period_end: date(2026, 3, 31),
+            generated_at: String::new(),
+            is_final: true,
+            sites: vec![SiteUsage {
+                site_id: "site-1".to_string(),
+                hostnames: vec!["example.com".to_string(), "www.example.com".to_string()],
+                requests: 10,
+                bytes_sent: 2048,
+                storage_bytes: 4096,
+                certificates: 1,
+            }],
+        };
+        let csv = report.to_csv();
+        assert_eq!(csv.lines().nth(1), Some("2026-03-01,2026-03-31,site-1,example.com www.example.com,10,2048,4096,1"));
+        assert_eq!(escape_csv("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+}
@@ -0,0 +1,337 @@
+// ============================================================================
+// CONFIGURATION STORAGE
+// ============================================================================
+//
+// The configuration and the sessions of the admin portal are kept in the local
+// SQLite database by default. They can be kept in PostgreSQL instead, so the
+// nodes of a multi-node deployment share one authoritative configuration. A
+// change saved on one node is applied by the others when they see the revision
+// of the stored configuration change.
+//
+// The storage is chosen at startup with --config-storage or the
+// GRUXI_CONFIG_STORAGE environment variable, as it cannot be part of the
+// configuration it stores. Users, scheduled changes and the other state of a
+// node stay in its SQLite database either way.
+// ============================================================================
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tokio_util::sync::CancellationToken;
+
+use crate::configuration::configuration::Configuration;
+use crate::configuration::deprecated_fields::{DEPRECATED_SETTING_KEYS, DeprecationWarning, find_deprecated_setting_keys};
+use crate::configuration::load_configuration::load_configuration_from_sqlite;
+use crate::configuration::save_configuration::save_configuration_in_sqlite;
+use crate::core::admin_user::Session;
+use crate::core::database_connection::{DatabaseConnection, get_database_connection, get_database_writer, get_session_database_connection};
+use crate::core::triggers::get_trigger_handler;
+use crate::database::data_access::{Row, execute, query, query_one};
+use crate::database::database_schema::get_schema_version;
+use crate::database::postgres_storage::PostgresConfigurationStorage;
+use crate::error::gruxi_error::GruxiError;
+use crate::error::gruxi_error_enums::{ConfigurationError, DatabaseError};
+use crate::logging::syslog::{debug, info, trace, warn};
+use crate::network::postgres_client::PostgresSettings;
+
+// Read when the URL of the storage has no password, so it does not have to be on the command line
+pub const CONFIGURATION_STORAGE_PASSWORD_VARIABLE: &str = "GRUXI_CONFIG_STORAGE_PASSWORD";
+// How often nodes sharing a storage check whether the configuration was changed by another node
+const REVISION_POLL_INTERVAL_SECONDS: u64 = 10;
+
+/// Where the configuration is loaded from and saved to
+pub trait ConfigurationStorage: Send + Sync {
+    /// Short name for the log, such as "SQLite"
+    fn get_name(&self) -> &'static str;
+
+    /// Whether other nodes use the same storage, so changes they save have to be picked up
+    fn is_shared(&self) -> bool;
+
+    /// Whether a configuration was saved before, so the default one is only created on the first start
+    fn has_configuration(&self) -> Result<bool, GruxiError>;
+
+    fn load_configuration(&self) -> Result<Configuration, GruxiError>;
+
+    /// Replace the stored configuration with one that is sanitized and validated already
+    fn save_configuration(&self, configuration: &Configuration) -> Result<(), GruxiError>;
+
+    /// A value that changes whenever the configuration is saved
+    fn get_revision(&self) -> Result<String, GruxiError>;
+
+    /// The sessions of the admin portal. With SQLite they are kept in the shared session database when one is configured
+    fn get_session_storage(&self, shared_session_database: &str) -> Result<Box<dyn SessionStorage + '_>, GruxiError>;
+
+    /// Deprecated field names in the stored configuration, which are still read until the configuration is migrated
+    fn get_deprecated_fields(&self) -> Result<Vec<DeprecationWarning>, GruxiError>;
+
+    /// Rename the deprecated fields in the stored configuration to their current names, or only list them on a dry run
+    fn migrate_deprecated_fields(&self, dry_run: bool) -> Result<Vec<DeprecationWarning>, GruxiError>;
+}
+
+/// Where the sessions of the admin portal are kept
+pub trait SessionStorage {
+    fn insert_session(&self, session: &Session) -> Result<(), GruxiError>;
+
+    fn get_session_by_token(&self, token: &str) -> Result<Option<Session>, GruxiError>;
+
+    /// Returns whether there was a session with the token
+    fn delete_session(&self, token: &str) -> Result<bool, GruxiError>;
+
+    fn delete_sessions_for_user(&self, username: &str) -> Result<(), GruxiError>;
+
+    /// Returns the number of sessions deleted
+    fn delete_expired_sessions(&self) -> Result<u64, GruxiError>;
+}
+
+static CONFIGURATION_STORAGE: OnceLock<Box<dyn ConfigurationStorage>> = OnceLock::new();
+
+/// The configuration storage, which is the local SQLite database unless another one was set up at startup
+pub fn get_configuration_storage() -> &'static dyn ConfigurationStorage {
+    CONFIGURATION_STORAGE.get_or_init(|| Box::new(SqliteConfigurationStorage)).as_ref()
+}
+
+/// Set up the storage given at startup, as a postgres:// URL. Empty keeps the local SQLite database
+pub fn initialize_configuration_storage(url: &str) -> Result<(), GruxiError> {
+    let storage = create_configuration_storage(url)?;
+    let name = storage.get_name();
+    CONFIGURATION_STORAGE
+        .set(storage)
+        .map_err(|_| GruxiError::configuration(ConfigurationError::Storage, "The configuration storage was already in use before it was set up"))?;
+    info(format!("Configuration storage: {}", name));
+    Ok(())
+}
+
+fn create_configuration_storage(url: &str) -> Result<Box<dyn ConfigurationStorage>, GruxiError> {
+    if url.is_empty() || url == "sqlite" {
+        return Ok(Box::new(SqliteConfigurationStorage));
+    }
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        let mut settings = PostgresSettings::from_url(url).map_err(|e| GruxiError::configuration(ConfigurationError::Storage, e))?;
+        if settings.password.is_empty()
+            && let Ok(password) = std::env::var(CONFIGURATION_STORAGE_PASSWORD_VARIABLE)
+        {
+            settings.password = password;
+        }
+        return Ok(Box::new(PostgresConfigurationStorage::connect(settings)?));
+    }
+    if url.starts_with("mysql://") {
+        return Err(GruxiError::configuration(
+            ConfigurationError::Storage,
+            "MySQL is not supported as configuration storage, use PostgreSQL or the local SQLite database",
+        ));
+    }
+    Err(GruxiError::configuration(
+        ConfigurationError::Storage,
+        format!("Unsupported configuration storage '{}', use a postgres:// URL, or leave it empty for the local SQLite database", url),
+    ))
+}
+
+/// Start watching the revision of a shared storage, and reload when another node changed the configuration. It stops on
+/// shutdown or stop_services triggers, so it is started again on configuration reload
+pub async fn start_configuration_storage_watch() {
+    let storage = get_configuration_storage();
+    if !storage.is_shared() {
+        return;
+    }
+
+    let initial_revision = match tokio::task::spawn_blocking(|| get_configuration_storage().get_revision()).await {
+        Ok(Ok(revision)) => revision,
+        Ok(Err(e)) => {
+            warn(format!("Failed to read the revision of the configuration storage, changes of other nodes are not picked up: {}", e));
+            return;
+        }
+        Err(e) => {
+            warn(format!("Failed to read the revision of the configuration storage: {}", e));
+            return;
+        }
+    };
+
+    let triggers = get_trigger_handler();
+    let shutdown_token = triggers.get_token("shutdown").await.unwrap_or_else(CancellationToken::new);
+    let stop_services_token = triggers.get_token("stop_services").await.unwrap_or_else(CancellationToken::new);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(REVISION_POLL_INTERVAL_SECONDS));
+        loop {
+            tokio::select! {
+                _ = shutdown_token.cancelled() => break,
+                _ = stop_services_token.cancelled() => break,
+                _ = interval.tick() => {
+                    match tokio::task::spawn_blocking(|| get_configuration_storage().get_revision()).await {
+                        Ok(Ok(revision)) if revision != initial_revision => {
+                            info(format!("Configuration storage: the configuration was changed to revision {}, reloading", revision));
+                            // Same as a reload from the admin portal, which also restarts this task
+                            let triggers = get_trigger_handler();
+                            triggers.run_trigger("refresh_cached_configuration").await;
+                            triggers.run_trigger("reload_configuration").await;
+                            break;
+                        }
+                        Ok(Ok(_)) => trace("Configuration storage: the configuration is unchanged"),
+                        Ok(Err(e)) => debug(format!("Failed to check the revision of the configuration storage: {}", e)),
+                        Err(e) => debug(format!("Failed to check the revision of the configuration storage: {}", e)),
+                    }
+                }
+            }
+        }
+    });
+}
+
+// The configuration is kept in the tables of the local database, which migrations keep up with the configuration
+pub struct SqliteConfigurationStorage;
+
+impl ConfigurationStorage for SqliteConfigurationStorage {
+    fn get_name(&self) -> &'static str {
+        "SQLite"
+    }
+
+    fn is_shared(&self) -> bool {
+        false
+    }
+
+    fn has_configuration(&self) -> Result<bool, GruxiError> {
+        Ok(get_schema_version() > 0)
+    }
+
+    fn load_configuration(&self) -> Result<Configuration, GruxiError> {
+        load_configuration_from_sqlite()
+    }
+
+    fn save_configuration(&self, configuration: &Configuration) -> Result<(), GruxiError> {
+        save_configuration_in_sqlite(configuration)
+    }
+
+    // Changes of the local database are applied by the reload of whoever saved them, so there is nothing to watch
+    fn get_revision(&self) -> Result<String, GruxiError> {
+        Ok(String::new())
+    }
+
+    fn get_session_storage(&self, shared_session_database: &str) -> Result<Box<dyn SessionStorage + '_>, GruxiError> {
+        let connection = if shared_session_database.is_empty() {
+            get_database_writer()?
+        } else {
+            get_session_database_connection(shared_session_database)?
+        };
+        Ok(Box::new(SqliteSessionStorage { connection }))
+    }
+
+    // The tables have fixed columns that the schema migrations rename, only the keys of the server settings can be deprecated
+    fn get_deprecated_fields(&self) -> Result<Vec<DeprecationWarning>, GruxiError> {
+        let connection = get_database_connection()?;
+        let keys = query(&connection, "SELECT DISTINCT setting_key FROM server_settings", &[], |row| row.get_string("setting_key"))?;
+        Ok(find_deprecated_setting_keys(&keys))
+    }
+
+    fn migrate_deprecated_fields(&self, dry_run: bool) -> Result<Vec<DeprecationWarning>, GruxiError> {
+        let warnings = self.get_deprecated_fields()?;
+        if dry_run || warnings.is_empty() {
+            return Ok(warnings);
+        }
+
+        let connection = get_database_writer()?;
+        connection
+            .execute("BEGIN IMMEDIATE TRANSACTION;")
+            .map_err(|e| GruxiError::database(DatabaseError::Transaction, format!("Failed to begin transaction: {}", e)))?;
+        let result = DEPRECATED_SETTING_KEYS.iter().try_for_each(|deprecated_field| {
+            // The value saved under the current key wins, the same as when loading
+            execute(
+                &connection,
+                "UPDATE server_settings SET setting_key = ? WHERE setting_key = ? AND NOT EXISTS (SELECT 1 FROM server_settings WHERE setting_key = ?)",
+                &[&deprecated_field.new_name, &deprecated_field.old_name, &deprecated_field.new_name],
+            )?;
+            execute(&connection, "DELETE FROM server_settings WHERE setting_key = ?", &[&deprecated_field.old_name])
+        });
+        match result {
+            Ok(()) => connection.execute("COMMIT;").map_err(|e| {
+                let _ = connection.execute("ROLLBACK;");
+                GruxiError::database(DatabaseError::Transaction, format!("Failed to commit the renamed server settings: {}", e))
+            })?,
+            Err(e) => {
+                let _ = connection.execute("ROLLBACK;");
+                return Err(e.with_context("Failed to rename the deprecated server settings"));
+            }
+        }
+        Ok(warnings)
+    }
+}
+
+pub struct SqliteSessionStorage {
+    connection: DatabaseConnection,
+}
+
+impl SessionStorage for SqliteSessionStorage {
+    fn insert_session(&self, session: &Session) -> Result<(), GruxiError> {
+        execute(
+            &self.connection,
+            "INSERT INTO sessions (id, user_id, username, token, expires_at, created_at, client_binding) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            &[
+                &session.id,
+                &session.user_id,
+                &session.username,
+                &session.token,
+                &session.expires_at.to_rfc3339(),
+                &session.created_at.to_rfc3339(),
+                &session.client_binding,
+            ],
+        )
+    }
+
+    fn get_session_by_token(&self, token: &str) -> Result<Option<Session>, GruxiError> {
+        query_one(
+            &self.connection,
+            "SELECT id, user_id, username, token, expires_at, created_at, client_binding FROM sessions WHERE token = ?",
+            &[&token],
+            map_session,
+        )
+    }
+
+    fn delete_session(&self, token: &str) -> Result<bool, GruxiError> {
+        execute(&self.connection, "DELETE FROM sessions WHERE token = ?", &[&token])?;
+        Ok(self.connection.change_count() > 0)
+    }
+
+    fn delete_sessions_for_user(&self, username: &str) -> Result<(), GruxiError> {
+        execute(&self.connection, "DELETE FROM sessions WHERE username = ?", &[&username])
+    }
+
+    // The times are RFC 3339 in UTC, which sort the same as text
+    fn delete_expired_sessions(&self) -> Result<u64, GruxiError> {
+        execute(&self.connection, "DELETE FROM sessions WHERE expires_at < ?", &[&Utc::now().to_rfc3339()])?;
+        Ok(self.connection.change_count() as u64)
+    }
+}
+
+fn map_session(row: &Row) -> Result<Session, GruxiError> {
+    Ok(Session {
+        id: row.get_string("id")?,
+        user_id: row.get_i64("user_id")?,
+        username: row.get_string("username")?,
+        token: row.get_string("token")?,
+        expires_at: parse_session_time(&row.get_string("expires_at")?, "expires_at")?,
+        created_at: parse_session_time(&row.get_string("created_at")?, "created_at")?,
+        client_binding: row.get_string("client_binding")?,
+    })
+}
+
+pub fn parse_session_time(value: &str, column: &str) -> Result<DateTime<Utc>, GruxiError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| GruxiError::database(DatabaseError::Read, format!("Failed to parse {}: {}", column, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_configuration_storage() {
+        assert_eq!(create_configuration_storage("").unwrap().get_name(), "SQLite");
+        assert!(!create_configuration_storage("sqlite").unwrap().is_shared());
+
+        let error = create_configuration_storage("mysql://gruxi@db/gruxi").err().unwrap();
+        assert!(error.to_string().contains("MySQL is not supported"), "{}", error);
+        assert_eq!(error.get_http_status_code(), 503);
+        assert!(create_configuration_storage("redis://db").is_err());
+        assert!(create_configuration_storage("postgres://db.example.com/gruxi").is_err());
+    }
+}
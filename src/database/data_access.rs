@@ -0,0 +1,213 @@
+// ============================================================================
+// DATA ACCESS
+// ============================================================================
+//
+// All queries with values go through here. The values are bound to "?"
+// placeholders instead of being formatted into the SQL, so a quote in a
+// hostname, path or password can never change what a query does.
+//
+// Rows are read through Row, by column name. Migrations append columns to the
+// end of the tables, so reading by name keeps the mappers independent of the
+// order the columns were added in.
+// ============================================================================
+
+use serde::de::DeserializeOwned;
+use sqlite::{Connection, State, Statement, Value};
+
+use crate::error::gruxi_error::GruxiError;
+use crate::error::gruxi_error_enums::DatabaseError;
+
+/// A value that can be bound to a "?" placeholder
+pub trait SqlParameter {
+    fn to_sql_value(&self) -> Value;
+}
+
+impl SqlParameter for str {
+    fn to_sql_value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl SqlParameter for &str {
+    fn to_sql_value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl SqlParameter for String {
+    fn to_sql_value(&self) -> Value {
+        Value::String(self.clone())
+    }
+}
+
+impl SqlParameter for &[u8] {
+    fn to_sql_value(&self) -> Value {
+        Value::Binary(self.to_vec())
+    }
+}
+
+// Booleans are stored as 1 and 0, as SQLite has no boolean type
+impl SqlParameter for bool {
+    fn to_sql_value(&self) -> Value {
+        Value::Integer(if *self { 1 } else { 0 })
+    }
+}
+
+macro_rules! impl_integer_parameter {
+    ($($type:ty),*) => {
+        $(
+            impl SqlParameter for $type {
+                fn to_sql_value(&self) -> Value {
+                    Value::Integer(*self as i64)
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_parameter!(i32, i64, u16, u32, u64, usize);
+
+impl<T: SqlParameter> SqlParameter for Option<T> {
+    fn to_sql_value(&self) -> Value {
+        match self {
+            Some(value) => value.to_sql_value(),
+            None => Value::Null,
+        }
+    }
+}
+
+fn read_error(column: &str, error: sqlite::Error) -> GruxiError {
+    GruxiError::database(DatabaseError::Read, format!("Failed to read {}: {}", column, error))
+}
+
+/// A row of a query result, which reads the columns by name
+pub struct Row<'a, 'l> {
+    statement: &'a Statement<'l>,
+}
+
+impl Row<'_, '_> {
+    /// A text column, where NULL is read as an empty string
+    pub fn get_string(&self, column: &str) -> Result<String, GruxiError> {
+        let value: Option<String> = self.statement.read(column).map_err(|e| read_error(column, e))?;
+        Ok(value.unwrap_or_default())
+    }
+
+    pub fn get_optional_string(&self, column: &str) -> Result<Option<String>, GruxiError> {
+        self.statement.read(column).map_err(|e| read_error(column, e))
+    }
+
+    pub fn get_bytes(&self, column: &str) -> Result<Vec<u8>, GruxiError> {
+        self.statement.read(column).map_err(|e| read_error(column, e))
+    }
+
+    pub fn get_i64(&self, column: &str) -> Result<i64, GruxiError> {
+        self.statement.read(column).map_err(|e| read_error(column, e))
+    }
+
+    pub fn get_u16(&self, column: &str) -> Result<u16, GruxiError> {
+        Ok(self.get_i64(column)? as u16)
+    }
+
+    pub fn get_u32(&self, column: &str) -> Result<u32, GruxiError> {
+        Ok(self.get_i64(column)? as u32)
+    }
+
+    pub fn get_u64(&self, column: &str) -> Result<u64, GruxiError> {
+        Ok(self.get_i64(column)? as u64)
+    }
+
+    pub fn get_usize(&self, column: &str) -> Result<usize, GruxiError> {
+        Ok(self.get_i64(column)? as usize)
+    }
+
+    pub fn get_bool(&self, column: &str) -> Result<bool, GruxiError> {
+        Ok(self.get_i64(column)? != 0)
+    }
+
+    /// A comma separated text column, without empty entries
+    pub fn get_list(&self, column: &str) -> Result<Vec<String>, GruxiError> {
+        Ok(self.get_string(column)?.split(',').map(|item| item.trim().to_string()).filter(|item| !item.is_empty()).collect())
+    }
+
+    /// A JSON text column, where an empty column is read as the default
+    pub fn get_json<T: DeserializeOwned + Default>(&self, column: &str) -> Result<T, GruxiError> {
+        let json = self.get_string(column)?;
+        if json.is_empty() {
+            return Ok(T::default());
+        }
+        serde_json::from_str(&json).map_err(|e| GruxiError::database(DatabaseError::Read, format!("Failed to parse {} JSON: {}", column, e)))
+    }
+}
+
+fn query_error(message: String) -> GruxiError {
+    GruxiError::database(DatabaseError::Query, message)
+}
+
+fn prepare<'l>(connection: &'l Connection, sql: &str, parameters: &[&dyn SqlParameter]) -> Result<Statement<'l>, GruxiError> {
+    let mut statement = connection.prepare(sql).map_err(|e| query_error(format!("Failed to prepare query '{}': {}", sql, e)))?;
+    for (index, parameter) in parameters.iter().enumerate() {
+        statement
+            .bind((index + 1, parameter.to_sql_value()))
+            .map_err(|e| query_error(format!("Failed to bind parameter {} of query '{}': {}", index + 1, sql, e)))?;
+    }
+    Ok(statement)
+}
+
+/// Run a statement, with the parameters bound to its "?" placeholders in order
+pub fn execute(connection: &Connection, sql: &str, parameters: &[&dyn SqlParameter]) -> Result<(), GruxiError> {
+    let mut statement = prepare(connection, sql, parameters)?;
+    while statement.next().map_err(|e| query_error(format!("Failed to execute query '{}': {}", sql, e)))? == State::Row {}
+    Ok(())
+}
+
+/// Run a query, with the parameters bound to its "?" placeholders in order, and map each row
+pub fn query<T>(connection: &Connection, sql: &str, parameters: &[&dyn SqlParameter], mapper: impl Fn(&Row) -> Result<T, GruxiError>) -> Result<Vec<T>, GruxiError> {
+    let mut statement = prepare(connection, sql, parameters)?;
+    let mut items = Vec::new();
+    while statement.next().map_err(|e| query_error(format!("Failed to execute query '{}': {}", sql, e)))? == State::Row {
+        items.push(mapper(&Row { statement: &statement })?);
+    }
+    Ok(items)
+}
+
+/// Run a query, and map the first row when there is one
+pub fn query_one<T>(connection: &Connection, sql: &str, parameters: &[&dyn SqlParameter], mapper: impl Fn(&Row) -> Result<T, GruxiError>) -> Result<Option<T>, GruxiError> {
+    let mut statement = prepare(connection, sql, parameters)?;
+    match statement.next().map_err(|e| query_error(format!("Failed to execute query '{}': {}", sql, e)))? {
+        State::Row => Ok(Some(mapper(&Row { statement: &statement })?)),
+        State::Done => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parameters_are_not_part_of_the_sql() {
+        let connection = sqlite::open(":memory:").unwrap();
+        execute(
+            &connection,
+            "CREATE TABLE items (id TEXT PRIMARY KEY, count INTEGER NOT NULL, is_enabled INTEGER NOT NULL, tags TEXT)",
+            &[],
+        )
+        .unwrap();
+
+        let id = "a'); DROP TABLE items; --";
+        execute(
+            &connection,
+            "INSERT INTO items (id, count, is_enabled, tags) VALUES (?, ?, ?, ?)",
+            &[&id, &42u32, &true, &None::<String>],
+        )
+        .unwrap();
+
+        let items = query(&connection, "SELECT * FROM items WHERE id = ?", &[&id], |row| {
+            Ok((row.get_string("id")?, row.get_u32("count")?, row.get_bool("is_enabled")?, row.get_list("tags")?))
+        })
+        .unwrap();
+        assert_eq!(items, vec![(id.to_string(), 42, true, Vec::new())]);
+
+        let missing = query_one(&connection, "SELECT id FROM items WHERE id = ?", &[&"other"], |row| row.get_string("id")).unwrap();
+        assert_eq!(missing, None);
+    }
+}
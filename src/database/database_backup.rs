@@ -0,0 +1,360 @@
+// ============================================================================
+// DATABASE BACKUP
+// ============================================================================
+//
+// Online backups of the configuration database, taken with the backup API of
+// SQLite while Gruxi is running, so they are consistent without stopping
+// anything. Backups are written to the configured directory, on schedule and
+// from the admin portal, and only the newest are kept.
+//
+// A backup is restored from the admin portal, which reloads the configuration
+// right after, or with --restore-backup while Gruxi is stopped. The current
+// database is backed up first, so a restore can be undone by restoring that.
+// ============================================================================
+
+use std::ffi::CStr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use sqlite::ffi;
+use tokio_util::sync::CancellationToken;
+
+use crate::configuration::database_backup::DatabaseBackupSettings;
+use crate::core::database_connection::{get_database_connection, get_database_writer};
+use crate::core::triggers::get_trigger_handler;
+use crate::database::data_access::query_one;
+use crate::database::database_schema::CURRENT_DB_SCHEMA_VERSION;
+use crate::logging::syslog::{debug, error, info, trace};
+
+// How often to check whether a scheduled backup is due
+const BACKUP_CHECK_INTERVAL_SECS: u64 = 300;
+
+// Pages copied at a time, so other connections get the database in between
+const BACKUP_PAGES_PER_STEP: i32 = 256;
+
+// How long to wait for another connection to release the database, before giving up
+const BACKUP_BUSY_RETRY_MILLISECONDS: u64 = 50;
+const BACKUP_MAX_BUSY_RETRIES: u32 = 200;
+
+// Backups are named after the time they were taken, so sorting by name sorts them by age
+const BACKUP_FILE_PREFIX: &str = "gruxi-";
+const BACKUP_FILE_EXTENSION: &str = ".db";
+const BACKUP_TIME_FORMAT: &str = "%Y%m%d-%H%M%S-%3f";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DatabaseBackup {
+    pub name: String,
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Take a backup of the configuration database now, and remove the backups beyond the retention count
+pub fn create_backup(settings: &DatabaseBackupSettings) -> Result<DatabaseBackup, String> {
+    let backup = write_backup_to_directory(Path::new(&settings.directory))?;
+    if let Err(e) = remove_old_backups(Path::new(&settings.directory), settings.retention_count as usize) {
+        error(format!("Failed to remove old database backups: {}", e));
+    }
+    Ok(backup)
+}
+
+fn write_backup_to_directory(directory: &Path) -> Result<DatabaseBackup, String> {
+    std::fs::create_dir_all(directory).map_err(|e| format!("Failed to create backup directory {}: {}", directory.display(), e))?;
+
+    let created_at = Utc::now();
+    let name = format!("{}{}{}", BACKUP_FILE_PREFIX, created_at.format(BACKUP_TIME_FORMAT), BACKUP_FILE_EXTENSION);
+    let path = directory.join(&name);
+    // Written under another name first, so a backup that failed halfway is never listed or restored
+    let partial_path = directory.join(format!("{}.partial", name));
+
+    let result = write_backup(&partial_path).and_then(|_| std::fs::rename(&partial_path, &path).map_err(|e| format!("Failed to move backup to {}: {}", path.display(), e)));
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&partial_path);
+        return Err(e);
+    }
+
+    let size_bytes = std::fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+    info(format!("Database backup written to {} ({} bytes)", path.display(), size_bytes));
+    Ok(DatabaseBackup { name, size_bytes, created_at })
+}
+
+fn write_backup(path: &Path) -> Result<(), String> {
+    let source = get_database_connection()?;
+    let destination = sqlite::open(path).map_err(|e| format!("Failed to create backup file {}: {}", path.display(), e))?;
+    copy_database(&source, &destination)?;
+    // The copy has the WAL journal mode of the live database, which needs extra files next to it to be read
+    destination.execute("PRAGMA journal_mode=DELETE;").map_err(|e| format!("Failed to set journal mode of backup: {}", e))?;
+    Ok(())
+}
+
+/// The backups in the directory, newest first
+pub fn list_backups(directory: &str) -> Result<Vec<DatabaseBackup>, String> {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read backup directory {}: {}", directory, e)),
+    };
+
+    let mut backups: Vec<DatabaseBackup> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let created_at = parse_backup_time(&name)?;
+            let size_bytes = entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+            Some(DatabaseBackup { name, size_bytes, created_at })
+        })
+        .collect();
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(backups)
+}
+
+fn parse_backup_time(name: &str) -> Option<DateTime<Utc>> {
+    let time = name.strip_prefix(BACKUP_FILE_PREFIX)?.strip_suffix(BACKUP_FILE_EXTENSION)?;
+    NaiveDateTime::parse_from_str(time, BACKUP_TIME_FORMAT).ok().map(|time| time.and_utc())
+}
+
+fn remove_old_backups(directory: &Path, retention_count: usize) -> Result<(), String> {
+    let backups = list_backups(&directory.to_string_lossy())?;
+    for backup in backups.iter().skip(retention_count.max(1)) {
+        let path = directory.join(&backup.name);
+        std::fs::remove_file(&path).map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+        debug(format!("Removed old database backup {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Replace the configuration database with a backup from the backup directory. The current database is backed up first.
+/// With a running server, the configuration has to be reloaded afterwards to apply it
+pub fn restore_backup(settings: &DatabaseBackupSettings, name: &str) -> Result<DatabaseBackup, String> {
+    // Only a plain name of a backup is accepted, so no other file can be restored
+    if parse_backup_time(name).is_none() || name.contains(['/', '\\']) {
+        return Err(format!("'{}' is not the name of a database backup", name));
+    }
+    let path = Path::new(&settings.directory).join(name);
+    if !path.is_file() {
+        return Err(format!("Database backup {} does not exist", path.display()));
+    }
+    restore_backup_file(settings, &path)
+}
+
+/// Replace the configuration database with a backup file, which may be anywhere, such as when copied from another server
+pub fn restore_backup_file(settings: &DatabaseBackupSettings, path: &Path) -> Result<DatabaseBackup, String> {
+    let backup = sqlite::Connection::open_with_flags(path, sqlite::OpenFlags::new().with_read_only()).map_err(|e| format!("Failed to open database backup {}: {}", path.display(), e))?;
+    check_backup(&backup)?;
+
+    // Old backups are not removed here, as that could be the one being restored
+    let pre_restore_backup = write_backup_to_directory(Path::new(&settings.directory)).map_err(|e| format!("Failed to back up the current database before restoring: {}", e))?;
+
+    let writer = get_database_writer()?;
+    copy_database(&backup, &writer).map_err(|e| format!("Failed to restore database backup {}: {}", path.display(), e))?;
+    info(format!("Database restored from backup {}", path.display()));
+    Ok(pre_restore_backup)
+}
+
+// A backup from a newer Gruxi may have data this one does not know about
+fn check_backup(backup: &sqlite::Connection) -> Result<(), String> {
+    let integrity = query_one(backup, "PRAGMA quick_check", &[], |row| row.get_string("quick_check"))?;
+    if integrity.as_deref() != Some("ok") {
+        return Err(format!("Database backup is damaged: {}", integrity.unwrap_or_default()));
+    }
+
+    let schema_version = get_backup_schema_version(backup)?;
+    if schema_version > CURRENT_DB_SCHEMA_VERSION {
+        return Err(format!(
+            "Database backup has schema version {}, which is newer than the version {} of this Gruxi",
+            schema_version, CURRENT_DB_SCHEMA_VERSION
+        ));
+    }
+    Ok(())
+}
+
+fn get_backup_schema_version(backup: &sqlite::Connection) -> Result<i32, String> {
+    let schema_version = query_one(backup, "SELECT gruxi_value FROM gruxi WHERE gruxi_key = 'schema_version' LIMIT 1", &[], |row| {
+        row.get_i64("gruxi_value")
+    })
+    .map_err(|e| format!("Database backup is not a Gruxi database: {}", e))?;
+    Ok(schema_version.unwrap_or(0) as i32)
+}
+
+/// Restore a backup with a running server, and reload the configuration from it. A backup with an older schema is not
+/// restored here, as migrations only run at startup, so it has to be restored with --restore-backup while Gruxi is stopped
+pub async fn restore_backup_and_reload(settings: DatabaseBackupSettings, name: String) -> Result<DatabaseBackup, String> {
+    let pre_restore_backup = tokio::task::spawn_blocking(move || {
+        let path = Path::new(&settings.directory).join(&name);
+        if let Ok(backup) = sqlite::Connection::open_with_flags(&path, sqlite::OpenFlags::new().with_read_only())
+            && let Ok(schema_version) = get_backup_schema_version(&backup)
+            && schema_version < CURRENT_DB_SCHEMA_VERSION
+        {
+            return Err(format!(
+                "Database backup has schema version {}, older than the version {} of this Gruxi. Restore it with --restore-backup while Gruxi is stopped, so it is migrated at startup",
+                schema_version, CURRENT_DB_SCHEMA_VERSION
+            ));
+        }
+        restore_backup(&settings, &name)
+    })
+    .await
+    .map_err(|e| format!("Failed to restore database backup: {}", e))??;
+
+    let triggers = get_trigger_handler();
+    triggers.run_trigger("refresh_cached_configuration").await;
+    triggers.run_trigger("reload_configuration").await;
+    Ok(pre_restore_backup)
+}
+
+// Copy all pages of the source database to the destination with the SQLite backup API. Other connections can read and
+// write the source in between steps, and the copy starts over by itself when another connection changed it
+fn copy_database(source: &sqlite::Connection, destination: &sqlite::Connection) -> Result<(), String> {
+    let main: &CStr = c"main";
+    // SAFETY: both connections stay open until the backup is finished, which is done before returning
+    unsafe {
+        let backup = ffi::sqlite3_backup_init(destination.as_raw(), main.as_ptr(), source.as_raw(), main.as_ptr());
+        if backup.is_null() {
+            return Err(get_error_message(destination));
+        }
+
+        let mut busy_retries = 0;
+        loop {
+            match ffi::sqlite3_backup_step(backup, BACKUP_PAGES_PER_STEP) {
+                ffi::SQLITE_OK => {}
+                ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED if busy_retries < BACKUP_MAX_BUSY_RETRIES => {
+                    busy_retries += 1;
+                    std::thread::sleep(Duration::from_millis(BACKUP_BUSY_RETRY_MILLISECONDS));
+                }
+                // Done, or an error, which finishing the backup reports
+                _ => break,
+            }
+        }
+
+        if ffi::sqlite3_backup_finish(backup) != ffi::SQLITE_OK {
+            return Err(get_error_message(destination));
+        }
+    }
+    Ok(())
+}
+
+fn get_error_message(connection: &sqlite::Connection) -> String {
+    // SAFETY: the connection is open, and the message is copied before anything else uses it
+    let message = unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(connection.as_raw())) };
+    format!("SQLite backup failed: {}", message.to_string_lossy())
+}
+
+/// Start the scheduled backups, if enabled. It stops on shutdown or stop_services triggers, so it is started again on configuration reload
+pub async fn start_database_backups() {
+    let cached_configuration = crate::configuration::cached_configuration::get_cached_configuration();
+    let settings = cached_configuration.get_configuration().await.core.database_backup.clone();
+    if !settings.is_enabled {
+        debug("Scheduled database backups are not enabled");
+        return;
+    }
+
+    let triggers = get_trigger_handler();
+    let shutdown_token = triggers.get_token("shutdown").await.unwrap_or_else(CancellationToken::new);
+    let stop_services_token = triggers.get_token("stop_services").await.unwrap_or_else(CancellationToken::new);
+
+    info(format!("Scheduled database backups started, every {} hours to {}", settings.interval_hours, settings.directory));
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(BACKUP_CHECK_INTERVAL_SECS));
+
+        loop {
+            tokio::select! {
+                _ = shutdown_token.cancelled() => {
+                    debug("Scheduled database backups stopping due to shutdown signal");
+                    break;
+                }
+                _ = stop_services_token.cancelled() => {
+                    debug("Scheduled database backups stopping due to stop_services signal");
+                    break;
+                }
+                _ = interval.tick() => {
+                    let settings = settings.clone();
+                    match tokio::task::spawn_blocking(move || create_backup_if_due(&settings)).await {
+                        Ok(Err(e)) => error(format!("Scheduled database backup failed: {}", e)),
+                        Err(e) => error(format!("Scheduled database backup failed: {}", e)),
+                        Ok(Ok(())) => {}
+                    }
+                }
+            }
+        }
+    });
+}
+
+// The newest backup tells when the last one was taken, so a restart does not take an extra one
+fn create_backup_if_due(settings: &DatabaseBackupSettings) -> Result<(), String> {
+    let newest_backup_time = list_backups(&settings.directory)?.first().map(|backup| backup.created_at);
+    if !is_backup_due(newest_backup_time, settings.interval_hours, Utc::now()) {
+        trace("No scheduled database backup is due yet");
+        return Ok(());
+    }
+    create_backup(settings)?;
+    Ok(())
+}
+
+fn is_backup_due(newest_backup_time: Option<DateTime<Utc>>, interval_hours: u32, now: DateTime<Utc>) -> bool {
+    match newest_backup_time {
+        Some(newest_backup_time) => now - newest_backup_time >= chrono::Duration::hours(interval_hours as i64),
+        None => true,
+    }
+}
+
+/// Where a backup given on the command line is read from, which is a name in the backup directory or a path to a file
+pub fn resolve_backup_path(settings: &DatabaseBackupSettings, backup: &str) -> PathBuf {
+    let in_directory = Path::new(&settings.directory).join(backup);
+    if parse_backup_time(backup).is_some() && in_directory.is_file() {
+        in_directory
+    } else {
+        PathBuf::from(backup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_copy_database_and_backup_names() {
+        let directory = std::env::temp_dir().join(format!("gruxi-backup-test-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let source = sqlite::open(":memory:").unwrap();
+        source
+            .execute("CREATE TABLE gruxi (gruxi_key TEXT, gruxi_value TEXT); INSERT INTO gruxi VALUES ('schema_version', '1');")
+            .unwrap();
+        let destination = sqlite::open(directory.join("gruxi-20260101-020304-005.db")).unwrap();
+        copy_database(&source, &destination).unwrap();
+        drop(destination);
+
+        let backup = sqlite::Connection::open_with_flags(directory.join("gruxi-20260101-020304-005.db"), sqlite::OpenFlags::new().with_read_only()).unwrap();
+        check_backup(&backup).unwrap();
+
+        std::fs::write(directory.join("gruxi-20260102-000000-000.db"), b"").unwrap();
+        std::fs::write(directory.join("notes.txt"), b"").unwrap();
+        let backups = list_backups(&directory.to_string_lossy()).unwrap();
+        assert_eq!(
+            backups.iter().map(|backup| backup.name.as_str()).collect::<Vec<_>>(),
+            ["gruxi-20260102-000000-000.db", "gruxi-20260101-020304-005.db"]
+        );
+
+        remove_old_backups(&directory, 1).unwrap();
+        assert_eq!(list_backups(&directory.to_string_lossy()).unwrap().len(), 1);
+        assert!(directory.join("notes.txt").exists());
+
+        let settings = DatabaseBackupSettings {
+            directory: directory.to_string_lossy().to_string(),
+            ..DatabaseBackupSettings::new()
+        };
+        assert!(restore_backup(&settings, "../gruxi.db").is_err());
+        assert!(restore_backup(&settings, "gruxi-20250101-000000-000.db").is_err());
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn test_is_backup_due() {
+        let now = Utc::now();
+        assert!(is_backup_due(None, 24, now));
+        assert!(!is_backup_due(Some(now - chrono::Duration::hours(23)), 24, now));
+        assert!(is_backup_due(Some(now - chrono::Duration::hours(24)), 24, now));
+    }
+}
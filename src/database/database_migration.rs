@@ -1,83 +1,1290 @@
+// ============================================================================
+// DATABASE MIGRATION
+// ============================================================================
+//
+// The schema of the database is versioned, and each version has a migration
+// in MIGRATIONS that goes up to it from the version before, and down again.
+// Migrations run in order, each in its own transaction together with the
+// update of the schema version, so a failed migration leaves the database at
+// the last version that was fully applied.
+//
+// At startup the database is migrated up to CURRENT_DB_SCHEMA_VERSION. The
+// migrate command moves it to any version in between, such as before going
+// back to an older Gruxi, and with --dry-run applies the migrations in a
+// transaction that is rolled back, to see that they would succeed.
+//
+// A new schema version needs a migration here, and the change added to the
+// init SQL in database_schema.rs for new databases.
+// ============================================================================
+
+use std::fmt;
+
 use sqlite::Connection;
 
 use crate::{
-    core::database_connection::get_database_connection,
-    database::database_schema::{get_schema_version},
+    core::database_connection::get_database_writer,
+    database::data_access::{execute, query_one},
+    database::database_schema::CURRENT_DB_SCHEMA_VERSION,
+    error::gruxi_error::GruxiError,
+    error::gruxi_error_enums::DatabaseError,
 };
 
-pub fn migrate_database() -> i32 {
-    // Get our current schema version from db
-    let mut schema_version = get_schema_version();
-    if schema_version < 1 {
-        return 0;
+/// Migrates the schema between the version before and this version
+pub struct Migration {
+    pub version: i32,
+    pub description: &'static str,
+    up: fn(&Connection) -> Result<(), sqlite::Error>,
+    down: fn(&Connection) -> Result<(), sqlite::Error>,
+}
+
+// Version 1 and 2 databases predate the migrations, so version 2 is as far down as they go
+const OLDEST_SCHEMA_VERSION: i32 = 2;
+
+static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 3,
+        description: "Add server_software_spoof to php_processors",
+        up: migrate_db_2_to_3,
+        down: revert_db_3_to_2,
+    },
+    Migration {
+        version: 4,
+        description: "Add tls_automatic_enabled to sites",
+        up: migrate_db_3_to_4,
+        down: revert_db_4_to_3,
+    },
+    Migration {
+        version: 5,
+        description: "Add locations to sites",
+        up: migrate_db_4_to_5,
+        down: revert_db_5_to_4,
+    },
+    Migration {
+        version: 6,
+        description: "Add the webdav_processors table",
+        up: migrate_db_5_to_6,
+        down: revert_db_6_to_5,
+    },
+    Migration {
+        version: 7,
+        description: "Add the cgi_processors table",
+        up: migrate_db_6_to_7,
+        down: revert_db_7_to_6,
+    },
+    Migration {
+        version: 8,
+        description: "Add fastcgi_pool_size to php_processors",
+        up: migrate_db_7_to_8,
+        down: revert_db_8_to_7,
+    },
+    Migration {
+        version: 9,
+        description: "Add integrity_headers_enabled to static_file_processors",
+        up: migrate_db_8_to_9,
+        down: revert_db_9_to_8,
+    },
+    Migration {
+        version: 10,
+        description: "Add the python_processors and python_handlers tables",
+        up: migrate_db_9_to_10,
+        down: revert_db_10_to_9,
+    },
+    Migration {
+        version: 11,
+        description: "Add the node_processors and node_handlers tables",
+        up: migrate_db_10_to_11,
+        down: revert_db_11_to_10,
+    },
+    Migration {
+        version: 12,
+        description: "Add the auth_providers table",
+        up: migrate_db_11_to_12,
+        down: revert_db_12_to_11,
+    },
+    Migration {
+        version: 13,
+        description: "Add LDAP group lookup settings to auth_providers",
+        up: migrate_db_12_to_13,
+        down: revert_db_13_to_12,
+    },
+    Migration {
+        version: 14,
+        description: "Add kerberos_keytab_file to auth_providers",
+        up: migrate_db_13_to_14,
+        down: revert_db_14_to_13,
+    },
+    Migration {
+        version: 15,
+        description: "Add request queue settings to the external handler tables",
+        up: migrate_db_14_to_15,
+        down: revert_db_15_to_14,
+    },
+    Migration {
+        version: 16,
+        description: "Add client_binding to sessions",
+        up: migrate_db_15_to_16,
+        down: revert_db_16_to_15,
+    },
+    Migration {
+        version: 17,
+        description: "Add php_ini_settings and php_environment to sites",
+        up: migrate_db_16_to_17,
+        down: revert_db_17_to_16,
+    },
+    Migration {
+        version: 18,
+        description: "Add sendfile_root to sites",
+        up: migrate_db_17_to_18,
+        down: revert_db_18_to_17,
+    },
+    Migration {
+        version: 19,
+        description: "Add site_scope to users",
+        up: migrate_db_18_to_19,
+        down: revert_db_19_to_18,
+    },
+    Migration {
+        version: 20,
+        description: "Add streaming paths and timeout to proxy_processors",
+        up: migrate_db_19_to_20,
+        down: revert_db_20_to_19,
+    },
+    Migration {
+        version: 21,
+        description: "Add the site_traffic table",
+        up: migrate_db_20_to_21,
+        down: revert_db_21_to_20,
+    },
+    Migration {
+        version: 22,
+        description: "Add upstream TLS settings to proxy_processors",
+        up: migrate_db_21_to_22,
+        down: revert_db_22_to_21,
+    },
+    Migration {
+        version: 23,
+        description: "Add websocket to sites",
+        up: migrate_db_22_to_23,
+        down: revert_db_23_to_22,
+    },
+    Migration {
+        version: 24,
+        description: "Add upstream connection pool settings to proxy_processors",
+        up: migrate_db_23_to_24,
+        down: revert_db_24_to_23,
+    },
+    Migration {
+        version: 25,
+        description: "Add timeouts and body size limits to proxy_processors",
+        up: migrate_db_24_to_25,
+        down: revert_db_25_to_24,
+    },
+    Migration {
+        version: 26,
+        description: "Add webroot_sync to sites",
+        up: migrate_db_25_to_26,
+        down: revert_db_26_to_25,
+    },
+    Migration {
+        version: 27,
+        description: "Add upstream groups to proxy_processors",
+        up: migrate_db_26_to_27,
+        down: revert_db_27_to_26,
+    },
+    Migration {
+        version: 28,
+        description: "Add cache_warm to sites",
+        up: migrate_db_27_to_28,
+        down: revert_db_28_to_27,
+    },
+    Migration {
+        version: 29,
+        description: "Add HTTP/1.0 strict close and keep-alive timeout to bindings",
+        up: migrate_db_28_to_29,
+        down: revert_db_29_to_28,
+    },
+    Migration {
+        version: 30,
+        description: "Add the scheduled_changes and scheduled_change_events tables",
+        up: migrate_db_29_to_30,
+        down: revert_db_30_to_29,
+    },
+    Migration {
+        version: 31,
+        description: "Add the acme_cache table",
+        up: migrate_db_30_to_31,
+        down: revert_db_31_to_30,
+    },
+    Migration {
+        version: 32,
+        description: "Add header allow and deny lists to proxy_processors",
+        up: migrate_db_31_to_32,
+        down: revert_db_32_to_31,
+    },
+    Migration {
+        version: 33,
+        description: "Add http_versions to bindings",
+        up: migrate_db_32_to_33,
+        down: revert_db_33_to_32,
+    },
+    Migration {
+        version: 34,
+        description: "Add worker recycling settings to php_cgi_handlers and python_handlers",
+        up: migrate_db_33_to_34,
+        down: revert_db_34_to_33,
+    },
+    Migration {
+        version: 35,
+        description: "Add the command_hooks table",
+        up: migrate_db_34_to_35,
+        down: revert_db_35_to_34,
+    },
+    Migration {
+        version: 36,
+        description: "Add error_response_format to sites",
+        up: migrate_db_35_to_36,
+        down: revert_db_36_to_35,
+    },
+    Migration {
+        version: 37,
+        description: "Add max_keep_alive_requests to bindings",
+        up: migrate_db_36_to_37,
+        down: revert_db_37_to_36,
+    },
+    Migration {
+        version: 38,
+        description: "Add bandwidth to sites",
+        up: migrate_db_37_to_38,
+        down: revert_db_38_to_37,
+    },
+    Migration {
+        version: 39,
+        description: "Add sni_mismatch_policy to bindings",
+        up: migrate_db_38_to_39,
+        down: revert_db_39_to_38,
+    },
+    Migration {
+        version: 40,
+        description: "Add default_site_id and unknown_host_policy to bindings",
+        up: migrate_db_39_to_40,
+        down: revert_db_40_to_39,
+    },
+    Migration {
+        version: 41,
+        description: "Add waf to sites",
+        up: migrate_db_40_to_41,
+        down: revert_db_41_to_40,
+    },
+    Migration {
+        version: 42,
+        description: "Add bots to sites",
+        up: migrate_db_41_to_42,
+        down: revert_db_42_to_41,
+    },
+    Migration {
+        version: 43,
+        description: "Add middleware to sites",
+        up: migrate_db_42_to_43,
+        down: revert_db_43_to_42,
+    },
+    Migration {
+        version: 44,
+        description: "Add plugins to sites",
+        up: migrate_db_43_to_44,
+        down: revert_db_44_to_43,
+    },
+    Migration {
+        version: 45,
+        description: "Add Lua hooks to sites",
+        up: migrate_db_44_to_45,
+        down: revert_db_45_to_44,
+    },
+    Migration {
+        version: 46,
+        description: "Add ssi_enabled to static_file_processors",
+        up: migrate_db_45_to_46,
+        down: revert_db_46_to_45,
+    },
+    Migration {
+        version: 47,
+        description: "Add disk_quota_mb to sites",
+        up: migrate_db_46_to_47,
+        down: revert_db_47_to_46,
+    },
+    Migration {
+        version: 48,
+        description: "Add the scheduled_tasks table",
+        up: migrate_db_47_to_48,
+        down: revert_db_48_to_47,
+    },
+    Migration {
+        version: 49,
+        description: "Add synthetic_probe to sites",
+        up: migrate_db_48_to_49,
+        down: revert_db_49_to_48,
+    },
+    Migration {
+        version: 50,
+        description: "Add cache_header_rules to sites",
+        up: migrate_db_49_to_50,
+        down: revert_db_50_to_49,
+    },
+    Migration {
+        version: 51,
+        description: "Add the image_processors table",
+        up: migrate_db_50_to_51,
+        down: revert_db_51_to_50,
+    },
+    Migration {
+        version: 52,
+        description: "Add acme to sites",
+        up: migrate_db_51_to_52,
+        down: revert_db_52_to_51,
+    },
+    Migration {
+        version: 53,
+        description: "Add the acme_certificates table",
+        up: migrate_db_52_to_53,
+        down: revert_db_53_to_52,
+    },
+];
+
+/// A migration to apply, up to its version or down from it
+#[derive(Clone, Copy)]
+pub struct MigrationStep {
+    pub migration: &'static Migration,
+    pub is_down: bool,
+}
+
+impl MigrationStep {
+    pub fn from_version(&self) -> i32 {
+        if self.is_down { self.migration.version } else { self.migration.version - 1 }
+    }
+
+    pub fn to_version(&self) -> i32 {
+        if self.is_down { self.migration.version - 1 } else { self.migration.version }
     }
 
-    let connection_result = get_database_connection();
-    let connection = match connection_result {
-        Ok(conn) => conn,
-        Err(e) => {
-            panic!("Failed to get database connection for migration: {}", e);
+    fn run(&self, connection: &Connection) -> Result<(), String> {
+        let migration_fn = if self.is_down { self.migration.down } else { self.migration.up };
+        migration_fn(connection).map_err(|e| format!("Failed to migrate database from version {} to {}: {}", self.from_version(), self.to_version(), e))
+    }
+}
+
+impl fmt::Display for MigrationStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let revert = if self.is_down { "Revert: " } else { "" };
+        write!(f, "{} -> {}: {}{}", self.from_version(), self.to_version(), revert, self.migration.description)
+    }
+}
+
+/// The migrations that take the schema from one version to another, in the order they are applied
+pub fn plan_migrations(from_version: i32, to_version: i32) -> Result<Vec<MigrationStep>, String> {
+    for version in [from_version, to_version] {
+        if !(OLDEST_SCHEMA_VERSION..=CURRENT_DB_SCHEMA_VERSION).contains(&version) {
+            return Err(format!(
+                "Schema version {} cannot be migrated, it has to be between {} and {}",
+                version, OLDEST_SCHEMA_VERSION, CURRENT_DB_SCHEMA_VERSION
+            ));
         }
+    }
+
+    let (versions, is_down): (Vec<i32>, bool) = if to_version >= from_version {
+        ((from_version + 1..=to_version).collect(), false)
+    } else {
+        ((to_version + 1..=from_version).rev().collect(), true)
     };
 
-    // Migration from 2 to 3
-    if schema_version == 2 {
-        let result = migrate_db_helper(&connection, 2, 3, migrate_db_2_to_3);
-        if let Err(e) = result {
-            panic!("Database migration from version 2 to 3 failed: {}", e);
-        }
-        schema_version = 3;
+    versions
+        .into_iter()
+        .map(|version| match MIGRATIONS.iter().find(|migration| migration.version == version) {
+            Some(migration) => Ok(MigrationStep { migration, is_down }),
+            None => Err(format!("No migration to schema version {}", version)),
+        })
+        .collect()
+}
+
+/// Migrate the database up to the current schema version. Returns the schema version, which is 0 for a new database
+pub fn migrate_database() -> Result<i32, GruxiError> {
+    let connection = get_database_writer().map_err(|e| e.with_context("Failed to get database connection for migration"))?;
+    let schema_version = read_schema_version(&connection).map_err(migration_error)?;
+    if schema_version < 1 {
+        return Ok(0);
     }
-    // Migration from 3 to 4
-    if schema_version == 3 {
-        let result = migrate_db_helper(&connection, 3, 4, migrate_db_3_to_4);
-        if let Err(e) = result {
-            panic!("Database migration from version 3 to 4 failed: {}", e);
-        }
-        schema_version = 4;
+    let steps = plan_migrations(schema_version, CURRENT_DB_SCHEMA_VERSION).map_err(migration_error)?;
+    apply_migrations(&connection, &steps).map_err(migration_error)?;
+    Ok(CURRENT_DB_SCHEMA_VERSION)
+}
+
+/// Migrate the database up or down to the schema version. With dry run, the migrations are applied and rolled back,
+/// so the database is left as it was. Returns the migrations applied, or that would be
+pub fn migrate_database_to(to_version: i32, dry_run: bool) -> Result<Vec<MigrationStep>, GruxiError> {
+    let connection = get_database_writer().map_err(|e| e.with_context("Failed to get database connection for migration"))?;
+    let schema_version = read_schema_version(&connection).map_err(migration_error)?;
+    if schema_version < 1 {
+        return Err(migration_error("The database has no schema yet, it is created at the current version on the first start".to_string()));
+    }
+
+    let steps = plan_migrations(schema_version, to_version).map_err(migration_error)?;
+    if dry_run {
+        dry_run_migrations(&connection, &steps).map_err(migration_error)?;
+    } else {
+        apply_migrations(&connection, &steps).map_err(migration_error)?;
+    }
+    Ok(steps)
+}
+
+// The steps report their errors as strings, the same as the migration functions they run
+fn migration_error(message: String) -> GruxiError {
+    GruxiError::database(DatabaseError::Migration, message)
+}
+
+// A database without the gruxi table has no schema yet, which is version 0
+fn read_schema_version(connection: &Connection) -> Result<i32, String> {
+    let has_schema = query_one(connection, "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'gruxi'", &[], |row| row.get_string("name"))
+        .map_err(|e| format!("Failed to read the schema version: {}", e))?;
+    if has_schema.is_none() {
+        return Ok(0);
+    }
+
+    let schema_version = query_one(connection, "SELECT gruxi_value FROM gruxi WHERE gruxi_key = 'schema_version' LIMIT 1", &[], |row| {
+        row.get_i64("gruxi_value")
+    })
+    .map_err(|e| format!("Failed to read the schema version: {}", e))?;
+    Ok(schema_version.unwrap_or(0) as i32)
+}
+
+fn apply_migrations(connection: &Connection, steps: &[MigrationStep]) -> Result<(), String> {
+    for step in steps {
+        run_in_transaction(connection, step, |connection| {
+            step.run(connection)?;
+            execute(connection, "UPDATE gruxi SET gruxi_value = ? WHERE gruxi_key = 'schema_version'", &[&step.to_version()])?;
+            Ok(())
+        })?;
     }
+    Ok(())
+}
 
-    schema_version
+// All steps run in one transaction, which is always rolled back, so the last step sees the changes of those before it
+fn dry_run_migrations(connection: &Connection, steps: &[MigrationStep]) -> Result<(), String> {
+    connection
+        .execute("BEGIN IMMEDIATE TRANSACTION;")
+        .map_err(|e| format!("Failed to begin transaction for the dry run: {}", e))?;
+    let result = steps.iter().try_for_each(|step| step.run(connection));
+    connection.execute("ROLLBACK;").map_err(|e| format!("Failed to roll back the dry run: {}", e))?;
+    result
 }
 
-fn migrate_db_helper(connection: &Connection, from_version: i32, to_version: i32, migration_fn: fn(&Connection) -> Result<(), sqlite::Error>) -> Result<(), String> {
+fn run_in_transaction(connection: &Connection, step: &MigrationStep, migration_fn: impl FnOnce(&Connection) -> Result<(), String>) -> Result<(), String> {
     if let Err(e) = connection.execute("BEGIN IMMEDIATE TRANSACTION;") {
-        return Err(format!("Failed to begin transaction for database migration from version {} to {}: {}", from_version, to_version, e));
+        return Err(format!(
+            "Failed to begin transaction for database migration from version {} to {}: {}",
+            step.from_version(),
+            step.to_version(),
+            e
+        ));
     }
 
-    let migration_result: Result<(), sqlite::Error> = (|| {
-        migration_fn(connection)?;
+    if let Err(e) = migration_fn(connection) {
+        let _ = connection.execute("ROLLBACK;");
+        return Err(e);
+    }
 
-        // Update schema version
-        connection.execute(format!("UPDATE gruxi SET gruxi_value = '{}' WHERE gruxi_key = 'schema_version';", to_version))?;
+    if let Err(e) = connection.execute("COMMIT;") {
+        let _ = connection.execute("ROLLBACK;");
+        return Err(format!(
+            "Failed to commit transaction for database migration from version {} to {}: {}",
+            step.from_version(),
+            step.to_version(),
+            e
+        ));
+    }
+    Ok(())
+}
 
-        Ok(())
-    })();
+// Startup creates the missing tables with all their columns before migrating, so a table created that way has the column already
+fn add_column(connection: &Connection, table: &str, column_definition: &str) -> Result<(), sqlite::Error> {
+    let column = column_definition.split_whitespace().next().unwrap_or_default();
+    let mut statement = connection.prepare(format!("SELECT name FROM pragma_table_info('{}') WHERE name = ?", table))?;
+    statement.bind((1, column))?;
+    if statement.next()? == sqlite::State::Row {
+        return Ok(());
+    }
+    connection.execute(format!("ALTER TABLE {} ADD COLUMN {};", table, column_definition))
+}
 
-    match migration_result {
-        Ok(()) => {
-            if let Err(e) = connection.execute("COMMIT;") {
-                let _ = connection.execute("ROLLBACK;");
-                return Err(format!("Failed to commit transaction for database migration from version {} to {}: {}", from_version, to_version, e));
-            }
-        }
-        Err(e) => {
-            let _ = connection.execute("ROLLBACK;");
-            return Err(format!("Failed to migrate database from version {} to {}: {}", from_version, to_version, e));
-        }
-    };
+fn drop_columns(connection: &Connection, table: &str, columns: &[&str]) -> Result<(), sqlite::Error> {
+    for column in columns {
+        connection.execute(format!("ALTER TABLE {} DROP COLUMN {};", table, column))?;
+    }
+    Ok(())
+}
 
+fn drop_tables(connection: &Connection, tables: &[&str]) -> Result<(), sqlite::Error> {
+    for table in tables {
+        connection.execute(format!("DROP TABLE IF EXISTS {};", table))?;
+    }
     Ok(())
 }
 
 fn migrate_db_2_to_3(connection: &Connection) -> Result<(), sqlite::Error> {
     // Add "server_software_spoof" to "php_processors" table
-    connection.execute("ALTER TABLE php_processors ADD COLUMN server_software_spoof TEXT NOT NULL DEFAULT '';")?;
+    add_column(connection, "php_processors", "server_software_spoof TEXT NOT NULL DEFAULT ''")?;
     Ok(())
 }
 
 fn migrate_db_3_to_4(connection: &Connection) -> Result<(), sqlite::Error> {
     // Add "tls_automatic_enabled" to "sites" table
-    connection.execute("ALTER TABLE sites ADD COLUMN tls_automatic_enabled BOOLEAN NOT NULL DEFAULT 0;")?;
+    add_column(connection, "sites", "tls_automatic_enabled BOOLEAN NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+fn migrate_db_4_to_5(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "locations" to "sites" table, stored as JSON
+    add_column(connection, "sites", "locations TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_5_to_6(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "webdav_processors" table
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS webdav_processors (
+        id TEXT PRIMARY KEY,
+        web_root TEXT NOT NULL DEFAULT '',
+        read_only BOOLEAN NOT NULL DEFAULT 0,
+        require_authentication BOOLEAN NOT NULL DEFAULT 1,
+        auth_realm TEXT NOT NULL DEFAULT '',
+        auth_users TEXT NOT NULL DEFAULT ''
+    );",
+    )?;
+    Ok(())
+}
+
+fn migrate_db_6_to_7(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "cgi_processors" table
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS cgi_processors (
+        id TEXT PRIMARY KEY,
+        cgi_bin_dir TEXT NOT NULL DEFAULT '',
+        url_prefix TEXT NOT NULL DEFAULT '',
+        interpreters TEXT NOT NULL DEFAULT '',
+        request_timeout INTEGER NOT NULL DEFAULT 30
+    );",
+    )?;
+    Ok(())
+}
+
+fn migrate_db_7_to_8(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "fastcgi_pool_size" to "php_processors" table
+    add_column(connection, "php_processors", "fastcgi_pool_size INTEGER NOT NULL DEFAULT 8")?;
+    Ok(())
+}
+
+fn migrate_db_8_to_9(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "integrity_headers_enabled" to "static_file_processors" table
+    add_column(connection, "static_file_processors", "integrity_headers_enabled BOOLEAN NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+fn migrate_db_9_to_10(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "python_processors" and "python_handlers" tables
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS python_processors (
+        id TEXT PRIMARY KEY,
+        python_handler_id TEXT NOT NULL DEFAULT '',
+        request_timeout INTEGER NOT NULL DEFAULT 30,
+        preserve_host_header BOOLEAN NOT NULL DEFAULT 1
+    );",
+    )?;
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS python_handlers (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL DEFAULT '',
+        server_type TEXT NOT NULL DEFAULT '',
+        executable TEXT NOT NULL DEFAULT '',
+        app_module TEXT NOT NULL DEFAULT '',
+        working_directory TEXT NOT NULL DEFAULT '',
+        workers INTEGER NOT NULL DEFAULT 1,
+        extra_arguments TEXT NOT NULL DEFAULT '',
+        extra_environment TEXT NOT NULL DEFAULT '',
+        health_check_path TEXT NOT NULL DEFAULT ''
+    );",
+    )?;
+    Ok(())
+}
+
+fn migrate_db_10_to_11(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "node_processors" and "node_handlers" tables
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS node_processors (
+        id TEXT PRIMARY KEY,
+        node_handler_id TEXT NOT NULL DEFAULT '',
+        request_timeout INTEGER NOT NULL DEFAULT 30,
+        preserve_host_header BOOLEAN NOT NULL DEFAULT 1
+    );",
+    )?;
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS node_handlers (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL DEFAULT '',
+        executable TEXT NOT NULL DEFAULT '',
+        entry_script TEXT NOT NULL DEFAULT '',
+        working_directory TEXT NOT NULL DEFAULT '',
+        extra_arguments TEXT NOT NULL DEFAULT '',
+        extra_environment TEXT NOT NULL DEFAULT '',
+        health_check_path TEXT NOT NULL DEFAULT ''
+    );",
+    )?;
+    Ok(())
+}
+
+fn migrate_db_11_to_12(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "auth_providers" table. Existing admin users keep logging in through the user database, as no provider is selected
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS auth_providers (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL DEFAULT '',
+        provider_type TEXT NOT NULL DEFAULT '',
+        htpasswd_file TEXT NOT NULL DEFAULT '',
+        ldap_url TEXT NOT NULL DEFAULT '',
+        ldap_bind_dn_template TEXT NOT NULL DEFAULT '',
+        oidc_token_url TEXT NOT NULL DEFAULT '',
+        oidc_client_id TEXT NOT NULL DEFAULT '',
+        oidc_client_secret TEXT NOT NULL DEFAULT '',
+        oidc_scope TEXT NOT NULL DEFAULT '',
+        timeout_seconds INTEGER NOT NULL DEFAULT 10,
+        cache_seconds INTEGER NOT NULL DEFAULT 60
+    );",
+    )?;
+    Ok(())
+}
+
+fn migrate_db_12_to_13(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add LDAP group lookup settings to "auth_providers". Without a search base, no groups are looked up
+    add_column(connection, "auth_providers", "ldap_search_base_dn TEXT NOT NULL DEFAULT ''")?;
+    add_column(connection, "auth_providers", "ldap_user_attribute TEXT NOT NULL DEFAULT 'sAMAccountName'")?;
+    add_column(connection, "auth_providers", "ldap_group_attribute TEXT NOT NULL DEFAULT 'memberOf'")?;
+    Ok(())
+}
+
+fn migrate_db_13_to_14(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the keytab for Kerberos auth providers to "auth_providers"
+    add_column(connection, "auth_providers", "kerberos_keytab_file TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_14_to_15(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add request queue settings to the external handler tables
+    add_column(connection, "php_cgi_handlers", "max_queue_length INTEGER NOT NULL DEFAULT 100")?;
+    add_column(connection, "php_cgi_handlers", "queue_timeout INTEGER NOT NULL DEFAULT 30")?;
+    for table in ["python_handlers", "node_handlers"] {
+        add_column(connection, table, "max_concurrent_requests INTEGER NOT NULL DEFAULT 0")?;
+        add_column(connection, table, "max_queue_length INTEGER NOT NULL DEFAULT 100")?;
+        add_column(connection, table, "queue_timeout INTEGER NOT NULL DEFAULT 30")?;
+    }
+    Ok(())
+}
+
+fn migrate_db_15_to_16(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the client fingerprint a session is bound to, to "sessions". Existing sessions are not bound
+    add_column(connection, "sessions", "client_binding TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_16_to_17(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the per-site PHP ini settings and environment variables (JSON) to "sites"
+    add_column(connection, "sites", "php_ini_settings TEXT NOT NULL DEFAULT ''")?;
+    add_column(connection, "sites", "php_environment TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_17_to_18(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the internal root for X-Sendfile and X-Accel-Redirect responses to "sites"
+    add_column(connection, "sites", "sendfile_root TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_18_to_19(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the sites a user is delegated to manage to "users". NULL keeps existing users as full admins
+    add_column(connection, "users", "site_scope TEXT")?;
+    Ok(())
+}
+
+fn migrate_db_19_to_20(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the paths with long-lived responses, such as Server-Sent Events, and their timeout to "proxy_processors"
+    add_column(connection, "proxy_processors", "streaming_paths TEXT NOT NULL DEFAULT ''")?;
+    add_column(connection, "proxy_processors", "streaming_timeout_seconds INTEGER NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+fn migrate_db_20_to_21(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "site_traffic", with the requests and bytes sent per site and day, for usage reports
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS site_traffic (
+                site_id TEXT NOT NULL,
+                day TEXT NOT NULL,
+                requests INTEGER NOT NULL DEFAULT 0,
+                bytes_sent INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (site_id, day)
+            )",
+    )?;
+    Ok(())
+}
+
+fn migrate_db_21_to_22(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the TLS settings for https:// upstreams to "proxy_processors": CA bundle, server name override and client certificate
+    add_column(connection, "proxy_processors", "tls_ca_bundle_path TEXT NOT NULL DEFAULT ''")?;
+    add_column(connection, "proxy_processors", "tls_server_name TEXT NOT NULL DEFAULT ''")?;
+    add_column(connection, "proxy_processors", "tls_client_cert_path TEXT NOT NULL DEFAULT ''")?;
+    add_column(connection, "proxy_processors", "tls_client_key_path TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_22_to_23(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the WebSocket limits, as JSON, to "sites"
+    add_column(connection, "sites", "websocket TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_23_to_24(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the connection pool settings for upstreams to "proxy_processors"
+    add_column(connection, "proxy_processors", "pool_max_idle_per_host INTEGER NOT NULL DEFAULT 0")?;
+    add_column(connection, "proxy_processors", "pool_idle_timeout_seconds INTEGER NOT NULL DEFAULT 0")?;
+    add_column(connection, "proxy_processors", "upstream_http2_only BOOLEAN NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+fn migrate_db_24_to_25(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the timeouts and body size limits for proxied traffic to "proxy_processors"
+    add_column(connection, "proxy_processors", "connect_timeout_seconds INTEGER NOT NULL DEFAULT 0")?;
+    add_column(connection, "proxy_processors", "total_timeout_seconds INTEGER NOT NULL DEFAULT 0")?;
+    add_column(connection, "proxy_processors", "max_request_body_bytes INTEGER NOT NULL DEFAULT 0")?;
+    add_column(connection, "proxy_processors", "max_response_body_bytes INTEGER NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+fn migrate_db_25_to_26(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the webroot sync, as JSON, to "sites"
+    add_column(connection, "sites", "webroot_sync TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_26_to_27(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the upstream groups for traffic splitting, as JSON, and their override header and cookie to "proxy_processors"
+    add_column(connection, "proxy_processors", "upstream_groups TEXT NOT NULL DEFAULT ''")?;
+    add_column(connection, "proxy_processors", "upstream_group_header TEXT NOT NULL DEFAULT ''")?;
+    add_column(connection, "proxy_processors", "upstream_group_cookie TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_27_to_28(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the cache warming, as JSON, to "sites"
+    add_column(connection, "sites", "cache_warm TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_28_to_29(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the HTTP/1.0 strict mode and keep-alive timeout to "bindings"
+    add_column(connection, "bindings", "http10_strict_close BOOLEAN NOT NULL DEFAULT 0")?;
+    add_column(connection, "bindings", "keep_alive_timeout_seconds INTEGER NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+fn migrate_db_29_to_30(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "scheduled_changes", with configuration changes scheduled for a later time, and "scheduled_change_events" with their audit trail
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS scheduled_changes (
+                id TEXT PRIMARY KEY,
+                description TEXT NOT NULL DEFAULT '',
+                scheduled_at TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'pending',
+                changes TEXT NOT NULL DEFAULT '',
+                created_by TEXT NOT NULL DEFAULT '',
+                created_at TEXT NOT NULL
+            )",
+    )?;
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS scheduled_change_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                change_id TEXT NOT NULL,
+                event TEXT NOT NULL,
+                username TEXT NOT NULL DEFAULT '',
+                details TEXT NOT NULL DEFAULT '',
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (change_id) REFERENCES scheduled_changes (id) ON DELETE CASCADE
+            )",
+    )?;
+    Ok(())
+}
+
+fn migrate_db_30_to_31(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "acme_cache", with ACME accounts and certificates when they are cached in the database
+    connection.execute(crate::database::database_schema::get_acme_cache_schema())?;
+    Ok(())
+}
+
+fn migrate_db_31_to_32(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the header allow and deny lists, comma separated, to "proxy_processors"
+    add_column(connection, "proxy_processors", "request_headers_allowed TEXT NOT NULL DEFAULT ''")?;
+    add_column(connection, "proxy_processors", "request_headers_denied TEXT NOT NULL DEFAULT ''")?;
+    add_column(connection, "proxy_processors", "response_headers_allowed TEXT NOT NULL DEFAULT ''")?;
+    add_column(connection, "proxy_processors", "response_headers_denied TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_32_to_33(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the HTTP version pinning to "bindings"
+    add_column(connection, "bindings", "http_versions TEXT NOT NULL DEFAULT 'auto'")?;
+    Ok(())
+}
+
+fn migrate_db_33_to_34(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the worker recycling settings to the PHP-CGI and Python handler tables, where PHP-CGI keeps its former limit
+    add_column(connection, "php_cgi_handlers", "max_requests_per_worker INTEGER NOT NULL DEFAULT 10000")?;
+    add_column(connection, "php_cgi_handlers", "max_worker_lifetime INTEGER NOT NULL DEFAULT 0")?;
+    add_column(connection, "python_handlers", "max_requests_per_worker INTEGER NOT NULL DEFAULT 0")?;
+    add_column(connection, "python_handlers", "max_worker_lifetime INTEGER NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+fn migrate_db_34_to_35(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "command_hooks" table
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS command_hooks (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL DEFAULT '',
+        event TEXT NOT NULL DEFAULT '',
+        command TEXT NOT NULL DEFAULT '',
+        arguments TEXT NOT NULL DEFAULT '[]',
+        working_directory TEXT NOT NULL DEFAULT '',
+        timeout_seconds INTEGER NOT NULL DEFAULT 30,
+        is_enabled INTEGER NOT NULL DEFAULT 1
+    );",
+    )?;
+    Ok(())
+}
+
+fn migrate_db_35_to_36(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the format of error responses, HTML as before or JSON for API sites, to "sites"
+    add_column(connection, "sites", "error_response_format TEXT NOT NULL DEFAULT 'html'")?;
+    Ok(())
+}
+
+fn migrate_db_36_to_37(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the number of requests served on a kept-alive connection before it is closed, 0 for no limit, to "bindings"
+    add_column(connection, "bindings", "max_keep_alive_requests INTEGER NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+fn migrate_db_37_to_38(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the download rate limits per connection and per site, stored as JSON, to "sites"
+    add_column(connection, "sites", "bandwidth TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_38_to_39(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the handling of requests whose Host differs from the TLS SNI name, serving them as before, to "bindings"
+    add_column(connection, "bindings", "sni_mismatch_policy TEXT NOT NULL DEFAULT 'allow'")?;
+    Ok(())
+}
+
+fn migrate_db_39_to_40(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the default site and the handling of unknown hostnames, serving the sites marked as default as before, to "bindings"
+    add_column(connection, "bindings", "default_site_id TEXT NOT NULL DEFAULT ''")?;
+    add_column(connection, "bindings", "unknown_host_policy TEXT NOT NULL DEFAULT 'default'")?;
     Ok(())
 }
+
+fn migrate_db_40_to_41(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the web application firewall, stored as JSON, to "sites"
+    add_column(connection, "sites", "waf TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_41_to_42(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the bot and crawler rules and the generated robots.txt, stored as JSON, to "sites"
+    add_column(connection, "sites", "bots TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_42_to_43(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the middleware chain, comma separated and empty for the default chain, to "sites"
+    add_column(connection, "sites", "middleware TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_43_to_44(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the WebAssembly plugins, stored as JSON, to "sites"
+    add_column(connection, "sites", "plugins TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_44_to_45(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add the Lua hooks, stored as JSON, to "sites"
+    add_column(connection, "sites", "lua_hooks TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_45_to_46(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "ssi_enabled" to "static_file_processors" table
+    add_column(connection, "static_file_processors", "ssi_enabled BOOLEAN NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+fn migrate_db_46_to_47(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "disk_quota_mb" to "sites" table
+    add_column(connection, "sites", "disk_quota_mb INTEGER NOT NULL DEFAULT 0")?;
+    Ok(())
+}
+
+fn migrate_db_47_to_48(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "scheduled_tasks", with recurring maintenance jobs and the status of their last run
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS scheduled_tasks (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL DEFAULT '',
+                job_type TEXT NOT NULL,
+                schedule TEXT NOT NULL,
+                parameters TEXT NOT NULL DEFAULT '',
+                is_enabled BOOLEAN NOT NULL DEFAULT 1,
+                created_by TEXT NOT NULL DEFAULT '',
+                created_at TEXT NOT NULL,
+                last_run_at TEXT NOT NULL DEFAULT '',
+                last_status TEXT NOT NULL DEFAULT '',
+                last_message TEXT NOT NULL DEFAULT '',
+                last_duration_ms INTEGER NOT NULL DEFAULT 0
+            )",
+    )?;
+    Ok(())
+}
+
+fn migrate_db_48_to_49(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "synthetic_probe" to "sites" table
+    add_column(connection, "sites", "synthetic_probe TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_49_to_50(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "cache_header_rules" to "sites" table
+    add_column(connection, "sites", "cache_header_rules TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_50_to_51(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "image_processors" table
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS image_processors (
+                id TEXT PRIMARY KEY,
+                web_root TEXT NOT NULL DEFAULT '',
+                allowed_paths TEXT NOT NULL DEFAULT '',
+                cache_dir TEXT NOT NULL DEFAULT '',
+                converter_path TEXT NOT NULL DEFAULT 'magick',
+                max_width INTEGER NOT NULL DEFAULT 4096,
+                allowed_widths TEXT NOT NULL DEFAULT '',
+                default_quality INTEGER NOT NULL DEFAULT 80,
+                max_concurrent_conversions INTEGER NOT NULL DEFAULT 2,
+                request_timeout INTEGER NOT NULL DEFAULT 30
+            )",
+    )?;
+    Ok(())
+}
+
+fn migrate_db_51_to_52(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "acme" to "sites" table
+    add_column(connection, "sites", "acme TEXT NOT NULL DEFAULT ''")?;
+    Ok(())
+}
+
+fn migrate_db_52_to_53(connection: &Connection) -> Result<(), sqlite::Error> {
+    // Add "acme_certificates", with every certificate issued or renewed through ACME
+    connection.execute(crate::database::database_schema::get_acme_certificates_schema())?;
+    Ok(())
+}
+
+fn revert_db_3_to_2(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "php_processors", &["server_software_spoof"])
+}
+
+fn revert_db_4_to_3(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["tls_automatic_enabled"])
+}
+
+fn revert_db_5_to_4(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["locations"])
+}
+
+fn revert_db_6_to_5(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_tables(connection, &["webdav_processors"])
+}
+
+fn revert_db_7_to_6(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_tables(connection, &["cgi_processors"])
+}
+
+fn revert_db_8_to_7(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "php_processors", &["fastcgi_pool_size"])
+}
+
+fn revert_db_9_to_8(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "static_file_processors", &["integrity_headers_enabled"])
+}
+
+fn revert_db_10_to_9(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_tables(connection, &["python_processors", "python_handlers"])
+}
+
+fn revert_db_11_to_10(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_tables(connection, &["node_processors", "node_handlers"])
+}
+
+fn revert_db_12_to_11(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_tables(connection, &["auth_providers"])
+}
+
+fn revert_db_13_to_12(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "auth_providers", &["ldap_search_base_dn", "ldap_user_attribute", "ldap_group_attribute"])
+}
+
+fn revert_db_14_to_13(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "auth_providers", &["kerberos_keytab_file"])
+}
+
+fn revert_db_15_to_14(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "php_cgi_handlers", &["max_queue_length", "queue_timeout"])?;
+    for table in ["python_handlers", "node_handlers"] {
+        drop_columns(connection, table, &["max_concurrent_requests", "max_queue_length", "queue_timeout"])?;
+    }
+    Ok(())
+}
+
+fn revert_db_16_to_15(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sessions", &["client_binding"])
+}
+
+fn revert_db_17_to_16(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["php_ini_settings", "php_environment"])
+}
+
+fn revert_db_18_to_17(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["sendfile_root"])
+}
+
+fn revert_db_19_to_18(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "users", &["site_scope"])
+}
+
+fn revert_db_20_to_19(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "proxy_processors", &["streaming_paths", "streaming_timeout_seconds"])
+}
+
+fn revert_db_21_to_20(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_tables(connection, &["site_traffic"])
+}
+
+fn revert_db_22_to_21(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(
+        connection,
+        "proxy_processors",
+        &["tls_ca_bundle_path", "tls_server_name", "tls_client_cert_path", "tls_client_key_path"],
+    )
+}
+
+fn revert_db_23_to_22(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["websocket"])
+}
+
+fn revert_db_24_to_23(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "proxy_processors", &["pool_max_idle_per_host", "pool_idle_timeout_seconds", "upstream_http2_only"])
+}
+
+fn revert_db_25_to_24(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(
+        connection,
+        "proxy_processors",
+        &["connect_timeout_seconds", "total_timeout_seconds", "max_request_body_bytes", "max_response_body_bytes"],
+    )
+}
+
+fn revert_db_26_to_25(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["webroot_sync"])
+}
+
+fn revert_db_27_to_26(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "proxy_processors", &["upstream_groups", "upstream_group_header", "upstream_group_cookie"])
+}
+
+fn revert_db_28_to_27(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["cache_warm"])
+}
+
+fn revert_db_29_to_28(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "bindings", &["http10_strict_close", "keep_alive_timeout_seconds"])
+}
+
+fn revert_db_30_to_29(connection: &Connection) -> Result<(), sqlite::Error> {
+    // The events reference the changes, so they go first
+    drop_tables(connection, &["scheduled_change_events", "scheduled_changes"])
+}
+
+fn revert_db_31_to_30(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_tables(connection, &["acme_cache"])
+}
+
+fn revert_db_32_to_31(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(
+        connection,
+        "proxy_processors",
+        &["request_headers_allowed", "request_headers_denied", "response_headers_allowed", "response_headers_denied"],
+    )
+}
+
+fn revert_db_33_to_32(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "bindings", &["http_versions"])
+}
+
+fn revert_db_34_to_33(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "php_cgi_handlers", &["max_requests_per_worker", "max_worker_lifetime"])?;
+    drop_columns(connection, "python_handlers", &["max_requests_per_worker", "max_worker_lifetime"])
+}
+
+fn revert_db_35_to_34(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_tables(connection, &["command_hooks"])
+}
+
+fn revert_db_36_to_35(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["error_response_format"])
+}
+
+fn revert_db_37_to_36(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "bindings", &["max_keep_alive_requests"])
+}
+
+fn revert_db_38_to_37(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["bandwidth"])
+}
+
+fn revert_db_39_to_38(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "bindings", &["sni_mismatch_policy"])
+}
+
+fn revert_db_40_to_39(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "bindings", &["default_site_id", "unknown_host_policy"])
+}
+
+fn revert_db_41_to_40(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["waf"])
+}
+
+fn revert_db_42_to_41(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["bots"])
+}
+
+fn revert_db_43_to_42(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["middleware"])
+}
+
+fn revert_db_44_to_43(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["plugins"])
+}
+
+fn revert_db_45_to_44(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["lua_hooks"])
+}
+
+fn revert_db_46_to_45(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "static_file_processors", &["ssi_enabled"])
+}
+
+fn revert_db_47_to_46(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["disk_quota_mb"])
+}
+
+fn revert_db_48_to_47(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_tables(connection, &["scheduled_tasks"])
+}
+
+fn revert_db_49_to_48(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["synthetic_probe"])
+}
+
+fn revert_db_50_to_49(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["cache_header_rules"])
+}
+
+fn revert_db_51_to_50(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_tables(connection, &["image_processors"])
+}
+
+fn revert_db_52_to_51(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_columns(connection, "sites", &["acme"])
+}
+
+fn revert_db_53_to_52(connection: &Connection) -> Result<(), sqlite::Error> {
+    drop_tables(connection, &["acme_certificates"])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::database_schema::DatabaseSchema;
+
+    fn get_columns(connection: &Connection) -> Vec<String> {
+        let tables = crate::database::data_access::query(connection, "SELECT name FROM sqlite_master WHERE type = 'table' AND name != 'sqlite_sequence'", &[], |row| {
+            row.get_string("name")
+        })
+        .unwrap();
+        let mut columns = Vec::new();
+        for table in tables {
+            let table_columns = crate::database::data_access::query(connection, &format!("PRAGMA table_info({})", table), &[], |row| row.get_string("name")).unwrap();
+            columns.extend(table_columns.into_iter().map(|column| format!("{}.{}", table, column)));
+        }
+        columns.sort();
+        columns
+    }
+
+    #[test]
+    fn test_migrations_cover_every_version() {
+        let versions: Vec<i32> = MIGRATIONS.iter().map(|migration| migration.version).collect();
+        let expected: Vec<i32> = (OLDEST_SCHEMA_VERSION + 1..=CURRENT_DB_SCHEMA_VERSION).collect();
+        assert_eq!(versions, expected);
+    }
+
+    #[test]
+    fn test_plan_migrations() {
+        let up = plan_migrations(30, 32).unwrap();
+        assert_eq!(up.iter().map(|step| (step.from_version(), step.to_version())).collect::<Vec<_>>(), [(30, 31), (31, 32)]);
+
+        let down = plan_migrations(32, 30).unwrap();
+        assert_eq!(down.iter().map(|step| (step.from_version(), step.to_version())).collect::<Vec<_>>(), [(32, 31), (31, 30)]);
+
+        assert!(plan_migrations(CURRENT_DB_SCHEMA_VERSION, CURRENT_DB_SCHEMA_VERSION).unwrap().is_empty());
+        assert!(plan_migrations(1, CURRENT_DB_SCHEMA_VERSION).is_err());
+        assert!(plan_migrations(CURRENT_DB_SCHEMA_VERSION, CURRENT_DB_SCHEMA_VERSION + 1).is_err());
+    }
+
+    #[test]
+    fn test_migrations_down_and_up_again() {
+        let connection = sqlite::open(":memory:").unwrap();
+        for sql in DatabaseSchema::new().init_sql {
+            connection.execute(&sql).unwrap();
+        }
+        execute(&connection, "UPDATE gruxi SET gruxi_value = ? WHERE gruxi_key = 'schema_version'", &[&CURRENT_DB_SCHEMA_VERSION]).unwrap();
+        let current_columns = get_columns(&connection);
+
+        // A dry run leaves the database as it was
+        dry_run_migrations(&connection, &plan_migrations(CURRENT_DB_SCHEMA_VERSION, OLDEST_SCHEMA_VERSION).unwrap()).unwrap();
+        assert_eq!(get_columns(&connection), current_columns);
+
+        apply_migrations(&connection, &plan_migrations(CURRENT_DB_SCHEMA_VERSION, OLDEST_SCHEMA_VERSION).unwrap()).unwrap();
+        assert_eq!(read_schema_version(&connection).unwrap(), OLDEST_SCHEMA_VERSION);
+        assert!(!get_columns(&connection).contains(&"sites.locations".to_string()));
+
+        // As at startup, the tables missing at the old version are created with all their columns before migrating
+        for sql in DatabaseSchema::new().init_sql {
+            connection.execute(&sql).unwrap();
+        }
+        apply_migrations(&connection, &plan_migrations(OLDEST_SCHEMA_VERSION, CURRENT_DB_SCHEMA_VERSION).unwrap()).unwrap();
+        assert_eq!(read_schema_version(&connection).unwrap(), CURRENT_DB_SCHEMA_VERSION);
+        assert_eq!(get_columns(&connection), current_columns);
+    }
+}
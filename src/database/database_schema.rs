@@ -1,8 +1,9 @@
-use sqlite::State;
+use crate::core::database_connection::{get_database_connection, get_database_writer};
+use crate::database::data_access::{execute, query_one};
+use crate::error::gruxi_error::GruxiError;
+use crate::error::gruxi_error_enums::DatabaseError;
 
-use crate::core::database_connection::get_database_connection;
-
-pub const CURRENT_DB_SCHEMA_VERSION: i32 = 4;
+pub const CURRENT_DB_SCHEMA_VERSION: i32 = 53;
 
 pub struct DatabaseSchema {
     pub version: i32,
@@ -20,13 +21,15 @@ impl DatabaseSchema {
     }
 }
 
-pub fn initialize_database() -> Result<(), String> {
-    let connection = get_database_connection()?;
+pub fn initialize_database() -> Result<(), GruxiError> {
+    let connection = get_database_writer()?;
 
     // Get database schema and apply it
     let database_schema = DatabaseSchema::new();
     for sql in database_schema.init_sql {
-        connection.execute(&sql).map_err(|e| format!("Failed to execute init SQL: {}. Error: {}", sql, e))?;
+        connection
+            .execute(&sql)
+            .map_err(|e| GruxiError::database(DatabaseError::Query, format!("Failed to execute init SQL: {}. Error: {}", sql, e)))?;
     }
 
     Ok(())
@@ -41,36 +44,22 @@ pub fn get_schema_version() -> i32 {
         }
     };
 
-    let statement_result = connection.prepare("SELECT gruxi_value FROM gruxi WHERE gruxi_key = 'schema_version' LIMIT 1");
-    let mut statement = match statement_result {
-        Ok(s) => s,
-        Err(_) => {
-            return 0;
-        }
-    };
-
-    let state_result = statement.next();
-    let state = match state_result {
-        Ok(s) => s,
-        Err(_) => {
-            return 0;
-        }
-    };
-
-    match state {
-        State::Row => {
-            let version: i64 = statement.read(0).unwrap_or(0);
-            version as i32
-        }
-        State::Done => 0, // No version found, assume 0
+    // No version found, assume 0
+    match query_one(&connection, "SELECT gruxi_value FROM gruxi WHERE gruxi_key = 'schema_version' LIMIT 1", &[], |row| {
+        row.get_i64("gruxi_value")
+    }) {
+        Ok(Some(version)) => version as i32,
+        _ => 0,
     }
 }
 
-pub fn set_schema_version(version: i32) -> Result<(), String> {
-    let connection = get_database_connection()?;
-    connection
-        .execute(format!("UPDATE gruxi SET gruxi_value = '{}' WHERE gruxi_key = 'schema_version';", version))
-        .map_err(|e| format!("Failed to set schema version: {}", e))?;
+pub fn set_schema_version(version: i32) -> Result<(), GruxiError> {
+    let connection = get_database_writer()?;
+    execute(&connection, "UPDATE gruxi SET gruxi_value = ? WHERE gruxi_key = 'schema_version'", &[&version]).map_err(|e| e.with_context("Failed to set schema version"))?;
+    // The version is saved with the configuration, so it is missing when the configuration is kept in another storage
+    if connection.change_count() == 0 {
+        execute(&connection, "INSERT INTO gruxi (gruxi_key, gruxi_value) VALUES ('schema_version', ?)", &[&version]).map_err(|e| e.with_context("Failed to set schema version"))?;
+    }
     Ok(())
 }
 
@@ -101,7 +90,14 @@ fn get_init_sql() -> Vec<String> {
         ip TEXT NOT NULL,
         port INTEGER NOT NULL,
         is_admin BOOLEAN NOT NULL DEFAULT 0,
-        is_tls BOOLEAN NOT NULL DEFAULT 0
+        is_tls BOOLEAN NOT NULL DEFAULT 0,
+        http10_strict_close BOOLEAN NOT NULL DEFAULT 0,
+        keep_alive_timeout_seconds INTEGER NOT NULL DEFAULT 0,
+        max_keep_alive_requests INTEGER NOT NULL DEFAULT 0,
+        http_versions TEXT NOT NULL DEFAULT 'auto',
+        sni_mismatch_policy TEXT NOT NULL DEFAULT 'allow',
+        default_site_id TEXT NOT NULL DEFAULT '',
+        unknown_host_policy TEXT NOT NULL DEFAULT 'default'
     );"
         .to_string(),
         // Sites table
@@ -119,7 +115,25 @@ fn get_init_sql() -> Vec<String> {
         access_log_enabled BOOLEAN NOT NULL DEFAULT 0,
         access_log_file TEXT NOT NULL DEFAULT '',
         extra_headers TEXT NOT NULL DEFAULT '',
-        tls_automatic_enabled BOOLEAN NOT NULL DEFAULT 0
+        tls_automatic_enabled BOOLEAN NOT NULL DEFAULT 0,
+        locations TEXT NOT NULL DEFAULT '',
+        php_ini_settings TEXT NOT NULL DEFAULT '',
+        php_environment TEXT NOT NULL DEFAULT '',
+        sendfile_root TEXT NOT NULL DEFAULT '',
+        websocket TEXT NOT NULL DEFAULT '',
+        webroot_sync TEXT NOT NULL DEFAULT '',
+        cache_warm TEXT NOT NULL DEFAULT '',
+        error_response_format TEXT NOT NULL DEFAULT 'html',
+        bandwidth TEXT NOT NULL DEFAULT '',
+        waf TEXT NOT NULL DEFAULT '',
+        bots TEXT NOT NULL DEFAULT '',
+        middleware TEXT NOT NULL DEFAULT '',
+        plugins TEXT NOT NULL DEFAULT '',
+        lua_hooks TEXT NOT NULL DEFAULT '',
+        disk_quota_mb INTEGER NOT NULL DEFAULT 0,
+        synthetic_probe TEXT NOT NULL DEFAULT '',
+        cache_header_rules TEXT NOT NULL DEFAULT '',
+        acme TEXT NOT NULL DEFAULT ''
     );"
         .to_string(),
         // Junction table for many-to-many relationship between bindings and sites
@@ -146,7 +160,9 @@ fn get_init_sql() -> Vec<String> {
         "CREATE TABLE IF NOT EXISTS static_file_processors (
         id TEXT PRIMARY KEY,
         web_root TEXT NOT NULL DEFAULT '',
-        web_root_index_file_list TEXT NOT NULL DEFAULT ''
+        web_root_index_file_list TEXT NOT NULL DEFAULT '',
+        integrity_headers_enabled BOOLEAN NOT NULL DEFAULT 0,
+        ssi_enabled BOOLEAN NOT NULL DEFAULT 0
     );"
         .to_string(),
         // PHP processors table
@@ -158,7 +174,8 @@ fn get_init_sql() -> Vec<String> {
         request_timeout INTEGER NOT NULL DEFAULT 30,
         local_web_root TEXT NOT NULL DEFAULT '',
         fastcgi_web_root TEXT NOT NULL DEFAULT '',
-        server_software_spoof TEXT NOT NULL DEFAULT ''
+        server_software_spoof TEXT NOT NULL DEFAULT '',
+        fastcgi_pool_size INTEGER NOT NULL DEFAULT 8
     );"
         .to_string(),
         // Proxy processors table
@@ -174,7 +191,76 @@ fn get_init_sql() -> Vec<String> {
         url_rewrites TEXT NOT NULL DEFAULT '',
         preserve_host_header BOOLEAN NOT NULL DEFAULT 0,
         forced_host_header TEXT NOT NULL DEFAULT '',
-        verify_tls_certificates BOOLEAN NOT NULL DEFAULT 1
+        verify_tls_certificates BOOLEAN NOT NULL DEFAULT 1,
+        streaming_paths TEXT NOT NULL DEFAULT '',
+        streaming_timeout_seconds INTEGER NOT NULL DEFAULT 0,
+        tls_ca_bundle_path TEXT NOT NULL DEFAULT '',
+        tls_server_name TEXT NOT NULL DEFAULT '',
+        tls_client_cert_path TEXT NOT NULL DEFAULT '',
+        tls_client_key_path TEXT NOT NULL DEFAULT '',
+        pool_max_idle_per_host INTEGER NOT NULL DEFAULT 0,
+        pool_idle_timeout_seconds INTEGER NOT NULL DEFAULT 0,
+        upstream_http2_only BOOLEAN NOT NULL DEFAULT 0,
+        connect_timeout_seconds INTEGER NOT NULL DEFAULT 0,
+        total_timeout_seconds INTEGER NOT NULL DEFAULT 0,
+        max_request_body_bytes INTEGER NOT NULL DEFAULT 0,
+        max_response_body_bytes INTEGER NOT NULL DEFAULT 0,
+        upstream_groups TEXT NOT NULL DEFAULT '',
+        upstream_group_header TEXT NOT NULL DEFAULT '',
+        upstream_group_cookie TEXT NOT NULL DEFAULT '',
+        request_headers_allowed TEXT NOT NULL DEFAULT '',
+        request_headers_denied TEXT NOT NULL DEFAULT '',
+        response_headers_allowed TEXT NOT NULL DEFAULT '',
+        response_headers_denied TEXT NOT NULL DEFAULT ''
+    );"
+        .to_string(),
+        // WebDAV processors table
+        "CREATE TABLE IF NOT EXISTS webdav_processors (
+        id TEXT PRIMARY KEY,
+        web_root TEXT NOT NULL DEFAULT '',
+        read_only BOOLEAN NOT NULL DEFAULT 0,
+        require_authentication BOOLEAN NOT NULL DEFAULT 1,
+        auth_realm TEXT NOT NULL DEFAULT '',
+        auth_users TEXT NOT NULL DEFAULT ''
+    );"
+        .to_string(),
+        // CGI processors table
+        "CREATE TABLE IF NOT EXISTS cgi_processors (
+        id TEXT PRIMARY KEY,
+        cgi_bin_dir TEXT NOT NULL DEFAULT '',
+        url_prefix TEXT NOT NULL DEFAULT '',
+        interpreters TEXT NOT NULL DEFAULT '',
+        request_timeout INTEGER NOT NULL DEFAULT 30
+    );"
+        .to_string(),
+        // Python processors table
+        "CREATE TABLE IF NOT EXISTS python_processors (
+        id TEXT PRIMARY KEY,
+        python_handler_id TEXT NOT NULL DEFAULT '',
+        request_timeout INTEGER NOT NULL DEFAULT 30,
+        preserve_host_header BOOLEAN NOT NULL DEFAULT 1
+    );"
+        .to_string(),
+        // Node.js processors table
+        "CREATE TABLE IF NOT EXISTS node_processors (
+        id TEXT PRIMARY KEY,
+        node_handler_id TEXT NOT NULL DEFAULT '',
+        request_timeout INTEGER NOT NULL DEFAULT 30,
+        preserve_host_header BOOLEAN NOT NULL DEFAULT 1
+    );"
+        .to_string(),
+        // Image processors table
+        "CREATE TABLE IF NOT EXISTS image_processors (
+        id TEXT PRIMARY KEY,
+        web_root TEXT NOT NULL DEFAULT '',
+        allowed_paths TEXT NOT NULL DEFAULT '',
+        cache_dir TEXT NOT NULL DEFAULT '',
+        converter_path TEXT NOT NULL DEFAULT 'magick',
+        max_width INTEGER NOT NULL DEFAULT 4096,
+        allowed_widths TEXT NOT NULL DEFAULT '',
+        default_quality INTEGER NOT NULL DEFAULT 80,
+        max_concurrent_conversions INTEGER NOT NULL DEFAULT 2,
+        request_timeout INTEGER NOT NULL DEFAULT 30
     );"
         .to_string(),
         // PHP-CGI handlers table
@@ -183,7 +269,77 @@ fn get_init_sql() -> Vec<String> {
         name TEXT NOT NULL DEFAULT '',
         request_timeout INTEGER NOT NULL DEFAULT 30,
         concurrent_threads INTEGER NOT NULL DEFAULT 0,
-        executable TEXT NOT NULL DEFAULT ''
+        executable TEXT NOT NULL DEFAULT '',
+        max_queue_length INTEGER NOT NULL DEFAULT 100,
+        queue_timeout INTEGER NOT NULL DEFAULT 30,
+        max_requests_per_worker INTEGER NOT NULL DEFAULT 10000,
+        max_worker_lifetime INTEGER NOT NULL DEFAULT 0
+    );"
+        .to_string(),
+        // Python handlers table
+        "CREATE TABLE IF NOT EXISTS python_handlers (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL DEFAULT '',
+        server_type TEXT NOT NULL DEFAULT '',
+        executable TEXT NOT NULL DEFAULT '',
+        app_module TEXT NOT NULL DEFAULT '',
+        working_directory TEXT NOT NULL DEFAULT '',
+        workers INTEGER NOT NULL DEFAULT 1,
+        extra_arguments TEXT NOT NULL DEFAULT '',
+        extra_environment TEXT NOT NULL DEFAULT '',
+        health_check_path TEXT NOT NULL DEFAULT '',
+        max_concurrent_requests INTEGER NOT NULL DEFAULT 0,
+        max_queue_length INTEGER NOT NULL DEFAULT 100,
+        queue_timeout INTEGER NOT NULL DEFAULT 30,
+        max_requests_per_worker INTEGER NOT NULL DEFAULT 0,
+        max_worker_lifetime INTEGER NOT NULL DEFAULT 0
+    );"
+        .to_string(),
+        // Node.js handlers table
+        "CREATE TABLE IF NOT EXISTS node_handlers (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL DEFAULT '',
+        executable TEXT NOT NULL DEFAULT '',
+        entry_script TEXT NOT NULL DEFAULT '',
+        working_directory TEXT NOT NULL DEFAULT '',
+        extra_arguments TEXT NOT NULL DEFAULT '',
+        extra_environment TEXT NOT NULL DEFAULT '',
+        health_check_path TEXT NOT NULL DEFAULT '',
+        max_concurrent_requests INTEGER NOT NULL DEFAULT 0,
+        max_queue_length INTEGER NOT NULL DEFAULT 100,
+        queue_timeout INTEGER NOT NULL DEFAULT 30
+    );"
+        .to_string(),
+        // Auth providers table
+        "CREATE TABLE IF NOT EXISTS auth_providers (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL DEFAULT '',
+        provider_type TEXT NOT NULL DEFAULT '',
+        htpasswd_file TEXT NOT NULL DEFAULT '',
+        ldap_url TEXT NOT NULL DEFAULT '',
+        ldap_bind_dn_template TEXT NOT NULL DEFAULT '',
+        oidc_token_url TEXT NOT NULL DEFAULT '',
+        oidc_client_id TEXT NOT NULL DEFAULT '',
+        oidc_client_secret TEXT NOT NULL DEFAULT '',
+        oidc_scope TEXT NOT NULL DEFAULT '',
+        timeout_seconds INTEGER NOT NULL DEFAULT 10,
+        cache_seconds INTEGER NOT NULL DEFAULT 60,
+        ldap_search_base_dn TEXT NOT NULL DEFAULT '',
+        ldap_user_attribute TEXT NOT NULL DEFAULT 'sAMAccountName',
+        ldap_group_attribute TEXT NOT NULL DEFAULT 'memberOf',
+        kerberos_keytab_file TEXT NOT NULL DEFAULT ''
+    );"
+        .to_string(),
+        // Command hooks table
+        "CREATE TABLE IF NOT EXISTS command_hooks (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL DEFAULT '',
+        event TEXT NOT NULL DEFAULT '',
+        command TEXT NOT NULL DEFAULT '',
+        arguments TEXT NOT NULL DEFAULT '[]',
+        working_directory TEXT NOT NULL DEFAULT '',
+        timeout_seconds INTEGER NOT NULL DEFAULT 30,
+        is_enabled INTEGER NOT NULL DEFAULT 1
     );"
         .to_string(),
         // Users table for admin portal
@@ -193,7 +349,8 @@ fn get_init_sql() -> Vec<String> {
                 password_hash TEXT NOT NULL,
                 created_at TEXT NOT NULL,
                 last_login TEXT,
-                is_active BOOLEAN NOT NULL DEFAULT 1
+                is_active BOOLEAN NOT NULL DEFAULT 1,
+                site_scope TEXT
             )"
         .to_string(),
         // User session table
@@ -204,8 +361,101 @@ fn get_init_sql() -> Vec<String> {
                 token TEXT NOT NULL UNIQUE,
                 expires_at TEXT NOT NULL,
                 created_at TEXT NOT NULL,
+                client_binding TEXT NOT NULL DEFAULT '',
                 FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
             )"
         .to_string(),
+        // Traffic per site and day (UTC), for usage reports
+        "CREATE TABLE IF NOT EXISTS site_traffic (
+                site_id TEXT NOT NULL,
+                day TEXT NOT NULL,
+                requests INTEGER NOT NULL DEFAULT 0,
+                bytes_sent INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (site_id, day)
+            )"
+        .to_string(),
+        // Configuration changes scheduled for a later time, as the fields to change in JSON
+        "CREATE TABLE IF NOT EXISTS scheduled_changes (
+                id TEXT PRIMARY KEY,
+                description TEXT NOT NULL DEFAULT '',
+                scheduled_at TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'pending',
+                changes TEXT NOT NULL DEFAULT '',
+                created_by TEXT NOT NULL DEFAULT '',
+                created_at TEXT NOT NULL
+            )"
+        .to_string(),
+        // Audit trail of scheduled configuration changes
+        "CREATE TABLE IF NOT EXISTS scheduled_change_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                change_id TEXT NOT NULL,
+                event TEXT NOT NULL,
+                username TEXT NOT NULL DEFAULT '',
+                details TEXT NOT NULL DEFAULT '',
+                created_at TEXT NOT NULL,
+                FOREIGN KEY (change_id) REFERENCES scheduled_changes (id) ON DELETE CASCADE
+            )"
+        .to_string(),
+        // ACME accounts and certificates, when they are cached in the database
+        get_acme_cache_schema(),
+        // Every certificate issued or renewed through ACME, for auditing
+        get_acme_certificates_schema(),
+        // Recurring maintenance jobs on a cron schedule, with the status of their last run
+        "CREATE TABLE IF NOT EXISTS scheduled_tasks (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL DEFAULT '',
+                job_type TEXT NOT NULL,
+                schedule TEXT NOT NULL,
+                parameters TEXT NOT NULL DEFAULT '',
+                is_enabled BOOLEAN NOT NULL DEFAULT 1,
+                created_by TEXT NOT NULL DEFAULT '',
+                created_at TEXT NOT NULL,
+                last_run_at TEXT NOT NULL DEFAULT '',
+                last_status TEXT NOT NULL DEFAULT '',
+                last_message TEXT NOT NULL DEFAULT '',
+                last_duration_ms INTEGER NOT NULL DEFAULT 0
+            )"
+        .to_string(),
     ]
 }
+
+// Sessions table in the database shared by replicas of the admin portal. Users are local to each node, so there is no foreign key
+pub fn get_shared_session_schema() -> String {
+    "CREATE TABLE IF NOT EXISTS sessions (
+                id TEXT PRIMARY KEY,
+                user_id INTEGER NOT NULL,
+                username TEXT NOT NULL,
+                token TEXT NOT NULL UNIQUE,
+                expires_at TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                client_binding TEXT NOT NULL DEFAULT ''
+            )"
+    .to_string()
+}
+
+// Certificates issued through ACME, with the domains comma separated
+pub fn get_acme_certificates_schema() -> String {
+    "CREATE TABLE IF NOT EXISTS acme_certificates (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                domains TEXT NOT NULL,
+                serial TEXT NOT NULL,
+                not_before TEXT NOT NULL,
+                not_after TEXT NOT NULL,
+                provider TEXT NOT NULL DEFAULT '',
+                directory_url TEXT NOT NULL DEFAULT '',
+                key_type TEXT NOT NULL DEFAULT 'ecdsa',
+                event TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            )"
+    .to_string()
+}
+
+// ACME accounts and certificates, keyed by the file names of the ACME directory cache. Also created in a shared ACME cache database
+pub fn get_acme_cache_schema() -> String {
+    "CREATE TABLE IF NOT EXISTS acme_cache (
+                cache_key TEXT PRIMARY KEY,
+                cache_value BLOB NOT NULL,
+                updated_at TEXT NOT NULL
+            )"
+    .to_string()
+}
@@ -1,2 +1,6 @@
+pub mod configuration_storage;
+pub mod data_access;
+pub mod database_backup;
+pub mod database_migration;
 pub mod database_schema;
-pub mod database_migration;
\ No newline at end of file
+pub mod postgres_storage;
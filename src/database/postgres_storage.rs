@@ -0,0 +1,233 @@
+use std::sync::Mutex;
+
+use chrono::Utc;
+
+use crate::configuration::configuration::{CURRENT_CONFIGURATION_VERSION, Configuration};
+use crate::configuration::deprecated_fields::{DeprecationWarning, find_deprecated_fields, rename_deprecated_fields};
+use crate::core::admin_user::Session;
+use crate::database::configuration_storage::{ConfigurationStorage, SessionStorage, parse_session_time};
+use crate::error::gruxi_error::GruxiError;
+use crate::error::gruxi_error_enums::{ConfigurationError, DatabaseError};
+use crate::network::postgres_client::{PostgresClient, PostgresSettings};
+
+const MAX_IDLE_CLIENTS: usize = 4;
+
+// The configuration is kept as one JSON document, the same as an export, so the tables do not need migrations of their
+// own. The revision goes up with every save, which is what the other nodes watch
+const POSTGRES_SCHEMA: [&str; 2] = [
+    "CREATE TABLE IF NOT EXISTS gruxi_configuration (
+        id INTEGER PRIMARY KEY,
+        revision BIGINT NOT NULL,
+        configuration TEXT NOT NULL,
+        updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+    )",
+    "CREATE TABLE IF NOT EXISTS gruxi_sessions (
+        id TEXT PRIMARY KEY,
+        user_id BIGINT NOT NULL,
+        username TEXT NOT NULL,
+        token TEXT NOT NULL UNIQUE,
+        expires_at TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        client_binding TEXT NOT NULL DEFAULT ''
+    )",
+];
+
+pub struct PostgresConfigurationStorage {
+    settings: PostgresSettings,
+    idle_clients: Mutex<Vec<PostgresClient>>,
+}
+
+impl PostgresConfigurationStorage {
+    /// Connect, and create the tables when they do not exist yet
+    pub fn connect(settings: PostgresSettings) -> Result<Self, GruxiError> {
+        let storage = PostgresConfigurationStorage {
+            settings,
+            idle_clients: Mutex::new(Vec::new()),
+        };
+        storage.with_client(|client| {
+            for sql in POSTGRES_SCHEMA {
+                client.execute(sql, &[])?;
+            }
+            Ok(())
+        })?;
+        Ok(storage)
+    }
+
+    // Run an operation on an idle client, or a new one. The server may have closed an idle connection in the meantime,
+    // so an operation that breaks a client from the pool is tried once more on a new one. Failing to connect is a
+    // connection error, anything the operation fails with a query error
+    fn with_client<T>(&self, operation: impl Fn(&mut PostgresClient) -> Result<T, String>) -> Result<T, GruxiError> {
+        let idle_client = self.idle_clients.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).pop();
+        let (mut client, mut result) = match idle_client {
+            Some(mut client) => {
+                let result = operation(&mut client);
+                (client, result)
+            }
+            None => {
+                let mut client = PostgresClient::connect(&self.settings).map_err(|e| GruxiError::database(DatabaseError::Connection, e))?;
+                let result = operation(&mut client);
+                (client, result)
+            }
+        };
+        if client.is_broken() && result.is_err() {
+            client = PostgresClient::connect(&self.settings).map_err(|e| GruxiError::database(DatabaseError::Connection, e))?;
+            result = operation(&mut client);
+        }
+
+        if !client.is_broken() {
+            let mut idle_clients = self.idle_clients.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if idle_clients.len() < MAX_IDLE_CLIENTS {
+                idle_clients.push(client);
+            }
+        }
+        result.map_err(|e| GruxiError::database(DatabaseError::Query, e))
+    }
+
+    fn load_configuration_json(&self) -> Result<String, GruxiError> {
+        let rows = self.with_client(|client| client.query("SELECT configuration FROM gruxi_configuration WHERE id = 1", &[]))?;
+        rows.into_iter()
+            .next()
+            .and_then(|row| row.into_iter().next().flatten())
+            .ok_or_else(|| GruxiError::configuration(ConfigurationError::Load, "No configuration was saved in PostgreSQL yet"))
+    }
+
+    fn save_configuration_json(&self, json: String) -> Result<(), GruxiError> {
+        self.with_client(|client| {
+            client.execute(
+                "INSERT INTO gruxi_configuration (id, revision, configuration, updated_at) VALUES (1, 1, $1, now())
+                 ON CONFLICT (id) DO UPDATE SET revision = gruxi_configuration.revision + 1, configuration = EXCLUDED.configuration, updated_at = now()",
+                &[Some(&json)],
+            )
+        })?;
+        Ok(())
+    }
+}
+
+impl ConfigurationStorage for PostgresConfigurationStorage {
+    fn get_name(&self) -> &'static str {
+        "PostgreSQL"
+    }
+
+    fn is_shared(&self) -> bool {
+        true
+    }
+
+    fn has_configuration(&self) -> Result<bool, GruxiError> {
+        self.with_client(|client| Ok(!client.query("SELECT id FROM gruxi_configuration WHERE id = 1", &[])?.is_empty()))
+    }
+
+    fn load_configuration(&self) -> Result<Configuration, GruxiError> {
+        let json = self.load_configuration_json()?;
+        let mut configuration: Configuration = serde_json::from_str(&json).map_err(parse_error)?;
+        // Fields added since an older node saved it get their defaults, but a newer node may have saved fields this one would drop
+        if configuration.version > CURRENT_CONFIGURATION_VERSION {
+            return Err(GruxiError::configuration(
+                ConfigurationError::Load,
+                format!(
+                    "The configuration stored in PostgreSQL has version {}, which is newer than the version {} of this Gruxi",
+                    configuration.version, CURRENT_CONFIGURATION_VERSION
+                ),
+            ));
+        }
+        configuration.version = CURRENT_CONFIGURATION_VERSION;
+        configuration.sanitize();
+        Ok(configuration)
+    }
+
+    fn save_configuration(&self, configuration: &Configuration) -> Result<(), GruxiError> {
+        let json = serde_json::to_string(configuration).map_err(|e| GruxiError::configuration(ConfigurationError::Serialization, format!("Failed to serialize configuration: {}", e)))?;
+        self.save_configuration_json(json)
+    }
+
+    fn get_revision(&self) -> Result<String, GruxiError> {
+        let rows = self.with_client(|client| client.query("SELECT revision::text FROM gruxi_configuration WHERE id = 1", &[]))?;
+        Ok(rows.into_iter().next().and_then(|row| row.into_iter().next().flatten()).unwrap_or_default())
+    }
+
+    // The sessions are shared by all nodes already, so the shared session database is not used
+    fn get_session_storage(&self, _shared_session_database: &str) -> Result<Box<dyn SessionStorage + '_>, GruxiError> {
+        Ok(Box::new(PostgresSessionStorage { storage: self }))
+    }
+
+    fn get_deprecated_fields(&self) -> Result<Vec<DeprecationWarning>, GruxiError> {
+        let configuration: serde_json::Value = serde_json::from_str(&self.load_configuration_json()?).map_err(parse_error)?;
+        Ok(find_deprecated_fields(&configuration))
+    }
+
+    // The JSON is rewritten as is, so fields this Gruxi does not know of yet are kept
+    fn migrate_deprecated_fields(&self, dry_run: bool) -> Result<Vec<DeprecationWarning>, GruxiError> {
+        let mut configuration: serde_json::Value = serde_json::from_str(&self.load_configuration_json()?).map_err(parse_error)?;
+        let warnings = rename_deprecated_fields(&mut configuration);
+        if !dry_run && !warnings.is_empty() {
+            self.save_configuration_json(configuration.to_string())?;
+        }
+        Ok(warnings)
+    }
+}
+
+pub struct PostgresSessionStorage<'a> {
+    storage: &'a PostgresConfigurationStorage,
+}
+
+impl SessionStorage for PostgresSessionStorage<'_> {
+    fn insert_session(&self, session: &Session) -> Result<(), GruxiError> {
+        let (user_id, expires_at, created_at) = (session.user_id.to_string(), session.expires_at.to_rfc3339(), session.created_at.to_rfc3339());
+        self.storage.with_client(|client| {
+            client.execute(
+                "INSERT INTO gruxi_sessions (id, user_id, username, token, expires_at, created_at, client_binding) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                &[
+                    Some(&session.id),
+                    Some(&user_id),
+                    Some(&session.username),
+                    Some(&session.token),
+                    Some(&expires_at),
+                    Some(&created_at),
+                    Some(&session.client_binding),
+                ],
+            )
+        })?;
+        Ok(())
+    }
+
+    fn get_session_by_token(&self, token: &str) -> Result<Option<Session>, GruxiError> {
+        let rows = self.storage.with_client(|client| {
+            client.query(
+                "SELECT id, user_id::text, username, token, expires_at, created_at, client_binding FROM gruxi_sessions WHERE token = $1",
+                &[Some(token)],
+            )
+        })?;
+        let Some(row) = rows.into_iter().next() else {
+            return Ok(None);
+        };
+
+        let column = |index: usize| row.get(index).cloned().flatten().unwrap_or_default();
+        Ok(Some(Session {
+            id: column(0),
+            user_id: column(1).parse().map_err(|e| GruxiError::database(DatabaseError::Read, format!("Failed to parse user_id: {}", e)))?,
+            username: column(2),
+            token: column(3),
+            expires_at: parse_session_time(&column(4), "expires_at")?,
+            created_at: parse_session_time(&column(5), "created_at")?,
+            client_binding: column(6),
+        }))
+    }
+
+    fn delete_session(&self, token: &str) -> Result<bool, GruxiError> {
+        let deleted = self.storage.with_client(|client| client.execute("DELETE FROM gruxi_sessions WHERE token = $1", &[Some(token)]))?;
+        Ok(deleted > 0)
+    }
+
+    fn delete_sessions_for_user(&self, username: &str) -> Result<(), GruxiError> {
+        self.storage.with_client(|client| client.execute("DELETE FROM gruxi_sessions WHERE username = $1", &[Some(username)]))?;
+        Ok(())
+    }
+
+    fn delete_expired_sessions(&self) -> Result<u64, GruxiError> {
+        let now = Utc::now().to_rfc3339();
+        self.storage.with_client(|client| client.execute("DELETE FROM gruxi_sessions WHERE expires_at < $1", &[Some(&now)]))
+    }
+}
+
+fn parse_error(error: serde_json::Error) -> GruxiError {
+    GruxiError::configuration(ConfigurationError::Load, format!("Failed to parse the configuration stored in PostgreSQL: {}", error))
+}
@@ -15,10 +15,93 @@ impl GruxiError {
         Self { kind, message: String::new() }
     }
 
+    pub fn configuration(error: ConfigurationError, message: impl Into<String>) -> Self {
+        Self::new(GruxiErrorKind::Configuration(error), message.into())
+    }
+
+    pub fn database(error: DatabaseError, message: impl Into<String>) -> Self {
+        Self::new(GruxiErrorKind::Database(error), message.into())
+    }
+
+    pub fn tls(error: TlsError, message: impl Into<String>) -> Self {
+        Self::new(GruxiErrorKind::Tls(error), message.into())
+    }
+
+    pub fn server(error: ServerError, message: impl Into<String>) -> Self {
+        Self::new(GruxiErrorKind::Server(error), message.into())
+    }
+
+    /// The same error, with what was being done when it happened in front of the message, such as "Failed to save site"
+    pub fn with_context(mut self, context: impl std::fmt::Display) -> Self {
+        self.message = if self.message.is_empty() { context.to_string() } else { format!("{}: {}", context, self.message) };
+        self
+    }
+
     pub fn get_http_status_code(&self) -> u16 {
         match self.kind {
             GruxiErrorKind::HttpRequestValidation(status_code) => status_code,
+            GruxiErrorKind::Configuration(ConfigurationError::Validation(_)) => 400,
+            GruxiErrorKind::Configuration(ConfigurationError::Storage) | GruxiErrorKind::Database(DatabaseError::Connection) => 503,
             _ => 500, // Default to Internal Server Error for other error kinds
         }
     }
+
+    /// The messages to show the user, which are the problems found for a configuration that failed validation. Server side
+    /// failures get a generic message, as their details, such as database errors, belong in the log and not with the client
+    pub fn get_messages(&self) -> Vec<String> {
+        match &self.kind {
+            GruxiErrorKind::Configuration(ConfigurationError::Validation(errors)) => errors.clone(),
+            _ => match self.get_http_status_code() {
+                503 => vec!["The service is temporarily unavailable, please try again later".to_string()],
+                500..=599 => vec!["An internal server error occurred, see the server log for details".to_string()],
+                _ => vec![self.to_string()],
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for GruxiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            GruxiErrorKind::Configuration(ConfigurationError::Validation(errors)) if self.message.is_empty() => write!(f, "{}", errors.join("; ")),
+            kind if self.message.is_empty() => write!(f, "{:?}", kind),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for GruxiError {}
+
+// Most of the crate still reports errors as strings, so a GruxiError can be returned with ? from those functions
+impl From<GruxiError> for String {
+    fn from(error: GruxiError) -> Self {
+        error.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes_and_messages() {
+        let validation = GruxiError::new_with_kind_only(GruxiErrorKind::Configuration(ConfigurationError::Validation(vec![
+            "Site 1: No hostnames".to_string(),
+            "Binding 2: Port 0 is not allowed".to_string(),
+        ])));
+        assert_eq!(validation.get_http_status_code(), 400);
+        assert_eq!(validation.get_messages().len(), 2);
+        assert_eq!(validation.to_string(), "Site 1: No hostnames; Binding 2: Port 0 is not allowed");
+
+        let database = GruxiError::database(DatabaseError::Query, "no such table: sites").with_context("Failed to save site");
+        assert_eq!(database.get_http_status_code(), 500);
+        assert_eq!(database.to_string(), "Failed to save site: no such table: sites");
+        assert!(!database.get_messages().concat().contains("no such table"));
+
+        let message: String = GruxiError::tls(TlsError::PrivateKey, "No private key found").into();
+        assert_eq!(message, "No private key found");
+        let connection = GruxiError::database(DatabaseError::Connection, "unable to open database file: ./db/gruxi.db");
+        assert_eq!(connection.get_http_status_code(), 503);
+        assert!(!connection.get_messages().concat().contains("gruxi.db"));
+    }
 }
@@ -3,10 +3,19 @@ pub enum GruxiErrorKind {
     ProxyProcessor(ProxyProcessorError),
     StaticFileProcessor(StaticFileProcessorError),
     PHPProcessor(PHPProcessorError),
+    WebDavProcessor(WebDavProcessorError),
+    CgiProcessor(CgiProcessorError),
+    PythonProcessor(PythonProcessorError),
+    NodeProcessor(NodeProcessorError),
+    ImageProcessor(ImageProcessorError),
     HttpRequestValidation(u16), // HTTP status code for request validation errors
     FastCgi(FastCgiError),
     Internal(&'static str),
-    AdminApi(AdminApiError)
+    AdminApi(AdminApiError),
+    Configuration(ConfigurationError),
+    Database(DatabaseError),
+    Tls(TlsError),
+    Server(ServerError),
 }
 
 #[derive(Debug)]
@@ -16,6 +25,10 @@ pub enum ProxyProcessorError {
     InvalidResponse,
     UpstreamUnavailable,
     UpstreamTimeout,
+    TooManyWebSocketConnections, // The site has the maximum number of WebSocket connections open
+    RequestBodyTooLarge,         // The request body is larger than the proxy forwards
+    ResponseBodyTooLarge,        // The upstream response body is larger than the proxy relays
+    ClientTimeout,               // The client was still sending its request body when the upstream timed out
     Internal,
 }
 
@@ -33,6 +46,53 @@ pub enum PHPProcessorError {
     PathError(std::io::Error),
     FileNotFound,
     Timeout,
+    Unavailable,
+    Overloaded, // The request queue of the PHP-CGI handler is full, or the request waited too long in it
+    Internal,
+}
+
+#[derive(Debug)]
+pub enum WebDavProcessorError {
+    Io(std::io::Error),
+    UploadScanFailed,
+    Internal,
+}
+
+#[derive(Debug)]
+pub enum CgiProcessorError {
+    ScriptNotFound,
+    Execution(std::io::Error),
+    Timeout,
+    InvalidResponse,
+    Internal,
+}
+
+#[derive(Debug)]
+pub enum PythonProcessorError {
+    HandlerUnavailable,
+    Overloaded, // The request queue of the handler is full, or the request waited too long in it
+    ConnectionFailed,
+    Timeout,
+    Internal,
+}
+
+#[derive(Debug)]
+pub enum NodeProcessorError {
+    HandlerUnavailable,
+    Overloaded, // The request queue of the handler is full, or the request waited too long in it
+    ConnectionFailed,
+    Timeout,
+    Internal,
+}
+
+#[derive(Debug)]
+pub enum ImageProcessorError {
+    NotFound,          // Not an image under the allowed paths, so the next handler gets the request
+    InvalidParameters, // The query parameters ask for a size, quality or format that is not allowed
+    Execution(std::io::Error),
+    ConversionFailed,
+    Timeout,
+    Io(std::io::Error),
     Internal,
 }
 
@@ -41,7 +101,6 @@ pub enum FastCgiError {
     Initialization,
     Connection(std::io::Error),
     Communication(std::io::Error),
-    ConnectionPermitAcquisition,
     Timeout,
     InvalidResponse,
     Internal, // Internal processing errors, that should not happen
@@ -52,3 +111,37 @@ pub enum AdminApiError {
     NoRouteMatched,
     InvalidRequest,
 }
+
+#[derive(Debug)]
+pub enum ConfigurationError {
+    Validation(Vec<String>), // One message per problem found, each shown to the user
+    Load,
+    Save,
+    Serialization,
+    Storage, // The configuration storage could not be set up or reached
+}
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    Connection,
+    Query,
+    Read, // A column could not be read or parsed
+    Transaction,
+    Migration,
+}
+
+#[derive(Debug)]
+pub enum TlsError {
+    Certificate, // A certificate could not be read, parsed or generated
+    PrivateKey,
+    Configuration, // The rustls server configuration could not be built
+    Acme,
+    Storage, // A certificate could not be written to or read from disk
+}
+
+#[derive(Debug)]
+pub enum ServerError {
+    AlreadyRunning, // Only one server runs in a process at a time
+    Startup,
+    Timeout, // The bindings did not start listening, or stop, in time
+}
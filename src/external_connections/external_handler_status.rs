@@ -0,0 +1,164 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU16, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Notify;
+
+// Longest time a request is held while its handler is recycled, before it is sent on anyway
+const RECYCLE_MAX_WAIT: Duration = Duration::from_secs(30);
+
+// Runtime status of an external handler managed by Gruxi. Updated by its monitoring thread and by the processors
+// sending requests to it, and reported in the admin API
+#[derive(Debug, Default)]
+pub struct ExternalHandlerStatus {
+    is_alive: AtomicBool,
+    port: AtomicU16,
+    restart_count: AtomicU32,
+    requests_in_progress: AtomicUsize, // Requests waiting for or being handled by the handler
+    requests_handled: AtomicU64,
+    total_handling_time_micros: AtomicU64,
+    is_recycling: AtomicBool, // The process is being replaced, so new requests are held until the new one is up
+    recycled: Notify,
+}
+
+// Keeps a request counted as in progress, until dropped
+pub struct ExternalHandlerRequestGuard {
+    status: Arc<ExternalHandlerStatus>,
+    started_at: Instant,
+}
+
+impl Drop for ExternalHandlerRequestGuard {
+    fn drop(&mut self) {
+        self.status.requests_in_progress.fetch_sub(1, Ordering::Relaxed);
+        self.status.requests_handled.fetch_add(1, Ordering::Relaxed);
+        self.status.total_handling_time_micros.fetch_add(self.started_at.elapsed().as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+impl ExternalHandlerStatus {
+    pub fn set_process_state(&self, is_alive: bool, port: Option<u16>, restart_count: u32) {
+        self.is_alive.store(is_alive, Ordering::Relaxed);
+        self.port.store(port.unwrap_or(0), Ordering::Relaxed);
+        self.restart_count.store(restart_count, Ordering::Relaxed);
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.is_alive.load(Ordering::Relaxed)
+    }
+
+    // Hold new requests while the process is replaced, or let them through again when done
+    pub fn set_recycling(&self, is_recycling: bool) {
+        self.is_recycling.store(is_recycling, Ordering::Relaxed);
+        if !is_recycling {
+            self.recycled.notify_waiters();
+        }
+    }
+
+    // Wait until the handler is no longer being recycled, so the request does not go to the process that is stopping
+    pub async fn wait_while_recycling(&self) {
+        let _ = tokio::time::timeout(RECYCLE_MAX_WAIT, async {
+            loop {
+                let recycled = self.recycled.notified();
+                if !self.is_recycling.load(Ordering::Relaxed) {
+                    break;
+                }
+                recycled.await;
+            }
+        })
+        .await;
+    }
+
+    // Wait until the requests in progress are done, or the timeout has passed
+    pub async fn drain(&self, timeout: Duration) {
+        let drain_until = Instant::now() + timeout;
+        loop {
+            // Checked after a pause, so requests that just passed wait_while_recycling are counted
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            if self.requests_in_progress.load(Ordering::Relaxed) == 0 || Instant::now() >= drain_until {
+                break;
+            }
+        }
+    }
+
+    // Count a request to the handler, until the returned guard is dropped
+    pub fn begin_request(self: &Arc<Self>) -> ExternalHandlerRequestGuard {
+        self.requests_in_progress.fetch_add(1, Ordering::Relaxed);
+        ExternalHandlerRequestGuard {
+            status: self.clone(),
+            started_at: Instant::now(),
+        }
+    }
+
+    pub fn get_average_handling_time_ms(&self) -> f64 {
+        let requests_handled = self.requests_handled.load(Ordering::Relaxed);
+        if requests_handled == 0 {
+            return 0.0;
+        }
+        self.total_handling_time_micros.load(Ordering::Relaxed) as f64 / requests_handled as f64 / 1000.0
+    }
+
+    pub fn get_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "alive": self.is_alive.load(Ordering::Relaxed),
+            "port": self.port.load(Ordering::Relaxed),
+            "restart_count": self.restart_count.load(Ordering::Relaxed),
+            "queue_depth": self.requests_in_progress.load(Ordering::Relaxed),
+            "requests_handled": self.requests_handled.load(Ordering::Relaxed),
+            "average_handling_time_ms": self.get_average_handling_time_ms(),
+            "recycling": self.is_recycling.load(Ordering::Relaxed),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_external_handler_status_tracks_requests() {
+        let status = Arc::new(ExternalHandlerStatus::default());
+        status.set_process_state(true, Some(9000), 2);
+
+        let first_request = status.begin_request();
+        let second_request = status.begin_request();
+        assert_eq!(status.get_json()["queue_depth"], 2);
+        drop(first_request);
+        drop(second_request);
+
+        let json = status.get_json();
+        assert_eq!(json["alive"], true);
+        assert_eq!(json["port"], 9000);
+        assert_eq!(json["restart_count"], 2);
+        assert_eq!(json["queue_depth"], 0);
+        assert_eq!(json["requests_handled"], 2);
+        assert!(status.get_average_handling_time_ms() >= 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_external_handler_status_holds_requests_while_recycling() {
+        let status = Arc::new(ExternalHandlerStatus::default());
+        status.set_recycling(true);
+
+        let waiting_status = status.clone();
+        let waiting_request = tokio::spawn(async move { waiting_status.wait_while_recycling().await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting_request.is_finished());
+        assert_eq!(status.get_json()["recycling"], true);
+
+        status.set_recycling(false);
+        tokio::time::timeout(Duration::from_secs(1), waiting_request).await.unwrap().unwrap();
+
+        // Draining returns once the requests in progress are done
+        let request = status.begin_request();
+        let drain_status = status.clone();
+        let drain = tokio::spawn(async move { drain_status.drain(Duration::from_secs(10)).await });
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert!(!drain.is_finished());
+        drop(request);
+        tokio::time::timeout(Duration::from_secs(1), drain).await.unwrap().unwrap();
+    }
+}
@@ -1,26 +1,48 @@
-use std::{collections::HashMap, sync::Arc};
-
-use tokio::sync::Semaphore;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 
 use crate::{
-    external_connections::managed_system::php_cgi::PhpCgi,
+    external_connections::{
+        external_handler_status::ExternalHandlerStatus,
+        handler_request_queue::HandlerRequestQueue,
+        managed_system::{node_app::NodeApp, php_cgi::PhpCgi, python_app::PythonApp},
+    },
     logging::syslog::{error, trace},
 };
 
+// An external handler started by Gruxi, kept for reporting its status
+pub struct ManagedHandler {
+    pub handler_type: String, // "php-cgi", "python" or "node"
+    pub id: String,
+    pub name: String,
+    pub status: Arc<ExternalHandlerStatus>,
+}
+
 pub struct ExternalSystemHandler {
     pub php_cgi_id_to_port: HashMap<String, u16>,
-    pub connection_semaphore: HashMap<String, Arc<Semaphore>>,
+    pub php_cgi_circuit_breakers: HashMap<String, Arc<AtomicBool>>,
+    pub python_app_id_to_port: HashMap<String, u16>,
+    pub node_app_id_to_port: HashMap<String, u16>,
+    pub request_queues: HashMap<String, Arc<HandlerRequestQueue>>,
+    pub managed_handlers: Vec<ManagedHandler>,
 }
 
 impl ExternalSystemHandler {
     pub async fn new() -> Self {
-        let mut connection_semaphore = HashMap::new();
+        let mut request_queues = HashMap::new();
+        let mut managed_handlers = Vec::new();
 
         // Get the config, to determine what we need
         let cached_configuration = crate::configuration::cached_configuration::get_cached_configuration();
         let config = cached_configuration.get_configuration().await;
 
         let mut php_cgi_id_to_port = HashMap::new();
+        let mut php_cgi_circuit_breakers = HashMap::new();
 
         // Load PHP-CGI handlers from configuration
         for php_cgi_config in &config.php_cgi_handlers {
@@ -31,6 +53,16 @@ impl ExternalSystemHandler {
                 php_cgi_config.concurrent_threads,
                 php_cgi_config.executable.clone(),
             );
+            new_php_cgi.max_requests_per_worker = php_cgi_config.max_requests_per_worker;
+            new_php_cgi.max_worker_lifetime = php_cgi_config.max_worker_lifetime;
+
+            // Handlers that fail to start are reported as not alive
+            managed_handlers.push(ManagedHandler {
+                handler_type: "php-cgi".to_string(),
+                id: php_cgi_config.id.clone(),
+                name: php_cgi_config.name.clone(),
+                status: new_php_cgi.get_status(),
+            });
 
             let port_result = new_php_cgi.start().await;
             let port = match port_result {
@@ -49,9 +81,12 @@ impl ExternalSystemHandler {
             // We save the id matched to port for reference
             php_cgi_id_to_port.insert(php_cgi_config.id.clone(), port);
 
-            // Create a connection semaphore for this PHP-CGI instance
-            let connection_semaphore_value = Arc::new(Semaphore::new(php_cgi_config.get_max_children_processes() as usize));
-            connection_semaphore.insert(php_cgi_config.id.clone(), connection_semaphore_value);
+            // Open while the handler is taken out of service, because it keeps crashing
+            php_cgi_circuit_breakers.insert(php_cgi_config.id.clone(), new_php_cgi.get_circuit_breaker());
+
+            // Requests are queued, when all PHP-CGI children are busy
+            let request_queue = HandlerRequestQueue::new(php_cgi_config.get_max_children_processes() as usize, php_cgi_config.max_queue_length, php_cgi_config.queue_timeout);
+            request_queues.insert(php_cgi_config.id.clone(), Arc::new(request_queue));
 
             // Start monitoring thread for this PHP-CGI instance
             tokio::spawn(PhpCgi::start_monitoring_thread(new_php_cgi));
@@ -59,9 +94,83 @@ impl ExternalSystemHandler {
             trace(format!("Initialized PHP-CGI handler with ID: {}", php_cgi_config.id));
         }
 
+        let mut python_app_id_to_port = HashMap::new();
+
+        // Load Python application servers from configuration
+        for python_app_config in &config.python_handlers {
+            let mut new_python_app = python_app_config.clone();
+            managed_handlers.push(ManagedHandler {
+                handler_type: "python".to_string(),
+                id: python_app_config.id.clone(),
+                name: python_app_config.name.clone(),
+                status: new_python_app.get_status(),
+            });
+
+            let port = match new_python_app.start().await {
+                Ok(p) => p,
+                Err(e) => {
+                    error(format!("Failed to start Python handler with ID: {}: {}", python_app_config.id, e));
+                    continue;
+                }
+            };
+
+            // We save the id matched to port for reference, the port is kept when the process is restarted
+            python_app_id_to_port.insert(python_app_config.id.clone(), port);
+
+            // Requests are only queued, when the number of concurrent requests to the application server is limited
+            if python_app_config.max_concurrent_requests > 0 {
+                let request_queue = HandlerRequestQueue::new(python_app_config.max_concurrent_requests as usize, python_app_config.max_queue_length, python_app_config.queue_timeout);
+                request_queues.insert(python_app_config.id.clone(), Arc::new(request_queue));
+            }
+
+            // Start monitoring thread, which restarts the application server if it exits or becomes unhealthy
+            tokio::spawn(PythonApp::start_monitoring_thread(new_python_app));
+
+            trace(format!("Initialized Python handler with ID: {}", python_app_config.id));
+        }
+
+        let mut node_app_id_to_port = HashMap::new();
+
+        // Load Node.js applications from configuration
+        for node_app_config in &config.node_handlers {
+            let mut new_node_app = node_app_config.clone();
+            managed_handlers.push(ManagedHandler {
+                handler_type: "node".to_string(),
+                id: node_app_config.id.clone(),
+                name: node_app_config.name.clone(),
+                status: new_node_app.get_status(),
+            });
+
+            let port = match new_node_app.start().await {
+                Ok(p) => p,
+                Err(e) => {
+                    error(format!("Failed to start Node.js handler with ID: {}: {}", node_app_config.id, e));
+                    continue;
+                }
+            };
+
+            // We save the id matched to port for reference, the port is kept when the process is restarted
+            node_app_id_to_port.insert(node_app_config.id.clone(), port);
+
+            // Requests are only queued, when the number of concurrent requests to the application is limited
+            if node_app_config.max_concurrent_requests > 0 {
+                let request_queue = HandlerRequestQueue::new(node_app_config.max_concurrent_requests as usize, node_app_config.max_queue_length, node_app_config.queue_timeout);
+                request_queues.insert(node_app_config.id.clone(), Arc::new(request_queue));
+            }
+
+            // Start monitoring thread, which restarts the application with backoff if it exits or becomes unhealthy
+            tokio::spawn(NodeApp::start_monitoring_thread(new_node_app));
+
+            trace(format!("Initialized Node.js handler with ID: {}", node_app_config.id));
+        }
+
         ExternalSystemHandler {
             php_cgi_id_to_port,
-            connection_semaphore,
+            php_cgi_circuit_breakers,
+            python_app_id_to_port,
+            node_app_id_to_port,
+            request_queues,
+            managed_handlers,
         }
     }
 
@@ -69,7 +178,41 @@ impl ExternalSystemHandler {
         self.php_cgi_id_to_port.get(php_cgi_id).cloned().ok_or(())
     }
 
-    pub fn get_connection_semaphore(&self, external_system_id: &str) -> Option<Arc<Semaphore>> {
-        self.connection_semaphore.get(external_system_id).cloned()
+    pub fn is_php_cgi_circuit_open(&self, php_cgi_id: &str) -> bool {
+        self.php_cgi_circuit_breakers.get(php_cgi_id).is_some_and(|circuit_breaker| circuit_breaker.load(Ordering::Relaxed))
+    }
+
+    pub fn get_port_for_python_app(&self, python_app_id: &str) -> Option<u16> {
+        self.python_app_id_to_port.get(python_app_id).cloned()
+    }
+
+    pub fn get_port_for_node_app(&self, node_app_id: &str) -> Option<u16> {
+        self.node_app_id_to_port.get(node_app_id).cloned()
+    }
+
+    pub fn get_request_queue(&self, external_system_id: &str) -> Option<Arc<HandlerRequestQueue>> {
+        self.request_queues.get(external_system_id).cloned()
+    }
+
+    pub fn get_handler_status(&self, handler_id: &str) -> Option<Arc<ExternalHandlerStatus>> {
+        self.managed_handlers.iter().find(|handler| handler.id == handler_id).map(|handler| handler.status.clone())
+    }
+
+    // Runtime status of all managed handlers, for the admin API and the monitoring data
+    pub fn get_handlers_status_json(&self) -> serde_json::Value {
+        let handlers: Vec<serde_json::Value> = self
+            .managed_handlers
+            .iter()
+            .map(|handler| {
+                let mut handler_json = handler.status.get_json();
+                handler_json["type"] = serde_json::json!(handler.handler_type);
+                handler_json["id"] = serde_json::json!(handler.id);
+                handler_json["name"] = serde_json::json!(handler.name);
+                handler_json["circuit_open"] = serde_json::json!(handler.handler_type == "php-cgi" && self.is_php_cgi_circuit_open(&handler.id));
+                handler_json["queue_waiting"] = serde_json::json!(self.get_request_queue(&handler.id).map_or(0, |queue| queue.get_waiting_count()));
+                handler_json
+            })
+            .collect();
+        serde_json::Value::Array(handlers)
     }
 }
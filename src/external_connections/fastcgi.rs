@@ -1,4 +1,6 @@
 use crate::error::gruxi_error_enums::FastCgiError;
+use crate::external_connections::fastcgi_connection_pool::{FastCgiConnectionPool, FastCgiStream};
+use crate::external_connections::managed_system::environment_variable::EnvironmentVariable;
 use crate::file::file_util::replace_web_root_in_path;
 use crate::file::file_util::split_path;
 use crate::http::http_util::full;
@@ -20,11 +22,11 @@ impl FastCgi {
 
     pub async fn send_fastcgi_keep_alive(ip_and_port: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         // Connect to the FastCGI server with a short timeout
-        let stream = tokio::time::timeout(Duration::from_secs(2), tokio::net::TcpStream::connect(ip_and_port)).await??;
+        let stream = tokio::time::timeout(Duration::from_secs(2), FastCgiStream::connect(ip_and_port)).await??;
 
         // Send a minimal FastCGI request just to test connectivity
         let mut stream = stream;
-        let begin_request = Self::create_fastcgi_begin_request();
+        let begin_request = Self::create_fastcgi_begin_request(false);
         stream.write_all(&begin_request).await?;
 
         // Send empty params to signal end
@@ -43,7 +45,7 @@ impl FastCgi {
     }
 
     // Helper functions for FastCGI protocol (moved from main impl)
-    pub fn create_fastcgi_begin_request() -> Vec<u8> {
+    pub fn create_fastcgi_begin_request(keep_connection: bool) -> Vec<u8> {
         let mut packet = Vec::new();
         packet.push(1); // version
         packet.push(1); // type: FCGI_BEGIN_REQUEST
@@ -54,7 +56,7 @@ impl FastCgi {
 
         // FCGI_BEGIN_REQUEST body
         packet.extend(&1u16.to_be_bytes()); // role: FCGI_RESPONDER
-        packet.push(0); // flags
+        packet.push(if keep_connection { 1 } else { 0 }); // flags: FCGI_KEEP_CONN
         packet.extend(&[0; 5]); // reserved
 
         packet
@@ -228,130 +230,62 @@ impl FastCgi {
             }
         };
 
-        // Now we work on getting a semaphore permit for the connection, if relevant
-        let connection_semaphore_option = gruxi_request.get_connection_semaphore();
-
-        let response = match connection_semaphore_option {
-            Some(connection_semaphore) => {
-                // We only need a permit, if a connection semaphore is set
-                let available_permits = connection_semaphore.available_permits();
-                trace(format!("Acquiring connection permit for FastCGI server at {} (available permits: {})", ip_and_port, available_permits));
-
-                // Acquire a connection permit to limit concurrent connections to php-fpm
-                let _permit = match connection_semaphore.acquire().await {
-                    Ok(permit) => {
-                        trace(format!(
-                            "Connection permit acquired for FastCGI server (remaining permits: {})",
-                            connection_semaphore.available_permits()
-                        ));
-                        permit
-                    }
-                    Err(e) => {
-                        error(format!("Failed to acquire connection permit for FastCGI server: {}", e));
-                        return Err(FastCgiError::ConnectionPermitAcquisition);
-                    }
-                };
-                Self::do_fastcgi_request_and_response(gruxi_request, &ip_and_port, &params).await
-            }
-            None => Self::do_fastcgi_request_and_response(gruxi_request, &ip_and_port, &params).await,
-        };
-
-        response
+        Self::do_fastcgi_request_and_response(gruxi_request, &ip_and_port, &params).await
     }
 
     pub async fn do_fastcgi_request_and_response(gruxi_request: &mut GruxiRequest, ip_and_port: &str, params: &HashMap<String, String>) -> Result<GruxiResponse, FastCgiError> {
-        trace(format!("Connecting to FastCGI server at {}", ip_and_port));
+        // Keep-alive of FastCGI connections is used when pooling is enabled for the processor
+        let max_idle_connections = gruxi_request.get_calculated_data("fastcgi_pool_size").and_then(|s| s.parse::<usize>().ok()).unwrap_or(0);
+        let keep_connection = max_idle_connections > 0;
 
-        // Connect to the FastCGI server
-        let mut stream = match tokio::net::TcpStream::connect(&ip_and_port).await {
-            Ok(stream) => stream,
-            Err(e) => {
-                error(format!("FastCGI Error: Failed to connect to FastCGI server {}: {}", ip_and_port, e));
-                return Err(FastCgiError::Connection(e));
-            }
-        };
-
-        // Send FastCGI request
-        trace(format!("Sending FastCGI request... with parameters: {:?}", params));
+        let body_bytes = gruxi_request.get_body_bytes().await;
         let start_time = Instant::now();
 
-        // Send BEGIN_REQUEST
-        let begin_request = Self::create_fastcgi_begin_request();
-        if let Err(e) = stream.write_all(&begin_request).await {
-            error(format!("FastCGI Error: Failed to send BEGIN_REQUEST: {}", e));
-            return Err(FastCgiError::Communication(e));
-        }
-
-        // Send parameters
-        let params_data = Self::create_fastcgi_params(&params);
-        if let Err(e) = stream.write_all(&params_data).await {
-            error(format!("FastCGI Error: Failed to send PARAMS: {}", e));
-            return Err(FastCgiError::Communication(e));
-        }
+        // A pooled connection may have been closed by the server while idle, so we retry once with a new connection
+        let mut response_buffer = Vec::new();
+        for attempt in 0..2 {
+            trace(format!("Connecting to FastCGI server at {}", ip_and_port));
 
-        // Send empty params to signal end
-        let empty_params = Self::create_fastcgi_params(&HashMap::new());
-        if let Err(e) = stream.write_all(&empty_params).await {
-            error(format!("FastCGI Error: Failed to send empty params: {}", e));
-            return Err(FastCgiError::Communication(e));
-        }
+            let connection_result = if keep_connection && attempt == 0 {
+                FastCgiConnectionPool::get_connection(ip_and_port).await
+            } else {
+                FastCgiStream::connect(ip_and_port).await.map(|stream| (stream, false))
+            };
+            let (mut stream, is_reused) = match connection_result {
+                Ok(connection) => connection,
+                Err(e) => {
+                    error(format!("FastCGI Error: Failed to connect to FastCGI server {}: {}", ip_and_port, e));
+                    return Err(FastCgiError::Connection(e));
+                }
+            };
 
-        // Send body if present
-        let body_bytes = gruxi_request.get_body_bytes().await;
-        if body_bytes.len() > 0 {
-            let stdin_data = Self::create_fastcgi_stdin(&body_bytes);
-            if let Err(e) = stream.write_all(&stdin_data).await {
-                error(format!("FastCGI Error: Failed to send STDIN: {}", e));
+            // Send FastCGI request
+            trace(format!("Sending FastCGI request... with parameters: {:?}", params));
+            if let Err(e) = Self::send_fastcgi_request(&mut stream, params, &body_bytes, keep_connection).await {
+                if is_reused {
+                    trace(format!("FastCGI pooled connection failed while sending, retrying with new connection: {}", e));
+                    continue;
+                }
+                error(format!("FastCGI Error: Failed to send request: {}", e));
                 return Err(FastCgiError::Communication(e));
             }
-        }
 
-        // Send empty stdin to signal end
-        let empty_stdin = Self::create_fastcgi_stdin(&[]);
-        if let Err(e) = stream.write_all(&empty_stdin).await {
-            error(format!("FastCGI Error: Failed to send empty stdin: {}", e));
-            return Err(FastCgiError::Communication(e));
-        }
-
-        // Read response
-        trace("Reading FastCGI response...".to_string());
-        let mut response_buffer = Vec::new();
-        // Use 65535 byte buffer to match FastCGI max record size (FCGI_MAX_LENGTH)
-        let mut buffer = vec![0u8; 65535];
-
-        // Read with timeout
-        let timeout_duration = Duration::from_secs(30);
-        match tokio::time::timeout(timeout_duration, async {
-            loop {
-                match stream.read(&mut buffer).await {
-                    Ok(0) => {
-                        trace("FastCGI connection closed by server".to_string());
-                        break; // Connection closed
-                    }
-                    Ok(n) => {
-                        trace(format!("Read {} bytes from FastCGI stream (total: {} bytes)", n, response_buffer.len() + n));
-                        response_buffer.extend_from_slice(&buffer[..n]);
-
-                        // Check for complete response (empty STDOUT + END_REQUEST)
-                        if Self::is_fastcgi_response_complete(&response_buffer) {
-                            trace(format!("FastCGI response complete, total size: {} bytes", response_buffer.len()));
-                            break;
-                        }
-                    }
-                    Err(e) => {
-                        return Err(FastCgiError::Communication(e));
-                    }
+            // Read response
+            trace("Reading FastCGI response...".to_string());
+            let (is_complete, connection_closed) = match Self::read_fastcgi_response(&mut stream, &mut response_buffer).await {
+                Ok((_, true)) | Err(FastCgiError::Communication(_)) if is_reused && response_buffer.is_empty() => {
+                    trace("FastCGI pooled connection was closed by server, retrying with new connection".to_string());
+                    continue;
                 }
+                Ok(result) => result,
+                Err(e) => return Err(e),
+            };
+
+            // Put the connection back in the pool, if the server kept it open for us
+            if keep_connection && is_complete && !connection_closed {
+                FastCgiConnectionPool::return_connection(ip_and_port, stream, max_idle_connections);
             }
-            Ok::<(), FastCgiError>(())
-        })
-        .await
-        {
-            Ok(_) => {}
-            Err(_) => {
-                error(format!("FastCGI response timeout after reading {} bytes", response_buffer.len()));
-                return Err(FastCgiError::Timeout);
-            }
+            break;
         }
 
         // Parse FastCGI response and extract HTTP response
@@ -424,6 +358,63 @@ impl FastCgi {
         }
     }
 
+    async fn send_fastcgi_request(stream: &mut FastCgiStream, params: &HashMap<String, String>, body_bytes: &[u8], keep_connection: bool) -> std::io::Result<()> {
+        // Send BEGIN_REQUEST
+        stream.write_all(&Self::create_fastcgi_begin_request(keep_connection)).await?;
+
+        // Send parameters, followed by empty params to signal end
+        stream.write_all(&Self::create_fastcgi_params(params)).await?;
+        stream.write_all(&Self::create_fastcgi_params(&HashMap::new())).await?;
+
+        // Send body if present, followed by empty stdin to signal end
+        if !body_bytes.is_empty() {
+            stream.write_all(&Self::create_fastcgi_stdin(body_bytes)).await?;
+        }
+        stream.write_all(&Self::create_fastcgi_stdin(&[])).await?;
+
+        Ok(())
+    }
+
+    // Read the response into the buffer, returns if the response is complete and if the connection was closed by the server
+    async fn read_fastcgi_response(stream: &mut FastCgiStream, response_buffer: &mut Vec<u8>) -> Result<(bool, bool), FastCgiError> {
+        // Use 65535 byte buffer to match FastCGI max record size (FCGI_MAX_LENGTH)
+        let mut buffer = vec![0u8; 65535];
+
+        // Read with timeout
+        let timeout_duration = Duration::from_secs(30);
+        match tokio::time::timeout(timeout_duration, async {
+            loop {
+                match stream.read(&mut buffer).await {
+                    Ok(0) => {
+                        trace("FastCGI connection closed by server".to_string());
+                        return Ok((Self::is_fastcgi_response_complete(response_buffer), true));
+                    }
+                    Ok(n) => {
+                        trace(format!("Read {} bytes from FastCGI stream (total: {} bytes)", n, response_buffer.len() + n));
+                        response_buffer.extend_from_slice(&buffer[..n]);
+
+                        // Check for complete response (empty STDOUT + END_REQUEST)
+                        if Self::is_fastcgi_response_complete(response_buffer) {
+                            trace(format!("FastCGI response complete, total size: {} bytes", response_buffer.len()));
+                            return Ok((true, false));
+                        }
+                    }
+                    Err(e) => {
+                        return Err(FastCgiError::Communication(e));
+                    }
+                }
+            }
+        })
+        .await
+        {
+            Ok(result) => result,
+            Err(_) => {
+                error(format!("FastCGI response timeout after reading {} bytes", response_buffer.len()));
+                Err(FastCgiError::Timeout)
+            }
+        }
+    }
+
     pub fn generate_fast_cgi_params(gruxi_request: &mut GruxiRequest) -> Result<HashMap<String, String>, ()> {
         let mut params: HashMap<String, String> = HashMap::new();
 
@@ -453,6 +444,14 @@ impl FastCgi {
             }
         }
 
+        // Per-site environment variables. Added before the standard CGI variables below, so those cannot be overridden
+        if let Some(environment_json) = gruxi_request.get_calculated_data("fastcgi_environment") {
+            let environment: Vec<EnvironmentVariable> = serde_json::from_str(&environment_json).unwrap_or_default();
+            for variable in environment {
+                params.insert(variable.key, variable.value);
+            }
+        }
+
         // Handle web root mapping
         let mut full_script_path = gruxi_request.get_calculated_data("fastcgi_script_file").unwrap_or("".to_string());
         let mut script_web_root = gruxi_request.get_calculated_data("fastcgi_local_web_root").unwrap_or("".to_string());
@@ -511,6 +510,15 @@ impl FastCgi {
         params.insert("REDIRECT_STATUS".to_string(), "200".to_string());
         params.insert("HTTP_HOST".to_string(), gruxi_request.get_hostname());
 
+        // Per-site PHP ini settings
+        for (param_name, calculated_data_key) in [("PHP_VALUE", "fastcgi_php_value"), ("PHP_ADMIN_VALUE", "fastcgi_php_admin_value")] {
+            if let Some(php_value) = gruxi_request.get_calculated_data(calculated_data_key)
+                && !php_value.is_empty()
+            {
+                params.insert(param_name.to_string(), php_value);
+            }
+        }
+
         Ok(params)
     }
 
@@ -584,6 +592,26 @@ mod tests {
         assert_eq!(params.get("SCRIPT_FILENAME").unwrap(), "D:/websites/test1/public/index.php");
         assert_eq!(params.get("DOCUMENT_ROOT").unwrap(), "D:/websites/test1/public");
         assert_eq!(params.get("PATH_INFO").unwrap(), "");
+        assert!(!params.contains_key("PHP_VALUE"));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_generate_fastcgi_params_with_site_php_settings() {
+        let request = hyper::Request::builder().method("GET").uri("/").header("Host", "localhost").body(Bytes::new()).unwrap();
+        let mut gruxi_request = GruxiRequest::new(request);
+        gruxi_request.add_calculated_data("fastcgi_script_file", "/var/www/public/index.php");
+        gruxi_request.add_calculated_data("fastcgi_local_web_root", "/var/www/public");
+        gruxi_request.add_calculated_data("fastcgi_php_value", "memory_limit=256M\nmax_execution_time=60");
+        gruxi_request.add_calculated_data("fastcgi_php_admin_value", "");
+        gruxi_request.add_calculated_data("fastcgi_environment", r#"[{"key":"APP_ENV","value":"staging"},{"key":"REQUEST_METHOD","value":"DELETE"}]"#);
+
+        let params = FastCgi::generate_fast_cgi_params(&mut gruxi_request).unwrap();
+
+        assert_eq!(params.get("PHP_VALUE").unwrap(), "memory_limit=256M\nmax_execution_time=60");
+        assert!(!params.contains_key("PHP_ADMIN_VALUE"));
+        assert_eq!(params.get("APP_ENV").unwrap(), "staging");
+        // The environment cannot override the standard CGI variables
+        assert_eq!(params.get("REQUEST_METHOD").unwrap(), "GET");
     }
 
     #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
@@ -623,4 +651,10 @@ mod tests {
         assert!(parsed_response.len() > 0);
         assert!(parsed_response.windows(binary_content.len()).any(|w| w == binary_content.as_slice()));
     }
+
+    #[test]
+    fn test_fastcgi_begin_request_keep_connection_flag() {
+        assert_eq!(FastCgi::create_fastcgi_begin_request(false)[10], 0);
+        assert_eq!(FastCgi::create_fastcgi_begin_request(true)[10], 1);
+    }
 }
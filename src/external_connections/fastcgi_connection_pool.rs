@@ -0,0 +1,150 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::LazyLock;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::logging::syslog::trace;
+
+// Connections that have been idle for longer than this are not reused, as the FastCGI server may have closed them
+const FASTCGI_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Prefix for addresses that are unix domain sockets, such as "unix:/run/php/php8.3-fpm.sock"
+pub const FASTCGI_UNIX_SOCKET_PREFIX: &str = "unix:";
+
+// Idle connections by FastCGI address, shared by all processors connecting to the same address
+static FASTCGI_IDLE_CONNECTIONS: LazyLock<DashMap<String, Vec<(FastCgiStream, Instant)>>> = LazyLock::new(DashMap::new);
+
+// A connection to a FastCGI server, over TCP or a unix domain socket
+pub enum FastCgiStream {
+    Tcp(tokio::net::TcpStream),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixStream),
+}
+
+impl FastCgiStream {
+    // Connect to a FastCGI server, address is either "ip:port" or "unix:/path/to/socket"
+    pub async fn connect(address: &str) -> io::Result<Self> {
+        match address.strip_prefix(FASTCGI_UNIX_SOCKET_PREFIX) {
+            #[cfg(unix)]
+            Some(socket_path) => Ok(FastCgiStream::Unix(tokio::net::UnixStream::connect(socket_path).await?)),
+            #[cfg(not(unix))]
+            Some(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "Unix domain sockets are not supported on this platform")),
+            None => Ok(FastCgiStream::Tcp(tokio::net::TcpStream::connect(address).await?)),
+        }
+    }
+}
+
+impl AsyncRead for FastCgiStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            FastCgiStream::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            #[cfg(unix)]
+            FastCgiStream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for FastCgiStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            FastCgiStream::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            FastCgiStream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            FastCgiStream::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            FastCgiStream::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            FastCgiStream::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            FastCgiStream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+pub struct FastCgiConnectionPool;
+
+impl FastCgiConnectionPool {
+    // Get an idle connection for the address, if one is available and not expired.
+    // Returns the connection and whether it was reused, as reused connections may turn out to be closed
+    pub async fn get_connection(address: &str) -> io::Result<(FastCgiStream, bool)> {
+        if let Some(mut idle_connections) = FASTCGI_IDLE_CONNECTIONS.get_mut(address) {
+            while let Some((stream, idle_since)) = idle_connections.pop() {
+                if idle_since.elapsed() < FASTCGI_IDLE_TIMEOUT {
+                    trace(format!("Reusing pooled FastCGI connection to {}", address));
+                    return Ok((stream, true));
+                }
+            }
+        }
+
+        trace(format!("Opening new FastCGI connection to {}", address));
+        Ok((FastCgiStream::connect(address).await?, false))
+    }
+
+    // Return a connection to the pool after a completed request, if there is room for it
+    pub fn return_connection(address: &str, stream: FastCgiStream, max_idle_connections: usize) {
+        let mut idle_connections = FASTCGI_IDLE_CONNECTIONS.entry(address.to_string()).or_default();
+        idle_connections.retain(|(_, idle_since)| idle_since.elapsed() < FASTCGI_IDLE_TIMEOUT);
+        if idle_connections.len() < max_idle_connections {
+            idle_connections.push((stream, Instant::now()));
+        }
+    }
+
+    // Number of idle connections for the address
+    #[allow(dead_code)]
+    pub fn idle_connection_count(address: &str) -> usize {
+        FASTCGI_IDLE_CONNECTIONS.get(address).map(|c| c.len()).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fastcgi_pool_reuses_returned_connection() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let _ = listener.accept().await;
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+
+        let (stream, reused) = FastCgiConnectionPool::get_connection(&address).await.unwrap();
+        assert!(!reused);
+
+        FastCgiConnectionPool::return_connection(&address, stream, 4);
+        assert_eq!(FastCgiConnectionPool::idle_connection_count(&address), 1);
+
+        let (_stream, reused) = FastCgiConnectionPool::get_connection(&address).await.unwrap();
+        assert!(reused);
+        assert_eq!(FastCgiConnectionPool::idle_connection_count(&address), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fastcgi_pool_respects_max_idle_connections() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            loop {
+                let _ = listener.accept().await;
+            }
+        });
+
+        let (stream, _) = FastCgiConnectionPool::get_connection(&address).await.unwrap();
+        FastCgiConnectionPool::return_connection(&address, stream, 0);
+        assert_eq!(FastCgiConnectionPool::idle_connection_count(&address), 0);
+    }
+}
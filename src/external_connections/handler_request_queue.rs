@@ -0,0 +1,106 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// Defaults for the queue settings of external handlers
+pub const DEFAULT_MAX_QUEUE_LENGTH: u32 = 100;
+pub const DEFAULT_QUEUE_TIMEOUT: u32 = 30;
+// Sent in the "Retry-After" header, when a request is rejected because the handler is saturated
+pub const HANDLER_QUEUE_RETRY_AFTER_SECONDS: u32 = 5;
+
+pub fn default_max_queue_length() -> u32 {
+    DEFAULT_MAX_QUEUE_LENGTH
+}
+
+pub fn default_queue_timeout() -> u32 {
+    DEFAULT_QUEUE_TIMEOUT
+}
+
+#[derive(Debug, PartialEq)]
+pub enum HandlerQueueError {
+    Full,    // Too many requests are already waiting
+    Timeout, // Waited for the queue timeout, without a free slot
+}
+
+// Bounded queue in front of an external handler. Up to max_concurrent requests are handled at a time, up to max_queue_length
+// more wait for a free slot, and anything beyond that is rejected right away, so a slow handler cannot pile up requests in memory
+pub struct HandlerRequestQueue {
+    slots: Arc<Semaphore>,
+    waiting: AtomicUsize,
+    max_queue_length: usize,
+    queue_timeout: Duration,
+}
+
+impl HandlerRequestQueue {
+    pub fn new(max_concurrent: usize, max_queue_length: u32, queue_timeout_seconds: u32) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(max_concurrent.max(1))),
+            waiting: AtomicUsize::new(0),
+            max_queue_length: max_queue_length as usize,
+            queue_timeout: Duration::from_secs(queue_timeout_seconds as u64),
+        }
+    }
+
+    // Wait for a slot at the handler. The slot is released when the returned permit is dropped
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit, HandlerQueueError> {
+        if let Ok(permit) = self.slots.clone().try_acquire_owned() {
+            return Ok(permit);
+        }
+
+        if self.waiting.fetch_add(1, Ordering::Relaxed) >= self.max_queue_length {
+            self.waiting.fetch_sub(1, Ordering::Relaxed);
+            return Err(HandlerQueueError::Full);
+        }
+
+        let result = tokio::time::timeout(self.queue_timeout, self.slots.clone().acquire_owned()).await;
+        self.waiting.fetch_sub(1, Ordering::Relaxed);
+
+        match result {
+            Ok(Ok(permit)) => Ok(permit),
+            // The semaphore is never closed, so an error can only be the timeout
+            _ => Err(HandlerQueueError::Timeout),
+        }
+    }
+
+    pub fn get_waiting_count(&self) -> usize {
+        self.waiting.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_handler_request_queue_rejects_when_full() {
+        let queue = Arc::new(HandlerRequestQueue::new(1, 1, 1));
+
+        let first_permit = queue.acquire().await.unwrap();
+
+        // The second request waits for the slot, while a third does not fit in the queue
+        let waiting_queue = queue.clone();
+        let waiting_request = tokio::spawn(async move { waiting_queue.acquire().await.is_ok() });
+        while queue.get_waiting_count() == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(queue.acquire().await.err(), Some(HandlerQueueError::Full));
+
+        drop(first_permit);
+        assert!(waiting_request.await.unwrap());
+        assert_eq!(queue.get_waiting_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_handler_request_queue_times_out() {
+        let queue = HandlerRequestQueue::new(1, 10, 0);
+        let _permit = queue.acquire().await.unwrap();
+        assert_eq!(queue.acquire().await.err(), Some(HandlerQueueError::Timeout));
+        assert_eq!(queue.get_waiting_count(), 0);
+    }
+}
@@ -0,0 +1,31 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// Extra environment variable passed to a managed process
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct EnvironmentVariable {
+    pub key: String,
+    pub value: String,
+}
+
+impl EnvironmentVariable {
+    pub fn sanitize(&mut self) {
+        self.key = self.key.trim().to_string();
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.key.is_empty() {
+            errors.push("Environment variable name cannot be empty.".to_string());
+        } else if self.key.contains('=') || self.key.contains('\0') {
+            errors.push(format!("Environment variable name '{}' cannot contain '=' or null characters.", self.key));
+        }
+
+        if self.value.contains('\0') {
+            errors.push(format!("Environment variable '{}' cannot contain null characters in its value.", self.key));
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
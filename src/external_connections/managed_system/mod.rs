@@ -1 +1,5 @@
-pub mod php_cgi;
\ No newline at end of file
+pub mod environment_variable;
+pub mod node_app;
+pub mod php_cgi;
+pub mod python_app;
+pub mod restart_supervisor;
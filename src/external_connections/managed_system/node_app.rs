@@ -0,0 +1,469 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    process::{Child, Command},
+    select,
+};
+
+use crate::{
+    core::triggers::get_trigger_handler,
+    external_connections::{
+        external_handler_status::ExternalHandlerStatus,
+        handler_request_queue::{default_max_queue_length, default_queue_timeout},
+        managed_system::environment_variable::EnvironmentVariable,
+    },
+    logging::syslog::{error, trace, warn},
+    network::port_manager::{PortManager, get_port_manager},
+};
+
+// Time given to the application to start listening, before health checks count as failures
+const NODE_APP_STARTUP_GRACE_PERIOD: Duration = Duration::from_secs(15);
+// Consecutive failed health checks before the application is restarted
+const NODE_APP_MAX_HEALTH_CHECK_FAILURES: u32 = 3;
+const NODE_APP_HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+// Restart delay doubles for each crash in a row, starting at the base delay and capped at the max delay
+const NODE_APP_RESTART_BASE_DELAY: Duration = Duration::from_secs(1);
+const NODE_APP_RESTART_MAX_DELAY: Duration = Duration::from_secs(60);
+// A process that ran at least this long is considered stable, so the next crash starts the backoff over
+const NODE_APP_STABLE_RUNTIME: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct NodeApp {
+    // Unique identifier for the external system
+    pub id: String,
+    // Inputs from configuration
+    pub name: String,
+    pub executable: String,           // Path to the node executable, or just "node" to find it in PATH
+    pub entry_script: String,         // Script to start, such as "server.js" or "dist/main.js", relative to the working directory
+    pub working_directory: String,    // Directory the application is started in
+    pub extra_arguments: Vec<String>, // Arguments to node, placed before the entry script, such as "--max-old-space-size=512"
+    pub extra_environment: Vec<EnvironmentVariable>,
+    pub health_check_path: String, // If empty, health is checked by connecting to the port only
+    #[serde(default)]
+    pub max_concurrent_requests: u32, // Requests sent to the application at a time, 0 means no limit and no queue
+    #[serde(default = "default_max_queue_length")]
+    pub max_queue_length: u32, // Requests waiting for a free slot, before new requests are answered with 503
+    #[serde(default = "default_queue_timeout")]
+    pub queue_timeout: u32, // Seconds a request waits for a free slot, before it is answered with 503
+
+    // Internal state
+    #[serde(skip)]
+    process: Option<Child>,
+    #[serde(skip)]
+    restart_count: u32,
+    #[serde(skip)]
+    assigned_port: Option<u16>,
+    #[serde(skip)]
+    port_manager: PortManager,
+    #[serde(skip, default = "Instant::now")]
+    started_at: Instant,
+    #[serde(skip)]
+    health_check_failures: u32,
+    #[serde(skip)]
+    consecutive_crashes: u32,
+    #[serde(skip)]
+    next_restart_at: Option<Instant>,
+    #[serde(skip)]
+    status: Arc<ExternalHandlerStatus>,
+}
+
+impl Clone for NodeApp {
+    // Only the configuration is cloned, a clone never owns the running process
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            executable: self.executable.clone(),
+            entry_script: self.entry_script.clone(),
+            working_directory: self.working_directory.clone(),
+            extra_arguments: self.extra_arguments.clone(),
+            extra_environment: self.extra_environment.clone(),
+            health_check_path: self.health_check_path.clone(),
+            max_concurrent_requests: self.max_concurrent_requests,
+            max_queue_length: self.max_queue_length,
+            queue_timeout: self.queue_timeout,
+            ..Self::new()
+        }
+    }
+}
+
+impl Default for NodeApp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeApp {
+    pub fn new() -> Self {
+        // Get the singleton port manager instance
+        let port_manager = get_port_manager().clone();
+
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: String::new(),
+            executable: "node".to_string(),
+            entry_script: String::new(),
+            working_directory: String::new(),
+            extra_arguments: Vec::new(),
+            extra_environment: Vec::new(),
+            health_check_path: String::new(),
+            max_concurrent_requests: 0,
+            max_queue_length: default_max_queue_length(),
+            queue_timeout: default_queue_timeout(),
+            process: None,
+            restart_count: 0,
+            assigned_port: None,
+            port_manager,
+            started_at: Instant::now(),
+            health_check_failures: 0,
+            consecutive_crashes: 0,
+            next_restart_at: None,
+            status: Arc::new(ExternalHandlerStatus::default()),
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.name = self.name.trim().to_string();
+        self.executable = self.executable.trim().to_string();
+        self.entry_script = self.entry_script.trim().to_string();
+        self.working_directory = self.working_directory.trim().to_string();
+        self.health_check_path = self.health_check_path.trim().to_string();
+        self.extra_arguments = self.extra_arguments.iter().map(|a| a.trim().to_string()).filter(|a| !a.is_empty()).collect();
+        for variable in &mut self.extra_environment {
+            variable.sanitize();
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.id.is_empty() {
+            errors.push("Node.js handler ID cannot be empty.".to_string());
+        }
+
+        if self.name.is_empty() {
+            errors.push("Node.js handler name cannot be empty.".to_string());
+        }
+
+        if self.executable.is_empty() {
+            errors.push("Node.js handler executable cannot be empty.".to_string());
+        } else if (self.executable.contains('/') || self.executable.contains('\\')) && !std::path::Path::new(&self.executable).exists() {
+            // Plain names such as "node" are looked up in PATH when started
+            errors.push(format!("Node.js handler executable not found at path: {}", self.executable));
+        }
+
+        if !self.working_directory.is_empty() && !std::path::Path::new(&self.working_directory).is_dir() {
+            errors.push(format!("Node.js handler working directory does not exist: {}", self.working_directory));
+        }
+
+        if self.entry_script.is_empty() {
+            errors.push("Node.js handler entry script cannot be empty.".to_string());
+        } else if !self.get_entry_script_path().is_file() {
+            errors.push(format!("Node.js handler entry script not found: {}", self.entry_script));
+        }
+
+        if self.queue_timeout < 1 {
+            errors.push("Node.js handler queue timeout must be at least 1 second.".to_string());
+        }
+
+        if !self.health_check_path.is_empty() && !self.health_check_path.starts_with('/') {
+            errors.push("Node.js handler health check path must start with '/', such as '/health'.".to_string());
+        }
+
+        for variable in &self.extra_environment {
+            if let Err(variable_errors) = variable.validate() {
+                errors.extend(variable_errors);
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    // The entry script is relative to the working directory, as that is where node is started
+    fn get_entry_script_path(&self) -> std::path::PathBuf {
+        let entry_script = std::path::Path::new(&self.entry_script);
+        if entry_script.is_absolute() || self.working_directory.is_empty() {
+            entry_script.to_path_buf()
+        } else {
+            std::path::Path::new(&self.working_directory).join(entry_script)
+        }
+    }
+
+    pub fn get_arguments(&self) -> Vec<String> {
+        let mut arguments: Vec<String> = self.extra_arguments.clone();
+        arguments.push(self.entry_script.clone());
+        arguments
+    }
+
+    // Delay before the next restart, doubling with each crash in a row
+    fn get_restart_delay(consecutive_crashes: u32) -> Duration {
+        let multiplier = 2u32.saturating_pow(consecutive_crashes.saturating_sub(1));
+        NODE_APP_RESTART_BASE_DELAY.saturating_mul(multiplier).min(NODE_APP_RESTART_MAX_DELAY)
+    }
+
+    // Start the application and returns the assigned port
+    pub async fn start(&mut self) -> Result<u16, String> {
+        // Allocate a port if we don't have one, it is kept across restarts so requests keep finding the application
+        if self.assigned_port.is_none() {
+            self.assigned_port = self.port_manager.allocate_port(format!("node-app-{}", self.id)).await;
+            if self.assigned_port.is_none() {
+                return Err("Failed to allocate port for Node.js application".to_string());
+            }
+        }
+        let port = match self.assigned_port {
+            Some(p) => p,
+            None => {
+                return Err("Assigned port is missing after allocation".to_string());
+            }
+        };
+
+        let mut cmd = Command::new(&self.executable);
+        cmd.kill_on_drop(true);
+        cmd.args(self.get_arguments());
+        if !self.working_directory.is_empty() {
+            cmd.current_dir(&self.working_directory);
+        }
+
+        // The configured environment can override NODE_ENV, but not where the application listens
+        cmd.env("NODE_ENV", "production");
+        for variable in &self.extra_environment {
+            cmd.env(&variable.key, &variable.value);
+        }
+        // Node.js applications conventionally read the port to listen on from PORT
+        cmd.env("PORT", port.to_string());
+        cmd.env("HOST", "127.0.0.1");
+
+        match cmd.spawn() {
+            Ok(child) => {
+                self.process = Some(child);
+                self.restart_count += 1;
+                self.started_at = Instant::now();
+                self.health_check_failures = 0;
+                trace(format!("Node.js application '{}' started on port {} (restart count: {})", self.name, port, self.restart_count));
+            }
+            Err(e) => {
+                error(format!("Failed to start Node.js application '{}': {}", self.name, e));
+                // Release the port if process failed to start
+                if let Some(port) = self.assigned_port.take() {
+                    self.port_manager.release_port(port).await;
+                }
+                return Err(format!("Failed to start Node.js application: {}", e));
+            }
+        }
+
+        Ok(port)
+    }
+
+    pub fn get_status(&self) -> Arc<ExternalHandlerStatus> {
+        self.status.clone()
+    }
+
+    // Publish the process state for the admin API, where the first start is not counted as a restart
+    fn update_status(&self) {
+        self.status.set_process_state(self.process.is_some(), self.assigned_port, self.restart_count.saturating_sub(1));
+    }
+
+    pub async fn start_monitoring_thread(mut instance: NodeApp) {
+        let triggers = get_trigger_handler();
+
+        let shutdown_token_option = triggers.get_token("shutdown").await;
+        let shutdown_token = match shutdown_token_option {
+            Some(token) => token,
+            None => {
+                error("Failed to get shutdown token - Node.js application monitoring thread exiting - Please report a bug".to_string());
+                return;
+            }
+        };
+
+        let stop_services_token_option = triggers.get_token("stop_services").await;
+        let stop_services_token = match stop_services_token_option {
+            Some(token) => token,
+            None => {
+                error("Failed to get stop_services token - Node.js application monitoring thread exiting - Please report a bug".to_string());
+                return;
+            }
+        };
+
+        instance.update_status();
+
+        loop {
+            select! {
+                _ = shutdown_token.cancelled() => {
+                    trace("Shutdown signal received, stopping Node.js application if running".to_string());
+                    instance.stop().await;
+                    break;
+                },
+                _ = stop_services_token.cancelled() => {
+                    trace("Stop services signal received, stopping Node.js application if running".to_string());
+                    instance.stop().await;
+                    break;
+                },
+                _ = tokio::time::sleep(Duration::from_secs(1)) => {
+                    if let Err(e) = instance.ensure_running().await {
+                        error(format!("Failed to ensure Node.js application '{}' is running: {}", instance.name, e));
+                    }
+                    instance.update_status();
+                }
+            }
+        }
+    }
+
+    fn is_alive(&mut self) -> bool {
+        if let Some(process) = self.process.as_mut() {
+            match process.try_wait() {
+                Ok(Some(status)) => {
+                    warn(format!("Node.js application '{}' has exited with {}", self.name, status));
+                    self.process = None;
+                    false
+                }
+                Ok(None) => true, // Process is still running
+                Err(e) => {
+                    error(format!("Error checking Node.js application '{}' status: {}", self.name, e));
+                    self.process = None;
+                    false
+                }
+            }
+        } else {
+            false
+        }
+    }
+
+    // Check that the application accepts connections, and answers the health check path without a server error if configured
+    async fn is_healthy(&self) -> bool {
+        let port = match self.assigned_port {
+            Some(p) => p,
+            None => return false,
+        };
+
+        let result = tokio::time::timeout(NODE_APP_HEALTH_CHECK_TIMEOUT, async {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+            if self.health_check_path.is_empty() {
+                return Ok::<bool, std::io::Error>(true);
+            }
+
+            let request = format!("GET {} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nConnection: close\r\n\r\n", self.health_check_path, port);
+            stream.write_all(request.as_bytes()).await?;
+
+            // We only need the status line, such as "HTTP/1.1 200 OK"
+            let mut buffer = [0u8; 64];
+            let read_bytes = stream.read(&mut buffer).await?;
+            let status_line = String::from_utf8_lossy(&buffer[..read_bytes]);
+            let status_code = status_line.split_whitespace().nth(1).and_then(|code| code.parse::<u16>().ok());
+            Ok(matches!(status_code, Some(code) if code < 500))
+        })
+        .await;
+
+        matches!(result, Ok(Ok(true)))
+    }
+
+    // Schedule a restart with exponential backoff, so a crash looping application does not spin
+    fn schedule_restart(&mut self) {
+        if self.started_at.elapsed() >= NODE_APP_STABLE_RUNTIME {
+            self.consecutive_crashes = 0;
+        }
+        self.consecutive_crashes += 1;
+
+        let delay = Self::get_restart_delay(self.consecutive_crashes);
+        self.next_restart_at = Some(Instant::now() + delay);
+        warn(format!(
+            "Node.js application '{}' will be restarted in {} seconds ({} crashes in a row)",
+            self.name,
+            delay.as_secs(),
+            self.consecutive_crashes
+        ));
+    }
+
+    async fn ensure_running(&mut self) -> Result<(), String> {
+        if !self.is_alive() {
+            match self.next_restart_at {
+                None => {
+                    self.schedule_restart();
+                    return Ok(());
+                }
+                Some(restart_at) if Instant::now() < restart_at => {
+                    return Ok(());
+                }
+                Some(_) => {
+                    self.next_restart_at = None;
+                    warn(format!("Node.js application '{}' is not running, restarting...", self.name));
+                    self.start().await?;
+                    return Ok(());
+                }
+            }
+        }
+
+        // Give the application time to start listening, before we count failed health checks
+        if self.started_at.elapsed() < NODE_APP_STARTUP_GRACE_PERIOD {
+            return Ok(());
+        }
+
+        if self.is_healthy().await {
+            self.health_check_failures = 0;
+            return Ok(());
+        }
+
+        self.health_check_failures += 1;
+        warn(format!(
+            "Node.js application '{}' failed health check ({}/{})",
+            self.name, self.health_check_failures, NODE_APP_MAX_HEALTH_CHECK_FAILURES
+        ));
+        if self.health_check_failures >= NODE_APP_MAX_HEALTH_CHECK_FAILURES {
+            warn(format!("Node.js application '{}' is unhealthy, restarting...", self.name));
+            self.kill_process().await;
+            self.schedule_restart();
+        }
+        Ok(())
+    }
+
+    async fn kill_process(&mut self) {
+        if let Some(mut process) = self.process.take() {
+            trace(format!("Stopping Node.js application '{}'", self.name));
+            if let Err(e) = process.kill().await {
+                error(format!("Failed to kill Node.js application '{}': {}", self.name, e));
+            }
+        }
+    }
+
+    pub async fn stop(&mut self) {
+        self.kill_process().await;
+
+        // Release the assigned port
+        if let Some(port) = self.assigned_port.take() {
+            self.port_manager.release_port(port).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_app_restart_delay_backs_off() {
+        assert_eq!(NodeApp::get_restart_delay(1), Duration::from_secs(1));
+        assert_eq!(NodeApp::get_restart_delay(2), Duration::from_secs(2));
+        assert_eq!(NodeApp::get_restart_delay(4), Duration::from_secs(8));
+        assert_eq!(NodeApp::get_restart_delay(7), Duration::from_secs(60));
+        assert_eq!(NodeApp::get_restart_delay(100), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_node_app_arguments_and_entry_script_path() {
+        let mut app = NodeApp::new();
+        app.entry_script = "server.js".to_string();
+        app.extra_arguments = vec!["--enable-source-maps".to_string()];
+        assert_eq!(app.get_arguments(), vec!["--enable-source-maps", "server.js"]);
+
+        app.working_directory = "/srv/app".to_string();
+        assert_eq!(app.get_entry_script_path(), std::path::PathBuf::from("/srv/app/server.js"));
+
+        app.entry_script = "/opt/other/main.js".to_string();
+        assert_eq!(app.get_entry_script_path(), std::path::PathBuf::from("/opt/other/main.js"));
+    }
+}
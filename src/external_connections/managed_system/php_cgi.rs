@@ -1,5 +1,9 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::time::{Duration, Instant};
+use std::{
+    sync::{Arc, atomic::AtomicBool},
+    time::{Duration, Instant},
+};
 use tokio::{
     process::{Child, Command},
     select,
@@ -7,12 +11,22 @@ use tokio::{
 
 use crate::{
     core::triggers::get_trigger_handler,
-    external_connections::fastcgi::FastCgi,
-    logging::syslog::{error, trace, warn},
+    external_connections::{
+        external_handler_status::ExternalHandlerStatus,
+        fastcgi::FastCgi,
+        handler_request_queue::{default_max_queue_length, default_queue_timeout},
+        managed_system::restart_supervisor::{RestartPolicy, RestartSupervisor},
+    },
+    logging::syslog::{error, info, trace, warn},
     network::port_manager::{PortManager, get_port_manager},
 };
 
-#[derive(Debug, Serialize, Deserialize)]
+// Same as the limit used before it could be configured, which keeps memory leaks in PHP extensions in check
+pub fn default_max_requests_per_worker() -> u32 {
+    10000
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct PhpCgi {
     // Unique identifier for the external system
     pub id: String,
@@ -21,6 +35,14 @@ pub struct PhpCgi {
     pub request_timeout: u32,
     pub concurrent_threads: u32,
     pub executable: String,
+    #[serde(default = "default_max_queue_length")]
+    pub max_queue_length: u32, // Requests waiting for a free PHP-CGI child, before new requests are answered with 503
+    #[serde(default = "default_queue_timeout")]
+    pub queue_timeout: u32, // Seconds a request waits for a free PHP-CGI child, before it is answered with 503
+    #[serde(default = "default_max_requests_per_worker")]
+    pub max_requests_per_worker: u32, // Requests a PHP-CGI child handles before it is replaced, 0 means no limit
+    #[serde(default)]
+    pub max_worker_lifetime: u32, // Minutes before the PHP-CGI process is recycled, 0 means it runs until it fails
 
     // Internal state
     #[serde(skip)]
@@ -33,12 +55,19 @@ pub struct PhpCgi {
     port_manager: PortManager,
     #[serde(skip, default = "Instant::now")]
     last_activity: Instant,
+    #[serde(skip, default = "Instant::now")]
+    started_at: Instant,
+    #[serde(skip)]
+    supervisor: RestartSupervisor,
+    #[serde(skip)]
+    status: Arc<ExternalHandlerStatus>,
 }
 
 impl PhpCgi {
     pub fn new(id: String, name: String, request_timeout: u32, concurrent_threads: u32, executable: String) -> Self {
         // Get the singleton port manager instance
         let port_manager = get_port_manager().clone();
+        let supervisor = RestartSupervisor::new(format!("PHP-CGI handler '{}'", name), RestartPolicy::default());
 
         Self {
             id,
@@ -46,14 +75,26 @@ impl PhpCgi {
             request_timeout,
             concurrent_threads,
             executable,
+            max_queue_length: default_max_queue_length(),
+            queue_timeout: default_queue_timeout(),
+            max_requests_per_worker: default_max_requests_per_worker(),
+            max_worker_lifetime: 0,
             process: None,
             restart_count: 0,
             assigned_port: None,
             port_manager,
             last_activity: Instant::now(),
+            started_at: Instant::now(),
+            supervisor,
+            status: Arc::new(ExternalHandlerStatus::default()),
         }
     }
 
+    // Shared flag that is true while the handler is taken out of service, because it keeps failing
+    pub fn get_circuit_breaker(&self) -> Arc<AtomicBool> {
+        self.supervisor.get_circuit_breaker()
+    }
+
     pub fn sanitize(&mut self) {
         // Clean up executable path
         self.executable = self.executable.trim().to_string();
@@ -80,6 +121,10 @@ impl PhpCgi {
             errors.push("PHP-CGI request timeout must be at least 1 second.".to_string());
         }
 
+        if self.queue_timeout < 1 {
+            errors.push("PHP-CGI queue timeout must be at least 1 second.".to_string());
+        }
+
         // Validate executable path
         if self.executable.is_empty() {
             errors.push("PHP-CGI executable path cannot be empty.".to_string());
@@ -134,13 +179,15 @@ impl PhpCgi {
 
         // Set environment variable for FastCGI children
         cmd.env("PHP_FCGI_CHILDREN", self.get_max_children_processes().to_string());
-        cmd.env("PHP_FCGI_MAX_REQUESTS", "10000"); // Request limit before restart the child process
+        // PHP-CGI replaces a child after it has handled this many requests, where 0 means no limit
+        cmd.env("PHP_FCGI_MAX_REQUESTS", self.max_requests_per_worker.to_string());
 
         match cmd.spawn() {
             Ok(child) => {
                 self.process = Some(child);
                 self.restart_count += 1;
                 self.last_activity = Instant::now();
+                self.started_at = Instant::now();
                 trace(format!("PHP-CGI process started successfully on port {} (restart count: {})", port, self.restart_count));
             }
             Err(e) => {
@@ -157,6 +204,15 @@ impl PhpCgi {
         Ok(port)
     }
 
+    pub fn get_status(&self) -> Arc<ExternalHandlerStatus> {
+        self.status.clone()
+    }
+
+    // Publish the process state for the admin API, where the first start is not counted as a restart
+    fn update_status(&self) {
+        self.status.set_process_state(self.process.is_some(), self.assigned_port, self.restart_count.saturating_sub(1));
+    }
+
     pub async fn start_monitoring_thread(mut instance: PhpCgi) {
         let triggers = get_trigger_handler();
 
@@ -178,6 +234,8 @@ impl PhpCgi {
             }
         };
 
+        instance.update_status();
+
         loop {
             select! {
                 _ = shutdown_token.cancelled() => {
@@ -194,6 +252,7 @@ impl PhpCgi {
                     if let Err(e) = instance.ensure_running().await {
                         error(format!("Failed to ensure PHP-CGI process is running: {}", e));
                     }
+                    instance.update_status();
                 }
             }
         }
@@ -239,25 +298,68 @@ impl PhpCgi {
 
     async fn ensure_running(&mut self) -> Result<(), String> {
         if !self.is_alive().await {
+            // Restarts are backed off, and the handler is taken out of service if it keeps failing
+            if !self.supervisor.is_restart_planned() {
+                self.supervisor.register_failure();
+            }
+            if !self.supervisor.is_restart_due() {
+                return Ok(());
+            }
+
             warn("PHP-CGI process is not running, restarting...".to_string());
-            // Wait a bit before restarting to avoid rapid restart loops
-            tokio::time::sleep(Duration::from_millis(1000)).await;
-            self.start().await?;
+            self.supervisor.register_restart();
+            if let Err(e) = self.start().await {
+                self.supervisor.register_failure();
+                return Err(e);
+            }
+        } else if self.max_worker_lifetime > 0 && self.started_at.elapsed() >= Duration::from_secs(self.max_worker_lifetime as u64 * 60) {
+            self.recycle().await?;
         } else {
             // Check if we need to send a keep-alive
             let time_since_activity = self.last_activity.elapsed();
             if time_since_activity >= Duration::from_secs(10) {
-                if !self.send_keep_alive().await {
+                if self.send_keep_alive().await {
+                    self.supervisor.register_healthy();
+                } else {
+                    // Keep the port, so the processors can still reach the process once restarted
                     warn("Keep-alive failed, restarting PHP-CGI process".to_string());
-                    self.stop().await;
-                    tokio::time::sleep(Duration::from_millis(1000)).await;
-                    self.start().await?;
+                    self.kill_process().await;
+                    self.supervisor.register_failure();
                 }
             }
         }
         Ok(())
     }
 
+    // Replace the process with a new one, after the requests in progress are done. New requests are held meanwhile
+    async fn recycle(&mut self) -> Result<(), String> {
+        info(format!("Recycling PHP-CGI handler '{}' after {} minutes", self.name, self.max_worker_lifetime));
+        self.status.set_recycling(true);
+        self.status.drain(Duration::from_secs(self.request_timeout as u64)).await;
+        self.kill_process().await;
+
+        let result = self.start().await;
+        if let Ok(port) = result {
+            // Give the new process a moment to listen, before the held requests are sent to it
+            for _ in 0..20 {
+                if tokio::net::TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(250)).await;
+            }
+        }
+        self.status.set_recycling(false);
+        result.map(|_| ())
+    }
+
+    async fn kill_process(&mut self) {
+        if let Some(mut process) = self.process.take()
+            && let Err(e) = process.kill().await
+        {
+            error(format!("Failed to kill PHP-CGI process: {}", e));
+        }
+    }
+
     pub async fn stop(&mut self) {
         if let Some(mut process) = self.process.take() {
             trace("Stopping PHP-CGI process".to_string());
@@ -0,0 +1,511 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    process::{Child, Command},
+    select,
+};
+
+use crate::{
+    core::triggers::get_trigger_handler,
+    external_connections::{
+        external_handler_status::ExternalHandlerStatus,
+        handler_request_queue::{default_max_queue_length, default_queue_timeout},
+        managed_system::environment_variable::EnvironmentVariable,
+    },
+    logging::syslog::{error, info, trace, warn},
+    network::port_manager::{PortManager, get_port_manager},
+};
+
+// Time given to the application server to start listening, before health checks count as failures
+const PYTHON_APP_STARTUP_GRACE_PERIOD: Duration = Duration::from_secs(15);
+// Consecutive failed health checks before the application server is restarted
+const PYTHON_APP_MAX_HEALTH_CHECK_FAILURES: u32 = 3;
+const PYTHON_APP_HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+// Time given to requests in progress to finish, before the application server is recycled
+const PYTHON_APP_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub static PYTHON_APP_SERVER_TYPES: &[&str] = &["uvicorn", "gunicorn"];
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PythonApp {
+    // Unique identifier for the external system
+    pub id: String,
+    // Inputs from configuration
+    pub name: String,
+    pub server_type: String,       // "uvicorn" (ASGI) or "gunicorn" (WSGI, or ASGI with a uvicorn worker class in extra_arguments)
+    pub executable: String,        // Path to the uvicorn/gunicorn executable, typically inside the virtualenv of the application
+    pub app_module: String,        // Application to serve, such as "myproject.wsgi:application" or "main:app"
+    pub working_directory: String, // Directory the application server is started in, so the app module can be imported
+    pub workers: u32,
+    pub extra_arguments: Vec<String>,
+    pub extra_environment: Vec<EnvironmentVariable>,
+    pub health_check_path: String, // If empty, health is checked by connecting to the port only
+    #[serde(default)]
+    pub max_concurrent_requests: u32, // Requests sent to the application at a time, 0 means no limit and no queue
+    #[serde(default = "default_max_queue_length")]
+    pub max_queue_length: u32, // Requests waiting for a free slot, before new requests are answered with 503
+    #[serde(default = "default_queue_timeout")]
+    pub queue_timeout: u32, // Seconds a request waits for a free slot, before it is answered with 503
+    #[serde(default)]
+    pub max_requests_per_worker: u32, // Requests a worker handles before the application server replaces it, 0 means no limit
+    #[serde(default)]
+    pub max_worker_lifetime: u32, // Minutes before the application server is recycled, 0 means it runs until it fails
+
+    // Internal state
+    #[serde(skip)]
+    process: Option<Child>,
+    #[serde(skip)]
+    restart_count: u32,
+    #[serde(skip)]
+    assigned_port: Option<u16>,
+    #[serde(skip)]
+    port_manager: PortManager,
+    #[serde(skip, default = "Instant::now")]
+    started_at: Instant,
+    #[serde(skip)]
+    health_check_failures: u32,
+    #[serde(skip)]
+    status: Arc<ExternalHandlerStatus>,
+}
+
+impl Clone for PythonApp {
+    // Only the configuration is cloned, a clone never owns the running process
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            server_type: self.server_type.clone(),
+            executable: self.executable.clone(),
+            app_module: self.app_module.clone(),
+            working_directory: self.working_directory.clone(),
+            workers: self.workers,
+            extra_arguments: self.extra_arguments.clone(),
+            extra_environment: self.extra_environment.clone(),
+            health_check_path: self.health_check_path.clone(),
+            max_concurrent_requests: self.max_concurrent_requests,
+            max_queue_length: self.max_queue_length,
+            queue_timeout: self.queue_timeout,
+            max_requests_per_worker: self.max_requests_per_worker,
+            max_worker_lifetime: self.max_worker_lifetime,
+            ..Self::new()
+        }
+    }
+}
+
+impl Default for PythonApp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PythonApp {
+    pub fn new() -> Self {
+        // Get the singleton port manager instance
+        let port_manager = get_port_manager().clone();
+
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: String::new(),
+            server_type: "uvicorn".to_string(),
+            executable: String::new(),
+            app_module: String::new(),
+            working_directory: String::new(),
+            workers: 1,
+            extra_arguments: Vec::new(),
+            extra_environment: Vec::new(),
+            health_check_path: String::new(),
+            max_concurrent_requests: 0,
+            max_queue_length: default_max_queue_length(),
+            queue_timeout: default_queue_timeout(),
+            max_requests_per_worker: 0,
+            max_worker_lifetime: 0,
+            process: None,
+            restart_count: 0,
+            assigned_port: None,
+            port_manager,
+            started_at: Instant::now(),
+            health_check_failures: 0,
+            status: Arc::new(ExternalHandlerStatus::default()),
+        }
+    }
+
+    pub fn sanitize(&mut self) {
+        self.name = self.name.trim().to_string();
+        self.server_type = self.server_type.trim().to_lowercase();
+        self.executable = self.executable.trim().to_string();
+        self.app_module = self.app_module.trim().to_string();
+        self.working_directory = self.working_directory.trim().to_string();
+        self.health_check_path = self.health_check_path.trim().to_string();
+        self.extra_arguments = self.extra_arguments.iter().map(|a| a.trim().to_string()).filter(|a| !a.is_empty()).collect();
+        for variable in &mut self.extra_environment {
+            variable.sanitize();
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.id.is_empty() {
+            errors.push("Python handler ID cannot be empty.".to_string());
+        }
+
+        if self.name.is_empty() {
+            errors.push("Python handler name cannot be empty.".to_string());
+        }
+
+        if !PYTHON_APP_SERVER_TYPES.contains(&self.server_type.as_str()) {
+            errors.push(format!(
+                "Python handler server type '{}' is not supported, use one of: {}.",
+                self.server_type,
+                PYTHON_APP_SERVER_TYPES.join(", ")
+            ));
+        }
+
+        if self.executable.is_empty() {
+            errors.push("Python handler executable cannot be empty.".to_string());
+        } else if (self.executable.contains('/') || self.executable.contains('\\')) && !std::path::Path::new(&self.executable).exists() {
+            // Plain names such as "uvicorn" are looked up in PATH when started
+            errors.push(format!("Python handler executable not found at path: {}", self.executable));
+        }
+
+        // Both uvicorn and gunicorn expect "module:attribute"
+        match self.app_module.split_once(':') {
+            Some((module, attribute)) if !module.is_empty() && !attribute.is_empty() => {}
+            _ => errors.push(format!("Python handler app module '{}' must be in the format 'module:attribute', such as 'main:app'.", self.app_module)),
+        }
+
+        if !self.working_directory.is_empty() && !std::path::Path::new(&self.working_directory).is_dir() {
+            errors.push(format!("Python handler working directory does not exist: {}", self.working_directory));
+        }
+
+        if self.workers < 1 || self.workers > 64 {
+            errors.push("Python handler workers must be between 1 and 64.".to_string());
+        }
+
+        if self.queue_timeout < 1 {
+            errors.push("Python handler queue timeout must be at least 1 second.".to_string());
+        }
+
+        if !self.health_check_path.is_empty() && !self.health_check_path.starts_with('/') {
+            errors.push("Python handler health check path must start with '/', such as '/health'.".to_string());
+        }
+
+        for variable in &self.extra_environment {
+            if let Err(variable_errors) = variable.validate() {
+                errors.extend(variable_errors);
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    // Arguments to the application server, listening on the host and port
+    pub fn get_arguments(&self, host: &str, port: u16) -> Vec<String> {
+        let mut arguments = vec![self.app_module.clone()];
+        match self.server_type.as_str() {
+            "gunicorn" => {
+                arguments.extend(["--bind".to_string(), format!("{}:{}", host, port)]);
+            }
+            _ => {
+                arguments.extend(["--host".to_string(), host.to_string(), "--port".to_string(), port.to_string()]);
+            }
+        }
+        arguments.extend(["--workers".to_string(), self.workers.to_string()]);
+        if self.max_requests_per_worker > 0 {
+            match self.server_type.as_str() {
+                // Jitter spreads the restarts, so the workers are not all replaced at the same time
+                "gunicorn" => arguments.extend([
+                    "--max-requests".to_string(),
+                    self.max_requests_per_worker.to_string(),
+                    "--max-requests-jitter".to_string(),
+                    (self.max_requests_per_worker / 10).to_string(),
+                ]),
+                _ => arguments.extend(["--limit-max-requests".to_string(), self.max_requests_per_worker.to_string()]),
+            }
+        }
+        arguments.extend(self.extra_arguments.iter().cloned());
+        arguments
+    }
+
+    // Start the application server and returns the assigned port
+    pub async fn start(&mut self) -> Result<u16, String> {
+        // Allocate a port if we don't have one, it is kept across restarts so requests keep finding the server
+        if self.assigned_port.is_none() {
+            self.assigned_port = self.port_manager.allocate_port(format!("python-app-{}", self.id)).await;
+            if self.assigned_port.is_none() {
+                return Err("Failed to allocate port for Python application server".to_string());
+            }
+        }
+        let port = match self.assigned_port {
+            Some(p) => p,
+            None => {
+                return Err("Assigned port is missing after allocation".to_string());
+            }
+        };
+
+        let mut cmd = Command::new(&self.executable);
+        cmd.kill_on_drop(true);
+        cmd.args(self.get_arguments("127.0.0.1", port));
+        if !self.working_directory.is_empty() {
+            cmd.current_dir(&self.working_directory);
+        }
+
+        // Make sure output is not held back in buffers, and inject the configured environment
+        cmd.env("PYTHONUNBUFFERED", "1");
+        for variable in &self.extra_environment {
+            cmd.env(&variable.key, &variable.value);
+        }
+
+        match cmd.spawn() {
+            Ok(child) => {
+                self.process = Some(child);
+                self.restart_count += 1;
+                self.started_at = Instant::now();
+                self.health_check_failures = 0;
+                trace(format!("Python application server '{}' started on port {} (restart count: {})", self.name, port, self.restart_count));
+            }
+            Err(e) => {
+                error(format!("Failed to start Python application server '{}': {}", self.name, e));
+                // Release the port if process failed to start
+                if let Some(port) = self.assigned_port.take() {
+                    self.port_manager.release_port(port).await;
+                }
+                return Err(format!("Failed to start Python application server: {}", e));
+            }
+        }
+
+        Ok(port)
+    }
+
+    pub fn get_status(&self) -> Arc<ExternalHandlerStatus> {
+        self.status.clone()
+    }
+
+    // Publish the process state for the admin API, where the first start is not counted as a restart
+    fn update_status(&self) {
+        self.status.set_process_state(self.process.is_some(), self.assigned_port, self.restart_count.saturating_sub(1));
+    }
+
+    pub async fn start_monitoring_thread(mut instance: PythonApp) {
+        let triggers = get_trigger_handler();
+
+        let shutdown_token_option = triggers.get_token("shutdown").await;
+        let shutdown_token = match shutdown_token_option {
+            Some(token) => token,
+            None => {
+                error("Failed to get shutdown token - Python application monitoring thread exiting - Please report a bug".to_string());
+                return;
+            }
+        };
+
+        let stop_services_token_option = triggers.get_token("stop_services").await;
+        let stop_services_token = match stop_services_token_option {
+            Some(token) => token,
+            None => {
+                error("Failed to get stop_services token - Python application monitoring thread exiting - Please report a bug".to_string());
+                return;
+            }
+        };
+
+        instance.update_status();
+
+        loop {
+            select! {
+                _ = shutdown_token.cancelled() => {
+                    trace("Shutdown signal received, stopping Python application server if running".to_string());
+                    instance.stop().await;
+                    break;
+                },
+                _ = stop_services_token.cancelled() => {
+                    trace("Stop services signal received, stopping Python application server if running".to_string());
+                    instance.stop().await;
+                    break;
+                },
+                _ = tokio::time::sleep(Duration::from_secs(5)) => {
+                    if let Err(e) = instance.ensure_running().await {
+                        error(format!("Failed to ensure Python application server '{}' is running: {}", instance.name, e));
+                    }
+                    instance.update_status();
+                }
+            }
+        }
+    }
+
+    fn is_alive(&mut self) -> bool {
+        if let Some(process) = self.process.as_mut() {
+            match process.try_wait() {
+                Ok(Some(status)) => {
+                    warn(format!("Python application server '{}' has exited with {}", self.name, status));
+                    self.process = None;
+                    false
+                }
+                Ok(None) => true, // Process is still running
+                Err(e) => {
+                    error(format!("Error checking Python application server '{}' status: {}", self.name, e));
+                    self.process = None;
+                    false
+                }
+            }
+        } else {
+            false
+        }
+    }
+
+    // Check that the application server accepts connections, and answers the health check path without a server error if configured
+    async fn is_healthy(&self) -> bool {
+        let port = match self.assigned_port {
+            Some(p) => p,
+            None => return false,
+        };
+
+        let result = tokio::time::timeout(PYTHON_APP_HEALTH_CHECK_TIMEOUT, async {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).await?;
+            if self.health_check_path.is_empty() {
+                return Ok::<bool, std::io::Error>(true);
+            }
+
+            let request = format!("GET {} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nConnection: close\r\n\r\n", self.health_check_path, port);
+            stream.write_all(request.as_bytes()).await?;
+
+            // We only need the status line, such as "HTTP/1.1 200 OK"
+            let mut buffer = [0u8; 64];
+            let read_bytes = stream.read(&mut buffer).await?;
+            let status_line = String::from_utf8_lossy(&buffer[..read_bytes]);
+            let status_code = status_line.split_whitespace().nth(1).and_then(|code| code.parse::<u16>().ok());
+            Ok(matches!(status_code, Some(code) if code < 500))
+        })
+        .await;
+
+        matches!(result, Ok(Ok(true)))
+    }
+
+    async fn ensure_running(&mut self) -> Result<(), String> {
+        if !self.is_alive() {
+            warn(format!("Python application server '{}' is not running, restarting...", self.name));
+            // Wait a bit before restarting to avoid rapid restart loops
+            tokio::time::sleep(Duration::from_millis(1000)).await;
+            self.start().await?;
+            return Ok(());
+        }
+
+        // Give the application time to start listening, before we count failed health checks
+        if self.started_at.elapsed() < PYTHON_APP_STARTUP_GRACE_PERIOD {
+            return Ok(());
+        }
+
+        if self.max_worker_lifetime > 0 && self.started_at.elapsed() >= Duration::from_secs(self.max_worker_lifetime as u64 * 60) {
+            return self.recycle().await;
+        }
+
+        if self.is_healthy().await {
+            self.health_check_failures = 0;
+            return Ok(());
+        }
+
+        self.health_check_failures += 1;
+        warn(format!(
+            "Python application server '{}' failed health check ({}/{})",
+            self.name, self.health_check_failures, PYTHON_APP_MAX_HEALTH_CHECK_FAILURES
+        ));
+        if self.health_check_failures >= PYTHON_APP_MAX_HEALTH_CHECK_FAILURES {
+            warn(format!("Python application server '{}' is unhealthy, restarting...", self.name));
+            self.kill_process().await;
+            tokio::time::sleep(Duration::from_millis(1000)).await;
+            self.start().await?;
+        }
+        Ok(())
+    }
+
+    // Replace the application server with a new one, after the requests in progress are done. New requests are held meanwhile
+    async fn recycle(&mut self) -> Result<(), String> {
+        info(format!("Recycling Python application server '{}' after {} minutes", self.name, self.max_worker_lifetime));
+        self.status.set_recycling(true);
+        self.status.drain(PYTHON_APP_DRAIN_TIMEOUT).await;
+        self.kill_process().await;
+
+        let result = self.start().await;
+        if result.is_ok() {
+            // Give the application time to start listening, before the held requests are sent to it
+            while self.started_at.elapsed() < PYTHON_APP_STARTUP_GRACE_PERIOD && !self.is_healthy().await {
+                tokio::time::sleep(Duration::from_millis(250)).await;
+            }
+        }
+        self.status.set_recycling(false);
+        result.map(|_| ())
+    }
+
+    async fn kill_process(&mut self) {
+        if let Some(mut process) = self.process.take() {
+            trace(format!("Stopping Python application server '{}'", self.name));
+            if let Err(e) = process.kill().await {
+                error(format!("Failed to kill Python application server '{}': {}", self.name, e));
+            }
+        }
+    }
+
+    pub async fn stop(&mut self) {
+        self.kill_process().await;
+
+        // Release the assigned port
+        if let Some(port) = self.assigned_port.take() {
+            self.port_manager.release_port(port).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_valid_python_app() -> PythonApp {
+        let mut app = PythonApp::new();
+        app.name = "My API".to_string();
+        app.executable = "uvicorn".to_string();
+        app.app_module = "main:app".to_string();
+        app
+    }
+
+    #[test]
+    fn test_python_app_arguments_per_server_type() {
+        let mut app = create_valid_python_app();
+        app.workers = 2;
+        app.extra_arguments = vec!["--proxy-headers".to_string()];
+        assert_eq!(
+            app.get_arguments("127.0.0.1", 9001),
+            vec!["main:app", "--host", "127.0.0.1", "--port", "9001", "--workers", "2", "--proxy-headers"]
+        );
+
+        app.server_type = "gunicorn".to_string();
+        app.extra_arguments = vec![];
+        assert_eq!(app.get_arguments("127.0.0.1", 9001), vec!["main:app", "--bind", "127.0.0.1:9001", "--workers", "2"]);
+
+        // Workers are replaced after a number of requests by the application server itself
+        app.max_requests_per_worker = 1000;
+        assert_eq!(app.get_arguments("127.0.0.1", 9001)[5..], ["--max-requests", "1000", "--max-requests-jitter", "100"]);
+        app.server_type = "uvicorn".to_string();
+        assert_eq!(app.get_arguments("127.0.0.1", 9001)[7..], ["--limit-max-requests", "1000"]);
+    }
+
+    #[test]
+    fn test_python_app_validation() {
+        let app = create_valid_python_app();
+        assert!(app.validate().is_ok());
+
+        let mut app = create_valid_python_app();
+        app.app_module = "main".to_string();
+        app.server_type = "waitress".to_string();
+        app.workers = 0;
+        app.extra_environment = vec![EnvironmentVariable {
+            key: "A=B".to_string(),
+            value: String::new(),
+        }];
+        let errors = app.validate().unwrap_err();
+        assert_eq!(errors.len(), 4);
+    }
+}
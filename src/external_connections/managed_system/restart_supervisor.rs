@@ -0,0 +1,202 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    core::admin_alerts::add_admin_alert,
+    logging::syslog::{info, warn},
+};
+
+// How a managed process is restarted when it fails
+#[derive(Clone, Debug)]
+pub struct RestartPolicy {
+    pub max_restarts: u32, // Restarts allowed within the window, before the circuit is opened
+    pub restart_window: Duration,
+    pub base_delay: Duration, // Delay before the first restart, doubled for each failure in a row
+    pub max_delay: Duration,
+    pub circuit_open_duration: Duration, // How long the handler is taken out of service, before a new restart is tried
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            restart_window: Duration::from_secs(120),
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            circuit_open_duration: Duration::from_secs(60),
+        }
+    }
+}
+
+// Keeps track of failures of a managed process, to back off restarts and to take the handler out of service
+// (open the circuit) when it keeps crashing. The circuit flag is shared, so processors can answer 503 right away
+#[derive(Debug)]
+pub struct RestartSupervisor {
+    name: String,
+    policy: RestartPolicy,
+    restart_times: VecDeque<Instant>,
+    consecutive_failures: u32,
+    next_restart_at: Option<Instant>,
+    circuit_open: Arc<AtomicBool>,
+}
+
+impl Default for RestartSupervisor {
+    fn default() -> Self {
+        Self::new(String::new(), RestartPolicy::default())
+    }
+}
+
+impl RestartSupervisor {
+    pub fn new(name: String, policy: RestartPolicy) -> Self {
+        Self {
+            name,
+            policy,
+            restart_times: VecDeque::new(),
+            consecutive_failures: 0,
+            next_restart_at: None,
+            circuit_open: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Shared flag that is true while the handler is taken out of service
+    pub fn get_circuit_breaker(&self) -> Arc<AtomicBool> {
+        self.circuit_open.clone()
+    }
+
+    pub fn is_circuit_open(&self) -> bool {
+        self.circuit_open.load(Ordering::Relaxed)
+    }
+
+    pub fn is_restart_planned(&self) -> bool {
+        self.next_restart_at.is_some()
+    }
+
+    pub fn is_restart_due(&self) -> bool {
+        match self.next_restart_at {
+            Some(restart_at) => Instant::now() >= restart_at,
+            None => true,
+        }
+    }
+
+    // Register that the process failed (exited, could not be started or stopped responding), and plan the next restart
+    pub fn register_failure(&mut self) {
+        if self.plan_restart(Instant::now()) {
+            add_admin_alert(
+                "external_handler",
+                format!(
+                    "{} failed {} times within {} seconds and is taken out of service for {} seconds, requests are answered with 503",
+                    self.name,
+                    self.restart_times.len(),
+                    self.policy.restart_window.as_secs(),
+                    self.policy.circuit_open_duration.as_secs()
+                ),
+            );
+        } else {
+            warn(format!(
+                "{} failed, restarting in {} seconds ({} failures in a row)",
+                self.name,
+                self.get_restart_delay().as_secs(),
+                self.consecutive_failures
+            ));
+        }
+    }
+
+    // Set when the next restart is due. Returns true if the circuit was opened, because of too many restarts in the window
+    fn plan_restart(&mut self, now: Instant) -> bool {
+        while let Some(restart_time) = self.restart_times.front()
+            && now.duration_since(*restart_time) > self.policy.restart_window
+        {
+            self.restart_times.pop_front();
+        }
+        self.consecutive_failures += 1;
+
+        if self.restart_times.len() as u32 >= self.policy.max_restarts {
+            self.circuit_open.store(true, Ordering::Relaxed);
+            self.next_restart_at = Some(now + self.policy.circuit_open_duration);
+            true
+        } else {
+            self.next_restart_at = Some(now + self.get_restart_delay());
+            false
+        }
+    }
+
+    // Register that a restart is being attempted now
+    pub fn register_restart(&mut self) {
+        self.restart_times.push_back(Instant::now());
+        self.next_restart_at = None;
+    }
+
+    // Register that the process answers, which resets the backoff and puts the handler back in service
+    pub fn register_healthy(&mut self) {
+        self.consecutive_failures = 0;
+        if self.circuit_open.swap(false, Ordering::Relaxed) {
+            info(format!("{} has recovered and is back in service", self.name));
+        }
+    }
+
+    fn get_restart_delay(&self) -> Duration {
+        let multiplier = 2u32.saturating_pow(self.consecutive_failures.saturating_sub(1));
+        self.policy.base_delay.saturating_mul(multiplier).min(self.policy.max_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_supervisor() -> RestartSupervisor {
+        let policy = RestartPolicy {
+            max_restarts: 3,
+            restart_window: Duration::from_secs(60),
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(4),
+            circuit_open_duration: Duration::from_secs(60),
+        };
+        RestartSupervisor::new("Test handler".to_string(), policy)
+    }
+
+    #[test]
+    fn test_restart_supervisor_backoff_is_capped() {
+        let mut supervisor = create_supervisor();
+        supervisor.consecutive_failures = 1;
+        assert_eq!(supervisor.get_restart_delay(), Duration::from_secs(1));
+        supervisor.consecutive_failures = 3;
+        assert_eq!(supervisor.get_restart_delay(), Duration::from_secs(4));
+        supervisor.consecutive_failures = 30;
+        assert_eq!(supervisor.get_restart_delay(), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_restart_supervisor_circuit_opens_and_closes() {
+        let mut supervisor = create_supervisor();
+        let circuit_breaker = supervisor.get_circuit_breaker();
+        let now = Instant::now();
+
+        // Restarts within the window are allowed up to the max
+        for _ in 0..3 {
+            assert!(!supervisor.plan_restart(now));
+            supervisor.register_restart();
+        }
+        assert!(!circuit_breaker.load(Ordering::Relaxed));
+
+        // The next failure opens the circuit, and no restart is due until it has been open for a while
+        assert!(supervisor.plan_restart(now));
+        assert!(circuit_breaker.load(Ordering::Relaxed));
+        assert!(!supervisor.is_restart_due());
+
+        // Restarts outside the window no longer count
+        supervisor.register_restart();
+        assert!(!supervisor.plan_restart(now + Duration::from_secs(120)));
+
+        supervisor.circuit_open.store(false, Ordering::Relaxed);
+        supervisor.register_healthy();
+        assert_eq!(supervisor.consecutive_failures, 0);
+        assert!(!circuit_breaker.load(Ordering::Relaxed));
+    }
+}
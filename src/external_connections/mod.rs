@@ -1,4 +1,7 @@
+pub mod external_handler_status;
+pub mod external_system;
 pub mod external_system_handler;
+pub mod fastcgi;
+pub mod fastcgi_connection_pool;
+pub mod handler_request_queue;
 pub mod managed_system;
-pub mod external_system;
-pub mod fastcgi;
\ No newline at end of file
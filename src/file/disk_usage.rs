@@ -0,0 +1,225 @@
+// ============================================================================
+// DISK USAGE
+// ============================================================================
+//
+// Measures the disk usage of the web roots of each site at an interval, for
+// the admin portal and the monitoring output, and checks the soft disk quota
+// of sites. The web roots of a site are those of the static file, PHP and
+// WebDAV processors of its request handlers, as for usage reports, each
+// counted once. Symlinks inside the web roots are not followed.
+//
+// The quota is soft: it is checked against the last measurement, plus what
+// was written through WebDAV since, so a site can go somewhat over it before
+// writes are rejected.
+// ============================================================================
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+use crate::configuration::cached_configuration::get_cached_configuration;
+use crate::configuration::configuration::Configuration;
+use crate::core::triggers::get_trigger_handler;
+use crate::core::usage_reports::get_site_web_roots;
+use crate::logging::syslog::{debug, trace, warn};
+
+// How often the disk usage of the sites is measured
+const DISK_USAGE_INTERVAL_SECS: u64 = 300;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SiteDiskUsage {
+    pub site_id: String,
+    pub hostnames: Vec<String>,
+    pub web_roots: Vec<String>,
+    pub bytes: u64,
+    pub files: u64,
+    pub quota_bytes: u64, // 0 for no quota
+    pub is_over_quota: bool,
+    pub measured_at: DateTime<Utc>,
+}
+
+// Last measurement per site ID
+static SITE_DISK_USAGE: LazyLock<DashMap<String, SiteDiskUsage>> = LazyLock::new(DashMap::new);
+
+// The total size and number of the files under the path. The path itself may be a symlink, such as the web root of a
+// site with deployments, but symlinks below it are not followed
+fn measure_directory(path: &Path) -> (u64, u64) {
+    let mut bytes = 0;
+    let mut files = 0;
+    let mut pending = vec![path.to_path_buf()];
+
+    while let Some(directory) = pending.pop() {
+        let entries = match std::fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(e) => {
+                trace(format!("Disk usage could not read directory '{}': {}", directory.display(), e));
+                continue;
+            }
+        };
+        for entry in entries.flatten() {
+            // Does not follow symlinks
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if metadata.is_file() {
+                bytes += metadata.len();
+                files += 1;
+            }
+        }
+    }
+    (bytes, files)
+}
+
+// The sites to measure, with their web roots and quotas, not measured yet
+fn get_sites_to_measure(configuration: &Configuration) -> Vec<SiteDiskUsage> {
+    configuration
+        .sites
+        .iter()
+        .map(|site| SiteDiskUsage {
+            site_id: site.id.clone(),
+            hostnames: site.hostnames.clone(),
+            web_roots: get_site_web_roots(configuration, site),
+            bytes: 0,
+            files: 0,
+            quota_bytes: site.disk_quota_mb.saturating_mul(1024 * 1024),
+            is_over_quota: false,
+            measured_at: Utc::now(),
+        })
+        .collect()
+}
+
+// Measure the disk usage of the sites, replacing the last measurements
+fn measure_disk_usage(sites: Vec<SiteDiskUsage>) {
+    let mut measured_site_ids = HashSet::new();
+
+    for mut usage in sites {
+        for web_root in &usage.web_roots {
+            let (bytes, files) = measure_directory(Path::new(web_root));
+            usage.bytes += bytes;
+            usage.files += files;
+        }
+        usage.is_over_quota = usage.quota_bytes > 0 && usage.bytes >= usage.quota_bytes;
+        usage.measured_at = Utc::now();
+
+        let was_over_quota = SITE_DISK_USAGE.get(&usage.site_id).is_some_and(|last| last.is_over_quota);
+        if usage.is_over_quota && !was_over_quota {
+            warn(format!(
+                "Site '{}' uses {} MB of disk, over its quota of {} MB. WebDAV writes and deployments are rejected",
+                usage.site_id,
+                usage.bytes / (1024 * 1024),
+                usage.quota_bytes / (1024 * 1024)
+            ));
+        }
+
+        measured_site_ids.insert(usage.site_id.clone());
+        SITE_DISK_USAGE.insert(usage.site_id.clone(), usage);
+    }
+
+    // Forget removed sites
+    SITE_DISK_USAGE.retain(|site_id, _| measured_site_ids.contains(site_id));
+    trace(format!("Measured the disk usage of {} sites", measured_site_ids.len()));
+}
+
+// The last measurements of all sites, ordered by usage, largest first
+pub fn get_disk_usage() -> Vec<SiteDiskUsage> {
+    let mut usage: Vec<SiteDiskUsage> = SITE_DISK_USAGE.iter().map(|entry| entry.value().clone()).collect();
+    usage.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.site_id.cmp(&b.site_id)));
+    usage
+}
+
+// Summary for the monitoring output
+pub fn get_disk_usage_json() -> serde_json::Value {
+    let usage = get_disk_usage();
+    serde_json::json!({
+        "total_bytes": usage.iter().map(|site| site.bytes).sum::<u64>(),
+        "sites_over_quota": usage.iter().filter(|site| site.is_over_quota).map(|site| site.site_id.clone()).collect::<Vec<String>>(),
+        "sites": usage.iter().map(|site| serde_json::json!({ "site_id": site.site_id, "bytes": site.bytes, "files": site.files, "quota_bytes": site.quota_bytes })).collect::<Vec<_>>(),
+    })
+}
+
+// Whether writes to the site are rejected, because it was over its quota when last measured or got there since
+pub fn is_over_disk_quota(site_id: &str) -> bool {
+    SITE_DISK_USAGE.get(site_id).is_some_and(|usage| usage.is_over_quota)
+}
+
+// Count bytes written to the site between measurements, so it does not get far over its quota before the next one
+pub fn add_written_bytes(site_id: &str, bytes: u64) {
+    if let Some(mut usage) = SITE_DISK_USAGE.get_mut(site_id) {
+        usage.bytes += bytes;
+        usage.is_over_quota = usage.quota_bytes > 0 && usage.bytes >= usage.quota_bytes;
+    }
+}
+
+/// Start measuring the disk usage of the sites at an interval, beginning right away. It stops on shutdown or
+/// stop_services triggers, so it is started again, and the sites measured again, on configuration reload.
+pub async fn start_disk_usage_monitor() {
+    let triggers = get_trigger_handler();
+    let shutdown_token = triggers.get_token("shutdown").await.unwrap_or_else(CancellationToken::new);
+    let stop_services_token = triggers.get_token("stop_services").await.unwrap_or_else(CancellationToken::new);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(DISK_USAGE_INTERVAL_SECS));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = shutdown_token.cancelled() => {
+                    debug("Disk usage monitor stopping due to shutdown signal");
+                    break;
+                }
+                _ = stop_services_token.cancelled() => {
+                    debug("Disk usage monitor stopping due to stop_services signal");
+                    break;
+                }
+                _ = interval.tick() => {
+                    let sites = get_sites_to_measure(&*get_cached_configuration().get_configuration().await);
+                    let _ = tokio::task::spawn_blocking(move || measure_disk_usage(sites)).await;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_measure_directory_and_quota() {
+        let root = std::env::temp_dir().join(format!("gruxi-disk-usage-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("a.txt"), vec![0u8; 1000]).unwrap();
+        std::fs::write(root.join("sub/b.txt"), vec![0u8; 500]).unwrap();
+        assert_eq!(measure_directory(&root), (1500, 2));
+
+        let site_id = uuid::Uuid::new_v4().to_string();
+        measure_disk_usage(vec![SiteDiskUsage {
+            site_id: site_id.clone(),
+            hostnames: vec![],
+            web_roots: vec![root.to_string_lossy().to_string()],
+            bytes: 0,
+            files: 0,
+            quota_bytes: 2000,
+            is_over_quota: false,
+            measured_at: Utc::now(),
+        }]);
+        assert_eq!(get_disk_usage()[0].bytes, 1500);
+        assert!(!is_over_disk_quota(&site_id));
+        add_written_bytes(&site_id, 500);
+        assert!(is_over_disk_quota(&site_id));
+        assert!(!is_over_disk_quota("unknown-site"));
+
+        SITE_DISK_USAGE.remove(&site_id);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
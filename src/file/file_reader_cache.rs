@@ -1,5 +1,5 @@
 use std::{
-    sync::Arc,
+    sync::{Arc, OnceLock, atomic::AtomicU64},
     time::{Duration, SystemTime},
 };
 
@@ -8,6 +8,7 @@ use crate::{
     configuration::cached_configuration::get_cached_configuration,
     core::triggers::get_trigger_handler,
     file::file_reader_structs::*,
+    file::open_file_cache::{OpenFileCache, get_open_file_body},
     http::request_response::{
         body_error::{BodyError, box_err},
         gruxi_request::GruxiRequest,
@@ -15,6 +16,7 @@ use crate::{
     logging::syslog::{debug, error, trace, warn},
 };
 
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64_STANDARD};
 use dashmap::DashMap;
 use futures::TryStreamExt;
 use http_body_util::BodyExt;
@@ -23,6 +25,7 @@ use http_body_util::{StreamBody, combinators::BoxBody};
 use hyper::body::{Bytes, Frame};
 use tokio::{
     fs::File,
+    io::AsyncReadExt,
     select,
     time::{Instant, interval},
 };
@@ -43,11 +46,34 @@ impl FileReaderCache {
         let cleanup_thread_interval = file_data_config.cleanup_thread_interval;
         let forced_eviction_threshold = file_data_config.forced_eviction_threshold;
 
+        // Memory-mapped files are served from their mapping, instead of the content cache
+        let mmap_file_length_range = if file_data_config.mmap_is_enabled {
+            Some((file_data_config.mmap_min_file_size as u64, file_data_config.mmap_max_file_size as u64))
+        } else {
+            None
+        };
+
+        // Files whose content is cached are not kept open, unless content caching is disabled or they are memory mapped
+        let mut open_file_min_length = if is_caching_enabled { max_file_size + 1 } else { 0 };
+        if let Some((mmap_min_length, _)) = mmap_file_length_range {
+            open_file_min_length = open_file_min_length.min(mmap_min_length);
+        }
+        let open_file_cache = OpenFileCache::new(
+            file_data_config.open_file_cache_max_entries,
+            open_file_min_length,
+            file_data_config.open_file_cache_revalidate_seconds as u64,
+            mmap_file_length_range,
+        );
+
         let compressible_content_types = &config.core.gzip.compressible_content_types;
         let gzip_enabled = &config.core.gzip.is_enabled;
 
         let cache = Arc::new(DashMap::new());
         let cached_items_last_checked = Arc::new(DashMap::new());
+        let variant_memory = Arc::new(VariantMemory {
+            used_bytes: AtomicU64::new(0),
+            max_bytes: file_data_config.compressed_variants_max_size as u64,
+        });
 
         // Start the cleanup thread
         if is_caching_enabled {
@@ -75,6 +101,8 @@ impl FileReaderCache {
             max_file_size,
             gzip_enabled: *gzip_enabled,
             compressible_content_types: compressible_content_types.clone(),
+            open_file_cache,
+            variant_memory,
         }
     }
 
@@ -82,6 +110,39 @@ impl FileReaderCache {
         self.cache.len() as u64
     }
 
+    // Memory used by the cached content of the files and by their compressed variants, in bytes
+    pub fn get_memory_usage(&self) -> (u64, u64) {
+        let content_bytes: usize = self.cache.iter().filter_map(|entry| entry.value().content.raw.as_ref().map(|raw| raw.len())).sum();
+        (content_bytes as u64, self.variant_memory.get_used_bytes())
+    }
+
+    // Forget the cached files under a directory, such as after its content was replaced
+    pub fn remove_directory(&self, directory_path: &str) {
+        self.cache.retain(|file_path, _| !file_path.starts_with(directory_path));
+        self.cached_items_last_checked.retain(|file_path, _| !file_path.starts_with(directory_path));
+        self.open_file_cache.remove_directory(directory_path);
+    }
+
+    // Forget the files cached longer than the lifetime, whether the cache is over its eviction threshold or not. Returns
+    // how many were removed
+    pub fn remove_items_older_than(&self, max_item_lifetime: Duration) -> usize {
+        let files_to_remove: Vec<String> = self
+            .cached_items_last_checked
+            .iter()
+            .filter(|entry| entry.value().0.elapsed() > max_item_lifetime)
+            .map(|entry| entry.key().clone())
+            .collect();
+        for path in &files_to_remove {
+            self.cache.remove(path);
+            self.cached_items_last_checked.remove(path);
+        }
+        files_to_remove.len()
+    }
+
+    pub fn get_open_file_cache(&self) -> &OpenFileCache {
+        &self.open_file_cache
+    }
+
     // Get file data
     pub async fn get_file(&self, file_path: &str) -> Result<Arc<FileEntry>, std::io::Error> {
         // Check the cache first
@@ -94,9 +155,14 @@ impl FileReaderCache {
 
         // Not found in cache, so we populate it, maybe saving it to cache if enabled
         trace(format!("File/dir not found in cache, reading from disk: {}", file_path));
-        let (length, exists, is_directory, last_modified) = match std::fs::metadata(file_path) {
-            Ok(metadata) => (metadata.len(), true, metadata.is_dir(), metadata.modified().unwrap_or(SystemTime::now())),
-            Err(_) => (0, false, false, SystemTime::now()),
+        // Files kept open have their metadata at hand, without a stat of the file
+        let open_file = if self.open_file_cache.is_enabled() { self.open_file_cache.get(file_path).ok() } else { None };
+        let (length, exists, is_directory, last_modified) = match &open_file {
+            Some(open_file) => (open_file.length, true, false, open_file.modified),
+            None => match std::fs::metadata(file_path) {
+                Ok(metadata) => (metadata.len(), true, metadata.is_dir(), metadata.modified().unwrap_or(SystemTime::now())),
+                Err(_) => (0, false, false, SystemTime::now()),
+            },
         };
 
         // Determine MIME type, if we have a file
@@ -116,44 +182,28 @@ impl FileReaderCache {
                 length,
                 is_too_large_to_store: length > self.max_file_size,
                 mime_type: mime_type,
+                is_compressible: should_compress,
+            },
+            content: ContentCache {
+                raw: None,
+                encoded: DashMap::new(),
+                raw_sha256: OnceLock::new(),
+                encoded_sha256: DashMap::new(),
+                variant_memory: self.variant_memory.clone(),
             },
-            content: ContentCache { raw: None, gzip: None },
         };
 
-        // Pre-fetch content of file if caching is enabled
-        if self.is_caching_enabled && !is_directory && exists && length <= self.max_file_size {
-            match std::fs::read(file_path) {
+        // Pre-fetch content of file if caching is enabled. Memory-mapped files are not copied into the cache, unless they are
+        // compressed, as their compressed variants are made from the cached content
+        let is_mapped = self.open_file_cache.is_mmap_eligible(length) && !should_compress;
+        if self.is_caching_enabled && !is_directory && exists && !is_mapped && length <= self.max_file_size {
+            let file_bytes_result = match &open_file {
+                Some(open_file) => open_file.read_at(0, length as usize).map(Vec::from),
+                None => std::fs::read(file_path),
+            };
+            match file_bytes_result {
                 Ok(file_bytes) => {
-                    let raw_bytes = Arc::new(Bytes::from(file_bytes));
-                    file_entry.content.raw = Some(raw_bytes);
-
-                    if should_compress {
-                        let raw_content_result = file_entry.content.raw.as_ref();
-                        let mut content_found = false;
-                        let raw_content = match raw_content_result {
-                            Some(content) => content.as_ref(),
-                            None => {
-                                warn(format!("Raw content is missing for file: {}", file_path));
-                                content_found = false;
-                                &Arc::new(Bytes::new())
-                            }
-                        };
-
-                        // Content should be found, but for safety we check
-                        if content_found {
-                            let mut gzip_content = Vec::new();
-
-                            match Compression::compress_content(raw_content, &mut gzip_content) {
-                                Ok(_) => {}
-                                Err(e) => {
-                                    warn(format!("Failed to compress file {}: {}", file_path, e));
-                                }
-                            }
-                            let gzip_bytes = Arc::new(Bytes::from(gzip_content));
-                            file_entry.content.gzip = Some(gzip_bytes);
-                        }
-                    }
-
+                    file_entry.content.raw = Some(Arc::new(Bytes::from(file_bytes)));
                     trace(format!("File content cached for file: {}", file_path));
                 }
                 Err(e) => {
@@ -304,12 +354,31 @@ impl FileReaderCache {
 }
 
 impl FileEntry {
-    pub async fn get_content_stream(&self, gruxi_request: &mut GruxiRequest) -> (BoxBody<Bytes, BodyError>, String) {
+    pub async fn get_content_stream(&self, gruxi_request: &mut GruxiRequest, open_file_cache: &OpenFileCache) -> (BoxBody<Bytes, BodyError>, String) {
         let accept_encoding_headers = gruxi_request.get_accepted_encodings();
 
-        if self.content.raw.is_none() && self.content.gzip.is_none() {
+        if self.content.raw.is_none() {
             trace("No cached file data content is present, so we return from the filesystem instead (full if small and stream if big)".to_string());
 
+            // Files kept open are read from the open file, from its memory mapping when within the thresholds, in full when
+            // small and streamed otherwise
+            if (open_file_cache.is_enabled() || open_file_cache.is_mmap_eligible(self.meta.length))
+                && let Ok(open_file) = open_file_cache.get(&self.meta.file_path)
+            {
+                if let Some(mapped_content) = open_file_cache.get_mapped_content(&open_file) {
+                    let full_body = Full::new(mapped_content).map_err(|never| -> BodyError { match never {} });
+                    return (BoxBody::new(full_body), String::new());
+                }
+                if open_file.length > 64 * 1024 {
+                    return (get_open_file_body(open_file), String::new());
+                }
+                let length = open_file.length as usize;
+                if let Ok(Ok(file_bytes)) = tokio::task::spawn_blocking(move || open_file.read_at(0, length)).await {
+                    let full_body = Full::new(file_bytes).map_err(|never| -> BodyError { match never {} });
+                    return (BoxBody::new(full_body), String::new());
+                }
+            }
+
             // For smaller files (<= 64 KB), return full content, otherwise stream
             if self.meta.length <= 64 * 1024 {
                 // Small file, return full
@@ -341,13 +410,12 @@ impl FileEntry {
         }
 
         // We prefer gzip if the client accepts it
-        if accept_encoding_headers.iter().any(|enc| enc.to_lowercase() == "gzip") {
-            if let Some(gzip_content) = &self.content.gzip {
-                trace("Serving gzipped content from cache".to_string());
-                let gzipped_bytes = gzip_content.as_ref().clone();
-                let boxbody = BoxBody::new(Full::new(gzipped_bytes).map_err(|never| -> BodyError { match never {} }));
-                return (boxbody, "gzip".to_string());
-            }
+        if self.meta.is_compressible
+            && accept_encoding_headers.iter().any(|enc| enc.to_lowercase() == "gzip")
+            && let Some(gzipped_bytes) = self.get_encoded_content("gzip")
+        {
+            let boxbody = BoxBody::new(Full::new(gzipped_bytes).map_err(|never| -> BodyError { match never {} }));
+            return (boxbody, "gzip".to_string());
         }
 
         // Otherwise serve raw content
@@ -362,4 +430,154 @@ impl FileEntry {
         let empty = Full::new(Bytes::new()).map_err(|never| -> BodyError { match never {} });
         return (BoxBody::new(empty), String::new());
     }
+
+    // The cached content compressed with the content encoding. The variant is made on the first request for it and kept on
+    // the entry while the variant memory budget allows, otherwise it is compressed for each request
+    fn get_encoded_content(&self, content_encoding: &str) -> Option<Bytes> {
+        if let Some(variant) = self.content.encoded.get(content_encoding) {
+            trace(format!("Serving {} content from cache", content_encoding));
+            return Some(variant.as_ref().clone());
+        }
+
+        let raw_content = self.content.raw.as_ref()?;
+        let mut encoded_content = Vec::new();
+        let encode_result = match content_encoding {
+            "gzip" => Compression::compress_content(raw_content, &mut encoded_content),
+            _ => return None,
+        };
+        if let Err(e) = encode_result {
+            warn(format!("Failed to compress file {}: {}", self.meta.file_path, e));
+            return None;
+        }
+        let encoded_bytes = Bytes::from(encoded_content);
+
+        // Another request may have made the variant meanwhile, so it is only counted when it is the one kept
+        if let dashmap::Entry::Vacant(entry) = self.content.encoded.entry(content_encoding.to_string())
+            && self.content.variant_memory.try_reserve(encoded_bytes.len() as u64)
+        {
+            trace(format!("Caching {} variant of file: {}", content_encoding, self.meta.file_path));
+            entry.insert(Arc::new(encoded_bytes.clone()));
+        }
+        Some(encoded_bytes)
+    }
+
+    // Get the base64 encoded SHA-256 digest of the content, as served with the given content encoding.
+    // The digest is calculated once and kept on the entry, so it lives as long as the file cache entry
+    pub async fn get_sha256_digest(&self, content_encoding: &str) -> Option<String> {
+        if !content_encoding.is_empty() {
+            if let Some(digest) = self.content.encoded_sha256.get(content_encoding) {
+                return Some(digest.clone());
+            }
+            let encoded_content = self.get_encoded_content(content_encoding)?;
+            let digest = get_sha256_base64(&encoded_content);
+            // Variants compressed per request are not kept, so neither is their digest
+            if self.content.encoded.contains_key(content_encoding) {
+                self.content.encoded_sha256.insert(content_encoding.to_string(), digest.clone());
+            }
+            return Some(digest);
+        }
+
+        if let Some(digest) = self.content.raw_sha256.get() {
+            return Some(digest.clone());
+        }
+
+        let digest = match &self.content.raw {
+            Some(raw_content) => get_sha256_base64(raw_content),
+            None => {
+                // Content is not cached, so we hash the file from disk in chunks, to keep memory usage low
+                let mut file = match File::open(&self.meta.file_path).await {
+                    Ok(f) => f,
+                    Err(e) => {
+                        trace(format!("Failed to open file {} for digest calculation: {}", self.meta.file_path, e));
+                        return None;
+                    }
+                };
+
+                let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+                let mut buffer = vec![0u8; 64 * 1024];
+                loop {
+                    match file.read(&mut buffer).await {
+                        Ok(0) => break,
+                        Ok(read_bytes) => context.update(&buffer[..read_bytes]),
+                        Err(e) => {
+                            trace(format!("Failed to read file {} for digest calculation: {}", self.meta.file_path, e));
+                            return None;
+                        }
+                    }
+                }
+                BASE64_STANDARD.encode(context.finish().as_ref())
+            }
+        };
+
+        let _ = self.content.raw_sha256.set(digest.clone());
+        Some(digest)
+    }
+}
+
+// Base64 encoded SHA-256 digest of the bytes, as used in Digest and Repr-Digest headers
+pub fn get_sha256_base64(bytes: &[u8]) -> String {
+    BASE64_STANDARD.encode(ring::digest::digest(&ring::digest::SHA256, bytes).as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_sha256_base64() {
+        assert_eq!(get_sha256_base64(b""), "47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=");
+        assert_eq!(get_sha256_base64(b"hello"), "LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=");
+    }
+
+    fn cached_file_entry(raw: &str, variant_memory: &Arc<VariantMemory>) -> FileEntry {
+        FileEntry {
+            meta: FileMeta {
+                file_path: "/var/www/app.js".to_string(),
+                is_directory: false,
+                exists: true,
+                length: raw.len() as u64,
+                is_too_large_to_store: false,
+                mime_type: "application/javascript".to_string(),
+                is_compressible: true,
+            },
+            content: ContentCache {
+                raw: Some(Arc::new(Bytes::from(raw.to_string()))),
+                encoded: DashMap::new(),
+                raw_sha256: OnceLock::new(),
+                encoded_sha256: DashMap::new(),
+                variant_memory: variant_memory.clone(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_compressed_variants_within_memory_budget() {
+        let raw = "console.log('hello');".repeat(200);
+        let mut expected_gzip = Vec::new();
+        Compression::compress_content(raw.as_bytes(), &mut expected_gzip).unwrap();
+        // Room for the variant of one file
+        let variant_memory = Arc::new(VariantMemory {
+            used_bytes: AtomicU64::new(0),
+            max_bytes: expected_gzip.len() as u64,
+        });
+
+        let first_entry = cached_file_entry(&raw, &variant_memory);
+        assert_eq!(first_entry.get_encoded_content("gzip").unwrap(), expected_gzip);
+        assert!(first_entry.content.encoded.contains_key("gzip"));
+        assert_eq!(variant_memory.get_used_bytes(), expected_gzip.len() as u64);
+
+        // Served from the cached variant, without counting it again
+        assert_eq!(first_entry.get_encoded_content("gzip").unwrap(), expected_gzip);
+        assert_eq!(variant_memory.get_used_bytes(), expected_gzip.len() as u64);
+
+        // Past the budget the variant is still made, but not kept
+        let second_entry = cached_file_entry(&raw, &variant_memory);
+        assert_eq!(second_entry.get_encoded_content("gzip").unwrap(), expected_gzip);
+        assert!(!second_entry.content.encoded.contains_key("gzip"));
+        assert!(second_entry.get_encoded_content("br").is_none());
+
+        // Dropping the entry releases its variants
+        drop(first_entry);
+        assert_eq!(variant_memory.get_used_bytes(), 0);
+    }
 }
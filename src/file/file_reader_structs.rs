@@ -1,7 +1,15 @@
-use std::{sync::Arc, time::SystemTime};
-use tokio::time::Instant;
 use dashmap::DashMap;
 use hyper::body::Bytes;
+use std::{
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::SystemTime,
+};
+use tokio::time::Instant;
+
+use crate::file::open_file_cache::OpenFileCache;
 
 pub struct FileReaderCache {
     pub(crate) cache: Arc<DashMap<String, Arc<FileEntry>>>,
@@ -10,6 +18,33 @@ pub struct FileReaderCache {
     pub(crate) max_file_size: u64,
     pub(crate) gzip_enabled: bool,
     pub(crate) compressible_content_types: Vec<String>,
+    pub(crate) open_file_cache: OpenFileCache,
+    pub(crate) variant_memory: Arc<VariantMemory>,
+}
+
+// Memory used by the compressed variants of the cached files, which are only kept while within the budget
+pub struct VariantMemory {
+    pub(crate) used_bytes: AtomicU64,
+    pub(crate) max_bytes: u64,
+}
+
+impl VariantMemory {
+    // Reserve memory for a variant, false when it would go over the budget
+    pub fn try_reserve(&self, bytes: u64) -> bool {
+        self.used_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used_bytes| {
+                used_bytes.checked_add(bytes).filter(|total| *total <= self.max_bytes)
+            })
+            .is_ok()
+    }
+
+    pub fn release(&self, bytes: u64) {
+        let _ = self.used_bytes.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used_bytes| Some(used_bytes.saturating_sub(bytes)));
+    }
+
+    pub fn get_used_bytes(&self) -> u64 {
+        self.used_bytes.load(Ordering::Relaxed)
+    }
 }
 
 pub struct FileEntry {
@@ -19,7 +54,20 @@ pub struct FileEntry {
 
 pub struct ContentCache {
     pub raw: Option<Arc<Bytes>>,
-    pub gzip: Option<Arc<Bytes>>,
+    // Compressed variants of the raw content by content encoding, such as "gzip", made on the first request accepting it
+    pub encoded: DashMap<String, Arc<Bytes>>,
+    // SHA-256 digests (base64) of the raw content and of the variants, calculated on first use
+    pub raw_sha256: OnceLock<String>,
+    pub encoded_sha256: DashMap<String, String>,
+    // The budget the variants are counted in, released when the entry is dropped
+    pub(crate) variant_memory: Arc<VariantMemory>,
+}
+
+impl Drop for ContentCache {
+    fn drop(&mut self) {
+        let variant_bytes: usize = self.encoded.iter().map(|variant| variant.value().len()).sum();
+        self.variant_memory.release(variant_bytes as u64);
+    }
 }
 
 #[derive(Debug)]
@@ -30,4 +78,5 @@ pub struct FileMeta {
     pub length: u64,
     pub is_too_large_to_store: bool,
     pub mime_type: String,
-}
\ No newline at end of file
+    pub is_compressible: bool, // By its content type and length, so responses with it vary by Accept-Encoding
+}
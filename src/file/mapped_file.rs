@@ -0,0 +1,93 @@
+// ============================================================================
+// MEMORY-MAPPED FILES
+// ============================================================================
+//
+// Serves medium and large static files from a read-only memory mapping, when
+// enabled in the file cache settings. The mapping is handed to hyper as Bytes,
+// and ranges are slices of it, so the content is sent from the page cache
+// without being copied into buffers first. A file must not be truncated in
+// place while it is mapped, so files should be replaced by renaming a new file
+// over them. Memory mapping is only available on unix, elsewhere files are
+// streamed as they are without it.
+// ============================================================================
+
+use std::fs::File;
+use std::io;
+
+use hyper::body::Bytes;
+
+// A read-only mapping of a whole file, unmapped when the last Bytes of it is dropped
+struct MappedFile {
+    address: *mut u8,
+    length: usize,
+}
+
+// The mapping is read-only and only unmapped on drop, so it can be shared between threads
+unsafe impl Send for MappedFile {}
+unsafe impl Sync for MappedFile {}
+
+impl AsRef<[u8]> for MappedFile {
+    fn as_ref(&self) -> &[u8] {
+        // SAFETY: the address is a mapping of length bytes, that lives as long as self
+        unsafe { std::slice::from_raw_parts(self.address, self.length) }
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        // SAFETY: the address and length are of a mapping made by map_file, which is unmapped only here
+        unsafe {
+            libc::munmap(self.address as *mut libc::c_void, self.length);
+        }
+    }
+}
+
+pub fn is_mmap_supported() -> bool {
+    cfg!(unix)
+}
+
+// Map the first length bytes of the file. Empty files are not mapped, as there is nothing to map
+#[cfg(unix)]
+pub fn map_file(file: &File, length: u64) -> io::Result<Bytes> {
+    use std::os::fd::AsRawFd;
+
+    if length == 0 {
+        return Ok(Bytes::new());
+    }
+    let length = usize::try_from(length).map_err(|_| io::Error::other("File is too large to map"))?;
+
+    // SAFETY: a new read-only, private mapping of an open file, which is checked for failure before use
+    let address = unsafe { libc::mmap(std::ptr::null_mut(), length, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0) };
+    if address == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Bytes::from_owner(MappedFile { address: address as *mut u8, length }))
+}
+
+#[cfg(not(unix))]
+pub fn map_file(_file: &File, _length: u64) -> io::Result<Bytes> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Memory-mapped files are only supported on unix"))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_file() {
+        let path = std::env::temp_dir().join(format!("gruxi-mapped-file-{}.txt", std::process::id()));
+        std::fs::write(&path, b"0123456789").unwrap();
+        let file = File::open(&path).unwrap();
+
+        let mapped = map_file(&file, 10).unwrap();
+        assert_eq!(mapped.as_ref(), b"0123456789");
+        // Ranges are slices of the same mapping, which stays mapped while any of them is alive
+        let range = mapped.slice(2..5);
+        drop(mapped);
+        assert_eq!(range.as_ref(), b"234");
+
+        assert!(map_file(&file, 0).unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,4 +1,10 @@
-pub mod file_util;
+pub mod disk_usage;
 pub mod file_reader_cache;
 pub mod file_reader_structs;
-pub mod normalized_path;
\ No newline at end of file
+pub mod file_util;
+pub mod mapped_file;
+pub mod normalized_path;
+pub mod open_file_cache;
+pub mod site_deployments;
+pub mod upload_scanner;
+pub mod webroot_sync;
@@ -0,0 +1,312 @@
+// ============================================================================
+// OPEN FILE CACHE
+// ============================================================================
+//
+// Keeps files open, with their size and modification time, so files that are
+// not held in the content cache, such as large files, are not opened and
+// stat'ed again on every request. Reads use positional I/O on the shared file,
+// so any number of responses can stream the same file at once. An entry is
+// checked against the file on disk when it is older than the revalidation
+// interval, and opened again when the file was changed or replaced. When the
+// cache is full, the least recently used file is closed. Files within the
+// memory mapping thresholds are mapped on first use, and the mapping is kept
+// with the open file.
+// ============================================================================
+
+use std::fs::File;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use dashmap::DashMap;
+use futures::stream;
+use http_body_util::{BodyExt, StreamBody, combinators::BoxBody};
+use hyper::body::{Bytes, Frame};
+
+use crate::file::mapped_file::{is_mmap_supported, map_file};
+use crate::http::request_response::body_error::{BodyError, box_err};
+use crate::logging::syslog::{trace, warn};
+
+// Size of the reads when streaming a file
+const READ_CHUNK_SIZE: u64 = 64 * 1024;
+
+pub struct OpenFileCache {
+    entries: DashMap<String, Arc<OpenFile>>,
+    max_entries: usize,   // 0 disables the cache
+    min_file_length: u64, // Smaller files are not kept open, as the content cache holds them
+    revalidate_after: Duration,
+    mmap_file_length_range: Option<(u64, u64)>, // Smallest and largest files served from a memory mapping, if enabled
+    created: Instant,
+    access_counter: AtomicU64,
+}
+
+pub struct OpenFile {
+    file: File,
+    pub length: u64,
+    pub modified: SystemTime,
+    identity: u64,              // The inode on unix, so a file replaced by a rename is noticed even with the same size and time
+    validated_at_ms: AtomicU64, // Milliseconds since the cache was created
+    last_used: AtomicU64,
+    mapping: OnceLock<Option<Bytes>>, // Mapped on first use, None when mapping failed
+}
+
+fn get_identity(metadata: &std::fs::Metadata) -> u64 {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::MetadataExt::ino(metadata)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        0
+    }
+}
+
+impl OpenFile {
+    fn is_same_file(&self, metadata: &std::fs::Metadata) -> bool {
+        metadata.is_file() && metadata.len() == self.length && metadata.modified().ok() == Some(self.modified) && get_identity(metadata) == self.identity
+    }
+
+    // Read up to length bytes at the offset, without moving a shared position
+    pub fn read_at(&self, offset: u64, length: usize) -> io::Result<Bytes> {
+        let mut buffer = vec![0u8; length];
+        let mut filled = 0;
+        while filled < length {
+            #[cfg(unix)]
+            let read = std::os::unix::fs::FileExt::read_at(&self.file, &mut buffer[filled..], offset + filled as u64)?;
+            #[cfg(windows)]
+            let read = std::os::windows::fs::FileExt::seek_read(&self.file, &mut buffer[filled..], offset + filled as u64)?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+        }
+        buffer.truncate(filled);
+        Ok(Bytes::from(buffer))
+    }
+}
+
+impl OpenFileCache {
+    pub fn new(max_entries: usize, min_file_length: u64, revalidate_seconds: u64, mmap_file_length_range: Option<(u64, u64)>) -> Self {
+        let mmap_file_length_range = match mmap_file_length_range {
+            Some(_) if !is_mmap_supported() => {
+                warn("Memory-mapped file serving is enabled, but not supported on this platform, so files are streamed instead".to_string());
+                None
+            }
+            range => range,
+        };
+
+        OpenFileCache {
+            entries: DashMap::new(),
+            max_entries,
+            min_file_length,
+            revalidate_after: Duration::from_secs(revalidate_seconds),
+            mmap_file_length_range,
+            created: Instant::now(),
+            access_counter: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_entries > 0
+    }
+
+    pub fn get_current_item_count(&self) -> u64 {
+        self.entries.len() as u64
+    }
+
+    pub fn is_mmap_enabled(&self) -> bool {
+        self.mmap_file_length_range.is_some()
+    }
+
+    // Whether a file of this length is served from a memory mapping
+    pub fn is_mmap_eligible(&self, file_length: u64) -> bool {
+        self.mmap_file_length_range
+            .is_some_and(|(min_length, max_length)| file_length > 0 && file_length >= min_length && file_length <= max_length)
+    }
+
+    // The content of the open file as a memory mapping, when its length is within the thresholds. Slices of it share the mapping
+    pub fn get_mapped_content(&self, open_file: &OpenFile) -> Option<Bytes> {
+        if !self.is_mmap_eligible(open_file.length) {
+            return None;
+        }
+        open_file
+            .mapping
+            .get_or_init(|| match map_file(&open_file.file, open_file.length) {
+                Ok(mapping) => Some(mapping),
+                Err(e) => {
+                    warn(format!("Failed to memory map file of {} bytes, streaming it instead: {}", open_file.length, e));
+                    None
+                }
+            })
+            .clone()
+    }
+
+    fn get_elapsed_ms(&self) -> u64 {
+        self.created.elapsed().as_millis() as u64
+    }
+
+    // Get the open file for a path, opening it when it is not cached or was changed. Fails for missing files and
+    // directories, which are left to the caller
+    pub fn get(&self, file_path: &str) -> io::Result<Arc<OpenFile>> {
+        if let Some(open_file) = self.get_valid_entry(file_path) {
+            open_file.last_used.store(self.access_counter.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+            return Ok(open_file);
+        }
+
+        let file = File::open(file_path)?;
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Err(io::Error::other("Not a regular file"));
+        }
+
+        let open_file = Arc::new(OpenFile {
+            length: metadata.len(),
+            modified: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            identity: get_identity(&metadata),
+            file,
+            validated_at_ms: AtomicU64::new(self.get_elapsed_ms()),
+            last_used: AtomicU64::new(self.access_counter.fetch_add(1, Ordering::Relaxed)),
+            mapping: OnceLock::new(),
+        });
+
+        if self.is_enabled() && open_file.length >= self.min_file_length {
+            if self.entries.len() >= self.max_entries && !self.entries.contains_key(file_path) {
+                self.evict_least_recently_used();
+            }
+            self.entries.insert(file_path.to_string(), open_file.clone());
+        }
+        Ok(open_file)
+    }
+
+    // The cached entry, checked against the file on disk when it has not been for the revalidation interval
+    fn get_valid_entry(&self, file_path: &str) -> Option<Arc<OpenFile>> {
+        let open_file = self.entries.get(file_path)?.value().clone();
+        let now_ms = self.get_elapsed_ms();
+        if now_ms.saturating_sub(open_file.validated_at_ms.load(Ordering::Relaxed)) < self.revalidate_after.as_millis() as u64 {
+            return Some(open_file);
+        }
+
+        match std::fs::metadata(file_path) {
+            Ok(metadata) if open_file.is_same_file(&metadata) => {
+                open_file.validated_at_ms.store(now_ms, Ordering::Relaxed);
+                Some(open_file)
+            }
+            _ => {
+                trace(format!("[OpenFileCache] File was changed or removed, closing it: {}", file_path));
+                self.entries.remove(file_path);
+                None
+            }
+        }
+    }
+
+    fn evict_least_recently_used(&self) {
+        let least_recently_used = self.entries.iter().min_by_key(|entry| entry.value().last_used.load(Ordering::Relaxed)).map(|entry| entry.key().clone());
+        if let Some(file_path) = least_recently_used {
+            trace(format!("[OpenFileCache] Cache is full, closing least recently used file: {}", file_path));
+            self.entries.remove(&file_path);
+        }
+    }
+
+    // Close the cached files under a directory, such as after its content was replaced
+    pub fn remove_directory(&self, directory_path: &str) {
+        self.entries.retain(|file_path, _| !file_path.starts_with(directory_path));
+    }
+}
+
+// Stream the content of an open file, read in chunks on the blocking thread pool
+pub fn get_open_file_body(open_file: Arc<OpenFile>) -> BoxBody<Bytes, BodyError> {
+    let length = open_file.length;
+    let chunks = stream::try_unfold(0u64, move |offset| {
+        let open_file = open_file.clone();
+        async move {
+            if offset >= length {
+                return Ok(None);
+            }
+            let chunk_length = (length - offset).min(READ_CHUNK_SIZE) as usize;
+            let chunk = tokio::task::spawn_blocking(move || open_file.read_at(offset, chunk_length)).await.map_err(io::Error::other)??;
+            if chunk.is_empty() {
+                // The file was truncated since it was opened
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "File is shorter than when it was opened"));
+            }
+            let next_offset = offset + chunk.len() as u64;
+            Ok(Some((Frame::data(chunk), next_offset)))
+        }
+    });
+    BoxBody::new(StreamBody::new(chunks).map_err(box_err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_test_file(name: &str, content: &[u8]) -> String {
+        let directory = std::env::temp_dir().join(format!("gruxi-open-file-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(name);
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_open_file_cache_serves_and_revalidates() {
+        let path = write_test_file("revalidate.bin", &vec![b'a'; 200 * 1024]);
+        let cache = OpenFileCache::new(10, 0, 0, None);
+
+        let first = cache.get(&path).unwrap();
+        assert_eq!(first.length, 200 * 1024);
+        let body = get_open_file_body(first.clone()).collect().await.unwrap().to_bytes();
+        assert_eq!(body.len(), 200 * 1024);
+
+        // Unchanged files are served from the same open file
+        assert!(Arc::ptr_eq(&first, &cache.get(&path).unwrap()));
+
+        // A replaced file is opened again
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&path, b"changed").unwrap();
+        let changed = cache.get(&path).unwrap();
+        assert_eq!(changed.length, 7);
+        assert_eq!(changed.read_at(0, 7).unwrap().as_ref(), b"changed");
+    }
+
+    #[test]
+    fn test_open_file_cache_evicts_least_recently_used() {
+        let paths: Vec<String> = (0..3).map(|index| write_test_file(&format!("lru{}.bin", index), b"content")).collect();
+        let cache = OpenFileCache::new(2, 0, 60, None);
+
+        cache.get(&paths[0]).unwrap();
+        cache.get(&paths[1]).unwrap();
+        cache.get(&paths[0]).unwrap();
+        cache.get(&paths[2]).unwrap();
+
+        assert_eq!(cache.get_current_item_count(), 2);
+        assert!(cache.entries.contains_key(&paths[0]));
+        assert!(!cache.entries.contains_key(&paths[1]));
+
+        // Small files are left to the content cache, directories are not cached
+        let small_file_cache = OpenFileCache::new(2, 1024, 60, None);
+        small_file_cache.get(&paths[0]).unwrap();
+        assert_eq!(small_file_cache.get_current_item_count(), 0);
+        assert!(cache.get(&std::env::temp_dir().to_string_lossy()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_open_file_cache_maps_files_within_thresholds() {
+        let large_path = write_test_file("mapped.bin", &vec![b'm'; 4096]);
+        let small_path = write_test_file("not-mapped.bin", b"small");
+        let cache = OpenFileCache::new(10, 0, 60, Some((1024, 1024 * 1024)));
+
+        let large_file = cache.get(&large_path).unwrap();
+        let mapped = cache.get_mapped_content(&large_file).unwrap();
+        assert_eq!(mapped.len(), 4096);
+        // The mapping is made once and kept with the open file
+        assert_eq!(cache.get_mapped_content(&large_file).unwrap().as_ptr(), mapped.as_ptr());
+        assert_eq!(mapped.slice(10..20).as_ref(), &[b'm'; 10]);
+
+        let small_file = cache.get(&small_path).unwrap();
+        assert!(cache.get_mapped_content(&small_file).is_none());
+        assert!(OpenFileCache::new(10, 0, 60, None).get_mapped_content(&large_file).is_none());
+    }
+}
@@ -0,0 +1,404 @@
+// ============================================================================
+// SITE DEPLOYMENTS
+// ============================================================================
+//
+// Deploys static sites from uploaded bundles, as zip, tar or tar.gz files.
+// Each deployment is a release, unpacked next to the web root into
+//
+//   <web root>.releases/<release id>/
+//
+// and the web root itself is a symlink to the active release, so switching to
+// a new release, or back to a previous one, is a single rename of the symlink
+// and requests never see a half-unpacked web root. A web root that is a plain
+// directory before the first deployment is kept as the "initial" release.
+//
+// Bundles are verified before anything is switched: the SHA-256 checksum when
+// the client sends one, and each entry, which must be a plain file or
+// directory inside the bundle, within the entry count and size limits. When
+// everything in the bundle is in one top directory, such as dist/, the
+// content of that directory is the release. The last MAX_KEPT_RELEASES
+// releases are kept for rollbacks.
+// ============================================================================
+
+use std::io::{Cursor, Read};
+use std::path::{Component, Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::configuration::configuration::Configuration;
+use crate::core::running_state_manager::get_running_state_manager;
+use crate::file::normalized_path::NormalizedPath;
+
+const INITIAL_RELEASE_ID: &str = "initial";
+const MAX_KEPT_RELEASES: usize = 5;
+const MAX_BUNDLE_ENTRIES: usize = 100_000;
+const MAX_UNPACKED_SIZE: u64 = 2 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Serialize)]
+pub struct DeploymentStatus {
+    pub web_root: String,
+    pub active_release: Option<String>,
+    pub releases: Vec<String>, // Oldest first
+}
+
+// The web root deployments of a site go to, which is the web root of its static file processors
+pub fn get_site_deployment_web_root(configuration: &Configuration, site_id: &str) -> Result<String, String> {
+    let site = configuration.sites.iter().find(|site| site.id == site_id).ok_or_else(|| "Site not found".to_string())?;
+
+    let mut web_roots: Vec<String> = site
+        .request_handlers
+        .iter()
+        .filter_map(|handler_id| configuration.request_handlers.iter().find(|handler| &handler.id == handler_id))
+        .filter(|handler| handler.is_enabled && handler.processor_type == "static")
+        .filter_map(|handler| configuration.static_file_processors.iter().find(|processor| processor.id == handler.processor_id))
+        .map(|processor| processor.web_root.trim_end_matches(['/', '\\']).to_string())
+        .collect();
+    web_roots.sort();
+    web_roots.dedup();
+
+    match web_roots.len() {
+        0 => Err("The site has no static file processor to deploy to".to_string()),
+        1 => Ok(web_roots.remove(0)),
+        _ => Err(format!("The site has static file processors with different web roots: {}", web_roots.join(", "))),
+    }
+}
+
+// Verify and unpack the bundle as a new release, and make it the active one. Returns the ID of the release
+pub async fn deploy_bundle(web_root: &str, bundle: Vec<u8>, expected_sha256: Option<String>) -> Result<String, String> {
+    let web_root_path = PathBuf::from(web_root);
+    let release_id = tokio::task::spawn_blocking(move || {
+        if let Some(expected_sha256) = expected_sha256 {
+            let sha256: String = ring::digest::digest(&ring::digest::SHA256, &bundle).as_ref().iter().map(|byte| format!("{:02x}", byte)).collect();
+            if !sha256.eq_ignore_ascii_case(expected_sha256.trim()) {
+                return Err(format!("Checksum mismatch, the bundle has SHA-256 {}", sha256));
+            }
+        }
+        create_release(&web_root_path, &bundle)
+    })
+    .await
+    .map_err(|e| format!("Deployment task failed: {}", e))??;
+
+    // A release that cannot be switched to is not kept, so it is not activated by mistake later
+    if let Err(e) = activate_release(web_root, &release_id).await {
+        let _ = tokio::fs::remove_dir_all(get_releases_path(Path::new(web_root)).join(&release_id)).await;
+        return Err(e);
+    }
+    Ok(release_id)
+}
+
+// Make a release the active one, such as to roll back to a previous release
+pub async fn activate_release(web_root: &str, release_id: &str) -> Result<(), String> {
+    let web_root_path = PathBuf::from(web_root);
+    let switched_release_id = release_id.to_string();
+    tokio::task::spawn_blocking(move || switch_to_release(&web_root_path, &switched_release_id))
+        .await
+        .map_err(|e| format!("Deployment task failed: {}", e))??;
+
+    // Cached files of the previous release are not served any longer
+    if let Ok(normalized_web_root) = NormalizedPath::new(web_root, "") {
+        let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+        running_state.get_file_reader_cache().remove_directory(&normalized_web_root.get_full_path());
+    }
+    Ok(())
+}
+
+pub fn get_deployment_status(web_root: &str) -> DeploymentStatus {
+    let web_root_path = Path::new(web_root);
+    DeploymentStatus {
+        web_root: web_root.to_string(),
+        active_release: get_active_release(web_root_path),
+        releases: list_releases(&get_releases_path(web_root_path)),
+    }
+}
+
+fn get_releases_path(web_root: &Path) -> PathBuf {
+    let mut path = web_root.as_os_str().to_owned();
+    path.push(".releases");
+    PathBuf::from(path)
+}
+
+fn get_active_release(web_root: &Path) -> Option<String> {
+    let target = std::fs::read_link(web_root).ok()?;
+    target.file_name().map(|name| name.to_string_lossy().to_string())
+}
+
+// Release IDs sort by the time they were deployed, with the initial release first
+fn list_releases(releases_path: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(releases_path) else {
+        return Vec::new();
+    };
+    let mut releases: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|name| !name.starts_with('.'))
+        .collect();
+    releases.sort_by_key(|name| (name != INITIAL_RELEASE_ID, name.clone()));
+    releases
+}
+
+// Unpack the bundle into a staging directory in the releases directory, which is renamed to the release when complete
+fn create_release(web_root: &Path, bundle: &[u8]) -> Result<String, String> {
+    let releases_path = get_releases_path(web_root);
+    std::fs::create_dir_all(&releases_path).map_err(|e| format!("Failed to create {}: {}", releases_path.display(), e))?;
+
+    let base_release_id = chrono::Utc::now().format("%Y%m%d-%H%M%S").to_string();
+    let mut release_id = base_release_id.clone();
+    let mut suffix = 1;
+    while releases_path.join(&release_id).exists() {
+        suffix += 1;
+        release_id = format!("{}-{}", base_release_id, suffix);
+    }
+
+    let staging_path = releases_path.join(format!(".{}.staging", release_id));
+    if let Err(e) = unpack_bundle(bundle, &staging_path) {
+        let _ = std::fs::remove_dir_all(&staging_path);
+        return Err(e);
+    }
+
+    // A bundle of a single top directory, such as dist/, deploys the content of that directory
+    let mut content_path = staging_path.clone();
+    if let Ok(entries) = std::fs::read_dir(&staging_path) {
+        let entries: Vec<_> = entries.flatten().collect();
+        if entries.len() == 1 && entries[0].file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            content_path = entries[0].path();
+        }
+    }
+
+    let release_path = releases_path.join(&release_id);
+    let rename_result = std::fs::rename(&content_path, &release_path);
+    let _ = std::fs::remove_dir_all(&staging_path);
+    rename_result.map_err(|e| format!("Failed to move the unpacked bundle into {}: {}", release_path.display(), e))?;
+
+    Ok(release_id)
+}
+
+fn unpack_bundle(bundle: &[u8], target_path: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(target_path).map_err(|e| format!("Failed to create {}: {}", target_path.display(), e))?;
+
+    if bundle.starts_with(b"PK\x03\x04") {
+        return unpack_zip(bundle, target_path);
+    }
+    if bundle.starts_with(&[0x1f, 0x8b]) {
+        return unpack_tar(flate2::read::GzDecoder::new(bundle), target_path);
+    }
+    if bundle.len() > 262 && &bundle[257..262] == b"ustar" {
+        return unpack_tar(bundle, target_path);
+    }
+    Err("The bundle is not a zip, tar or tar.gz file".to_string())
+}
+
+fn unpack_zip(bundle: &[u8], target_path: &Path) -> Result<(), String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bundle)).map_err(|e| format!("Invalid zip file: {}", e))?;
+    if archive.len() > MAX_BUNDLE_ENTRIES {
+        return Err(format!("The bundle has more than {} entries", MAX_BUNDLE_ENTRIES));
+    }
+
+    let mut unpacked_size = 0;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(|e| format!("Invalid zip entry: {}", e))?;
+        let entry_name = entry.name().to_string();
+        let relative_path = get_safe_entry_path(&entry_name)?;
+        if entry.is_symlink() {
+            return Err(format!("The bundle entry '{}' is a symlink, which is not allowed", entry_name));
+        }
+
+        if entry.is_dir() {
+            create_entry_directory(target_path, &relative_path)?;
+        } else if !relative_path.as_os_str().is_empty() {
+            unpacked_size += write_entry_file(target_path, &relative_path, &mut entry, unpacked_size)?;
+        }
+    }
+    Ok(())
+}
+
+fn unpack_tar<R: Read>(reader: R, target_path: &Path) -> Result<(), String> {
+    let mut archive = tar::Archive::new(reader);
+    let entries = archive.entries().map_err(|e| format!("Invalid tar file: {}", e))?;
+
+    let mut unpacked_size = 0;
+    for (index, entry) in entries.enumerate() {
+        if index >= MAX_BUNDLE_ENTRIES {
+            return Err(format!("The bundle has more than {} entries", MAX_BUNDLE_ENTRIES));
+        }
+        let mut entry = entry.map_err(|e| format!("Invalid tar entry: {}", e))?;
+        let entry_name = entry.path().map(|path| path.to_string_lossy().to_string()).map_err(|e| format!("Invalid tar entry path: {}", e))?;
+        let relative_path = get_safe_entry_path(&entry_name)?;
+
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => create_entry_directory(target_path, &relative_path)?,
+            tar::EntryType::Regular | tar::EntryType::Continuous if !relative_path.as_os_str().is_empty() => {
+                unpacked_size += write_entry_file(target_path, &relative_path, &mut entry, unpacked_size)?;
+            }
+            // Headers carrying metadata of the next entry
+            tar::EntryType::XHeader | tar::EntryType::XGlobalHeader | tar::EntryType::GNULongName => {}
+            _ => return Err(format!("The bundle entry '{}' is not a file or directory, which is not allowed", entry_name)),
+        }
+    }
+    Ok(())
+}
+
+// The path of an entry within the release, which may not leave it
+fn get_safe_entry_path(entry_name: &str) -> Result<PathBuf, String> {
+    let mut relative_path = PathBuf::new();
+    for component in Path::new(&entry_name.replace('\\', "/")).components() {
+        match component {
+            Component::Normal(part) => relative_path.push(part),
+            Component::CurDir => {}
+            _ => return Err(format!("The bundle entry '{}' has a path outside of the bundle", entry_name)),
+        }
+    }
+    Ok(relative_path)
+}
+
+fn create_entry_directory(target_path: &Path, relative_path: &Path) -> Result<(), String> {
+    let path = target_path.join(relative_path);
+    std::fs::create_dir_all(&path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))
+}
+
+// Write a file of the bundle, returning its size. The sizes entries declare are not trusted, the content is counted
+fn write_entry_file<R: Read>(target_path: &Path, relative_path: &Path, reader: &mut R, unpacked_size: u64) -> Result<u64, String> {
+    let path = target_path.join(relative_path);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let mut file = std::fs::File::create(&path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let remaining_size = MAX_UNPACKED_SIZE - unpacked_size;
+    let copied = std::io::copy(&mut reader.take(remaining_size + 1), &mut file).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    if copied > remaining_size {
+        return Err(format!("The bundle unpacks to more than {} bytes", MAX_UNPACKED_SIZE));
+    }
+    Ok(copied)
+}
+
+// Point the web root symlink to the release, by renaming a new symlink over it. A web root that is a plain directory is
+// kept as the initial release. Old releases beyond the kept ones are removed afterwards
+fn switch_to_release(web_root: &Path, release_id: &str) -> Result<(), String> {
+    let releases_path = get_releases_path(web_root);
+    if release_id.is_empty() || release_id.starts_with('.') || release_id.contains(['/', '\\']) || !releases_path.join(release_id).is_dir() {
+        return Err(format!("Release '{}' does not exist", release_id));
+    }
+
+    // The releases directory name is also the start of the symlink target, telling symlinks of deployments from others
+    let releases_directory_name = releases_path.file_name().map(|name| name.to_os_string()).unwrap_or_default();
+    let is_symlink = std::fs::symlink_metadata(web_root).is_ok_and(|metadata| metadata.file_type().is_symlink());
+    if is_symlink && !std::fs::read_link(web_root).is_ok_and(|target| target.starts_with(&releases_directory_name)) {
+        return Err(format!("The web root {} is a symlink that is not managed by deployments", web_root.display()));
+    }
+    if !is_symlink && web_root.exists() {
+        let initial_path = releases_path.join(INITIAL_RELEASE_ID);
+        std::fs::rename(web_root, &initial_path).map_err(|e| format!("Failed to keep {} as the initial release: {}", web_root.display(), e))?;
+    } else if let Some(parent) = web_root.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+
+    // The target is relative, so the web root and its releases can be moved together
+    let target = Path::new(&releases_directory_name).join(release_id);
+    let mut switch_path = web_root.as_os_str().to_owned();
+    switch_path.push(".switch");
+    let switch_path = PathBuf::from(switch_path);
+    let _ = std::fs::remove_file(&switch_path);
+    create_directory_symlink(&target, &switch_path).map_err(|e| format!("Failed to create a symlink to release '{}': {}", release_id, e))?;
+
+    // Windows cannot rename over an existing symlink, so there the old one is removed first
+    #[cfg(windows)]
+    if is_symlink {
+        let _ = std::fs::remove_dir(web_root);
+    }
+    if let Err(e) = std::fs::rename(&switch_path, web_root) {
+        let _ = std::fs::remove_file(&switch_path);
+        return Err(format!("Failed to switch {} to release '{}': {}", web_root.display(), release_id, e));
+    }
+
+    remove_old_releases(&releases_path, release_id);
+    Ok(())
+}
+
+fn remove_old_releases(releases_path: &Path, active_release_id: &str) {
+    let releases = list_releases(releases_path);
+    if releases.len() <= MAX_KEPT_RELEASES {
+        return;
+    }
+    for release_id in releases.iter().take(releases.len() - MAX_KEPT_RELEASES).filter(|release_id| *release_id != active_release_id) {
+        let _ = std::fs::remove_dir_all(releases_path.join(release_id));
+    }
+}
+
+#[cfg(unix)]
+fn create_directory_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_directory_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_dir(target, link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_tar_bundle(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_deploy_and_roll_back_releases() {
+        let base = std::env::temp_dir().join(format!("gruxi-deployments-test-{}", std::process::id()));
+        let web_root = base.join("webroot");
+        std::fs::create_dir_all(&web_root).unwrap();
+        std::fs::write(web_root.join("index.html"), "before").unwrap();
+
+        // The content before the first deployment is kept as the initial release
+        let first_release = create_release(&web_root, &get_tar_bundle(&[("dist/index.html", "first")])).unwrap();
+        switch_to_release(&web_root, &first_release).unwrap();
+        assert_eq!(std::fs::read_to_string(web_root.join("index.html")).unwrap(), "first");
+        assert_eq!(get_active_release(&web_root).as_deref(), Some(first_release.as_str()));
+
+        let second_release = create_release(&web_root, &get_tar_bundle(&[("index.html", "second"), ("css/site.css", "body {}")])).unwrap();
+        switch_to_release(&web_root, &second_release).unwrap();
+        assert_eq!(std::fs::read_to_string(web_root.join("css/site.css")).unwrap(), "body {}");
+        assert_eq!(
+            list_releases(&get_releases_path(&web_root)),
+            vec![INITIAL_RELEASE_ID.to_string(), first_release.clone(), second_release]
+        );
+
+        switch_to_release(&web_root, INITIAL_RELEASE_ID).unwrap();
+        assert_eq!(std::fs::read_to_string(web_root.join("index.html")).unwrap(), "before");
+        assert!(switch_to_release(&web_root, "../webroot").is_err());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_unsafe_bundles_are_rejected() {
+        let base = std::env::temp_dir().join(format!("gruxi-deployments-unsafe-test-{}", std::process::id()));
+
+        assert!(get_safe_entry_path("../etc/passwd").is_err());
+        assert!(get_safe_entry_path("/etc/passwd").is_err());
+        assert_eq!(get_safe_entry_path("./assets/app.js").unwrap(), PathBuf::from("assets/app.js"));
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_cksum();
+        builder.append_link(&mut header, "passwd", "/etc/passwd").unwrap();
+        let symlink_bundle = builder.into_inner().unwrap();
+        assert!(create_release(&base.join("webroot"), &symlink_bundle).is_err());
+        assert!(create_release(&base.join("webroot"), b"not a bundle").is_err());
+        assert!(list_releases(&get_releases_path(&base.join("webroot"))).is_empty());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,286 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::process::Command;
+use uuid::Uuid;
+
+use crate::{
+    configuration::upload_scanning::UploadScanning,
+    logging::syslog::{trace, warn},
+};
+
+// ClamAV style exit codes for the scanner command
+const SCANNER_EXIT_CODE_CLEAN: i32 = 0;
+const SCANNER_EXIT_CODE_INFECTED: i32 = 1;
+
+const ICAP_DEFAULT_PORT: u16 = 1344;
+
+#[derive(Debug, PartialEq)]
+pub enum UploadScanVerdict {
+    Clean,
+    Infected(String), // Reason reported by the scanner, such as the signature name
+}
+
+// A rejected upload, stored as "<id>.json" next to the quarantined file "<id>.bin"
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    pub id: String,
+    pub original_path: String,
+    pub reason: String,
+    pub size: u64,
+    pub quarantined_at: String,
+}
+
+// Scan a file that is not yet live. Errors mean the scan could not be completed, and the file should not be made live
+pub async fn scan_upload(settings: &UploadScanning, file_path: &Path) -> Result<UploadScanVerdict, String> {
+    let timeout = Duration::from_secs(settings.timeout_seconds as u64);
+    let scan = async {
+        match settings.scanner_type.as_str() {
+            "command" => scan_with_command(&settings.command, file_path).await,
+            "icap" => scan_with_icap(&settings.icap_url, file_path).await,
+            other => Err(format!("Unsupported upload scanner type: {}", other)),
+        }
+    };
+
+    match tokio::time::timeout(timeout, scan).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("Upload scan timed out after {} seconds", settings.timeout_seconds)),
+    }
+}
+
+fn get_command_arguments(command: &str, file_path: &str) -> Vec<String> {
+    let mut arguments: Vec<String> = command.split_whitespace().map(|a| a.replace("{file}", file_path)).collect();
+    if !command.contains("{file}") {
+        arguments.push(file_path.to_string());
+    }
+    arguments
+}
+
+async fn scan_with_command(command: &str, file_path: &Path) -> Result<UploadScanVerdict, String> {
+    let arguments = get_command_arguments(command, &file_path.to_string_lossy());
+    let (executable, arguments) = match arguments.split_first() {
+        Some(split) => split,
+        None => return Err("Upload scanner command is empty".to_string()),
+    };
+
+    let output = Command::new(executable)
+        .args(arguments)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run upload scanner '{}': {}", executable, e))?;
+
+    match output.status.code() {
+        Some(SCANNER_EXIT_CODE_CLEAN) => Ok(UploadScanVerdict::Clean),
+        Some(SCANNER_EXIT_CODE_INFECTED) => {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let reason = stdout.lines().find(|l| !l.trim().is_empty()).unwrap_or("Rejected by upload scanner").trim().to_string();
+            Ok(UploadScanVerdict::Infected(reason))
+        }
+        _ => Err(format!(
+            "Upload scanner '{}' failed with {}: {}",
+            executable,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+    }
+}
+
+// Send the file to an ICAP server (RFC 3507) as an encapsulated HTTP response, 204 means no modification is needed, so the file is clean
+async fn scan_with_icap(icap_url: &str, file_path: &Path) -> Result<UploadScanVerdict, String> {
+    let uri = icap_url.parse::<http::Uri>().map_err(|e| format!("Invalid ICAP server URL '{}': {}", icap_url, e))?;
+    let host = uri.host().ok_or_else(|| format!("ICAP server URL has no host: {}", icap_url))?;
+    let port = uri.port_u16().unwrap_or(ICAP_DEFAULT_PORT);
+
+    let file_content = tokio::fs::read(file_path).await.map_err(|e| format!("Failed to read upload for scanning: {}", e))?;
+
+    let mut stream = TcpStream::connect((host, port))
+        .await
+        .map_err(|e| format!("Failed to connect to ICAP server {}:{}: {}", host, port, e))?;
+    stream
+        .write_all(&build_icap_request(&uri, &file_content))
+        .await
+        .map_err(|e| format!("Failed to send to ICAP server: {}", e))?;
+
+    // The ICAP headers are all we need, the server may close the connection after them
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 4096];
+    while !response.windows(4).any(|w| w == b"\r\n\r\n") {
+        let read_bytes = stream.read(&mut buffer).await.map_err(|e| format!("Failed to read from ICAP server: {}", e))?;
+        if read_bytes == 0 {
+            break;
+        }
+        response.extend_from_slice(&buffer[..read_bytes]);
+    }
+
+    parse_icap_response(&String::from_utf8_lossy(&response))
+}
+
+fn build_icap_request(uri: &http::Uri, file_content: &[u8]) -> Vec<u8> {
+    let http_response_header = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", file_content.len());
+    let mut request = format!(
+        "RESPMOD {} ICAP/1.0\r\nHost: {}\r\nAllow: 204\r\nConnection: close\r\nEncapsulated: res-hdr=0, res-body={}\r\n\r\n{}",
+        uri,
+        uri.authority().map(|a| a.as_str()).unwrap_or(""),
+        http_response_header.len(),
+        http_response_header
+    )
+    .into_bytes();
+
+    // Body is sent chunked, as a single chunk followed by the last chunk
+    if !file_content.is_empty() {
+        request.extend_from_slice(format!("{:x}\r\n", file_content.len()).as_bytes());
+        request.extend_from_slice(file_content);
+        request.extend_from_slice(b"\r\n");
+    }
+    request.extend_from_slice(b"0\r\n\r\n");
+    request
+}
+
+fn parse_icap_response(response: &str) -> Result<UploadScanVerdict, String> {
+    let mut lines = response.lines();
+    let status_line = lines.next().unwrap_or("");
+    let status_code = status_line.split_whitespace().nth(1).and_then(|code| code.parse::<u16>().ok());
+
+    match status_code {
+        Some(204) => Ok(UploadScanVerdict::Clean),
+        Some(200) => {
+            // The server modified the content, which antivirus servers do to block it. Most also report the threat in a header
+            let reason = lines
+                .take_while(|l| !l.is_empty())
+                .filter_map(|l| l.split_once(':'))
+                .find(|(name, _)| name.eq_ignore_ascii_case("X-Infection-Found") || name.eq_ignore_ascii_case("X-Virus-ID"))
+                .map(|(_, value)| value.trim().to_string())
+                .unwrap_or_else(|| "Rejected by ICAP server".to_string());
+            Ok(UploadScanVerdict::Infected(reason))
+        }
+        _ => Err(format!("Unexpected ICAP server response: {}", status_line)),
+    }
+}
+
+// Move a rejected upload to the quarantine directory, with its metadata, so it can be reviewed through the admin API
+pub async fn quarantine_upload(settings: &UploadScanning, file_path: &Path, original_path: &str, reason: &str) -> std::io::Result<QuarantineEntry> {
+    let quarantine_dir = PathBuf::from(&settings.quarantine_path);
+    tokio::fs::create_dir_all(&quarantine_dir).await?;
+
+    let entry = QuarantineEntry {
+        id: Uuid::new_v4().to_string(),
+        original_path: original_path.to_string(),
+        reason: reason.to_string(),
+        size: tokio::fs::metadata(file_path).await.map(|m| m.len()).unwrap_or(0),
+        quarantined_at: chrono::Utc::now().to_rfc3339(),
+    };
+
+    let quarantine_file = quarantine_dir.join(format!("{}.bin", entry.id));
+    if tokio::fs::rename(file_path, &quarantine_file).await.is_err() {
+        // Quarantine may be on another file system
+        tokio::fs::copy(file_path, &quarantine_file).await?;
+        tokio::fs::remove_file(file_path).await?;
+    }
+
+    let metadata_json = serde_json::to_string_pretty(&entry).map_err(std::io::Error::other)?;
+    tokio::fs::write(quarantine_dir.join(format!("{}.json", entry.id)), metadata_json).await?;
+
+    warn(format!("Upload '{}' was quarantined as '{}': {}", original_path, entry.id, reason));
+    Ok(entry)
+}
+
+pub fn list_quarantine(settings: &UploadScanning) -> std::io::Result<Vec<QuarantineEntry>> {
+    let quarantine_dir = Path::new(&settings.quarantine_path);
+    if !quarantine_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+    for dir_entry in std::fs::read_dir(quarantine_dir)?.flatten() {
+        let path = dir_entry.path();
+        if path.extension().map(|e| e == "json").unwrap_or(false) {
+            match std::fs::read_to_string(&path).ok().and_then(|c| serde_json::from_str::<QuarantineEntry>(&c).ok()) {
+                Some(entry) => entries.push(entry),
+                None => trace(format!("Skipping unreadable quarantine metadata: {}", path.display())),
+            }
+        }
+    }
+
+    entries.sort_by(|a, b| b.quarantined_at.cmp(&a.quarantined_at));
+    Ok(entries)
+}
+
+// Permanently delete a quarantined upload, returns false if it was not found
+pub fn delete_quarantine_entry(settings: &UploadScanning, id: &str) -> std::io::Result<bool> {
+    // Ids are generated uuids, anything else could be used for path traversal
+    if Uuid::parse_str(id).is_err() {
+        return Ok(false);
+    }
+
+    let quarantine_dir = Path::new(&settings.quarantine_path);
+    let metadata_file = quarantine_dir.join(format!("{}.json", id));
+    if !metadata_file.exists() {
+        return Ok(false);
+    }
+
+    let quarantine_file = quarantine_dir.join(format!("{}.bin", id));
+    if quarantine_file.exists() {
+        std::fs::remove_file(quarantine_file)?;
+    }
+    std::fs::remove_file(metadata_file)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_scanner_command_arguments() {
+        assert_eq!(get_command_arguments("clamdscan --no-summary {file}", "/tmp/a b"), vec!["clamdscan", "--no-summary", "/tmp/a b"]);
+        assert_eq!(get_command_arguments("clamscan", "/tmp/upload"), vec!["clamscan", "/tmp/upload"]);
+    }
+
+    #[test]
+    fn test_upload_scanner_icap_response() {
+        assert_eq!(parse_icap_response("ICAP/1.0 204 No Content\r\nISTag: \"1\"\r\n\r\n"), Ok(UploadScanVerdict::Clean));
+        assert_eq!(
+            parse_icap_response("ICAP/1.0 200 OK\r\nX-Infection-Found: Type=0; Resolution=2; Threat=Eicar-Test-Signature;\r\n\r\n"),
+            Ok(UploadScanVerdict::Infected("Type=0; Resolution=2; Threat=Eicar-Test-Signature;".to_string()))
+        );
+        assert!(parse_icap_response("ICAP/1.0 500 Server Error\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn test_upload_scanner_icap_request() {
+        let uri = "icap://127.0.0.1:1344/avscan".parse::<http::Uri>().unwrap();
+        let request = String::from_utf8(build_icap_request(&uri, b"hello")).unwrap();
+        assert!(request.starts_with("RESPMOD icap://127.0.0.1:1344/avscan ICAP/1.0\r\nHost: 127.0.0.1:1344\r\n"));
+        assert!(request.contains("Encapsulated: res-hdr=0, res-body=38\r\n"));
+        assert!(request.ends_with("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n5\r\nhello\r\n0\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_upload_scanner_quarantine_and_delete() {
+        let base_dir = std::env::temp_dir().join(format!("gruxi-quarantine-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&base_dir).unwrap();
+        let upload = base_dir.join("upload.tmp");
+        std::fs::write(&upload, b"infected").unwrap();
+
+        let mut settings = UploadScanning::new();
+        settings.quarantine_path = base_dir.join("quarantine").to_string_lossy().to_string();
+
+        let entry = quarantine_upload(&settings, &upload, "/files/upload.exe", "Eicar").await.unwrap();
+        assert!(!upload.exists());
+        assert_eq!(entry.size, 8);
+
+        let entries = list_quarantine(&settings).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].original_path, "/files/upload.exe");
+
+        assert!(!delete_quarantine_entry(&settings, "../upload").unwrap());
+        assert!(delete_quarantine_entry(&settings, &entry.id).unwrap());
+        assert!(list_quarantine(&settings).unwrap().is_empty());
+
+        std::fs::remove_dir_all(base_dir).unwrap();
+    }
+}
@@ -0,0 +1,281 @@
+// ============================================================================
+// WEBROOT SYNC
+// ============================================================================
+//
+// Pulls the content of a site from a remote SFTP or FTPS server into its local
+// web root at an interval, for sites migrated from shared hosting that are
+// still uploaded to the old way. Each sync downloads the whole tree into a
+// staging directory next to the web root, which is then swapped in, so
+// requests never see a half-synchronized web root. A failed sync leaves the
+// current content in place and raises an admin alert.
+//
+// SFTP uses the OpenSSH "sftp" client in batch mode, logging in with a private
+// key. FTPS (explicit TLS) uses the built-in client.
+// ============================================================================
+
+use std::net::Ipv6Addr;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+use crate::configuration::webroot_sync_settings::WebrootSyncSettings;
+use crate::core::admin_alerts::add_admin_alert;
+use crate::core::running_state_manager::get_running_state_manager;
+use crate::core::triggers::get_trigger_handler;
+use crate::http::cache_warmer::spawn_cache_warming;
+use crate::logging::syslog::{debug, info, trace, warn};
+use crate::network::ftps_client::{FtpEntryKind, FtpsClient};
+
+/// Start the webroot sync of each enabled site that has one. The syncs stop on
+/// shutdown or stop_services triggers, so they are started again on configuration reload.
+pub async fn start_webroot_sync() {
+    let cached_configuration = crate::configuration::cached_configuration::get_cached_configuration();
+    let config = cached_configuration.get_configuration().await;
+
+    let syncs: Vec<(String, WebrootSyncSettings, bool)> = config
+        .sites
+        .iter()
+        .filter(|site| site.is_enabled && site.webroot_sync.is_enabled())
+        .map(|site| (site.id.clone(), site.webroot_sync.clone(), site.cache_warm.is_enabled))
+        .collect();
+    if syncs.is_empty() {
+        debug("No sites have a webroot sync");
+        return;
+    }
+
+    let triggers = get_trigger_handler();
+    let shutdown_token = triggers.get_token("shutdown").await.unwrap_or_else(CancellationToken::new);
+    let stop_services_token = triggers.get_token("stop_services").await.unwrap_or_else(CancellationToken::new);
+
+    for (site_id, settings, cache_warm_enabled) in syncs {
+        info(format!(
+            "Webroot sync started for site '{}', pulling {}://{}{} every {} minutes",
+            site_id, settings.protocol, settings.host, settings.remote_path, settings.interval_minutes
+        ));
+
+        let shutdown_token = shutdown_token.clone();
+        let stop_services_token = stop_services_token.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(settings.interval_minutes as u64 * 60));
+            // A sync that takes longer than the interval is not followed by a burst of syncs
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                tokio::select! {
+                    _ = shutdown_token.cancelled() => {
+                        debug(format!("Webroot sync for site '{}' stopping due to shutdown signal", site_id));
+                        break;
+                    }
+                    _ = stop_services_token.cancelled() => {
+                        debug(format!("Webroot sync for site '{}' stopping due to stop_services signal", site_id));
+                        break;
+                    }
+                    _ = interval.tick() => {
+                        match sync_webroot(&settings).await {
+                            Ok(file_count) => {
+                                info(format!("Webroot sync for site '{}' done, {} files pulled into {}", site_id, file_count, settings.local_path));
+                                // Warm the caches with the new content, if the site has cache warming
+                                if cache_warm_enabled {
+                                    spawn_cache_warming(&site_id);
+                                }
+                            }
+                            Err(e) => add_admin_alert("Webroot sync", format!("Webroot sync for site '{}' failed, the current content is kept: {}", site_id, e)),
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+// Pull the remote content into a staging directory, and swap it in for the local web root
+async fn sync_webroot(settings: &WebrootSyncSettings) -> Result<u64, String> {
+    let local_path = PathBuf::from(settings.local_path.trim_end_matches(['/', '\\']));
+    let staging_path = get_sibling_path(&local_path, "sync-staging");
+
+    // Leftovers of an interrupted sync
+    if staging_path.exists() {
+        tokio::fs::remove_dir_all(&staging_path)
+            .await
+            .map_err(|e| format!("Failed to remove {}: {}", staging_path.display(), e))?;
+    }
+
+    let download_result = match settings.protocol.as_str() {
+        "sftp" => download_over_sftp(settings, &staging_path).await,
+        "ftps" => download_over_ftps(settings, &staging_path).await,
+        protocol => Err(format!("Unsupported protocol '{}'", protocol)),
+    };
+    let file_count = match download_result {
+        Ok(_) => count_files(&staging_path),
+        Err(e) => {
+            let _ = tokio::fs::remove_dir_all(&staging_path).await;
+            return Err(e);
+        }
+    };
+
+    swap_into_place(&staging_path, &local_path).await?;
+
+    // Cached files of the old content are not served any longer
+    let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+    running_state.get_file_reader_cache().remove_directory(&settings.local_path);
+
+    Ok(file_count)
+}
+
+// The staging directory is downloaded into, or the previous content is moved to, next to the web root, so renames stay on one file system
+fn get_sibling_path(local_path: &Path, suffix: &str) -> PathBuf {
+    let mut path = local_path.as_os_str().to_owned();
+    path.push(format!(".{}", suffix));
+    PathBuf::from(path)
+}
+
+// Replace the local web root with the staging directory. If the staging directory cannot be moved in, the previous content is restored
+async fn swap_into_place(staging_path: &Path, local_path: &Path) -> Result<(), String> {
+    let previous_path = get_sibling_path(local_path, "sync-previous");
+    if previous_path.exists() {
+        tokio::fs::remove_dir_all(&previous_path)
+            .await
+            .map_err(|e| format!("Failed to remove {}: {}", previous_path.display(), e))?;
+    }
+
+    let has_previous = local_path.exists();
+    if has_previous {
+        tokio::fs::rename(local_path, &previous_path)
+            .await
+            .map_err(|e| format!("Failed to move {} aside: {}", local_path.display(), e))?;
+    }
+    if let Err(e) = tokio::fs::rename(staging_path, local_path).await {
+        if has_previous {
+            let _ = tokio::fs::rename(&previous_path, local_path).await;
+        }
+        return Err(format!("Failed to move the synchronized content into {}: {}", local_path.display(), e));
+    }
+
+    if has_previous && let Err(e) = tokio::fs::remove_dir_all(&previous_path).await {
+        warn(format!("Failed to remove the previous content in {}: {}", previous_path.display(), e));
+    }
+    Ok(())
+}
+
+async fn download_over_sftp(settings: &WebrootSyncSettings, staging_path: &Path) -> Result<(), String> {
+    // IPv6 addresses are bracketed, as sftp would take their colons for a path separator
+    let host = if settings.host.parse::<Ipv6Addr>().is_ok() {
+        format!("[{}]", settings.host)
+    } else {
+        settings.host.clone()
+    };
+
+    let mut child = Command::new("sftp")
+        .arg("-b")
+        .arg("-")
+        .arg("-P")
+        .arg(settings.get_port().to_string())
+        .arg("-i")
+        .arg(&settings.private_key_path)
+        .args(["-o", "BatchMode=yes", "-o", "StrictHostKeyChecking=accept-new", "-o", "ConnectTimeout=30"])
+        .arg("-o")
+        .arg(format!("User={}", settings.username))
+        .arg(host)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("Failed to start the sftp client, is OpenSSH installed? {}", e))?;
+
+    // The content of the remote directory is downloaded into the staging directory, which sftp creates
+    let batch = format!("get -R \"{}\" \"{}\"\n", settings.remote_path, staging_path.display());
+    trace(format!("Webroot sync sftp batch: {}", batch.trim_end()));
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(batch.as_bytes()).await.map_err(|e| format!("Failed to send commands to sftp: {}", e))?;
+    }
+
+    let output = child.wait_with_output().await.map_err(|e| format!("Failed to run sftp: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("sftp failed ({}): {}", output.status, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    if !staging_path.is_dir() {
+        return Err(format!("sftp did not download the directory {}", settings.remote_path));
+    }
+    Ok(())
+}
+
+async fn download_over_ftps(settings: &WebrootSyncSettings, staging_path: &Path) -> Result<(), String> {
+    let mut client = FtpsClient::connect(&settings.host, settings.get_port(), settings.verify_tls_certificates).await?;
+    client.login(&settings.username, &settings.password).await?;
+
+    tokio::fs::create_dir_all(staging_path)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", staging_path.display(), e))?;
+    let mut directories = vec![(settings.remote_path.trim_end_matches('/').to_string(), staging_path.to_path_buf())];
+    while let Some((remote_directory, local_directory)) = directories.pop() {
+        let listing_path = if remote_directory.is_empty() { "/" } else { remote_directory.as_str() };
+        for entry in client.list_directory(listing_path).await? {
+            let remote_path = format!("{}/{}", remote_directory, entry.name);
+            let local_path = local_directory.join(&entry.name);
+            match entry.kind {
+                FtpEntryKind::Directory => {
+                    tokio::fs::create_dir(&local_path).await.map_err(|e| format!("Failed to create {}: {}", local_path.display(), e))?;
+                    directories.push((remote_path, local_path));
+                }
+                FtpEntryKind::File => {
+                    client.download_file(&remote_path, &local_path).await?;
+                }
+            }
+        }
+    }
+
+    client.quit().await;
+    Ok(())
+}
+
+fn count_files(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => count_files(&entry.path()),
+            Ok(_) => 1,
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_webroot_sync_swap_into_place() {
+        let base = std::env::temp_dir().join(format!("gruxi-webroot-sync-test-{}", std::process::id()));
+        let local_path = base.join("webroot");
+        let staging_path = get_sibling_path(&local_path, "sync-staging");
+        std::fs::create_dir_all(local_path.join("old")).unwrap();
+        std::fs::write(local_path.join("old/index.html"), "old").unwrap();
+        std::fs::create_dir_all(staging_path.join("new")).unwrap();
+        std::fs::write(staging_path.join("new/index.html"), "new").unwrap();
+        std::fs::write(staging_path.join("style.css"), "new").unwrap();
+        assert_eq!(count_files(&staging_path), 2);
+
+        swap_into_place(&staging_path, &local_path).await.unwrap();
+        assert_eq!(std::fs::read_to_string(local_path.join("new/index.html")).unwrap(), "new");
+        assert!(!local_path.join("old").exists());
+        assert!(!staging_path.exists());
+        assert!(!get_sibling_path(&local_path, "sync-previous").exists());
+
+        // A web root that does not exist yet is created by the first sync
+        let first_local_path = base.join("first");
+        std::fs::create_dir_all(&staging_path).unwrap();
+        swap_into_place(&staging_path, &first_local_path).await.unwrap();
+        assert!(first_local_path.is_dir());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+use hyper::header::HeaderValue;
+
+use crate::http::http_util::empty_response_with_status;
+use crate::http::request_handlers::processors::webdav_processor::WEBDAV_ALLOWED_METHODS;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+
+// Methods a static file can be requested with
+pub const STATIC_ALLOWED_METHODS: &str = "GET, HEAD, OPTIONS";
+// Methods we know of, for sites where a backend decides for itself what it supports
+pub const ALL_ALLOWED_METHODS: &str = "GET, HEAD, POST, PUT, DELETE, OPTIONS, TRACE, CONNECT, PATCH";
+
+// The methods a processor type supports, or None when the request is passed on to a backend (proxy, PHP, CGI etc.)
+// that answers OPTIONS and unsupported methods itself
+pub fn get_allowed_methods_for_processor_type(processor_type: &str) -> Option<&'static str> {
+    match processor_type {
+        "static" => Some(STATIC_ALLOWED_METHODS),
+        "webdav" => Some(WEBDAV_ALLOWED_METHODS),
+        "image" => Some(STATIC_ALLOWED_METHODS),
+        _ => None,
+    }
+}
+
+pub fn is_method_allowed(allowed_methods: &str, method: &str) -> bool {
+    allowed_methods.split(',').any(|allowed_method| allowed_method.trim().eq_ignore_ascii_case(method))
+}
+
+// The Allow header for "OPTIONS *" on a site, as the union of what its request handlers support
+pub fn merge_allowed_methods(allowed_methods_list: &[Option<&str>]) -> String {
+    if allowed_methods_list.is_empty() || allowed_methods_list.iter().any(|allowed_methods| allowed_methods.is_none()) {
+        return ALL_ALLOWED_METHODS.to_string();
+    }
+
+    let mut methods: Vec<&str> = Vec::new();
+    for allowed_methods in allowed_methods_list.iter().flatten() {
+        for method in allowed_methods.split(',').map(|m| m.trim()).filter(|m| !m.is_empty()) {
+            if !methods.contains(&method) {
+                methods.push(method);
+            }
+        }
+    }
+    methods.join(", ")
+}
+
+// Answer to an OPTIONS request for a resource
+pub fn options_response(allowed_methods: &str) -> GruxiResponse {
+    let mut response = empty_response_with_status(hyper::StatusCode::NO_CONTENT);
+    set_allow_header(&mut response, allowed_methods);
+    response
+}
+
+// Answer to a request with a method the resource does not support
+pub fn method_not_allowed_response(allowed_methods: &str) -> GruxiResponse {
+    let mut response = empty_response_with_status(hyper::StatusCode::METHOD_NOT_ALLOWED);
+    set_allow_header(&mut response, allowed_methods);
+    response
+}
+
+fn set_allow_header(response: &mut GruxiResponse, allowed_methods: &str) {
+    if let Ok(value) = HeaderValue::from_str(allowed_methods) {
+        response.headers_mut().insert("Allow", value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowed_methods_for_processor_type() {
+        let static_methods = get_allowed_methods_for_processor_type("static").unwrap();
+        assert!(is_method_allowed(static_methods, "GET"));
+        assert!(is_method_allowed(static_methods, "OPTIONS"));
+        assert!(!is_method_allowed(static_methods, "POST"));
+        assert!(is_method_allowed(get_allowed_methods_for_processor_type("webdav").unwrap(), "PROPFIND"));
+        assert!(get_allowed_methods_for_processor_type("proxy").is_none());
+        assert!(get_allowed_methods_for_processor_type("php").is_none());
+    }
+
+    #[test]
+    fn test_merge_allowed_methods() {
+        assert_eq!(merge_allowed_methods(&[Some(STATIC_ALLOWED_METHODS), Some("OPTIONS, GET, PUT")]), "GET, HEAD, OPTIONS, PUT");
+        assert_eq!(merge_allowed_methods(&[Some(STATIC_ALLOWED_METHODS), None]), ALL_ALLOWED_METHODS);
+        assert_eq!(merge_allowed_methods(&[]), ALL_ALLOWED_METHODS);
+    }
+
+    #[test]
+    fn test_method_not_allowed_response() {
+        let response = method_not_allowed_response(STATIC_ALLOWED_METHODS);
+        assert_eq!(response.get_status(), 405);
+        assert_eq!(response.get_header("Allow").unwrap(), STATIC_ALLOWED_METHODS);
+    }
+}
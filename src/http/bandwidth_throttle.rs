@@ -0,0 +1,255 @@
+// ============================================================================
+// BANDWIDTH THROTTLING
+// ============================================================================
+//
+// Limits the download rate of the response bodies of a site, per connection
+// and for all connections of the site together, as set in its bandwidth
+// settings. Each limit is a token bucket holding up to one second of bytes, so
+// a response can start with a burst before it is paced. Bodies are sent in
+// chunks of at most 16 KB, so connections sharing the site limit take turns.
+// ============================================================================
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use http_body_util::combinators::BoxBody;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use tokio::time::{Instant, Sleep};
+
+use crate::configuration::site::Site;
+use crate::http::request_response::body_error::BodyError;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+
+const MAX_CHUNK_SIZE: u64 = 16 * 1024;
+// Chunks are at least this part of a second of the lowest rate, so slow limits do not send a few bytes at a time
+const MIN_CHUNK_DIVISOR: u64 = 20;
+
+struct TokenBucket {
+    bytes_per_second: u64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(bytes_per_second: u64) -> Self {
+        TokenBucket {
+            bytes_per_second,
+            tokens: bytes_per_second as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn set_rate(&mut self, bytes_per_second: u64) {
+        self.bytes_per_second = bytes_per_second;
+        self.tokens = self.tokens.min(bytes_per_second as f64);
+    }
+
+    fn available(&mut self, now: Instant) -> u64 {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.bytes_per_second as f64).min(self.bytes_per_second as f64);
+        self.last_refill = now;
+        self.tokens as u64
+    }
+
+    fn consume(&mut self, bytes: u64) {
+        self.tokens -= bytes as f64;
+    }
+
+    // How long until the bucket holds the bytes, after available() has refilled it
+    fn time_until(&self, bytes: u64) -> Duration {
+        let missing = bytes as f64 - self.tokens;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing / self.bytes_per_second as f64)
+        }
+    }
+}
+
+static SITE_BUCKETS: LazyLock<DashMap<String, Arc<Mutex<TokenBucket>>>> = LazyLock::new(DashMap::new);
+
+// The shared bucket of a site, following changes of its limit
+fn get_site_bucket(site_id: &str, bytes_per_second: u64) -> Arc<Mutex<TokenBucket>> {
+    let bucket = SITE_BUCKETS
+        .entry(site_id.to_string())
+        .or_insert_with(|| Arc::new(Mutex::new(TokenBucket::new(bytes_per_second))))
+        .clone();
+    if let Ok(mut locked_bucket) = bucket.lock()
+        && locked_bucket.bytes_per_second != bytes_per_second
+    {
+        locked_bucket.set_rate(bytes_per_second);
+    }
+    bucket
+}
+
+// Pace the response body by the bandwidth limits of the site. Empty bodies are left as they are
+pub fn throttle_response(response: &mut GruxiResponse, site: &Site) {
+    if !site.bandwidth.is_limited() || response.get_buffered_body_size() == Some(0) {
+        return;
+    }
+
+    let connection_bucket = (site.bandwidth.connection_bytes_per_second > 0).then(|| TokenBucket::new(site.bandwidth.connection_bytes_per_second));
+    let site_bucket = (site.bandwidth.site_bytes_per_second > 0).then(|| get_site_bucket(&site.id, site.bandwidth.site_bytes_per_second));
+    let lowest_rate = [site.bandwidth.connection_bytes_per_second, site.bandwidth.site_bytes_per_second]
+        .into_iter()
+        .filter(|rate| *rate > 0)
+        .min()
+        .unwrap_or(MAX_CHUNK_SIZE);
+
+    response.map_body(|body| {
+        BoxBody::new(ThrottledBody {
+            inner: body,
+            pending: None,
+            connection_bucket,
+            site_bucket,
+            min_chunk_size: (lowest_rate / MIN_CHUNK_DIVISOR).clamp(1, MAX_CHUNK_SIZE),
+            sleep: None,
+        })
+    });
+}
+
+// Response body that passes on the data of the inner body no faster than its buckets allow
+struct ThrottledBody {
+    inner: BoxBody<Bytes, BodyError>,
+    pending: Option<Bytes>, // Data taken from the inner body that is not sent yet
+    connection_bucket: Option<TokenBucket>,
+    site_bucket: Option<Arc<Mutex<TokenBucket>>>,
+    min_chunk_size: u64,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl ThrottledBody {
+    // Take up to the wanted bytes from the buckets, or the time to wait when they hold less than a chunk
+    fn take_tokens(&mut self, wanted: u64) -> Result<u64, Duration> {
+        let now = Instant::now();
+        let minimum = wanted.min(self.min_chunk_size);
+        let mut allowed = wanted;
+        let mut wait = Duration::ZERO;
+
+        if let Some(bucket) = &mut self.connection_bucket {
+            allowed = allowed.min(bucket.available(now));
+            wait = wait.max(bucket.time_until(minimum));
+        }
+        let mut site_bucket = self.site_bucket.as_ref().and_then(|bucket| bucket.lock().ok());
+        if let Some(bucket) = &mut site_bucket {
+            allowed = allowed.min(bucket.available(now));
+            wait = wait.max(bucket.time_until(minimum));
+        }
+
+        if allowed < minimum {
+            return Err(wait.max(Duration::from_millis(1)));
+        }
+        if let Some(bucket) = &mut site_bucket {
+            bucket.consume(allowed);
+        }
+        drop(site_bucket);
+        if let Some(bucket) = &mut self.connection_bucket {
+            bucket.consume(allowed);
+        }
+        Ok(allowed)
+    }
+}
+
+impl Body for ThrottledBody {
+    type Data = Bytes;
+    type Error = BodyError;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        loop {
+            if let Some(sleep) = &mut self.sleep {
+                ready!(sleep.as_mut().poll(cx));
+                self.sleep = None;
+            }
+
+            let pending = match self.pending.take() {
+                Some(pending) => pending,
+                None => match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+                    Some(Ok(frame)) => match frame.into_data() {
+                        Ok(data) if !data.is_empty() => data,
+                        Ok(data) => return Poll::Ready(Some(Ok(Frame::data(data)))),
+                        // Trailers are passed on as they are
+                        Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                    },
+                    other => return Poll::Ready(other),
+                },
+            };
+
+            let wanted = (pending.len() as u64).min(MAX_CHUNK_SIZE);
+            match self.take_tokens(wanted) {
+                Ok(allowed) => {
+                    let mut rest = pending;
+                    let chunk = rest.split_to(allowed as usize);
+                    if !rest.is_empty() {
+                        self.pending = Some(rest);
+                    }
+                    return Poll::Ready(Some(Ok(Frame::data(chunk))));
+                }
+                Err(wait) => {
+                    self.pending = Some(pending);
+                    self.sleep = Some(Box::pin(tokio::time::sleep(wait)));
+                }
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.pending.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let inner_hint = self.inner.size_hint();
+        let pending_length = self.pending.as_ref().map(|pending| pending.len() as u64).unwrap_or(0);
+        if let Some(exact) = inner_hint.exact() {
+            return SizeHint::with_exact(exact + pending_length);
+        }
+        let mut size_hint = SizeHint::new();
+        size_hint.set_lower(inner_hint.lower() + pending_length);
+        if let Some(upper) = inner_hint.upper() {
+            size_hint.set_upper(upper + pending_length);
+        }
+        size_hint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::request_response::gruxi_body::GruxiBody;
+    use http_body_util::BodyExt;
+
+    #[tokio::test]
+    async fn test_throttled_body_is_paced() {
+        let mut site = Site::new();
+        site.bandwidth.connection_bytes_per_second = 64 * 1024;
+
+        let mut response = GruxiResponse::new_empty_with_status(200);
+        response.set_body(GruxiBody::Buffered(Bytes::from(vec![b'x'; 96 * 1024])));
+        throttle_response(&mut response, &site);
+
+        // The first 64 KB are the burst of the bucket, the remaining 32 KB take half a second
+        let started = Instant::now();
+        let body = response.into_hyper().into_body();
+        assert_eq!(body.size_hint().exact(), Some(96 * 1024));
+        let bytes = body.collect().await.unwrap().to_bytes();
+        assert_eq!(bytes.len(), 96 * 1024);
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(450) && elapsed <= Duration::from_millis(1500), "took {:?}", elapsed);
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let mut bucket = TokenBucket::new(1000);
+        let now = Instant::now();
+        assert_eq!(bucket.available(now), 1000);
+        bucket.consume(1000);
+        assert_eq!(bucket.time_until(500), Duration::from_millis(500));
+        assert_eq!(bucket.available(now + Duration::from_millis(250)), 250);
+
+        // Unused tokens are capped at one second
+        assert_eq!(bucket.available(now + Duration::from_secs(10)), 1000);
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::LazyLock;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct BasicAuthUser {
+    pub username: String,
+    pub password: String, // Stored as bcrypt hash, plain text passwords are hashed when sanitized
+}
+
+// Verified against for unknown usernames, so they take as long to reject as wrong passwords and do not reveal which users exist
+static DUMMY_PASSWORD_HASH: LazyLock<String> = LazyLock::new(|| bcrypt::hash("gruxi-unknown-user", bcrypt::DEFAULT_COST).unwrap_or_default());
+
+// Verify a basic auth "Authorization" header value against a list of users. Bcrypt is slow by design, so it runs on
+// the blocking thread pool instead of holding up the other requests of the worker thread
+pub async fn verify_basic_auth(users: &[BasicAuthUser], authorization_header: &str) -> bool {
+    let (username, password) = match parse_basic_auth_header(authorization_header) {
+        Some(credentials) => credentials,
+        None => return false,
+    };
+
+    let (password_hash, is_known_user) = match users.iter().find(|user| user.username == username) {
+        Some(user) => (user.password.clone(), true),
+        None => (DUMMY_PASSWORD_HASH.clone(), false),
+    };
+
+    let is_verified = tokio::task::spawn_blocking(move || bcrypt::verify(password, &password_hash).unwrap_or(false)).await.unwrap_or(false);
+    is_known_user && is_verified
+}
+
+// Get the username and password from a basic auth "Authorization" header value
+pub fn parse_basic_auth_header(authorization_header: &str) -> Option<(String, String)> {
+    let encoded = authorization_header.strip_prefix("Basic ")?.trim();
+    let decoded = String::from_utf8(STANDARD.decode(encoded).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+// Trim usernames and make sure we never store plain text passwords
+pub fn sanitize_basic_auth_users(users: &mut [BasicAuthUser]) {
+    for user in users.iter_mut() {
+        user.username = user.username.trim().to_string();
+        if !user.password.is_empty()
+            && !user.password.starts_with("$2")
+            && let Ok(hash) = bcrypt::hash(&user.password, bcrypt::DEFAULT_COST)
+        {
+            user.password = hash;
+        }
+    }
+}
+
+pub fn validate_basic_auth_users(users: &[BasicAuthUser]) -> Vec<String> {
+    let mut errors = Vec::new();
+    for (idx, user) in users.iter().enumerate() {
+        if user.username.is_empty() || user.username.contains(':') {
+            errors.push(format!("Auth user {} must have a username without ':'", idx + 1));
+        }
+        if user.password.is_empty() {
+            errors.push(format!("Auth user {} password cannot be empty", idx + 1));
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_basic_auth_verify() {
+        let mut users = vec![BasicAuthUser {
+            username: "alice".to_string(),
+            password: "secret".to_string(),
+        }];
+        sanitize_basic_auth_users(&mut users);
+
+        assert!(users[0].password.starts_with("$2"));
+        // "alice:secret" and "alice:wrong"
+        assert!(verify_basic_auth(&users, "Basic YWxpY2U6c2VjcmV0").await);
+        assert!(!verify_basic_auth(&users, "Basic YWxpY2U6d3Jvbmc=").await);
+        assert!(!verify_basic_auth(&users, "Bearer YWxpY2U6c2VjcmV0").await);
+        // "bob:secret", an unknown user
+        assert!(!verify_basic_auth(&users, "Basic Ym9iOnNlY3JldA==").await);
+        assert!(!verify_basic_auth(&users, "Basic not base64!").await);
+    }
+
+    #[test]
+    fn test_basic_auth_validate_username_with_colon() {
+        let users = vec![BasicAuthUser {
+            username: "al:ice".to_string(),
+            password: "secret".to_string(),
+        }];
+        let errors = validate_basic_auth_users(&users);
+        assert!(errors.iter().any(|e| e.contains("without ':'")));
+    }
+}
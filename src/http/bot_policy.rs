@@ -0,0 +1,213 @@
+// ============================================================================
+// BOT POLICY
+// ============================================================================
+//
+// Applies the bot rules of a site to its requests, before the web application
+// firewall and the request handlers. The first rule matching the User-Agent,
+// and the client address when the rule has IP ranges, decides:
+//
+//   - allow: the request goes on, without checking further rules
+//   - deny: answered with 403
+//   - throttle: answered with 429 when the client address has made more than
+//     the requests per minute of the rule, counted in one minute windows
+//   - robots_only: answered with 403 for all but robots.txt
+//
+// Rules with IP ranges match verified crawlers only, so a crawler spoofing the
+// User-Agent of a search engine falls through to the next rules. Sites can also
+// serve a robots.txt generated from these settings, to all clients.
+// ============================================================================
+
+use std::net::IpAddr;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use hyper::header::HeaderValue;
+
+use crate::configuration::bot_settings::{BotRule, BotSettings};
+use crate::configuration::site::Site;
+use crate::http::http_util::add_standard_headers_to_response;
+use crate::http::request_response::gruxi_request::GruxiRequest;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+use crate::logging::syslog::{debug, trace};
+
+pub const ROBOTS_TXT_PATH: &str = "/robots.txt";
+
+const THROTTLE_WINDOW: Duration = Duration::from_secs(60);
+// Windows of clients are dropped once there are more than this many, keeping those still counting
+const MAX_THROTTLE_WINDOWS: usize = 100_000;
+
+struct ThrottleWindow {
+    started: Instant,
+    requests: u32,
+}
+
+// Request counts of throttled clients, by site, rule and client address
+static THROTTLE_WINDOWS: LazyLock<DashMap<String, ThrottleWindow>> = LazyLock::new(DashMap::new);
+
+// The first rule matching the request, by its User-Agent and client address
+pub fn find_matching_rule<'a>(settings: &'a BotSettings, user_agent: &str, remote_ip: Option<IpAddr>) -> Option<(usize, &'a BotRule)> {
+    settings.rules.iter().enumerate().find(|(_, rule)| {
+        let is_user_agent_match = rule.user_agent_pattern.is_empty() || rule.get_user_agent_pattern().is_some_and(|pattern| pattern.is_match(user_agent));
+        let is_address_match = rule.ip_ranges.is_empty() || remote_ip.is_some_and(|remote_ip| rule.get_ip_ranges().iter().any(|range| range.contains(&remote_ip)));
+        is_user_agent_match && is_address_match
+    })
+}
+
+// Count a request of a throttled client. Returns the seconds until the client can make requests again, when it is over the rate
+fn count_throttled_request(key: String, requests_per_minute: u32, now: Instant) -> Option<u64> {
+    if THROTTLE_WINDOWS.len() > MAX_THROTTLE_WINDOWS {
+        THROTTLE_WINDOWS.retain(|_, window| now.duration_since(window.started) < THROTTLE_WINDOW);
+    }
+
+    let mut window = THROTTLE_WINDOWS.entry(key).or_insert(ThrottleWindow { started: now, requests: 0 });
+    if now.duration_since(window.started) >= THROTTLE_WINDOW {
+        window.started = now;
+        window.requests = 0;
+    }
+    window.requests += 1;
+    if window.requests <= requests_per_minute {
+        return None;
+    }
+    Some(THROTTLE_WINDOW.saturating_sub(now.duration_since(window.started)).as_secs().max(1))
+}
+
+// The robots.txt of the settings. Crawlers of denied and robots-only rules with a plain User-Agent pattern, such as
+// "GPTBot", are disallowed everything by name, unless another rule lets them in, as for verified crawlers with impostors
+// denied. All crawlers are disallowed the paths of the settings
+pub fn generate_robots_txt(settings: &BotSettings) -> String {
+    let mut robots_txt = String::from("# Generated by Gruxi\n");
+
+    for rule in settings.rules.iter().filter(|rule| rule.action == "deny" || rule.action == "robots_only") {
+        let is_plain_name = !rule.user_agent_pattern.is_empty() && rule.user_agent_pattern.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        let is_let_in = settings
+            .rules
+            .iter()
+            .any(|other| (other.action == "allow" || other.action == "throttle") && other.user_agent_pattern.eq_ignore_ascii_case(&rule.user_agent_pattern));
+        if is_plain_name && !is_let_in {
+            robots_txt.push_str(&format!("\nUser-agent: {}\nDisallow: /\n", rule.user_agent_pattern));
+        }
+    }
+
+    robots_txt.push_str("\nUser-agent: *\n");
+    if settings.robots_disallow_paths.is_empty() {
+        robots_txt.push_str("Disallow:\n");
+    }
+    for path in &settings.robots_disallow_paths {
+        robots_txt.push_str(&format!("Disallow: {}\n", path));
+    }
+    if settings.robots_crawl_delay_seconds > 0 {
+        robots_txt.push_str(&format!("Crawl-delay: {}\n", settings.robots_crawl_delay_seconds));
+    }
+    if !settings.robots_sitemap_url.is_empty() {
+        robots_txt.push_str(&format!("\nSitemap: {}\n", settings.robots_sitemap_url));
+    }
+    robots_txt
+}
+
+fn forbidden_response() -> GruxiResponse {
+    let mut response = GruxiResponse::new_empty_with_status(hyper::StatusCode::FORBIDDEN.as_u16());
+    add_standard_headers_to_response(&mut response);
+    response
+}
+
+// Apply the bot rules of the site to a request. Returns the response to answer with, when the request is not let through
+pub async fn check_request(gruxi_request: &mut GruxiRequest, site: &Site) -> Option<GruxiResponse> {
+    let settings = &site.bots;
+    if !settings.is_enabled {
+        return None;
+    }
+
+    let is_robots_txt_request = gruxi_request.get_path_str() == ROBOTS_TXT_PATH;
+    if is_robots_txt_request && settings.robots_txt_enabled && matches!(gruxi_request.get_http_method_str(), "GET" | "HEAD") {
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), generate_robots_txt(settings));
+        response.headers_mut().insert("Content-Type", HeaderValue::from_static("text/plain; charset=utf-8"));
+        add_standard_headers_to_response(&mut response);
+        return Some(response);
+    }
+
+    let user_agent = gruxi_request.get_headers().get("user-agent").and_then(|value| value.to_str().ok()).unwrap_or("").to_string();
+    let remote_ip = gruxi_request.get_remote_ip();
+    let (rule_index, rule) = find_matching_rule(settings, &user_agent, remote_ip.parse().ok())?;
+    trace(format!("Bot rule '{}' matched User-Agent '{}' from {} on site {}", rule.name, user_agent, remote_ip, site.id));
+
+    match rule.action.as_str() {
+        "deny" => {
+            debug(format!("Bot rule '{}' denied request from {} with User-Agent '{}'", rule.name, remote_ip, user_agent));
+            Some(forbidden_response())
+        }
+        "robots_only" if !is_robots_txt_request => Some(forbidden_response()),
+        "throttle" => {
+            let key = format!("{}|{}|{}", site.id, rule_index, remote_ip);
+            let retry_after_seconds = count_throttled_request(key, rule.requests_per_minute, Instant::now())?;
+            debug(format!("Bot rule '{}' throttled request from {} with User-Agent '{}'", rule.name, remote_ip, user_agent));
+            let mut response = GruxiResponse::new_empty_with_status(hyper::StatusCode::TOO_MANY_REQUESTS.as_u16());
+            response.headers_mut().insert(hyper::header::RETRY_AFTER, HeaderValue::from(retry_after_seconds));
+            add_standard_headers_to_response(&mut response);
+            Some(response)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_matching_rule_verifies_crawler_addresses() {
+        let mut settings = BotSettings::new();
+        let mut verified_googlebot = BotRule::new("verified-googlebot", "Googlebot", "allow");
+        verified_googlebot.ip_ranges = vec!["66.249.64.0/19".to_string()];
+        settings.rules = vec![
+            verified_googlebot,
+            BotRule::new("fake-googlebot", "Googlebot", "deny"),
+            BotRule::new("ai-crawlers", "GPTBot|CCBot", "robots_only"),
+        ];
+
+        let googlebot = "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)";
+        assert_eq!(find_matching_rule(&settings, googlebot, "66.249.66.1".parse().ok()).unwrap().1.name, "verified-googlebot");
+        assert_eq!(find_matching_rule(&settings, googlebot, "203.0.113.9".parse().ok()).unwrap().1.name, "fake-googlebot");
+        assert_eq!(
+            find_matching_rule(&settings, "Mozilla/5.0 AppleWebKit/537.36 (KHTML, like Gecko; compatible; GPTBot/1.2)", None)
+                .unwrap()
+                .1
+                .name,
+            "ai-crawlers"
+        );
+        assert!(find_matching_rule(&settings, "Mozilla/5.0 (Windows NT 10.0; Win64; x64) Firefox/128.0", "203.0.113.9".parse().ok()).is_none());
+    }
+
+    #[test]
+    fn test_count_throttled_request() {
+        let now = Instant::now();
+        let key = "test-site|0|192.0.2.1".to_string();
+        assert!(count_throttled_request(key.clone(), 2, now).is_none());
+        assert!(count_throttled_request(key.clone(), 2, now + Duration::from_secs(1)).is_none());
+        assert_eq!(count_throttled_request(key.clone(), 2, now + Duration::from_secs(20)), Some(40));
+        // A new window starts after a minute
+        assert!(count_throttled_request(key, 2, now + Duration::from_secs(61)).is_none());
+    }
+
+    #[test]
+    fn test_generate_robots_txt() {
+        let mut settings = BotSettings::new();
+        let mut verified_googlebot = BotRule::new("verified-googlebot", "Googlebot", "allow");
+        verified_googlebot.ip_ranges = vec!["66.249.64.0/19".to_string()];
+        settings.rules = vec![
+            verified_googlebot,
+            BotRule::new("fake-googlebot", "Googlebot", "deny"),
+            BotRule::new("gptbot", "GPTBot", "deny"),
+            BotRule::new("scrapers", "python-requests|curl", "deny"),
+        ];
+        settings.robots_disallow_paths = vec!["/admin/".to_string(), "/search".to_string()];
+        settings.robots_crawl_delay_seconds = 10;
+        settings.robots_sitemap_url = "https://example.com/sitemap.xml".to_string();
+
+        assert_eq!(
+            generate_robots_txt(&settings),
+            "# Generated by Gruxi\n\nUser-agent: GPTBot\nDisallow: /\n\nUser-agent: *\nDisallow: /admin/\nDisallow: /search\nCrawl-delay: 10\n\nSitemap: https://example.com/sitemap.xml\n"
+        );
+        assert_eq!(generate_robots_txt(&BotSettings::new()), "# Generated by Gruxi\n\nUser-agent: *\nDisallow:\n");
+    }
+}
@@ -0,0 +1,470 @@
+// ============================================================================
+// CACHE WARMING
+// ============================================================================
+//
+// Crawls the pages of a site, starting from its start paths and sitemap and
+// following the links of its HTML pages to a bounded depth, so the file cache,
+// the upstreams and their caches are warm before visitors arrive. Crawls run
+// after starts and configuration reloads, optionally at an interval, after
+// webroot syncs and when asked for through the admin portal.
+//
+// Requests are handled in process, just as the server would handle them,
+// without going through the network. The last crawl of each site is kept as a
+// report of the warmed URLs for the admin portal.
+// ============================================================================
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use http_body_util::BodyExt;
+use hyper::{Request, body::Bytes};
+use serde::Serialize;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+use crate::configuration::binding::Binding;
+use crate::configuration::cached_configuration::get_cached_configuration;
+use crate::configuration::site::Site;
+use crate::core::running_state_manager::get_running_state_manager;
+use crate::core::triggers::get_trigger_handler;
+use crate::http::handle_request::handle_request;
+use crate::http::http_util::strip_port;
+use crate::http::request_response::gruxi_request::GruxiRequest;
+use crate::http::site_match::hostname_pattern::is_regex_hostname;
+use crate::http::site_match::site_matcher::find_best_match_site;
+use crate::logging::syslog::{debug, info, trace, warn};
+
+// Time for external handlers, such as PHP-FPM, to start before the first crawl
+const CACHE_WARM_START_DELAY_SECS: u64 = 10;
+const CACHE_WARM_REQUEST_TIMEOUT_SECS: u64 = 60;
+const CACHE_WARM_USER_AGENT: &str = "Gruxi-Cache-Warmer";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WarmedUrl {
+    pub path: String,
+    pub status: u16, // 0 when the request failed or timed out
+    pub bytes: u64,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheWarmReport {
+    pub site_id: String,
+    pub started_at: String,
+    pub finished_at: String,
+    pub urls: Vec<WarmedUrl>,
+    pub errors: Vec<String>,
+}
+
+// The last crawl per site
+static CACHE_WARM_REPORTS: LazyLock<DashMap<String, CacheWarmReport>> = LazyLock::new(DashMap::new);
+// Sites being crawled, so a crawl is never started twice for a site
+static RUNNING_CRAWLS: LazyLock<DashMap<String, ()>> = LazyLock::new(DashMap::new);
+
+// The reports of the last crawls, sorted by site
+pub fn get_cache_warm_reports() -> Vec<CacheWarmReport> {
+    let mut reports: Vec<CacheWarmReport> = CACHE_WARM_REPORTS.iter().map(|entry| entry.value().clone()).collect();
+    reports.sort_by(|a, b| a.site_id.cmp(&b.site_id));
+    reports
+}
+
+/// Start the cache warming of each enabled site that has it, after a short delay and then at the interval, if any.
+/// The crawls stop on shutdown or stop_services triggers, so they are started again on configuration reload.
+pub async fn start_cache_warming() {
+    let cached_configuration = get_cached_configuration();
+    let config = cached_configuration.get_configuration().await;
+
+    let sites: Vec<(String, u32)> = config
+        .sites
+        .iter()
+        .filter(|site| site.is_enabled && site.cache_warm.is_enabled)
+        .map(|site| (site.id.clone(), site.cache_warm.interval_minutes))
+        .collect();
+    if sites.is_empty() {
+        debug("No sites have cache warming");
+        return;
+    }
+
+    let triggers = get_trigger_handler();
+    let shutdown_token = triggers.get_token("shutdown").await.unwrap_or_else(CancellationToken::new);
+    let stop_services_token = triggers.get_token("stop_services").await.unwrap_or_else(CancellationToken::new);
+
+    for (site_id, interval_minutes) in sites {
+        let shutdown_token = shutdown_token.clone();
+        let stop_services_token = stop_services_token.clone();
+        tokio::spawn(async move {
+            let mut delay = Duration::from_secs(CACHE_WARM_START_DELAY_SECS);
+            loop {
+                tokio::select! {
+                    _ = shutdown_token.cancelled() => break,
+                    _ = stop_services_token.cancelled() => break,
+                    _ = tokio::time::sleep(delay) => {
+                        if let Err(e) = warm_site_cache(&site_id).await {
+                            warn(format!("Cache warming for site '{}' did not run: {}", site_id, e));
+                        }
+                    }
+                }
+                if interval_minutes == 0 {
+                    break;
+                }
+                delay = Duration::from_secs(interval_minutes as u64 * 60);
+            }
+            debug(format!("Cache warming for site '{}' stopped", site_id));
+        });
+    }
+}
+
+/// Start a crawl of the site in the background, such as after a deploy, if it has cache warming enabled
+pub fn spawn_cache_warming(site_id: &str) {
+    let site_id = site_id.to_string();
+    tokio::spawn(async move {
+        if let Err(e) = warm_site_cache(&site_id).await {
+            warn(format!("Cache warming for site '{}' did not run: {}", site_id, e));
+        }
+    });
+}
+
+/// Crawl the site now, and keep the report as its last crawl. Fails if the site has no cache warming, or is already being crawled
+pub async fn warm_site_cache(site_id: &str) -> Result<CacheWarmReport, String> {
+    let site = get_cached_configuration()
+        .get_configuration()
+        .await
+        .sites
+        .iter()
+        .find(|site| site.id == site_id)
+        .cloned()
+        .ok_or_else(|| format!("Site '{}' not found", site_id))?;
+    if !site.is_enabled || !site.cache_warm.is_enabled {
+        return Err("Cache warming is not enabled for the site".to_string());
+    }
+
+    if RUNNING_CRAWLS.insert(site_id.to_string(), ()).is_some() {
+        return Err("A crawl is already running".to_string());
+    }
+    let result = crawl_site(&site).await;
+    RUNNING_CRAWLS.remove(site_id);
+
+    let report = result?;
+    info(format!(
+        "Cache warming for site '{}' done, {} URLs warmed with {} errors",
+        site_id,
+        report.urls.len(),
+        report.errors.len()
+    ));
+    CACHE_WARM_REPORTS.insert(site_id.to_string(), report.clone());
+    Ok(report)
+}
+
+async fn crawl_site(site: &Site) -> Result<CacheWarmReport, String> {
+    let settings = &site.cache_warm;
+    let (binding, hostname) = find_binding_and_hostname(site).await?;
+
+    let triggers = get_trigger_handler();
+    let shutdown_token = triggers.get_token("shutdown").await.unwrap_or_else(CancellationToken::new);
+    let stop_services_token = triggers.get_token("stop_services").await.unwrap_or_else(CancellationToken::new);
+
+    let mut report = CacheWarmReport {
+        site_id: site.id.clone(),
+        started_at: chrono::Utc::now().to_rfc3339(),
+        finished_at: String::new(),
+        urls: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    let mut rate = tokio::time::interval(Duration::from_secs_f64(1.0 / settings.requests_per_second as f64));
+    rate.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut queue: VecDeque<(String, u32)> = VecDeque::new();
+    for path in &settings.start_paths {
+        if seen.insert(path.clone()) {
+            queue.push_back((path.clone(), 0));
+        }
+    }
+
+    // Sitemap URLs are crawled as start pages. A sitemap index lists further sitemaps, which are read as well
+    let mut sitemaps: VecDeque<String> = VecDeque::new();
+    if !settings.sitemap_path.is_empty() {
+        sitemaps.push_back(settings.sitemap_path.clone());
+    }
+    let mut sitemaps_read = 0;
+    while let Some(sitemap_path) = sitemaps.pop_front() {
+        sitemaps_read += 1;
+        if sitemaps_read > 10 {
+            report.errors.push("More than 10 sitemaps, the rest are skipped".to_string());
+            break;
+        }
+        rate.tick().await;
+        let (warmed_url, content) = fetch(&binding, &hostname, &sitemap_path, true).await;
+        report.urls.push(warmed_url);
+        let Some((_, sitemap)) = content else {
+            report.errors.push(format!("Failed to read the sitemap {}", sitemap_path));
+            continue;
+        };
+        for location in parse_sitemap_locations(&sitemap) {
+            let Some(path) = get_site_path(&location, &site.hostnames) else {
+                continue;
+            };
+            if sitemap.contains("<sitemapindex") {
+                sitemaps.push_back(path);
+            } else if seen.insert(path.clone()) {
+                queue.push_back((path, 0));
+            }
+        }
+    }
+
+    while let Some((path, depth)) = queue.pop_front() {
+        if report.urls.len() >= settings.max_urls as usize {
+            report.errors.push(format!("Stopped at the limit of {} URLs", settings.max_urls));
+            break;
+        }
+
+        tokio::select! {
+            _ = shutdown_token.cancelled() => {
+                report.errors.push("Stopped by a shutdown".to_string());
+                break;
+            }
+            _ = stop_services_token.cancelled() => {
+                report.errors.push("Stopped by a configuration reload".to_string());
+                break;
+            }
+            _ = rate.tick() => {}
+        }
+
+        let follow_links = depth < settings.max_depth;
+        let (warmed_url, content) = fetch(&binding, &hostname, &path, follow_links).await;
+        trace(format!("Cache warming for site '{}' requested {} with status {}", site.id, path, warmed_url.status));
+        if warmed_url.status == 0 || warmed_url.status >= 400 {
+            report.errors.push(format!("{} answered with status {}", path, warmed_url.status));
+        }
+        report.urls.push(warmed_url);
+
+        if let Some((content_type, html)) = content
+            && content_type.starts_with("text/html")
+        {
+            for link in extract_links(&html) {
+                if let Some(link_path) = resolve_link(&path, &link, &site.hostnames)
+                    && seen.insert(link_path.clone())
+                {
+                    queue.push_back((link_path, depth + 1));
+                }
+            }
+        }
+    }
+
+    report.finished_at = chrono::Utc::now().to_rfc3339();
+    Ok(report)
+}
+
+// A binding the site is served on, and a hostname that selects the site on it, as visitors would request it
+pub async fn find_binding_and_hostname(site: &Site) -> Result<(Binding, String), String> {
+    let bindings = get_cached_configuration().get_configuration().await.bindings.clone();
+    let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+    let binding_site_cache = running_state.get_binding_site_cache();
+
+    let mut hostnames: Vec<&str> = site
+        .hostnames
+        .iter()
+        .map(|hostname| hostname.as_str())
+        .filter(|hostname| !hostname.contains('*') && !is_regex_hostname(hostname))
+        .collect();
+    hostnames.push("localhost");
+
+    for binding in bindings.into_iter().filter(|binding| !binding.is_admin) {
+        let sites = binding_site_cache.get_sites_for_binding(&binding.id);
+        if !sites.iter().any(|binding_site| binding_site.id == site.id) {
+            continue;
+        }
+        if let Some(hostname) = hostnames
+            .iter()
+            .find(|hostname| find_best_match_site(&sites, hostname, &binding).is_some_and(|matched_site| matched_site.id == site.id))
+        {
+            return Ok((binding, hostname.to_string()));
+        }
+    }
+    Err("The site is not served on any binding with a hostname that can be requested".to_string())
+}
+
+// Request the path and read the whole response. The content type and body are returned for text content when asked for
+async fn fetch(binding: &Binding, hostname: &str, path: &str, keep_content: bool) -> (WarmedUrl, Option<(String, String)>) {
+    let started = Instant::now();
+    let mut warmed_url = WarmedUrl {
+        path: path.to_string(),
+        status: 0,
+        bytes: 0,
+        duration_ms: 0,
+    };
+
+    let request = Request::builder()
+        .method("GET")
+        .uri(path)
+        .header(hyper::header::HOST, hostname)
+        .header(hyper::header::USER_AGENT, CACHE_WARM_USER_AGENT)
+        // Compressed responses are cached separately, and are what browsers ask for
+        .header(hyper::header::ACCEPT_ENCODING, "gzip")
+        .body(Bytes::new());
+    let Ok(request) = request else {
+        return (warmed_url, None);
+    };
+    let mut gruxi_request = GruxiRequest::new(request);
+    gruxi_request.add_calculated_data("remote_ip", "127.0.0.1");
+
+    let result = tokio::time::timeout(Duration::from_secs(CACHE_WARM_REQUEST_TIMEOUT_SECS), async {
+        let response = handle_request(gruxi_request, binding.clone()).await.ok()?.into_hyper();
+        let status = response.status().as_u16();
+        let content_type = response.headers().get(hyper::header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or("").to_lowercase();
+        let is_compressed = response.headers().contains_key(hyper::header::CONTENT_ENCODING);
+        let keep_content = keep_content && !is_compressed && (content_type.starts_with("text/") || content_type.contains("xml"));
+
+        // Read the body frame by frame, so large files are not buffered
+        let mut body = response.into_body();
+        let mut bytes = 0;
+        let mut content = Vec::new();
+        while let Some(frame) = body.frame().await {
+            if let Ok(data) = frame.ok()?.into_data() {
+                bytes += data.len() as u64;
+                if keep_content {
+                    content.extend_from_slice(&data);
+                }
+            }
+        }
+        let content = if keep_content {
+            Some((content_type, String::from_utf8_lossy(&content).to_string()))
+        } else {
+            None
+        };
+        Some((status, bytes, content))
+    })
+    .await;
+
+    warmed_url.duration_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(Some((status, bytes, content))) => {
+            warmed_url.status = status;
+            warmed_url.bytes = bytes;
+            (warmed_url, content)
+        }
+        _ => (warmed_url, None),
+    }
+}
+
+// The <loc> entries of a sitemap or sitemap index
+fn parse_sitemap_locations(sitemap: &str) -> Vec<String> {
+    sitemap
+        .split("<loc>")
+        .skip(1)
+        .filter_map(|part| part.split_once("</loc>").map(|(location, _)| location.trim().replace("&amp;", "&")))
+        .collect()
+}
+
+// The href and src attributes of an HTML page
+fn extract_links(html: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    let lower = html.to_ascii_lowercase();
+    for attribute in ["href=", "src="] {
+        let mut offset = 0;
+        while let Some(found) = lower[offset..].find(attribute) {
+            let value_start = offset + found + attribute.len();
+            offset = value_start;
+            // Only whole attribute names, not such as data-href=
+            if lower[..value_start - attribute.len()].ends_with(|c: char| c.is_ascii_alphanumeric() || c == '-') {
+                continue;
+            }
+            let Some(quote) = html[value_start..].chars().next().filter(|c| *c == '"' || *c == '\'') else {
+                continue;
+            };
+            if let Some(length) = html[value_start + 1..].find(quote) {
+                links.push(html[value_start + 1..value_start + 1 + length].trim().replace("&amp;", "&"));
+            }
+        }
+    }
+    links
+}
+
+// The path of a link on a page of the site, or None for links to other sites and links that are not pages, such as mailto:
+fn resolve_link(page_path: &str, link: &str, hostnames: &[String]) -> Option<String> {
+    let link = link.split('#').next().unwrap_or("");
+    if link.is_empty() || (link.contains(':') && !link.starts_with("http://") && !link.starts_with("https://") && !link.starts_with('/')) {
+        return None;
+    }
+
+    let path = if link.starts_with("http://") || link.starts_with("https://") || link.starts_with("//") {
+        get_site_path(link, hostnames)?
+    } else if link.starts_with('/') {
+        link.to_string()
+    } else {
+        // Relative to the directory of the page
+        let page_path = page_path.split('?').next().unwrap_or("/");
+        let directory = &page_path[..page_path.rfind('/').map(|index| index + 1).unwrap_or(0)];
+        format!("{}{}", directory, link)
+    };
+    Some(normalize_path(&path))
+}
+
+// The path and query of an absolute URL on one of the hostnames of the site
+fn get_site_path(url: &str, hostnames: &[String]) -> Option<String> {
+    let without_scheme = url.trim_start_matches("https:").trim_start_matches("http:").strip_prefix("//")?;
+    let (authority, path) = match without_scheme.find(['/', '?']) {
+        Some(index) => (&without_scheme[..index], &without_scheme[index..]),
+        None => (without_scheme, "/"),
+    };
+    let host = strip_port(authority);
+    if !hostnames.iter().any(|hostname| hostname.eq_ignore_ascii_case(host)) {
+        return None;
+    }
+    if path.starts_with('?') { Some(format!("/{}", path)) } else { Some(path.to_string()) }
+}
+
+// Resolve "." and ".." segments, which never go above the root
+fn normalize_path(path: &str) -> String {
+    let (path, query) = match path.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path, None),
+    };
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/').skip(1) {
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    let trailing_slash = path.ends_with("/.") || path.ends_with("/..");
+    let mut normalized = format!("/{}", segments.join("/"));
+    if trailing_slash && !normalized.ends_with('/') {
+        normalized.push('/');
+    }
+    match query {
+        Some(query) => format!("{}?{}", normalized, query),
+        None => normalized,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_warm_link_parsing() {
+        let html = r##"<a href="/about">About</a> <a data-href="/skip" HREF='contact.html#form'>Contact</a>
+            <img src="../images/logo.png"> <a href="https://www.example.com/blog?page=2&amp;sort=new">Blog</a>
+            <a href="https://other.example.org/">Other</a> <a href="mailto:info@example.com">Mail</a> <a href="#top">Top</a>"##;
+        let hostnames = vec!["www.example.com".to_string()];
+        let paths: Vec<String> = extract_links(html).iter().filter_map(|link| resolve_link("/docs/index.html", link, &hostnames)).collect();
+        assert_eq!(paths, vec!["/about", "/docs/contact.html", "/blog?page=2&sort=new", "/images/logo.png"]);
+
+        assert_eq!(normalize_path("/a/./b/../../../c"), "/c");
+        assert_eq!(normalize_path("/a/b/.."), "/a/");
+
+        let sitemap = "<urlset><url><loc> https://www.example.com/ </loc></url><url><loc>https://www.example.com:443/news?id=1&amp;x=2</loc></url></urlset>";
+        let locations = parse_sitemap_locations(sitemap);
+        assert_eq!(locations.len(), 2);
+        assert_eq!(get_site_path(&locations[0], &hostnames), Some("/".to_string()));
+        assert_eq!(get_site_path(&locations[1], &hostnames), Some("/news?id=1&x=2".to_string()));
+        assert_eq!(get_site_path("https://www.example.com", &hostnames), Some("/".to_string()));
+    }
+}
@@ -1,27 +1,119 @@
+use std::fs::File;
+use std::io::BufReader;
 use std::sync::Arc;
+use std::time::Duration;
 
-use hyper_rustls::HttpsConnector;
+use dashmap::DashMap;
+use hyper_rustls::{FixedServerNameResolver, HttpsConnector};
 use hyper_util::client::legacy::Client;
 use hyper_util::client::legacy::connect::HttpConnector;
 use hyper_util::rt::TokioExecutor;
 
 use http_body_util::combinators::BoxBody;
 use hyper::body::Bytes;
+use rustls::{ClientConfig, RootCertStore};
+use rustls_pki_types::{CertificateDer, ServerName};
 
 use crate::http::request_handlers::processors::proxy_helpers::no_verifier::NoVerifier;
-use crate::tls::tls_config::tls_config;
+use crate::http::request_response::body_error::BodyError;
+use crate::network::dns_resolver::DnsResolver;
+use crate::tls::tls_config::{tls_config, tls_root_store};
 
 pub struct HttpClient {
-    client_with_tls_verify: Client<HttpsConnector<HttpConnector>, GruxiRequestBody>,
-    client_without_tls_verify: Client<HttpsConnector<HttpConnector>, GruxiRequestBody>
+    client_with_tls_verify: UpstreamClient,
+    client_without_tls_verify: UpstreamClient,
+    // Clients for upstreams with their own TLS or connection pool settings, built on first use. Each client keeps its own
+    // pool of keep-alive connections, which is reused by all requests with the same settings
+    upstream_clients: DashMap<UpstreamClientSettings, UpstreamClient>,
+    dns_resolver: DnsResolver,
 }
 
 // Request body type used by Gruxi's outbound HTTP client.
 // Note: responses are still Response<hyper::body::Incoming>.
-type GruxiRequestBody = BoxBody<Bytes, hyper::Error>;
+type GruxiRequestBody = BoxBody<Bytes, BodyError>;
 
-impl HttpClient {
+pub type UpstreamClient = Client<HttpsConnector<HttpConnector<DnsResolver>>, GruxiRequestBody>;
+
+// How to connect to https:// upstreams
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UpstreamTlsSettings {
+    pub verify_certificates: bool, // Set to false to accept any certificate, such as self-signed ones
+    pub ca_bundle_path: String,    // PEM file with the CA certificates to trust, instead of the system roots
+    pub server_name: String,       // Server name for SNI and certificate verification, instead of the host in the URL
+    pub client_cert_path: String,  // PEM client certificate (chain) for mutual TLS
+    pub client_key_path: String,   // PEM private key of the client certificate
+}
+
+impl Default for UpstreamTlsSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UpstreamTlsSettings {
+    pub fn new() -> Self {
+        Self {
+            verify_certificates: true,
+            ca_bundle_path: String::new(),
+            server_name: String::new(),
+            client_cert_path: String::new(),
+            client_key_path: String::new(),
+        }
+    }
+
+    // Whether the shared clients can be used, as nothing but certificate verification is set
+    pub fn is_default(&self) -> bool {
+        self.ca_bundle_path.is_empty() && self.server_name.is_empty() && self.client_cert_path.is_empty() && self.client_key_path.is_empty()
+    }
+}
+
+// How connections to upstreams are made and kept open for reuse
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UpstreamPoolSettings {
+    pub max_idle_per_host: u32,       // Idle keep-alive connections kept per upstream host, 0 for no limit
+    pub idle_timeout_seconds: u32,    // How long an idle connection is kept before it is closed, 0 for the default of 90 seconds
+    pub http2_only: bool,             // Only speak HTTP/2 to the upstream, also over plain http (prior knowledge), so requests share connections
+    pub connect_timeout_seconds: u32, // How long a new connection may take to be established, 0 for no separate limit
+}
+
+impl Default for UpstreamPoolSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UpstreamPoolSettings {
     pub fn new() -> Self {
+        Self {
+            max_idle_per_host: 0,
+            idle_timeout_seconds: 0,
+            http2_only: false,
+            connect_timeout_seconds: 0,
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::new()
+    }
+}
+
+// Everything that decides which client is used for an upstream
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct UpstreamClientSettings {
+    pub tls: UpstreamTlsSettings,
+    pub pool: UpstreamPoolSettings,
+}
+
+impl UpstreamClientSettings {
+    // Whether the shared clients can be used
+    pub fn is_default(&self) -> bool {
+        self.tls.is_default() && self.pool.is_default()
+    }
+}
+
+impl HttpClient {
+    // Host names are looked up with the DNS resolver, which can be the resolver of the OS or a DoH/DoT provider
+    pub fn new(dns_resolver: DnsResolver) -> Self {
         // Client with TLS certificate verification, for streaming bodies
         let tls_config_with_verify = tls_config();
         let https_with_verify = hyper_rustls::HttpsConnectorBuilder::new()
@@ -29,7 +121,7 @@ impl HttpClient {
             .https_or_http()
             .enable_http1()
             .enable_http2()
-            .build();
+            .wrap_connector(get_http_connector(&dns_resolver, 0));
 
         let client_with_tls_verify: Client<_, GruxiRequestBody> = Client::builder(TokioExecutor::new()).build(https_with_verify);
 
@@ -42,21 +134,119 @@ impl HttpClient {
             .https_or_http()
             .enable_http1()
             .enable_http2()
-            .build();
+            .wrap_connector(get_http_connector(&dns_resolver, 0));
 
         let client_without_tls_verify: Client<_, GruxiRequestBody> = Client::builder(TokioExecutor::new()).build(https_without_verify);
 
         Self {
             client_with_tls_verify,
-            client_without_tls_verify
+            client_without_tls_verify,
+            upstream_clients: DashMap::new(),
+            dns_resolver,
         }
     }
 
-    pub fn get_client(&self, verify_tls: bool) -> Client<HttpsConnector<HttpConnector>, GruxiRequestBody> {
+    pub fn get_client(&self, verify_tls: bool) -> UpstreamClient {
         if verify_tls {
             self.client_with_tls_verify.clone()
         } else {
             self.client_without_tls_verify.clone()
         }
     }
+
+    // Get the client for upstreams with the given TLS and pool settings. The certificate files are read when the client is
+    // first needed, so changes to them are picked up on configuration reload
+    pub fn get_upstream_client(&self, settings: &UpstreamClientSettings) -> Result<UpstreamClient, String> {
+        if settings.is_default() {
+            return Ok(self.get_client(settings.tls.verify_certificates));
+        }
+        if let Some(client) = self.upstream_clients.get(settings) {
+            return Ok(client.clone());
+        }
+
+        let client = build_upstream_client(settings, &self.dns_resolver)?;
+        self.upstream_clients.insert(settings.clone(), client.clone());
+        Ok(client)
+    }
+}
+
+// The TCP connector under the TLS connector. It does not enforce http://, as https:// URLs are passed through it as well
+fn get_http_connector(dns_resolver: &DnsResolver, connect_timeout_seconds: u32) -> HttpConnector<DnsResolver> {
+    let mut http = HttpConnector::new_with_resolver(dns_resolver.clone());
+    http.enforce_http(false);
+    http.set_happy_eyeballs_timeout(dns_resolver.get_happy_eyeballs_delay());
+    if connect_timeout_seconds > 0 {
+        http.set_connect_timeout(Some(Duration::from_secs(connect_timeout_seconds as u64)));
+    }
+    http
+}
+
+fn build_upstream_client(client_settings: &UpstreamClientSettings, dns_resolver: &DnsResolver) -> Result<UpstreamClient, String> {
+    let settings = &client_settings.tls;
+    let roots = if settings.ca_bundle_path.is_empty() {
+        tls_root_store()
+    } else {
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+        let mut roots = RootCertStore::empty();
+        for cert in load_certificates(&settings.ca_bundle_path)? {
+            roots.add(cert).map_err(|e| format!("Invalid CA certificate in {}: {}", settings.ca_bundle_path, e))?;
+        }
+        roots
+    };
+
+    let builder = ClientConfig::builder().with_root_certificates(roots);
+    let mut tls_config = if settings.client_cert_path.is_empty() {
+        builder.with_no_client_auth()
+    } else {
+        let certs = load_certificates(&settings.client_cert_path)?;
+        let key_file = File::open(&settings.client_key_path).map_err(|e| format!("Failed to open client key {}: {}", settings.client_key_path, e))?;
+        let key = rustls_pemfile::private_key(&mut BufReader::new(key_file))
+            .map_err(|e| format!("Failed to read client key {}: {}", settings.client_key_path, e))?
+            .ok_or_else(|| format!("No private key found in {}", settings.client_key_path))?;
+        builder
+            .with_client_auth_cert(certs, key)
+            .map_err(|e| format!("Invalid client certificate or key ({}, {}): {}", settings.client_cert_path, settings.client_key_path, e))?
+    };
+
+    if !settings.verify_certificates {
+        tls_config.dangerous().set_certificate_verifier(Arc::new(NoVerifier));
+    }
+
+    let builder = hyper_rustls::HttpsConnectorBuilder::new().with_tls_config(tls_config).https_or_http();
+    let https = if settings.server_name.is_empty() {
+        builder
+            .enable_http1()
+            .enable_http2()
+            .wrap_connector(get_http_connector(dns_resolver, client_settings.pool.connect_timeout_seconds))
+    } else {
+        let server_name = ServerName::try_from(settings.server_name.clone()).map_err(|e| format!("Invalid TLS server name '{}': {}", settings.server_name, e))?;
+        builder
+            .with_server_name_resolver(FixedServerNameResolver::new(server_name))
+            .enable_http1()
+            .enable_http2()
+            .wrap_connector(get_http_connector(dns_resolver, client_settings.pool.connect_timeout_seconds))
+    };
+
+    let pool = &client_settings.pool;
+    let mut builder = Client::builder(TokioExecutor::new());
+    if pool.max_idle_per_host > 0 {
+        builder.pool_max_idle_per_host(pool.max_idle_per_host as usize);
+    }
+    if pool.idle_timeout_seconds > 0 {
+        builder.pool_idle_timeout(Duration::from_secs(pool.idle_timeout_seconds as u64));
+    }
+    builder.http2_only(pool.http2_only);
+
+    Ok(builder.build(https))
+}
+
+fn load_certificates(path: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open certificate file {}: {}", path, e))?;
+    let certs: Vec<CertificateDer<'static>> = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Failed to read certificates from {}: {}", path, e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates found in {}", path));
+    }
+    Ok(certs)
 }
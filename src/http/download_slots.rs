@@ -0,0 +1,163 @@
+// ============================================================================
+// DOWNLOAD SLOTS
+// ============================================================================
+//
+// Limits how many large responses a site sends at the same time, so a handful
+// of clients downloading big files cannot saturate the disk and the uplink.
+// Responses of at least the large file size threshold of the site take one of
+// its download slots until their body is sent, or the client goes away. When
+// all slots are taken, the response waits for one up to the queue timeout of
+// the site, and is answered with 503 Service Unavailable if none frees up.
+// ============================================================================
+
+use std::pin::Pin;
+use std::sync::{Arc, LazyLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use http_body_util::combinators::BoxBody;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use hyper::header::HeaderValue;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::configuration::site::Site;
+use crate::http::http_util::empty_response_with_status;
+use crate::http::request_response::body_error::BodyError;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+use crate::logging::syslog::debug;
+
+// Sent in the "Retry-After" header, when a large download is rejected because all slots are taken
+pub const DOWNLOAD_SLOT_RETRY_AFTER_SECONDS: u32 = 10;
+
+// The slots of each site, with the slot count they were made for, so changed settings get new slots
+static SITE_DOWNLOAD_SLOTS: LazyLock<DashMap<String, (u32, Arc<Semaphore>)>> = LazyLock::new(DashMap::new);
+
+fn get_site_download_slots(site_id: &str, slot_count: u32) -> Arc<Semaphore> {
+    let mut entry = SITE_DOWNLOAD_SLOTS
+        .entry(site_id.to_string())
+        .or_insert_with(|| (slot_count, Arc::new(Semaphore::new(slot_count as usize))));
+    if entry.0 != slot_count {
+        // Downloads holding a slot of the old semaphore finish as they are
+        *entry = (slot_count, Arc::new(Semaphore::new(slot_count as usize)));
+    }
+    entry.1.clone()
+}
+
+// Size of the body a response sends, as far as we know it before sending it
+fn get_transfer_size(response: &mut GruxiResponse) -> u64 {
+    if let Some(file_length) = response.get_file_length() {
+        return file_length;
+    }
+    let content_length = response.get_header("Content-Length").and_then(|value| value.to_str().ok()).and_then(|value| value.parse::<u64>().ok());
+    content_length.unwrap_or_else(|| response.get_body_size())
+}
+
+// Take a download slot of the site for a large response, held until its body is sent. Returns the 503 response to send
+// instead, when no slot frees up within the queue timeout
+pub async fn acquire_download_slot(response: &mut GruxiResponse, site: &Site) -> Result<(), GruxiResponse> {
+    let bandwidth = &site.bandwidth;
+    if bandwidth.large_file_size_threshold == 0 || bandwidth.large_file_slots == 0 || get_transfer_size(response) < bandwidth.large_file_size_threshold {
+        return Ok(());
+    }
+
+    let slots = get_site_download_slots(&site.id, bandwidth.large_file_slots);
+    let permit = match slots.clone().try_acquire_owned() {
+        Ok(permit) => Some(permit),
+        Err(_) if bandwidth.large_file_queue_timeout_seconds > 0 => {
+            let queue_timeout = Duration::from_secs(bandwidth.large_file_queue_timeout_seconds as u64);
+            tokio::time::timeout(queue_timeout, slots.acquire_owned()).await.ok().and_then(|result| result.ok())
+        }
+        Err(_) => None,
+    };
+
+    let Some(permit) = permit else {
+        debug(format!(
+            "All {} download slots of site '{}' are taken, answering large download with 503",
+            bandwidth.large_file_slots, site.id
+        ));
+        let mut busy_response = empty_response_with_status(hyper::StatusCode::SERVICE_UNAVAILABLE);
+        busy_response.headers_mut().insert("Retry-After", HeaderValue::from(DOWNLOAD_SLOT_RETRY_AFTER_SECONDS));
+        return Err(busy_response);
+    };
+
+    response.map_body(|body| BoxBody::new(SlotBody { inner: body, _permit: permit }));
+    Ok(())
+}
+
+// Response body that holds a download slot until it is sent or dropped
+struct SlotBody {
+    inner: BoxBody<Bytes, BodyError>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Body for SlotBody {
+    type Data = Bytes;
+    type Error = BodyError;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::request_response::gruxi_body::GruxiBody;
+
+    fn large_response() -> GruxiResponse {
+        let mut response = GruxiResponse::new_empty_with_status(200);
+        response.set_body(GruxiBody::Buffered(Bytes::from(vec![b'x'; 2048])));
+        response
+    }
+
+    #[tokio::test]
+    async fn test_download_slots_are_limited() {
+        let mut site = Site::new();
+        site.bandwidth.large_file_size_threshold = 1024;
+        site.bandwidth.large_file_slots = 1;
+
+        let mut first = large_response();
+        assert!(acquire_download_slot(&mut first, &site).await.is_ok());
+
+        // The only slot is taken, so the next large download is rejected, while small responses pass
+        let mut second = large_response();
+        let busy_response = acquire_download_slot(&mut second, &site).await.unwrap_err();
+        assert_eq!(busy_response.get_status(), 503);
+        assert_eq!(busy_response.get_header("Retry-After").unwrap(), "10");
+        let mut small = GruxiResponse::new_with_bytes(200, "small");
+        assert!(acquire_download_slot(&mut small, &site).await.is_ok());
+
+        // Sending the body frees the slot
+        drop(first);
+        assert!(acquire_download_slot(&mut second, &site).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_download_slots_queue() {
+        let mut site = Site::new();
+        site.bandwidth.large_file_size_threshold = 1024;
+        site.bandwidth.large_file_slots = 1;
+        site.bandwidth.large_file_queue_timeout_seconds = 5;
+
+        let mut first = large_response();
+        assert!(acquire_download_slot(&mut first, &site).await.is_ok());
+
+        let queued_site = site.clone();
+        let queued = tokio::spawn(async move {
+            let mut second = large_response();
+            acquire_download_slot(&mut second, &queued_site).await.is_ok()
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        drop(first);
+        assert!(queued.await.unwrap());
+    }
+}
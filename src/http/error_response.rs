@@ -0,0 +1,112 @@
+use hyper::header::HeaderValue;
+
+use crate::http::request_response::gruxi_body::GruxiBody;
+use crate::http::request_response::gruxi_request::GruxiRequest;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+
+// How the error responses of a site are answered, see Site::error_response_format
+pub const ERROR_RESPONSE_FORMATS: [&str; 3] = ["html", "json", "negotiate"];
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-ID";
+// Longer request IDs from the client are replaced, as they end up in the response and the logs
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+// Whether an error response should be JSON, for the format of the site and the Accept header of the request
+pub fn wants_json_error(error_response_format: &str, accept: &str) -> bool {
+    match error_response_format {
+        "json" => true,
+        "negotiate" => prefers_json(accept),
+        _ => false,
+    }
+}
+
+// JSON is preferred when the client accepts it with a higher quality than HTML. "*/*" alone keeps HTML, as browsers send it
+fn prefers_json(accept: &str) -> bool {
+    let mut json_quality = 0.0;
+    let mut html_quality = 0.0;
+    for media_range in accept.split(',') {
+        let mut parameters = media_range.split(';');
+        let media_type = parameters.next().unwrap_or("").trim().to_ascii_lowercase();
+        let quality = parameters
+            .filter_map(|parameter| parameter.trim().strip_prefix("q="))
+            .next()
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if media_type == "application/json" || (media_type.starts_with("application/") && media_type.ends_with("+json")) {
+            json_quality = f32::max(json_quality, quality);
+        } else if media_type == "text/html" {
+            html_quality = f32::max(html_quality, quality);
+        }
+    }
+    json_quality > 0.0 && json_quality >= html_quality
+}
+
+// The request ID the client or a proxy in front of us sent, when it is usable, or a new one
+pub fn get_request_id(gruxi_request: &GruxiRequest) -> String {
+    let client_request_id = gruxi_request.get_headers().get(REQUEST_ID_HEADER).and_then(|value| value.to_str().ok()).unwrap_or("");
+    let is_usable =
+        !client_request_id.is_empty() && client_request_id.len() <= MAX_REQUEST_ID_LENGTH && client_request_id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'));
+    if is_usable { client_request_id.to_string() } else { uuid::Uuid::new_v4().to_string() }
+}
+
+// Give an error response without a body a JSON body with the status, its message and the request ID. Error responses with a
+// body of their own, such as from a proxied API, are left as they are
+pub fn set_json_error_body(response: &mut GruxiResponse, request_id: &str) {
+    let status = response.get_status();
+    if status < 400 || response.get_buffered_body_size() != Some(0) {
+        return;
+    }
+
+    let message = hyper::StatusCode::from_u16(status).ok().and_then(|status_code| status_code.canonical_reason()).unwrap_or("Error");
+    let body = serde_json::json!({
+        "status": status,
+        "message": message,
+        "request_id": request_id,
+    });
+    response.set_body(GruxiBody::Buffered(body.to_string().into()));
+    response.headers_mut().insert("Content-Type", HeaderValue::from_static("application/json"));
+    if let Ok(value) = HeaderValue::from_str(request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wants_json_error() {
+        assert!(!wants_json_error("html", "application/json"));
+        assert!(wants_json_error("json", "text/html"));
+
+        assert!(wants_json_error("negotiate", "application/json"));
+        assert!(wants_json_error("negotiate", "application/problem+json, */*;q=0.1"));
+        assert!(wants_json_error("negotiate", "text/html;q=0.5, application/json"));
+        assert!(!wants_json_error("negotiate", "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"));
+        assert!(!wants_json_error("negotiate", "text/html, application/json;q=0.9"));
+        assert!(!wants_json_error("negotiate", "*/*"));
+        assert!(!wants_json_error("negotiate", ""));
+    }
+
+    #[test]
+    fn test_set_json_error_body() {
+        let mut response = GruxiResponse::new_empty_with_status(404);
+        set_json_error_body(&mut response, "abc-123");
+        let body: serde_json::Value = serde_json::from_slice(response.get_buffered_body().unwrap()).unwrap();
+        assert_eq!(body, serde_json::json!({ "status": 404, "message": "Not Found", "request_id": "abc-123" }));
+        assert_eq!(response.get_header("Content-Type").unwrap(), "application/json");
+        assert_eq!(response.get_header(REQUEST_ID_HEADER).unwrap(), "abc-123");
+        assert_eq!(response.get_body_size(), body.to_string().len() as u64);
+
+        // Bodies of the handler and successful responses are kept
+        let mut response = GruxiResponse::new_empty_with_status(500);
+        response.set_body(GruxiBody::Buffered("upstream error".into()));
+        set_json_error_body(&mut response, "abc-123");
+        assert_eq!(response.get_buffered_body().unwrap().as_ref(), b"upstream error");
+
+        let mut response = GruxiResponse::new_empty_with_status(204);
+        set_json_error_body(&mut response, "abc-123");
+        assert_eq!(response.get_buffered_body_size(), Some(0));
+    }
+}
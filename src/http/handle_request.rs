@@ -1,29 +1,99 @@
 use crate::admin_portal::http_admin_api::*;
-use crate::compression::compression::Compression;
 use crate::configuration::binding::Binding;
+use crate::configuration::site::Site;
+use crate::core::running_state::RunningState;
 use crate::core::running_state_manager::get_running_state_manager;
 use crate::error::gruxi_error::GruxiError;
 use crate::error::gruxi_error_enums::{AdminApiError, GruxiErrorKind};
+use crate::http::error_response::{get_request_id, set_json_error_body, wants_json_error};
+use crate::http::health_probes::handle_health_probe;
 use crate::http::http_util::*;
+use crate::http::landing_page::get_landing_page_response;
+use crate::http::middleware::middleware_chain::{MiddlewareContext, get_middleware_chain};
+use crate::http::request_priority::{REQUEST_PRIORITY_RETRY_AFTER_SECONDS, get_request_admission, get_request_priority};
 use crate::http::request_response::gruxi_request::GruxiRequest;
 use crate::http::request_response::gruxi_response::GruxiResponse;
+use crate::http::sendfile::{SENDFILE_HEADERS, handle_sendfile_response};
 use crate::http::site_match::site_matcher::find_best_match_site;
-use crate::logging::syslog::{debug, trace};
-use chrono::Local;
+use crate::http::uri_normalization::{is_canonical_path, normalize_request_path};
+use crate::logging::debug_dump::{capture_debug_dump_request, write_debug_dump};
+use crate::logging::syslog::{debug, info, is_debug_enabled, is_trace_enabled, trace};
+use crate::tls::shared_acme_manager::{get_acme_http01_key_authorization, get_acme_http01_token};
 use hyper::header::HeaderValue;
+use std::sync::Arc;
 
 // Entry point to handle request, as we need to do post-processing, like access logging etc
 pub async fn handle_request(mut gruxi_request: GruxiRequest, binding: Binding) -> Result<GruxiResponse, GruxiError> {
-    // Log the request details
-    debug(format!(
-        "Received request: hostname={}, method={}, path={}, query={}, body_size={}, headers={:?}",
-        gruxi_request.get_hostname(),
-        gruxi_request.get_http_method(),
-        gruxi_request.get_path(),
-        gruxi_request.get_query(),
-        gruxi_request.get_body_size(),
-        gruxi_request.get_headers()
-    ));
+    let hostname = gruxi_request.get_hostname();
+
+    // Make the path canonical before anything routes on it or checks it, so encoded and dotted variants of a path are
+    // handled as the path itself
+    if !is_canonical_path(gruxi_request.get_path_str()) {
+        let encoded_slash_policy = crate::configuration::cached_configuration::get_cached_configuration()
+            .get_configuration()
+            .await
+            .core
+            .server_settings
+            .encoded_slash_policy
+            .clone();
+        if let Err(e) = normalize_request_path(gruxi_request.get_path_str(), &encoded_slash_policy).and_then(|path| gruxi_request.set_path(&path)) {
+            debug(format!("Rejected request path '{}': {}", gruxi_request.get_path_str(), e));
+            let mut response = GruxiResponse::new_empty_with_status(hyper::StatusCode::BAD_REQUEST.as_u16());
+            response.headers_mut().insert("Connection", HeaderValue::from_static("close"));
+            return Ok(response);
+        }
+    }
+
+    // Log the request details, only formatted when debug logs are written, as the headers make it costly
+    if is_debug_enabled() {
+        let query = gruxi_request.get_query();
+        let body_size = gruxi_request.get_body_size();
+        debug(format!(
+            "Received request: hostname={}, method={}, path={}, query={}, body_size={}, headers={:?}",
+            hostname,
+            gruxi_request.get_http_method_str(),
+            gruxi_request.get_path_str(),
+            query,
+            body_size,
+            gruxi_request.get_headers()
+        ));
+    }
+
+    // Answer ACME HTTP-01 challenges of our automatic TLS domains, before any site gets to handle the request
+    if let Some(token) = get_acme_http01_token(gruxi_request.get_path_str())
+        && let Some(key_authorization) = get_acme_http01_key_authorization(&hostname, token).await
+    {
+        trace(format!("Answering ACME HTTP-01 challenge for hostname: {}", hostname));
+        let mut resp = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), key_authorization);
+        resp.headers_mut().insert("Content-Type", HeaderValue::from_static("application/octet-stream"));
+        return Ok(resp);
+    }
+
+    // Requests for another hostname than the TLS connection was made for are served, logged or misdirected, as the binding says
+    if binding.sni_mismatch_policy != "allow"
+        && let Some(tls_server_name) = gruxi_request.get_calculated_data("tls_server_name")
+        && is_sni_mismatch(&tls_server_name, &hostname)
+    {
+        if binding.sni_mismatch_policy == "reject" {
+            debug(format!("Request for hostname '{}' on TLS connection for '{}' is misdirected, answering 421", hostname, tls_server_name));
+            return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::MISDIRECTED_REQUEST.as_u16()));
+        }
+        info(format!(
+            "Request for hostname '{}' on TLS connection for '{}' from {} on binding ID: '{}'",
+            hostname,
+            tls_server_name,
+            gruxi_request.get_calculated_data("remote_ip").unwrap_or_default(),
+            &binding.id
+        ));
+    }
+
+    // Answer the liveness and readiness probes of Gruxi itself, before the running state, which is locked during reloads
+    {
+        let configuration = crate::configuration::cached_configuration::get_cached_configuration().get_configuration().await;
+        if let Some(response) = handle_health_probe(&mut gruxi_request, &configuration).await {
+            return Ok(response);
+        }
+    }
 
     // Get the running state
     let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
@@ -33,59 +103,133 @@ pub async fn handle_request(mut gruxi_request: GruxiRequest, binding: Binding) -
     let sites = binding_site_cache.get_sites_for_binding(&binding.id);
     if sites.is_empty() {
         trace(format!("No sites configured for binding ID: '{}'", &binding.id));
-        return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::NOT_FOUND.as_u16()));
+        return Ok(get_not_found_response(&gruxi_request, &sites, &binding).await);
     }
 
-    // Get the hostname and figure out which site matches
-    let hostname = gruxi_request.get_hostname();
-    let site = match find_best_match_site(&sites, &hostname) {
-        Some(site) => site,
+    // Figure out which site matches the hostname
+    let site = match find_best_match_site(&sites, &hostname, &binding) {
+        Some(site) => Arc::clone(site),
         None => {
             if hostname.is_empty() {
                 trace(format!("No hostname provided in request on binding ID: '{}'", &binding.id));
                 return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::BAD_REQUEST.as_u16()));
+            } else if binding.unknown_host_policy == "reject" {
+                trace(format!("Unknown hostname: '{}' on binding ID: '{}' is misdirected, answering 421", &hostname, &binding.id));
+                return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::MISDIRECTED_REQUEST.as_u16()));
             } else {
                 trace(format!("No matching site found for hostname: '{}' on binding ID: '{}'", &hostname, &binding.id));
-                return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::NOT_FOUND.as_u16()));
+                return Ok(get_not_found_response(&gruxi_request, &sites, &binding).await);
             }
         }
     };
-    trace(format!("Matched site with request: {:?}", &site));
+    if is_trace_enabled() {
+        trace(format!("Matched site with request: {:?}", &site));
+    }
 
-    // Validate the request
-    if let Err(gruxi_error) = validate_request(&mut gruxi_request).await {
-        debug(format!("Request validation failed: {:?}", gruxi_error));
-        let status_code = match &gruxi_error.kind {
-            GruxiErrorKind::HttpRequestValidation(code) => *code,
-            _ => 500, // Default for other errors
-        };
-        let response = GruxiResponse::new_empty_with_status(status_code);
-        return Ok(response);
+    // Under overload, requests wait for a slot by priority, and low priority requests are turned away first
+    let request_priority_settings = crate::configuration::cached_configuration::get_cached_configuration()
+        .get_configuration()
+        .await
+        .core
+        .request_priority
+        .clone();
+    let _admission_permit = if request_priority_settings.is_enabled {
+        let priority = get_request_priority(&request_priority_settings, binding.is_admin, gruxi_request.get_path_str());
+        match get_request_admission().acquire(priority, &request_priority_settings).await {
+            Ok(permit) => Some(permit),
+            Err(e) => {
+                debug(format!("Request with {:?} priority for path '{}' not admitted: {:?}", priority, gruxi_request.get_path_str(), e));
+                let mut resp = GruxiResponse::new_empty_with_status(hyper::StatusCode::SERVICE_UNAVAILABLE.as_u16());
+                resp.headers_mut().insert(hyper::header::RETRY_AFTER, HeaderValue::from(REQUEST_PRIORITY_RETRY_AFTER_SECONDS));
+                return Ok(resp);
+            }
+        }
+    } else {
+        None
+    };
+
+    // Dump the request and its response when a debug dump of the site is running, however the request is answered
+    let debug_dump_request = capture_debug_dump_request(&site.id, &mut gruxi_request);
+    let mut response = handle_site_request(&mut gruxi_request, &binding, &site, &running_state).await?;
+
+    // API sites answer errors with JSON, also the errors answered before a handler got the request
+    let accept = gruxi_request.get_headers().get("Accept").and_then(|value| value.to_str().ok()).unwrap_or("");
+    if response.get_status() >= 400 && gruxi_request.get_http_method_str() != "HEAD" && wants_json_error(&site.error_response_format, accept) {
+        set_json_error_body(&mut response, &get_request_id(&gruxi_request));
     }
 
-    // Handle special case for OPTIONS * request, which is stupid but valid
-    if gruxi_request.get_http_method() == "OPTIONS" && gruxi_request.get_path() == "*" {
-        // Special case for OPTIONS * request
-        let mut resp = GruxiResponse::new_empty_with_status(hyper::StatusCode::OK.as_u16());
-        resp.headers_mut()
-            .insert("Allow", HeaderValue::from_static("GET, HEAD, POST, PUT, DELETE, OPTIONS, TRACE, CONNECT, PATCH"));
-        add_standard_headers_to_response(&mut resp);
-        return Ok(resp);
+    if let Some(debug_dump_request) = debug_dump_request {
+        write_debug_dump(debug_dump_request, &response);
+    }
+    Ok(response)
+}
+
+// The response for a request no site of the binding serves, which is the landing page when it is enabled
+async fn get_not_found_response(gruxi_request: &GruxiRequest, sites: &[Arc<Site>], binding: &Binding) -> GruxiResponse {
+    let configuration = crate::configuration::cached_configuration::get_cached_configuration().get_configuration().await;
+    let landing_page = &configuration.core.landing_page;
+    if !landing_page.is_enabled {
+        return GruxiResponse::new_empty_with_status(hyper::StatusCode::NOT_FOUND.as_u16());
     }
+    let accept = gruxi_request.get_headers().get("Accept").and_then(|value| value.to_str().ok()).unwrap_or("");
+    get_landing_page_response(landing_page, sites, binding, accept)
+}
 
-    // Handle EXPECT: 100-continue header
-    if let Some(expect_header) = gruxi_request.get_headers().get("expect") {
-        if expect_header.to_str().unwrap_or("").eq_ignore_ascii_case("100-continue") {
-            // Send 100 Continue response
-            let mut resp = empty_response_with_status(hyper::StatusCode::CONTINUE);
-            add_standard_headers_to_response(&mut resp);
-            return Ok(resp);
+// Handle a request for the matched site through its middleware chain, with its request handlers at the end of the chain
+async fn handle_site_request(gruxi_request: &mut GruxiRequest, binding: &Binding, site: &Site, running_state: &RunningState) -> Result<GruxiResponse, GruxiError> {
+    let middleware_chain = get_middleware_chain(site);
+    let mut context = MiddlewareContext::new(gruxi_request, binding, site, running_state);
+
+    // The request goes through the middleware in order, until one of them answers it
+    let mut middleware_seen = middleware_chain.len();
+    let mut middleware_response = None;
+    for (index, middleware) in middleware_chain.iter().enumerate() {
+        let path = middleware.can_rewrite_path().then(|| gruxi_request.get_path_str().to_string());
+        if let Some(response) = middleware.handle_request(&context, gruxi_request).await {
+            trace(format!("Middleware {:?} answered request for path: {}", middleware, gruxi_request.get_path_str()));
+            middleware_seen = index;
+            middleware_response = Some(response);
+            break;
+        }
+
+        // A rewritten path can be in another location, so it is matched again, and validated and authenticated for that location
+        if path.is_some_and(|path| path != gruxi_request.get_path_str()) {
+            trace(format!("Middleware {:?} rewrote the path to: {}", middleware, gruxi_request.get_path_str()));
+            context = MiddlewareContext::new(gruxi_request, binding, site, running_state);
+            for required_middleware in middleware_chain.iter().filter(|required_middleware| required_middleware.is_required()) {
+                if let Some(response) = required_middleware.handle_request(&context, gruxi_request).await {
+                    trace(format!("Middleware {:?} answered request for rewritten path: {}", required_middleware, gruxi_request.get_path_str()));
+                    middleware_response = Some(response);
+                    break;
+                }
+            }
+            if middleware_response.is_some() {
+                middleware_seen = index + 1;
+                break;
+            }
         }
     }
 
+    let mut response = match middleware_response {
+        Some(response) => response,
+        None => dispatch_request(&mut context, gruxi_request).await,
+    };
+
+    // The response goes back through the middleware that saw the request, in reverse order
+    for middleware in middleware_chain[..middleware_seen].iter().rev() {
+        middleware.handle_response(&context, gruxi_request, &mut response).await;
+    }
+
+    Ok(response)
+}
+
+// Let the admin portal or the request handlers of the site answer the request, at the end of the middleware chain
+async fn dispatch_request(context: &mut MiddlewareContext<'_>, gruxi_request: &mut GruxiRequest) -> GruxiResponse {
+    let site = context.get_site();
+
     // Check if the request is for the admin portal - handle these first
-    let admin_response = if binding.is_admin {
-        match handle_api_routes(&mut gruxi_request, site).await {
+    let admin_response = if context.binding.is_admin {
+        match handle_api_routes(gruxi_request, site).await {
             Ok(response) => Some(response),
             Err(e) => {
                 // If the error is NoRouteMatched, we continue to normal processing
@@ -109,173 +253,31 @@ pub async fn handle_request(mut gruxi_request: GruxiRequest, binding: Binding) -
     } else {
         // If no handler wants it, we return 404
         if site.request_handlers.is_empty() {
-            return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::NOT_FOUND.as_u16()));
+            return GruxiResponse::new_empty_with_status(hyper::StatusCode::NOT_FOUND.as_u16());
         }
 
         // Now we let the request handler manager process the request in the order defined by the site's request_handlers list.
-        let request_handler_manager = running_state.get_request_handler_manager();
-        let response_result = request_handler_manager.handle_request(&mut gruxi_request, &site).await;
-        if response_result.is_err() {
-            trace(format!("No request handler matched for URL path: {}", &gruxi_request.get_path_and_query()));
-            return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::NOT_FOUND.as_u16()));
-        }
-
-        match response_result {
+        let request_handler_manager = context.running_state.get_request_handler_manager();
+        match request_handler_manager.handle_request(gruxi_request, site).await {
             Ok(response) => response,
             Err(_) => {
                 trace(format!("No request handler matched for URL path: {}", &gruxi_request.get_path_and_query()));
-                return Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::NOT_FOUND.as_u16()));
+                return GruxiResponse::new_empty_with_status(hyper::StatusCode::NOT_FOUND.as_u16());
             }
         }
     };
 
-    // Consider gzipping content if not already gzipped
-    let content_length = response.get_body_size();
-    let content_type_header_option = response.get_header("Content-Type");
-    let content_type_header = if let Some(cth) = content_type_header_option {
-        cth.to_str().unwrap_or("").to_string()
-    } else {
-        "".to_string()
-    };
-
-    let content_encoding_header_option = response.get_header("Content-Encoding");
-    let content_encoding_header = if let Some(ceh) = content_encoding_header_option {
-        ceh.to_str().unwrap_or("").to_string()
-    } else {
-        "".to_string()
-    };
-
-    let file_reader_cache = running_state.get_file_reader_cache();
-
-    // Only gzip if not already gzipped and if we should compress based on config and sizes
-    if content_encoding_header.to_lowercase() != "gzip" && file_reader_cache.should_compress(&content_type_header, content_length) {
-        let accepted_encodings = gruxi_request.get_accepted_encodings();
-        let compression = Compression::new();
-        compression.compress_response(&mut response, accepted_encodings, content_encoding_header).await;
-    }
-
-    // Vector for additional headers to set
-    let mut additional_headers: Vec<(&str, &str)> = vec![];
-
-    // If method is OPTIONS, we add the Allow header if not already present
-    if gruxi_request.get_http_method() == "OPTIONS" {
-        if !response.headers().iter().any(|(k, _)| k.as_str().to_lowercase() == "allow") {
-            additional_headers.push(("Allow", "GET, HEAD, POST, PUT, DELETE, OPTIONS, TRACE, CONNECT, PATCH"));
-        }
-    }
-
-    // Set any additional headers
-    for (key, value) in additional_headers {
-        let header_value_result = HeaderValue::from_str(value);
-        match header_value_result {
-            Ok(header_value) => {
-                response.headers_mut().insert(key, header_value);
-            }
-            Err(e) => debug(format!("Failed to create header value for key '{}', value '{}': {}", key, value, e)),
-        }
-    }
-
-    // Apply site-specific extra headers
-    for kv in &site.extra_headers {
-        if let Ok(key_name) = hyper::http::HeaderName::from_bytes(kv.key.as_bytes()) {
-            if let Ok(val) = HeaderValue::from_str(kv.value.as_str()) {
-                response.headers_mut().insert(key_name, val);
-            }
-        }
-    }
-
-    // Handle access logging
-    if site.access_log_enabled {
-        // Get current date and time in CLF format, which is like 10/Oct/2000:13:55:36 -0700
-        let now = Local::now();
-        let clf_date = now.format("%d/%b/%Y:%H:%M:%S %z").to_string();
-        let log_entry = format!(
-            "{} - - [{}] \"{} {} {}\" {} {}",
-            gruxi_request.get_remote_ip(),
-            clf_date,
-            gruxi_request.get_http_method(),
-            gruxi_request.get_path_and_query(),
-            gruxi_request.get_http_version(),
-            response.get_status(),
-            response.get_body_size()
-        );
-
-        let access_log_buffer_rwlock = running_state.get_access_log_buffer();
-        let access_log_buffer = access_log_buffer_rwlock.read().await;
-        access_log_buffer.add_log(site.id.to_string(), log_entry);
+    // Serve the file a handler asked for with X-Sendfile or X-Accel-Redirect, instead of its response. It is sent as is, as ranges apply to the file
+    let is_sendfile_response = !site.sendfile_root.is_empty() && SENDFILE_HEADERS.iter().any(|header| response.get_header(header).is_some());
+    if is_sendfile_response {
+        response = handle_sendfile_response(gruxi_request, response, &site.sendfile_root).await;
     }
+    context.is_sendfile_response = is_sendfile_response;
 
-    Ok(response)
-}
-
-async fn validate_request(gruxi_request: &mut GruxiRequest) -> Result<(), GruxiError> {
-    // Here we can add any request validation logic if needed
-    let cached_configuration = crate::configuration::cached_configuration::get_cached_configuration();
-    let configuration = cached_configuration.get_configuration().await;
-
-    // Validation for HTTP/1.1 only
-    if gruxi_request.get_http_version() == "HTTP/1.1" {
-        // [HTTP1.1] Requires a Host header
-        if !gruxi_request.get_headers().contains_key("Host") {
-            return Err(GruxiError::new(
-                GruxiErrorKind::HttpRequestValidation(hyper::StatusCode::BAD_REQUEST.as_u16()),
-                format!("Failed to get streaming HTTP request for request: {:?}", gruxi_request),
-            ));
-        }
-
-        // [HTTP1.1] If there is multiple host headers, we return a 400 error
-        if gruxi_request.get_headers().get_all("Host").iter().count() > 1 {
-            return Err(GruxiError::new(
-                GruxiErrorKind::HttpRequestValidation(hyper::StatusCode::BAD_REQUEST.as_u16()),
-                format!("Multiple Host headers for request: {:?}", gruxi_request),
-            ));
-        }
-    }
-
-    // [HTTP1.1 and later] Basic validation: check for valid method
-    let http_method = gruxi_request.get_http_method();
-    if http_method != "GET"
-        && http_method != "POST"
-        && http_method != "HEAD"
-        && http_method != "PUT"
-        && http_method != "DELETE"
-        && http_method != "OPTIONS"
-        && http_method != "TRACE"
-        && http_method != "CONNECT"
-        && http_method != "PATCH"
-    {
-        // Return a error for unsupported method
-        return Err(GruxiError::new(
-            GruxiErrorKind::HttpRequestValidation(hyper::StatusCode::NOT_IMPLEMENTED.as_u16()),
-            format!("Unsupported HTTP method for request: {:?}", gruxi_request),
-        ));
-    }
-
-    // Protect our server from overly large bodies
-    let max_body_size = configuration.core.server_settings.max_body_size;
-    if max_body_size > 0 && (http_method == "POST" || http_method == "PUT") {
-        // Check Content-Length header if present
-        if let Some(content_length_header) = gruxi_request.get_headers().get("Content-Length") {
-            if let Ok(content_length_str) = content_length_header.to_str() {
-                if let Ok(content_length) = content_length_str.parse::<u64>() {
-                    if content_length > max_body_size {
-                        return Err(GruxiError::new(
-                            GruxiErrorKind::HttpRequestValidation(hyper::StatusCode::PAYLOAD_TOO_LARGE.as_u16()),
-                            format!("Payload too large for request, based on content-length header: {:?}", gruxi_request),
-                        ));
-                    }
-                }
-            }
-        }
-
-        // Also check the expected body size
-        if gruxi_request.get_body_size() > max_body_size.try_into().unwrap_or(0) {
-            return Err(GruxiError::new(
-                GruxiErrorKind::HttpRequestValidation(hyper::StatusCode::PAYLOAD_TOO_LARGE.as_u16()),
-                format!("Payload too large for request, based on actual body size: {:?}", gruxi_request),
-            ));
-        }
+    // Unbuffered responses are passed on as they arrive, so tell any proxy in front of us not to buffer them either
+    if response.is_unbuffered() {
+        response.headers_mut().insert("X-Accel-Buffering", HeaderValue::from_static("no"));
     }
 
-    Ok(())
+    response
 }
@@ -0,0 +1,168 @@
+// ============================================================================
+// HEALTH PROBES
+// ============================================================================
+//
+// Liveness and readiness endpoints of Gruxi itself, such as /healthz and
+// /readyz, for Kubernetes probes and external load balancers. They are
+// answered on every binding before the sites, for the clients allowed by the
+// health probe settings.
+//
+// Liveness only tells the server runs and answers. Readiness tells it can
+// serve: the configuration is loaded and not being reloaded, every binding
+// is listening and every managed handler, such as PHP-CGI, is running. A
+// server that is not ready answers 503 with the checks that failed.
+// ============================================================================
+
+use std::sync::LazyLock;
+
+use dashmap::DashMap;
+use hyper::header::HeaderValue;
+use serde::Serialize;
+
+use crate::configuration::configuration::Configuration;
+use crate::core::running_state_manager::get_running_state_manager;
+use crate::http::request_response::gruxi_request::GruxiRequest;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+use crate::logging::syslog::trace;
+
+// How many listeners of each binding are running, by binding id. Counted, as the listeners of a reload can start before
+// the ones they replace have stopped
+static LISTENING_BINDINGS: LazyLock<DashMap<String, usize>> = LazyLock::new(DashMap::new);
+
+// Counts a binding as listening, until dropped
+pub struct ListeningBindingGuard {
+    binding_id: String,
+}
+
+impl ListeningBindingGuard {
+    pub fn new(binding_id: &str) -> Self {
+        *LISTENING_BINDINGS.entry(binding_id.to_string()).or_insert(0) += 1;
+        Self { binding_id: binding_id.to_string() }
+    }
+}
+
+impl Drop for ListeningBindingGuard {
+    fn drop(&mut self) {
+        if let Some(mut count) = LISTENING_BINDINGS.get_mut(&self.binding_id) {
+            *count = count.saturating_sub(1);
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub is_ready: bool,
+    pub configuration_loaded: bool,
+    pub bindings_not_listening: Vec<String>, // Ids of the bindings without a running listener
+    pub handlers_not_running: Vec<String>,   // Names of the managed handlers whose process is not running
+}
+
+// Configured bindings without a running listener
+fn get_bindings_not_listening(configuration: &Configuration) -> Vec<String> {
+    configuration
+        .bindings
+        .iter()
+        .filter(|binding| LISTENING_BINDINGS.get(&binding.id).is_none_or(|count| *count == 0))
+        .map(|binding| binding.id.clone())
+        .collect()
+}
+
+pub async fn get_readiness(configuration: &Configuration) -> Readiness {
+    // The running state is locked while a reload replaces it
+    let (configuration_loaded, handlers_not_running) = match get_running_state_manager().await.current_running_state.try_read() {
+        Ok(running_state) => {
+            let handlers_not_running = running_state
+                .get_external_system_handler()
+                .managed_handlers
+                .iter()
+                .filter(|handler| !handler.status.is_alive())
+                .map(|handler| handler.name.clone())
+                .collect();
+            (true, handlers_not_running)
+        }
+        Err(_) => (false, Vec::new()),
+    };
+    let bindings_not_listening = get_bindings_not_listening(configuration);
+
+    Readiness {
+        is_ready: configuration_loaded && bindings_not_listening.is_empty() && handlers_not_running.is_empty(),
+        configuration_loaded,
+        bindings_not_listening,
+        handlers_not_running,
+    }
+}
+
+// Answer the request when it is for a health probe the client is allowed, or None to let the site handle it
+pub async fn handle_health_probe(gruxi_request: &mut GruxiRequest, configuration: &Configuration) -> Option<GruxiResponse> {
+    let settings = &configuration.core.health_probes;
+    let method = gruxi_request.get_http_method_str();
+    if !settings.is_enabled || (method != "GET" && method != "HEAD") {
+        return None;
+    }
+    let path = gruxi_request.get_path_str().to_string();
+    let is_liveness = path == settings.liveness_path;
+    if !is_liveness && path != settings.readiness_path {
+        return None;
+    }
+    let remote_ip = gruxi_request.get_remote_ip();
+    if !settings.is_allowed(&remote_ip) {
+        trace(format!("Health probe '{}' from {} is not allowed, passing it to the site", path, remote_ip));
+        return None;
+    }
+
+    let mut response = if is_liveness {
+        let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), "ok");
+        response.headers_mut().insert("Content-Type", HeaderValue::from_static("text/plain"));
+        response
+    } else {
+        let readiness = get_readiness(configuration).await;
+        let status = if readiness.is_ready { hyper::StatusCode::OK } else { hyper::StatusCode::SERVICE_UNAVAILABLE };
+        let mut response = GruxiResponse::new_with_bytes(status.as_u16(), serde_json::to_string(&readiness).unwrap_or_default());
+        response.headers_mut().insert("Content-Type", HeaderValue::from_static("application/json"));
+        response
+    };
+    response.headers_mut().insert("Cache-Control", HeaderValue::from_static("no-store"));
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::binding::Binding;
+    use crate::configuration::health_probe_settings::HealthProbeSettings;
+
+    #[test]
+    fn test_bindings_not_listening() {
+        let mut configuration = Configuration::new();
+        let mut binding = Binding::new();
+        binding.id = uuid::Uuid::new_v4().to_string();
+        configuration.bindings.push(binding.clone());
+        assert_eq!(get_bindings_not_listening(&configuration), vec![binding.id.clone()]);
+
+        // The listener of a reload starts before the one it replaces stops
+        let old_listener = ListeningBindingGuard::new(&binding.id);
+        let new_listener = ListeningBindingGuard::new(&binding.id);
+        drop(old_listener);
+        assert!(get_bindings_not_listening(&configuration).is_empty());
+        drop(new_listener);
+        assert_eq!(get_bindings_not_listening(&configuration).len(), 1);
+    }
+
+    #[test]
+    fn test_health_probe_allowed_ips() {
+        let mut settings = HealthProbeSettings::new();
+        assert!(settings.is_allowed("203.0.113.5"));
+
+        settings.allowed_ips = vec!["10.0.0.0/8".to_string(), "::1".to_string()];
+        settings.sanitize();
+        assert!(settings.is_allowed("10.244.1.17"));
+        assert!(settings.is_allowed("::ffff:10.0.0.1"));
+        assert!(settings.is_allowed("::1"));
+        assert!(!settings.is_allowed("203.0.113.5"));
+        assert!(!settings.is_allowed(""));
+
+        settings.allowed_ips = vec!["10.0.0.0/33".to_string()];
+        settings.readiness_path = "/healthz".to_string();
+        assert_eq!(settings.validate().unwrap_err().len(), 2);
+    }
+}
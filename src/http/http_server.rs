@@ -1,19 +1,41 @@
 use crate::configuration::binding::Binding;
+use crate::configuration::server_settings::ServerSettings;
+use crate::core::cluster_sync::start_cluster_sync;
 use crate::core::monitoring::get_monitoring_state;
+use crate::core::scheduled_changes::start_scheduled_changes;
+use crate::core::scheduled_tasks::start_scheduled_tasks;
+use crate::core::site_health::start_site_health_sampling;
+use crate::core::synthetic_probes::start_synthetic_probes;
+use crate::core::traffic_accounting::start_traffic_accounting;
+use crate::core::usage_reports::start_usage_report_delivery;
+use crate::database::configuration_storage::start_configuration_storage_watch;
+use crate::database::database_backup::start_database_backups;
+use crate::file::disk_usage::start_disk_usage_monitor;
+use crate::file::webroot_sync::start_webroot_sync;
+use crate::http::cache_warmer::start_cache_warming;
 use crate::http::handle_request::handle_request;
+use crate::http::health_probes::ListeningBindingGuard;
 use crate::http::http_tls::build_unified_tls_acceptor;
-use crate::http::http_util::add_standard_headers_to_response;
+use crate::http::http_util::{add_standard_headers_to_response, apply_connection_semantics};
+use crate::http::request_limits::{LimitViolation, RequestLimitStream, RequestLimits, UpgradeOutcome};
+use crate::http::request_line::{PrefixedStream, check_first_request_line};
 use crate::http::request_response::gruxi_request::GruxiRequest;
 use crate::http::request_response::gruxi_response::GruxiResponse;
 use crate::logging::syslog::{debug, error, info, trace, warn};
+use crate::tls::ct_log_monitor::start_ct_log_monitor;
 use crate::tls::shared_acme_manager::initialize_shared_acme_manager;
+use dashmap::DashMap;
 use futures::FutureExt;
-use hyper::Request;
 use hyper::body::Incoming;
 use hyper::service::service_fn;
-use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper::{Request, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
 use hyper_util::server::conn::auto::Builder as HttpAutoBuilder;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+use tls_listener::rustls::rustls::HandshakeKind;
 use tokio::net::TcpListener;
 use tokio::select;
 use tokio_util::sync::CancellationToken;
@@ -30,6 +52,43 @@ pub async fn initialize_server() {
         error(format!("Failed to initialize shared ACME manager: {}. ACME certificates will not be available.", e));
     }
 
+    // Start certificate transparency monitoring, if enabled
+    start_ct_log_monitor().await;
+
+    // Pull the content of sites with a webroot sync from their SFTP or FTPS server
+    start_webroot_sync().await;
+
+    // Measure the disk usage of the sites, for their quotas
+    start_disk_usage_monitor().await;
+
+    // Crawl the sites with cache warming, so their caches are warm before visitors arrive
+    start_cache_warming().await;
+
+    // Save the traffic per site for usage reports, and deliver the reports of finished periods
+    start_traffic_accounting().await;
+    start_usage_report_delivery().await;
+
+    // Apply scheduled configuration changes when they are due
+    start_scheduled_changes().await;
+
+    // Run the recurring maintenance tasks on their schedules
+    start_scheduled_tasks().await;
+
+    // Sample the health of the sites, for their availability on the status page
+    start_site_health_sampling().await;
+
+    // Probe the sites with synthetic probes at their intervals, alerting when they keep failing
+    start_synthetic_probes().await;
+
+    // Keep the configuration in sync with the primary, if this instance is a replica
+    start_cluster_sync().await;
+
+    // Reload when another node changes the configuration, if the configuration storage is shared
+    start_configuration_storage_watch().await;
+
+    // Back up the configuration database on schedule, if enabled
+    start_database_backups().await;
+
     // Starting listening on all configured bindings
     for binding in &config.bindings {
         let ip_result = binding.ip.parse::<std::net::IpAddr>();
@@ -79,7 +138,18 @@ async fn start_listener_with_retry(addr: SocketAddr) -> TcpListener {
     }
 }
 
-async fn start_server_binding(binding: Binding) {
+// Ports picked by the OS for the bindings on port 0, by binding id. Kept, so a binding listens on the same port after a reload
+static ASSIGNED_PORTS: LazyLock<DashMap<String, u16>> = LazyLock::new(DashMap::new);
+
+/// The port the binding listens on. A binding on port 0 gets a free port from the OS when it first starts, and 0 until then
+pub fn get_binding_port(binding: &Binding) -> u16 {
+    if binding.port != 0 {
+        return binding.port;
+    }
+    ASSIGNED_PORTS.get(&binding.id).map(|port| *port).unwrap_or(0)
+}
+
+pub async fn start_server_binding(binding: Binding) {
     let ip_result = binding.ip.parse::<std::net::IpAddr>();
     let ip = match ip_result {
         Ok(ip_addr) => ip_addr,
@@ -88,11 +158,28 @@ async fn start_server_binding(binding: Binding) {
             return;
         }
     };
-    let port = binding.port;
+    let port = get_binding_port(&binding);
     let addr = SocketAddr::new(ip, port);
 
     let listener = start_listener_with_retry(addr).await;
+    if binding.port == 0
+        && let Ok(local_addr) = listener.local_addr()
+    {
+        ASSIGNED_PORTS.insert(binding.id.clone(), local_addr.port());
+    }
     trace(format!("Listening on binding: {:?}", binding));
+    // Counted as listening for the readiness probe, until this listener stops
+    let _listening_guard = ListeningBindingGuard::new(&binding.id);
+
+    // Bindings are started again on configuration reload, so the policy and limits are read once per binding
+    let server_settings = Arc::new(
+        crate::configuration::cached_configuration::get_cached_configuration()
+            .get_configuration()
+            .await
+            .core
+            .server_settings
+            .clone(),
+    );
 
     let triggers = crate::core::triggers::get_trigger_handler();
 
@@ -145,18 +232,20 @@ async fn start_server_binding(binding: Binding) {
 
                             let acceptor = tls_acceptor.clone();
                             let binding = binding.clone();
+                            let server_settings = server_settings.clone();
                             let shutdown_token = shutdown_token.clone();
                             let stop_services_token = stop_services_token.clone();
 
                             tokio::spawn(async move {
                                 match acceptor.accept(tcp_stream).await {
                                     Ok(tls_stream) => {
-                                        let io = TokioIo::new(tls_stream);
                                         // Increment requests in queue when connection is ready to be served
                                         let monitoring_state = get_monitoring_state().await;
+                                        monitoring_state.record_tls_handshake(tls_stream.get_ref().1.handshake_kind() == Some(HandshakeKind::Resumed));
+                                        let tls_server_name = tls_stream.get_ref().1.server_name().map(|server_name| server_name.to_string());
                                         monitoring_state.increment_requests_in_queue();
 
-                                        if let Err(panic) = std::panic::AssertUnwindSafe(serve_connection(io, binding, remote_addr_ip, shutdown_token, stop_services_token)).catch_unwind().await {
+                                        if let Err(panic) = std::panic::AssertUnwindSafe(serve_connection(tls_stream, binding, remote_addr_ip, tls_server_name, server_settings, shutdown_token, stop_services_token)).catch_unwind().await {
                                             debug(format!("Panic occurred while serving TLS connection: {:?}", panic));
                                         }
 
@@ -194,8 +283,8 @@ async fn start_server_binding(binding: Binding) {
                                 .map(|addr| addr.ip().to_string())
                                 .unwrap_or_else(|_| "<unknown>".to_string());
 
-                            let io = TokioIo::new(tcp_stream);
                             let binding = binding.clone();
+                            let server_settings = server_settings.clone();
                             let shutdown_token = shutdown_token.clone();
                             let stop_services_token = stop_services_token.clone();
 
@@ -204,7 +293,7 @@ async fn start_server_binding(binding: Binding) {
                                 let monitoring_state = get_monitoring_state().await;
                                 monitoring_state.increment_requests_in_queue();
 
-                                if let Err(panic) = std::panic::AssertUnwindSafe(serve_connection(io, binding, remote_addr_ip, shutdown_token, stop_services_token)).catch_unwind().await {
+                                if let Err(panic) = std::panic::AssertUnwindSafe(serve_connection(tcp_stream, binding, remote_addr_ip, None, server_settings, shutdown_token, stop_services_token)).catch_unwind().await {
                                     debug(format!("Panic occurred while serving connection: {:?}", panic));
                                 }
 
@@ -223,23 +312,76 @@ async fn start_server_binding(binding: Binding) {
 }
 
 // Helper function to serve a connection (works for both TLS and non-TLS)
-async fn serve_connection<S>(io: TokioIo<S>, binding: Binding, remote_addr_ip: String, shutdown_token: CancellationToken, stop_services_token: CancellationToken)
-where
+async fn serve_connection<S>(
+    mut stream: S,
+    binding: Binding,
+    remote_addr_ip: String,
+    tls_server_name: Option<String>, // The SNI name of TLS connections
+    server_settings: Arc<ServerSettings>,
+    shutdown_token: CancellationToken,
+    stop_services_token: CancellationToken,
+) where
     S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
 {
+    // Unsupported HTTP versions and malformed request lines are answered before hyper parses the request
+    let Some(request_line_bytes) = check_first_request_line(&mut stream, &server_settings.http_version_policy).await else {
+        return;
+    };
+    // Requests over the limits are cut off before hyper reads them, and answered by the service below
+    let request_limits = RequestLimits::from_settings(&server_settings);
+    let limit_violation = LimitViolation::default();
+    let upgrade_outcome = UpgradeOutcome::default();
+    let io = TokioIo::new(RequestLimitStream::new(
+        PrefixedStream::new(request_line_bytes, stream),
+        request_limits,
+        limit_violation.clone(),
+        upgrade_outcome.clone(),
+    ));
+
     let shutdown_token_conn = shutdown_token.clone();
     let stop_services_token_conn = stop_services_token.clone();
+    let keep_alive_timeout_seconds = binding.keep_alive_timeout_seconds;
+    let max_keep_alive_requests = binding.max_keep_alive_requests;
+    let served_requests = Arc::new(AtomicU32::new(0));
+    let received_requests = Arc::new(AtomicU32::new(0));
+    let (http1_only, http2_only) = (binding.is_http1_only(), binding.is_http2_only());
 
     let svc = service_fn(move |req: Request<Incoming>| {
         let binding = binding.clone();
         let remote_ip = remote_addr_ip.clone();
+        let tls_server_name = tls_server_name.clone();
+        let served_requests = served_requests.clone();
+        let received_requests = received_requests.clone();
+        let limit_violation = limit_violation.clone();
+        let upgrade_outcome = upgrade_outcome.clone();
 
         async move {
             // Count the request in monitoring
             get_monitoring_state().await.increment_requests_served();
 
+            let request_version = req.version();
+            // HTTP/1.x requests are served in order, so their position tells whether the stream cut them off
+            let request_index = received_requests.fetch_add(1, Ordering::Relaxed);
+            // Requests of connections the stream passes through, such as after malformed framing, are checked as hyper parsed them
+            let limit_status = if request_version == Version::HTTP_2 {
+                request_limits.check_request(&req)
+            } else {
+                limit_violation.get_status(request_index).or_else(|| request_limits.check_request(&req))
+            };
+            if let Some(status) = limit_status {
+                upgrade_outcome.set(request_index, false);
+                return Ok::<_, std::convert::Infallible>(get_limit_response(status, request_version).into_hyper());
+            }
+            let is_connect = req.method() == hyper::Method::CONNECT;
+
+            let request_connection = req.headers().get(hyper::header::CONNECTION).and_then(|value| value.to_str().ok()).unwrap_or("").to_string();
+            let http10_strict_close = binding.http10_strict_close;
+
             let mut gruxi_request = GruxiRequest::from_hyper(req);
             gruxi_request.add_calculated_data("remote_ip", &remote_ip);
+            if let Some(tls_server_name) = &tls_server_name {
+                gruxi_request.add_calculated_data("tls_server_name", tls_server_name);
+            }
             let gruxi_response_result = handle_request(gruxi_request, binding).await;
             let mut response = match gruxi_response_result {
                 Err(err) => {
@@ -250,17 +392,61 @@ where
                 Ok(response) => response,
             };
 
+            // A chunk over the limits fails the body while the request is handled, which is answered instead of the response
+            if request_version != Version::HTTP_2
+                && let Some(status) = limit_violation.get_status(request_index)
+            {
+                upgrade_outcome.set(request_index, false);
+                return Ok(get_limit_response(status, request_version).into_hyper());
+            }
+
             // Add standard headers
             add_standard_headers_to_response(&mut response);
 
             debug(format!("Responding with: {:?}", response));
 
-            // Convert gruxi_response to hyper response
-            Ok::<_, std::convert::Infallible>(response.into_hyper())
+            // Convert gruxi_response to hyper response, and keep or close the connection as the client and binding want
+            let mut hyper_response = response.into_hyper();
+            // HTTP/2 multiplexes requests, so the limit is only for HTTP/1.x connections
+            let served = served_requests.fetch_add(1, Ordering::Relaxed) + 1;
+            let remaining_requests = if max_keep_alive_requests > 0 && request_version != Version::HTTP_2 {
+                Some(max_keep_alive_requests.saturating_sub(served))
+            } else {
+                None
+            };
+            apply_connection_semantics(
+                &mut hyper_response,
+                request_version,
+                &request_connection,
+                http10_strict_close,
+                keep_alive_timeout_seconds,
+                remaining_requests,
+            );
+
+            // Only a response switching protocols makes the rest of the connection something other than requests
+            let status = hyper_response.status();
+            upgrade_outcome.set(request_index, status == hyper::StatusCode::SWITCHING_PROTOCOLS || (is_connect && status.is_success()));
+            Ok::<_, std::convert::Infallible>(hyper_response)
         }
     });
 
-    let connection = HttpAutoBuilder::new(TokioExecutor::new());
+    let mut connection = HttpAutoBuilder::new(TokioExecutor::new());
+    // Bindings pinned to one HTTP version refuse the other, also when a client skips ALPN
+    if http1_only {
+        connection = connection.http1_only();
+    } else if http2_only {
+        connection = connection.http2_only();
+    }
+    // hyper has its own limits, which are set to let through what the request limits do
+    connection.http1().max_headers(request_limits.max_header_count).max_buf_size(request_limits.get_hyper_buffer_size());
+    connection.http2().max_header_list_size(request_limits.get_http2_header_list_size());
+    // Idle kept-alive connections are closed when no further request starts within the timeout
+    if keep_alive_timeout_seconds > 0 {
+        connection.http1().timer(TokioTimer::new()).header_read_timeout(Duration::from_secs(keep_alive_timeout_seconds as u64));
+    } else {
+        // No idle timeout, also not the default of hyper
+        connection.http1().header_read_timeout(None);
+    }
 
     // Serve the connection and listen for shutdown signals
     let result = tokio::select! {
@@ -273,3 +459,13 @@ where
         trace(format!("Connection error: {:?}", err));
     }
 }
+
+// The answer to a request over the request limits, after which HTTP/1.x connections are closed, as the rest of the stream was cut off
+fn get_limit_response(status: u16, request_version: Version) -> GruxiResponse {
+    let mut response = GruxiResponse::new_empty_with_status(status);
+    if request_version != Version::HTTP_2 {
+        response.headers_mut().insert(hyper::header::CONNECTION, hyper::header::HeaderValue::from_static("close"));
+    }
+    add_standard_headers_to_response(&mut response);
+    response
+}
@@ -1,8 +1,12 @@
+use crate::configuration::cached_configuration::get_cached_configuration;
 use crate::core::running_state_manager::get_running_state_manager;
+use crate::http::site_match::hostname_pattern::{HostnamePattern, find_best_pattern_match, is_regex_hostname};
 use crate::logging::syslog::{debug, warn};
-use crate::tls::shared_acme_manager::{get_shared_acme_domains, get_shared_acme_manager_async};
+use crate::tls::acme_smoke_test::get_smoke_test_tls_alpn01_certificate;
+use crate::tls::dev_ca::generate_site_certificate;
+use crate::tls::session_resumption::apply_session_resumption;
+use crate::tls::shared_acme_manager::{SharedAcmeResolver, get_shared_acme_domains, get_shared_acme_manager_async, get_wildcard_domain};
 use rand;
-use rustls_acme::ResolvesServerCertAcme;
 use rustls::crypto::aws_lc_rs;
 use rustls_pki_types::{CertificateDer, PrivateKeyDer};
 use std::io::BufReader;
@@ -17,13 +21,18 @@ use tokio_rustls::rustls::{self, ServerConfig as RustlsServerConfig};
 
 use crate::configuration::binding::Binding;
 use crate::configuration::site::Site;
-use crate::core::database_connection::get_database_connection;
+use crate::core::database_connection::get_database_writer;
+use crate::database::data_access::execute;
+use crate::error::gruxi_error::GruxiError;
+use crate::error::gruxi_error_enums::TlsError;
 
 // Persist generated cert/key to disk and update configuration for a specific site
-pub async fn persist_generated_tls_for_site(site: &Site, cert_pem: &str, key_pem: &str, is_admin: bool) -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
+pub async fn persist_generated_tls_for_site(site: &Site, cert_pem: &str, key_pem: &str, is_admin: bool) -> Result<(String, String), GruxiError> {
     // Ensure target directory exists with appropriate permissions
     let dir = "certs";
-    fs::create_dir_all(dir).await.map_err(|e| format!("Failed to create certs directory '{}': {}", dir, e))?;
+    fs::create_dir_all(dir)
+        .await
+        .map_err(|e| GruxiError::tls(TlsError::Storage, format!("Failed to create certs directory '{}': {}", dir, e)))?;
 
     // Generate a random number for this cert
     let random_number: u32 = rand::random();
@@ -36,50 +45,58 @@ pub async fn persist_generated_tls_for_site(site: &Site, cert_pem: &str, key_pem
     let key_tmp = format!("{}.tmp", &key_path);
 
     {
-        let mut f = fs::File::create(&cert_tmp).await.map_err(|e| format!("Failed to create temp cert file '{}': {}", cert_tmp, e))?;
-        f.write_all(cert_pem.as_bytes()).await.map_err(|e| format!("Failed to write cert data to '{}': {}", cert_tmp, e))?;
-        f.flush().await.map_err(|e| format!("Failed to flush cert file '{}': {}", cert_tmp, e))?;
+        let mut f = fs::File::create(&cert_tmp)
+            .await
+            .map_err(|e| GruxiError::tls(TlsError::Storage, format!("Failed to create temp cert file '{}': {}", cert_tmp, e)))?;
+        f.write_all(cert_pem.as_bytes())
+            .await
+            .map_err(|e| GruxiError::tls(TlsError::Storage, format!("Failed to write cert data to '{}': {}", cert_tmp, e)))?;
+        f.flush()
+            .await
+            .map_err(|e| GruxiError::tls(TlsError::Storage, format!("Failed to flush cert file '{}': {}", cert_tmp, e)))?;
     }
     fs::rename(&cert_tmp, &cert_path)
         .await
-        .map_err(|e| format!("Failed to rename temp cert file '{}' to '{}': {}", cert_tmp, cert_path, e))?;
+        .map_err(|e| GruxiError::tls(TlsError::Storage, format!("Failed to rename temp cert file '{}' to '{}': {}", cert_tmp, cert_path, e)))?;
 
     {
-        let mut f = fs::File::create(&key_tmp).await.map_err(|e| format!("Failed to create temp key file '{}': {}", key_tmp, e))?;
-        f.write_all(key_pem.as_bytes()).await.map_err(|e| format!("Failed to write key data to '{}': {}", key_tmp, e))?;
-        f.flush().await.map_err(|e| format!("Failed to flush key file '{}': {}", key_tmp, e))?;
+        let mut f = fs::File::create(&key_tmp)
+            .await
+            .map_err(|e| GruxiError::tls(TlsError::Storage, format!("Failed to create temp key file '{}': {}", key_tmp, e)))?;
+        f.write_all(key_pem.as_bytes())
+            .await
+            .map_err(|e| GruxiError::tls(TlsError::Storage, format!("Failed to write key data to '{}': {}", key_tmp, e)))?;
+        f.flush()
+            .await
+            .map_err(|e| GruxiError::tls(TlsError::Storage, format!("Failed to flush key file '{}': {}", key_tmp, e)))?;
     }
     fs::rename(&key_tmp, &key_path)
         .await
-        .map_err(|e| format!("Failed to rename temp key file '{}' to '{}': {}", key_tmp, key_path, e))?;
+        .map_err(|e| GruxiError::tls(TlsError::Storage, format!("Failed to rename temp key file '{}' to '{}': {}", key_tmp, key_path, e)))?;
 
     // Update configuration in DB so future runs use persisted files
-    let connection = get_database_connection()?;
+    let connection = get_database_writer()?;
 
     // Update the fields in the database directly
     if is_admin {
         // For admin portal, update the configuration table
-        let sql_update = format!(
-            "UPDATE server_settings SET setting_value = '{}' WHERE setting_key = 'admin_portal_tls_certificate_path';",
-            cert_path.clone()
-        );
-        connection
-            .execute(sql_update.as_str())
-            .map_err(|e| format!("Failed to update admin portal TLS paths in database: {}", e))?;
-        let sql_update = format!("UPDATE server_settings SET setting_value = '{}' WHERE setting_key = 'admin_portal_tls_key_path';", key_path.clone());
-        connection
-            .execute(sql_update.as_str())
-            .map_err(|e| format!("Failed to update admin portal TLS paths in database: {}", e))?;
+        execute(
+            &connection,
+            "UPDATE server_settings SET setting_value = ? WHERE setting_key = 'admin_portal_tls_certificate_path'",
+            &[&cert_path],
+        )
+        .map_err(|e| e.with_context("Failed to update admin portal TLS paths in database"))?;
+        execute(
+            &connection,
+            "UPDATE server_settings SET setting_value = ? WHERE setting_key = 'admin_portal_tls_key_path'",
+            &[&key_path],
+        )
+        .map_err(|e| e.with_context("Failed to update admin portal TLS paths in database"))?;
         return Ok((cert_path, key_path));
     } else {
         // For regular site, update the sites table
-        let sql_update = format!(
-            "UPDATE sites SET tls_cert_path = '{}', tls_key_path = '{}' WHERE id = '{}';",
-            cert_path.clone(),
-            key_path.clone(),
-            site.id
-        );
-        connection.execute(sql_update.as_str()).map_err(|e| format!("Failed to update site TLS paths in database: {}", e))?;
+        execute(&connection, "UPDATE sites SET tls_cert_path = ?, tls_key_path = ? WHERE id = ?", &[&cert_path, &key_path, &site.id])
+            .map_err(|e| e.with_context("Failed to update site TLS paths in database"))?;
     }
 
     Ok((cert_path, key_path))
@@ -91,9 +108,11 @@ pub async fn persist_generated_tls_for_site(site: &Site, cert_pem: &str, key_pem
 #[derive(Debug)]
 pub struct UnifiedCertResolver {
     /// The ACME resolver handles TLS-ALPN-01 challenges and serves ACME-acquired certificates
-    acme_resolver: Option<std::sync::Arc<ResolvesServerCertAcme>>,
+    acme_resolver: Option<std::sync::Arc<SharedAcmeResolver>>,
     /// SNI-based resolver for manually configured certificates
     sni_resolver: ResolvesServerCertUsingSni,
+    /// Certificates for wildcard and regex hostnames, tried after the exact names of the SNI resolver
+    pattern_certs: Vec<(HostnamePattern, std::sync::Arc<RustlsCertifiedKey>)>,
     /// Fallback certificate when no SNI match is found
     fallback_cert: Option<std::sync::Arc<RustlsCertifiedKey>>,
     /// Domains that are managed by ACME (should not use manual certs)
@@ -101,10 +120,11 @@ pub struct UnifiedCertResolver {
 }
 
 impl UnifiedCertResolver {
-    pub fn new(acme_resolver: Option<std::sync::Arc<ResolvesServerCertAcme>>, acme_domains: std::collections::HashSet<String>) -> Self {
+    pub fn new(acme_resolver: Option<std::sync::Arc<SharedAcmeResolver>>, acme_domains: std::collections::HashSet<String>) -> Self {
         Self {
             acme_resolver,
             sni_resolver: ResolvesServerCertUsingSni::new(),
+            pattern_certs: Vec::new(),
             fallback_cert: None,
             acme_domains,
         }
@@ -114,13 +134,18 @@ impl UnifiedCertResolver {
         self.sni_resolver.add(hostname, cert)
     }
 
+    pub fn add_pattern_cert(&mut self, pattern: HostnamePattern, cert: std::sync::Arc<RustlsCertifiedKey>) {
+        self.pattern_certs.push((pattern, cert));
+    }
+
     pub fn set_fallback(&mut self, cert: std::sync::Arc<RustlsCertifiedKey>) {
         self.fallback_cert = Some(cert);
     }
 
-    /// Check if a domain is managed by ACME
+    /// Check if a domain is managed by ACME, either by name or by the wildcard covering it
     fn is_acme_domain(&self, domain: &str) -> bool {
-        self.acme_domains.contains(&domain.to_lowercase())
+        let domain = domain.to_lowercase();
+        self.acme_domains.contains(&domain) || get_wildcard_domain(&domain).is_some_and(|wildcard| self.acme_domains.contains(&wildcard))
     }
 }
 
@@ -131,6 +156,10 @@ impl ResolvesServerCert for UnifiedCertResolver {
         let is_acme_challenge = rustls_acme::is_tls_alpn_challenge(&client_hello);
 
         if is_acme_challenge {
+            // Challenges of the staging smoke test are answered first, as the domain may not be managed by ACME yet
+            if let Some(cert) = client_hello.server_name().and_then(get_smoke_test_tls_alpn01_certificate) {
+                return Some(cert);
+            }
             // For ACME challenges, delegate to the ACME resolver
             if let Some(ref acme_resolver) = self.acme_resolver {
                 return acme_resolver.resolve(client_hello);
@@ -158,6 +187,10 @@ impl ResolvesServerCert for UnifiedCertResolver {
                 if let Some(cert) = self.sni_resolver.resolve(client_hello) {
                     return Some(cert);
                 }
+                // Then wildcard and regex hostnames, by the same precedence as the site matching
+                if let Some(cert) = find_best_pattern_match(self.pattern_certs.iter().map(|(pattern, cert)| (pattern, cert)), domain) {
+                    return Some(cert.clone());
+                }
             }
         } else {
             // No SNI provided, try the SNI resolver anyway (it might have a default)
@@ -216,7 +249,7 @@ pub async fn get_acme_domains_for_binding(binding: &Binding) -> std::collections
     for site in sites.iter().filter(|s| s.is_enabled && s.tls_automatic_enabled) {
         for hostname in &site.hostnames {
             let h = hostname.trim().to_lowercase();
-            if h.is_empty() || h == "*" || h.contains('*') || h == "localhost" || !h.contains('.') {
+            if h.is_empty() || h == "*" || h.contains('*') || is_regex_hostname(&h) || h == "localhost" || !h.contains('.') {
                 continue;
             }
             domains.insert(h);
@@ -228,10 +261,7 @@ pub async fn get_acme_domains_for_binding(binding: &Binding) -> std::collections
 
 /// Build a unified certificate resolver that handles both ACME and manual certificates.
 /// Uses the shared ACME manager if available.
-pub async fn build_unified_cert_resolver(
-    binding: &Binding,
-    acme_resolver: Option<std::sync::Arc<ResolvesServerCertAcme>>,
-) -> Result<UnifiedCertResolver, Box<dyn std::error::Error + Send + Sync>> {
+pub async fn build_unified_cert_resolver(binding: &Binding, acme_resolver: Option<std::sync::Arc<SharedAcmeResolver>>) -> Result<UnifiedCertResolver, GruxiError> {
     // Get ACME domains from the shared manager if available, otherwise use binding-specific lookup
     let acme_domains = {
         let shared_domains = get_shared_acme_domains().await;
@@ -268,11 +298,7 @@ pub async fn build_unified_cert_resolver(
         }
 
         // Determine SANs for this site
-        let mut sans: Vec<String> = site.hostnames
-            .iter()
-            .cloned()
-            .filter(|h| !h.trim().is_empty() && h != "*")
-            .collect();
+        let mut sans: Vec<String> = site.hostnames.iter().cloned().filter(|h| !h.trim().is_empty() && h != "*" && !is_regex_hostname(h)).collect();
         let has_wildcard = site.hostnames.contains(&"*".to_string());
 
         if sans.is_empty() || has_wildcard {
@@ -291,20 +317,17 @@ pub async fn build_unified_cert_resolver(
         // Load or generate certificate
         let (cert_chain, priv_key) = if !site.tls_cert_path.is_empty() && !site.tls_key_path.is_empty() {
             // Load from PEM files
-            let cert_file = std::fs::File::open(&site.tls_cert_path)
-                .map_err(|e| format!("Failed to open TLS cert file {}: {}", site.tls_cert_path, e))?;
-            let key_file = std::fs::File::open(&site.tls_key_path)
-                .map_err(|e| format!("Failed to open TLS key file {}: {}", site.tls_key_path, e))?;
+            let cert_file = std::fs::File::open(&site.tls_cert_path).map_err(|e| GruxiError::tls(TlsError::Certificate, format!("Failed to open TLS cert file {}: {}", site.tls_cert_path, e)))?;
+            let key_file = std::fs::File::open(&site.tls_key_path).map_err(|e| GruxiError::tls(TlsError::PrivateKey, format!("Failed to open TLS key file {}: {}", site.tls_key_path, e)))?;
 
             let mut cert_reader = BufReader::new(cert_file);
             let mut key_reader = BufReader::new(key_file);
 
             let certs: Result<Vec<CertificateDer<'static>>, _> = rustls_pemfile::certs(&mut cert_reader).collect();
-            let cert_chain = certs.map_err(|e| format!("Failed to parse TLS cert file {}: {}", site.tls_cert_path, e))?;
+            let cert_chain = certs.map_err(|e| GruxiError::tls(TlsError::Certificate, format!("Failed to parse TLS cert file {}: {}", site.tls_cert_path, e)))?;
 
-            let key_result = rustls_pemfile::private_key(&mut key_reader)
-                .map_err(|e| format!("Failed to parse TLS key file {}: {}", site.tls_key_path, e))?;
-            let priv_key = key_result.ok_or_else(|| format!("No private key found in {}", site.tls_key_path))?;
+            let key_result = rustls_pemfile::private_key(&mut key_reader).map_err(|e| GruxiError::tls(TlsError::PrivateKey, format!("Failed to parse TLS key file {}: {}", site.tls_key_path, e)))?;
+            let priv_key = key_result.ok_or_else(|| GruxiError::tls(TlsError::PrivateKey, format!("No private key found in {}", site.tls_key_path)))?;
 
             (cert_chain, priv_key)
         } else if !site.tls_cert_content.is_empty() && !site.tls_key_content.is_empty() {
@@ -313,30 +336,25 @@ pub async fn build_unified_cert_resolver(
             let mut key_cursor = std::io::Cursor::new(site.tls_key_content.as_bytes());
 
             let certs: Result<Vec<CertificateDer<'static>>, _> = rustls_pemfile::certs(&mut cert_cursor).collect();
-            let cert_chain = certs.map_err(|e| format!("Failed to parse TLS cert PEM content: {}", e))?;
+            let cert_chain = certs.map_err(|e| GruxiError::tls(TlsError::Certificate, format!("Failed to parse TLS cert PEM content: {}", e)))?;
 
-            let key_result = rustls_pemfile::private_key(&mut key_cursor)
-                .map_err(|e| format!("Failed to parse TLS key PEM content: {}", e))?;
-            let priv_key = key_result.ok_or_else(|| "No private key found in PEM content".to_string())?;
+            let key_result = rustls_pemfile::private_key(&mut key_cursor).map_err(|e| GruxiError::tls(TlsError::PrivateKey, format!("Failed to parse TLS key PEM content: {}", e)))?;
+            let priv_key = key_result.ok_or_else(|| GruxiError::tls(TlsError::PrivateKey, "No private key found in PEM content"))?;
 
             (cert_chain, priv_key)
         } else {
-            // Generate self-signed certificate
-            debug(format!("Generating self-signed certificate for site with hostnames: {:?}", sans));
-            let rcgen::CertifiedKey { cert, signing_key } = rcgen::generate_simple_self_signed(sans.clone())
-                .map_err(|e| format!("Failed to generate self-signed cert: {}", e))?;
-            let cert_pem = cert.pem();
-            let key_pem = signing_key.serialize_pem();
+            // Generate a certificate, signed by the development CA when there is one
+            debug(format!("Generating certificate for site with hostnames: {:?}", sans));
+            let (cert_pem, key_pem) = generate_site_certificate(&sans).map_err(|e| GruxiError::tls(TlsError::Certificate, e))?;
 
             let mut cert_cursor = std::io::Cursor::new(cert_pem.as_bytes());
             let mut key_cursor = std::io::Cursor::new(key_pem.as_bytes());
 
             let certs: Result<Vec<CertificateDer<'static>>, _> = rustls_pemfile::certs(&mut cert_cursor).collect();
-            let cert_chain = certs.map_err(|e| format!("Failed to parse generated TLS cert PEM content: {}", e))?;
+            let cert_chain = certs.map_err(|e| GruxiError::tls(TlsError::Certificate, format!("Failed to parse generated TLS cert PEM content: {}", e)))?;
 
-            let key_result = rustls_pemfile::private_key(&mut key_cursor)
-                .map_err(|e| format!("Failed to parse generated TLS key PEM content: {}", e))?;
-            let priv_key = key_result.ok_or_else(|| "No private key found in generated PEM content".to_string())?;
+            let key_result = rustls_pemfile::private_key(&mut key_cursor).map_err(|e| GruxiError::tls(TlsError::PrivateKey, format!("Failed to parse generated TLS key PEM content: {}", e)))?;
+            let priv_key = key_result.ok_or_else(|| GruxiError::tls(TlsError::PrivateKey, "No private key found in generated PEM content"))?;
 
             // Persist generated cert/key to disk
             match persist_generated_tls_for_site(site, &cert_pem, &key_pem, binding.is_admin).await {
@@ -357,18 +375,18 @@ pub async fn build_unified_cert_resolver(
         }
 
         // Build certified key
-        let signing_key = aws_lc_rs::sign::any_supported_type(&priv_key)
-            .map_err(|e| format!("Unsupported private key type: {}", e))?;
+        let signing_key = aws_lc_rs::sign::any_supported_type(&priv_key).map_err(|e| GruxiError::tls(TlsError::PrivateKey, format!("Unsupported private key type: {}", e)))?;
         let certified = RustlsCertifiedKey::new(cert_chain, signing_key);
         let certified_arc = std::sync::Arc::new(certified);
 
-        // Set as fallback if this is the first certificate
-        if fallback_certificate.is_none() {
+        // Set as fallback if this is the first certificate, or the certificate of the default site of the binding, so
+        // unknown hostnames get the certificate of the site serving them
+        if fallback_certificate.is_none() || site.id == binding.default_site_id {
             fallback_certificate = Some(certified_arc.clone());
         }
 
-        // Add certificate for each hostname
-        for name in &sans {
+        // Add certificate for each hostname. Wildcards are not accepted by the SNI resolver, so they are added below
+        for name in sans.iter().filter(|name| !name.starts_with("*.")) {
             match resolver.add_manual_cert(name, certified_arc.as_ref().clone()) {
                 Ok(()) => {
                     cert_added = true;
@@ -380,6 +398,17 @@ pub async fn build_unified_cert_resolver(
             }
         }
 
+        // Wildcard and regex hostnames are matched against the SNI name, when no exact hostname matches it
+        for pattern in site
+            .get_hostname_patterns()
+            .iter()
+            .filter(|pattern| matches!(pattern, HostnamePattern::Wildcard(_) | HostnamePattern::Regex(_)))
+        {
+            resolver.add_pattern_cert(pattern.clone(), certified_arc.clone());
+            cert_added = true;
+            debug(format!("Added manual cert for hostname pattern {:?}", pattern));
+        }
+
         // For wildcard sites, add localhost
         if has_wildcard {
             if !sans.contains(&"localhost".to_string()) {
@@ -397,13 +426,10 @@ pub async fn build_unified_cert_resolver(
     if !cert_added && acme_domains.is_empty() {
         // Generate a fallback self-signed cert
         let rcgen::CertifiedKey { cert, signing_key } =
-            rcgen::generate_simple_self_signed(vec!["localhost".to_string()])
-                .map_err(|e| format!("Failed to generate fallback self-signed cert: {}", e))?;
+            rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).map_err(|e| GruxiError::tls(TlsError::Certificate, format!("Failed to generate fallback self-signed cert: {}", e)))?;
         let cert_der = CertificateDer::from(cert.der().to_vec());
-        let key_der = PrivateKeyDer::try_from(signing_key.serialize_der())
-            .map_err(|e| format!("Invalid key DER: {}", e))?;
-        let signing_key = aws_lc_rs::sign::any_supported_type(&key_der)
-            .map_err(|e| format!("Unsupported private key type: {}", e))?;
+        let key_der = PrivateKeyDer::try_from(signing_key.serialize_der()).map_err(|e| GruxiError::tls(TlsError::PrivateKey, format!("Invalid key DER: {}", e)))?;
+        let signing_key = aws_lc_rs::sign::any_supported_type(&key_der).map_err(|e| GruxiError::tls(TlsError::PrivateKey, format!("Unsupported private key type: {}", e)))?;
         let certified = RustlsCertifiedKey::new(vec![cert_der], signing_key);
         let certified_arc = std::sync::Arc::new(certified);
 
@@ -427,14 +453,11 @@ pub async fn build_unified_cert_resolver(
 /// Build a unified TLS acceptor that handles both ACME and manual certificates.
 /// Uses the shared ACME manager if available, ensuring only one ACME client exists globally.
 /// Returns the TlsAcceptor only (ACME polling is handled by the shared manager).
-pub async fn build_unified_tls_acceptor(
-    binding: &Binding,
-) -> Result<TlsAcceptor, Box<dyn std::error::Error + Send + Sync>> {
+pub async fn build_unified_tls_acceptor(binding: &Binding) -> Result<TlsAcceptor, GruxiError> {
     let provider = rustls::crypto::aws_lc_rs::default_provider();
 
     // Get the shared ACME resolver if available (already initialized during server startup)
     let acme_resolver = get_shared_acme_manager_async().await;
-    let has_acme = acme_resolver.is_some();
 
     // Build the unified cert resolver with ACME and manual certs
     let unified_resolver = build_unified_cert_resolver(binding, acme_resolver).await?;
@@ -442,16 +465,17 @@ pub async fn build_unified_tls_acceptor(
     // Build ServerConfig with our unified resolver
     let mut server_config = RustlsServerConfig::builder_with_provider(provider.into())
         .with_safe_default_protocol_versions()
-        .map_err(|_| "Protocol versions unavailable")?
+        .map_err(|_| GruxiError::tls(TlsError::Configuration, "Protocol versions unavailable"))?
         .with_no_client_auth()
         .with_cert_resolver(std::sync::Arc::new(unified_resolver));
 
-    // Enable ALPN for HTTP/2 and HTTP/1.1, and add ACME TLS-ALPN-01 protocol if ACME is enabled
-    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
-    if has_acme {
-        // TLS-ALPN-01 protocol identifier for ACME challenges
-        server_config.alpn_protocols.push(b"acme-tls/1".to_vec());
-    }
+    // Enable ALPN for the HTTP versions of the binding, and add the ACME TLS-ALPN-01 protocol. It is added even without
+    // ACME domains, so the staging smoke test can validate a domain before automatic TLS is enabled for it
+    server_config.alpn_protocols = binding.get_alpn_protocols();
+    server_config.alpn_protocols.push(b"acme-tls/1".to_vec());
+
+    let tls_settings = get_cached_configuration().get_configuration().await.core.tls_settings.clone();
+    apply_session_resumption(&mut server_config, &tls_settings);
 
     let tls_acceptor = TlsAcceptor::from(std::sync::Arc::new(server_config));
 
@@ -459,7 +483,7 @@ pub async fn build_unified_tls_acceptor(
 }
 
 // Build a TLS acceptor that selects certificates per-site using SNI
-pub async fn build_tls_acceptor(binding: &Binding) -> Result<TlsAcceptor, Box<dyn std::error::Error + Send + Sync>> {
+pub async fn build_tls_acceptor(binding: &Binding) -> Result<TlsAcceptor, GruxiError> {
     let provider = rustls::crypto::aws_lc_rs::default_provider();
 
     // Create SNI resolver
@@ -497,17 +521,17 @@ pub async fn build_tls_acceptor(binding: &Binding) -> Result<TlsAcceptor, Box<dy
 
         let (cert_chain, priv_key) = if site.tls_cert_path.len() > 0 && site.tls_key_path.len() > 0 {
             // Load from PEM files
-            let cert_file = std::fs::File::open(&site.tls_cert_path).map_err(|e| format!("Failed to open TLS cert file {}: {}", site.tls_cert_path, e))?;
-            let key_file = std::fs::File::open(&site.tls_key_path).map_err(|e| format!("Failed to open TLS key file {}: {}", site.tls_key_path, e))?;
+            let cert_file = std::fs::File::open(&site.tls_cert_path).map_err(|e| GruxiError::tls(TlsError::Certificate, format!("Failed to open TLS cert file {}: {}", site.tls_cert_path, e)))?;
+            let key_file = std::fs::File::open(&site.tls_key_path).map_err(|e| GruxiError::tls(TlsError::PrivateKey, format!("Failed to open TLS key file {}: {}", site.tls_key_path, e)))?;
 
             let mut cert_reader = BufReader::new(cert_file);
             let mut key_reader = BufReader::new(key_file);
 
             let certs: Result<Vec<CertificateDer<'static>>, _> = rustls_pemfile::certs(&mut cert_reader).collect();
-            let cert_chain = certs.map_err(|e| format!("Failed to parse TLS cert file {}: {}", site.tls_cert_path, e))?;
+            let cert_chain = certs.map_err(|e| GruxiError::tls(TlsError::Certificate, format!("Failed to parse TLS cert file {}: {}", site.tls_cert_path, e)))?;
 
-            let key_result = rustls_pemfile::private_key(&mut key_reader).map_err(|e| format!("Failed to parse TLS key file {}: {}", site.tls_key_path, e))?;
-            let priv_key = key_result.ok_or_else(|| format!("No private key found in {}", site.tls_key_path))?;
+            let key_result = rustls_pemfile::private_key(&mut key_reader).map_err(|e| GruxiError::tls(TlsError::PrivateKey, format!("Failed to parse TLS key file {}: {}", site.tls_key_path, e)))?;
+            let priv_key = key_result.ok_or_else(|| GruxiError::tls(TlsError::PrivateKey, format!("No private key found in {}", site.tls_key_path)))?;
 
             (cert_chain, priv_key)
         } else if site.tls_cert_content.len() > 0 && site.tls_key_content.len() > 0 {
@@ -516,27 +540,25 @@ pub async fn build_tls_acceptor(binding: &Binding) -> Result<TlsAcceptor, Box<dy
             let mut key_cursor = std::io::Cursor::new(site.tls_key_content.as_bytes());
 
             let certs: Result<Vec<CertificateDer<'static>>, _> = rustls_pemfile::certs(&mut cert_cursor).collect();
-            let cert_chain = certs.map_err(|e| format!("Failed to parse TLS cert PEM content: {}", e))?;
+            let cert_chain = certs.map_err(|e| GruxiError::tls(TlsError::Certificate, format!("Failed to parse TLS cert PEM content: {}", e)))?;
 
-            let key_result = rustls_pemfile::private_key(&mut key_cursor).map_err(|e| format!("Failed to parse TLS key PEM content: {}", e))?;
-            let priv_key = key_result.ok_or_else(|| "No private key found in PEM content".to_string())?;
+            let key_result = rustls_pemfile::private_key(&mut key_cursor).map_err(|e| GruxiError::tls(TlsError::PrivateKey, format!("Failed to parse TLS key PEM content: {}", e)))?;
+            let priv_key = key_result.ok_or_else(|| GruxiError::tls(TlsError::PrivateKey, "No private key found in PEM content"))?;
 
             (cert_chain, priv_key)
         } else {
-            // Generate self-signed cert with comprehensive SAN list
-            debug(format!("Generating self-signed certificate for site with hostnames: {:?}", sans));
-            let rcgen::CertifiedKey { cert, signing_key } = rcgen::generate_simple_self_signed(sans.clone()).map_err(|e| format!("Failed to generate self-signed cert: {}", e))?;
-            let cert_pem = cert.pem();
-            let key_pem = signing_key.serialize_pem();
+            // Generate a certificate with comprehensive SAN list, signed by the development CA when there is one
+            debug(format!("Generating certificate for site with hostnames: {:?}", sans));
+            let (cert_pem, key_pem) = generate_site_certificate(&sans).map_err(|e| GruxiError::tls(TlsError::Certificate, e))?;
 
             let mut cert_cursor = std::io::Cursor::new(cert_pem.as_bytes());
             let mut key_cursor = std::io::Cursor::new(key_pem.as_bytes());
 
             let certs: Result<Vec<CertificateDer<'static>>, _> = rustls_pemfile::certs(&mut cert_cursor).collect();
-            let cert_chain = certs.map_err(|e| format!("Failed to parse generated TLS cert PEM content: {}", e))?;
+            let cert_chain = certs.map_err(|e| GruxiError::tls(TlsError::Certificate, format!("Failed to parse generated TLS cert PEM content: {}", e)))?;
 
-            let key_result = rustls_pemfile::private_key(&mut key_cursor).map_err(|e| format!("Failed to parse generated TLS key PEM content: {}", e))?;
-            let priv_key = key_result.ok_or_else(|| "No private key found in generated PEM content".to_string())?;
+            let key_result = rustls_pemfile::private_key(&mut key_cursor).map_err(|e| GruxiError::tls(TlsError::PrivateKey, format!("Failed to parse generated TLS key PEM content: {}", e)))?;
+            let priv_key = key_result.ok_or_else(|| GruxiError::tls(TlsError::PrivateKey, "No private key found in generated PEM content"))?;
 
             // Persist generated cert/key to disk and update the site configuration
             match persist_generated_tls_for_site(site, &cert_pem, &key_pem, binding.is_admin).await {
@@ -557,7 +579,7 @@ pub async fn build_tls_acceptor(binding: &Binding) -> Result<TlsAcceptor, Box<dy
         }
 
         // Build a signing key and certified key for rustls
-        let signing_key = aws_lc_rs::sign::any_supported_type(&priv_key).map_err(|e| format!("Unsupported private key type for: {}", e))?;
+        let signing_key = aws_lc_rs::sign::any_supported_type(&priv_key).map_err(|e| GruxiError::tls(TlsError::PrivateKey, format!("Unsupported private key type for: {}", e)))?;
         let certified = RustlsCertifiedKey::new(cert_chain.clone(), signing_key);
         let certified_arc = std::sync::Arc::new(certified);
 
@@ -609,10 +631,10 @@ pub async fn build_tls_acceptor(binding: &Binding) -> Result<TlsAcceptor, Box<dy
     if !site_added {
         // As a last resort, generate a single default cert
         let rcgen::CertifiedKey { cert, signing_key } =
-            rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).map_err(|e| format!("Failed to generate fallback self-signed cert: {}", e))?;
+            rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).map_err(|e| GruxiError::tls(TlsError::Certificate, format!("Failed to generate fallback self-signed cert: {}", e)))?;
         let cert_der = CertificateDer::from(cert.der().to_vec());
-        let key_der = PrivateKeyDer::try_from(signing_key.serialize_der()).map_err(|e| format!("Invalid key DER: {}", e))?;
-        let signing_key = aws_lc_rs::sign::any_supported_type(&key_der).map_err(|e| format!("Unsupported private key type for rustls: {}", e))?;
+        let key_der = PrivateKeyDer::try_from(signing_key.serialize_der()).map_err(|e| GruxiError::tls(TlsError::PrivateKey, format!("Invalid key DER: {}", e)))?;
+        let signing_key = aws_lc_rs::sign::any_supported_type(&key_der).map_err(|e| GruxiError::tls(TlsError::PrivateKey, format!("Unsupported private key type for rustls: {}", e)))?;
         let certified = RustlsCertifiedKey::new(vec![cert_der], signing_key);
 
         let certified_arc = std::sync::Arc::new(certified);
@@ -631,7 +653,7 @@ pub async fn build_tls_acceptor(binding: &Binding) -> Result<TlsAcceptor, Box<dy
     }
 
     if !site_added {
-        return Err("No valid TLS certificates could be configured for this binding".into());
+        return Err(GruxiError::tls(TlsError::Certificate, "No valid TLS certificates could be configured for this binding"));
     }
 
     // Create a fallback certificate resolver that can handle cases where SNI doesn't match
@@ -642,12 +664,15 @@ pub async fn build_tls_acceptor(binding: &Binding) -> Result<TlsAcceptor, Box<dy
 
     let mut server_config = RustlsServerConfig::builder_with_provider(provider.into())
         .with_safe_default_protocol_versions()
-        .map_err(|_| "Protocol versions unavailable")?
+        .map_err(|_| GruxiError::tls(TlsError::Configuration, "Protocol versions unavailable"))?
         .with_no_client_auth()
         .with_cert_resolver(std::sync::Arc::new(fallback_resolver));
 
-    // Enable ALPN for HTTP/2 and HTTP/1.1 (prefer h2)
-    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    // Enable ALPN for the HTTP versions of the binding (prefer h2)
+    server_config.alpn_protocols = binding.get_alpn_protocols();
+
+    let tls_settings = get_cached_configuration().get_configuration().await.core.tls_settings.clone();
+    apply_session_resumption(&mut server_config, &tls_settings);
 
     Ok(TlsAcceptor::from(std::sync::Arc::new(server_config)))
 }
@@ -2,7 +2,8 @@ use std::sync::Arc;
 
 use http::HeaderValue;
 use http_body_util::{BodyExt, Full, combinators::BoxBody};
-use hyper::body::Bytes;
+use hyper::body::{Body, Bytes};
+use hyper::{Response, Version, header};
 
 use crate::core::running_state_manager::get_running_state_manager;
 use crate::file::file_reader_structs::FileEntry;
@@ -63,15 +64,203 @@ pub fn add_standard_headers_to_response(resp: &mut GruxiResponse) {
     }
 }
 
-pub fn get_list_of_hop_by_hop_headers(is_websocket_upgrade: bool) -> Vec<String> {
-    // Remove hop-by-hop headers as per RFC 2616 Section 13.5.1
-    let mut hop_by_hop_headers = vec!["Keep-Alive".to_string(), "Proxy-Authenticate".to_string(), "Proxy-Authorization".to_string(), "TE".to_string(), "Trailers".to_string(), "Transfer-Encoding".to_string(), "Content-Length".to_string()];
+// Hop-by-hop headers as per RFC 2616 Section 13.5.1. Connection and Upgrade are kept for websocket upgrades
+const HOP_BY_HOP_HEADERS: [&str; 9] = [
+    "Keep-Alive",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "TE",
+    "Trailers",
+    "Transfer-Encoding",
+    "Content-Length",
+    "Connection",
+    "Upgrade",
+];
 
-    if !is_websocket_upgrade {
-        // Also remove Connection and Upgrade headers if not a websocket upgrade
-        hop_by_hop_headers.push("Connection".to_string());
-        hop_by_hop_headers.push("Upgrade".to_string());
+pub fn get_list_of_hop_by_hop_headers(is_websocket_upgrade: bool) -> &'static [&'static str] {
+    if is_websocket_upgrade { &HOP_BY_HOP_HEADERS[..7] } else { &HOP_BY_HOP_HEADERS }
+}
+
+// Set the Connection and Keep-Alive headers of a HTTP/1.x response, which hyper then closes or keeps the connection by.
+// HTTP/1.0 connections are only kept alive when the client asked for it with "Connection: keep-alive", the response has a known
+// length, as it would otherwise be ended by closing the connection, and the binding is not in strict mode, which always closes them.
+// Kept-alive connections announce the idle timeout in a Keep-Alive header, when the binding has one, and the requests left on the
+// connection when the binding limits them. The response to the last permitted request closes the connection
+pub fn apply_connection_semantics<B: Body>(
+    response: &mut Response<B>,
+    request_version: Version,
+    request_connection: &str,
+    strict_http10: bool,
+    keep_alive_timeout_seconds: u32,
+    remaining_requests: Option<u32>,
+) {
+    // Upgraded connections are taken over by the upgrade
+    if response.status() == hyper::StatusCode::SWITCHING_PROTOCOLS {
+        return;
+    }
+    let has_connection_option = |option: &str| request_connection.split(',').any(|value| value.trim().eq_ignore_ascii_case(option));
+    let response_closes = response
+        .headers()
+        .get(header::CONNECTION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|value| value.trim().eq_ignore_ascii_case("close")));
+    let is_last_request = remaining_requests == Some(0);
+    if is_last_request && request_version == Version::HTTP_11 {
+        response.headers_mut().insert(header::CONNECTION, HeaderValue::from_static("close"));
+    }
+
+    let keep_alive = match request_version {
+        Version::HTTP_10 => {
+            let has_known_length = response.headers().contains_key(header::CONTENT_LENGTH) || response.body().size_hint().exact().is_some();
+            let keep_alive = !strict_http10 && has_connection_option("keep-alive") && !response_closes && !is_last_request && has_known_length;
+            let connection = if keep_alive { HeaderValue::from_static("keep-alive") } else { HeaderValue::from_static("close") };
+            response.headers_mut().insert(header::CONNECTION, connection);
+            keep_alive
+        }
+        Version::HTTP_11 => !has_connection_option("close") && !response_closes && !is_last_request,
+        _ => false,
+    };
+
+    if !keep_alive {
+        return;
+    }
+    let mut keep_alive_parameters = Vec::new();
+    if keep_alive_timeout_seconds > 0 {
+        keep_alive_parameters.push(format!("timeout={}", keep_alive_timeout_seconds));
+    }
+    if let Some(remaining_requests) = remaining_requests {
+        keep_alive_parameters.push(format!("max={}", remaining_requests));
+    }
+    if !keep_alive_parameters.is_empty()
+        && let Ok(value) = HeaderValue::from_str(&keep_alive_parameters.join(", "))
+    {
+        response.headers_mut().insert(header::HeaderName::from_static("keep-alive"), value);
+    }
+}
+
+/// The host of a "host:port" authority. IPv6 addresses keep their brackets, such as "[::1]" of "[::1]:8080"
+pub fn strip_port(authority: &str) -> &str {
+    if authority.starts_with('[') {
+        return match authority.find(']') {
+            Some(index) => &authority[..=index],
+            None => authority,
+        };
+    }
+    // More than one colon is an IPv6 address without brackets, which has no port
+    match authority.split_once(':') {
+        Some((host, port)) if !port.contains(':') => host,
+        _ => authority,
+    }
+}
+
+/// Whether the hostname of a request differs from the SNI name of its TLS connection. Names are compared without case and
+/// trailing dot, and requests without a hostname are left to the site matching
+pub fn is_sni_mismatch(server_name: &str, hostname: &str) -> bool {
+    if hostname.is_empty() {
+        return false;
+    }
+    !server_name.trim_end_matches('.').eq_ignore_ascii_case(hostname.trim_end_matches('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_port() {
+        assert_eq!(strip_port("example.com:8080"), "example.com");
+        assert_eq!(strip_port("example.com"), "example.com");
+        assert_eq!(strip_port("[::1]:8080"), "[::1]");
+        assert_eq!(strip_port("[2001:db8::1]"), "[2001:db8::1]");
+        assert_eq!(strip_port("2001:db8::1"), "2001:db8::1");
+    }
+
+    #[test]
+    fn test_is_sni_mismatch() {
+        assert!(!is_sni_mismatch("www.example.com", "WWW.example.com."));
+        assert!(!is_sni_mismatch("www.example.com", ""));
+        assert!(is_sni_mismatch("www.example.com", "admin.example.com"));
+    }
+
+    #[test]
+    fn test_clean_hop_by_hop_headers() {
+        use crate::http::request_response::gruxi_request::GruxiRequest;
+
+        let hyper_request = hyper::Request::builder()
+            .uri("/path")
+            .header("Host", "example.com")
+            .header("Connection", "keep-alive, X-Hop")
+            .header("Keep-Alive", "timeout=5")
+            .header("X-Hop", "1")
+            .header("X-End", "1")
+            .body(Bytes::new())
+            .unwrap();
+        let mut gruxi_request = GruxiRequest::new(hyper_request);
+        gruxi_request.clean_hop_by_hop_headers();
+
+        let headers = gruxi_request.get_headers();
+        assert!(headers.get("Connection").is_none());
+        assert!(headers.get("Keep-Alive").is_none());
+        assert!(headers.get("X-Hop").is_none());
+        assert_eq!(headers.get("X-End").unwrap(), "1");
+        assert_eq!(headers.get("Host").unwrap(), "example.com");
+
+        // Websocket upgrades keep the Connection and Upgrade headers
+        assert!(!get_list_of_hop_by_hop_headers(true).contains(&"Connection"));
+        assert!(get_list_of_hop_by_hop_headers(false).contains(&"Upgrade"));
     }
 
-    hop_by_hop_headers
+    #[test]
+    fn test_apply_connection_semantics() {
+        let response_with_length = || Response::new(Full::new(Bytes::from("Hello")));
+
+        // HTTP/1.0 clients are only kept alive when they ask for it
+        let mut response = response_with_length();
+        apply_connection_semantics(&mut response, Version::HTTP_10, "", false, 5, None);
+        assert_eq!(response.headers().get(header::CONNECTION).unwrap(), "close");
+        assert!(!response.headers().contains_key("keep-alive"));
+
+        let mut response = response_with_length();
+        apply_connection_semantics(&mut response, Version::HTTP_10, "Keep-Alive", false, 5, None);
+        assert_eq!(response.headers().get(header::CONNECTION).unwrap(), "keep-alive");
+        assert_eq!(response.headers().get("keep-alive").unwrap(), "timeout=5");
+
+        // Strict mode always closes them
+        let mut response = response_with_length();
+        apply_connection_semantics(&mut response, Version::HTTP_10, "keep-alive", true, 5, None);
+        assert_eq!(response.headers().get(header::CONNECTION).unwrap(), "close");
+
+        // A response without a known length is ended by closing the connection
+        let mut response = Response::new(http_body_util::StreamBody::new(futures::stream::empty::<Result<hyper::body::Frame<Bytes>, std::convert::Infallible>>()));
+        apply_connection_semantics(&mut response, Version::HTTP_10, "keep-alive", false, 5, None);
+        assert_eq!(response.headers().get(header::CONNECTION).unwrap(), "close");
+
+        // HTTP/1.1 connections only get the Keep-Alive header, unless they are closed
+        let mut response = response_with_length();
+        apply_connection_semantics(&mut response, Version::HTTP_11, "", true, 5, None);
+        assert!(!response.headers().contains_key(header::CONNECTION));
+        assert_eq!(response.headers().get("keep-alive").unwrap(), "timeout=5");
+
+        let mut response = response_with_length();
+        apply_connection_semantics(&mut response, Version::HTTP_11, "close", false, 5, None);
+        assert!(!response.headers().contains_key("keep-alive"));
+
+        let mut response = response_with_length();
+        apply_connection_semantics(&mut response, Version::HTTP_11, "", false, 0, None);
+        assert!(!response.headers().contains_key("keep-alive"));
+
+        // Limited connections announce the requests left, and the last permitted response closes them
+        let mut response = response_with_length();
+        apply_connection_semantics(&mut response, Version::HTTP_11, "", false, 5, Some(3));
+        assert_eq!(response.headers().get("keep-alive").unwrap(), "timeout=5, max=3");
+
+        let mut response = response_with_length();
+        apply_connection_semantics(&mut response, Version::HTTP_11, "", false, 5, Some(0));
+        assert_eq!(response.headers().get(header::CONNECTION).unwrap(), "close");
+        assert!(!response.headers().contains_key("keep-alive"));
+
+        let mut response = response_with_length();
+        apply_connection_semantics(&mut response, Version::HTTP_10, "keep-alive", false, 5, Some(0));
+        assert_eq!(response.headers().get(header::CONNECTION).unwrap(), "close");
+    }
 }
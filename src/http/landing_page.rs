@@ -0,0 +1,140 @@
+// ============================================================================
+// LANDING PAGE
+// ============================================================================
+//
+// The page answered for requests whose hostname matches none of the sites of
+// the binding, when no default site serves them. Instead of an empty 404 it
+// answers a branded page, or a small JSON document for clients preferring
+// JSON, optionally listing the hostnames of the enabled sites on the binding
+// so a visitor can find the site they were looking for.
+//
+// The status stays 404, so monitoring and crawlers see the hostname is not
+// served. Wildcard hostnames are not listed, as they cannot be linked to.
+// ============================================================================
+
+use std::sync::Arc;
+
+use hyper::header::HeaderValue;
+use serde::Serialize;
+
+use crate::configuration::binding::Binding;
+use crate::configuration::landing_page::LandingPageSettings;
+use crate::configuration::site::Site;
+use crate::http::error_response::wants_json_error;
+use crate::http::request_handlers::processors::server_side_includes::escape_html;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+
+#[derive(Debug, Serialize)]
+struct LandingPage {
+    status: u16,
+    title: String,
+    message: String,
+    sites: Vec<String>, // URLs of the sites on the binding
+}
+
+// URLs of the enabled sites on the binding, by their hostnames, with the port when it is not the default of the scheme
+fn get_site_urls(sites: &[Arc<Site>], binding: &Binding) -> Vec<String> {
+    let scheme = if binding.is_tls { "https" } else { "http" };
+    let port = match (binding.is_tls, crate::http::http_server::get_binding_port(binding)) {
+        (true, 443) | (false, 80) => String::new(),
+        (_, port) => format!(":{}", port),
+    };
+
+    let mut urls: Vec<String> = sites
+        .iter()
+        .filter(|site| site.is_enabled)
+        .flat_map(|site| site.hostnames.iter())
+        .filter(|hostname| !hostname.is_empty() && !hostname.contains('*'))
+        .map(|hostname| format!("{}://{}{}/", scheme, hostname.to_lowercase(), port))
+        .collect();
+    urls.sort();
+    urls.dedup();
+    urls
+}
+
+fn render_landing_page_html(landing_page: &LandingPage) -> String {
+    let title = escape_html(&landing_page.title);
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<meta name=\"robots\" content=\"noindex\">\n");
+    html.push_str(&format!("<title>{}</title>\n", title));
+    html.push_str(
+        "<style>body{font-family:system-ui,sans-serif;max-width:760px;margin:4em auto;padding:0 1em;color:#1f2328}\
+         ul{list-style:none;padding:0}li{padding:.5em 0;border-bottom:1px solid #d0d7de}a{color:#0969da;text-decoration:none}\
+         .muted{color:#6e7781;font-size:.9em}</style>\n</head>\n<body>\n",
+    );
+    html.push_str(&format!("<h1>{}</h1>\n", title));
+    if !landing_page.message.is_empty() {
+        html.push_str(&format!("<p>{}</p>\n", escape_html(&landing_page.message)));
+    }
+    if !landing_page.sites.is_empty() {
+        html.push_str("<h2>Sites on this address</h2>\n<ul>\n");
+        for url in &landing_page.sites {
+            let url = escape_html(url);
+            html.push_str(&format!("<li><a href=\"{}\">{}</a></li>\n", url, url));
+        }
+        html.push_str("</ul>\n");
+    }
+    html.push_str("<p class=\"muted\">Served by Gruxi</p>\n</body>\n</html>\n");
+    html
+}
+
+// The landing page for a request on the binding, as HTML or JSON by the settings and the Accept header
+pub fn get_landing_page_response(settings: &LandingPageSettings, sites: &[Arc<Site>], binding: &Binding, accept: &str) -> GruxiResponse {
+    let status = hyper::StatusCode::NOT_FOUND.as_u16();
+    let landing_page = LandingPage {
+        status,
+        title: settings.title.clone(),
+        message: settings.message.clone(),
+        sites: if settings.show_sites { get_site_urls(sites, binding) } else { Vec::new() },
+    };
+
+    let (body, content_type) = if wants_json_error(&settings.format, accept) {
+        (serde_json::to_string(&landing_page).unwrap_or_default(), "application/json")
+    } else {
+        (render_landing_page_html(&landing_page), "text/html; charset=utf-8")
+    };
+
+    let mut response = GruxiResponse::new_with_bytes(status, bytes::Bytes::from(body));
+    response.headers_mut().insert(hyper::header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    response.headers_mut().insert(hyper::header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_landing_page_lists_sites_of_binding() {
+        let mut binding = Binding::new();
+        binding.port = 8080;
+        let mut shop = Site::new();
+        shop.is_enabled = true;
+        shop.hostnames = vec!["Shop.example.com".to_string(), "*.example.com".to_string()];
+        let mut disabled = Site::new();
+        disabled.is_enabled = false;
+        disabled.hostnames = vec!["old.example.com".to_string()];
+        let sites = vec![Arc::new(shop), Arc::new(disabled)];
+
+        assert_eq!(get_site_urls(&sites, &binding), vec!["http://shop.example.com:8080/".to_string()]);
+
+        let mut settings = LandingPageSettings::new();
+        settings.title = "<Hosting>".to_string();
+        let landing_page = LandingPage {
+            status: 404,
+            title: settings.title.clone(),
+            message: settings.message.clone(),
+            sites: get_site_urls(&sites, &binding),
+        };
+        let html = render_landing_page_html(&landing_page);
+        assert!(html.contains("&lt;Hosting&gt;"));
+        assert!(html.contains("href=\"http://shop.example.com:8080/\""));
+        assert!(!html.contains("old.example.com"));
+
+        let response = get_landing_page_response(&settings, &sites, &binding, "application/json");
+        assert_eq!(response.get_status(), 404);
+        assert_eq!(response.headers().get(hyper::header::CONTENT_TYPE).unwrap(), "application/json");
+    }
+}
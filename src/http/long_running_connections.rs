@@ -0,0 +1,309 @@
+// ============================================================================
+// LONG-RUNNING CONNECTIONS
+// ============================================================================
+//
+// Keeps track of the connections that outlive their request: WebSocket
+// connections, other upgraded connections (tunnels) and streamed responses,
+// such as server-sent events. Monitoring shows their count, age and bytes per
+// site, and all of them can be closed for a site, such as before maintenance.
+// ============================================================================
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use dashmap::DashMap;
+use http_body_util::combinators::BoxBody;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+use crate::http::request_response::body_error::BodyError;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+use crate::logging::syslog::{debug, error, trace};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LongRunningConnectionKind {
+    WebSocket,
+    Tunnel, // Upgraded to another protocol than WebSocket
+    Stream, // Unbuffered response, such as server-sent events or long polling
+}
+
+struct ConnectionState {
+    site_id: String,
+    kind: LongRunningConnectionKind,
+    started_at: Instant,
+    bytes_from_client: AtomicU64,
+    bytes_to_client: AtomicU64,
+    close_token: CancellationToken,
+}
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+static LONG_RUNNING_CONNECTIONS: LazyLock<DashMap<u64, Arc<ConnectionState>>> = LazyLock::new(DashMap::new);
+
+// A long-running connection, shown in monitoring for as long as it is held
+pub struct LongRunningConnection {
+    id: u64,
+    state: Arc<ConnectionState>,
+}
+
+impl LongRunningConnection {
+    pub fn register(site_id: &str, kind: LongRunningConnectionKind) -> Self {
+        let id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed);
+        let state = Arc::new(ConnectionState {
+            site_id: site_id.to_string(),
+            kind,
+            started_at: Instant::now(),
+            bytes_from_client: AtomicU64::new(0),
+            bytes_to_client: AtomicU64::new(0),
+            close_token: CancellationToken::new(),
+        });
+        LONG_RUNNING_CONNECTIONS.insert(id, state.clone());
+        LongRunningConnection { id, state }
+    }
+
+    pub fn add_bytes_from_client(&self, bytes: u64) {
+        self.state.bytes_from_client.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn add_bytes_to_client(&self, bytes: u64) {
+        self.state.bytes_to_client.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    // Cancelled when an admin closes the long-running connections of the site
+    pub fn get_close_token(&self) -> CancellationToken {
+        self.state.close_token.clone()
+    }
+}
+
+impl Drop for LongRunningConnection {
+    fn drop(&mut self) {
+        LONG_RUNNING_CONNECTIONS.remove(&self.id);
+    }
+}
+
+// Close all long-running connections of a site, returning how many there were
+pub fn close_site_connections(site_id: &str) -> usize {
+    let mut closed = 0;
+    for entry in LONG_RUNNING_CONNECTIONS.iter() {
+        if entry.site_id == site_id && !entry.close_token.is_cancelled() {
+            entry.close_token.cancel();
+            closed += 1;
+        }
+    }
+    closed
+}
+
+// Connections by how long they have been open
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct ConnectionAges {
+    pub under_1m: u64,
+    pub under_10m: u64,
+    pub under_1h: u64,
+    pub over_1h: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ConnectionSummary {
+    pub count: u64,
+    pub websocket: u64,
+    pub tunnel: u64,
+    pub stream: u64,
+    pub ages: ConnectionAges,
+    pub oldest_seconds: u64,
+    pub bytes_from_client: u64,
+    pub bytes_to_client: u64,
+}
+
+impl ConnectionSummary {
+    fn add(&mut self, state: &ConnectionState) {
+        let age_seconds = state.started_at.elapsed().as_secs();
+        self.count += 1;
+        match state.kind {
+            LongRunningConnectionKind::WebSocket => self.websocket += 1,
+            LongRunningConnectionKind::Tunnel => self.tunnel += 1,
+            LongRunningConnectionKind::Stream => self.stream += 1,
+        }
+        match age_seconds {
+            0..60 => self.ages.under_1m += 1,
+            60..600 => self.ages.under_10m += 1,
+            600..3600 => self.ages.under_1h += 1,
+            _ => self.ages.over_1h += 1,
+        }
+        self.oldest_seconds = self.oldest_seconds.max(age_seconds);
+        self.bytes_from_client += state.bytes_from_client.load(Ordering::Relaxed);
+        self.bytes_to_client += state.bytes_to_client.load(Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct LongRunningConnectionsSummary {
+    pub total: ConnectionSummary,
+    pub sites: BTreeMap<String, ConnectionSummary>,
+}
+
+pub fn get_long_running_connections_summary() -> LongRunningConnectionsSummary {
+    let mut summary = LongRunningConnectionsSummary::default();
+    for entry in LONG_RUNNING_CONNECTIONS.iter() {
+        summary.total.add(entry.value());
+        summary.sites.entry(entry.site_id.clone()).or_default().add(entry.value());
+    }
+    summary
+}
+
+// Upgraded connection that counts the bytes from and to the client, and is registered for as long as it is open
+pub struct CountedClientIo<T> {
+    inner: T,
+    connection: LongRunningConnection,
+}
+
+impl<T> CountedClientIo<T> {
+    pub fn new(inner: T, connection: LongRunningConnection) -> Self {
+        CountedClientIo { inner, connection }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for CountedClientIo<T> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let filled_before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = &poll {
+            self.connection.add_bytes_from_client((buf.filled().len() - filled_before) as u64);
+        }
+        poll
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for CountedClientIo<T> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = &poll {
+            self.connection.add_bytes_to_client(*written as u64);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+// Bridge an upgraded connection of another protocol than WebSocket, until either side closes it or an admin closes it for the site
+pub async fn relay_tunnel<C, U>(client: C, mut upstream: U, site_id: &str)
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    U: AsyncRead + AsyncWrite + Unpin,
+{
+    let connection = LongRunningConnection::register(site_id, LongRunningConnectionKind::Tunnel);
+    let close_token = connection.get_close_token();
+    let mut client = CountedClientIo::new(client, connection);
+
+    tokio::select! {
+        result = tokio::io::copy_bidirectional(&mut client, &mut upstream) => match result {
+            Ok((from_client, from_server)) => {
+                trace(format!("Upgraded connection closed. Client→Server: {} bytes, Server→Client: {} bytes", from_client, from_server));
+            }
+            Err(e) => {
+                error(format!("Upgraded connection proxy error: {}", e));
+            }
+        },
+        _ = close_token.cancelled() => {
+            debug(format!("Upgraded connection of site '{}' closed by an admin", site_id));
+        }
+    }
+}
+
+// Register an unbuffered streaming response for the site, so it shows in monitoring and can be closed.
+// Buffered responses are done when they are sent, and are left as is
+pub fn track_streaming_response(response: &mut GruxiResponse, site_id: &str) {
+    if !response.is_unbuffered() || response.get_status() == hyper::StatusCode::SWITCHING_PROTOCOLS.as_u16() {
+        return;
+    }
+
+    response.map_streaming_body(|body| {
+        let connection = LongRunningConnection::register(site_id, LongRunningConnectionKind::Stream);
+        let closed = Box::pin(connection.get_close_token().cancelled_owned());
+        BoxBody::new(TrackedStreamBody { inner: body, connection, closed })
+    });
+}
+
+// Response body that counts the bytes sent, and ends the stream when an admin closes it
+struct TrackedStreamBody {
+    inner: BoxBody<Bytes, BodyError>,
+    connection: LongRunningConnection,
+    closed: Pin<Box<WaitForCancellationFutureOwned>>,
+}
+
+impl Body for TrackedStreamBody {
+    type Data = Bytes;
+    type Error = BodyError;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if self.closed.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(None);
+        }
+
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll
+            && let Some(data) = frame.data_ref()
+        {
+            self.connection.add_bytes_to_client(data.len() as u64);
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_long_running_connections_summary_and_close() {
+        let site_id = "long-running-connections-test";
+        let websocket = LongRunningConnection::register(site_id, LongRunningConnectionKind::WebSocket);
+        let stream = LongRunningConnection::register(site_id, LongRunningConnectionKind::Stream);
+        websocket.add_bytes_from_client(10);
+        websocket.add_bytes_to_client(20);
+        stream.add_bytes_to_client(5);
+
+        let summary = get_long_running_connections_summary();
+        let site = summary.sites.get(site_id).unwrap();
+        assert_eq!((site.count, site.websocket, site.tunnel, site.stream), (2, 1, 0, 1));
+        assert_eq!((site.bytes_from_client, site.bytes_to_client), (10, 25));
+        assert_eq!(
+            site.ages,
+            ConnectionAges {
+                under_1m: 2,
+                ..ConnectionAges::default()
+            }
+        );
+
+        assert_eq!(close_site_connections("some-other-site"), 0);
+        assert!(!websocket.get_close_token().is_cancelled());
+        assert_eq!(close_site_connections(site_id), 2);
+        assert!(websocket.get_close_token().is_cancelled() && stream.get_close_token().is_cancelled());
+        assert_eq!(close_site_connections(site_id), 0);
+
+        drop(websocket);
+        drop(stream);
+        assert!(!get_long_running_connections_summary().sites.contains_key(site_id));
+    }
+}
@@ -0,0 +1,495 @@
+// ============================================================================
+// LUA HOOKS
+// ============================================================================
+//
+// Runs the Lua hooks of a site, in the "lua" middleware, at the phases known
+// from OpenResty:
+//
+//   rewrite        before the request handlers, to change the request
+//   access         after rewrite, to let the request through or answer it
+//   header_filter  on the response status and headers
+//   body_filter    on the response body, when it is buffered
+//   log            last, to read the request and its response
+//
+// Each run gets a new Lua state with only the table, string, math and utf8
+// libraries, so scripts have no access to files, processes or the network,
+// and with the instruction and memory limits of the hook. Scripts use the
+// "gruxi" table:
+//
+//   gruxi.phase                         the phase the script runs in
+//   gruxi.log(message)                  writes to the server log
+//   gruxi.exit(status[, body])          answers the request (rewrite, access)
+//   gruxi.req.get_method()
+//   gruxi.req.get_path()
+//   gruxi.req.set_path(path)            rewrite, access
+//   gruxi.req.get_query()
+//   gruxi.req.get_remote_ip()
+//   gruxi.req.get_header(name)          nil when not sent
+//   gruxi.req.get_headers()             table of header names and values
+//   gruxi.req.set_header(name, value)   rewrite, access
+//   gruxi.req.clear_header(name)        rewrite, access
+//   gruxi.resp.get_status()             response phases
+//   gruxi.resp.set_status(status)       header_filter
+//   gruxi.resp.get_header(name)         response phases
+//   gruxi.resp.set_header(name, value)  header_filter
+//   gruxi.resp.clear_header(name)       header_filter
+//   gruxi.resp.get_body()               body_filter, nil for streamed bodies
+//   gruxi.resp.set_body(body)           body_filter
+//
+// A rewritten path is matched to a location again, and validated and
+// authenticated for it, once the hooks of the request phases have run.
+// A failing script in the rewrite or access phase answers the request with
+// 500. In the response phases, failures are logged and the response is sent
+// as the scripts left it.
+// ============================================================================
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::LazyLock;
+use std::time::SystemTime;
+
+use dashmap::DashMap;
+use hyper::HeaderMap;
+use hyper::body::Bytes;
+use hyper::header::{HeaderName, HeaderValue};
+use mlua::{HookTriggers, Lua, LuaOptions, StdLib, Table};
+
+use crate::configuration::lua_hook::LuaHook;
+use crate::http::middleware::middleware_chain::MiddlewareContext;
+use crate::http::request_response::gruxi_body::GruxiBody;
+use crate::http::request_response::gruxi_request::GruxiRequest;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+use crate::http::uri_normalization::is_canonical_path;
+use crate::logging::syslog::{error, info, trace};
+
+// Instructions are counted in steps of this many, so the limit is checked without slowing scripts down
+const INSTRUCTION_STEP: u32 = 1000;
+
+// Scripts loaded from files, by path, with the modification time of the file
+static SCRIPTS: LazyLock<DashMap<String, (SystemTime, String)>> = LazyLock::new(DashMap::new);
+
+// What scripts read and change, copied from the request and response before the scripts of a phase run, and applied after
+#[derive(Default)]
+struct ScriptState {
+    phase: &'static str,
+    method: String,
+    path: String,
+    query: String,
+    remote_ip: String,
+    request_headers: HeaderMap,
+    // Header changes, in order, with None for a cleared header
+    request_header_changes: Vec<(HeaderName, Option<HeaderValue>)>,
+    is_path_changed: bool,
+    status: u16,
+    response_headers: HeaderMap,
+    response_header_changes: Vec<(HeaderName, Option<HeaderValue>)>,
+    response_body: Option<Bytes>,
+    is_response_body_changed: bool,
+    exit: Option<(u16, String)>,
+}
+
+impl ScriptState {
+    fn new(gruxi_request: &mut GruxiRequest) -> Self {
+        Self {
+            method: gruxi_request.get_http_method(),
+            path: gruxi_request.get_path(),
+            query: gruxi_request.get_query(),
+            remote_ip: gruxi_request.get_remote_ip(),
+            request_headers: gruxi_request.get_headers().clone(),
+            ..Default::default()
+        }
+    }
+}
+
+fn lua_error(message: String) -> mlua::Error {
+    mlua::Error::RuntimeError(message)
+}
+
+// Fail the calling script when the function is not available in the current phase
+fn check_phase(state: &Rc<RefCell<ScriptState>>, function_name: &str, phases: &[&str]) -> mlua::Result<()> {
+    let phase = state.borrow().phase;
+    if phases.contains(&phase) {
+        return Ok(());
+    }
+    Err(lua_error(format!("{} is only available in the {} phases, not in {}", function_name, phases.join(" and "), phase)))
+}
+
+fn parse_header(name: &str, value: Option<&str>) -> mlua::Result<(HeaderName, Option<HeaderValue>)> {
+    let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| lua_error(format!("Invalid header name '{}': {}", name, e)))?;
+    let value = match value {
+        Some(value) => Some(HeaderValue::from_str(value).map_err(|e| lua_error(format!("Invalid value for header '{}': {}", name, e)))?),
+        None => None,
+    };
+    Ok((name, value))
+}
+
+fn apply_header_change(headers: &mut HeaderMap, name: &HeaderName, value: &Option<HeaderValue>) {
+    match value {
+        Some(value) => {
+            headers.insert(name.clone(), value.clone());
+        }
+        None => {
+            headers.remove(name);
+        }
+    }
+}
+
+fn get_header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers.get(name).map(|value| String::from_utf8_lossy(value.as_bytes()).to_string())
+}
+
+// The "gruxi" table of a script
+fn create_api<'lua>(lua: &'lua Lua, state: &Rc<RefCell<ScriptState>>) -> mlua::Result<Table<'lua>> {
+    let api = lua.create_table()?;
+    api.set("phase", state.borrow().phase)?;
+    api.set(
+        "log",
+        lua.create_function(|_, message: String| {
+            info(format!("Lua hook: {}", message));
+            Ok(())
+        })?,
+    )?;
+    let exit_state = state.clone();
+    api.set(
+        "exit",
+        lua.create_function(move |_, (status, body): (u16, Option<String>)| {
+            check_phase(&exit_state, "gruxi.exit", &["rewrite", "access"])?;
+            exit_state.borrow_mut().exit = Some((status, body.unwrap_or_default()));
+            // Stops the script, which is not an error once the exit is recorded
+            Err::<(), _>(lua_error("gruxi.exit".to_string()))
+        })?,
+    )?;
+
+    let req = lua.create_table()?;
+    let s = state.clone();
+    req.set("get_method", lua.create_function(move |_, ()| Ok(s.borrow().method.clone()))?)?;
+    let s = state.clone();
+    req.set("get_path", lua.create_function(move |_, ()| Ok(s.borrow().path.clone()))?)?;
+    let s = state.clone();
+    req.set(
+        "set_path",
+        lua.create_function(move |_, path: String| {
+            check_phase(&s, "gruxi.req.set_path", &["rewrite", "access"])?;
+            let mut state = s.borrow_mut();
+            state.path = path;
+            state.is_path_changed = true;
+            Ok(())
+        })?,
+    )?;
+    let s = state.clone();
+    req.set("get_query", lua.create_function(move |_, ()| Ok(s.borrow().query.clone()))?)?;
+    let s = state.clone();
+    req.set("get_remote_ip", lua.create_function(move |_, ()| Ok(s.borrow().remote_ip.clone()))?)?;
+    let s = state.clone();
+    req.set("get_header", lua.create_function(move |_, name: String| Ok(get_header_value(&s.borrow().request_headers, &name)))?)?;
+    let s = state.clone();
+    req.set(
+        "get_headers",
+        lua.create_function(move |lua, ()| {
+            let headers = lua.create_table()?;
+            for name in s.borrow().request_headers.keys() {
+                headers.set(name.as_str(), get_header_value(&s.borrow().request_headers, name.as_str()))?;
+            }
+            Ok(headers)
+        })?,
+    )?;
+    for (function_name, is_clear) in [("set_header", false), ("clear_header", true)] {
+        let s = state.clone();
+        req.set(
+            function_name,
+            lua.create_function(move |_, (name, value): (String, Option<String>)| {
+                check_phase(&s, &format!("gruxi.req.{}", function_name), &["rewrite", "access"])?;
+                let (name, value) = parse_header(&name, if is_clear { None } else { Some(value.as_deref().unwrap_or("")) })?;
+                let mut state = s.borrow_mut();
+                apply_header_change(&mut state.request_headers, &name, &value);
+                state.request_header_changes.push((name, value));
+                Ok(())
+            })?,
+        )?;
+    }
+    api.set("req", req)?;
+
+    let resp = lua.create_table()?;
+    let response_phases = ["header_filter", "body_filter", "log"];
+    let s = state.clone();
+    resp.set(
+        "get_status",
+        lua.create_function(move |_, ()| {
+            check_phase(&s, "gruxi.resp.get_status", &response_phases)?;
+            Ok(s.borrow().status)
+        })?,
+    )?;
+    let s = state.clone();
+    resp.set(
+        "set_status",
+        lua.create_function(move |_, status: u16| {
+            check_phase(&s, "gruxi.resp.set_status", &["header_filter"])?;
+            if !(100..=999).contains(&status) {
+                return Err(lua_error(format!("Invalid status {}", status)));
+            }
+            s.borrow_mut().status = status;
+            Ok(())
+        })?,
+    )?;
+    let s = state.clone();
+    resp.set(
+        "get_header",
+        lua.create_function(move |_, name: String| {
+            check_phase(&s, "gruxi.resp.get_header", &response_phases)?;
+            Ok(get_header_value(&s.borrow().response_headers, &name))
+        })?,
+    )?;
+    for (function_name, is_clear) in [("set_header", false), ("clear_header", true)] {
+        let s = state.clone();
+        resp.set(
+            function_name,
+            lua.create_function(move |_, (name, value): (String, Option<String>)| {
+                check_phase(&s, &format!("gruxi.resp.{}", function_name), &["header_filter"])?;
+                let (name, value) = parse_header(&name, if is_clear { None } else { Some(value.as_deref().unwrap_or("")) })?;
+                let mut state = s.borrow_mut();
+                apply_header_change(&mut state.response_headers, &name, &value);
+                state.response_header_changes.push((name, value));
+                Ok(())
+            })?,
+        )?;
+    }
+    let s = state.clone();
+    resp.set(
+        "get_body",
+        lua.create_function(move |lua, ()| {
+            check_phase(&s, "gruxi.resp.get_body", &["body_filter"])?;
+            match &s.borrow().response_body {
+                Some(body) => Ok(Some(lua.create_string(body)?)),
+                None => Ok(None),
+            }
+        })?,
+    )?;
+    let s = state.clone();
+    resp.set(
+        "set_body",
+        lua.create_function(move |_, body: mlua::String| {
+            check_phase(&s, "gruxi.resp.set_body", &["body_filter"])?;
+            let mut state = s.borrow_mut();
+            state.response_body = Some(Bytes::copy_from_slice(body.as_bytes()));
+            state.is_response_body_changed = true;
+            Ok(())
+        })?,
+    )?;
+    api.set("resp", resp)?;
+
+    Ok(api)
+}
+
+// The Lua source of a hook, read again when its file has changed
+fn get_script_source(hook: &LuaHook) -> Result<String, String> {
+    if hook.script_path.is_empty() {
+        return Ok(hook.script.clone());
+    }
+
+    let path = &hook.script_path;
+    let modified = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| format!("Failed to read Lua script '{}': {}", path, e))?;
+    if let Some(entry) = SCRIPTS.get(path)
+        && entry.0 == modified
+    {
+        return Ok(entry.1.clone());
+    }
+    let source = std::fs::read_to_string(path).map_err(|e| format!("Failed to read Lua script '{}': {}", path, e))?;
+    SCRIPTS.insert(path.to_string(), (modified, source.clone()));
+    Ok(source)
+}
+
+// Run a hook in a new Lua state with the limits of the hook
+fn run_hook(hook: &LuaHook, state: &Rc<RefCell<ScriptState>>) -> Result<(), String> {
+    let source = get_script_source(hook)?;
+    let lua = Lua::new_with(StdLib::TABLE | StdLib::STRING | StdLib::MATH | StdLib::UTF8, LuaOptions::default()).map_err(|e| e.to_string())?;
+    lua.set_memory_limit(hook.memory_limit_mb as usize * 1024 * 1024).map_err(|e| e.to_string())?;
+
+    let instruction_limit = hook.instruction_limit;
+    let instructions = std::cell::Cell::new(0u64);
+    lua.set_hook(HookTriggers::new().every_nth_instruction(INSTRUCTION_STEP), move |_, _| {
+        instructions.set(instructions.get() + INSTRUCTION_STEP as u64);
+        if instructions.get() > instruction_limit {
+            return Err(lua_error(format!("Instruction limit of {} reached", instruction_limit)));
+        }
+        Ok(())
+    });
+
+    let globals = lua.globals();
+    let set_globals = || -> mlua::Result<()> {
+        // The base library can read files with these
+        globals.set("dofile", mlua::Nil)?;
+        globals.set("loadfile", mlua::Nil)?;
+        globals.set("gruxi", create_api(&lua, state)?)
+    };
+    set_globals().map_err(|e| e.to_string())?;
+
+    match lua.load(source.as_str()).set_name(hook.name.as_str()).exec() {
+        Ok(()) => Ok(()),
+        Err(_) if state.borrow().exit.is_some() => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Run the enabled hooks of a phase, in order. Stops at the first failing hook or the first to exit
+fn run_phase(hooks: &[LuaHook], phase: &'static str, state: &Rc<RefCell<ScriptState>>) -> Result<(), String> {
+    state.borrow_mut().phase = phase;
+    for hook in hooks.iter().filter(|hook| hook.is_enabled && hook.phase == phase) {
+        trace(format!("Running Lua hook '{}' in phase {}", hook.name, phase));
+        run_hook(hook, state).map_err(|e| format!("Lua hook '{}' failed: {}", hook.name, e))?;
+        if state.borrow().exit.is_some() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn has_hooks(hooks: &[LuaHook], phases: &[&str]) -> bool {
+    hooks.iter().any(|hook| hook.is_enabled && phases.contains(&hook.phase.as_str()))
+}
+
+// The request phase of the "lua" middleware, running the rewrite and then the access hooks of the site
+pub fn run_request_hooks(context: &MiddlewareContext<'_>, gruxi_request: &mut GruxiRequest) -> Option<GruxiResponse> {
+    let hooks = &context.get_site().lua_hooks;
+    if !has_hooks(hooks, &["rewrite", "access"]) {
+        return None;
+    }
+
+    let state = Rc::new(RefCell::new(ScriptState::new(gruxi_request)));
+    let mut result = run_phase(hooks, "rewrite", &state);
+    if result.is_ok() && state.borrow().exit.is_none() {
+        result = run_phase(hooks, "access", &state);
+    }
+    let state = state.take();
+
+    if let Some((status, body)) = state.exit {
+        let status = if (100..=999).contains(&status) {
+            status
+        } else {
+            hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16()
+        };
+        trace(format!("Lua hook answered request for path {} with status {}", gruxi_request.get_path_str(), status));
+        return Some(GruxiResponse::new_with_bytes(status, body));
+    }
+
+    for (name, value) in &state.request_header_changes {
+        match value {
+            Some(value) => gruxi_request.set_header(name.as_str(), value.to_str().unwrap_or("")),
+            None => gruxi_request.remove_header(name.as_str()),
+        }
+    }
+    // The rewritten path has to be canonical, as the path checks before the hooks were made on the canonical path
+    if result.is_ok() && state.is_path_changed {
+        result = if is_canonical_path(&state.path) {
+            gruxi_request.set_path(&state.path)
+        } else {
+            Err(format!("Rewritten path '{}' is not canonical", state.path))
+        };
+    }
+
+    if let Err(e) = result {
+        error(e);
+        return Some(GruxiResponse::new_empty_with_status(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16()));
+    }
+    None
+}
+
+// The response phase of the "lua" middleware, running the header filter, body filter and log hooks of the site
+pub fn run_response_hooks(context: &MiddlewareContext<'_>, gruxi_request: &mut GruxiRequest, response: &mut GruxiResponse) {
+    let hooks = &context.get_site().lua_hooks;
+    if !has_hooks(hooks, &["header_filter", "body_filter", "log"]) {
+        return;
+    }
+
+    let mut state = ScriptState::new(gruxi_request);
+    state.status = response.get_status();
+    state.response_headers = response.headers().clone();
+    state.response_body = response.get_buffered_body().cloned();
+    let state = Rc::new(RefCell::new(state));
+
+    for phase in ["header_filter", "body_filter", "log"] {
+        if let Err(e) = run_phase(hooks, phase, &state) {
+            error(e);
+        }
+
+        let mut state = state.borrow_mut();
+        for (name, value) in state.response_header_changes.drain(..) {
+            apply_header_change(response.headers_mut(), &name, &value);
+        }
+        if state.status != response.get_status() {
+            response.set_status(state.status);
+        }
+        if state.is_response_body_changed {
+            state.is_response_body_changed = false;
+            response.set_body(GruxiBody::Buffered(state.response_body.clone().unwrap_or_default()));
+            response.headers_mut().remove(hyper::header::CONTENT_LENGTH);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(hook: &LuaHook, phase: &'static str, state: ScriptState) -> (Result<(), String>, ScriptState) {
+        let state = Rc::new(RefCell::new(state));
+        let result = run_phase(std::slice::from_ref(hook), phase, &state);
+        (result, state.take())
+    }
+
+    #[test]
+    fn test_request_hooks() {
+        let hook = LuaHook::new(
+            "rewrite",
+            "rewrite",
+            r#"
+                if gruxi.req.get_path() == "/old" then gruxi.req.set_path("/new") end
+                gruxi.req.set_header("X-Script", gruxi.req.get_method())
+                if gruxi.req.get_header("x-block") then gruxi.exit(403, "blocked") end
+                gruxi.req.clear_header("x-ignored")
+            "#,
+        );
+        let mut state = ScriptState {
+            method: "GET".to_string(),
+            path: "/old".to_string(),
+            ..Default::default()
+        };
+        state.request_headers.insert("x-ignored", HeaderValue::from_static("1"));
+        let (result, state) = run(&hook, "rewrite", state);
+        assert!(result.is_ok());
+        assert!(state.is_path_changed);
+        assert_eq!(state.path, "/new");
+        assert_eq!(state.request_header_changes.len(), 2);
+        assert!(state.request_headers.get("x-ignored").is_none());
+        assert!(state.exit.is_none());
+
+        let mut state = ScriptState::default();
+        state.request_headers.insert("x-block", HeaderValue::from_static("1"));
+        let (result, state) = run(&hook, "rewrite", state);
+        assert!(result.is_ok());
+        assert_eq!(state.exit, Some((403, "blocked".to_string())));
+    }
+
+    #[test]
+    fn test_response_hooks_and_sandbox() {
+        let hook = LuaHook::new("filter", "body_filter", r#"gruxi.resp.set_body(string.upper(gruxi.resp.get_body()))"#);
+        let state = ScriptState {
+            response_body: Some(Bytes::from_static(b"hello")),
+            ..Default::default()
+        };
+        let (result, state) = run(&hook, "body_filter", state);
+        assert!(result.is_ok());
+        assert_eq!(state.response_body, Some(Bytes::from_static(b"HELLO")));
+
+        // Changing the request after the request handlers is refused
+        let hook = LuaHook::new("late", "log", r#"gruxi.req.set_path("/other")"#);
+        assert!(run(&hook, "log", ScriptState::default()).0.is_err());
+
+        // No access to files or processes, and loops are stopped
+        for script in ["io.open('/etc/passwd')", "os.execute('true')", "dofile('/etc/passwd')", "while true do end"] {
+            let hook = LuaHook::new("sandbox", "access", script);
+            assert!(run(&hook, "access", ScriptState::default()).0.is_err(), "{}", script);
+        }
+    }
+}
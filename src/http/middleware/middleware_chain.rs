@@ -0,0 +1,263 @@
+// ============================================================================
+// MIDDLEWARE CHAIN
+// ============================================================================
+//
+// The steps a request for a site goes through around its request handlers,
+// such as request validation, the firewall, authentication, compression and
+// access logging. Each step is a middleware, and a site lists the middleware
+// it uses in the order it wants them, or keeps the default chain:
+//
+//   validation, methods, bots, waf, auth,
+//   bandwidth, accounting, access_log, headers, download_slots, compression,
+//   plugins, lua
+//
+// Each middleware sees the request in the order of the chain, and the response
+// in the reverse order, so the first middleware is the outermost. A middleware
+// can answer the request itself, such as the firewall blocking it, in which
+// case the middleware after it and the request handlers never see it, and the
+// response goes back through the middleware before it only. With the default
+// chain, responses of the request phase are sent as they are, while moving
+// "access_log" to the front logs them too.
+//
+// The "plugins" and "lua" middleware can rewrite the path. The location is
+// then matched again for the new path, and the required middleware,
+// "validation" and "auth", see the request again before the chain goes on.
+//
+// New cross-cutting features are added as a middleware here, with a name
+// sites can put in their chain, instead of in the request handling itself.
+// ============================================================================
+
+use std::sync::Arc;
+
+use crate::configuration::binding::Binding;
+use crate::configuration::location::Location;
+use crate::configuration::site::Site;
+use crate::core::running_state::RunningState;
+use crate::http::middleware::{request_middleware, response_middleware};
+use crate::http::request_response::gruxi_request::GruxiRequest;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+use crate::http::{bot_policy, lua_hooks, waf, wasm_plugins};
+use crate::logging::syslog::trace;
+
+// The middleware sites can put in their chain
+pub const MIDDLEWARE_NAMES: [&str; 13] = [
+    "validation",     // Rejects malformed, unsupported and too large requests
+    "methods",        // Answers TRACE, OPTIONS * and Expect: 100-continue
+    "bots",           // The bot rules and generated robots.txt of the site
+    "waf",            // The web application firewall of the site
+    "auth",           // The authentication of the locations of the site
+    "bandwidth",      // Paces response bodies by the bandwidth limits of the site
+    "accounting",     // Counts requests and bytes for usage reports, and tracks streamed responses
+    "access_log",     // Writes the access log of the site
+    "headers",        // Adds the extra headers and location Cache-Control of the site
+    "download_slots", // Limits the large downloads of the site sent at once
+    "compression",    // Compresses responses
+    "plugins",        // The WebAssembly plugins of the site
+    "lua",            // The Lua hooks of the site
+];
+
+// The chain of sites without one of their own, in the order requests were always handled
+pub const DEFAULT_MIDDLEWARE: [&str; 13] = [
+    "validation",
+    "methods",
+    "bots",
+    "waf",
+    "auth",
+    "bandwidth",
+    "accounting",
+    "access_log",
+    "headers",
+    "download_slots",
+    "compression",
+    "plugins",
+    "lua",
+];
+
+// Middleware a chain cannot leave out, as the server relies on them or leaving them out would open up protected locations
+pub const REQUIRED_MIDDLEWARE: [&str; 2] = ["validation", "auth"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Middleware {
+    Validation,
+    Methods,
+    Bots,
+    Waf,
+    Auth,
+    Bandwidth,
+    Accounting,
+    AccessLog,
+    Headers,
+    DownloadSlots,
+    Compression,
+    Plugins,
+    Lua,
+}
+
+impl Middleware {
+    pub fn from_name(name: &str) -> Option<Middleware> {
+        match name {
+            "validation" => Some(Middleware::Validation),
+            "methods" => Some(Middleware::Methods),
+            "bots" => Some(Middleware::Bots),
+            "waf" => Some(Middleware::Waf),
+            "auth" => Some(Middleware::Auth),
+            "bandwidth" => Some(Middleware::Bandwidth),
+            "accounting" => Some(Middleware::Accounting),
+            "access_log" => Some(Middleware::AccessLog),
+            "headers" => Some(Middleware::Headers),
+            "download_slots" => Some(Middleware::DownloadSlots),
+            "compression" => Some(Middleware::Compression),
+            "plugins" => Some(Middleware::Plugins),
+            "lua" => Some(Middleware::Lua),
+            _ => None,
+        }
+    }
+
+    // Whether every chain contains the middleware
+    pub fn is_required(&self) -> bool {
+        REQUIRED_MIDDLEWARE.iter().any(|name| Middleware::from_name(name) == Some(*self))
+    }
+
+    // Whether the middleware can rewrite the path of a request, after which the request is checked again for its new location
+    pub fn can_rewrite_path(&self) -> bool {
+        matches!(self, Middleware::Plugins | Middleware::Lua)
+    }
+
+    // Let the middleware see the request. Returns the response to answer with, when it answers the request itself
+    pub async fn handle_request(&self, context: &MiddlewareContext<'_>, gruxi_request: &mut GruxiRequest) -> Option<GruxiResponse> {
+        match self {
+            Middleware::Validation => request_middleware::validate(gruxi_request).await,
+            Middleware::Methods => request_middleware::answer_special_methods(context, gruxi_request).await,
+            Middleware::Bots => bot_policy::check_request(gruxi_request, context.get_site()).await,
+            Middleware::Waf => waf::check_request(gruxi_request, context.get_site()).await,
+            Middleware::Auth => request_middleware::authenticate(context, gruxi_request).await,
+            Middleware::Plugins => wasm_plugins::run_request_plugins(context, gruxi_request),
+            Middleware::Lua => lua_hooks::run_request_hooks(context, gruxi_request),
+            _ => None,
+        }
+    }
+
+    // Let the middleware see the response, on its way back to the client
+    pub async fn handle_response(&self, context: &MiddlewareContext<'_>, gruxi_request: &mut GruxiRequest, response: &mut GruxiResponse) {
+        match self {
+            Middleware::Bandwidth => response_middleware::throttle(context, gruxi_request, response),
+            Middleware::Accounting => response_middleware::count(context, gruxi_request, response),
+            Middleware::AccessLog => response_middleware::write_access_log(context, gruxi_request, response).await,
+            Middleware::Headers => response_middleware::add_headers(context, gruxi_request, response),
+            Middleware::DownloadSlots => response_middleware::acquire_download_slot(context, gruxi_request, response).await,
+            Middleware::Compression => response_middleware::compress(context, gruxi_request, response).await,
+            Middleware::Plugins => wasm_plugins::run_response_plugins(context, gruxi_request, response),
+            Middleware::Lua => lua_hooks::run_response_hooks(context, gruxi_request, response),
+            _ => {}
+        }
+    }
+}
+
+// The middleware chain of a site, in order
+pub fn get_middleware_chain(site: &Site) -> Vec<Middleware> {
+    if site.middleware.is_empty() {
+        return DEFAULT_MIDDLEWARE.iter().filter_map(|name| Middleware::from_name(name)).collect();
+    }
+    site.middleware.iter().filter_map(|name| Middleware::from_name(name)).collect()
+}
+
+// Validate the middleware chain of a site. An empty chain is the default chain
+pub fn validate_middleware_chain(middleware: &[String]) -> Result<(), Vec<String>> {
+    let mut errors = Vec::new();
+    if middleware.is_empty() {
+        return Ok(());
+    }
+
+    for (index, name) in middleware.iter().enumerate() {
+        if Middleware::from_name(name).is_none() {
+            errors.push(format!("Unknown middleware '{}', must be one of {}", name, MIDDLEWARE_NAMES.join(", ")));
+        } else if middleware[..index].contains(name) {
+            errors.push(format!("Middleware '{}' is in the middleware chain more than once", name));
+        }
+    }
+    for required in REQUIRED_MIDDLEWARE {
+        if !middleware.iter().any(|name| name == required) {
+            errors.push(format!("Middleware chain must contain '{}'", required));
+        }
+    }
+    if middleware.first().is_some_and(|name| name != "validation") {
+        errors.push("Middleware chain must start with 'validation', so no other middleware sees invalid requests".to_string());
+    }
+
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+// What the middleware of a request share: where the request came in, its site and the location of the site it is for
+pub struct MiddlewareContext<'a> {
+    pub binding: &'a Binding,
+    pub running_state: &'a RunningState,
+    // The location matching the path, if any
+    pub location: Option<&'a Location>,
+    site: &'a Site,
+    // The site with the overrides of the location applied
+    location_site: Option<Arc<Site>>,
+    // Whether the response is a file sent for X-Sendfile or X-Accel-Redirect, which is passed on as it is
+    pub is_sendfile_response: bool,
+}
+
+impl<'a> MiddlewareContext<'a> {
+    pub fn new(gruxi_request: &mut GruxiRequest, binding: &'a Binding, site: &'a Site, running_state: &'a RunningState) -> Self {
+        // Check if a location within the site matches the path, which can override the site settings for this request
+        let location = site.get_matching_location(&gruxi_request.get_path());
+        let location_site = location.map(|location| {
+            trace(format!("Matched location '{}' for path: {}", &location.name, &gruxi_request.get_path()));
+            site.with_location_overrides(location)
+        });
+
+        Self {
+            binding,
+            running_state,
+            location,
+            site,
+            location_site,
+            is_sendfile_response: false,
+        }
+    }
+
+    // The site, with the overrides of the matching location applied
+    pub fn get_site(&self) -> &Site {
+        self.location_site.as_deref().unwrap_or(self.site)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_validate_middleware_chain() {
+        assert!(validate_middleware_chain(&[]).is_ok());
+        assert!(validate_middleware_chain(&chain(&DEFAULT_MIDDLEWARE)).is_ok());
+        // Logging requests answered by the firewall too, without compression
+        assert!(validate_middleware_chain(&chain(&["validation", "access_log", "waf", "auth"])).is_ok());
+
+        assert_eq!(validate_middleware_chain(&chain(&["validation", "auth", "gzip"])).unwrap_err().len(), 1);
+        assert_eq!(validate_middleware_chain(&chain(&["validation", "auth", "waf", "waf"])).unwrap_err().len(), 1);
+        assert_eq!(
+            validate_middleware_chain(&chain(&["validation", "waf"])).unwrap_err(),
+            vec!["Middleware chain must contain 'auth'".to_string()]
+        );
+        assert_eq!(validate_middleware_chain(&chain(&["auth", "validation"])).unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn test_get_middleware_chain() {
+        let mut site = Site::new();
+        let default_chain = get_middleware_chain(&site);
+        assert_eq!(default_chain.len(), DEFAULT_MIDDLEWARE.len());
+        assert_eq!(default_chain.first(), Some(&Middleware::Validation));
+        assert_eq!(default_chain.last(), Some(&Middleware::Lua));
+
+        site.middleware = chain(&["validation", "access_log", "waf", "auth"]);
+        assert_eq!(get_middleware_chain(&site), vec![Middleware::Validation, Middleware::AccessLog, Middleware::Waf, Middleware::Auth]);
+    }
+}
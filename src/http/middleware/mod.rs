@@ -0,0 +1,3 @@
+pub mod middleware_chain;
+pub mod request_middleware;
+pub mod response_middleware;
@@ -0,0 +1,230 @@
+use hyper::header::HeaderValue;
+
+use crate::authentication::authenticator::{LocationAccess, authenticate_location_request};
+use crate::error::gruxi_error::GruxiError;
+use crate::error::gruxi_error_enums::GruxiErrorKind;
+use crate::http::allowed_methods::method_not_allowed_response;
+use crate::http::http_util::*;
+use crate::http::middleware::middleware_chain::MiddlewareContext;
+use crate::http::request_handlers::processors::webdav_processor::WEBDAV_METHODS;
+use crate::http::request_response::gruxi_request::GruxiRequest;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+use crate::logging::syslog::debug;
+
+// The "validation" middleware, answering requests that fail validation with the status of the failure
+pub async fn validate(gruxi_request: &mut GruxiRequest) -> Option<GruxiResponse> {
+    let gruxi_error = validate_request(gruxi_request).await.err()?;
+    debug(format!("Request validation failed: {:?}", gruxi_error));
+    let status_code = match &gruxi_error.kind {
+        GruxiErrorKind::HttpRequestValidation(code) => *code,
+        _ => 500, // Default for other errors
+    };
+    let mut response = GruxiResponse::new_empty_with_status(status_code);
+    if status_code == hyper::StatusCode::BAD_REQUEST.as_u16() {
+        // We cannot trust where a malformed request ends, so the connection is not used for another one
+        response.headers_mut().insert("Connection", HeaderValue::from_static("close"));
+    }
+    Some(response)
+}
+
+// The "methods" middleware, answering the methods that are about the server rather than a resource of the site
+pub async fn answer_special_methods(context: &MiddlewareContext<'_>, gruxi_request: &mut GruxiRequest) -> Option<GruxiResponse> {
+    // TRACE echoes the request back, including cookies and credentials, so it is only served when enabled
+    if gruxi_request.get_http_method_str() == "TRACE" && !is_trace_method_enabled().await {
+        let allowed_methods = context.running_state.get_request_handler_manager().get_allowed_methods_for_site(context.get_site()).await;
+        let allowed_methods: Vec<&str> = allowed_methods.split(", ").filter(|method| *method != "TRACE").collect();
+        let mut resp = method_not_allowed_response(&allowed_methods.join(", "));
+        add_standard_headers_to_response(&mut resp);
+        return Some(resp);
+    }
+
+    // Handle special case for OPTIONS * request, which is stupid but valid
+    if gruxi_request.get_http_method_str() == "OPTIONS" && gruxi_request.get_path_str() == "*" {
+        // Special case for OPTIONS * request, answered with what the request handlers of the site support
+        let allowed_methods = context.running_state.get_request_handler_manager().get_allowed_methods_for_site(context.get_site()).await;
+        let mut resp = GruxiResponse::new_empty_with_status(hyper::StatusCode::OK.as_u16());
+        if let Ok(value) = HeaderValue::from_str(&allowed_methods) {
+            resp.headers_mut().insert("Allow", value);
+        }
+        add_standard_headers_to_response(&mut resp);
+        return Some(resp);
+    }
+
+    // Handle EXPECT: 100-continue header
+    if let Some(expect_header) = gruxi_request.get_headers().get("expect")
+        && expect_header.to_str().unwrap_or("").eq_ignore_ascii_case("100-continue")
+    {
+        // Send 100 Continue response
+        let mut resp = empty_response_with_status(hyper::StatusCode::CONTINUE);
+        add_standard_headers_to_response(&mut resp);
+        return Some(resp);
+    }
+
+    None
+}
+
+// The "auth" middleware. Locations can require basic authentication, verified by the location users or an auth provider
+pub async fn authenticate(context: &MiddlewareContext<'_>, gruxi_request: &mut GruxiRequest) -> Option<GruxiResponse> {
+    let location = context.location.filter(|location| location.requires_authentication())?;
+
+    // The credentials are taken from the request once verified, so the site never sees them. They are kept aside for when a
+    // rewritten path is authenticated again
+    let authorization = match gruxi_request.get_headers().get("Authorization") {
+        Some(header) => header.to_str().unwrap_or("").to_string(),
+        None => gruxi_request.get_calculated_data("authorization").unwrap_or_default(),
+    };
+    match authenticate_location_request(location, &authorization).await {
+        LocationAccess::Granted(username) => {
+            gruxi_request.remove_header("Authorization");
+            gruxi_request.add_calculated_data("authorization", &authorization);
+            // The client cannot set the forwarded user itself, as it is always replaced here
+            if !location.auth_forward_user_header.is_empty() {
+                gruxi_request.set_header(&location.auth_forward_user_header, &username);
+            }
+            None
+        }
+        LocationAccess::Unauthenticated(challenge) => {
+            let mut resp = GruxiResponse::new_empty_with_status(hyper::StatusCode::UNAUTHORIZED.as_u16());
+            if let Ok(header_value) = HeaderValue::from_str(&challenge) {
+                resp.headers_mut().insert("WWW-Authenticate", header_value);
+            }
+            add_standard_headers_to_response(&mut resp);
+            Some(resp)
+        }
+        LocationAccess::Forbidden => {
+            let mut resp = GruxiResponse::new_empty_with_status(hyper::StatusCode::FORBIDDEN.as_u16());
+            add_standard_headers_to_response(&mut resp);
+            Some(resp)
+        }
+    }
+}
+
+async fn validate_request(gruxi_request: &mut GruxiRequest) -> Result<(), GruxiError> {
+    // Here we can add any request validation logic if needed
+    let cached_configuration = crate::configuration::cached_configuration::get_cached_configuration();
+    let configuration = cached_configuration.get_configuration().await;
+
+    // Validation for HTTP/1.1 only
+    if gruxi_request.get_http_version() == "HTTP/1.1" {
+        // [HTTP1.1] Requires a Host header
+        if !gruxi_request.get_headers().contains_key("Host") {
+            return Err(GruxiError::new(
+                GruxiErrorKind::HttpRequestValidation(hyper::StatusCode::BAD_REQUEST.as_u16()),
+                format!("Failed to get streaming HTTP request for request: {:?}", gruxi_request),
+            ));
+        }
+
+        // [HTTP1.1] If there is multiple host headers, we return a 400 error
+        if gruxi_request.get_headers().get_all("Host").iter().count() > 1 {
+            return Err(GruxiError::new(
+                GruxiErrorKind::HttpRequestValidation(hyper::StatusCode::BAD_REQUEST.as_u16()),
+                format!("Multiple Host headers for request: {:?}", gruxi_request),
+            ));
+        }
+    }
+
+    // [HTTP1.x] A request with both Transfer-Encoding and Content-Length can be read differently by us and a proxy
+    // in front of or behind us, which is how requests are smuggled, so it is rejected (RFC 7230 section 3.3.3)
+    if gruxi_request.get_http_version().starts_with("HTTP/1") && has_conflicting_message_length(gruxi_request.get_headers()) {
+        return Err(GruxiError::new(
+            GruxiErrorKind::HttpRequestValidation(hyper::StatusCode::BAD_REQUEST.as_u16()),
+            format!("Both Transfer-Encoding and Content-Length headers for request: {:?}", gruxi_request),
+        ));
+    }
+
+    // [HTTP1.1 and later] Basic validation: check for valid method
+    let http_method = gruxi_request.get_http_method();
+    if http_method != "GET"
+        && http_method != "POST"
+        && http_method != "HEAD"
+        && http_method != "PUT"
+        && http_method != "DELETE"
+        && http_method != "OPTIONS"
+        && http_method != "TRACE"
+        && http_method != "CONNECT"
+        && http_method != "PATCH"
+        && !WEBDAV_METHODS.contains(&http_method.as_str())
+    {
+        // Return a error for unsupported method
+        return Err(GruxiError::new(
+            GruxiErrorKind::HttpRequestValidation(hyper::StatusCode::NOT_IMPLEMENTED.as_u16()),
+            format!("Unsupported HTTP method for request: {:?}", gruxi_request),
+        ));
+    }
+
+    // Protect our server from overly large bodies
+    let max_body_size = configuration.core.server_settings.max_body_size;
+    if max_body_size > 0 && (http_method == "POST" || http_method == "PUT") {
+        // Check Content-Length header if present
+        if let Some(content_length_header) = gruxi_request.get_headers().get("Content-Length")
+            && let Ok(content_length_str) = content_length_header.to_str()
+            && let Ok(content_length) = content_length_str.parse::<u64>()
+            && content_length > max_body_size
+        {
+            return Err(GruxiError::new(
+                GruxiErrorKind::HttpRequestValidation(hyper::StatusCode::PAYLOAD_TOO_LARGE.as_u16()),
+                format!("Payload too large for request, based on content-length header: {:?}", gruxi_request),
+            ));
+        }
+
+        // Also check the expected body size
+        if gruxi_request.get_body_size() > max_body_size {
+            return Err(GruxiError::new(
+                GruxiErrorKind::HttpRequestValidation(hyper::StatusCode::PAYLOAD_TOO_LARGE.as_u16()),
+                format!("Payload too large for request, based on actual body size: {:?}", gruxi_request),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+async fn is_trace_method_enabled() -> bool {
+    let cached_configuration = crate::configuration::cached_configuration::get_cached_configuration();
+    cached_configuration.get_configuration().await.core.server_settings.trace_method_enabled
+}
+
+// Whether the length of the body is given both by Transfer-Encoding and Content-Length, or by Content-Length headers that
+// disagree. Hyper drops a Content-Length that comes after Transfer-Encoding and rejects differing Content-Length values
+// itself, this catches the rest. Obsolete line folding (obs-fold) in headers is rejected by hyper with a 400 as well,
+// so header values never reach us folded
+fn has_conflicting_message_length(headers: &hyper::HeaderMap) -> bool {
+    if headers.contains_key(hyper::header::TRANSFER_ENCODING) && headers.contains_key(hyper::header::CONTENT_LENGTH) {
+        return true;
+    }
+    let mut content_lengths = headers
+        .get_all(hyper::header::CONTENT_LENGTH)
+        .iter()
+        .flat_map(|value| value.to_str().unwrap_or("").split(',').map(|length| length.trim().to_string()).collect::<Vec<_>>());
+    match content_lengths.next() {
+        Some(first) => content_lengths.any(|length| length != first),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_conflicting_message_length() {
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("Content-Length", HeaderValue::from_static("10"));
+        assert!(!has_conflicting_message_length(&headers));
+
+        headers.append("Content-Length", HeaderValue::from_static("10"));
+        assert!(!has_conflicting_message_length(&headers));
+        headers.append("Content-Length", HeaderValue::from_static("12"));
+        assert!(has_conflicting_message_length(&headers));
+
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("Content-Length", HeaderValue::from_static("10, 11"));
+        assert!(has_conflicting_message_length(&headers));
+
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("Transfer-Encoding", HeaderValue::from_static("chunked"));
+        assert!(!has_conflicting_message_length(&headers));
+        headers.insert("Content-Length", HeaderValue::from_static("10"));
+        assert!(has_conflicting_message_length(&headers));
+    }
+}
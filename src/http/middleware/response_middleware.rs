@@ -0,0 +1,136 @@
+use chrono::{Local, Utc};
+use hyper::header::HeaderValue;
+
+use crate::compression::compression::Compression;
+use crate::configuration::site::Site;
+use crate::core::traffic_accounting::count_response;
+use crate::http::bandwidth_throttle::throttle_response;
+use crate::http::download_slots;
+use crate::http::long_running_connections::track_streaming_response;
+use crate::http::middleware::middleware_chain::MiddlewareContext;
+use crate::http::request_response::gruxi_request::GruxiRequest;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+
+// The "compression" middleware. Only gzip if not already gzipped and if we should compress based on config and sizes.
+// Unbuffered responses are passed on as they arrive, and sendfile responses as they are, as ranges apply to the file
+pub async fn compress(context: &MiddlewareContext<'_>, gruxi_request: &mut GruxiRequest, response: &mut GruxiResponse) {
+    if context.is_sendfile_response || response.is_unbuffered() {
+        return;
+    }
+
+    let content_length = response.get_body_size();
+    let content_type_header = response.get_header("Content-Type").map(|cth| cth.to_str().unwrap_or("").to_string()).unwrap_or_default();
+    let content_encoding_header = response.get_header("Content-Encoding").map(|ceh| ceh.to_str().unwrap_or("").to_string()).unwrap_or_default();
+
+    let file_reader_cache = context.running_state.get_file_reader_cache();
+    if content_encoding_header.to_lowercase() != "gzip" && file_reader_cache.should_compress(&content_type_header, content_length) {
+        let accepted_encodings = gruxi_request.get_accepted_encodings();
+        let compression = Compression::new();
+        compression.compress_response(response, accepted_encodings, content_encoding_header).await;
+    }
+}
+
+// The "download_slots" middleware. Large downloads take a download slot of the site while they are sent, or are answered
+// with 503 when none is free
+pub async fn acquire_download_slot(context: &MiddlewareContext<'_>, gruxi_request: &mut GruxiRequest, response: &mut GruxiResponse) {
+    if gruxi_request.get_http_method() != "HEAD"
+        && let Err(busy_response) = download_slots::acquire_download_slot(response, context.get_site()).await
+    {
+        *response = busy_response;
+    }
+}
+
+// The "headers" middleware, applying the site-specific extra headers, the cache header rules of the site and the location
+// cache rules
+pub fn add_headers(context: &MiddlewareContext<'_>, gruxi_request: &mut GruxiRequest, response: &mut GruxiResponse) {
+    for kv in &context.get_site().extra_headers {
+        if let Ok(key_name) = hyper::http::HeaderName::from_bytes(kv.key.as_bytes())
+            && let Ok(val) = HeaderValue::from_str(kv.value.as_str())
+        {
+            response.headers_mut().insert(key_name, val);
+        }
+    }
+
+    add_cache_headers(context.get_site(), gruxi_request, response);
+
+    if let Some(location) = context.location
+        && !location.cache_control.is_empty()
+        && let Ok(val) = HeaderValue::from_str(&location.cache_control)
+    {
+        response.headers_mut().insert("Cache-Control", val);
+    }
+}
+
+// Cache-Control and Expires from the first cache header rule of the site matching the response. Only successful and not
+// modified responses get them, so errors are not cached for as long as the content
+fn add_cache_headers(site: &Site, gruxi_request: &GruxiRequest, response: &mut GruxiResponse) {
+    let status = response.get_status();
+    if !(200..300).contains(&status) && status != 304 {
+        return;
+    }
+
+    let content_type = response.get_header("Content-Type").and_then(|value| value.to_str().ok()).unwrap_or_default().to_string();
+    let Some(rule) = site.cache_header_rules.iter().find(|rule| rule.is_enabled && rule.matches(gruxi_request.get_path_str(), &content_type)) else {
+        return;
+    };
+
+    if !rule.cache_control.is_empty()
+        && (rule.replace_existing || response.get_header("Cache-Control").is_none())
+        && let Ok(val) = HeaderValue::from_str(&rule.cache_control)
+    {
+        response.headers_mut().insert("Cache-Control", val);
+    }
+    if rule.expires_seconds > 0 && (rule.replace_existing || response.get_header("Expires").is_none()) {
+        let expires = Utc::now() + chrono::Duration::seconds(rule.expires_seconds as i64);
+        if let Ok(val) = HeaderValue::from_str(&expires.format("%a, %d %b %Y %H:%M:%S GMT").to_string()) {
+            response.headers_mut().insert("Expires", val);
+        }
+    }
+}
+
+// The "access_log" middleware
+pub async fn write_access_log(context: &MiddlewareContext<'_>, gruxi_request: &mut GruxiRequest, response: &mut GruxiResponse) {
+    let site = context.get_site();
+    if !site.access_log_enabled {
+        return;
+    }
+
+    // Get current date and time in CLF format, which is like 10/Oct/2000:13:55:36 -0700
+    let now = Local::now();
+    // Streamed files are logged with their length, as streamed bodies have no size otherwise
+    let body_size = match response.get_buffered_body_size().or(response.get_file_length()) {
+        Some(body_size) => body_size,
+        None => response.get_body_size(),
+    };
+    let clf_date = now.format("%d/%b/%Y:%H:%M:%S %z").to_string();
+    let log_entry = format!(
+        "{} - - [{}] \"{} {} {}\" {} {}",
+        gruxi_request.get_remote_ip(),
+        clf_date,
+        gruxi_request.get_http_method(),
+        gruxi_request.get_path_and_query(),
+        gruxi_request.get_http_version(),
+        response.get_status(),
+        body_size
+    );
+
+    let access_log_buffer_rwlock = context.running_state.get_access_log_buffer();
+    let access_log_buffer = access_log_buffer_rwlock.read().await;
+    access_log_buffer.add_log(site.id.to_string(), log_entry);
+}
+
+// The "accounting" middleware. Counts the request and the bytes sent for usage reports, HEAD responses having no body on
+// the wire. Streamed responses stay open after the request, so they show in monitoring with the other long-running connections
+pub fn count(context: &MiddlewareContext<'_>, gruxi_request: &mut GruxiRequest, response: &mut GruxiResponse) {
+    let site = context.get_site();
+    count_response(response, &site.id, gruxi_request.get_http_method() != "HEAD");
+    track_streaming_response(response, &site.id);
+}
+
+// The "bandwidth" middleware, pacing the body by the bandwidth limits of the site. In the default chain, this is after the
+// body is counted, so the count is of the whole body
+pub fn throttle(context: &MiddlewareContext<'_>, gruxi_request: &mut GruxiRequest, response: &mut GruxiResponse) {
+    if gruxi_request.get_http_method() != "HEAD" {
+        throttle_response(response, context.get_site());
+    }
+}
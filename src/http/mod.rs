@@ -1,8 +1,28 @@
+pub mod allowed_methods;
+pub mod bandwidth_throttle;
+pub mod basic_auth;
+pub mod bot_policy;
+pub mod cache_warmer;
+pub mod client;
+pub mod download_slots;
+pub mod error_response;
 pub mod handle_request;
-pub mod http_util;
-pub mod http_tls;
+pub mod health_probes;
 pub mod http_server;
+pub mod http_tls;
+pub mod http_util;
+pub mod landing_page;
+pub mod long_running_connections;
+pub mod lua_hooks;
+pub mod middleware;
 pub mod request_handlers;
+pub mod request_limits;
+pub mod request_line;
+pub mod request_priority;
 pub mod request_response;
-pub mod client;
-pub mod site_match;
\ No newline at end of file
+pub mod sendfile;
+pub mod site_match;
+pub mod uri_normalization;
+pub mod waf;
+pub mod wasm_plugins;
+pub mod websocket_relay;
@@ -1,8 +1,29 @@
+use hyper::body::Bytes;
+
 use crate::{
     configuration::site::Site,
     error::gruxi_error::GruxiError, http::request_response::{gruxi_request::GruxiRequest, gruxi_response::GruxiResponse},
 };
 
+// What a body inspector makes of a chunk of the request body
+pub enum BodyInspection {
+    // Pass on these bytes instead of the chunk, which can be the chunk as it is, a changed chunk or nothing
+    Continue(Bytes),
+    // Stop the request, answering it with this status
+    Reject { status_code: u16, reason: String },
+}
+
+// Examines, and can transform, a request body chunk by chunk as the processor reads it, so the body is never buffered for it
+pub trait RequestBodyInspector: Send + Sync {
+    // Called with each chunk of the body, in order
+    fn inspect_chunk(&mut self, chunk: Bytes) -> BodyInspection;
+
+    // Called once at the end of the body, with what to add at its end
+    fn finish(&mut self) -> BodyInspection {
+        BodyInspection::Continue(Bytes::new())
+    }
+}
+
 // Trait that processors must implement
 #[allow(async_fn_in_trait)]
 pub trait ProcessorTrait {
@@ -21,6 +42,12 @@ pub trait ProcessorTrait {
     // Reurns the default pretty name of the processor, such as "PHP Processor", "Static File Processor", etc
     fn get_default_pretty_name(&self) -> String;
 
+    // Returns an inspector for the body of a request this processor is about to handle, such as for validating uploads.
+    // Processors not looking at bodies keep the default of none
+    fn get_request_body_inspector(&self, _gruxi_request: &GruxiRequest, _site: &Site) -> Option<Box<dyn RequestBodyInspector>> {
+        None
+    }
+
     // Handle an incoming request (details would depend on the actual implementation)
     async fn handle_request(&self, gruxi_request: &mut GruxiRequest, site: &Site) -> Result<GruxiResponse, GruxiError>;
 }
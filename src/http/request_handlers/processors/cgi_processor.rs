@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::time::Duration;
+
+use hyper::body::Bytes;
+use hyper::header::{HeaderName, HeaderValue};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use crate::{
+    configuration::site::Site,
+    error::{
+        gruxi_error::GruxiError,
+        gruxi_error_enums::{CgiProcessorError, GruxiErrorKind},
+    },
+    file::{file_util::check_path_secure, normalized_path::NormalizedPath},
+    http::{
+        request_handlers::processor_trait::ProcessorTrait,
+        request_response::{gruxi_request::GruxiRequest, gruxi_response::GruxiResponse},
+    },
+    logging::syslog::{debug, error, trace},
+};
+
+// Request headers that are not passed on to CGI scripts as HTTP_* variables.
+// Authorization contains credentials and "Proxy" is blocked to avoid the httpoxy vulnerability
+static CGI_EXCLUDED_HEADERS: &[&str] = &["authorization", "proxy-authorization", "proxy", "content-type", "content-length"];
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CgiInterpreter {
+    pub extension: String,  // File extension, including the dot, e.g. ".py"
+    pub executable: String, // Executable to run the script with, e.g. "/usr/bin/python3"
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct CgiProcessor {
+    pub id: String,          // Unique identifier for the processor
+    pub cgi_bin_dir: String, // Directory containing the CGI scripts
+    pub url_prefix: String,  // URL prefix that maps to the cgi-bin directory, e.g. "/cgi-bin", can be empty
+    // Interpreters to use based on the script extension. Scripts without a matching interpreter are executed directly
+    pub interpreters: Vec<CgiInterpreter>,
+    pub request_timeout: u32, // Seconds
+
+    // Calculated fields (not serialized)
+    #[serde(skip)]
+    normalized_cgi_bin_dir: Option<NormalizedPath>,
+}
+
+impl CgiProcessor {
+    pub fn new() -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            cgi_bin_dir: String::new(),
+            url_prefix: "/cgi-bin".to_string(),
+            interpreters: Vec::new(),
+            request_timeout: 30,
+            normalized_cgi_bin_dir: None,
+        }
+    }
+
+    // Find the script in the path, returning the script name (url path of the script) and the remaining path info
+    async fn find_script(cgi_bin_dir: &str, url_prefix: &str, path: &str) -> Option<(String, String, String)> {
+        let relative_path = match path.strip_prefix(url_prefix) {
+            Some(p) if url_prefix.is_empty() || p.is_empty() || p.starts_with('/') => p,
+            _ => return None,
+        };
+
+        let segments: Vec<&str> = relative_path.split('/').filter(|s| !s.is_empty()).collect();
+        for idx in 1..=segments.len() {
+            let script_path = format!("/{}", segments[..idx].join("/"));
+            let normalized_path = NormalizedPath::new(cgi_bin_dir, &script_path).ok()?;
+            let full_path = normalized_path.get_full_path();
+
+            let metadata = tokio::fs::metadata(&full_path).await.ok()?;
+            if metadata.is_file() {
+                let path_info = if idx < segments.len() { format!("/{}", segments[idx..].join("/")) } else { String::new() };
+                return Some((full_path, format!("{}{}", url_prefix, script_path), path_info));
+            }
+        }
+        None
+    }
+
+    fn get_interpreter(&self, script_path: &str) -> Option<&CgiInterpreter> {
+        let script_path = script_path.to_lowercase();
+        self.interpreters.iter().find(|i| script_path.ends_with(&i.extension.to_lowercase()))
+    }
+}
+
+impl Default for CgiProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Build the CGI 1.1 (RFC 3875) meta-variables for the request
+fn build_cgi_environment(gruxi_request: &mut GruxiRequest, cgi_bin_dir: &str, script_filename: &str, script_name: &str, path_info: &str) -> HashMap<String, String> {
+    let mut env: HashMap<String, String> = HashMap::new();
+
+    // Protocol specific meta-variables, from the request headers
+    for (key, value) in gruxi_request.get_headers().iter() {
+        let key_lowercase = key.as_str().to_lowercase();
+        if CGI_EXCLUDED_HEADERS.contains(&key_lowercase.as_str()) {
+            continue;
+        }
+        if let Ok(value_str) = value.to_str() {
+            let env_key = format!("HTTP_{}", key_lowercase.replace('-', "_").to_uppercase());
+            env.entry(env_key)
+                .and_modify(|v: &mut String| v.push_str(&format!(", {}", value_str)))
+                .or_insert_with(|| value_str.to_string());
+        }
+    }
+
+    if let Some(content_type) = gruxi_request.get_headers().get("content-type").and_then(|v| v.to_str().ok()) {
+        env.insert("CONTENT_TYPE".to_string(), content_type.to_string());
+    }
+    let body_size = gruxi_request.get_body_size();
+    if body_size > 0 {
+        env.insert("CONTENT_LENGTH".to_string(), body_size.to_string());
+    }
+
+    let path_translated = if path_info.is_empty() {
+        String::new()
+    } else {
+        format!("{}{}", cgi_bin_dir.trim_end_matches('/'), path_info)
+    };
+
+    env.insert("GATEWAY_INTERFACE".to_string(), "CGI/1.1".to_string());
+    env.insert("REQUEST_METHOD".to_string(), gruxi_request.get_http_method());
+    env.insert("REQUEST_URI".to_string(), gruxi_request.get_path_and_query());
+    env.insert("SCRIPT_NAME".to_string(), script_name.to_string());
+    env.insert("SCRIPT_FILENAME".to_string(), script_filename.to_string());
+    env.insert("PATH_INFO".to_string(), path_info.to_string());
+    env.insert("PATH_TRANSLATED".to_string(), path_translated);
+    env.insert("QUERY_STRING".to_string(), gruxi_request.get_query());
+    env.insert("DOCUMENT_ROOT".to_string(), cgi_bin_dir.to_string());
+    env.insert("SERVER_SOFTWARE".to_string(), "Gruxi".to_string());
+    env.insert("SERVER_NAME".to_string(), gruxi_request.get_hostname());
+    env.insert("SERVER_PORT".to_string(), gruxi_request.get_server_port().to_string());
+    env.insert("SERVER_PROTOCOL".to_string(), gruxi_request.get_http_version());
+    env.insert("REMOTE_ADDR".to_string(), gruxi_request.get_remote_ip());
+    env.insert("REMOTE_HOST".to_string(), gruxi_request.get_remote_ip());
+    env.insert("HTTPS".to_string(), if gruxi_request.is_https() { "on" } else { "off" }.to_string());
+    env.insert("REDIRECT_STATUS".to_string(), "200".to_string());
+
+    env
+}
+
+// Status code, headers and body of a CGI response
+type CgiOutput<'a> = (u16, Vec<(String, String)>, &'a [u8]);
+
+// Parse the CGI script output into status, headers and body. Returns None if the output has no header section
+fn parse_cgi_output(output: &[u8]) -> Option<CgiOutput<'_>> {
+    let (headers_bytes, body_bytes) = if let Some(pos) = output.windows(4).position(|w| w == b"\r\n\r\n") {
+        (&output[..pos], &output[pos + 4..])
+    } else if let Some(pos) = output.windows(2).position(|w| w == b"\n\n") {
+        (&output[..pos], &output[pos + 2..])
+    } else {
+        return None;
+    };
+
+    let mut status_code: Option<u16> = None;
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut has_location = false;
+
+    for line in String::from_utf8_lossy(headers_bytes).lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (key, value) = line.split_once(':')?;
+        let key = key.trim();
+        let value = value.trim();
+
+        if key.eq_ignore_ascii_case("status") {
+            status_code = value.split_whitespace().next().and_then(|code| code.parse::<u16>().ok());
+        } else {
+            if key.eq_ignore_ascii_case("location") {
+                has_location = true;
+            }
+            headers.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    // A Location header without an explicit status is a redirect
+    let status_code = status_code.unwrap_or(if has_location { 302 } else { 200 });
+    Some((status_code, headers, body_bytes))
+}
+
+impl ProcessorTrait for CgiProcessor {
+    fn initialize(&mut self) {
+        if self.normalized_cgi_bin_dir.is_none() {
+            let normalized_path_result = NormalizedPath::new(&self.cgi_bin_dir, "");
+            self.normalized_cgi_bin_dir = match normalized_path_result {
+                Ok(path) => Some(path),
+                Err(_) => {
+                    error(format!("Failed to normalize CGI bin directory path: {}", self.cgi_bin_dir));
+                    return;
+                }
+            };
+        }
+    }
+
+    fn sanitize(&mut self) {
+        self.id = self.id.trim().to_string();
+        self.cgi_bin_dir = self.cgi_bin_dir.trim().replace("\\", "/");
+        self.url_prefix = self.url_prefix.trim().trim_end_matches('/').to_string();
+        for interpreter in &mut self.interpreters {
+            interpreter.extension = interpreter.extension.trim().to_lowercase();
+            interpreter.executable = interpreter.executable.trim().to_string();
+        }
+    }
+
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if Uuid::parse_str(&self.id).is_err() {
+            errors.push(format!("Invalid ID, must be a valid UUID: {}", self.id));
+        }
+
+        if self.cgi_bin_dir.is_empty() {
+            errors.push("CGI bin directory must be set".to_string());
+        } else if NormalizedPath::new(&self.cgi_bin_dir, "").is_err() {
+            errors.push(format!("CGI bin directory path is invalid: '{}' - Check strange characters and path format", self.cgi_bin_dir));
+        }
+
+        if !self.url_prefix.is_empty() && !self.url_prefix.starts_with('/') {
+            errors.push(format!("URL prefix '{}' must start with '/'", self.url_prefix));
+        }
+
+        if self.request_timeout < 1 {
+            errors.push("Request timeout must be greater than 0".to_string());
+        }
+
+        for (idx, interpreter) in self.interpreters.iter().enumerate() {
+            if !interpreter.extension.starts_with('.') || interpreter.extension.len() < 2 {
+                errors.push(format!("Interpreter {} extension '{}' must start with '.', such as '.py'", idx + 1, interpreter.extension));
+            }
+            if interpreter.executable.is_empty() {
+                errors.push(format!("Interpreter {} executable cannot be empty", idx + 1));
+            }
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    async fn handle_request(&self, gruxi_request: &mut GruxiRequest, _site: &Site) -> Result<GruxiResponse, GruxiError> {
+        let cgi_bin_dir = match self.normalized_cgi_bin_dir.as_ref() {
+            Some(path) => path.get_full_path(),
+            None => {
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::CgiProcessor(CgiProcessorError::Internal)));
+            }
+        };
+
+        let path = gruxi_request.get_path();
+        let (script_filename, script_name, path_info) = match Self::find_script(&cgi_bin_dir, &self.url_prefix, &path).await {
+            Some(script) => script,
+            None => {
+                trace(format!("CGI script not found for path: {}", path));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::CgiProcessor(CgiProcessorError::ScriptNotFound)));
+            }
+        };
+
+        if !check_path_secure(&cgi_bin_dir, &script_filename).await {
+            return Err(GruxiError::new_with_kind_only(GruxiErrorKind::CgiProcessor(CgiProcessorError::ScriptNotFound)));
+        }
+
+        let env = build_cgi_environment(gruxi_request, &cgi_bin_dir, &script_filename, &script_name, &path_info);
+        let body = gruxi_request.get_body_bytes().await;
+
+        // Scripts are run from their own directory, with only the CGI variables and PATH in the environment
+        let mut command = match self.get_interpreter(&script_filename) {
+            Some(interpreter) => {
+                let mut command = tokio::process::Command::new(&interpreter.executable);
+                command.arg(&script_filename);
+                command
+            }
+            None => tokio::process::Command::new(&script_filename),
+        };
+        if let Some(script_dir) = std::path::Path::new(&script_filename).parent() {
+            command.current_dir(script_dir);
+        }
+        command.env_clear();
+        if let Ok(path_env) = std::env::var("PATH") {
+            command.env("PATH", path_env);
+        }
+        command.envs(&env).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).kill_on_drop(true);
+
+        trace(format!("Executing CGI script: {} with environment: {:?}", script_filename, env));
+
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                error(format!("Failed to execute CGI script '{}': {}", script_filename, e));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::CgiProcessor(CgiProcessorError::Execution(e))));
+            }
+        };
+
+        // Pipe the request body to the script, from a separate task so large bodies do not block reading the output
+        if let Some(mut stdin) = child.stdin.take() {
+            tokio::spawn(async move {
+                if !body.is_empty()
+                    && let Err(e) = stdin.write_all(&body).await
+                {
+                    debug(format!("Failed to write request body to CGI script: {}", e));
+                }
+            });
+        }
+
+        let output = match tokio::time::timeout(Duration::from_secs(self.request_timeout as u64), child.wait_with_output()).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => {
+                error(format!("Failed to read output from CGI script '{}': {}", script_filename, e));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::CgiProcessor(CgiProcessorError::Execution(e))));
+            }
+            Err(_) => {
+                debug(format!("CGI script '{}' timed out after {} seconds", script_filename, self.request_timeout));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::CgiProcessor(CgiProcessorError::Timeout)));
+            }
+        };
+
+        if !output.stderr.is_empty() {
+            debug(format!("CGI script '{}' stderr: {}", script_filename, String::from_utf8_lossy(&output.stderr)));
+        }
+
+        let (status_code, headers, body_bytes) = match parse_cgi_output(&output.stdout) {
+            Some(parsed) => parsed,
+            None => {
+                error(format!("CGI script '{}' returned an invalid response, exit status: {}", script_filename, output.status));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::CgiProcessor(CgiProcessorError::InvalidResponse)));
+            }
+        };
+
+        let mut response = GruxiResponse::new_with_bytes(status_code, Bytes::copy_from_slice(body_bytes));
+        for (key, value) in headers {
+            if let (Ok(header_name), Ok(header_value)) = (HeaderName::from_bytes(key.as_bytes()), HeaderValue::from_str(&value)) {
+                response.headers_mut().append(header_name, header_value);
+            }
+        }
+
+        Ok(response)
+    }
+
+    fn get_type(&self) -> String {
+        "cgi".to_string()
+    }
+
+    fn get_default_pretty_name(&self) -> String {
+        "CGI Processor".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cgi_output_with_status() {
+        let output = b"Status: 404 Not Found\r\nContent-Type: text/plain\r\n\r\nnot here";
+        let (status, headers, body) = parse_cgi_output(output).unwrap();
+
+        assert_eq!(status, 404);
+        assert_eq!(headers, vec![("Content-Type".to_string(), "text/plain".to_string())]);
+        assert_eq!(body, b"not here");
+    }
+
+    #[test]
+    fn test_parse_cgi_output_location_redirect() {
+        let output = b"Location: https://example.com/\n\n";
+        let (status, _, body) = parse_cgi_output(output).unwrap();
+
+        assert_eq!(status, 302);
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn test_parse_cgi_output_without_headers() {
+        assert!(parse_cgi_output(b"just some output").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_find_script_with_path_info() {
+        let cgi_bin_dir = std::env::temp_dir().join(format!("gruxi_cgi_test_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(cgi_bin_dir.join("tools")).unwrap();
+        std::fs::write(cgi_bin_dir.join("tools").join("script.sh"), b"#!/bin/sh\n").unwrap();
+        let cgi_bin_dir_str = cgi_bin_dir.to_string_lossy().replace("\\", "/");
+
+        let (script_filename, script_name, path_info) = CgiProcessor::find_script(&cgi_bin_dir_str, "/cgi-bin", "/cgi-bin/tools/script.sh/extra/info").await.unwrap();
+        assert!(script_filename.ends_with("/tools/script.sh"));
+        assert_eq!(script_name, "/cgi-bin/tools/script.sh");
+        assert_eq!(path_info, "/extra/info");
+
+        assert!(CgiProcessor::find_script(&cgi_bin_dir_str, "/cgi-bin", "/cgi-binx/tools/script.sh").await.is_none());
+        assert!(CgiProcessor::find_script(&cgi_bin_dir_str, "/cgi-bin", "/cgi-bin/tools/missing.sh").await.is_none());
+
+        std::fs::remove_dir_all(&cgi_bin_dir).unwrap();
+    }
+
+    #[test]
+    fn test_cgi_processor_validation() {
+        let mut processor = CgiProcessor::new();
+        processor.url_prefix = "cgi-bin".to_string();
+        processor.interpreters.push(CgiInterpreter {
+            extension: "py".to_string(),
+            executable: "".to_string(),
+        });
+
+        let errors = processor.validate().unwrap_err();
+        assert!(errors.iter().any(|e| e.contains("CGI bin directory must be set")));
+        assert!(errors.iter().any(|e| e.contains("must start with '/'")));
+        assert!(errors.iter().any(|e| e.contains("extension 'py'")));
+        assert!(errors.iter().any(|e| e.contains("executable cannot be empty")));
+    }
+}
@@ -0,0 +1,473 @@
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use hyper::body::Bytes;
+use hyper::header::HeaderValue;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+use crate::{
+    configuration::site::Site,
+    error::{
+        gruxi_error::GruxiError,
+        gruxi_error_enums::{GruxiErrorKind, ImageProcessorError},
+    },
+    file::{file_util::check_path_secure, normalized_path::NormalizedPath},
+    http::{
+        allowed_methods::{STATIC_ALLOWED_METHODS, method_not_allowed_response, options_response},
+        request_handlers::processor_trait::ProcessorTrait,
+        request_response::{gruxi_request::GruxiRequest, gruxi_response::GruxiResponse},
+    },
+    logging::syslog::{debug, error, trace},
+};
+
+// Image files that can be resized and re-encoded. Other files are left to the next request handler
+static IMAGE_SOURCE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "avif"];
+
+// Widths images can be resized to when the processor does not list its own, up to its max width
+static DEFAULT_IMAGE_WIDTHS: &[u32] = &[160, 320, 640, 960, 1280, 1920, 2560];
+
+// Qualities a request can ask for, besides the default quality of the processor, so clients cannot fill the cache with every quality
+static IMAGE_QUALITIES: &[u32] = &[50, 60, 70, 75, 80, 85, 90, 95];
+
+// Formats images can be re-encoded to, as the format query parameter, with their content type
+static IMAGE_OUTPUT_FORMATS: &[(&str, &str)] = &[("webp", "image/webp"), ("avif", "image/avif"), ("jpeg", "image/jpeg"), ("png", "image/png")];
+
+// Serves the images under the allowed paths of the web root resized and re-encoded by the query parameters, such as
+// "/images/photo.jpg?w=640&q=75&format=webp". The conversion is done by ImageMagick and the results are kept in the cache
+// directory, keyed by the source file and the parameters, so each variant is only converted once
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ImageProcessor {
+    pub id: String,       // Unique identifier for the processor
+    pub web_root: String, // Directory the source images are read from
+    // URL path prefixes of the images that are served, such as "/images". Requests outside of them go to the next handler
+    pub allowed_paths: Vec<String>,
+    pub cache_dir: String,      // Directory the converted images are kept in
+    pub converter_path: String, // ImageMagick executable, or just "magick" to find it in PATH
+    pub max_width: u32,         // Largest width an image can be resized to
+    // Widths images can be resized to, so clients cannot fill the cache with every width. Empty for the default widths up to max_width
+    pub allowed_widths: Vec<u32>,
+    pub default_quality: u32,            // Quality (1-100) when the request does not ask for one
+    pub max_concurrent_conversions: u32, // Conversions running at the same time, others wait for their turn
+    pub request_timeout: u32,            // Seconds, including the wait for a conversion slot
+
+    // Calculated fields (not serialized)
+    #[serde(skip)]
+    normalized_web_root: Option<NormalizedPath>,
+    #[serde(skip)]
+    conversion_slots: Option<Arc<Semaphore>>,
+}
+
+// What a request asks the image to be converted to
+#[derive(Debug, PartialEq)]
+struct ImageOptions {
+    width: Option<u32>,
+    quality: u32,
+    format: String,
+}
+
+impl ImageProcessor {
+    pub fn new() -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            web_root: String::new(),
+            allowed_paths: Vec::new(),
+            cache_dir: "./cache/images".to_string(),
+            converter_path: "magick".to_string(),
+            max_width: 4096,
+            allowed_widths: Vec::new(),
+            default_quality: 80,
+            max_concurrent_conversions: 2,
+            request_timeout: 30,
+            normalized_web_root: None,
+            conversion_slots: None,
+        }
+    }
+
+    fn is_allowed_path(&self, path: &str) -> bool {
+        self.allowed_paths
+            .iter()
+            .any(|allowed_path| allowed_path.is_empty() || path.strip_prefix(allowed_path.as_str()).is_some_and(|rest| rest.starts_with('/')))
+    }
+
+    // The conversion asked for by the query parameters, or None when there are none and the image is served as it is.
+    // Unknown parameters, such as cache busters, are ignored
+    fn parse_options(&self, query: &str, source_format: &str) -> Result<Option<ImageOptions>, String> {
+        let mut width = None;
+        let mut quality = None;
+        let mut format = None;
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            match key {
+                "w" | "width" => width = Some(value.parse::<u32>().map_err(|_| format!("Invalid width: {}", value))?),
+                "q" | "quality" => quality = Some(value.parse::<u32>().map_err(|_| format!("Invalid quality: {}", value))?),
+                "format" => format = Some(value.to_lowercase()),
+                _ => {}
+            }
+        }
+
+        if width.is_none() && quality.is_none() && format.is_none() {
+            return Ok(None);
+        }
+
+        if let Some(width) = width {
+            if width == 0 || width > self.max_width {
+                return Err(format!("Width must be between 1 and {}, got {}", self.max_width, width));
+            }
+            let allowed_widths = if self.allowed_widths.is_empty() { DEFAULT_IMAGE_WIDTHS } else { self.allowed_widths.as_slice() };
+            if !allowed_widths.contains(&width) {
+                return Err(format!("Width {} is not one of the allowed widths", width));
+            }
+        }
+        let quality = quality.unwrap_or(self.default_quality);
+        if quality != self.default_quality && !IMAGE_QUALITIES.contains(&quality) {
+            return Err(format!("Quality {} is not one of the allowed qualities", quality));
+        }
+        let format = match format.as_deref() {
+            Some("jpg") => "jpeg".to_string(),
+            Some(format) => format.to_string(),
+            None => source_format.to_string(),
+        };
+        if get_output_content_type(&format).is_none() {
+            return Err(format!("Unsupported format: {}", format));
+        }
+
+        Ok(Some(ImageOptions { width, quality, format }))
+    }
+
+    // Converts into temp_path, which is renamed to result_path once the conversion is done
+    async fn convert_image(&self, source_path: &str, source_format: &str, temp_path: &str, result_path: &str, options: &ImageOptions) -> Result<(), GruxiError> {
+        let Some(conversion_slots) = self.conversion_slots.as_ref() else {
+            return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::Internal)));
+        };
+        let _permit = conversion_slots
+            .acquire()
+            .await
+            .map_err(|_| GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::Internal)))?;
+
+        // The source is read with the coder of its extension, so a file with other content, such as SVG or MVG with an image
+        // extension, fails instead of being handed to one of the many other coders of ImageMagick
+        let mut command = tokio::process::Command::new(&self.converter_path);
+        command.arg(format!("{}:{}", source_format, source_path)).arg("-auto-orient").arg("-strip");
+        if let Some(width) = options.width {
+            // Only shrink, as enlarging an image makes it larger without adding detail
+            command.arg("-resize").arg(format!("{}x>", width));
+        }
+        command.arg("-quality").arg(options.quality.to_string()).arg(format!("{}:{}", options.format, temp_path));
+        command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::piped()).kill_on_drop(true);
+
+        trace(format!("Converting image '{}' to '{}' with {:?}", source_path, result_path, options));
+
+        let child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                error(format!("Failed to run image converter '{}': {}", self.converter_path, e));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::Execution(e))));
+            }
+        };
+        let output = match child.wait_with_output().await {
+            Ok(output) => output,
+            Err(e) => {
+                error(format!("Failed to read output from image converter '{}': {}", self.converter_path, e));
+                let _ = tokio::fs::remove_file(temp_path).await;
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::Execution(e))));
+            }
+        };
+        if !output.status.success() {
+            error(format!(
+                "Image converter failed for '{}', exit status: {}: {}",
+                source_path,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+            let _ = tokio::fs::remove_file(temp_path).await;
+            return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::ConversionFailed)));
+        }
+
+        if let Err(e) = tokio::fs::rename(temp_path, result_path).await {
+            error(format!("Failed to store converted image '{}': {}", result_path, e));
+            let _ = tokio::fs::remove_file(temp_path).await;
+            return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::Io(e))));
+        }
+        Ok(())
+    }
+}
+
+impl Default for ImageProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn get_output_content_type(format: &str) -> Option<&'static str> {
+    IMAGE_OUTPUT_FORMATS.iter().find(|(name, _)| *name == format).map(|(_, content_type)| *content_type)
+}
+
+// File name of a converted image in the cache. The source file is identified by its path, modification time and size, so
+// a changed image gets new results and the old ones are no longer used
+fn get_cache_file_name(source_path: &str, modified_nanos: u128, size: u64, options: &ImageOptions) -> String {
+    let key = format!("{}|{}|{}|{}|{}|{}", source_path, modified_nanos, size, options.width.unwrap_or(0), options.quality, options.format);
+    let hash: String = ring::digest::digest(&ring::digest::SHA256, key.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{}.{}", hash, options.format)
+}
+
+fn image_response(bytes: Vec<u8>, content_type: &str) -> GruxiResponse {
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), Bytes::from(bytes));
+    if let Ok(value) = HeaderValue::from_str(content_type) {
+        response.headers_mut().insert(hyper::header::CONTENT_TYPE, value);
+    }
+    response
+}
+
+impl ProcessorTrait for ImageProcessor {
+    fn initialize(&mut self) {
+        if self.conversion_slots.is_none() {
+            self.conversion_slots = Some(Arc::new(Semaphore::new(self.max_concurrent_conversions.max(1) as usize)));
+        }
+        if self.normalized_web_root.is_none() {
+            let normalized_path_result = NormalizedPath::new(&self.web_root, "");
+            self.normalized_web_root = match normalized_path_result {
+                Ok(path) => Some(path),
+                Err(_) => {
+                    error(format!("Failed to normalize image processor web root path: {}", self.web_root));
+                    return;
+                }
+            };
+        }
+    }
+
+    fn sanitize(&mut self) {
+        self.id = self.id.trim().to_string();
+        self.web_root = self.web_root.trim().replace("\\", "/");
+        self.cache_dir = self.cache_dir.trim().replace("\\", "/").trim_end_matches('/').to_string();
+        self.converter_path = self.converter_path.trim().to_string();
+        self.allowed_paths = self.allowed_paths.iter().map(|path| path.trim().trim_end_matches('/').to_string()).collect();
+        self.allowed_widths.sort_unstable();
+        self.allowed_widths.dedup();
+    }
+
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if Uuid::parse_str(&self.id).is_err() {
+            errors.push(format!("Invalid ID, must be a valid UUID: {}", self.id));
+        }
+
+        if self.web_root.is_empty() {
+            errors.push("Web root cannot be empty".to_string());
+        } else if NormalizedPath::new(&self.web_root, "").is_err() {
+            errors.push(format!("Web root path is invalid: '{}' - Check strange characters and path format", self.web_root));
+        }
+
+        if self.cache_dir.is_empty() {
+            errors.push("Cache directory cannot be empty".to_string());
+        }
+
+        if self.converter_path.is_empty() {
+            errors.push("Converter path cannot be empty".to_string());
+        }
+
+        // The source paths have to be allowed explicitly, "/" to allow the whole web root
+        if self.allowed_paths.is_empty() {
+            errors.push("At least one allowed path must be set, such as '/images'".to_string());
+        }
+        for allowed_path in &self.allowed_paths {
+            if !allowed_path.is_empty() && !allowed_path.starts_with('/') {
+                errors.push(format!("Allowed path '{}' must start with '/'", allowed_path));
+            }
+        }
+
+        if self.max_width < 1 || self.max_width > 16384 {
+            errors.push(format!("Max width must be between 1 and 16384, got {}", self.max_width));
+        }
+        for width in &self.allowed_widths {
+            if *width < 1 || *width > self.max_width {
+                errors.push(format!("Allowed width {} must be between 1 and the max width {}", width, self.max_width));
+            }
+        }
+
+        if !(1..=100).contains(&self.default_quality) {
+            errors.push(format!("Default quality must be between 1 and 100, got {}", self.default_quality));
+        }
+
+        if self.max_concurrent_conversions < 1 {
+            errors.push("Max concurrent conversions must be greater than 0".to_string());
+        }
+
+        if self.request_timeout < 1 {
+            errors.push("Request timeout must be greater than 0".to_string());
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    async fn handle_request(&self, gruxi_request: &mut GruxiRequest, _site: &Site) -> Result<GruxiResponse, GruxiError> {
+        let web_root = match self.normalized_web_root.as_ref() {
+            Some(path) => path.get_full_path(),
+            None => {
+                error(format!("ImageProcessor web root is not initialized as expected for id: '{}'", self.id));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::Internal)));
+            }
+        };
+
+        let path = gruxi_request.get_path();
+        if !self.is_allowed_path(&path) {
+            trace(format!("Image path is not in the allowed paths: {}", path));
+            return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::NotFound)));
+        }
+
+        let extension = path.rsplit_once('.').map(|(_, extension)| extension.to_lowercase()).unwrap_or_default();
+        if !IMAGE_SOURCE_EXTENSIONS.contains(&extension.as_str()) {
+            trace(format!("Path is not an image the image processor handles: {}", path));
+            return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::NotFound)));
+        }
+
+        let source_path = match NormalizedPath::new(&web_root, &path) {
+            Ok(normalized_path) => normalized_path.get_full_path(),
+            Err(_) => {
+                trace(format!("Failed or rejected to normalize image path: {}", path));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::NotFound)));
+            }
+        };
+        let metadata = match tokio::fs::metadata(&source_path).await {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::NotFound))),
+        };
+        if !check_path_secure(&web_root, &source_path).await {
+            return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::NotFound)));
+        }
+
+        match gruxi_request.get_http_method().as_str() {
+            "GET" | "HEAD" => {}
+            "OPTIONS" => return Ok(options_response(STATIC_ALLOWED_METHODS)),
+            _ => return Ok(method_not_allowed_response(STATIC_ALLOWED_METHODS)),
+        }
+
+        let source_format = if extension == "jpg" { "jpeg" } else { extension.as_str() };
+        let options = match self.parse_options(&gruxi_request.get_query(), source_format) {
+            Ok(Some(options)) => options,
+            Ok(None) => {
+                // No conversion asked for, so the image is served as it is
+                let bytes = tokio::fs::read(&source_path)
+                    .await
+                    .map_err(|e| GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::Io(e))))?;
+                let mime_type = mime_guess::from_path(&source_path).first_or_octet_stream().to_string();
+                return Ok(image_response(bytes, &mime_type));
+            }
+            Err(message) => {
+                debug(format!("Invalid image parameters for '{}': {}", path, message));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::InvalidParameters)));
+            }
+        };
+        let content_type = get_output_content_type(&options.format).unwrap_or("application/octet-stream");
+
+        let modified_nanos = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_nanos())
+            .unwrap_or(0);
+        let result_path = format!("{}/{}", self.cache_dir, get_cache_file_name(&source_path, modified_nanos, metadata.len(), &options));
+
+        if let Ok(bytes) = tokio::fs::read(&result_path).await {
+            trace(format!("Serving converted image from cache: {}", result_path));
+            return Ok(image_response(bytes, content_type));
+        }
+
+        if let Err(e) = tokio::fs::create_dir_all(&self.cache_dir).await {
+            error(format!("Failed to create image cache directory '{}': {}", self.cache_dir, e));
+            return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::Io(e))));
+        }
+
+        // Written next to the result first, so a result that is only partly written is never served
+        let temp_path = format!("{}.{}.tmp", result_path, Uuid::new_v4());
+        let conversion = self.convert_image(&source_path, source_format, &temp_path, &result_path, &options);
+        match tokio::time::timeout(Duration::from_secs(self.request_timeout as u64), conversion).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(e),
+            Err(_) => {
+                // The converter is killed as the conversion is dropped, which leaves what it wrote so far behind
+                let _ = tokio::fs::remove_file(&temp_path).await;
+                debug(format!("Converting image '{}' timed out after {} seconds", source_path, self.request_timeout));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::Timeout)));
+            }
+        }
+
+        let bytes = tokio::fs::read(&result_path)
+            .await
+            .map_err(|e| GruxiError::new_with_kind_only(GruxiErrorKind::ImageProcessor(ImageProcessorError::Io(e))))?;
+        Ok(image_response(bytes, content_type))
+    }
+
+    fn get_type(&self) -> String {
+        "image".to_string()
+    }
+
+    fn get_default_pretty_name(&self) -> String {
+        "Image Processor".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_image_options() {
+        let mut processor = ImageProcessor::new();
+        processor.max_width = 2000;
+
+        assert_eq!(processor.parse_options("v=3", "png").unwrap(), None);
+        assert_eq!(
+            processor.parse_options("w=640&format=webp", "jpeg").unwrap(),
+            Some(ImageOptions {
+                width: Some(640),
+                quality: 80,
+                format: "webp".to_string()
+            })
+        );
+        assert_eq!(processor.parse_options("q=50", "png").unwrap().unwrap().format, "png");
+        assert_eq!(processor.parse_options("format=jpg", "png").unwrap().unwrap().format, "jpeg");
+
+        assert!(processor.parse_options("w=3000", "jpeg").is_err());
+        assert!(processor.parse_options("w=abc", "jpeg").is_err());
+        assert!(processor.parse_options("q=0", "jpeg").is_err());
+        assert!(processor.parse_options("q=73", "jpeg").is_err());
+        assert!(processor.parse_options("w=641", "jpeg").is_err());
+        assert!(processor.parse_options("format=tiff", "jpeg").is_err());
+
+        processor.allowed_widths = vec![320, 640];
+        assert!(processor.parse_options("w=640", "jpeg").is_ok());
+        assert!(processor.parse_options("w=641", "jpeg").is_err());
+    }
+
+    #[test]
+    fn test_allowed_paths_and_cache_file_name() {
+        let mut processor = ImageProcessor::new();
+        processor.allowed_paths = vec![" /images/ ".to_string()];
+        processor.sanitize();
+        assert!(processor.is_allowed_path("/images/photo.jpg"));
+        assert!(!processor.is_allowed_path("/images-private/photo.jpg"));
+        assert!(!processor.is_allowed_path("/photo.jpg"));
+
+        processor.allowed_paths = vec!["/".to_string()];
+        processor.sanitize();
+        assert!(processor.is_allowed_path("/photo.jpg"));
+
+        let options = ImageOptions {
+            width: Some(640),
+            quality: 80,
+            format: "webp".to_string(),
+        };
+        let file_name = get_cache_file_name("/www/photo.jpg", 1, 100, &options);
+        assert!(file_name.ends_with(".webp"));
+        assert_eq!(file_name, get_cache_file_name("/www/photo.jpg", 1, 100, &options));
+        assert_ne!(file_name, get_cache_file_name("/www/photo.jpg", 2, 100, &options));
+    }
+}
@@ -6,7 +6,9 @@ use tokio::sync::{RwLock, mpsc, oneshot};
 use tokio::time::{self, Duration};
 
 use crate::core::running_state_manager;
+use crate::core::site_health::record_upstream_health;
 use crate::core::triggers::get_trigger_handler;
+use crate::http::client::http_client::UpstreamClientSettings;
 use crate::logging::syslog::{debug, error};
 
 // Commands sent to a load balancer task
@@ -19,23 +21,40 @@ pub enum LoadBalancerCommand {
 pub trait LoadBalancerImpl: Send + 'static {
     fn get_next_server(&mut self) -> Option<String>;
     fn check_health(&mut self);
-    fn check_uri_health(&self, uri: &str, health_register: Arc<AtomicBool>, request_timeout_secs: u64) {
+    // Health checks skip certificate verification, unless the upstream has its own TLS settings, such as a client certificate.
+    // They use the pool settings of the upstream, so upstreams that only speak HTTP/2 are checked over HTTP/2
+    fn check_uri_health(&self, uri: &str, health_register: Arc<AtomicBool>, request_timeout_secs: u64, upstream_client_settings: &UpstreamClientSettings) {
         let uri_parsed_result: Result<Uri, _> = uri.parse();
         let server_uri = match uri_parsed_result {
             Ok(u) => u,
             Err(e) => {
                 health_register.store(false, Ordering::SeqCst);
+                record_upstream_health(uri, false);
                 error(format!("Health check failed: Invalid URI for server '{}': {}", uri, e));
                 return;
             }
         };
 
+        let mut upstream_client_settings = upstream_client_settings.clone();
+        if upstream_client_settings.tls.is_default() {
+            upstream_client_settings.tls.verify_certificates = false;
+        }
+        let checked_uri = uri.to_string();
         tokio::spawn(async move {
             // Get a client from the running state
             let running_state_manager = running_state_manager::get_running_state_manager().await;
             let running_state = running_state_manager.get_running_state();
             let running_state_read_lock = running_state.read().await;
-            let client = running_state_read_lock.get_http_client().get_client(false);
+            let http_client = running_state_read_lock.get_http_client();
+            let client = match http_client.get_upstream_client(&upstream_client_settings) {
+                Ok(client) => client,
+                Err(e) => {
+                    health_register.store(false, Ordering::SeqCst);
+                    record_upstream_health(&checked_uri, false);
+                    error(format!("Health check failed: Could not set up TLS for server '{}': {}", server_uri, e));
+                    return;
+                }
+            };
 
             // Make the request and make sure it times out after X seconds
             let start_time = tokio::time::Instant::now();
@@ -49,6 +68,8 @@ pub trait LoadBalancerImpl: Send + 'static {
                 elapsed
             ));
             health_register.store(is_healthy, Ordering::SeqCst);
+            // Kept for the status page too
+            record_upstream_health(&checked_uri, is_healthy);
         });
     }
     fn get_health_check_interval_secs(&self) -> u64;
@@ -1,3 +1,4 @@
+use crate::http::client::http_client::UpstreamClientSettings;
 use crate::http::request_handlers::processors::load_balancer::load_balancer::LoadBalancerImpl;
 
 use std::{
@@ -16,10 +17,11 @@ pub struct RoundRobin {
     health_url_path: String,
     health_timeout_secs: u64,
     health_check_interval_secs: u64,
+    upstream_client_settings: UpstreamClientSettings,
 }
 
 impl RoundRobin {
-    pub fn new(servers: Vec<String>, health_url_path: String, health_timeout_secs: u64, health_check_interval_secs: u64) -> Self {
+    pub fn new(servers: Vec<String>, health_url_path: String, health_timeout_secs: u64, health_check_interval_secs: u64, upstream_client_settings: UpstreamClientSettings) -> Self {
         // All servers are healthy at start
         let health_state = servers.iter().map(|s| (s.clone(), Arc::new(AtomicBool::new(true)))).collect();
 
@@ -30,6 +32,7 @@ impl RoundRobin {
             health_url_path,
             health_timeout_secs,
             health_check_interval_secs,
+            upstream_client_settings,
         }
     }
 }
@@ -66,7 +69,7 @@ impl LoadBalancerImpl for RoundRobin {
                 Some(s) => s.clone(),
                 None => continue,
             };
-            self.check_uri_health(&server_uri, healthy_state, self.health_timeout_secs);
+            self.check_uri_health(&server_uri, healthy_state, self.health_timeout_secs, &self.upstream_client_settings);
         }
     }
 
@@ -1,6 +1,12 @@
+pub mod cgi_processor;
+pub mod image_processor;
+pub mod load_balancer;
+pub mod node_processor;
+pub mod php_processor;
 pub mod processor_manager;
-pub mod static_files_processor;
+pub mod proxy_helpers;
 pub mod proxy_processor;
-pub mod php_processor;
-pub mod load_balancer;
-pub mod proxy_helpers;
\ No newline at end of file
+pub mod python_processor;
+pub mod server_side_includes;
+pub mod static_files_processor;
+pub mod webdav_processor;
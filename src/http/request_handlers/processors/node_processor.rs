@@ -0,0 +1,147 @@
+use crate::{
+    configuration::site::Site,
+    core::running_state_manager::get_running_state_manager,
+    error::{
+        gruxi_error::GruxiError,
+        gruxi_error_enums::{GruxiErrorKind, NodeProcessorError, ProxyProcessorError},
+    },
+    http::{
+        request_handlers::{
+            processor_trait::ProcessorTrait,
+            processors::{proxy_helpers::body_limits::UpstreamLimits, proxy_processor::ProxyProcessor},
+        },
+        request_response::{gruxi_request::GruxiRequest, gruxi_response::GruxiResponse},
+    },
+    logging::syslog::{error, trace, warn},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// Reverse proxies requests to a Node.js application managed by Gruxi
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct NodeProcessor {
+    pub id: String,              // Unique identifier for the processor
+    pub node_handler_id: String, // ID of the Node.js handler running the application
+    pub request_timeout: u32,    // Seconds
+    #[serde(default = "default_preserve_host_header")]
+    pub preserve_host_header: bool, // Pass the original Host header, so the application can build correct URLs
+}
+
+fn default_preserve_host_header() -> bool {
+    true
+}
+
+impl NodeProcessor {
+    pub fn new() -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            node_handler_id: String::new(),
+            request_timeout: 30,
+            preserve_host_header: default_preserve_host_header(),
+        }
+    }
+}
+
+impl Default for NodeProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProcessorTrait for NodeProcessor {
+    fn initialize(&mut self) {}
+
+    fn sanitize(&mut self) {
+        self.node_handler_id = self.node_handler_id.trim().to_string();
+    }
+
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.node_handler_id.is_empty() {
+            errors.push("Node.js handler must be selected.".to_string());
+        }
+
+        if self.request_timeout < 1 {
+            errors.push("Request timeout must be at least 1 second.".to_string());
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    async fn handle_request(&self, gruxi_request: &mut GruxiRequest, site: &Site) -> Result<GruxiResponse, GruxiError> {
+        let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+
+        // Find the port the application is listening on
+        let port = match running_state.get_external_system_handler().get_port_for_node_app(&self.node_handler_id) {
+            Some(port) => port,
+            None => {
+                error(format!("Node.js Processor: Cannot find port for Node.js handler ID: {}", self.node_handler_id));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::NodeProcessor(NodeProcessorError::HandlerUnavailable)));
+            }
+        };
+
+        let upstream_url = format!("http://127.0.0.1:{}{}", port, gruxi_request.get_uri());
+        let upstream_uri: hyper::Uri = match upstream_url.parse() {
+            Ok(uri) => uri,
+            Err(e) => {
+                error(format!("Node.js Processor: Could not parse upstream URL '{}': {:?}", upstream_url, e));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::NodeProcessor(NodeProcessorError::Internal)));
+            }
+        };
+
+        trace(format!("Node.js Processor: Forwarding request to Node.js application at {}", upstream_uri));
+
+        // Counted in the handler status until the response is back, for the queue depth and handling time
+        let _handler_request_guard = running_state
+            .get_external_system_handler()
+            .get_handler_status(&self.node_handler_id)
+            .map(|status| status.begin_request());
+
+        // Only handlers limiting their concurrent requests have a queue, where the request waits for a free slot
+        let mut _queue_permit = None;
+        if let Some(request_queue) = running_state.get_external_system_handler().get_request_queue(&self.node_handler_id) {
+            match request_queue.acquire().await {
+                Ok(permit) => _queue_permit = Some(permit),
+                Err(e) => {
+                    warn(format!("Node.js Processor: Node.js handler ID {} is saturated, request is rejected: {:?}", self.node_handler_id, e));
+                    return Err(GruxiError::new_with_kind_only(GruxiErrorKind::NodeProcessor(NodeProcessorError::Overloaded)));
+                }
+            }
+        }
+
+        let result = ProxyProcessor::forward_request_to_upstream(
+            gruxi_request,
+            site,
+            upstream_uri,
+            running_state.get_http_client().get_client(false),
+            self.preserve_host_header,
+            "",
+            &UpstreamLimits::new(self.request_timeout as u64),
+        )
+        .await;
+
+        result.map_err(|e| {
+            let node_error = match e {
+                ProxyProcessorError::ConnectionFailed => NodeProcessorError::ConnectionFailed,
+                ProxyProcessorError::UpstreamTimeout => NodeProcessorError::Timeout,
+                ProxyProcessorError::TooManyWebSocketConnections => NodeProcessorError::Overloaded,
+                // Statuses about the request itself are the same as for the proxy
+                ProxyProcessorError::RequestBodyTooLarge | ProxyProcessorError::ResponseBodyTooLarge | ProxyProcessorError::ClientTimeout => {
+                    return GruxiError::new_with_kind_only(GruxiErrorKind::ProxyProcessor(e));
+                }
+                _ => NodeProcessorError::Internal,
+            };
+            GruxiError::new_with_kind_only(GruxiErrorKind::NodeProcessor(node_error))
+        })
+    }
+
+    fn get_type(&self) -> String {
+        "node".to_string()
+    }
+
+    fn get_default_pretty_name(&self) -> String {
+        "Node.js Processor".to_string()
+    }
+}
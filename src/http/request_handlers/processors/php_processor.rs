@@ -3,25 +3,30 @@ use std::time::Duration;
 use crate::error::gruxi_error::GruxiError;
 use crate::error::gruxi_error_enums::{GruxiErrorKind, PHPProcessorError};
 use crate::external_connections::fastcgi::FastCgi;
+use crate::external_connections::fastcgi_connection_pool::FASTCGI_UNIX_SOCKET_PREFIX;
 use crate::file::normalized_path::NormalizedPath;
 use crate::http::http_util::resolve_web_root_and_path_and_get_file;
 use crate::http::request_response::gruxi_response::GruxiResponse;
-use crate::logging::syslog::{debug, error, trace};
+use crate::logging::syslog::{debug, error, trace, warn};
 use crate::{
     configuration::site::Site,
     core::running_state_manager::get_running_state_manager,
     http::{http_util::empty_response_with_status, request_handlers::processor_trait::ProcessorTrait, request_response::gruxi_request::GruxiRequest},
 };
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct PHPProcessor {
     pub id: String, // Unique identifier for the processor
     // Can either be served by a local PHP-CGI executable or via FastCGI (PHP-FPM or similar)
     pub served_by_type: String,      // How it is handled, by Gruxi handled "win-php-cgi" or "php-fpm"
     pub php_cgi_handler_id: String,  // Optional ID of the PHP-CGI handler to use, if user has selected "win-php-cgi" as the type
-    pub fastcgi_ip_and_port: String, // Optional IP and port to connect to FastCGI handler, if user has selected "php-fpm" as the type
+    pub fastcgi_ip_and_port: String, // Optional IP and port (or "unix:/path/to/socket") to connect to FastCGI handler, if user has selected "php-fpm" as the type
+    // Max idle FastCGI connections kept open for reuse with "php-fpm", 0 disables keep-alive of connections
+    #[serde(default = "default_fastcgi_pool_size")]
+    pub fastcgi_pool_size: u32,
     // Request timeout, that may be different from the global timeout
     pub request_timeout: u32, // Seconds
     // Web root
@@ -37,6 +42,10 @@ pub struct PHPProcessor {
     normalized_fastcgi_web_root: Option<NormalizedPath>,
 }
 
+fn default_fastcgi_pool_size() -> u32 {
+    8
+}
+
 impl PHPProcessor {
     pub fn new() -> Self {
         Self {
@@ -44,6 +53,7 @@ impl PHPProcessor {
             served_by_type: "php-fpm".to_string(),
             php_cgi_handler_id: String::new(),
             fastcgi_ip_and_port: String::new(),
+            fastcgi_pool_size: default_fastcgi_pool_size(),
             request_timeout: 30,
             local_web_root: String::new(),
             fastcgi_web_root: String::new(),
@@ -113,6 +123,29 @@ impl ProcessorTrait for PHPProcessor {
             errors.push("PHP Processor: FastCGI IP and port must be set when served by PHP-FPM.".to_string());
         }
 
+        // fastcgi_ip_and_port must be either a unix socket or an IP and port
+        if self.served_by_type == "php-fpm" && !self.fastcgi_ip_and_port.trim().is_empty() {
+            match self.fastcgi_ip_and_port.strip_prefix(FASTCGI_UNIX_SOCKET_PREFIX) {
+                Some(socket_path) if socket_path.trim().is_empty() => {
+                    errors.push("PHP Processor: FastCGI unix socket path must be set after 'unix:'.".to_string());
+                }
+                Some(_) if !cfg!(unix) => {
+                    errors.push("PHP Processor: FastCGI unix sockets are not supported on this platform.".to_string());
+                }
+                Some(_) => {}
+                None => {
+                    if !self.fastcgi_ip_and_port.contains(':') {
+                        errors.push(format!("PHP Processor: FastCGI address must be IP:port or unix:/path/to/socket: {}", self.fastcgi_ip_and_port));
+                    }
+                }
+            }
+        }
+
+        // Pool size is kept reasonable, as each idle connection occupies a PHP-FPM worker
+        if self.fastcgi_pool_size > 256 {
+            errors.push("PHP Processor: FastCGI connection pool size cannot be more than 256.".to_string());
+        }
+
         // Request time must be greater than 0
         if self.request_timeout < 1 {
             errors.push("PHP Processor: Request timeout must be greater than 0.".to_string());
@@ -254,20 +287,48 @@ impl ProcessorTrait for PHPProcessor {
             }
         };
 
-        // Figure out if we have a connection semaphore to use
+        // Managed PHP-CGI handlers limit the requests in flight, with a bounded queue in front of them
+        let mut _handler_request_guard = None;
+        let mut handler_status_option = None;
+        let mut request_queue_option = None;
         if !self.php_cgi_handler_id.trim().is_empty() {
             let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
             let external_system_handler = running_state.get_external_system_handler();
 
-            let semaphore_option = external_system_handler.get_connection_semaphore(&self.php_cgi_handler_id);
-            let connection_semaphore = match semaphore_option {
-                Some(semaphore) => semaphore,
+            // The handler is taken out of service while it keeps crashing, so answer right away instead of waiting on it
+            if self.served_by_type == "win-php-cgi" && external_system_handler.is_php_cgi_circuit_open(&self.php_cgi_handler_id) {
+                trace(format!("PHP Processor: PHP-CGI handler ID {} is out of service, request is not served", self.php_cgi_handler_id));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::PHPProcessor(PHPProcessorError::Unavailable)));
+            }
+
+            request_queue_option = match external_system_handler.get_request_queue(&self.php_cgi_handler_id) {
+                Some(request_queue) => Some(request_queue),
                 None => {
-                    error(format!("PHP Processor: Cannot find connection semaphore for PHP-CGI handler ID: {}", self.php_cgi_handler_id));
+                    error(format!("PHP Processor: Cannot find request queue for PHP-CGI handler ID: {}", self.php_cgi_handler_id));
                     return Err(GruxiError::new_with_kind_only(GruxiErrorKind::PHPProcessor(PHPProcessorError::Internal)));
                 }
             };
-            gruxi_request.set_connection_semaphore(connection_semaphore);
+
+            handler_status_option = external_system_handler.get_handler_status(&self.php_cgi_handler_id);
+        }
+
+        // Held while the PHP-CGI process is recycled, then counted in the handler status until the response is back, for
+        // the queue depth and handling time
+        if let Some(handler_status) = handler_status_option {
+            handler_status.wait_while_recycling().await;
+            _handler_request_guard = Some(handler_status.begin_request());
+        }
+
+        // Waiting in the queue happens without holding the running state, so it can be replaced meanwhile
+        let mut _queue_permit = None;
+        if let Some(request_queue) = request_queue_option {
+            match request_queue.acquire().await {
+                Ok(permit) => _queue_permit = Some(permit),
+                Err(e) => {
+                    warn(format!("PHP Processor: PHP-CGI handler ID {} is saturated, request is rejected: {:?}", self.php_cgi_handler_id, e));
+                    return Err(GruxiError::new_with_kind_only(GruxiErrorKind::PHPProcessor(PHPProcessorError::Overloaded)));
+                }
+            }
         }
 
         // So now we have everything we need to handle the request, so we pass it to the FastCGI handler
@@ -279,6 +340,15 @@ impl ProcessorTrait for PHPProcessor {
         gruxi_request.add_calculated_data("fastcgi_local_web_root", &local_web_root);
         gruxi_request.add_calculated_data("fastcgi_web_root", &fastcgi_web_root);
         gruxi_request.add_calculated_data("fastcgi_override_server_software", &self.server_software_spoof);
+        // Per-site PHP settings, so sites sharing a handler can still have different limits. PHP_VALUE is honored by php-fpm, php-cgi ignores it
+        gruxi_request.add_calculated_data("fastcgi_php_value", &site.get_php_ini_values(false));
+        gruxi_request.add_calculated_data("fastcgi_php_admin_value", &site.get_php_ini_values(true));
+        if !site.php_environment.is_empty() {
+            gruxi_request.add_calculated_data("fastcgi_environment", &serde_json::to_string(&site.php_environment).unwrap_or_default());
+        }
+        // Connections are only pooled for external FastCGI servers, as the managed PHP-CGI limits connections with its request queue
+        let fastcgi_pool_size = if self.served_by_type == "php-fpm" { self.fastcgi_pool_size } else { 0 };
+        gruxi_request.add_calculated_data("fastcgi_pool_size", &fastcgi_pool_size.to_string());
 
         // Process the FastCGI request with timeout
         match tokio::time::timeout(Duration::from_secs(self.request_timeout as u64), FastCgi::process_fastcgi_request(gruxi_request)).await {
@@ -1,7 +1,8 @@
 use std::collections::HashMap;
 
 use crate::http::request_handlers::processors::{
-    load_balancer::load_balancer::LoadBalancerRegistry, php_processor::PHPProcessor, proxy_processor::ProxyProcessor, static_files_processor::StaticFileProcessor,
+    cgi_processor::CgiProcessor, image_processor::ImageProcessor, load_balancer::load_balancer::LoadBalancerRegistry, node_processor::NodeProcessor, php_processor::PHPProcessor,
+    proxy_processor::ProxyProcessor, python_processor::PythonProcessor, static_files_processor::StaticFileProcessor, webdav_processor::WebDavProcessor,
 };
 
 pub struct ProcessorManager {
@@ -9,6 +10,11 @@ pub struct ProcessorManager {
     pub static_file_processors: HashMap<String, StaticFileProcessor>,
     pub php_processors: HashMap<String, PHPProcessor>,
     pub proxy_processors: HashMap<String, ProxyProcessor>,
+    pub webdav_processors: HashMap<String, WebDavProcessor>,
+    pub cgi_processors: HashMap<String, CgiProcessor>,
+    pub python_processors: HashMap<String, PythonProcessor>,
+    pub node_processors: HashMap<String, NodeProcessor>,
+    pub image_processors: HashMap<String, ImageProcessor>,
     // Helpers for processors
     pub load_balancer_registry: LoadBalancerRegistry,
 }
@@ -22,6 +28,11 @@ impl ProcessorManager {
             static_file_processors: HashMap::new(),
             php_processors: HashMap::new(),
             proxy_processors: HashMap::new(),
+            webdav_processors: HashMap::new(),
+            cgi_processors: HashMap::new(),
+            python_processors: HashMap::new(),
+            node_processors: HashMap::new(),
+            image_processors: HashMap::new(),
             load_balancer_registry: LoadBalancerRegistry::new(),
         };
 
@@ -40,10 +51,36 @@ impl ProcessorManager {
             processor_manager.proxy_processors.insert(p.id.clone(), p.clone());
         });
 
+        // Insert the WebDAV processors from config
+        config.webdav_processors.iter().for_each(|p| {
+            processor_manager.webdav_processors.insert(p.id.clone(), p.clone());
+        });
+
+        // Insert the CGI processors from config
+        config.cgi_processors.iter().for_each(|p| {
+            processor_manager.cgi_processors.insert(p.id.clone(), p.clone());
+        });
+
+        // Insert the Python processors from config
+        config.python_processors.iter().for_each(|p| {
+            processor_manager.python_processors.insert(p.id.clone(), p.clone());
+        });
+
+        // Insert the Node.js processors from config
+        config.node_processors.iter().for_each(|p| {
+            processor_manager.node_processors.insert(p.id.clone(), p.clone());
+        });
+
+        // Insert the image processors from config
+        config.image_processors.iter().for_each(|p| {
+            processor_manager.image_processors.insert(p.id.clone(), p.clone());
+        });
+
         // Create load balancers for proxy processors
         for proxy_processor in processor_manager.proxy_processors.values() {
-            let lb = proxy_processor.get_load_balancer_service();
-            processor_manager.load_balancer_registry.create(proxy_processor.id.clone(), lb).await;
+            for (load_balancer_id, lb) in proxy_processor.get_load_balancer_services() {
+                processor_manager.load_balancer_registry.create(load_balancer_id, lb).await;
+            }
         }
 
         processor_manager
@@ -60,4 +97,24 @@ impl ProcessorManager {
     pub fn get_proxy_processor_by_id(&self, processor_id: &String) -> Option<&ProxyProcessor> {
         self.proxy_processors.get(processor_id)
     }
+
+    pub fn get_webdav_processor_by_id(&self, processor_id: &String) -> Option<&WebDavProcessor> {
+        self.webdav_processors.get(processor_id)
+    }
+
+    pub fn get_cgi_processor_by_id(&self, processor_id: &String) -> Option<&CgiProcessor> {
+        self.cgi_processors.get(processor_id)
+    }
+
+    pub fn get_python_processor_by_id(&self, processor_id: &String) -> Option<&PythonProcessor> {
+        self.python_processors.get(processor_id)
+    }
+
+    pub fn get_node_processor_by_id(&self, processor_id: &String) -> Option<&NodeProcessor> {
+        self.node_processors.get(processor_id)
+    }
+
+    pub fn get_image_processor_by_id(&self, processor_id: &String) -> Option<&ImageProcessor> {
+        self.image_processors.get(processor_id)
+    }
 }
@@ -0,0 +1,258 @@
+use std::error::Error as StdError;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use http_body_util::combinators::BoxBody;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+use tokio::time::Sleep;
+use tokio_util::sync::CancellationToken;
+
+use crate::http::request_response::body_error::BodyError;
+
+// Limits on a single exchange with an upstream. A value of 0 means no limit
+#[derive(Clone, Debug, PartialEq)]
+pub struct UpstreamLimits {
+    pub header_timeout_seconds: u64,  // For the upstream to send its response headers
+    pub total_timeout_seconds: u64,   // For the whole exchange, including relaying the response body
+    pub max_request_body_bytes: u64,  // Largest request body forwarded to the upstream, larger ones get 413
+    pub max_response_body_bytes: u64, // Largest response body relayed to the client, larger ones get 502 or are cut off
+}
+
+impl UpstreamLimits {
+    // Only a header timeout, as used by the processors of locally managed application servers
+    pub fn new(header_timeout_seconds: u64) -> Self {
+        Self {
+            header_timeout_seconds,
+            total_timeout_seconds: 0,
+            max_request_body_bytes: 0,
+            max_response_body_bytes: 0,
+        }
+    }
+
+    // How long to wait for the response headers, which is bound by the total timeout as well
+    pub fn get_header_timeout(&self) -> Option<Duration> {
+        match (self.header_timeout_seconds, self.total_timeout_seconds) {
+            (0, 0) => None,
+            (0, total) => Some(Duration::from_secs(total)),
+            (header, 0) => Some(Duration::from_secs(header)),
+            (header, total) => Some(Duration::from_secs(header.min(total))),
+        }
+    }
+}
+
+// Shared between the request body forwarded to the upstream and the exchange waiting on it
+pub struct RequestBodyState {
+    finished: AtomicBool,
+    too_large: CancellationToken,
+}
+
+impl RequestBodyState {
+    // Whether the client has sent its whole request body, so a timeout is on the upstream and not on the client
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    // Cancelled when the client sends more than the maximum request body size
+    pub fn get_too_large_token(&self) -> CancellationToken {
+        self.too_large.clone()
+    }
+}
+
+// Request body that keeps track of how much the client has sent. When it goes over the limit the body stalls and the
+// exchange is cancelled through the state, so the client gets 413 instead of the upstream a truncated body
+pub struct LimitedRequestBody {
+    inner: BoxBody<Bytes, BodyError>,
+    max_bytes: u64,
+    received_bytes: u64,
+    state: Arc<RequestBodyState>,
+}
+
+impl LimitedRequestBody {
+    pub fn new(inner: BoxBody<Bytes, BodyError>, max_bytes: u64) -> Self {
+        let state = Arc::new(RequestBodyState {
+            finished: AtomicBool::new(inner.is_end_stream()),
+            too_large: CancellationToken::new(),
+        });
+        Self {
+            inner,
+            max_bytes,
+            received_bytes: 0,
+            state,
+        }
+    }
+
+    pub fn get_state(&self) -> Arc<RequestBodyState> {
+        self.state.clone()
+    }
+}
+
+impl Body for LimitedRequestBody {
+    type Data = Bytes;
+    type Error = BodyError;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if self.state.too_large.is_cancelled() {
+            return Poll::Pending;
+        }
+
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    self.received_bytes += data.len() as u64;
+                    if self.max_bytes > 0 && self.received_bytes > self.max_bytes {
+                        self.state.too_large.cancel();
+                        return Poll::Pending;
+                    }
+                }
+                if self.inner.is_end_stream() {
+                    self.state.finished.store(true, Ordering::Relaxed);
+                }
+            }
+            Poll::Ready(None) => self.state.finished.store(true, Ordering::Relaxed),
+            _ => {}
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[derive(Debug)]
+enum ResponseLimitError {
+    TooLarge(u64),
+    TimedOut(u64),
+}
+
+impl std::fmt::Display for ResponseLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResponseLimitError::TooLarge(max_bytes) => write!(f, "Upstream response body is larger than the maximum of {} bytes", max_bytes),
+            ResponseLimitError::TimedOut(seconds) => write!(f, "Upstream response was not relayed within the total timeout of {} seconds", seconds),
+        }
+    }
+}
+
+impl StdError for ResponseLimitError {}
+
+// Response body that is cut off with an error when it goes over the maximum size or the total timeout, as the
+// status has already been sent by then
+pub struct LimitedResponseBody {
+    inner: BoxBody<Bytes, BodyError>,
+    max_bytes: u64,
+    sent_bytes: u64,
+    total_timeout_seconds: u64,
+    deadline: Option<Pin<Box<Sleep>>>,
+}
+
+impl LimitedResponseBody {
+    pub fn new(inner: BoxBody<Bytes, BodyError>, max_bytes: u64, deadline: Option<tokio::time::Instant>, total_timeout_seconds: u64) -> Self {
+        Self {
+            inner,
+            max_bytes,
+            sent_bytes: 0,
+            total_timeout_seconds,
+            deadline: deadline.map(|deadline| Box::pin(tokio::time::sleep_until(deadline))),
+        }
+    }
+}
+
+impl Body for LimitedResponseBody {
+    type Data = Bytes;
+    type Error = BodyError;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let total_timeout_seconds = self.total_timeout_seconds;
+        if let Some(deadline) = self.deadline.as_mut()
+            && deadline.as_mut().poll(cx).is_ready()
+        {
+            self.deadline = None;
+            return Poll::Ready(Some(Err(Box::new(ResponseLimitError::TimedOut(total_timeout_seconds)))));
+        }
+
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll
+            && let Some(data) = frame.data_ref()
+        {
+            self.sent_bytes += data.len() as u64;
+            if self.max_bytes > 0 && self.sent_bytes > self.max_bytes {
+                return Poll::Ready(Some(Err(Box::new(ResponseLimitError::TooLarge(self.max_bytes)))));
+            }
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+// Whether a failed upstream request ran into the connect timeout, rather than being refused or failing otherwise
+pub fn is_connect_timeout(error: &hyper_util::client::legacy::Error) -> bool {
+    if !error.is_connect() {
+        return false;
+    }
+    let mut source = error.source();
+    while let Some(inner) = source {
+        if let Some(io_error) = inner.downcast_ref::<std::io::Error>()
+            && io_error.kind() == std::io::ErrorKind::TimedOut
+        {
+            return true;
+        }
+        source = inner.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full, StreamBody};
+
+    fn full_body(data: &'static [u8]) -> BoxBody<Bytes, BodyError> {
+        BoxBody::new(Full::new(Bytes::from_static(data)).map_err(|never| -> BodyError { match never {} }))
+    }
+
+    #[test]
+    fn test_upstream_header_timeout_is_bound_by_total_timeout() {
+        let mut limits = UpstreamLimits::new(0);
+        assert_eq!(limits.get_header_timeout(), None);
+        limits.total_timeout_seconds = 60;
+        assert_eq!(limits.get_header_timeout(), Some(Duration::from_secs(60)));
+        limits.header_timeout_seconds = 30;
+        assert_eq!(limits.get_header_timeout(), Some(Duration::from_secs(30)));
+        limits.total_timeout_seconds = 10;
+        assert_eq!(limits.get_header_timeout(), Some(Duration::from_secs(10)));
+    }
+
+    #[tokio::test]
+    async fn test_limited_response_body() {
+        let body = LimitedResponseBody::new(full_body(b"0123456789"), 10, None, 0);
+        assert_eq!(body.collect().await.unwrap().to_bytes(), Bytes::from_static(b"0123456789"));
+
+        let body = LimitedResponseBody::new(full_body(b"0123456789"), 9, None, 0);
+        let error = body.collect().await.unwrap_err();
+        assert_eq!(error.to_string(), "Upstream response body is larger than the maximum of 9 bytes");
+
+        // An upstream that stalls is cut off at the deadline
+        let stalled_body = BoxBody::new(StreamBody::new(futures::stream::pending::<Result<Frame<Bytes>, BodyError>>()));
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(10);
+        let body = LimitedResponseBody::new(stalled_body, 0, Some(deadline), 5);
+        let error = body.collect().await.unwrap_err();
+        assert_eq!(error.to_string(), "Upstream response was not relayed within the total timeout of 5 seconds");
+    }
+}
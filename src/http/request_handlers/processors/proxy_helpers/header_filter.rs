@@ -0,0 +1,144 @@
+use hyper::HeaderMap;
+use hyper::header::HeaderName;
+
+// Headers that frame the message or are set by Gruxi itself, which are never removed by the filters,
+// as the exchange would break without them. "Sec-WebSocket-" headers are kept for WebSocket upgrades
+const PROTECTED_HEADERS: [&str; 12] = [
+    "host",
+    "content-length",
+    "content-type",
+    "content-encoding",
+    "transfer-encoding",
+    "connection",
+    "upgrade",
+    "x-forwarded-for",
+    "x-forwarded-proto",
+    "x-forwarded-host",
+    "forwarded",
+    "sec-websocket-*",
+];
+
+// Which headers are passed between clients and upstreams. An empty allow list passes all headers, otherwise only the
+// listed headers pass. Listed names are case insensitive and can end in '*' to match a prefix, such as "X-Debug-*"
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UpstreamHeaderFilter {
+    pub request_allowed: Vec<String>,  // Client request headers that reach the upstream
+    pub request_denied: Vec<String>,   // Client request headers that are removed before the upstream
+    pub response_allowed: Vec<String>, // Upstream response headers that reach the client
+    pub response_denied: Vec<String>,  // Upstream response headers that are removed, such as "Server" and "X-Powered-By"
+}
+
+impl UpstreamHeaderFilter {
+    // No filtering, as used by the processors of locally managed application servers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The client request headers that do not reach the upstream
+    pub fn get_removed_request_headers(&self, headers: &HeaderMap) -> Vec<HeaderName> {
+        get_removed_headers(headers, &self.request_allowed, &self.request_denied)
+    }
+
+    pub fn filter_response_headers(&self, headers: &mut HeaderMap) {
+        for name in get_removed_headers(headers, &self.response_allowed, &self.response_denied) {
+            headers.remove(name);
+        }
+    }
+
+    // Lowercase the patterns, for matching against header names as hyper keeps them
+    pub fn sanitize(&mut self) {
+        for list in [&mut self.request_allowed, &mut self.request_denied, &mut self.response_allowed, &mut self.response_denied] {
+            *list = list.iter().map(|pattern| pattern.trim().to_lowercase()).filter(|pattern| !pattern.is_empty()).collect();
+        }
+    }
+
+    pub fn validate(&self, errors: &mut Vec<String>) {
+        let lists = [
+            ("request header allow list", &self.request_allowed),
+            ("request header deny list", &self.request_denied),
+            ("response header allow list", &self.response_allowed),
+            ("response header deny list", &self.response_denied),
+        ];
+        for (list_name, patterns) in lists {
+            for pattern in patterns {
+                let name = pattern.strip_suffix('*').unwrap_or(pattern);
+                if name.is_empty() || HeaderName::from_bytes(name.as_bytes()).is_err() {
+                    errors.push(format!("'{}' in the {} is not a valid header name, optionally ending in '*'.", pattern, list_name));
+                }
+            }
+        }
+        for pattern in self.request_denied.iter().chain(&self.response_denied) {
+            if PROTECTED_HEADERS
+                .iter()
+                .any(|protected| matches_pattern(protected.trim_end_matches('*'), pattern) || matches_pattern(pattern, protected))
+            {
+                errors.push(format!("Header '{}' cannot be denied, as it is needed to relay the request or is set by Gruxi.", pattern));
+            }
+        }
+    }
+}
+
+fn matches_pattern(name: &str, pattern: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+fn get_removed_headers(headers: &HeaderMap, allowed: &[String], denied: &[String]) -> Vec<HeaderName> {
+    if allowed.is_empty() && denied.is_empty() {
+        return Vec::new();
+    }
+    headers
+        .keys()
+        .filter(|name| {
+            let name = name.as_str();
+            if PROTECTED_HEADERS.iter().any(|protected| matches_pattern(name, protected)) {
+                return false;
+            }
+            let is_allowed = allowed.is_empty() || allowed.iter().any(|pattern| matches_pattern(name, pattern));
+            !is_allowed || denied.iter().any(|pattern| matches_pattern(name, pattern))
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_headers(names: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for name in names {
+            headers.insert(HeaderName::from_bytes(name.as_bytes()).unwrap(), "value".parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_header_filter() {
+        let mut filter = UpstreamHeaderFilter::new();
+        filter.response_denied = vec![" Server".to_string(), "X-Powered-By".to_string(), "X-Debug-*".to_string()];
+        filter.request_allowed = vec!["accept*".to_string(), "authorization".to_string()];
+        filter.sanitize();
+
+        let mut errors = Vec::new();
+        filter.validate(&mut errors);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let mut response_headers = create_headers(&["server", "x-powered-by", "x-debug-trace", "x-debugger", "content-type", "cache-control"]);
+        filter.filter_response_headers(&mut response_headers);
+        let mut names: Vec<&str> = response_headers.keys().map(|name| name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["cache-control", "content-type", "x-debugger"]);
+
+        // The allow list keeps the headers needed to relay the request, and those set by Gruxi
+        let request_headers = create_headers(&["host", "accept", "accept-language", "authorization", "cookie", "x-forwarded-for", "sec-websocket-key"]);
+        assert_eq!(filter.get_removed_request_headers(&request_headers), vec![HeaderName::from_static("cookie")]);
+
+        filter.request_denied = vec!["content-length".to_string(), "sec-*".to_string(), "bad header".to_string()];
+        let mut errors = Vec::new();
+        filter.validate(&mut errors);
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+    }
+}
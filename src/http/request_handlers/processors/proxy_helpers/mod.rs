@@ -1 +1,3 @@
-pub mod no_verifier;
\ No newline at end of file
+pub mod body_limits;
+pub mod header_filter;
+pub mod no_verifier;
@@ -8,36 +8,56 @@ use crate::{
         gruxi_error_enums::{GruxiErrorKind, ProxyProcessorError},
     },
     http::{
+        client::http_client::{UpstreamClient, UpstreamClientSettings, UpstreamPoolSettings, UpstreamTlsSettings},
+        long_running_connections::relay_tunnel,
         request_handlers::{
             processor_trait::ProcessorTrait,
-            processors::load_balancer::{load_balancer::LoadBalancerImpl, round_robin::RoundRobin},
+            processors::{
+                load_balancer::{load_balancer::LoadBalancerImpl, round_robin::RoundRobin},
+                proxy_helpers::{
+                    body_limits::{LimitedRequestBody, LimitedResponseBody, UpstreamLimits, is_connect_timeout},
+                    header_filter::UpstreamHeaderFilter,
+                },
+            },
         },
         request_response::{gruxi_request::GruxiRequest, gruxi_response::GruxiResponse},
+        websocket_relay::{WebSocketConnectionSlot, is_websocket_upgrade, relay_websocket},
     },
-    logging::syslog::{error, trace},
+    logging::syslog::{error, trace, warn},
 };
 use http::HeaderValue;
+use http_body_util::combinators::BoxBody;
 use hyper::Response;
+use hyper::body::Body;
 use hyper_util::rt::TokioIo;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use tokio::time::timeout;
 use uuid::Uuid;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct ProxyProcessorRewrite {
     pub from: String,
     pub to: String,
     pub is_case_insensitive: bool,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+// Upstream servers that get a share of the traffic instead of the main upstream servers, such as a canary of a new version
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct ProxyUpstreamGroup {
+    pub name: String,                  // Name of the group, such as "canary", which testers can ask for with the override header or cookie
+    pub upstream_servers: Vec<String>, // Upstream servers of the group, load balanced as the main upstream servers
+    pub traffic_percent: u8,           // Share of the requests sent to the group, 0 for only the requests asking for it
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct ProxyProcessor {
     pub id: String,         // Unique identifier for the processor
     pub proxy_type: String, // e.g., "http", for further extension
     // HTTP Proxy specific settings
     pub upstream_servers: Vec<String>,   // List of upstream servers e.g., ["http://server1:8080", "https://server2:8080"]
     pub load_balancing_strategy: String, // e.g., "round_robin" only for now
-    pub timeout_seconds: u16,            // Timeout for upstream requests, in seconds
+    pub timeout_seconds: u16,            // Timeout for the upstream to send its response headers, in seconds
     // Health check settings
     pub health_check_path: String,          // Path to use for health checks, if empty, we dont do health checks
     pub health_check_interval_seconds: u32, // Interval between health checks, in seconds
@@ -49,8 +69,56 @@ pub struct ProxyProcessor {
     pub forced_host_header: String, // If set, this host header will be used instead of the original request's Host header, disregarding preserve_host_header - normally not recommended for normal use
     // SSL/TLS settings
     pub verify_tls_certificates: bool, // Whether to verify TLS certificates (set to false for self-signed certs)
+    #[serde(default)]
+    pub tls_ca_bundle_path: String, // PEM file with the CA certificates that upstream certificates are verified against, instead of the system roots
+    #[serde(default)]
+    pub tls_server_name: String, // Server name sent as SNI and verified in the upstream certificate, instead of the host of the upstream URL
+    #[serde(default)]
+    pub tls_client_cert_path: String, // PEM client certificate, for upstreams that require mutual TLS
+    #[serde(default)]
+    pub tls_client_key_path: String, // PEM private key of the client certificate
+    // Streaming, for Server-Sent Events and long-polling endpoints
+    #[serde(default)]
+    pub streaming_paths: Vec<String>, // Path prefixes with long-lived responses, such as "/events", which are never buffered
+    #[serde(default)]
+    pub streaming_timeout_seconds: u32, // Timeout for the upstream to start responding on streaming paths, instead of timeout_seconds. 0 waits forever
+    // Connection pooling, so keep-alive connections to the upstream are reused
+    #[serde(default)]
+    pub pool_max_idle_per_host: u32, // Idle connections kept open per upstream server, 0 for no limit
+    #[serde(default)]
+    pub pool_idle_timeout_seconds: u32, // How long an idle connection is kept open, 0 for the default of 90 seconds
+    #[serde(default)]
+    pub upstream_http2_only: bool, // Only speak HTTP/2 to the upstream servers, also over plain http, such as for gRPC. WebSocket upgrades need HTTP/1.1
+    // Limits on the proxied traffic, 0 for no limit
+    #[serde(default)]
+    pub connect_timeout_seconds: u32, // Timeout for connecting to an upstream server, which gives 504 instead of waiting the whole header timeout
+    #[serde(default)]
+    pub total_timeout_seconds: u32, // Timeout for the whole request, including relaying the response body. Not applied on streaming paths
+    #[serde(default)]
+    pub max_request_body_bytes: u64, // Largest request body forwarded to the upstream, larger ones get 413
+    #[serde(default)]
+    pub max_response_body_bytes: u64, // Largest response body relayed to the client, larger ones get 502 or are cut off when already streaming
+    // Traffic splitting, the main upstream servers get the requests not sent to a group
+    #[serde(default)]
+    pub upstream_groups: Vec<ProxyUpstreamGroup>,
+    #[serde(default)]
+    pub upstream_group_header: String, // Request header that picks a group by name, or "main" for the main upstream servers, such as for testers
+    #[serde(default)]
+    pub upstream_group_cookie: String, // Cookie that picks a group by name in the same way, so testers can stick to a group in their browser
+    // Which headers pass between clients and upstreams. An empty allow list passes all headers. Names can end in '*' to match a prefix
+    #[serde(default)]
+    pub request_headers_allowed: Vec<String>,
+    #[serde(default)]
+    pub request_headers_denied: Vec<String>,
+    #[serde(default)]
+    pub response_headers_allowed: Vec<String>,
+    #[serde(default)]
+    pub response_headers_denied: Vec<String>, // Such as "Server", "X-Powered-By" and internal debugging headers
 }
 
+// Name of the main upstream servers in the override header and cookie
+pub const MAIN_UPSTREAM_GROUP: &str = "main";
+
 impl ProxyProcessor {
     pub fn new() -> Self {
         Self {
@@ -66,7 +134,125 @@ impl ProxyProcessor {
             preserve_host_header: false,
             forced_host_header: "".to_string(),
             verify_tls_certificates: true,
+            tls_ca_bundle_path: "".to_string(),
+            tls_server_name: "".to_string(),
+            tls_client_cert_path: "".to_string(),
+            tls_client_key_path: "".to_string(),
+            streaming_paths: Vec::new(),
+            streaming_timeout_seconds: 0,
+            pool_max_idle_per_host: 0,
+            pool_idle_timeout_seconds: 0,
+            upstream_http2_only: false,
+            connect_timeout_seconds: 0,
+            total_timeout_seconds: 0,
+            max_request_body_bytes: 0,
+            max_response_body_bytes: 0,
+            upstream_groups: Vec::new(),
+            upstream_group_header: "".to_string(),
+            upstream_group_cookie: "".to_string(),
+            request_headers_allowed: Vec::new(),
+            request_headers_denied: Vec::new(),
+            response_headers_allowed: Vec::new(),
+            response_headers_denied: Vec::new(),
+        }
+    }
+
+    pub fn get_header_filter(&self) -> UpstreamHeaderFilter {
+        UpstreamHeaderFilter {
+            request_allowed: self.request_headers_allowed.clone(),
+            request_denied: self.request_headers_denied.clone(),
+            response_allowed: self.response_headers_allowed.clone(),
+            response_denied: self.response_headers_denied.clone(),
+        }
+    }
+
+    pub fn get_upstream_tls_settings(&self) -> UpstreamTlsSettings {
+        UpstreamTlsSettings {
+            verify_certificates: self.verify_tls_certificates,
+            ca_bundle_path: self.tls_ca_bundle_path.clone(),
+            server_name: self.tls_server_name.clone(),
+            client_cert_path: self.tls_client_cert_path.clone(),
+            client_key_path: self.tls_client_key_path.clone(),
+        }
+    }
+
+    pub fn get_upstream_client_settings(&self) -> UpstreamClientSettings {
+        UpstreamClientSettings {
+            tls: self.get_upstream_tls_settings(),
+            pool: UpstreamPoolSettings {
+                max_idle_per_host: self.pool_max_idle_per_host,
+                idle_timeout_seconds: self.pool_idle_timeout_seconds,
+                http2_only: self.upstream_http2_only,
+                connect_timeout_seconds: self.connect_timeout_seconds,
+            },
+        }
+    }
+
+    // Long-lived responses on streaming paths have their own header timeout and no total timeout
+    pub fn get_upstream_limits(&self, is_streaming_path: bool) -> UpstreamLimits {
+        UpstreamLimits {
+            header_timeout_seconds: if is_streaming_path { self.streaming_timeout_seconds as u64 } else { self.timeout_seconds as u64 },
+            total_timeout_seconds: if is_streaming_path { 0 } else { self.total_timeout_seconds as u64 },
+            max_request_body_bytes: self.max_request_body_bytes,
+            max_response_body_bytes: self.max_response_body_bytes,
+        }
+    }
+
+    // The load balancer of the main upstream servers has the id of the processor, those of the groups have the group name added
+    pub fn get_load_balancer_id(&self, group: Option<&ProxyUpstreamGroup>) -> String {
+        match group {
+            Some(group) => format!("{}#{}", self.id, group.name),
+            None => self.id.clone(),
+        }
+    }
+
+    // The group a request is sent to, or None for the main upstream servers. Testers can pick a group with the override header
+    // or cookie, other requests are split by the traffic percentages of the groups
+    pub fn select_upstream_group(&self, gruxi_request: &GruxiRequest) -> Option<&ProxyUpstreamGroup> {
+        if self.upstream_groups.is_empty() {
+            return None;
+        }
+
+        let header_override = if self.upstream_group_header.is_empty() {
+            None
+        } else {
+            gruxi_request
+                .get_headers()
+                .get(self.upstream_group_header.as_str())
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_string())
+        };
+        let cookie_override = if self.upstream_group_cookie.is_empty() {
+            None
+        } else {
+            gruxi_request.get_cookie(&self.upstream_group_cookie)
+        };
+        if let Some(requested_group) = header_override.or(cookie_override) {
+            if requested_group.eq_ignore_ascii_case(MAIN_UPSTREAM_GROUP) {
+                return None;
+            }
+            if let Some(group) = self.upstream_groups.iter().find(|group| group.name.eq_ignore_ascii_case(&requested_group)) {
+                return Some(group);
+            }
         }
+
+        self.select_upstream_group_by_percent(rand::random_range(0..100))
+    }
+
+    // The group for a roll from 0 to 99, where the groups take their percentages in order and the main upstream servers the rest
+    fn select_upstream_group_by_percent(&self, roll: u8) -> Option<&ProxyUpstreamGroup> {
+        let mut threshold: u16 = 0;
+        for group in &self.upstream_groups {
+            threshold += group.traffic_percent as u16;
+            if (roll as u16) < threshold {
+                return Some(group);
+            }
+        }
+        None
+    }
+
+    pub fn is_streaming_path(&self, path: &str) -> bool {
+        self.streaming_paths.iter().any(|streaming_path| path.starts_with(streaming_path.as_str()))
     }
 
     pub fn apply_url_rewrites(&self, original_url: &str) -> String {
@@ -117,19 +303,216 @@ impl ProxyProcessor {
     }
 
     fn clean_hop_by_hop_headers_in_response(response: &mut Response<hyper::body::Incoming>, is_websocket_upgrade: bool) {
-        let hop_by_hop_headers = crate::http::http_util::get_list_of_hop_by_hop_headers(is_websocket_upgrade);
-        for header in &hop_by_hop_headers {
-            response.headers_mut().remove(header);
+        for header in crate::http::http_util::get_list_of_hop_by_hop_headers(is_websocket_upgrade) {
+            response.headers_mut().remove(*header);
         }
     }
 
-    pub fn get_load_balancer_service(&self) -> impl LoadBalancerImpl {
+    // Forward the request to the upstream URI with the client for the upstream, and return the upstream response, bridging
+    // protocol upgrades such as WebSockets within the WebSocket limits of the site. Shared with processors that proxy to
+    // locally managed application servers. The limits bound the body sizes and how long the upstream may take
+    pub async fn forward_request_to_upstream(
+        gruxi_request: &mut GruxiRequest,
+        site: &Site,
+        upstream_uri: hyper::Uri,
+        client: UpstreamClient,
+        preserve_host_header: bool,
+        forced_host_header: &str,
+        limits: &UpstreamLimits,
+    ) -> Result<GruxiResponse, ProxyProcessorError> {
+        // A request body that is known to be too large is rejected before bothering the upstream
+        let content_length = gruxi_request
+            .get_headers()
+            .get(hyper::header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        if limits.max_request_body_bytes > 0 && content_length.is_some_and(|length| length > limits.max_request_body_bytes) {
+            warn(format!(
+                "Request body of {} bytes is larger than the maximum of {} bytes for the upstream",
+                content_length.unwrap_or(0),
+                limits.max_request_body_bytes
+            ));
+            return Err(ProxyProcessorError::RequestBodyTooLarge);
+        }
+        let started_at = tokio::time::Instant::now();
+
+        // Get the client-side upgrade on the request side
+        let client_upgrade = gruxi_request.take_upgrade();
+
+        // WebSocket upgrades take a connection slot of the site up front, so the upstream is not bothered when the site is full
+        let websocket_slot = if client_upgrade.is_some() && is_websocket_upgrade(gruxi_request.get_headers()) {
+            match WebSocketConnectionSlot::acquire(&site.id, site.websocket.max_connections) {
+                Some(slot) => Some(slot),
+                None => {
+                    warn(format!(
+                        "WebSocket upgrade rejected, site '{}' already has the maximum of {} WebSocket connections",
+                        site.id, site.websocket.max_connections
+                    ));
+                    return Err(ProxyProcessorError::TooManyWebSocketConnections);
+                }
+            }
+        } else {
+            None
+        };
+
+        // Clean any hop by hop headers from the request and add forwarded headers
+        gruxi_request.clean_hop_by_hop_headers();
+        gruxi_request.add_forwarded_headers();
+
+        // Get the original request to extract headers and body
+        let mut proxy_request = gruxi_request.get_streaming_http_request();
+
+        // Update the URI to point to the upstream server (with full URL including scheme/host/port)
+        let upstream_uri_string = upstream_uri.to_string();
+        *proxy_request.uri_mut() = upstream_uri;
+
+        // Keep track of the request body, so a body over the limit is stopped and a slow client can be told apart from a slow upstream
+        let (request_parts, request_body) = proxy_request.into_parts();
+        let request_body = LimitedRequestBody::new(request_body, limits.max_request_body_bytes);
+        let request_body_state = request_body.get_state();
+        let request_body_too_large = request_body_state.get_too_large_token();
+        let mut proxy_request = hyper::Request::from_parts(request_parts, BoxBody::new(request_body));
+
+        // Check if we should preserve the host header or remote it to let hyper set it
+        if forced_host_header.is_empty() {
+            // Header is there already, so we only remove it if we are not preserving it
+            if !preserve_host_header {
+                proxy_request.headers_mut().remove(hyper::header::HOST);
+                trace("Not preserving original Host header for upstream request");
+            }
+        } else {
+            trace("Using forced Host header for upstream request");
+            if let Ok(header_value) = HeaderValue::from_str(forced_host_header) {
+                proxy_request.headers_mut().insert(hyper::header::HOST, header_value);
+            }
+        }
+
+        trace(format!("Forwarding request to upstream server: {:?}", proxy_request));
+
+        let upstream_request = async {
+            tokio::select! {
+                response = client.request(proxy_request) => Ok(response),
+                _ = request_body_too_large.cancelled() => Err(()),
+            }
+        };
+        let response_result = match limits.get_header_timeout() {
+            Some(header_timeout) => timeout(header_timeout, upstream_request).await,
+            None => Ok(upstream_request.await),
+        };
+        let response_result = match response_result {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(())) => {
+                warn(format!(
+                    "Request body is larger than the maximum of {} bytes for upstream server '{}'",
+                    limits.max_request_body_bytes, upstream_uri_string
+                ));
+                return Err(ProxyProcessorError::RequestBodyTooLarge);
+            }
+            Err(elapsed) => Err(elapsed),
+        };
+        match response_result {
+            Ok(Ok(mut resp)) => {
+                // Check if this is a protocol upgrade
+                let mut is_websocket_upgrade = false;
+                if resp.status() == hyper::StatusCode::SWITCHING_PROTOCOLS {
+                    trace("Detected WebSocket/protocol upgrade (HTTP 101)");
+
+                    // Get the upstream upgrade from the response extensions
+                    let upstream_upgrade = resp.extensions_mut().remove::<hyper::upgrade::OnUpgrade>();
+
+                    if let (Some(client_upgrade), Some(upstream_upgrade)) = (client_upgrade, upstream_upgrade) {
+                        // Spawn task to bridge the connections
+                        let websocket_settings = site.websocket.clone();
+                        let site_id = site.id.clone();
+                        tokio::spawn(async move {
+                            match tokio::try_join!(client_upgrade, upstream_upgrade) {
+                                Ok((client, upstream)) => {
+                                    trace("Protocol upgrade successful, bridging connections");
+                                    // Wrap the upgraded connections with TokioIo to make them compatible with tokio::io
+                                    let client = TokioIo::new(client);
+                                    let upstream = TokioIo::new(upstream);
+                                    match websocket_slot {
+                                        Some(slot) => relay_websocket(client, upstream, websocket_settings, slot).await,
+                                        None => relay_tunnel(client, upstream, &site_id).await,
+                                    }
+                                }
+                                Err(e) => {
+                                    error(format!("Failed to upgrade connections: {}", e));
+                                }
+                            }
+                        });
+                        is_websocket_upgrade = true;
+                    }
+                }
+
+                // In the response, we make sure to update/clean the headers as needed
+                Self::clean_hop_by_hop_headers_in_response(&mut resp, is_websocket_upgrade);
+
+                // A response body that is known to be too large is not relayed at all
+                if limits.max_response_body_bytes > 0
+                    && let Some(response_length) = resp.body().size_hint().exact()
+                    && response_length > limits.max_response_body_bytes
+                {
+                    warn(format!(
+                        "Response body of {} bytes from upstream server '{}' is larger than the maximum of {} bytes",
+                        response_length, upstream_uri_string, limits.max_response_body_bytes
+                    ));
+                    return Err(ProxyProcessorError::ResponseBodyTooLarge);
+                }
+
+                // Wrap response in GruxiResponse
+                let mut gruxi_response = GruxiResponse::from_hyper(resp);
+
+                // Other response bodies are cut off when they go over the maximum size or the total timeout
+                if !is_websocket_upgrade && (limits.max_response_body_bytes > 0 || limits.total_timeout_seconds > 0) {
+                    let deadline = (limits.total_timeout_seconds > 0).then(|| started_at + Duration::from_secs(limits.total_timeout_seconds));
+                    gruxi_response.map_streaming_body(|body| BoxBody::new(LimitedResponseBody::new(body, limits.max_response_body_bytes, deadline, limits.total_timeout_seconds)));
+                }
+
+                // Upstreams can ask for their response not to be buffered, just as with nginx
+                if gruxi_response.get_header("X-Accel-Buffering").is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"no")) {
+                    gruxi_response.set_unbuffered();
+                }
+
+                Ok(gruxi_response)
+            }
+            Ok(Err(e)) => {
+                if is_connect_timeout(&e) {
+                    error(format!("Connecting to upstream server '{}' timed out", upstream_uri_string));
+                    return Err(ProxyProcessorError::UpstreamTimeout);
+                }
+                error(format!("Failed to send request to upstream server: {:?}", e));
+                Err(ProxyProcessorError::ConnectionFailed)
+            }
+            Err(_) => {
+                // While the client is still sending its request body, the upstream cannot be blamed for not responding
+                if !request_body_state.is_finished() {
+                    warn(format!("Client did not send its request body for upstream server '{}' in time", upstream_uri_string));
+                    return Err(ProxyProcessorError::ClientTimeout);
+                }
+                error(format!("Upstream server '{}' did not send its response headers in time", upstream_uri_string));
+                Err(ProxyProcessorError::UpstreamTimeout)
+            }
+        }
+    }
+
+    // The load balancers of the main upstream servers and of each group, with their ids
+    pub fn get_load_balancer_services(&self) -> Vec<(String, impl LoadBalancerImpl)> {
+        let mut services = vec![(self.get_load_balancer_id(None), self.get_load_balancer_service(self.upstream_servers.clone()))];
+        for group in &self.upstream_groups {
+            services.push((self.get_load_balancer_id(Some(group)), self.get_load_balancer_service(group.upstream_servers.clone())));
+        }
+        services
+    }
+
+    fn get_load_balancer_service(&self, upstream_servers: Vec<String>) -> impl LoadBalancerImpl {
         match self.load_balancing_strategy.as_str() {
             "round_robin" => RoundRobin::new(
-                self.upstream_servers.clone(),
+                upstream_servers,
                 self.health_check_path.clone(),
                 self.health_check_timeout_seconds as u64,
                 self.health_check_interval_seconds as u64,
+                self.get_upstream_client_settings(),
             ),
             _ => {
                 error(format!("Unsupported load balancing strategy: {}", self.load_balancing_strategy));
@@ -139,6 +522,24 @@ impl ProxyProcessor {
     }
 }
 
+impl ProxyProcessor {
+    fn validate_upstream_servers(upstream_servers: &[String], errors: &mut Vec<String>) {
+        for server in upstream_servers {
+            if !server.starts_with("http://") && !server.starts_with("https://") {
+                errors.push(format!("Upstream server '{}' is not a valid upstream URL. It must start with 'http://' or 'https://'.", server));
+            }
+            if server.ends_with("/") {
+                errors.push(format!("Upstream server '{}' should not end with a trailing slash '/'.", server));
+            }
+
+            // Try to parse the URL
+            if server.parse::<hyper::Uri>().is_err() {
+                errors.push(format!("Upstream server '{}' is not a valid URL.", server));
+            }
+        }
+    }
+}
+
 impl ProcessorTrait for ProxyProcessor {
     fn initialize(&mut self) {}
 
@@ -160,6 +561,31 @@ impl ProcessorTrait for ProxyProcessor {
 
         // Forced host header trim
         self.forced_host_header = self.forced_host_header.trim().to_string();
+
+        // Upstream TLS settings trim
+        self.tls_ca_bundle_path = self.tls_ca_bundle_path.trim().to_string();
+        self.tls_server_name = self.tls_server_name.trim().to_string();
+        self.tls_client_cert_path = self.tls_client_cert_path.trim().to_string();
+        self.tls_client_key_path = self.tls_client_key_path.trim().to_string();
+
+        // Upstream groups cleanup
+        for group in &mut self.upstream_groups {
+            group.name = group.name.trim().to_string();
+            group.upstream_servers = group.upstream_servers.iter().map(|url| url.trim().to_string()).filter(|url| !url.is_empty()).collect();
+        }
+        self.upstream_group_header = self.upstream_group_header.trim().to_string();
+        self.upstream_group_cookie = self.upstream_group_cookie.trim().to_string();
+
+        // Streaming paths cleanup
+        self.streaming_paths = self.streaming_paths.iter().map(|path| path.trim().to_string()).filter(|path| !path.is_empty()).collect();
+
+        // Header allow and deny lists are matched in lowercase
+        let mut header_filter = self.get_header_filter();
+        header_filter.sanitize();
+        self.request_headers_allowed = header_filter.request_allowed;
+        self.request_headers_denied = header_filter.request_denied;
+        self.response_headers_allowed = header_filter.response_allowed;
+        self.response_headers_denied = header_filter.response_denied;
     }
 
     fn validate(&self) -> Result<(), Vec<String>> {
@@ -175,18 +601,36 @@ impl ProcessorTrait for ProxyProcessor {
         }
 
         // All upstream servers must be valid URLs, starting with http:// or https://
-        for server in &self.upstream_servers {
-            if !server.starts_with("http://") && !server.starts_with("https://") {
-                errors.push(format!("Upstream server '{}' is not a valid upstream URL. It must start with 'http://' or 'https://'.", server));
+        Self::validate_upstream_servers(&self.upstream_servers, &mut errors);
+
+        // Upstream groups need a unique name, their own upstream servers, and can together take at most all traffic
+        let mut group_names = std::collections::HashSet::new();
+        for group in &self.upstream_groups {
+            if group.name.is_empty() || !group.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+                errors.push(format!("Upstream group name '{}' must be non-empty and only contain letters, digits, '-' and '_'.", group.name));
             }
-            if server.ends_with("/") {
-                errors.push(format!("Upstream server '{}' should not end with a trailing slash '/'.", server));
+            if group.name.eq_ignore_ascii_case(MAIN_UPSTREAM_GROUP) {
+                errors.push(format!("Upstream group name '{}' is reserved for the main upstream servers.", group.name));
             }
-
-            // Try to parse the URL
-            if let Err(_) = server.parse::<hyper::Uri>() {
-                errors.push(format!("Upstream server '{}' is not a valid URL.", server));
+            if !group_names.insert(group.name.to_lowercase()) {
+                errors.push(format!("Upstream group name '{}' is used more than once.", group.name));
+            }
+            if group.upstream_servers.is_empty() {
+                errors.push(format!("Upstream group '{}' needs at least one upstream server.", group.name));
             }
+            Self::validate_upstream_servers(&group.upstream_servers, &mut errors);
+        }
+        let total_group_percent: u32 = self.upstream_groups.iter().map(|group| group.traffic_percent as u32).sum();
+        if total_group_percent > 100 {
+            errors.push(format!("The traffic percentages of the upstream groups add up to {}%, which is more than 100%.", total_group_percent));
+        }
+        self.get_header_filter().validate(&mut errors);
+
+        if !self.upstream_group_header.is_empty() && http::HeaderName::from_bytes(self.upstream_group_header.as_bytes()).is_err() {
+            errors.push(format!("Upstream group header '{}' is not a valid header name.", self.upstream_group_header));
+        }
+        if self.upstream_group_cookie.contains(|c: char| c.is_whitespace() || c == '=' || c == ';') {
+            errors.push(format!("Upstream group cookie '{}' is not a valid cookie name.", self.upstream_group_cookie));
         }
 
         if self.load_balancing_strategy != "round_robin" {
@@ -203,10 +647,44 @@ impl ProcessorTrait for ProxyProcessor {
             }
         }
 
+        // A client certificate needs its key, and the other way around
+        if self.tls_client_cert_path.is_empty() != self.tls_client_key_path.is_empty() {
+            errors.push("Both the TLS client certificate and key must be set for mutual TLS to upstreams, or neither.".to_string());
+        }
+        if !self.tls_server_name.is_empty() && rustls_pki_types::ServerName::try_from(self.tls_server_name.as_str()).is_err() {
+            errors.push(format!("TLS server name '{}' is not a valid hostname or IP address.", self.tls_server_name));
+        }
+        for (name, path) in [
+            ("CA bundle", &self.tls_ca_bundle_path),
+            ("client certificate", &self.tls_client_cert_path),
+            ("client key", &self.tls_client_key_path),
+        ] {
+            if !path.is_empty() && !std::path::Path::new(path).is_file() {
+                errors.push(format!("TLS {} file '{}' does not exist.", name, path));
+            }
+        }
+
         if self.timeout_seconds < 1 {
             errors.push("Timeout seconds must be greater than zero.".to_string());
         }
 
+        if self.total_timeout_seconds > 0 && self.total_timeout_seconds < self.timeout_seconds as u32 {
+            errors.push("Total timeout seconds cannot be less than the timeout seconds for the response headers.".to_string());
+        }
+
+        if self.pool_idle_timeout_seconds > 3600 {
+            errors.push("Pool idle timeout seconds cannot be more than 3600 (one hour).".to_string());
+        }
+
+        for streaming_path in &self.streaming_paths {
+            if !streaming_path.starts_with('/') {
+                errors.push(format!("Streaming path '{}' must start with '/', such as '/events'.", streaming_path));
+            }
+            if streaming_path.contains(',') {
+                errors.push(format!("Streaming path '{}' cannot contain a comma.", streaming_path));
+            }
+        }
+
         if !self.health_check_path.is_empty() {
             if !self.health_check_path.starts_with('/') {
                 errors.push("Health check path must start with '/', such as '/health' or '/healthcheck/'.".to_string());
@@ -224,7 +702,7 @@ impl ProcessorTrait for ProxyProcessor {
         if errors.is_empty() { Ok(()) } else { Err(errors) }
     }
 
-    async fn handle_request(&self, gruxi_request: &mut GruxiRequest, _site: &Site) -> Result<GruxiResponse, GruxiError> {
+    async fn handle_request(&self, gruxi_request: &mut GruxiRequest, site: &Site) -> Result<GruxiResponse, GruxiError> {
         trace(format!("ProxyProcessor handling request - {:?}", &self));
 
         // We determine which upstream server to use based on the load balancing strategy.
@@ -233,7 +711,18 @@ impl ProcessorTrait for ProxyProcessor {
         let running_state_read_lock = running_state.read().await;
         let processor_manager = running_state_read_lock.get_processor_manager();
 
-        let server_to_handle_request_option = processor_manager.load_balancer_registry.get_next_server(self.id.as_str()).await;
+        // A group without healthy upstream servers leaves its requests to the main upstream servers
+        let upstream_group = self.select_upstream_group(gruxi_request);
+        let mut server_to_handle_request_option = processor_manager.load_balancer_registry.get_next_server(&self.get_load_balancer_id(upstream_group)).await;
+        if server_to_handle_request_option.is_none()
+            && let Some(group) = upstream_group
+        {
+            warn(format!(
+                "No upstream servers are currently available in upstream group '{}' of proxy processor with id: {}, using the main upstream servers",
+                group.name, self.id
+            ));
+            server_to_handle_request_option = processor_manager.load_balancer_registry.get_next_server(&self.id).await;
+        }
         let server_to_handle_request = match server_to_handle_request_option {
             Some(s) => s,
             None => {
@@ -261,99 +750,34 @@ impl ProcessorTrait for ProxyProcessor {
             }
         };
 
-        // Get the client appropriate for TLS verification settings
-        let client = running_state_read_lock.get_http_client().get_client(self.verify_tls_certificates);
+        // Long-lived responses on streaming paths get their own timeout, and are passed on as they arrive from the upstream
+        let is_streaming_path = self.is_streaming_path(&gruxi_request.get_path());
+        let limits = self.get_upstream_limits(is_streaming_path);
 
-        // Get the client-side upgrade on the request side
-        let client_upgrade = gruxi_request.take_upgrade();
-
-        // Clean any hop by hop headers from the request and add forwarded headers
-        gruxi_request.clean_hop_by_hop_headers();
-        gruxi_request.add_forwarded_headers();
-
-        // Get the original request to extract headers and body
-        let mut proxy_request = match gruxi_request.get_streaming_http_request() {
-            Ok(req) => req,
-            Err(_) => {
-                error(format!("Failed to get streaming HTTP request for request: {:?}", gruxi_request));
-                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ProxyProcessor(ProxyProcessorError::Internal)));
+        // The client for the TLS and pool settings of this processor, which keeps its connections open for the next requests
+        let client = match running_state_read_lock.get_http_client().get_upstream_client(&self.get_upstream_client_settings()) {
+            Ok(client) => client,
+            Err(e) => {
+                error(format!("Failed to set up TLS for upstream server '{}': {}", upstream_uri, e));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ProxyProcessor(ProxyProcessorError::ConnectionFailed)));
             }
         };
 
-        // Update the URI to point to the upstream server (with full URL including scheme/host/port)
-        *proxy_request.uri_mut() = upstream_uri;
-
-        // Check if we should preserve the host header or remote it to let hyper set it
-        if self.forced_host_header.is_empty() {
-            // Header is there already, so we only remove it if we are not preserving it
-            if !self.preserve_host_header {
-                proxy_request.headers_mut().remove(hyper::header::HOST);
-                trace("Not preserving original Host header for upstream request");
-            }
-        } else {
-            trace("Using forced Host header for upstream request");
-            if let Ok(header_value) = HeaderValue::from_str(&self.forced_host_header) {
-                proxy_request.headers_mut().insert(hyper::header::HOST, header_value);
-            }
+        // Only the allowed client headers reach the upstream, and only the allowed upstream headers reach the client
+        let header_filter = self.get_header_filter();
+        for header_name in header_filter.get_removed_request_headers(gruxi_request.get_headers()) {
+            gruxi_request.remove_header(header_name.as_str());
         }
 
-        trace(format!("Forwarding request to upstream server: {:?}", proxy_request));
-
-        let timeout_duration = Duration::from_secs(self.timeout_seconds as u64);
-        match timeout(timeout_duration, client.request(proxy_request)).await {
-            Ok(Ok(mut resp)) => {
-                // Check if this is a protocol upgrade
-                let mut is_websocket_upgrade = false;
-                if resp.status() == hyper::StatusCode::SWITCHING_PROTOCOLS {
-                    trace("Detected WebSocket/protocol upgrade (HTTP 101)");
-
-                    // Get the upstream upgrade from the response extensions
-                    let upstream_upgrade = resp.extensions_mut().remove::<hyper::upgrade::OnUpgrade>();
-
-                    if let (Some(client_upgrade), Some(upstream_upgrade)) = (client_upgrade, upstream_upgrade) {
-                        // Spawn task to bridge the connections
-                        tokio::spawn(async move {
-                            match tokio::try_join!(client_upgrade, upstream_upgrade) {
-                                Ok((client, upstream)) => {
-                                    trace("WebSocket upgrade successful, bridging connections");
-                                    // Wrap the upgraded connections with TokioIo to make them compatible with tokio::io
-                                    let mut client = TokioIo::new(client);
-                                    let mut upstream = TokioIo::new(upstream);
-                                    match tokio::io::copy_bidirectional(&mut client, &mut upstream).await {
-                                        Ok((from_client, from_server)) => {
-                                            trace(format!("WebSocket closed. Client→Server: {} bytes, Server→Client: {} bytes", from_client, from_server));
-                                        }
-                                        Err(e) => {
-                                            error(format!("WebSocket proxy error: {}", e));
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    error(format!("Failed to upgrade connections: {}", e));
-                                }
-                            }
-                        });
-                        is_websocket_upgrade = true;
-                    }
-                }
-
-                // In the response, we make sure to update/clean the headers as needed
-                Self::clean_hop_by_hop_headers_in_response(&mut resp, is_websocket_upgrade);
-
-                // Wrap response in GruxiResponse
-                let gruxi_response = GruxiResponse::from_hyper(resp);
+        let mut response = Self::forward_request_to_upstream(gruxi_request, site, upstream_uri, client, self.preserve_host_header, &self.forced_host_header, &limits)
+            .await
+            .map_err(|e| GruxiError::new_with_kind_only(GruxiErrorKind::ProxyProcessor(e)))?;
+        header_filter.filter_response_headers(response.headers_mut());
 
-                return Ok(gruxi_response);
-            }
-            Ok(Err(e)) => {
-                error(format!("Failed to send request to upstream server: {:?}", e));
-                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ProxyProcessor(ProxyProcessorError::ConnectionFailed)));
-            }
-            Err(_) => {
-                error(format!("Request to upstream server '{}' timed out after {} seconds", server_to_handle_request, self.timeout_seconds));
-                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::ProxyProcessor(ProxyProcessorError::UpstreamTimeout)));
-            }
+        if is_streaming_path {
+            response.set_unbuffered();
         }
+        Ok(response)
     }
 
     fn get_type(&self) -> String {
@@ -364,3 +788,130 @@ impl ProcessorTrait for ProxyProcessor {
         "Proxy Processor".to_string()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::body::Bytes;
+
+    #[test]
+    fn test_proxy_streaming_paths() {
+        let mut processor = ProxyProcessor::new();
+        processor.upstream_servers = vec!["http://localhost:8080".to_string()];
+        processor.streaming_paths = vec![" /events ".to_string(), "".to_string(), "/api/poll".to_string()];
+        processor.sanitize();
+        assert_eq!(processor.streaming_paths, vec!["/events".to_string(), "/api/poll".to_string()]);
+        assert!(processor.validate().is_ok());
+
+        assert!(processor.is_streaming_path("/events"));
+        assert!(processor.is_streaming_path("/api/poll?since=10"));
+        assert!(!processor.is_streaming_path("/api/users"));
+
+        processor.streaming_paths.push("events".to_string());
+        assert_eq!(processor.validate().unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn test_proxy_upstream_tls_settings_validation() {
+        let mut processor = ProxyProcessor::new();
+        processor.upstream_servers = vec!["https://10.0.0.5:8443".to_string()];
+        processor.tls_server_name = " backend.internal ".to_string();
+        processor.sanitize();
+        assert!(processor.validate().is_ok());
+        assert!(processor.get_upstream_tls_settings().verify_certificates);
+        assert!(!processor.get_upstream_tls_settings().is_default());
+
+        // A client certificate without its key, a missing CA bundle and an invalid server name
+        processor.tls_client_cert_path = "Cargo.toml".to_string();
+        processor.tls_ca_bundle_path = "./does-not-exist.pem".to_string();
+        processor.tls_server_name = "not a hostname".to_string();
+        assert_eq!(processor.validate().unwrap_err().len(), 3);
+    }
+
+    #[test]
+    fn test_proxy_upstream_pool_settings() {
+        let mut processor = ProxyProcessor::new();
+        processor.upstream_servers = vec!["http://localhost:50051".to_string()];
+        assert!(processor.get_upstream_client_settings().is_default());
+
+        // Pool settings alone get the upstream its own client, with the shared TLS settings
+        processor.pool_max_idle_per_host = 8;
+        processor.upstream_http2_only = true;
+        let settings = processor.get_upstream_client_settings();
+        assert!(!settings.is_default());
+        assert!(settings.tls.is_default());
+        assert_eq!(settings.pool.max_idle_per_host, 8);
+        assert!(processor.validate().is_ok());
+
+        processor.pool_idle_timeout_seconds = 7200;
+        assert_eq!(processor.validate().unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn test_proxy_upstream_limits() {
+        let mut processor = ProxyProcessor::new();
+        processor.upstream_servers = vec!["http://localhost:8080".to_string()];
+        processor.streaming_timeout_seconds = 5;
+        processor.total_timeout_seconds = 120;
+        processor.max_request_body_bytes = 1024;
+        assert!(processor.validate().is_ok());
+
+        let limits = processor.get_upstream_limits(false);
+        assert_eq!((limits.header_timeout_seconds, limits.total_timeout_seconds, limits.max_request_body_bytes), (30, 120, 1024));
+
+        // Streaming paths keep their own header timeout and are never cut off by the total timeout
+        let limits = processor.get_upstream_limits(true);
+        assert_eq!((limits.header_timeout_seconds, limits.total_timeout_seconds), (5, 0));
+
+        processor.connect_timeout_seconds = 3;
+        assert_eq!(processor.get_upstream_client_settings().pool.connect_timeout_seconds, 3);
+
+        processor.total_timeout_seconds = 10;
+        assert_eq!(processor.validate().unwrap_err().len(), 1);
+    }
+
+    #[test]
+    fn test_proxy_upstream_groups() {
+        let mut processor = ProxyProcessor::new();
+        processor.upstream_servers = vec!["http://localhost:8080".to_string()];
+        processor.upstream_groups = vec![
+            ProxyUpstreamGroup {
+                name: " canary ".to_string(),
+                upstream_servers: vec!["http://localhost:8081".to_string()],
+                traffic_percent: 5,
+            },
+            ProxyUpstreamGroup {
+                name: "blue".to_string(),
+                upstream_servers: vec!["http://localhost:8082".to_string()],
+                traffic_percent: 45,
+            },
+        ];
+        processor.upstream_group_header = "X-Upstream-Group".to_string();
+        processor.upstream_group_cookie = "upstream_group".to_string();
+        processor.sanitize();
+        assert!(processor.validate().is_ok());
+        assert_eq!(processor.get_load_balancer_id(processor.upstream_groups.first()), format!("{}#canary", processor.id));
+
+        assert_eq!(processor.select_upstream_group_by_percent(4).map(|group| group.name.as_str()), Some("canary"));
+        assert_eq!(processor.select_upstream_group_by_percent(49).map(|group| group.name.as_str()), Some("blue"));
+        assert!(processor.select_upstream_group_by_percent(50).is_none());
+
+        let request = hyper::Request::builder().uri("/").header("X-Upstream-Group", "Blue").body(Bytes::new()).unwrap();
+        assert_eq!(processor.select_upstream_group(&GruxiRequest::new(request)).map(|group| group.name.as_str()), Some("blue"));
+        let request = hyper::Request::builder().uri("/").header("Cookie", "theme=dark; upstream_group=canary").body(Bytes::new()).unwrap();
+        assert_eq!(processor.select_upstream_group(&GruxiRequest::new(request)).map(|group| group.name.as_str()), Some("canary"));
+        // "main" asks for the main upstream servers, whatever the percentages
+        processor.upstream_groups[0].traffic_percent = 100;
+        processor.upstream_groups[1].traffic_percent = 0;
+        let request = hyper::Request::builder().uri("/").header("X-Upstream-Group", "main").body(Bytes::new()).unwrap();
+        assert!(processor.select_upstream_group(&GruxiRequest::new(request)).is_none());
+
+        processor.upstream_groups[1].traffic_percent = 1;
+        assert_eq!(processor.validate().unwrap_err().len(), 1);
+        processor.upstream_groups[1].traffic_percent = 0;
+        processor.upstream_groups[1].name = "canary".to_string();
+        assert_eq!(processor.validate().unwrap_err().len(), 1);
+        processor.upstream_groups[1].name = "main".to_string();
+        assert_eq!(processor.validate().unwrap_err().len(), 1);
+    }
+}
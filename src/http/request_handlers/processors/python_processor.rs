@@ -0,0 +1,149 @@
+use crate::{
+    configuration::site::Site,
+    core::running_state_manager::get_running_state_manager,
+    error::{
+        gruxi_error::GruxiError,
+        gruxi_error_enums::{GruxiErrorKind, ProxyProcessorError, PythonProcessorError},
+    },
+    http::{
+        request_handlers::{
+            processor_trait::ProcessorTrait,
+            processors::{proxy_helpers::body_limits::UpstreamLimits, proxy_processor::ProxyProcessor},
+        },
+        request_response::{gruxi_request::GruxiRequest, gruxi_response::GruxiResponse},
+    },
+    logging::syslog::{error, trace, warn},
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+// Reverse proxies requests to a Python application server (uvicorn/gunicorn) managed by Gruxi
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PythonProcessor {
+    pub id: String,                // Unique identifier for the processor
+    pub python_handler_id: String, // ID of the Python handler running the application server
+    pub request_timeout: u32,      // Seconds
+    #[serde(default = "default_preserve_host_header")]
+    pub preserve_host_header: bool, // Pass the original Host header, so the application can build correct URLs
+}
+
+fn default_preserve_host_header() -> bool {
+    true
+}
+
+impl PythonProcessor {
+    pub fn new() -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            python_handler_id: String::new(),
+            request_timeout: 30,
+            preserve_host_header: default_preserve_host_header(),
+        }
+    }
+}
+
+impl Default for PythonProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProcessorTrait for PythonProcessor {
+    fn initialize(&mut self) {}
+
+    fn sanitize(&mut self) {
+        self.python_handler_id = self.python_handler_id.trim().to_string();
+    }
+
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if self.python_handler_id.is_empty() {
+            errors.push("Python handler must be selected.".to_string());
+        }
+
+        if self.request_timeout < 1 {
+            errors.push("Request timeout must be at least 1 second.".to_string());
+        }
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    async fn handle_request(&self, gruxi_request: &mut GruxiRequest, site: &Site) -> Result<GruxiResponse, GruxiError> {
+        let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+
+        // Find the port the application server is listening on
+        let port = match running_state.get_external_system_handler().get_port_for_python_app(&self.python_handler_id) {
+            Some(port) => port,
+            None => {
+                error(format!("Python Processor: Cannot find port for Python handler ID: {}", self.python_handler_id));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::PythonProcessor(PythonProcessorError::HandlerUnavailable)));
+            }
+        };
+
+        let upstream_url = format!("http://127.0.0.1:{}{}", port, gruxi_request.get_uri());
+        let upstream_uri: hyper::Uri = match upstream_url.parse() {
+            Ok(uri) => uri,
+            Err(e) => {
+                error(format!("Python Processor: Could not parse upstream URL '{}': {:?}", upstream_url, e));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::PythonProcessor(PythonProcessorError::Internal)));
+            }
+        };
+
+        trace(format!("Python Processor: Forwarding request to application server at {}", upstream_uri));
+
+        // Held while the application server is recycled, then counted in the handler status until the response is back,
+        // for the queue depth and handling time
+        let handler_status = running_state.get_external_system_handler().get_handler_status(&self.python_handler_id);
+        if let Some(status) = &handler_status {
+            status.wait_while_recycling().await;
+        }
+        let _handler_request_guard = handler_status.map(|status| status.begin_request());
+
+        // Only handlers limiting their concurrent requests have a queue, where the request waits for a free slot
+        let mut _queue_permit = None;
+        if let Some(request_queue) = running_state.get_external_system_handler().get_request_queue(&self.python_handler_id) {
+            match request_queue.acquire().await {
+                Ok(permit) => _queue_permit = Some(permit),
+                Err(e) => {
+                    warn(format!("Python Processor: Python handler ID {} is saturated, request is rejected: {:?}", self.python_handler_id, e));
+                    return Err(GruxiError::new_with_kind_only(GruxiErrorKind::PythonProcessor(PythonProcessorError::Overloaded)));
+                }
+            }
+        }
+
+        let result = ProxyProcessor::forward_request_to_upstream(
+            gruxi_request,
+            site,
+            upstream_uri,
+            running_state.get_http_client().get_client(false),
+            self.preserve_host_header,
+            "",
+            &UpstreamLimits::new(self.request_timeout as u64),
+        )
+        .await;
+
+        result.map_err(|e| {
+            let python_error = match e {
+                ProxyProcessorError::ConnectionFailed => PythonProcessorError::ConnectionFailed,
+                ProxyProcessorError::UpstreamTimeout => PythonProcessorError::Timeout,
+                ProxyProcessorError::TooManyWebSocketConnections => PythonProcessorError::Overloaded,
+                // Statuses about the request itself are the same as for the proxy
+                ProxyProcessorError::RequestBodyTooLarge | ProxyProcessorError::ResponseBodyTooLarge | ProxyProcessorError::ClientTimeout => {
+                    return GruxiError::new_with_kind_only(GruxiErrorKind::ProxyProcessor(e));
+                }
+                _ => PythonProcessorError::Internal,
+            };
+            GruxiError::new_with_kind_only(GruxiErrorKind::PythonProcessor(python_error))
+        })
+    }
+
+    fn get_type(&self) -> String {
+        "python".to_string()
+    }
+
+    fn get_default_pretty_name(&self) -> String {
+        "Python Processor".to_string()
+    }
+}
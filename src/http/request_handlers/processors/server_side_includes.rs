@@ -0,0 +1,328 @@
+// ============================================================================
+// SERVER-SIDE INCLUDES
+// ============================================================================
+//
+// Puts together .shtml documents served by static file processors with SSI
+// enabled, following the directives known from Apache mod_include:
+//
+//   <!--#include virtual="/footer.html" -->  a file by its URL path, relative
+//                                            to the document when not starting
+//                                            with "/"
+//   <!--#include file="menu.html" -->        a file relative to the document,
+//                                            without ".." or a leading "/"
+//   <!--#echo var="DATE_LOCAL" -->           a variable, HTML escaped unless
+//                                            encoding="none" or "url" comes first
+//   <!--#config timefmt="%Y-%m-%d" -->       the strftime format of dates
+//   <!--#config errmsg="..." -->             the text shown for failed directives
+//
+// Included files are read through the file cache, so fragments shared by many
+// documents are read from disk once. Included .shtml files are processed too,
+// up to MAX_INCLUDE_DEPTH levels deep, which also ends files including
+// themselves. Included paths get the same security checks as requested ones.
+//
+// ============================================================================
+
+use std::{collections::HashMap, fmt::Write, future::Future, pin::Pin};
+
+use chrono::{DateTime, Local, TimeZone, Utc};
+use hyper::{body::Bytes, header::HeaderValue};
+
+use crate::{
+    core::running_state_manager::get_running_state_manager,
+    file::{file_util::check_path_secure, normalized_path::NormalizedPath},
+    http::{
+        http_util::empty_response_with_status,
+        request_response::{gruxi_request::GruxiRequest, gruxi_response::GruxiResponse},
+    },
+    logging::syslog::{error, trace},
+};
+
+pub const SSI_FILE_EXTENSION: &str = ".shtml";
+const MAX_INCLUDE_DEPTH: usize = 8;
+const MAX_OUTPUT_SIZE: usize = 16 * 1024 * 1024;
+const DEFAULT_TIME_FORMAT: &str = "%A, %d-%b-%Y %H:%M:%S %Z";
+const DEFAULT_ERROR_MESSAGE: &str = "[an error occurred while processing this directive]";
+
+struct SsiState {
+    web_root: String,
+    time_format: String,
+    error_message: String,
+    variables: HashMap<&'static str, String>,
+    last_modified: Option<DateTime<Local>>,
+    output: Vec<u8>,
+}
+
+impl SsiState {
+    fn new(gruxi_request: &mut GruxiRequest, web_root: &str, file_path: &str) -> Self {
+        let header = |name: &str| gruxi_request.get_headers().get(name).and_then(|value| value.to_str().ok()).unwrap_or("").to_string();
+        let user_agent = header("User-Agent");
+        let referer = header("Referer");
+
+        let mut variables = HashMap::new();
+        variables.insert("DOCUMENT_NAME", file_path.rsplit('/').next().unwrap_or("").to_string());
+        variables.insert("DOCUMENT_URI", gruxi_request.get_path());
+        variables.insert("QUERY_STRING", gruxi_request.get_query());
+        variables.insert("REMOTE_ADDR", gruxi_request.get_remote_ip());
+        variables.insert("REQUEST_METHOD", gruxi_request.get_http_method());
+        variables.insert("HTTP_HOST", gruxi_request.get_hostname());
+        variables.insert("HTTP_USER_AGENT", user_agent);
+        variables.insert("HTTP_REFERER", referer);
+        variables.insert("SERVER_SOFTWARE", "Gruxi".to_string());
+
+        Self {
+            web_root: web_root.to_string(),
+            time_format: DEFAULT_TIME_FORMAT.to_string(),
+            error_message: DEFAULT_ERROR_MESSAGE.to_string(),
+            variables,
+            last_modified: std::fs::metadata(file_path).and_then(|metadata| metadata.modified()).ok().map(DateTime::<Local>::from),
+            output: Vec::new(),
+        }
+    }
+
+    fn push_error(&mut self) {
+        self.output.extend_from_slice(self.error_message.as_bytes());
+    }
+
+    // The value of a variable, with dates in the configured time format. Unknown variables are "(none)", as in Apache
+    fn get_variable(&self, name: &str) -> String {
+        let value = match name {
+            "DATE_LOCAL" => format_time(&Local::now(), &self.time_format),
+            "DATE_GMT" => format_time(&Utc::now(), &self.time_format),
+            "LAST_MODIFIED" => self.last_modified.and_then(|last_modified| format_time(&last_modified, &self.time_format)),
+            _ => self.variables.get(name).cloned(),
+        };
+        value.unwrap_or_else(|| "(none)".to_string())
+    }
+}
+
+// Process the .shtml file and answer with the resulting document
+pub async fn get_ssi_response(gruxi_request: &mut GruxiRequest, web_root: &str, file_path: &str) -> GruxiResponse {
+    let content = match read_fragment(file_path).await {
+        Ok(content) => content,
+        Err(e) => {
+            error(format!("Failed to read server-side include document {}: {}", file_path, e));
+            return empty_response_with_status(hyper::StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let uri = gruxi_request.get_path();
+    let mut state = SsiState::new(gruxi_request, web_root, file_path);
+    process_document(&mut state, &content, &uri, 0).await;
+
+    let mut response = GruxiResponse::new_with_bytes(hyper::StatusCode::OK.as_u16(), state.output);
+    response.headers_mut().insert(hyper::header::CONTENT_TYPE, HeaderValue::from_static("text/html"));
+    response
+}
+
+// Copy the document to the output, running its directives. Boxed, as included .shtml files are processed by it too
+fn process_document<'a>(state: &'a mut SsiState, content: &'a [u8], uri: &'a str, depth: usize) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+    Box::pin(async move {
+        let mut rest = content;
+        while let Some(start) = find(rest, b"<!--#") {
+            state.output.extend_from_slice(&rest[..start]);
+
+            let directive_start = &rest[start + 5..];
+            let Some(end) = find(directive_start, b"-->") else {
+                // An unterminated directive is just text
+                rest = &rest[start..];
+                break;
+            };
+            let directive = String::from_utf8_lossy(&directive_start[..end]).to_string();
+            rest = &directive_start[end + 3..];
+
+            match parse_directive(&directive) {
+                Some((command, attributes)) => run_directive(state, &command, &attributes, uri, depth).await,
+                None => {
+                    trace(format!("Malformed server-side include directive in {}: {}", uri, directive));
+                    state.push_error();
+                }
+            }
+        }
+        state.output.extend_from_slice(rest);
+    })
+}
+
+async fn run_directive(state: &mut SsiState, command: &str, attributes: &[(String, String)], uri: &str, depth: usize) {
+    match command {
+        "include" => {
+            for (name, value) in attributes {
+                let target_uri = match name.as_str() {
+                    "virtual" => resolve_include_uri(uri, value.split('?').next().unwrap_or("")),
+                    "file" if !value.starts_with('/') && !value.contains("..") => resolve_include_uri(uri, value),
+                    _ => {
+                        trace(format!("Rejected server-side include {}=\"{}\" in {}", name, value, uri));
+                        state.push_error();
+                        continue;
+                    }
+                };
+                include(state, &target_uri, depth).await;
+            }
+        }
+        "echo" => {
+            let mut encoding = "entity";
+            for (name, value) in attributes {
+                match name.as_str() {
+                    "encoding" => encoding = value.as_str(),
+                    "var" => {
+                        let variable = state.get_variable(value);
+                        let encoded = match encoding {
+                            "none" => variable,
+                            "url" => urlencoding::encode(&variable).to_string(),
+                            _ => escape_html(&variable),
+                        };
+                        state.output.extend_from_slice(encoded.as_bytes());
+                    }
+                    _ => state.push_error(),
+                }
+            }
+        }
+        "config" => {
+            for (name, value) in attributes {
+                match name.as_str() {
+                    "timefmt" if format_time(&Utc::now(), value).is_some() => state.time_format = value.clone(),
+                    "errmsg" => state.error_message = value.clone(),
+                    _ => state.push_error(),
+                }
+            }
+        }
+        _ => {
+            trace(format!("Unsupported server-side include directive in {}: {}", uri, command));
+            state.push_error();
+        }
+    }
+}
+
+async fn include(state: &mut SsiState, target_uri: &str, depth: usize) {
+    if depth >= MAX_INCLUDE_DEPTH || state.output.len() > MAX_OUTPUT_SIZE {
+        trace(format!("Server-side include of {} stopped, by the include depth or output size limit", target_uri));
+        state.push_error();
+        return;
+    }
+
+    let file_path = match NormalizedPath::new(&state.web_root, target_uri) {
+        Ok(normalized_path) => normalized_path.get_full_path(),
+        Err(_) => {
+            trace(format!("Failed or rejected to normalize server-side include path: {}", target_uri));
+            state.push_error();
+            return;
+        }
+    };
+    if !check_path_secure(&state.web_root, &file_path).await {
+        trace(format!("Server-side include path is not secure: {}", file_path));
+        state.push_error();
+        return;
+    }
+
+    match read_fragment(&file_path).await {
+        Ok(content) if file_path.to_lowercase().ends_with(SSI_FILE_EXTENSION) => process_document(state, &content, target_uri, depth + 1).await,
+        Ok(content) => state.output.extend_from_slice(&content),
+        Err(e) => {
+            trace(format!("Failed to read server-side include {}: {}", file_path, e));
+            state.push_error();
+        }
+    }
+}
+
+// Read a file through the file cache, from its cached content when it has it
+async fn read_fragment(file_path: &str) -> Result<Bytes, String> {
+    let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+    let file_data = running_state.get_file_reader_cache().get_file(file_path).await.map_err(|e| e.to_string())?;
+    drop(running_state);
+
+    if !file_data.meta.exists || file_data.meta.is_directory {
+        return Err("File does not exist".to_string());
+    }
+    if let Some(raw_content) = &file_data.content.raw {
+        return Ok(raw_content.as_ref().clone());
+    }
+    if file_data.meta.length > MAX_OUTPUT_SIZE as u64 {
+        return Err(format!("File is larger than {} bytes", MAX_OUTPUT_SIZE));
+    }
+    tokio::fs::read(file_path).await.map(Bytes::from).map_err(|e| e.to_string())
+}
+
+// The URL path of an include, relative to the directory of the including document unless it starts with "/"
+fn resolve_include_uri(document_uri: &str, include_path: &str) -> String {
+    if include_path.starts_with('/') {
+        return include_path.to_string();
+    }
+    let directory = match document_uri.rfind('/') {
+        Some(index) => &document_uri[..=index],
+        None => "/",
+    };
+    format!("{}{}", directory, include_path)
+}
+
+// Parse the inside of a directive, such as `#include virtual="/a.html" ` after the "<!--#", into the command and its
+// attributes in order. Values are quoted with double or single quotes, or backticks as Apache also allows
+fn parse_directive(directive: &str) -> Option<(String, Vec<(String, String)>)> {
+    let directive = directive.trim_end();
+    let command_end = directive.find(|c: char| c.is_whitespace()).unwrap_or(directive.len());
+    let command = directive[..command_end].to_lowercase();
+    if command.is_empty() || !command.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let mut attributes = Vec::new();
+    let mut rest = directive[command_end..].trim_start();
+    while !rest.is_empty() {
+        let (name, after_name) = rest.split_once('=')?;
+        let name = name.trim().to_lowercase();
+        let after_name = after_name.trim_start();
+        let quote = after_name.chars().next().filter(|c| ['"', '\'', '`'].contains(c))?;
+        let value_end = after_name[1..].find(quote)?;
+        attributes.push((name, after_name[1..1 + value_end].to_string()));
+        rest = after_name[value_end + 2..].trim_start();
+    }
+
+    Some((command, attributes))
+}
+
+// Format a time with a strftime format, or None if the format is invalid
+fn format_time<Tz: TimeZone>(time: &DateTime<Tz>, time_format: &str) -> Option<String>
+where
+    Tz::Offset: std::fmt::Display,
+{
+    let mut formatted = String::new();
+    write!(formatted, "{}", time.format(time_format)).ok()?;
+    Some(formatted)
+}
+
+pub fn escape_html(input: &str) -> String {
+    input.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&#39;")
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_directive() {
+        assert_eq!(
+            parse_directive("include virtual=\"/footer.html\" "),
+            Some(("include".to_string(), vec![("virtual".to_string(), "/footer.html".to_string())]))
+        );
+        assert_eq!(
+            parse_directive("echo encoding='none' var=\"DATE_LOCAL\""),
+            Some(("echo".to_string(), vec![("encoding".to_string(), "none".to_string()), ("var".to_string(), "DATE_LOCAL".to_string())]))
+        );
+        assert_eq!(parse_directive("config timefmt=\"%Y\"").map(|(command, _)| command), Some("config".to_string()));
+        assert_eq!(parse_directive("include virtual=/unquoted.html"), None);
+        assert_eq!(parse_directive("include virtual=\"/unterminated.html"), None);
+        assert_eq!(parse_directive(" include"), None);
+    }
+
+    #[test]
+    fn test_resolve_include_uri_and_format_time() {
+        assert_eq!(resolve_include_uri("/docs/page.shtml", "menu.html"), "/docs/menu.html");
+        assert_eq!(resolve_include_uri("/page.shtml", "/parts/footer.html"), "/parts/footer.html");
+
+        let time = Utc.with_ymd_and_hms(2024, 5, 17, 8, 30, 0).unwrap();
+        assert_eq!(format_time(&time, "%Y-%m-%d %H:%M").as_deref(), Some("2024-05-17 08:30"));
+        assert_eq!(format_time(&time, "%Q"), None);
+    }
+}
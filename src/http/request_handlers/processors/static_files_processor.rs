@@ -1,26 +1,36 @@
 use crate::{
     configuration::site::Site,
+    core::running_state_manager::get_running_state_manager,
     error::{
         gruxi_error::GruxiError,
         gruxi_error_enums::{GruxiErrorKind, StaticFileProcessorError},
     },
     file::{file_util::check_path_secure, normalized_path::NormalizedPath},
     http::{
+        allowed_methods::{STATIC_ALLOWED_METHODS, method_not_allowed_response, options_response},
         http_util::resolve_web_root_and_path_and_get_file,
-        request_handlers::processor_trait::ProcessorTrait,
+        request_handlers::{
+            processor_trait::ProcessorTrait,
+            processors::server_side_includes::{SSI_FILE_EXTENSION, get_ssi_response},
+        },
         request_response::{gruxi_request::GruxiRequest, gruxi_response::GruxiResponse},
     },
     logging::syslog::{error, trace},
 };
 use hyper::header::HeaderValue;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
 pub struct StaticFileProcessor {
     pub id: String,                            // Unique identifier for the processor
     pub web_root: String,                      // Web root directory for static files
     pub web_root_index_file_list: Vec<String>, // List of index files to look for in directories
+    #[serde(default)]
+    pub integrity_headers_enabled: bool, // Add SHA-256 Digest/Repr-Digest headers to file responses
+    #[serde(default)]
+    pub ssi_enabled: bool, // Process server-side include directives in .shtml files
 
     // Calculated fields (not serialized)
     #[serde(skip)]
@@ -34,6 +44,8 @@ impl StaticFileProcessor {
             id,
             web_root,
             web_root_index_file_list,
+            integrity_headers_enabled: false,
+            ssi_enabled: false,
             normalized_web_root: None,
         }
     }
@@ -232,10 +244,25 @@ impl ProcessorTrait for StaticFileProcessor {
             ))));
         }
 
+        // The file exists, so the method decides the answer, as a static file can only be read
+        match gruxi_request.get_http_method().as_str() {
+            "GET" | "HEAD" => {}
+            "OPTIONS" => return Ok(options_response(STATIC_ALLOWED_METHODS)),
+            _ => return Ok(method_not_allowed_response(STATIC_ALLOWED_METHODS)),
+        }
+
+        // Documents with server-side includes are put together per request, so they are not sent as the file is
+        if self.ssi_enabled && file_path.to_lowercase().ends_with(SSI_FILE_EXTENSION) {
+            return Ok(get_ssi_response(gruxi_request, &web_root, &file_path).await);
+        }
+
         // Get a stream of the file content, based on the accept-encoding header
-        let (stream, compression) = file_data.get_content_stream(gruxi_request).await;
+        let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+        let (stream, compression) = file_data.get_content_stream(gruxi_request, running_state.get_file_reader_cache().get_open_file_cache()).await;
+        drop(running_state);
 
         let mut response = GruxiResponse::new_with_body(hyper::StatusCode::OK.as_u16(), stream);
+        response.set_file_length(file_data.meta.length);
 
         // Set content type
         let header_value = HeaderValue::from_str(&file_data.meta.mime_type);
@@ -264,6 +291,29 @@ impl ProcessorTrait for StaticFileProcessor {
             }
         }
 
+        // Files that are compressed for clients accepting it vary by Accept-Encoding, also when sent as they are, so shared
+        // caches keep the variants apart
+        if file_data.meta.is_compressible {
+            response.headers_mut().insert(hyper::header::VARY, HeaderValue::from_static("Accept-Encoding"));
+        }
+
+        // Set integrity headers, digest is of the content as sent, so after any content encoding
+        if self.integrity_headers_enabled {
+            match file_data.get_sha256_digest(&compression).await {
+                Some(digest) => {
+                    if let Ok(value) = HeaderValue::from_str(&format!("sha-256=:{}:", digest)) {
+                        response.headers_mut().insert("Repr-Digest", value);
+                    }
+                    if let Ok(value) = HeaderValue::from_str(&format!("SHA-256={}", digest)) {
+                        response.headers_mut().insert("Digest", value);
+                    }
+                }
+                None => {
+                    error(format!("Failed to calculate digest for file: {}", file_path));
+                }
+            }
+        }
+
         Ok(response)
     }
 
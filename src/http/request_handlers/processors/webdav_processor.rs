@@ -0,0 +1,869 @@
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant, SystemTime};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use futures::TryStreamExt;
+use http_body_util::{BodyExt, StreamBody, combinators::BoxBody};
+use hyper::body::{Bytes, Frame};
+use hyper::header::HeaderValue;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::{
+    configuration::{cached_configuration::get_cached_configuration, site::Site},
+    error::{
+        gruxi_error::GruxiError,
+        gruxi_error_enums::{GruxiErrorKind, WebDavProcessorError},
+    },
+    file::{
+        disk_usage::{add_written_bytes, is_over_disk_quota},
+        file_util::check_path_secure,
+        normalized_path::NormalizedPath,
+        upload_scanner::{UploadScanVerdict, quarantine_upload, scan_upload},
+    },
+    http::{
+        basic_auth::{BasicAuthUser, sanitize_basic_auth_users, validate_basic_auth_users, verify_basic_auth},
+        http_util::empty_response_with_status,
+        request_handlers::processor_trait::ProcessorTrait,
+        request_response::{
+            body_error::{BodyError, box_err},
+            gruxi_request::GruxiRequest,
+            gruxi_response::GruxiResponse,
+        },
+    },
+    logging::syslog::{debug, error, trace},
+};
+
+// WebDAV specific methods, on top of the standard HTTP methods
+pub static WEBDAV_METHODS: &[&str] = &["PROPFIND", "PROPPATCH", "MKCOL", "COPY", "MOVE", "LOCK", "UNLOCK"];
+
+// Methods that modify the file system, which are rejected when the processor is read only
+static WEBDAV_WRITE_METHODS: &[&str] = &["PUT", "DELETE", "MKCOL", "COPY", "MOVE", "LOCK", "UNLOCK"];
+
+// Methods supported by the WebDAV processor
+pub static WEBDAV_ALLOWED_METHODS: &str = "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, MKCOL, COPY, MOVE, LOCK, UNLOCK";
+
+// Default and maximum lock timeout, in seconds
+const WEBDAV_DEFAULT_LOCK_TIMEOUT: u64 = 3600;
+const WEBDAV_MAX_LOCK_TIMEOUT: u64 = 86400;
+
+#[derive(Clone, Debug)]
+struct WebDavLock {
+    token: String,
+    is_depth_infinity: bool,
+    owner: String,
+    timeout_seconds: u64,
+    expires_at: Instant,
+}
+
+// Active locks, by full file system path. Locks are in-memory only and are lost on restart
+static WEBDAV_LOCKS: LazyLock<DashMap<String, WebDavLock>> = LazyLock::new(DashMap::new);
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct WebDavProcessor {
+    pub id: String,       // Unique identifier for the processor
+    pub web_root: String, // Directory that is shared through WebDAV
+    pub read_only: bool,  // If true, only OPTIONS, GET, HEAD and PROPFIND are allowed
+    // Authentication
+    pub require_authentication: bool, // Require basic authentication for all requests
+    pub auth_realm: String,
+    pub auth_users: Vec<BasicAuthUser>,
+
+    // Calculated fields (not serialized)
+    #[serde(skip)]
+    normalized_web_root: Option<NormalizedPath>,
+}
+
+impl WebDavProcessor {
+    pub fn new(web_root: String) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            web_root,
+            read_only: false,
+            require_authentication: true,
+            auth_realm: "WebDAV".to_string(),
+            auth_users: Vec::new(),
+            normalized_web_root: None,
+        }
+    }
+
+    fn response_with_status(status: hyper::StatusCode) -> Result<GruxiResponse, GruxiError> {
+        Ok(empty_response_with_status(status))
+    }
+
+    fn xml_response(status: u16, xml: String) -> Result<GruxiResponse, GruxiError> {
+        let mut response = GruxiResponse::new_with_bytes(status, Bytes::from(xml));
+        response.headers_mut().insert(hyper::header::CONTENT_TYPE, HeaderValue::from_static("application/xml; charset=utf-8"));
+        Ok(response)
+    }
+
+    // Resolve a request path (or destination path) to a full path within the web root, if it is allowed
+    async fn resolve_path(web_root: &str, path: &str) -> Option<String> {
+        let normalized_path = NormalizedPath::new(web_root, path).ok()?;
+        let full_path = normalized_path.get_full_path().trim_end_matches('/').to_string();
+        let full_path = if full_path.len() < web_root.len() { web_root.to_string() } else { full_path };
+        if !check_path_secure(web_root, &full_path).await {
+            trace(format!("WebDAV path is not secure: {}", full_path));
+            return None;
+        }
+        Some(full_path)
+    }
+
+    // Get the lock that covers the path, if any, and clean expired ones
+    fn get_active_lock(full_path: &str) -> Option<WebDavLock> {
+        let now = Instant::now();
+        WEBDAV_LOCKS.retain(|_, lock| lock.expires_at > now);
+
+        if let Some(lock) = WEBDAV_LOCKS.get(full_path) {
+            return Some(lock.clone());
+        }
+
+        WEBDAV_LOCKS
+            .iter()
+            .find(|entry| entry.value().is_depth_infinity && full_path.starts_with(&format!("{}/", entry.key())))
+            .map(|entry| entry.value().clone())
+    }
+
+    // Check if the path is locked by a lock that the client did not submit in the If header
+    fn is_locked_for_request(full_path: &str, gruxi_request: &GruxiRequest) -> bool {
+        match Self::get_active_lock(full_path) {
+            Some(lock) => {
+                let if_header = gruxi_request.get_headers().get("If").and_then(|h| h.to_str().ok()).unwrap_or("");
+                !if_header.contains(&lock.token)
+            }
+            None => false,
+        }
+    }
+
+    fn get_depth(gruxi_request: &GruxiRequest, default: &str) -> String {
+        gruxi_request
+            .get_headers()
+            .get("Depth")
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.trim().to_lowercase())
+            .unwrap_or_else(|| default.to_string())
+    }
+
+    async fn handle_get(&self, full_path: &str, is_head: bool) -> Result<GruxiResponse, GruxiError> {
+        let metadata = match tokio::fs::metadata(full_path).await {
+            Ok(m) => m,
+            Err(_) => return Self::response_with_status(hyper::StatusCode::NOT_FOUND),
+        };
+        if metadata.is_dir() {
+            return Self::response_with_status(hyper::StatusCode::METHOD_NOT_ALLOWED);
+        }
+
+        let mut response = if is_head {
+            GruxiResponse::new_empty_with_status(hyper::StatusCode::OK.as_u16())
+        } else {
+            let file = match tokio::fs::File::open(full_path).await {
+                Ok(f) => f,
+                Err(e) => {
+                    debug(format!("WebDAV failed to open file '{}': {}", full_path, e));
+                    return Self::response_with_status(hyper::StatusCode::NOT_FOUND);
+                }
+            };
+            let stream = ReaderStream::new(file).map_ok(Frame::data);
+            let body = BodyExt::map_err(StreamBody::new(stream), box_err);
+            GruxiResponse::new_with_body(hyper::StatusCode::OK.as_u16(), body.boxed())
+        };
+
+        let mime_type = mime_guess::from_path(full_path).first_or_octet_stream().to_string();
+        if let Ok(value) = HeaderValue::from_str(&mime_type) {
+            response.headers_mut().insert(hyper::header::CONTENT_TYPE, value);
+        }
+        if let Ok(value) = HeaderValue::from_str(&metadata.len().to_string()) {
+            response.headers_mut().insert(hyper::header::CONTENT_LENGTH, value);
+        }
+        if let Ok(modified) = metadata.modified()
+            && let Ok(value) = HeaderValue::from_str(&format_http_date(modified))
+        {
+            response.headers_mut().insert(hyper::header::LAST_MODIFIED, value);
+        }
+        Ok(response)
+    }
+
+    async fn handle_put(&self, gruxi_request: &mut GruxiRequest, full_path: &str, site_id: &str) -> Result<GruxiResponse, GruxiError> {
+        let existing = tokio::fs::metadata(full_path).await.ok();
+        if existing.as_ref().map(|m| m.is_dir()).unwrap_or(false) {
+            return Self::response_with_status(hyper::StatusCode::METHOD_NOT_ALLOWED);
+        }
+        if !parent_exists(full_path).await {
+            return Self::response_with_status(hyper::StatusCode::CONFLICT);
+        }
+
+        let (upload_scanning, max_upload_size) = {
+            let configuration = get_cached_configuration().get_configuration().await;
+            (configuration.core.upload_scanning.clone(), configuration.core.server_settings.max_body_size)
+        };
+
+        // The body is written next to its destination first and only made live once it is complete, and clean when uploads
+        // are scanned, so an upload that is aborted or too large leaves the existing file as it was
+        let file_name = Path::new(full_path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let write_path = format!(
+            "{}/.{}.gruxi-upload-{}",
+            Path::new(full_path).parent().map(|p| p.to_string_lossy().to_string()).unwrap_or_default(),
+            file_name,
+            Uuid::new_v4()
+        );
+
+        let body = gruxi_request.get_streaming_http_request().into_body();
+        let written_bytes = match write_body_to_file(body, &write_path, max_upload_size).await {
+            Ok(written_bytes) => written_bytes,
+            Err(UploadWriteError::TooLarge) => {
+                debug(format!("WebDAV upload '{}' is larger than the max body size of {} bytes", full_path, max_upload_size));
+                return Self::response_with_status(hyper::StatusCode::PAYLOAD_TOO_LARGE);
+            }
+            Err(UploadWriteError::Incomplete(e)) => {
+                debug(format!("WebDAV upload '{}' was not completed by the client: {}", full_path, e));
+                return Self::response_with_status(hyper::StatusCode::BAD_REQUEST);
+            }
+            Err(UploadWriteError::Io(e)) => {
+                error(format!("WebDAV failed to write file '{}': {}", write_path, e));
+                return Err(GruxiError::new(
+                    GruxiErrorKind::WebDavProcessor(WebDavProcessorError::Io(e)),
+                    format!("Failed to write file '{}'", full_path),
+                ));
+            }
+        };
+
+        if upload_scanning.is_enabled {
+            let upload_path = Path::new(&write_path);
+            match scan_upload(&upload_scanning, upload_path).await {
+                Ok(UploadScanVerdict::Clean) => {
+                    trace(format!("WebDAV upload '{}' passed upload scanning", full_path));
+                }
+                Ok(UploadScanVerdict::Infected(reason)) => {
+                    if let Err(e) = quarantine_upload(&upload_scanning, upload_path, full_path, &reason).await {
+                        error(format!("WebDAV failed to quarantine rejected upload '{}', deleting it instead: {}", full_path, e));
+                        let _ = tokio::fs::remove_file(&write_path).await;
+                    }
+                    return Self::response_with_status(hyper::StatusCode::FORBIDDEN);
+                }
+                Err(e) => {
+                    // Fail closed, files that could not be scanned never go live
+                    let _ = tokio::fs::remove_file(&write_path).await;
+                    error(format!("WebDAV upload '{}' could not be scanned: {}", full_path, e));
+                    return Err(GruxiError::new(GruxiErrorKind::WebDavProcessor(WebDavProcessorError::UploadScanFailed), e));
+                }
+            }
+        }
+
+        if let Err(e) = tokio::fs::rename(&write_path, full_path).await {
+            let _ = tokio::fs::remove_file(&write_path).await;
+            error(format!("WebDAV failed to move upload to '{}': {}", full_path, e));
+            return Err(GruxiError::new(
+                GruxiErrorKind::WebDavProcessor(WebDavProcessorError::Io(e)),
+                format!("Failed to write file '{}'", full_path),
+            ));
+        }
+        add_written_bytes(site_id, written_bytes);
+
+        if existing.is_some() {
+            Self::response_with_status(hyper::StatusCode::NO_CONTENT)
+        } else {
+            Self::response_with_status(hyper::StatusCode::CREATED)
+        }
+    }
+
+    async fn handle_delete(&self, full_path: &str, web_root: &str) -> Result<GruxiResponse, GruxiError> {
+        if full_path == web_root {
+            return Self::response_with_status(hyper::StatusCode::FORBIDDEN);
+        }
+        let metadata = match tokio::fs::metadata(full_path).await {
+            Ok(m) => m,
+            Err(_) => return Self::response_with_status(hyper::StatusCode::NOT_FOUND),
+        };
+
+        let result = if metadata.is_dir() {
+            tokio::fs::remove_dir_all(full_path).await
+        } else {
+            tokio::fs::remove_file(full_path).await
+        };
+        if let Err(e) = result {
+            error(format!("WebDAV failed to delete '{}': {}", full_path, e));
+            return Err(GruxiError::new(
+                GruxiErrorKind::WebDavProcessor(WebDavProcessorError::Io(e)),
+                format!("Failed to delete '{}'", full_path),
+            ));
+        }
+
+        WEBDAV_LOCKS.retain(|path, _| path != full_path && !path.starts_with(&format!("{}/", full_path)));
+        Self::response_with_status(hyper::StatusCode::NO_CONTENT)
+    }
+
+    async fn handle_mkcol(&self, gruxi_request: &mut GruxiRequest, full_path: &str) -> Result<GruxiResponse, GruxiError> {
+        // We do not support MKCOL request bodies
+        if gruxi_request.get_body_size() > 0 {
+            return Self::response_with_status(hyper::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        }
+        if tokio::fs::metadata(full_path).await.is_ok() {
+            return Self::response_with_status(hyper::StatusCode::METHOD_NOT_ALLOWED);
+        }
+        if !parent_exists(full_path).await {
+            return Self::response_with_status(hyper::StatusCode::CONFLICT);
+        }
+
+        if let Err(e) = tokio::fs::create_dir(full_path).await {
+            error(format!("WebDAV failed to create collection '{}': {}", full_path, e));
+            return Err(GruxiError::new(
+                GruxiErrorKind::WebDavProcessor(WebDavProcessorError::Io(e)),
+                format!("Failed to create collection '{}'", full_path),
+            ));
+        }
+        Self::response_with_status(hyper::StatusCode::CREATED)
+    }
+
+    async fn handle_copy_or_move(&self, gruxi_request: &mut GruxiRequest, full_path: &str, web_root: &str, is_move: bool) -> Result<GruxiResponse, GruxiError> {
+        if tokio::fs::metadata(full_path).await.is_err() {
+            return Self::response_with_status(hyper::StatusCode::NOT_FOUND);
+        }
+
+        // Destination can be an absolute URI or an absolute path
+        let destination_header = gruxi_request.get_headers().get("Destination").and_then(|h| h.to_str().ok()).unwrap_or("").to_string();
+        let destination_path = match destination_header.parse::<hyper::Uri>() {
+            Ok(uri) if !destination_header.is_empty() => uri.path().to_string(),
+            _ => return Self::response_with_status(hyper::StatusCode::BAD_REQUEST),
+        };
+        let destination_full_path = match Self::resolve_path(web_root, &destination_path).await {
+            Some(p) => p,
+            None => return Self::response_with_status(hyper::StatusCode::FORBIDDEN),
+        };
+        if destination_full_path == full_path || destination_full_path.starts_with(&format!("{}/", full_path)) || destination_full_path == web_root {
+            return Self::response_with_status(hyper::StatusCode::FORBIDDEN);
+        }
+        if Self::is_locked_for_request(&destination_full_path, gruxi_request) {
+            return Self::response_with_status(hyper::StatusCode::LOCKED);
+        }
+        if !parent_exists(&destination_full_path).await {
+            return Self::response_with_status(hyper::StatusCode::CONFLICT);
+        }
+
+        // Overwrite defaults to true
+        let overwrite = gruxi_request
+            .get_headers()
+            .get("Overwrite")
+            .and_then(|h| h.to_str().ok())
+            .map(|s| !s.trim().eq_ignore_ascii_case("F"))
+            .unwrap_or(true);
+        let destination_exists = tokio::fs::metadata(&destination_full_path).await.is_ok();
+        if destination_exists {
+            if !overwrite {
+                return Self::response_with_status(hyper::StatusCode::PRECONDITION_FAILED);
+            }
+            let _ = self.handle_delete(&destination_full_path, web_root).await?;
+        }
+
+        let source = full_path.to_string();
+        let destination = destination_full_path.clone();
+        let result = if is_move {
+            tokio::fs::rename(&source, &destination).await
+        } else {
+            let depth = Self::get_depth(gruxi_request, "infinity");
+            let is_recursive = depth != "0";
+            tokio::task::spawn_blocking(move || copy_path(Path::new(&source), Path::new(&destination), is_recursive))
+                .await
+                .unwrap_or_else(|e| Err(std::io::Error::other(e)))
+        };
+
+        if let Err(e) = result {
+            if e.kind() == std::io::ErrorKind::PermissionDenied {
+                debug(format!(
+                    "WebDAV refused to {} '{}' to '{}': {}",
+                    if is_move { "move" } else { "copy" },
+                    full_path,
+                    destination_full_path,
+                    e
+                ));
+                return Self::response_with_status(hyper::StatusCode::FORBIDDEN);
+            }
+            error(format!(
+                "WebDAV failed to {} '{}' to '{}': {}",
+                if is_move { "move" } else { "copy" },
+                full_path,
+                destination_full_path,
+                e
+            ));
+            return Err(GruxiError::new(
+                GruxiErrorKind::WebDavProcessor(WebDavProcessorError::Io(e)),
+                format!("Failed to copy or move '{}'", full_path),
+            ));
+        }
+
+        if is_move {
+            WEBDAV_LOCKS.retain(|path, _| path != full_path && !path.starts_with(&format!("{}/", full_path)));
+        }
+
+        if destination_exists {
+            Self::response_with_status(hyper::StatusCode::NO_CONTENT)
+        } else {
+            Self::response_with_status(hyper::StatusCode::CREATED)
+        }
+    }
+
+    async fn handle_propfind(&self, gruxi_request: &mut GruxiRequest, full_path: &str, web_root: &str) -> Result<GruxiResponse, GruxiError> {
+        let metadata = match tokio::fs::metadata(full_path).await {
+            Ok(m) => m,
+            Err(_) => return Self::response_with_status(hyper::StatusCode::NOT_FOUND),
+        };
+
+        // We do not support "infinity" depth, as it can be very expensive on large trees
+        let depth = Self::get_depth(gruxi_request, "infinity");
+        if depth != "0" && depth != "1" {
+            return Self::response_with_status(hyper::StatusCode::FORBIDDEN);
+        }
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n");
+        xml.push_str(&get_propfind_response_xml(full_path, web_root, &metadata));
+
+        if depth == "1" && metadata.is_dir() {
+            let mut entries = match tokio::fs::read_dir(full_path).await {
+                Ok(entries) => entries,
+                Err(e) => {
+                    error(format!("WebDAV failed to read directory '{}': {}", full_path, e));
+                    return Err(GruxiError::new(
+                        GruxiErrorKind::WebDavProcessor(WebDavProcessorError::Io(e)),
+                        format!("Failed to read directory '{}'", full_path),
+                    ));
+                }
+            };
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let entry_path = format!("{}/{}", full_path, entry.file_name().to_string_lossy());
+                if !check_path_secure(web_root, &entry_path).await {
+                    continue;
+                }
+                if let Ok(entry_metadata) = entry.metadata().await {
+                    xml.push_str(&get_propfind_response_xml(&entry_path, web_root, &entry_metadata));
+                }
+            }
+        }
+
+        xml.push_str("</D:multistatus>\n");
+        Self::xml_response(207, xml)
+    }
+
+    async fn handle_lock(&self, gruxi_request: &mut GruxiRequest, full_path: &str) -> Result<GruxiResponse, GruxiError> {
+        let timeout_seconds = gruxi_request
+            .get_headers()
+            .get("Timeout")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|s| s.split(',').next())
+            .and_then(|s| s.trim().strip_prefix("Second-").and_then(|n| n.parse::<u64>().ok()))
+            .unwrap_or(WEBDAV_DEFAULT_LOCK_TIMEOUT)
+            .min(WEBDAV_MAX_LOCK_TIMEOUT);
+
+        let body = gruxi_request.get_body_bytes().await;
+        let existing_lock = Self::get_active_lock(full_path);
+
+        // An empty body means a lock refresh, which requires the lock token in the If header
+        if body.is_empty() {
+            let lock = match existing_lock {
+                Some(lock) if !Self::is_locked_for_request(full_path, gruxi_request) => lock,
+                _ => return Self::response_with_status(hyper::StatusCode::PRECONDITION_FAILED),
+            };
+            let mut refreshed_lock = lock.clone();
+            refreshed_lock.timeout_seconds = timeout_seconds;
+            refreshed_lock.expires_at = Instant::now() + Duration::from_secs(timeout_seconds);
+            if let Some(mut entry) = WEBDAV_LOCKS.iter_mut().find(|entry| entry.value().token == lock.token) {
+                *entry.value_mut() = refreshed_lock.clone();
+            }
+            return Self::lock_response(200, &refreshed_lock);
+        }
+
+        // We only support exclusive write locks, so any existing lock is a conflict
+        if existing_lock.is_some() {
+            return Self::response_with_status(hyper::StatusCode::LOCKED);
+        }
+
+        let body_str = String::from_utf8_lossy(&body).to_string();
+        let owner = match (
+            body_str.find("<D:owner>").or_else(|| body_str.find("<owner>")),
+            body_str.find("</D:owner>").or_else(|| body_str.find("</owner>")),
+        ) {
+            (Some(start), Some(end)) if end > start => {
+                let start = body_str[start..].find('>').map(|p| start + p + 1).unwrap_or(end);
+                body_str[start..end].to_string()
+            }
+            _ => String::new(),
+        };
+
+        let lock = WebDavLock {
+            token: format!("opaquelocktoken:{}", Uuid::new_v4()),
+            is_depth_infinity: Self::get_depth(gruxi_request, "infinity") != "0",
+            owner,
+            timeout_seconds,
+            expires_at: Instant::now() + Duration::from_secs(timeout_seconds),
+        };
+
+        // Locking an unmapped URL creates an empty resource
+        let mut status = 200;
+        if tokio::fs::metadata(full_path).await.is_err() {
+            if !parent_exists(full_path).await {
+                return Self::response_with_status(hyper::StatusCode::CONFLICT);
+            }
+            if let Err(e) = tokio::fs::write(full_path, b"").await {
+                error(format!("WebDAV failed to create locked resource '{}': {}", full_path, e));
+                return Err(GruxiError::new(
+                    GruxiErrorKind::WebDavProcessor(WebDavProcessorError::Io(e)),
+                    format!("Failed to create '{}'", full_path),
+                ));
+            }
+            status = 201;
+        }
+
+        WEBDAV_LOCKS.insert(full_path.to_string(), lock.clone());
+        Self::lock_response(status, &lock)
+    }
+
+    fn lock_response(status: u16, lock: &WebDavLock) -> Result<GruxiResponse, GruxiError> {
+        let xml = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:prop xmlns:D=\"DAV:\"><D:lockdiscovery><D:activelock><D:locktype><D:write/></D:locktype><D:lockscope><D:exclusive/></D:lockscope><D:depth>{}</D:depth><D:owner>{}</D:owner><D:timeout>Second-{}</D:timeout><D:locktoken><D:href>{}</D:href></D:locktoken></D:activelock></D:lockdiscovery></D:prop>\n",
+            if lock.is_depth_infinity { "infinity" } else { "0" },
+            lock.owner,
+            lock.timeout_seconds,
+            lock.token
+        );
+        let mut response = Self::xml_response(status, xml)?;
+        if let Ok(value) = HeaderValue::from_str(&format!("<{}>", lock.token)) {
+            response.headers_mut().insert("Lock-Token", value);
+        }
+        Ok(response)
+    }
+
+    fn handle_unlock(&self, gruxi_request: &mut GruxiRequest, full_path: &str) -> Result<GruxiResponse, GruxiError> {
+        let token = gruxi_request
+            .get_headers()
+            .get("Lock-Token")
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or("")
+            .trim()
+            .trim_start_matches('<')
+            .trim_end_matches('>')
+            .to_string();
+        match Self::get_active_lock(full_path) {
+            Some(lock) if lock.token == token => {
+                WEBDAV_LOCKS.retain(|_, l| l.token != token);
+                Self::response_with_status(hyper::StatusCode::NO_CONTENT)
+            }
+            _ => Self::response_with_status(hyper::StatusCode::CONFLICT),
+        }
+    }
+}
+
+impl ProcessorTrait for WebDavProcessor {
+    fn initialize(&mut self) {
+        if self.normalized_web_root.is_none() {
+            let normalized_path_result = NormalizedPath::new(&self.web_root, "");
+            self.normalized_web_root = match normalized_path_result {
+                Ok(path) => Some(path),
+                Err(_) => {
+                    error(format!("Failed to normalize WebDAV web root path: {}", self.web_root));
+                    return;
+                }
+            };
+        }
+    }
+
+    fn sanitize(&mut self) {
+        self.id = self.id.trim().to_string();
+        self.web_root = self.web_root.trim().replace("\\", "/");
+        self.auth_realm = self.auth_realm.trim().to_string();
+        sanitize_basic_auth_users(&mut self.auth_users);
+    }
+
+    fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+
+        if Uuid::parse_str(&self.id).is_err() {
+            errors.push(format!("Invalid ID, must be a valid UUID: {}", self.id));
+        }
+
+        if self.web_root.is_empty() {
+            errors.push("Web root cannot be empty".to_string());
+        } else if NormalizedPath::new(&self.web_root, "").is_err() {
+            errors.push(format!("Web root path is invalid: '{}' - Check strange characters and path format", self.web_root));
+        }
+
+        if self.require_authentication && self.auth_users.is_empty() {
+            errors.push("At least one user must be added when authentication is required".to_string());
+        }
+
+        errors.extend(validate_basic_auth_users(&self.auth_users));
+
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    async fn handle_request(&self, gruxi_request: &mut GruxiRequest, site: &Site) -> Result<GruxiResponse, GruxiError> {
+        let web_root = match self.normalized_web_root.as_ref() {
+            Some(path) => path.get_full_path().trim_end_matches('/').to_string(),
+            None => {
+                error(format!("WebDAV processor web root is not initialized as expected for id: '{}'", self.id));
+                return Err(GruxiError::new_with_kind_only(GruxiErrorKind::WebDavProcessor(WebDavProcessorError::Internal)));
+            }
+        };
+
+        // Authentication
+        if self.require_authentication {
+            let authorization = gruxi_request.get_headers().get("Authorization").and_then(|h| h.to_str().ok()).unwrap_or("").to_string();
+            if !verify_basic_auth(&self.auth_users, &authorization).await {
+                let mut response = empty_response_with_status(hyper::StatusCode::UNAUTHORIZED);
+                if let Ok(value) = HeaderValue::from_str(&format!("Basic realm=\"{}\"", self.auth_realm)) {
+                    response.headers_mut().insert("WWW-Authenticate", value);
+                }
+                return Ok(response);
+            }
+        }
+
+        let method = gruxi_request.get_http_method();
+        if self.read_only && WEBDAV_WRITE_METHODS.contains(&method.as_str()) {
+            return Self::response_with_status(hyper::StatusCode::FORBIDDEN);
+        }
+
+        // Methods that add to the disk usage are rejected while the site is over its disk quota
+        if matches!(method.as_str(), "PUT" | "MKCOL" | "COPY") && is_over_disk_quota(&site.id) {
+            debug(format!("WebDAV {} rejected, site '{}' is over its disk quota", method, site.id));
+            return Self::response_with_status(hyper::StatusCode::INSUFFICIENT_STORAGE);
+        }
+
+        let path = gruxi_request.get_path();
+        let full_path = match Self::resolve_path(&web_root, &path).await {
+            Some(p) => p,
+            None => return Self::response_with_status(hyper::StatusCode::NOT_FOUND),
+        };
+
+        trace(format!("WebDAV processor handling {} for path: {}", method, full_path));
+
+        // Modifying methods need to respect existing locks
+        if matches!(method.as_str(), "PUT" | "DELETE" | "MKCOL" | "MOVE") && Self::is_locked_for_request(&full_path, gruxi_request) {
+            return Self::response_with_status(hyper::StatusCode::LOCKED);
+        }
+
+        match method.as_str() {
+            "OPTIONS" => {
+                let mut response = empty_response_with_status(hyper::StatusCode::OK);
+                response.headers_mut().insert("DAV", HeaderValue::from_static("1, 2"));
+                response.headers_mut().insert("Allow", HeaderValue::from_static(WEBDAV_ALLOWED_METHODS));
+                response.headers_mut().insert("MS-Author-Via", HeaderValue::from_static("DAV"));
+                Ok(response)
+            }
+            "GET" => self.handle_get(&full_path, false).await,
+            "HEAD" => self.handle_get(&full_path, true).await,
+            "PUT" => self.handle_put(gruxi_request, &full_path, &site.id).await,
+            "DELETE" => self.handle_delete(&full_path, &web_root).await,
+            "MKCOL" => self.handle_mkcol(gruxi_request, &full_path).await,
+            "COPY" => self.handle_copy_or_move(gruxi_request, &full_path, &web_root, false).await,
+            "MOVE" => self.handle_copy_or_move(gruxi_request, &full_path, &web_root, true).await,
+            "PROPFIND" => self.handle_propfind(gruxi_request, &full_path, &web_root).await,
+            "LOCK" => self.handle_lock(gruxi_request, &full_path).await,
+            "UNLOCK" => self.handle_unlock(gruxi_request, &full_path),
+            _ => {
+                let mut response = empty_response_with_status(hyper::StatusCode::METHOD_NOT_ALLOWED);
+                response.headers_mut().insert("Allow", HeaderValue::from_static(WEBDAV_ALLOWED_METHODS));
+                Ok(response)
+            }
+        }
+    }
+
+    fn get_type(&self) -> String {
+        "webdav".to_string()
+    }
+
+    fn get_default_pretty_name(&self) -> String {
+        "WebDAV Processor".to_string()
+    }
+}
+
+async fn parent_exists(full_path: &str) -> bool {
+    match Path::new(full_path).parent() {
+        Some(parent) => tokio::fs::metadata(parent).await.map(|m| m.is_dir()).unwrap_or(false),
+        None => false,
+    }
+}
+
+// Why the body of an upload did not make it to its file, which is removed again
+#[derive(Debug)]
+enum UploadWriteError {
+    TooLarge,
+    Incomplete(BodyError),
+    Io(std::io::Error),
+}
+
+// Stream a request body into a new file, as long as it is within max_bytes, 0 for no limit. Returns the bytes written
+async fn write_body_to_file(mut body: BoxBody<Bytes, BodyError>, path: &str, max_bytes: u64) -> Result<u64, UploadWriteError> {
+    let mut file = tokio::fs::File::create(path).await.map_err(UploadWriteError::Io)?;
+    let mut written_bytes: u64 = 0;
+    let result = loop {
+        let data = match body.frame().await {
+            None => break file.flush().await.map_err(UploadWriteError::Io),
+            Some(Err(e)) => break Err(UploadWriteError::Incomplete(e)),
+            Some(Ok(frame)) => match frame.into_data() {
+                Ok(data) => data,
+                Err(_) => continue, // Trailers
+            },
+        };
+        written_bytes += data.len() as u64;
+        if max_bytes > 0 && written_bytes > max_bytes {
+            break Err(UploadWriteError::TooLarge);
+        }
+        if let Err(e) = file.write_all(&data).await {
+            break Err(UploadWriteError::Io(e));
+        }
+    };
+    drop(file);
+
+    match result {
+        Ok(()) => Ok(written_bytes),
+        Err(e) => {
+            let _ = tokio::fs::remove_file(path).await;
+            Err(e)
+        }
+    }
+}
+
+// Copy a file, or a directory with its content when recursive. Symlinks are refused, as they can lead out of the web root
+fn copy_path(source: &Path, destination: &Path, is_recursive: bool) -> std::io::Result<()> {
+    let metadata = std::fs::symlink_metadata(source)?;
+    if metadata.file_type().is_symlink() {
+        return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, format!("'{}' is a symlink", source.display())));
+    }
+    if metadata.is_dir() {
+        std::fs::create_dir(destination)?;
+        if is_recursive {
+            for entry in std::fs::read_dir(source)? {
+                let entry = entry?;
+                copy_path(&entry.path(), &destination.join(entry.file_name()), true)?;
+            }
+        }
+    } else {
+        std::fs::copy(source, destination)?;
+    }
+    Ok(())
+}
+
+fn format_http_date(time: SystemTime) -> String {
+    let datetime: DateTime<Utc> = time.into();
+    datetime.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn escape_xml(input: &str) -> String {
+    input.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;").replace('\'', "&apos;")
+}
+
+fn get_propfind_response_xml(full_path: &str, web_root: &str, metadata: &std::fs::Metadata) -> String {
+    // Build the href relative to the web root, with each segment url encoded
+    let relative_path = full_path.strip_prefix(web_root).unwrap_or("");
+    let mut href = relative_path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| urlencoding::encode(s).to_string())
+        .collect::<Vec<String>>()
+        .join("/");
+    href = format!("/{}", href);
+    if metadata.is_dir() && !href.ends_with('/') {
+        href.push('/');
+    }
+
+    let display_name = Path::new(full_path).file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let last_modified = metadata.modified().map(format_http_date).unwrap_or_default();
+
+    let mut props = format!("<D:displayname>{}</D:displayname><D:getlastmodified>{}</D:getlastmodified>", escape_xml(&display_name), last_modified);
+    if metadata.is_dir() {
+        props.push_str("<D:resourcetype><D:collection/></D:resourcetype>");
+    } else {
+        let mime_type = mime_guess::from_path(full_path).first_or_octet_stream().to_string();
+        props.push_str(&format!(
+            "<D:resourcetype/><D:getcontentlength>{}</D:getcontentlength><D:getcontenttype>{}</D:getcontenttype>",
+            metadata.len(),
+            escape_xml(&mime_type)
+        ));
+    }
+    props.push_str("<D:supportedlock><D:lockentry><D:lockscope><D:exclusive/></D:lockscope><D:locktype><D:write/></D:locktype></D:lockentry></D:supportedlock>");
+
+    format!(
+        "<D:response><D:href>{}</D:href><D:propstat><D:prop>{}</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n",
+        escape_xml(&href),
+        props
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_webdav_validation_requires_users_when_authentication_required() {
+        let processor = WebDavProcessor::new("./www-default".to_string());
+        let errors = processor.validate().unwrap_err();
+        assert!(errors.iter().any(|e| e.contains("At least one user")));
+    }
+
+    #[test]
+    fn test_webdav_validation_without_authentication() {
+        let mut processor = WebDavProcessor::new("./www-default".to_string());
+        processor.require_authentication = false;
+        assert!(processor.validate().is_ok());
+    }
+
+    #[test]
+    fn test_webdav_escape_xml() {
+        assert_eq!(escape_xml("a<b>&\"c'"), "a&lt;b&gt;&amp;&quot;c&apos;");
+    }
+
+    #[test]
+    fn test_webdav_copy_path_recursive() {
+        let base = std::env::temp_dir().join(format!("gruxi_webdav_test_{}", Uuid::new_v4()));
+        let source = base.join("source");
+        std::fs::create_dir_all(source.join("sub")).unwrap();
+        std::fs::write(source.join("sub").join("file.txt"), b"hello").unwrap();
+
+        copy_path(&source, &base.join("destination"), true).unwrap();
+        assert_eq!(std::fs::read(base.join("destination").join("sub").join("file.txt")).unwrap(), b"hello");
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_webdav_copy_path_refuses_symlinks() {
+        let base = std::env::temp_dir().join(format!("gruxi_webdav_test_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(base.join("root")).unwrap();
+        std::fs::write(base.join("secret.txt"), b"secret").unwrap();
+        std::os::unix::fs::symlink(base.join("secret.txt"), base.join("root").join("link.txt")).unwrap();
+
+        let error = copy_path(&base.join("root").join("link.txt"), &base.join("root").join("copy.txt"), true).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::PermissionDenied);
+        assert!(!base.join("root").join("copy.txt").exists());
+        assert!(copy_path(&base.join("root"), &base.join("root-copy"), true).is_err());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_webdav_upload_is_only_kept_when_complete_and_within_limit() {
+        let base = std::env::temp_dir().join(format!("gruxi_webdav_test_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&base).unwrap();
+        let path = base.join("upload.tmp").to_string_lossy().to_string();
+        let get_body = |chunks: Vec<Result<Frame<Bytes>, BodyError>>| BodyExt::boxed(StreamBody::new(futures::stream::iter(chunks)));
+
+        let body = get_body(vec![Ok(Frame::data(Bytes::from("hello "))), Ok(Frame::data(Bytes::from("world")))]);
+        assert_eq!(write_body_to_file(body, &path, 0).await.unwrap(), 11);
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello world");
+        std::fs::remove_file(&path).unwrap();
+
+        // A client that aborts the upload
+        let body = get_body(vec![Ok(Frame::data(Bytes::from("hello "))), Err(box_err(std::io::Error::from(std::io::ErrorKind::ConnectionReset)))]);
+        assert!(matches!(write_body_to_file(body, &path, 0).await, Err(UploadWriteError::Incomplete(_))));
+        assert!(!Path::new(&path).exists());
+
+        let body = get_body(vec![Ok(Frame::data(Bytes::from("hello "))), Ok(Frame::data(Bytes::from("world")))]);
+        assert!(matches!(write_body_to_file(body, &path, 10).await, Err(UploadWriteError::TooLarge)));
+        assert!(!Path::new(&path).exists());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -4,7 +4,10 @@ use tokio::sync::RwLock;
 use crate::{
     configuration::{request_handler::RequestHandler, site::Site},
     error::gruxi_error::GruxiError,
-    http::request_response::{gruxi_request::GruxiRequest, gruxi_response::GruxiResponse},
+    http::{
+        allowed_methods::{get_allowed_methods_for_processor_type, merge_allowed_methods},
+        request_response::{gruxi_request::GruxiRequest, gruxi_response::GruxiResponse},
+    },
     logging::syslog::trace,
 };
 
@@ -60,4 +63,17 @@ impl RequestHandlerManager {
         trace(format!("No request handler found for request path '{}'", &gruxi_request.get_path_and_query()));
         Ok(GruxiResponse::new_empty_with_status(hyper::StatusCode::NOT_FOUND.as_u16()))
     }
+
+    // The Allow header for "OPTIONS *" on a site, from the methods its enabled request handlers support
+    pub async fn get_allowed_methods_for_site(&self, site: &Site) -> String {
+        let request_handler_read_lock = self.request_handlers.read().await;
+        let allowed_methods_list: Vec<Option<&str>> = site
+            .request_handlers
+            .iter()
+            .filter_map(|request_handler_id| request_handler_read_lock.get(request_handler_id))
+            .filter(|handler| handler.is_enabled)
+            .map(|handler| get_allowed_methods_for_processor_type(&handler.processor_type))
+            .collect();
+        merge_allowed_methods(&allowed_methods_list)
+    }
 }
@@ -0,0 +1,659 @@
+// ============================================================================
+// REQUEST LIMITS
+// ============================================================================
+//
+// Hard limits on the size of requests, set server-wide:
+//   max_uri_length   - longer request targets are answered with 414
+//   max_header_count - more header fields are answered with 431
+//   max_header_size  - larger header sections are answered with 431
+//   max_chunk_size   - larger chunks of a chunked body are answered with 400
+//
+// On HTTP/1.x connections they are enforced on the bytes of the connection,
+// before hyper reads them, so a client cannot make it buffer an oversized head
+// or chunk. The stream follows the framing of the requests: the head of each
+// request is held back until it is complete and within the limits, and bodies
+// pass through while their length or chunk sizes are tracked.
+//
+// A head over a limit is replaced by a stand-in request, which the service
+// answers with the status of the limit before closing the connection, so the
+// answer comes after the responses to any earlier pipelined requests. A chunk
+// over the limit fails the body of its request, which is answered with a 400
+// unless the response was already sent.
+//
+// HTTP/2 connections are passed through, and their requests checked as hyper
+// parsed them. After a request to upgrade the connection, what follows is held
+// back until it is answered: a response switching protocols passes the rest
+// of the connection through, any other response lets it be read as requests
+// again. Malformed framing is passed through too, for hyper to reject, and the
+// service checks the requests hyper parsed of those connections as well.
+// ============================================================================
+
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker, ready};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use crate::configuration::server_settings::ServerSettings;
+use crate::http::request_line::HTTP2_PREFACE_LINE;
+use crate::logging::syslog::trace;
+
+// The method, version and separators of a request line, on top of its target
+const MAX_REQUEST_LINE_OVERHEAD: usize = 64;
+// Chunk size lines with longer extensions are passed through, hyper limits extensions too
+const MAX_CHUNK_SIZE_LINE_LENGTH: usize = 4096;
+// HTTP/2 counts 32 bytes for each header field on top of its name and value, HTTP/1.x the separator and line ending
+const HTTP2_HEADER_FIELD_OVERHEAD: usize = 32;
+const HTTP1_HEADER_FIELD_OVERHEAD: usize = 4;
+// The default of hyper, which is kept unless the limits need a larger buffer
+const MIN_HYPER_BUFFER_SIZE: usize = 8192 + 4096 * 100;
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+
+// Stands in for a request that was cut off, for the service to answer with the status of the limit
+const STAND_IN_REQUEST: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+#[derive(Debug, Clone, Copy)]
+pub struct RequestLimits {
+    pub max_header_count: usize,
+    pub max_header_size: usize,
+    pub max_uri_length: usize,
+    pub max_chunk_size: u64,
+}
+
+impl RequestLimits {
+    pub fn from_settings(server_settings: &ServerSettings) -> Self {
+        Self {
+            max_header_count: server_settings.max_header_count,
+            max_header_size: server_settings.max_header_size,
+            max_uri_length: server_settings.max_uri_length,
+            max_chunk_size: server_settings.max_chunk_size,
+        }
+    }
+
+    /// The read buffer hyper needs to hold a head within the limits
+    pub fn get_hyper_buffer_size(&self) -> usize {
+        (self.max_uri_length + MAX_REQUEST_LINE_OVERHEAD + self.max_header_size).max(MIN_HYPER_BUFFER_SIZE)
+    }
+
+    /// The header list size HTTP/2 connections accept, where the exact limits are checked with check_request
+    pub fn get_http2_header_list_size(&self) -> u32 {
+        (self.max_header_size + self.max_header_count * HTTP2_HEADER_FIELD_OVERHEAD).min(u32::MAX as usize) as u32
+    }
+
+    /// Check a request as hyper parsed it, for requests not read through a RequestLimitStream. Returns the status to answer it with when over a limit
+    pub fn check_request<B>(&self, request: &hyper::Request<B>) -> Option<u16> {
+        let uri_length = request.uri().path_and_query().map(|path_and_query| path_and_query.as_str().len()).unwrap_or(0);
+        if uri_length > self.max_uri_length {
+            return Some(414);
+        }
+        let headers = request.headers();
+        let header_size: usize = headers.iter().map(|(name, value)| name.as_str().len() + value.len() + HTTP1_HEADER_FIELD_OVERHEAD).sum();
+        if headers.len() > self.max_header_count || header_size > self.max_header_size {
+            return Some(431);
+        }
+        None
+    }
+}
+
+/// The request of a connection that went over a limit, by its position on the connection, with the status to answer it with
+#[derive(Debug, Clone, Default)]
+pub struct LimitViolation(Arc<Mutex<Option<(u32, u16)>>>);
+
+impl LimitViolation {
+    fn set(&self, request_index: u32, status: u16) {
+        *self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some((request_index, status));
+    }
+
+    /// The status to answer the request at the position on the connection with, when it went over a limit
+    pub fn get_status(&self, request_index: u32) -> Option<u16> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .filter(|(violating_index, _)| *violating_index == request_index)
+            .map(|(_, status)| status)
+    }
+}
+
+/// Whether the response to a request asking to upgrade the connection switched protocols, by the position of the request on
+/// the connection, for the stream to know if what follows is still HTTP/1.x
+#[derive(Debug, Clone, Default)]
+pub struct UpgradeOutcome(Arc<Mutex<UpgradeOutcomeState>>);
+
+#[derive(Debug, Default)]
+struct UpgradeOutcomeState {
+    outcome: Option<(u32, bool)>,
+    // The stream waiting for the outcome
+    waker: Option<Waker>,
+}
+
+impl UpgradeOutcome {
+    /// Set by the service for each HTTP/1.x response, before it is sent
+    pub fn set(&self, request_index: u32, is_upgraded: bool) {
+        let mut state = self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        state.outcome = Some((request_index, is_upgraded));
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+
+    // The outcome for the request, or None with the task woken once it is set
+    fn get(&self, request_index: u32, cx: &Context<'_>) -> Option<bool> {
+        let mut state = self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match state.outcome {
+            Some((index, is_upgraded)) if index == request_index => Some(is_upgraded),
+            _ => {
+                state.waker = Some(cx.waker().clone());
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Cutoff {
+    // The head of the next request, with the status to answer it with
+    Head(u16),
+    // A chunk of the body of the last request
+    Chunk,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Framing {
+    Head,
+    Body(u64),
+    ChunkSize,
+    // Including the line ending after the data
+    ChunkData(u64),
+    Trailers,
+    Passthrough,
+}
+
+// Follows the requests in the bytes of a connection, passing on what is within the limits
+struct RequestFraming {
+    limits: RequestLimits,
+    state: Framing,
+    // Heads passed on so far
+    requests: u32,
+    // The head being read, and where its current line starts
+    head: Vec<u8>,
+    line_start: usize,
+    request_line_seen: bool,
+    header_count: usize,
+    header_bytes: usize,
+    content_length: Option<u64>,
+    invalid_content_length: bool,
+    transfer_encoding_chunked: Option<bool>,
+    upgrade: bool,
+    // A request asking to upgrade the connection that is not answered yet, with what came after it
+    pending_upgrade: Option<u32>,
+    held: Vec<u8>,
+    // The chunk size or trailer line being read
+    line: Vec<u8>,
+}
+
+impl RequestFraming {
+    fn new(limits: RequestLimits) -> Self {
+        Self {
+            limits,
+            state: Framing::Head,
+            requests: 0,
+            head: Vec::new(),
+            line_start: 0,
+            request_line_seen: false,
+            header_count: 0,
+            header_bytes: 0,
+            content_length: None,
+            invalid_content_length: false,
+            transfer_encoding_chunked: None,
+            upgrade: false,
+            pending_upgrade: None,
+            held: Vec::new(),
+            line: Vec::new(),
+        }
+    }
+
+    // Whether everything up to a request asking to upgrade the connection is passed on, and the rest waits for its answer
+    fn is_waiting_for_upgrade(&self) -> Option<u32> {
+        self.pending_upgrade.filter(|_| self.state == Framing::Head)
+    }
+
+    // Go on with what came after a request asking to upgrade the connection, now that it is answered
+    fn resolve_upgrade(&mut self, is_upgraded: bool, output: &mut Vec<u8>) -> Result<(), Cutoff> {
+        self.pending_upgrade = None;
+        if is_upgraded {
+            self.state = Framing::Passthrough;
+        }
+        let held = std::mem::take(&mut self.held);
+        self.feed(&held, output)
+    }
+
+    // Add the bytes within the limits to the output, holding back an incomplete head
+    fn feed(&mut self, mut data: &[u8], output: &mut Vec<u8>) -> Result<(), Cutoff> {
+        while !data.is_empty() {
+            match self.state {
+                Framing::Passthrough => {
+                    output.extend_from_slice(data);
+                    return Ok(());
+                }
+                Framing::Body(remaining) | Framing::ChunkData(remaining) => {
+                    let length = remaining.min(data.len() as u64);
+                    output.extend_from_slice(&data[..length as usize]);
+                    data = &data[length as usize..];
+                    self.state = match self.state {
+                        Framing::Body(_) if length == remaining => Framing::Head,
+                        Framing::Body(_) => Framing::Body(remaining - length),
+                        _ if length == remaining => Framing::ChunkSize,
+                        _ => Framing::ChunkData(remaining - length),
+                    };
+                }
+                Framing::Head if self.pending_upgrade.is_some() => {
+                    self.held.extend_from_slice(data);
+                    return Ok(());
+                }
+                Framing::Head => {
+                    let used = self.feed_head(data, output)?;
+                    data = &data[used..];
+                }
+                Framing::ChunkSize | Framing::Trailers => {
+                    let used = self.feed_line(data, output)?;
+                    data = &data[used..];
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Read head bytes until the head is complete, returning how many were used
+    fn feed_head(&mut self, data: &[u8], output: &mut Vec<u8>) -> Result<usize, Cutoff> {
+        for (index, byte) in data.iter().enumerate() {
+            self.head.push(*byte);
+            let line_length = self.head.len() - self.line_start;
+            if *byte != b'\n' {
+                // The line being read is bounded by the limits too, so the head never grows beyond them
+                if !self.request_line_seen && line_length > self.limits.max_uri_length + MAX_REQUEST_LINE_OVERHEAD {
+                    return Err(Cutoff::Head(414));
+                }
+                if self.request_line_seen && self.header_bytes + line_length > self.limits.max_header_size {
+                    return Err(Cutoff::Head(431));
+                }
+                continue;
+            }
+
+            let content = trim_line_ending(&self.head[self.line_start..]);
+            self.line_start = self.head.len();
+            if !self.request_line_seen {
+                if content.is_empty() {
+                    // Empty lines before the request line are skipped by hyper
+                    self.header_bytes += line_length;
+                    continue;
+                }
+                if content == HTTP2_PREFACE_LINE {
+                    output.append(&mut self.head);
+                    self.state = Framing::Passthrough;
+                    return Ok(index + 1);
+                }
+                let mut parts = content.split(|byte| byte.is_ascii_whitespace()).filter(|part| !part.is_empty());
+                let method = parts.next().unwrap_or_default();
+                if parts.next().unwrap_or_default().len() > self.limits.max_uri_length {
+                    return Err(Cutoff::Head(414));
+                }
+                self.upgrade = method == b"CONNECT";
+                self.request_line_seen = true;
+                self.header_bytes = 0;
+                continue;
+            }
+            if content.is_empty() {
+                output.append(&mut self.head);
+                self.end_head();
+                return Ok(index + 1);
+            }
+
+            self.header_count += 1;
+            self.header_bytes += line_length;
+            if self.header_count > self.limits.max_header_count || self.header_bytes > self.limits.max_header_size {
+                return Err(Cutoff::Head(431));
+            }
+            let Some(colon) = content.iter().position(|byte| *byte == b':') else {
+                continue;
+            };
+            let (name, value) = (&content[..colon], content[colon + 1..].trim_ascii());
+            if name.eq_ignore_ascii_case(b"content-length") {
+                match std::str::from_utf8(value).ok().and_then(|value| value.parse::<u64>().ok()) {
+                    Some(length) if self.content_length.is_none_or(|content_length| content_length == length) => self.content_length = Some(length),
+                    _ => self.invalid_content_length = true,
+                }
+            } else if name.eq_ignore_ascii_case(b"transfer-encoding") {
+                let last_coding = value.rsplit(|byte| *byte == b',').next().unwrap_or_default().trim_ascii();
+                self.transfer_encoding_chunked = Some(last_coding.eq_ignore_ascii_case(b"chunked"));
+            } else if name.eq_ignore_ascii_case(b"upgrade") {
+                self.upgrade = true;
+            }
+        }
+        Ok(data.len())
+    }
+
+    // A complete head was passed on, so what follows is its body, or the next head
+    fn end_head(&mut self) {
+        self.requests += 1;
+        if self.upgrade {
+            self.pending_upgrade = Some(self.requests - 1);
+        }
+        self.state = match (self.transfer_encoding_chunked, self.content_length) {
+            (Some(true), _) => Framing::ChunkSize,
+            (Some(false), _) => Framing::Passthrough,
+            _ if self.invalid_content_length => Framing::Passthrough,
+            (_, Some(length)) if length > 0 => Framing::Body(length),
+            _ => Framing::Head,
+        };
+        self.line_start = 0;
+        self.request_line_seen = false;
+        self.header_count = 0;
+        self.header_bytes = 0;
+        self.content_length = None;
+        self.invalid_content_length = false;
+        self.transfer_encoding_chunked = None;
+        self.upgrade = false;
+    }
+
+    // Read a chunk size or trailer line, returning how many bytes were used
+    fn feed_line(&mut self, data: &[u8], output: &mut Vec<u8>) -> Result<usize, Cutoff> {
+        for (index, byte) in data.iter().enumerate() {
+            self.line.push(*byte);
+            if *byte != b'\n' {
+                if self.line.len() > MAX_CHUNK_SIZE_LINE_LENGTH.max(self.limits.max_header_size) {
+                    output.append(&mut self.line);
+                    self.state = Framing::Passthrough;
+                    return Ok(index + 1);
+                }
+                continue;
+            }
+
+            let content = trim_line_ending(&self.line);
+            self.state = match self.state {
+                Framing::ChunkSize => {
+                    let size = content.split(|byte| *byte == b';').next().unwrap_or_default().trim_ascii();
+                    match std::str::from_utf8(size).ok().and_then(|size| u64::from_str_radix(size, 16).ok()) {
+                        Some(size) if size > self.limits.max_chunk_size => return Err(Cutoff::Chunk),
+                        Some(0) => Framing::Trailers,
+                        Some(size) => Framing::ChunkData(size + 2),
+                        None => Framing::Passthrough,
+                    }
+                }
+                _ if content.is_empty() => Framing::Head,
+                state => state,
+            };
+            output.append(&mut self.line);
+            return Ok(index + 1);
+        }
+        Ok(data.len())
+    }
+}
+
+fn trim_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+#[derive(Debug, PartialEq)]
+enum Ending {
+    // The client closed the connection
+    Closed,
+    // The stand-in request was passed on. Nothing more is read, as hyper drops the response when the client closes, and
+    // closes the connection itself after answering the stand-in
+    CutOff,
+    // The body of the last request went over a limit
+    Failed,
+}
+
+/// A connection stream passing on only requests within the limits, see the top of this file
+pub struct RequestLimitStream<S> {
+    inner: S,
+    framing: RequestFraming,
+    violation: LimitViolation,
+    upgrade_outcome: UpgradeOutcome,
+    read_buffer: Vec<u8>,
+    pending: Vec<u8>,
+    position: usize,
+    ending: Option<Ending>,
+}
+
+impl<S> RequestLimitStream<S> {
+    pub fn new(inner: S, limits: RequestLimits, violation: LimitViolation, upgrade_outcome: UpgradeOutcome) -> Self {
+        Self {
+            inner,
+            framing: RequestFraming::new(limits),
+            violation,
+            upgrade_outcome,
+            read_buffer: vec![0u8; READ_BUFFER_SIZE],
+            pending: Vec::new(),
+            position: 0,
+            ending: None,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for RequestLimitStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.position < this.pending.len() {
+                let length = buf.remaining().min(this.pending.len() - this.position);
+                buf.put_slice(&this.pending[this.position..this.position + length]);
+                this.position += length;
+                return Poll::Ready(Ok(()));
+            }
+            this.pending.clear();
+            this.position = 0;
+            match this.ending {
+                Some(Ending::Closed) => return Poll::Ready(Ok(())),
+                Some(Ending::CutOff) => return Poll::Pending,
+                Some(Ending::Failed) => return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, "Chunk size over the limit"))),
+                None => {}
+            }
+
+            // Nothing more is read until a request asking to upgrade the connection is answered
+            if let Some(request_index) = this.framing.is_waiting_for_upgrade() {
+                let Some(is_upgraded) = this.upgrade_outcome.get(request_index, cx) else {
+                    return Poll::Pending;
+                };
+                let result = this.framing.resolve_upgrade(is_upgraded, &mut this.pending);
+                this.handle_cutoff(result);
+                continue;
+            }
+
+            let mut read_buf = ReadBuf::new(&mut this.read_buffer);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut read_buf))?;
+            if read_buf.filled().is_empty() {
+                // The client closed the connection, and hyper decides what to make of a head held back
+                this.pending.append(&mut this.framing.held);
+                this.pending.append(&mut this.framing.head);
+                this.ending = Some(Ending::Closed);
+                continue;
+            }
+            let result = this.framing.feed(read_buf.filled(), &mut this.pending);
+            this.handle_cutoff(result);
+        }
+    }
+}
+
+impl<S> RequestLimitStream<S> {
+    fn handle_cutoff(&mut self, result: Result<(), Cutoff>) {
+        match result {
+            Ok(()) => {}
+            Err(Cutoff::Head(status)) => {
+                trace(format!("Request {} on the connection is over the request limits, answering with {}", self.framing.requests + 1, status));
+                self.violation.set(self.framing.requests, status);
+                self.pending.extend_from_slice(STAND_IN_REQUEST);
+                self.ending = Some(Ending::CutOff);
+            }
+            Err(Cutoff::Chunk) => {
+                trace(format!("Request {} on the connection has a chunk over the request limits", self.framing.requests));
+                self.violation.set(self.framing.requests.saturating_sub(1), 400);
+                self.ending = Some(Ending::Failed);
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for RequestLimitStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(mut self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[io::IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn get_test_limits() -> RequestLimits {
+        RequestLimits {
+            max_header_count: 4,
+            max_header_size: 100,
+            max_uri_length: 20,
+            max_chunk_size: 16,
+        }
+    }
+
+    // Feed the data in pieces of the size, returning the output and the cutoff, if any
+    fn feed_in_pieces(data: &[u8], piece_size: usize) -> (Vec<u8>, Option<Cutoff>, u32) {
+        let mut framing = RequestFraming::new(get_test_limits());
+        let mut output = Vec::new();
+        for piece in data.chunks(piece_size) {
+            if let Err(cutoff) = framing.feed(piece, &mut output) {
+                return (output, Some(cutoff), framing.requests);
+            }
+        }
+        (output, None, framing.requests)
+    }
+
+    #[test]
+    fn test_request_framing_within_limits() {
+        let requests = b"GET /a HTTP/1.1\r\nHost: x\r\n\r\nPOST /b HTTP/1.1\r\nHost: x\r\nContent-Length: 5\r\n\r\nhelloPOST /c HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n10\r\n0123456789abcdef\r\n3;ext=1\r\nabc\r\n0\r\nX-Trailer: 1\r\n\r\nGET /d HTTP/1.1\r\n\r\n";
+        for piece_size in [1, 7, requests.len()] {
+            let (output, cutoff, count) = feed_in_pieces(requests, piece_size);
+            assert_eq!(cutoff, None);
+            assert_eq!(output, requests.to_vec());
+            assert_eq!(count, 4);
+        }
+
+        // An incomplete head is held back
+        let (output, _, count) = feed_in_pieces(b"GET / HTTP/1.1\r\nHost: x\r\n", 100);
+        assert!(output.is_empty());
+        assert_eq!(count, 0);
+
+        // HTTP/2 connections are passed through
+        let (output, _, _) = feed_in_pieces(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n", 3);
+        assert_eq!(output, b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n".to_vec());
+
+        // Malformed framing is passed through for hyper to reject
+        let invalid_length = b"POST / HTTP/1.1\r\nContent-Length: 2, 2\r\n\r\nabGET /aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa HTTP/1.1\r\n\r\n";
+        assert_eq!(feed_in_pieces(invalid_length, 9), (invalid_length.to_vec(), None, 1));
+    }
+
+    #[test]
+    fn test_request_framing_upgrade() {
+        let head = b"GET /ws HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n";
+        let frames = format!("binary frames that are not HTTP, and no request line within the limits: {}", "x".repeat(100));
+        let frames = frames.as_bytes();
+
+        // What follows a request to upgrade is held back until it is answered, and then passed through when switching protocols
+        let mut framing = RequestFraming::new(get_test_limits());
+        let mut output = Vec::new();
+        framing.feed(&[head.as_slice(), frames].concat(), &mut output).unwrap();
+        assert_eq!(output, head.to_vec());
+        assert_eq!(framing.is_waiting_for_upgrade(), Some(0));
+        framing.resolve_upgrade(true, &mut output).unwrap();
+        framing.feed(b" more frames", &mut output).unwrap();
+        assert_eq!(output, [head.as_slice(), frames, b" more frames"].concat());
+
+        // Or read as requests again when the upgrade was refused, so they are within the limits
+        let mut framing = RequestFraming::new(get_test_limits());
+        let mut output = Vec::new();
+        framing.feed(&[head.as_slice(), frames].concat(), &mut output).unwrap();
+        assert_eq!(framing.resolve_upgrade(false, &mut output), Err(Cutoff::Head(414)));
+        assert_eq!(output, head.to_vec());
+    }
+
+    #[test]
+    fn test_request_framing_cutoffs() {
+        let long_uri = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(20));
+        assert_eq!(feed_in_pieces(long_uri.as_bytes(), 100).1, Some(Cutoff::Head(414)));
+        // Also before the request line is complete
+        assert_eq!(feed_in_pieces(format!("GET /{}", "a".repeat(200)).as_bytes(), 1).1, Some(Cutoff::Head(414)));
+
+        let many_headers = b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\nE: 5\r\n\r\n";
+        assert_eq!(feed_in_pieces(many_headers, 100).1, Some(Cutoff::Head(431)));
+        let large_header = format!("GET / HTTP/1.1\r\nX-Large: {}", "x".repeat(100));
+        assert_eq!(feed_in_pieces(large_header.as_bytes(), 1).1, Some(Cutoff::Head(431)));
+
+        // The first request is passed on before the second is cut off
+        let pipelined = format!("GET / HTTP/1.1\r\n\r\nGET /{} HTTP/1.1\r\n\r\n", "a".repeat(30));
+        let (output, cutoff, count) = feed_in_pieces(pipelined.as_bytes(), 1000);
+        assert_eq!(output, b"GET / HTTP/1.1\r\n\r\n".to_vec());
+        assert_eq!(cutoff, Some(Cutoff::Head(414)));
+        assert_eq!(count, 1);
+
+        let large_chunk = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n11\r\n";
+        assert_eq!(feed_in_pieces(large_chunk, 4).1, Some(Cutoff::Chunk));
+    }
+
+    #[tokio::test]
+    async fn test_request_limit_stream_stand_in() {
+        let (mut client, server) = tokio::io::duplex(4096);
+        let violation = LimitViolation::default();
+        let mut stream = RequestLimitStream::new(server, get_test_limits(), violation.clone(), UpgradeOutcome::default());
+        client.write_all(b"GET / HTTP/1.1\r\n\r\nGET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\nD: 4\r\nE: 5\r\n\r\n").await.unwrap();
+
+        let expected = [b"GET / HTTP/1.1\r\n\r\n".as_slice(), STAND_IN_REQUEST].concat();
+        let mut passed_on = vec![0u8; expected.len()];
+        stream.read_exact(&mut passed_on).await.unwrap();
+        assert_eq!(passed_on, expected);
+        // Nothing is passed on after the stand-in
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(50), stream.read_u8()).await.is_err());
+        assert_eq!(violation.get_status(0), None);
+        assert_eq!(violation.get_status(1), Some(431));
+    }
+
+    #[tokio::test]
+    async fn test_request_limit_stream_waits_for_upgrade_outcome() {
+        let (mut client, server) = tokio::io::duplex(4096);
+        let violation = LimitViolation::default();
+        let upgrade_outcome = UpgradeOutcome::default();
+        let mut stream = RequestLimitStream::new(server, get_test_limits(), violation.clone(), upgrade_outcome.clone());
+        let head = b"GET /ws HTTP/1.1\r\nUpgrade: websocket\r\n\r\n";
+        client
+            .write_all(&[head.as_slice(), format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(30)).as_bytes()].concat())
+            .await
+            .unwrap();
+
+        let mut passed_on = vec![0u8; head.len()];
+        stream.read_exact(&mut passed_on).await.unwrap();
+        assert_eq!(passed_on, head.to_vec());
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(50), stream.read_u8()).await.is_err());
+
+        // Answered without switching protocols, so the next request is cut off for its URI
+        upgrade_outcome.set(0, false);
+        let mut passed_on = vec![0u8; STAND_IN_REQUEST.len()];
+        stream.read_exact(&mut passed_on).await.unwrap();
+        assert_eq!(passed_on, STAND_IN_REQUEST.to_vec());
+        assert_eq!(violation.get_status(1), Some(414));
+    }
+}
@@ -0,0 +1,241 @@
+// ============================================================================
+// REQUEST LINE POLICY
+// ============================================================================
+//
+// Checks the request line of the first request on a HTTP/1.x connection before
+// hyper parses it, so unsupported HTTP versions get a 505 HTTP Version Not
+// Supported instead of the generic 400 of the parser. Malformed request lines
+// get a 400. How strictly this is done is set server-wide:
+//
+//   lenient  - HTTP/1.2 and later 1.x versions are served as HTTP/1.1, as they
+//              are compatible (RFC 9110 section 6.2), and requests without a
+//              version (HTTP/0.9) get a 505
+//   standard - only HTTP/1.0 and HTTP/1.1 are served, other well-formed
+//              versions get a 505 and requests without a version a 400
+//   strict   - as standard, and the request line must end with CRLF, use
+//              single spaces and not be preceded by empty lines
+//
+// Later requests on kept-alive connections are left to hyper, which answers
+// any invalid version with a 400. The HTTP/2 connection preface passes through.
+// ============================================================================
+
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+use crate::logging::syslog::trace;
+
+pub const HTTP_VERSION_POLICIES: [&str; 3] = ["lenient", "standard", "strict"];
+
+// Longer request lines are left to the request limits, which answer them with a 414
+const MAX_REQUEST_LINE_LENGTH: usize = 8192;
+const REQUEST_LINE_TIMEOUT_SECS: u64 = 60;
+pub const HTTP2_PREFACE_LINE: &[u8] = b"PRI * HTTP/2.0";
+
+#[derive(Debug, PartialEq)]
+pub enum RequestLineVerdict {
+    Accept,
+    // Served as HTTP/1.1, by replacing the version in the request line
+    ServeAsHttp11,
+    Reject(u16),
+}
+
+// Check a request line, with its line ending, against the policy
+pub fn check_request_line(line: &[u8], policy: &str) -> RequestLineVerdict {
+    let is_strict = policy == "strict";
+    let is_lenient = policy == "lenient";
+
+    if line.starts_with(b"\n") || line.starts_with(b"\r\n") {
+        // Empty lines before the request line are skipped by the parser, so only strict mode looks at them
+        return if is_strict { RequestLineVerdict::Reject(400) } else { RequestLineVerdict::Accept };
+    }
+    let Some(content) = line.strip_suffix(b"\n") else {
+        return RequestLineVerdict::Accept;
+    };
+    let content = match content.strip_suffix(b"\r") {
+        Some(content) => content,
+        None if is_strict => return RequestLineVerdict::Reject(400),
+        None => content,
+    };
+    if content == HTTP2_PREFACE_LINE {
+        return RequestLineVerdict::Accept;
+    }
+
+    let Ok(content) = std::str::from_utf8(content) else {
+        return RequestLineVerdict::Reject(400);
+    };
+    let parts: Vec<&str> = if is_strict { content.split(' ').collect() } else { content.split_whitespace().collect() };
+    if parts.iter().any(|part| part.is_empty()) {
+        return RequestLineVerdict::Reject(400);
+    }
+
+    match parts.as_slice() {
+        [_, _, "HTTP/1.0" | "HTTP/1.1"] => RequestLineVerdict::Accept,
+        [_, _, version] => match parse_http_version(version) {
+            Some((1, _)) if is_lenient => RequestLineVerdict::ServeAsHttp11,
+            Some(_) => RequestLineVerdict::Reject(505),
+            None => RequestLineVerdict::Reject(400),
+        },
+        // "GET /" is a HTTP/0.9 request
+        [_, _] if is_lenient => RequestLineVerdict::Reject(505),
+        _ => RequestLineVerdict::Reject(400),
+    }
+}
+
+// "HTTP/1.1" as (1, 1), and "HTTP/2" as (2, 0)
+fn parse_http_version(version: &str) -> Option<(u8, u8)> {
+    let number = version.strip_prefix("HTTP/")?;
+    let (major, minor) = number.split_once('.').unwrap_or((number, "0"));
+    if major.len() != 1 || minor.len() != 1 {
+        return None;
+    }
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// Read the first request line of a connection and check it against the policy. The bytes read are returned to be
+/// served before the rest of the connection, or None when the connection was answered with an error and closed
+pub async fn check_first_request_line<S>(stream: &mut S, policy: &str) -> Option<Vec<u8>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut buffer = Vec::with_capacity(1024);
+    let read_result = tokio::time::timeout(Duration::from_secs(REQUEST_LINE_TIMEOUT_SECS), async {
+        let mut chunk = [0u8; 1024];
+        while !buffer.contains(&b'\n') && buffer.len() < MAX_REQUEST_LINE_LENGTH {
+            let read = stream.read(&mut chunk).await?;
+            if read == 0 {
+                break;
+            }
+            buffer.extend_from_slice(&chunk[..read]);
+        }
+        Ok::<_, io::Error>(())
+    })
+    .await;
+    if !matches!(read_result, Ok(Ok(()))) || buffer.is_empty() {
+        return None;
+    }
+
+    let line_end = buffer.iter().position(|byte| *byte == b'\n').map(|index| index + 1).unwrap_or(buffer.len());
+    match check_request_line(&buffer[..line_end], policy) {
+        RequestLineVerdict::Accept => Some(buffer),
+        RequestLineVerdict::ServeAsHttp11 => {
+            // The version is the last part of the line, before the line ending
+            let version_end = line_end - if buffer[..line_end].ends_with(b"\r\n") { 2 } else { 1 };
+            let version_start = buffer[..version_end].iter().rposition(|byte| byte.is_ascii_whitespace()).map(|index| index + 1)?;
+            buffer.splice(version_start..version_end, b"HTTP/1.1".iter().copied());
+            Some(buffer)
+        }
+        RequestLineVerdict::Reject(status) => {
+            trace(format!("Rejected request line '{}' with status {}", String::from_utf8_lossy(&buffer[..line_end]).trim_end(), status));
+            let reason = if status == 505 { "HTTP Version Not Supported" } else { "Bad Request" };
+            let response = format!("HTTP/1.1 {} {}\r\nServer: Gruxi\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status, reason);
+            let _ = stream.write_all(response.as_bytes()).await;
+            let _ = stream.shutdown().await;
+            None
+        }
+    }
+}
+
+// A stream that first returns bytes that were already read from it
+pub struct PrefixedStream<S> {
+    prefix: Vec<u8>,
+    position: usize,
+    inner: S,
+}
+
+impl<S> PrefixedStream<S> {
+    pub fn new(prefix: Vec<u8>, inner: S) -> Self {
+        Self { prefix, position: 0, inner }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for PrefixedStream<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        if self.position < self.prefix.len() {
+            let length = buf.remaining().min(self.prefix.len() - self.position);
+            let start = self.position;
+            buf.put_slice(&self.prefix[start..start + length]);
+            self.position += length;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for PrefixedStream<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(mut self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[io::IoSlice<'_>]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_request_line() {
+        use RequestLineVerdict::*;
+
+        for policy in HTTP_VERSION_POLICIES {
+            assert_eq!(check_request_line(b"GET / HTTP/1.1\r\n", policy), Accept);
+            assert_eq!(check_request_line(b"GET /index.html HTTP/1.0\r\n", policy), Accept);
+            assert_eq!(check_request_line(b"PRI * HTTP/2.0\r\n", policy), Accept);
+            assert_eq!(check_request_line(b"GET / HTTP/2.0\r\n", policy), Reject(505));
+            assert_eq!(check_request_line(b"GET / HTTP/3\r\n", policy), Reject(505));
+            assert_eq!(check_request_line(b"GET / HTTP/X.Y\r\n", policy), Reject(400));
+            assert_eq!(check_request_line(b"GET / FOO/1.1\r\n", policy), Reject(400));
+            assert_eq!(check_request_line(b"GET / HTTP/1.1 extra\r\n", policy), Reject(400));
+        }
+
+        assert_eq!(check_request_line(b"GET / HTTP/1.2\r\n", "lenient"), ServeAsHttp11);
+        assert_eq!(check_request_line(b"GET / HTTP/1.2\r\n", "standard"), Reject(505));
+        assert_eq!(check_request_line(b"GET /\r\n", "lenient"), Reject(505));
+        assert_eq!(check_request_line(b"GET /\r\n", "standard"), Reject(400));
+
+        // Bare line feeds, double spaces and leading empty lines are only rejected in strict mode
+        assert_eq!(check_request_line(b"GET / HTTP/1.1\n", "standard"), Accept);
+        assert_eq!(check_request_line(b"GET / HTTP/1.1\n", "strict"), Reject(400));
+        assert_eq!(check_request_line(b"GET  / HTTP/1.1\r\n", "standard"), Accept);
+        assert_eq!(check_request_line(b"GET  / HTTP/1.1\r\n", "strict"), Reject(400));
+        assert_eq!(check_request_line(b"\r\n", "standard"), Accept);
+        assert_eq!(check_request_line(b"\r\n", "strict"), Reject(400));
+    }
+
+    #[tokio::test]
+    async fn test_check_first_request_line() {
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        client.write_all(b"GET / HTTP/1\r\nHost: localhost\r\n\r\n").await.unwrap();
+        let prefix = check_first_request_line(&mut server, "lenient").await.unwrap();
+        let mut stream = PrefixedStream::new(prefix, server);
+        let expected_request = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        let mut request = vec![0u8; expected_request.len()];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(request, expected_request);
+
+        let (mut client, mut server) = tokio::io::duplex(4096);
+        client.write_all(b"GET / HTTP/2.0\r\nHost: localhost\r\n\r\n").await.unwrap();
+        assert!(check_first_request_line(&mut server, "standard").await.is_none());
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 505 HTTP Version Not Supported\r\n"));
+    }
+}
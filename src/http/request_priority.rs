@@ -0,0 +1,274 @@
+// ============================================================================
+// REQUEST PRIORITY
+// ============================================================================
+//
+// Admission of requests by priority class, when request priority is enabled:
+//   - High priority requests (admin portal bindings and the configured high
+//     priority paths) are always let through, and are never queued or shed
+//   - Normal and low priority requests share max_concurrent_requests slots.
+//     When all are taken they wait, and a freed slot goes to the oldest
+//     normal priority waiter before any low priority one
+//   - When the queue is full, the newest low priority waiter is shed to make
+//     room for a normal priority request. Otherwise the new request is
+//     rejected
+// ============================================================================
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use crate::configuration::request_priority::RequestPrioritySettings;
+
+// Sent in the "Retry-After" header, when a request is rejected or shed because the server is overloaded
+pub const REQUEST_PRIORITY_RETRY_AFTER_SECONDS: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestPriority {
+    High,
+    Normal,
+    Low,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RequestAdmissionError {
+    Full,    // Too many requests are already waiting
+    Timeout, // Waited for the queue timeout, without a free slot
+    Shed,    // Made room in the queue for a request of higher priority
+}
+
+// Classify a request by its path, with the longest matching prefix deciding when both lists match
+pub fn get_request_priority(settings: &RequestPrioritySettings, is_admin_binding: bool, path: &str) -> RequestPriority {
+    if is_admin_binding {
+        return RequestPriority::High;
+    }
+    let longest_match = |paths: &[String]| paths.iter().filter(|prefix| path.starts_with(prefix.as_str())).map(|prefix| prefix.len()).max();
+    match (longest_match(&settings.high_priority_paths), longest_match(&settings.low_priority_paths)) {
+        (Some(high), Some(low)) if low > high => RequestPriority::Low,
+        (Some(_), _) => RequestPriority::High,
+        (None, Some(_)) => RequestPriority::Low,
+        (None, None) => RequestPriority::Normal,
+    }
+}
+
+// The permit is sent to the waiter, so it is released again if the waiting request is cancelled just after getting it
+struct Waiter {
+    id: u64,
+    sender: oneshot::Sender<RequestAdmissionPermit>,
+}
+
+#[derive(Default)]
+struct AdmissionState {
+    active: usize, // Normal and low priority requests holding a slot
+    normal_waiters: VecDeque<Waiter>,
+    low_waiters: VecDeque<Waiter>,
+    admitted: u64,
+    rejected: u64,
+    shed: u64,
+}
+
+impl AdmissionState {
+    // Waiters whose request was cancelled while waiting are dropped, so they do not take up room in the queue
+    fn waiting_count(&mut self) -> usize {
+        self.normal_waiters.retain(|waiter| !waiter.sender.is_closed());
+        self.low_waiters.retain(|waiter| !waiter.sender.is_closed());
+        self.normal_waiters.len() + self.low_waiters.len()
+    }
+
+    fn remove_waiter(&mut self, id: u64) -> bool {
+        for waiters in [&mut self.normal_waiters, &mut self.low_waiters] {
+            if let Some(index) = waiters.iter().position(|waiter| waiter.id == id) {
+                waiters.remove(index);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+pub struct RequestAdmission {
+    state: Mutex<AdmissionState>,
+    next_waiter_id: AtomicU64,
+}
+
+// Held while the request is handled. Dropping it hands the slot to the next waiter
+pub struct RequestAdmissionPermit {
+    admission: Option<&'static RequestAdmission>, // None for high priority requests, which do not take a slot
+}
+
+impl Drop for RequestAdmissionPermit {
+    fn drop(&mut self) {
+        if let Some(admission) = self.admission {
+            admission.release();
+        }
+    }
+}
+
+impl Default for RequestAdmission {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestAdmission {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(AdmissionState::default()),
+            next_waiter_id: AtomicU64::new(1),
+        }
+    }
+
+    pub async fn acquire(&'static self, priority: RequestPriority, settings: &RequestPrioritySettings) -> Result<RequestAdmissionPermit, RequestAdmissionError> {
+        if priority == RequestPriority::High {
+            self.lock_state().admitted += 1;
+            return Ok(RequestAdmissionPermit { admission: None });
+        }
+
+        let id = self.next_waiter_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = oneshot::channel();
+        {
+            let mut state = self.lock_state();
+            let nobody_waiting = state.waiting_count() == 0;
+            if state.active < settings.max_concurrent_requests as usize && nobody_waiting {
+                state.active += 1;
+                state.admitted += 1;
+                return Ok(RequestAdmissionPermit { admission: Some(self) });
+            }
+
+            if state.waiting_count() >= settings.max_queue_length as usize {
+                // Dropping the sender tells the newest low priority waiter that it is shed
+                if priority == RequestPriority::Normal && state.low_waiters.pop_back().is_some() {
+                    state.shed += 1;
+                } else {
+                    state.rejected += 1;
+                    return Err(RequestAdmissionError::Full);
+                }
+            }
+
+            let waiter = Waiter { id, sender };
+            match priority {
+                RequestPriority::Low => state.low_waiters.push_back(waiter),
+                _ => state.normal_waiters.push_back(waiter),
+            }
+        }
+
+        let mut receiver = receiver;
+        match tokio::time::timeout(Duration::from_secs(settings.queue_timeout_seconds as u64), &mut receiver).await {
+            Ok(Ok(permit)) => Ok(permit),
+            Ok(Err(_)) => Err(RequestAdmissionError::Shed),
+            Err(_) => {
+                {
+                    let mut state = self.lock_state();
+                    if state.remove_waiter(id) {
+                        state.rejected += 1;
+                        return Err(RequestAdmissionError::Timeout);
+                    }
+                }
+                // The slot was handed over or the waiter shed, just as the timeout hit
+                receiver.try_recv().map_err(|_| RequestAdmissionError::Shed)
+            }
+        }
+    }
+
+    // Hand the slot to the oldest waiter of the highest priority, or free it when nobody waits
+    fn release(&'static self) {
+        let mut state = self.lock_state();
+        loop {
+            let next_waiter = match state.normal_waiters.pop_front() {
+                Some(waiter) => Some(waiter),
+                None => state.low_waiters.pop_front(),
+            };
+            let Some(waiter) = next_waiter else {
+                state.active = state.active.saturating_sub(1);
+                return;
+            };
+            match waiter.sender.send(RequestAdmissionPermit { admission: Some(self) }) {
+                Ok(()) => {
+                    state.admitted += 1;
+                    return;
+                }
+                // The request stopped waiting, so the slot goes to the next one. The permit must not release it again
+                Err(mut permit) => permit.admission = None,
+            }
+        }
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, AdmissionState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn get_json(&self) -> serde_json::Value {
+        let mut state = self.lock_state();
+        serde_json::json!({
+            "active": state.active,
+            "waiting": state.waiting_count(),
+            "waiting_low_priority": state.low_waiters.len(),
+            "admitted": state.admitted,
+            "rejected": state.rejected,
+            "shed": state.shed,
+        })
+    }
+}
+
+static REQUEST_ADMISSION: LazyLock<RequestAdmission> = LazyLock::new(RequestAdmission::new);
+
+pub fn get_request_admission() -> &'static RequestAdmission {
+    &REQUEST_ADMISSION
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(max_concurrent_requests: u32, max_queue_length: u32) -> RequestPrioritySettings {
+        RequestPrioritySettings {
+            is_enabled: true,
+            max_concurrent_requests,
+            max_queue_length,
+            queue_timeout_seconds: 5,
+            high_priority_paths: vec!["/health".to_string(), "/api/status".to_string()],
+            low_priority_paths: vec!["/api".to_string(), "/search".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_get_request_priority() {
+        let settings = settings(1, 1);
+        assert_eq!(get_request_priority(&settings, true, "/search"), RequestPriority::High);
+        assert_eq!(get_request_priority(&settings, false, "/health"), RequestPriority::High);
+        assert_eq!(get_request_priority(&settings, false, "/api/status/cpu"), RequestPriority::High);
+        assert_eq!(get_request_priority(&settings, false, "/api/orders"), RequestPriority::Low);
+        assert_eq!(get_request_priority(&settings, false, "/search?q=x"), RequestPriority::Low);
+        assert_eq!(get_request_priority(&settings, false, "/index.html"), RequestPriority::Normal);
+    }
+
+    #[tokio::test]
+    async fn test_request_admission_prefers_higher_priority() {
+        let admission: &'static RequestAdmission = Box::leak(Box::new(RequestAdmission::new()));
+        let settings = settings(1, 1);
+
+        let permit = admission.acquire(RequestPriority::Normal, &settings).await.unwrap();
+        // High priority requests get through regardless
+        assert!(admission.acquire(RequestPriority::High, &settings).await.is_ok());
+
+        // A low priority request waits, and is shed when a normal priority one needs its place in the queue
+        let low_settings = settings.clone();
+        let low_request = tokio::spawn(async move { admission.acquire(RequestPriority::Low, &low_settings).await.err() });
+        while admission.lock_state().low_waiters.is_empty() {
+            tokio::task::yield_now().await;
+        }
+        let normal_settings = settings.clone();
+        let normal_request = tokio::spawn(async move { admission.acquire(RequestPriority::Normal, &normal_settings).await.is_ok() });
+        assert_eq!(low_request.await.unwrap(), Some(RequestAdmissionError::Shed));
+
+        // Another low priority request does not fit the queue, and the waiting normal one gets the freed slot
+        assert_eq!(admission.acquire(RequestPriority::Low, &settings).await.err(), Some(RequestAdmissionError::Full));
+        drop(permit);
+        assert!(normal_request.await.unwrap());
+        assert_eq!(admission.lock_state().active, 0);
+    }
+}
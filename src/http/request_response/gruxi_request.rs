@@ -1,7 +1,9 @@
 use http::HeaderValue;
-use http::header::HOST;
+use http::header::{CONTENT_LENGTH, HOST};
 use http::request::Parts;
 use http_body_util::BodyExt;
+use http_body_util::Full;
+use http_body_util::Limited;
 use http_body_util::combinators::BoxBody;
 use hyper::HeaderMap;
 use hyper::Request;
@@ -9,10 +11,12 @@ use hyper::body::Body;
 use hyper::body::Bytes;
 use std::collections::HashMap;
 use std::mem;
-use std::sync::Arc;
-use tokio::sync::Semaphore;
 
+use crate::http::http_util::strip_port;
+use crate::http::request_handlers::processor_trait::RequestBodyInspector;
+use crate::http::request_response::body_error::{BodyError, box_err};
 use crate::http::request_response::gruxi_body::GruxiBody;
+use crate::http::request_response::inspected_body::{BodyInspectorChain, BodyRejection, InspectedBody};
 
 // Wrapper around hyper Request to add calculated data and serve as a request in Gruxi
 #[derive(Debug)]
@@ -22,10 +26,10 @@ pub struct GruxiRequest {
     body: GruxiBody,
     // Calculated data cache, such as remote_ip, hostname etc
     pub calculated_data: HashMap<String, String>,
-    // Optional connection semaphore for limiting concurrent requests
-    pub connection_semaphore: Option<Arc<Semaphore>>,
     // Upgrade future for handling protocol upgrades
     upgrade_future: Option<hyper::upgrade::OnUpgrade>,
+    // Inspectors the body goes through when it is read
+    body_inspectors: BodyInspectorChain,
 }
 
 impl GruxiRequest {
@@ -44,8 +48,8 @@ impl GruxiRequest {
             parts,
             body: GruxiBody::Buffered(body),
             calculated_data,
-            connection_semaphore: None,
             upgrade_future,
+            body_inspectors: BodyInspectorChain::default(),
         }
     }
 
@@ -66,8 +70,8 @@ impl GruxiRequest {
             parts,
             body,
             calculated_data,
-            connection_semaphore: None,
             upgrade_future,
+            body_inspectors: BodyInspectorChain::default(),
         }
     }
 
@@ -75,12 +79,17 @@ impl GruxiRequest {
         &self.parts.headers
     }
 
-    pub fn get_connection_semaphore(&self) -> Option<Arc<Semaphore>> {
-        self.connection_semaphore.clone()
-    }
-
-    pub fn set_connection_semaphore(&mut self, semaphore: Arc<Semaphore>) {
-        self.connection_semaphore = Some(semaphore);
+    // The value of a cookie sent by the client, from any of its Cookie headers
+    pub fn get_cookie(&self, name: &str) -> Option<String> {
+        self.parts
+            .headers
+            .get_all(hyper::header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .find(|(cookie_name, _)| *cookie_name == name)
+            .map(|(_, value)| value.trim_matches('"').to_string())
     }
 
     pub fn add_calculated_data(&mut self, key: &str, value: &str) {
@@ -96,25 +105,14 @@ impl GruxiRequest {
             return hostname.clone();
         }
 
-        // Default to empty string
-        let mut hostname = String::new();
-
-        // Host / :authority
-        if let Some(host) = self.parts.headers.get(HOST) {
-            if let Ok(host) = host.to_str() {
-                hostname = host.to_string();
-            }
-        }
-
-        // Absolute-form URI (proxy requests)
-        if let Some(authority) = self.parts.uri.authority() {
-            hostname = authority.as_str().to_string();
-        }
+        // Absolute-form URI (proxy requests), or else Host / :authority. Defaults to empty string
+        let host = match self.parts.uri.authority() {
+            Some(authority) => authority.as_str(),
+            None => self.parts.headers.get(HOST).and_then(|host| host.to_str().ok()).unwrap_or(""),
+        };
 
-        // Remove any ports if present
-        if let Some(colon_index) = hostname.find(':') {
-            hostname = hostname[..colon_index].to_string();
-        }
+        // Remove any ports if present, without cutting into IPv6 addresses
+        let hostname = strip_port(host).to_string();
 
         self.add_calculated_data("hostname", &hostname);
         hostname
@@ -158,6 +156,11 @@ impl GruxiRequest {
         http_method
     }
 
+    // The method, borrowed from the request, for hot paths that only compare it
+    pub fn get_http_method_str(&self) -> &str {
+        self.parts.method.as_str()
+    }
+
     pub fn get_uri(&mut self) -> String {
         if let Some(uri) = self.calculated_data.get("uri") {
             return uri.to_string();
@@ -180,6 +183,11 @@ impl GruxiRequest {
         path
     }
 
+    // The path, borrowed from the request, for hot paths that only compare it. Same as get_path()
+    pub fn get_path_str(&self) -> &str {
+        self.calculated_data.get("path").map(String::as_str).unwrap_or_else(|| self.parts.uri.path())
+    }
+
     pub fn get_query(&mut self) -> String {
         if let Some(query) = self.calculated_data.get("query") {
             return query.to_string();
@@ -210,6 +218,16 @@ impl GruxiRequest {
 
     // Returns the full body bytes. Beware this consumes the internal body bytes
     pub async fn get_body_bytes(&mut self) -> Bytes {
+        // Inspected bodies are kept as they came out of the inspectors, with their new length
+        if !self.body_inspectors.is_empty() {
+            let body = InspectedBody::new(self.take_boxed_body(), self.body_inspectors.take());
+            let bytes = body.collect().await.map(|collected| collected.to_bytes()).unwrap_or_default();
+            self.parts.headers.insert(CONTENT_LENGTH, HeaderValue::from(bytes.len()));
+            self.calculated_data.insert("body_size_hint".to_string(), bytes.len().to_string());
+            self.body = GruxiBody::Buffered(bytes.clone());
+            return bytes;
+        }
+
         match &mut self.body {
             GruxiBody::Buffered(bytes) => bytes.clone(),
             GruxiBody::Streaming(incoming_body) => {
@@ -229,17 +247,64 @@ impl GruxiRequest {
         }
     }
 
-    pub fn get_streaming_http_request(&mut self) -> Result<Request<BoxBody<Bytes, hyper::Error>>, ()> {
+    // The body, if it is buffered
+    pub fn get_buffered_body(&self) -> Option<&Bytes> {
+        match &self.body {
+            GruxiBody::Buffered(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    // Reads a streaming body into memory, so it can be inspected and still be handled after. A body over max_bytes is an
+    // error, and is then gone, as is a body the client did not finish
+    pub async fn buffer_body(&mut self, max_bytes: usize) -> Result<&Bytes, String> {
+        if let GruxiBody::Streaming(_) = &self.body
+            && let GruxiBody::Streaming(incoming_body) = mem::replace(&mut self.body, GruxiBody::Buffered(Bytes::new()))
+        {
+            let bytes = Limited::new(incoming_body, max_bytes)
+                .collect()
+                .await
+                .map_err(|e| format!("Failed to read request body: {}", e))?
+                .to_bytes();
+            self.body = GruxiBody::Buffered(bytes);
+        }
+        match &self.body {
+            GruxiBody::Buffered(bytes) => Ok(bytes),
+            _ => Err("Request body cannot be buffered".to_string()),
+        }
+    }
+
+    // Takes the body as it is, leaving an empty one
+    fn take_boxed_body(&mut self) -> BoxBody<Bytes, BodyError> {
         match mem::replace(&mut self.body, GruxiBody::Buffered(Bytes::new())) {
-            GruxiBody::Streaming(incoming_body) => {
-                let request = Request::from_parts(self.parts.clone(), incoming_body.boxed());
-                Ok(request)
-            }
-            other => {
-                self.body = other;
-                Err(())
-            }
+            GruxiBody::Streaming(incoming_body) => incoming_body.map_err(box_err).boxed(),
+            // Read before, such as for inspection, so it is sent on from memory
+            GruxiBody::Buffered(bytes) => Full::new(bytes).map_err(|never| match never {}).boxed(),
+            GruxiBody::StreamingBoxed(boxed_body) => boxed_body,
+        }
+    }
+
+    // Adds an inspector the body goes through when it is read, such as the one of the processor handling the request
+    pub fn add_body_inspector(&mut self, inspector: Box<dyn RequestBodyInspector>) {
+        self.body_inspectors.add(inspector);
+    }
+
+    // Why a body inspector stopped the request, if it did
+    pub fn get_body_rejection(&self) -> Option<BodyRejection> {
+        self.body_inspectors.get_rejection()
+    }
+
+    // The request with its body streamed, through the body inspectors if there are any
+    pub fn get_streaming_http_request(&mut self) -> Request<BoxBody<Bytes, BodyError>> {
+        let body = self.take_boxed_body();
+        if self.body_inspectors.is_empty() {
+            return Request::from_parts(self.parts.clone(), body);
         }
+
+        // Inspectors can change the length, so the body is sent chunked
+        let mut parts = self.parts.clone();
+        parts.headers.remove(CONTENT_LENGTH);
+        Request::from_parts(parts, InspectedBody::new(body, self.body_inspectors.take()).boxed())
     }
 
     pub fn get_body_size(&mut self) -> u64 {
@@ -289,6 +354,21 @@ impl GruxiRequest {
         self.add_calculated_data("uri", new_uri);
     }
 
+    // Replace the path of the request URI, keeping its query, such as with the canonical path
+    pub fn set_path(&mut self, new_path: &str) -> Result<(), String> {
+        let path_and_query = match self.parts.uri.query() {
+            Some(query) => format!("{}?{}", new_path, query),
+            None => new_path.to_string(),
+        };
+        let mut uri_parts = self.parts.uri.clone().into_parts();
+        uri_parts.path_and_query = Some(path_and_query.parse().map_err(|e| format!("Invalid path '{}': {}", new_path, e))?);
+        self.parts.uri = http::Uri::from_parts(uri_parts).map_err(|e| format!("Invalid path '{}': {}", new_path, e))?;
+        for key in ["uri", "path", "path_and_query"] {
+            self.calculated_data.remove(key);
+        }
+        Ok(())
+    }
+
     pub fn set_new_hostname(&mut self, new_hostname: &str) {
         self.parts
             .headers
@@ -296,31 +376,40 @@ impl GruxiRequest {
         self.add_calculated_data("hostname", new_hostname);
     }
 
+    // Set a header, replacing any value sent by the client. An invalid value removes the header
+    pub fn set_header(&mut self, header_name: &str, value: &str) {
+        let name = match hyper::header::HeaderName::from_bytes(header_name.as_bytes()) {
+            Ok(name) => name,
+            Err(_) => return,
+        };
+        match HeaderValue::from_str(value) {
+            Ok(value) => {
+                self.parts.headers.insert(name, value);
+            }
+            Err(_) => {
+                self.parts.headers.remove(name);
+            }
+        }
+    }
+
     pub fn remove_header(&mut self, header_name: &str) {
         self.parts.headers.remove(header_name);
     }
 
     pub fn clean_hop_by_hop_headers(&mut self) {
         let is_upgrade = self.parts.headers.get("Upgrade").is_some();
-        let connection_header_option = self.parts.headers.get("Connection");
-
-        let mut hop_by_hop_headers = crate::http::http_util::get_list_of_hop_by_hop_headers(is_upgrade);
 
         // Check the connection header for any additional hop-by-hop headers, before we remove the connection header itself
-        if !is_upgrade {
-            if let Some(connection_header) = connection_header_option {
-                if let Ok(connection_header_str) = connection_header.to_str() {
-                    for token in connection_header_str.split(',') {
-                        let token_trimmed = token.trim();
-                        if !token_trimmed.is_empty() {
-                            hop_by_hop_headers.push(token_trimmed.to_string());
-                        }
-                    }
-                }
+        if !is_upgrade
+            && let Some(connection_header) = self.parts.headers.remove("Connection")
+            && let Ok(connection_header_str) = connection_header.to_str()
+        {
+            for token in connection_header_str.split(',').map(str::trim).filter(|token| !token.is_empty()) {
+                self.remove_header(token);
             }
         }
 
-        for header in &hop_by_hop_headers {
+        for header in crate::http::http_util::get_list_of_hop_by_hop_headers(is_upgrade) {
             self.remove_header(header);
         }
     }
@@ -110,10 +110,77 @@ impl GruxiResponse {
         0
     }
 
+    // Unbuffered responses, such as Server-Sent Events, are streamed to the client as they arrive and never compressed
+    pub fn set_unbuffered(&mut self) {
+        self.calculated_data.insert("is_unbuffered".to_string(), "true".to_string());
+    }
+
+    pub fn is_unbuffered(&self) -> bool {
+        self.calculated_data.get("is_unbuffered").is_some_and(|value| value == "true")
+    }
+
+    // Length of the file a response serves, set by the static file processor, as streamed files have no body size hint
+    pub fn set_file_length(&mut self, length: u64) {
+        self.calculated_data.insert("file_length".to_string(), length.to_string());
+    }
+
+    pub fn get_file_length(&self) -> Option<u64> {
+        self.calculated_data.get("file_length").and_then(|value| value.parse().ok())
+    }
+
+    // The length of the body, if it is buffered
+    pub fn get_buffered_body_size(&self) -> Option<u64> {
+        match &self.body {
+            GruxiBody::Buffered(bytes) => Some(bytes.len() as u64),
+            _ => None,
+        }
+    }
+
+    // The body, if it is buffered
+    pub fn get_buffered_body(&self) -> Option<&Bytes> {
+        match &self.body {
+            GruxiBody::Buffered(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    // Wrap a streaming body, keeping the body size hint. Buffered bodies are left as is
+    pub fn map_streaming_body<F>(&mut self, f: F)
+    where
+        F: FnOnce(BoxBody<Bytes, BodyError>) -> BoxBody<Bytes, BodyError>,
+    {
+        let body = std::mem::replace(&mut self.body, GruxiBody::Buffered(Bytes::new()));
+        self.body = match body {
+            GruxiBody::Buffered(bytes) => GruxiBody::Buffered(bytes),
+            GruxiBody::Streaming(incoming) => GruxiBody::StreamingBoxed(f(BoxBody::new(incoming.map_err(box_err)))),
+            GruxiBody::StreamingBoxed(boxed_body) => GruxiBody::StreamingBoxed(f(boxed_body)),
+        };
+    }
+
+    // Wrap the body, also when it is buffered, keeping the body size hint. The body is streaming afterwards
+    pub fn map_body<F>(&mut self, f: F)
+    where
+        F: FnOnce(BoxBody<Bytes, BodyError>) -> BoxBody<Bytes, BodyError>,
+    {
+        let body = std::mem::replace(&mut self.body, GruxiBody::Buffered(Bytes::new()));
+        self.body = match body {
+            GruxiBody::Buffered(bytes) => GruxiBody::StreamingBoxed(f(BoxBody::new(Full::new(bytes).map_err(|never| -> BodyError { match never {} })))),
+            GruxiBody::Streaming(incoming) => GruxiBody::StreamingBoxed(f(BoxBody::new(incoming.map_err(box_err)))),
+            GruxiBody::StreamingBoxed(boxed_body) => GruxiBody::StreamingBoxed(f(boxed_body)),
+        };
+    }
+
     pub fn get_status(&self) -> u16 {
         self.parts.status.as_u16()
     }
 
+    // Replace the status, keeping the current one when the status code is invalid
+    pub fn set_status(&mut self, status_code: u16) {
+        if let Ok(status) = http::StatusCode::from_u16(status_code) {
+            self.parts.status = status;
+        }
+    }
+
     // Returns the full body bytes. Beware this consumes the internal body bytes
     pub async fn get_body_bytes(&mut self) -> Bytes {
         match &mut self.body {
@@ -0,0 +1,226 @@
+use std::fmt;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use bytes::BytesMut;
+use http_body_util::combinators::BoxBody;
+use hyper::body::{Body, Bytes, Frame, SizeHint};
+
+use crate::http::request_handlers::processor_trait::{BodyInspection, RequestBodyInspector};
+use crate::http::request_response::body_error::BodyError;
+
+// Why a body inspector stopped a request, and the status to answer it with
+#[derive(Clone, Debug)]
+pub struct BodyRejection {
+    pub status_code: u16,
+    pub reason: String,
+}
+
+impl fmt::Display for BodyRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Request body rejected with status {}: {}", self.status_code, self.reason)
+    }
+}
+
+impl std::error::Error for BodyRejection {}
+
+// The body inspectors of a request, applied in the order they were added, with the output of one going into the next
+#[derive(Default)]
+pub struct BodyInspectorChain {
+    inspectors: Vec<Box<dyn RequestBodyInspector>>,
+    // Shared with the request, so the rejection is known after the body was handed to a processor
+    rejection: Arc<Mutex<Option<BodyRejection>>>,
+}
+
+impl fmt::Debug for BodyInspectorChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BodyInspectorChain").field("inspectors", &self.inspectors.len()).finish()
+    }
+}
+
+impl BodyInspectorChain {
+    pub fn add(&mut self, inspector: Box<dyn RequestBodyInspector>) {
+        self.inspectors.push(inspector);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inspectors.is_empty()
+    }
+
+    pub fn get_rejection(&self) -> Option<BodyRejection> {
+        self.rejection.lock().ok().and_then(|rejection| rejection.clone())
+    }
+
+    // Takes the inspectors, to inspect a body with, leaving the rejection shared
+    pub fn take(&mut self) -> BodyInspectorChain {
+        BodyInspectorChain {
+            inspectors: std::mem::take(&mut self.inspectors),
+            rejection: self.rejection.clone(),
+        }
+    }
+
+    fn reject(&self, status_code: u16, reason: String) -> BodyRejection {
+        let rejection = BodyRejection { status_code, reason };
+        if let Ok(mut shared_rejection) = self.rejection.lock() {
+            *shared_rejection = Some(rejection.clone());
+        }
+        rejection
+    }
+
+    fn inspect_from(&mut self, first_inspector: usize, mut chunk: Bytes) -> Result<Bytes, BodyRejection> {
+        for index in first_inspector..self.inspectors.len() {
+            match self.inspectors[index].inspect_chunk(chunk) {
+                BodyInspection::Continue(bytes) => chunk = bytes,
+                BodyInspection::Reject { status_code, reason } => return Err(self.reject(status_code, reason)),
+            }
+        }
+        Ok(chunk)
+    }
+
+    pub fn inspect_chunk(&mut self, chunk: Bytes) -> Result<Bytes, BodyRejection> {
+        self.inspect_from(0, chunk)
+    }
+
+    // Ends the body for each inspector in turn, passing what it adds through the inspectors after it
+    pub fn finish(&mut self) -> Result<Bytes, BodyRejection> {
+        let mut tail = BytesMut::new();
+        for index in 0..self.inspectors.len() {
+            match self.inspectors[index].finish() {
+                BodyInspection::Continue(bytes) if !bytes.is_empty() => {
+                    let bytes = self.inspect_from(index + 1, bytes)?;
+                    tail.extend_from_slice(&bytes);
+                }
+                BodyInspection::Continue(_) => {}
+                BodyInspection::Reject { status_code, reason } => return Err(self.reject(status_code, reason)),
+            }
+        }
+        Ok(tail.freeze())
+    }
+}
+
+// A request body streamed through body inspectors. A rejection ends the body with a BodyRejection error
+pub struct InspectedBody {
+    inner: BoxBody<Bytes, BodyError>,
+    chain: BodyInspectorChain,
+    pending_trailers: Option<Frame<Bytes>>,
+    is_finished: bool,
+}
+
+impl InspectedBody {
+    pub fn new(inner: BoxBody<Bytes, BodyError>, chain: BodyInspectorChain) -> Self {
+        Self {
+            inner,
+            chain,
+            pending_trailers: None,
+            is_finished: false,
+        }
+    }
+
+    // The end of the body for the inspectors, followed by trailers, if any
+    fn finish(&mut self, trailers: Option<Frame<Bytes>>) -> Poll<Option<Result<Frame<Bytes>, BodyError>>> {
+        self.is_finished = true;
+        match self.chain.finish() {
+            Ok(tail) if !tail.is_empty() => {
+                self.pending_trailers = trailers;
+                Poll::Ready(Some(Ok(Frame::data(tail))))
+            }
+            Ok(_) => Poll::Ready(trailers.map(Ok)),
+            Err(rejection) => Poll::Ready(Some(Err(Box::new(rejection)))),
+        }
+    }
+}
+
+impl Body for InspectedBody {
+    type Data = Bytes;
+    type Error = BodyError;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if self.is_finished {
+            return Poll::Ready(self.pending_trailers.take().map(Ok));
+        }
+
+        match Pin::new(&mut self.inner).poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => match frame.into_data() {
+                Ok(data) => match self.chain.inspect_chunk(data) {
+                    Ok(data) => Poll::Ready(Some(Ok(Frame::data(data)))),
+                    Err(rejection) => {
+                        self.is_finished = true;
+                        Poll::Ready(Some(Err(Box::new(rejection))))
+                    }
+                },
+                Err(trailers) => self.finish(Some(trailers)),
+            },
+            Poll::Ready(None) => self.finish(None),
+            other => other,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.is_finished && self.pending_trailers.is_none()
+    }
+
+    // Inspectors can change the length of the body, so it is not known
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+
+    // Uppercases the body and adds a line at its end
+    struct UppercaseInspector;
+
+    impl RequestBodyInspector for UppercaseInspector {
+        fn inspect_chunk(&mut self, chunk: Bytes) -> BodyInspection {
+            BodyInspection::Continue(Bytes::from(chunk.to_ascii_uppercase()))
+        }
+
+        fn finish(&mut self) -> BodyInspection {
+            BodyInspection::Continue(Bytes::from_static(b"\nend"))
+        }
+    }
+
+    // Rejects bodies over a number of bytes, counted over the chunks
+    struct SizeInspector {
+        max_bytes: usize,
+        seen_bytes: usize,
+    }
+
+    impl RequestBodyInspector for SizeInspector {
+        fn inspect_chunk(&mut self, chunk: Bytes) -> BodyInspection {
+            self.seen_bytes += chunk.len();
+            if self.seen_bytes > self.max_bytes {
+                return BodyInspection::Reject {
+                    status_code: 413,
+                    reason: "Too large".to_string(),
+                };
+            }
+            BodyInspection::Continue(chunk)
+        }
+    }
+
+    fn boxed_body(body: &'static str) -> BoxBody<Bytes, BodyError> {
+        Full::new(Bytes::from_static(body.as_bytes())).map_err(|never| match never {}).boxed()
+    }
+
+    #[tokio::test]
+    async fn test_inspected_body_transforms_and_rejects() {
+        let mut chain = BodyInspectorChain::default();
+        chain.add(Box::new(UppercaseInspector));
+        chain.add(Box::new(SizeInspector { max_bytes: 20, seen_bytes: 0 }));
+        let body = InspectedBody::new(boxed_body("name=gruxi"), chain.take()).collect().await.unwrap().to_bytes();
+        assert_eq!(body, Bytes::from_static(b"NAME=GRUXI\nend"));
+        assert!(chain.get_rejection().is_none());
+
+        // The end added by the first inspector goes through the second, which then rejects the body
+        let mut chain = BodyInspectorChain::default();
+        chain.add(Box::new(UppercaseInspector));
+        chain.add(Box::new(SizeInspector { max_bytes: 12, seen_bytes: 0 }));
+        assert!(InspectedBody::new(boxed_body("name=gruxi"), chain.take()).collect().await.is_err());
+        assert_eq!(chain.get_rejection().unwrap().status_code, 413);
+    }
+}
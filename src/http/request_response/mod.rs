@@ -2,3 +2,4 @@ pub mod gruxi_body;
 pub mod gruxi_request;
 pub mod gruxi_response;
 pub mod body_error;
+pub mod inspected_body;
@@ -0,0 +1,345 @@
+use std::{io::SeekFrom, time::SystemTime};
+
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use http_body_util::{BodyExt, Full, StreamBody, combinators::BoxBody};
+use hyper::{
+    StatusCode,
+    body::{Bytes, Frame},
+    header::HeaderValue,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    core::running_state_manager::get_running_state_manager,
+    file::normalized_path::NormalizedPath,
+    http::request_response::{
+        body_error::{BodyError, box_err},
+        gruxi_request::GruxiRequest,
+        gruxi_response::GruxiResponse,
+    },
+    logging::syslog::{trace, warn},
+};
+
+// Response headers from upstreams and PHP, asking Gruxi to serve a file instead of the response body.
+// X-Sendfile holds a file path, X-Accel-Redirect a path relative to the internal root
+pub const SENDFILE_HEADERS: [&str; 2] = ["X-Sendfile", "X-Accel-Redirect"];
+
+// Upstream headers that describe the original body, so they do not apply to the file
+const REPLACED_HEADERS: [&str; 5] = ["Content-Length", "Content-Encoding", "Transfer-Encoding", "Content-Range", "Accept-Ranges"];
+
+#[derive(Debug, PartialEq)]
+enum ByteRange {
+    Full,              // No (usable) range, so the whole file is sent
+    Partial(u64, u64), // Start and end, both inclusive
+    Unsatisfiable,
+}
+
+// If the response asks for a file to be sent, replace it with the file from the internal root of the site, with support for
+// conditional and range requests. The other headers of the response, such as Content-Disposition, are kept
+pub async fn handle_sendfile_response(gruxi_request: &mut GruxiRequest, response: GruxiResponse, sendfile_root: &str) -> GruxiResponse {
+    let Some((header_name, header_value)) = SENDFILE_HEADERS
+        .iter()
+        .find_map(|name| response.get_header(name).map(|value| (*name, value.to_str().unwrap_or("").to_string())))
+    else {
+        return response;
+    };
+
+    let upstream_status = response.get_status();
+    let mut upstream_headers = response.headers().clone();
+    for name in SENDFILE_HEADERS.iter().chain(REPLACED_HEADERS.iter()) {
+        upstream_headers.remove(*name);
+    }
+
+    let file_path = match NormalizedPath::new(sendfile_root, "")
+        .ok()
+        .and_then(|root| resolve_sendfile_path(&root.get_full_path(), header_name, &header_value))
+    {
+        Some(file_path) => file_path,
+        None => {
+            warn(format!(
+                "{} path '{}' is outside the internal root '{}' of the site, so it is not served",
+                header_name, header_value, sendfile_root
+            ));
+            return GruxiResponse::new_empty_with_status(StatusCode::FORBIDDEN.as_u16());
+        }
+    };
+
+    // The path may still lead out of the root through a symlink, so the file it resolves to has to be in the root as well
+    let file_path = match get_canonical_path_in_root(sendfile_root, &file_path).await {
+        Ok(Some(file_path)) => file_path,
+        Ok(None) => {
+            warn(format!(
+                "{} path '{}' resolves to outside the internal root '{}' of the site, so it is not served",
+                header_name, header_value, sendfile_root
+            ));
+            return GruxiResponse::new_empty_with_status(StatusCode::FORBIDDEN.as_u16());
+        }
+        Err(_) => {
+            trace(format!("{} file does not exist: {}", header_name, file_path));
+            return GruxiResponse::new_empty_with_status(StatusCode::NOT_FOUND.as_u16());
+        }
+    };
+
+    let metadata = match tokio::fs::metadata(&file_path).await {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => {
+            trace(format!("{} file does not exist: {}", header_name, file_path));
+            return GruxiResponse::new_empty_with_status(StatusCode::NOT_FOUND.as_u16());
+        }
+    };
+
+    let length = metadata.len();
+    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    let etag = get_etag(length, modified);
+    let last_modified = DateTime::<Utc>::from(modified).format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+
+    let request_headers = gruxi_request.get_headers();
+    let get_request_header = |name: &str| request_headers.get(name).and_then(|value| value.to_str().ok()).map(|value| value.to_string());
+
+    let not_modified = match get_request_header("If-None-Match") {
+        Some(if_none_match) => if_none_match.split(',').any(|tag| tag.trim() == etag || tag.trim() == "*"),
+        None => get_request_header("If-Modified-Since").is_some_and(|since| is_not_modified_since(&since, modified)),
+    };
+
+    // A range is only served when If-Range, if any, still matches the file
+    let range_header = get_request_header("Range").filter(|_| get_request_header("If-Range").is_none_or(|if_range| if_range == etag || if_range == last_modified));
+    let byte_range = if not_modified {
+        ByteRange::Full
+    } else {
+        range_header.map(|range| parse_range_header(&range, length)).unwrap_or(ByteRange::Full)
+    };
+
+    // An upstream answering with an error, such as a 404 page from a file, keeps its status, without ranges or revalidation
+    let upstream_error_status = StatusCode::from_u16(upstream_status).ok().filter(|status| status.is_client_error() || status.is_server_error());
+    let (status, start, content_length) = match (upstream_error_status, byte_range) {
+        (Some(error_status), _) => (error_status, 0, length),
+        _ if not_modified => (StatusCode::NOT_MODIFIED, 0, 0),
+        (None, ByteRange::Full) => (StatusCode::OK, 0, length),
+        (None, ByteRange::Partial(start, end)) => (StatusCode::PARTIAL_CONTENT, start, end - start + 1),
+        (None, ByteRange::Unsatisfiable) => (StatusCode::RANGE_NOT_SATISFIABLE, 0, 0),
+    };
+
+    let body = if status == StatusCode::OK || status == StatusCode::PARTIAL_CONTENT || upstream_error_status.is_some() {
+        if gruxi_request.get_http_method() == "HEAD" {
+            get_empty_body()
+        } else {
+            match get_file_body(&file_path, start, content_length).await {
+                Some(body) => body,
+                None => return GruxiResponse::new_empty_with_status(StatusCode::INTERNAL_SERVER_ERROR.as_u16()),
+            }
+        }
+    } else {
+        get_empty_body()
+    };
+
+    trace(format!("Serving {} file {} with status {}", header_name, file_path, status.as_u16()));
+
+    let mut file_response = GruxiResponse::new_with_body(status.as_u16(), body);
+    file_response.calculated_data.insert("body_size_hint".to_string(), content_length.to_string());
+    let headers = file_response.headers_mut();
+    headers.extend(upstream_headers);
+    if !headers.contains_key("Content-Type") {
+        let mime_type = mime_guess::from_path(&file_path).first_or_octet_stream().to_string();
+        if let Ok(value) = HeaderValue::from_str(&mime_type) {
+            headers.insert("Content-Type", value);
+        }
+    }
+    if upstream_error_status.is_none() {
+        headers.insert("Accept-Ranges", HeaderValue::from_static("bytes"));
+        if let Ok(value) = HeaderValue::from_str(&etag) {
+            headers.insert("ETag", value);
+        }
+        if let Ok(value) = HeaderValue::from_str(&last_modified) {
+            headers.insert("Last-Modified", value);
+        }
+    }
+    match status {
+        StatusCode::PARTIAL_CONTENT => {
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes {}-{}/{}", start, start + content_length - 1, length)) {
+                headers.insert("Content-Range", value);
+            }
+        }
+        StatusCode::RANGE_NOT_SATISFIABLE => {
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes */{}", length)) {
+                headers.insert("Content-Range", value);
+            }
+        }
+        _ => {}
+    }
+    if status != StatusCode::NOT_MODIFIED
+        && let Ok(value) = HeaderValue::from_str(&content_length.to_string())
+    {
+        headers.insert("Content-Length", value);
+    }
+
+    file_response
+}
+
+// The full path of the file, as long as it is inside the internal root
+fn resolve_sendfile_path(root: &str, header_name: &str, header_value: &str) -> Option<String> {
+    let root = root.trim_end_matches('/');
+    let value = header_value.trim().replace('\\', "/");
+    if root.is_empty() || value.is_empty() {
+        return None;
+    }
+
+    // X-Sendfile has the full path of the file, which has to be in the root. X-Accel-Redirect is a URI, relative to the root
+    let relative_path = if header_name == "X-Sendfile" {
+        value.strip_prefix(root)?.to_string()
+    } else {
+        value.split('?').next().unwrap_or("").to_string()
+    };
+    if !relative_path.starts_with('/') {
+        return None;
+    }
+
+    let full_path = NormalizedPath::new(root, &relative_path).ok()?.get_full_path();
+    if full_path.starts_with(&format!("{}/", root)) { Some(full_path) } else { None }
+}
+
+// The file the path resolves to, following symlinks, as long as it is inside the root. An error when it does not exist
+async fn get_canonical_path_in_root(root: &str, file_path: &str) -> std::io::Result<Option<String>> {
+    let canonical_root = tokio::fs::canonicalize(root).await?;
+    let canonical_path = tokio::fs::canonicalize(file_path).await?;
+    if canonical_path.starts_with(&canonical_root) && canonical_path != canonical_root {
+        Ok(Some(canonical_path.to_string_lossy().to_string()))
+    } else {
+        Ok(None)
+    }
+}
+
+// Single byte ranges only. Multiple ranges are answered with the full file, which is allowed
+fn parse_range_header(range_header: &str, length: u64) -> ByteRange {
+    let Some(range) = range_header.trim().strip_prefix("bytes=") else {
+        return ByteRange::Full;
+    };
+    if range.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = range.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+
+    let (start, end) = match (start.trim().parse::<u64>().ok(), end.trim().parse::<u64>().ok()) {
+        // The last N bytes
+        (None, Some(suffix_length)) if start.trim().is_empty() => {
+            if suffix_length == 0 || length == 0 {
+                return ByteRange::Unsatisfiable;
+            }
+            (length.saturating_sub(suffix_length), length - 1)
+        }
+        (Some(start), None) if end.trim().is_empty() => (start, length.saturating_sub(1)),
+        (Some(start), Some(end)) if start <= end => (start, end.min(length.saturating_sub(1))),
+        _ => return ByteRange::Full,
+    };
+
+    if start >= length { ByteRange::Unsatisfiable } else { ByteRange::Partial(start, end) }
+}
+
+// Weak validator from the size and modification time, like most web servers use
+fn get_etag(length: u64, modified: SystemTime) -> String {
+    let modified_seconds = modified.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    format!("\"{:x}-{:x}\"", modified_seconds, length)
+}
+
+fn is_not_modified_since(if_modified_since: &str, modified: SystemTime) -> bool {
+    match DateTime::parse_from_rfc2822(if_modified_since) {
+        Ok(since) => DateTime::<Utc>::from(modified).timestamp() <= since.timestamp(),
+        Err(_) => false,
+    }
+}
+
+async fn get_file_body(file_path: &str, start: u64, length: u64) -> Option<BoxBody<Bytes, BodyError>> {
+    // Files within the memory mapping thresholds are sent as a slice of their mapping
+    if let Some(mapped_body) = get_mapped_file_body(file_path, start, length).await {
+        return Some(mapped_body);
+    }
+
+    let mut file = match File::open(file_path).await {
+        Ok(file) => file,
+        Err(e) => {
+            warn(format!("Failed to open file {} for sending: {}", file_path, e));
+            return None;
+        }
+    };
+    if start > 0
+        && let Err(e) = file.seek(SeekFrom::Start(start)).await
+    {
+        warn(format!("Failed to seek in file {} for sending: {}", file_path, e));
+        return None;
+    }
+
+    let stream = ReaderStream::new(file.take(length)).map_ok(Frame::data);
+    Some(BoxBody::new(BodyExt::map_err(StreamBody::new(stream), box_err)))
+}
+
+async fn get_mapped_file_body(file_path: &str, start: u64, length: u64) -> Option<BoxBody<Bytes, BodyError>> {
+    let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+    let open_file_cache = running_state.get_file_reader_cache().get_open_file_cache();
+    if !open_file_cache.is_mmap_enabled() {
+        return None;
+    }
+    let open_file = open_file_cache.get(file_path).ok()?;
+    let mapped_content = open_file_cache.get_mapped_content(&open_file)?;
+    let end = start.checked_add(length).filter(|end| *end <= mapped_content.len() as u64)?;
+    let range = mapped_content.slice(start as usize..end as usize);
+    Some(BoxBody::new(Full::new(range).map_err(|never| -> BodyError { match never {} })))
+}
+
+fn get_empty_body() -> BoxBody<Bytes, BodyError> {
+    BoxBody::new(Full::new(Bytes::new()).map_err(|never| -> BodyError { match never {} }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sendfile_parse_range_header() {
+        assert_eq!(parse_range_header("bytes=0-99", 1000), ByteRange::Partial(0, 99));
+        assert_eq!(parse_range_header("bytes=900-", 1000), ByteRange::Partial(900, 999));
+        assert_eq!(parse_range_header("bytes=-100", 1000), ByteRange::Partial(900, 999));
+        assert_eq!(parse_range_header("bytes=500-5000", 1000), ByteRange::Partial(500, 999));
+        assert_eq!(parse_range_header("bytes=1000-", 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse_range_header("bytes=0-1,5-9", 1000), ByteRange::Full);
+        assert_eq!(parse_range_header("items=0-1", 1000), ByteRange::Full);
+        assert_eq!(parse_range_header("bytes=9-1", 1000), ByteRange::Full);
+    }
+
+    #[tokio::test]
+    async fn test_sendfile_resolve_path_stays_in_root() {
+        assert_eq!(resolve_sendfile_path("/srv/files", "X-Sendfile", "/srv/files/report.pdf"), Some("/srv/files/report.pdf".to_string()));
+        assert_eq!(
+            resolve_sendfile_path("/srv/files", "X-Accel-Redirect", "/invoices/1.pdf?x=1"),
+            Some("/srv/files/invoices/1.pdf".to_string())
+        );
+        assert_eq!(resolve_sendfile_path("/srv/files", "X-Sendfile", "/etc/passwd"), None);
+        assert_eq!(resolve_sendfile_path("/srv/files", "X-Sendfile", "/srv/files-other/secret"), None);
+        assert_eq!(resolve_sendfile_path("/srv/files", "X-Accel-Redirect", "/../../etc/passwd"), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_sendfile_symlink_out_of_root_is_refused() {
+        let dir = std::env::temp_dir().join(format!("gruxi-sendfile-test-{}", std::process::id()));
+        let root = dir.join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(dir.join("secret.txt"), "secret").unwrap();
+        std::fs::write(root.join("public.txt"), "public").unwrap();
+        std::os::unix::fs::symlink(dir.join("secret.txt"), root.join("link.txt")).unwrap();
+
+        let root_path = root.to_string_lossy().to_string();
+        let public_path = resolve_sendfile_path(&root_path, "X-Accel-Redirect", "/public.txt").unwrap();
+        assert!(get_canonical_path_in_root(&root_path, &public_path).await.unwrap().is_some());
+        let link_path = resolve_sendfile_path(&root_path, "X-Accel-Redirect", "/link.txt").unwrap();
+        assert_eq!(get_canonical_path_in_root(&root_path, &link_path).await.unwrap(), None);
+        assert!(get_canonical_path_in_root(&root_path, &format!("{}/missing.txt", root_path)).await.is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
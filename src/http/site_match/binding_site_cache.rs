@@ -3,9 +3,12 @@ use std::{collections::HashMap, sync::Arc};
 use dashmap::DashMap;
 
 use crate::configuration::{binding::Binding, binding_site_relation::BindingSiteRelationship, cached_configuration::get_cached_configuration, site::Site};
+use crate::http::site_match::hostname_pattern::is_regex_hostname;
 
+// The enabled sites of each binding, shared with the requests through Arc's, so matching a site does not clone it.
+// Hostnames, other than regular expressions, are lowercased here once, so the site matcher can compare them to the requested hostname as they are
 pub struct BindingSiteCache {
-    binding_to_sites: DashMap<String, Arc<Vec<Site>>>,
+    binding_to_sites: DashMap<String, Arc<Vec<Arc<Site>>>>,
 }
 
 impl BindingSiteCache {
@@ -28,12 +31,24 @@ impl BindingSiteCache {
         let unique_binding_ids: Vec<String> = bindings.iter().map(|b| b.id.clone()).collect();
 
         // Generate hashmap with site id to Site for quick lookup
-        let site_map: HashMap<String, Site> = sites.iter().filter(|site| site.is_enabled).map(|site| (site.id.clone(), site.clone())).collect();
+        let site_map: HashMap<String, Arc<Site>> = sites
+            .iter()
+            .filter(|site| site.is_enabled)
+            .map(|site| {
+                let mut site = site.clone();
+                site.hostnames = site
+                    .hostnames
+                    .iter()
+                    .map(|hostname| if is_regex_hostname(hostname) { hostname.clone() } else { hostname.to_lowercase() })
+                    .collect();
+                (site.id.clone(), Arc::new(site))
+            })
+            .collect();
 
         // For each binding, find associated sites
         for binding_id in unique_binding_ids {
             // For each binding, we get a list of sites associated with it and fetch the actual Site objects
-            let associated_sites: Vec<Site> = binding_sites
+            let associated_sites: Vec<Arc<Site>> = binding_sites
                 .iter()
                 .filter(|rel| rel.binding_id == binding_id)
                 .filter_map(|rel| site_map.get(&rel.site_id).cloned())
@@ -44,7 +59,7 @@ impl BindingSiteCache {
         }
     }
 
-    pub fn get_sites_for_binding(&self, binding_id: &str) -> Arc<Vec<Site>> {
+    pub fn get_sites_for_binding(&self, binding_id: &str) -> Arc<Vec<Arc<Site>>> {
         self.binding_to_sites.get(binding_id).map(|entry| Arc::clone(&entry)).unwrap_or_else(|| Arc::new(Vec::new()))
     }
 }
@@ -58,7 +73,8 @@ mod tests {
         let binding1 = Binding::new();
         let binding2 = Binding::new();
 
-        let site1 = Site::new();
+        let mut site1 = Site::new();
+        site1.hostnames = vec!["Example.COM".to_string()];
         let site2 = Site::new();
         let site3 = Site::new();
         let site4 = Site::new();
@@ -94,5 +110,10 @@ mod tests {
         assert!(sites_for_binding1.iter().any(|s| s.id == site2.id));
         assert!(sites_for_binding1.iter().any(|s| s.id == site4.id));
         assert!(sites_for_binding2.iter().any(|s| s.id == site3.id));
+
+        // Sites are shared between the bindings and requests, with lowercased hostnames
+        let cached_site1 = sites_for_binding1.iter().find(|s| s.id == site1.id).unwrap();
+        assert_eq!(cached_site1.hostnames, vec!["example.com".to_string()]);
+        assert!(Arc::ptr_eq(cached_site1, cache.get_sites_for_binding(&binding1.id).iter().find(|s| s.id == site1.id).unwrap()));
     }
 }
@@ -0,0 +1,110 @@
+use regex::{Regex, RegexBuilder};
+
+// Site hostnames starting with this are regular expressions, matched against the whole requested hostname without case
+pub const REGEX_HOSTNAME_PREFIX: char = '~';
+
+// A site hostname, as matched against requested hostnames and TLS SNI names. When more than one matches, the most specific
+// wins, in the order: exact names, then "*.example.com" wildcards with the longest suffix first, then regular expressions
+// in the order of the configuration, and last the catch-all "*"
+#[derive(Clone, Debug)]
+pub enum HostnamePattern {
+    Exact(String),
+    Wildcard(String), // The suffix with its leading dot, such as ".example.com" of "*.example.com", which matches all subdomains
+    Regex(Regex),
+    Any,
+}
+
+pub fn is_regex_hostname(hostname: &str) -> bool {
+    hostname.starts_with(REGEX_HOSTNAME_PREFIX)
+}
+
+impl HostnamePattern {
+    pub fn parse(hostname: &str) -> Result<Self, String> {
+        let hostname = hostname.trim();
+        if hostname == "*" {
+            return Ok(HostnamePattern::Any);
+        }
+
+        if let Some(expression) = hostname.strip_prefix(REGEX_HOSTNAME_PREFIX) {
+            // Anchored, so the expression has to match the whole hostname
+            let regex = RegexBuilder::new(&format!("^(?:{})$", expression))
+                .case_insensitive(true)
+                .build()
+                .map_err(|e| format!("Hostname regex '{}' is invalid: {}", expression, e))?;
+            return Ok(HostnamePattern::Regex(regex));
+        }
+
+        let hostname = hostname.to_lowercase();
+        if let Some(suffix) = hostname.strip_prefix('*') {
+            if !suffix.starts_with('.') || suffix.len() < 2 || suffix[1..].contains('*') {
+                return Err(format!("Wildcard hostname '{}' must be in the form '*.example.com'", hostname));
+            }
+            return Ok(HostnamePattern::Wildcard(suffix.to_string()));
+        }
+        if hostname.contains('*') {
+            return Err(format!("Hostname '{}' can only have a wildcard as its first label, such as '*.example.com'", hostname));
+        }
+        Ok(HostnamePattern::Exact(hostname))
+    }
+
+    // The precedence of a match, lower is more specific, or None when the hostname does not match. The hostname is expected
+    // in lowercase
+    fn get_match_rank(&self, hostname: &str) -> Option<(u8, usize)> {
+        match self {
+            HostnamePattern::Exact(name) => (name == hostname).then_some((0, 0)),
+            // Longer suffixes are more specific, so they rank first
+            HostnamePattern::Wildcard(suffix) => (hostname.len() > suffix.len() && hostname.ends_with(suffix.as_str())).then(|| (1, usize::MAX - suffix.len())),
+            HostnamePattern::Regex(regex) => regex.is_match(hostname).then_some((2, 0)),
+            HostnamePattern::Any => Some((3, 0)),
+        }
+    }
+
+    pub fn matches(&self, hostname: &str) -> bool {
+        self.get_match_rank(&hostname.to_lowercase()).is_some()
+    }
+}
+
+// The value of the most specific pattern matching the hostname, by the precedence of HostnamePattern. Of equally specific
+// matches, the first one wins. The hostname is expected in lowercase
+pub fn find_best_pattern_match<'a, T>(candidates: impl IntoIterator<Item = (&'a HostnamePattern, T)>, hostname: &str) -> Option<T> {
+    let mut best_match: Option<((u8, usize), T)> = None;
+    for (pattern, value) in candidates {
+        if let Some(rank) = pattern.get_match_rank(hostname)
+            && best_match.as_ref().is_none_or(|(best_rank, _)| rank < *best_rank)
+        {
+            if rank == (0, 0) {
+                return Some(value);
+            }
+            best_match = Some((rank, value));
+        }
+    }
+    best_match.map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hostname_pattern_precedence() {
+        let patterns: Vec<HostnamePattern> = ["*", "~cdn[0-9]+\\.example\\.(org|net)", "*.example.com", "*.eu.example.com", "api.example.com"]
+            .iter()
+            .map(|hostname| HostnamePattern::parse(hostname).unwrap())
+            .collect();
+        let best = |hostname: &str| find_best_pattern_match(patterns.iter().enumerate().map(|(index, pattern)| (pattern, index)), hostname);
+
+        // Exact names first, then the longest wildcard, then regular expressions and last the catch-all
+        assert_eq!(best("api.example.com"), Some(4));
+        assert_eq!(best("shop.eu.example.com"), Some(3));
+        assert_eq!(best("www.example.com"), Some(2));
+        assert_eq!(best("cdn12.example.net"), Some(1));
+        assert_eq!(best("cdn.example.net"), Some(0));
+        // A wildcard does not match its own domain
+        assert_eq!(best("example.com"), Some(0));
+
+        assert!(HostnamePattern::parse("~CDN1\\.example\\.org").unwrap().matches("cdn1.EXAMPLE.org"));
+        assert!(HostnamePattern::parse("www.*.com").is_err());
+        assert!(HostnamePattern::parse("*example.com").is_err());
+        assert!(HostnamePattern::parse("~cdn[").is_err());
+    }
+}
@@ -1,2 +1,3 @@
+pub mod hostname_pattern;
 pub mod site_matcher;
 pub mod binding_site_cache;
\ No newline at end of file
@@ -1,18 +1,27 @@
-use crate::{configuration::site::Site, logging::syslog::trace};
-
-// Find a best match site for the requested hostname, comparing case-insensitively
-pub fn find_best_match_site<'a>(sites: &'a Vec<Site>, requested_hostname: &str) -> Option<&'a Site> {
-    let requested_hostname_lower = requested_hostname.to_lowercase();
-    let mut site = sites.iter().find(|s| s.hostnames.iter().any(|h| h.to_string() == requested_hostname_lower) && s.is_enabled);
+use std::borrow::Cow;
+use std::sync::Arc;
 
-    // We check for star hostnames
-    if site.is_none() {
-        site = sites.iter().find(|s| s.hostnames.iter().any(|h| h.to_string() == "*") && s.is_enabled);
-    }
+use crate::configuration::binding::Binding;
+use crate::http::site_match::hostname_pattern::find_best_pattern_match;
+use crate::{configuration::site::Site, logging::syslog::trace};
 
-    // If we cant find a matching site, we see if there is a default one
-    if site.is_none() {
-        site = sites.iter().find(|s| s.is_default && s.is_enabled);
+// Find a best match site for the requested hostname, comparing case-insensitively. Exact hostnames win over "*.example.com"
+// wildcards, with the longest first, then regular expressions and then "*". Hostnames matching no site get the default site
+// of the binding, unless the unknown host policy of the binding answers them with an error instead
+pub fn find_best_match_site<'a>(sites: &'a [Arc<Site>], requested_hostname: &str, binding: &Binding) -> Option<&'a Arc<Site>> {
+    let requested_hostname_lower = if requested_hostname.chars().any(char::is_uppercase) {
+        Cow::Owned(requested_hostname.to_lowercase())
+    } else {
+        Cow::Borrowed(requested_hostname)
+    };
+    let mut site = find_best_pattern_match(
+        sites.iter().filter(|s| s.is_enabled).flat_map(|s| s.get_hostname_patterns().iter().map(move |pattern| (pattern, s))),
+        &requested_hostname_lower,
+    );
+
+    // If we cant find a matching site, we see if the binding serves a default one
+    if site.is_none() && binding.unknown_host_policy == "default" {
+        site = find_default_site(sites, &binding.default_site_id);
     }
 
     // If we still cant find a proper site, we return None
@@ -24,6 +33,16 @@ pub fn find_best_match_site<'a>(sites: &'a Vec<Site>, requested_hostname: &str)
     site
 }
 
+// The site selected as default for the binding, or else the first site marked as default
+fn find_default_site<'a>(sites: &'a [Arc<Site>], default_site_id: &str) -> Option<&'a Arc<Site>> {
+    if !default_site_id.is_empty()
+        && let Some(site) = sites.iter().find(|s| s.id == default_site_id && s.is_enabled)
+    {
+        return Some(site);
+    }
+    sites.iter().find(|s| s.is_default && s.is_enabled)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -46,26 +65,27 @@ mod tests {
         site3.is_default = true;
         site3.is_enabled = true;
 
-        let sites = vec![site1.clone(), site2.clone(), site3.clone()];
+        let binding = Binding::new();
+        let sites = vec![Arc::new(site1.clone()), Arc::new(site2.clone()), Arc::new(site3.clone())];
 
         // Exact match
-        let matched_site = find_best_match_site(&sites, "grux.eu").unwrap();
+        let matched_site = find_best_match_site(&sites, "grux.eu", &binding).unwrap();
         assert_eq!(matched_site.id, site1.id);
-        let matched_site = find_best_match_site(&sites, "GRUX.eu").unwrap();
+        let matched_site = find_best_match_site(&sites, "GRUX.eu", &binding).unwrap();
         assert_eq!(matched_site.id, site1.id);
-        let matched_site = find_best_match_site(&sites, "grux.EU").unwrap();
+        let matched_site = find_best_match_site(&sites, "grux.EU", &binding).unwrap();
         assert_eq!(matched_site.id, site1.id);
-        let matched_site = find_best_match_site(&sites, "gruxi.org").unwrap();
+        let matched_site = find_best_match_site(&sites, "gruxi.org", &binding).unwrap();
         assert_eq!(matched_site.id, site1.id);
-        let matched_site = find_best_match_site(&sites, "GRUXI.ORG").unwrap();
+        let matched_site = find_best_match_site(&sites, "GRUXI.ORG", &binding).unwrap();
         assert_eq!(matched_site.id, site1.id);
 
         // Wildcard match for rest, none should hit the default, as we have a wildcard site
-        let matched_site = find_best_match_site(&sites, "unknown.com").unwrap();
+        let matched_site = find_best_match_site(&sites, "unknown.com", &binding).unwrap();
         assert_eq!(matched_site.id, site2.id);
-        let matched_site = find_best_match_site(&sites, "anotherunknown.com").unwrap();
+        let matched_site = find_best_match_site(&sites, "anotherunknown.com", &binding).unwrap();
         assert_eq!(matched_site.id, site2.id);
-        let matched_site = find_best_match_site(&sites, "GRUXI.CoM").unwrap();
+        let matched_site = find_best_match_site(&sites, "GRUXI.CoM", &binding).unwrap();
         assert_eq!(matched_site.id, site2.id);
     }
 
@@ -82,11 +102,12 @@ mod tests {
         site2.is_enabled = true;
 
         // grux.eu should match site1, www.grux.eu should match site2
-        let sites = vec![site1.clone(), site2.clone()];
+        let binding = Binding::new();
+        let sites = vec![Arc::new(site1.clone()), Arc::new(site2.clone())];
 
-        let matched_site = find_best_match_site(&sites, "grux.eu").unwrap();
+        let matched_site = find_best_match_site(&sites, "grux.eu", &binding).unwrap();
         assert_eq!(matched_site.id, site1.id);
-        let matched_site = find_best_match_site(&sites, "www.grux.eu").unwrap();
+        let matched_site = find_best_match_site(&sites, "www.grux.eu", &binding).unwrap();
         assert_eq!(matched_site.id, site2.id);
     }
 
@@ -103,11 +124,12 @@ mod tests {
         site2.is_enabled = true;
 
         // grux.eu should not match site1 as it is disabled, gruxi.org should match site2
-        let sites = vec![site1.clone(), site2.clone()];
+        let binding = Binding::new();
+        let sites = vec![Arc::new(site1.clone()), Arc::new(site2.clone())];
 
-        let matched_site = find_best_match_site(&sites, "grux.eu");
+        let matched_site = find_best_match_site(&sites, "grux.eu", &binding);
         assert!(matched_site.is_none());
-        let matched_site = find_best_match_site(&sites, "gruxi.org").unwrap();
+        let matched_site = find_best_match_site(&sites, "gruxi.org", &binding).unwrap();
         assert_eq!(matched_site.id, site2.id);
     }
 
@@ -124,16 +146,83 @@ mod tests {
         site2.is_enabled = true;
 
         // unknown.com should match site1 as default, gruxi.org should match site2
-        let sites = vec![site1.clone(), site2.clone()];
+        let binding = Binding::new();
+        let sites = vec![Arc::new(site1.clone()), Arc::new(site2.clone())];
 
-        let matched_site = find_best_match_site(&sites, "unknown.com").unwrap();
+        let matched_site = find_best_match_site(&sites, "unknown.com", &binding).unwrap();
         assert_eq!(matched_site.id, site1.id);
-        let matched_site = find_best_match_site(&sites, "UnKnoWN.com").unwrap();
+        let matched_site = find_best_match_site(&sites, "UnKnoWN.com", &binding).unwrap();
         assert_eq!(matched_site.id, site1.id);
 
-        let matched_site = find_best_match_site(&sites, "gruxi.org").unwrap();
+        let matched_site = find_best_match_site(&sites, "gruxi.org", &binding).unwrap();
         assert_eq!(matched_site.id, site2.id);
-        let matched_site = find_best_match_site(&sites, "GruXi.Org").unwrap();
+        let matched_site = find_best_match_site(&sites, "GruXi.Org", &binding).unwrap();
         assert_eq!(matched_site.id, site2.id);
     }
+
+    #[test]
+    fn test_site_matcher_wildcard_and_regex_precedence() {
+        let mut exact_site = Site::new();
+        exact_site.hostnames = vec!["api.gruxi.org".to_string()];
+
+        let mut wildcard_site = Site::new();
+        wildcard_site.hostnames = vec!["*.gruxi.org".to_string()];
+
+        let mut deeper_wildcard_site = Site::new();
+        deeper_wildcard_site.hostnames = vec!["*.eu.gruxi.org".to_string()];
+
+        let mut regex_site = Site::new();
+        regex_site.hostnames = vec!["~shop[0-9]+\\.(gruxi|grux)\\.(org|eu)".to_string()];
+
+        let mut default_site = Site::new();
+        default_site.hostnames = vec!["gruxi.org".to_string()];
+        default_site.is_default = true;
+
+        // Listed with the least specific first, as the order of the sites must not matter
+        let binding = Binding::new();
+        let sites = vec![
+            Arc::new(default_site.clone()),
+            Arc::new(regex_site.clone()),
+            Arc::new(wildcard_site.clone()),
+            Arc::new(deeper_wildcard_site.clone()),
+            Arc::new(exact_site.clone()),
+        ];
+
+        assert_eq!(find_best_match_site(&sites, "api.gruxi.org", &binding).unwrap().id, exact_site.id);
+        assert_eq!(find_best_match_site(&sites, "shop.eu.gruxi.org", &binding).unwrap().id, deeper_wildcard_site.id);
+        assert_eq!(find_best_match_site(&sites, "WWW.gruxi.org", &binding).unwrap().id, wildcard_site.id);
+        assert_eq!(find_best_match_site(&sites, "a.b.gruxi.org", &binding).unwrap().id, wildcard_site.id);
+        // The wildcard wins over the regular expression, which only gets the hostnames no wildcard matches
+        assert_eq!(find_best_match_site(&sites, "shop1.gruxi.org", &binding).unwrap().id, wildcard_site.id);
+        assert_eq!(find_best_match_site(&sites, "SHOP12.grux.eu", &binding).unwrap().id, regex_site.id);
+        assert_eq!(find_best_match_site(&sites, "shop.grux.eu", &binding).unwrap().id, default_site.id);
+    }
+
+    #[test]
+    fn test_site_matcher_binding_default_site_and_unknown_host_policy() {
+        let mut site1 = Site::new();
+        site1.hostnames = vec!["grux.eu".to_string()];
+        site1.is_default = true;
+
+        let mut site2 = Site::new();
+        site2.hostnames = vec!["gruxi.org".to_string()];
+
+        let sites = vec![Arc::new(site1.clone()), Arc::new(site2.clone())];
+        let mut binding = Binding::new();
+
+        // The site selected on the binding wins over the site marked as default
+        binding.default_site_id = site2.id.clone();
+        assert_eq!(find_best_match_site(&sites, "unknown.com", &binding).unwrap().id, site2.id);
+
+        // Unless it is disabled
+        let disabled_sites = vec![Arc::new(site1.clone()), Arc::new(Site { is_enabled: false, ..site2.clone() })];
+        assert_eq!(find_best_match_site(&disabled_sites, "unknown.com", &binding).unwrap().id, site1.id);
+
+        // Unknown hostnames are not served by the other policies, while known hostnames still are
+        for policy in ["not_found", "reject"] {
+            binding.unknown_host_policy = policy.to_string();
+            assert!(find_best_match_site(&sites, "unknown.com", &binding).is_none());
+            assert_eq!(find_best_match_site(&sites, "grux.eu", &binding).unwrap().id, site1.id);
+        }
+    }
 }
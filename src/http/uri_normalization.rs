@@ -0,0 +1,155 @@
+// ============================================================================
+// URI NORMALIZATION
+// ============================================================================
+//
+// Brings the path of every request to one canonical form before it is routed,
+// matched against locations and request handlers, or checked by the file path
+// security, so an encoded or dotted variant of a path is handled exactly as the
+// path itself. The request URI is replaced with the canonical one, so upstream
+// servers and applications get the same path as the routing saw:
+//
+//   - percent-encoded unreserved characters (letters, digits, "-._~") are
+//     decoded, other percent-encodings are kept with uppercase hex digits
+//   - encoded slashes (%2F) are kept, decoded or rejected, as set server-wide
+//   - the decoded path must be valid UTF-8 and cannot contain null bytes
+//   - duplicate slashes are collapsed
+//   - "." and ".." segments are removed (RFC 3986 section 5.2.4), and paths
+//     going above the root are rejected
+//
+// Decoding is done once, so "%252e" stays as it is, and the file path security
+// rejects what it decodes to on its own.
+// ============================================================================
+
+// How encoded slashes are handled: kept encoded, so they are part of a segment, decoded to a separator, or rejected
+pub const ENCODED_SLASH_POLICIES: [&str; 3] = ["keep", "decode", "reject"];
+
+// Whether the path is canonical as it is. Most paths are, so they are not copied
+pub fn is_canonical_path(path: &str) -> bool {
+    !path.starts_with('/') || (!path.contains('%') && !path.contains("//") && !path.contains("/."))
+}
+
+// The canonical form of a request path, or why the path is rejected. Paths not starting with a slash, such as "*" of
+// OPTIONS requests, are returned as they are
+pub fn normalize_request_path(path: &str, encoded_slash_policy: &str) -> Result<String, String> {
+    if !path.starts_with('/') {
+        return Ok(path.to_string());
+    }
+
+    let bytes = path.as_bytes();
+    let mut canonical: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len()); // Fully decoded, for the UTF-8 validation
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index];
+        if byte != b'%' {
+            canonical.push(byte);
+            decoded.push(byte);
+            index += 1;
+            continue;
+        }
+
+        let value = match (
+            bytes.get(index + 1).and_then(|b| (*b as char).to_digit(16)),
+            bytes.get(index + 2).and_then(|b| (*b as char).to_digit(16)),
+        ) {
+            (Some(high), Some(low)) => (high * 16 + low) as u8,
+            _ => return Err(format!("Invalid percent-encoding at position {}", index)),
+        };
+        index += 3;
+        decoded.push(value);
+
+        match value {
+            0 => return Err("Path contains an encoded null byte".to_string()),
+            b'/' => match encoded_slash_policy {
+                "reject" => return Err("Path contains an encoded slash".to_string()),
+                "decode" => canonical.push(b'/'),
+                _ => canonical.extend_from_slice(b"%2F"),
+            },
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => canonical.push(value),
+            _ => canonical.extend_from_slice(format!("%{:02X}", value).as_bytes()),
+        }
+    }
+
+    if std::str::from_utf8(&decoded).is_err() {
+        return Err("Path is not valid UTF-8 when decoded".to_string());
+    }
+    // Only ASCII was added to the path, which was valid UTF-8 to begin with
+    let canonical = String::from_utf8(canonical).map_err(|_| "Path is not valid UTF-8".to_string())?;
+
+    // Collapse duplicate slashes and remove dot segments. A path ending in a dot segment or a slash keeps its trailing slash
+    let mut segments: Vec<&str> = Vec::new();
+    let mut has_trailing_slash = false;
+    for segment in canonical[1..].split('/') {
+        has_trailing_slash = true;
+        match segment {
+            "" | "." => {}
+            ".." => {
+                if segments.pop().is_none() {
+                    return Err("Path goes above the root".to_string());
+                }
+            }
+            _ => {
+                segments.push(segment);
+                has_trailing_slash = false;
+            }
+        }
+    }
+
+    let mut normalized = String::with_capacity(canonical.len());
+    for segment in &segments {
+        normalized.push('/');
+        normalized.push_str(segment);
+    }
+    if segments.is_empty() || has_trailing_slash {
+        normalized.push('/');
+    }
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_request_path() {
+        let normalize = |path: &str| normalize_request_path(path, "keep");
+
+        assert_eq!(normalize("/index.html").unwrap(), "/index.html");
+        assert_eq!(normalize("*").unwrap(), "*");
+        assert_eq!(normalize("//a///b/./c/").unwrap(), "/a/b/c/");
+        assert_eq!(normalize("/a/b/../c").unwrap(), "/a/c");
+        assert_eq!(normalize("/a/b/..").unwrap(), "/a/");
+        assert_eq!(normalize("/.well-known/acme-challenge/token").unwrap(), "/.well-known/acme-challenge/token");
+
+        // Encoded unreserved characters are decoded, so they cannot get past prefix matching of locations
+        assert_eq!(normalize("/%61dmin/%7Euser").unwrap(), "/admin/~user");
+        // Other encodings are kept, with uppercase hex digits
+        assert_eq!(normalize("/caf%c3%a9/a%20b").unwrap(), "/caf%C3%A9/a%20b");
+
+        assert!(normalize("/file%00.txt").is_err());
+        assert!(normalize("/a%zz").is_err());
+        assert!(normalize("/a%2").is_err());
+        // Overlong encoding of "." is not valid UTF-8
+        assert!(normalize("/%c0%ae%c0%ae/etc/passwd").is_err());
+        assert!(normalize("/../etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_normalize_request_path_traversal_encodings() {
+        // Encoded dots are dot segments
+        assert!(normalize_request_path("/%2e%2e/etc/passwd", "keep").is_err());
+        assert!(normalize_request_path("/static/%2E%2e/%2e%2E/etc/passwd", "keep").is_err());
+        assert_eq!(normalize_request_path("/static/%2e%2e/index.html", "keep").unwrap(), "/index.html");
+        assert_eq!(normalize_request_path("/a/.%2e/b", "keep").unwrap(), "/b");
+
+        // An encoded slash is not a separator when kept, so "..%2F" is not a dot segment
+        assert_eq!(normalize_request_path("/static/%2e%2e%2fsecret", "keep").unwrap(), "/static/..%2Fsecret");
+        assert_eq!(normalize_request_path("/static/%2e%2e%2fsecret", "decode").unwrap(), "/secret");
+        assert!(normalize_request_path("/%2e%2e%2f%2e%2e%2fetc/passwd", "decode").is_err());
+        assert!(normalize_request_path("/static/%2e%2e%2fsecret", "reject").is_err());
+
+        // Double encoding is decoded once, and left for the file path security to reject
+        assert_eq!(normalize_request_path("/%252e%252e%252fetc/passwd", "keep").unwrap(), "/%252e%252e%252fetc/passwd");
+        assert!(crate::file::normalized_path::NormalizedPath::new("/var/www", "/%252e%252e%252fetc/passwd").is_err());
+    }
+}
@@ -0,0 +1,288 @@
+// ============================================================================
+// WEB APPLICATION FIREWALL
+// ============================================================================
+//
+// Checks the requests of sites with the firewall enabled, before they reach the
+// request handlers. A request is matched against a small built-in core rule set
+// for SQL injection and cross-site scripting, and against the rules of the
+// site, each matching the method, path, query, a header or the body with a
+// regular expression. Matching rules add their score to the anomaly score of
+// the request, and a request reaching the threshold of the site, or matching a
+// rule with the "block" action, is answered with 403, or only logged when the
+// firewall is in "detect" mode.
+//
+// Path, query and form bodies are matched percent-decoded, so encoding does not
+// hide a pattern. Bodies are inspected when they declare a Content-Length of at
+// most 64 KB and have a textual content type; others, such as file uploads and
+// chunked bodies, are passed on without inspection.
+// ============================================================================
+
+use std::sync::LazyLock;
+
+use hyper::HeaderMap;
+use regex::{Regex, RegexBuilder};
+
+use crate::configuration::site::Site;
+use crate::configuration::waf_settings::WafSettings;
+use crate::http::http_util::add_standard_headers_to_response;
+use crate::http::request_response::gruxi_request::GruxiRequest;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+use crate::logging::syslog::{info, warn};
+
+// Bodies larger than this are not read for inspection
+const MAX_INSPECTED_BODY_BYTES: u64 = 64 * 1024;
+
+// Content types of the bodies inspected, others are binary or uploads
+const INSPECTED_CONTENT_TYPES: [&str; 5] = ["application/x-www-form-urlencoded", "application/json", "application/xml", "text/", "application/graphql"];
+
+// Headers the core rules inspect, as they are often passed on to applications and logged
+const CORE_RULE_HEADERS: [&str; 3] = ["user-agent", "referer", "cookie"];
+
+struct CoreRule {
+    name: &'static str,
+    score: u32,
+    pattern: Regex,
+}
+
+// Critical patterns score 5, enough to block with the default threshold, while patterns also seen in harmless input score 3
+static CORE_RULES: LazyLock<Vec<CoreRule>> = LazyLock::new(|| {
+    [
+        ("sqli-union-select", 5, r"\bunion\b[\s/*()]+(all[\s/*()]+|distinct[\s/*()]+)?select\b"),
+        ("sqli-tautology", 5, r#"['"`]\s*\)?\s*\b(or|and)\b\s+['"`]?\w+['"`]?\s*(=|<>|!=|\blike\b)\s*['"`]?\w+"#),
+        ("sqli-numeric-tautology", 5, r"\b(or|and)\s+(\d+)\s*=\s*(\d+)\b"),
+        ("sqli-time-based", 5, r"\b(sleep|benchmark|pg_sleep)\s*\(|\bwaitfor\s+delay\b"),
+        ("sqli-schema-probe", 5, r"\b(information_schema|sysobjects|pg_catalog|sqlite_master)\b"),
+        ("sqli-stacked-query", 3, r";\s*(drop|delete|insert|update|alter|create|truncate|exec)\s+\w+"),
+        ("sqli-comment-terminator", 3, r#"['"`]\s*(--|#|/\*)"#),
+        ("xss-script-tag", 5, r"<\s*/?\s*script\b"),
+        ("xss-event-handler", 5, r"<[^>]*[\s/]on[a-z]+\s*="),
+        ("xss-javascript-uri", 3, r"\b(javascript|vbscript)\s*:"),
+        ("xss-embedding-tag", 3, r"<\s*(iframe|object|embed|svg|math|base)\b"),
+        ("xss-dom-sink", 3, r"\bdocument\s*\.\s*(cookie|domain|write)\b|\b(eval|alert|prompt)\s*\("),
+    ]
+    .into_iter()
+    .filter_map(|(name, score, pattern)| RegexBuilder::new(pattern).case_insensitive(true).build().ok().map(|pattern| CoreRule { name, score, pattern }))
+    .collect()
+});
+
+// The parts of a request the rules are matched against, decoded
+pub struct InspectedRequest<'a> {
+    pub method: &'a str,
+    pub path: String,
+    pub query: String,
+    pub headers: &'a HeaderMap,
+    pub body: Option<String>,
+}
+
+impl<'a> InspectedRequest<'a> {
+    pub fn new(method: &'a str, path: &str, query: &str, headers: &'a HeaderMap, body: Option<&[u8]>, is_form_body: bool) -> Self {
+        InspectedRequest {
+            method,
+            path: decode_component(path, false),
+            query: decode_component(query, true),
+            headers,
+            body: body.map(|body| {
+                if is_form_body {
+                    decode_component(&String::from_utf8_lossy(body), true)
+                } else {
+                    String::from_utf8_lossy(body).into_owned()
+                }
+            }),
+        }
+    }
+
+    fn get_target_values(&self, target: &str, header_name: &str) -> Vec<&str> {
+        match target {
+            "method" => vec![self.method],
+            "path" => vec![self.path.as_str()],
+            "query" => vec![self.query.as_str()],
+            "header" => self.headers.get_all(header_name).iter().filter_map(|value| value.to_str().ok()).collect(),
+            "body" => self.body.as_deref().into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn decode_component(value: &str, is_form_encoded: bool) -> String {
+    let value = if is_form_encoded { value.replace('+', " ") } else { value.to_string() };
+    String::from_utf8_lossy(&urlencoding::decode_binary(value.as_bytes())).into_owned()
+}
+
+#[derive(Debug, Clone)]
+pub struct WafMatch {
+    pub rule_name: String,
+    pub target: String,
+    pub score: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct WafVerdict {
+    pub score: u32,
+    pub matches: Vec<WafMatch>,
+    pub is_blocked: bool, // Whether the request reached the threshold or matched a blocking rule, regardless of the mode
+}
+
+// Match a request against the core rules and the rules of the site. Each rule scores at most once per request
+pub fn inspect_request(settings: &WafSettings, request: &InspectedRequest) -> WafVerdict {
+    let mut verdict = WafVerdict::default();
+
+    if settings.core_rules_enabled {
+        let mut core_targets: Vec<(&str, &str)> = vec![("path", request.path.as_str()), ("query", request.query.as_str())];
+        for header_name in CORE_RULE_HEADERS {
+            core_targets.extend(request.headers.get_all(header_name).iter().filter_map(|value| value.to_str().ok()).map(|value| ("header", value)));
+        }
+        if let Some(body) = &request.body {
+            core_targets.push(("body", body.as_str()));
+        }
+
+        for rule in CORE_RULES.iter() {
+            if let Some((target, _)) = core_targets.iter().find(|(_, value)| !value.is_empty() && rule.pattern.is_match(value)) {
+                verdict.score += rule.score;
+                verdict.matches.push(WafMatch {
+                    rule_name: rule.name.to_string(),
+                    target: target.to_string(),
+                    score: rule.score,
+                });
+            }
+        }
+    }
+
+    for rule in &settings.rules {
+        let Some(pattern) = rule.get_pattern() else {
+            continue;
+        };
+        if !request.get_target_values(&rule.target, &rule.header_name).iter().any(|value| pattern.is_match(value)) {
+            continue;
+        }
+
+        let score = if rule.action == "log" { 0 } else { rule.score };
+        verdict.score += score;
+        verdict.is_blocked |= rule.action == "block";
+        verdict.matches.push(WafMatch {
+            rule_name: rule.name.clone(),
+            target: rule.target.clone(),
+            score,
+        });
+    }
+
+    verdict.is_blocked |= verdict.score >= settings.anomaly_threshold;
+    verdict
+}
+
+fn is_inspected_body(headers: &HeaderMap) -> bool {
+    let content_length = headers.get("content-length").and_then(|value| value.to_str().ok()).and_then(|value| value.trim().parse::<u64>().ok());
+    let content_type = headers.get("content-type").and_then(|value| value.to_str().ok()).unwrap_or("").to_ascii_lowercase();
+    matches!(content_length, Some(length) if length > 0 && length <= MAX_INSPECTED_BODY_BYTES) && INSPECTED_CONTENT_TYPES.iter().any(|inspected| content_type.starts_with(inspected))
+}
+
+// Check a request against the firewall of its site. Returns the response to answer with when the request is blocked, or
+// when its body could not be read for inspection
+pub async fn check_request(gruxi_request: &mut GruxiRequest, site: &Site) -> Option<GruxiResponse> {
+    let settings = &site.waf;
+    if !settings.is_enabled {
+        return None;
+    }
+
+    let headers = gruxi_request.get_headers();
+    let is_form_body = headers
+        .get("content-type")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.to_ascii_lowercase().starts_with("application/x-www-form-urlencoded"));
+    let body = if is_inspected_body(headers) {
+        match gruxi_request.buffer_body(MAX_INSPECTED_BODY_BYTES as usize).await {
+            Ok(body) => Some(body.clone()),
+            Err(e) => {
+                info(format!("WAF could not read the request body for inspection on site {}: {}", site.id, e));
+                let mut response = GruxiResponse::new_empty_with_status(hyper::StatusCode::BAD_REQUEST.as_u16());
+                response.headers_mut().insert("Connection", hyper::header::HeaderValue::from_static("close"));
+                return Some(response);
+            }
+        }
+    } else {
+        None
+    };
+
+    let path = gruxi_request.get_path();
+    let query = gruxi_request.get_query();
+    let remote_ip = gruxi_request.get_remote_ip();
+    let method = gruxi_request.get_http_method_str();
+    let inspected_request = InspectedRequest::new(method, &path, &query, gruxi_request.get_headers(), body.as_deref(), is_form_body);
+    let verdict = inspect_request(settings, &inspected_request);
+    if verdict.matches.is_empty() {
+        return None;
+    }
+
+    let matched_rules: Vec<String> = verdict.matches.iter().map(|m| format!("{} ({}, score {})", m.rule_name, m.target, m.score)).collect();
+    let summary = format!("{} {} from {} on site {}, score {}: {}", method, path, remote_ip, site.id, verdict.score, matched_rules.join(", "));
+    if !verdict.is_blocked {
+        info(format!("WAF rules matched {}", summary));
+        return None;
+    }
+    if !settings.is_blocking() {
+        info(format!("WAF would block {}", summary));
+        return None;
+    }
+
+    warn(format!("WAF blocked {}", summary));
+    let mut response = GruxiResponse::new_empty_with_status(hyper::StatusCode::FORBIDDEN.as_u16());
+    add_standard_headers_to_response(&mut response);
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::waf_settings::WafRule;
+
+    fn inspect(settings: &WafSettings, path: &str, query: &str, body: Option<&str>) -> WafVerdict {
+        let headers = HeaderMap::new();
+        let request = InspectedRequest::new("GET", path, query, &headers, body.map(|body| body.as_bytes()), true);
+        inspect_request(settings, &request)
+    }
+
+    #[test]
+    fn test_core_rules() {
+        let settings = WafSettings::new();
+
+        assert!(inspect(&settings, "/products", "id=1%27%20UNION%20SELECT%20password%20FROM%20users", None).is_blocked);
+        assert!(inspect(&settings, "/login", "", Some("user=admin'+or+'1'%3D'1&password=x")).is_blocked);
+        assert!(inspect(&settings, "/search", "q=<script>alert(1)</script>", None).is_blocked);
+        assert!(inspect(&settings, "/search", "q=%3Cimg%20src%3Dx%20onerror%3Dalert(1)%3E", None).is_blocked);
+
+        // Ordinary requests pass
+        assert!(inspect(&settings, "/blog/union-station-select-menu", "page=2&sort=date", None).matches.is_empty());
+        assert!(inspect(&settings, "/comments", "", Some("text=I+prefer+tea+or+coffee%2C+and+that%27s+it")).matches.is_empty());
+
+        let mut settings = WafSettings::new();
+        settings.core_rules_enabled = false;
+        assert!(!inspect(&settings, "/search", "q=<script>alert(1)</script>", None).is_blocked);
+    }
+
+    #[test]
+    fn test_site_rules_scoring_and_actions() {
+        let mut settings = WafSettings::new();
+        settings.core_rules_enabled = false;
+        settings.anomaly_threshold = 5;
+        settings.rules = vec![
+            WafRule::new("admin-path", "path", "^/wp-admin", 3, "score"),
+            WafRule::new("scanner-query", "query", "cmd=", 3, "score"),
+            WafRule::new("no-trace", "method", "^TRACE$", 0, "block"),
+            WafRule::new("watch-debug", "query", "debug=1", 10, "log"),
+        ];
+
+        // A single rule stays below the threshold, two reach it
+        let verdict = inspect(&settings, "/wp-admin/", "", None);
+        assert_eq!(verdict.score, 3);
+        assert!(!verdict.is_blocked);
+        assert!(inspect(&settings, "/wp-admin/", "cmd=ls", None).is_blocked);
+
+        // Logging rules match without scoring
+        let verdict = inspect(&settings, "/", "debug=1", None);
+        assert_eq!(verdict.matches.len(), 1);
+        assert!(!verdict.is_blocked);
+
+        // Blocking rules block on their own
+        let headers = HeaderMap::new();
+        assert!(inspect_request(&settings, &InspectedRequest::new("TRACE", "/", "", &headers, None, false)).is_blocked);
+    }
+}
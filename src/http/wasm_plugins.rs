@@ -0,0 +1,364 @@
+// ============================================================================
+// WEBASSEMBLY PLUGINS
+// ============================================================================
+//
+// Runs the WebAssembly plugins of a site on its requests and responses, in the
+// "plugins" middleware. Plugins run in a sandbox: they get no access to files,
+// network or clock, each call has a fuel limit bounding the instructions it
+// runs and a memory limit, and each call gets a new instance, so nothing is
+// kept from one request to the next. Modules are compiled once, and again when
+// their file changes.
+//
+// ABI, version 1. A plugin module exports:
+//
+//   memory                                   its linear memory
+//   gruxi_abi_version() -> i32               returns 1
+//   gruxi_alloc(len: i32) -> i32             returns where the host can write len bytes
+//   gruxi_on_request(ptr: i32, len: i32) -> i64    optional
+//   gruxi_on_response(ptr: i32, len: i32) -> i64   optional
+//
+// and can import gruxi.log(ptr: i32, len: i32) to write to the server log.
+// The hooks get JSON at ptr and len:
+//
+//   on_request:  {"config", "method", "path", "query", "remote_ip", "headers"}
+//   on_response: {"config", "method", "path", "status", "headers"}
+//
+// with headers as [name, value] pairs, and return 0 to let the request or
+// response go on unchanged, or (ptr << 32) | len of an action in JSON:
+//
+//   {"action": "continue", "headers": [[name, value]], "remove_headers": [name],
+//    "path": "/rewritten"}
+//   {"action": "respond", "status": 403, "headers": [[name, value]], "body": "..."}
+//
+// "continue" sets and removes headers of the request, or of the response for
+// on_response, and can rewrite the path of a request, which is then matched to
+// a location again, and validated and authenticated for it. "respond" answers
+// the request, or replaces the response, with the response of the plugin.
+// ============================================================================
+
+use std::sync::LazyLock;
+use std::time::SystemTime;
+
+use dashmap::DashMap;
+use hyper::header::{HeaderName, HeaderValue};
+use serde::Deserialize;
+use wasmtime::{Caller, Config, Engine, Extern, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+use crate::configuration::plugin_settings::WasmPlugin;
+use crate::http::middleware::middleware_chain::MiddlewareContext;
+use crate::http::request_response::gruxi_request::GruxiRequest;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+use crate::http::uri_normalization::is_canonical_path;
+use crate::logging::syslog::{error, info, trace};
+
+pub const PLUGIN_ABI_VERSION: i32 = 1;
+
+// Actions and log messages of plugins larger than this are rejected and cut off
+const MAX_PLUGIN_OUTPUT_BYTES: usize = 1024 * 1024;
+const MAX_PLUGIN_LOG_MESSAGE_BYTES: usize = 4096;
+
+static ENGINE: LazyLock<Result<Engine, String>> = LazyLock::new(|| {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    Engine::new(&config).map_err(|e| format!("Failed to create WebAssembly engine: {}", e))
+});
+
+// Compiled modules, by path, with the modification time of the file they were compiled from
+static MODULES: LazyLock<DashMap<String, (SystemTime, Module)>> = LazyLock::new(DashMap::new);
+
+// What a plugin asks for, from one of its hooks
+#[derive(Debug, Default, Deserialize)]
+struct PluginAction {
+    #[serde(default)]
+    action: String,
+    #[serde(default)]
+    status: u16,
+    #[serde(default)]
+    headers: Vec<(String, String)>,
+    #[serde(default)]
+    remove_headers: Vec<String>,
+    #[serde(default)]
+    path: String,
+    #[serde(default)]
+    body: String,
+}
+
+struct PluginState {
+    plugin_name: String,
+    limits: StoreLimits,
+}
+
+fn get_engine() -> Result<&'static Engine, String> {
+    ENGINE.as_ref().map_err(|e| e.clone())
+}
+
+// The compiled module of a plugin, compiled again when its file has changed
+fn get_module(path: &str) -> Result<Module, String> {
+    let modified = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|e| format!("Failed to read plugin file '{}': {}", path, e))?;
+    if let Some(entry) = MODULES.get(path)
+        && entry.0 == modified
+    {
+        return Ok(entry.1.clone());
+    }
+
+    trace(format!("Compiling WebAssembly plugin: {}", path));
+    let module = Module::from_file(get_engine()?, path).map_err(|e| format!("Failed to compile plugin '{}': {}", path, e))?;
+    MODULES.insert(path.to_string(), (modified, module.clone()));
+    Ok(module)
+}
+
+// Call a hook of a plugin module with its input, in a new instance with the limits of the plugin. Returns None when the
+// module does not have the hook or the hook lets things go on unchanged
+fn call_plugin_hook(module: &Module, plugin: &WasmPlugin, hook: &str, input: &[u8]) -> Result<Option<PluginAction>, String> {
+    if module.get_export(hook).is_none() {
+        return Ok(None);
+    }
+
+    let engine = get_engine()?;
+    let limits = StoreLimitsBuilder::new().memory_size(plugin.memory_limit_mb as usize * 1024 * 1024).instances(1).build();
+    let mut store = Store::new(
+        engine,
+        PluginState {
+            plugin_name: plugin.name.clone(),
+            limits,
+        },
+    );
+    store.limiter(|state| &mut state.limits);
+    store.set_fuel(plugin.fuel_limit).map_err(|e| e.to_string())?;
+
+    let mut linker = Linker::new(engine);
+    linker
+        .func_wrap("gruxi", "log", |mut caller: Caller<'_, PluginState>, ptr: i32, len: i32| {
+            if let Some(Extern::Memory(memory)) = caller.get_export("memory") {
+                let mut message = vec![0u8; (len.max(0) as usize).min(MAX_PLUGIN_LOG_MESSAGE_BYTES)];
+                if memory.read(&caller, ptr as u32 as usize, &mut message).is_ok() {
+                    info(format!("Plugin '{}': {}", caller.data().plugin_name, String::from_utf8_lossy(&message)));
+                }
+            }
+        })
+        .map_err(|e| e.to_string())?;
+    let instance = linker.instantiate(&mut store, module).map_err(|e| format!("Failed to instantiate plugin: {}", e))?;
+
+    let abi_version = instance
+        .get_typed_func::<(), i32>(&mut store, "gruxi_abi_version")
+        .and_then(|abi_version| abi_version.call(&mut store, ()))
+        .map_err(|e| format!("Failed to get ABI version of plugin: {}", e))?;
+    if abi_version != PLUGIN_ABI_VERSION {
+        return Err(format!("Plugin is for ABI version {}, but Gruxi supports version {}", abi_version, PLUGIN_ABI_VERSION));
+    }
+
+    let memory = instance.get_memory(&mut store, "memory").ok_or("Plugin does not export its memory")?;
+    let input_len = i32::try_from(input.len()).map_err(|_| "Plugin input too large".to_string())?;
+    let input_ptr = instance
+        .get_typed_func::<i32, i32>(&mut store, "gruxi_alloc")
+        .and_then(|alloc| alloc.call(&mut store, input_len))
+        .map_err(|e| format!("Failed to allocate plugin input: {}", e))?;
+    memory.write(&mut store, input_ptr as u32 as usize, input).map_err(|e| format!("Failed to write plugin input: {}", e))?;
+
+    let result = instance
+        .get_typed_func::<(i32, i32), i64>(&mut store, hook)
+        .and_then(|hook_func| hook_func.call(&mut store, (input_ptr, input_len)))
+        .map_err(|e| format!("Plugin {} failed: {}", hook, e))?;
+    if result == 0 {
+        return Ok(None);
+    }
+
+    let output_ptr = (result as u64 >> 32) as usize;
+    let output_len = (result as u64 & 0xFFFF_FFFF) as usize;
+    if output_len > MAX_PLUGIN_OUTPUT_BYTES {
+        return Err(format!("Plugin {} returned {} bytes, more than the {} allowed", hook, output_len, MAX_PLUGIN_OUTPUT_BYTES));
+    }
+    let mut output = vec![0u8; output_len];
+    memory.read(&store, output_ptr, &mut output).map_err(|e| format!("Failed to read plugin output: {}", e))?;
+    let action: PluginAction = serde_json::from_slice(&output).map_err(|e| format!("Plugin {} returned an invalid action: {}", hook, e))?;
+    if !matches!(action.action.as_str(), "" | "continue" | "respond") {
+        return Err(format!("Plugin {} returned unknown action '{}'", hook, action.action));
+    }
+    Ok(Some(action))
+}
+
+fn run_plugin(plugin: &WasmPlugin, hook: &str, input: &serde_json::Value) -> Result<Option<PluginAction>, String> {
+    let module = get_module(&plugin.path)?;
+    call_plugin_hook(&module, plugin, hook, input.to_string().as_bytes())
+}
+
+fn headers_to_pairs(headers: &hyper::HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| (name.as_str().to_string(), String::from_utf8_lossy(value.as_bytes()).to_string()))
+        .collect()
+}
+
+fn plugin_response(action: &PluginAction) -> GruxiResponse {
+    let status = if (100..=999).contains(&action.status) { action.status } else { hyper::StatusCode::OK.as_u16() };
+    let mut response = GruxiResponse::new_with_bytes(status, action.body.clone());
+    set_response_headers(&mut response, action);
+    response
+}
+
+fn set_response_headers(response: &mut GruxiResponse, action: &PluginAction) {
+    for name in &action.remove_headers {
+        response.headers_mut().remove(name.as_str());
+    }
+    for (name, value) in &action.headers {
+        if let Ok(name) = HeaderName::from_bytes(name.as_bytes())
+            && let Ok(value) = HeaderValue::from_str(value)
+        {
+            response.headers_mut().insert(name, value);
+        }
+    }
+}
+
+fn failed_plugin_response(plugin: &WasmPlugin, e: &str) -> Option<GruxiResponse> {
+    error(format!("Plugin '{}' failed: {}", plugin.name, e));
+    if plugin.is_fail_open {
+        return None;
+    }
+    Some(GruxiResponse::new_empty_with_status(hyper::StatusCode::INTERNAL_SERVER_ERROR.as_u16()))
+}
+
+// The request phase of the "plugins" middleware, running the plugins of the site in order until one answers the request
+pub fn run_request_plugins(context: &MiddlewareContext<'_>, gruxi_request: &mut GruxiRequest) -> Option<GruxiResponse> {
+    for plugin in context.get_site().plugins.iter().filter(|plugin| plugin.is_enabled) {
+        let input = serde_json::json!({
+            "config": plugin.config,
+            "method": gruxi_request.get_http_method_str(),
+            "path": gruxi_request.get_path_str(),
+            "query": gruxi_request.get_query(),
+            "remote_ip": gruxi_request.get_remote_ip(),
+            "headers": headers_to_pairs(gruxi_request.get_headers()),
+        });
+        let action = match run_plugin(plugin, "gruxi_on_request", &input) {
+            Ok(Some(action)) => action,
+            Ok(None) => continue,
+            Err(e) => match failed_plugin_response(plugin, &e) {
+                Some(response) => return Some(response),
+                None => continue,
+            },
+        };
+
+        if action.action == "respond" {
+            trace(format!("Plugin '{}' answered request for path: {}", plugin.name, gruxi_request.get_path_str()));
+            return Some(plugin_response(&action));
+        }
+        for name in &action.remove_headers {
+            gruxi_request.remove_header(name);
+        }
+        for (name, value) in &action.headers {
+            gruxi_request.set_header(name, value);
+        }
+        // The rewritten path has to be canonical, as the path checks before the plugins were made on the canonical path
+        if !action.path.is_empty() {
+            let rewrite_result = if is_canonical_path(&action.path) {
+                gruxi_request.set_path(&action.path)
+            } else {
+                Err(format!("Rewritten path '{}' is not canonical", action.path))
+            };
+            if let Err(e) = rewrite_result
+                && let Some(response) = failed_plugin_response(plugin, &e)
+            {
+                return Some(response);
+            }
+        }
+    }
+    None
+}
+
+// The response phase of the "plugins" middleware, running the plugins of the site in reverse order
+pub fn run_response_plugins(context: &MiddlewareContext<'_>, gruxi_request: &mut GruxiRequest, response: &mut GruxiResponse) {
+    for plugin in context.get_site().plugins.iter().rev().filter(|plugin| plugin.is_enabled) {
+        let input = serde_json::json!({
+            "config": plugin.config,
+            "method": gruxi_request.get_http_method_str(),
+            "path": gruxi_request.get_path_str(),
+            "status": response.get_status(),
+            "headers": headers_to_pairs(response.headers()),
+        });
+        match run_plugin(plugin, "gruxi_on_response", &input) {
+            Ok(Some(action)) if action.action == "respond" => *response = plugin_response(&action),
+            Ok(Some(action)) => set_response_headers(response, &action),
+            Ok(None) => {}
+            Err(e) => {
+                if let Some(failed_response) = failed_plugin_response(plugin, &e) {
+                    *response = failed_response;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Answers requests for /blocked with 403, and adds a header to all other requests
+    const TEST_PLUGIN: &str = r#"
+        (module
+            (import "gruxi" "log" (func $log (param i32 i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 0) "{\"action\":\"respond\",\"status\":403,\"body\":\"blocked\"}")
+            (data (i32.const 64) "{\"action\":\"continue\",\"headers\":[[\"x-plugin\",\"seen\"]]}")
+            (data (i32.const 128) "/blocked")
+            (func (export "gruxi_abi_version") (result i32) i32.const 1)
+            (func (export "gruxi_alloc") (param i32) (result i32) i32.const 1024)
+            (func (export "gruxi_on_request") (param $ptr i32) (param $len i32) (result i64)
+                (local $i i32)
+                (call $log (i32.const 128) (i32.const 8))
+                ;; Look for "/blocked" anywhere in the input
+                (block $done
+                    (loop $search
+                        (br_if $done (i32.gt_u (i32.add (local.get $i) (i32.const 8)) (local.get $len)))
+                        (if (i64.eq (i64.load (i32.add (local.get $ptr) (local.get $i))) (i64.load (i32.const 128)))
+                            (then (return (i64.const 50))))
+                        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                        (br $search)))
+                (i64.or (i64.shl (i64.const 64) (i64.const 32)) (i64.const 53)))
+        )
+    "#;
+
+    const LOOPING_PLUGIN: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (func (export "gruxi_abi_version") (result i32) i32.const 1)
+            (func (export "gruxi_alloc") (param i32) (result i32) i32.const 0)
+            (func (export "gruxi_on_request") (param i32 i32) (result i64)
+                (loop $forever (br $forever))
+                i64.const 0)
+        )
+    "#;
+
+    fn compile(wat: &str) -> Module {
+        Module::new(get_engine().unwrap(), wat).unwrap()
+    }
+
+    #[test]
+    fn test_call_plugin_hook() {
+        let module = compile(TEST_PLUGIN);
+        let plugin = WasmPlugin::new("test", "test.wat");
+
+        let action = call_plugin_hook(&module, &plugin, "gruxi_on_request", br#"{"config":"","path":"/blocked"}"#).unwrap().unwrap();
+        assert_eq!(action.action, "respond");
+        assert_eq!(action.status, 403);
+        assert_eq!(action.body, "blocked");
+
+        let action = call_plugin_hook(&module, &plugin, "gruxi_on_request", br#"{"config":"","path":"/index.html"}"#).unwrap().unwrap();
+        assert_eq!(action.action, "continue");
+        assert_eq!(action.headers, vec![("x-plugin".to_string(), "seen".to_string())]);
+
+        // The module has no response hook
+        assert!(call_plugin_hook(&module, &plugin, "gruxi_on_response", b"{}").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_call_plugin_hook_limits() {
+        let mut plugin = WasmPlugin::new("looping", "looping.wat");
+        plugin.fuel_limit = 100_000;
+        assert!(call_plugin_hook(&compile(LOOPING_PLUGIN), &plugin, "gruxi_on_request", b"{}").is_err());
+
+        // One page of memory is more than the limit
+        plugin.memory_limit_mb = 0;
+        assert!(call_plugin_hook(&compile(TEST_PLUGIN), &plugin, "gruxi_on_request", b"{}").is_err());
+    }
+}
@@ -0,0 +1,321 @@
+// ============================================================================
+// WEBSOCKET RELAY
+// ============================================================================
+//
+// Bridges upgraded WebSocket connections between the client and the upstream,
+// with the limits of the site: concurrent connections per site, idle timeouts
+// with ping probing, and max frame and message sizes. Frames are passed on as
+// they arrive, only their headers are read to enforce the limits and to find
+// the frame boundaries where Gruxi can send its own ping and close frames.
+// ============================================================================
+
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::Instant;
+
+use crate::configuration::websocket_settings::WebSocketSettings;
+use crate::http::long_running_connections::{CountedClientIo, LongRunningConnection, LongRunningConnectionKind};
+use crate::logging::syslog::{debug, trace, warn};
+
+// How long to wait for the client to answer a ping, at most
+const PING_TIMEOUT_SECS: u64 = 30;
+
+// Close status codes, from RFC 6455
+const CLOSE_GOING_AWAY: u16 = 1001;
+const CLOSE_MESSAGE_TOO_BIG: u16 = 1009;
+
+// Open WebSocket connections per site
+static WEBSOCKET_CONNECTIONS: LazyLock<DashMap<String, u32>> = LazyLock::new(DashMap::new);
+
+// A WebSocket connection counted against the limit of its site, for as long as it is held
+pub struct WebSocketConnectionSlot {
+    site_id: String,
+}
+
+impl WebSocketConnectionSlot {
+    // Take a slot, unless the site already has max_connections (0 for no limit) open
+    pub fn acquire(site_id: &str, max_connections: u32) -> Option<Self> {
+        let mut count = WEBSOCKET_CONNECTIONS.entry(site_id.to_string()).or_insert(0);
+        if max_connections > 0 && *count >= max_connections {
+            return None;
+        }
+        *count += 1;
+        Some(WebSocketConnectionSlot { site_id: site_id.to_string() })
+    }
+}
+
+impl Drop for WebSocketConnectionSlot {
+    fn drop(&mut self) {
+        if let Some(mut count) = WEBSOCKET_CONNECTIONS.get_mut(&self.site_id) {
+            *count = count.saturating_sub(1);
+        }
+    }
+}
+
+pub fn get_websocket_connection_count(site_id: &str) -> u32 {
+    WEBSOCKET_CONNECTIONS.get(site_id).map(|count| *count).unwrap_or(0)
+}
+
+// Whether the request asks for a WebSocket upgrade, rather than another protocol
+pub fn is_websocket_upgrade(headers: &hyper::HeaderMap) -> bool {
+    headers
+        .get_all(hyper::header::UPGRADE)
+        .iter()
+        .any(|value| value.to_str().unwrap_or("").to_ascii_lowercase().contains("websocket"))
+}
+
+#[derive(Debug, PartialEq)]
+enum FrameLimitExceeded {
+    FrameTooLarge(u64),
+    MessageTooLarge(u64),
+}
+
+// Follows the frames in one direction of the connection, from the bytes passing through
+struct FrameTracker {
+    header: Vec<u8>,        // Bytes of the frame header read so far
+    payload_remaining: u64, // Bytes of the current frame payload still to come
+    message_size: u64,      // Size of the current (fragmented) message so far
+}
+
+impl FrameTracker {
+    fn new() -> Self {
+        FrameTracker {
+            header: Vec::with_capacity(14),
+            payload_remaining: 0,
+            message_size: 0,
+        }
+    }
+
+    fn is_at_frame_boundary(&self) -> bool {
+        self.header.is_empty() && self.payload_remaining == 0
+    }
+
+    fn feed(&mut self, data: &[u8], settings: &WebSocketSettings) -> Result<(), FrameLimitExceeded> {
+        let mut position = 0;
+        while position < data.len() {
+            if self.payload_remaining > 0 {
+                let length = self.payload_remaining.min((data.len() - position) as u64);
+                self.payload_remaining -= length;
+                position += length as usize;
+                continue;
+            }
+
+            self.header.push(data[position]);
+            position += 1;
+
+            let Some((is_final, opcode, payload_length)) = parse_frame_header(&self.header) else {
+                continue;
+            };
+            self.header.clear();
+            self.payload_remaining = payload_length;
+
+            if settings.max_frame_size > 0 && payload_length > settings.max_frame_size {
+                return Err(FrameLimitExceeded::FrameTooLarge(payload_length));
+            }
+
+            // Control frames (opcode 8 and up) can come between the fragments of a message, and are not part of it
+            if opcode < 8 {
+                self.message_size = if opcode == 0 { self.message_size + payload_length } else { payload_length };
+                if settings.max_message_size > 0 && self.message_size > settings.max_message_size {
+                    return Err(FrameLimitExceeded::MessageTooLarge(self.message_size));
+                }
+                if is_final {
+                    self.message_size = 0;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Parse a frame header, returning whether it is the final frame of its message, the opcode and the payload length,
+// or None if the header is not complete yet
+fn parse_frame_header(header: &[u8]) -> Option<(bool, u8, u64)> {
+    if header.len() < 2 {
+        return None;
+    }
+    let is_final = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0f;
+    let is_masked = header[1] & 0x80 != 0;
+    let (extended_length_bytes, length) = match header[1] & 0x7f {
+        126 => (2, None),
+        127 => (8, None),
+        length => (0, Some(length as u64)),
+    };
+    let header_length = 2 + extended_length_bytes + if is_masked { 4 } else { 0 };
+    if header.len() < header_length {
+        return None;
+    }
+
+    let length = length.unwrap_or_else(|| header[2..2 + extended_length_bytes].iter().fold(0u64, |length, byte| (length << 8) | *byte as u64));
+    Some((is_final, opcode, length))
+}
+
+// Frames sent to the client are not masked. Frames sent to the upstream, where we are the client, must be
+fn build_control_frame(opcode: u8, payload: &[u8], is_masked: bool) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode, payload.len() as u8];
+    if is_masked {
+        let mask: [u8; 4] = rand::random();
+        frame[1] |= 0x80;
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(index, byte)| byte ^ mask[index % 4]));
+    } else {
+        frame.extend_from_slice(payload);
+    }
+    frame
+}
+
+fn build_close_frame(status_code: u16, is_masked: bool) -> Vec<u8> {
+    build_control_frame(0x8, &status_code.to_be_bytes(), is_masked)
+}
+
+// Relay the frames between the client and the upstream until either side closes, or a limit of the site or an admin closes
+// the connection. The connection slot is held, and so counted for the site, until the relay ends
+pub async fn relay_websocket<C, U>(client: C, upstream: U, settings: WebSocketSettings, slot: WebSocketConnectionSlot)
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    U: AsyncRead + AsyncWrite + Unpin,
+{
+    let connection = LongRunningConnection::register(&slot.site_id, LongRunningConnectionKind::WebSocket);
+    let close_token = connection.get_close_token();
+    let (mut client_read, mut client_write) = tokio::io::split(CountedClientIo::new(client, connection));
+    let (mut upstream_read, mut upstream_write) = tokio::io::split(upstream);
+    let mut client_buffer = vec![0u8; 16 * 1024];
+    let mut upstream_buffer = vec![0u8; 16 * 1024];
+    let mut client_frames = FrameTracker::new();
+    let mut upstream_frames = FrameTracker::new();
+
+    let idle_timeout = Duration::from_secs(settings.idle_timeout_seconds as u64);
+    let ping_timeout = idle_timeout.min(Duration::from_secs(PING_TIMEOUT_SECS));
+    let mut last_activity = Instant::now();
+    let mut ping_sent_at: Option<Instant> = None;
+    let (mut from_client, mut from_upstream) = (0u64, 0u64);
+
+    // When a limit closes the connection: the close status, and whether the client and the upstream are between frames, so a
+    // close frame can be sent to them. Otherwise it would end up inside the frame being passed on
+    let close_status: Option<(u16, bool, bool)> = loop {
+        let deadline = match ping_sent_at {
+            Some(ping_sent_at) => ping_sent_at + ping_timeout,
+            None => last_activity + idle_timeout,
+        };
+
+        tokio::select! {
+            result = client_read.read(&mut client_buffer) => {
+                let length = match result {
+                    Ok(0) | Err(_) => break None,
+                    Ok(length) => length,
+                };
+                let was_at_frame_boundary = client_frames.is_at_frame_boundary();
+                if let Err(e) = client_frames.feed(&client_buffer[..length], &settings) {
+                    warn(format!("WebSocket connection of site '{}' closed, the client sent too much: {:?}", slot.site_id, e));
+                    break Some((CLOSE_MESSAGE_TOO_BIG, upstream_frames.is_at_frame_boundary(), was_at_frame_boundary));
+                }
+                if upstream_write.write_all(&client_buffer[..length]).await.is_err() {
+                    break None;
+                }
+                from_client += length as u64;
+                // Anything from the client, such as the pong, shows it is still there
+                last_activity = Instant::now();
+                ping_sent_at = None;
+            }
+            result = upstream_read.read(&mut upstream_buffer) => {
+                let length = match result {
+                    Ok(0) | Err(_) => break None,
+                    Ok(length) => length,
+                };
+                let was_at_frame_boundary = upstream_frames.is_at_frame_boundary();
+                if let Err(e) = upstream_frames.feed(&upstream_buffer[..length], &settings) {
+                    warn(format!("WebSocket connection of site '{}' closed, the upstream sent too much: {:?}", slot.site_id, e));
+                    break Some((CLOSE_MESSAGE_TOO_BIG, was_at_frame_boundary, client_frames.is_at_frame_boundary()));
+                }
+                if client_write.write_all(&upstream_buffer[..length]).await.is_err() {
+                    break None;
+                }
+                from_upstream += length as u64;
+                if ping_sent_at.is_none() {
+                    last_activity = Instant::now();
+                }
+            }
+            _ = tokio::time::sleep_until(deadline), if !idle_timeout.is_zero() => {
+                if ping_sent_at.is_some() || !upstream_frames.is_at_frame_boundary() {
+                    debug(format!("WebSocket connection of site '{}' closed after being idle for {} seconds", slot.site_id, settings.idle_timeout_seconds));
+                    break Some((CLOSE_GOING_AWAY, upstream_frames.is_at_frame_boundary(), client_frames.is_at_frame_boundary()));
+                }
+                trace(format!("WebSocket connection of site '{}' is idle, pinging the client", slot.site_id));
+                if client_write.write_all(&build_control_frame(0x9, b"gruxi", false)).await.is_err() {
+                    break None;
+                }
+                ping_sent_at = Some(Instant::now());
+            }
+            _ = close_token.cancelled() => {
+                debug(format!("WebSocket connection of site '{}' closed by an admin", slot.site_id));
+                break Some((CLOSE_GOING_AWAY, upstream_frames.is_at_frame_boundary(), client_frames.is_at_frame_boundary()));
+            }
+        }
+    };
+
+    if let Some((status_code, is_client_at_frame_boundary, is_upstream_at_frame_boundary)) = close_status {
+        if is_client_at_frame_boundary {
+            let _ = client_write.write_all(&build_close_frame(status_code, false)).await;
+        }
+        if is_upstream_at_frame_boundary {
+            let _ = upstream_write.write_all(&build_close_frame(status_code, true)).await;
+        }
+    }
+    let _ = client_write.shutdown().await;
+    let _ = upstream_write.shutdown().await;
+
+    trace(format!("WebSocket closed. Client→Server: {} bytes, Server→Client: {} bytes", from_client, from_upstream));
+    drop(slot);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(max_frame_size: u64, max_message_size: u64) -> WebSocketSettings {
+        WebSocketSettings {
+            max_frame_size,
+            max_message_size,
+            ..WebSocketSettings::new()
+        }
+    }
+
+    #[test]
+    fn test_websocket_frame_tracker_limits() {
+        // A masked text frame of 5 bytes, split over two reads, followed by a frame with a 16 bit length
+        let mut tracker = FrameTracker::new();
+        let mut frame = build_control_frame(0x1, b"hello", true);
+        assert!(tracker.feed(&frame[..3], &settings(0, 0)).is_ok());
+        assert!(!tracker.is_at_frame_boundary());
+        assert!(tracker.feed(&frame[3..], &settings(0, 0)).is_ok());
+        assert!(tracker.is_at_frame_boundary());
+
+        frame = vec![0x82, 126, 0x01, 0x00];
+        frame.extend(vec![0u8; 256]);
+        assert_eq!(tracker.feed(&frame, &settings(200, 0)), Err(FrameLimitExceeded::FrameTooLarge(256)));
+
+        // Fragments add up to the message size, control frames in between do not
+        let mut tracker = FrameTracker::new();
+        let limits = settings(0, 8);
+        assert!(tracker.feed(&[0x01, 4, b'a', b'b', b'c', b'd'], &limits).is_ok());
+        assert!(tracker.feed(&build_control_frame(0x9, b"ping-ping", false), &limits).is_ok());
+        assert_eq!(tracker.feed(&[0x80, 5, b'e', b'f', b'g', b'h', b'i'], &limits), Err(FrameLimitExceeded::MessageTooLarge(9)));
+    }
+
+    #[test]
+    fn test_websocket_connection_slots() {
+        let site_id = "websocket-slot-test";
+        let first = WebSocketConnectionSlot::acquire(site_id, 2).unwrap();
+        let second = WebSocketConnectionSlot::acquire(site_id, 2).unwrap();
+        assert!(WebSocketConnectionSlot::acquire(site_id, 2).is_none());
+        assert_eq!(get_websocket_connection_count(site_id), 2);
+        drop(first);
+        assert!(WebSocketConnectionSlot::acquire(site_id, 2).is_some());
+        drop(second);
+        assert_eq!(get_websocket_connection_count(site_id), 0);
+    }
+}
@@ -0,0 +1,342 @@
+// ============================================================================
+// ACCESS LOG ANALYTICS
+// ============================================================================
+//
+// Summaries of the access logs of each site, over the last 24 hours: the most
+// requested paths, the status code distribution, an estimate of the unique
+// client IPs and the bytes sent. The entries are aggregated as the access log
+// buffers are flushed, so only sites with access logging enabled have them.
+//
+// Memory per site is bounded. The summaries are kept in hourly buckets that
+// roll off after the window, the paths of each bucket are counted with the
+// Space-Saving algorithm over a fixed number of counters, and the unique IPs
+// with a HyperLogLog sketch. The summaries are in memory only, and are lost on
+// restart.
+// ============================================================================
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{LazyLock, Mutex};
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+
+// The window the summaries are over, in hourly buckets
+const ANALYTICS_WINDOW_HOURS: i64 = 24;
+
+// Counters for paths per bucket. Paths outside the most requested ones share counters, so counts near the bottom of the
+// top list are estimates
+const MAX_PATH_COUNTERS: usize = 1000;
+
+// Maximum number of paths in a summary
+pub const MAX_TOP_PATHS: usize = 100;
+
+// HyperLogLog precision, with 2^12 registers for a standard error of about 1.6%
+const HLL_PRECISION: u32 = 12;
+const HLL_REGISTERS: usize = 1 << HLL_PRECISION;
+
+// Estimates the number of distinct values added, in fixed memory
+#[derive(Clone)]
+struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn new() -> Self {
+        HyperLogLog { registers: vec![0; HLL_REGISTERS] }
+    }
+
+    fn add(&mut self, value: &str) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        let index = (hash >> (64 - HLL_PRECISION)) as usize;
+        let rank = ((hash << HLL_PRECISION).leading_zeros() + 1).min(64 - HLL_PRECISION + 1) as u8;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    fn merge(&mut self, other: &HyperLogLog) {
+        for (register, other_register) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other_register);
+        }
+    }
+
+    fn estimate(&self) -> u64 {
+        let registers = HLL_REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / registers);
+        let sum: f64 = self.registers.iter().map(|register| 2f64.powi(-(*register as i32))).sum();
+        let estimate = alpha * registers * registers / sum;
+
+        // Linear counting for small cardinalities, where the raw estimate is biased
+        let zero_registers = self.registers.iter().filter(|register| **register == 0).count();
+        if estimate <= 2.5 * registers && zero_registers > 0 {
+            return (registers * (registers / zero_registers as f64).ln()).round() as u64;
+        }
+        estimate.round() as u64
+    }
+}
+
+// The entries of one site in one hour
+struct AnalyticsBucket {
+    hour: i64, // Hours since the Unix epoch
+    requests: u64,
+    bytes_sent: u64,
+    status_codes: HashMap<u16, u64>,
+    path_counters: HashMap<String, u64>,
+    unique_ips: HyperLogLog,
+}
+
+impl AnalyticsBucket {
+    fn new(hour: i64) -> Self {
+        AnalyticsBucket {
+            hour,
+            requests: 0,
+            bytes_sent: 0,
+            status_codes: HashMap::new(),
+            path_counters: HashMap::new(),
+            unique_ips: HyperLogLog::new(),
+        }
+    }
+
+    // Space-Saving: when all counters are taken, the least counted path gives its counter to the new one
+    fn count_path(&mut self, path: &str) {
+        if let Some(count) = self.path_counters.get_mut(path) {
+            *count += 1;
+            return;
+        }
+        if self.path_counters.len() < MAX_PATH_COUNTERS {
+            self.path_counters.insert(path.to_string(), 1);
+            return;
+        }
+        let least_counted = self.path_counters.iter().min_by_key(|(_, count)| **count).map(|(path, count)| (path.clone(), *count));
+        if let Some((least_counted_path, count)) = least_counted {
+            self.path_counters.remove(&least_counted_path);
+            self.path_counters.insert(path.to_string(), count + 1);
+        }
+    }
+}
+
+// The fields of an access log entry the summaries are made of
+#[derive(Debug, PartialEq)]
+struct AccessLogEntry<'a> {
+    remote_ip: &'a str,
+    path: &'a str,
+    status: u16,
+    bytes_sent: u64,
+}
+
+// Parse an access log entry as written by the "access_log" middleware, in Common Log Format:
+// 127.0.0.1 - - [10/Oct/2000:13:55:36 -0700] "GET /index.html HTTP/1.1" 200 2326
+fn parse_access_log_entry(line: &str) -> Option<AccessLogEntry<'_>> {
+    let remote_ip = line.split(' ').next()?;
+    let request_start = line.find('"')?;
+    let request_end = line.rfind('"')?;
+    if request_end <= request_start {
+        return None;
+    }
+
+    let mut request = line[request_start + 1..request_end].split(' ');
+    let _method = request.next()?;
+    let path_and_query = request.next()?;
+    let path = path_and_query.split('?').next().unwrap_or(path_and_query);
+
+    let mut response = line[request_end + 1..].split_whitespace();
+    let status = response.next()?.parse().ok()?;
+    let bytes_sent = response.next().and_then(|size| size.parse().ok()).unwrap_or(0);
+
+    Some(AccessLogEntry { remote_ip, path, status, bytes_sent })
+}
+
+// Buckets of the last hours per site ID, oldest first
+static SITE_ANALYTICS: LazyLock<Mutex<HashMap<String, VecDeque<AnalyticsBucket>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn get_current_hour() -> i64 {
+    Utc::now().timestamp().div_euclid(3600)
+}
+
+// Add access log entries of the site to its summary, as they are flushed from its access log buffer
+pub fn record_access_log_entries(site_id: &str, entries: &[String]) {
+    record_entries_at(site_id, entries, get_current_hour());
+}
+
+fn record_entries_at(site_id: &str, entries: &[String], hour: i64) {
+    let mut site_analytics = match SITE_ANALYTICS.lock() {
+        Ok(site_analytics) => site_analytics,
+        Err(_) => return,
+    };
+    let buckets = site_analytics.entry(site_id.to_string()).or_default();
+    if buckets.back().is_none_or(|bucket| bucket.hour != hour) {
+        buckets.push_back(AnalyticsBucket::new(hour));
+    }
+    while buckets.front().is_some_and(|bucket| bucket.hour <= hour - ANALYTICS_WINDOW_HOURS) {
+        buckets.pop_front();
+    }
+    let Some(bucket) = buckets.back_mut() else {
+        return;
+    };
+
+    for entry in entries.iter().filter_map(|line| parse_access_log_entry(line)) {
+        bucket.requests += 1;
+        bucket.bytes_sent += entry.bytes_sent;
+        *bucket.status_codes.entry(entry.status).or_default() += 1;
+        bucket.count_path(entry.path);
+        bucket.unique_ips.add(entry.remote_ip);
+    }
+}
+
+// Roll the buckets of all sites over the window, including those without entries since, and forget sites without any
+// left. Returns how many buckets were removed
+pub fn roll_up_access_log_analytics() -> usize {
+    roll_up_at(get_current_hour())
+}
+
+fn roll_up_at(hour: i64) -> usize {
+    let mut site_analytics = match SITE_ANALYTICS.lock() {
+        Ok(site_analytics) => site_analytics,
+        Err(_) => return 0,
+    };
+    let mut removed_buckets = 0;
+    for buckets in site_analytics.values_mut() {
+        while buckets.front().is_some_and(|bucket| bucket.hour <= hour - ANALYTICS_WINDOW_HOURS) {
+            buckets.pop_front();
+            removed_buckets += 1;
+        }
+    }
+    site_analytics.retain(|_, buckets| !buckets.is_empty());
+    removed_buckets
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PathCount {
+    pub path: String,
+    pub requests: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessLogSummary {
+    pub site_id: String,
+    pub from: DateTime<Utc>,
+    pub requests: u64,
+    pub bytes_sent: u64,
+    pub unique_ips_estimate: u64,
+    pub status_codes: HashMap<u16, u64>,
+    pub top_paths: Vec<PathCount>,
+}
+
+// The summaries over the window of the sites passing the filter, with the given number of most requested paths
+pub fn get_access_log_summaries<F: Fn(&str) -> bool>(include_site: F, top_paths: usize) -> Vec<AccessLogSummary> {
+    get_summaries_at(include_site, top_paths, get_current_hour())
+}
+
+fn get_summaries_at<F: Fn(&str) -> bool>(include_site: F, top_paths: usize, hour: i64) -> Vec<AccessLogSummary> {
+    let mut site_analytics = match SITE_ANALYTICS.lock() {
+        Ok(site_analytics) => site_analytics,
+        Err(_) => return Vec::new(),
+    };
+    let first_hour = hour - ANALYTICS_WINDOW_HOURS + 1;
+
+    // Forget sites without entries in the window, such as removed sites
+    site_analytics.retain(|_, buckets| buckets.back().is_some_and(|bucket| bucket.hour >= first_hour));
+
+    let mut summaries: Vec<AccessLogSummary> = site_analytics
+        .iter()
+        .filter(|(site_id, _)| include_site(site_id))
+        .filter_map(|(site_id, buckets)| {
+            let buckets: Vec<&AnalyticsBucket> = buckets.iter().filter(|bucket| bucket.hour >= first_hour).collect();
+            if buckets.is_empty() {
+                return None;
+            }
+
+            let mut unique_ips = HyperLogLog::new();
+            let mut status_codes: HashMap<u16, u64> = HashMap::new();
+            let mut path_counts: HashMap<&str, u64> = HashMap::new();
+            for bucket in &buckets {
+                unique_ips.merge(&bucket.unique_ips);
+                for (status, count) in &bucket.status_codes {
+                    *status_codes.entry(*status).or_default() += count;
+                }
+                for (path, count) in &bucket.path_counters {
+                    *path_counts.entry(path).or_default() += count;
+                }
+            }
+            let mut path_counts: Vec<(&str, u64)> = path_counts.into_iter().collect();
+            path_counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+            Some(AccessLogSummary {
+                site_id: site_id.clone(),
+                from: Utc.timestamp_opt(first_hour * 3600, 0).single().unwrap_or_else(Utc::now),
+                requests: buckets.iter().map(|bucket| bucket.requests).sum(),
+                bytes_sent: buckets.iter().map(|bucket| bucket.bytes_sent).sum(),
+                unique_ips_estimate: unique_ips.estimate(),
+                status_codes,
+                top_paths: path_counts
+                    .into_iter()
+                    .take(top_paths.min(MAX_TOP_PATHS))
+                    .map(|(path, requests)| PathCount { path: path.to_string(), requests })
+                    .collect(),
+            })
+        })
+        .collect();
+    summaries.sort_by(|a, b| b.requests.cmp(&a.requests).then_with(|| a.site_id.cmp(&b.site_id)));
+    summaries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_access_log_entry() {
+        let entry = parse_access_log_entry(r#"192.0.2.1 - - [10/Oct/2000:13:55:36 -0700] "GET /index.html?a=1 HTTP/1.1" 200 2326"#).unwrap();
+        assert_eq!(
+            entry,
+            AccessLogEntry {
+                remote_ip: "192.0.2.1",
+                path: "/index.html",
+                status: 200,
+                bytes_sent: 2326
+            }
+        );
+        assert!(parse_access_log_entry("not an access log entry").is_none());
+    }
+
+    #[test]
+    fn test_summaries_roll_over_the_window() {
+        let site_id = uuid::Uuid::new_v4().to_string();
+        let only_this_site = |id: &str| id == site_id;
+        let hour = 500_000;
+
+        let entries: Vec<String> = (0..1000)
+            .map(|i| {
+                format!(
+                    r#"10.0.{}.{} - - [10/Oct/2000:13:55:36 +0000] "GET /page-{} HTTP/1.1" {} 100"#,
+                    i / 256,
+                    i % 256,
+                    i % 3,
+                    if i % 10 == 0 { 404 } else { 200 }
+                )
+            })
+            .collect();
+        record_entries_at(&site_id, &entries, hour);
+        record_entries_at(&site_id, &entries[..10], hour + 1);
+
+        let summary = get_summaries_at(only_this_site, 2, hour + 1).remove(0);
+        assert_eq!(summary.requests, 1010);
+        assert_eq!(summary.bytes_sent, 101_000);
+        assert_eq!(summary.status_codes.get(&404), Some(&101));
+        assert_eq!(summary.top_paths.len(), 2);
+        assert_eq!(summary.top_paths[0].path, "/page-0");
+        assert!((970..=1030).contains(&summary.unique_ips_estimate), "estimate was {}", summary.unique_ips_estimate);
+
+        // The first hour rolls off the window
+        let summary = get_summaries_at(only_this_site, 10, hour + ANALYTICS_WINDOW_HOURS).remove(0);
+        assert_eq!(summary.requests, 10);
+
+        if let Ok(mut site_analytics) = SITE_ANALYTICS.lock() {
+            site_analytics.remove(&site_id);
+        }
+    }
+}
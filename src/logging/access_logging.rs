@@ -5,6 +5,7 @@ use std::time::Instant;
 use tokio::select;
 
 use crate::core::running_state_manager::get_running_state_manager;
+use crate::logging::access_log_analytics::record_access_log_entries;
 use crate::logging::buffered_log::BufferedLog;
 
 // Key is site ID, value is buffered log entries
@@ -112,8 +113,8 @@ impl AccessLogBuffer {
                         let access_log_buffer_rwlock = running_state.get_access_log_buffer();
                         let access_log_buffer = access_log_buffer_rwlock.read().await;
 
-                        for (_site_id, log) in access_log_buffer.buffered_logs.iter() {
-                            log.consider_flush(false);
+                        for (site_id, log) in access_log_buffer.buffered_logs.iter() {
+                            log.consider_flush_with(false, |entries| record_access_log_entries(site_id, entries));
                         }
                         let elapsed = start_time.elapsed().as_millis();
                         if elapsed > 0 {
@@ -125,8 +126,8 @@ impl AccessLogBuffer {
                     let access_log_buffer_rwlock = running_state.get_access_log_buffer();
                     let access_log_buffer = access_log_buffer_rwlock.read().await;
 
-                    for (_site_id, log) in access_log_buffer.buffered_logs.iter() {
-                        log.consider_flush(true);
+                    for (site_id, log) in access_log_buffer.buffered_logs.iter() {
+                        log.consider_flush_with(true, |entries| record_access_log_entries(site_id, entries));
                     }
                     break;
                 },
@@ -135,8 +136,8 @@ impl AccessLogBuffer {
                     let access_log_buffer_rwlock = running_state.get_access_log_buffer();
                     let access_log_buffer = access_log_buffer_rwlock.read().await;
 
-                    for (_site_id, log) in access_log_buffer.buffered_logs.iter() {
-                        log.consider_flush(true);
+                    for (site_id, log) in access_log_buffer.buffered_logs.iter() {
+                        log.consider_flush_with(true, |entries| record_access_log_entries(site_id, entries));
                     }
                     break;
                 }
@@ -58,6 +58,11 @@ impl BufferedLog {
     }
 
     pub fn consider_flush(&self, force_flush: bool) {
+        self.consider_flush_with(force_flush, |_| {});
+    }
+
+    // As consider_flush, also handing the entries to before_write when they are flushed, such as for access log analytics
+    pub fn consider_flush_with<F: FnOnce(&[String])>(&self, force_flush: bool, before_write: F) {
         // Get lock
         let mut log_buffer_result = self.buffered_log.lock();
 
@@ -86,6 +91,8 @@ impl BufferedLog {
             }
         }
 
+        before_write(&log_buffer);
+
         // Append the log to the file path
         let log_data = log_buffer.join("\n") + "\n";
         if let Err(e) = std::fs::OpenOptions::new().create(true).append(true).open(&self.log_file_path).and_then(|mut file| {
@@ -0,0 +1,376 @@
+// ============================================================================
+// DEBUG DUMPS
+// ============================================================================
+//
+// A debug dump writes the full request and response headers of the requests
+// of one site to a file of its own in the logs directory, for a short forensic
+// window in production. It is started through the admin portal with a
+// duration and a number of requests, and ends itself at whichever comes first.
+//
+// Secrets are redacted: the values of credential and cookie headers, and of
+// query, form and JSON fields with names such as "password" or "token". Bodies
+// are only dumped when they are already buffered, and truncated, so a dump
+// never makes Gruxi buffer a streamed request or response.
+// ============================================================================
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use hyper::HeaderMap;
+use hyper::body::Bytes;
+use serde::Serialize;
+
+use crate::http::request_response::gruxi_request::GruxiRequest;
+use crate::http::request_response::gruxi_response::GruxiResponse;
+use crate::logging::syslog::{info, warn};
+
+pub const DEBUG_DUMP_DEFAULT_DURATION_SECONDS: u64 = 300;
+pub const DEBUG_DUMP_MAX_DURATION_SECONDS: u64 = 3600;
+pub const DEBUG_DUMP_DEFAULT_MAX_REQUESTS: u64 = 1000;
+pub const DEBUG_DUMP_MAX_REQUESTS: u64 = 100_000;
+// The most of each body that is dumped
+const DEBUG_DUMP_MAX_BODY_BYTES: usize = 4096;
+// The admin portal lists the .log files of this directory, so the dumps can be read there
+const DEBUG_DUMP_DIRECTORY: &str = "./logs";
+
+const REDACTED: &str = "[REDACTED]";
+// Headers carrying credentials, whose values are never dumped
+const SECRET_HEADERS: [&str; 8] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+    "x-auth-token",
+    "x-csrf-token",
+    "x-xsrf-token",
+];
+// Parts of header, query, form and JSON field names that make the value a secret
+const SECRET_NAME_PARTS: [&str; 8] = ["password", "passwd", "secret", "token", "apikey", "api_key", "api-key", "credential"];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DebugDumpStatus {
+    pub site_id: String,
+    pub file_name: String,
+    pub started_by: String,
+    pub started_at: String,
+    pub ends_at: String,
+    pub max_requests: u64,
+    pub requests_dumped: u64,
+}
+
+struct DebugDump {
+    site_id: String,
+    file_name: String,
+    started_by: String,
+    started_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    max_requests: u64,
+    requests_dumped: AtomicU64,
+    file: Mutex<File>,
+}
+
+impl DebugDump {
+    fn get_status(&self) -> DebugDumpStatus {
+        DebugDumpStatus {
+            site_id: self.site_id.clone(),
+            file_name: self.file_name.clone(),
+            started_by: self.started_by.clone(),
+            started_at: self.started_at.to_rfc3339(),
+            ends_at: self.ends_at.to_rfc3339(),
+            max_requests: self.max_requests,
+            requests_dumped: self.requests_dumped.load(Ordering::SeqCst),
+        }
+    }
+
+    fn write(&self, text: &str) {
+        let mut file = self.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Err(e) = file.write_all(text.as_bytes()) {
+            warn(format!("Failed to write debug dump of site '{}' to {}: {}", self.site_id, self.file_name, e));
+        }
+    }
+}
+
+// The running dump per site
+static DEBUG_DUMPS: LazyLock<DashMap<String, Arc<DebugDump>>> = LazyLock::new(DashMap::new);
+// Kept apart from the map, so the requests of a server without dumps only cost an atomic load
+static ACTIVE_DEBUG_DUMPS: AtomicUsize = AtomicUsize::new(0);
+
+/// The running debug dumps, sorted by site
+pub fn get_debug_dumps() -> Vec<DebugDumpStatus> {
+    let mut dumps: Vec<DebugDumpStatus> = DEBUG_DUMPS.iter().map(|entry| entry.value().get_status()).collect();
+    dumps.sort_by(|a, b| a.site_id.cmp(&b.site_id));
+    dumps
+}
+
+/// Start dumping the requests of a site to a new file, replacing a dump already running for it
+pub fn start_debug_dump(site_id: &str, duration_seconds: u64, max_requests: u64, started_by: &str) -> Result<DebugDumpStatus, String> {
+    if !(1..=DEBUG_DUMP_MAX_DURATION_SECONDS).contains(&duration_seconds) {
+        return Err(format!("Duration must be between 1 and {} seconds", DEBUG_DUMP_MAX_DURATION_SECONDS));
+    }
+    if !(1..=DEBUG_DUMP_MAX_REQUESTS).contains(&max_requests) {
+        return Err(format!("Number of requests must be between 1 and {}", DEBUG_DUMP_MAX_REQUESTS));
+    }
+
+    let started_at = Utc::now();
+    let ends_at = started_at + chrono::Duration::seconds(duration_seconds as i64);
+    let safe_site_id: String = site_id.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+    let file_name = format!("debug-dump-{}-{}.log", safe_site_id, started_at.format("%Y%m%d-%H%M%S"));
+
+    std::fs::create_dir_all(DEBUG_DUMP_DIRECTORY).map_err(|e| format!("Failed to create {}: {}", DEBUG_DUMP_DIRECTORY, e))?;
+    let file_path = std::path::Path::new(DEBUG_DUMP_DIRECTORY).join(&file_name);
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&file_path)
+        .map_err(|e| format!("Failed to open {}: {}", file_path.display(), e))?;
+
+    let dump = Arc::new(DebugDump {
+        site_id: site_id.to_string(),
+        file_name,
+        started_by: started_by.to_string(),
+        started_at,
+        ends_at,
+        max_requests,
+        requests_dumped: AtomicU64::new(0),
+        file: Mutex::new(file),
+    });
+    dump.write(&format!(
+        "# Debug dump of site '{}', started by '{}' at {}, until {} or {} requests\n\n",
+        site_id,
+        started_by,
+        started_at.to_rfc3339(),
+        ends_at.to_rfc3339(),
+        max_requests
+    ));
+
+    DEBUG_DUMPS.insert(site_id.to_string(), dump.clone());
+    ACTIVE_DEBUG_DUMPS.store(DEBUG_DUMPS.len(), Ordering::SeqCst);
+    info(format!("Debug dump of site '{}' to {} was started by '{}'", site_id, dump.file_name, started_by));
+
+    // End it when its time is up, also when no more requests arrive
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        let dump = dump.clone();
+        handle.spawn(async move {
+            tokio::time::sleep(Duration::from_secs(duration_seconds)).await;
+            end_debug_dump(&dump, "its duration passed");
+        });
+    }
+
+    Ok(dump.get_status())
+}
+
+/// Stop the debug dump of a site. Returns None when there is no dump running for it
+pub fn stop_debug_dump(site_id: &str) -> Option<DebugDumpStatus> {
+    let dump = DEBUG_DUMPS.get(site_id).map(|entry| entry.value().clone())?;
+    end_debug_dump(&dump, "it was stopped");
+    Some(dump.get_status())
+}
+
+// Remove the dump, unless it was already replaced by a newer one
+fn end_debug_dump(dump: &Arc<DebugDump>, reason: &str) {
+    if DEBUG_DUMPS.remove_if(&dump.site_id, |_, current| Arc::ptr_eq(current, dump)).is_none() {
+        return;
+    }
+    ACTIVE_DEBUG_DUMPS.store(DEBUG_DUMPS.len(), Ordering::SeqCst);
+
+    let requests_dumped = dump.requests_dumped.load(Ordering::SeqCst);
+    dump.write(&format!("# Debug dump ended at {}, as {}, after {} requests\n", Utc::now().to_rfc3339(), reason, requests_dumped));
+    info(format!(
+        "Debug dump of site '{}' ended, as {}. {} requests were dumped to {}",
+        dump.site_id, reason, requests_dumped, dump.file_name
+    ));
+}
+
+/// The request part of a dump entry. It is taken before the request is handled, as handlers may change or consume the request
+pub struct DebugDumpRequest {
+    dump: Arc<DebugDump>,
+    request_number: u64,
+    started: Instant,
+    entry: String,
+}
+
+/// Take the request part of a dump entry, when a debug dump is running for the site
+pub fn capture_debug_dump_request(site_id: &str, gruxi_request: &mut GruxiRequest) -> Option<DebugDumpRequest> {
+    if ACTIVE_DEBUG_DUMPS.load(Ordering::SeqCst) == 0 {
+        return None;
+    }
+    let dump = DEBUG_DUMPS.get(site_id).map(|entry| entry.value().clone())?;
+    if Utc::now() >= dump.ends_at {
+        end_debug_dump(&dump, "its duration passed");
+        return None;
+    }
+    let request_number = dump
+        .requests_dumped
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| (count < dump.max_requests).then_some(count + 1))
+        .ok()?
+        + 1;
+
+    let mut entry = format!("=== Request {} at {} from {}\n", request_number, Utc::now().to_rfc3339(), gruxi_request.get_remote_ip());
+    entry.push_str(&format!(
+        "{} {} {}\n",
+        gruxi_request.get_http_method(),
+        redact_query(&gruxi_request.get_path_and_query()),
+        gruxi_request.get_http_version()
+    ));
+    entry.push_str(&format_headers(gruxi_request.get_headers()));
+    entry.push_str(&format_body(gruxi_request.get_headers(), gruxi_request.get_buffered_body()));
+
+    Some(DebugDumpRequest {
+        dump,
+        request_number,
+        started: Instant::now(),
+        entry,
+    })
+}
+
+/// Write the dump entry of a request, with the response it got
+pub fn write_debug_dump(debug_dump_request: DebugDumpRequest, response: &GruxiResponse) {
+    let mut entry = debug_dump_request.entry;
+    entry.push_str(&format!("--- Response {} in {} ms\n", response.get_status(), debug_dump_request.started.elapsed().as_millis()));
+    entry.push_str(&format_headers(response.headers()));
+    entry.push_str(&format_body(response.headers(), response.get_buffered_body()));
+    entry.push('\n');
+    debug_dump_request.dump.write(&entry);
+
+    if debug_dump_request.request_number == debug_dump_request.dump.max_requests {
+        end_debug_dump(&debug_dump_request.dump, "its number of requests was reached");
+    }
+}
+
+fn is_secret_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    SECRET_NAME_PARTS.iter().any(|part| name.contains(part))
+}
+
+fn format_headers(headers: &HeaderMap) -> String {
+    let mut text = String::new();
+    for (name, value) in headers {
+        let value = if SECRET_HEADERS.contains(&name.as_str()) || is_secret_name(name.as_str()) {
+            REDACTED.to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).to_string()
+        };
+        text.push_str(&format!("{}: {}\n", name, value));
+    }
+    text.push('\n');
+    text
+}
+
+// The body as text, with the secrets of forms and JSON redacted, truncated
+fn format_body(headers: &HeaderMap, body: Option<&Bytes>) -> String {
+    let Some(body) = body else {
+        let has_body = headers.contains_key("transfer-encoding")
+            || headers
+                .get("content-length")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok())
+                .is_some_and(|length| length > 0);
+        return if has_body { "[Streamed body, not dumped]\n".to_string() } else { String::new() };
+    };
+    if body.is_empty() {
+        return String::new();
+    }
+
+    let content_type = headers.get("content-type").and_then(|value| value.to_str().ok()).unwrap_or("").to_lowercase();
+    let Ok(text) = std::str::from_utf8(body) else {
+        return format!("[Binary body of {} bytes, not dumped]\n", body.len());
+    };
+    let text = if content_type.starts_with("application/x-www-form-urlencoded") {
+        redact_form(text)
+    } else if content_type.contains("json") {
+        match serde_json::from_str::<serde_json::Value>(text) {
+            Ok(mut value) => {
+                redact_json(&mut value);
+                value.to_string()
+            }
+            Err(_) => return format!("[JSON body of {} bytes that could not be parsed to redact it, not dumped]\n", body.len()),
+        }
+    } else {
+        text.to_string()
+    };
+
+    if text.len() <= DEBUG_DUMP_MAX_BODY_BYTES {
+        return format!("{}\n", text);
+    }
+    let mut end = DEBUG_DUMP_MAX_BODY_BYTES;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}\n[Truncated, {} of {} bytes dumped]\n", &text[..end], end, text.len())
+}
+
+fn redact_form(form: &str) -> String {
+    form.split('&')
+        .map(|field| match field.split_once('=') {
+            Some((name, _)) if is_secret_name(&urlencoding::decode(name).unwrap_or_default()) => format!("{}={}", name, REDACTED),
+            _ => field.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn redact_query(path_and_query: &str) -> String {
+    match path_and_query.split_once('?') {
+        Some((path, query)) => format!("{}?{}", path, redact_form(query)),
+        None => path_and_query.to_string(),
+    }
+}
+
+fn redact_json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(object) => {
+            for (name, value) in object.iter_mut() {
+                if is_secret_name(name) {
+                    *value = serde_json::Value::String(REDACTED.to_string());
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        serde_json::Value::Array(array) => array.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    #[test]
+    fn test_secrets_are_redacted() {
+        assert_eq!(redact_query("/login?user=bob&password=hunter2&next=%2F"), "/login?user=bob&password=[REDACTED]&next=%2F");
+        assert_eq!(redact_query("/plain"), "/plain");
+
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("Bearer abc"));
+        headers.insert("x-upstream-token", HeaderValue::from_static("abc"));
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        let text = format_headers(&headers);
+        assert!(!text.contains("abc"), "{}", text);
+        assert!(text.contains("content-type: application/json"), "{}", text);
+
+        let body = Bytes::from(r#"{"user":"bob","nested":[{"api_key":"abc"}],"client_secret":"abc"}"#);
+        let text = format_body(&headers, Some(&body));
+        assert!(!text.contains("abc") && text.contains("bob"), "{}", text);
+    }
+
+    #[test]
+    fn test_bodies_are_truncated_and_streams_are_not_dumped() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("text/plain"));
+        let body = Bytes::from("é".repeat(DEBUG_DUMP_MAX_BODY_BYTES));
+        let text = format_body(&headers, Some(&body));
+        assert!(text.contains(&format!("[Truncated, {} of {} bytes dumped]", DEBUG_DUMP_MAX_BODY_BYTES, body.len())), "{}", text);
+
+        assert_eq!(format_body(&headers, None), "");
+        headers.insert("content-length", HeaderValue::from_static("10"));
+        assert_eq!(format_body(&headers, None), "[Streamed body, not dumped]\n");
+    }
+}
@@ -0,0 +1,115 @@
+// ============================================================================
+// LOG ROTATION
+// ============================================================================
+//
+// Rotates the system log and the access logs of the sites when they grow over
+// a size, as a scheduled task. A log is rotated by renaming it with the time
+// appended, such as "access.log.20260131-030000", so the next flush of its
+// buffer starts a new file, as buffered logs open the file on every flush.
+// Only the newest rotated files of each log are kept.
+// ============================================================================
+
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+
+use crate::core::running_state_manager::get_running_state_manager;
+use crate::logging::syslog::{SYS_LOG, info};
+
+const ROTATED_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// The files of the system log and of the access logs of the running sites
+pub async fn get_log_file_paths() -> Vec<String> {
+    let mut log_file_paths = Vec::new();
+    if let Ok(sys_log) = SYS_LOG.read() {
+        log_file_paths.push(sys_log.buffered_log.log_file_path.clone());
+    }
+
+    let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+    let access_log_buffer_rwlock = running_state.get_access_log_buffer();
+    let access_log_buffer = access_log_buffer_rwlock.read().await;
+    log_file_paths.extend(access_log_buffer.buffered_logs.values().map(|log| log.log_file_path.clone()));
+
+    log_file_paths.sort();
+    log_file_paths.dedup();
+    log_file_paths
+}
+
+/// Rotate the log file if it is at least max_bytes, keeping the newest keep_files rotated files of it. Returns whether it
+/// was rotated
+pub fn rotate_log_file(log_file_path: &str, max_bytes: u64, keep_files: usize) -> Result<bool, String> {
+    let path = Path::new(log_file_path);
+    let size = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(format!("Failed to read '{}': {}", log_file_path, e)),
+    };
+    if size < max_bytes {
+        return Ok(false);
+    }
+
+    let rotated_path = format!("{}.{}", log_file_path, Utc::now().format(ROTATED_TIME_FORMAT));
+    std::fs::rename(path, &rotated_path).map_err(|e| format!("Failed to rename '{}' to '{}': {}", log_file_path, rotated_path, e))?;
+    info(format!("Rotated log file '{}' of {} bytes to '{}'", log_file_path, size, rotated_path));
+
+    let mut rotated_files = get_rotated_files(path)?;
+    // Named by time, so the newest sort last
+    rotated_files.sort();
+    let remove_count = rotated_files.len().saturating_sub(keep_files);
+    for rotated_file in rotated_files.into_iter().take(remove_count) {
+        std::fs::remove_file(&rotated_file).map_err(|e| format!("Failed to remove rotated log file '{}': {}", rotated_file.display(), e))?;
+    }
+    Ok(true)
+}
+
+// The rotated files of a log, which are named after it with the time they were rotated at appended
+fn get_rotated_files(path: &Path) -> Result<Vec<PathBuf>, String> {
+    let (Some(directory), Some(file_name)) = (path.parent(), path.file_name().and_then(|name| name.to_str())) else {
+        return Ok(Vec::new());
+    };
+    let directory = if directory.as_os_str().is_empty() { Path::new(".") } else { directory };
+    let prefix = format!("{}.", file_name);
+
+    let entries = std::fs::read_dir(directory).map_err(|e| format!("Failed to read directory '{}': {}", directory.display(), e))?;
+    Ok(entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_prefix(&prefix))
+                .is_some_and(|suffix| chrono::NaiveDateTime::parse_from_str(suffix, ROTATED_TIME_FORMAT).is_ok())
+        })
+        .map(|entry| entry.path())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate_log_file() {
+        let directory = std::env::temp_dir().join(format!("gruxi-log-rotation-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let log_file = directory.join("access.log");
+        let log_file_path = log_file.to_string_lossy().to_string();
+        // Older rotated files, and a file that only looks like one
+        std::fs::write(directory.join("access.log.20250101-000000"), "old").unwrap();
+        std::fs::write(directory.join("access.log.20250102-000000"), "old").unwrap();
+        std::fs::write(directory.join("access.log.backup"), "other").unwrap();
+
+        std::fs::write(&log_file, vec![b'x'; 100]).unwrap();
+        assert!(!rotate_log_file(&log_file_path, 1000, 2).unwrap());
+        assert!(log_file.exists());
+
+        assert!(rotate_log_file(&log_file_path, 100, 2).unwrap());
+        assert!(!log_file.exists());
+        let rotated_files = get_rotated_files(&log_file).unwrap();
+        assert_eq!(rotated_files.len(), 2);
+        assert!(!directory.join("access.log.20250101-000000").exists());
+        assert!(directory.join("access.log.backup").exists());
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+}
@@ -1,3 +1,6 @@
+pub mod access_log_analytics;
 pub mod access_logging;
 pub mod buffered_log;
-pub mod syslog;
\ No newline at end of file
+pub mod debug_dump;
+pub mod log_rotation;
+pub mod syslog;
@@ -224,7 +224,7 @@ impl SysLog {
             OperationMode::DEV => LogType::Trace,
             OperationMode::DEBUG => LogType::Debug,
             OperationMode::PRODUCTION => LogType::Info,
-            OperationMode::ULTIMATE => LogType::Error,
+            OperationMode::ULTIMATE | OperationMode::SPEEDTEST => LogType::Error,
         }
     }
 }
@@ -240,7 +240,7 @@ fn init_log() -> SysLog {
         OperationMode::DEV => LogType::Trace,
         OperationMode::DEBUG => LogType::Debug,
         OperationMode::PRODUCTION => LogType::Info,
-        OperationMode::ULTIMATE => LogType::Error,
+        OperationMode::ULTIMATE | OperationMode::SPEEDTEST => LogType::Error,
     };
 
     let sys_log = SysLog::new(log_level, LogType::Info);
@@ -292,3 +292,12 @@ pub fn trace<S: Into<String>>(log: S) {
         }
     }
 }
+
+// Whether debug and trace logs are written, so hot paths can skip formatting logs that would be dropped
+pub fn is_debug_enabled() -> bool {
+    SYS_LOG.read().map(|sys_log| sys_log.debug_enabled).unwrap_or(false)
+}
+
+pub fn is_trace_enabled() -> bool {
+    SYS_LOG.read().map(|sys_log| sys_log.trace_enabled).unwrap_or(false)
+}
@@ -1,11 +1,12 @@
-use gruxi::core::command_line_args::{check_for_command_line_actions, get_command_line_args};
-use gruxi::core::operation_mode::get_operation_mode;
+use gruxi::core::command_line_args::{check_for_command_line_actions, cmd_get_configuration_storage, cmd_get_site_test_path, cmd_get_speedtest_settings, get_command_line_args};
+use gruxi::core::gruxi_server::reload_on_configuration_changes;
+use gruxi::core::operation_mode::{OperationMode, get_operation_mode};
 use gruxi::core::running_state_manager::get_running_state_manager;
 use gruxi::core::triggers::get_trigger_handler;
+use gruxi::database::configuration_storage::initialize_configuration_storage;
 use gruxi::database::database_schema::initialize_database;
 use gruxi::logging::syslog::{error, info};
 use gruxi::{admin_portal::init::initialize_admin_site, core::background_tasks::start_background_tasks};
-use tokio::select;
 
 #[tokio::main]
 async fn main() {
@@ -22,20 +23,30 @@ async fn main() {
     // Start the basics, logging etc.
     start_gruxi_basics();
 
+    // Run the site tests against the current configuration instead of serving, when started with "test"
+    if let Some(test_path) = cmd_get_site_test_path() {
+        let exit_code = gruxi::core::site_test_runner::run_site_tests(&test_path).await;
+        std::process::exit(exit_code);
+    }
+
+    // Benchmark a loopback speedtest configuration instead of serving, when started in SPEEDTEST mode
+    if get_operation_mode() == OperationMode::SPEEDTEST {
+        let exit_code = gruxi::core::speedtest::run_speedtest(&cmd_get_speedtest_settings()).await;
+        std::process::exit(exit_code);
+    }
+
     // Start the running state manager thread, which also listens for configuration changes
     let join_handle = tokio::spawn(async {
         // Start tasks that run in the background
         start_background_tasks().await;
 
         // Start the running state, which are all the configuration dependent parts
-        let running_state_manager = get_running_state_manager().await;
+        get_running_state_manager().await;
 
         // Start the main http server
         gruxi::http::http_server::initialize_server().await;
 
-        let triggers = get_trigger_handler();
-
-        let shutdown_token_trigger_option = triggers.get_trigger("shutdown");
+        let shutdown_token_trigger_option = get_trigger_handler().get_trigger("shutdown");
         let shutdown_token_trigger = match shutdown_token_trigger_option {
             Some(trigger) => trigger,
             None => {
@@ -45,29 +56,7 @@ async fn main() {
         };
         let shutdown_token = shutdown_token_trigger.read().await.clone();
 
-        loop {
-            let configuration_trigger_option = triggers.get_trigger("reload_configuration");
-            let configuration_trigger = match configuration_trigger_option {
-                Some(trigger) => trigger,
-                None => {
-                    error("Failed to get reload_configuration trigger - If this happens, please report a bug");
-                    return;
-                }
-            };
-            let configuration_token = configuration_trigger.read().await.clone();
-
-            select! {
-                _ = configuration_token.cancelled() => {
-                    info("Reloading running state due to configuration change");
-                    running_state_manager.set_new_running_state().await;
-                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                    gruxi::http::http_server::initialize_server().await;
-                }
-                _ = shutdown_token.cancelled() => {
-                    break;
-                }
-            }
-        }
+        reload_on_configuration_changes(shutdown_token).await;
     })
     .await;
     if let Err(e) = join_handle {
@@ -82,6 +71,12 @@ async fn main() {
 fn start_gruxi_basics() {
     // Load commandline args
     get_command_line_args();
+
+    // Set up the configuration storage first, so the command line actions work on it too
+    if let Err(e) = initialize_configuration_storage(&cmd_get_configuration_storage()) {
+        error(format!("Failed to set up the configuration storage: {}", e));
+        std::process::exit(1);
+    }
     check_for_command_line_actions();
 
     // Initialize database tables and migrations
@@ -0,0 +1,352 @@
+// ============================================================================
+// DNS RESOLVER
+// ============================================================================
+//
+// Resolves the host names of upstreams, health checks and webhooks for the
+// outbound HTTP clients. Uses the resolver of the OS, or DNS-over-HTTPS
+// (RFC 8484) or DNS-over-TLS (RFC 7858) with a configured provider, for
+// environments where plaintext DNS is filtered or cannot be trusted. Answers
+// from the provider are cached for their TTL.
+//
+// The addresses of dual-stack hosts are ordered by the configured address
+// preference. The HTTP connector connects to the family of the first address,
+// and races the other family when that takes longer than the happy eyeballs
+// delay (RFC 8305), so an unreachable family never stalls a connection.
+// ============================================================================
+
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper_util::client::legacy::connect::dns::Name;
+use hyper_util::rt::TokioIo;
+use rustls_pki_types::ServerName;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{Instant, timeout};
+use tokio_rustls::TlsConnector;
+use tower_service::Service;
+
+use crate::configuration::dns_resolution::DnsResolution;
+use crate::logging::syslog::trace;
+use crate::tls::tls_config::tls_config;
+
+const DNS_QUERY_TIMEOUT_SECS: u64 = 5;
+const DNS_MESSAGE_CONTENT_TYPE: &str = "application/dns-message";
+
+// Answers are cached for their TTL, within these bounds
+const MIN_CACHE_TTL_SECS: u32 = 5;
+const MAX_CACHE_TTL_SECS: u32 = 3600;
+
+const RECORD_TYPE_A: u16 = 1;
+const RECORD_TYPE_AAAA: u16 = 28;
+const RECORD_CLASS_IN: u16 = 1;
+const RESPONSE_CODE_NAME_ERROR: u8 = 3;
+
+#[derive(Clone)]
+pub struct DnsResolver {
+    settings: Arc<DnsResolution>,
+    cache: Arc<DashMap<String, (Vec<IpAddr>, Instant)>>,
+}
+
+impl DnsResolver {
+    pub fn new(settings: DnsResolution) -> Self {
+        DnsResolver {
+            settings: Arc::new(settings),
+            cache: Arc::new(DashMap::new()),
+        }
+    }
+
+    pub async fn lookup(&self, host: &str) -> Result<Vec<IpAddr>, String> {
+        if self.settings.is_system() {
+            let addresses = tokio::net::lookup_host((host, 0)).await.map_err(|e| format!("Failed to resolve '{}': {}", host, e))?;
+            return Ok(addresses.map(|address| address.ip()).collect());
+        }
+
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        if let Some(cached) = self.cache.get(&host)
+            && cached.1 > Instant::now()
+        {
+            return Ok(cached.0.clone());
+        }
+
+        let (ipv4, ipv6) = tokio::join!(self.query(&host, RECORD_TYPE_A), self.query(&host, RECORD_TYPE_AAAA));
+        let (mut addresses, mut ttl) = (Vec::new(), MAX_CACHE_TTL_SECS);
+        let mut last_error = None;
+        // IPv6 first, as RFC 8305 recommends when there is no other preference
+        for result in [ipv6, ipv4] {
+            match result {
+                Ok((record_addresses, record_ttl)) => {
+                    addresses.extend(record_addresses);
+                    ttl = ttl.min(record_ttl);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        if addresses.is_empty() {
+            return Err(last_error.unwrap_or_else(|| format!("No addresses found for '{}'", host)));
+        }
+        trace(format!("Resolved '{}' with {}: {:?}, valid for {} seconds", host, self.settings.mode, addresses, ttl));
+
+        let expires_at = Instant::now() + Duration::from_secs(ttl.max(MIN_CACHE_TTL_SECS) as u64);
+        self.cache.insert(host, (addresses.clone(), expires_at));
+        Ok(addresses)
+    }
+
+    /// How long the connector waits for the preferred address family before racing the other, or None to try the addresses one after another
+    pub fn get_happy_eyeballs_delay(&self) -> Option<Duration> {
+        match self.settings.happy_eyeballs_delay_ms {
+            0 => None,
+            delay => Some(Duration::from_millis(delay as u64)),
+        }
+    }
+
+    // Put the preferred family first, keeping the order within each family, or drop the other family for the "_only" preferences
+    fn order_addresses(&self, mut addresses: Vec<IpAddr>) -> Vec<IpAddr> {
+        match self.settings.address_preference.as_str() {
+            "ipv6" => addresses.sort_by_key(|address| address.is_ipv4()),
+            "ipv4" => addresses.sort_by_key(|address| address.is_ipv6()),
+            "ipv6_only" => addresses.retain(|address| address.is_ipv6()),
+            "ipv4_only" => addresses.retain(|address| address.is_ipv4()),
+            _ => {}
+        }
+        addresses
+    }
+
+    async fn query(&self, host: &str, record_type: u16) -> Result<(Vec<IpAddr>, u32), String> {
+        let id: u16 = rand::random();
+        let query = build_query(id, host, record_type)?;
+        let response = timeout(Duration::from_secs(DNS_QUERY_TIMEOUT_SECS), self.exchange(query))
+            .await
+            .map_err(|_| format!("DNS query for '{}' timed out after {} seconds", host, DNS_QUERY_TIMEOUT_SECS))??;
+        parse_response(&response, id)
+    }
+
+    // Send the query to the provider over a new TLS connection and return the response
+    async fn exchange(&self, query: Vec<u8>) -> Result<Vec<u8>, String> {
+        let address = self
+            .settings
+            .get_server_address()
+            .ok_or_else(|| format!("Invalid DNS server address '{}'", self.settings.server_address))?;
+        let server_name = ServerName::try_from(self.settings.server_name.clone()).map_err(|e| format!("Invalid DNS server name '{}': {}", self.settings.server_name, e))?;
+
+        let stream = TcpStream::connect(address).await.map_err(|e| format!("Failed to connect to DNS server {}: {}", address, e))?;
+        let tls_stream = TlsConnector::from(Arc::new(tls_config()))
+            .connect(server_name, stream)
+            .await
+            .map_err(|e| format!("TLS handshake with DNS server {} failed: {}", address, e))?;
+
+        if self.settings.mode == "doh" {
+            exchange_over_https(tls_stream, &self.settings, query).await
+        } else {
+            exchange_over_tls(tls_stream, query).await
+        }
+    }
+}
+
+// DNS-over-TLS: messages are sent as over TCP, prefixed with their length
+async fn exchange_over_tls<S>(mut stream: S, query: Vec<u8>) -> Result<Vec<u8>, String>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut message = (query.len() as u16).to_be_bytes().to_vec();
+    message.extend_from_slice(&query);
+    stream.write_all(&message).await.map_err(|e| format!("Failed to send DNS query: {}", e))?;
+
+    let mut length = [0u8; 2];
+    stream.read_exact(&mut length).await.map_err(|e| format!("Failed to read DNS response: {}", e))?;
+    let mut response = vec![0u8; u16::from_be_bytes(length) as usize];
+    stream.read_exact(&mut response).await.map_err(|e| format!("Failed to read DNS response: {}", e))?;
+    Ok(response)
+}
+
+// DNS-over-HTTPS: the query is posted as the body of an HTTP/1.1 request
+async fn exchange_over_https<S>(stream: S, settings: &DnsResolution, query: Vec<u8>) -> Result<Vec<u8>, String>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(|e| format!("HTTP handshake with DNS server failed: {}", e))?;
+    tokio::spawn(connection);
+
+    let request = hyper::Request::post(settings.doh_path.as_str())
+        .header(hyper::header::HOST, settings.server_name.as_str())
+        .header(hyper::header::CONTENT_TYPE, DNS_MESSAGE_CONTENT_TYPE)
+        .header(hyper::header::ACCEPT, DNS_MESSAGE_CONTENT_TYPE)
+        .body(Full::new(Bytes::from(query)))
+        .map_err(|e| format!("Failed to build DNS request: {}", e))?;
+    let response = sender.send_request(request).await.map_err(|e| format!("Failed to send DNS query: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("DNS server answered with HTTP status {}", response.status()));
+    }
+
+    let body = response.into_body().collect().await.map_err(|e| format!("Failed to read DNS response: {}", e))?;
+    Ok(body.to_bytes().to_vec())
+}
+
+// Build a recursive query for the records of the given type of the host name
+fn build_query(id: u16, host: &str, record_type: u16) -> Result<Vec<u8>, String> {
+    let mut query = Vec::with_capacity(32 + host.len());
+    query.extend_from_slice(&id.to_be_bytes());
+    query.extend_from_slice(&[0x01, 0x00]); // Recursion desired
+    query.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]); // One question, no other records
+    for label in host.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("Invalid host name '{}'", host));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&record_type.to_be_bytes());
+    query.extend_from_slice(&RECORD_CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+// Get the addresses in the answer of a response, with the lowest TTL among them. Other records, such as the CNAMEs
+// leading to the addresses, are skipped
+fn parse_response(response: &[u8], id: u16) -> Result<(Vec<IpAddr>, u32), String> {
+    let truncated = || "DNS response is truncated".to_string();
+    let read_u16 = |position: usize| response.get(position..position + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]])).ok_or_else(truncated);
+
+    if response.len() < 12 {
+        return Err(truncated());
+    }
+    if read_u16(0)? != id {
+        return Err("DNS response does not match the query".to_string());
+    }
+    let response_code = response[3] & 0x0f;
+    if response_code == RESPONSE_CODE_NAME_ERROR {
+        return Ok((Vec::new(), MIN_CACHE_TTL_SECS));
+    }
+    if response_code != 0 {
+        return Err(format!("DNS server answered with response code {}", response_code));
+    }
+
+    let question_count = read_u16(4)?;
+    let answer_count = read_u16(6)?;
+    let mut position = 12;
+    for _ in 0..question_count {
+        position = skip_name(response, position)? + 4;
+    }
+
+    let mut addresses = Vec::new();
+    let mut ttl = MAX_CACHE_TTL_SECS;
+    for _ in 0..answer_count {
+        position = skip_name(response, position)?;
+        let header = response.get(position..position + 10).ok_or_else(truncated)?;
+        let record_type = u16::from_be_bytes([header[0], header[1]]);
+        let record_ttl = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let length = u16::from_be_bytes([header[8], header[9]]) as usize;
+        position += 10;
+        let data = response.get(position..position + length).ok_or_else(truncated)?;
+        position += length;
+
+        let address = match (record_type, <[u8; 4]>::try_from(data), <[u8; 16]>::try_from(data)) {
+            (RECORD_TYPE_A, Ok(octets), _) => IpAddr::V4(Ipv4Addr::from(octets)),
+            (RECORD_TYPE_AAAA, _, Ok(octets)) => IpAddr::V6(Ipv6Addr::from(octets)),
+            _ => continue,
+        };
+        addresses.push(address);
+        ttl = ttl.min(record_ttl);
+    }
+    Ok((addresses, ttl))
+}
+
+// Skip a name in the message, returning the position after it. Names can end with a pointer to the rest of the name elsewhere
+fn skip_name(message: &[u8], mut position: usize) -> Result<usize, String> {
+    loop {
+        let length = *message.get(position).ok_or_else(|| "DNS response is truncated".to_string())?;
+        match length {
+            0 => return Ok(position + 1),
+            length if length & 0xc0 == 0xc0 => return Ok(position + 2),
+            length => position += 1 + length as usize,
+        }
+    }
+}
+
+// Resolver for the HTTP connector of the outbound clients. IP addresses in URLs are used as is, without a lookup
+impl Service<Name> for DnsResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addresses = resolver.order_addresses(resolver.lookup(name.as_str()).await.map_err(io::Error::other)?);
+            if addresses.is_empty() {
+                return Err(io::Error::other(format!(
+                    "No addresses of '{}' are allowed by the address preference '{}'",
+                    name.as_str(),
+                    resolver.settings.address_preference
+                )));
+            }
+            Ok(addresses.into_iter().map(|ip| SocketAddr::new(ip, 0)).collect::<Vec<_>>().into_iter())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dns_query_and_response() {
+        let query = build_query(0x1234, "api.example.com", RECORD_TYPE_A).unwrap();
+        assert_eq!(&query[..2], &[0x12, 0x34]);
+        assert_eq!(&query[12..29], b"\x03api\x07example\x03com\x00");
+        assert!(build_query(1, "bad..name", RECORD_TYPE_A).is_err());
+
+        // The answer is a CNAME to another name, then its address, with the names compressed as pointers to the question
+        let mut response = query.clone();
+        response[2] = 0x81;
+        response[3] = 0x80;
+        response[7] = 2;
+        response.extend_from_slice(&[0xc0, 12, 0, 5, 0, 1, 0, 0, 0x0e, 0x10, 0, 6, 3, b'w', b'w', b'w', 0xc0, 16]);
+        response.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 10, 0, 0, 7]);
+        assert_eq!(parse_response(&response, 0x1234).unwrap(), (vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 7))], 60));
+        assert!(parse_response(&response, 0x4321).is_err());
+
+        // A name that does not exist has no addresses
+        response[3] = 0x83;
+        assert_eq!(parse_response(&response, 0x1234).unwrap().0, Vec::<IpAddr>::new());
+        response.truncate(40);
+        response[3] = 0x80;
+        assert!(parse_response(&response, 0x1234).is_err());
+    }
+
+    #[test]
+    fn test_order_addresses_by_preference() {
+        let ipv4 = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let ipv6 = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        let other_ipv4 = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+        let addresses = vec![ipv4, ipv6, other_ipv4];
+
+        let resolver_with = |preference: &str| {
+            let mut settings = DnsResolution::new();
+            settings.address_preference = preference.to_string();
+            DnsResolver::new(settings)
+        };
+        assert_eq!(resolver_with("auto").order_addresses(addresses.clone()), addresses);
+        assert_eq!(resolver_with("ipv6").order_addresses(addresses.clone()), vec![ipv6, ipv4, other_ipv4]);
+        assert_eq!(resolver_with("ipv4").order_addresses(vec![ipv6, ipv4]), vec![ipv4, ipv6]);
+        assert_eq!(resolver_with("ipv6_only").order_addresses(addresses.clone()), vec![ipv6]);
+        assert_eq!(resolver_with("ipv4_only").order_addresses(addresses.clone()), vec![ipv4, other_ipv4]);
+        assert_eq!(resolver_with("ipv4_only").order_addresses(vec![ipv6]), Vec::<IpAddr>::new());
+        assert_eq!(resolver_with("auto").get_happy_eyeballs_delay(), Some(Duration::from_millis(250)));
+    }
+}
@@ -0,0 +1,287 @@
+// ============================================================================
+// FTPS CLIENT
+// ============================================================================
+//
+// Minimal FTP client with explicit TLS (RFC 4217), for pulling the content of
+// a directory tree from legacy shared hosting. Both the control connection and
+// the passive data connections are encrypted, and directories are listed with
+// MLSD (RFC 3659), so the listings can be parsed reliably.
+// ============================================================================
+
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls_pki_types::ServerName;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+
+use crate::http::request_handlers::processors::proxy_helpers::no_verifier::NoVerifier;
+use crate::logging::syslog::trace;
+use crate::tls::tls_config::tls_config;
+
+const FTP_COMMAND_TIMEOUT_SECS: u64 = 60;
+
+#[derive(Debug, PartialEq)]
+pub enum FtpEntryKind {
+    File,
+    Directory,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FtpEntry {
+    pub name: String,
+    pub kind: FtpEntryKind,
+}
+
+pub struct FtpsClient {
+    control: BufReader<TlsStream<TcpStream>>,
+    connector: TlsConnector,
+    server_name: ServerName<'static>,
+    server_ip: IpAddr,
+}
+
+impl FtpsClient {
+    // Connect and upgrade the control connection to TLS, before anything else is sent
+    pub async fn connect(host: &str, port: u16, verify_certificates: bool) -> Result<Self, String> {
+        let tcp = timeout(Duration::from_secs(FTP_COMMAND_TIMEOUT_SECS), TcpStream::connect((host, port)))
+            .await
+            .map_err(|_| format!("Connecting to {}:{} timed out", host, port))?
+            .map_err(|e| format!("Failed to connect to {}:{}: {}", host, port, e))?;
+        // Data connections go to the same server, whatever address it announces in its passive replies
+        let server_ip = tcp.peer_addr().map_err(|e| e.to_string())?.ip();
+
+        let mut plain = BufReader::new(tcp);
+        expect_reply(&mut plain, 220).await?;
+        send_command(&mut plain, "AUTH TLS").await?;
+        expect_reply(&mut plain, 234).await?;
+
+        let mut config = tls_config();
+        if !verify_certificates {
+            config.dangerous().set_certificate_verifier(Arc::new(NoVerifier));
+        }
+        // The same connector is used for the data connections, so their TLS sessions are resumed, as many servers require
+        let connector = TlsConnector::from(Arc::new(config));
+        let server_name = ServerName::try_from(host.to_string()).map_err(|e| format!("Invalid server name '{}': {}", host, e))?;
+        let tls = timeout(Duration::from_secs(FTP_COMMAND_TIMEOUT_SECS), connector.connect(server_name.clone(), plain.into_inner()))
+            .await
+            .map_err(|_| "TLS handshake timed out".to_string())?
+            .map_err(|e| format!("TLS handshake failed: {}", e))?;
+
+        Ok(FtpsClient {
+            control: BufReader::new(tls),
+            connector,
+            server_name,
+            server_ip,
+        })
+    }
+
+    // Log in and set up binary transfers over encrypted data connections
+    pub async fn login(&mut self, username: &str, password: &str) -> Result<(), String> {
+        self.command(&format!("USER {}", username)).await?;
+        let (code, message) = read_reply(&mut self.control).await?;
+        if code == 331 {
+            self.command(&format!("PASS {}", password)).await?;
+            let (code, message) = read_reply(&mut self.control).await?;
+            if code != 230 {
+                return Err(format!("Login failed: {} {}", code, message));
+            }
+        } else if code != 230 {
+            return Err(format!("Login failed: {} {}", code, message));
+        }
+
+        for (command, expected_code) in [("PBSZ 0", 200), ("PROT P", 200), ("TYPE I", 200)] {
+            self.command(command).await?;
+            expect_reply(&mut self.control, expected_code).await?;
+        }
+        Ok(())
+    }
+
+    // The files and directories in a directory
+    pub async fn list_directory(&mut self, path: &str) -> Result<Vec<FtpEntry>, String> {
+        let mut data = self.open_data_connection(&format!("MLSD {}", path)).await?;
+        let mut listing = String::new();
+        data.read_to_string(&mut listing).await.map_err(|e| format!("Failed to read the listing of {}: {}", path, e))?;
+        let _ = data.shutdown().await;
+        expect_reply(&mut self.control, 226).await?;
+
+        Ok(listing.lines().filter_map(parse_mlsd_line).collect())
+    }
+
+    // Download a file to the local path
+    pub async fn download_file(&mut self, path: &str, local_path: &Path) -> Result<u64, String> {
+        let mut data = self.open_data_connection(&format!("RETR {}", path)).await?;
+        let mut file = tokio::fs::File::create(local_path).await.map_err(|e| format!("Failed to create {}: {}", local_path.display(), e))?;
+        let length = tokio::io::copy(&mut data, &mut file).await.map_err(|e| format!("Failed to download {}: {}", path, e))?;
+        file.flush().await.map_err(|e| format!("Failed to write {}: {}", local_path.display(), e))?;
+        let _ = data.shutdown().await;
+        expect_reply(&mut self.control, 226).await?;
+        Ok(length)
+    }
+
+    pub async fn quit(mut self) {
+        if self.command("QUIT").await.is_ok() {
+            let _ = read_reply(&mut self.control).await;
+        }
+    }
+
+    async fn command(&mut self, command: &str) -> Result<(), String> {
+        send_command(&mut self.control, command).await
+    }
+
+    // Open a passive data connection for the command, and encrypt it once the server accepted the command
+    async fn open_data_connection(&mut self, command: &str) -> Result<TlsStream<TcpStream>, String> {
+        self.command("EPSV").await?;
+        let (code, message) = read_reply(&mut self.control).await?;
+        let port = if code == 229 {
+            parse_epsv_port(&message)
+        } else {
+            self.command("PASV").await?;
+            let (code, message) = read_reply(&mut self.control).await?;
+            if code != 227 {
+                return Err(format!("Server refused passive mode: {} {}", code, message));
+            }
+            parse_pasv_port(&message)
+        }
+        .ok_or_else(|| format!("Could not parse the passive mode reply '{}'", message))?;
+
+        let tcp = timeout(Duration::from_secs(FTP_COMMAND_TIMEOUT_SECS), TcpStream::connect((self.server_ip, port)))
+            .await
+            .map_err(|_| "Opening the data connection timed out".to_string())?
+            .map_err(|e| format!("Failed to open the data connection: {}", e))?;
+
+        self.command(command).await?;
+        let (code, message) = read_reply(&mut self.control).await?;
+        if code != 125 && code != 150 {
+            return Err(format!("'{}' failed: {} {}", command, code, message));
+        }
+
+        timeout(Duration::from_secs(FTP_COMMAND_TIMEOUT_SECS), self.connector.connect(self.server_name.clone(), tcp))
+            .await
+            .map_err(|_| "TLS handshake on the data connection timed out".to_string())?
+            .map_err(|e| format!("TLS handshake on the data connection failed: {}", e))
+    }
+}
+
+async fn send_command<S: AsyncRead + AsyncWrite + Unpin>(stream: &mut BufReader<S>, command: &str) -> Result<(), String> {
+    // Never log the password
+    trace(format!("FTPS command: {}", if command.starts_with("PASS ") { "PASS ***" } else { command }));
+    stream
+        .get_mut()
+        .write_all(format!("{}\r\n", command).as_bytes())
+        .await
+        .map_err(|e| format!("Failed to send command: {}", e))?;
+    stream.get_mut().flush().await.map_err(|e| format!("Failed to send command: {}", e))
+}
+
+// Read a reply, which can span several lines, such as "211-Features:" up to "211 End"
+async fn read_reply<S: AsyncRead + Unpin>(stream: &mut BufReader<S>) -> Result<(u16, String), String> {
+    let first_line = read_line(stream).await?;
+    let code = first_line
+        .get(..3)
+        .and_then(|code| code.parse::<u16>().ok())
+        .ok_or_else(|| format!("Invalid reply from the server: '{}'", first_line))?;
+
+    if first_line.as_bytes().get(3) == Some(&b'-') {
+        let last_line_prefix = format!("{} ", code);
+        loop {
+            let line = read_line(stream).await?;
+            if line.starts_with(&last_line_prefix) || line == code.to_string() {
+                break;
+            }
+        }
+    }
+    Ok((code, first_line.get(4..).unwrap_or("").to_string()))
+}
+
+async fn read_line<S: AsyncRead + Unpin>(stream: &mut BufReader<S>) -> Result<String, String> {
+    let mut line = String::new();
+    let read = timeout(Duration::from_secs(FTP_COMMAND_TIMEOUT_SECS), stream.read_line(&mut line))
+        .await
+        .map_err(|_| "Timed out waiting for the server".to_string())?
+        .map_err(|e| format!("Failed to read from the server: {}", e))?;
+    if read == 0 {
+        return Err("The server closed the connection".to_string());
+    }
+    Ok(line.trim_end().to_string())
+}
+
+async fn expect_reply<S: AsyncRead + Unpin>(stream: &mut BufReader<S>, expected_code: u16) -> Result<(), String> {
+    let (code, message) = read_reply(stream).await?;
+    if code != expected_code {
+        return Err(format!("Unexpected reply from the server, expected {}: {} {}", expected_code, code, message));
+    }
+    Ok(())
+}
+
+// "Entering Extended Passive Mode (|||6446|)"
+fn parse_epsv_port(message: &str) -> Option<u16> {
+    let start = message.find("(|||")? + 4;
+    let end = message[start..].find('|')? + start;
+    message[start..end].parse().ok()
+}
+
+// "Entering Passive Mode (192,168,1,2,25,46)", where the port is 25 * 256 + 46
+fn parse_pasv_port(message: &str) -> Option<u16> {
+    let start = message.find('(')? + 1;
+    let end = message[start..].find(')')? + start;
+    let numbers: Vec<u16> = message[start..end].split(',').filter_map(|number| number.trim().parse().ok()).collect();
+    if numbers.len() != 6 || numbers[4] > 255 || numbers[5] > 255 {
+        return None;
+    }
+    Some(numbers[4] * 256 + numbers[5])
+}
+
+// "type=file;size=1830;modify=20240101120000; index.html". The entries for the directory itself and its parent are skipped
+fn parse_mlsd_line(line: &str) -> Option<FtpEntry> {
+    let (facts, name) = line.split_once(' ')?;
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return None;
+    }
+    let kind = facts.split(';').find_map(|fact| {
+        let (key, value) = fact.split_once('=')?;
+        if !key.eq_ignore_ascii_case("type") {
+            return None;
+        }
+        match value.to_lowercase().as_str() {
+            "file" => Some(FtpEntryKind::File),
+            "dir" => Some(FtpEntryKind::Directory),
+            _ => None,
+        }
+    })?;
+    Some(FtpEntry { name: name.to_string(), kind })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ftp_reply_parsing() {
+        assert_eq!(parse_epsv_port("Entering Extended Passive Mode (|||6446|)"), Some(6446));
+        assert_eq!(parse_pasv_port("Entering Passive Mode (192,168,1,2,25,46)."), Some(25 * 256 + 46));
+        assert_eq!(parse_pasv_port("Entering Passive Mode (192,168,1,2,25)"), None);
+
+        assert_eq!(
+            parse_mlsd_line("type=file;size=1830;modify=20240101120000; index.html"),
+            Some(FtpEntry {
+                name: "index.html".to_string(),
+                kind: FtpEntryKind::File
+            })
+        );
+        assert_eq!(
+            parse_mlsd_line("Type=dir;Modify=20240101120000; my images"),
+            Some(FtpEntry {
+                name: "my images".to_string(),
+                kind: FtpEntryKind::Directory
+            })
+        );
+        assert_eq!(parse_mlsd_line("type=cdir;modify=20240101120000; ."), None);
+        assert_eq!(parse_mlsd_line("type=OS.unix=symlink; link"), None);
+        assert_eq!(parse_mlsd_line("type=file; ../escape"), None);
+    }
+}
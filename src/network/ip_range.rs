@@ -0,0 +1,67 @@
+use std::net::IpAddr;
+
+// An IPv4 or IPv6 network in CIDR notation, such as "66.249.64.0/19", or a single address
+#[derive(Clone, Debug, PartialEq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix_length: u8,
+}
+
+impl IpRange {
+    pub fn parse(range: &str) -> Result<Self, String> {
+        let range = range.trim();
+        let (address, prefix_length) = match range.split_once('/') {
+            Some((address, prefix_length)) => (address, Some(prefix_length)),
+            None => (range, None),
+        };
+        let network: IpAddr = address.parse().map_err(|_| format!("'{}' is not a valid IP address or CIDR range", range))?;
+        let max_prefix_length = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_length = match prefix_length {
+            Some(prefix_length) => prefix_length
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix_length| *prefix_length <= max_prefix_length)
+                .ok_or_else(|| format!("'{}' has an invalid prefix length, it must be 0 to {}", range, max_prefix_length))?,
+            None => max_prefix_length,
+        };
+        Ok(IpRange { network, prefix_length })
+    }
+
+    pub fn contains(&self, address: &IpAddr) -> bool {
+        // IPv4 clients on dual-stack sockets show up as IPv4-mapped IPv6 addresses
+        match (self.network, address.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_length as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_length as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_range_contains() {
+        let range = IpRange::parse("66.249.64.0/19").unwrap();
+        assert!(range.contains(&"66.249.79.1".parse().unwrap()));
+        assert!(range.contains(&"::ffff:66.249.64.10".parse().unwrap()));
+        assert!(!range.contains(&"66.249.96.1".parse().unwrap()));
+
+        let range = IpRange::parse("2001:4860:4801::/48").unwrap();
+        assert!(range.contains(&"2001:4860:4801:10::1".parse().unwrap()));
+        assert!(!range.contains(&"2001:4860:4802::1".parse().unwrap()));
+        assert!(!range.contains(&"66.249.79.1".parse().unwrap()));
+
+        assert!(IpRange::parse("0.0.0.0/0").unwrap().contains(&"10.1.2.3".parse().unwrap()));
+        assert!(IpRange::parse("10.0.0.1").unwrap().contains(&"10.0.0.1".parse().unwrap()));
+        assert!(IpRange::parse("10.0.0.0/33").is_err());
+        assert!(IpRange::parse("googlebot").is_err());
+    }
+}
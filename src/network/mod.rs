@@ -1 +1,6 @@
-pub mod port_manager;
\ No newline at end of file
+pub mod dns_resolver;
+pub mod ftps_client;
+pub mod ip_range;
+pub mod port_manager;
+pub mod postgres_client;
+pub mod smtp_client;
@@ -0,0 +1,257 @@
+// ============================================================================
+// SMTP CLIENT
+// ============================================================================
+//
+// Minimal SMTP client (RFC 5321) for sending plain text notifications, such as
+// admin alerts. The connection is secured with STARTTLS (RFC 3207) or TLS from
+// the start, and authenticated with AUTH PLAIN or AUTH LOGIN (RFC 4954),
+// whichever the server offers. Each message is sent on a connection of its
+// own, as notifications are few.
+// ============================================================================
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use rustls_pki_types::ServerName;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+
+use crate::http::request_handlers::processors::proxy_helpers::no_verifier::NoVerifier;
+use crate::logging::syslog::trace;
+use crate::tls::tls_config::tls_config;
+
+const SMTP_COMMAND_TIMEOUT_SECS: u64 = 30;
+
+// Name this client introduces itself with in EHLO
+const SMTP_CLIENT_NAME: &str = "gruxi";
+
+pub struct SmtpServer<'a> {
+    pub host: &'a str,
+    pub port: u16,
+    pub tls_mode: &'a str, // "starttls", "tls" or "none"
+    pub verify_certificates: bool,
+    pub username: &'a str, // Empty to send without authentication
+    pub password: &'a str,
+}
+
+pub struct SmtpMessage<'a> {
+    pub from: &'a str,
+    pub to: &'a [String],
+    pub subject: &'a str,
+    pub body: &'a str,
+}
+
+trait SmtpStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> SmtpStream for T {}
+
+// Send a message through the server
+pub async fn send_mail(server: &SmtpServer<'_>, message: &SmtpMessage<'_>) -> Result<(), String> {
+    let tcp = timeout(Duration::from_secs(SMTP_COMMAND_TIMEOUT_SECS), TcpStream::connect((server.host, server.port)))
+        .await
+        .map_err(|_| format!("Connecting to {}:{} timed out", server.host, server.port))?
+        .map_err(|e| format!("Failed to connect to {}:{}: {}", server.host, server.port, e))?;
+
+    let stream: Box<dyn SmtpStream> = if server.tls_mode == "tls" { start_tls(server, tcp).await? } else { Box::new(tcp) };
+    let mut connection = BufReader::new(stream);
+    expect_reply(&mut connection, 220).await?;
+    let mut extensions = ehlo(&mut connection).await?;
+
+    if server.tls_mode == "starttls" {
+        if !extensions.iter().any(|extension| extension.eq_ignore_ascii_case("STARTTLS")) {
+            return Err("The server does not support STARTTLS".to_string());
+        }
+        send_command(&mut connection, "STARTTLS").await?;
+        expect_reply(&mut connection, 220).await?;
+        connection = BufReader::new(start_tls(server, connection.into_inner()).await?);
+        // What the server offers can change once the connection is secure
+        extensions = ehlo(&mut connection).await?;
+    }
+
+    if !server.username.is_empty() {
+        authenticate(&mut connection, &extensions, server.username, server.password).await?;
+    }
+
+    send_command(&mut connection, &format!("MAIL FROM:<{}>", message.from)).await?;
+    expect_reply(&mut connection, 250).await?;
+    for recipient in message.to {
+        send_command(&mut connection, &format!("RCPT TO:<{}>", recipient)).await?;
+        let (code, reply) = read_reply(&mut connection).await?;
+        if code != 250 && code != 251 {
+            return Err(format!("The server refused recipient {}: {} {}", recipient, code, reply));
+        }
+    }
+    send_command(&mut connection, "DATA").await?;
+    expect_reply(&mut connection, 354).await?;
+    connection
+        .get_mut()
+        .write_all(format_message(message).as_bytes())
+        .await
+        .map_err(|e| format!("Failed to send the message: {}", e))?;
+    connection.get_mut().flush().await.map_err(|e| format!("Failed to send the message: {}", e))?;
+    expect_reply(&mut connection, 250).await?;
+
+    if send_command(&mut connection, "QUIT").await.is_ok() {
+        let _ = read_reply(&mut connection).await;
+    }
+    Ok(())
+}
+
+async fn start_tls<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(server: &SmtpServer<'_>, stream: S) -> Result<Box<dyn SmtpStream>, String> {
+    let mut config = tls_config();
+    if !server.verify_certificates {
+        config.dangerous().set_certificate_verifier(Arc::new(NoVerifier));
+    }
+    let connector = TlsConnector::from(Arc::new(config));
+    let server_name = ServerName::try_from(server.host.to_string()).map_err(|e| format!("Invalid server name '{}': {}", server.host, e))?;
+    let tls = timeout(Duration::from_secs(SMTP_COMMAND_TIMEOUT_SECS), connector.connect(server_name, stream))
+        .await
+        .map_err(|_| "TLS handshake timed out".to_string())?
+        .map_err(|e| format!("TLS handshake failed: {}", e))?;
+    Ok(Box::new(tls))
+}
+
+// Introduce the client, returning the extensions the server offers, such as "STARTTLS" and "AUTH PLAIN LOGIN"
+async fn ehlo<S: AsyncRead + AsyncWrite + Unpin>(connection: &mut BufReader<S>) -> Result<Vec<String>, String> {
+    send_command(connection, &format!("EHLO {}", SMTP_CLIENT_NAME)).await?;
+    let (code, lines) = read_reply_lines(connection).await?;
+    if code != 250 {
+        return Err(format!("The server refused EHLO: {} {}", code, lines.join(" ")));
+    }
+    Ok(lines.into_iter().skip(1).collect())
+}
+
+async fn authenticate<S: AsyncRead + AsyncWrite + Unpin>(connection: &mut BufReader<S>, extensions: &[String], username: &str, password: &str) -> Result<(), String> {
+    let mechanisms: Vec<String> = extensions
+        .iter()
+        .filter_map(|extension| extension.strip_prefix("AUTH ").or_else(|| extension.strip_prefix("AUTH=")))
+        .flat_map(|mechanisms| mechanisms.split_whitespace().map(|mechanism| mechanism.to_uppercase()))
+        .collect();
+
+    if mechanisms.iter().any(|mechanism| mechanism == "PLAIN") {
+        let credentials = BASE64.encode(format!("\0{}\0{}", username, password));
+        send_secret_command(connection, &format!("AUTH PLAIN {}", credentials), "AUTH PLAIN ***").await?;
+    } else if mechanisms.iter().any(|mechanism| mechanism == "LOGIN") {
+        send_command(connection, "AUTH LOGIN").await?;
+        expect_reply(connection, 334).await?;
+        send_secret_command(connection, &BASE64.encode(username), "***").await?;
+        expect_reply(connection, 334).await?;
+        send_secret_command(connection, &BASE64.encode(password), "***").await?;
+    } else {
+        return Err("The server offers no supported authentication mechanism (PLAIN or LOGIN)".to_string());
+    }
+
+    let (code, reply) = read_reply(connection).await?;
+    if code != 235 {
+        return Err(format!("Authentication failed: {} {}", code, reply));
+    }
+    Ok(())
+}
+
+// The message as sent after DATA: headers, the body with lines starting with a dot escaped, and the terminating dot
+fn format_message(message: &SmtpMessage<'_>) -> String {
+    // Header values are kept on one line, so they cannot add headers of their own
+    let subject: String = message.subject.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+    // Non-ASCII subjects are encoded (RFC 2047), as headers are ASCII
+    let subject = if subject.is_ascii() { subject } else { format!("=?utf-8?B?{}?=", BASE64.encode(subject)) };
+    let domain = message.from.split_once('@').map(|(_, domain)| domain).unwrap_or("localhost");
+
+    let mut data = String::new();
+    data.push_str(&format!("From: <{}>\r\n", message.from));
+    data.push_str(&format!("To: {}\r\n", message.to.iter().map(|recipient| format!("<{}>", recipient)).collect::<Vec<_>>().join(", ")));
+    data.push_str(&format!("Subject: {}\r\n", subject));
+    data.push_str(&format!("Date: {}\r\n", chrono::Utc::now().to_rfc2822()));
+    data.push_str(&format!("Message-ID: <{}@{}>\r\n", uuid::Uuid::new_v4(), domain));
+    data.push_str("MIME-Version: 1.0\r\n");
+    data.push_str("Content-Type: text/plain; charset=utf-8\r\n");
+    data.push_str("Content-Transfer-Encoding: 8bit\r\n");
+    data.push_str("\r\n");
+    for line in message.body.lines() {
+        if line.starts_with('.') {
+            data.push('.');
+        }
+        data.push_str(line);
+        data.push_str("\r\n");
+    }
+    data.push_str(".\r\n");
+    data
+}
+
+async fn send_command<S: AsyncRead + AsyncWrite + Unpin>(connection: &mut BufReader<S>, command: &str) -> Result<(), String> {
+    send_secret_command(connection, command, command).await
+}
+
+// Send a command, logging it as shown, so credentials are never logged
+async fn send_secret_command<S: AsyncRead + AsyncWrite + Unpin>(connection: &mut BufReader<S>, command: &str, shown_command: &str) -> Result<(), String> {
+    trace(format!("SMTP command: {}", shown_command));
+    connection
+        .get_mut()
+        .write_all(format!("{}\r\n", command).as_bytes())
+        .await
+        .map_err(|e| format!("Failed to send command: {}", e))?;
+    connection.get_mut().flush().await.map_err(|e| format!("Failed to send command: {}", e))
+}
+
+// Read a reply, which can span several lines, such as "250-STARTTLS" up to "250 SIZE 35882577", returning the text of
+// each line
+async fn read_reply_lines<S: AsyncRead + Unpin>(connection: &mut BufReader<S>) -> Result<(u16, Vec<String>), String> {
+    let mut lines = Vec::new();
+    loop {
+        let mut line = String::new();
+        let read = timeout(Duration::from_secs(SMTP_COMMAND_TIMEOUT_SECS), connection.read_line(&mut line))
+            .await
+            .map_err(|_| "Timed out waiting for the server".to_string())?
+            .map_err(|e| format!("Failed to read from the server: {}", e))?;
+        if read == 0 {
+            return Err("The server closed the connection".to_string());
+        }
+        let line = line.trim_end();
+        let code = line
+            .get(..3)
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or_else(|| format!("Invalid reply from the server: '{}'", line))?;
+        lines.push(line.get(4..).unwrap_or("").to_string());
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok((code, lines));
+        }
+    }
+}
+
+async fn read_reply<S: AsyncRead + Unpin>(connection: &mut BufReader<S>) -> Result<(u16, String), String> {
+    let (code, lines) = read_reply_lines(connection).await?;
+    Ok((code, lines.join(" ")))
+}
+
+async fn expect_reply<S: AsyncRead + Unpin>(connection: &mut BufReader<S>, expected_code: u16) -> Result<(), String> {
+    let (code, reply) = read_reply(connection).await?;
+    if code != expected_code {
+        return Err(format!("Unexpected reply from the server, expected {}: {} {}", expected_code, code, reply));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_message() {
+        let recipients = vec!["ops@example.com".to_string(), "oncall@example.com".to_string()];
+        let message = SmtpMessage {
+            from: "gruxi@example.com",
+            to: &recipients,
+            subject: "Alert\r\nBcc: evil@example.com",
+            body: "First line\n.hidden line\nLast line",
+        };
+        let data = format_message(&message);
+
+        assert!(data.contains("To: <ops@example.com>, <oncall@example.com>\r\n"));
+        assert!(data.contains("Subject: Alert  Bcc: evil@example.com\r\n"));
+        assert!(!data.contains("\r\nBcc:"));
+        assert!(data.contains("\r\n\r\nFirst line\r\n..hidden line\r\nLast line\r\n.\r\n"));
+        assert!(data.ends_with("\r\n.\r\n"));
+    }
+}
@@ -0,0 +1,323 @@
+// ============================================================================
+// ACME ACCOUNT
+// ============================================================================
+//
+// The ACME account certificates are ordered with, and the CA it is registered
+// at. Besides Let's Encrypt, the provider can be ZeroSSL, Buypass, Google
+// Trust Services or any other CA by its directory URL.
+//
+// Some CAs only accept accounts bound to an account at the CA itself, with an
+// External Account Binding (EAB): a key id and an HMAC key from the dashboard
+// of the CA. rustls-acme registers accounts without one, so when EAB is
+// configured and no account is cached yet, we register the account here, with
+// the binding, and store its key in the ACME cache. rustls-acme then finds the
+// key, and registering it again only returns the existing account.
+//
+// The account key can be exported and imported, to move an account between
+// installations, or to keep using an account registered elsewhere.
+// ============================================================================
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::prelude::*;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use ring::digest;
+use ring::hmac;
+use ring::rand::SystemRandom;
+use ring::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair};
+use rustls_acme::AccountCache;
+use rustls_acme::acme::{Account, Directory, LETS_ENCRYPT_PRODUCTION_DIRECTORY, LETS_ENCRYPT_STAGING_DIRECTORY};
+
+use crate::configuration::tls_settings::TlsSettings;
+use crate::core::running_state_manager::get_running_state_manager;
+use crate::logging::syslog::info;
+use crate::tls::acme_cache::AcmeCache;
+use crate::tls::certificate_export::encode_pem;
+use crate::tls::tls_config::tls_config;
+
+/// The CAs certificates can be ordered from, where "custom" uses the configured directory URL
+pub const ACME_PROVIDERS: [&str; 5] = ["letsencrypt", "zerossl", "buypass", "google", "custom"];
+
+// Timeout for each request to the CA
+const ACME_REQUEST_TIMEOUT_SECS: u64 = 30;
+
+/// Whether the CA of the provider only accepts accounts with an External Account Binding
+pub fn provider_requires_eab(provider: &str) -> bool {
+    matches!(provider, "zerossl" | "google")
+}
+
+/// The directory URL of the configured CA
+pub fn get_acme_directory_url(tls_settings: &TlsSettings) -> String {
+    get_provider_directory_url(&tls_settings.acme_provider, &tls_settings.acme_directory_url, tls_settings.use_staging_server)
+}
+
+/// The directory URL of a provider, or the given URL for "custom". ZeroSSL has no staging environment, so it is always production
+pub fn get_provider_directory_url(provider: &str, directory_url: &str, staging: bool) -> String {
+    match provider {
+        "zerossl" => "https://acme.zerossl.com/v2/DV90",
+        "buypass" if staging => "https://api.test4.buypass.no/acme/directory",
+        "buypass" => "https://api.buypass.com/acme/directory",
+        "google" if staging => "https://dv.acme-v02.test-api.pki.goog/directory",
+        "google" => "https://dv.acme-v02.api.pki.goog/directory",
+        "custom" => directory_url,
+        _ if staging => LETS_ENCRYPT_STAGING_DIRECTORY,
+        _ => LETS_ENCRYPT_PRODUCTION_DIRECTORY,
+    }
+    .to_string()
+}
+
+/// The provider with the directory URL, or "custom" when it is none of the known CAs
+pub fn get_provider_name(directory_url: &str) -> String {
+    ACME_PROVIDERS
+        .iter()
+        .filter(|provider| **provider != "custom")
+        .find(|provider| [false, true].iter().any(|staging| get_provider_directory_url(provider, "", *staging) == directory_url))
+        .unwrap_or(&"custom")
+        .to_string()
+}
+
+/// The contact of the account, as rustls-acme caches the account by it
+pub fn get_acme_account_contact(tls_settings: &TlsSettings) -> Vec<String> {
+    vec![format!("mailto:{}", tls_settings.account_email.trim())]
+}
+
+/// Register the account with its External Account Binding, when one is configured and the cache has no account for the
+/// CA yet. Without EAB, rustls-acme registers the account itself on the first order
+pub async fn ensure_acme_account(tls_settings: &TlsSettings, cache: &AcmeCache) -> Result<(), String> {
+    if tls_settings.acme_eab_key_id.is_empty() {
+        return Ok(());
+    }
+
+    let directory_url = get_acme_directory_url(tls_settings);
+    let contact = get_acme_account_contact(tls_settings);
+    if cache.load_account(&contact, &directory_url).await?.is_some() {
+        return Ok(());
+    }
+
+    let hmac_key = decode_eab_hmac_key(&tls_settings.acme_eab_hmac_key)?;
+    let pkcs8 = Account::generate_key_pair();
+    let key_pair = parse_account_key(&pkcs8)?;
+
+    let client_config = Arc::new(tls_config());
+    let directory = Directory::discover(&client_config, &directory_url)
+        .await
+        .map_err(|e| format!("Failed to fetch the ACME directory '{}': {}", directory_url, e))?;
+    let nonce = directory.nonce(&client_config).await.map_err(|e| format!("Failed to get a nonce from the ACME server: {}", e))?;
+
+    let external_account_binding = get_external_account_binding(&tls_settings.acme_eab_key_id, &hmac_key, &get_jwk(&key_pair), &directory.new_account);
+    let payload = serde_json::json!({
+        "termsOfServiceAgreed": true,
+        "contact": contact,
+        "externalAccountBinding": external_account_binding,
+    });
+    let body = sign_request(&key_pair, None, &nonce, &directory.new_account, &payload.to_string())?;
+
+    let response = post_jose(&directory.new_account, body).await.map_err(|e| format!("Failed to register the ACME account: {}", e))?;
+    if !response.status.is_success() {
+        return Err(format!("ACME server refused the account with status {}: {}", response.status, response.body));
+    }
+
+    cache.store_account(&contact, &directory_url, &pkcs8).await?;
+    info(format!("Registered ACME account for {} at {} with external account binding", tls_settings.account_email, directory_url));
+    Ok(())
+}
+
+/// The account key of the configured CA as PEM, or None when no account is registered yet
+pub async fn export_acme_account_key(tls_settings: &TlsSettings, cache: &AcmeCache) -> Result<Option<String>, String> {
+    let account = cache.load_account(&get_acme_account_contact(tls_settings), &get_acme_directory_url(tls_settings)).await?;
+    Ok(account.map(|pkcs8| encode_pem("PRIVATE KEY", &pkcs8)))
+}
+
+/// Store an account key, as PKCS#8 PEM, for the configured CA. The next order uses the account of the key
+pub async fn import_acme_account_key(tls_settings: &TlsSettings, cache: &AcmeCache, pem: &str) -> Result<(), String> {
+    let mut reader = std::io::Cursor::new(pem.as_bytes());
+    let pkcs8 = match rustls_pemfile::read_one(&mut reader) {
+        Ok(Some(rustls_pemfile::Item::Pkcs8Key(key))) => key.secret_pkcs8_der().to_vec(),
+        _ => return Err("Account key must be a PEM encoded PKCS#8 private key (BEGIN PRIVATE KEY)".to_string()),
+    };
+    parse_account_key(&pkcs8)?;
+
+    cache.store_account(&get_acme_account_contact(tls_settings), &get_acme_directory_url(tls_settings), &pkcs8).await
+}
+
+// rustls-acme signs with ES256, so the account key has to be a P-256 key
+pub fn parse_account_key(pkcs8: &[u8]) -> Result<EcdsaKeyPair, String> {
+    EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8, &SystemRandom::new()).map_err(|_| "Account key must be an ECDSA P-256 key".to_string())
+}
+
+// The HMAC key is given base64url encoded by the CAs, some with padding
+fn decode_eab_hmac_key(hmac_key: &str) -> Result<Vec<u8>, String> {
+    BASE64_URL_SAFE_NO_PAD
+        .decode(hmac_key.trim().trim_end_matches('='))
+        .map_err(|_| "External account binding HMAC key must be base64url encoded".to_string())
+}
+
+// The public key of the account as a JWK, with its members in lexicographic order
+fn get_jwk(key_pair: &EcdsaKeyPair) -> serde_json::Value {
+    get_public_key_jwk(key_pair.public_key().as_ref())
+}
+
+fn get_public_key_jwk(public_key: &[u8]) -> serde_json::Value {
+    // The public key is the uncompressed point, 0x04 followed by the coordinates
+    let (x, y) = public_key[1..].split_at(32);
+    serde_json::json!({
+        "crv": "P-256",
+        "kty": "EC",
+        "x": BASE64_URL_SAFE_NO_PAD.encode(x),
+        "y": BASE64_URL_SAFE_NO_PAD.encode(y),
+    })
+}
+
+/// The value of the TXT record answering a DNS-01 challenge: the SHA-256 digest of the key authorization, which is the
+/// token and the thumbprint of the public key of the account (RFC 8555, section 8.4)
+pub fn get_dns01_record_value(public_key: &[u8], token: &str) -> String {
+    let thumbprint = BASE64_URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, get_public_key_jwk(public_key).to_string().as_bytes()));
+    let key_authorization = format!("{}.{}", token, thumbprint);
+    BASE64_URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, key_authorization.as_bytes()))
+}
+
+// The binding is a JWS over the account JWK, signed with the HMAC key of the CA (RFC 8555, section 7.3.4)
+fn get_external_account_binding(key_id: &str, hmac_key: &[u8], jwk: &serde_json::Value, url: &str) -> serde_json::Value {
+    let protected = BASE64_URL_SAFE_NO_PAD.encode(serde_json::json!({ "alg": "HS256", "kid": key_id, "url": url }).to_string());
+    let payload = BASE64_URL_SAFE_NO_PAD.encode(jwk.to_string());
+    let key = hmac::Key::new(hmac::HMAC_SHA256, hmac_key);
+    let signature = hmac::sign(&key, format!("{}.{}", protected, payload).as_bytes());
+    serde_json::json!({
+        "protected": protected,
+        "payload": payload,
+        "signature": BASE64_URL_SAFE_NO_PAD.encode(signature.as_ref()),
+    })
+}
+
+/// Sign a request to the ACME server with the account key. Requests of a registered account name it by its URL as kid,
+/// while the newAccount request carries the public key as JWK, as the account has no URL yet
+pub fn sign_request(key_pair: &EcdsaKeyPair, kid: Option<&str>, nonce: &str, url: &str, payload: &str) -> Result<String, String> {
+    let protected = match kid {
+        Some(kid) => serde_json::json!({ "alg": "ES256", "kid": kid, "nonce": nonce, "url": url }),
+        None => serde_json::json!({ "alg": "ES256", "jwk": get_jwk(key_pair), "nonce": nonce, "url": url }),
+    };
+    let protected = BASE64_URL_SAFE_NO_PAD.encode(protected.to_string());
+    let payload = BASE64_URL_SAFE_NO_PAD.encode(payload);
+    let signature = key_pair
+        .sign(&SystemRandom::new(), format!("{}.{}", protected, payload).as_bytes())
+        .map_err(|_| "Failed to sign the account request".to_string())?;
+    Ok(serde_json::json!({
+        "protected": protected,
+        "payload": payload,
+        "signature": BASE64_URL_SAFE_NO_PAD.encode(signature.as_ref()),
+    })
+    .to_string())
+}
+
+/// Response of the ACME server to a signed request
+pub struct AcmeResponse {
+    pub status: hyper::StatusCode,
+    pub location: Option<String>,
+    pub body: String,
+}
+
+/// Send a signed request to the ACME server
+pub async fn post_jose(url: &str, body: String) -> Result<AcmeResponse, String> {
+    let request = hyper::Request::builder()
+        .method(hyper::Method::POST)
+        .uri(url)
+        .header(hyper::header::CONTENT_TYPE, "application/jose+json")
+        .body(Full::new(Bytes::from(body)).map_err(|never| match never {}).boxed())
+        .map_err(|e| format!("Failed to build the request: {}", e))?;
+
+    let client = {
+        let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+        running_state.get_http_client().get_client(true)
+    };
+    let response = tokio::time::timeout(Duration::from_secs(ACME_REQUEST_TIMEOUT_SECS), client.request(request))
+        .await
+        .map_err(|_| "Request to the ACME server timed out".to_string())?
+        .map_err(|e| format!("Request to the ACME server failed: {}", e))?;
+
+    let status = response.status();
+    let location = response.headers().get(hyper::header::LOCATION).and_then(|value| value.to_str().ok()).map(|value| value.to_string());
+    let body = tokio::time::timeout(Duration::from_secs(ACME_REQUEST_TIMEOUT_SECS), response.into_body().collect())
+        .await
+        .map_err(|_| "Reading the response of the ACME server timed out".to_string())?
+        .map_err(|e| format!("Failed to read the response of the ACME server: {}", e))?
+        .to_bytes();
+    Ok(AcmeResponse {
+        status,
+        location,
+        body: String::from_utf8_lossy(&body).to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_lc_rs::signature::KeyPair as _;
+    use rustls_acme::acme::{Challenge, ChallengeType};
+
+    #[test]
+    fn test_acme_directory_url() {
+        let mut tls_settings = TlsSettings::new();
+        assert_eq!(get_acme_directory_url(&tls_settings), LETS_ENCRYPT_PRODUCTION_DIRECTORY);
+        tls_settings.use_staging_server = true;
+        assert_eq!(get_acme_directory_url(&tls_settings), LETS_ENCRYPT_STAGING_DIRECTORY);
+        tls_settings.acme_provider = "buypass".to_string();
+        assert_eq!(get_acme_directory_url(&tls_settings), "https://api.test4.buypass.no/acme/directory");
+        tls_settings.acme_provider = "custom".to_string();
+        tls_settings.acme_directory_url = "https://ca.example.com/acme/directory".to_string();
+        assert_eq!(get_acme_directory_url(&tls_settings), "https://ca.example.com/acme/directory");
+
+        assert_eq!(get_provider_name(LETS_ENCRYPT_STAGING_DIRECTORY), "letsencrypt");
+        assert_eq!(get_provider_name("https://dv.acme-v02.api.pki.goog/directory"), "google");
+        assert_eq!(get_provider_name("https://ca.example.com/acme/directory"), "custom");
+    }
+
+    #[test]
+    fn test_external_account_binding() {
+        let key_pair = parse_account_key(&Account::generate_key_pair()).unwrap();
+        let jwk = get_jwk(&key_pair);
+        let hmac_key = decode_eab_hmac_key("c2VjcmV0LWhtYWMta2V5").unwrap();
+        let binding = get_external_account_binding("kid-1", &hmac_key, &jwk, "https://ca.example.com/new-account");
+
+        let protected: serde_json::Value = serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(binding["protected"].as_str().unwrap()).unwrap()).unwrap();
+        assert_eq!(protected["alg"], "HS256");
+        assert_eq!(protected["kid"], "kid-1");
+        assert_eq!(protected["url"], "https://ca.example.com/new-account");
+
+        let payload: serde_json::Value = serde_json::from_slice(&BASE64_URL_SAFE_NO_PAD.decode(binding["payload"].as_str().unwrap()).unwrap()).unwrap();
+        assert_eq!(payload, jwk);
+
+        let key = hmac::Key::new(hmac::HMAC_SHA256, &hmac_key);
+        let signed = format!("{}.{}", binding["protected"].as_str().unwrap(), binding["payload"].as_str().unwrap());
+        let signature = BASE64_URL_SAFE_NO_PAD.decode(binding["signature"].as_str().unwrap()).unwrap();
+        assert!(hmac::verify(&key, signed.as_bytes(), &signature).is_ok());
+
+        let pem = encode_pem("PRIVATE KEY", &Account::generate_key_pair());
+        let mut reader = std::io::Cursor::new(pem.as_bytes());
+        assert!(matches!(rustls_pemfile::read_one(&mut reader), Ok(Some(rustls_pemfile::Item::Pkcs8Key(_)))));
+    }
+
+    #[test]
+    fn test_dns01_record_value() {
+        let account = Account {
+            key_pair: aws_lc_rs::signature::EcdsaKeyPair::from_pkcs8(&aws_lc_rs::signature::ECDSA_P256_SHA256_FIXED_SIGNING, &Account::generate_key_pair()).unwrap(),
+            directory: Directory {
+                new_nonce: String::new(),
+                new_account: String::new(),
+                new_order: String::new(),
+            },
+            kid: String::new(),
+        };
+        let challenges = vec![Challenge {
+            typ: ChallengeType::Http01,
+            url: String::new(),
+            token: "token-1".to_string(),
+            error: None,
+        }];
+        // The key authorization rustls-acme answers HTTP-01 challenges with is the one digested for DNS-01
+        let (_, key_authorization) = account.http_01(&challenges).unwrap();
+        let expected = BASE64_URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, key_authorization.as_bytes()));
+        assert_eq!(get_dns01_record_value(account.key_pair.public_key().as_ref(), "token-1"), expected);
+    }
+}
@@ -0,0 +1,164 @@
+// ============================================================================
+// ACME CERTIFICATE LOG
+// ============================================================================
+//
+// Every certificate ordered through ACME is recorded in the acme_certificates
+// table when it lands in the ACME cache, with its domains, serial number,
+// validity and the CA it came from, so operators can audit what Gruxi has
+// requested on their behalf and match it against certificate transparency
+// logs. Certificates loaded from the cache at startup are not recorded again.
+//
+// A certificate is "issued" the first time the domains get one of its key type
+// from the CA, and "renewed" after that. Failing to record a certificate is
+// logged, and never fails the order.
+// ============================================================================
+
+use serde::Serialize;
+
+use crate::core::database_connection::{get_database_connection, get_database_writer};
+use crate::database::data_access::{execute, query, query_one};
+use crate::logging::syslog::{error, info};
+use crate::tls::acme_account::get_provider_name;
+use crate::tls::certificate_export::CertificateWithKey;
+
+/// Most records returned by one listing
+pub const MAX_ACME_CERTIFICATE_RECORDS: usize = 1000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AcmeCertificateRecord {
+    pub id: i64,
+    pub domains: Vec<String>,
+    pub serial: String,
+    pub not_before: String,
+    pub not_after: String,
+    pub provider: String,
+    pub directory_url: String,
+    pub key_type: String,
+    pub event: String, // "issued" or "renewed"
+    pub created_at: String,
+}
+
+/// Record a certificate the CA issued for the domains, from the PEM stored in the ACME cache
+pub async fn record_acme_certificate(domains: &[String], directory_url: &str, key_type: &str, pem: &[u8]) {
+    let domains = domains.to_vec();
+    let directory_url = directory_url.to_string();
+    let key_type = key_type.to_string();
+    let pem = pem.to_vec();
+    let result = tokio::task::spawn_blocking(move || insert_acme_certificate(&domains, &directory_url, &key_type, &pem))
+        .await
+        .map_err(|e| format!("Failed to record the certificate: {}", e))
+        .and_then(|result| result);
+    if let Err(e) = result {
+        error(format!("Failed to record ACME certificate in the certificate log: {}", e));
+    }
+}
+
+fn insert_acme_certificate(domains: &[String], directory_url: &str, key_type: &str, pem: &[u8]) -> Result<(), String> {
+    let (serial, not_before, not_after) = describe_certificate(pem)?;
+    let domains = domains.join(",");
+
+    let connection = get_database_writer()?;
+    let previous = query_one(
+        &connection,
+        "SELECT id FROM acme_certificates WHERE domains = ? AND key_type = ? LIMIT 1",
+        &[&domains, &key_type],
+        |row| row.get_i64("id"),
+    )
+    .map_err(|e| format!("Failed to query the certificate log: {}", e))?;
+    let event = if previous.is_some() { "renewed" } else { "issued" };
+
+    execute(
+        &connection,
+        "INSERT INTO acme_certificates (domains, serial, not_before, not_after, provider, directory_url, key_type, event, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        &[
+            &domains,
+            &serial,
+            &not_before,
+            &not_after,
+            &get_provider_name(directory_url),
+            &directory_url,
+            &key_type,
+            &event,
+            &chrono::Utc::now().to_rfc3339(),
+        ],
+    )
+    .map_err(|e| format!("Failed to save to the certificate log: {}", e))?;
+
+    info(format!(
+        "ACME certificate {} for {} ({}, serial {}, valid until {})",
+        event,
+        domains,
+        key_type.to_uppercase(),
+        serial,
+        not_after
+    ));
+    Ok(())
+}
+
+// The serial number and validity of the leaf certificate in an ACME PEM
+fn describe_certificate(pem: &[u8]) -> Result<(String, String, String), String> {
+    let certificate = CertificateWithKey::from_acme_pem(pem)?;
+    let leaf = certificate.certificate_chain.first().ok_or("No certificates in the chain")?;
+    let (_, parsed) = x509_parser::parse_x509_certificate(leaf).map_err(|e| format!("Failed to parse the certificate: {}", e))?;
+    let to_rfc3339 = |timestamp: i64| chrono::DateTime::from_timestamp(timestamp, 0).map(|time| time.to_rfc3339()).unwrap_or_default();
+    Ok((
+        parsed.raw_serial_as_string(),
+        to_rfc3339(parsed.validity().not_before.timestamp()),
+        to_rfc3339(parsed.validity().not_after.timestamp()),
+    ))
+}
+
+/// The recorded certificates, the most recent first, optionally only those covering the domain
+pub fn list_acme_certificates(domain: Option<&str>, limit: usize) -> Result<Vec<AcmeCertificateRecord>, String> {
+    let connection = get_database_connection()?;
+    let limit = limit.clamp(1, MAX_ACME_CERTIFICATE_RECORDS) as i64;
+    let mapper = |row: &crate::database::data_access::Row| {
+        Ok(AcmeCertificateRecord {
+            id: row.get_i64("id")?,
+            domains: row.get_string("domains")?.split(',').map(|domain| domain.to_string()).collect(),
+            serial: row.get_string("serial")?,
+            not_before: row.get_string("not_before")?,
+            not_after: row.get_string("not_after")?,
+            provider: row.get_string("provider")?,
+            directory_url: row.get_string("directory_url")?,
+            key_type: row.get_string("key_type")?,
+            event: row.get_string("event")?,
+            created_at: row.get_string("created_at")?,
+        })
+    };
+    let columns = "id, domains, serial, not_before, not_after, provider, directory_url, key_type, event, created_at";
+
+    match domain {
+        Some(domain) => query(
+            &connection,
+            &format!("SELECT {} FROM acme_certificates WHERE ',' || domains || ',' LIKE ? ORDER BY id DESC LIMIT ?", columns),
+            &[&format!("%,{},%", domain.trim().to_lowercase()), &limit],
+            mapper,
+        ),
+        None => query(&connection, &format!("SELECT {} FROM acme_certificates ORDER BY id DESC LIMIT ?", columns), &[&limit], mapper),
+    }
+    .map_err(|e| format!("Failed to query the certificate log: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_certificate() {
+        let key_pair = rcgen::KeyPair::generate().unwrap();
+        let mut params = rcgen::CertificateParams::new(vec!["www.example.com".to_string()]).unwrap();
+        params.serial_number = Some(rcgen::SerialNumber::from_slice(&[0x01, 0xab, 0xcd]));
+        params.not_before = rcgen::date_time_ymd(2026, 1, 2);
+        params.not_after = rcgen::date_time_ymd(2026, 4, 2);
+        let certificate = params.self_signed(&key_pair).unwrap();
+        let pem = format!("{}\n{}", key_pair.serialize_pem(), certificate.pem());
+
+        let (serial, not_before, not_after) = describe_certificate(pem.as_bytes()).unwrap();
+        assert_eq!(serial, "01:ab:cd");
+        assert_eq!(not_before, "2026-01-02T00:00:00+00:00");
+        assert_eq!(not_after, "2026-04-02T00:00:00+00:00");
+
+        assert!(describe_certificate(key_pair.serialize_pem().as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,449 @@
+// ============================================================================
+// ACME ORDERS
+// ============================================================================
+//
+// Certificate orders that rustls-acme cannot make, as it only orders ECDSA
+// P-256 certificates, without an ACME profile or wildcards. The orders of sites
+// asking for RSA keys, for both key types or for a profile of the CA, and those
+// of wildcard hostnames, are made here, with the same account and cache as
+// those of rustls-acme:
+//   - Each order keeps one certificate per key type, loaded from the cache,
+//     and ordered when it is missing or a third of its lifetime is left
+//   - The challenges are answered by the bindings through the shared
+//     resolver, with the challenge type of the TLS settings
+//   - Orders with a wildcard are validated with DNS-01 instead, by records
+//     the "acme_dns_challenge" command hooks publish and remove
+//   - Clients supporting ECDSA get the ECDSA certificate, others the RSA one
+//
+// The steps of answering the challenges and finalizing an order are shared
+// with the staging smoke test.
+// ============================================================================
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use aws_lc_rs::encoding::{AsDer, Pkcs8V1Der};
+use aws_lc_rs::rsa::KeySize;
+use aws_lc_rs::signature::KeyPair;
+use ring::signature::EcdsaKeyPair;
+use rustls::SignatureScheme;
+use rustls::crypto::aws_lc_rs as rustls_aws_lc_rs;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::server::ClientHello;
+use rustls::sign::CertifiedKey;
+use rustls_acme::acme::{Account, AuthStatus, ChallengeType, Directory, Identifier, Order, OrderStatus, Problem};
+use rustls_acme::{AccountCache, CertCache};
+use tokio_util::sync::CancellationToken;
+
+use crate::configuration::tls_settings::TlsSettings;
+use crate::logging::syslog::{debug, error, info};
+use crate::tls::acme_account::{ensure_acme_account, get_acme_account_contact, get_acme_directory_url, get_dns01_record_value, parse_account_key, post_jose, sign_request};
+use crate::tls::acme_cache::AcmeCache;
+use crate::tls::acme_certificate_log::record_acme_certificate;
+use crate::tls::certificate_export::CertificateWithKey;
+use crate::tls::shared_acme_manager::{AcmeOrderSettings, get_trigger_token, run_acme_dns_challenge_hooks, run_certificate_renewed_hooks};
+use crate::tls::tls_config::tls_config;
+
+// Times the authorization and order status are polled, waiting twice as long each time, starting at a second
+const MAX_STATUS_POLLS: u32 = 6;
+
+// The cache is checked at least this often, so certificates ordered by other nodes sharing it are picked up
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+
+// Failed orders are retried after this long, doubling up to the check interval
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+
+/// Challenge answers of pending authorizations, read by the bindings when the CA validates a domain
+#[derive(Default)]
+pub struct ChallengeAnswers {
+    http01_key_authorizations: RwLock<HashMap<String, String>>,          // Token to key authorization
+    tls_alpn01_certificates: RwLock<HashMap<String, Arc<CertifiedKey>>>, // Domain to challenge certificate
+    dns01_records: RwLock<Vec<(String, String)>>,                        // Domain and TXT record value, published by the hooks
+}
+
+impl ChallengeAnswers {
+    pub fn get_http01_key_authorization(&self, token: &str) -> Option<String> {
+        self.http01_key_authorizations.read().ok()?.get(token).cloned()
+    }
+
+    pub fn set_http01_key_authorization(&self, token: &str, key_authorization: String) {
+        if let Ok(mut http01_key_authorizations) = self.http01_key_authorizations.write() {
+            http01_key_authorizations.insert(token.to_string(), key_authorization);
+        }
+    }
+
+    pub fn get_tls_alpn01_certificate(&self, domain: &str) -> Option<Arc<CertifiedKey>> {
+        self.tls_alpn01_certificates.read().ok()?.get(&domain.to_lowercase()).cloned()
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut answers) = self.http01_key_authorizations.write() {
+            answers.clear();
+        }
+        if let Ok(mut answers) = self.tls_alpn01_certificates.write() {
+            answers.clear();
+        }
+    }
+
+    /// Remove the DNS-01 records published for the order, once the CA is done with them
+    pub async fn remove_dns01_records(&self) {
+        let records = self.dns01_records.write().map(|mut records| std::mem::take(&mut *records)).unwrap_or_default();
+        for (domain, value) in records {
+            run_acme_dns_challenge_hooks("cleanup", &domain, &value).await;
+        }
+    }
+}
+
+/// An order made by Gruxi itself, with its certificates by key type
+pub struct ManagedAcmeOrder {
+    pub domains: Vec<String>,
+    pub settings: AcmeOrderSettings,
+    certificates: RwLock<HashMap<String, Arc<CertifiedKey>>>,
+    challenges: ChallengeAnswers,
+}
+
+impl ManagedAcmeOrder {
+    pub fn new(domains: Vec<String>, settings: AcmeOrderSettings) -> Self {
+        Self {
+            domains,
+            settings,
+            certificates: RwLock::new(HashMap::new()),
+            challenges: ChallengeAnswers::default(),
+        }
+    }
+
+    /// The certificate for the client: ECDSA when it supports it, RSA otherwise, or whichever the order has
+    pub fn resolve(&self, client_hello: &ClientHello) -> Option<Arc<CertifiedKey>> {
+        let supports_ecdsa = client_hello.signature_schemes().iter().any(|scheme| {
+            matches!(
+                scheme,
+                SignatureScheme::ECDSA_NISTP256_SHA256 | SignatureScheme::ECDSA_NISTP384_SHA384 | SignatureScheme::ECDSA_NISTP521_SHA512
+            )
+        });
+        let certificates = self.certificates.read().ok()?;
+        let (preferred, other) = if supports_ecdsa { ("ecdsa", "rsa") } else { ("rsa", "ecdsa") };
+        certificates.get(preferred).or_else(|| certificates.get(other)).cloned()
+    }
+
+    pub fn get_http01_key_authorization(&self, token: &str) -> Option<String> {
+        self.challenges.get_http01_key_authorization(token)
+    }
+
+    pub fn get_tls_alpn01_certificate(&self, domain: &str) -> Option<Arc<CertifiedKey>> {
+        self.challenges.get_tls_alpn01_certificate(domain)
+    }
+
+    fn set_certificate(&self, key_type: &str, certificate: Arc<CertifiedKey>) {
+        if let Ok(mut certificates) = self.certificates.write() {
+            certificates.insert(key_type.to_string(), certificate);
+        }
+    }
+}
+
+/// The name the certificate of a key type is cached by. The cache names entries by the domains and directory URL, so the
+/// key type and profile are added to the URL to keep them apart from each other and from the certificates of rustls-acme
+pub fn get_cache_directory_key(settings: &AcmeOrderSettings, key_type: &str) -> String {
+    format!("{}#{}#{}", settings.directory_url, key_type, settings.profile)
+}
+
+/// Spawn a task keeping the certificates of the order issued and renewed, until the token is cancelled or the server stops
+pub fn spawn_managed_order_task(order: Arc<ManagedAcmeOrder>, tls_settings: TlsSettings, cache_dir: String, cancel_token: CancellationToken) {
+    tokio::spawn(async move {
+        let shutdown_token = get_trigger_token("shutdown");
+        let stop_services_token = get_trigger_token("stop_services");
+        let cache = AcmeCache::from_settings(&tls_settings, &cache_dir);
+        let mut retry_delay = MIN_RETRY_DELAY;
+
+        loop {
+            let next_check = match renew_certificates(&order, &tls_settings, &cache).await {
+                Ok(until_renewal) => {
+                    retry_delay = MIN_RETRY_DELAY;
+                    until_renewal.min(MAX_CHECK_INTERVAL)
+                }
+                Err(e) => {
+                    error(format!("ACME order for {} failed, retrying in {} minutes: {}", order.domains.join(", "), retry_delay.as_secs() / 60, e));
+                    let delay = retry_delay;
+                    retry_delay = (retry_delay * 2).min(MAX_CHECK_INTERVAL);
+                    delay
+                }
+            };
+
+            tokio::select! {
+                _ = cancel_token.cancelled() => break,
+                _ = shutdown_token.cancelled() => break,
+                _ = stop_services_token.cancelled() => break,
+                _ = tokio::time::sleep(next_check) => {}
+            }
+        }
+
+        debug(format!("ACME order task for {} ended", order.domains.join(", ")));
+    });
+}
+
+// Load the certificates of the order from the cache, and order those missing or due for renewal. Returns how long until
+// the first of them is due
+async fn renew_certificates(order: &ManagedAcmeOrder, tls_settings: &TlsSettings, cache: &AcmeCache) -> Result<Duration, String> {
+    let mut next_renewal = MAX_CHECK_INTERVAL;
+
+    for key_type in order.settings.get_key_types() {
+        let cache_key = get_cache_directory_key(&order.settings, key_type);
+        let cached = match cache.load_cert(&order.domains, &cache_key).await? {
+            Some(pem) => match parse_certificate(&pem) {
+                Ok(cached) => Some(cached),
+                Err(e) => {
+                    debug(format!("Ignoring cached {} certificate for {}: {}", key_type, order.domains.join(", "), e));
+                    None
+                }
+            },
+            None => None,
+        };
+
+        match cached {
+            Some((certificate, until_renewal)) if !until_renewal.is_zero() => {
+                order.set_certificate(key_type, certificate);
+                next_renewal = next_renewal.min(until_renewal);
+            }
+            cached => {
+                // An expiring certificate is still served while its replacement is ordered
+                if let Some((certificate, _)) = cached {
+                    order.set_certificate(key_type, certificate);
+                }
+                let pem = issue_certificate(order, tls_settings, cache, key_type).await;
+                order.challenges.clear();
+                order.challenges.remove_dns01_records().await;
+                let pem = pem?;
+                cache.store_cert(&order.domains, &cache_key, &pem).await?;
+                record_acme_certificate(&order.domains, &order.settings.directory_url, key_type, &pem).await;
+                let (certificate, until_renewal) = parse_certificate(&pem)?;
+                order.set_certificate(key_type, certificate);
+                next_renewal = next_renewal.min(until_renewal.max(MIN_RETRY_DELAY));
+
+                info(format!(
+                    "Issued {} certificate for {} from {}",
+                    key_type.to_uppercase(),
+                    order.domains.join(", "),
+                    order.settings.directory_url
+                ));
+                run_certificate_renewed_hooks(&order.domains);
+            }
+        }
+    }
+
+    Ok(next_renewal)
+}
+
+// The certificate and key of a cached PEM, and how long until a third of its lifetime is left
+fn parse_certificate(pem: &[u8]) -> Result<(Arc<CertifiedKey>, Duration), String> {
+    let certificate = CertificateWithKey::from_acme_pem(pem)?;
+    let leaf = certificate.certificate_chain.first().ok_or("No certificates in the chain")?;
+    let (_, parsed) = x509_parser::parse_x509_certificate(leaf).map_err(|e| format!("Failed to parse the certificate: {}", e))?;
+    let not_before = parsed.validity().not_before.timestamp();
+    let not_after = parsed.validity().not_after.timestamp();
+    let renew_at = not_after - (not_after - not_before) / 3;
+    let until_renewal = Duration::from_secs((renew_at - chrono::Utc::now().timestamp()).max(0) as u64);
+
+    let chain: Vec<CertificateDer<'static>> = certificate.certificate_chain.into_iter().map(CertificateDer::from).collect();
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certificate.private_key_der));
+    let certified_key = CertifiedKey::from_der(chain, key, &rustls_aws_lc_rs::default_provider()).map_err(|e| format!("Failed to load the certificate: {}", e))?;
+    Ok((Arc::new(certified_key), until_renewal))
+}
+
+// Order a certificate of the key type for the domains of the order. Returns the PEM to cache, with the key first
+async fn issue_certificate(order: &ManagedAcmeOrder, tls_settings: &TlsSettings, cache: &AcmeCache, key_type: &str) -> Result<Vec<u8>, String> {
+    let client_config = Arc::new(tls_config());
+    let directory_url = &order.settings.directory_url;
+    let directory = Directory::discover(&client_config, directory_url)
+        .await
+        .map_err(|e| format!("Failed to fetch the ACME directory '{}': {}", directory_url, e))?;
+    let (account, account_key) = get_account(&client_config, directory, tls_settings, cache, directory_url).await?;
+
+    let (order_url, acme_order) = new_order(&client_config, &account, &account_key, &order.domains, &order.settings.profile).await?;
+    // Only DNS-01 can validate a wildcard, and the CA validates all names of an order the same way
+    let challenge_type = if order.domains.iter().any(|domain| domain.starts_with("*.")) {
+        "dns-01"
+    } else {
+        tls_settings.acme_challenge_type.as_str()
+    };
+    authorize(&account, &client_config, &acme_order, challenge_type, &order.challenges).await?;
+
+    let key_pair = generate_certificate_key(key_type)?;
+    let certificate_url = finalize(&account, &client_config, &order_url, &order.domains, &key_pair).await?;
+    let certificate_chain = account
+        .certificate(&client_config, &certificate_url)
+        .await
+        .map_err(|e| format!("Failed to download the certificate: {}", e))?;
+    Ok(format!("{}\n{}", key_pair.serialize_pem(), certificate_chain).into_bytes())
+}
+
+// The account of the CA from the cache, registering it when there is none yet, with the external account binding when
+// the CA is the one of the TLS settings
+async fn get_account(client_config: &Arc<rustls::ClientConfig>, directory: Directory, tls_settings: &TlsSettings, cache: &AcmeCache, directory_url: &str) -> Result<(Account, EcdsaKeyPair), String> {
+    if directory_url == get_acme_directory_url(tls_settings) {
+        ensure_acme_account(tls_settings, cache).await?;
+    }
+
+    let contact = get_acme_account_contact(tls_settings);
+    let (pkcs8, is_new) = match cache.load_account(&contact, directory_url).await? {
+        Some(pkcs8) => (pkcs8, false),
+        None => (Account::generate_key_pair(), true),
+    };
+    let account = Account::create_with_keypair(client_config, directory, &contact, &pkcs8)
+        .await
+        .map_err(|e| format!("Failed to register the ACME account: {}", e))?;
+    if is_new {
+        cache.store_account(&contact, directory_url, &pkcs8).await?;
+    }
+    Ok((account, parse_account_key(&pkcs8)?))
+}
+
+// Create the order, with the profile when one is set, which rustls-acme cannot send
+async fn new_order(client_config: &Arc<rustls::ClientConfig>, account: &Account, account_key: &EcdsaKeyPair, domains: &[String], profile: &str) -> Result<(String, Order), String> {
+    if profile.is_empty() {
+        return account.new_order(client_config, domains.to_vec()).await.map_err(|e| format!("Failed to create the order: {}", e));
+    }
+
+    let identifiers: Vec<serde_json::Value> = domains.iter().map(|domain| serde_json::json!({ "type": "dns", "value": domain })).collect();
+    let payload = serde_json::json!({ "identifiers": identifiers, "profile": profile }).to_string();
+    let nonce = account.directory.nonce(client_config).await.map_err(|e| format!("Failed to get a nonce from the ACME server: {}", e))?;
+    let body = sign_request(account_key, Some(&account.kid), &nonce, &account.directory.new_order, &payload)?;
+
+    let response = post_jose(&account.directory.new_order, body).await.map_err(|e| format!("Failed to create the order: {}", e))?;
+    if !response.status.is_success() {
+        return Err(format!("ACME server refused the order with status {}: {}", response.status, response.body));
+    }
+    let order_url = response.location.ok_or("ACME server did not return the URL of the order")?;
+    let order: Order = serde_json::from_str(&response.body).map_err(|e| format!("Failed to parse the order: {}", e))?;
+    Ok((order_url, order))
+}
+
+// rcgen generates RSA keys only with aws-lc-rs, so they are generated there and signed with by rcgen
+fn generate_certificate_key(key_type: &str) -> Result<rcgen::KeyPair, String> {
+    if key_type != "rsa" {
+        return rcgen::KeyPair::generate().map_err(|e| format!("Failed to generate the certificate key: {}", e));
+    }
+    let key_pair = aws_lc_rs::rsa::KeyPair::generate(KeySize::Rsa2048).map_err(|_| "Failed to generate the RSA certificate key".to_string())?;
+    let pkcs8: Pkcs8V1Der = key_pair.as_der().map_err(|_| "Failed to encode the RSA certificate key".to_string())?;
+    rcgen::KeyPair::from_pkcs8_der_and_sign_algo(&PrivatePkcs8KeyDer::from(pkcs8.as_ref()), &rcgen::PKCS_RSA_SHA256).map_err(|e| format!("Failed to load the RSA certificate key: {}", e))
+}
+
+/// Answer the challenge of each authorization of the order, and wait for the CA to validate it. Returns the validated domains
+pub async fn authorize(account: &Account, client_config: &Arc<rustls::ClientConfig>, order: &Order, challenge_type: &str, answers: &ChallengeAnswers) -> Result<Vec<String>, String> {
+    let mut domains = Vec::new();
+    for authorization_url in &order.authorizations {
+        let authorization = account.auth(client_config, authorization_url).await.map_err(|e| format!("Failed to fetch the authorization: {}", e))?;
+        let Identifier::Dns(domain) = authorization.identifier;
+        if matches!(authorization.status, AuthStatus::Valid) {
+            // The CA may reuse a recent validation of the account
+            domains.push(domain);
+            continue;
+        }
+
+        let challenge = if challenge_type == "dns-01" {
+            // The identifier of a wildcard is its parent domain, which the record is published under
+            let challenge = authorization
+                .challenges
+                .iter()
+                .find(|challenge| challenge.typ == ChallengeType::Dns01)
+                .ok_or("The CA offered no DNS-01 challenge")?;
+            let value = get_dns01_record_value(account.key_pair.public_key().as_ref(), &challenge.token);
+            if let Ok(mut dns01_records) = answers.dns01_records.write() {
+                dns01_records.push((domain.clone(), value.clone()));
+            }
+            run_acme_dns_challenge_hooks("present", &domain, &value).await;
+            challenge
+        } else if challenge_type == "http-01" {
+            let (challenge, key_authorization) = account.http_01(&authorization.challenges).map_err(|e| e.to_string())?;
+            answers.set_http01_key_authorization(&challenge.token, key_authorization);
+            challenge
+        } else {
+            let (challenge, certificate) = account.tls_alpn_01(&authorization.challenges, domain.clone()).map_err(|e| e.to_string())?;
+            if let Ok(mut tls_alpn01_certificates) = answers.tls_alpn01_certificates.write() {
+                tls_alpn01_certificates.insert(domain.clone(), Arc::new(certificate));
+            }
+            challenge
+        };
+        account
+            .challenge(client_config, &challenge.url)
+            .await
+            .map_err(|e| format!("Failed to ask the CA to validate {}: {}", domain, e))?;
+
+        let mut validated = false;
+        for attempt in 0..MAX_STATUS_POLLS {
+            tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+            let authorization = account.auth(client_config, authorization_url).await.map_err(|e| format!("Failed to fetch the authorization: {}", e))?;
+            match authorization.status {
+                AuthStatus::Pending => continue,
+                AuthStatus::Valid => {
+                    validated = true;
+                    break;
+                }
+                status => {
+                    // The challenge tells why, such as the CA not reaching the binding or getting the wrong answer
+                    let problem = authorization.challenges.iter().find_map(|challenge| challenge.error.as_ref()).map(describe_problem).unwrap_or_default();
+                    return Err(format!("The CA did not validate {}, the authorization is {:?}{}", domain, status, problem));
+                }
+            }
+        }
+        if !validated {
+            return Err(format!("The CA did not validate {} in time", domain));
+        }
+        domains.push(domain);
+    }
+    Ok(domains)
+}
+
+/// Send the certificate request, and wait for the order to become valid. Returns the URL to download the certificate from
+pub async fn finalize(account: &Account, client_config: &Arc<rustls::ClientConfig>, order_url: &str, domains: &[String], key_pair: &rcgen::KeyPair) -> Result<String, String> {
+    let mut params = rcgen::CertificateParams::new(domains.to_vec()).map_err(|e| format!("Failed to create the certificate request: {}", e))?;
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    let csr = params.serialize_request(key_pair).map_err(|e| format!("Failed to create the certificate request: {}", e))?;
+
+    let mut order = account.order(client_config, order_url).await.map_err(|e| format!("Failed to fetch the order: {}", e))?;
+    for attempt in 0..MAX_STATUS_POLLS {
+        match &order.status {
+            OrderStatus::Ready => {
+                order = account
+                    .finalize(client_config, &order.finalize, csr.der())
+                    .await
+                    .map_err(|e| format!("Failed to finalize the order: {}", e))?;
+                continue;
+            }
+            OrderStatus::Valid { certificate } => return Ok(certificate.clone()),
+            OrderStatus::Invalid => {
+                return Err(format!("The order is invalid{}", order.error.as_ref().map(describe_problem).unwrap_or_default()));
+            }
+            OrderStatus::Pending | OrderStatus::Processing => {}
+        }
+        tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+        order = account.order(client_config, order_url).await.map_err(|e| format!("Failed to fetch the order: {}", e))?;
+    }
+    Err("The CA did not issue the certificate in time".to_string())
+}
+
+fn describe_problem(problem: &Problem) -> String {
+    format!(": {}", problem.detail.clone().or_else(|| problem.typ.clone()).unwrap_or_else(|| "no details".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_certificate_keys_by_type() {
+        let settings = AcmeOrderSettings {
+            directory_url: "https://ca.example.com/directory".to_string(),
+            key_type: "both".to_string(),
+            profile: "tlsserver".to_string(),
+        };
+        assert_eq!(settings.get_key_types(), vec!["ecdsa", "rsa"]);
+        assert_ne!(get_cache_directory_key(&settings, "ecdsa"), get_cache_directory_key(&settings, "rsa"));
+
+        // The RSA key generated with aws-lc-rs signs certificate requests through rcgen
+        let key_pair = generate_certificate_key("rsa").unwrap();
+        assert!(key_pair.is_compatible(&rcgen::PKCS_RSA_SHA256));
+        let params = rcgen::CertificateParams::new(vec!["www.example.com".to_string()]).unwrap();
+        assert!(params.serialize_request(&key_pair).is_ok());
+        assert!(generate_certificate_key("ecdsa").unwrap().is_compatible(&rcgen::PKCS_ECDSA_P256_SHA256));
+    }
+}
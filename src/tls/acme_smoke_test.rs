@@ -0,0 +1,239 @@
+// ============================================================================
+// ACME STAGING SMOKE TEST
+// ============================================================================
+//
+// Runs a full certificate issuance for one domain against the Let's Encrypt
+// staging CA, step by step, and reports how each step went. This validates
+// the DNS, port forwarding and challenge setup before turning off
+// use_staging_server, without touching the certificates in use:
+//   - A new staging account is created for the test, and not cached
+//   - The challenge is answered by the running bindings, next to the
+//     challenges of the ACME manager
+//   - The issued certificate is stored in a temporary cache, read back and
+//     removed again
+//
+// Only one smoke test runs at a time, as the CA validates the challenge
+// against whichever test set it up.
+// ============================================================================
+
+use std::sync::{Arc, LazyLock};
+use std::time::Instant;
+
+use rustls::sign::CertifiedKey;
+use rustls_acme::CertCache;
+use rustls_acme::acme::{Account, Directory, LETS_ENCRYPT_STAGING_DIRECTORY};
+use rustls_acme::caches::DirCache;
+use serde::Serialize;
+
+use crate::configuration::cached_configuration::get_cached_configuration;
+use crate::logging::syslog::{debug, info};
+use crate::tls::acme_orders::{ChallengeAnswers, authorize, finalize};
+use crate::tls::certificate_export::CertificateWithKey;
+use crate::tls::shared_acme_manager::{ACME_CHALLENGE_TYPES, is_acme_domain_candidate};
+use crate::tls::tls_config::tls_config;
+
+/// Challenge answers of the running smoke test, read by the bindings when the CA validates the domain
+static SMOKE_TEST_CHALLENGES: LazyLock<ChallengeAnswers> = LazyLock::new(ChallengeAnswers::default);
+
+static SMOKE_TEST_RUNNING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AcmeSmokeTestStep {
+    pub name: String,
+    pub success: bool,
+    pub message: String,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AcmeSmokeTestReport {
+    pub domain: String,
+    pub challenge_type: String,
+    pub directory_url: String,
+    pub success: bool,
+    pub steps: Vec<AcmeSmokeTestStep>,
+}
+
+impl AcmeSmokeTestReport {
+    fn add_step<T>(&mut self, name: &str, started: Instant, result: &Result<T, String>, message: impl FnOnce(&T) -> String) {
+        let (success, message) = match result {
+            Ok(value) => (true, message(value)),
+            Err(e) => (false, e.clone()),
+        };
+        debug(format!("ACME smoke test for '{}', step {}: {} ({})", self.domain, name, if success { "ok" } else { "failed" }, message));
+        self.steps.push(AcmeSmokeTestStep {
+            name: name.to_string(),
+            success,
+            message,
+            duration_ms: started.elapsed().as_millis() as u64,
+        });
+    }
+}
+
+/// Get the key authorization of a pending HTTP-01 challenge of the smoke test, if the token matches
+pub fn get_smoke_test_http01_key_authorization(token: &str) -> Option<String> {
+    SMOKE_TEST_CHALLENGES.get_http01_key_authorization(token)
+}
+
+/// Get the certificate answering a pending TLS-ALPN-01 challenge of the smoke test for the domain
+pub fn get_smoke_test_tls_alpn01_certificate(domain: &str) -> Option<Arc<CertifiedKey>> {
+    SMOKE_TEST_CHALLENGES.get_tls_alpn01_certificate(domain)
+}
+
+/// Check that a smoke test can be run for the domain with the challenge type, before contacting the CA
+pub fn validate_smoke_test_request(domain: &str, challenge_type: &str) -> Result<(), String> {
+    if !is_acme_domain_candidate(domain) {
+        return Err(format!("'{}' is not a public domain name that ACME can issue a certificate for", domain));
+    }
+    if !ACME_CHALLENGE_TYPES.contains(&challenge_type) {
+        return Err(format!("Challenge type must be one of {}, got '{}'", ACME_CHALLENGE_TYPES.join(", "), challenge_type));
+    }
+    Ok(())
+}
+
+/// Run the smoke test for the domain, with the configured challenge type unless another is given. Returns an error
+/// when the test cannot start, and otherwise a report, which tells whether the issuance succeeded
+pub async fn run_acme_smoke_test(domain: &str, challenge_type: Option<&str>) -> Result<AcmeSmokeTestReport, String> {
+    let tls_settings = get_cached_configuration().get_configuration().await.core.tls_settings.clone();
+    if tls_settings.account_email.is_empty() {
+        return Err("An ACME account email has to be configured first".to_string());
+    }
+    let domain = domain.trim().to_lowercase();
+    let challenge_type = challenge_type.unwrap_or(&tls_settings.acme_challenge_type).to_string();
+    validate_smoke_test_request(&domain, &challenge_type)?;
+
+    let Ok(_running) = SMOKE_TEST_RUNNING.try_lock() else {
+        return Err("Another ACME smoke test is running".to_string());
+    };
+
+    info(format!("ACME smoke test started for '{}' with {} against the staging CA", domain, challenge_type));
+    let mut report = AcmeSmokeTestReport {
+        domain: domain.clone(),
+        challenge_type,
+        directory_url: LETS_ENCRYPT_STAGING_DIRECTORY.to_string(),
+        success: false,
+        steps: Vec::new(),
+    };
+
+    let result = run_steps(&mut report, &format!("mailto:{}", tls_settings.account_email)).await;
+    SMOKE_TEST_CHALLENGES.clear();
+    report.success = result.is_some();
+
+    info(format!("ACME smoke test for '{}' {}", domain, if report.success { "succeeded" } else { "failed" }));
+    Ok(report)
+}
+
+// Each step adds itself to the report, and the test stops at the first that fails
+async fn run_steps(report: &mut AcmeSmokeTestReport, contact: &str) -> Option<()> {
+    let client_config = Arc::new(tls_config());
+    let domain = report.domain.clone();
+    let challenge_type = report.challenge_type.clone();
+
+    let started = Instant::now();
+    let directory = Directory::discover(&client_config, LETS_ENCRYPT_STAGING_DIRECTORY)
+        .await
+        .map_err(|e| format!("Failed to reach the staging CA: {}", e));
+    report.add_step("directory", started, &directory, |_| "Fetched the directory of the staging CA".to_string());
+
+    let started = Instant::now();
+    let account = Account::create(&client_config, directory.ok()?, &vec![contact.to_string()])
+        .await
+        .map_err(|e| format!("Failed to create a staging account: {}", e));
+    report.add_step("account", started, &account, |_| format!("Created a staging account for {}", contact));
+    let account = account.ok()?;
+
+    let started = Instant::now();
+    let order = account.new_order(&client_config, vec![domain.clone()]).await.map_err(|e| format!("Failed to create the order: {}", e));
+    report.add_step("order", started, &order, |(_, order)| format!("Created the order, with {} authorizations", order.authorizations.len()));
+    let (order_url, order) = order.ok()?;
+
+    let started = Instant::now();
+    let authorized = authorize(&account, &client_config, &order, &challenge_type, &SMOKE_TEST_CHALLENGES).await;
+    report.add_step("challenge", started, &authorized, |domains| format!("The CA validated {} with {}", domains.join(", "), challenge_type));
+    authorized.ok()?;
+
+    let started = Instant::now();
+    let key_pair = rcgen::KeyPair::generate();
+    let issued = match key_pair {
+        Ok(key_pair) => finalize(&account, &client_config, &order_url, std::slice::from_ref(&domain), &key_pair)
+            .await
+            .map(|certificate_url| (key_pair, certificate_url)),
+        Err(e) => Err(format!("Failed to generate the certificate key: {}", e)),
+    };
+    report.add_step("finalize", started, &issued, |_| "The CA issued the certificate".to_string());
+    let (key_pair, certificate_url) = issued.ok()?;
+
+    let started = Instant::now();
+    let certificate_chain = account
+        .certificate(&client_config, &certificate_url)
+        .await
+        .map_err(|e| format!("Failed to download the certificate: {}", e));
+    report.add_step("download", started, &certificate_chain, |_| "Downloaded the certificate chain".to_string());
+    let pem = format!("{}\n{}", key_pair.serialize_pem(), certificate_chain.ok()?);
+
+    let started = Instant::now();
+    let stored = store_in_temporary_cache(&domain, pem.as_bytes()).await;
+    report.add_step("store", started, &stored, |summary| summary.clone());
+    stored.ok()?;
+
+    Some(())
+}
+
+// Store the certificate as the ACME manager would, read it back, and remove the cache again
+async fn store_in_temporary_cache(domain: &str, pem: &[u8]) -> Result<String, String> {
+    let cache_dir = std::env::temp_dir().join(format!("gruxi-acme-smoke-test-{}", uuid::Uuid::new_v4()));
+    let result = store_and_load(DirCache::new(cache_dir.clone()), domain, pem).await;
+    let _ = tokio::fs::remove_dir_all(&cache_dir).await;
+    result
+}
+
+async fn store_and_load(cache: DirCache<std::path::PathBuf>, domain: &str, pem: &[u8]) -> Result<String, String> {
+    let domains = [domain.to_string()];
+    cache
+        .store_cert(&domains, LETS_ENCRYPT_STAGING_DIRECTORY, pem)
+        .await
+        .map_err(|e| format!("Failed to store the certificate in the temporary cache: {}", e))?;
+    let loaded = cache
+        .load_cert(&domains, LETS_ENCRYPT_STAGING_DIRECTORY)
+        .await
+        .map_err(|e| format!("Failed to load the certificate from the temporary cache: {}", e))?
+        .ok_or("The certificate was not found in the temporary cache")?;
+
+    let certificate = CertificateWithKey::from_acme_pem(&loaded)?;
+    let leaf = certificate.certificate_chain.first().ok_or("No certificates in the chain")?;
+    let (_, leaf) = x509_parser::parse_x509_certificate(leaf).map_err(|e| format!("Failed to parse the certificate: {}", e))?;
+    Ok(format!(
+        "Stored and loaded the certificate from a temporary cache, issued by {} and valid until {}",
+        leaf.issuer(),
+        leaf.validity().not_after
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tls::shared_acme_manager::{get_acme_http01_key_authorization, get_acme_http01_token};
+
+    #[test]
+    fn test_validate_smoke_test_request() {
+        assert!(validate_smoke_test_request("www.example.com", "http-01").is_ok());
+        assert!(validate_smoke_test_request("www.example.com", "tls-alpn-01").is_ok());
+        assert!(validate_smoke_test_request("www.example.com", "dns-01").is_err());
+        assert!(validate_smoke_test_request("*.example.com", "http-01").is_err());
+        assert!(validate_smoke_test_request("localhost", "http-01").is_err());
+        assert!(validate_smoke_test_request("intranet", "http-01").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http01_key_authorization() {
+        SMOKE_TEST_CHALLENGES.set_http01_key_authorization("token-1", "token-1.thumbprint".to_string());
+
+        // The bindings answer pending tokens of the smoke test for any domain, as it may not be managed by ACME yet
+        let token = get_acme_http01_token("/.well-known/acme-challenge/token-1").unwrap();
+        assert_eq!(get_acme_http01_key_authorization("www.example.com", token).await, Some("token-1.thumbprint".to_string()));
+        assert_eq!(get_acme_http01_key_authorization("www.example.com", "token-2").await, None);
+
+        SMOKE_TEST_CHALLENGES.clear();
+        assert_eq!(get_acme_http01_key_authorization("www.example.com", "token-1").await, None);
+    }
+}
@@ -0,0 +1,408 @@
+// ============================================================================
+// CERTIFICATE EXPORT
+// ============================================================================
+//
+// Exports the certificates Gruxi got from ACME, so other services on the same
+// host, such as mail servers and databases, can use them as well. The ACME
+// cache keeps each certificate as PEM, with the private key first and the
+// certificate chain after it. It is exported as PEM, or as a PKCS#12 file
+// protected by a passphrase, encrypted with PBES2 (PBKDF2 with HMAC-SHA256
+// and AES-256-CBC) and an HMAC-SHA256 integrity check, as OpenSSL 3 does.
+// AES-CBC comes from aws-lc-rs, which rustls already uses, as ring has no CBC.
+// ============================================================================
+
+use aws_lc_rs::cipher::{AES_256, EncryptionContext, PaddedBlockEncryptingKey, UnboundCipherKey};
+use ring::{digest, hmac, pbkdf2};
+use std::num::NonZeroU32;
+use yasna::models::ObjectIdentifier;
+use yasna::{DERWriter, Tag};
+
+pub const CERTIFICATE_EXPORT_FORMATS: [&str; 4] = ["pem", "cert", "key", "pkcs12"];
+
+// Iterations for the key derivations, the OpenSSL default
+const PKCS12_ITERATIONS: u32 = 2048;
+
+const OID_DATA: &[u64] = &[1, 2, 840, 113549, 1, 7, 1];
+const OID_ENCRYPTED_DATA: &[u64] = &[1, 2, 840, 113549, 1, 7, 6];
+const OID_PKCS8_SHROUDED_KEY_BAG: &[u64] = &[1, 2, 840, 113549, 1, 12, 10, 1, 2];
+const OID_CERT_BAG: &[u64] = &[1, 2, 840, 113549, 1, 12, 10, 1, 3];
+const OID_X509_CERTIFICATE: &[u64] = &[1, 2, 840, 113549, 1, 9, 22, 1];
+const OID_FRIENDLY_NAME: &[u64] = &[1, 2, 840, 113549, 1, 9, 20];
+const OID_LOCAL_KEY_ID: &[u64] = &[1, 2, 840, 113549, 1, 9, 21];
+const OID_PBES2: &[u64] = &[1, 2, 840, 113549, 1, 5, 13];
+const OID_PBKDF2: &[u64] = &[1, 2, 840, 113549, 1, 5, 12];
+const OID_HMAC_WITH_SHA256: &[u64] = &[1, 2, 840, 113549, 2, 9];
+const OID_AES_256_CBC: &[u64] = &[2, 16, 840, 1, 101, 3, 4, 1, 42];
+const OID_SHA256: &[u64] = &[2, 16, 840, 1, 101, 3, 4, 2, 1];
+
+/// A certificate with its private key, as kept in the ACME cache
+pub struct CertificateWithKey {
+    pub private_key_der: Vec<u8>,        // PKCS#8
+    pub certificate_chain: Vec<Vec<u8>>, // DER, the certificate for the domain first
+}
+
+impl CertificateWithKey {
+    /// Parse the PEM of the ACME cache, with the private key first and the certificate chain after it
+    pub fn from_acme_pem(pem_bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = std::io::Cursor::new(pem_bytes);
+        let mut private_key_der = None;
+        let mut certificate_chain = Vec::new();
+        for item in rustls_pemfile::read_all(&mut reader) {
+            match item.map_err(|e| format!("Failed to parse PEM: {}", e))? {
+                rustls_pemfile::Item::Pkcs8Key(key) => private_key_der = Some(key.secret_pkcs8_der().to_vec()),
+                rustls_pemfile::Item::X509Certificate(certificate) => certificate_chain.push(certificate.to_vec()),
+                _ => {}
+            }
+        }
+        let private_key_der = private_key_der.ok_or("No PKCS#8 private key in the PEM")?;
+        if certificate_chain.is_empty() {
+            return Err("No certificates in the PEM".to_string());
+        }
+        Ok(Self { private_key_der, certificate_chain })
+    }
+
+    pub fn get_certificate_chain_pem(&self) -> String {
+        self.certificate_chain.iter().map(|certificate| encode_pem("CERTIFICATE", certificate)).collect()
+    }
+
+    pub fn get_private_key_pem(&self) -> String {
+        encode_pem("PRIVATE KEY", &self.private_key_der)
+    }
+
+    /// The certificate chain and private key as PKCS#12, with the domain as friendly name
+    pub fn to_pkcs12(&self, passphrase: &str, friendly_name: &str) -> Result<Vec<u8>, String> {
+        if passphrase.is_empty() {
+            return Err("A passphrase is required for PKCS#12".to_string());
+        }
+
+        // The key and its certificate are paired by the hash of the certificate, as OpenSSL does
+        let local_key_id = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &self.certificate_chain[0]).as_ref().to_vec();
+
+        let certificate_bags = yasna::construct_der(|writer| {
+            writer.write_sequence_of(|writer| {
+                for (index, certificate) in self.certificate_chain.iter().enumerate() {
+                    writer.next().write_sequence(|writer| {
+                        writer.next().write_oid(&oid(OID_CERT_BAG));
+                        writer.next().write_tagged(Tag::context(0), |writer| {
+                            writer.write_sequence(|writer| {
+                                writer.next().write_oid(&oid(OID_X509_CERTIFICATE));
+                                writer.next().write_tagged(Tag::context(0), |writer| writer.write_bytes(certificate));
+                            });
+                        });
+                        if index == 0 {
+                            write_bag_attributes(writer.next(), friendly_name, &local_key_id);
+                        }
+                    });
+                }
+            });
+        });
+
+        let (key_algorithm, encrypted_key) = encrypt_pbes2(passphrase, &self.private_key_der)?;
+        let key_bags = yasna::construct_der(|writer| {
+            writer.write_sequence_of(|writer| {
+                writer.next().write_sequence(|writer| {
+                    writer.next().write_oid(&oid(OID_PKCS8_SHROUDED_KEY_BAG));
+                    writer.next().write_tagged(Tag::context(0), |writer| {
+                        writer.write_sequence(|writer| {
+                            writer.next().write_der(&key_algorithm);
+                            writer.next().write_bytes(&encrypted_key);
+                        });
+                    });
+                    write_bag_attributes(writer.next(), friendly_name, &local_key_id);
+                });
+            });
+        });
+
+        // The certificates are encrypted as a whole, the key is encrypted in its bag
+        let (certificates_algorithm, encrypted_certificates) = encrypt_pbes2(passphrase, &certificate_bags)?;
+        let authenticated_safe = yasna::construct_der(|writer| {
+            writer.write_sequence_of(|writer| {
+                writer.next().write_sequence(|writer| {
+                    writer.next().write_oid(&oid(OID_ENCRYPTED_DATA));
+                    writer.next().write_tagged(Tag::context(0), |writer| {
+                        writer.write_sequence(|writer| {
+                            writer.next().write_u8(0);
+                            writer.next().write_sequence(|writer| {
+                                writer.next().write_oid(&oid(OID_DATA));
+                                writer.next().write_der(&certificates_algorithm);
+                                writer.next().write_tagged_implicit(Tag::context(0), |writer| writer.write_bytes(&encrypted_certificates));
+                            });
+                        });
+                    });
+                });
+                writer.next().write_sequence(|writer| {
+                    writer.next().write_oid(&oid(OID_DATA));
+                    writer.next().write_tagged(Tag::context(0), |writer| writer.write_bytes(&key_bags));
+                });
+            });
+        });
+
+        let mac_salt: [u8; 16] = rand::random();
+        let mac_key = derive_pkcs12_mac_key(passphrase, &mac_salt, PKCS12_ITERATIONS);
+        let mac = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &mac_key), &authenticated_safe);
+
+        Ok(yasna::construct_der(|writer| {
+            writer.write_sequence(|writer| {
+                writer.next().write_u8(3);
+                writer.next().write_sequence(|writer| {
+                    writer.next().write_oid(&oid(OID_DATA));
+                    writer.next().write_tagged(Tag::context(0), |writer| writer.write_bytes(&authenticated_safe));
+                });
+                writer.next().write_sequence(|writer| {
+                    writer.next().write_sequence(|writer| {
+                        write_algorithm_without_parameters(writer.next(), OID_SHA256);
+                        writer.next().write_bytes(mac.as_ref());
+                    });
+                    writer.next().write_bytes(&mac_salt);
+                    writer.next().write_u32(PKCS12_ITERATIONS);
+                });
+            });
+        }))
+    }
+}
+
+fn oid(components: &[u64]) -> ObjectIdentifier {
+    ObjectIdentifier::from_slice(components)
+}
+
+pub fn encode_pem(label: &str, der: &[u8]) -> String {
+    use base64::Engine;
+    let encoded = base64::engine::general_purpose::STANDARD.encode(der);
+    let lines: Vec<&str> = encoded.as_bytes().chunks(64).map(|line| std::str::from_utf8(line).unwrap_or_default()).collect();
+    format!("-----BEGIN {}-----\n{}\n-----END {}-----\n", label, lines.join("\n"), label)
+}
+
+fn write_algorithm_without_parameters(writer: DERWriter, algorithm: &[u64]) {
+    writer.write_sequence(|writer| {
+        writer.next().write_oid(&oid(algorithm));
+        writer.next().write_null();
+    });
+}
+
+fn write_bag_attributes(writer: DERWriter, friendly_name: &str, local_key_id: &[u8]) {
+    writer.write_set(|writer| {
+        writer.next().write_sequence(|writer| {
+            writer.next().write_oid(&oid(OID_FRIENDLY_NAME));
+            writer.next().write_set(|writer| writer.next().write_bmp_string(friendly_name));
+        });
+        writer.next().write_sequence(|writer| {
+            writer.next().write_oid(&oid(OID_LOCAL_KEY_ID));
+            writer.next().write_set(|writer| writer.next().write_bytes(local_key_id));
+        });
+    });
+}
+
+// Encrypt with PBES2, returning the DER of the algorithm identifier with its parameters, and the encrypted data
+fn encrypt_pbes2(passphrase: &str, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let salt: [u8; 16] = rand::random();
+    let iv: [u8; 16] = rand::random();
+
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(PKCS12_ITERATIONS).unwrap_or(NonZeroU32::MIN),
+        &salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    let cipher_key = UnboundCipherKey::new(&AES_256, &key).map_err(|_| "Failed to create encryption key".to_string())?;
+    let encrypting_key = PaddedBlockEncryptingKey::cbc_pkcs7(cipher_key).map_err(|_| "Failed to create cipher".to_string())?;
+    let mut encrypted = data.to_vec();
+    encrypting_key
+        .less_safe_encrypt(&mut encrypted, EncryptionContext::Iv128(iv.into()))
+        .map_err(|_| "Failed to encrypt".to_string())?;
+
+    let algorithm = yasna::construct_der(|writer| {
+        writer.write_sequence(|writer| {
+            writer.next().write_oid(&oid(OID_PBES2));
+            writer.next().write_sequence(|writer| {
+                writer.next().write_sequence(|writer| {
+                    writer.next().write_oid(&oid(OID_PBKDF2));
+                    writer.next().write_sequence(|writer| {
+                        writer.next().write_bytes(&salt);
+                        writer.next().write_u32(PKCS12_ITERATIONS);
+                        write_algorithm_without_parameters(writer.next(), OID_HMAC_WITH_SHA256);
+                    });
+                });
+                writer.next().write_sequence(|writer| {
+                    writer.next().write_oid(&oid(OID_AES_256_CBC));
+                    writer.next().write_bytes(&iv);
+                });
+            });
+        });
+    });
+    Ok((algorithm, encrypted))
+}
+
+// The key for the integrity check, derived as in RFC 7292 appendix B with SHA-256, from the passphrase as a
+// null terminated BMPString
+fn derive_pkcs12_mac_key(passphrase: &str, salt: &[u8], iterations: u32) -> Vec<u8> {
+    const BLOCK_LENGTH: usize = 64;
+    const HASH_LENGTH: usize = 32;
+
+    let mut password: Vec<u8> = passphrase.encode_utf16().flat_map(|unit| unit.to_be_bytes()).collect();
+    password.extend_from_slice(&[0, 0]);
+
+    let fill = |input: &[u8]| -> Vec<u8> {
+        let length = input.len().div_ceil(BLOCK_LENGTH) * BLOCK_LENGTH;
+        input.iter().cycle().take(length).copied().collect()
+    };
+    let diversifier = [3u8; BLOCK_LENGTH];
+    let input = [fill(salt), fill(&password)].concat();
+
+    let mut hash = digest::digest(&digest::SHA256, &[&diversifier[..], &input].concat()).as_ref().to_vec();
+    for _ in 1..iterations {
+        hash = digest::digest(&digest::SHA256, &hash).as_ref().to_vec();
+    }
+    // One round of hashing gives the 32 bytes needed for HMAC-SHA256, so the input does not have to be updated
+    hash.truncate(HASH_LENGTH);
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_lc_rs::cipher::{DecryptionContext, PaddedBlockDecryptingKey};
+    use yasna::ASN1Result;
+
+    // Decrypt data encrypted by encrypt_pbes2(), with the DER of its algorithm identifier
+    fn decrypt_pbes2(passphrase: &str, algorithm: &[u8], data: &[u8]) -> Vec<u8> {
+        let (salt, iterations, iv) = yasna::parse_der(algorithm, |reader| {
+            reader.read_sequence(|reader| {
+                assert_eq!(reader.next().read_oid()?, oid(OID_PBES2));
+                reader.next().read_sequence(|reader| {
+                    let (salt, iterations) = reader.next().read_sequence(|reader| {
+                        assert_eq!(reader.next().read_oid()?, oid(OID_PBKDF2));
+                        reader.next().read_sequence(|reader| {
+                            let salt = reader.next().read_bytes()?;
+                            let iterations = reader.next().read_u32()?;
+                            reader.next().read_der()?;
+                            Ok((salt, iterations))
+                        })
+                    })?;
+                    let iv = reader.next().read_sequence(|reader| {
+                        assert_eq!(reader.next().read_oid()?, oid(OID_AES_256_CBC));
+                        reader.next().read_bytes()
+                    })?;
+                    Ok((salt, iterations, iv))
+                })
+            })
+        })
+        .unwrap();
+
+        let mut key = [0u8; 32];
+        pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, NonZeroU32::new(iterations).unwrap(), &salt, passphrase.as_bytes(), &mut key);
+        let decrypting_key = PaddedBlockDecryptingKey::cbc_pkcs7(UnboundCipherKey::new(&AES_256, &key).unwrap()).unwrap();
+        let mut decrypted = data.to_vec();
+        let iv: [u8; 16] = iv.try_into().unwrap();
+        decrypting_key.decrypt(&mut decrypted, DecryptionContext::Iv128(iv.into())).unwrap().to_vec()
+    }
+
+    // Read a bag of the form SEQUENCE { type, [0] value, attributes }, returning the DER of the value
+    fn read_bag(reader: yasna::BERReader, bag_type: &[u64]) -> ASN1Result<Vec<u8>> {
+        reader.read_sequence(|reader| {
+            assert_eq!(reader.next().read_oid()?, oid(bag_type));
+            let value = reader.next().read_tagged(Tag::context(0), |reader| reader.read_der())?;
+            reader.read_optional(|reader| reader.read_der())?;
+            Ok(value)
+        })
+    }
+
+    // The certificates and private key of a PKCS#12 file, after checking its integrity
+    fn parse_pkcs12(pkcs12: &[u8], passphrase: &str) -> (Vec<Vec<u8>>, Vec<u8>) {
+        let (authenticated_safe, mac, mac_salt, iterations) = yasna::parse_der(pkcs12, |reader| {
+            reader.read_sequence(|reader| {
+                assert_eq!(reader.next().read_u8()?, 3);
+                let authenticated_safe = reader.next().read_sequence(|reader| {
+                    assert_eq!(reader.next().read_oid()?, oid(OID_DATA));
+                    reader.next().read_tagged(Tag::context(0), |reader| reader.read_bytes())
+                })?;
+                let (mac, mac_salt, iterations) = reader.next().read_sequence(|reader| {
+                    let mac = reader.next().read_sequence(|reader| {
+                        reader.next().read_der()?;
+                        reader.next().read_bytes()
+                    })?;
+                    Ok((mac, reader.next().read_bytes()?, reader.next().read_u32()?))
+                })?;
+                Ok((authenticated_safe, mac, mac_salt, iterations))
+            })
+        })
+        .unwrap();
+        let mac_key = derive_pkcs12_mac_key(passphrase, &mac_salt, iterations);
+        hmac::verify(&hmac::Key::new(hmac::HMAC_SHA256, &mac_key), &authenticated_safe, &mac).expect("The integrity check should pass");
+
+        let (certificates_algorithm, encrypted_certificates, key_bags) = yasna::parse_der(&authenticated_safe, |reader| {
+            reader.read_sequence(|reader| {
+                let (certificates_algorithm, encrypted_certificates) = reader.next().read_sequence(|reader| {
+                    assert_eq!(reader.next().read_oid()?, oid(OID_ENCRYPTED_DATA));
+                    reader.next().read_tagged(Tag::context(0), |reader| {
+                        reader.read_sequence(|reader| {
+                            assert_eq!(reader.next().read_u8()?, 0);
+                            reader.next().read_sequence(|reader| {
+                                assert_eq!(reader.next().read_oid()?, oid(OID_DATA));
+                                let algorithm = reader.next().read_der()?;
+                                Ok((algorithm, reader.next().read_tagged_implicit(Tag::context(0), |reader| reader.read_bytes())?))
+                            })
+                        })
+                    })
+                })?;
+                let key_bags = reader.next().read_sequence(|reader| {
+                    assert_eq!(reader.next().read_oid()?, oid(OID_DATA));
+                    reader.next().read_tagged(Tag::context(0), |reader| reader.read_bytes())
+                })?;
+                Ok((certificates_algorithm, encrypted_certificates, key_bags))
+            })
+        })
+        .unwrap();
+
+        let certificate_bags = decrypt_pbes2(passphrase, &certificates_algorithm, &encrypted_certificates);
+        let certificates = yasna::parse_der(&certificate_bags, |reader| {
+            reader.collect_sequence_of(|reader| {
+                let certificate_bag = read_bag(reader, OID_CERT_BAG)?;
+                yasna::parse_der(&certificate_bag, |reader| {
+                    reader.read_sequence(|reader| {
+                        assert_eq!(reader.next().read_oid()?, oid(OID_X509_CERTIFICATE));
+                        reader.next().read_tagged(Tag::context(0), |reader| reader.read_bytes())
+                    })
+                })
+            })
+        })
+        .unwrap();
+
+        let mut key_bags = yasna::parse_der(&key_bags, |reader| reader.collect_sequence_of(|reader| read_bag(reader, OID_PKCS8_SHROUDED_KEY_BAG))).unwrap();
+        assert_eq!(key_bags.len(), 1);
+        let (key_algorithm, encrypted_key) = yasna::parse_der(&key_bags.remove(0), |reader| {
+            reader.read_sequence(|reader| Ok((reader.next().read_der()?, reader.next().read_bytes()?)))
+        })
+        .unwrap();
+        (certificates, decrypt_pbes2(passphrase, &key_algorithm, &encrypted_key))
+    }
+
+    #[test]
+    fn test_pkcs12_export() {
+        let rcgen::CertifiedKey { cert, signing_key } = rcgen::generate_simple_self_signed(vec!["example.com".to_string()]).unwrap();
+        let acme_pem = format!("{}{}", signing_key.serialize_pem(), cert.pem());
+        let certificate = CertificateWithKey::from_acme_pem(acme_pem.as_bytes()).unwrap();
+        assert_eq!(certificate.certificate_chain.len(), 1);
+        assert_eq!(certificate.get_certificate_chain_pem().trim(), cert.pem().trim());
+        assert_eq!(certificate.get_private_key_pem().trim(), signing_key.serialize_pem().trim());
+
+        assert!(certificate.to_pkcs12("", "example.com").is_err());
+        let pkcs12 = certificate.to_pkcs12("secret", "example.com").unwrap();
+        let (certificates, private_key_der) = parse_pkcs12(&pkcs12, "secret");
+        assert_eq!(certificates, certificate.certificate_chain);
+        assert_eq!(private_key_der, certificate.private_key_der);
+        assert_eq!(private_key_der, signing_key.serialize_der());
+
+        assert!(CertificateWithKey::from_acme_pem(cert.pem().as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_pkcs12_mac_key() {
+        // Same as "openssl kdf -keylen 32 -kdfopt digest:SHA256 -kdfopt hexpass:0073006500630072006500740000
+        // -kdfopt hexsalt:0102030405060708 -kdfopt iter:2048 -kdfopt id:3 PKCS12KDF", with "secret" as BMPString
+        let key = derive_pkcs12_mac_key("secret", &[1, 2, 3, 4, 5, 6, 7, 8], 2048);
+        let hex: String = key.iter().map(|byte| format!("{:02x}", byte)).collect();
+        assert_eq!(hex, "f5482fd03f702689b4e96cbbea867c6b16e5bda934929f2ecaf4da9e1c67be8f");
+    }
+}
@@ -0,0 +1,298 @@
+// ============================================================================
+// INSTALLED CERTIFICATES
+// ============================================================================
+//
+// Certificates uploaded through the admin portal, for sites that cannot use
+// ACME, such as those with certificates from an internal or commercial CA.
+// Each is kept as a PEM certificate chain and key pair in certs/installed,
+// named by its id, and is used by a site by pointing its TLS paths at them.
+//
+// Uploads are checked before anything is written, so a site never ends up with
+// a key that does not belong to its certificate, a chain in the wrong order or
+// a certificate that has already expired. Uploading with the id of an installed
+// certificate replaces it, which is how a renewed certificate is installed for
+// the sites using it.
+// ============================================================================
+
+use std::io::BufReader;
+
+use chrono::{DateTime, Utc};
+use rustls::crypto::aws_lc_rs;
+use rustls::sign::CertifiedKey;
+use rustls_pki_types::CertificateDer;
+use serde::Serialize;
+use x509_parser::extensions::GeneralName;
+use x509_parser::prelude::X509Certificate;
+
+use crate::configuration::site::Site;
+use crate::tls::dev_ca::write_private_file;
+
+pub const INSTALLED_CERTIFICATES_DIRECTORY: &str = "certs/installed";
+
+#[derive(Clone, Debug, Serialize)]
+pub struct InstalledCertificate {
+    pub id: String,
+    pub subject: String,
+    pub issuer: String,
+    pub sans: Vec<String>,
+    pub not_before: String,
+    pub not_after: String,
+    pub is_expired: bool,
+    pub chain_length: usize,
+    pub cert_path: String,
+    pub key_path: String,
+    pub site_ids: Vec<String>, // Sites that use the certificate
+}
+
+impl InstalledCertificate {
+    fn from_chain(id: &str, chain: &[CertificateDer<'static>]) -> Result<Self, String> {
+        let leaf = chain.first().ok_or_else(|| "No certificate found in PEM content".to_string())?;
+        let (_, certificate) = x509_parser::parse_x509_certificate(leaf).map_err(|e| format!("Failed to parse certificate: {}", e))?;
+        let not_after = get_time(certificate.validity().not_after.timestamp());
+        Ok(Self {
+            id: id.to_string(),
+            subject: certificate.subject().to_string(),
+            issuer: certificate.issuer().to_string(),
+            sans: get_sans(&certificate),
+            not_before: get_time(certificate.validity().not_before.timestamp()).to_rfc3339(),
+            not_after: not_after.to_rfc3339(),
+            is_expired: not_after < Utc::now(),
+            chain_length: chain.len(),
+            cert_path: get_cert_path(id),
+            key_path: get_key_path(id),
+            site_ids: Vec::new(),
+        })
+    }
+
+    pub fn is_used_by(&self, site: &Site) -> bool {
+        site.tls_cert_path == self.cert_path
+    }
+}
+
+fn get_time(timestamp: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(timestamp, 0).unwrap_or_default()
+}
+
+fn get_sans(certificate: &X509Certificate) -> Vec<String> {
+    let Ok(Some(san_extension)) = certificate.subject_alternative_name() else {
+        return Vec::new();
+    };
+    san_extension
+        .value
+        .general_names
+        .iter()
+        .filter_map(|name| match name {
+            GeneralName::DNSName(dns_name) => Some(dns_name.to_string()),
+            GeneralName::IPAddress(bytes) => match bytes.len() {
+                4 => Some(std::net::IpAddr::from(<[u8; 4]>::try_from(*bytes).ok()?).to_string()),
+                16 => Some(std::net::IpAddr::from(<[u8; 16]>::try_from(*bytes).ok()?).to_string()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+fn get_cert_path(id: &str) -> String {
+    format!("{}/{}.crt.pem", INSTALLED_CERTIFICATES_DIRECTORY, id)
+}
+
+fn get_key_path(id: &str) -> String {
+    format!("{}/{}.key.pem", INSTALLED_CERTIFICATES_DIRECTORY, id)
+}
+
+// Ids are used as file names, so they are limited to characters that are safe in them on all platforms
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 100 && !id.starts_with('.') && id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' || c == '-' || c == '_')
+}
+
+fn parse_certificate_chain(cert_pem: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let chain = rustls_pemfile::certs(&mut BufReader::new(cert_pem.as_bytes()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Failed to parse certificate PEM content: {}", e))?;
+    if chain.is_empty() {
+        return Err("No certificate found in PEM content".to_string());
+    }
+    Ok(chain)
+}
+
+/// Check that the key belongs to the first certificate of the chain, that each certificate is issued by the one after
+/// it, and that none have expired. Returns the parsed chain, or all problems found
+pub fn validate_certificate_pair(cert_pem: &str, key_pem: &str) -> Result<Vec<CertificateDer<'static>>, Vec<String>> {
+    let chain = parse_certificate_chain(cert_pem).map_err(|e| vec![e])?;
+    let mut errors = Vec::new();
+
+    match rustls_pemfile::private_key(&mut BufReader::new(key_pem.as_bytes())) {
+        Ok(Some(key)) => {
+            if let Err(e) = CertifiedKey::from_der(chain.clone(), key, &aws_lc_rs::default_provider()) {
+                errors.push(format!("Private key does not match the certificate: {}", e));
+            }
+        }
+        Ok(None) => errors.push("No private key found in PEM content".to_string()),
+        Err(e) => errors.push(format!("Failed to parse private key PEM content: {}", e)),
+    }
+
+    let mut certificates = Vec::new();
+    for (index, der) in chain.iter().enumerate() {
+        match x509_parser::parse_x509_certificate(der) {
+            Ok((_, certificate)) => certificates.push(certificate),
+            Err(e) => {
+                errors.push(format!("Failed to parse certificate {} of the chain: {}", index + 1, e));
+                return Err(errors);
+            }
+        }
+    }
+
+    let now = Utc::now().timestamp();
+    for (index, certificate) in certificates.iter().enumerate() {
+        let validity = certificate.validity();
+        if validity.not_after.timestamp() < now {
+            errors.push(format!("Certificate '{}' expired on {}", certificate.subject(), get_time(validity.not_after.timestamp()).to_rfc3339()));
+        } else if validity.not_before.timestamp() > now {
+            errors.push(format!(
+                "Certificate '{}' is not valid until {}",
+                certificate.subject(),
+                get_time(validity.not_before.timestamp()).to_rfc3339()
+            ));
+        }
+        if let Some(issuer) = certificates.get(index + 1)
+            && certificate.issuer().as_raw() != issuer.subject().as_raw()
+        {
+            errors.push(format!(
+                "Certificate {} of the chain is issued by '{}', but is followed by '{}'. The chain must start with the site certificate, followed by the certificate that issued it, and so on",
+                index + 1,
+                certificate.issuer(),
+                issuer.subject()
+            ));
+        }
+    }
+
+    if errors.is_empty() { Ok(chain) } else { Err(errors) }
+}
+
+/// Validate and install a certificate chain and key pair, replacing an installed certificate with the same id
+pub fn install_certificate(id: &str, cert_pem: &str, key_pem: &str) -> Result<InstalledCertificate, Vec<String>> {
+    let id = id.trim().to_lowercase();
+    if !is_valid_id(&id) {
+        return Err(vec![format!(
+            "Certificate id '{}' must be 1 to 100 characters of a-z, 0-9, '.', '-' and '_', and cannot start with '.'",
+            id
+        )]);
+    }
+    let chain = validate_certificate_pair(cert_pem, key_pem)?;
+    let installed_certificate = InstalledCertificate::from_chain(&id, &chain).map_err(|e| vec![e])?;
+
+    std::fs::create_dir_all(INSTALLED_CERTIFICATES_DIRECTORY).map_err(|e| vec![format!("Failed to create directory '{}': {}", INSTALLED_CERTIFICATES_DIRECTORY, e)])?;
+    write_private_file(&installed_certificate.key_path, key_pem).map_err(|e| vec![e])?;
+    std::fs::write(&installed_certificate.cert_path, cert_pem).map_err(|e| vec![format!("Failed to write '{}': {}", installed_certificate.cert_path, e)])?;
+
+    Ok(installed_certificate)
+}
+
+/// The installed certificates, with the sites that use them
+pub fn list_installed_certificates(sites: &[Site]) -> Result<Vec<InstalledCertificate>, String> {
+    let entries = match std::fs::read_dir(INSTALLED_CERTIFICATES_DIRECTORY) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read directory '{}': {}", INSTALLED_CERTIFICATES_DIRECTORY, e)),
+    };
+
+    let mut ids: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str().and_then(|name| name.strip_suffix(".crt.pem")).map(|id| id.to_string()))
+        .filter(|id| is_valid_id(id))
+        .collect();
+    ids.sort();
+
+    let mut installed_certificates = Vec::new();
+    for id in ids {
+        if let Some(installed_certificate) = get_installed_certificate(&id, sites)? {
+            installed_certificates.push(installed_certificate);
+        }
+    }
+    Ok(installed_certificates)
+}
+
+pub fn get_installed_certificate(id: &str, sites: &[Site]) -> Result<Option<InstalledCertificate>, String> {
+    if !is_valid_id(id) || !std::path::Path::new(&get_cert_path(id)).is_file() {
+        return Ok(None);
+    }
+    let cert_pem = std::fs::read_to_string(get_cert_path(id)).map_err(|e| format!("Failed to read '{}': {}", get_cert_path(id), e))?;
+    let mut installed_certificate = InstalledCertificate::from_chain(id, &parse_certificate_chain(&cert_pem)?)?;
+    installed_certificate.site_ids = sites.iter().filter(|site| installed_certificate.is_used_by(site)).map(|site| site.id.clone()).collect();
+    Ok(Some(installed_certificate))
+}
+
+/// When the site certificate of a PEM certificate chain file expires, such as the TLS certificate of a site
+pub fn get_certificate_expiry(cert_path: &str) -> Result<DateTime<Utc>, String> {
+    let cert_pem = std::fs::read_to_string(cert_path).map_err(|e| format!("Failed to read '{}': {}", cert_path, e))?;
+    let chain = parse_certificate_chain(&cert_pem)?;
+    let (_, certificate) = x509_parser::parse_x509_certificate(&chain[0]).map_err(|e| format!("Failed to parse certificate: {}", e))?;
+    Ok(get_time(certificate.validity().not_after.timestamp()))
+}
+
+/// Remove an installed certificate, which must no longer be used by any site
+pub fn delete_installed_certificate(installed_certificate: &InstalledCertificate) -> Result<(), String> {
+    if !installed_certificate.site_ids.is_empty() {
+        return Err(format!("Certificate is still used by site(s) {}", installed_certificate.site_ids.join(", ")));
+    }
+    std::fs::remove_file(&installed_certificate.cert_path).map_err(|e| format!("Failed to delete '{}': {}", installed_certificate.cert_path, e))?;
+    std::fs::remove_file(&installed_certificate.key_path).map_err(|e| format!("Failed to delete '{}': {}", installed_certificate.key_path, e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, Issuer, KeyPair};
+
+    fn create_certificate(common_name: &str, is_ca: bool, issuer: Option<&Issuer<'_, KeyPair>>, expired: bool) -> (String, KeyPair, CertificateParams) {
+        let key_pair = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(if is_ca { Vec::new() } else { vec![common_name.to_string(), "127.0.0.1".to_string()] }).unwrap();
+        params.distinguished_name.push(DnType::CommonName, common_name);
+        if is_ca {
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        }
+        if expired {
+            params.not_before = rcgen::date_time_ymd(2020, 1, 1);
+            params.not_after = rcgen::date_time_ymd(2021, 1, 1);
+        }
+        let certificate = match issuer {
+            Some(issuer) => params.signed_by(&key_pair, issuer).unwrap(),
+            None => params.self_signed(&key_pair).unwrap(),
+        };
+        (certificate.pem(), key_pair, params)
+    }
+
+    #[test]
+    fn test_validate_certificate_pair() {
+        let (ca_pem, ca_key, ca_params) = create_certificate("Test CA", true, None, false);
+        let ca = Issuer::new(ca_params, ca_key);
+        let (leaf_pem, leaf_key, _) = create_certificate("example.com", false, Some(&ca), false);
+        let key_pem = leaf_key.serialize_pem();
+
+        let chain = validate_certificate_pair(&format!("{}{}", leaf_pem, ca_pem), &key_pem).unwrap();
+        let installed_certificate = InstalledCertificate::from_chain("example.com", &chain).unwrap();
+        assert_eq!(installed_certificate.sans, vec!["example.com".to_string(), "127.0.0.1".to_string()]);
+        assert_eq!(installed_certificate.chain_length, 2);
+        assert!(!installed_certificate.is_expired);
+
+        // Chain in the wrong order
+        assert_eq!(validate_certificate_pair(&format!("{}{}", ca_pem, leaf_pem), &key_pem).unwrap_err().len(), 2);
+
+        // Key of another certificate
+        let (_, other_key, _) = create_certificate("other.example.com", false, Some(&ca), false);
+        let errors = validate_certificate_pair(&leaf_pem, &other_key.serialize_pem()).unwrap_err();
+        assert!(errors[0].starts_with("Private key does not match"), "{:?}", errors);
+
+        // Expired certificate
+        let (expired_pem, expired_key, _) = create_certificate("expired.example.com", false, Some(&ca), true);
+        let errors = validate_certificate_pair(&expired_pem, &expired_key.serialize_pem()).unwrap_err();
+        assert!(errors[0].contains("expired on 2021-01-01"), "{:?}", errors);
+
+        assert!(validate_certificate_pair("", &key_pem).is_err());
+        assert!(is_valid_id("example.com-2025"));
+        assert!(!is_valid_id("../example"));
+        assert!(!is_valid_id("Example"));
+    }
+}
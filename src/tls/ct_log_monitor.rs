@@ -0,0 +1,310 @@
+// ============================================================================
+// CERTIFICATE TRANSPARENCY LOG MONITOR
+// ============================================================================
+//
+// Optional background monitor that polls the certificate transparency (CT) logs,
+// through the crt.sh search service, for certificates issued for the configured
+// hostnames. Any newly issued certificate that does not originate from this Gruxi
+// instance (not found in the ACME certificate cache or the manually configured
+// certificates) is alerted on, through the log and optionally a webhook.
+// This catches misissuance and shadow infrastructure issuing for our domains.
+// ============================================================================
+
+use std::collections::{BTreeSet, HashSet};
+use std::io::BufReader;
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use serde::Deserialize;
+use tokio_util::sync::CancellationToken;
+
+use crate::configuration::site::Site;
+use crate::core::running_state_manager::get_running_state_manager;
+use crate::core::triggers::get_trigger_handler;
+use crate::http::site_match::hostname_pattern::is_regex_hostname;
+use crate::logging::syslog::{debug, error, info, trace, warn};
+
+// Base URL for the crt.sh CT log search
+const CT_SEARCH_BASE_URL: &str = "https://crt.sh/";
+
+// Timeout for each request to crt.sh or the webhook
+const CT_REQUEST_TIMEOUT_SECS: u64 = 60;
+
+// Pause between the queries for each hostname, to be nice to crt.sh
+const CT_QUERY_PAUSE_SECS: u64 = 2;
+
+#[derive(Debug, Deserialize)]
+struct CtLogEntry {
+    id: u64,
+    #[serde(default)]
+    issuer_name: String,
+    #[serde(default)]
+    name_value: String,
+    #[serde(default)]
+    not_before: String,
+    #[serde(default)]
+    serial_number: String,
+}
+
+/// Start the CT log monitor, if enabled in the configuration. The monitor stops on
+/// shutdown or stop_services triggers, so it is started again on configuration reload.
+pub async fn start_ct_log_monitor() {
+    let cached_configuration = crate::configuration::cached_configuration::get_cached_configuration();
+    let config = cached_configuration.get_configuration().await;
+
+    let tls_settings = &config.core.tls_settings;
+    if !tls_settings.ct_monitoring_enabled {
+        debug("Certificate transparency monitoring is not enabled");
+        return;
+    }
+
+    let sites: Vec<Site> = config.sites.iter().filter(|s| s.is_enabled).cloned().collect();
+    let hostnames = get_monitored_hostnames(&sites);
+    if hostnames.is_empty() {
+        debug("Certificate transparency monitoring not started: no public hostnames configured");
+        return;
+    }
+
+    let cache_dir = if tls_settings.certificate_cache_path.trim().is_empty() {
+        "certs/cache".to_string()
+    } else {
+        tls_settings.certificate_cache_path.trim().to_string()
+    };
+    let interval_secs = tls_settings.ct_monitoring_interval_minutes.max(15) as u64 * 60;
+    let webhook_url = tls_settings.ct_monitoring_webhook_url.clone();
+
+    let triggers = get_trigger_handler();
+    let shutdown_token = triggers.get_token("shutdown").await.unwrap_or_else(CancellationToken::new);
+    let stop_services_token = triggers.get_token("stop_services").await.unwrap_or_else(CancellationToken::new);
+
+    info(format!(
+        "Certificate transparency monitoring started for {} hostnames, polling every {} minutes",
+        hostnames.len(),
+        interval_secs / 60
+    ));
+
+    tokio::spawn(async move {
+        // Only certificates issued after this point are alerted on, so old certificates do not cause noise on startup
+        let alert_after = Utc::now().naive_utc() - chrono::Duration::seconds(interval_secs as i64);
+        let mut seen_entry_ids: HashSet<u64> = HashSet::new();
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+
+        loop {
+            tokio::select! {
+                _ = shutdown_token.cancelled() => {
+                    debug("Certificate transparency monitor stopping due to shutdown signal");
+                    break;
+                }
+                _ = stop_services_token.cancelled() => {
+                    debug("Certificate transparency monitor stopping due to stop_services signal");
+                    break;
+                }
+                _ = interval.tick() => {
+                    check_ct_logs(&hostnames, &sites, &cache_dir, &webhook_url, alert_after, &mut seen_entry_ids).await;
+                }
+            }
+        }
+    });
+}
+
+// Get the hostnames that can have publicly trusted certificates
+fn get_monitored_hostnames(sites: &[Site]) -> BTreeSet<String> {
+    sites
+        .iter()
+        .flat_map(|s| s.hostnames.iter())
+        .map(|h| h.trim().to_lowercase())
+        .filter(|h| !h.is_empty() && !h.contains('*') && !is_regex_hostname(h) && h != "localhost" && h.contains('.') && h.parse::<std::net::IpAddr>().is_err())
+        .collect()
+}
+
+async fn check_ct_logs(hostnames: &BTreeSet<String>, sites: &[Site], cache_dir: &str, webhook_url: &str, alert_after: NaiveDateTime, seen_entry_ids: &mut HashSet<u64>) {
+    let local_serials = load_local_certificate_serials(sites, cache_dir).await;
+    trace(format!("Certificate transparency check found {} local certificate serials", local_serials.len()));
+
+    for hostname in hostnames {
+        let entries = match fetch_ct_log_entries(hostname).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                debug(format!("Certificate transparency lookup for '{}' failed: {}", hostname, e));
+                continue;
+            }
+        };
+
+        for entry in entries {
+            if !seen_entry_ids.insert(entry.id) {
+                continue;
+            }
+
+            let is_recent = NaiveDateTime::parse_from_str(&entry.not_before, "%Y-%m-%dT%H:%M:%S").map(|nb| nb > alert_after).unwrap_or(false);
+            if !is_recent || local_serials.contains(&normalize_serial(&entry.serial_number)) {
+                continue;
+            }
+
+            let names = entry.name_value.replace('\n', ", ");
+            warn(format!(
+                "Certificate transparency: unknown certificate issued for '{}' by '{}' (names: {}, serial: {}, not before: {}, https://crt.sh/?id={})",
+                hostname, entry.issuer_name, names, entry.serial_number, entry.not_before, entry.id
+            ));
+
+            if !webhook_url.is_empty() {
+                send_webhook_alert(webhook_url, hostname, &entry).await;
+            }
+        }
+
+        tokio::time::sleep(Duration::from_secs(CT_QUERY_PAUSE_SECS)).await;
+    }
+}
+
+async fn fetch_ct_log_entries(hostname: &str) -> Result<Vec<CtLogEntry>, String> {
+    let uri = format!("{}?q={}&output=json&exclude=expired", CT_SEARCH_BASE_URL, urlencoding::encode(hostname));
+    let uri: hyper::Uri = uri.parse().map_err(|e| format!("Invalid CT search URI: {}", e))?;
+
+    let client = {
+        let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+        running_state.get_http_client().get_client(true)
+    };
+
+    let response = tokio::time::timeout(Duration::from_secs(CT_REQUEST_TIMEOUT_SECS), client.get(uri))
+        .await
+        .map_err(|_| "Request timed out".to_string())?
+        .map_err(|e| format!("Request failed: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("Unexpected status code {}", response.status()));
+    }
+
+    let body = tokio::time::timeout(Duration::from_secs(CT_REQUEST_TIMEOUT_SECS), response.into_body().collect())
+        .await
+        .map_err(|_| "Reading response timed out".to_string())?
+        .map_err(|e| format!("Failed to read response: {}", e))?
+        .to_bytes();
+
+    serde_json::from_slice(&body).map_err(|e| format!("Failed to parse response: {}", e))
+}
+
+async fn send_webhook_alert(webhook_url: &str, hostname: &str, entry: &CtLogEntry) {
+    let payload = serde_json::json!({
+        "event": "unknown_certificate_issued",
+        "hostname": hostname,
+        "issuer": entry.issuer_name,
+        "names": entry.name_value.split('\n').collect::<Vec<&str>>(),
+        "serial_number": entry.serial_number,
+        "not_before": entry.not_before,
+        "crt_sh_id": entry.id,
+    });
+
+    let request = hyper::Request::builder()
+        .method(hyper::Method::POST)
+        .uri(webhook_url)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(payload.to_string())).map_err(|never| match never {}).boxed());
+    let request = match request {
+        Ok(r) => r,
+        Err(e) => {
+            error(format!("Failed to build certificate transparency webhook request: {}", e));
+            return;
+        }
+    };
+
+    let client = {
+        let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
+        running_state.get_http_client().get_client(true)
+    };
+
+    match tokio::time::timeout(Duration::from_secs(CT_REQUEST_TIMEOUT_SECS), client.request(request)).await {
+        Ok(Ok(response)) if response.status().is_success() => {
+            trace(format!("Certificate transparency webhook alert sent for '{}'", hostname));
+        }
+        Ok(Ok(response)) => error(format!("Certificate transparency webhook returned status {}", response.status())),
+        Ok(Err(e)) => error(format!("Certificate transparency webhook request failed: {}", e)),
+        Err(_) => error("Certificate transparency webhook request timed out".to_string()),
+    }
+}
+
+// Collect the serial numbers of all certificates this instance has, both ACME issued and manually configured
+async fn load_local_certificate_serials(sites: &[Site], cache_dir: &str) -> HashSet<String> {
+    let mut pem_contents: Vec<Vec<u8>> = Vec::new();
+
+    if let Ok(mut entries) = tokio::fs::read_dir(cache_dir).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if let Ok(content) = tokio::fs::read(entry.path()).await {
+                pem_contents.push(content);
+            }
+        }
+    }
+
+    for site in sites {
+        if !site.tls_cert_path.is_empty()
+            && let Ok(content) = tokio::fs::read(&site.tls_cert_path).await
+        {
+            pem_contents.push(content);
+        }
+        if !site.tls_cert_content.is_empty() {
+            pem_contents.push(site.tls_cert_content.as_bytes().to_vec());
+        }
+    }
+
+    let mut serials = HashSet::new();
+    for content in pem_contents {
+        let mut reader = BufReader::new(content.as_slice());
+        for cert in rustls_pemfile::certs(&mut reader).flatten() {
+            if let Some(serial) = get_certificate_serial(cert.as_ref()) {
+                serials.insert(serial);
+            }
+        }
+    }
+    serials
+}
+
+// Extract the serial number from a DER encoded X.509 certificate, as normalized hex
+fn get_certificate_serial(der: &[u8]) -> Option<String> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(der).ok()?;
+    Some(normalize_serial(&certificate.raw_serial_as_string()))
+}
+
+// Normalize hex serial numbers, so they compare equal regardless of formatting
+fn normalize_serial(serial: &str) -> String {
+    let serial: String = serial.chars().filter(|c| c.is_ascii_hexdigit()).collect::<String>().to_lowercase();
+    let trimmed = serial.trim_start_matches('0');
+    if trimmed.is_empty() { "0".to_string() } else { trimmed.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_serial() {
+        assert_eq!(normalize_serial("00:0A:ff"), "aff");
+        assert_eq!(normalize_serial("03ab"), "3ab");
+        assert_eq!(normalize_serial("00"), "0");
+    }
+
+    #[test]
+    fn test_get_certificate_serial_from_generated_certificate() {
+        let mut params = rcgen::CertificateParams::new(vec!["example.com".to_string()]).unwrap();
+        params.serial_number = Some(rcgen::SerialNumber::from_slice(&[0x01, 0x02, 0xab]));
+        let key_pair = rcgen::KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key_pair).unwrap();
+
+        assert_eq!(get_certificate_serial(cert.der().as_ref()), Some("102ab".to_string()));
+        assert_eq!(get_certificate_serial(&cert.der()[..20]), None);
+    }
+
+    #[test]
+    fn test_get_monitored_hostnames() {
+        let mut site = Site::new();
+        site.hostnames = vec![
+            "*".to_string(),
+            "Example.com".to_string(),
+            "localhost".to_string(),
+            "127.0.0.1".to_string(),
+            "*.example.org".to_string(),
+        ];
+
+        let hostnames = get_monitored_hostnames(&[site]);
+        assert_eq!(hostnames.into_iter().collect::<Vec<String>>(), vec!["example.com".to_string()]);
+    }
+}
@@ -0,0 +1,220 @@
+// ============================================================================
+// DEVELOPMENT CERTIFICATE AUTHORITY
+// ============================================================================
+//
+// Self-signed certificates make browsers warn on every site during local
+// development, as each one has to be trusted by itself. With "gruxi cert
+// trust-dev", Gruxi creates a local certificate authority once, which is
+// trusted in the operating system and browsers, and signs the certificates of
+// the sites without a certificate of their own from it.
+//
+// The CA only exists on the machine it was created on, and its key never
+// leaves the certs/dev-ca directory. It is meant for development, so it is
+// never used when no one ran the command.
+// ============================================================================
+
+use chrono::{Datelike, Duration, Utc};
+use rcgen::{BasicConstraints, CertificateParams, DistinguishedName, DnType, ExtendedKeyUsagePurpose, IsCa, Issuer, KeyPair, KeyUsagePurpose};
+
+use crate::core::database_connection::get_database_writer;
+use crate::database::data_access::{execute, query, query_one};
+
+pub const DEV_CA_DIRECTORY: &str = "certs/dev-ca";
+const DEV_CA_CERT_FILE: &str = "gruxi-dev-ca.crt.pem";
+const DEV_CA_KEY_FILE: &str = "gruxi-dev-ca.key.pem";
+const DEV_CA_NAME: &str = "Gruxi Development CA";
+
+const DEV_CA_VALIDITY_DAYS: i64 = 3650;
+// The longest validity Apple platforms accept for TLS server certificates
+const DEV_CERTIFICATE_VALIDITY_DAYS: i64 = 825;
+
+pub struct DevCa {
+    key_pair: KeyPair,
+    cert_path: String,
+}
+
+impl DevCa {
+    /// Load the development CA, if it has been created
+    pub fn load() -> Result<Option<Self>, String> {
+        let cert_path = format!("{}/{}", DEV_CA_DIRECTORY, DEV_CA_CERT_FILE);
+        let key_path = format!("{}/{}", DEV_CA_DIRECTORY, DEV_CA_KEY_FILE);
+        if !std::path::Path::new(&cert_path).is_file() || !std::path::Path::new(&key_path).is_file() {
+            return Ok(None);
+        }
+        let key_pem = std::fs::read_to_string(&key_path).map_err(|e| format!("Failed to read development CA key '{}': {}", key_path, e))?;
+        let key_pair = KeyPair::from_pem(&key_pem).map_err(|e| format!("Failed to parse development CA key '{}': {}", key_path, e))?;
+        Ok(Some(Self { key_pair, cert_path }))
+    }
+
+    /// Load the development CA, or create it when it does not exist yet. Returns whether it was created
+    pub fn load_or_create() -> Result<(Self, bool), String> {
+        if let Some(dev_ca) = Self::load()? {
+            return Ok((dev_ca, false));
+        }
+
+        let key_pair = KeyPair::generate().map_err(|e| format!("Failed to generate development CA key: {}", e))?;
+        let mut params = get_ca_params();
+        set_validity(&mut params, DEV_CA_VALIDITY_DAYS);
+        let certificate = params.self_signed(&key_pair).map_err(|e| format!("Failed to create development CA certificate: {}", e))?;
+
+        std::fs::create_dir_all(DEV_CA_DIRECTORY).map_err(|e| format!("Failed to create directory '{}': {}", DEV_CA_DIRECTORY, e))?;
+        let cert_path = format!("{}/{}", DEV_CA_DIRECTORY, DEV_CA_CERT_FILE);
+        let key_path = format!("{}/{}", DEV_CA_DIRECTORY, DEV_CA_KEY_FILE);
+        write_private_file(&key_path, &key_pair.serialize_pem())?;
+        std::fs::write(&cert_path, certificate.pem()).map_err(|e| format!("Failed to write development CA certificate '{}': {}", cert_path, e))?;
+
+        Ok((Self { key_pair, cert_path }, true))
+    }
+
+    pub fn get_cert_path(&self) -> &str {
+        &self.cert_path
+    }
+
+    /// Sign a certificate for the hostnames, returned as certificate and key PEM
+    pub fn issue_certificate(&self, hostnames: &[String]) -> Result<(String, String), String> {
+        let mut params = CertificateParams::new(hostnames.to_vec()).map_err(|e| format!("Invalid hostnames {:?}: {}", hostnames, e))?;
+        let mut distinguished_name = DistinguishedName::new();
+        distinguished_name.push(DnType::CommonName, hostnames.first().map(|h| h.as_str()).unwrap_or("localhost"));
+        distinguished_name.push(DnType::OrganizationName, DEV_CA_NAME);
+        params.distinguished_name = distinguished_name;
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        params.use_authority_key_identifier_extension = true;
+        set_validity(&mut params, DEV_CERTIFICATE_VALIDITY_DAYS);
+
+        let key_pair = KeyPair::generate().map_err(|e| format!("Failed to generate key: {}", e))?;
+        let issuer = Issuer::new(get_ca_params(), &self.key_pair);
+        let certificate = params.signed_by(&key_pair, &issuer).map_err(|e| format!("Failed to sign certificate: {}", e))?;
+        Ok((certificate.pem(), key_pair.serialize_pem()))
+    }
+}
+
+// The parameters of the CA certificate, which also identify it as issuer of the certificates it signs
+fn get_ca_params() -> CertificateParams {
+    let mut params = CertificateParams::default();
+    let mut distinguished_name = DistinguishedName::new();
+    distinguished_name.push(DnType::CommonName, DEV_CA_NAME);
+    distinguished_name.push(DnType::OrganizationName, DEV_CA_NAME);
+    params.distinguished_name = distinguished_name;
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign, KeyUsagePurpose::DigitalSignature];
+    params
+}
+
+// Valid from yesterday, so clocks running a bit behind accept the certificate as well
+fn set_validity(params: &mut CertificateParams, validity_days: i64) {
+    let not_before = Utc::now() - Duration::days(1);
+    let not_after = Utc::now() + Duration::days(validity_days);
+    params.not_before = rcgen::date_time_ymd(not_before.year(), not_before.month() as u8, not_before.day() as u8);
+    params.not_after = rcgen::date_time_ymd(not_after.year(), not_after.month() as u8, not_after.day() as u8);
+}
+
+// Private keys are only readable by the user running Gruxi, where the platform supports it
+pub fn write_private_file(path: &str, content: &str) -> Result<(), String> {
+    std::fs::write(path, content).map_err(|e| format!("Failed to write '{}': {}", path, e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600)).map_err(|e| format!("Failed to set permissions of '{}': {}", path, e))?;
+    }
+    Ok(())
+}
+
+/// Generate a certificate for the hostnames of a site without one. It is signed by the development CA when it has
+/// been created with "gruxi cert trust-dev", and self-signed otherwise. Returns the certificate and key PEM
+pub fn generate_site_certificate(hostnames: &[String]) -> Result<(String, String), String> {
+    if let Some(dev_ca) = DevCa::load()? {
+        return dev_ca.issue_certificate(hostnames);
+    }
+    let rcgen::CertifiedKey { cert, signing_key } = rcgen::generate_simple_self_signed(hostnames.to_vec()).map_err(|e| format!("Failed to generate self-signed cert: {}", e))?;
+    Ok((cert.pem(), signing_key.serialize_pem()))
+}
+
+// Certificates Gruxi generated for sites are saved as "certs/<random number>.crt.pem"
+fn is_generated_certificate_path(path: &str) -> bool {
+    path.strip_prefix("certs/")
+        .and_then(|name| name.strip_suffix(".crt.pem"))
+        .is_some_and(|number| !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Clear the paths of the certificates Gruxi generated for sites and the admin portal, so they are generated again from
+/// the development CA on the next start. Returns how many were cleared
+pub fn clear_generated_certificates() -> Result<usize, String> {
+    let connection = get_database_writer()?;
+    let mut cleared = 0;
+
+    let mut site_ids = Vec::new();
+    let sites = query(&connection, "SELECT id, tls_cert_path FROM sites", &[], |row| {
+        Ok((row.get_string("id")?, row.get_string("tls_cert_path")?))
+    })
+    .map_err(|e| format!("Failed to query sites: {}", e))?;
+    for (id, cert_path) in sites {
+        if is_generated_certificate_path(&cert_path) {
+            site_ids.push(id);
+        }
+    }
+    for id in site_ids {
+        execute(&connection, "UPDATE sites SET tls_cert_path = '', tls_key_path = '' WHERE id = ?", &[&id]).map_err(|e| format!("Failed to clear site TLS paths: {}", e))?;
+        cleared += 1;
+    }
+
+    let admin_portal_cert_path = query_one(
+        &connection,
+        "SELECT setting_value FROM server_settings WHERE setting_key = 'admin_portal_tls_certificate_path'",
+        &[],
+        |row| row.get_string("setting_value"),
+    )
+    .map_err(|e| format!("Failed to query server settings: {}", e))?;
+    if let Some(cert_path) = admin_portal_cert_path
+        && is_generated_certificate_path(&cert_path)
+    {
+        connection
+            .execute("UPDATE server_settings SET setting_value = '' WHERE setting_key IN ('admin_portal_tls_certificate_path', 'admin_portal_tls_key_path');")
+            .map_err(|e| format!("Failed to clear admin portal TLS paths: {}", e))?;
+        cleared += 1;
+    }
+
+    Ok(cleared)
+}
+
+/// How to trust the development CA on each platform
+pub fn get_trust_instructions(cert_path: &str) -> String {
+    let absolute_path = std::fs::canonicalize(cert_path).map(|p| p.display().to_string()).unwrap_or(cert_path.to_string());
+    [
+        format!("Development CA certificate: {}", absolute_path),
+        String::new(),
+        "Trust it on this machine, then restart the browser:".to_string(),
+        String::new(),
+        "  Windows (as administrator):".to_string(),
+        format!("    certutil -addstore -f ROOT \"{}\"", absolute_path),
+        String::new(),
+        "  macOS:".to_string(),
+        format!("    sudo security add-trusted-cert -d -r trustRoot -k /Library/Keychains/System.keychain \"{}\"", absolute_path),
+        String::new(),
+        "  Debian and Ubuntu:".to_string(),
+        format!("    sudo cp \"{}\" /usr/local/share/ca-certificates/gruxi-dev-ca.crt && sudo update-ca-certificates", absolute_path),
+        String::new(),
+        "  Fedora and RHEL:".to_string(),
+        format!("    sudo cp \"{}\" /etc/pki/ca-trust/source/anchors/gruxi-dev-ca.crt && sudo update-ca-trust", absolute_path),
+        String::new(),
+        "  Firefox, and Chrome on Linux, use their own store (certutil is in libnss3-tools):".to_string(),
+        format!("    certutil -d sql:$HOME/.pki/nssdb -A -t \"C,,\" -n \"{}\" -i \"{}\"", DEV_CA_NAME, absolute_path),
+        "    or import it in Firefox under Settings > Privacy & Security > Certificates > View Certificates > Authorities".to_string(),
+        String::new(),
+        "Never share the key next to it, anyone with it can create certificates this machine trusts.".to_string(),
+    ]
+    .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_certificate_path() {
+        assert!(is_generated_certificate_path("certs/123456.crt.pem"));
+        assert!(!is_generated_certificate_path("certs/example.com.crt.pem"));
+        assert!(!is_generated_certificate_path("/etc/ssl/123.crt.pem"));
+        assert!(!is_generated_certificate_path(""));
+    }
+}
@@ -1,2 +1,12 @@
+pub mod acme_account;
+pub mod acme_cache;
+pub mod acme_certificate_log;
+pub mod acme_orders;
+pub mod acme_smoke_test;
+pub mod certificate_export;
+pub mod certificate_store;
+pub mod ct_log_monitor;
+pub mod dev_ca;
+pub mod session_resumption;
 pub mod shared_acme_manager;
 pub mod tls_config;
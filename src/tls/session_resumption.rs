@@ -0,0 +1,223 @@
+// ============================================================================
+// TLS SESSION RESUMPTION
+// ============================================================================
+//
+// Lets returning clients resume their TLS session, skipping the certificate
+// signature and key exchange of a full handshake. All TLS bindings share one
+// session cache, for session IDs and stateful resumption, and one ticket
+// encrypter, for stateless session tickets, so a session made on one binding
+// resumes on another. Ticket keys are generated in memory and rotate every
+// half ticket lifetime, with the previous key kept for decryption, so a ticket
+// is never accepted for longer than the lifetime and keys do not outlive it.
+// ============================================================================
+
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, LazyLock, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aws_lc_rs::aead::{AES_256_GCM, Aad, NONCE_LEN, Nonce, RandomizedNonceKey};
+use tls_listener::rustls as tokio_rustls;
+use tokio_rustls::rustls::ServerConfig as RustlsServerConfig;
+use tokio_rustls::rustls::server::{NoServerSessionStorage, ProducesTickets, ServerSessionMemoryCache};
+
+use crate::configuration::tls_settings::TlsSettings;
+use crate::logging::syslog::{trace, warn};
+
+// Sessions kept for session ID and stateful resumption, shared by all bindings
+const SESSION_CACHE_SIZE: usize = 4096;
+
+// Sent to clients with each full handshake, so they can resume more than one connection
+const TLS13_TICKETS_PER_HANDSHAKE: usize = 2;
+
+const TICKET_KEY_NAME_LENGTH: usize = 16;
+
+static SHARED_SESSION_CACHE: LazyLock<Arc<ServerSessionMemoryCache>> = LazyLock::new(|| ServerSessionMemoryCache::new(SESSION_CACHE_SIZE));
+
+static SHARED_TICKETER: LazyLock<Option<Arc<RotatingTicketer>>> = LazyLock::new(|| match RotatingTicketer::new() {
+    Ok(ticketer) => Some(Arc::new(ticketer)),
+    Err(e) => {
+        warn(format!("Failed to create TLS session ticket key, session tickets are disabled: {}", e));
+        None
+    }
+});
+
+// Set up session resumption on the server config of a binding, from the TLS settings
+pub fn apply_session_resumption(server_config: &mut RustlsServerConfig, tls_settings: &TlsSettings) {
+    if !tls_settings.session_resumption_enabled {
+        server_config.session_storage = Arc::new(NoServerSessionStorage {});
+        server_config.send_tls13_tickets = 0;
+        return;
+    }
+
+    server_config.session_storage = SHARED_SESSION_CACHE.clone();
+    server_config.send_tls13_tickets = TLS13_TICKETS_PER_HANDSHAKE;
+    if let Some(ticketer) = SHARED_TICKETER.as_ref() {
+        ticketer.set_lifetime(tls_settings.session_ticket_lifetime_seconds);
+        server_config.ticketer = ticketer.clone();
+    }
+}
+
+fn get_unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+}
+
+struct TicketKey {
+    name: [u8; TICKET_KEY_NAME_LENGTH],
+    key: RandomizedNonceKey,
+}
+
+impl TicketKey {
+    fn generate() -> Result<Self, String> {
+        let mut name = [0u8; TICKET_KEY_NAME_LENGTH];
+        let mut key_bytes = [0u8; 32];
+        aws_lc_rs::rand::fill(&mut name).map_err(|_| "Failed to generate ticket key name".to_string())?;
+        aws_lc_rs::rand::fill(&mut key_bytes).map_err(|_| "Failed to generate ticket key".to_string())?;
+        let key = RandomizedNonceKey::new(&AES_256_GCM, &key_bytes).map_err(|_| "Failed to create ticket key".to_string())?;
+        Ok(TicketKey { name, key })
+    }
+}
+
+struct TicketKeys {
+    current: TicketKey,
+    previous: Option<TicketKey>,
+    rotate_at: u64, // Unix time in seconds
+}
+
+// Session ticket encrypter, with a name of the key, the nonce and the AES-256-GCM sealed session in each ticket
+pub struct RotatingTicketer {
+    lifetime_seconds: AtomicU32,
+    keys: RwLock<TicketKeys>,
+}
+
+impl fmt::Debug for RotatingTicketer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RotatingTicketer").field("lifetime_seconds", &self.lifetime_seconds).finish()
+    }
+}
+
+impl RotatingTicketer {
+    fn new() -> Result<Self, String> {
+        Ok(RotatingTicketer {
+            lifetime_seconds: AtomicU32::new(0),
+            keys: RwLock::new(TicketKeys {
+                current: TicketKey::generate()?,
+                previous: None,
+                rotate_at: 0,
+            }),
+        })
+    }
+
+    fn set_lifetime(&self, lifetime_seconds: u32) {
+        let previous_lifetime = self.lifetime_seconds.swap(lifetime_seconds, Ordering::Relaxed);
+        if previous_lifetime != lifetime_seconds {
+            // Schedule the next rotation from the new lifetime
+            if let Ok(mut keys) = self.keys.write() {
+                keys.rotate_at = get_unix_time() + self.get_rotation_interval();
+            }
+        }
+    }
+
+    fn get_rotation_interval(&self) -> u64 {
+        (self.lifetime_seconds.load(Ordering::Relaxed) as u64 / 2).max(1)
+    }
+
+    // Replace the current key, when it has been used for half the lifetime. Keys older than the lifetime are dropped, even
+    // without tickets being issued in between
+    fn rotate_if_due(&self, now: u64) {
+        let is_due = self.keys.read().map(|keys| now >= keys.rotate_at).unwrap_or(false);
+        if !is_due {
+            return;
+        }
+
+        let next_key = match TicketKey::generate() {
+            Ok(key) => key,
+            Err(e) => {
+                warn(format!("Failed to rotate TLS session ticket key, keeping the current one: {}", e));
+                return;
+            }
+        };
+        let Ok(mut keys) = self.keys.write() else {
+            return;
+        };
+        if now < keys.rotate_at {
+            // Rotated by another connection in the meantime
+            return;
+        }
+        let rotation_interval = self.get_rotation_interval();
+        let previous = std::mem::replace(&mut keys.current, next_key);
+        // The previous key only decrypts tickets issued within the last rotation interval
+        keys.previous = if now < keys.rotate_at + rotation_interval { Some(previous) } else { None };
+        keys.rotate_at = now + rotation_interval;
+        trace("Rotated TLS session ticket key".to_string());
+    }
+}
+
+impl ProducesTickets for RotatingTicketer {
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn lifetime(&self) -> u32 {
+        self.lifetime_seconds.load(Ordering::Relaxed)
+    }
+
+    fn encrypt(&self, plain: &[u8]) -> Option<Vec<u8>> {
+        self.rotate_if_due(get_unix_time());
+        let keys = self.keys.read().ok()?;
+
+        let mut sealed = plain.to_vec();
+        let nonce = keys.current.key.seal_in_place_append_tag(Aad::from(&keys.current.name), &mut sealed).ok()?;
+
+        let mut ticket = Vec::with_capacity(TICKET_KEY_NAME_LENGTH + NONCE_LEN + sealed.len());
+        ticket.extend_from_slice(&keys.current.name);
+        ticket.extend_from_slice(nonce.as_ref());
+        ticket.extend_from_slice(&sealed);
+        Some(ticket)
+    }
+
+    fn decrypt(&self, cipher: &[u8]) -> Option<Vec<u8>> {
+        self.rotate_if_due(get_unix_time());
+        if cipher.len() < TICKET_KEY_NAME_LENGTH + NONCE_LEN {
+            return None;
+        }
+        let (name, rest) = cipher.split_at(TICKET_KEY_NAME_LENGTH);
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+
+        let keys = self.keys.read().ok()?;
+        let key = [Some(&keys.current), keys.previous.as_ref()].into_iter().flatten().find(|key| key.name == name)?;
+
+        let mut plain = sealed.to_vec();
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+        let plain_length = key.key.open_in_place(nonce, Aad::from(&key.name), &mut plain).ok()?.len();
+        plain.truncate(plain_length);
+        Some(plain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ticketer_rotates_keys() {
+        let ticketer = RotatingTicketer::new().unwrap();
+        ticketer.set_lifetime(100);
+        let now = get_unix_time();
+
+        let ticket = ticketer.encrypt(b"session state").unwrap();
+        assert_eq!(ticketer.decrypt(&ticket).unwrap(), b"session state");
+        assert!(ticketer.decrypt(&ticket[..ticket.len() - 1]).is_none());
+
+        // After half the lifetime, new tickets use a new key, while the previous key still decrypts
+        ticketer.rotate_if_due(now + 50);
+        let newer_ticket = ticketer.encrypt(b"newer").unwrap();
+        assert_ne!(newer_ticket[..TICKET_KEY_NAME_LENGTH], ticket[..TICKET_KEY_NAME_LENGTH]);
+        assert_eq!(ticketer.decrypt(&ticket).unwrap(), b"session state");
+
+        // After the full lifetime, the first key is gone
+        ticketer.rotate_if_due(now + 100);
+        assert!(ticketer.decrypt(&ticket).is_none());
+        assert_eq!(ticketer.decrypt(&newer_ticket).unwrap(), b"newer");
+    }
+}
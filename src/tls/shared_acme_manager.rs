@@ -5,20 +5,45 @@
 // This module provides a single, shared ACME client instance for all TLS bindings.
 // Instead of creating one ACME client per binding (which would cause rate-limiting
 // issues and duplicate certificate requests), we create one shared manager that:
-//   - Holds a single AcmeConfig and AcmeState
 //   - Collects all ACME-enabled domains across all bindings
-//   - Provides a shared resolver (Arc<ResolvesServerCertAcme>) to all bindings
-//   - Runs a single background task to poll for certificate updates
+//   - Plans the certificate orders, either one for all domains or, when
+//     consolidation is enabled, one multi-SAN order per apex domain, keeping
+//     sites with different CAs, key types or ACME profiles in separate orders
+//   - Orders wildcard hostnames when "acme_dns_challenge" command hooks can
+//     publish the DNS-01 records they are validated with, leaving out the
+//     names a wildcard covers
+//   - Holds one AcmeState per order and maps each domain to its order, except
+//     for orders of RSA keys, ACME profiles or wildcards, which rustls-acme
+//     cannot make and acme_orders.rs makes instead
+//   - Caches accounts and certificates in the configured backend, which can be
+//     shared by several nodes (see acme_cache.rs)
+//   - Provides a shared resolver (Arc<SharedAcmeResolver>) to all bindings
+//   - Answers HTTP-01 challenges from the request path, when validating over
+//     port 80 instead of with TLS-ALPN-01
+//   - Runs a background task per order to poll for certificate updates
 //   - Responds to shutdown/stop_services/reload_configuration triggers
 // ============================================================================
 
+use crate::configuration::cached_configuration::get_cached_configuration;
+use crate::configuration::site::Site;
+use crate::configuration::tls_settings::TlsSettings;
+use crate::core::command_hooks::{get_command_hooks_for_event, run_command_hooks};
 use crate::core::running_state_manager::get_running_state_manager;
 use crate::core::triggers::get_trigger_handler;
+use crate::error::gruxi_error::GruxiError;
+use crate::error::gruxi_error_enums::TlsError;
+use crate::http::site_match::hostname_pattern::is_regex_hostname;
 use crate::logging::syslog::{debug, trace};
-use rustls_acme::caches::DirCache;
-use rustls_acme::{AcmeConfig, ResolvesServerCertAcme};
-use std::collections::BTreeSet;
+use crate::tls::acme_account::{ensure_acme_account, get_acme_account_contact, get_acme_directory_url, get_provider_directory_url};
+use crate::tls::acme_cache::AcmeCache;
+use crate::tls::acme_certificate_log::record_acme_certificate;
+use crate::tls::acme_orders::{ManagedAcmeOrder, get_cache_directory_key, spawn_managed_order_task};
+use crate::tls::acme_smoke_test::get_smoke_test_http01_key_authorization;
+use rustls_acme::{AcmeConfig, CertCache, ResolvesServerCertAcme, UseChallenge};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::sync::Arc;
+use tls_listener::rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tls_listener::rustls::rustls::sign::CertifiedKey;
 use tokio::fs;
 use tokio::sync::RwLock;
 use tokio_stream::StreamExt;
@@ -27,22 +52,149 @@ use tokio_util::sync::CancellationToken;
 /// Global singleton for the shared ACME manager (can be reset on configuration reload)
 static SHARED_ACME_MANAGER: RwLock<Option<SharedAcmeManager>> = RwLock::const_new(None);
 
+/// Let's Encrypt allows at most 100 names per certificate
+const MAX_DOMAINS_PER_ORDER: usize = 100;
+
+/// How the ACME server validates that we control the domains
+pub const ACME_CHALLENGE_TYPES: [&str; 2] = ["tls-alpn-01", "http-01"];
+
+/// Path the ACME server requests HTTP-01 challenge tokens on, followed by the token
+pub const ACME_HTTP01_CHALLENGE_PATH: &str = "/.well-known/acme-challenge/";
+
+/// The CA, key type and ACME profile of a certificate order, from the ACME settings of its sites
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AcmeOrderSettings {
+    pub directory_url: String,
+    pub key_type: String,
+    pub profile: String,
+}
+
+impl AcmeOrderSettings {
+    /// The settings of the orders of a site, where the site falls back to the provider of the TLS settings
+    pub fn for_site(site: &Site, tls_settings: &TlsSettings) -> Self {
+        let directory_url = if site.acme.provider.is_empty() {
+            get_acme_directory_url(tls_settings)
+        } else {
+            get_provider_directory_url(&site.acme.provider, &site.acme.directory_url, tls_settings.use_staging_server)
+        };
+        Self {
+            directory_url,
+            key_type: site.acme.key_type.clone(),
+            profile: site.acme.profile.clone(),
+        }
+    }
+
+    /// The key types a certificate is ordered with
+    pub fn get_key_types(&self) -> Vec<&'static str> {
+        match self.key_type.as_str() {
+            "both" => vec!["ecdsa", "rsa"],
+            "rsa" => vec!["rsa"],
+            _ => vec!["ecdsa"],
+        }
+    }
+
+    /// Whether rustls-acme can make the order, which it can for ECDSA certificates without a profile
+    pub fn is_rustls_acme_order(&self) -> bool {
+        self.key_type == "ecdsa" && self.profile.is_empty()
+    }
+}
+
+/// The domains of a certificate order, and how it is made
+#[derive(Debug, Clone)]
+pub struct AcmeOrder {
+    pub domains: BTreeSet<String>,
+    pub settings: AcmeOrderSettings,
+}
+
+impl AcmeOrder {
+    /// Whether rustls-acme can make the order, which it cannot for wildcards as they are validated with DNS-01
+    pub fn is_rustls_acme_order(&self) -> bool {
+        self.settings.is_rustls_acme_order() && !self.domains.iter().any(|domain| domain.starts_with("*."))
+    }
+}
+
+/// Resolves ACME certificates and TLS-ALPN-01 challenges by dispatching to the
+/// resolver of the order that covers the requested domain
+pub struct SharedAcmeResolver {
+    /// One resolver per ACME order made by rustls-acme
+    order_resolvers: Vec<Arc<ResolvesServerCertAcme>>,
+    /// Maps each domain to the index of the order that covers it
+    domain_to_order: HashMap<String, usize>,
+    /// Orders made by Gruxi itself, and the index of the order covering each of their domains
+    managed_orders: Vec<Arc<ManagedAcmeOrder>>,
+    domain_to_managed_order: HashMap<String, usize>,
+}
+
+impl std::fmt::Debug for SharedAcmeResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedAcmeResolver")
+            .field("order_resolvers", &self.order_resolvers)
+            .field("domain_to_order", &self.domain_to_order)
+            .field("domain_to_managed_order", &self.domain_to_managed_order)
+            .finish()
+    }
+}
+
+impl SharedAcmeResolver {
+    /// Get the resolver of the order covering the domain, if any
+    fn resolver_for_domain(&self, domain: &str) -> Option<&Arc<ResolvesServerCertAcme>> {
+        self.domain_to_order.get(&domain.to_lowercase()).and_then(|idx| self.order_resolvers.get(*idx))
+    }
+
+    /// Get the order made by Gruxi covering the domain, if any, either by name or by the wildcard covering it
+    fn managed_order_for_domain(&self, domain: &str) -> Option<&Arc<ManagedAcmeOrder>> {
+        let domain = domain.to_lowercase();
+        self.domain_to_managed_order
+            .get(&domain)
+            .or_else(|| get_wildcard_domain(&domain).and_then(|wildcard| self.domain_to_managed_order.get(&wildcard)))
+            .and_then(|idx| self.managed_orders.get(*idx))
+    }
+
+    /// Get the key authorization for a pending HTTP-01 challenge of the domain, if the token matches
+    pub fn get_http01_key_authorization(&self, domain: &str, token: &str) -> Option<String> {
+        if let Some(order) = self.managed_order_for_domain(domain) {
+            return order.get_http01_key_authorization(token);
+        }
+        self.resolver_for_domain(domain)?.get_http_01_key_auth(token)
+    }
+}
+
+impl ResolvesServerCert for SharedAcmeResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let domain = client_hello.server_name()?.to_string();
+        if let Some(order) = self.managed_order_for_domain(&domain) {
+            if rustls_acme::is_tls_alpn_challenge(&client_hello) {
+                return order.get_tls_alpn01_certificate(&domain);
+            }
+            return order.resolve(&client_hello);
+        }
+        self.resolver_for_domain(&domain)?.resolve(client_hello)
+    }
+}
+
 /// Holds the shared ACME state and resolver that can be used across all TLS bindings
 pub struct SharedAcmeManager {
-    /// The ACME resolver used to resolve certificates for ACME-managed domains
-    resolver: Arc<ResolvesServerCertAcme>,
+    /// The resolver used to resolve certificates for ACME-managed domains, across all orders
+    resolver: Arc<SharedAcmeResolver>,
     /// All domains managed by this ACME instance
     domains: std::collections::HashSet<String>,
-    /// Cancellation token for the polling task
+    /// Domains and settings of each certificate order
+    orders: Vec<AcmeOrder>,
+    /// Cancellation token for the polling tasks
     polling_cancel_token: CancellationToken,
 }
 
 impl SharedAcmeManager {
     /// Get the shared ACME resolver
-    pub fn resolver(&self) -> Arc<ResolvesServerCertAcme> {
+    pub fn resolver(&self) -> Arc<SharedAcmeResolver> {
         self.resolver.clone()
     }
 
+    /// Get the domains and settings of each certificate order
+    pub fn orders(&self) -> &Vec<AcmeOrder> {
+        &self.orders
+    }
+
     /// Check if a domain is managed by ACME
     #[allow(dead_code)]
     pub fn is_acme_domain(&self, domain: &str) -> bool {
@@ -70,7 +222,7 @@ pub async fn shutdown_shared_acme_manager() {
 /// before creating a new one.
 ///
 /// Returns Ok(()) if initialization succeeded (or ACME is not configured).
-pub async fn initialize_shared_acme_manager() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+pub async fn initialize_shared_acme_manager() -> Result<(), GruxiError> {
     // First, shut down any existing manager
     shutdown_shared_acme_manager().await;
 
@@ -85,11 +237,55 @@ pub async fn initialize_shared_acme_manager() -> Result<(), Box<dyn std::error::
 }
 
 /// Get the shared ACME manager if it has been initialized
-pub async fn get_shared_acme_manager_async() -> Option<Arc<ResolvesServerCertAcme>> {
+pub async fn get_shared_acme_manager_async() -> Option<Arc<SharedAcmeResolver>> {
     let manager = SHARED_ACME_MANAGER.read().await;
     manager.as_ref().map(|m| m.resolver())
 }
 
+/// Get the key authorization to answer an HTTP-01 challenge with, if the token is for a pending challenge of the domain
+pub async fn get_acme_http01_key_authorization(domain: &str, token: &str) -> Option<String> {
+    let manager = SHARED_ACME_MANAGER.read().await;
+    // The staging smoke test answers challenges for domains the manager may not have
+    manager
+        .as_ref()
+        .and_then(|m| m.resolver.get_http01_key_authorization(domain, token))
+        .or_else(|| get_smoke_test_http01_key_authorization(token))
+}
+
+/// The token of a request for an HTTP-01 challenge. Tokens are base64url (RFC 8555, section 8.1), so a path leaving the
+/// challenge directory, such as with "..", is never taken for one
+pub fn get_acme_http01_token(path: &str) -> Option<&str> {
+    let token = path.strip_prefix(ACME_HTTP01_CHALLENGE_PATH)?;
+    let is_token = !token.is_empty() && token.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_');
+    is_token.then_some(token)
+}
+
+/// Get the domains and settings of each certificate order of the shared manager
+pub async fn get_shared_acme_orders() -> Vec<AcmeOrder> {
+    let manager = SHARED_ACME_MANAGER.read().await;
+    manager.as_ref().map(|m| m.orders().clone()).unwrap_or_default()
+}
+
+/// Load the certificate covering the domain from the ACME cache, as PEM with the private key first and the
+/// certificate chain after it. Returns None when the domain is not managed by ACME or has no certificate yet.
+pub async fn load_acme_certificate_pem(domain: &str) -> Result<Option<Vec<u8>>, String> {
+    let domain = domain.trim().to_lowercase();
+    let Some(order) = get_shared_acme_orders().await.into_iter().find(|order| order.domains.contains(&domain)) else {
+        return Ok(None);
+    };
+
+    let cached_configuration = crate::configuration::cached_configuration::get_cached_configuration();
+    let tls_settings = cached_configuration.get_configuration().await.core.tls_settings.clone();
+    let cache = AcmeCache::from_settings(&tls_settings, &get_cache_dir(&tls_settings));
+    // Orders with both key types export the ECDSA certificate
+    let directory_key = if order.is_rustls_acme_order() {
+        order.settings.directory_url.clone()
+    } else {
+        get_cache_directory_key(&order.settings, order.settings.get_key_types()[0])
+    };
+    cache.load_cert(&order.domains.into_iter().collect::<Vec<_>>(), &directory_key).await
+}
+
 /// Get ACME domains from the shared manager
 pub async fn get_shared_acme_domains() -> std::collections::HashSet<String> {
     let manager = SHARED_ACME_MANAGER.read().await;
@@ -97,7 +293,7 @@ pub async fn get_shared_acme_domains() -> std::collections::HashSet<String> {
 }
 
 /// Internal function to create the shared ACME manager
-async fn create_shared_acme_manager() -> Result<Option<SharedAcmeManager>, Box<dyn std::error::Error + Send + Sync>> {
+async fn create_shared_acme_manager() -> Result<Option<SharedAcmeManager>, GruxiError> {
     let cached_configuration = crate::configuration::cached_configuration::get_cached_configuration();
     let config = cached_configuration.get_configuration().await;
 
@@ -109,8 +305,13 @@ async fn create_shared_acme_manager() -> Result<Option<SharedAcmeManager>, Box<d
         return Ok(None);
     }
 
-    // Collect all ACME-enabled domains across all TLS bindings
-    let mut all_domains: BTreeSet<String> = BTreeSet::new();
+    // Wildcards are validated with DNS-01, so they are only ordered when a command hook publishes the challenge records
+    let is_dns01_available = !get_command_hooks_for_event(&config, "acme_dns_challenge").is_empty();
+
+    // Collect the ACME-enabled hostname set of each site across all TLS bindings, by the settings of its orders. A domain
+    // is ordered with the settings of the first site having it
+    let mut site_domain_sets: BTreeMap<AcmeOrderSettings, Vec<BTreeSet<String>>> = BTreeMap::new();
+    let mut claimed_domains: BTreeSet<String> = BTreeSet::new();
 
     let running_state = get_running_state_manager().await.get_running_state_unlocked().await;
     let binding_site_cache = running_state.get_binding_site_cache();
@@ -123,113 +324,229 @@ async fn create_shared_acme_manager() -> Result<Option<SharedAcmeManager>, Box<d
         let sites = binding_site_cache.get_sites_for_binding(&binding.id);
 
         for site in sites.iter().filter(|s| s.is_enabled && s.tls_automatic_enabled) {
+            let mut site_domains: BTreeSet<String> = BTreeSet::new();
             for hostname in &site.hostnames {
                 let h = hostname.trim().to_lowercase();
-                if h.is_empty() || h == "*" {
+                if claimed_domains.contains(&h) {
                     continue;
                 }
-
-                // Wildcards require DNS-01, which rustls-acme does not support.
-                if h.contains('*') {
-                    continue;
+                if is_acme_domain_candidate(&h) || (is_dns01_available && is_acme_wildcard_candidate(&h)) {
+                    site_domains.insert(h);
+                } else if is_acme_wildcard_candidate(&h) {
+                    debug(format!("Not ordering a certificate for '{}': wildcards need an 'acme_dns_challenge' command hook", h));
                 }
-
-                // Avoid obviously-non-public hostnames.
-                if h == "localhost" {
-                    continue;
-                }
-
-                // Minimal sanity: must look like a DNS name.
-                if !h.contains('.') {
-                    continue;
-                }
-
-                all_domains.insert(h);
+            }
+            if !site_domains.is_empty() {
+                claimed_domains.extend(site_domains.iter().cloned());
+                site_domain_sets.entry(AcmeOrderSettings::for_site(site, tls_settings)).or_default().push(site_domains);
             }
         }
     }
 
+    let orders: Vec<AcmeOrder> = site_domain_sets
+        .into_iter()
+        .flat_map(|(settings, domain_sets)| {
+            plan_acme_orders(&domain_sets, tls_settings.consolidate_acme_orders)
+                .into_iter()
+                .map(move |domains| AcmeOrder { domains, settings: settings.clone() })
+        })
+        .collect();
+    let all_domains: BTreeSet<String> = orders.iter().flat_map(|order| order.domains.iter()).cloned().collect();
+
     if all_domains.is_empty() {
         debug("ACME not enabled: no valid domains found with tls_automatic_enabled".to_string());
         return Ok(None);
     }
 
-    let cache_dir = if tls_settings.certificate_cache_path.trim().is_empty() {
-        "certs/cache".to_string()
-    } else {
-        tls_settings.certificate_cache_path.trim().to_string()
-    };
-
-    // Ensure cache directory exists.
-    fs::create_dir_all(&cache_dir)
-        .await
-        .map_err(|e| format!("Failed to create ACME cache directory '{}': {}", cache_dir, e))?;
-
-    let provider = rustls::crypto::aws_lc_rs::default_provider();
+    let cache_dir = get_cache_dir(tls_settings);
 
-    let mut acme_config = AcmeConfig::new_with_provider(all_domains.iter().cloned().collect::<Vec<_>>(), provider.into())
-        .cache_with_boxed_err(DirCache::new(cache_dir.clone()))
-        .directory_lets_encrypt(!tls_settings.use_staging_server);
+    // Ensure cache directory exists, when certificates are cached on the filesystem
+    if tls_settings.certificate_cache_backend == "filesystem" {
+        fs::create_dir_all(&cache_dir)
+            .await
+            .map_err(|e| GruxiError::tls(TlsError::Storage, format!("Failed to create ACME cache directory '{}': {}", cache_dir, e)))?;
+    }
 
-    // rustls-acme requires `mailto:` prefix.
-    acme_config = acme_config.contact_push(format!("mailto:{}", tls_settings.account_email.trim()));
+    // Accounts with an external account binding are registered before rustls-acme gets to register one without it
+    ensure_acme_account(tls_settings, &AcmeCache::from_settings(tls_settings, &cache_dir))
+        .await
+        .map_err(|e| GruxiError::tls(TlsError::Acme, e))?;
 
     trace(format!(
-        "ACME initialized (staging={}, cache_dir='{}') for {} domains: {:?}",
-        tls_settings.use_staging_server,
+        "ACME initialized (directory={}, cache_dir='{}', cache={}, consolidated={}, challenge={}) for {} domains in {} orders: {:?}",
+        get_acme_directory_url(tls_settings),
         cache_dir,
+        AcmeCache::from_settings(tls_settings, &cache_dir).describe(),
+        tls_settings.consolidate_acme_orders,
+        tls_settings.acme_challenge_type,
         all_domains.len(),
-        all_domains
+        orders.len(),
+        orders.iter().map(|order| &order.domains).collect::<Vec<_>>()
     ));
 
-    // Create the ACME state - this is the single instance that will handle all certificate operations
-    let acme_state = acme_config.state();
-    let resolver = acme_state.resolver();
-
-    // Create a cancellation token for the polling task
+    // Create a cancellation token for the polling tasks
     let polling_cancel_token = CancellationToken::new();
 
-    // Spawn a single background task to poll the ACME state for certificate updates
-    spawn_acme_polling_task(acme_state, polling_cancel_token.clone());
+    let mut order_resolvers = Vec::new();
+    let mut domain_to_order = HashMap::new();
+    let mut managed_orders = Vec::new();
+    let mut domain_to_managed_order = HashMap::new();
+
+    for order in &orders {
+        let order_domains = &order.domains;
+
+        // RSA keys, ACME profiles and wildcards are ordered by Gruxi itself, with the same account and cache
+        if !order.is_rustls_acme_order() {
+            let managed_order = Arc::new(ManagedAcmeOrder::new(order_domains.iter().cloned().collect(), order.settings.clone()));
+            for domain in order_domains {
+                domain_to_managed_order.insert(domain.clone(), managed_orders.len());
+            }
+            spawn_managed_order_task(managed_order.clone(), tls_settings.clone(), cache_dir.clone(), polling_cancel_token.clone());
+            managed_orders.push(managed_order);
+            continue;
+        }
+
+        let order_idx = order_resolvers.len();
+        let provider = rustls::crypto::aws_lc_rs::default_provider();
+
+        let mut acme_config = AcmeConfig::new_with_provider(order_domains.iter().cloned().collect::<Vec<_>>(), provider.into())
+            .cache_with_boxed_err(AcmeCache::from_settings(tls_settings, &cache_dir))
+            .directory(&order.settings.directory_url);
+
+        // HTTP-01 challenges are answered by handle_request, on any binding the ACME server reaches on port 80
+        if tls_settings.acme_challenge_type == "http-01" {
+            acme_config = acme_config.challenge_type(UseChallenge::Http01);
+        }
+
+        // rustls-acme requires `mailto:` prefix.
+        acme_config = acme_config.contact(get_acme_account_contact(tls_settings));
+
+        // Create the ACME state for this order - it handles the certificate operations for its domains
+        let acme_state = acme_config.state();
+        order_resolvers.push(acme_state.resolver());
+
+        for domain in order_domains {
+            domain_to_order.insert(domain.clone(), order_idx);
+        }
+
+        // Spawn a background task to poll the ACME state for certificate updates
+        spawn_acme_polling_task(acme_state, order.clone(), AcmeCache::from_settings(tls_settings, &cache_dir), polling_cancel_token.clone());
+    }
+
+    let resolver = Arc::new(SharedAcmeResolver {
+        order_resolvers,
+        domain_to_order,
+        managed_orders,
+        domain_to_managed_order,
+    });
 
     let domains_set: std::collections::HashSet<String> = all_domains.into_iter().collect();
 
     Ok(Some(SharedAcmeManager {
         resolver,
         domains: domains_set,
+        orders,
         polling_cancel_token,
     }))
 }
 
+/// Whether ACME can issue a certificate for the hostname, which has to be trimmed and lowercase
+pub fn is_acme_domain_candidate(hostname: &str) -> bool {
+    // Wildcards require DNS-01 (see is_acme_wildcard_candidate), and regular expressions are not domains.
+    if hostname.is_empty() || hostname.contains('*') || is_regex_hostname(hostname) {
+        return false;
+    }
+
+    // Avoid obviously-non-public hostnames.
+    if hostname == "localhost" {
+        return false;
+    }
+
+    // Minimal sanity: must look like a DNS name.
+    hostname.contains('.')
+}
+
+/// Whether ACME can issue a certificate for the wildcard hostname, such as "*.example.com", when validating with DNS-01
+pub fn is_acme_wildcard_candidate(hostname: &str) -> bool {
+    hostname.strip_prefix("*.").is_some_and(is_acme_domain_candidate)
+}
+
+/// The wildcard hostname covering the domain, such as "*.example.com" for "www.example.com". A wildcard covers a single
+/// label, and never a whole public suffix or another wildcard
+pub fn get_wildcard_domain(domain: &str) -> Option<String> {
+    if domain.starts_with("*.") {
+        return None;
+    }
+    let (_, parent) = domain.split_once('.')?;
+    parent.contains('.').then(|| format!("*.{}", parent))
+}
+
+/// Directory of the filesystem certificate cache
+pub fn get_cache_dir(tls_settings: &TlsSettings) -> String {
+    if tls_settings.certificate_cache_path.trim().is_empty() {
+        "certs/cache".to_string()
+    } else {
+        tls_settings.certificate_cache_path.trim().to_string()
+    }
+}
+
+/// Get the apex domain of a hostname, based on the public suffix list,
+/// e.g. "www.example.com" gives "example.com" and "shop.example.co.uk" gives "example.co.uk"
+fn get_apex_domain(hostname: &str) -> String {
+    match psl::domain(hostname.as_bytes()) {
+        Some(domain) => String::from_utf8_lossy(domain.as_bytes()).to_string(),
+        None => hostname.to_string(),
+    }
+}
+
+/// Plan the ACME certificate orders from the hostname sets of the sites.
+/// Without consolidation, all hostnames are ordered together, except wildcards, which get an order each. With consolidation,
+/// all hostnames sharing an apex domain are ordered together as a multi-SAN certificate, wildcards included.
+/// Names covered by a wildcard are left out, as the certificate of the wildcard serves them.
+/// A domain is only ever part of one order, and orders never exceed the per certificate name limit.
+fn plan_acme_orders(site_domain_sets: &[BTreeSet<String>], consolidate: bool) -> Vec<BTreeSet<String>> {
+    let domains: BTreeSet<String> = site_domain_sets.iter().flatten().cloned().collect();
+
+    let mut grouped: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for domain in &domains {
+        if get_wildcard_domain(domain).is_some_and(|wildcard| domains.contains(&wildcard)) {
+            continue;
+        }
+        let group = if consolidate {
+            get_apex_domain(domain.trim_start_matches("*."))
+        } else if domain.starts_with("*.") {
+            domain.clone()
+        } else {
+            String::new()
+        };
+        grouped.entry(group).or_default().push(domain.clone());
+    }
+
+    // Split any order that has more names than a certificate can hold
+    let mut orders = Vec::new();
+    for domains in grouped.into_values() {
+        for chunk in domains.chunks(MAX_DOMAINS_PER_ORDER) {
+            orders.push(chunk.iter().cloned().collect());
+        }
+    }
+    orders
+}
+
 /// Spawn a background task that polls the ACME state for certificate acquisition and renewal.
 /// The task will stop when the cancellation token is cancelled or when shutdown/stop_services triggers fire.
-fn spawn_acme_polling_task(
-    mut acme_state: rustls_acme::AcmeState<Box<dyn std::fmt::Debug>, Box<dyn std::fmt::Debug>>,
-    cancel_token: CancellationToken,
-) {
+fn spawn_acme_polling_task(mut acme_state: rustls_acme::AcmeState<Box<dyn std::fmt::Debug>, Box<dyn std::fmt::Debug>>, order: AcmeOrder, cache: AcmeCache, cancel_token: CancellationToken) {
+    let domains: Vec<String> = order.domains.iter().cloned().collect();
     tokio::spawn(async move {
         trace("ACME background polling task started".to_string());
 
         // Get shutdown and stop_services triggers
-        let triggers = get_trigger_handler();
-        let shutdown_token = triggers
-            .get_trigger("shutdown")
-            .map(|t| {
-                // We need to clone the token from inside the RwLock
-                // Use try_read to avoid blocking, fall back to a new token if locked
-                t.try_read().map(|guard| guard.clone()).unwrap_or_else(|_| CancellationToken::new())
-            })
-            .unwrap_or_else(|| CancellationToken::new());
-
-        let stop_services_token = triggers
-            .get_trigger("stop_services")
-            .map(|t| {
-                t.try_read().map(|guard| guard.clone()).unwrap_or_else(|_| CancellationToken::new())
-            })
-            .unwrap_or_else(|| CancellationToken::new());
+        let shutdown_token = get_trigger_token("shutdown");
+        let stop_services_token = get_trigger_token("stop_services");
 
         // Poll the ACME state to handle certificate acquisition and renewal
         loop {
+            // Set by the event branch, the other branches end the loop
+            let stored_new_cert;
             tokio::select! {
                 // Check for cancellation (from manager shutdown)
                 _ = cancel_token.cancelled() => {
@@ -248,23 +565,171 @@ fn spawn_acme_polling_task(
                 }
                 // Poll for ACME events
                 event = acme_state.next() => {
-                    match event {
+                    stored_new_cert = match event {
                         Some(Ok(ok)) => {
                             trace(format!("ACME event: {:?}", ok));
+                            if matches!(ok, rustls_acme::EventOk::DeployedNewCert) {
+                                run_certificate_renewed_hooks(&domains);
+                            }
+                            matches!(ok, rustls_acme::EventOk::CertCacheStore)
                         }
                         Some(Err(err)) => {
                             debug(format!("ACME error: {:?}", err));
+                            false
                         }
                         None => {
                             // Stream ended
                             debug("ACME event stream ended".to_string());
                             break;
                         }
-                    }
+                    };
                 }
             }
+
+            // Only a new certificate is stored in the cache, so this is when it is recorded
+            if stored_new_cert && let Ok(Some(pem)) = cache.load_cert(&domains, &order.settings.directory_url).await {
+                record_acme_certificate(&domains, &order.settings.directory_url, "ecdsa", &pem).await;
+            }
         }
 
         debug("ACME background polling task ended".to_string());
     });
 }
+
+/// Get the cancellation token of a trigger, to stop background tasks with
+pub fn get_trigger_token(name: &str) -> CancellationToken {
+    get_trigger_handler()
+        .get_trigger(name)
+        .map(|t| {
+            // We need to clone the token from inside the RwLock
+            // Use try_read to avoid blocking, fall back to a new token if locked
+            t.try_read().map(|guard| guard.clone()).unwrap_or_else(|_| CancellationToken::new())
+        })
+        .unwrap_or_default()
+}
+
+/// Let the command hooks know about a new certificate, such as to copy it into another service
+pub fn run_certificate_renewed_hooks(domains: &[String]) {
+    let domains = domains.to_vec();
+    tokio::spawn(async move {
+        let hooks = {
+            let cached_configuration = get_cached_configuration();
+            let configuration = cached_configuration.get_configuration().await;
+            get_command_hooks_for_event(&configuration, "certificate_renewed")
+        };
+        let domain = domains.first().cloned().unwrap_or_default();
+        run_command_hooks(&hooks, "certificate_renewed", &[("GRUXI_DOMAIN", domain), ("GRUXI_DOMAINS", domains.join(","))]).await;
+    });
+}
+
+/// Have the command hooks publish ("present") or remove ("cleanup") the TXT record of a DNS-01 challenge. The CA is only
+/// asked to check the record once the hooks are done, so they wait for the record to reach the name servers of the domain
+pub async fn run_acme_dns_challenge_hooks(action: &str, domain: &str, value: &str) {
+    let hooks = {
+        let cached_configuration = get_cached_configuration();
+        let configuration = cached_configuration.get_configuration().await;
+        get_command_hooks_for_event(&configuration, "acme_dns_challenge")
+    };
+    run_command_hooks(
+        &hooks,
+        "acme_dns_challenge",
+        &[
+            ("GRUXI_ACME_ACTION", action.to_string()),
+            ("GRUXI_DOMAIN", domain.to_string()),
+            ("GRUXI_ACME_RECORD_NAME", format!("_acme-challenge.{}", domain)),
+            ("GRUXI_ACME_RECORD_VALUE", value.to_string()),
+        ],
+    )
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domain_set(domains: &[&str]) -> BTreeSet<String> {
+        domains.iter().map(|d| d.to_string()).collect()
+    }
+
+    #[test]
+    fn test_get_apex_domain() {
+        assert_eq!(get_apex_domain("example.com"), "example.com");
+        assert_eq!(get_apex_domain("www.example.com"), "example.com");
+        assert_eq!(get_apex_domain("a.b.example.com"), "example.com");
+        assert_eq!(get_apex_domain("shop.example.co.uk"), "example.co.uk");
+    }
+
+    #[test]
+    fn test_plan_acme_orders_single() {
+        let sites = vec![
+            domain_set(&["example.com", "www.example.com"]),
+            domain_set(&["api.example.com", "www.example.com"]),
+            domain_set(&["other.org"]),
+        ];
+        let orders = plan_acme_orders(&sites, false);
+
+        assert_eq!(orders, vec![domain_set(&["api.example.com", "example.com", "other.org", "www.example.com"])]);
+    }
+
+    #[test]
+    fn test_plan_acme_orders_wildcards() {
+        let sites = vec![
+            domain_set(&["example.com", "www.example.com", "a.b.example.com"]),
+            domain_set(&["*.example.com"]),
+            domain_set(&["other.org"]),
+        ];
+
+        let orders = plan_acme_orders(&sites, false);
+        assert_eq!(orders, vec![domain_set(&["a.b.example.com", "example.com", "other.org"]), domain_set(&["*.example.com"])]);
+
+        let orders = plan_acme_orders(&sites, true);
+        assert_eq!(orders, vec![domain_set(&["*.example.com", "a.b.example.com", "example.com"]), domain_set(&["other.org"])]);
+    }
+
+    #[test]
+    fn test_acme_http01_token() {
+        assert_eq!(get_acme_http01_token("/.well-known/acme-challenge/xPb7e9pMq2T4-Lx_0qa"), Some("xPb7e9pMq2T4-Lx_0qa"));
+        assert_eq!(get_acme_http01_token("/.well-known/acme-challenge/token-1_a"), Some("token-1_a"));
+
+        assert_eq!(get_acme_http01_token("/.well-known/acme-challenge/"), None);
+        assert_eq!(get_acme_http01_token("/.well-known/acme-challenge/../index.html"), None);
+        assert_eq!(get_acme_http01_token("/.well-known/acme-challenge/%2e%2e/index.html"), None);
+        assert_eq!(get_acme_http01_token("/.well-known/acme-challenge/token/other"), None);
+        assert_eq!(get_acme_http01_token("/.well-known/acme-challenge-other/token"), None);
+        assert_eq!(get_acme_http01_token("/index.html"), None);
+    }
+
+    #[test]
+    fn test_wildcard_domains() {
+        assert_eq!(get_wildcard_domain("www.example.com"), Some("*.example.com".to_string()));
+        assert_eq!(get_wildcard_domain("a.b.example.com"), Some("*.b.example.com".to_string()));
+        assert_eq!(get_wildcard_domain("example.com"), None);
+        assert_eq!(get_wildcard_domain("*.example.com"), None);
+
+        assert!(is_acme_wildcard_candidate("*.example.com"));
+        assert!(!is_acme_wildcard_candidate("*.com"));
+        assert!(!is_acme_wildcard_candidate("www.*.example.com"));
+        assert!(!is_acme_domain_candidate("*.example.com"));
+    }
+
+    #[test]
+    fn test_plan_acme_orders_consolidated() {
+        let sites = vec![domain_set(&["example.com", "www.example.com"]), domain_set(&["api.example.com"]), domain_set(&["other.org"])];
+        let orders = plan_acme_orders(&sites, true);
+
+        assert_eq!(orders.len(), 2);
+        assert_eq!(orders[0], domain_set(&["api.example.com", "example.com", "www.example.com"]));
+        assert_eq!(orders[1], domain_set(&["other.org"]));
+    }
+
+    #[test]
+    fn test_plan_acme_orders_respects_name_limit() {
+        let hostnames: Vec<String> = (0..150).map(|i| format!("host{}.example.com", i)).collect();
+        let sites = vec![hostnames.into_iter().collect::<BTreeSet<String>>()];
+        let orders = plan_acme_orders(&sites, true);
+
+        assert_eq!(orders.len(), 2);
+        assert_eq!(orders[0].len(), MAX_DOMAINS_PER_ORDER);
+        assert_eq!(orders[1].len(), 50);
+    }
+}
@@ -1,6 +1,13 @@
 use rustls::{ClientConfig, RootCertStore};
 
 pub fn tls_config() -> ClientConfig {
+    let roots = tls_root_store();
+
+    ClientConfig::builder().with_root_certificates(roots).with_no_client_auth()
+}
+
+// The trusted root certificates of the system, extended with the webpki roots
+pub fn tls_root_store() -> RootCertStore {
     let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
 
     let mut roots = RootCertStore::empty();
@@ -13,7 +20,5 @@ pub fn tls_config() -> ClientConfig {
     // Extend with webpki-roots
     roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
 
-    let config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
-
-    config
+    roots
 }
@@ -0,0 +1,44 @@
+// Shared by the integration tests, with `mod common;`
+#![allow(dead_code)]
+
+use gruxi::configuration::configuration::Configuration;
+use gruxi::core::gruxi_server::{GruxiServer, GruxiServerBuilder};
+use std::net::SocketAddr;
+use tokio::sync::OnceCell;
+
+static TEST_SERVER: OnceCell<(GruxiServer, SocketAddr)> = OnceCell::const_new();
+
+/// The default configuration with only its plain HTTP binding, on the loopback interface and a port picked by the OS
+pub fn get_test_configuration() -> Configuration {
+    let mut configuration = Configuration::get_default();
+
+    let tls_binding_ids: Vec<String> = configuration.bindings.iter().filter(|binding| binding.is_tls).map(|binding| binding.id.clone()).collect();
+    configuration.bindings.retain(|binding| !binding.is_tls);
+    configuration.binding_sites.retain(|relation| !tls_binding_ids.contains(&relation.binding_id));
+
+    let binding = &mut configuration.bindings[0];
+    binding.ip = "127.0.0.1".to_string();
+    binding.port = 0;
+    configuration
+}
+
+/// A builder for a server with the test configuration, on a database in memory
+pub fn get_test_server_builder() -> GruxiServerBuilder {
+    // Before the configuration, which reads the arguments of the process
+    let builder = GruxiServer::builder();
+    builder.configuration(get_test_configuration()).in_memory_database()
+}
+
+/// The address of the server shared by the tests of this test binary, started on first use with the test configuration.
+/// It runs on a runtime of its own, as each test has one that stops when the test ends
+pub async fn get_test_server_addr() -> SocketAddr {
+    let (_, addr) = TEST_SERVER
+        .get_or_init(|| async {
+            let server = get_test_server_builder().start_in_background().expect("Failed to start the test server");
+            let binding_id = server.get_configuration().await.bindings[0].id.clone();
+            let addr = server.get_binding_address(&binding_id).await.expect("The test server has no address");
+            (server, addr)
+        })
+        .await;
+    *addr
+}
@@ -0,0 +1,64 @@
+use gruxi::core::gruxi_server::GruxiServer;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+mod common;
+
+// Tests of Gruxi embedded with GruxiServer::builder(), which need no server running beforehand.
+//
+// The server is process wide, so everything is tested in one test, on databases in memory
+// and on ports picked by the OS.
+
+async fn get_status_line(addr: SocketAddr) -> std::io::Result<String> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    Ok(String::from_utf8_lossy(&response).lines().next().unwrap_or_default().to_string())
+}
+
+async fn get_addr(server: &GruxiServer) -> SocketAddr {
+    let binding_id = server.get_configuration().await.bindings[0].id.clone();
+    server.get_binding_address(&binding_id).await.unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_embedded_server_starts_serves_stops_and_restarts() {
+    // A configuration that fails validation is not started, and is a validation error
+    let builder = GruxiServer::builder();
+    let mut configuration = common::get_test_configuration();
+    configuration.bindings[0].ip = "not an ip".to_string();
+    let error = builder.configuration(configuration).in_memory_database().start().await.err().unwrap();
+    assert_eq!(error.get_http_status_code(), 400);
+
+    let server = common::get_test_server_builder().start().await.unwrap();
+    let addr = get_addr(&server).await;
+    assert_ne!(addr.port(), 0);
+
+    // Serving as soon as start returns
+    assert_eq!(get_status_line(addr).await.unwrap(), "HTTP/1.1 200 OK");
+
+    // One server per process
+    assert!(GruxiServer::builder().in_memory_database().start().await.is_err());
+
+    // Not listening any more once stop returns
+    server.stop().await.unwrap();
+    assert!(TcpStream::connect(addr).await.is_err());
+
+    // Started again on a new database, and stopped through a shutdown handle from another task
+    let server = common::get_test_server_builder().start().await.unwrap();
+    let new_addr = get_addr(&server).await;
+    assert_eq!(get_status_line(new_addr).await.unwrap(), "HTTP/1.1 200 OK");
+    assert_eq!(server.get_configuration().await.bindings.len(), 1);
+
+    let shutdown_handle = server.get_shutdown_handle();
+    tokio::spawn(async move { shutdown_handle.shutdown() });
+    server.wait_for_shutdown().await.unwrap();
+    assert!(TcpStream::connect(new_addr).await.is_err());
+
+    // On a runtime of its own, which keeps it running after the runtime of this test
+    let server = common::get_test_server_builder().start_in_background().unwrap();
+    assert_eq!(get_status_line(get_addr(&server).await).await.unwrap(), "HTTP/1.1 200 OK");
+    server.stop().await.unwrap();
+}
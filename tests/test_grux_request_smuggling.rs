@@ -0,0 +1,186 @@
+use hyper::HeaderMap;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{Duration, timeout};
+
+// Request Smuggling and TRACE Test Suite for Gruxi Web Server
+//
+// This test suite validates that Gruxi frames HTTP/1.1 requests unambiguously,
+// so a proxy in front of it cannot be made to read a request differently than
+// Gruxi does (RFC 7230 section 3.3.3, RFC 9112 section 6.3), and that TRACE is
+// rejected unless it is enabled in the server settings.
+//
+// ============================================================================
+// IMPORTANT: These tests validate the ACTUAL running Gruxi server, not a mock!
+// ============================================================================
+//
+// SETUP INSTRUCTIONS:
+// 1. Ensure www-default/ directory has content (index.html, etc.)
+// 2. Run tests: `cargo test --test test_grux_request_smuggling`
+//
+// The tests start Gruxi themselves, embedded with the default configuration,
+// a database in memory and a port picked by the OS (see tests/common).
+//
+// WHAT THESE TESTS VERIFY:
+//
+// ✓ TRACE: Answered with 405 and an Allow header without TRACE
+// ✓ Framing: Transfer-Encoding with Content-Length, differing Content-Length values
+//   and Transfer-Encoding not ending in chunked are rejected with 400
+// ✓ Obsolete line folding (obs-fold) in headers is rejected with 400
+// ✓ Connections are closed after a rejected request, so no smuggled request is served
+//
+// TROUBLESHOOTING:
+// - If the TRACE test fails: Check that TRACE is not enabled in the default server settings
+
+mod common;
+
+const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Get the HTTP server address for testing
+async fn get_http_server_addr() -> SocketAddr {
+    common::get_test_server_addr().await
+}
+
+/// Send raw HTTP request and get raw response, reading until the server closes the connection
+async fn send_raw_http_request(addr: SocketAddr, request: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let mut stream = timeout(TEST_TIMEOUT, TcpStream::connect(addr)).await??;
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = Vec::new();
+    match timeout(Duration::from_millis(5000), stream.read_to_end(&mut response)).await {
+        Ok(Ok(_)) => Ok(String::from_utf8_lossy(&response).into_owned()),
+        Ok(Err(e)) => Err(e.into()),
+        Err(_) => Ok(String::from_utf8_lossy(&response).into_owned()),
+    }
+}
+
+/// Parse HTTP response into status line and headers
+fn parse_http_response(response: &str) -> (String, HeaderMap) {
+    let header_block = response.split("\r\n\r\n").next().unwrap_or("");
+    let mut header_lines = header_block.lines();
+    let status_line = header_lines.next().unwrap_or("").trim_end_matches('\r').to_string();
+
+    let mut headers = HeaderMap::new();
+    for line in header_lines {
+        if let Some((name, value)) = line.trim_end_matches('\r').split_once(':')
+            && let (Ok(header_name), Ok(header_value)) = (name.trim().to_lowercase().parse::<hyper::header::HeaderName>(), value.trim().parse::<hyper::header::HeaderValue>())
+        {
+            headers.insert(header_name, header_value);
+        }
+    }
+
+    (status_line, headers)
+}
+
+/// Count the responses in the raw response text, to see if a smuggled request was answered
+fn count_responses(response: &str) -> usize {
+    response.matches("HTTP/1.1 ").count()
+}
+
+// ============================================================================
+// 1. TRACE METHOD
+// ============================================================================
+
+#[tokio::test]
+async fn test_trace_rejected_by_default() {
+    let server_addr = get_http_server_addr().await;
+
+    let request = "TRACE / HTTP/1.1\r\nHost: localhost\r\nCookie: session=secret\r\nConnection: close\r\n\r\n";
+    let response = send_raw_http_request(server_addr, request).await.unwrap();
+    let (status_line, headers) = parse_http_response(&response);
+
+    assert!(status_line.contains("405"), "TRACE should return 405, got: {}", status_line);
+    let allow = headers.get("allow").expect("405 must include Allow header").to_str().unwrap().to_uppercase();
+    assert!(!allow.contains("TRACE"), "Allow header should not include TRACE: {}", allow);
+    assert!(!response.contains("session=secret"), "TRACE response must not echo the request");
+}
+
+// ============================================================================
+// 2. MESSAGE FRAMING
+// ============================================================================
+
+#[tokio::test]
+async fn test_content_length_then_transfer_encoding_rejected() {
+    let server_addr = get_http_server_addr().await;
+
+    // CL.TE: a proxy using Content-Length would forward the "GET /smuggled" as part of the body
+    let request = "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 6\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\nGET /smuggled HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let response = send_raw_http_request(server_addr, request).await.unwrap();
+    let (status_line, _) = parse_http_response(&response);
+
+    assert!(status_line.contains("400"), "Conflicting framing should return 400, got: {}", status_line);
+    assert_eq!(count_responses(&response), 1, "The smuggled request must not be served");
+}
+
+#[tokio::test]
+async fn test_transfer_encoding_then_content_length_not_smuggled() {
+    let server_addr = get_http_server_addr().await;
+
+    // TE.CL: the body is read as chunked, Content-Length is ignored or the request is rejected
+    let request =
+        "POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\nContent-Length: 4\r\n\r\n5c\r\nGET /smuggled HTTP/1.1\r\nHost: localhost\r\nContent-Length: 15\r\n\r\nx=1\r\n0\r\n\r\n";
+    let response = send_raw_http_request(server_addr, request).await.unwrap();
+
+    assert!(!response.contains("/smuggled"), "The smuggled request must not be served");
+    assert!(count_responses(&response) <= 1, "Only one response should be sent");
+}
+
+#[tokio::test]
+async fn test_differing_content_length_headers_rejected() {
+    let server_addr = get_http_server_addr().await;
+
+    let request = "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\nContent-Length: 5\r\nConnection: close\r\n\r\ntest!";
+    let response = send_raw_http_request(server_addr, request).await.unwrap();
+    let (status_line, _) = parse_http_response(&response);
+
+    assert!(status_line.contains("400"), "Differing Content-Length headers should return 400, got: {}", status_line);
+}
+
+#[tokio::test]
+async fn test_transfer_encoding_not_ending_in_chunked_rejected() {
+    let server_addr = get_http_server_addr().await;
+
+    let request = "POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked, identity\r\nConnection: close\r\n\r\n4\r\ntest\r\n0\r\n\r\n";
+    let response = send_raw_http_request(server_addr, request).await.unwrap();
+    let (status_line, _) = parse_http_response(&response);
+
+    assert!(status_line.contains("400"), "Transfer-Encoding not ending in chunked should return 400, got: {}", status_line);
+}
+
+#[tokio::test]
+async fn test_transfer_encoding_in_http10_rejected() {
+    let server_addr = get_http_server_addr().await;
+
+    let request = "POST / HTTP/1.0\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n4\r\ntest\r\n0\r\n\r\n";
+    let response = send_raw_http_request(server_addr, request).await.unwrap();
+
+    assert!(response.starts_with("HTTP/1.") && response.contains(" 400 "), "Transfer-Encoding in HTTP/1.0 should return 400");
+}
+
+// ============================================================================
+// 3. HEADER SYNTAX
+// ============================================================================
+
+#[tokio::test]
+async fn test_obs_fold_header_rejected() {
+    let server_addr = get_http_server_addr().await;
+
+    // A folded header can hide a header from a proxy that unfolds differently
+    let request = "GET / HTTP/1.1\r\nHost: localhost\r\nX-Folded: first\r\n Transfer-Encoding: chunked\r\nConnection: close\r\n\r\n";
+    let response = send_raw_http_request(server_addr, request).await.unwrap();
+    let (status_line, _) = parse_http_response(&response);
+
+    assert!(status_line.contains("400"), "Obsolete line folding should return 400, got: {}", status_line);
+}
+
+#[tokio::test]
+async fn test_whitespace_before_colon_rejected() {
+    let server_addr = get_http_server_addr().await;
+
+    let request = "POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding : chunked\r\nConnection: close\r\n\r\n0\r\n\r\n";
+    let response = send_raw_http_request(server_addr, request).await.unwrap();
+    let (status_line, _) = parse_http_response(&response);
+
+    assert!(status_line.contains("400"), "Whitespace between header name and colon should return 400, got: {}", status_line);
+}
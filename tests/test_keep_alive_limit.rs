@@ -0,0 +1,39 @@
+use gruxi::core::gruxi_server::GruxiServer;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{Duration, timeout};
+
+mod common;
+
+// Tests of the limit of requests served on a kept-alive connection, on a server with a database in memory
+
+const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_connection_is_closed_after_max_keep_alive_requests() {
+    let mut configuration = common::get_test_configuration();
+    configuration.bindings[0].max_keep_alive_requests = 2;
+
+    let server = GruxiServer::builder().configuration(configuration).in_memory_database().start().await.unwrap();
+    let binding_id = server.get_configuration().await.bindings[0].id.clone();
+    let addr = server.get_binding_address(&binding_id).await.unwrap();
+
+    // One request more than the limit, pipelined on one connection
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n".repeat(3).as_bytes()).await.unwrap();
+
+    // The server closes the connection after the last permitted response, which ends the read
+    let mut response = Vec::new();
+    timeout(TEST_TIMEOUT, stream.read_to_end(&mut response)).await.expect("The connection was not closed").unwrap();
+    let response = String::from_utf8_lossy(&response).to_lowercase();
+
+    let responses: Vec<&str> = response.split("http/1.1 ").skip(1).collect();
+    assert_eq!(responses.len(), 2, "Expected only the permitted responses: {}", response);
+    assert!(responses[0].starts_with("200 ok"));
+    assert!(responses[0].contains("max=1"));
+    assert!(!responses[0].contains("connection: close"));
+    assert!(responses[1].starts_with("200 ok"));
+    assert!(responses[1].contains("connection: close"));
+
+    server.stop().await.unwrap();
+}
@@ -0,0 +1,52 @@
+use gruxi::configuration::{location::Location, lua_hook::LuaHook};
+use gruxi::core::gruxi_server::GruxiServer;
+use gruxi::http::basic_auth::BasicAuthUser;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+mod common;
+
+// Tests of paths rewritten by the middleware of a site, which are checked again for the location they end up in.
+//
+// The server is process wide, so everything is tested in one test, on a database in memory.
+
+async fn get_status_line(addr: SocketAddr, path: &str, authorization: Option<&str>) -> std::io::Result<String> {
+    let mut stream = TcpStream::connect(addr).await?;
+    let authorization = authorization.map(|credentials| format!("Authorization: Basic {}\r\n", credentials)).unwrap_or_default();
+    stream
+        .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n", path, authorization).as_bytes())
+        .await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    Ok(String::from_utf8_lossy(&response).lines().next().unwrap_or_default().to_string())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rewritten_path_is_authenticated_for_its_location() {
+    let mut configuration = common::get_test_configuration();
+    let site = &mut configuration.sites[0];
+    let mut location = Location::new();
+    location.name = "Protected".to_string();
+    location.match_type = "regex".to_string();
+    location.pattern = "^/$".to_string();
+    location.auth_realm = "Protected".to_string();
+    location.auth_users = vec![BasicAuthUser {
+        username: "user".to_string(),
+        password: bcrypt::hash("password", 4).unwrap(),
+    }];
+    site.locations.push(location);
+    site.lua_hooks
+        .push(LuaHook::new("rewrite", "rewrite", r#"if gruxi.req.get_path() == "/open" then gruxi.req.set_path("/") end"#));
+
+    let server = GruxiServer::builder().configuration(configuration).in_memory_database().start().await.unwrap();
+    let binding_id = server.get_configuration().await.bindings[0].id.clone();
+    let addr = server.get_binding_address(&binding_id).await.unwrap();
+
+    assert_eq!(get_status_line(addr, "/", None).await.unwrap(), "HTTP/1.1 401 Unauthorized");
+    // Rewritten into the protected location after the auth middleware saw the request for an open path
+    assert_eq!(get_status_line(addr, "/open", None).await.unwrap(), "HTTP/1.1 401 Unauthorized");
+    assert_eq!(get_status_line(addr, "/open", Some("dXNlcjpwYXNzd29yZA==")).await.unwrap(), "HTTP/1.1 200 OK");
+
+    server.stop().await.unwrap();
+}